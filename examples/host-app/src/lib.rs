@@ -0,0 +1,142 @@
+//! An application embedding Nagari. Plugins are Nagari modules loaded into a sandboxed
+//! runtime; they call back into the application through host functions and report what they
+//! did through events.
+//!
+//! `src/main.rs` walks through a session, and the tests under `tests/` cover each part of the
+//! embedding API the host relies on.
+
+use nagari_embedded::{EmbeddedRuntime, EmbeddedValue, HostSignature, RuntimeBuilder};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The plugin shipped with the app
+pub const GREETER_PLUGIN: &str = include_str!("../plugins/greeter.nag");
+
+/// An event emitted by a script and its arguments
+pub type Notification = (String, Vec<EmbeddedValue>);
+
+pub struct PluginHost {
+    runtime: EmbeddedRuntime,
+    log: Arc<Mutex<Vec<String>>>,
+    notifications: Arc<Mutex<Vec<Notification>>>,
+}
+
+impl PluginHost {
+    /// A host whose scripts can't reach the file system or the network, and whose calls into
+    /// async host functions are cancelled after `timeout_ms`. `settings` are what plugins get
+    /// from `fetch_setting`.
+    pub fn new(timeout_ms: u64, settings: HashMap<String, EmbeddedValue>) -> Result<Self, String> {
+        let runtime = RuntimeBuilder::new()
+            .sandbox_mode(true)
+            .allow_io(false)
+            .allow_network(false)
+            .memory_limit(16 * 1024 * 1024)
+            .execution_timeout(timeout_ms)
+            .build()?;
+        let mut host = Self {
+            runtime,
+            log: Arc::default(),
+            notifications: Arc::default(),
+        };
+        host.register_host_api(settings)?;
+        Ok(host)
+    }
+
+    /// The host functions plugins can call
+    fn register_host_api(
+        &mut self,
+        settings: HashMap<String, EmbeddedValue>,
+    ) -> Result<(), String> {
+        let log = Arc::clone(&self.log);
+        self.runtime.register_typed_host_function(
+            "host_log",
+            HostSignature::new().param("message", "str"),
+            move |args| {
+                if let Some(message) = args[0].as_string() {
+                    log.lock().unwrap().push(message.to_string());
+                }
+                EmbeddedValue::None
+            },
+        )?;
+
+        self.runtime.register_typed_host_function(
+            "clamp",
+            HostSignature::new()
+                .param("value", "int")
+                .param("low", "int")
+                .param("high", "int")
+                .returns("int"),
+            |args| match (&args[0], &args[1], &args[2]) {
+                (EmbeddedValue::Int(value), EmbeddedValue::Int(low), EmbeddedValue::Int(high)) => {
+                    EmbeddedValue::Int((*value).max(*low).min(*high))
+                }
+                _ => EmbeddedValue::None,
+            },
+        )?;
+
+        // Settings stand in for an async source such as a configuration service
+        let settings = Arc::new(settings);
+        self.runtime.register_typed_async_host_function(
+            "fetch_setting",
+            HostSignature::new().param("key", "str"),
+            move |args: Vec<EmbeddedValue>| {
+                let settings = Arc::clone(&settings);
+                async move {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    let key = args[0].as_string().unwrap_or_default();
+                    settings
+                        .get(key)
+                        .cloned()
+                        .ok_or_else(|| format!("no setting named '{key}'"))
+                }
+            },
+        )?;
+
+        self.runtime.register_typed_async_host_function(
+            "wait_ms",
+            HostSignature::new().param("ms", "int").returns("int"),
+            |args: Vec<EmbeddedValue>| async move {
+                let ms = args[0].as_int().unwrap_or(0).max(0);
+                tokio::time::sleep(Duration::from_millis(ms as u64)).await;
+                Ok(EmbeddedValue::Int(ms))
+            },
+        )
+    }
+
+    pub fn load_plugin(&mut self, name: &str, code: &str) -> Result<(), String> {
+        self.runtime.load_module(name, code)
+    }
+
+    pub fn run(&mut self, script: &str) -> Result<EmbeddedValue, String> {
+        self.runtime.run_script(script)
+    }
+
+    /// Record every emit of `event` by scripts or the host, returning the subscription's id
+    pub fn subscribe(&mut self, event: &str) -> Result<u64, String> {
+        let notifications = Arc::clone(&self.notifications);
+        let name = event.to_string();
+        self.runtime.on_event(event, move |args| {
+            notifications.lock().unwrap().push((name.clone(), args));
+        })
+    }
+
+    pub fn unsubscribe(&mut self, id: u64) -> Result<bool, String> {
+        self.runtime.off_event(id)
+    }
+
+    /// The events recorded since the last call
+    pub fn take_notifications(&self) -> Vec<Notification> {
+        std::mem::take(&mut *self.notifications.lock().unwrap())
+    }
+
+    /// Messages plugins passed to `host_log`
+    pub fn log(&self) -> Vec<String> {
+        self.log.lock().unwrap().clone()
+    }
+
+    /// The underlying runtime, for the parts of the API the host doesn't wrap
+    pub fn runtime(&mut self) -> &mut EmbeddedRuntime {
+        &mut self.runtime
+    }
+}
@@ -0,0 +1,52 @@
+//! A session of the host app: configure a sandboxed runtime, load a plugin, call into it, and
+//! exchange events with it.
+
+use nagari_embedded::EmbeddedValue;
+use nagari_host_app::{PluginHost, GREETER_PLUGIN};
+use std::collections::HashMap;
+
+fn main() -> Result<(), String> {
+    let settings = HashMap::from([(
+        "motd".to_string(),
+        EmbeddedValue::String("welcome aboard".to_string()),
+    )]);
+    let mut host = PluginHost::new(200, settings)?;
+
+    // Sandboxing: scripts touching the file system or network are refused
+    if let Err(e) = host.run("read_fs(\"/etc/passwd\")") {
+        println!("sandbox: {e}");
+    }
+
+    host.subscribe("plugin_loaded")?;
+    host.load_plugin("greeter", GREETER_PLUGIN)?;
+    for (event, args) in host.take_notifications() {
+        println!("event {event}: {args:?}");
+    }
+    for message in host.log() {
+        println!("plugin log: {message}");
+    }
+
+    let banner = host.run("from greeter import banner\nbanner")?;
+    println!("banner: {}", banner.as_string().unwrap_or_default());
+
+    let clamped = host.runtime().call_function(
+        "clamp",
+        vec![
+            EmbeddedValue::Int(150),
+            EmbeddedValue::Int(0),
+            EmbeddedValue::Int(100),
+        ],
+    )?;
+    println!(
+        "clamp(150, 0, 100) = {}",
+        clamped.as_int().unwrap_or_default()
+    );
+
+    // Host calls outliving the execution timeout are cancelled
+    if let Err(e) = host.run("await wait_ms(1000)") {
+        println!("cancelled: {e}");
+    }
+
+    println!("{}", host.runtime().host_declarations()?);
+    Ok(())
+}
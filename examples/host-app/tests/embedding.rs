@@ -0,0 +1,998 @@
+use nagari_embedded::{
+    Access, Capability, EmbeddedValue, HeapSnapshot, HostSignature, JobRecord, JobStore,
+    MethodTable, NativeObject, Permission, PermissionDecision, Resource, RuntimeBuilder,
+    RuntimeConfig, Trigger,
+};
+use nagari_host_app::{PluginHost, GREETER_PLUGIN};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Integration tests for embedding the runtime in an application
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host() -> PluginHost {
+        let settings =
+            HashMap::from([("motd".to_string(), EmbeddedValue::String("hi".to_string()))]);
+        PluginHost::new(100, settings).expect("Failed to create host")
+    }
+
+    #[test]
+    fn test_sandbox_config() {
+        let config = RuntimeBuilder::new()
+            .execution_timeout(250)
+            .memory_limit(1024)
+            .allow_io(false)
+            .allow_network(false)
+            .sandbox_mode(true);
+        let mut runtime = config.build().unwrap();
+
+        assert_eq!(
+            runtime.run_script("read_fs(\"data.txt\")").unwrap_err(),
+            "IO operations not allowed"
+        );
+        assert_eq!(
+            runtime.run_script("http_get(\"x\")").unwrap_err(),
+            "Network operations not allowed"
+        );
+        assert!(runtime
+            .run_script("websocket.connect(\"ws://127.0.0.1:9\")")
+            .unwrap_err()
+            .contains("connect(): network operations are not allowed"));
+        assert!(runtime
+            .run_script("sqlite.open(\":memory:\")")
+            .unwrap_err()
+            .contains("open(): IO operations are not allowed"));
+        assert!(runtime.load_module("fs_helpers", "x = 1").is_err());
+        assert!(runtime
+            .register_host_function("unsafe_eval", |_| EmbeddedValue::None)
+            .is_err());
+
+        let mut trusted = RuntimeBuilder::new().sandbox_mode(false).build().unwrap();
+        assert!(trusted
+            .register_host_function("unsafe_eval", |_| EmbeddedValue::None)
+            .is_ok());
+
+        let mut io = RuntimeBuilder::new().allow_io(true).build().unwrap();
+        let script =
+            "db = sqlite.open(\":memory:\")\nsqlite.query(db, \"SELECT ? + 1 AS n\", [41])";
+        let EmbeddedValue::Array(rows) = io.run_script(script).unwrap() else {
+            panic!("expected rows");
+        };
+        let EmbeddedValue::Object(row) = &rows[0] else {
+            panic!("expected a row");
+        };
+        assert_eq!(row["n"].as_int(), Some(42));
+
+        let defaults = RuntimeConfig::default();
+        assert!(defaults.sandbox_mode);
+        assert!(!defaults.allow_io);
+        assert!(!defaults.allow_network);
+        assert!(defaults.permission_prompt.is_none());
+        assert_eq!(defaults.execution_timeout, Some(5000));
+    }
+
+    #[test]
+    fn test_permission_prompt() {
+        let asked = Arc::new(Mutex::new(Vec::new()));
+        let prompts = Arc::clone(&asked);
+        let mut runtime = RuntimeBuilder::new()
+            .permission_prompt(move |permission| {
+                prompts.lock().unwrap().push(permission.clone());
+                match permission {
+                    Permission::Io(path) if path == ":memory:" => PermissionDecision::AllowAlways,
+                    Permission::Io(_) => PermissionDecision::Deny,
+                    Permission::Network(_) => PermissionDecision::DenyAlways,
+                    _ => PermissionDecision::Deny,
+                }
+            })
+            .build()
+            .unwrap();
+
+        // The script isn't rejected up front, as the prompt may allow what it does
+        runtime
+            .run_script("db = sqlite.open(\":memory:\")")
+            .unwrap();
+        runtime
+            .run_script("db = sqlite.open(\":memory:\")")
+            .unwrap();
+        for _ in 0..2 {
+            assert!(runtime
+                .run_script("sqlite.open(\"secret.db\")")
+                .unwrap_err()
+                .contains("open(): IO access to 'secret.db' was denied"));
+            assert!(runtime
+                .run_script("websocket.connect(\"ws://example.com:9000/chat\")")
+                .unwrap_err()
+                .contains("connect(): network access to 'example.com:9000' was denied"));
+        }
+
+        // Decisions for good are remembered, others are asked again
+        assert_eq!(
+            *asked.lock().unwrap(),
+            [
+                Permission::Io(":memory:".to_string()),
+                Permission::Io("secret.db".to_string()),
+                Permission::Network("example.com:9000".to_string()),
+                Permission::Io("secret.db".to_string()),
+            ]
+        );
+
+        // What is allowed outright isn't asked about
+        let mut io = RuntimeBuilder::new()
+            .allow_io(true)
+            .permission_prompt(|_| PermissionDecision::Deny)
+            .build()
+            .unwrap();
+        io.run_script("sqlite.open(\":memory:\")").unwrap();
+    }
+
+    #[test]
+    fn test_access_report() {
+        let mut runtime = RuntimeBuilder::new().allow_io(true).build().unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        runtime
+            .on_access(move |access| recorded.lock().unwrap().push(access.clone()))
+            .unwrap();
+
+        let report = runtime
+            .run_script_with_report(
+                "db = sqlite.open(\":memory:\")\nwebsocket.connect(\"ws://example.com:9000/chat\")",
+            )
+            .unwrap();
+        // Denied accesses are reported along with the error they cause
+        assert!(report.result.is_err());
+        let expected = [
+            Access {
+                operation: "sqlite.open".to_string(),
+                permission: Permission::Io(":memory:".to_string()),
+                allowed: true,
+            },
+            Access {
+                operation: "websocket.connect".to_string(),
+                permission: Permission::Network("example.com:9000".to_string()),
+                allowed: false,
+            },
+        ];
+        assert_eq!(report.accesses, expected);
+        assert_eq!(*seen.lock().unwrap(), expected);
+
+        // Each report covers its own run, while the listener sees every access
+        runtime.run_script("sqlite.open(\":memory:\")").unwrap();
+        let report = runtime.run_script_with_report("1 + 1").unwrap();
+        assert_eq!(report.result.unwrap().as_int(), Some(2));
+        assert!(report.accesses.is_empty());
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let mut host = host();
+        let result = host.run("clamp(150, 0, 100)").unwrap();
+        assert_eq!(result.as_int(), Some(100));
+
+        let result = host
+            .runtime()
+            .call_function(
+                "clamp",
+                vec![
+                    EmbeddedValue::Int(-5),
+                    EmbeddedValue::Int(0),
+                    EmbeddedValue::Int(10),
+                ],
+            )
+            .unwrap();
+        assert_eq!(result.as_int(), Some(0));
+
+        let error = host.run("clamp(\"high\", 0, 10)").unwrap_err();
+        assert_eq!(error, "argument 'value' of clamp() expects int, got str");
+        assert!(host.run("clamp(1, 2)").is_err());
+
+        host.run("host_log(\"from a script\")").unwrap();
+        assert_eq!(host.log(), vec!["from a script".to_string()]);
+
+        let result = host.run("await fetch_setting(\"motd\")").unwrap();
+        assert_eq!(result.as_string(), Some("hi"));
+        let error = host.run("await fetch_setting(\"theme\")").unwrap_err();
+        assert_eq!(error, "no setting named 'theme'");
+    }
+
+    #[test]
+    fn test_host_declarations() {
+        let mut host = host();
+        let declarations = host.runtime().host_declarations().unwrap();
+        assert!(declarations.contains("def clamp(value: int, low: int, high: int) -> int"));
+        assert!(declarations.contains("async def fetch_setting(key: str)"));
+        assert!(declarations.contains("def host_log(message: str)"));
+    }
+
+    #[test]
+    fn test_module_loading() {
+        let mut host = host();
+        host.load_plugin("greeter", GREETER_PLUGIN).unwrap();
+        assert_eq!(host.runtime().get_loaded_modules(), vec!["greeter"]);
+        assert_eq!(host.log(), vec!["greeter loaded".to_string()]);
+
+        let result = host.run("from greeter import banner\nbanner").unwrap();
+        assert_eq!(result.as_string(), Some("Hello, hi"));
+        let result = host.run("import greeter\ngreeter.version").unwrap();
+        assert_eq!(result.as_int(), Some(2));
+
+        // Only exported names can be imported
+        let error = host.run("from greeter import motd").unwrap_err();
+        assert_eq!(error, "cannot import name 'motd' from module 'greeter'");
+
+        // A failing module is not loaded
+        assert!(host
+            .load_plugin("broken", "export x = await fetch_setting(\"missing\")")
+            .is_err());
+        assert!(host.runtime().get_module("broken").is_none());
+    }
+
+    #[test]
+    fn test_module_reload() {
+        let mut host = host();
+        host.load_plugin("greeter", GREETER_PLUGIN).unwrap();
+        host.run("from greeter import greeting").unwrap();
+
+        host.runtime()
+            .reload_module(
+                "greeter",
+                "export greeting = \"Hey, \"\nexport banner = \"\"",
+            )
+            .unwrap();
+        let result = host.run("greeting").unwrap();
+        assert_eq!(result.as_string(), Some("Hey, "));
+
+        // Imported names must survive a reload
+        let error = host
+            .runtime()
+            .reload_module("greeter", "export banner = \"\"")
+            .unwrap_err();
+        assert!(error.contains("greeting"), "{error}");
+
+        host.runtime().reset().unwrap();
+        assert!(host.runtime().get_loaded_modules().is_empty());
+    }
+
+    #[test]
+    fn test_events() {
+        let mut host = host();
+        let id = host.subscribe("plugin_loaded").unwrap();
+        host.load_plugin("greeter", GREETER_PLUGIN).unwrap();
+
+        let notifications = host.take_notifications();
+        assert_eq!(notifications.len(), 1);
+        let (event, args) = &notifications[0];
+        assert_eq!(event, "plugin_loaded");
+        assert_eq!(args[0].as_string(), Some("greeter"));
+        assert_eq!(args[1].as_int(), Some(2));
+
+        // The host can emit events too, and is told whether anything listened
+        let listened = host
+            .runtime()
+            .emit_event("plugin_loaded", vec![EmbeddedValue::String("host".into())])
+            .unwrap();
+        assert!(listened);
+        assert_eq!(host.take_notifications().len(), 1);
+
+        assert!(host.unsubscribe(id).unwrap());
+        assert!(!host.unsubscribe(id).unwrap());
+        assert!(!host.runtime().emit_event("plugin_loaded", vec![]).unwrap());
+        host.run("events.emit(\"plugin_loaded\", \"again\")")
+            .unwrap();
+        assert!(host.take_notifications().is_empty());
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let mut host = host();
+        let error = host.run("await wait_ms(1000)").unwrap_err();
+        assert_eq!(error, "host function 'wait_ms' timed out after 100 ms");
+        let result = host.run("await wait_ms(5)").unwrap();
+        assert_eq!(result.as_int(), Some(5));
+    }
+
+    #[test]
+    fn test_cancelled_calls_are_dropped() {
+        // Dropped together with the cancelled host future
+        struct Guard(Arc<AtomicBool>);
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut runtime = RuntimeBuilder::new().execution_timeout(20).build().unwrap();
+        let (on_drop, on_finish) = (Arc::clone(&dropped), Arc::clone(&finished));
+        runtime
+            .register_async_host_function("stuck", move |_args: Vec<EmbeddedValue>| {
+                let guard = Guard(Arc::clone(&on_drop));
+                let finished = Arc::clone(&on_finish);
+                async move {
+                    let _guard = guard;
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    finished.store(true, Ordering::SeqCst);
+                    Ok(EmbeddedValue::None)
+                }
+            })
+            .unwrap();
+
+        assert!(runtime.call_function("stuck", vec![]).is_err());
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_execution_limits() {
+        let mut runtime = RuntimeBuilder::new()
+            .execution_timeout(100)
+            .build()
+            .unwrap();
+        let started = std::time::Instant::now();
+        let error = runtime
+            .run_script("x = 0\nwhile true:\n    x = x + 1\n")
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "TimeoutError: script exceeded the execution timeout of 100 ms"
+        );
+        let error = runtime.run_script("timers.sleep(10000)").unwrap_err();
+        assert!(error.starts_with("TimeoutError"), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // Every run gets the whole budget again
+        assert_eq!(runtime.run_script("x > 0").unwrap().as_bool(), Some(true));
+
+        let mut runtime = RuntimeBuilder::new()
+            .instruction_limit(1000)
+            .build()
+            .unwrap();
+        runtime
+            .run_script(
+                "def count(n):\n    i = 0\n    while i < n:\n        i = i + 1\n    return i\n",
+            )
+            .unwrap();
+        let count = |runtime: &mut nagari_embedded::EmbeddedRuntime, n: i64| {
+            runtime.call_function("count", vec![EmbeddedValue::Int(n)])
+        };
+        assert_eq!(count(&mut runtime, 10).unwrap().as_int(), Some(10));
+        assert_eq!(
+            count(&mut runtime, 100_000).unwrap_err(),
+            "TimeoutError: script exceeded its limit of 1000 instructions"
+        );
+        assert_eq!(count(&mut runtime, 10).unwrap().as_int(), Some(10));
+    }
+
+    #[test]
+    fn test_yielding() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let mut runtime = RuntimeBuilder::new()
+            .yield_interval(Some(100))
+            .on_yield(move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .unwrap();
+
+        let script = "i = 0\nwhile i < 10000:\n    i = i + 1\n";
+        runtime.run_script(script).unwrap();
+        // Every iteration runs several instructions
+        let first = calls.swap(0, Ordering::SeqCst);
+        assert!(first >= 10_000 / 100, "{first}");
+        runtime.run_script(script).unwrap();
+        assert_eq!(calls.swap(0, Ordering::SeqCst), first);
+
+        // Each run starts a whole interval away from its first yield point
+        for _ in 0..100 {
+            runtime.run_script("x = 1").unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let mut runtime = RuntimeBuilder::new()
+            .yield_interval(None)
+            .on_yield(|| panic!("yielded with yield points off"))
+            .build()
+            .unwrap();
+        runtime.run_script(script).unwrap();
+    }
+
+    #[test]
+    fn test_resources() {
+        let mut runtime = RuntimeBuilder::new().build().unwrap();
+        let released = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&released);
+        runtime
+            .register_host_function("open_file", move |_| {
+                let counter = Arc::clone(&counter);
+                EmbeddedValue::Resource(Resource::new("file", move || {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }))
+            })
+            .unwrap();
+        let released = || released.load(Ordering::SeqCst);
+
+        // Closed at the end of the with statement, even though `file` is still around
+        let result = runtime
+            .run_script("with open_file() as file:\n    kind = file.kind\nfile.closed\n")
+            .unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+        assert_eq!(released(), 1);
+        assert!(runtime.run_script("with file:\n    x = 1\n").is_err());
+
+        // Released when a script forgets about it
+        runtime.run_script("forgotten = open_file()").unwrap();
+        assert_eq!(released(), 1);
+        runtime.run_script("forgotten = 0").unwrap();
+        assert_eq!(released(), 2);
+
+        // And when the body fails
+        let error = runtime
+            .run_script("with open_file() as failing:\n    y = 1 + \"a\"\n")
+            .unwrap_err();
+        assert!(error.contains("Cannot add int and str"), "{error}");
+        assert_eq!(released(), 3);
+
+        let result = runtime.run_script(
+            r#"
+class Log {
+    def __init__(self):
+        self.entries = []
+
+    def __enter__(self):
+        self.entries = self.entries + ["enter"]
+        return self
+
+    def __exit__(self, kind, error, trace):
+        self.entries = self.entries + ["exit"]
+}
+
+def closed(log, label):
+    log.entries = log.entries + [label]
+
+log = Log()
+handle = open_file()
+weakref.finalize(handle, closed, log, "finalized")
+probe = weakref.ref(handle)
+with log:
+    alive = probe() != null
+handle = 0
+[alive, probe() == null, log.entries]
+"#,
+        );
+        let result = result.unwrap();
+        let result = result.as_array().unwrap();
+        assert_eq!(result[0].as_bool(), Some(true));
+        assert_eq!(result[1].as_bool(), Some(true));
+        let entries: Vec<&str> = result[2]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.as_string().unwrap())
+            .collect();
+        assert_eq!(entries, ["enter", "exit", "finalized"]);
+        assert_eq!(released(), 4);
+
+        assert_eq!(
+            runtime.run_script("weakref.ref(1)").unwrap_err(),
+            "cannot create weak reference to 'int' object"
+        );
+    }
+
+    #[test]
+    fn test_globals() {
+        let mut host = host();
+        host.runtime()
+            .set_global("user", EmbeddedValue::String("ada".into()))
+            .unwrap();
+        let result = host.run("user").unwrap();
+        assert_eq!(result.as_string(), Some("ada"));
+
+        host.run("answer = 42").unwrap();
+        let answer = host.runtime().get_global("answer").unwrap();
+        assert_eq!(answer.and_then(|value| value.as_int()), Some(42));
+    }
+
+    #[test]
+    fn test_compiled_scripts() {
+        let mut runtime = RuntimeBuilder::new().build().unwrap();
+        let script = "total = 0\ni = 1\nwhile i <= 4:\n    total = total + i * i\n    i = i + 1\n[total, -i, total > 10 && i == 5]\n";
+        let result = runtime.run_script(script).unwrap();
+        let items = result.as_array().unwrap();
+        assert_eq!(items[0].as_int(), Some(30));
+        assert_eq!(items[1].as_int(), Some(-5));
+        assert_eq!(items[2].as_bool(), Some(true));
+        let total = runtime.get_global("total").unwrap().unwrap();
+        assert_eq!(total.as_int(), Some(30));
+
+        // A script ending with a statement has no value
+        assert!(matches!(
+            runtime.run_script("total = total * 2").unwrap(),
+            EmbeddedValue::None
+        ));
+        assert_eq!(
+            runtime.run_script("missing + 1").unwrap_err(),
+            "Undefined variable: missing"
+        );
+
+        // Functions run in a scope of their own, and keep working in later scripts
+        let script = "def fact(n):\n    if n <= 1:\n        return 1\n    rest = fact(n - 1)\n    return n * rest\nrest = 0\nfact(10)\n";
+        let result = runtime.run_script(script).unwrap();
+        assert_eq!(result.as_int(), Some(3628800));
+        let rest = runtime.get_global("rest").unwrap().unwrap();
+        assert_eq!(rest.as_int(), Some(0));
+        let result = runtime
+            .run_script("def twice(x): return x * 2\ntwice(fact(3))")
+            .unwrap();
+        assert_eq!(result.as_int(), Some(12));
+        let result = runtime
+            .call_function("twice", vec![EmbeddedValue::Int(4)])
+            .unwrap();
+        assert_eq!(result.as_int(), Some(8));
+        assert_eq!(
+            runtime.call_function("thrice", vec![]).unwrap_err(),
+            "name 'thrice' is not defined"
+        );
+        assert_eq!(
+            runtime.run_script("twice(1, 2)").unwrap_err(),
+            "twice() takes 1 argument(s) (2 given)"
+        );
+        assert!(runtime
+            .run_script("def twice(x = 1):\n    return x * 2\n")
+            .unwrap_err()
+            .contains("default parameter values are not supported"));
+    }
+
+    #[test]
+    fn test_script_classes() {
+        let mut runtime = RuntimeBuilder::new().build().unwrap();
+        let script = r#"
+class Shape {
+    sides = 0
+
+    def __init__(self, name):
+        self.name = name
+
+    def describe(self):
+        return self.name + " with " + str(self.sides) + " sides"
+}
+
+class Polygon extends Shape {
+    def __init__(self, name, sides):
+        super().__init__(name)
+        self.sides = sides
+}
+
+class Square extends Polygon {
+    def __init__(self):
+        super().__init__("square", 4)
+
+    def describe(self):
+        return "a " + super().describe()
+}
+
+def describe_all():
+    return [Square().describe(), Shape("dot").describe(), type(Square())]
+"#;
+        runtime.run_script(script).unwrap();
+        let result = runtime.call_function("describe_all", vec![]).unwrap();
+        let items = result.as_array().unwrap();
+        assert_eq!(items[0].as_string(), Some("a square with 4 sides"));
+        assert_eq!(items[1].as_string(), Some("dot with 0 sides"));
+        assert_eq!(items[2].as_string(), Some("Square"));
+
+        // Instances are shared, not copied
+        let result = runtime
+            .run_script("s = Square()\nt = s\nt.name = \"box\"\ns.describe()")
+            .unwrap();
+        assert_eq!(result.as_string(), Some("a box with 4 sides"));
+        assert_eq!(
+            runtime.run_script("Square(2)").unwrap_err(),
+            "__init__() takes 1 argument(s) (2 given)"
+        );
+        assert_eq!(
+            runtime.run_script("Square().radius").unwrap_err(),
+            "'Square' object has no attribute 'radius'"
+        );
+    }
+
+    #[test]
+    fn test_script_sets() {
+        let mut runtime = RuntimeBuilder::new().build().unwrap();
+        runtime
+            .run_script("a = {1, 2, 3, 2}\nb = set([2, 3, 4])\nf = frozenset(\"abca\")")
+            .unwrap();
+        let mut text = |code: &str| {
+            let result = runtime.run_script(&format!("str({code})")).unwrap();
+            result.as_string().unwrap().to_string()
+        };
+        assert_eq!(text("a"), "{1, 2, 3}");
+        assert_eq!(text("a | b"), "{1, 2, 3, 4}");
+        assert_eq!(text("a & b"), "{2, 3}");
+        assert_eq!(text("a - b"), "{1}");
+        assert_eq!(text("a ^ b"), "{1, 4}");
+        assert_eq!(text("a - a"), "set()");
+        // The result has the type of the left operand
+        assert_eq!(text("f | {\"d\"}"), "frozenset({a, b, c, d})");
+        assert_eq!(text("{\"d\"} | f"), "{d, a, b, c}");
+        assert_eq!(text("type(f)"), "frozenset");
+
+        let result = runtime
+            .run_script("[{1, 2} < a, a < a, a <= a, a >= b, {3, 2, 1} == a, frozenset(a) == a]")
+            .unwrap();
+        let flags: Vec<bool> = result
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|flag| flag.as_bool().unwrap())
+            .collect();
+        assert_eq!(flags, [true, false, true, false, true, true]);
+        assert_eq!(
+            runtime.run_script("a | 1").unwrap_err(),
+            "Cannot bitwise or set and int"
+        );
+    }
+
+    #[test]
+    fn test_heap_snapshot() {
+        let mut host = host();
+        let cache = |entries: usize| {
+            EmbeddedValue::Array(
+                (0..entries)
+                    .map(|i| EmbeddedValue::String(format!("entry {i}")))
+                    .collect(),
+            )
+        };
+        host.runtime().set_global("cache", cache(2)).unwrap();
+        let before = host.runtime().heap_snapshot().unwrap();
+
+        let root = before
+            .roots()
+            .find(|root| root.edge == "global cache")
+            .unwrap();
+        assert_eq!(root.type_name, "list");
+        assert_eq!(before.path(root.children[1]).unwrap(), "global cache[1]");
+        assert!(before.roots().all(|root| !root.edge.contains("print")));
+
+        host.runtime().set_global("cache", cache(50)).unwrap();
+        let after = host.runtime().heap_snapshot().unwrap();
+        let diff = HeapSnapshot::from_json(&before.to_json())
+            .unwrap()
+            .diff(&after);
+        assert_eq!(diff.roots[0].root, "global cache");
+        assert!(diff.roots[0].after > diff.roots[0].before);
+        let strings = diff
+            .types
+            .iter()
+            .find(|change| change.type_name == "str")
+            .unwrap();
+        assert_eq!(strings.after.count - strings.before.count, 48);
+    }
+
+    struct Player {
+        x: i64,
+        y: i64,
+        saved: Arc<AtomicUsize>,
+    }
+
+    impl NativeObject for Player {
+        fn type_name(&self) -> &str {
+            "Player"
+        }
+
+        fn methods() -> MethodTable<Self> {
+            MethodTable::new()
+                .method("move", |player: &mut Player, args| {
+                    player.x += args[0].as_int().unwrap_or(0);
+                    player.y += args[1].as_int().unwrap_or(0);
+                    Ok(EmbeddedValue::None)
+                })
+                .method_requiring("save", Capability::Io, |player: &mut Player, _| {
+                    player.saved.fetch_add(1, Ordering::SeqCst);
+                    Ok(EmbeddedValue::Bool(true))
+                })
+        }
+
+        fn get_property(&self, name: &str) -> Option<EmbeddedValue> {
+            match name {
+                "x" => Some(EmbeddedValue::Int(self.x)),
+                "y" => Some(EmbeddedValue::Int(self.y)),
+                _ => None,
+            }
+        }
+
+        fn set_property(&mut self, name: &str, value: EmbeddedValue) -> Result<(), String> {
+            match (name, value.as_int()) {
+                ("x", Some(x)) => self.x = x,
+                ("y", Some(y)) => self.y = y,
+                _ => return Err(format!("cannot set attribute '{name}' of 'Player' object")),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_native_objects() {
+        let saved = Arc::new(AtomicUsize::new(0));
+        let player = |saved: &Arc<AtomicUsize>| Player {
+            x: 0,
+            y: 0,
+            saved: Arc::clone(saved),
+        };
+
+        let mut runtime = RuntimeBuilder::new().build().unwrap();
+        runtime
+            .register_native_object("player", player(&saved))
+            .unwrap();
+        let result = runtime
+            .run_script(
+                "player.move(3, 4)
+step = player.move
+step(1, 1)
+player.y = 10
+[player.x, player.y]",
+            )
+            .unwrap();
+        let position: Vec<_> = result
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_int())
+            .collect();
+        assert_eq!(position, vec![Some(4), Some(10)]);
+        assert_eq!(
+            runtime.run_script("str(player)").unwrap().as_string(),
+            Some("<Player object>")
+        );
+        assert_eq!(
+            runtime.run_script("player.jump()").unwrap_err(),
+            "'Player' object has no attribute 'jump'"
+        );
+        assert!(runtime.run_script("player.name = \"a\"").is_err());
+
+        // Methods needing a capability only run where the runtime allows it
+        let error = runtime.run_script("player.save()").unwrap_err();
+        assert!(
+            error.starts_with("PermissionError: Player.save()"),
+            "{error}"
+        );
+        assert_eq!(saved.load(Ordering::SeqCst), 0);
+
+        let mut runtime = RuntimeBuilder::new().allow_io(true).build().unwrap();
+        runtime
+            .register_native_object("player", player(&saved))
+            .unwrap();
+        let result = runtime.run_script("player.save()").unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+        assert_eq!(saved.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_handles() {
+        struct Texture {
+            width: i64,
+        }
+
+        let mut runtime = RuntimeBuilder::new().build().unwrap();
+        let handles = runtime.handles();
+        let created = handles.clone();
+        runtime
+            .register_host_function("load_texture", move |args| {
+                let width = args[0].as_int().unwrap_or(0);
+                created.insert("texture", Texture { width })
+            })
+            .unwrap();
+        let used = handles.clone();
+        runtime
+            .register_host_function("texture_width", move |args| {
+                match used.with(&args[0], |texture: &mut Texture| texture.width) {
+                    Ok(width) => EmbeddedValue::Int(width),
+                    Err(error) => EmbeddedValue::String(error),
+                }
+            })
+            .unwrap();
+
+        // Scripts pass handles around but can't look inside
+        let result = runtime
+            .run_script("grass = load_texture(16)\ntextures = [grass]\ntexture_width(textures[0])")
+            .unwrap();
+        assert_eq!(result.as_int(), Some(16));
+        assert_eq!(
+            runtime.run_script("str(grass)").unwrap().as_string(),
+            Some("<texture handle>")
+        );
+        assert!(runtime.run_script("grass.width").is_err());
+        assert_eq!(
+            runtime
+                .run_script("grass == textures[0]")
+                .unwrap()
+                .as_bool(),
+            Some(true)
+        );
+
+        let grass = runtime.get_global("grass").unwrap().unwrap();
+        assert!(handles.with(&grass, |_: &mut String| ()).is_err());
+        assert_eq!(handles.len(), 1);
+
+        // Resetting the runtime invalidates handles the host still has
+        runtime.reset().unwrap();
+        assert!(handles.is_empty());
+        assert!(!handles.contains(&grass));
+        assert_eq!(
+            handles.with(&grass, |texture: &mut Texture| texture.width),
+            Err("invalid texture handle".to_string())
+        );
+
+        let stone = handles.insert("texture", Texture { width: 8 });
+        assert_eq!(handles.remove::<Texture>(&stone).unwrap().width, 8);
+        assert!(!handles.contains(&stone));
+    }
+
+    #[derive(Clone, Default)]
+    struct MemoryStore(Arc<Mutex<Vec<JobRecord>>>);
+
+    impl JobStore for MemoryStore {
+        fn load(&self) -> Result<Vec<JobRecord>, String> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn save(&self, jobs: &[JobRecord]) -> Result<(), String> {
+            *self.0.lock().unwrap() = jobs.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_scheduled_jobs() {
+        let store = MemoryStore::default();
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let runtime = |ticks: &Arc<AtomicUsize>| {
+            let mut runtime = RuntimeBuilder::new().build().unwrap();
+            let counter = Arc::clone(ticks);
+            runtime
+                .register_host_function("tick", move |_| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    EmbeddedValue::None
+                })
+                .unwrap();
+            runtime.set_job_store(store.clone()).unwrap();
+            runtime
+        };
+
+        let mut first = runtime(&ticks);
+        first
+            .run_script(
+                "def beat():\n    tick()\n\
+                 schedule.every(\"beat\", 200, beat)\n\
+                 schedule.cron(\"report\", \"0 9 * * mon-fri\", beat)",
+            )
+            .unwrap();
+        let jobs = first.jobs().unwrap();
+        assert_eq!(jobs[0].trigger, Trigger::Every(200));
+        assert_eq!(
+            jobs[1].trigger,
+            Trigger::Cron("0 9 * * mon-fri".to_string())
+        );
+        assert_eq!(jobs[1].function.as_deref(), Some("beat"));
+        // Cron expressions are in UTC
+        assert_eq!(jobs[1].next_run % 86_400_000, 9 * 3_600_000);
+        assert_eq!(*store.0.lock().unwrap(), jobs);
+
+        // Jobs only run when the host runs them, once they are due
+        assert_eq!(first.run_due_jobs().unwrap(), 0);
+        assert!(first.next_job_due().unwrap().is_some());
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(first.run_due_jobs().unwrap(), 1);
+        assert_eq!(ticks.load(Ordering::SeqCst), 1);
+        let beat = store.0.lock().unwrap()[0].clone();
+        assert!(beat.last_run.is_some());
+
+        // A restarted runtime picks the saved jobs up again once their function is defined
+        let mut second = runtime(&ticks);
+        second.run_script("def beat():\n    tick()").unwrap();
+        let mut restored = second.restore_jobs().unwrap();
+        restored.sort();
+        assert_eq!(restored, ["beat", "report"]);
+        assert_eq!(second.jobs().unwrap()[0], beat);
+
+        let cancelled = second.run_script("schedule.cancel(\"report\")").unwrap();
+        assert_eq!(cancelled.as_bool(), Some(true));
+        assert_eq!(store.0.lock().unwrap().len(), 1);
+        let error = second
+            .run_script("schedule.cron(\"never\", \"61 * * * *\", beat)")
+            .unwrap_err();
+        assert!(error.contains("61 is not within 0-59"), "{error}");
+
+        // Resetting the runtime removes its jobs but keeps them saved
+        second.reset().unwrap();
+        assert!(second.jobs().unwrap().is_empty());
+        assert_eq!(store.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_async_runtime() {
+        let runtime = RuntimeBuilder::new()
+            .execution_timeout(100)
+            .build_async()
+            .await
+            .unwrap();
+        runtime
+            .register_typed_host_function(
+                "double",
+                HostSignature::new().param("x", "int").returns("int"),
+                |args: Vec<EmbeddedValue>| async move {
+                    Ok(EmbeddedValue::Int(args[0].as_int().unwrap_or(0) * 2))
+                },
+            )
+            .await
+            .unwrap();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        runtime
+            .on_event("doubled", move |args| recorded.lock().unwrap().extend(args))
+            .await;
+
+        runtime
+            .load_module("math", "export four = await double(2)")
+            .await
+            .unwrap();
+        let result = runtime
+            .run_script("from math import four\nawait double(four)")
+            .await
+            .unwrap();
+        assert_eq!(result.as_int(), Some(8));
+
+        runtime
+            .emit_event("doubled", vec![EmbeddedValue::Int(8)])
+            .await
+            .unwrap();
+        assert_eq!(seen.lock().unwrap()[0].as_int(), Some(8));
+    }
+
+    #[tokio::test]
+    async fn test_async_runtime_yields() {
+        // Counts the turns of a task sharing the test's single thread with the script
+        let turns = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let turns = Arc::clone(&turns);
+            tokio::spawn(async move {
+                loop {
+                    turns.fetch_add(1, Ordering::SeqCst);
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        let observed = Arc::clone(&turns);
+        let runtime = RuntimeBuilder::new()
+            .yield_interval(Some(100))
+            .on_yield(move || {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(observed.load(Ordering::SeqCst))
+            })
+            .build_async()
+            .await
+            .unwrap();
+        runtime
+            .run_script("i = 0\nwhile i < 10000:\n    i = i + 1\n")
+            .await
+            .unwrap();
+        ticker.abort();
+
+        // The other task ran between the script's yield points
+        let seen = seen.lock().unwrap();
+        assert!(seen.len() >= 10_000 / 100, "{}", seen.len());
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]), "{seen:?}");
+    }
+}
@@ -1,11 +1,20 @@
 // Built-in function implementations for Nagari
 
+import { SafeHtml } from './interop.js';
+
 export function len(obj: any): number {
     if (obj === null || obj === undefined) {
         throw new Error("object has no len()");
     }
 
-    if (typeof obj === 'string' || Array.isArray(obj)) {
+    // Strings are measured in Unicode code points, not UTF-16 code units
+    if (typeof obj === 'string') {
+        let count = 0;
+        for (const _ of obj) count++;
+        return count;
+    }
+
+    if (Array.isArray(obj)) {
         return obj.length;
     }
 
@@ -27,6 +36,7 @@ export function type(obj: any): string {
 export function str(obj: any): string {
     if (obj === null || obj === undefined) return 'none';
     if (typeof obj === 'boolean') return obj ? 'true' : 'false';
+    if (obj instanceof SafeHtml) return obj.html;
     if (Array.isArray(obj)) {
         return '[' + obj.map(str).join(', ') + ']';
     }
@@ -256,6 +266,31 @@ export function str_center(s: string, width: number, fillchar: string = ' '): st
     return char.repeat(leftPadding) + s + char.repeat(rightPadding);
 }
 
+export function str_slice(s: string, start?: number, stop?: number, step: number = 1): string {
+    // Slice by code point so results agree with len()
+    const chars = Array.from(s);
+    const length = chars.length;
+    if (step === 0) {
+        throw new ValueError('slice step cannot be zero');
+    }
+
+    const bound = (index: number | undefined, fallback: number, low: number, high: number) => {
+        if (index === undefined || index === null) return fallback;
+        if (index < 0) index += length;
+        return Math.min(Math.max(index, low), high);
+    };
+
+    let result = '';
+    if (step > 0) {
+        const end = bound(stop, length, 0, length);
+        for (let i = bound(start, 0, 0, length); i < end; i += step) result += chars[i];
+    } else {
+        const end = bound(stop, -1, -1, length - 1);
+        for (let i = bound(start, length - 1, -1, length - 1); i > end; i += step) result += chars[i];
+    }
+    return result;
+}
+
 // Format helper functions for enhanced f-string support
 export function format_percentage(value: number, precision: number = 2): string {
     return (value * 100).toFixed(precision) + '%';
@@ -302,6 +302,1945 @@ export const JSONInterop = {
         JSON.stringify(nagariToJS(value), replacer, space), 'stringify')
 };
 
+/**
+ * unicodedata module: Unicode normalization and grapheme segmentation
+ */
+export const UnicodeDataInterop = {
+    normalize: wrapJSFunction((form: string, s: string) => {
+        if (!['NFC', 'NFD', 'NFKC', 'NFKD'].includes(form)) {
+            throw new Error(`invalid normalization form: '${form}'`);
+        }
+        return s.normalize(form);
+    }, 'normalize'),
+    graphemes: wrapJSFunction((s: string) => {
+        const Segmenter = (Intl as any).Segmenter;
+        if (Segmenter === undefined) {
+            // Without Intl.Segmenter fall back to code points
+            return Array.from(s);
+        }
+        return Array.from(new Segmenter(undefined, { granularity: 'grapheme' }).segment(s),
+            (part: any) => part.segment);
+    }, 'graphemes')
+};
+
+/**
+ * timers module: sleeping, and timeouts and intervals cancelled through integer handles.
+ * Every pending timer, sleeps included, is cancelled by InteropRegistry.reset().
+ */
+const pendingTimers: Map<number, () => void> = new Map();
+let nextTimerHandle = 0;
+
+function timerDelay(name: string, ms: number): number {
+    if (typeof ms !== 'number' || !Number.isFinite(ms) || ms < 0) {
+        throw new Error(`${name}() delay must be a non-negative number of milliseconds`);
+    }
+    return ms;
+}
+
+function startTimer(name: string, callback: Function, ms: number, repeat: boolean): number {
+    if (typeof callback !== 'function') {
+        throw new Error(`${name}() callback must be a function`);
+    }
+    const delay = timerDelay(name, ms);
+    const handle = ++nextTimerHandle;
+    if (repeat) {
+        const timer = setInterval(() => callback(), delay);
+        pendingTimers.set(handle, () => clearInterval(timer));
+    } else {
+        const timer = setTimeout(() => {
+            pendingTimers.delete(handle);
+            callback();
+        }, delay);
+        pendingTimers.set(handle, () => clearTimeout(timer));
+    }
+    return handle;
+}
+
+/**
+ * Cancel every pending timer; sleeping scripts fail with a cancellation error
+ */
+export function cancelAllTimers(): void {
+    const cancels = Array.from(pendingTimers.values());
+    pendingTimers.clear();
+    cancels.forEach(cancel => cancel());
+}
+
+// sleep() returns a promise, which wrapJSFunction would convert into a plain object
+const sleep = Object.assign(
+    (ms: number): Promise<null> => new Promise((resolve, reject) => {
+        const delay = timerDelay('sleep', ms);
+        const handle = ++nextTimerHandle;
+        const timer = setTimeout(() => {
+            pendingTimers.delete(handle);
+            resolve(null);
+        }, delay);
+        pendingTimers.set(handle, () => {
+            clearTimeout(timer);
+            reject(new Error('sleep() was cancelled'));
+        });
+    }),
+    { __nagari_function__: true as const, arity: 1 }
+);
+
+export const TimersInterop = {
+    sleep,
+    set_timeout: wrapJSFunction(
+        (callback: Function, ms: number) => startTimer('set_timeout', callback, ms, false),
+        'set_timeout'
+    ),
+    set_interval: wrapJSFunction(
+        (callback: Function, ms: number) => startTimer('set_interval', callback, ms, true),
+        'set_interval'
+    ),
+    cancel: wrapJSFunction((handle: number) => {
+        const cancel = pendingTimers.get(handle);
+        if (cancel === undefined) {
+            return false;
+        }
+        pendingTimers.delete(handle);
+        cancel();
+        return true;
+    }, 'cancel')
+};
+
+// Functions taking or returning bytes, readers or promises, which the conversions of
+// wrapJSFunction would turn into plain objects
+function rawFunction<F extends Function>(fn: F, arity: number): F & NagariFunction {
+    return Object.assign(fn, { __nagari_function__: true as const, arity }) as any;
+}
+
+/**
+ * csv module: parsing and formatting rows, reading and writing files, and readers going
+ * through large files a row at a time. Rows are lists of strings, or objects keyed by the
+ * header row when `header` is true. Mirrors the VM's module.
+ */
+type CsvRow = string[] | { [column: string]: string };
+
+function csvDelimiter(name: string, delimiter: string = ','): string {
+    if (typeof delimiter !== 'string' || Array.from(delimiter).length !== 1 ||
+        delimiter === '"' || delimiter === '\n' || delimiter === '\r') {
+        throw new Error(`${name}() delimiter must be one character other than a quote or line break, got ${delimiter}`);
+    }
+    return delimiter;
+}
+
+// Rows parsed from lines of text, with what is left of a record whose quoted field goes on
+// to the next line
+class CsvRecords {
+    private header: string[] | null | undefined;
+    private pending = '';
+    private line = 0;
+    private linesRead = 0;
+    private readonly delimiter: string;
+
+    constructor(name: string, header: boolean = false, delimiter?: string) {
+        if (typeof header !== 'boolean') {
+            throw new Error(`${name}() header must be a bool`);
+        }
+        // null until the header row is read
+        this.header = header ? null : undefined;
+        this.delimiter = csvDelimiter(name, delimiter);
+    }
+
+    // Add a line, returning the row it completes if any
+    push(line: string): CsvRow | null {
+        this.linesRead += 1;
+        if (this.pending === '') {
+            this.line = this.linesRead;
+        }
+        this.pending += line;
+        // Inside a quoted field while the quotes don't pair up
+        if (this.pending.split('"').length % 2 === 0) {
+            return null;
+        }
+        const record = this.pending;
+        this.pending = '';
+        return this.row(record);
+    }
+
+    // The row of the last record, at the end of the input
+    finish(): CsvRow | null {
+        if (this.pending === '') {
+            return null;
+        }
+        if (this.pending.split('"').length % 2 === 0) {
+            throw new Error(`line ${this.line}: quoted field is not closed`);
+        }
+        const record = this.pending;
+        this.pending = '';
+        return this.row(record);
+    }
+
+    private row(record: string): CsvRow | null {
+        record = record.replace(/\r?\n$/, '');
+        if (record === '') {
+            return null;
+        }
+        let fields: string[];
+        try {
+            fields = splitCsvFields(record, this.delimiter);
+        } catch (error: any) {
+            throw new Error(`line ${this.line}: ${error.message}`);
+        }
+        if (this.header === undefined) {
+            return fields;
+        }
+        if (this.header === null) {
+            this.header = fields;
+            return null;
+        }
+        if (fields.length !== this.header.length) {
+            throw new Error(`line ${this.line}: row has ${fields.length} fields, the header has ${this.header.length}`);
+        }
+        const row: { [column: string]: string } = {};
+        this.header.forEach((column, index) => {
+            row[column] = fields[index];
+        });
+        return row;
+    }
+}
+
+// The fields of one record, without its line break
+function splitCsvFields(record: string, delimiter: string): string[] {
+    const fields: string[] = [];
+    let i = 0;
+    for (;;) {
+        let field = '';
+        if (record[i] === '"') {
+            i += 1;
+            for (;;) {
+                if (i >= record.length) {
+                    throw new Error('quoted field is not closed');
+                }
+                if (record[i] === '"' && record[i + 1] === '"') {
+                    field += '"';
+                    i += 2;
+                } else if (record[i] === '"') {
+                    i += 1;
+                    break;
+                } else {
+                    field += record[i];
+                    i += 1;
+                }
+            }
+            fields.push(field);
+            if (i >= record.length) {
+                return fields;
+            }
+            if (!record.startsWith(delimiter, i)) {
+                throw new Error(`unexpected '${Array.from(record.slice(i))[0]}' after a quoted field`);
+            }
+            i += delimiter.length;
+        } else {
+            const end = record.indexOf(delimiter, i);
+            if (end < 0) {
+                fields.push(record.slice(i));
+                return fields;
+            }
+            fields.push(record.slice(i, end));
+            i = end + delimiter.length;
+        }
+    }
+}
+
+function parseCsv(name: string, text: string, header?: boolean, delimiter?: string): CsvRow[] {
+    if (typeof text !== 'string') {
+        throw new Error(`${name}() text must be a str`);
+    }
+    const records = new CsvRecords(name, header, delimiter);
+    const rows: CsvRow[] = [];
+    // A record ends with a line break, or the end of the text
+    for (const line of text.match(/[^\n]*\n|[^\n]+$/g) || []) {
+        const row = records.push(line);
+        if (row !== null) {
+            rows.push(row);
+        }
+    }
+    const last = records.finish();
+    if (last !== null) {
+        rows.push(last);
+    }
+    return rows;
+}
+
+function csvCell(value: any): string {
+    if (value === null || value === undefined) {
+        return '';
+    }
+    if (typeof value === 'boolean') {
+        return value ? 'true' : 'false';
+    }
+    return String(value);
+}
+
+function formatCsv(name: string, rows: any[], header?: string[] | null, delimiter?: string): string {
+    if (!Array.isArray(rows)) {
+        throw new Error(`${name}() rows must be a list`);
+    }
+    if (header !== undefined && header !== null &&
+        (!Array.isArray(header) || header.some(column => typeof column !== 'string'))) {
+        throw new Error(`${name}() header must be a list of column names`);
+    }
+    const separator = csvDelimiter(name, delimiter);
+    const quote = (field: string) =>
+        field.includes(separator) || /["\r\n]/.test(field)
+            ? `"${field.replace(/"/g, '""')}"`
+            : field;
+    const lines: string[] = [];
+    const writeRow = (fields: string[]) => lines.push(fields.map(quote).join(separator) + '\n');
+    if (header) {
+        writeRow(header);
+    }
+    rows.forEach((row, index) => {
+        if (Array.isArray(row)) {
+            writeRow(row.map(csvCell));
+        } else if (row !== null && typeof row === 'object') {
+            if (!header) {
+                throw new Error(`${name}() needs a header to write dict rows, for the order of the columns`);
+            }
+            writeRow(header.map(column => csvCell(row[column])));
+        } else {
+            throw new Error(`${name}() row ${index} must be a list or dict`);
+        }
+    });
+    return lines.join('');
+}
+
+// A file read in chunks, going through the records of the lines it holds
+class CsvReader {
+    private buffer = '';
+    private done = false;
+    private readonly decoder = new TextDecoder();
+
+    constructor(private file: any, private records: CsvRecords) {}
+
+    async next(): Promise<CsvRow | null> {
+        for (;;) {
+            const newline = this.buffer.indexOf('\n');
+            if (newline >= 0) {
+                const line = this.buffer.slice(0, newline + 1);
+                this.buffer = this.buffer.slice(newline + 1);
+                const row = this.records.push(line);
+                if (row !== null) {
+                    return row;
+                }
+            } else if (this.done || this.file === null) {
+                const line = this.buffer;
+                this.buffer = '';
+                if (line !== '') {
+                    const row = this.records.push(line);
+                    if (row !== null) {
+                        return row;
+                    }
+                }
+                return this.records.finish();
+            } else {
+                const chunk = new Uint8Array(64 * 1024);
+                const { bytesRead } = await this.file.read(chunk, 0, chunk.length, null);
+                this.done = bytesRead === 0;
+                this.buffer += this.decoder.decode(chunk.subarray(0, bytesRead), { stream: !this.done });
+            }
+        }
+    }
+
+    async close(): Promise<boolean> {
+        if (this.file === null) {
+            return false;
+        }
+        const file = this.file;
+        this.file = null;
+        this.done = true;
+        await file.close();
+        return true;
+    }
+
+    async *[Symbol.asyncIterator](): AsyncGenerator<CsvRow> {
+        for (let row = await this.next(); row !== null; row = await this.next()) {
+            yield row;
+        }
+    }
+}
+
+export const CsvInterop = {
+    parse: rawFunction(
+        (text: string, header?: boolean, delimiter?: string) => parseCsv('parse', text, header, delimiter),
+        1
+    ),
+    format: rawFunction(
+        (rows: any[], header?: string[] | null, delimiter?: string) => formatCsv('format', rows, header, delimiter),
+        1
+    ),
+    read: rawFunction(async (path: string, header?: boolean, delimiter?: string) => {
+        const fs = await import('node:fs/promises');
+        const text = await fs.readFile(path, 'utf8').catch((error: any) => {
+            throw new Error(`read(): cannot read '${path}': ${error.message}`);
+        });
+        return parseCsv('read', text, header, delimiter);
+    }, 1),
+    write: rawFunction(async (path: string, rows: any[], header?: string[] | null, delimiter?: string) => {
+        const text = formatCsv('write', rows, header, delimiter);
+        const fs = await import('node:fs/promises');
+        await fs.writeFile(path, text, 'utf8').catch((error: any) => {
+            throw new Error(`write(): cannot write '${path}': ${error.message}`);
+        });
+        return null;
+    }, 2),
+    open: rawFunction(async (path: string, header?: boolean, delimiter?: string) => {
+        const records = new CsvRecords('open', header, delimiter);
+        const fs = await import('node:fs/promises');
+        const file = await fs.open(path, 'r').catch((error: any) => {
+            throw new Error(`open(): cannot open '${path}': ${error.message}`);
+        });
+        return new CsvReader(file, records);
+    }, 1),
+    next_row: rawFunction((reader: CsvReader) => {
+        if (!(reader instanceof CsvReader)) {
+            throw new Error('next_row() takes a reader from open()');
+        }
+        return reader.next();
+    }, 1),
+    close: rawFunction((reader: CsvReader) => {
+        if (!(reader instanceof CsvReader)) {
+            throw new Error('close() takes a reader from open()');
+        }
+        return reader.close();
+    }, 1)
+};
+
+/**
+ * struct module: packing values into the bytes of binary records and back, with Python's
+ * format codes, standard sizes and no alignment. Bytes are Uint8Arrays. Mirrors the VM's
+ * module.
+ */
+type StructField = { code: string; size: number };
+
+const STRUCT_SIZES: { [code: string]: number } = {
+    x: 1, c: 1, '?': 1, b: 1, B: 1, h: 2, H: 2, i: 4, I: 4, l: 4, L: 4, q: 8, Q: 8, f: 4, d: 8
+};
+
+function parseStructFormat(format: string): { littleEndian: boolean; fields: StructField[] } {
+    if (typeof format !== 'string') {
+        throw new Error('struct format must be a str');
+    }
+    let spec = format.replace(/\s+/g, '');
+    let littleEndian = new Uint8Array(new Uint16Array([1]).buffer)[0] === 1;
+    if (spec !== '' && '<>!@='.includes(spec[0])) {
+        if (spec[0] === '<') littleEndian = true;
+        if (spec[0] === '>' || spec[0] === '!') littleEndian = false;
+        spec = spec.slice(1);
+    }
+    const fields: StructField[] = [];
+    const pattern = /(\d*)(.)/g;
+    let match: RegExpExecArray | null;
+    while ((match = pattern.exec(spec)) !== null) {
+        const [, digits, code] = match;
+        if (/\d/.test(code)) {
+            throw new Error(`bad struct format '${format}': count without a code`);
+        }
+        const count = digits === '' ? 1 : parseInt(digits, 10);
+        if (code === 's') {
+            // The count of `s` is the length of one field
+            fields.push({ code, size: count });
+        } else if (code in STRUCT_SIZES) {
+            for (let i = 0; i < count; i++) {
+                fields.push({ code, size: STRUCT_SIZES[code] });
+            }
+        } else {
+            throw new Error(`bad struct format '${format}': unknown code '${code}'`);
+        }
+    }
+    return { littleEndian, fields };
+}
+
+function structSize(fields: StructField[]): number {
+    return fields.reduce((size, field) => size + field.size, 0);
+}
+
+function structBytes(name: string, data: any): Uint8Array {
+    if (data instanceof Uint8Array) return data;
+    if (data instanceof ArrayBuffer) return new Uint8Array(data);
+    throw new Error(`${name}() data must be bytes`);
+}
+
+function typeOfValue(value: any): string {
+    if (value === null || value === undefined) return 'none';
+    if (value instanceof Uint8Array) return 'bytes';
+    if (typeof value === 'number') return Number.isInteger(value) ? 'int' : 'float';
+    if (typeof value === 'string') return 'str';
+    if (typeof value === 'boolean') return 'bool';
+    return Array.isArray(value) ? 'list' : 'dict';
+}
+
+function packStruct(format: string, values: any[]): Uint8Array {
+    const { littleEndian, fields } = parseStructFormat(format);
+    const expected = fields.filter(field => field.code !== 'x').length;
+    if (values.length !== expected) {
+        throw new Error(`pack() expected ${expected} values, got ${values.length}`);
+    }
+    const data = new Uint8Array(structSize(fields));
+    const view = new DataView(data.buffer);
+    let offset = 0;
+    let index = 0;
+    for (const { code, size } of fields) {
+        if (code === 'x') {
+            offset += size;
+            continue;
+        }
+        const value = values[index];
+        const mismatch = (expected: string) =>
+            new Error(`pack() value ${index} must be ${expected}, not '${typeOfValue(value)}'`);
+        if (code === 'c') {
+            if (!(value instanceof Uint8Array) || value.length !== 1) throw mismatch('bytes of length 1');
+            data[offset] = value[0];
+        } else if (code === '?') {
+            data[offset] = value ? 1 : 0;
+        } else if (code === 's') {
+            const bytes = typeof value === 'string' ? new TextEncoder().encode(value) : value;
+            if (!(bytes instanceof Uint8Array)) throw mismatch('bytes or a str');
+            data.set(bytes.subarray(0, size), offset);
+        } else if (code === 'f' || code === 'd') {
+            if (typeof value !== 'number') throw mismatch('a number');
+            if (code === 'f') view.setFloat32(offset, value, littleEndian);
+            else view.setFloat64(offset, value, littleEndian);
+        } else {
+            if (typeof value !== 'number' || !Number.isInteger(value)) throw mismatch('an int');
+            const signed = code === code.toLowerCase();
+            const bits = BigInt(size * 8);
+            const n = BigInt(value);
+            const inRange = signed
+                ? n >= -(1n << (bits - 1n)) && n < 1n << (bits - 1n)
+                : n >= 0n && n < 1n << bits;
+            if (!inRange) {
+                throw new Error(`pack() value ${index} does not fit in ${signed ? 'a signed' : 'an unsigned'} ${size * 8}-bit field`);
+            }
+            const unsigned = BigInt.asUintN(size * 8, n);
+            for (let i = 0; i < size; i++) {
+                const byte = Number((unsigned >> BigInt(8 * i)) & 0xffn);
+                data[offset + (littleEndian ? i : size - 1 - i)] = byte;
+            }
+        }
+        offset += size;
+        index += 1;
+    }
+    return data;
+}
+
+function unpackStruct(format: string, data: Uint8Array): any[] {
+    const { littleEndian, fields } = parseStructFormat(format);
+    const view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+    const values: any[] = [];
+    let offset = 0;
+    for (const { code, size } of fields) {
+        if (code === 'c' || code === 's') {
+            values.push(data.slice(offset, offset + size));
+        } else if (code === '?') {
+            values.push(data[offset] !== 0);
+        } else if (code === 'f') {
+            values.push(view.getFloat32(offset, littleEndian));
+        } else if (code === 'd') {
+            values.push(view.getFloat64(offset, littleEndian));
+        } else if (code !== 'x') {
+            let n = 0n;
+            for (let i = 0; i < size; i++) {
+                n |= BigInt(data[offset + (littleEndian ? i : size - 1 - i)]) << BigInt(8 * i);
+            }
+            if (code === code.toLowerCase()) {
+                n = BigInt.asIntN(size * 8, n);
+            }
+            if (n > BigInt(Number.MAX_SAFE_INTEGER) || n < BigInt(Number.MIN_SAFE_INTEGER)) {
+                throw new Error(`unpack() value ${values.length} does not fit in a JavaScript number`);
+            }
+            values.push(Number(n));
+        }
+        offset += size;
+    }
+    return values;
+}
+
+export const StructInterop = {
+    pack: rawFunction((format: string, ...values: any[]) => packStruct(format, values), 1),
+    unpack: rawFunction((format: string, data: any) => {
+        const bytes = structBytes('unpack', data);
+        const size = structSize(parseStructFormat(format).fields);
+        if (bytes.length !== size) {
+            throw new Error(`unpack() needs ${size} bytes, got ${bytes.length}`);
+        }
+        return unpackStruct(format, bytes);
+    }, 2),
+    unpack_from: rawFunction((format: string, data: any, offset: number = 0) => {
+        const bytes = structBytes('unpack_from', data);
+        if (!Number.isInteger(offset) || offset < 0) {
+            throw new Error('unpack_from() offset must be a non-negative int');
+        }
+        const end = offset + structSize(parseStructFormat(format).fields);
+        if (end > bytes.length) {
+            throw new Error(`unpack_from() needs ${end} bytes from offset ${offset}, got ${bytes.length}`);
+        }
+        return unpackStruct(format, bytes.subarray(offset, end));
+    }, 2),
+    calcsize: rawFunction((format: string) => structSize(parseStructFormat(format).fields), 1)
+};
+
+/**
+ * path module: joining, splitting and normalizing paths with the platform's separators, and
+ * globbing. Only glob looks at the file system. Mirrors the VM's module.
+ */
+const PATH_WINDOWS = (globalThis as any).process?.platform === 'win32';
+const PATH_SEP = PATH_WINDOWS ? '\\' : '/';
+
+function isPathSeparator(c: string): boolean {
+    return c === '/' || (PATH_WINDOWS && c === '\\');
+}
+
+// Length of the root of a path: `/` on Unix, and a drive letter, a separator or both on Windows
+function pathRootLength(path: string): number {
+    let length = PATH_WINDOWS && /^[A-Za-z]:/.test(path) ? 2 : 0;
+    if (length < path.length && isPathSeparator(path[length])) {
+        length += 1;
+    }
+    return length;
+}
+
+function splitPath(path: string): { root: string; components: string[] } {
+    const rootLength = pathRootLength(path);
+    const root = Array.from(path.slice(0, rootLength), c => isPathSeparator(c) ? PATH_SEP : c).join('');
+    const components = path.slice(rootLength).split(PATH_WINDOWS ? /[\\/]/ : '/').filter(c => c !== '');
+    return { root, components };
+}
+
+function normalizePath(path: string): string {
+    const { root, components } = splitPath(path);
+    const absolute = root.endsWith(PATH_SEP);
+    const normalized: string[] = [];
+    for (const component of components) {
+        if (component === '.') {
+            continue;
+        }
+        if (component === '..') {
+            if (normalized.length > 0 && normalized[normalized.length - 1] !== '..') {
+                normalized.pop();
+            } else if (!absolute) {
+                // Nothing is above the root
+                normalized.push('..');
+            }
+            continue;
+        }
+        normalized.push(component);
+    }
+    const joined = normalized.join(PATH_SEP);
+    return root === '' && joined === '' ? '.' : root + joined;
+}
+
+// A path without trailing separators, unless it is all root
+function trimPathEnd(path: string): string {
+    const root = pathRootLength(path);
+    let end = path.length;
+    while (end > 0 && isPathSeparator(path[end - 1])) {
+        end -= 1;
+    }
+    return path.slice(0, Math.max(end, root));
+}
+
+function lastSeparator(path: string, from: number): number {
+    for (let i = path.length - 1; i >= from; i--) {
+        if (isPathSeparator(path[i])) {
+            return i;
+        }
+    }
+    return -1;
+}
+
+function pathBasename(path: string): string {
+    path = trimPathEnd(path);
+    const root = pathRootLength(path);
+    return path.slice(Math.max(lastSeparator(path, root) + 1, root));
+}
+
+function samePathPart(a: string, b: string): boolean {
+    return PATH_WINDOWS ? a.toLowerCase() === b.toLowerCase() : a === b;
+}
+
+function pathArgument(name: string, value: any): string {
+    if (typeof value !== 'string') {
+        throw new Error(`${name}() arguments must be str, not '${typeOfValue(value)}'`);
+    }
+    return value;
+}
+
+// Whether a name matches one component of a glob pattern, going through both once as the VM
+// does: on a mismatch, the last `*` takes one more character and matching resumes after it
+function matchesGlobComponent(patternText: string, nameText: string): boolean {
+    if (nameText.startsWith('.') && !patternText.startsWith('.')) {
+        return false;
+    }
+    const pattern = Array.from(patternText);
+    const name = Array.from(nameText);
+    let p = 0;
+    let n = 0;
+    // Where matching resumes after the last `*`: its pattern position and the next name one
+    let star: [number, number] | null = null;
+    while (n < name.length) {
+        if (pattern[p] === '*') {
+            p += 1;
+            star = [p, n];
+            continue;
+        }
+        const length = globCharacterLength(pattern, p, name[n]);
+        if (length > 0) {
+            p += length;
+            n += 1;
+            continue;
+        }
+        if (star === null) {
+            return false;
+        }
+        p = star[0];
+        n = star[1] + 1;
+        star = [p, n];
+    }
+    return pattern.slice(p).every(c => c === '*');
+}
+
+// Length of the one-character wildcard, class or literal at `p` in a glob pattern if it
+// matches `ch`, and 0 if not
+function globCharacterLength(pattern: string[], p: number, ch: string): number {
+    const c = pattern[p];
+    if (c === undefined || c === '*') {
+        return 0;
+    }
+    if (c === '?') {
+        return 1;
+    }
+    if (c === '[') {
+        const negated = pattern[p + 1] === '!';
+        const start = p + 1 + (negated ? 1 : 0);
+        // A `]` right after the `[` is part of the set
+        const end = pattern.indexOf(']', start + 1);
+        if (end !== -1) {
+            const set = pattern.slice(start, end);
+            let found = false;
+            for (let i = 0; i < set.length;) {
+                if (i + 2 < set.length && set[i + 1] === '-') {
+                    found = found || (set[i] <= ch && ch <= set[i + 2]);
+                    i += 3;
+                } else {
+                    found = found || set[i] === ch;
+                    i += 1;
+                }
+            }
+            return found !== negated ? end + 1 - p : 0;
+        }
+        // An unclosed `[` is a literal
+    }
+    return ch === c ? 1 : 0;
+}
+
+function childPath(base: string, name: string): string {
+    return base === '' || base.endsWith(PATH_SEP) ? base + name : base + PATH_SEP + name;
+}
+
+async function globPaths(pattern: string): Promise<string[]> {
+    const fs = await import('node:fs/promises');
+    const entries = async (dir: string) => fs.readdir(dir, { withFileTypes: true }).catch(() => []);
+    const descendants = async (base: string, found: string[]) => {
+        found.push(base);
+        for (const entry of await entries(base === '' ? '.' : base)) {
+            if (!entry.name.startsWith('.') && entry.isDirectory()) {
+                await descendants(childPath(base, entry.name), found);
+            }
+        }
+    };
+
+    const { root, components } = splitPath(pattern);
+    // Every match stays below where globbing starts looking, as in the VM
+    const wildcard = components.findIndex(component => /[*?[]/.test(component));
+    if (wildcard !== -1 && components.slice(wildcard).includes('..')) {
+        throw new Error(`glob(): '..' can't follow a wildcard, as in '${pattern}'`);
+    }
+    if (components.length === 0) {
+        return [];
+    }
+    let matches = [root];
+    for (let index = 0; index < components.length; index++) {
+        const component = components[index];
+        const last = index === components.length - 1;
+        const next: string[] = [];
+        for (const base of matches) {
+            if (component === '**') {
+                await descendants(base, next);
+            } else if (/[*?[]/.test(component)) {
+                for (const entry of await entries(base === '' ? '.' : base)) {
+                    if ((last || entry.isDirectory()) && matchesGlobComponent(component, entry.name)) {
+                        next.push(childPath(base, entry.name));
+                    }
+                }
+            } else {
+                const path = childPath(base, component);
+                if (await fs.access(path).then(() => true, () => false)) {
+                    next.push(path);
+                }
+            }
+        }
+        matches = Array.from(new Set(next)).sort();
+    }
+    return matches;
+}
+
+export const PathInterop = {
+    sep: PATH_SEP,
+    join: rawFunction((...parts: string[]) => {
+        let joined = '';
+        for (const part of parts.map(part => pathArgument('join', part))) {
+            if (part === '') {
+                continue;
+            }
+            if (pathRootLength(part) > 0) {
+                joined = '';
+            } else if (joined !== '' && !isPathSeparator(joined[joined.length - 1])) {
+                joined += PATH_SEP;
+            }
+            joined += part;
+        }
+        return normalizePath(joined);
+    }, 0),
+    dirname: rawFunction((path: string) => {
+        path = trimPathEnd(pathArgument('dirname', path));
+        const root = pathRootLength(path);
+        const index = lastSeparator(path, root);
+        if (index === -1) {
+            return root > 0 ? path.slice(0, root) : '.';
+        }
+        return trimPathEnd(path.slice(0, index)) || path.slice(0, root);
+    }, 1),
+    basename: rawFunction((path: string) => pathBasename(pathArgument('basename', path)), 1),
+    extension: rawFunction((path: string) => {
+        const name = pathBasename(pathArgument('extension', path));
+        const index = name.lastIndexOf('.');
+        return index > 0 ? name.slice(index) : '';
+    }, 1),
+    normalize: rawFunction((path: string) => normalizePath(pathArgument('normalize', path)), 1),
+    relative: rawFunction((from: string, to: string) => {
+        from = normalizePath(pathArgument('relative', from));
+        to = normalizePath(pathArgument('relative', to));
+        const source = splitPath(from);
+        const target = splitPath(to);
+        if (!samePathPart(source.root, target.root)) {
+            throw new Error(`relative(): '${from}' and '${to}' don't have the same root`);
+        }
+        let common = 0;
+        while (common < source.components.length && common < target.components.length &&
+            samePathPart(source.components[common], target.components[common])) {
+            common += 1;
+        }
+        if (source.components.slice(common).includes('..')) {
+            throw new Error(`relative(): can't tell the way from '${from}' to '${to}' without the current directory`);
+        }
+        const parts = [
+            ...source.components.slice(common).map(() => '..'),
+            ...target.components.slice(common)
+        ];
+        return parts.length === 0 ? '.' : parts.join(PATH_SEP);
+    }, 2),
+    is_absolute: rawFunction((path: string) => {
+        path = pathArgument('is_absolute', path);
+        const root = pathRootLength(path);
+        return root > 0 && isPathSeparator(path[root - 1]);
+    }, 1),
+    glob: rawFunction((pattern: string) => globPaths(pathArgument('glob', pattern)), 1)
+};
+
+/**
+ * crypto module: hashes and HMACs through WebCrypto, which Node and browsers both provide,
+ * base64 and hex encodings, and random UUIDs. Hashes and HMACs return promises, as WebCrypto
+ * digests asynchronously. Mirrors the VM's module.
+ */
+function cryptoBytes(name: string, data: any): Uint8Array {
+    if (typeof data === 'string') return new TextEncoder().encode(data);
+    if (data instanceof Uint8Array) return data;
+    throw new Error(`${name}() data must be str or bytes, not '${typeOfValue(data)}'`);
+}
+
+function webCrypto(): any {
+    const crypto = (globalThis as any).crypto;
+    if (!crypto?.subtle) {
+        throw new Error('crypto: WebCrypto is not available in this environment');
+    }
+    return crypto;
+}
+
+function toHex(bytes: Uint8Array): string {
+    return Array.from(bytes, byte => byte.toString(16).padStart(2, '0')).join('');
+}
+
+// Decoded bytes as a Uint8Array, or as a string unless asBytes is true
+function decodedValue(name: string, bytes: Uint8Array, asBytes: boolean = false): Uint8Array | string {
+    if (typeof asBytes !== 'boolean') {
+        throw new Error(`${name}() as_bytes must be a bool, not '${typeOfValue(asBytes)}'`);
+    }
+    if (asBytes) {
+        return bytes;
+    }
+    try {
+        return new TextDecoder('utf-8', { fatal: true }).decode(bytes);
+    } catch {
+        throw new Error(`${name}(): decoded data is not UTF-8 text; pass true for as_bytes to get bytes`);
+    }
+}
+
+async function cryptoDigest(algorithm: string, name: string, data: any): Promise<string> {
+    return toHex(new Uint8Array(await webCrypto().subtle.digest(algorithm, cryptoBytes(name, data))));
+}
+
+export const CryptoInterop = {
+    hash_sha1: rawFunction((data: any) => cryptoDigest('SHA-1', 'hash_sha1', data), 1),
+    hash_sha256: rawFunction((data: any) => cryptoDigest('SHA-256', 'hash_sha256', data), 1),
+    hmac: rawFunction(async (key: any, data: any, algorithm: string = 'sha256') => {
+        if (algorithm !== 'sha1' && algorithm !== 'sha256') {
+            throw new Error(`hmac() algorithm must be "sha1" or "sha256", got ${algorithm}`);
+        }
+        const { subtle } = webCrypto();
+        const hash = algorithm === 'sha1' ? 'SHA-1' : 'SHA-256';
+        const cryptoKey = await subtle.importKey(
+            'raw', cryptoBytes('hmac', key), { name: 'HMAC', hash }, false, ['sign']
+        );
+        return toHex(new Uint8Array(await subtle.sign('HMAC', cryptoKey, cryptoBytes('hmac', data))));
+    }, 2),
+    base64_encode: rawFunction((data: any) => {
+        const bytes = cryptoBytes('base64_encode', data);
+        let binary = '';
+        for (const byte of bytes) {
+            binary += String.fromCharCode(byte);
+        }
+        return btoa(binary);
+    }, 1),
+    base64_decode: rawFunction((text: string, asBytes?: boolean) => {
+        if (typeof text !== 'string') {
+            throw new Error(`base64_decode() text must be a str, not '${typeOfValue(text)}'`);
+        }
+        let binary: string;
+        try {
+            binary = atob(text.trim());
+        } catch {
+            throw new Error('base64_decode(): invalid base64 text');
+        }
+        return decodedValue('base64_decode', Uint8Array.from(binary, c => c.charCodeAt(0)), asBytes);
+    }, 1),
+    hex_encode: rawFunction((data: any) => toHex(cryptoBytes('hex_encode', data)), 1),
+    hex_decode: rawFunction((text: string, asBytes?: boolean) => {
+        if (typeof text !== 'string') {
+            throw new Error(`hex_decode() text must be a str, not '${typeOfValue(text)}'`);
+        }
+        text = text.trim();
+        if (text.length % 2 !== 0) {
+            throw new Error('hex_decode(): text must have an even number of digits');
+        }
+        const bytes = new Uint8Array(text.length / 2);
+        for (let i = 0; i < text.length; i += 2) {
+            const pair = text.slice(i, i + 2);
+            if (!/^[0-9a-fA-F]{2}$/.test(pair)) {
+                throw new Error(`hex_decode(): invalid hex digits at ${i}`);
+            }
+            bytes[i / 2] = parseInt(pair, 16);
+        }
+        return decodedValue('hex_decode', bytes, asBytes);
+    }, 1),
+    uuid4: rawFunction(() => webCrypto().randomUUID(), 0)
+};
+
+/**
+ * intl module: locale-aware numbers, currencies, percents and dates through Intl. Dates are
+ * formatted in UTC and currencies with their narrow symbols, as the VM's module does.
+ */
+const DATE_STYLES = ['short', 'medium', 'long', 'full'];
+const TIME_STYLES = ['short', 'medium'];
+
+function intlDigits(name: string, decimals: number | null | undefined, fallback: [number, number]): object {
+    if (decimals === null || decimals === undefined) {
+        return { minimumFractionDigits: fallback[0], maximumFractionDigits: fallback[1] };
+    }
+    if (!Number.isInteger(decimals) || decimals < 0 || decimals > 20) {
+        throw new Error(`${name}() decimals must be an int from 0 to 20, got ${decimals}`);
+    }
+    return { minimumFractionDigits: decimals, maximumFractionDigits: decimals };
+}
+
+function intlNumber(name: string, value: any): number {
+    if (typeof value !== 'number') {
+        throw new Error(`${name}() value must be a number, not '${typeOfValue(value)}'`);
+    }
+    return value;
+}
+
+function intlDate(name: string, value: any): Date {
+    const date = value instanceof Date ? value : typeof value === 'number' ? new Date(value) : null;
+    if (date === null) {
+        throw new Error(`${name}() date must be a date or milliseconds since the epoch, not '${typeOfValue(value)}'`);
+    }
+    if (Number.isNaN(date.getTime())) {
+        throw new Error(`${name}(): invalid date`);
+    }
+    return date;
+}
+
+function intlStyle(name: string, kind: string, style: string | null | undefined, styles: string[], fallback: string): string {
+    style = style ?? fallback;
+    if (!styles.includes(style)) {
+        const expected = styles.map(s => `"${s}"`).join(', ').replace(/, ([^,]*)$/, ' or $1');
+        throw new Error(`${name}() ${kind} style must be ${expected}, got ${style}`);
+    }
+    return style;
+}
+
+function formatDate(name: string, date: any, locale: string | null | undefined, options: object): string {
+    return new Intl.DateTimeFormat(locale ?? 'en-US', { ...options, timeZone: 'UTC' }).format(intlDate(name, date));
+}
+
+export const IntlInterop = {
+    format_number: rawFunction((value: number, locale?: string | null, decimals?: number | null) =>
+        new Intl.NumberFormat(locale ?? 'en-US', intlDigits('format_number', decimals, [0, 3]))
+            .format(intlNumber('format_number', value)), 1),
+    format_percent: rawFunction((value: number, locale?: string | null, decimals?: number | null) =>
+        new Intl.NumberFormat(locale ?? 'en-US', { style: 'percent', ...intlDigits('format_percent', decimals, [0, 0]) })
+            .format(intlNumber('format_percent', value)), 1),
+    format_currency: rawFunction((amount: number, currency: string, locale?: string | null) => {
+        if (typeof currency !== 'string' || !/^[A-Za-z]{3}$/.test(currency)) {
+            throw new Error(`format_currency() currency must be an ISO 4217 code such as "USD", got ${currency}`);
+        }
+        return new Intl.NumberFormat(locale ?? 'en-US', {
+            style: 'currency',
+            currency: currency.toUpperCase(),
+            currencyDisplay: 'narrowSymbol'
+        }).format(intlNumber('format_currency', amount));
+    }, 2),
+    format_date: rawFunction((date: any, locale?: string | null, style?: string | null) =>
+        formatDate('format_date', date, locale, {
+            dateStyle: intlStyle('format_date', 'date', style, DATE_STYLES, 'medium')
+        }), 1),
+    format_time: rawFunction((date: any, locale?: string | null, style?: string | null) =>
+        formatDate('format_time', date, locale, {
+            timeStyle: intlStyle('format_time', 'time', style, TIME_STYLES, 'short')
+        }), 1),
+    format_datetime: rawFunction((date: any, locale?: string | null, dateStyle?: string | null, timeStyle?: string | null) =>
+        formatDate('format_datetime', date, locale, {
+            dateStyle: intlStyle('format_datetime', 'date', dateStyle, DATE_STYLES, 'medium'),
+            timeStyle: intlStyle('format_datetime', 'time', timeStyle, TIME_STYLES, 'short')
+        }), 1)
+};
+
+/**
+ * html module: templates that escape the values put into them unless they are SafeHtml, the
+ * type of markup known to be safe. Mirrors the VM's module.
+ */
+export class SafeHtml {
+    constructor(readonly html: string) {}
+
+    toString(): string {
+        return this.html;
+    }
+}
+
+function escapeHtml(text: string): string {
+    return text.replace(/[&<>"']/g, c => ({ '&': '&amp;', '<': '&lt;', '>': '&gt;', '"': '&quot;', "'": '&#39;' })[c]!);
+}
+
+function htmlMarkup(value: any): string {
+    if (value === null || value === undefined) return '';
+    if (value instanceof SafeHtml) return value.html;
+    if (Array.isArray(value)) return value.map(htmlMarkup).join('');
+    return escapeHtml(String(value));
+}
+
+export const HtmlInterop = {
+    html: rawFunction((template: string, ...values: any[]) => {
+        if (typeof template !== 'string') {
+            throw new Error(`html() template must be a str, not '${typeOfValue(template)}'`);
+        }
+        let next = 0;
+        const filled = template.replace(/\{\{|\}\}|\{([^}]*)\}|\{|\}/g, (match, field: string | undefined) => {
+            if (match === '{{') return '{';
+            if (match === '}}') return '}';
+            if (field === undefined) {
+                throw new Error(`html() template has an unmatched '${match}'`);
+            }
+            if (field !== '' && !/^\d+$/.test(field)) {
+                throw new Error(`html() placeholder '{${field}}' must be {} or an index`);
+            }
+            const index = field === '' ? next++ : Number(field);
+            if (index >= values.length) {
+                throw new Error(`html() placeholder ${index} has no value (${values.length} given)`);
+            }
+            return htmlMarkup(values[index]);
+        });
+        return new SafeHtml(filled);
+    }, 1),
+    escape: rawFunction((value: any) => new SafeHtml(htmlMarkup(value)), 1),
+    safe: rawFunction((text: string) => {
+        if (text instanceof SafeHtml) return text;
+        if (typeof text !== 'string') {
+            throw new Error(`safe() text must be a str, not '${typeOfValue(text)}'`);
+        }
+        return new SafeHtml(text);
+    }, 1)
+};
+
+/**
+ * websocket module: client connections through the WebSocket of browsers and newer Node
+ * versions, or a client of its own on older ones, and servers on Node. Mirrors the VM's
+ * module, with sockets and servers as objects rather than integers.
+ */
+type WebSocketMessage = string | Uint8Array;
+
+const WEBSOCKET_GUID = '258EAFA5-E914-47DA-95CA-C5AB0DC85B11';
+
+interface WebSocketTransport {
+    send(message: WebSocketMessage): void;
+    close(): void;
+}
+
+export class WebSocketConnection {
+    transport: WebSocketTransport | null = null;
+    private inbox: WebSocketMessage[] = [];
+    private waiting: ((message: WebSocketMessage | null) => void)[] = [];
+    private listener: ((message: WebSocketMessage) => any) | null = null;
+    // Whether the other end closed the socket, and whether this end did
+    private ended = false;
+    private closed = false;
+
+    deliver(message: WebSocketMessage): void {
+        if (this.listener) {
+            this.listener(message);
+        } else if (this.waiting.length > 0) {
+            this.waiting.shift()!(message);
+        } else {
+            this.inbox.push(message);
+        }
+    }
+
+    end(): void {
+        this.ended = true;
+        for (const resolve of this.waiting.splice(0)) {
+            resolve(null);
+        }
+    }
+
+    receive(): Promise<WebSocketMessage | null> {
+        this.check('receive');
+        if (this.inbox.length > 0) {
+            return Promise.resolve(this.inbox.shift()!);
+        }
+        if (this.ended) {
+            return Promise.resolve(null);
+        }
+        return new Promise(resolve => this.waiting.push(resolve));
+    }
+
+    onMessage(listener: ((message: WebSocketMessage) => any) | null): void {
+        this.check('on_message');
+        this.listener = listener;
+        if (listener) {
+            for (const message of this.inbox.splice(0)) {
+                listener(message);
+            }
+        }
+    }
+
+    send(message: WebSocketMessage): void {
+        this.check('send');
+        if (this.ended) {
+            throw new Error('send(): the socket was closed by the other end');
+        }
+        this.transport!.send(message);
+    }
+
+    close(): boolean {
+        if (this.closed) {
+            return false;
+        }
+        this.closed = true;
+        this.transport!.close();
+        this.end();
+        return true;
+    }
+
+    private check(name: string): void {
+        if (this.closed) {
+            throw new Error(`${name}(): socket is not open`);
+        }
+    }
+}
+
+export class WebSocketServer {
+    constructor(private server: any) {}
+
+    close(): boolean {
+        if (!this.server.listening) {
+            return false;
+        }
+        this.server.close();
+        return true;
+    }
+}
+
+// RFC 6455 framing over a Node socket, masked when this end is the client
+class NodeWebSocketTransport implements WebSocketTransport {
+    private buffer = Buffer.alloc(0);
+    private fragments: Buffer[] = [];
+    private fragmentOpcode = 0;
+    private closing = false;
+
+    constructor(private socket: any, head: Buffer, private masked: boolean,
+                private connection: WebSocketConnection, private randomBytes: (size: number) => Buffer) {
+        socket.on('data', (data: Buffer) => this.read(data));
+        socket.on('close', () => connection.end());
+        // A broken connection ends like a closed one
+        socket.on('error', () => connection.end());
+        if (head.length > 0) {
+            this.read(head);
+        }
+    }
+
+    send(message: WebSocketMessage): void {
+        this.write(typeof message === 'string' ? 0x1 : 0x2, Buffer.from(message));
+    }
+
+    close(): void {
+        this.closing = true;
+        this.write(0x8, Buffer.alloc(0));
+        this.socket.end();
+    }
+
+    private write(opcode: number, payload: Buffer): void {
+        const length = payload.length;
+        const mask = this.masked ? 0x80 : 0;
+        let header: Buffer;
+        if (length < 126) {
+            header = Buffer.from([0x80 | opcode, mask | length]);
+        } else if (length < 0x10000) {
+            header = Buffer.from([0x80 | opcode, mask | 126, 0, 0]);
+            header.writeUInt16BE(length, 2);
+        } else {
+            header = Buffer.alloc(10);
+            header[0] = 0x80 | opcode;
+            header[1] = mask | 127;
+            header.writeBigUInt64BE(BigInt(length), 2);
+        }
+        if (this.masked) {
+            const key = this.randomBytes(4);
+            payload = payload.map((byte, i) => byte ^ key[i % 4]);
+            header = Buffer.concat([header, key]);
+        }
+        if (!this.socket.destroyed) {
+            this.socket.write(Buffer.concat([header, payload]));
+        }
+    }
+
+    private read(data: Buffer): void {
+        this.buffer = Buffer.concat([this.buffer, data]);
+        while (this.buffer.length >= 2) {
+            const buffer = this.buffer;
+            let length = buffer[1] & 0x7f;
+            let offset = 2;
+            if (length === 126) {
+                if (buffer.length < 4) return;
+                length = buffer.readUInt16BE(2);
+                offset = 4;
+            } else if (length === 127) {
+                if (buffer.length < 10) return;
+                length = Number(buffer.readBigUInt64BE(2));
+                offset = 10;
+            }
+            const masked = (buffer[1] & 0x80) !== 0;
+            const payloadStart = offset + (masked ? 4 : 0);
+            if (buffer.length < payloadStart + length) return;
+            const payload = Buffer.from(buffer.subarray(payloadStart, payloadStart + length));
+            if (masked) {
+                for (let i = 0; i < payload.length; i++) {
+                    payload[i] ^= buffer[offset + (i % 4)];
+                }
+            }
+            this.buffer = buffer.subarray(payloadStart + length);
+            this.frame((buffer[0] & 0x80) !== 0, buffer[0] & 0x0f, payload);
+        }
+    }
+
+    private frame(fin: boolean, opcode: number, payload: Buffer): void {
+        switch (opcode) {
+            case 0x8:
+                if (!this.closing) {
+                    this.closing = true;
+                    this.write(0x8, payload.subarray(0, 2));
+                }
+                this.socket.end();
+                return;
+            case 0x9:
+                this.write(0xa, payload);
+                return;
+            case 0xa:
+                return;
+        }
+        if (opcode !== 0x0) {
+            this.fragmentOpcode = opcode;
+            this.fragments = [];
+        }
+        this.fragments.push(payload);
+        if (fin) {
+            const data = Buffer.concat(this.fragments);
+            this.fragments = [];
+            this.connection.deliver(this.fragmentOpcode === 0x1 ? data.toString('utf8') : new Uint8Array(data));
+        }
+    }
+}
+
+// What the server answers the key of a handshake with
+function websocketAccept(createHash: any, key: string): string {
+    return createHash('sha1').update(key + WEBSOCKET_GUID).digest('base64');
+}
+
+function connectBrowser(url: string): Promise<WebSocketConnection> {
+    return new Promise((resolve, reject) => {
+        const socket = new (globalThis as any).WebSocket(url);
+        socket.binaryType = 'arraybuffer';
+        const connection = new WebSocketConnection();
+        connection.transport = {
+            send: message => socket.send(message),
+            close: () => socket.close()
+        };
+        socket.onopen = () => resolve(connection);
+        socket.onmessage = (event: any) => connection.deliver(
+            typeof event.data === 'string' ? event.data : new Uint8Array(event.data));
+        socket.onclose = () => {
+            connection.end();
+            reject(new Error(`connect(): cannot connect to '${url}'`));
+        };
+    });
+}
+
+async function connectNode(url: string): Promise<WebSocketConnection> {
+    const parsed = new URL(url);
+    const secure = parsed.protocol === 'wss:';
+    const http: any = await import(secure ? 'node:https' : 'node:http');
+    const { createHash, randomBytes } = await import('node:crypto');
+    const key = randomBytes(16).toString('base64');
+    return new Promise((resolve, reject) => {
+        const fail = (reason: string) => reject(new Error(`connect(): cannot connect to '${url}': ${reason}`));
+        const request = http.request({
+            hostname: parsed.hostname.replace(/^\[|\]$/g, ''),
+            port: parsed.port || (secure ? 443 : 80),
+            path: parsed.pathname + parsed.search,
+            headers: {
+                Connection: 'Upgrade',
+                Upgrade: 'websocket',
+                'Sec-WebSocket-Key': key,
+                'Sec-WebSocket-Version': '13'
+            }
+        });
+        request.on('upgrade', (response: any, socket: any, head: Buffer) => {
+            if (response.headers['sec-websocket-accept'] !== websocketAccept(createHash, key)) {
+                socket.destroy();
+                fail('invalid handshake');
+                return;
+            }
+            const connection = new WebSocketConnection();
+            connection.transport = new NodeWebSocketTransport(socket, head, true, connection, randomBytes);
+            resolve(connection);
+        });
+        request.on('response', (response: any) => {
+            response.resume();
+            fail(`HTTP ${response.statusCode}`);
+        });
+        request.on('error', (error: any) => fail(error.message));
+        request.end();
+    });
+}
+
+function websocketConnection(name: string, socket: any): WebSocketConnection {
+    if (!(socket instanceof WebSocketConnection)) {
+        throw new Error(`${name}() takes a socket from connect() or serve()`);
+    }
+    return socket;
+}
+
+export const WebSocketInterop = {
+    connect: rawFunction(async (url: string) => {
+        if (typeof url !== 'string') {
+            throw new Error(`connect() url must be a str, not '${typeOfValue(url)}'`);
+        }
+        if (!/^wss?:\/\//i.test(url)) {
+            throw new Error(`connect(): cannot connect to '${url}': URL scheme must be ws or wss`);
+        }
+        return typeof (globalThis as any).WebSocket === 'function' ? connectBrowser(url) : connectNode(url);
+    }, 1),
+    send: rawFunction((socket: WebSocketConnection, message: WebSocketMessage) => {
+        if (typeof message !== 'string' && !(message instanceof Uint8Array)) {
+            throw new Error(`send() message must be str or bytes, not '${typeOfValue(message)}'`);
+        }
+        websocketConnection('send', socket).send(message);
+        return null;
+    }, 2),
+    receive: rawFunction((socket: WebSocketConnection) => websocketConnection('receive', socket).receive(), 1),
+    on_message: rawFunction((socket: WebSocketConnection, listener: any) => {
+        if (listener !== null && listener !== undefined && typeof listener !== 'function') {
+            throw new Error(`on_message() listener must be a function, not '${typeOfValue(listener)}'`);
+        }
+        websocketConnection('on_message', socket).onMessage(listener ?? null);
+        return null;
+    }, 2),
+    close: rawFunction((handle: WebSocketConnection | WebSocketServer) => {
+        if (handle instanceof WebSocketServer) {
+            return handle.close();
+        }
+        return websocketConnection('close', handle).close();
+    }, 1),
+    serve: rawFunction(async (port: number, handler: (socket: WebSocketConnection) => any) => {
+        if (!Number.isInteger(port)) {
+            throw new Error(`serve() port must be an int, not '${typeOfValue(port)}'`);
+        }
+        if (port < 0 || port > 65535) {
+            throw new Error(`serve() port must be from 0 to 65535, got ${port}`);
+        }
+        if (typeof handler !== 'function') {
+            throw new Error(`serve() handler must be a function, not '${typeOfValue(handler)}'`);
+        }
+        if ((globalThis as any).process?.versions?.node === undefined) {
+            throw new Error('serve(): websocket servers need Node.js');
+        }
+        const http: any = await import('node:http');
+        const { createHash, randomBytes } = await import('node:crypto');
+        const server = http.createServer((_request: any, response: any) => {
+            response.writeHead(426, { Upgrade: 'websocket' });
+            response.end();
+        });
+        server.on('upgrade', (request: any, socket: any, head: Buffer) => {
+            const key = request.headers['sec-websocket-key'];
+            if (typeof key !== 'string' || request.headers.upgrade?.toLowerCase() !== 'websocket') {
+                socket.end('HTTP/1.1 400 Bad Request\r\n\r\n');
+                return;
+            }
+            socket.write([
+                'HTTP/1.1 101 Switching Protocols',
+                'Upgrade: websocket',
+                'Connection: Upgrade',
+                `Sec-WebSocket-Accept: ${websocketAccept(createHash, key)}`,
+                '',
+                ''
+            ].join('\r\n'));
+            const connection = new WebSocketConnection();
+            connection.transport = new NodeWebSocketTransport(socket, head, false, connection, randomBytes);
+            handler(connection);
+        });
+        await new Promise<void>((resolve, reject) => {
+            server.once('error', (error: any) =>
+                reject(new Error(`serve(): cannot listen on port ${port}: ${error.message}`)));
+            server.listen(port, () => resolve());
+        });
+        return new WebSocketServer(server);
+    }, 2)
+};
+
+/**
+ * http.server module: apps with routes, static files and JSON bodies, served through
+ * node:http. Mirrors the VM's module, with apps as objects rather than integers.
+ */
+export class HttpResponse {
+    constructor(readonly status: number, readonly headers: { [name: string]: string },
+                readonly body: Uint8Array) {}
+}
+
+interface HttpRoute {
+    method: string;
+    segments: string[];
+    handler: (request: any) => any;
+}
+
+export class HttpApp {
+    routes: HttpRoute[] = [];
+    statics: { prefix: string[]; directory: string }[] = [];
+    server: any = null;
+}
+
+const HTTP_CONTENT_TYPES: { [extension: string]: string } = {
+    html: 'text/html; charset=utf-8',
+    htm: 'text/html; charset=utf-8',
+    css: 'text/css; charset=utf-8',
+    js: 'text/javascript; charset=utf-8',
+    mjs: 'text/javascript; charset=utf-8',
+    json: 'application/json',
+    txt: 'text/plain; charset=utf-8',
+    svg: 'image/svg+xml',
+    png: 'image/png',
+    jpg: 'image/jpeg',
+    jpeg: 'image/jpeg',
+    gif: 'image/gif',
+    ico: 'image/x-icon',
+    wasm: 'application/wasm'
+};
+
+function httpSegments(path: string): string[] {
+    return path.split('/').filter(segment => segment !== '');
+}
+
+function httpDecode(text: string, form: boolean): string {
+    try {
+        return decodeURIComponent(form ? text.replace(/\+/g, ' ') : text);
+    } catch {
+        return text;
+    }
+}
+
+function httpText(status: number, text: string): HttpResponse {
+    return new HttpResponse(status, { 'content-type': 'text/plain; charset=utf-8' },
+        new TextEncoder().encode(text));
+}
+
+function httpReply(value: any): HttpResponse {
+    if (value instanceof HttpResponse) return value;
+    if (value === null || value === undefined) return new HttpResponse(204, {}, new Uint8Array(0));
+    if (typeof value === 'string') return httpText(200, value);
+    if (value instanceof SafeHtml) {
+        return new HttpResponse(200, { 'content-type': 'text/html; charset=utf-8' },
+            new TextEncoder().encode(value.html));
+    }
+    if (value instanceof Uint8Array) {
+        return new HttpResponse(200, { 'content-type': 'application/octet-stream' }, value);
+    }
+    return new HttpResponse(200, { 'content-type': 'application/json' },
+        new TextEncoder().encode(JSON.stringify(value)));
+}
+
+function httpApp(name: string, app: HttpApp): HttpApp {
+    if (!(app instanceof HttpApp)) {
+        throw new Error(`${name}() takes an app from create()`);
+    }
+    return app;
+}
+
+// The file under `directory` that `path` asks for, if it is below `prefix` and exists
+async function httpStaticFile(prefix: string[], directory: string, path: string[]): Promise<string | null> {
+    if (path.length < prefix.length || prefix.some((segment, i) => path[i] !== segment)) {
+        return null;
+    }
+    const rest = path.slice(prefix.length);
+    if (rest.some(segment => segment === '..' || segment.includes('\\'))) {
+        return null;
+    }
+    const fs = await import('node:fs/promises');
+    const { join } = await import('node:path');
+    let file = join(directory, ...rest.map(segment => httpDecode(segment, false)));
+    try {
+        if ((await fs.stat(file)).isDirectory()) {
+            file = join(file, 'index.html');
+        }
+        return (await fs.stat(file)).isFile() ? file : null;
+    } catch {
+        return null;
+    }
+}
+
+// The response of `app` to a request, calling the handler of the route that takes it
+async function httpAnswer(app: HttpApp, method: string, url: string, headerList: any, body: Uint8Array): Promise<HttpResponse> {
+    const [path, query = ''] = url.split(/\?(.*)/s);
+    const segments = httpSegments(path);
+    const allowed: string[] = [];
+    for (const route of app.routes) {
+        if (route.segments.length !== segments.length) continue;
+        const params: { [name: string]: string } = {};
+        const matches = route.segments.every((segment, i) => {
+            if (segment.startsWith(':')) {
+                params[segment.slice(1)] = httpDecode(segments[i], false);
+                return true;
+            }
+            return segment === segments[i];
+        });
+        if (!matches) continue;
+        if (route.method === '*' || route.method === method || (method === 'HEAD' && route.method === 'GET')) {
+            const headers: { [name: string]: string } = {};
+            for (const [name, value] of Object.entries(headerList)) {
+                headers[name] = Array.isArray(value) ? value.join(', ') : String(value);
+            }
+            let text: string | null = null;
+            try {
+                text = new TextDecoder('utf-8', { fatal: true }).decode(body);
+            } catch {
+                // Bodies that aren't UTF-8 are passed on as bytes
+            }
+            let json = null;
+            const mediaType = (headers['content-type'] ?? '').split(';')[0].trim().toLowerCase();
+            if (mediaType === 'application/json') {
+                if (text === null) {
+                    return httpText(400, 'Invalid JSON body: not UTF-8');
+                }
+                if (text.trim() !== '') {
+                    try {
+                        json = JSON.parse(text);
+                    } catch (error: any) {
+                        return httpText(400, `Invalid JSON body: ${error.message}`);
+                    }
+                }
+            }
+            const queryValues: { [name: string]: string } = {};
+            for (const pair of query.split('&').filter(pair => pair !== '')) {
+                const [name, value = ''] = pair.split(/=(.*)/s);
+                queryValues[httpDecode(name, true)] = httpDecode(value, true);
+            }
+            const request = { method, path, query: queryValues, params, headers, body: text ?? body, json };
+            return httpReply(await route.handler(request));
+        }
+        if (!allowed.includes(route.method)) {
+            allowed.push(route.method);
+        }
+    }
+    if (method === 'GET' || method === 'HEAD') {
+        for (const { prefix, directory } of app.statics) {
+            const file = await httpStaticFile(prefix, directory, segments);
+            if (file !== null) {
+                const fs = await import('node:fs/promises');
+                const extension = file.slice(file.lastIndexOf('.') + 1).toLowerCase();
+                const contentType = HTTP_CONTENT_TYPES[extension] ?? 'application/octet-stream';
+                return new HttpResponse(200, { 'content-type': contentType }, await fs.readFile(file));
+            }
+        }
+    }
+    if (allowed.length === 0) {
+        return httpText(404, 'Not Found');
+    }
+    const reply = httpText(405, 'Method Not Allowed');
+    reply.headers.allow = allowed.join(', ');
+    return reply;
+}
+
+export const HttpServerInterop = {
+    create: rawFunction(() => new HttpApp(), 0),
+    route: rawFunction((app: HttpApp, method: string, path: string, handler: (request: any) => any) => {
+        if (typeof method !== 'string') {
+            throw new Error(`route() method must be a str, not '${typeOfValue(method)}'`);
+        }
+        if (typeof path !== 'string') {
+            throw new Error(`route() path must be a str, not '${typeOfValue(path)}'`);
+        }
+        if (typeof handler !== 'function') {
+            throw new Error(`route() handler must be a function, not '${typeOfValue(handler)}'`);
+        }
+        httpApp('route', app).routes.push({ method: method.toUpperCase(), segments: httpSegments(path), handler });
+        return null;
+    }, 4),
+    static_files: rawFunction((app: HttpApp, prefix: string, directory: string) => {
+        if (typeof prefix !== 'string') {
+            throw new Error(`static_files() prefix must be a str, not '${typeOfValue(prefix)}'`);
+        }
+        if (typeof directory !== 'string') {
+            throw new Error(`static_files() directory must be a str, not '${typeOfValue(directory)}'`);
+        }
+        httpApp('static_files', app).statics.push({ prefix: httpSegments(prefix), directory });
+        return null;
+    }, 3),
+    listen: rawFunction(async (app: HttpApp, port: number, host: string | null = null) => {
+        httpApp('listen', app);
+        if (!Number.isInteger(port)) {
+            throw new Error(`listen() port must be an int, not '${typeOfValue(port)}'`);
+        }
+        if (host !== null && typeof host !== 'string') {
+            throw new Error(`listen() host must be a str, not '${typeOfValue(host)}'`);
+        }
+        // Only the same machine can connect unless every interface is asked for
+        const address = host ?? '127.0.0.1';
+        if (port < 0 || port > 65535) {
+            throw new Error(`listen() port must be from 0 to 65535, got ${port}`);
+        }
+        if (app.server !== null) {
+            throw new Error('listen(): app is already listening');
+        }
+        if ((globalThis as any).process?.versions?.node === undefined) {
+            throw new Error('listen(): HTTP servers need Node.js');
+        }
+        const http: any = await import('node:http');
+        const server = http.createServer(async (request: any, response: any) => {
+            const chunks: Uint8Array[] = [];
+            for await (const chunk of request) {
+                chunks.push(chunk);
+            }
+            let reply: HttpResponse;
+            try {
+                reply = await httpAnswer(app, request.method, request.url, request.headers, Buffer.concat(chunks));
+            } catch (error) {
+                // A handler that fails is answered with 500, and its error ends the program
+                response.writeHead(500, { 'content-type': 'text/plain; charset=utf-8' });
+                response.end('Internal Server Error');
+                throw error;
+            }
+            response.writeHead(reply.status, reply.headers);
+            response.end(reply.body);
+        });
+        await new Promise<void>((resolve, reject) => {
+            server.once('error', (error: any) =>
+                reject(new Error(`listen(): cannot listen on ${address}:${port}: ${error.message}`)));
+            server.listen(port, address, () => resolve());
+        });
+        app.server = server;
+        return server.address().port;
+    }, 2),
+    stop: rawFunction((app: HttpApp) => {
+        const server = httpApp('stop', app).server;
+        if (server === null) {
+            return false;
+        }
+        server.close();
+        server.closeIdleConnections?.();
+        app.server = null;
+        return true;
+    }, 1),
+    response: rawFunction((body: any, status?: number | null, headers?: { [name: string]: any } | null) => {
+        const reply = httpReply(body);
+        let code = body === null || body === undefined ? 200 : reply.status;
+        if (status !== null && status !== undefined) {
+            if (!Number.isInteger(status)) {
+                throw new Error(`response() status must be an int, not '${typeOfValue(status)}'`);
+            }
+            if (status < 100 || status > 599) {
+                throw new Error(`response() status must be from 100 to 599, got ${status}`);
+            }
+            code = status;
+        }
+        if (headers !== null && headers !== undefined) {
+            if (typeof headers !== 'object' || Array.isArray(headers) || headers instanceof Uint8Array) {
+                throw new Error(`response() headers must be a dict, not '${typeOfValue(headers)}'`);
+            }
+            for (const [name, value] of Object.entries(headers)) {
+                reply.headers[name.toLowerCase()] = String(value);
+            }
+        }
+        return new HttpResponse(code, reply.headers, reply.body);
+    }, 1)
+};
+
+/**
+ * sqlite module: databases queried with bound parameters, through better-sqlite3 in Node.js or
+ * sql.js where it isn't installed. Mirrors the VM's module, with databases as objects rather
+ * than integers.
+ */
+type SqliteParams = any[] | { [name: string]: any } | null | undefined;
+
+interface SqliteDriver {
+    run(sql: string, params: SqliteParams): { changes: number; lastInsertId: number };
+    all(sql: string, params: SqliteParams): { [column: string]: any }[];
+    exec(sql: string): void;
+    close(): Promise<void>;
+}
+
+export class SqliteDatabase {
+    private open = true;
+
+    constructor(readonly path: string, private driver: SqliteDriver) {}
+
+    use(name: string): SqliteDriver {
+        if (!this.open) {
+            throw new Error(`${name}(): database '${this.path}' is not open`);
+        }
+        return this.driver;
+    }
+
+    async close(): Promise<boolean> {
+        if (!this.open) {
+            return false;
+        }
+        this.open = false;
+        await this.driver.close();
+        return true;
+    }
+}
+
+/** A module the program may not have installed, or null */
+async function optionalImport(name: string): Promise<any | null> {
+    try {
+        const module = await import(name);
+        return module.default ?? module;
+    } catch {
+        return null;
+    }
+}
+
+function sqliteValue(name: string, value: any, blob: (data: Uint8Array) => any): any {
+    if (value === null || value === undefined) return null;
+    if (typeof value === 'boolean') return value ? 1 : 0;
+    if (typeof value === 'number' || typeof value === 'string') return value;
+    if (value instanceof Uint8Array) return blob(value);
+    throw new Error(`${name}() cannot bind a '${typeOfValue(value)}' parameter`);
+}
+
+/** `params` as values the driver binds, named ones under each prefix the driver may need */
+function sqliteParams(name: string, params: SqliteParams, prefixes: string[],
+                      blob: (data: Uint8Array) => any): any[] | { [name: string]: any } {
+    if (params === null || params === undefined) return [];
+    if (Array.isArray(params)) return params.map(value => sqliteValue(name, value, blob));
+    if (typeof params === 'object' && !(params instanceof Uint8Array)) {
+        const bound: { [name: string]: any } = {};
+        for (const [key, value] of Object.entries(params)) {
+            for (const prefix of prefixes) {
+                bound[prefix + key] = sqliteValue(name, value, blob);
+            }
+        }
+        return bound;
+    }
+    throw new Error(`${name}() params must be a list or dict, not '${typeOfValue(params)}'`);
+}
+
+function betterSqliteDriver(Database: any, path: string): SqliteDriver {
+    const db = new Database(path);
+    const bind = (name: string, params: SqliteParams) => {
+        const bound = sqliteParams(name, params, [''], data => Buffer.from(data));
+        return Array.isArray(bound) ? bound : [bound];
+    };
+    return {
+        run(sql, params) {
+            const info = db.prepare(sql).run(...bind('execute', params));
+            return { changes: info.changes, lastInsertId: Number(info.lastInsertRowid) };
+        },
+        all(sql, params) {
+            return db.prepare(sql).all(...bind('query', params)).map((row: any) => {
+                for (const column of Object.keys(row)) {
+                    if (row[column] instanceof Uint8Array) row[column] = new Uint8Array(row[column]);
+                }
+                return row;
+            });
+        },
+        exec(sql) {
+            db.exec(sql);
+        },
+        async close() {
+            db.close();
+        }
+    };
+}
+
+/** A database of sql.js, which lives in memory; one opened from a file is written back on close */
+async function sqlJsDriver(initSqlJs: any, path: string): Promise<SqliteDriver> {
+    const SQL = await initSqlJs();
+    const inFile = path !== ':memory:' && path !== '';
+    let data: Uint8Array | undefined;
+    if (inFile) {
+        const fs = await import('node:fs/promises');
+        data = await fs.readFile(path).catch((error: any) => {
+            if (error.code === 'ENOENT') return undefined;
+            throw new Error(`open(): cannot open '${path}': ${error.message}`);
+        });
+    }
+    const db = new SQL.Database(data);
+    const statement = (name: string, sql: string, params: SqliteParams) => {
+        const prepared = db.prepare(sql);
+        prepared.bind(sqliteParams(name, params, [':', '@', '$'], data => data));
+        return prepared;
+    };
+    return {
+        run(sql, params) {
+            const prepared = statement('execute', sql, params);
+            try {
+                prepared.step();
+            } finally {
+                prepared.free();
+            }
+            const changes = db.getRowsModified();
+            const lastInsertId = Number(db.exec('SELECT last_insert_rowid()')[0].values[0][0]);
+            return { changes, lastInsertId };
+        },
+        all(sql, params) {
+            const prepared = statement('query', sql, params);
+            const rows: { [column: string]: any }[] = [];
+            try {
+                while (prepared.step()) {
+                    rows.push(prepared.getAsObject());
+                }
+            } finally {
+                prepared.free();
+            }
+            return rows;
+        },
+        exec(sql) {
+            db.exec(sql);
+        },
+        async close() {
+            if (inFile) {
+                const fs = await import('node:fs/promises');
+                await fs.writeFile(path, db.export());
+            }
+            db.close();
+        }
+    };
+}
+
+function sqliteDatabase(name: string, db: SqliteDatabase): SqliteDriver {
+    if (!(db instanceof SqliteDatabase)) {
+        throw new Error(`${name}() takes a database from open()`);
+    }
+    return db.use(name);
+}
+
+/** What `run` returns, with the errors of the driver prefixed by the function's name */
+function sqliteCall<T>(name: string, run: () => T): T {
+    try {
+        return run();
+    } catch (error: any) {
+        const message: string = error.message;
+        throw new Error(message.startsWith(`${name}()`) ? message : `${name}(): ${message}`);
+    }
+}
+
+function sqliteSql(name: string, sql: string): string {
+    if (typeof sql !== 'string') {
+        throw new Error(`${name}() sql must be a str, not '${typeOfValue(sql)}'`);
+    }
+    return sql;
+}
+
+export const SqliteInterop = {
+    open: rawFunction(async (path: string) => {
+        if (typeof path !== 'string') {
+            throw new Error(`open() path must be a str, not '${typeOfValue(path)}'`);
+        }
+        const Database = await optionalImport('better-sqlite3');
+        if (Database !== null) {
+            try {
+                return new SqliteDatabase(path, betterSqliteDriver(Database, path));
+            } catch (error: any) {
+                throw new Error(`open(): cannot open '${path}': ${error.message}`);
+            }
+        }
+        const initSqlJs = await optionalImport('sql.js');
+        if (initSqlJs !== null) {
+            return new SqliteDatabase(path, await sqlJsDriver(initSqlJs, path));
+        }
+        throw new Error('open(): the sqlite module needs the better-sqlite3 or sql.js package');
+    }, 1),
+    execute: rawFunction((db: SqliteDatabase, sql: string, params?: SqliteParams) => {
+        const driver = sqliteDatabase('execute', db);
+        const { changes, lastInsertId } = sqliteCall('execute', () => driver.run(sqliteSql('execute', sql), params));
+        return { changes, last_insert_id: lastInsertId };
+    }, 2),
+    query: rawFunction((db: SqliteDatabase, sql: string, params?: SqliteParams) => {
+        const driver = sqliteDatabase('query', db);
+        return sqliteCall('query', () => driver.all(sqliteSql('query', sql), params));
+    }, 2),
+    begin: rawFunction((db: SqliteDatabase) => {
+        const driver = sqliteDatabase('begin', db);
+        sqliteCall('begin', () => driver.exec('BEGIN'));
+        return null;
+    }, 1),
+    commit: rawFunction((db: SqliteDatabase) => {
+        const driver = sqliteDatabase('commit', db);
+        sqliteCall('commit', () => driver.exec('COMMIT'));
+        return null;
+    }, 1),
+    rollback: rawFunction((db: SqliteDatabase) => {
+        const driver = sqliteDatabase('rollback', db);
+        sqliteCall('rollback', () => driver.exec('ROLLBACK'));
+        return null;
+    }, 1),
+    close: rawFunction((db: SqliteDatabase) => {
+        if (!(db instanceof SqliteDatabase)) {
+            throw new Error('close() takes a database from open()');
+        }
+        return db.close();
+    }, 1)
+};
+
 /**
  * Promise utilities
  */
@@ -510,6 +2449,13 @@ export class InteropRegistry {
         return this.globals.get(name);
     }
 
+    /**
+     * Cancel what scripts left running, before running others in the same runtime
+     */
+    static reset(): void {
+        cancelAllTimers();
+    }
+
     static initialize(): void {
         // Register built-in modules
         this.registerModule('console', ConsoleInterop as any);
@@ -517,6 +2463,17 @@ export class InteropRegistry {
         this.registerModule('JSON', JSONInterop as any);
         this.registerModule('Promise', PromiseInterop as any);
         this.registerModule('http', HTTPInterop as any);
+        this.registerModule('unicodedata', UnicodeDataInterop as any);
+        this.registerModule('timers', TimersInterop as any);
+        this.registerModule('csv', CsvInterop as any);
+        this.registerModule('struct', StructInterop as any);
+        this.registerModule('path', PathInterop as any);
+        this.registerModule('crypto', CryptoInterop as any);
+        this.registerModule('intl', IntlInterop as any);
+        this.registerModule('html', HtmlInterop as any);
+        this.registerModule('websocket', WebSocketInterop as any);
+        this.registerModule('http.server', HttpServerInterop as any);
+        this.registerModule('sqlite', SqliteInterop as any);
 
         if (typeof document !== 'undefined') {
             this.registerModule('DOM', DOMInterop as any);
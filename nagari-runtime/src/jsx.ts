@@ -44,6 +44,105 @@ export function Fragment(props: { children?: any }): JSXElement {
     return jsx('React.Fragment', null, ...(Array.isArray(props.children) ? props.children : [props.children]));
 }
 
+const VOID_ELEMENTS = new Set([
+    'area', 'base', 'br', 'col', 'embed', 'hr', 'img', 'input',
+    'link', 'meta', 'param', 'source', 'track', 'wbr'
+]);
+
+const ATTRIBUTE_NAMES: { [key: string]: string } = {
+    className: 'class',
+    htmlFor: 'for'
+};
+
+function escapeHtml(text: string): string {
+    return text
+        .replace(/&/g, '&amp;')
+        .replace(/</g, '&lt;')
+        .replace(/>/g, '&gt;')
+        .replace(/"/g, '&quot;')
+        .replace(/'/g, '&#39;');
+}
+
+function styleToString(style: { [key: string]: any }): string {
+    return Object.entries(style)
+        .filter(([, value]) => value !== null && value !== undefined && value !== false)
+        .map(([key, value]) => `${key.replace(/[A-Z]/g, char => '-' + char.toLowerCase())}: ${value}`)
+        .join('; ');
+}
+
+function renderAttributes(props: { [key: string]: any }): string {
+    let html = '';
+    for (const [key, value] of Object.entries(props)) {
+        // Children are rendered as content, and event handlers only mean something in a browser
+        if (key === 'children' || key === 'key' || key === 'ref' || typeof value === 'function') {
+            continue;
+        }
+        if (value === null || value === undefined || value === false) {
+            continue;
+        }
+        const name = ATTRIBUTE_NAMES[key] || key;
+        if (value === true) {
+            html += ` ${name}`;
+        } else if (key === 'style' && typeof value === 'object') {
+            html += ` style="${escapeHtml(styleToString(value))}"`;
+        } else {
+            html += ` ${name}="${escapeHtml(String(value))}"`;
+        }
+    }
+    return html;
+}
+
+/**
+ * A component's props, with the element's children in `props.children` as jsx() puts them
+ */
+function componentProps(element: JSXElement): { [key: string]: any } {
+    const props = { ...(element.props || {}) };
+    const children = element.children;
+    if (!('children' in props) && children !== null && children !== undefined) {
+        if (!Array.isArray(children)) {
+            props.children = children;
+        } else if (children.length === 1) {
+            props.children = children[0];
+        } else if (children.length > 1) {
+            props.children = children;
+        }
+    }
+    return props;
+}
+
+/**
+ * Render an element tree built by jsx() to an HTML string, for server-side rendering without
+ * React. Components are called with their props, text is escaped, and event handlers are
+ * left out.
+ */
+export function render_to_string(element: any): string {
+    if (element === null || element === undefined || typeof element === 'boolean') {
+        return '';
+    }
+    if (Array.isArray(element)) {
+        return element.map(render_to_string).join('');
+    }
+    if (typeof element !== 'object') {
+        return escapeHtml(String(element));
+    }
+
+    const { type, props } = element as JSXElement;
+    const children = element.children;
+    if (typeof type === 'function') {
+        return render_to_string(type(componentProps(element)));
+    }
+    if (type === 'React.Fragment' || type === '') {
+        return render_to_string(children);
+    }
+
+    const tag = String(type);
+    const attributes = renderAttributes(props || {});
+    if (VOID_ELEMENTS.has(tag.toLowerCase())) {
+        return `<${tag}${attributes}>`;
+    }
+    return `<${tag}${attributes}>${render_to_string(children)}</${tag}>`;
+}
+
 /**
  * Convert Nagari JSX to React elements
  */
@@ -0,0 +1,57 @@
+// Tests for render_to_string, the server-side renderer of jsx() element trees
+
+import { describe, expect, test } from '@jest/globals';
+import { jsx, render_to_string } from '../dist/index.js';
+
+describe('render_to_string', () => {
+    test('renders elements, attributes and void elements', () => {
+        const element = jsx('div', { id: 'main', className: 'page', hidden: true, title: null },
+            jsx('label', { htmlFor: 'name' }, 'Name'),
+            jsx('input', { type: 'text', disabled: false }),
+            jsx('br', null)
+        );
+        expect(render_to_string(element)).toBe(
+            '<div id="main" class="page" hidden><label for="name">Name</label><input type="text"><br></div>'
+        );
+    });
+
+    test('escapes text and attribute values', () => {
+        const element = jsx('p', { title: '"quoted" & <tagged>' }, '<script>alert(\'x\')</script>');
+        expect(render_to_string(element)).toBe(
+            '<p title="&quot;quoted&quot; &amp; &lt;tagged&gt;">&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;</p>'
+        );
+    });
+
+    test('writes styles as CSS and leaves out event handlers', () => {
+        const element = jsx('button', {
+            style: { backgroundColor: 'red', fontSize: '12px', margin: null },
+            onClick: () => {}
+        }, 'Go');
+        expect(render_to_string(element)).toBe(
+            '<button style="background-color: red; font-size: 12px">Go</button>'
+        );
+    });
+
+    test('calls components with their props and children', () => {
+        const Greeting = props => jsx('p', null, 'Hello, ', props.name, '!');
+        const Card = props => jsx('section', { className: 'card' }, props.children);
+        const element = jsx(Card, null, jsx(Greeting, { name: '<Ada>' }), jsx('hr', null));
+        expect(render_to_string(element)).toBe(
+            '<section class="card"><p>Hello, &lt;Ada&gt;!</p><hr></section>'
+        );
+    });
+
+    test('gives components the children of elements built without jsx()', () => {
+        const Wrapper = props => jsx('div', null, props.children);
+        const single = { type: Wrapper, props: {}, children: ['only'] };
+        const several = { type: Wrapper, props: null, children: ['a', jsx('b', null, 'b')] };
+        expect(render_to_string(single)).toBe('<div>only</div>');
+        expect(render_to_string(several)).toBe('<div>a<b>b</b></div>');
+    });
+
+    test('renders fragments, lists and skips empty values', () => {
+        const items = ['one', 'two'].map(item => jsx('li', null, item));
+        const element = jsx('React.Fragment', null, jsx('ul', null, items), null, false, 0);
+        expect(render_to_string(element)).toBe('<ul><li>one</li><li>two</li></ul>0');
+    });
+});
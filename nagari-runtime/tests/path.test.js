@@ -0,0 +1,62 @@
+// Tests for the path module's glob, against the vectors the VM's tests use too
+
+import { afterAll, beforeAll, describe, expect, test } from '@jest/globals';
+import { mkdirSync, mkdtempSync, readFileSync, rmSync, writeFileSync } from 'node:fs';
+import { tmpdir } from 'node:os';
+import { dirname, join } from 'node:path';
+import { PathInterop } from '../dist/index.js';
+
+const vectors = JSON.parse(
+    readFileSync(new URL('../../tests/fixtures/glob.json', import.meta.url), 'utf8')
+);
+
+// A directory with one file per component vector, and one with the tree of the glob vectors
+let root;
+
+beforeAll(() => {
+    root = mkdtempSync(join(tmpdir(), 'nagari-glob-'));
+    vectors.components.forEach((vector, index) => {
+        const directory = join(root, 'components', String(index));
+        mkdirSync(directory, { recursive: true });
+        writeFileSync(join(directory, vector.name), '');
+    });
+    for (const file of vectors.tree) {
+        const path = join(root, 'tree', file);
+        mkdirSync(dirname(path), { recursive: true });
+        writeFileSync(path, '');
+    }
+});
+
+afterAll(() => {
+    rmSync(root, { recursive: true, force: true });
+});
+
+describe('path.glob', () => {
+    test('components match as in the VM', async () => {
+        for (const [index, vector] of vectors.components.entries()) {
+            const directory = join(root, 'components', String(index));
+            const found = await PathInterop.glob(PathInterop.join(directory, vector.pattern));
+            expect([vector.pattern, vector.name, found.length === 1])
+                .toEqual([vector.pattern, vector.name, vector.matches]);
+        }
+    });
+
+    test('patterns find the same paths as in the VM', async () => {
+        const tree = PathInterop.join(root, 'tree') + PathInterop.sep;
+        for (const vector of vectors.globs) {
+            const found = await PathInterop.glob(tree + vector.pattern);
+            const relative = found.map(path => path.slice(tree.length).split(PathInterop.sep).join('/'));
+            expect([vector.pattern, relative]).toEqual([vector.pattern, vector.matches]);
+        }
+    });
+
+    test('patterns with .. after a wildcard are rejected as in the VM', async () => {
+        const tree = PathInterop.join(root, 'tree') + PathInterop.sep;
+        for (const pattern of vectors.rejected) {
+            await expect(PathInterop.glob(tree + pattern)).rejects.toThrow("'..' can't follow a wildcard");
+        }
+        // Before any wildcard, `..` is part of where globbing starts looking
+        const found = await PathInterop.glob(tree + 'data/../*');
+        expect(found).toContain(tree + ['data', '..', 'main.nag'].join(PathInterop.sep));
+    });
+});
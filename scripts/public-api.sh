@@ -0,0 +1,42 @@
+#!/bin/bash
+# Check the public APIs of the library crates against the snapshots in public-api/
+# Usage: ./scripts/public-api.sh [--update]
+#
+# Needs cargo-public-api (cargo install cargo-public-api --locked) and a nightly toolchain.
+# Run with --update after an intended API change and commit the snapshots with it.
+
+set -e
+
+CRATES="nagari-compiler nagari-embedded nagari-wasm"
+SNAPSHOTS="public-api"
+
+if [ ! -f "Cargo.toml" ] || [ ! -d "$SNAPSHOTS" ]; then
+    echo "This script must be run from the project root directory"
+    exit 1
+fi
+
+status=0
+for crate in $CRATES; do
+    snapshot="$SNAPSHOTS/$crate.txt"
+    current=$(mktemp)
+    cargo public-api --package "$crate" --simplified --simplified --simplified --color never > "$current"
+
+    if [ "$1" = "--update" ]; then
+        mv "$current" "$snapshot"
+        echo "Updated $snapshot"
+    elif [ ! -f "$snapshot" ]; then
+        echo "No snapshot of $crate's public API; run ./scripts/public-api.sh --update"
+        rm "$current"
+        status=1
+    elif ! diff -u "$snapshot" "$current"; then
+        echo "The public API of $crate changed. If that is intended, deprecate what was removed"
+        echo "or renamed (see docs/api-stability.md) and run ./scripts/public-api.sh --update"
+        rm "$current"
+        status=1
+    else
+        echo "$crate: public API unchanged"
+        rm "$current"
+    fi
+done
+
+exit $status
@@ -1,10 +1,18 @@
-use crate::config::NagConfig;
+use crate::config::{MessageFormat, NagConfig};
+use crate::package::credentials::{self, TokenSource};
+use crate::package::manifest::PackageManifest;
+use crate::package::registry::RegistryClient;
+use crate::package::scripts;
 use crate::package::PackageManager;
 use crate::repl_engine::ReplEngine;
-use crate::{DocCommands, PackageCommands};
+use crate::tools::diagnostics::{self, Diagnostic};
+use crate::{DocCommands, PackageCommands, ToolingCommands};
 use anyhow::{Context, Result};
+use clap::CommandFactory;
 use colored::*;
+use nagari_compiler::{CompilationTimings, NagariError};
 use notify::{recommended_watcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::channel;
@@ -57,9 +65,12 @@ pub async fn run_command(
     file: PathBuf,
     args: Vec<String>,
     watch: bool,
+    quiet: bool,
     config: &NagConfig,
 ) -> Result<()> {
-    println!("{} Running {}", "✓".green().bold(), file.display());
+    if !quiet {
+        println!("{} Running {}", "✓".green().bold(), file.display());
+    }
 
     if watch {
         println!(
@@ -116,8 +127,16 @@ async fn run_file_once(file: &PathBuf, args: &[String], config: &NagConfig) -> R
     let compiler_config = nagari_compiler::CompilerConfigBuilder::new()
         .target(&config.build.target)
         .jsx(config.build.jsx)
+        .ssr(config.build.ssr)
         .sourcemap(config.build.sourcemap)
+        .environment(config.build.environment)
+        .runtime(config.build.runtime.clone())
         .verbose(config.verbose)
+        .warning_levels(config.build.warning_levels.clone())
+        .deny_warnings(config.build.deny_warnings)
+        .strict_types(config.build.strict_types)
+        .defines(compile_time_defines(config))
+        .externs(extern_declarations(config)?)
         .build();
 
     let compiler = nagari_compiler::Compiler::with_config(compiler_config);
@@ -252,20 +271,28 @@ fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn build_command(
     input: PathBuf,
     output: Option<PathBuf>,
     target: String,
     release: bool,
     sourcemap: bool,
+    timings: bool,
+    dual: bool,
+    declarations: bool,
     config: &NagConfig,
 ) -> Result<()> {
-    println!(
-        "{} Building {} (target: {})",
-        "🔨".yellow(),
-        input.display(),
-        target
-    );
+    // Human-oriented progress would corrupt machine-readable output
+    let quiet = config.json || config.message_format.is_machine_readable();
+    if !quiet {
+        println!(
+            "{} Building {} (target: {})",
+            "🔨".yellow(),
+            input.display(),
+            target
+        );
+    }
     let output_dir = output.unwrap_or_else(|| PathBuf::from(&config.project.output_dir));
     std::fs::create_dir_all(&output_dir)?;
 
@@ -273,22 +300,108 @@ pub async fn build_command(
     let compiler_config = nagari_compiler::CompilerConfigBuilder::new()
         .target(&target)
         .sourcemap(sourcemap)
+        .environment(config.build.environment)
+        .runtime(config.build.runtime.clone())
         .verbose(config.verbose)
         .minify(release)
+        .declarations(declarations)
+        .warning_levels(config.build.warning_levels.clone())
+        .deny_warnings(config.build.deny_warnings)
+        .strict_types(config.build.strict_types)
+        .defines(compile_time_defines(config))
+        .externs(extern_declarations(config)?)
+        .cache_dir(build_cache_dir())
+        .line_endings(config.build.line_endings)
         .build();
 
     let compiler = nagari_compiler::Compiler::with_config(compiler_config);
 
+    let mut outputs = Vec::new();
+    let mut failures = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut build_timings = crate::tools::build_timings::BuildTimings::new();
+    let mut record = |source: &Path,
+                      output_file: PathBuf,
+                      result: Result<CompilationTimings, NagariError>|
+     -> Result<()> {
+        match result {
+            Ok(module_timings) => {
+                if !quiet {
+                    println!("{} Generated {}", "✓".green(), output_file.display());
+                }
+                build_timings.add(source, module_timings);
+                outputs.push(output_file);
+                Ok(())
+            }
+            // In machine-readable modes keep going so every failing file is reported
+            Err(e) if quiet => {
+                failures.push(serde_json::json!({
+                    "file": source,
+                    "code": e.code(),
+                    "error": e.to_string(),
+                }));
+                diagnostics.push(Diagnostic::from_error(source, &e));
+                Ok(())
+            }
+            Err(e) => Err(anyhow::anyhow!(
+                "[{}] {}\n  Run `nag explain {}` for more information",
+                e.code(),
+                e,
+                e.code()
+            )),
+        }
+    };
+
     match target.as_str() {
-        "js" => {
+        "js" if dual => {
+            use nagari_compiler::dual::{self, Format};
+
+            let jobs = if input.is_file() {
+                let module = input.file_stem().unwrap().to_string_lossy().into_owned();
+                vec![(input.clone(), module)]
+            } else {
+                dual::collect_modules(&input)
+                    .with_context(|| format!("Failed to read {}", input.display()))?
+            };
+            let results = compiler.compile_dual(&jobs, &output_dir);
+            for ((source, module), result) in jobs.iter().zip(results) {
+                let output_file = dual::module_file(&output_dir, Format::Esm, module);
+                record(source, output_file, result)?;
+            }
+
+            // The exports map would point at modules that failed to build
+            if failures.is_empty() {
+                let modules: Vec<String> = jobs.into_iter().map(|(_, module)| module).collect();
+                let source_dir = if input.is_file() {
+                    input.parent().unwrap_or(Path::new("")).to_path_buf()
+                } else {
+                    input.clone()
+                };
+                let package = dual_package_info(config, &source_dir, &modules);
+                let package_json =
+                    dual::package_json(&package, &modules).map_err(anyhow::Error::msg)?;
+                let package_file = output_dir.join("package.json");
+                fs::write(&package_file, package_json)
+                    .with_context(|| format!("Failed to write {}", package_file.display()))?;
+                if !quiet {
+                    println!("{} Generated {}", "✓".green(), package_file.display());
+                }
+            }
+        }
+        _ if dual => {
+            anyhow::bail!("--dual only applies to the js target, not {}", target);
+        }
+        // `es5` is JavaScript lowered for legacy engines
+        "js" | "es5" => {
             if input.is_file() {
                 let output_file = output_dir
                     .join(input.file_stem().unwrap())
                     .with_extension("js");
-                compiler.compile_to_file(&input, &output_file)?;
-                println!("{} Generated {}", "✓".green(), output_file.display());
+                let result = compiler.compile_to_file_timed(&input, &output_file);
+                record(&input, output_file, result)?;
             } else {
-                // Process directory recursively
+                // Process directory recursively, parsing all modules in parallel
+                let mut jobs = Vec::new();
                 for entry in walkdir::WalkDir::new(&input) {
                     let entry = entry?;
                     if entry.file_type().is_file()
@@ -296,32 +409,278 @@ pub async fn build_command(
                     {
                         let relative_path = entry.path().strip_prefix(&input)?;
                         let output_file = output_dir.join(relative_path).with_extension("js");
-
-                        if let Some(parent) = output_file.parent() {
-                            std::fs::create_dir_all(parent)?;
-                        }
-
-                        compiler.compile_to_file(entry.path(), &output_file)?;
-                        println!("{} Generated {}", "✓".green(), output_file.display());
+                        jobs.push((entry.into_path(), output_file));
                     }
                 }
+
+                // Modules unchanged since the last build, along with what they import, come
+                // from the project's incremental build cache
+                let inputs: Vec<&Path> = jobs.iter().map(|(source, _)| source.as_path()).collect();
+                let results = compiler.compile_project(&inputs);
+                for ((source, output_file), result) in jobs.iter().zip(results) {
+                    let result =
+                        result.and_then(|module| compiler.write_module(module, output_file));
+                    record(source, output_file.clone(), result)?;
+                }
             }
         }
         "bytecode" => {
-            println!("{} Bytecode target not yet implemented", "⚠️".yellow());
+            if !quiet {
+                println!("{} Bytecode target not yet implemented", "⚠️".yellow());
+            }
         }
         "wasm" => {
-            println!("{} WASM target not yet implemented", "⚠️".yellow());
+            if !quiet {
+                println!("{} WASM target not yet implemented", "⚠️".yellow());
+            }
         }
         _ => {
             anyhow::bail!("Unknown target: {}", target);
         }
     }
 
+    let timings_report = if timings {
+        Some(build_timings.write_reports(&output_dir)?)
+    } else {
+        None
+    };
+
+    if config.message_format.is_machine_readable() {
+        emit_diagnostics("build", &diagnostics, config)?;
+        if !diagnostics.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if config.json {
+        let mut result = serde_json::json!({
+            "command": "build",
+            "success": failures.is_empty(),
+            "target": target,
+            "outputs": outputs,
+            "errors": failures,
+        });
+        if timings {
+            result["timings"] = build_timings.to_json();
+        }
+        emit_json(&result)?;
+        if !failures.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if let Some((json_report, html_report)) = timings_report {
+        println!("{} Build timings", "⏱️".cyan());
+        println!("{}", build_timings.summary(10));
+        println!(
+            "Reports written to {} and {}",
+            html_report.display(),
+            json_report.display()
+        );
+    }
+
     println!("{} Build completed!", "🎉".green().bold());
     Ok(())
 }
 
+pub async fn check_command(paths: Vec<PathBuf>, config: &NagConfig) -> Result<()> {
+    let files = source_files(paths, config)?;
+
+    if !config.json && !config.message_format.is_machine_readable() {
+        println!("{} Checking {} file(s)...", "🔍".cyan(), files.len());
+    }
+
+    let compiler = nagari_compiler::Compiler::with_config(
+        nagari_compiler::CompilerConfigBuilder::new()
+            .target(&config.build.target)
+            .jsx(config.build.jsx)
+            .ssr(config.build.ssr)
+            .verbose(config.verbose)
+            .warning_levels(config.build.warning_levels.clone())
+            .deny_warnings(config.build.deny_warnings)
+            .strict_types(config.build.strict_types)
+            .defines(compile_time_defines(config))
+            .externs(extern_declarations(config)?)
+            .build(),
+    );
+
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for (file, file_diagnostics) in files.iter().zip(compiler.diagnose_files(&files)) {
+        for diagnostic in file_diagnostics {
+            match diagnostic.severity {
+                nagari_compiler::Severity::Error => errors.push((file, diagnostic)),
+                _ => warnings.push((file, diagnostic)),
+            }
+        }
+    }
+
+    if config.message_format.is_machine_readable() {
+        let diagnostics: Vec<Diagnostic> = errors
+            .iter()
+            .chain(&warnings)
+            .map(|(file, diagnostic)| Diagnostic::from_compiler(file, diagnostic))
+            .collect();
+        emit_diagnostics("check", &diagnostics, config)?;
+        if !errors.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if config.json {
+        emit_json(&serde_json::json!({
+            "command": "check",
+            "success": errors.is_empty(),
+            "files": files.len(),
+            "errors": errors.iter().map(|(file, diagnostic)| serde_json::json!({
+                "file": file,
+                "code": diagnostic.code,
+                "error": diagnostic.message,
+                "line": diagnostic.span.map(|span| span.line),
+            })).collect::<Vec<_>>(),
+            "warnings": warnings.iter().map(|(file, diagnostic)| serde_json::json!({
+                "file": file,
+                "category": diagnostic.code,
+                "message": diagnostic.message,
+                "line": diagnostic.span.map(|span| span.line),
+            })).collect::<Vec<_>>(),
+        }))?;
+        if !errors.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    for (file, diagnostic) in errors.iter().chain(&warnings) {
+        let source = fs::read_to_string(file).ok();
+        println!(
+            "{}",
+            diagnostic.render(&file.display().to_string(), source.as_deref())
+        );
+    }
+
+    if !errors.is_empty() {
+        println!(
+            "{} Found {} error(s) and {} warning(s)",
+            "❌".red(),
+            errors.len(),
+            warnings.len()
+        );
+        println!("Run `nag explain <code>` for more information about an error");
+        std::process::exit(1);
+    }
+    if warnings.is_empty() {
+        println!("{} No problems found", "✓".green());
+    } else {
+        println!("{} Found {} warning(s)", "⚠️".yellow(), warnings.len());
+    }
+    Ok(())
+}
+
+/// The `.nag` files in `paths`, or in the project source directory if there are none
+fn source_files(paths: Vec<PathBuf>, config: &NagConfig) -> Result<Vec<PathBuf>> {
+    let paths = if paths.is_empty() {
+        vec![PathBuf::from(&config.project.source_dir)]
+    } else {
+        paths
+    };
+
+    let mut files = Vec::new();
+    for path in &paths {
+        if path.is_file() {
+            files.push(path.clone());
+            continue;
+        }
+        if !path.exists() {
+            anyhow::bail!("Path not found: {}", path.display());
+        }
+        for entry in walkdir::WalkDir::new(path) {
+            let entry = entry?;
+            if entry.file_type().is_file()
+                && entry.path().extension().and_then(|s| s.to_str()) == Some("nag")
+            {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Incremental build cache of the project the command runs in, or of the current directory
+fn build_cache_dir() -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    crate::utils::find_project_root(&cwd)
+        .unwrap_or(cwd)
+        .join(nagari_compiler::project::CACHE_DIR)
+}
+
+/// `[build] define` entries of the configuration, read as literals
+fn compile_time_defines(config: &NagConfig) -> HashMap<String, nagari_compiler::ast::Literal> {
+    config
+        .build
+        .define
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.clone(),
+                nagari_compiler::defines::parse_value(value),
+            )
+        })
+        .collect()
+}
+
+/// Declarations from the `[build] typings` files and directories that exist
+fn extern_declarations(config: &NagConfig) -> Result<nagari_compiler::ExternDeclarations> {
+    let mut declarations = nagari_compiler::ExternDeclarations::new();
+    for path in config.build.typings.iter().map(Path::new) {
+        if path.exists() {
+            declarations.extend(nagari_compiler::ExternDeclarations::load(path)?);
+        }
+    }
+    Ok(declarations)
+}
+
+/// The package a `--dual` build of `modules` from `source_dir` describes, from `[project]`
+fn dual_package_info(
+    config: &NagConfig,
+    source_dir: &Path,
+    modules: &[String],
+) -> nagari_compiler::dual::PackageInfo {
+    let project = &config.project;
+    let main = project
+        .main
+        .as_deref()
+        .map(|main| nagari_compiler::dual::module_path(Path::new(main), source_dir))
+        .filter(|main| modules.contains(main))
+        // A single-module build is its own main module
+        .or_else(|| (modules.len() == 1).then(|| modules[0].clone()));
+    nagari_compiler::dual::PackageInfo {
+        name: project.name.clone(),
+        version: project.version.clone(),
+        description: project.description.clone(),
+        license: project.license.clone(),
+        main,
+        runtime: config.build.runtime.clone(),
+        ..Default::default()
+    }
+}
+
+/// Print a structured command result for `--json` mode.
+fn emit_json(value: &serde_json::Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Print diagnostics as the `--message-format` document
+fn emit_diagnostics(tool: &str, diagnostics: &[Diagnostic], config: &NagConfig) -> Result<()> {
+    let document = match config.message_format {
+        MessageFormat::Sarif => diagnostics::to_sarif(diagnostics),
+        MessageFormat::Json | MessageFormat::Human => diagnostics::to_json(tool, diagnostics),
+    };
+    emit_json(&document)
+}
+
 pub async fn transpile_command(
     input: PathBuf,
     output: Option<PathBuf>,
@@ -330,12 +689,14 @@ pub async fn transpile_command(
     declarations: bool,
     config: &NagConfig,
 ) -> Result<()> {
-    println!(
-        "{} Transpiling {} (format: {})",
-        "🔄".cyan(),
-        input.display(),
-        format
-    );
+    if !config.json {
+        println!(
+            "{} Transpiling {} (format: {})",
+            "🔄".cyan(),
+            input.display(),
+            format
+        );
+    }
 
     let output_dir = output.unwrap_or_else(|| PathBuf::from(&config.project.output_dir));
     build_command(
@@ -344,18 +705,12 @@ pub async fn transpile_command(
         "js".to_string(),
         false,
         true,
+        false,
+        false,
+        declarations,
         config,
     )
-    .await?;
-
-    if declarations {
-        println!(
-            "{} TypeScript declarations not yet implemented",
-            "⚠️".yellow()
-        );
-    }
-
-    Ok(())
+    .await
 }
 
 pub async fn bundle_command(
@@ -463,9 +818,12 @@ pub async fn lint_command(
     format: String,
     config: &NagConfig,
 ) -> Result<()> {
-    println!("{} Linting files...", "🔍".cyan());
+    if !config.json && !config.message_format.is_machine_readable() {
+        println!("{} Linting files...", "🔍".cyan());
+    }
 
-    let linter = crate::tools::linter::NagLinter::new(&config.lint);
+    let linter = crate::tools::linter::NagLinter::new(&config.lint)
+        .with_warning_levels(&config.build.warning_levels, config.build.deny_warnings);
     let mut all_issues = Vec::new();
 
     for path in paths {
@@ -475,6 +833,36 @@ pub async fn lint_command(
 
     let stats = linter.get_statistics(&all_issues);
 
+    if config.message_format.is_machine_readable() {
+        let mut sources = HashMap::new();
+        let diagnostics: Vec<Diagnostic> = all_issues
+            .iter()
+            .map(|issue| {
+                let source = sources
+                    .entry(issue.file.clone())
+                    .or_insert_with(|| fs::read_to_string(&issue.file).unwrap_or_default());
+                Diagnostic::from_lint_issue(issue, linter.suggest_fix(issue, source))
+            })
+            .collect();
+        emit_diagnostics("lint", &diagnostics, config)?;
+        if stats.has_errors() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if config.json {
+        emit_json(&serde_json::json!({
+            "command": "lint",
+            "success": !stats.has_errors(),
+            "issues": all_issues,
+            "statistics": stats,
+        }))?;
+        if stats.has_errors() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     if !all_issues.is_empty() {
         let formatted_output = linter.format_issues(&all_issues, &format)?;
         if !formatted_output.is_empty() {
@@ -516,13 +904,186 @@ pub async fn lint_command(
     Ok(())
 }
 
+pub async fn fix_command(
+    paths: Vec<PathBuf>,
+    dry_run: bool,
+    unsafe_fixes: bool,
+    config: &NagConfig,
+) -> Result<()> {
+    let files = source_files(paths, config)?;
+    let linter = crate::tools::linter::NagLinter::new(&config.lint)
+        .with_warning_levels(&config.build.warning_levels, config.build.deny_warnings);
+
+    let mut changed_files = 0;
+    let mut applied = 0;
+    let mut unsafe_skipped = 0;
+    for file in &files {
+        let result = crate::tools::autofix::fix_file(&linter, file, unsafe_fixes)?;
+        unsafe_skipped += result.unsafe_skipped;
+        if !result.changed() {
+            continue;
+        }
+        changed_files += 1;
+        applied += result.applied;
+
+        if dry_run {
+            print!("{}", result.diff());
+        } else {
+            fs::write(file, &result.fixed)?;
+            if config.verbose {
+                println!(
+                    "{} Fixed {} issue(s) in {}",
+                    "✓".green(),
+                    result.applied,
+                    file.display()
+                );
+            }
+        }
+    }
+
+    let verb = if dry_run { "Would fix" } else { "Fixed" };
+    if applied == 0 {
+        println!("{} Nothing to fix in {} file(s)", "✓".green(), files.len());
+    } else {
+        println!(
+            "{} {} {} issue(s) in {} file(s)",
+            "✓".green(),
+            verb,
+            applied,
+            changed_files
+        );
+    }
+    if unsafe_skipped > 0 {
+        println!(
+            "{} fix(es) may change behaviour and were not applied; run with --unsafe to apply them",
+            unsafe_skipped
+        );
+    }
+    Ok(())
+}
+
+pub async fn upgrade_command(
+    paths: Vec<PathBuf>,
+    dry_run: bool,
+    yes: bool,
+    only: Vec<String>,
+    list: bool,
+    config: &NagConfig,
+) -> Result<()> {
+    use crate::tools::upgrade;
+    use std::io::{BufRead, IsTerminal, Write};
+
+    let mut codemods = upgrade::codemods();
+    if list {
+        for codemod in &codemods {
+            println!(
+                "{} (deprecated in {})\n    {}",
+                codemod.name().bold(),
+                codemod.since(),
+                codemod.description()
+            );
+        }
+        return Ok(());
+    }
+    if !only.is_empty() {
+        for name in &only {
+            if !codemods.iter().any(|codemod| codemod.name() == name) {
+                anyhow::bail!("Unknown codemod '{}'; see `nag upgrade --list`", name);
+            }
+        }
+        codemods.retain(|codemod| only.iter().any(|name| name == codemod.name()));
+    }
+
+    let files = source_files(paths, config)?;
+    let interactive = !dry_run && !yes;
+    if interactive && !std::io::stdin().is_terminal() {
+        anyhow::bail!("Pass --yes to apply the changes or --dry-run to preview them");
+    }
+
+    let mut apply_all = yes;
+    let mut upgraded = 0;
+    let mut skipped = 0;
+    for file in &files {
+        let result = upgrade::upgrade_file(file, &codemods)?;
+        if !result.changed() {
+            continue;
+        }
+
+        if !apply_all {
+            print!("{}", result.diff());
+        }
+        if dry_run {
+            upgraded += 1;
+            continue;
+        }
+        if !apply_all {
+            print!("Apply to {}? [y]es/[n]o/[a]ll/[q]uit: ", file.display());
+            std::io::stdout().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().lock().read_line(&mut answer)?;
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => {}
+                "a" | "all" => apply_all = true,
+                "q" | "quit" => break,
+                _ => {
+                    skipped += 1;
+                    continue;
+                }
+            }
+        }
+
+        fs::write(file, &result.upgraded)?;
+        upgraded += 1;
+        if config.verbose || apply_all {
+            let applied: Vec<String> = result
+                .applied
+                .iter()
+                .map(|(name, count)| format!("{} ({})", name, count))
+                .collect();
+            println!(
+                "{} Upgraded {}: {}",
+                "✓".green(),
+                file.display(),
+                applied.join(", ")
+            );
+        }
+    }
+
+    let verb = if dry_run { "Would upgrade" } else { "Upgraded" };
+    if upgraded == 0 && skipped == 0 {
+        println!(
+            "{} Nothing to upgrade in {} file(s)",
+            "✓".green(),
+            files.len()
+        );
+    } else {
+        println!("{} {} {} file(s)", "✓".green(), verb, upgraded);
+    }
+    if skipped > 0 {
+        println!("{} file(s) left unchanged", skipped);
+    }
+    Ok(())
+}
+
 pub async fn test_command(
     _paths: Vec<PathBuf>,
     _pattern: Option<String>,
     coverage: bool,
     watch: bool,
-    _config: &NagConfig,
+    config: &NagConfig,
 ) -> Result<()> {
+    if config.json {
+        // TODO: Report real results once the test runner exists
+        return emit_json(&serde_json::json!({
+            "command": "test",
+            "success": true,
+            "status": "not_implemented",
+            "passed": 0,
+            "failed": 0,
+            "skipped": 0,
+        }));
+    }
+
     println!("{} Running tests...", "🧪".cyan());
 
     if watch {
@@ -610,6 +1171,7 @@ pub async fn package_command(command: PackageCommands, config: &NagConfig) -> Re
             dev,
             global,
             exact,
+            frozen_lockfile: _,
         } => {
             println!("{} Installing packages...", "📦".cyan());
             crate::tools::package_manager::install_packages(packages, dev, global, exact, config)
@@ -662,13 +1224,11 @@ pub async fn package_command(command: PackageCommands, config: &NagConfig) -> Re
             println!("{} Unpublishing package...", "📦".cyan());
             // TODO: Implement package unpublish
         }
-        PackageCommands::Login { registry: _ } => {
-            println!("{} Logging in to registry...", "📦".cyan());
-            // TODO: Implement registry login
+        PackageCommands::Login { registry } => {
+            login_command(registry, None, config).await?;
         }
         PackageCommands::Logout => {
-            println!("{} Logging out from registry...", "📦".cyan());
-            // TODO: Implement registry logout
+            logout_command(None, config).await?;
         }
         PackageCommands::Cache { command } => {
             match command {
@@ -703,12 +1263,17 @@ pub async fn handle_package_command(
             dev,
             global: _,
             exact: _,
+            frozen_lockfile,
         } => {
             if packages.is_empty() {
                 // Install from manifest
-                package_manager.install(vec![], false).await?;
+                package_manager
+                    .install(vec![], false, frozen_lockfile)
+                    .await?;
             } else {
-                package_manager.install(packages, dev).await?;
+                package_manager
+                    .install(packages, dev, frozen_lockfile)
+                    .await?;
             }
         }
         PackageCommands::Uninstall { packages } => {
@@ -736,14 +1301,10 @@ pub async fn handle_package_command(
             println!("{} Package unpublishing not yet implemented", "⚠️".yellow());
         }
         PackageCommands::Login { registry } => {
-            println!(
-                "{} Registry login not yet implemented (registry: {:?})",
-                "⚠️".yellow(),
-                registry
-            );
+            login_command(registry, None, config).await?;
         }
         PackageCommands::Logout => {
-            println!("{} Registry logout not yet implemented", "⚠️".yellow());
+            logout_command(None, config).await?;
         }
         PackageCommands::Cache { command } => match command {
             crate::CacheCommands::Info => {
@@ -763,19 +1324,138 @@ pub async fn handle_package_command(
             } else {
                 package
             };
-            package_manager.install(vec![pkg_with_version], dev).await?;
+            package_manager
+                .install(vec![pkg_with_version], dev, false)
+                .await?;
         }
         PackageCommands::Remove { packages } => {
             package_manager.uninstall(packages).await?;
         }
-        PackageCommands::Pack { output: _ } => {
-            println!("{} Package packing not yet implemented", "⚠️".yellow());
+        PackageCommands::Pack { output } => {
+            println!("{} Packing package...", "📦".cyan());
+            let output_dir = output.unwrap_or_else(|| PathBuf::from("."));
+            let tarball = crate::package::pack::pack(Path::new("."), &output_dir)?;
+            println!("{} Packed {}", "✓".green(), tarball.display());
         }
     }
 
     Ok(())
 }
 
+pub async fn vendor_command(dir: PathBuf, verify: bool, config: &NagConfig) -> Result<()> {
+    let mut package_manager = PackageManager::new(config.clone())?;
+    package_manager.vendor(dir, verify).await
+}
+
+pub async fn login_command(
+    registry: Option<String>,
+    username: Option<String>,
+    config: &NagConfig,
+) -> Result<()> {
+    let registry = registry.unwrap_or_else(|| config.package.registry.clone());
+    let client = RegistryClient::new(&registry)?;
+
+    let (token, user) = match username {
+        Some(username) => {
+            let password = rpassword::prompt_password(format!("Password for {}: ", username))?;
+            let response = client.login(&username, &password).await?;
+            (response.token, response.user)
+        }
+        None => {
+            let token = read_token(&registry)?;
+            let user = RegistryClient::with_auth(&registry, token.clone())?
+                .whoami()
+                .await?;
+            (token, user)
+        }
+    };
+
+    credentials::store(&registry, &token)?;
+    println!(
+        "{} Logged in to {} as {}",
+        "✅".green(),
+        registry,
+        user.username.bold()
+    );
+    if std::env::var_os(credentials::TOKEN_ENV).is_some() {
+        println!(
+            "{} {} is set and is used instead of the stored token",
+            "⚠️".yellow(),
+            credentials::TOKEN_ENV
+        );
+    }
+    Ok(())
+}
+
+/// A token pasted at a hidden prompt, or piped in as in `echo $TOKEN | nag login`
+fn read_token(registry: &str) -> Result<String> {
+    use std::io::{BufRead, IsTerminal};
+
+    let token = if std::io::stdin().is_terminal() {
+        println!("Create an access token on {} and paste it below", registry);
+        rpassword::prompt_password("Token: ")?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        line
+    };
+    let token = token.trim();
+    if token.is_empty() {
+        anyhow::bail!("No token given");
+    }
+    Ok(token.to_string())
+}
+
+pub async fn logout_command(registry: Option<String>, config: &NagConfig) -> Result<()> {
+    let registry = registry.unwrap_or_else(|| config.package.registry.clone());
+    if credentials::remove(&registry)? {
+        println!("{} Logged out of {}", "✅".green(), registry);
+    } else {
+        println!("Not logged in to {}", registry);
+    }
+    if std::env::var_os(credentials::TOKEN_ENV).is_some() {
+        println!(
+            "{} {} is still set and is sent to the registry",
+            "⚠️".yellow(),
+            credentials::TOKEN_ENV
+        );
+    }
+    Ok(())
+}
+
+pub async fn whoami_command(registry: Option<String>, config: &NagConfig) -> Result<()> {
+    let registry = registry.unwrap_or_else(|| config.package.registry.clone());
+    let (token, source) = credentials::token(&registry).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Not logged in to {}; run `nag login` or set {}",
+            registry,
+            credentials::TOKEN_ENV
+        )
+    })?;
+    let user = RegistryClient::with_auth(&registry, token)?.whoami().await?;
+
+    if config.json {
+        return emit_json(&serde_json::json!({
+            "registry": registry,
+            "username": user.username,
+            "email": user.email,
+            "source": match source {
+                TokenSource::Environment => "env",
+                TokenSource::Keyring => "keyring",
+            },
+        }));
+    }
+    println!("{}", user.username);
+    if config.verbose {
+        let source = match source {
+            TokenSource::Environment => credentials::TOKEN_ENV,
+            TokenSource::Keyring => "the OS keyring",
+        };
+        println!("Registry: {}\nToken from {}", registry, source);
+    }
+    Ok(())
+}
+
 // Enhanced REPL command
 pub async fn handle_repl_command(
     script: Option<PathBuf>,
@@ -812,6 +1492,111 @@ pub async fn handle_repl_command(
     Ok(())
 }
 
+pub async fn completions_command(shell: clap_complete::Shell) -> Result<()> {
+    let mut command = crate::Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+    Ok(())
+}
+
+pub async fn explain_command(code: Option<String>, list: bool, config: &NagConfig) -> Result<()> {
+    use nagari_compiler::explain;
+
+    if list {
+        if config.json {
+            let codes: Vec<_> = explain::all()
+                .iter()
+                .map(|e| serde_json::json!({ "code": e.code, "title": e.title }))
+                .collect();
+            return emit_json(&serde_json::json!({ "command": "explain", "codes": codes }));
+        }
+        for explanation in explain::all() {
+            println!(
+                "{}  {}",
+                explanation.code.yellow().bold(),
+                explanation.title
+            );
+        }
+        return Ok(());
+    }
+
+    let code = code.unwrap_or_default();
+    let explanation = explain::lookup(&code).ok_or_else(|| {
+        anyhow::anyhow!(
+            "No explanation for error code '{}'. Run `nag explain --list` to see all codes",
+            code
+        )
+    })?;
+
+    if config.json {
+        return emit_json(&serde_json::json!({
+            "command": "explain",
+            "code": explanation.code,
+            "title": explanation.title,
+            "explanation": explanation.text,
+        }));
+    }
+
+    println!(
+        "{} {}",
+        format!("[{}]", explanation.code).yellow().bold(),
+        explanation.title.bold()
+    );
+    println!();
+    print!("{}", explanation.text);
+    Ok(())
+}
+
+pub async fn tooling_command(command: ToolingCommands, config: &NagConfig) -> Result<()> {
+    match command {
+        ToolingCommands::GenGrammar { output, format } => {
+            use nagari_parser::grammar;
+
+            let (textmate, tree_sitter) = match format.as_str() {
+                "all" => (true, true),
+                "textmate" => (true, false),
+                "tree-sitter" => (false, true),
+                other => anyhow::bail!(
+                    "Unknown grammar format '{}'. Expected all, textmate or tree-sitter",
+                    other
+                ),
+            };
+
+            let mut written = Vec::new();
+            if textmate {
+                fs::create_dir_all(&output)?;
+                let path = output.join("nagari.tmLanguage.json");
+                let mut content = serde_json::to_string_pretty(&grammar::textmate_grammar())?;
+                content.push('\n');
+                fs::write(&path, content)?;
+                written.push(path);
+            }
+            if tree_sitter {
+                let dir = output.join("tree-sitter-nagari");
+                fs::create_dir_all(dir.join("queries"))?;
+                let grammar_path = dir.join("grammar.js");
+                fs::write(&grammar_path, grammar::tree_sitter_grammar())?;
+                let highlights_path = dir.join("queries").join("highlights.scm");
+                fs::write(&highlights_path, grammar::tree_sitter_highlights())?;
+                written.push(grammar_path);
+                written.push(highlights_path);
+            }
+
+            if config.json {
+                return emit_json(&serde_json::json!({
+                    "command": "tooling gen-grammar",
+                    "files": written,
+                }));
+            }
+            for path in &written {
+                println!("{} Wrote {}", "✓".green(), path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn lsp_command(mode: String, port: Option<u16>, config: &NagConfig) -> Result<()> {
     println!(
         "{} Starting Nagari Language Server (mode: {})",
@@ -841,6 +1626,122 @@ pub async fn lsp_command(mode: String, port: Option<u16>, config: &NagConfig) ->
     Ok(())
 }
 
+pub async fn install_script_command(
+    script: PathBuf,
+    dir: Option<PathBuf>,
+    name: Option<String>,
+    force: bool,
+) -> Result<()> {
+    if !script.is_file() {
+        anyhow::bail!("Script not found: {}", script.display());
+    }
+    let script = script.canonicalize()?;
+    let dir = match dir {
+        Some(dir) => dir,
+        None => dirs::home_dir()
+            .context("Could not find the home directory; pass --dir")?
+            .join(".local")
+            .join("bin"),
+    };
+    let name = match name {
+        Some(name) => name,
+        None => script
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("The script name is not valid UTF-8; pass --name")?
+            .to_string(),
+    };
+
+    let (command, wrapper) = if cfg!(windows) {
+        (
+            dir.join(format!("{name}.cmd")),
+            format!(
+                "@echo off\r\n\
+                 rem Installed by `nag install-script` from {0}\r\n\
+                 nag run --quiet \"{0}\" -- %*\r\n",
+                script.display()
+            ),
+        )
+    } else {
+        // Single quotes keep the path as is; a quote in it is closed, escaped and reopened
+        let quoted = format!("'{}'", script.display().to_string().replace('\'', "'\\''"));
+        (
+            dir.join(&name),
+            format!(
+                "#!/bin/sh\n\
+                 # Installed by `nag install-script` from {}\n\
+                 exec nag run --quiet {} -- \"$@\"\n",
+                script.display(),
+                quoted
+            ),
+        )
+    };
+    if command.exists() && !force {
+        anyhow::bail!(
+            "{} already exists; pass --force to replace it",
+            command.display()
+        );
+    }
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&command, wrapper)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&command, fs::Permissions::from_mode(0o755))?;
+    }
+
+    println!(
+        "{} Installed {} as {}",
+        "✓".green(),
+        script.display(),
+        command.display()
+    );
+    let on_path = std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|entry| entry == dir));
+    if !on_path {
+        println!("Add {} to PATH to run it as `{}`", dir.display(), name);
+    }
+    Ok(())
+}
+
+pub async fn run_script_command(
+    name: Option<String>,
+    args: Vec<String>,
+    env: Vec<String>,
+    _config: &NagConfig,
+) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let project_dir = crate::utils::find_project_root(&current_dir)
+        .filter(|root| root.join("nagari.json").is_file())
+        .context("No nagari.json found in this directory or its parents")?;
+    let manifest_path = project_dir.join("nagari.json");
+    let manifest = PackageManifest::from_file(&manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+
+    let Some(name) = name else {
+        let mut scripts: Vec<_> = manifest.scripts.iter().collect();
+        scripts.sort();
+        if scripts.is_empty() {
+            println!("No scripts in {}", manifest_path.display());
+        }
+        for (name, command) in scripts {
+            println!("{}\n    {}", name.bold(), command);
+        }
+        return Ok(());
+    };
+
+    let env = env
+        .iter()
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Invalid env '{}' (expected KEY=VALUE)", pair))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    scripts::run(&project_dir, &manifest, &name, &args, &env)
+}
+
 pub async fn init_command(
     name: Option<String>,
     template: String,
@@ -941,6 +1842,7 @@ sourcemap = true
     let gitignore_content = r#"# Nagari build outputs
 dist/
 *.js.map
+.nag-cache/
 
 # Dependencies
 node_modules/
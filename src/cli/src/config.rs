@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use anyhow::Result;
+use nagari_compiler::{Environment, LineEndings, RuntimePackage, WarningLevel};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NagConfig {
@@ -13,6 +14,32 @@ pub struct NagConfig {
     pub test: TestConfig,
     pub package: PackageConfig,
     pub verbose: bool,
+    /// Emit machine-readable JSON results instead of human-oriented text
+    #[serde(default)]
+    pub json: bool,
+    /// How build, check and lint report diagnostics
+    #[serde(default)]
+    pub message_format: MessageFormat,
+}
+
+/// Output format of compiler and linter diagnostics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    /// Human-oriented text
+    #[default]
+    Human,
+    /// One JSON document in the stable diagnostics schema
+    Json,
+    /// A SARIF 2.1.0 log, for code scanning services
+    Sarif,
+}
+
+impl MessageFormat {
+    /// Whether diagnostics are a document for tools, so nothing else may go to stdout
+    pub fn is_machine_readable(self) -> bool {
+        self != MessageFormat::Human
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,10 +62,42 @@ pub struct BuildConfig {
     pub sourcemap: bool,
     pub minify: bool,
     pub jsx: bool,
+    /// Build JSX for server-side rendering with the runtime's `render_to_string` instead of
+    /// React; needs `jsx` and the `node` target
+    #[serde(default)]
+    pub ssr: bool,
     pub declarations: bool,
     pub treeshake: bool,
     pub external: Vec<String>,
     pub define: HashMap<String, String>,
+    /// Level per warning category; `warnings` sets the level of every other category
+    #[serde(default)]
+    pub warning_levels: HashMap<String, WarningLevel>,
+    /// Fail on every warning that has no explicit level
+    #[serde(default)]
+    pub deny_warnings: bool,
+    /// Fail on type errors instead of reporting them as `type-mismatch` warnings
+    #[serde(default)]
+    pub strict_types: bool,
+    /// `.nagd` files, or directories of them, declaring the types of untyped JavaScript modules
+    #[serde(default = "default_typings")]
+    pub typings: Vec<String>,
+    /// Engines the output runs on (`modern-browser`, `node18`, `node20` or `legacy`), which
+    /// decide the polyfills it carries
+    #[serde(default)]
+    pub environment: Option<Environment>,
+    /// Package the output imports the runtime from, e.g. a mirror such as
+    /// `@myorg/nagari-runtime` or a path to a vendored copy
+    #[serde(default)]
+    pub runtime: RuntimePackage,
+    /// Line endings of emitted files: `lf` (the default) or `crlf` on every platform, or
+    /// `native` for the building platform's
+    #[serde(default)]
+    pub line_endings: LineEndings,
+}
+
+fn default_typings() -> Vec<String> {
+    vec!["types".to_string()]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,6 +120,9 @@ pub struct FormatConfig {
     pub trailing_commas: bool,
     pub quote_style: String, // "single", "double", "prefer_single"
     pub space_around_operators: bool,
+    /// Merge, prune and sort the leading import block
+    #[serde(default)]
+    pub organize_imports: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -112,10 +174,18 @@ impl Default for NagConfig {
                 sourcemap: true,
                 minify: false,
                 jsx: false,
+                ssr: false,
                 declarations: false,
                 treeshake: true,
                 external: vec![],
                 define: HashMap::new(),
+                warning_levels: HashMap::new(),
+                deny_warnings: false,
+                strict_types: false,
+                typings: default_typings(),
+                environment: None,
+                runtime: RuntimePackage::default(),
+                line_endings: LineEndings::default(),
             },
             lsp: LspConfig {
                 enabled: true,
@@ -134,6 +204,7 @@ impl Default for NagConfig {
                 trailing_commas: true,
                 quote_style: "double".to_string(),
                 space_around_operators: true,
+                organize_imports: false,
             },
             lint: LintConfig {                enabled_rules: vec![
                     "unused-variables".to_string(),
@@ -167,6 +238,8 @@ impl Default for NagConfig {
                 auto_install: true,
             },
             verbose: false,
+            json: false,
+            message_format: MessageFormat::Human,
         }
     }
 }
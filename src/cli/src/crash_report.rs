@@ -0,0 +1,287 @@
+//! Crash bundles for internal errors.
+//!
+//! When `nag` panics, in the compiler, the VM or the CLI itself, the panic hook installed by
+//! [`install`] writes a directory holding what a bug report needs: a report with the version,
+//! platform, command line and panic message, the backtrace, the configuration in effect and a
+//! reproducer. Nothing leaves the machine; the user is told where the bundle is and asked to
+//! attach it to a GitHub issue. `--no-crash-report` keeps the default panic output instead.
+//!
+//! The reproducer comes from the `.nag` files on the command line. When compiling one of them
+//! panics at the same place, its lines are removed for as long as that keeps happening, so the
+//! bundle holds a small source that still crashes; otherwise the file is included as it is.
+
+use crate::config::NagConfig;
+use anyhow::Result;
+use std::backtrace::Backtrace;
+use std::panic::{AssertUnwindSafe, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const ISSUES_URL: &str = "https://github.com/ayanalamMOON/Nagari/issues/new";
+
+/// Compilations tried at most while reducing a reproducer
+const MAX_ATTEMPTS: usize = 500;
+/// Time spent at most reducing a reproducer
+const MAX_REDUCE_TIME: Duration = Duration::from_secs(20);
+
+static CONFIG: OnceLock<NagConfig> = OnceLock::new();
+/// Set once a bundle is written, so that further panics don't write more
+static REPORTED: AtomicBool = AtomicBool::new(false);
+/// Set while compiling candidate reproducers, whose panics are expected
+static REDUCING: AtomicBool = AtomicBool::new(false);
+/// Where the last panic while reducing happened
+static REDUCE_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Write a crash bundle for any panic from now on, with `config` as the configuration in effect
+pub fn install(config: &NagConfig) {
+    let _ = CONFIG.set(config.clone());
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if REDUCING.load(Ordering::SeqCst) {
+            *REDUCE_PANIC.lock().unwrap_or_else(|e| e.into_inner()) = location(info);
+            return;
+        }
+        if REPORTED.swap(true, Ordering::SeqCst) {
+            default_hook(info);
+            return;
+        }
+
+        let message = message(info);
+        match write_bundle(info, &message) {
+            Ok(dir) => eprintln!("{}", instructions(&message, location(info), &dir)),
+            Err(e) => {
+                default_hook(info);
+                eprintln!("Could not write a crash report: {}", e);
+            }
+        }
+    }));
+}
+
+fn message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+fn location(info: &PanicHookInfo) -> Option<String> {
+    info.location()
+        .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+}
+
+fn instructions(message: &str, location: Option<String>, dir: &Path) -> String {
+    let mut text = format!("error: internal error: {}\n", message);
+    if let Some(location) = location {
+        text.push_str(&format!("  --> {}\n", location));
+    }
+    text.push_str(&format!(
+        "\nThis is a bug in Nagari. A crash report was written to\n\n    {}\n\n\
+         It holds a reproducer, version information, the backtrace and your configuration; \
+         nothing has been sent anywhere.\nPlease open an issue at {} describing what you were \
+         doing, and attach the files of that directory after checking them for anything private.\n\
+         Pass --no-crash-report to skip writing the report.",
+        dir.display(),
+        ISSUES_URL
+    ));
+    text
+}
+
+/// A new bundle directory under the Nagari cache directory
+fn bundle_dir() -> Result<PathBuf> {
+    let name = format!(
+        "crash-{}-{}",
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        std::process::id()
+    );
+    let dir = crate::utils::get_cache_dir()?
+        .join("crash-reports")
+        .join(name);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn write_bundle(info: &PanicHookInfo, message: &str) -> Result<PathBuf> {
+    let dir = bundle_dir()?;
+    let backtrace = Backtrace::force_capture();
+    let config = CONFIG.get().cloned().unwrap_or_default();
+    let location = location(info);
+
+    let reproducer = std::env::args_os()
+        .skip(1)
+        .map(PathBuf::from)
+        .find(|path| path.is_file() && crate::utils::has_extension(path, "nag"))
+        .and_then(|path| Some((std::fs::read_to_string(&path).ok()?, path)));
+    let reproducer_note = match &reproducer {
+        Some((source, path)) => {
+            let reduced = reduce_in_thread(source, &config, location.clone());
+            let note = match &reduced {
+                Some(reduced) => format!(
+                    "`reproducer.nag` is `{}` reduced from {} to {} lines; compiling it panics at \
+                     the same place.",
+                    path.display(),
+                    source.lines().count(),
+                    reduced.lines().count()
+                ),
+                None => format!(
+                    "`reproducer.nag` is `{}` as it is; compiling it alone does not reproduce the \
+                     crash.",
+                    path.display()
+                ),
+            };
+            std::fs::write(
+                dir.join("reproducer.nag"),
+                reduced.as_deref().unwrap_or(source),
+            )?;
+            note
+        }
+        None => {
+            "No `.nag` file was given on the command line, so there is no reproducer.".to_string()
+        }
+    };
+
+    let command_line: Vec<String> = std::env::args().collect();
+    let report = format!(
+        "# Nagari crash report\n\n\
+         - Nagari: {}\n\
+         - Platform: {} {}\n\
+         - Command: `{}`\n\
+         - Thread: {}\n\
+         - Location: {}\n\n\
+         ## Panic\n\n```\n{}\n```\n\n\
+         ## Reproducer\n\n{}\n\n\
+         The backtrace is in `backtrace.txt` and the configuration in `config.json`.\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        command_line.join(" "),
+        std::thread::current().name().unwrap_or("unnamed"),
+        location.as_deref().unwrap_or("unknown"),
+        message,
+        reproducer_note
+    );
+
+    std::fs::write(dir.join("report.md"), report)?;
+    std::fs::write(dir.join("backtrace.txt"), backtrace.to_string())?;
+    std::fs::write(
+        dir.join("config.json"),
+        serde_json::to_string_pretty(&config)?,
+    )?;
+    Ok(dir)
+}
+
+/// Reduce `source` to lines whose compilation still panics at `location`, or `None` when
+/// compiling `source` doesn't panic there at all.
+///
+/// This runs on a thread of its own: a panic while the hook runs would abort the process.
+fn reduce_in_thread(source: &str, config: &NagConfig, location: Option<String>) -> Option<String> {
+    let location = location?;
+    let source = source.to_string();
+    let config = config.clone();
+    REDUCING.store(true, Ordering::SeqCst);
+    let reduced = std::thread::spawn(move || {
+        let compiler = nagari_compiler::Compiler::with_config(
+            nagari_compiler::CompilerConfigBuilder::new()
+                .target(&config.build.target)
+                .jsx(config.build.jsx)
+                .ssr(config.build.ssr)
+                .strict_types(config.build.strict_types)
+                .build(),
+        );
+        // The compiler recovers from some panics itself, so what counts is where one happened
+        let crashes = |candidate: &str| {
+            *REDUCE_PANIC.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                let _ = compiler.compile_string(candidate, None);
+            }));
+            REDUCE_PANIC
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .as_deref()
+                == Some(location.as_str())
+        };
+        if !crashes(&source) {
+            return None;
+        }
+        Some(reduce(&source, crashes))
+    })
+    .join()
+    .ok()
+    .flatten();
+    REDUCING.store(false, Ordering::SeqCst);
+    reduced
+}
+
+/// Remove chunks of lines from `source`, halving the chunk size down to single lines, as long
+/// as `crashes` holds for what remains
+fn reduce(source: &str, mut crashes: impl FnMut(&str) -> bool) -> String {
+    let started = Instant::now();
+    let mut attempts = 0;
+    let mut lines: Vec<&str> = source.lines().collect();
+    let mut chunk = lines.len().div_ceil(2).max(1);
+
+    loop {
+        let mut start = 0;
+        while start < lines.len() {
+            if attempts >= MAX_ATTEMPTS || started.elapsed() > MAX_REDUCE_TIME {
+                return join_lines(&lines);
+            }
+            attempts += 1;
+
+            let end = (start + chunk).min(lines.len());
+            let candidate: Vec<&str> = lines[..start]
+                .iter()
+                .chain(&lines[end..])
+                .copied()
+                .collect();
+            if !candidate.is_empty() && crashes(&join_lines(&candidate)) {
+                lines = candidate;
+            } else {
+                start = end;
+            }
+        }
+        if chunk == 1 {
+            return join_lines(&lines);
+        }
+        chunk = chunk.div_ceil(2);
+    }
+}
+
+fn join_lines(lines: &[&str]) -> String {
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduce_keeps_the_crashing_lines() {
+        let source = (1..=40)
+            .map(|n| match n {
+                7 => "boom = 1".to_string(),
+                31 => "bang = 2".to_string(),
+                _ => format!("x{} = {}", n, n),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let reduced = reduce(&source, |candidate| {
+            candidate.contains("boom") && candidate.contains("bang")
+        });
+        assert_eq!(reduced, "boom = 1\nbang = 2\n");
+
+        let mut calls = 0;
+        let unchanged = reduce("a\nb\n", |_| {
+            calls += 1;
+            false
+        });
+        assert_eq!(unchanged, "a\nb\n");
+        assert!(calls <= 3);
+    }
+}
@@ -1,8 +1,11 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{CommandFactory, Parser, Subcommand};
+use nagari_compiler::WarningLevel;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 mod commands;
 mod config;
+mod crash_report;
 mod lsp;
 mod package;
 mod repl;
@@ -11,7 +14,7 @@ mod tools;
 mod utils;
 
 use commands::*;
-use config::NagConfig;
+use config::{MessageFormat, NagConfig};
 
 #[derive(Parser)]
 #[command(name = "nag")]
@@ -28,6 +31,38 @@ pub struct Cli {
     /// Configuration file path
     #[arg(short, long, global = true)]
     pub config: Option<PathBuf>,
+
+    /// Emit machine-readable JSON output (build, lint, test)
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Format of build, check and lint diagnostics
+    #[arg(long, global = true, value_enum, value_name = "FORMAT")]
+    pub message_format: Option<MessageFormat>,
+
+    /// Report warnings in CATEGORY (`warnings` for all)
+    #[arg(short = 'W', long = "warn", global = true, value_name = "CATEGORY")]
+    pub warn: Vec<String>,
+
+    /// Silence warnings in CATEGORY (`warnings` for all)
+    #[arg(short = 'A', long = "allow", global = true, value_name = "CATEGORY")]
+    pub allow: Vec<String>,
+
+    /// Turn warnings in CATEGORY into errors (`warnings` for all)
+    #[arg(short = 'D', long = "deny", global = true, value_name = "CATEGORY")]
+    pub deny: Vec<String>,
+
+    /// Treat every warning without an explicit level as an error
+    #[arg(long, global = true)]
+    pub deny_warnings: bool,
+
+    /// Define a compile-time constant, e.g. `--define API_URL='"https://api.example.com"'`
+    #[arg(long = "define", global = true, value_name = "NAME=VALUE")]
+    pub defines: Vec<String>,
+
+    /// Don't write a crash report when nag hits an internal error
+    #[arg(long, global = true)]
+    pub no_crash_report: bool,
 }
 
 #[derive(Subcommand)]
@@ -42,6 +77,9 @@ pub enum Commands {
         /// Enable watch mode for hot reloading
         #[arg(short, long)]
         watch: bool,
+        /// Only print what the program prints
+        #[arg(short, long)]
+        quiet: bool,
     },
 
     /// Build/compile Nagari code
@@ -51,7 +89,7 @@ pub enum Commands {
         /// Output directory
         #[arg(short, long)]
         output: Option<PathBuf>,
-        /// Compilation target (js, bytecode, wasm)
+        /// Compilation target (js, es5, bytecode, wasm)
         #[arg(short, long, default_value = "js")]
         target: String,
         /// Enable optimizations
@@ -60,6 +98,18 @@ pub enum Commands {
         /// Generate source maps
         #[arg(long)]
         sourcemap: bool,
+        /// Record per-phase and per-module compile times and write a report
+        #[arg(long)]
+        timings: bool,
+        /// Emit an ES module and a CommonJS build with a package.json exports map
+        #[arg(long)]
+        dual: bool,
+    },
+
+    /// Report every error and warning a build would, without writing any output
+    Check {
+        /// Files or directories to check (defaults to the project source directory)
+        paths: Vec<PathBuf>,
     },
 
     /// Transpile Nagari to JavaScript
@@ -108,6 +158,9 @@ pub enum Commands {
         /// Print diff of changes
         #[arg(long)]
         diff: bool,
+        /// Merge duplicate imports, remove unused ones and sort them by origin
+        #[arg(long)]
+        organize_imports: bool,
     },
 
     /// Lint Nagari source code
@@ -122,6 +175,36 @@ pub enum Commands {
         format: String,
     },
 
+    /// Apply the suggested fixes of lint diagnostics
+    Fix {
+        /// Files or directories to fix (defaults to the project source directory)
+        paths: Vec<PathBuf>,
+        /// Show the changes as a diff instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Also apply fixes that may change behaviour
+        #[arg(long = "unsafe")]
+        unsafe_fixes: bool,
+    },
+
+    /// Rewrite sources written for an earlier language version to the current one
+    Upgrade {
+        /// Files or directories to upgrade (defaults to the project source directory)
+        paths: Vec<PathBuf>,
+        /// Show the changes as a diff instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Apply the changes without asking for each file
+        #[arg(short, long)]
+        yes: bool,
+        /// Only run these codemods
+        #[arg(long, value_name = "CODEMOD")]
+        only: Vec<String>,
+        /// List the available codemods
+        #[arg(long)]
+        list: bool,
+    },
+
     /// Run tests
     Test {
         /// Test files or directories
@@ -167,6 +250,62 @@ pub enum Commands {
         command: PackageCommands,
     },
 
+    /// Vendor locked dependencies into the repository
+    Vendor {
+        /// Vendor directory
+        #[arg(default_value = "vendor")]
+        dir: PathBuf,
+        /// Re-check vendored tarballs against nag.lock instead of vendoring
+        #[arg(long)]
+        verify: bool,
+    },
+
+    /// Log in to a package registry and store the token in the OS keyring
+    Login {
+        /// Registry URL (defaults to the configured registry)
+        #[arg(long)]
+        registry: Option<String>,
+        /// Log in with a username and password instead of pasting a token
+        #[arg(short, long)]
+        username: Option<String>,
+    },
+
+    /// Remove the stored token for a package registry
+    Logout {
+        /// Registry URL (defaults to the configured registry)
+        #[arg(long)]
+        registry: Option<String>,
+    },
+
+    /// Show which account the registry token belongs to
+    Whoami {
+        /// Registry URL (defaults to the configured registry)
+        #[arg(long)]
+        registry: Option<String>,
+    },
+
+    /// Generate shell completion scripts
+    Completions {
+        /// Target shell (bash, zsh, fish, powershell, elvish)
+        shell: clap_complete::Shell,
+    },
+
+    /// Explain a compiler error code in detail
+    Explain {
+        /// Error code, e.g. E0002
+        #[arg(required_unless_present = "list")]
+        code: Option<String>,
+        /// List all documented error codes
+        #[arg(long)]
+        list: bool,
+    },
+
+    /// Editor and language tooling
+    Tooling {
+        #[command(subcommand)]
+        command: ToolingCommands,
+    },
+
     /// Language Server Protocol
     Lsp {
         /// LSP mode (stdio, tcp, websocket)
@@ -177,6 +316,33 @@ pub enum Commands {
         port: Option<u16>,
     },
 
+    /// Install a script as a command that runs it with `nag run`
+    InstallScript {
+        /// Script to install
+        script: PathBuf,
+        /// Directory to put the command in (defaults to ~/.local/bin)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Command name (defaults to the script's file name without `.nag`)
+        #[arg(long)]
+        name: Option<String>,
+        /// Replace an existing command
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Run a script from the `scripts` of nagari.json with its pre/post hooks
+    RunScript {
+        /// Script name (lists the scripts when omitted)
+        name: Option<String>,
+        /// Set an environment variable for the script
+        #[arg(short, long = "env", value_name = "KEY=VALUE")]
+        env: Vec<String>,
+        /// Arguments to pass to the script
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
     /// Initialize new Nagari project
     Init {
         /// Project name
@@ -241,6 +407,19 @@ pub enum DocCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum ToolingCommands {
+    /// Generate editor syntax grammars from the lexer's token tables
+    GenGrammar {
+        /// Output directory
+        #[arg(short, long, default_value = "grammars")]
+        output: PathBuf,
+        /// Grammar format (all, textmate, tree-sitter)
+        #[arg(short, long, default_value = "all")]
+        format: String,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum PackageCommands {
     /// Initialize package.json equivalent
@@ -263,6 +442,9 @@ pub enum PackageCommands {
         /// Exact version matching
         #[arg(long)]
         exact: bool,
+        /// Fail instead of updating nag.lock (for CI)
+        #[arg(long)]
+        frozen_lockfile: bool,
     },
 
     /// Add package dependency
@@ -270,7 +452,7 @@ pub enum PackageCommands {
         /// Package name
         package: String,
         /// Package version
-        #[arg(short, long)]
+        #[arg(long)]
         version: Option<String>,
         /// Add as dev dependency
         #[arg(long)]
@@ -369,9 +551,27 @@ pub enum CacheCommands {
     Info,
 }
 
+/// Arguments with `nag script.nag args...`, as a `#!/usr/bin/env nag` line runs scripts,
+/// turned into `nag run --quiet script.nag -- args...`
+fn script_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let is_script = args.get(1).is_some_and(|first| {
+        let name = first.to_string_lossy();
+        !name.starts_with('-')
+            && Cli::command().find_subcommand(name.as_ref()).is_none()
+            && Path::new(first).is_file()
+    });
+    if is_script {
+        let rest = args.split_off(2);
+        args.splice(1..1, ["run".into(), "--quiet".into()]);
+        args.push("--".into());
+        args.extend(rest);
+    }
+    args
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(script_args(std::env::args_os().collect()));
     // Load configuration
     let mut config = NagConfig::load(cli.config.as_deref())?;
 
@@ -379,6 +579,37 @@ async fn main() -> anyhow::Result<()> {
     if cli.verbose {
         config.verbose = true;
     }
+    if cli.json {
+        config.json = true;
+    }
+    if let Some(message_format) = cli.message_format {
+        config.message_format = message_format;
+    }
+    for (categories, level) in [
+        (&cli.allow, WarningLevel::Allow),
+        (&cli.warn, WarningLevel::Warn),
+        (&cli.deny, WarningLevel::Deny),
+    ] {
+        for category in categories {
+            config.build.warning_levels.insert(category.clone(), level);
+        }
+    }
+    if cli.deny_warnings {
+        config.build.deny_warnings = true;
+    }
+    for define in &cli.defines {
+        let (name, value) = define
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid define '{}' (expected NAME=VALUE)", define))?;
+        config
+            .build
+            .define
+            .insert(name.trim().to_string(), value.to_string());
+    }
+
+    if !cli.no_crash_report {
+        crash_report::install(&config);
+    }
 
     // Set up logging based on verbosity
     if cli.verbose {
@@ -389,14 +620,27 @@ async fn main() -> anyhow::Result<()> {
 
     // Execute command
     match cli.command {
-        Commands::Run { file, args, watch } => run_command(file, args, watch, &config).await,
+        Commands::Run {
+            file,
+            args,
+            watch,
+            quiet,
+        } => run_command(file, args, watch, quiet, &config).await,
         Commands::Build {
             input,
             output,
             target,
             release,
             sourcemap,
-        } => build_command(input, output, target, release, sourcemap, &config).await,
+            timings,
+            dual,
+        } => {
+            build_command(
+                input, output, target, release, sourcemap, timings, dual, false, &config,
+            )
+            .await
+        }
+        Commands::Check { paths } => check_command(paths, &config).await,
         Commands::Transpile {
             input,
             output,
@@ -411,10 +655,30 @@ async fn main() -> anyhow::Result<()> {
             treeshake,
             external,
         } => bundle_command(entry, output, format, treeshake, external, &config).await,
-        Commands::Format { paths, check, diff } => {
+        Commands::Format {
+            paths,
+            check,
+            diff,
+            organize_imports,
+        } => {
+            if organize_imports {
+                config.format.organize_imports = true;
+            }
             format_command(paths, check, diff, &config).await
         }
         Commands::Lint { paths, fix, format } => lint_command(paths, fix, format, &config).await,
+        Commands::Fix {
+            paths,
+            dry_run,
+            unsafe_fixes,
+        } => fix_command(paths, dry_run, unsafe_fixes, &config).await,
+        Commands::Upgrade {
+            paths,
+            dry_run,
+            yes,
+            only,
+            list,
+        } => upgrade_command(paths, dry_run, yes, only, list, &config).await,
         Commands::Test {
             paths,
             pattern,
@@ -430,7 +694,23 @@ async fn main() -> anyhow::Result<()> {
         } => handle_repl_command(script, load, save, session, &config).await,
         Commands::Doc { command } => doc_command(command, &config).await,
         Commands::Package { command } => handle_package_command(command, &config).await,
+        Commands::Vendor { dir, verify } => vendor_command(dir, verify, &config).await,
+        Commands::Login { registry, username } => login_command(registry, username, &config).await,
+        Commands::Logout { registry } => logout_command(registry, &config).await,
+        Commands::Whoami { registry } => whoami_command(registry, &config).await,
+        Commands::Completions { shell } => completions_command(shell).await,
+        Commands::Explain { code, list } => explain_command(code, list, &config).await,
+        Commands::Tooling { command } => tooling_command(command, &config).await,
         Commands::Lsp { mode, port } => lsp_command(mode, port, &config).await,
+        Commands::InstallScript {
+            script,
+            dir,
+            name,
+            force,
+        } => install_script_command(script, dir, name, force).await,
+        Commands::RunScript { name, env, args } => {
+            run_script_command(name, args, env, &config).await
+        }
         Commands::Init {
             name,
             template,
@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use keyring::Entry;
+
+/// Environment variable whose token is used instead of the keyring, for CI
+pub const TOKEN_ENV: &str = "NAGARI_TOKEN";
+
+/// Keyring service the tokens are stored under, one entry per registry URL
+const KEYRING_SERVICE: &str = "nagari-registry";
+
+/// Where a registry token came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenSource {
+    Environment,
+    Keyring,
+}
+
+/// The token to send to `registry`: `NAGARI_TOKEN` when set, otherwise the one `nag login`
+/// stored in the OS keyring
+pub fn token(registry: &str) -> Option<(String, TokenSource)> {
+    if let Some(token) = env_token() {
+        return Some((token, TokenSource::Environment));
+    }
+    match entry(registry).and_then(|entry| Ok(entry.get_password()?)) {
+        Ok(token) => Some((token, TokenSource::Keyring)),
+        Err(error) => {
+            // No entry, or no keyring on this machine: requests go out without a token
+            log::debug!("No stored token for {}: {}", registry, error);
+            None
+        }
+    }
+}
+
+/// Store `token` for `registry` in the OS keyring
+pub fn store(registry: &str, token: &str) -> Result<()> {
+    entry(registry)?.set_password(token).with_context(|| {
+        format!(
+            "Could not store the token in the OS keyring; set {} instead",
+            TOKEN_ENV
+        )
+    })
+}
+
+/// Remove the token stored for `registry`, returning whether there was one
+pub fn remove(registry: &str) -> Result<bool> {
+    match entry(registry)?.delete_password() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(error) => Err(error).context("Could not remove the token from the OS keyring"),
+    }
+}
+
+fn env_token() -> Option<String> {
+    std::env::var(TOKEN_ENV)
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+fn entry(registry: &str) -> Result<Entry> {
+    Ok(Entry::new(KEYRING_SERVICE, &registry_key(registry))?)
+}
+
+/// `https://registry.example.com/` and `https://registry.example.com` share a token
+pub fn registry_key(registry: &str) -> String {
+    registry.trim().trim_end_matches('/').to_string()
+}
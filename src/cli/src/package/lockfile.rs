@@ -1,65 +1,161 @@
 #![allow(dead_code)]
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::fs;
 use anyhow::Result;
 use semver::Version;
+use sha2::{Digest, Sha512};
+use base64::{Engine as _, engine::general_purpose};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Current on-disk format version of `nag.lock`.
+pub const LOCKFILE_VERSION: &str = "3";
+
+// All maps are ordered so that serializing the same lock state always yields
+// byte-identical output, keeping diffs of `nag.lock` minimal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LockFile {
     pub lockfile_version: String,
     pub name: String,
     pub version: String,
     pub requires: bool,
-    pub packages: HashMap<String, LockedDependency>,
-    pub dependencies: HashMap<String, DependencyReference>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    pub packages: BTreeMap<String, LockedDependency>,
+    pub dependencies: BTreeMap<String, DependencyReference>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LockedDependency {
     pub version: String,
     pub resolved: String,
     pub integrity: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dev: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub optional: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub peer: Option<bool>,
-    pub requires: Option<HashMap<String, String>>,
-    pub dependencies: Option<HashMap<String, DependencyReference>>,
-    pub engines: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<BTreeMap<String, DependencyReference>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engines: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub os: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cpu: Option<Vec<String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DependencyReference {
     pub version: String,
-    pub requires: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<BTreeMap<String, String>>,
+}
+
+/// Differences between two lock states, keyed by package name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LockFileDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl LockFileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
 }
 
 impl LockFile {
     pub fn new(name: String, version: String) -> Self {
         Self {
-            lockfile_version: "3".to_string(),
+            lockfile_version: LOCKFILE_VERSION.to_string(),
             name,
             version,
             requires: true,
-            packages: HashMap::new(),
-            dependencies: HashMap::new(),
+            registry: None,
+            packages: BTreeMap::new(),
+            dependencies: BTreeMap::new(),
         }
     }
 
+    pub fn with_registry(mut self, registry: String) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = fs::read_to_string(path)?;
-        let lockfile: LockFile = serde_json::from_str(&content)?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let lockfile: LockFile = serde_json::from_str(content)?;
+        if lockfile.lockfile_version != LOCKFILE_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported lockfile version {} (expected {})",
+                lockfile.lockfile_version,
+                LOCKFILE_VERSION
+            ));
+        }
         Ok(lockfile)
     }
 
-    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let content = serde_json::to_string_pretty(self)?;
+    /// Serialize the lock file deterministically, with a trailing newline.
+    pub fn to_json_string(&self) -> Result<String> {
+        let mut content = serde_json::to_string_pretty(self)?;
+        content.push('\n');
+        Ok(content)
+    }
+
+    /// Write the lock file, leaving it untouched if the contents are unchanged.
+    ///
+    /// Returns `true` if the file was written.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref();
+        let content = self.to_json_string()?;
+        if let Ok(existing) = fs::read_to_string(path) {
+            if existing == content {
+                return Ok(false);
+            }
+        }
         fs::write(path, content)?;
-        Ok(())
+        Ok(true)
+    }
+
+    /// Compare this lock state against `other`, reporting packages that
+    /// `other` adds, removes or changes.
+    pub fn diff(&self, other: &LockFile) -> LockFileDiff {
+        let mut diff = LockFileDiff::default();
+
+        for (name, package) in &other.packages {
+            match self.packages.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(existing) if existing != package => diff.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+
+        for name in self.packages.keys() {
+            if !other.packages.contains_key(name) {
+                diff.removed.push(name.clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Check downloaded tarball contents against the locked integrity hash.
+    pub fn verify_package(&self, name: &str, data: &[u8]) -> Result<()> {
+        let package = self
+            .get_package(name)
+            .ok_or_else(|| anyhow::anyhow!("Package {} is not in the lockfile", name))?;
+        verify_integrity(&package.integrity, data)
+            .map_err(|e| anyhow::anyhow!("{}@{}: {}", name, package.version, e))
     }
 
     pub fn get_package(&self, name: &str) -> Option<&LockedDependency> {
@@ -124,7 +220,7 @@ impl LockFile {
         self.packages.keys().cloned().collect()
     }
 
-    pub fn get_direct_dependencies(&self) -> &HashMap<String, DependencyReference> {
+    pub fn get_direct_dependencies(&self) -> &BTreeMap<String, DependencyReference> {
         &self.dependencies
     }
 
@@ -153,13 +249,25 @@ impl LockFile {
     }
 
     pub fn validate_integrity(&self) -> Result<Vec<String>> {
-        let mut errors = Vec::new();        // Check that all required dependencies exist
+        let mut errors = Vec::new();
+
+        // Check that all required dependencies exist
         for name in self.dependencies.keys() {
             if !self.packages.contains_key(name) {
                 errors.push(format!("Missing package: {}", name));
             }
         }
 
+        // Check that every package carries a well-formed integrity hash
+        for (name, package) in &self.packages {
+            if parse_integrity(&package.integrity).is_none() {
+                errors.push(format!(
+                    "Package {} has an invalid integrity hash: {:?}",
+                    name, package.integrity
+                ));
+            }
+        }
+
         // Check that all package requirements are satisfied
         for (name, package) in &self.packages {
             if let Some(ref requires) = package.requires {
@@ -305,12 +413,12 @@ impl LockedDependency {
         self
     }
 
-    pub fn with_requires(mut self, requires: HashMap<String, String>) -> Self {
+    pub fn with_requires(mut self, requires: BTreeMap<String, String>) -> Self {
         self.requires = Some(requires);
         self
     }
 
-    pub fn with_dependencies(mut self, dependencies: HashMap<String, DependencyReference>) -> Self {
+    pub fn with_dependencies(mut self, dependencies: BTreeMap<String, DependencyReference>) -> Self {
         self.dependencies = Some(dependencies);
         self
     }
@@ -328,6 +436,50 @@ impl LockedDependency {
     }
 }
 
+/// Compute the integrity string (`sha512-<base64>`) for package tarball contents.
+pub fn compute_integrity(data: &[u8]) -> String {
+    let mut hasher = Sha512::new();
+    hasher.update(data);
+    format!("sha512-{}", general_purpose::STANDARD.encode(hasher.finalize()))
+}
+
+/// Verify `data` against an integrity string produced by [`compute_integrity`].
+///
+/// `sha256-` integrity strings written by the package cache are also accepted.
+pub fn verify_integrity(expected: &str, data: &[u8]) -> Result<()> {
+    let (algorithm, _) = parse_integrity(expected)
+        .ok_or_else(|| anyhow::anyhow!("invalid integrity hash {:?}", expected))?;
+
+    let actual = match algorithm {
+        "sha512" => compute_integrity(data),
+        "sha256" => {
+            let mut hasher = sha2::Sha256::new();
+            hasher.update(data);
+            format!("sha256-{}", general_purpose::STANDARD.encode(hasher.finalize()))
+        }
+        _ => unreachable!(),
+    };
+
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "integrity mismatch (expected {}, got {})",
+            expected,
+            actual
+        ))
+    }
+}
+
+fn parse_integrity(integrity: &str) -> Option<(&str, &str)> {
+    let (algorithm, digest) = integrity.split_once('-')?;
+    if !matches!(algorithm, "sha512" | "sha256") || digest.is_empty() {
+        return None;
+    }
+    general_purpose::STANDARD.decode(digest).ok()?;
+    Some((algorithm, digest))
+}
+
 // Helper functions
 
 fn version_satisfies(installed_version: &str, required_version: &str) -> bool {
@@ -1,17 +1,19 @@
 use crate::config::NagConfig;
 use crate::package::{
     cache::PackageCache,
-    lockfile::LockFile,
+    credentials,
+    lockfile::{compute_integrity, LockFile, LockedDependency},
     manifest::{DependencySpec, PackageManifest},
     registry::RegistryClient,
     resolver::{DependencyResolver, ResolutionContext, UpdateStrategy},
+    vendor::{VendorDir, DEFAULT_VENDOR_DIR},
 };
 use anyhow::Result;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
 pub struct PackageManager {
-    #[allow(dead_code)]
     config: NagConfig,
     registry: RegistryClient,
     resolver: DependencyResolver,
@@ -22,8 +24,14 @@ impl PackageManager {
     pub fn new(config: NagConfig) -> Result<Self> {
         let registry_url = config.package.registry.as_str();
 
-        let registry = RegistryClient::new(registry_url)?;
-        let resolver = DependencyResolver::new(registry.clone());
+        let registry = match credentials::token(registry_url) {
+            Some((token, _)) => RegistryClient::with_auth(registry_url, token)?,
+            None => RegistryClient::new(registry_url)?,
+        };
+        let mut resolver = DependencyResolver::new(registry.clone());
+        if let Some(vendor) = VendorDir::open(DEFAULT_VENDOR_DIR)? {
+            resolver = resolver.with_vendor(vendor.manifest().clone());
+        }
         let cache_dir = if config.package.cache_dir.is_empty() {
             dirs::cache_dir()
                 .unwrap_or_else(|| PathBuf::from(".nagari-cache"))
@@ -92,7 +100,12 @@ impl PackageManager {
         Ok(())
     }
 
-    pub async fn install(&mut self, packages: Vec<String>, save_dev: bool) -> Result<()> {
+    pub async fn install(
+        &mut self,
+        packages: Vec<String>,
+        save_dev: bool,
+        frozen_lockfile: bool,
+    ) -> Result<()> {
         let manifest_path = PathBuf::from("nagari.json");
         let mut manifest = if manifest_path.exists() {
             PackageManifest::from_file(&manifest_path)?
@@ -102,6 +115,26 @@ impl PackageManager {
             ));
         };
 
+        let lockfile_path = PathBuf::from("nag.lock");
+        let existing_lockfile = if lockfile_path.exists() {
+            Some(LockFile::from_file(&lockfile_path)?)
+        } else {
+            None
+        };
+
+        if frozen_lockfile {
+            if !packages.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Cannot add packages with --frozen-lockfile"
+                ));
+            }
+            if existing_lockfile.is_none() {
+                return Err(anyhow::anyhow!(
+                    "No nag.lock found but --frozen-lockfile was specified"
+                ));
+            }
+        }
+
         // Add packages to manifest
         for package_spec in packages {
             let (name, version) = self.parse_package_spec(&package_spec)?;
@@ -145,61 +178,138 @@ impl PackageManager {
             }
         }
 
+        let mut lockfile = existing_lockfile.clone().unwrap_or_else(|| {
+            LockFile::new(manifest.name.clone(), manifest.version.clone())
+                .with_registry(self.config.package.registry.clone())
+        });
+
         // Install packages
-        for (name, resolved_dep) in &resolution.resolved {
-            println!("📦 Installing {}@{}", name, resolved_dep.version);
+        let mut names: Vec<_> = resolution.resolved.keys().collect();
+        names.sort();
+        for name in names {
+            let resolved_dep = &resolution.resolved[name];
+            let version = resolved_dep.version.to_string();
+            println!("📦 Installing {}@{}", name, version);
+
+            // Download and cache package, preferring vendored sources
+            let package_data = self.fetch_package(name, &version).await?;
+
+            // Packages already locked at this version must match their recorded hash
+            let integrity = match lockfile.get_package(name) {
+                Some(locked) if locked.version == version => {
+                    lockfile.verify_package(name, &package_data)?;
+                    locked.integrity.clone()
+                }
+                _ => compute_integrity(&package_data),
+            };
 
-            // Download and cache package
-            let package_data = self
-                .registry
-                .download_package(name, &resolved_dep.version.to_string())
-                .await?;
             let metadata = serde_json::json!({
                 "name": name,
-                "version": resolved_dep.version.to_string(),
+                "version": version,
                 "resolved": resolved_dep.resolved_url,
-                "integrity": resolved_dep.integrity
+                "integrity": integrity
             });
 
             self.cache
-                .cache_package(
-                    name,
-                    &resolved_dep.version.to_string(),
-                    &package_data,
-                    metadata,
-                )
+                .cache_package(name, &version, &package_data, metadata)
                 .await?;
-        }
 
-        // Update manifest
-        manifest.to_file(&manifest_path)?;
+            let requires: BTreeMap<String, String> = resolved_dep
+                .dependencies
+                .iter()
+                .map(|(dep, dep_version)| (dep.clone(), dep_version.to_string()))
+                .collect();
+
+            let mut locked_dep =
+                LockedDependency::new(version, resolved_dep.resolved_url.clone(), integrity)
+                    .with_dev(resolved_dep.dev)
+                    .with_optional(resolved_dep.optional)
+                    .with_peer(resolved_dep.peer);
+            if !requires.is_empty() {
+                locked_dep = locked_dep.with_requires(requires);
+            }
 
-        // Create/update lock file
-        let lockfile_path = PathBuf::from("nag.lock");
-        let mut lockfile = if lockfile_path.exists() {
-            LockFile::from_file(&lockfile_path)?
+            lockfile.add_package(name.clone(), locked_dep);
+        }
+
+        if frozen_lockfile {
+            let diff = existing_lockfile
+                .as_ref()
+                .map(|existing| existing.diff(&lockfile))
+                .unwrap_or_default();
+            if !diff.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "nag.lock is out of date (--frozen-lockfile): {} added, {} removed, {} changed",
+                    diff.added.len(),
+                    diff.removed.len(),
+                    diff.changed.len()
+                ));
+            }
         } else {
-            LockFile::new(manifest.name.clone(), manifest.version.clone())
-        };
+            // Update manifest and lock file
+            manifest.to_file(&manifest_path)?;
+            if lockfile.to_file(&lockfile_path)? {
+                println!("🔒 Updated nag.lock");
+            }
+        }
 
-        for (name, resolved_dep) in &resolution.resolved {
-            use crate::package::lockfile::LockedDependency;
+        println!("✅ Installation completed!");
+        Ok(())
+    }
 
-            let locked_dep = LockedDependency::new(
-                resolved_dep.version.to_string(),
-                resolved_dep.resolved_url.clone(),
-                resolved_dep.integrity.clone(),
-            )
-            .with_dev(resolved_dep.dev)
-            .with_optional(resolved_dep.optional)
-            .with_peer(resolved_dep.peer);
+    /// Copy every locked package into a vendor directory, or with `verify`
+    /// re-check the vendored tarballs against `nag.lock`.
+    pub async fn vendor(&mut self, dir: PathBuf, verify: bool) -> Result<()> {
+        let lockfile_path = PathBuf::from("nag.lock");
+        if !lockfile_path.exists() {
+            return Err(anyhow::anyhow!(
+                "No nag.lock found. Run 'nag package install' first."
+            ));
+        }
+        let lockfile = LockFile::from_file(&lockfile_path)?;
+
+        if verify {
+            let vendor = VendorDir::open(&dir)?.ok_or_else(|| {
+                anyhow::anyhow!("No vendored packages found in {}", dir.display())
+            })?;
+            let problems = vendor.verify(&lockfile)?;
+            if !problems.is_empty() {
+                for problem in &problems {
+                    println!("❌ {}", problem);
+                }
+                return Err(anyhow::anyhow!(
+                    "Vendor verification failed ({} problems)",
+                    problems.len()
+                ));
+            }
+            println!(
+                "✅ Verified {} vendored packages in {}",
+                lockfile.packages.len(),
+                dir.display()
+            );
+            return Ok(());
+        }
 
-            lockfile.add_package(name.clone(), locked_dep);
+        let mut vendor = VendorDir::create(&dir)?;
+        for (name, locked) in &lockfile.packages {
+            if vendor.is_current(name, locked) {
+                continue;
+            }
+            println!("📦 Vendoring {}@{}", name, locked.version);
+            let package_data = self.registry.download_package(name, &locked.version).await?;
+            vendor.add_package(name, locked, &package_data)?;
         }
 
-        lockfile.to_file(&lockfile_path)?;
+        for name in vendor.prune(&lockfile)? {
+            println!("🗑️  Removed stale vendored package {}", name);
+        }
+        vendor.save()?;
 
-        println!("✅ Installation completed!");
+        println!(
+            "✅ Vendored {} packages into {}",
+            lockfile.packages.len(),
+            dir.display()
+        );
         Ok(())
     }
 
@@ -268,7 +378,8 @@ impl PackageManager {
         }
 
         // Install updated packages (same logic as install)
-        self.install_resolved_dependencies(&resolution).await?;
+        self.install_resolved_dependencies(&resolution, old_lockfile.as_ref())
+            .await?;
 
         println!("✅ Update completed!");
         Ok(())
@@ -408,34 +519,50 @@ impl PackageManager {
         }
     }
 
+    async fn fetch_package(&self, name: &str, version: &str) -> Result<Vec<u8>> {
+        if let Some(vendor) = VendorDir::open(DEFAULT_VENDOR_DIR)? {
+            if let Some(data) = vendor.read_tarball(name, version)? {
+                return Ok(data);
+            }
+        }
+        self.registry.download_package(name, version).await
+    }
+
     async fn install_resolved_dependencies(
         &mut self,
         resolution: &crate::package::resolver::ResolutionResult,
+        lockfile: Option<&LockFile>,
     ) -> Result<()> {
         // This would contain the actual installation logic
         // For now, just cache the packages
         for (name, resolved_dep) in &resolution.resolved {
-            let package_data = self
-                .registry
-                .download_package(name, &resolved_dep.version.to_string())
-                .await?;
+            let version = resolved_dep.version.to_string();
+            let package_data = self.fetch_package(name, &version).await?;
+
+            // Vendored or downloaded, a package still locked at this version must match its
+            // recorded hash before it is unpacked
+            if let Some(lockfile) = lockfile {
+                if lockfile
+                    .get_package(name)
+                    .is_some_and(|locked| locked.version == version)
+                {
+                    lockfile.verify_package(name, &package_data)?;
+                }
+            }
+
             let metadata = serde_json::json!({
                 "name": name,
-                "version": resolved_dep.version.to_string(),
+                "version": version,
                 "resolved": resolved_dep.resolved_url,
                 "integrity": resolved_dep.integrity
             });
 
             self.cache
-                .cache_package(
-                    name,
-                    &resolved_dep.version.to_string(),
-                    &package_data,
-                    metadata,
-                )
+                .cache_package(name, &version, &package_data, metadata)
                 .await?;
         }
 
         Ok(())
     }
 }
+
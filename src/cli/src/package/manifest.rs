@@ -1,10 +1,13 @@
 #![allow(dead_code)]
 
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use anyhow::Result;
+use nagari_compiler::RuntimePackage;
 
+// Maps are written with their keys sorted, so saving the same manifest always
+// yields byte-identical `nagari.json` files.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageManifest {
     pub name: String,
@@ -17,14 +20,21 @@ pub struct PackageManifest {
     pub keywords: Vec<String>,
 
     pub main: Option<String>,
+    #[serde(serialize_with = "sorted_option")]
     pub exports: Option<HashMap<String, String>>,
+    #[serde(serialize_with = "sorted_option")]
     pub bin: Option<HashMap<String, String>>,
 
+    #[serde(serialize_with = "sorted")]
     pub dependencies: HashMap<String, DependencySpec>,
+    #[serde(serialize_with = "sorted")]
     pub dev_dependencies: HashMap<String, DependencySpec>,
+    #[serde(serialize_with = "sorted")]
     pub peer_dependencies: HashMap<String, DependencySpec>,
+    #[serde(serialize_with = "sorted")]
     pub optional_dependencies: HashMap<String, DependencySpec>,
 
+    #[serde(serialize_with = "sorted")]
     pub scripts: HashMap<String, String>,
     pub nagari: Option<NagariConfig>,
     pub engines: Option<EngineRequirements>,
@@ -35,6 +45,22 @@ pub struct PackageManifest {
     pub publish_config: Option<PublishConfig>,
 }
 
+fn sorted<S: Serializer, V: Serialize>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn sorted_option<S: Serializer>(
+    map: &Option<HashMap<String, String>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.as_ref()
+        .map(|map| map.iter().collect::<BTreeMap<_, _>>())
+        .serialize(serializer)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum DependencySpec {
@@ -58,6 +84,8 @@ pub struct NagariConfig {
     pub module_format: String,
     pub compiler_options: Option<CompilerOptions>,
     pub runtime_options: Option<RuntimeOptions>,
+    /// Package the compiled modules import the runtime from, `nagari-runtime` by default
+    pub runtime: Option<RuntimePackage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -177,6 +205,7 @@ impl Default for NagariConfig {
             module_format: "esm".to_string(),
             compiler_options: None,
             runtime_options: None,
+            runtime: None,
         }
     }
 }
@@ -1,10 +1,14 @@
 pub mod cache;
+pub mod credentials;
 pub mod lockfile;
 pub mod manager;
 pub mod manifest;
+pub mod pack;
 pub mod registry;
 pub mod resolver;
+pub mod scripts;
 pub mod utils;
+pub mod vendor;
 
 #[cfg(test)]
 pub mod tests;
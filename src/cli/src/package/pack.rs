@@ -0,0 +1,113 @@
+use crate::package::manifest::PackageManifest;
+use crate::package::vendor::tarball_file_name;
+use anyhow::{Context, Result};
+use nagari_compiler::dual::{self, PackageInfo};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files copied into the tarball next to the builds when the project has them.
+const EXTRA_FILES: &[&str] = &["README.md", "LICENSE"];
+
+/// Compile the project at `project_dir` into a dual ES module/CommonJS package and write it
+/// to `<output_dir>/<name>-<version>.tgz`, returning the tarball's path.
+///
+/// Like npm's, the tarball keeps everything under a `package/` directory. It also carries
+/// `nagari.json` and the `.nag` sources, which the registry compiles before accepting it. The
+/// manifest is packed as canonical JSON, with sorted keys and no whitespace, so manifests with
+/// the same contents pack the same however they are formatted.
+pub fn pack(project_dir: &Path, output_dir: &Path) -> Result<PathBuf> {
+    let manifest_path = project_dir.join("nagari.json");
+    let manifest = PackageManifest::from_file(&manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+
+    let source_dir = manifest
+        .nagari
+        .as_ref()
+        .map(|nagari| nagari.source_dir.as_str())
+        .unwrap_or("src");
+    let jobs = dual::collect_modules(&project_dir.join(source_dir))
+        .with_context(|| format!("Failed to read source directory '{}'", source_dir))?;
+    if jobs.is_empty() {
+        anyhow::bail!("No .nag files found in '{}'", source_dir);
+    }
+
+    let package = package_info(&manifest, Path::new(source_dir));
+    let staging = tempfile::tempdir()?;
+    let compiler = nagari_compiler::Compiler::with_config(
+        nagari_compiler::CompilerConfigBuilder::new()
+            .runtime(package.runtime.clone())
+            .build(),
+    );
+    let results = compiler.compile_dual(&jobs, staging.path());
+    for ((source, _), result) in jobs.iter().zip(results) {
+        result.map_err(|e| anyhow::anyhow!("{}: [{}] {}", source.display(), e.code(), e))?;
+    }
+
+    // The manifest and sources go along, for the registry to check before accepting the
+    // package
+    let canonical = nagari_vm::json::parse(&fs::read_to_string(&manifest_path)?)
+        .and_then(|manifest| manifest.to_canonical_json())
+        .map_err(|e| anyhow::anyhow!("{}: {}", manifest_path.display(), e))?;
+    fs::write(staging.path().join("nagari.json"), canonical)?;
+    for (source, module) in &jobs {
+        let copy = staging
+            .path()
+            .join(source_dir)
+            .join(format!("{module}.nag"));
+        fs::create_dir_all(copy.parent().unwrap_or(staging.path()))?;
+        fs::copy(source, copy)?;
+    }
+
+    let modules: Vec<String> = jobs.into_iter().map(|(_, module)| module).collect();
+    let package_json = dual::package_json(&package, &modules).map_err(anyhow::Error::msg)?;
+    fs::write(staging.path().join("package.json"), package_json)?;
+    for file in EXTRA_FILES {
+        let path = project_dir.join(file);
+        if path.is_file() {
+            fs::copy(&path, staging.path().join(file))?;
+        }
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let tarball = output_dir.join(tarball_file_name(&manifest.name, &manifest.version));
+    let encoder = flate2::write::GzEncoder::new(
+        fs::File::create(&tarball)
+            .with_context(|| format!("Failed to create {}", tarball.display()))?,
+        flate2::Compression::default(),
+    );
+    let mut builder = tar::Builder::new(encoder);
+    builder.append_dir_all("package", staging.path())?;
+    builder.into_inner()?.finish()?;
+
+    Ok(tarball)
+}
+
+/// The npm side of `manifest`: module paths instead of source files, and only the
+/// dependencies npm can install by version
+fn package_info(manifest: &PackageManifest, source_dir: &Path) -> PackageInfo {
+    let module = |path: &str| dual::module_path(Path::new(path), source_dir);
+    PackageInfo {
+        name: manifest.name.clone(),
+        version: manifest.version.clone(),
+        description: manifest.description.clone(),
+        license: manifest.license.clone(),
+        main: manifest.main.as_deref().map(module),
+        exports: manifest.exports.as_ref().map(|exports| {
+            exports
+                .iter()
+                .map(|(subpath, path)| (subpath.clone(), module(path)))
+                .collect()
+        }),
+        dependencies: manifest
+            .dependencies
+            .iter()
+            .filter_map(|(name, spec)| Some((name.clone(), spec.get_version()?.to_string())))
+            .collect::<BTreeMap<_, _>>(),
+        runtime: manifest
+            .nagari
+            .as_ref()
+            .and_then(|nagari| nagari.runtime.clone())
+            .unwrap_or_default(),
+    }
+}
@@ -125,6 +125,25 @@ pub struct PublishRequest {
     pub metadata: VersionInfo,
 }
 
+/// The account a token belongs to, as `users/profile` returns it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub username: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub username_or_email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginResponse {
+    pub token: String,
+    pub user: UserProfile,
+}
+
 impl RegistryClient {
     pub fn new(registry_url: &str) -> Result<Self> {
         Ok(Self {
@@ -295,6 +314,48 @@ impl RegistryClient {
         }
     }
 
+    /// Exchange a username and password for a token
+    pub async fn login(&self, username_or_email: &str, password: &str) -> Result<LoginResponse> {
+        let url = self.registry_url.join("users/login")?;
+
+        let response = self.client
+            .post(url)
+            .json(&LoginRequest {
+                username_or_email: username_or_email.to_string(),
+                password: password.to_string(),
+            })
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(response.json().await?),
+            reqwest::StatusCode::UNAUTHORIZED => anyhow::bail!("Invalid username or password"),
+            status => anyhow::bail!("Login failed: {}", status),
+        }
+    }
+
+    /// The account the client's token belongs to
+    pub async fn whoami(&self) -> Result<UserProfile> {
+        let token = self.auth_token.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Not logged in"))?;
+
+        let url = self.registry_url.join("users/profile")?;
+
+        let response = self.client
+            .get(url)
+            .bearer_auth(token)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(response.json().await?),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                anyhow::bail!("The registry rejected the token")
+            }
+            status => anyhow::bail!("Registry request failed: {}", status),
+        }
+    }
+
     pub fn set_auth_token(&mut self, token: String) {
         self.auth_token = Some(token);
     }
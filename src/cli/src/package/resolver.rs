@@ -11,12 +11,14 @@ use std::process::Command;
 
 use crate::package::manifest::{DependencySpec, PackageManifest};
 use crate::package::registry::{RegistryClient, VersionInfo};
+use crate::package::vendor::VendorManifest;
 use tempfile::TempDir;
 
 #[derive(Debug, Clone)]
 pub struct DependencyResolver {
     registry: RegistryClient,
     cache: ResolverCache,
+    vendor: Option<VendorManifest>,
 }
 
 #[derive(Debug, Clone)]
@@ -112,9 +114,16 @@ impl DependencyResolver {
         Self {
             registry,
             cache: ResolverCache::new(),
+            vendor: None,
         }
     }
 
+    /// Prefer packages from a `vendor/` directory over the registry.
+    pub fn with_vendor(mut self, vendor: VendorManifest) -> Self {
+        self.vendor = Some(vendor);
+        self
+    }
+
     pub async fn resolve_dependencies(
         &mut self,
         manifest: &PackageManifest,
@@ -213,10 +222,10 @@ impl DependencyResolver {
     ) -> Pin<Box<dyn Future<Output = Result<ResolvedDependency>> + Send + 'a>> {
         Box::pin(async move {            // Handle local path dependencies
             if let DependencySpec::Detailed {
-                path: Some(_path), ..
+                path: Some(path), ..
             } = spec
             {
-                return self.resolve_git_dependency(name, "", None, None).await;
+                return self.resolve_local_dependency(name, path).await;
             }
 
             // Handle git dependencies
@@ -232,8 +241,46 @@ impl DependencyResolver {
                     .await;
             }
 
-            // Handle registry dependencies
             let version_req = self.parse_version_requirement(spec)?;
+
+            // Handle vendored dependencies
+            let vendored = self
+                .vendor
+                .as_ref()
+                .and_then(|vendor| vendor.packages.get(name))
+                .cloned();
+            if let Some(vendored) = vendored {
+                if let Ok(version) = Version::parse(&vendored.version) {
+                    if version_req.matches(&version) {
+                        let mut dependencies = HashMap::new();
+                        for (dep_name, dep_version) in &vendored.requires {
+                            let dep_spec = DependencySpec::Version(format!("={}", dep_version));
+                            let resolved_dep = self
+                                .resolve_dependency_tree_boxed(
+                                    dep_name,
+                                    &dep_spec,
+                                    context,
+                                    _resolution_graph,
+                                )
+                                .await?;
+                            dependencies.insert(dep_name.clone(), resolved_dep.version);
+                        }
+
+                        return Ok(ResolvedDependency {
+                            name: name.to_string(),
+                            version,
+                            resolved_url: vendored.resolved,
+                            integrity: vendored.integrity,
+                            dependencies,
+                            dev: false,
+                            optional: false,
+                            peer: false,
+                        });
+                    }
+                }
+            }
+
+            // Handle registry dependencies
             // Clone package_info to avoid holding a reference across await
             let package_info = self.get_package_info(name).await?.clone();
 
@@ -300,7 +347,8 @@ impl DependencyResolver {
         Ok(ResolvedDependency {
             name: name.to_string(),
             version,
-            resolved_url: format!("file:{}", path.display()),
+            // The lockfile is the same whichever platform resolved it
+            resolved_url: format!("file:{}", nagari_compiler::portable::slash_path(path)),
             integrity: String::new(),
             dependencies: HashMap::new(),
             dev: false,
@@ -0,0 +1,131 @@
+use crate::package::manifest::PackageManifest;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// A command `nag run-script` runs: the script asked for or one of its `pre`/`post` hooks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptStep {
+    /// Name of the script in `scripts`, e.g. `prebuild:assets`
+    pub name: String,
+    /// Shell command line, with the passed arguments quoted onto the end of the main script
+    pub command: String,
+}
+
+/// The steps running `name` takes: `pre<name>` and `post<name>` around it when the manifest
+/// defines them, like npm's. Only the script itself receives `args`.
+pub fn plan(manifest: &PackageManifest, name: &str, args: &[String]) -> Result<Vec<ScriptStep>> {
+    let Some(script) = manifest.scripts.get(name) else {
+        let mut names: Vec<&str> = manifest.scripts.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        if names.is_empty() {
+            anyhow::bail!(
+                "Script '{}' not found; nagari.json defines no scripts",
+                name
+            );
+        }
+        anyhow::bail!(
+            "Script '{}' not found; available scripts: {}",
+            name,
+            names.join(", ")
+        );
+    };
+
+    let mut command = script.clone();
+    for arg in args {
+        command.push(' ');
+        command.push_str(&quote(arg));
+    }
+
+    let hook = |prefix: &str| {
+        let hook = format!("{prefix}{name}");
+        manifest.scripts.get(&hook).map(|command| ScriptStep {
+            name: hook,
+            command: command.clone(),
+        })
+    };
+    let mut steps: Vec<ScriptStep> = hook("pre").into_iter().collect();
+    steps.push(ScriptStep {
+        name: name.to_string(),
+        command,
+    });
+    steps.extend(hook("post"));
+    Ok(steps)
+}
+
+/// Run the steps of script `name` in `project_dir`, stopping at the first that fails.
+///
+/// Each runs in the system shell with the variables `env` gives set, along with
+/// `NAGARI_PACKAGE_NAME`, `NAGARI_PACKAGE_VERSION`, `NAGARI_SCRIPT` (the step's name) and
+/// `NAGARI_PROJECT_DIR`; `node_modules/.bin` comes first on `PATH` when the project has one.
+pub fn run(
+    project_dir: &Path,
+    manifest: &PackageManifest,
+    name: &str,
+    args: &[String],
+    env: &[(String, String)],
+) -> Result<()> {
+    let bin = project_dir.join("node_modules").join(".bin");
+    let path = if bin.is_dir() {
+        let existing = std::env::var_os("PATH").unwrap_or_default();
+        let paths = std::iter::once(bin).chain(std::env::split_paths(&existing));
+        Some(std::env::join_paths(paths)?)
+    } else {
+        None
+    };
+
+    for step in plan(manifest, name, args)? {
+        println!("> {}: {}", step.name, step.command);
+        let mut command = shell(&step.command);
+        command
+            .current_dir(project_dir)
+            .env("NAGARI_PACKAGE_NAME", &manifest.name)
+            .env("NAGARI_PACKAGE_VERSION", &manifest.version)
+            .env("NAGARI_SCRIPT", &step.name)
+            .env("NAGARI_PROJECT_DIR", project_dir)
+            .envs(env.iter().map(|(key, value)| (key, value)));
+        if let Some(path) = &path {
+            command.env("PATH", path);
+        }
+
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to start script '{}'", step.name))?;
+        if !status.success() {
+            anyhow::bail!(
+                "Script '{}' exited with code: {}",
+                step.name,
+                status.code().unwrap_or(1)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn shell(command_line: &str) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(command_line);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(command_line);
+        command
+    }
+}
+
+/// `arg` as one word of a shell command line
+fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,".contains(c));
+    if plain {
+        arg.to_string()
+    } else if cfg!(windows) {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    } else {
+        // Single quotes keep the argument as is; a quote in it is closed, escaped and reopened
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
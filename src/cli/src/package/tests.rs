@@ -159,6 +159,20 @@ mod tests {
         assert_eq!(manifest.version, "1.0.0");
         assert_eq!(manifest.description, Some("A test package".to_string()));
         assert_eq!(manifest.dependencies.len(), 1);
+
+        // Dependencies are written in name order, whatever the order of the map
+        for name in ["zeta", "alpha", "mu"] {
+            manifest.dependencies.insert(
+                name.to_string(),
+                DependencySpec::Version("1.0.0".to_string()),
+            );
+        }
+        let json = serde_json::to_string(&manifest).unwrap();
+        let positions: Vec<usize> = ["\"alpha\"", "\"dep1\"", "\"mu\"", "\"zeta\""]
+            .iter()
+            .map(|name| json.find(name).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
     }
 
     #[tokio::test]
@@ -330,6 +344,46 @@ mod resolver_tests {
         // In a real implementation, we'd create packages that depend on each other
         // and verify the resolver detects and handles the circular dependency
     }
+
+    #[tokio::test]
+    async fn test_local_dependency_resolves_to_slash_path() {
+        use crate::package::resolver::ResolutionContext;
+
+        let temp_dir = TempDir::new().unwrap();
+        let util = temp_dir.path().join("libs").join("util");
+        std::fs::create_dir_all(&util).unwrap();
+        PackageManifest::new("util".to_string(), "0.2.0".to_string())
+            .to_file(&util.join("nagari.json"))
+            .unwrap();
+
+        let mut manifest = PackageManifest::new("app".to_string(), "1.0.0".to_string());
+        manifest.dependencies.insert(
+            "util".to_string(),
+            DependencySpec::Detailed {
+                version: None,
+                path: Some(util.clone()),
+                git: None,
+                branch: None,
+                tag: None,
+                registry: None,
+                optional: None,
+            },
+        );
+        let registry = RegistryClient::new("https://registry.example.com").unwrap();
+        let resolution = DependencyResolver::new(registry)
+            .resolve_dependencies(&manifest, &ResolutionContext::default())
+            .await
+            .unwrap();
+
+        // The lockfile says the same on Windows as elsewhere
+        let resolved = &resolution.resolved["util"];
+        assert_eq!(resolved.version.to_string(), "0.2.0");
+        assert_eq!(
+            resolved.resolved_url,
+            format!("file:{}", util.to_string_lossy().replace('\\', "/"))
+        );
+        assert!(!resolved.resolved_url.contains('\\'));
+    }
 }
 
 #[cfg(test)]
@@ -435,7 +489,7 @@ mod cache_tests {
 #[cfg(test)]
 mod lockfile_tests {
     use super::*;
-    use crate::package::lockfile::{LockFile, LockedDependency};
+    use crate::package::lockfile::{compute_integrity, LockFile, LockedDependency};
 
     #[tokio::test]
     async fn test_lockfile_creation() {
@@ -496,4 +550,423 @@ mod lockfile_tests {
         assert_eq!(lockfile.packages.len(), 1);
         assert!(lockfile.packages.contains_key("test-package"));
     }
+
+    #[test]
+    fn test_lockfile_serialization_is_deterministic() {
+        let mut first = LockFile::new("test-project".to_string(), "1.0.0".to_string());
+        let mut second = first.clone();
+
+        for name in ["zeta", "alpha", "mid"] {
+            first.add_package(
+                name.to_string(),
+                LockedDependency::new("1.0.0".to_string(), String::new(), String::new()),
+            );
+        }
+        for name in ["mid", "zeta", "alpha"] {
+            second.add_package(
+                name.to_string(),
+                LockedDependency::new("1.0.0".to_string(), String::new(), String::new()),
+            );
+        }
+
+        let content = first.to_json_string().unwrap();
+        assert_eq!(content, second.to_json_string().unwrap());
+        assert!(content.find("\"alpha\"").unwrap() < content.find("\"zeta\"").unwrap());
+        assert!(!content.contains("\"dev\""));
+        assert_eq!(LockFile::parse(&content).unwrap(), first);
+    }
+
+    #[test]
+    fn test_lockfile_integrity_verification() {
+        let data = b"package tarball";
+        let integrity = compute_integrity(data);
+        assert!(integrity.starts_with("sha512-"));
+
+        let mut lockfile = LockFile::new("test-project".to_string(), "1.0.0".to_string());
+        lockfile.add_package(
+            "pkg".to_string(),
+            LockedDependency::new("1.0.0".to_string(), String::new(), integrity),
+        );
+
+        assert!(lockfile.verify_package("pkg", data).is_ok());
+        assert!(lockfile.verify_package("pkg", b"tampered").is_err());
+        assert!(lockfile.verify_package("missing", data).is_err());
+        assert!(lockfile.validate_integrity().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_lockfile_diff_and_unchanged_write() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("nag.lock");
+
+        let mut old = LockFile::new("test-project".to_string(), "1.0.0".to_string());
+        old.add_package(
+            "kept".to_string(),
+            LockedDependency::new("1.0.0".to_string(), String::new(), compute_integrity(b"a")),
+        );
+        old.add_package(
+            "dropped".to_string(),
+            LockedDependency::new("1.0.0".to_string(), String::new(), compute_integrity(b"b")),
+        );
+
+        assert!(old.to_file(&path).unwrap());
+        assert!(!old.to_file(&path).unwrap());
+
+        let mut new = old.clone();
+        new.remove_package("dropped");
+        new.add_package(
+            "kept".to_string(),
+            LockedDependency::new("1.1.0".to_string(), String::new(), compute_integrity(b"c")),
+        );
+        new.add_package(
+            "added".to_string(),
+            LockedDependency::new("2.0.0".to_string(), String::new(), compute_integrity(b"d")),
+        );
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec!["added".to_string()]);
+        assert_eq!(diff.removed, vec!["dropped".to_string()]);
+        assert_eq!(diff.changed, vec!["kept".to_string()]);
+        assert!(old.diff(&old).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod vendor_tests {
+    use super::*;
+    use crate::package::lockfile::{compute_integrity, LockFile, LockedDependency};
+    use crate::package::vendor::VendorDir;
+
+    fn make_tarball(file_name: &str, contents: &[u8]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, file_name, contents).unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_vendor_add_and_verify() {
+        let temp_dir = TempDir::new().unwrap();
+        let vendor_root = temp_dir.path().join("vendor");
+        let tarball = make_tarball("index.nag", b"print(\"hi\")\n");
+
+        let locked = LockedDependency::new(
+            "1.2.0".to_string(),
+            "https://registry.example.com/pkg/-/pkg-1.2.0.tgz".to_string(),
+            compute_integrity(&tarball),
+        );
+        let mut lockfile = LockFile::new("test-project".to_string(), "1.0.0".to_string());
+        lockfile.add_package("pkg".to_string(), locked.clone());
+
+        let mut vendor = VendorDir::create(&vendor_root).unwrap();
+        vendor.add_package("pkg", &locked, &tarball).unwrap();
+        vendor.save().unwrap();
+
+        let vendor = VendorDir::open(&vendor_root).unwrap().unwrap();
+        assert!(vendor.is_current("pkg", &locked));
+        assert!(vendor.package_dir("pkg").join("index.nag").exists());
+        assert_eq!(vendor.read_tarball("pkg", "1.2.0").unwrap(), Some(tarball));
+        assert_eq!(vendor.read_tarball("pkg", "2.0.0").unwrap(), None);
+        assert!(vendor.verify(&lockfile).unwrap().is_empty());
+
+        let tarball_path = vendor_root.join(&vendor.get("pkg").unwrap().tarball);
+        std::fs::write(tarball_path, b"tampered").unwrap();
+        assert_eq!(vendor.verify(&lockfile).unwrap().len(), 1);
+        assert!(vendor.read_tarball("pkg", "1.2.0").is_err());
+    }
+
+    #[test]
+    fn test_vendor_verify_reports_integrity_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let tarball = make_tarball("index.nag", b"x = 1\n");
+        let locked = LockedDependency::new(
+            "1.0.0".to_string(),
+            String::new(),
+            compute_integrity(&tarball),
+        );
+
+        let mut vendor = VendorDir::create(temp_dir.path()).unwrap();
+        vendor.add_package("pkg", &locked, &tarball).unwrap();
+
+        let mut relocked = locked.clone();
+        relocked.integrity = compute_integrity(b"republished");
+        let mut lockfile = LockFile::new("test-project".to_string(), "1.0.0".to_string());
+        lockfile.add_package("pkg".to_string(), relocked);
+
+        let problems = vendor.verify(&lockfile).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("pkg@1.0.0: integrity mismatch"));
+    }
+
+    #[test]
+    fn test_vendor_rejects_integrity_mismatch() {
+        let temp_dir = TempDir::new().unwrap();
+        let tarball = make_tarball("index.nag", b"x = 1\n");
+        let locked = LockedDependency::new(
+            "1.0.0".to_string(),
+            String::new(),
+            compute_integrity(b"something else"),
+        );
+
+        let mut vendor = VendorDir::create(temp_dir.path()).unwrap();
+        assert!(vendor.add_package("pkg", &locked, &tarball).is_err());
+        assert!(VendorDir::open(temp_dir.path().join("missing")).unwrap().is_none());
+    }
+}
+
+#[cfg(test)]
+mod pack_tests {
+    use super::*;
+    use crate::package::manifest::NagariConfig;
+    use nagari_compiler::RuntimePackage;
+    use std::io::Read;
+
+    #[test]
+    fn test_pack_builds_dual_package_tarball() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        std::fs::create_dir_all(project.join("src/utils")).unwrap();
+        std::fs::write(
+            project.join("src/main.nag"),
+            "import { shout } from \"./utils/strings\"\nprint(shout(\"hi\"))\n",
+        )
+        .unwrap();
+        std::fs::write(
+            project.join("src/utils/strings.nag"),
+            "def shout(text):\n    return text + \"!\"\n",
+        )
+        .unwrap();
+        std::fs::write(project.join("README.md"), "# greeter\n").unwrap();
+
+        let mut manifest = PackageManifest::new("@acme/greeter".to_string(), "1.2.0".to_string());
+        manifest.main = Some("src/main.nag".to_string());
+        manifest.nagari = Some(NagariConfig {
+            runtime: Some(RuntimePackage::new("@acme/nagari-runtime", "^0.5.1")),
+            ..Default::default()
+        });
+        manifest.add_dependency(
+            "lodash".to_string(),
+            DependencySpec::Version("^4.17.0".to_string()),
+        );
+        manifest.to_file(&project.join("nagari.json")).unwrap();
+
+        let tarball = crate::package::pack::pack(project, &project.join("out")).unwrap();
+        assert_eq!(tarball, project.join("out/acme-greeter-1.2.0.tgz"));
+
+        let mut files = HashMap::new();
+        let file = std::fs::File::open(&tarball).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.header().entry_type().is_file() {
+                let path = entry.path().unwrap().to_string_lossy().into_owned();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                files.insert(path, contents);
+            }
+        }
+
+        for build in ["esm", "cjs"] {
+            for module in ["main", "utils/strings"] {
+                assert!(files.contains_key(&format!("package/{build}/{module}.js")));
+                assert!(files.contains_key(&format!("package/{build}/{module}.d.ts")));
+            }
+        }
+        assert!(files["package/cjs/package.json"].contains("commonjs"));
+        assert!(files.contains_key("package/README.md"));
+        assert!(files.contains_key("package/nagari.json"));
+        assert_eq!(
+            files["package/src/utils/strings.nag"],
+            "def shout(text):\n    return text + \"!\"\n"
+        );
+
+        let package: serde_json::Value =
+            serde_json::from_str(&files["package/package.json"]).unwrap();
+        assert_eq!(package["name"], "@acme/greeter");
+        assert_eq!(package["main"], "./cjs/main.js");
+        assert_eq!(
+            package["exports"]["."]["import"]["default"],
+            "./esm/main.js"
+        );
+        assert_eq!(
+            package["exports"]["./utils/strings"]["require"]["types"],
+            "./cjs/utils/strings.d.ts"
+        );
+        assert_eq!(package["dependencies"]["lodash"], "^4.17.0");
+        assert_eq!(package["dependencies"]["@acme/nagari-runtime"], "^0.5.1");
+        assert!(package["dependencies"]["nagari-runtime"].is_null());
+        assert!(files["package/esm/main.js"].contains("from '@acme/nagari-runtime'"));
+    }
+
+    #[test]
+    fn test_pack_writes_the_manifest_canonically() {
+        let packed_manifest = |manifest: &str| {
+            let temp_dir = TempDir::new().unwrap();
+            let project = temp_dir.path();
+            std::fs::create_dir_all(project.join("src")).unwrap();
+            std::fs::write(project.join("src/main.nag"), "x = 1\n").unwrap();
+            std::fs::write(project.join("nagari.json"), manifest).unwrap();
+
+            let tarball = crate::package::pack::pack(project, project).unwrap();
+            let file = std::fs::File::open(&tarball).unwrap();
+            let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+            let mut entry = archive
+                .entries()
+                .unwrap()
+                .map(Result::unwrap)
+                .find(|entry| entry.path().unwrap().ends_with("nagari.json"))
+                .unwrap();
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            contents
+        };
+
+        let project = TempDir::new().unwrap();
+        let path = project.path().join("nagari.json");
+        let mut manifest = PackageManifest::new("demo".to_string(), "1.0.0".to_string());
+        for name in ["zod", "lodash"] {
+            manifest.add_dependency(name.to_string(), DependencySpec::Version("^1.0.0".into()));
+        }
+        manifest.to_file(&path).unwrap();
+        let pretty = std::fs::read_to_string(&path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+
+        // Without whitespace and with sorted keys, as serde_json writes its own maps
+        let packed = packed_manifest(&pretty);
+        assert_eq!(packed, serde_json::to_string(&json).unwrap());
+
+        // The same manifest with its keys the other way round packs the same
+        let reversed: Vec<String> = json
+            .as_object()
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|(key, value)| format!("  {key:?}: {}", serde_json::to_string(value).unwrap()))
+            .collect();
+        let reordered = packed_manifest(&format!("{{\n{}\n}}\n", reversed.join(",\n")));
+        assert_eq!(reordered, packed);
+    }
+
+    #[test]
+    fn test_pack_rejects_export_of_missing_module() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        std::fs::create_dir_all(project.join("src")).unwrap();
+        std::fs::write(project.join("src/main.nag"), "x = 1\n").unwrap();
+
+        let mut manifest = PackageManifest::new("broken".to_string(), "0.1.0".to_string());
+        manifest.exports = Some(HashMap::from([(
+            "./extra".to_string(),
+            "src/extra.nag".to_string(),
+        )]));
+        manifest.to_file(&project.join("nagari.json")).unwrap();
+
+        let error = crate::package::pack::pack(project, project).unwrap_err();
+        assert!(error.to_string().contains("'./extra'"));
+    }
+
+    #[test]
+    fn test_token_from_environment() {
+        use crate::package::credentials::{self, TokenSource, TOKEN_ENV};
+
+        std::env::set_var(TOKEN_ENV, " ci-token\n");
+        let token = credentials::token("https://registry.example.com/");
+        std::env::remove_var(TOKEN_ENV);
+        assert_eq!(
+            token,
+            Some(("ci-token".to_string(), TokenSource::Environment))
+        );
+
+        assert_eq!(
+            credentials::registry_key("https://registry.example.com/"),
+            credentials::registry_key("https://registry.example.com")
+        );
+    }
+}
+
+#[cfg(test)]
+mod scripts_tests {
+    use super::*;
+    use crate::package::scripts::{self, ScriptStep};
+
+    fn manifest_with_scripts(scripts: &[(&str, &str)]) -> PackageManifest {
+        let mut manifest = PackageManifest::new("app".to_string(), "2.0.0".to_string());
+        for (name, command) in scripts {
+            manifest
+                .scripts
+                .insert(name.to_string(), command.to_string());
+        }
+        manifest
+    }
+
+    #[test]
+    fn test_script_plan_runs_hooks_around_script() {
+        let manifest = manifest_with_scripts(&[
+            ("prebuild:assets", "mkdir -p dist"),
+            ("build:assets", "cp -r assets dist"),
+            ("postbuild:assets", "echo done"),
+            ("test", "nag test"),
+        ]);
+        let args = vec!["--verbose".to_string(), "two words".to_string()];
+        let steps = scripts::plan(&manifest, "build:assets", &args).unwrap();
+        let main = if cfg!(windows) {
+            "cp -r assets dist --verbose \"two words\""
+        } else {
+            "cp -r assets dist --verbose 'two words'"
+        };
+        assert_eq!(
+            steps,
+            vec![
+                ScriptStep {
+                    name: "prebuild:assets".to_string(),
+                    command: "mkdir -p dist".to_string(),
+                },
+                ScriptStep {
+                    name: "build:assets".to_string(),
+                    command: main.to_string(),
+                },
+                ScriptStep {
+                    name: "postbuild:assets".to_string(),
+                    command: "echo done".to_string(),
+                },
+            ]
+        );
+
+        let error = scripts::plan(&manifest, "lint", &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Script 'lint' not found; available scripts: build:assets, postbuild:assets, prebuild:assets, test"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_script_sets_environment_and_stops_on_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        let manifest = manifest_with_scripts(&[
+            ("prestamp", "echo \"$NAGARI_SCRIPT\" > log"),
+            (
+                "stamp",
+                "echo \"$NAGARI_SCRIPT $NAGARI_PACKAGE_NAME@$NAGARI_PACKAGE_VERSION $MODE\" >> log && echo >> log",
+            ),
+            ("fail", "exit 3"),
+            ("postfail", "touch never"),
+        ]);
+
+        let env = vec![("MODE".to_string(), "release".to_string())];
+        scripts::run(project, &manifest, "stamp", &["it's".to_string()], &env).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(project.join("log")).unwrap(),
+            "prestamp\nstamp app@2.0.0 release\nit's\n"
+        );
+
+        let error = scripts::run(project, &manifest, "fail", &[], &[]).unwrap_err();
+        assert_eq!(error.to_string(), "Script 'fail' exited with code: 3");
+        assert!(!project.join("never").exists());
+    }
 }
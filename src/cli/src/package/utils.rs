@@ -8,7 +8,7 @@ use super::{
     resolver::{DependencyResolver, ResolutionContext, ResolutionResult},
 };
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 /// Package utilities that use all the imported types
@@ -88,8 +88,8 @@ impl PackageUtils {
             dev: None,
             optional: None,
             peer: None,
-            requires: Some(HashMap::new()),
-            dependencies: Some(HashMap::new()),
+            requires: Some(BTreeMap::new()),
+            dependencies: Some(BTreeMap::new()),
             engines: None,
             os: None,
             cpu: None,
@@ -0,0 +1,228 @@
+#![allow(dead_code)]
+
+use crate::package::lockfile::{verify_integrity, LockFile, LockedDependency};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default directory that `nag vendor` writes into.
+pub const DEFAULT_VENDOR_DIR: &str = "vendor";
+
+/// Index file kept at the root of the vendor directory.
+pub const VENDOR_MANIFEST: &str = "vendor.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VendorManifest {
+    pub packages: BTreeMap<String, VendoredPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VendoredPackage {
+    pub version: String,
+    pub resolved: String,
+    pub integrity: String,
+    /// Tarball file name, relative to the vendor directory
+    pub tarball: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub requires: BTreeMap<String, String>,
+}
+
+/// A `vendor/` directory holding locked package tarballs and their extracted contents.
+#[derive(Debug, Clone)]
+pub struct VendorDir {
+    root: PathBuf,
+    manifest: VendorManifest,
+}
+
+impl VendorDir {
+    /// Create (or reuse) a vendor directory at `root`.
+    pub fn create<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root)?;
+        let manifest = Self::load_manifest(&root)?.unwrap_or_default();
+        Ok(Self { root, manifest })
+    }
+
+    /// Open an existing vendor directory, returning `None` if nothing has been vendored.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Option<Self>> {
+        let root = root.as_ref().to_path_buf();
+        Ok(Self::load_manifest(&root)?.map(|manifest| Self { root, manifest }))
+    }
+
+    fn load_manifest(root: &Path) -> Result<Option<VendorManifest>> {
+        let path = root.join(VENDOR_MANIFEST);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&content)?))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn manifest(&self) -> &VendorManifest {
+        &self.manifest
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VendoredPackage> {
+        self.manifest.packages.get(name)
+    }
+
+    /// Directory holding the extracted contents of a vendored package.
+    pub fn package_dir(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// Read the vendored tarball for `name@version`, if that exact version is vendored. The
+    /// tarball must still match the integrity it was vendored with.
+    pub fn read_tarball(&self, name: &str, version: &str) -> Result<Option<Vec<u8>>> {
+        match self.get(name) {
+            Some(package) if package.version == version => {
+                let data = fs::read(self.root.join(&package.tarball))?;
+                verify_integrity(&package.integrity, &data)
+                    .map_err(|e| anyhow::anyhow!("vendored {}@{}: {}", name, version, e))?;
+                Ok(Some(data))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether `name` is already vendored at exactly the locked version.
+    pub fn is_current(&self, name: &str, locked: &LockedDependency) -> bool {
+        self.get(name).is_some_and(|package| {
+            package.version == locked.version
+                && package.integrity == locked.integrity
+                && self.root.join(&package.tarball).exists()
+        })
+    }
+
+    /// Store a locked package's tarball and extract it into `vendor/<name>/`.
+    pub fn add_package(
+        &mut self,
+        name: &str,
+        locked: &LockedDependency,
+        data: &[u8],
+    ) -> Result<()> {
+        verify_integrity(&locked.integrity, data)
+            .map_err(|e| anyhow::anyhow!("{}@{}: {}", name, locked.version, e))?;
+
+        let tarball = tarball_file_name(name, &locked.version);
+        fs::write(self.root.join(&tarball), data)?;
+
+        let package_dir = self.package_dir(name);
+        if package_dir.exists() {
+            fs::remove_dir_all(&package_dir)?;
+        }
+        fs::create_dir_all(&package_dir)?;
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+        archive.unpack(&package_dir)?;
+
+        self.manifest.packages.insert(
+            name.to_string(),
+            VendoredPackage {
+                version: locked.version.clone(),
+                resolved: locked.resolved.clone(),
+                integrity: locked.integrity.clone(),
+                tarball,
+                requires: locked.requires.clone().unwrap_or_default(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Drop vendored packages that are no longer in the lock file.
+    pub fn prune(&mut self, lockfile: &LockFile) -> Result<Vec<String>> {
+        let stale: Vec<String> = self
+            .manifest
+            .packages
+            .keys()
+            .filter(|name| lockfile.get_package(name).is_none())
+            .cloned()
+            .collect();
+
+        for name in &stale {
+            if let Some(package) = self.manifest.packages.remove(name) {
+                let tarball = self.root.join(&package.tarball);
+                if tarball.exists() {
+                    fs::remove_file(tarball)?;
+                }
+                let package_dir = self.package_dir(name);
+                if package_dir.exists() {
+                    fs::remove_dir_all(package_dir)?;
+                }
+            }
+        }
+
+        Ok(stale)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut content = serde_json::to_string_pretty(&self.manifest)?;
+        content.push('\n');
+        fs::write(self.root.join(VENDOR_MANIFEST), content)?;
+        Ok(())
+    }
+
+    /// Re-check every vendored tarball against the lock file, returning a list of problems.
+    pub fn verify(&self, lockfile: &LockFile) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+
+        for (name, locked) in &lockfile.packages {
+            let Some(vendored) = self.get(name) else {
+                problems.push(format!("{}@{} is not vendored", name, locked.version));
+                continue;
+            };
+
+            if vendored.version != locked.version {
+                problems.push(format!(
+                    "{} is vendored at {} but locked at {}",
+                    name, vendored.version, locked.version
+                ));
+                continue;
+            }
+            if vendored.integrity != locked.integrity {
+                problems.push(format!(
+                    "{}@{}: integrity mismatch (locked {}, vendored {})",
+                    name, locked.version, locked.integrity, vendored.integrity
+                ));
+                continue;
+            }
+
+            let tarball = self.root.join(&vendored.tarball);
+            match fs::read(&tarball) {
+                Ok(data) => {
+                    if let Err(e) = verify_integrity(&locked.integrity, &data) {
+                        problems.push(format!("{}@{}: {}", name, locked.version, e));
+                    }
+                }
+                Err(e) => problems.push(format!(
+                    "{}@{}: cannot read {}: {}",
+                    name,
+                    locked.version,
+                    tarball.display(),
+                    e
+                )),
+            }
+        }
+
+        for name in self.manifest.packages.keys() {
+            if lockfile.get_package(name).is_none() {
+                problems.push(format!("{} is vendored but not in nag.lock", name));
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+pub(crate) fn tarball_file_name(name: &str, version: &str) -> String {
+    format!(
+        "{}-{}.tgz",
+        name.trim_start_matches('@').replace('/', "-"),
+        version
+    )
+}
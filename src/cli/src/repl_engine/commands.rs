@@ -124,6 +124,16 @@ impl BuiltinCommands {
             },
         );
 
+        commands.insert(
+            "heap".to_string(),
+            CommandInfo {
+                name: "heap".to_string(),
+                description: "Show the VM heap and what changed since the last .heap".to_string(),
+                usage: ".heap [file]".to_string(),
+                aliases: vec!["memory".to_string()],
+            },
+        );
+
         Self { commands }
     }
     pub async fn execute(
@@ -150,6 +160,7 @@ impl BuiltinCommands {
             "reset" | "restart" => self.reset_command(args, repl).await,
             "load" | "source" => self.load_command(args, repl).await,
             "save" => self.save_command(args, repl).await,
+            "heap" | "memory" => self.heap_command(args, repl).await,
             _ => Ok(format!(
                 "Unknown command: {}. Type .help for available commands.",
                 command
@@ -350,6 +361,24 @@ impl BuiltinCommands {
         }
     }
 
+    async fn heap_command(&self, args: &[&str], repl: &mut ReplEngine) -> Result<String> {
+        let (snapshot, diff) = repl.heap_snapshot();
+        let mut output = format!("Heap: {}", snapshot);
+        if let Some(diff) = diff {
+            output.push_str(&format!("Since the last snapshot:\n{}", diff));
+        }
+
+        // The JSON snapshot can be compared with later ones outside the REPL
+        if let Some(path) = args.first() {
+            match std::fs::write(path, snapshot.to_json()) {
+                Ok(()) => output.push_str(&format!("Snapshot saved to: {}\n", path)),
+                Err(e) => output.push_str(&format!("Error saving snapshot: {}\n", e)),
+            }
+        }
+
+        Ok(output)
+    }
+
     #[allow(dead_code)]
     pub fn get_command_names(&self) -> Vec<String> {
         let mut names = Vec::new();
@@ -150,6 +150,7 @@ fn get_nagari_keywords() -> Vec<String> {
         "elif",
         "for",
         "while",
+        "with",
         "match",
         "when",
         "try",
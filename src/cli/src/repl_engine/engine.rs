@@ -23,6 +23,7 @@ pub struct ReplEngine {
     builtin_commands: BuiltinCommands,
     state: ReplState,
     vm: nagari_vm::VM,
+    last_heap_snapshot: Option<nagari_vm::HeapSnapshot>,
 }
 
 #[derive(Debug, Clone)]
@@ -110,6 +111,7 @@ impl ReplEngine {
             builtin_commands,
             state,
             vm,
+            last_heap_snapshot: None,
         })
     }
     pub async fn run(&mut self) -> Result<()> {
@@ -545,6 +547,18 @@ impl ReplEngine {
         // Sync all global variables from context to VM
         self.context.sync_with_vm(&mut self.vm);
     }
+
+    /// Snapshot of what the VM holds, with what changed since the previous snapshot
+    pub fn heap_snapshot(&mut self) -> (nagari_vm::HeapSnapshot, Option<nagari_vm::HeapDiff>) {
+        self.sync_globals_with_vm();
+        let snapshot = self.vm.heap_snapshot();
+        let diff = self
+            .last_heap_snapshot
+            .as_ref()
+            .map(|previous| previous.diff(&snapshot));
+        self.last_heap_snapshot = Some(snapshot.clone());
+        (snapshot, diff)
+    }
 }
 
 impl Default for ReplConfig {
@@ -285,6 +285,7 @@ fn classify_identifier(text: &str) -> TokenType {
             | "elif"
             | "for"
             | "while"
+            | "with"
             | "match"
             | "when"
             | "try"
@@ -0,0 +1,124 @@
+//! Applying the suggested edits of lint diagnostics, for `nag fix`.
+//!
+//! Edits are computed against the file as it is and applied together. When two edits touch
+//! the same lines only the first is applied; the file is linted again afterwards, so the
+//! other one is suggested anew against the updated text if it still applies. This repeats
+//! until no edit is left, or a bounded number of times.
+
+use crate::tools::diagnostics::Fix;
+use crate::tools::NagLinter;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Rounds of linting and fixing a file goes through at most
+const MAX_PASSES: usize = 10;
+
+/// What fixing a file did
+#[derive(Debug, Clone)]
+pub struct FileFix {
+    pub path: PathBuf,
+    pub original: String,
+    pub fixed: String,
+    /// Edits applied
+    pub applied: usize,
+    /// Fixes left out because they may change behaviour
+    pub unsafe_skipped: usize,
+}
+
+impl FileFix {
+    pub fn changed(&self) -> bool {
+        self.original != self.fixed
+    }
+
+    /// Unified diff from the original text to the fixed one
+    pub fn diff(&self) -> String {
+        let path = self.path.display().to_string();
+        similar::TextDiff::from_lines(&self.original, &self.fixed)
+            .unified_diff()
+            .header(&path, &path)
+            .to_string()
+    }
+}
+
+/// Apply the safe fixes, and the unsafe ones too if `include_unsafe`, to `path`'s text.
+/// Nothing is written.
+pub fn fix_file(linter: &NagLinter, path: &Path, include_unsafe: bool) -> Result<FileFix> {
+    let original = std::fs::read_to_string(path)?;
+    let mut fixed = original.clone();
+    let mut applied = 0;
+    let mut unsafe_skipped = 0;
+
+    for _ in 0..MAX_PASSES {
+        let issues = linter.lint_string(&fixed, path.to_path_buf(), false)?;
+        let (fixes, skipped): (Vec<Fix>, Vec<Fix>) = issues
+            .iter()
+            .filter_map(|issue| linter.suggest_fix(issue, &fixed))
+            .partition(|fix| fix.safe || include_unsafe);
+        unsafe_skipped = skipped.len();
+
+        let (text, count) = apply_fixes(&fixed, &fixes);
+        if count == 0 {
+            break;
+        }
+        fixed = text;
+        applied += count;
+    }
+
+    Ok(FileFix {
+        path: path.to_path_buf(),
+        original,
+        fixed,
+        applied,
+        unsafe_skipped,
+    })
+}
+
+/// Apply the edits of `fixes` that don't overlap an earlier one and still match `source`,
+/// returning the new text and the number applied
+pub fn apply_fixes(source: &str, fixes: &[Fix]) -> (String, usize) {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut fixes: Vec<&Fix> = fixes.iter().collect();
+    fixes.sort_by_key(|fix| (fix.line, fix.end_line));
+    fixes.dedup_by(|a, b| a == b);
+
+    let mut output: Vec<String> = Vec::with_capacity(lines.len());
+    // Lines before this one are copied or replaced already
+    let mut next = 1;
+    let mut applied = 0;
+    for fix in fixes {
+        let first = fix.line as usize;
+        let last = fix.end_line as usize;
+        if first == 0
+            || first < next
+            || last > lines.len()
+            || lines[first - 1..last] != fix.original[..]
+        {
+            continue;
+        }
+        output.extend(
+            lines[next - 1..first - 1]
+                .iter()
+                .map(|line| line.to_string()),
+        );
+        output.extend(fix.replacement.iter().cloned());
+        // An insertion leaves the line it comes before in place, for other edits
+        next = last.max(first - 1) + 1;
+        applied += 1;
+    }
+    output.extend(
+        lines[(next - 1).min(lines.len())..]
+            .iter()
+            .map(|line| line.to_string()),
+    );
+
+    let newline = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut text = output.join(newline);
+    if source.ends_with('\n') && !text.is_empty() {
+        text.push_str(newline);
+    }
+    (text, applied)
+}
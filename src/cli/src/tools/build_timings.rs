@@ -0,0 +1,193 @@
+use anyhow::Result;
+use nagari_compiler::{CompilationTimings, Phase};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Timings for a single compiled module
+#[derive(Debug, Clone)]
+pub struct ModuleTiming {
+    pub path: PathBuf,
+    pub timings: CompilationTimings,
+}
+
+/// Timings collected over a whole `nag build --timings` run
+#[derive(Debug, Clone, Default)]
+pub struct BuildTimings {
+    pub modules: Vec<ModuleTiming>,
+}
+
+impl BuildTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, path: &Path, timings: CompilationTimings) {
+        self.modules.push(ModuleTiming {
+            path: path.to_path_buf(),
+            timings,
+        });
+    }
+
+    /// Total time spent in `phase` across all modules
+    pub fn phase_total(&self, phase: Phase) -> Duration {
+        self.modules
+            .iter()
+            .filter_map(|module| module.timings.get(phase))
+            .sum()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.modules
+            .iter()
+            .map(|module| module.timings.total())
+            .sum()
+    }
+
+    /// Modules ordered from slowest to fastest
+    pub fn slowest(&self) -> Vec<&ModuleTiming> {
+        let mut modules: Vec<_> = self.modules.iter().collect();
+        modules.sort_by_key(|module| std::cmp::Reverse(module.timings.total()));
+        modules
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let phases: serde_json::Map<String, serde_json::Value> = Phase::ALL
+            .iter()
+            .map(|phase| {
+                (
+                    phase.name().to_string(),
+                    millis(self.phase_total(*phase)).into(),
+                )
+            })
+            .collect();
+
+        let modules: Vec<serde_json::Value> = self
+            .slowest()
+            .into_iter()
+            .map(|module| {
+                let module_phases: serde_json::Map<String, serde_json::Value> = module
+                    .timings
+                    .phases()
+                    .iter()
+                    .map(|(phase, duration)| (phase.name().to_string(), millis(*duration).into()))
+                    .collect();
+                serde_json::json!({
+                    "path": module.path,
+                    "total_ms": millis(module.timings.total()),
+                    "phases_ms": module_phases,
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "total_ms": millis(self.total()),
+            "phases_ms": phases,
+            "modules": modules,
+        })
+    }
+
+    pub fn to_html(&self) -> String {
+        let total = millis(self.total()).max(f64::EPSILON);
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n\
+             <title>Nagari build timings</title>\n<style>\n\
+             body { font-family: sans-serif; margin: 2em; }\n\
+             table { border-collapse: collapse; }\n\
+             th, td { padding: 4px 10px; text-align: right; border-bottom: 1px solid #ddd; }\n\
+             th:first-child, td:first-child { text-align: left; }\n\
+             .bar { background: #4a90d9; height: 10px; }\n\
+             </style>\n</head>\n<body>\n",
+        );
+
+        html.push_str(&format!(
+            "<h1>Nagari build timings</h1>\n<p>{} modules compiled in {:.2} ms</p>\n",
+            self.modules.len(),
+            millis(self.total())
+        ));
+
+        html.push_str(
+            "<h2>Phases</h2>\n<table>\n<tr><th>Phase</th><th>Time (ms)</th><th></th></tr>\n",
+        );
+        for phase in Phase::ALL {
+            let phase_ms = millis(self.phase_total(phase));
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{:.2}</td><td><div class=\"bar\" style=\"width: {:.0}px\"></div></td></tr>\n",
+                phase,
+                phase_ms,
+                phase_ms / total * 300.0
+            ));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Modules</h2>\n<table>\n<tr><th>Module</th>");
+        for phase in Phase::ALL {
+            html.push_str(&format!("<th>{}</th>", phase));
+        }
+        html.push_str("<th>Total (ms)</th></tr>\n");
+        for module in self.slowest() {
+            html.push_str(&format!(
+                "<tr><td>{}</td>",
+                html_escape(&module.path.display().to_string())
+            ));
+            for phase in Phase::ALL {
+                match module.timings.get(phase) {
+                    Some(duration) => html.push_str(&format!("<td>{:.2}</td>", millis(duration))),
+                    None => html.push_str("<td>-</td>"),
+                }
+            }
+            html.push_str(&format!(
+                "<td>{:.2}</td></tr>\n",
+                millis(module.timings.total())
+            ));
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Write `nag-timings.json` and `nag-timings.html` into `dir`
+    pub fn write_reports(&self, dir: &Path) -> Result<(PathBuf, PathBuf)> {
+        std::fs::create_dir_all(dir)?;
+        let json_path = dir.join("nag-timings.json");
+        let html_path = dir.join("nag-timings.html");
+        std::fs::write(&json_path, serde_json::to_string_pretty(&self.to_json())?)?;
+        std::fs::write(&html_path, self.to_html())?;
+        Ok((json_path, html_path))
+    }
+
+    /// Short human-readable summary: per-phase totals and the slowest modules
+    pub fn summary(&self, top: usize) -> String {
+        let mut lines = vec![format!(
+            "{} modules in {:.2} ms",
+            self.modules.len(),
+            millis(self.total())
+        )];
+        for phase in Phase::ALL {
+            lines.push(format!(
+                "  {:<10} {:>10.2} ms",
+                phase.name(),
+                millis(self.phase_total(phase))
+            ));
+        }
+        if !self.modules.is_empty() {
+            lines.push("Slowest modules:".to_string());
+            for module in self.slowest().into_iter().take(top) {
+                lines.push(format!(
+                    "  {:>10.2} ms  {}",
+                    millis(module.timings.total()),
+                    module.path.display()
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
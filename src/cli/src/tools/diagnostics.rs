@@ -0,0 +1,320 @@
+//! Compiler and linter diagnostics in one shape, for `--message-format json|sarif`.
+//!
+//! The JSON format is a single document:
+//!
+//! ```text
+//! {
+//!     "version": 1,
+//!     "tool": "check",
+//!     "diagnostics": [{
+//!         "file": "src/main.nag",
+//!         "code": "E0002" | "unused-variables" | ...,
+//!         "severity": "error" | "warning" | "info",
+//!         "message": "...",
+//!         "span": null | { "line": 3, "column": 5 },
+//!         "labels": [{ "span": { "line": 3, "column": 5 }, "message": "..." }],
+//!         "help": null | "...",
+//!         "fix": null | {
+//!             "description": "...",
+//!             "line": 3,                // first line replaced
+//!             "end_line": 3,            // last line replaced, `line - 1` to only insert
+//!             "replacement": ["..."],   // lines put in their place
+//!             "safe": true              // false if the fix may change behaviour
+//!         }
+//!     }]
+//! }
+//! ```
+//!
+//! Lines and columns count from 1. New fields may be added; existing ones keep their meaning
+//! while `version` stays the same. The SARIF format is a SARIF 2.1.0 log with one run.
+
+use crate::tools::{LintIssue, Severity};
+use nagari_compiler::{Label, NagariError, Warning};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Version of the JSON diagnostics schema
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// Error code or lint rule
+    pub code: String,
+    pub severity: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Other places the diagnostic points at, with what it says about each
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+    pub fix: Option<Fix>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// A suggested edit replacing lines `line` to `end_line`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fix {
+    pub description: String,
+    pub line: u32,
+    /// `line - 1` when the edit only inserts lines
+    pub end_line: u32,
+    /// Lines the edit replaces
+    #[serde(skip)]
+    pub original: Vec<String>,
+    /// Lines put in their place; none to delete them
+    pub replacement: Vec<String>,
+    /// Whether the fix keeps the program's behaviour, so `nag fix` applies it by default
+    pub safe: bool,
+}
+
+impl Fix {
+    /// The edit turning `content` into `fixed`: the lines between the ones they share at the
+    /// start and at the end
+    pub fn between(description: String, content: &str, fixed: &str, safe: bool) -> Option<Fix> {
+        let before: Vec<&str> = content.lines().collect();
+        let after: Vec<&str> = fixed.lines().collect();
+        let prefix = before
+            .iter()
+            .zip(&after)
+            .take_while(|(old, new)| old == new)
+            .count();
+        if prefix == before.len() && prefix == after.len() {
+            return None;
+        }
+        let suffix = before[prefix..]
+            .iter()
+            .rev()
+            .zip(after[prefix..].iter().rev())
+            .take_while(|(old, new)| old == new)
+            .count();
+
+        let original = &before[prefix..before.len() - suffix];
+        Some(Fix {
+            description,
+            line: prefix as u32 + 1,
+            end_line: (prefix + original.len()) as u32,
+            original: original.iter().map(|line| line.to_string()).collect(),
+            replacement: after[prefix..after.len() - suffix]
+                .iter()
+                .map(|line| line.to_string())
+                .collect(),
+            safe,
+        })
+    }
+}
+
+impl Diagnostic {
+    pub fn from_error(file: &Path, error: &NagariError) -> Self {
+        let message = error.to_string();
+        Self {
+            file: file.to_path_buf(),
+            code: error.code().to_string(),
+            severity: "error",
+            span: span_in_message(&message),
+            message,
+            labels: Vec::new(),
+            help: None,
+            fix: None,
+        }
+    }
+
+    pub fn from_compiler(file: &Path, diagnostic: &nagari_compiler::Diagnostic) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            code: diagnostic.code.clone(),
+            severity: match diagnostic.severity {
+                nagari_compiler::Severity::Error => "error",
+                nagari_compiler::Severity::Warning => "warning",
+                nagari_compiler::Severity::Note => "info",
+            },
+            message: diagnostic.message.clone(),
+            span: diagnostic.span.map(|span| Span {
+                line: span.line as u32,
+                column: span.column as u32,
+            }),
+            labels: diagnostic.labels.clone(),
+            help: diagnostic.help.clone(),
+            fix: None,
+        }
+    }
+
+    pub fn from_warning(file: &Path, warning: &Warning) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            code: warning.category.clone(),
+            severity: "warning",
+            message: warning.message.clone(),
+            span: warning.line.map(|line| Span {
+                line: line as u32,
+                column: warning.column.unwrap_or(1) as u32,
+            }),
+            labels: Vec::new(),
+            help: None,
+            fix: None,
+        }
+    }
+
+    pub fn from_lint_issue(issue: &LintIssue, fix: Option<Fix>) -> Self {
+        Self {
+            file: issue.file.clone(),
+            code: issue.rule.clone(),
+            severity: match issue.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Info => "info",
+            },
+            message: issue.message.clone(),
+            // Lint columns count from 0
+            span: Some(Span {
+                line: issue.line,
+                column: issue.column + 1,
+            }),
+            labels: Vec::new(),
+            help: None,
+            fix,
+        }
+    }
+}
+
+/// `line L, column C` or `line L` in a compiler message
+fn span_in_message(message: &str) -> Option<Span> {
+    let pattern = Regex::new(r"line (\d+)(?:, column (\d+))?").unwrap();
+    let captures = pattern.captures(message)?;
+    Some(Span {
+        line: captures[1].parse().ok()?,
+        column: captures
+            .get(2)
+            .and_then(|column| column.as_str().parse().ok())
+            .unwrap_or(1),
+    })
+}
+
+/// The JSON diagnostics document of `tool`
+pub fn to_json(tool: &str, diagnostics: &[Diagnostic]) -> Value {
+    json!({
+        "version": SCHEMA_VERSION,
+        "tool": tool,
+        "diagnostics": diagnostics,
+    })
+}
+
+/// A SARIF 2.1.0 log of `diagnostics`
+pub fn to_sarif(diagnostics: &[Diagnostic]) -> Value {
+    // Rules in order of their ids, so the log is the same from run to run
+    let rules: BTreeMap<&str, Value> = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.code.as_str(), sarif_rule(&diagnostic.code)))
+        .collect();
+    let rule_index = |code: &str| rules.keys().position(|id| *id == code);
+
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut result = json!({
+                "ruleId": diagnostic.code,
+                "ruleIndex": rule_index(&diagnostic.code),
+                "level": match diagnostic.severity {
+                    "info" => "note",
+                    level => level,
+                },
+                "message": { "text": diagnostic.message },
+                "locations": [{
+                    "physicalLocation": sarif_location(&diagnostic.file, diagnostic.span),
+                }],
+            });
+            if let Some(fix) = &diagnostic.fix {
+                result["fixes"] = json!([sarif_fix(&diagnostic.file, fix)]);
+            }
+            result
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "nag",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules.into_values().collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn sarif_rule(code: &str) -> Value {
+    match nagari_compiler::explain::lookup(code) {
+        Some(explanation) => json!({
+            "id": code,
+            "shortDescription": { "text": explanation.title },
+            "fullDescription": { "text": explanation.text },
+            "helpUri": format!("{}#{}", env!("CARGO_PKG_REPOSITORY"), code),
+        }),
+        None => json!({
+            "id": code,
+            "shortDescription": { "text": code.replace('-', " ") },
+        }),
+    }
+}
+
+fn sarif_location(file: &Path, span: Option<Span>) -> Value {
+    let mut location = json!({
+        "artifactLocation": { "uri": artifact_uri(file) },
+    });
+    if let Some(span) = span {
+        location["region"] = json!({
+            "startLine": span.line,
+            "startColumn": span.column,
+        });
+    }
+    location
+}
+
+fn sarif_fix(file: &Path, fix: &Fix) -> Value {
+    json!({
+        "description": { "text": fix.description },
+        "artifactChanges": [{
+            "artifactLocation": { "uri": artifact_uri(file) },
+            // Whole lines, up to the start of the line after the last one
+            "replacements": [{
+                "deletedRegion": {
+                    "startLine": fix.line,
+                    "startColumn": 1,
+                    "endLine": fix.end_line + 1,
+                    "endColumn": 1,
+                },
+                "insertedContent": {
+                    "text": fix
+                        .replacement
+                        .iter()
+                        .map(|line| format!("{line}\n"))
+                        .collect::<String>(),
+                },
+            }],
+        }],
+    })
+}
+
+/// `file` as a URI. Relative paths stay relative, and code scanning resolves them against the
+/// repository.
+fn artifact_uri(file: &Path) -> String {
+    let uri = file.to_string_lossy().replace('\\', "/");
+    if !file.is_absolute() {
+        return uri.strip_prefix("./").unwrap_or(&uri).to_string();
+    }
+    // Windows paths such as `C:/src` need a slash before the drive
+    let slash = if uri.starts_with('/') { "" } else { "/" };
+    format!("file://{slash}{uri}")
+}
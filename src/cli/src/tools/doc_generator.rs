@@ -127,7 +127,7 @@ impl DocGenerator {
 
         let mut module = DocModule {
             name: relative_path.clone(),
-            path: file_path.to_string_lossy().to_string(),
+            path: nagari_compiler::portable::slash_path(file_path),
             description: String::new(),
             functions: Vec::new(),
             classes: Vec::new(),
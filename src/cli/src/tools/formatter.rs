@@ -1,6 +1,8 @@
 use crate::config::FormatConfig;
 use crate::tools::FileChange;
 use anyhow::Result;
+use nagari_parser::Directives;
+use std::collections::HashSet;
 use std::path::Path;
 
 pub struct NagFormatter {
@@ -43,13 +45,44 @@ impl NagFormatter {
     }
 
     pub fn format_string(&self, content: &str) -> Result<String> {
+        let directives = Directives::from_source(content);
+        if directives.is_file_ignored() {
+            return Ok(content.to_string());
+        }
+
+        let organized;
+        let content = if self.config.organize_imports {
+            organized = self.organize_imports(content);
+            organized.as_str()
+        } else {
+            content
+        };
+
         let mut formatted = String::new();
         let mut indent_level: usize = 0;
         let _in_string = false;
         let _string_char = '\0';
         let _escape_next = false;
+        // Lines inside `# fmt: off` regions, copied through untouched
+        let mut verbatim = HashSet::new();
+        // Inside a `js"""..."""` block spanning several lines
+        let mut in_inline_js = false;
+
+        for (index, line) in content.lines().enumerate() {
+            // Lines holding inline JavaScript are not Nagari and keep their layout
+            let quotes = line.matches("\"\"\"").count();
+            let inline_js = in_inline_js || line.contains("js\"\"\"");
+            if inline_js {
+                in_inline_js ^= quotes % 2 == 1;
+            }
+
+            if inline_js || directives.is_formatting_disabled(index + 1) {
+                verbatim.insert(index);
+                formatted.push_str(line);
+                formatted.push('\n');
+                continue;
+            }
 
-        for line in content.lines() {
             let trimmed = line.trim();
 
             // Skip empty lines
@@ -114,7 +147,11 @@ impl NagFormatter {
         let mut result = String::new();
 
         for (i, line) in lines.iter().enumerate() {
-            result.push_str(line.trim_end());
+            if verbatim.contains(&i) {
+                result.push_str(line);
+            } else {
+                result.push_str(line.trim_end());
+            }
             if i < lines.len() - 1 {
                 result.push('\n');
             }
@@ -123,6 +160,11 @@ impl NagFormatter {
         Ok(result)
     }
 
+    /// Merge duplicate imports, drop unused ones and group them by origin
+    pub fn organize_imports(&self, content: &str) -> String {
+        nagari_parser::organize_imports(content)
+    }
+
     fn format_line_content(&self, line: &str) -> Result<String> {
         let mut result = String::new();
         let mut chars = line.chars().peekable();
@@ -140,6 +182,17 @@ impl NagFormatter {
                     in_string = false;
                     result.push(ch);
                 }
+                // Comments (and any directives in them) are kept as written
+                '#' if !in_string => {
+                    result.push(ch);
+                    result.extend(chars);
+                    break;
+                }
+                '/' if !in_string && chars.peek() == Some(&'/') => {
+                    result.push(ch);
+                    result.extend(chars);
+                    break;
+                }
                 ' ' if !in_string => {
                     // Handle spacing around operators
                     if self.config.space_around_operators {
@@ -1,13 +1,18 @@
 #![allow(dead_code)]
 
 use crate::config::LintConfig;
+use crate::tools::diagnostics::Fix;
 use crate::tools::{LintIssue, Severity};
 use anyhow::Result;
+use nagari_compiler::warnings::{self, WarningLevel};
+use nagari_parser::Directives;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use regex::Regex;
+use serde::Serialize;
 use walkdir::WalkDir;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct LintStatistics {
     pub total: usize,
     pub errors: usize,
@@ -42,6 +47,9 @@ fn xml_escape(text: &str) -> String {
 pub struct NagLinter {
     config: LintConfig,
     rules: Vec<Box<dyn LintRule>>,
+    // Compiler warning levels, applied to non-error issues by rule name
+    warning_levels: HashMap<String, WarningLevel>,
+    deny_warnings: bool,
 }
 
 impl NagLinter {
@@ -49,13 +57,28 @@ impl NagLinter {
         let mut linter = Self {
             config: config.clone(),
             rules: Vec::new(),
+            warning_levels: HashMap::new(),
+            deny_warnings: false,
         };
 
         // Register built-in lint rules
         linter.register_default_rules();
 
         linter
-    }    fn register_default_rules(&mut self) {
+    }
+
+    /// Allow warnings, or promote them to errors, per rule name
+    pub fn with_warning_levels(
+        mut self,
+        levels: &HashMap<String, WarningLevel>,
+        deny_warnings: bool,
+    ) -> Self {
+        self.warning_levels = levels.clone();
+        self.deny_warnings = deny_warnings;
+        self
+    }
+
+    fn register_default_rules(&mut self) {
         self.rules.push(Box::new(UnusedVariableRule::new(&self.config)));
         self.rules.push(Box::new(UndefinedVariableRule::new()));
         self.rules.push(Box::new(UnusedImportRule::new(&self.config)));
@@ -101,12 +124,28 @@ impl NagLinter {
     pub fn lint_string(&self, content: &str, file_path: PathBuf, fix: bool) -> Result<Vec<LintIssue>> {
         let mut issues = Vec::new();
         let mut fixed_content = content.to_string();
+        let directives = Directives::from_source(content);
+        if directives.is_file_ignored() {
+            return Ok(issues);
+        }
 
         for rule in &self.rules {
             if self.is_rule_enabled(rule.name()) {
                 let rule_issues = rule.check(&fixed_content, &file_path)?;
 
-                for issue in rule_issues {
+                for mut issue in rule_issues {
+                    if directives.is_suppressed(&issue.rule, issue.line as usize) {
+                        continue;
+                    }
+                    if issue.severity != Severity::Error {
+                        let level =
+                            warnings::level_for(&self.warning_levels, self.deny_warnings, &issue.rule);
+                        match level {
+                            WarningLevel::Allow => continue,
+                            WarningLevel::Warn => {}
+                            WarningLevel::Deny => issue.severity = Severity::Error,
+                        }
+                    }
                     if fix && issue.fixable {
                         // Apply the fix
                         if let Some(fixed) = rule.fix(&fixed_content, &issue)? {
@@ -150,6 +189,21 @@ impl NagLinter {
         false
     }
 
+    /// The edit `--fix` would make for `issue` in `content`
+    pub fn suggest_fix(&self, issue: &LintIssue, content: &str) -> Option<Fix> {
+        if !issue.fixable {
+            return None;
+        }
+        let rule = self.rules.iter().find(|rule| rule.name() == issue.rule)?;
+        let fixed = rule.fix(content, issue).ok()??;
+        Fix::between(
+            rule.fix_description(issue),
+            content,
+            &fixed,
+            rule.fix_is_safe(),
+        )
+    }
+
     /// Format lint issues according to the specified format
     pub fn format_issues(&self, issues: &[LintIssue], format: &str) -> Result<String> {
         match format {
@@ -271,6 +325,15 @@ pub trait LintRule {
     fn name(&self) -> &str;
     fn check(&self, content: &str, file_path: &Path) -> Result<Vec<LintIssue>>;
     fn fix(&self, content: &str, issue: &LintIssue) -> Result<Option<String>>;
+
+    /// Whether the fix keeps the program's behaviour
+    fn fix_is_safe(&self) -> bool {
+        true
+    }
+
+    fn fix_description(&self, issue: &LintIssue) -> String {
+        format!("Fix {}", issue.rule)
+    }
 }
 
 // Individual lint rules
@@ -337,6 +400,15 @@ impl LintRule for UnusedVariableRule {
 
         Ok(Some(fixed_lines.join("\n")))
     }
+
+    // The assigned value may have side effects, which commenting it out drops
+    fn fix_is_safe(&self) -> bool {
+        false
+    }
+
+    fn fix_description(&self, _issue: &LintIssue) -> String {
+        "Comment out the unused assignment".to_string()
+    }
 }
 
 pub struct UndefinedVariableRule;
@@ -448,6 +520,10 @@ impl LintRule for UnusedImportRule {
 
         Ok(Some(fixed_lines.join("\n")))
     }
+
+    fn fix_description(&self, _issue: &LintIssue) -> String {
+        "Remove the unused import".to_string()
+    }
 }
 
 pub struct ShadowingRule;
@@ -641,6 +717,10 @@ impl LintRule for IndentationRule {
 
         Ok(Some(fixed_lines.join("\n")))
     }
+
+    fn fix_description(&self, _issue: &LintIssue) -> String {
+        "Indent with spaces".to_string()
+    }
 }
 
 pub struct TrailingWhitespaceRule;
@@ -676,13 +756,24 @@ impl LintRule for TrailingWhitespaceRule {
         Ok(issues)
     }
 
-    fn fix(&self, content: &str, _issue: &LintIssue) -> Result<Option<String>> {
+    fn fix(&self, content: &str, issue: &LintIssue) -> Result<Option<String>> {
         let fixed = content
             .lines()
-            .map(|line| line.trim_end())
+            .enumerate()
+            .map(|(index, line)| {
+                if index + 1 == issue.line as usize {
+                    line.trim_end()
+                } else {
+                    line
+                }
+            })
             .collect::<Vec<_>>()
             .join("\n");
 
         Ok(Some(fixed))
     }
+
+    fn fix_description(&self, _issue: &LintIssue) -> String {
+        "Remove trailing whitespace".to_string()
+    }
 }
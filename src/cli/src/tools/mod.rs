@@ -4,10 +4,14 @@ use std::path::{Path, PathBuf};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
+pub mod autofix;
+pub mod build_timings;
+pub mod diagnostics;
 pub mod formatter;
 pub mod linter;
 pub mod doc_generator;
 pub mod package_manager;
+pub mod upgrade;
 
 pub use formatter::NagFormatter;
 pub use linter::NagLinter;
@@ -107,7 +107,7 @@ pub async fn init_package(yes: bool, _config: &NagConfig) -> Result<()> {
     // Create .gitignore if it doesn't exist
     let gitignore_file = PathBuf::from(".gitignore");
     if !gitignore_file.exists() {
-        let gitignore_content = "# Nagari build outputs\ndist/\n*.js.map\n\n# Dependencies\nnode_modules/\nnag_modules/\n\n# IDE\n.vscode/\n.idea/\n\n# OS\n.DS_Store\nThumbs.db\n";
+        let gitignore_content = "# Nagari build outputs\ndist/\n*.js.map\n.nag-cache/\n\n# Dependencies\nnode_modules/\nnag_modules/\n\n# IDE\n.vscode/\n.idea/\n\n# OS\n.DS_Store\nThumbs.db\n";
         std::fs::write(&gitignore_file, gitignore_content)?;
         println!("✓ Created .gitignore");
     }
@@ -263,6 +263,14 @@ pub async fn publish_package(
         return Ok(());
     }
 
+    if crate::package::credentials::token(&registry_url).is_none() {
+        anyhow::bail!(
+            "Not logged in to {}; run `nag login` or set {}",
+            registry_url,
+            crate::package::credentials::TOKEN_ENV
+        );
+    }
+
     // TODO: Implement actual publishing
     println!("Package publishing not yet implemented");
 
@@ -270,16 +278,9 @@ pub async fn publish_package(
 }
 
 pub async fn pack_package(output: Option<PathBuf>, _config: &NagConfig) -> Result<()> {
-    let package = load_package_json()?;
-    let output_file = output.unwrap_or_else(|| {
-        PathBuf::from(format!("{}-{}.tgz", package.name, package.version))
-    });
-
-    println!("Packing {} v{} to {}", package.name, package.version, output_file.display());
-
-    // TODO: Implement package creation (tar.gz with package contents)
-    println!("Package packing not yet implemented");
-
+    let output_dir = output.unwrap_or_else(|| PathBuf::from("."));
+    let tarball = crate::package::pack::pack(std::path::Path::new("."), &output_dir)?;
+    println!("Packed {}", tarball.display());
     Ok(())
 }
 
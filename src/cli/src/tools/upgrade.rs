@@ -0,0 +1,277 @@
+//! Codemods rewriting sources written for an earlier version of the language, for `nag upgrade`.
+//!
+//! Syntax that has a replacement stays accepted by the parser for at least one release, which
+//! records each use as a deprecation, so older sources still parse. A [`Codemod`] looks at the
+//! parsed program, its tokens and those deprecations and returns [`Edit`]s to the text. The
+//! edits of every codemod are applied together and the result is parsed again; a file is only
+//! upgraded when that succeeds.
+//!
+//! A migration for a future change is a new [`Codemod`] added to [`codemods`], or an entry of
+//! [`RENAMED_BUILTINS`] when a builtin gets a new name.
+
+use anyhow::Result;
+use nagari_parser::{
+    Deprecation, Expression, Lexer, Parser, Program, Statement, Token, TokenWithPosition,
+};
+use std::path::{Path, PathBuf};
+
+/// Builtins renamed since an earlier release, as (old name, new name, version of the rename)
+pub const RENAMED_BUILTINS: &[(&str, &str, &str)] = &[];
+
+/// A parsed source, as codemods see it
+pub struct Source<'a> {
+    pub text: &'a str,
+    pub program: &'a Program,
+    pub tokens: &'a [TokenWithPosition<'a>],
+    /// Deprecated syntax the parser accepted
+    pub deprecations: &'a [Deprecation],
+}
+
+impl Source<'_> {
+    /// The token starting at `line` and `column`
+    pub fn token_at(&self, line: usize, column: usize) -> Option<&TokenWithPosition<'_>> {
+        self.tokens
+            .iter()
+            .find(|token| token.line == line && token.column == column)
+    }
+}
+
+/// Replacing the bytes `start..end` of a source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+impl Edit {
+    /// Replace `token`, which is `len` bytes long
+    pub fn token(token: &TokenWithPosition, len: usize, replacement: &str) -> Self {
+        Self {
+            start: token.offset,
+            end: token.offset + len,
+            replacement: replacement.to_string(),
+        }
+    }
+}
+
+/// One migration from an older form of the language to the current one
+pub trait Codemod {
+    /// Name, as `nag upgrade --only` takes it
+    fn name(&self) -> &str;
+    /// Version in which the old form was deprecated
+    fn since(&self) -> &str;
+    fn description(&self) -> String;
+    fn edits(&self, source: &Source) -> Vec<Edit>;
+}
+
+/// `var x = 1` becomes `let x = 1`
+pub struct VarDeclarations;
+
+impl Codemod for VarDeclarations {
+    fn name(&self) -> &str {
+        "var-declarations"
+    }
+
+    fn since(&self) -> &str {
+        "0.3.0"
+    }
+
+    fn description(&self) -> String {
+        "Replace `var` declarations with `let`".to_string()
+    }
+
+    fn edits(&self, source: &Source) -> Vec<Edit> {
+        source
+            .deprecations
+            .iter()
+            .filter_map(|deprecation| {
+                source.token_at(deprecation.span.line, deprecation.span.column)
+            })
+            .filter(|token| token.token == Token::Var)
+            .map(|token| Edit::token(token, "var".len(), "let"))
+            .collect()
+    }
+}
+
+/// Uses of builtin `from` become uses of `to`, unless the program binds a name `from` itself
+pub struct RenameBuiltin {
+    pub name: String,
+    pub since: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl Codemod for RenameBuiltin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn since(&self) -> &str {
+        &self.since
+    }
+
+    fn description(&self) -> String {
+        format!("Rename builtin `{}` to `{}`", self.from, self.to)
+    }
+
+    fn edits(&self, source: &Source) -> Vec<Edit> {
+        if binds(&source.program.statements, &self.from) {
+            return Vec::new();
+        }
+        // Attribute names after a `.` are not the builtin
+        let mut edits = Vec::new();
+        let mut after_dot = false;
+        for token in source.tokens {
+            if token.token == Token::Identifier(&self.from) && !after_dot {
+                edits.push(Edit::token(token, self.from.len(), &self.to));
+            }
+            after_dot = token.token == Token::Dot;
+        }
+        edits
+    }
+}
+
+/// Whether `statements` define, assign, import or take as a parameter a name `name`
+fn binds(statements: &[Statement], name: &str) -> bool {
+    let params = |parameters: &[nagari_parser::FunctionParameter]| {
+        parameters.iter().any(|parameter| parameter.name == name)
+    };
+    statements.iter().any(|statement| match statement {
+        Statement::Let { name: bound, .. } | Statement::Const { name: bound, .. } => bound == name,
+        Statement::Expression(Expression::Assignment { left, .. }) => {
+            matches!(left.as_ref(), Expression::Identifier(bound) if bound == name)
+        }
+        Statement::If {
+            then_body,
+            else_body,
+            ..
+        } => binds(then_body, name) || else_body.as_ref().is_some_and(|body| binds(body, name)),
+        Statement::While { body, .. } => binds(body, name),
+        Statement::For { variable, body, .. } => variable == name || binds(body, name),
+        Statement::With { items, body, .. } => {
+            items.iter().any(|item| item.alias.as_deref() == Some(name)) || binds(body, name)
+        }
+        Statement::Match { cases, .. } => cases.iter().any(|case| binds(&case.body, name)),
+        Statement::Function {
+            name: bound,
+            parameters,
+            body,
+            ..
+        } => bound == name || params(parameters) || binds(body, name),
+        Statement::Class {
+            name: bound,
+            methods,
+            ..
+        } => bound == name || binds(methods, name),
+        Statement::ExportDeclaration { declaration } => {
+            binds(std::slice::from_ref(declaration.as_ref()), name)
+        }
+        Statement::Import { items, .. } => items
+            .iter()
+            .any(|item| item.alias.as_deref().unwrap_or(&item.name) == name),
+        _ => false,
+    })
+}
+
+/// Every migration `nag upgrade` knows, oldest first
+pub fn codemods() -> Vec<Box<dyn Codemod>> {
+    let mut codemods: Vec<Box<dyn Codemod>> = vec![Box::new(VarDeclarations)];
+    for (from, to, since) in RENAMED_BUILTINS {
+        codemods.push(Box::new(RenameBuiltin {
+            name: format!("rename-{from}"),
+            since: since.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+        }));
+    }
+    codemods
+}
+
+/// What upgrading a file did
+#[derive(Debug, Clone)]
+pub struct FileUpgrade {
+    pub path: PathBuf,
+    pub original: String,
+    pub upgraded: String,
+    /// Names of the codemods that changed the file, with the number of edits of each
+    pub applied: Vec<(String, usize)>,
+}
+
+impl FileUpgrade {
+    pub fn changed(&self) -> bool {
+        self.original != self.upgraded
+    }
+
+    /// Unified diff from the original text to the upgraded one
+    pub fn diff(&self) -> String {
+        let path = self.path.display().to_string();
+        similar::TextDiff::from_lines(&self.original, &self.upgraded)
+            .unified_diff()
+            .header(&path, &path)
+            .to_string()
+    }
+}
+
+/// Apply `codemods` to `path`'s text. Nothing is written.
+pub fn upgrade_file(path: &Path, codemods: &[Box<dyn Codemod>]) -> Result<FileUpgrade> {
+    let original = std::fs::read_to_string(path)?;
+    let (upgraded, applied) = upgrade_source(&original, codemods)
+        .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    Ok(FileUpgrade {
+        path: path.to_path_buf(),
+        original,
+        upgraded,
+        applied,
+    })
+}
+
+/// Apply `codemods` to `text`, returning the upgraded text and what each codemod changed.
+/// Edits overlapping an earlier one are left out.
+pub fn upgrade_source(
+    text: &str,
+    codemods: &[Box<dyn Codemod>],
+) -> Result<(String, Vec<(String, usize)>)> {
+    let tokens = Lexer::new(text).tokenize()?;
+    let mut parser = Parser::new(tokens.clone());
+    let program = parser.parse_program()?;
+    let source = Source {
+        text,
+        program: &program,
+        tokens: &tokens,
+        deprecations: parser.deprecations(),
+    };
+
+    let mut edits: Vec<(usize, Edit)> = Vec::new();
+    for (index, codemod) in codemods.iter().enumerate() {
+        edits.extend(codemod.edits(&source).into_iter().map(|edit| (index, edit)));
+    }
+    edits.sort_by_key(|(_, edit)| (edit.start, edit.end));
+
+    let mut upgraded = String::with_capacity(text.len());
+    let mut counts = vec![0; codemods.len()];
+    // Bytes before this one are copied or replaced already
+    let mut next = 0;
+    for (index, edit) in edits {
+        if edit.start < next || edit.end > text.len() {
+            continue;
+        }
+        upgraded.push_str(&text[next..edit.start]);
+        upgraded.push_str(&edit.replacement);
+        next = edit.end;
+        counts[index] += 1;
+    }
+    upgraded.push_str(&text[next..]);
+
+    if upgraded != text {
+        nagari_parser::parse(&upgraded)
+            .map_err(|e| anyhow::anyhow!("the upgraded source no longer parses: {}", e))?;
+    }
+    let applied = codemods
+        .iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(codemod, count)| (codemod.name().to_string(), count))
+        .collect();
+    Ok((upgraded, applied))
+}
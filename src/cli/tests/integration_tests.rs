@@ -36,6 +36,95 @@ mod tests {
         assert!(stdout.contains("Nagari CLI tool"));
     }
 
+    #[tokio::test]
+    async fn test_cli_completions() {
+        let output = Command::new("cargo")
+            .args(["run", "--bin", "nag", "--", "completions", "bash"])
+            .output()
+            .await
+            .expect("Failed to run command");
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("_nag()"));
+        assert!(stdout.contains("completions"));
+    }
+
+    #[tokio::test]
+    async fn test_cli_json_output() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let output = Command::new("cargo")
+            .args(["run", "--bin", "nag", "--", "--json", "lint"])
+            .arg(temp_dir.path())
+            .output()
+            .await
+            .expect("Failed to run command");
+
+        assert!(output.status.success());
+        let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(result["command"], "lint");
+        assert_eq!(result["success"], true);
+        assert_eq!(result["statistics"]["total"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_install_script() {
+        let temp_dir = TempDir::new().unwrap();
+        let script = temp_dir.path().join("greet.nag");
+        fs::write(&script, "#!/usr/bin/env nag\nprint(\"hi\")\n").unwrap();
+        let bin_dir = temp_dir.path().join("bin");
+
+        let output = Command::new("cargo")
+            .args(["run", "--bin", "nag", "--", "install-script"])
+            .arg(&script)
+            .arg("--dir")
+            .arg(&bin_dir)
+            .output()
+            .await
+            .expect("Failed to run command");
+
+        assert!(output.status.success());
+        let command = if cfg!(windows) { "greet.cmd" } else { "greet" };
+        let wrapper = fs::read_to_string(bin_dir.join(command)).unwrap();
+        assert!(wrapper.contains("nag run --quiet"));
+        assert!(wrapper.contains("greet.nag"));
+    }
+
+    #[tokio::test]
+    async fn test_upgrade() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("main.nag");
+        fs::write(&source, "var total = 1\nprint(total)\n").unwrap();
+
+        let preview = Command::new("cargo")
+            .args(["run", "--bin", "nag", "--", "upgrade", "--dry-run"])
+            .arg(&source)
+            .output()
+            .await
+            .expect("Failed to run command");
+        assert!(preview.status.success());
+        let stdout = String::from_utf8(preview.stdout).unwrap();
+        assert!(stdout.contains("-var total = 1"));
+        assert!(stdout.contains("+let total = 1"));
+        assert_eq!(
+            fs::read_to_string(&source).unwrap(),
+            "var total = 1\nprint(total)\n"
+        );
+
+        let output = Command::new("cargo")
+            .args(["run", "--bin", "nag", "--", "upgrade", "--yes"])
+            .arg(&source)
+            .output()
+            .await
+            .expect("Failed to run command");
+        assert!(output.status.success());
+        assert_eq!(
+            fs::read_to_string(&source).unwrap(),
+            "let total = 1\nprint(total)\n"
+        );
+    }
+
     #[tokio::test]
     #[ignore] // Integration tests require complex binary path setup - use unit tests for core validation
     async fn test_package_init() {
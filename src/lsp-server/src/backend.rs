@@ -8,9 +8,11 @@ use tower_lsp::{lsp_types::*, Client, LanguageServer};
 use crate::{
     capabilities::server_capabilities, code_actions::CodeActionsProvider,
     completion::CompletionProvider, diagnostics::DiagnosticsProvider, document::DocumentManager,
-    formatting::FormattingProvider, goto::GotoProvider, hover::HoverProvider,
-    inlay_hints::InlayHintsProvider, references::ReferenceProvider, rename::RenameProvider,
-    semantic_tokens::SemanticTokensProvider, symbols::SymbolProvider, workspace::WorkspaceManager,
+    evaluate::EvaluateParams, evaluate::Evaluation, evaluate::EvaluationProvider,
+    formatting::FormattingProvider, goto::GotoProvider, hierarchy::HierarchyProvider,
+    hover::HoverProvider, inlay_hints::InlayHintsProvider, references::ReferenceProvider,
+    rename::RenameProvider, semantic_tokens::SemanticTokensProvider, symbols::SymbolProvider,
+    workspace::WorkspaceManager,
 };
 
 pub struct NagariLanguageServer {
@@ -28,6 +30,8 @@ pub struct NagariLanguageServer {
     semantic_tokens_provider: SemanticTokensProvider,
     inlay_hints_provider: InlayHintsProvider,
     code_actions_provider: CodeActionsProvider,
+    hierarchy_provider: HierarchyProvider,
+    evaluation_provider: EvaluationProvider,
     // Cache for parsed ASTs and analysis results
     ast_cache: DashMap<String, Arc<String>>,
     symbol_cache: DashMap<String, Vec<String>>,
@@ -75,7 +79,15 @@ impl NagariLanguageServer {
                 document_manager.clone(),
                 workspace_manager.clone(),
             ),
-            code_actions_provider: CodeActionsProvider::new(),
+            code_actions_provider: CodeActionsProvider::new(document_manager.clone()),
+            hierarchy_provider: HierarchyProvider::with_managers(
+                document_manager.clone(),
+                workspace_manager.clone(),
+            ),
+            evaluation_provider: EvaluationProvider::with_managers(
+                document_manager.clone(),
+                workspace_manager.clone(),
+            ),
             document_manager,
             workspace_manager,
             ast_cache: DashMap::new(),
@@ -115,6 +127,14 @@ impl NagariLanguageServer {
     pub async fn clear_diagnostics(&self, uri: &Url) -> Result<()> {
         self.diagnostics_provider.clear_diagnostics(uri).await
     }
+
+    /// Handler of the `nagari/evaluate` request
+    pub async fn evaluate(&self, params: EvaluateParams) -> tower_lsp::jsonrpc::Result<Evaluation> {
+        self.evaluation_provider
+            .evaluate(params)
+            .await
+            .map_err(|e| tower_lsp::jsonrpc::Error::invalid_params(e.to_string()))
+    }
 }
 
 #[tower_lsp::async_trait]
@@ -125,6 +145,20 @@ impl LanguageServer for NagariLanguageServer {
     ) -> tower_lsp::jsonrpc::Result<InitializeResult> {
         tracing::info!("Initializing Nagari Language Server");
 
+        // Warning levels: `{"warnings": {"line-length": "allow"}, "denyWarnings": true}`
+        if let Some(options) = &params.initialization_options {
+            let levels = options
+                .get("warnings")
+                .and_then(|levels| serde_json::from_value(levels.clone()).ok())
+                .unwrap_or_default();
+            let deny_warnings = options
+                .get("denyWarnings")
+                .and_then(|deny| deny.as_bool())
+                .unwrap_or(false);
+            self.diagnostics_provider
+                .configure_warnings(levels, deny_warnings);
+        }
+
         // Initialize workspace
         if let Some(workspace_folders) = params.workspace_folders {
             for folder in workspace_folders {
@@ -411,6 +445,78 @@ impl LanguageServer for NagariLanguageServer {
         Ok(result)
     }
 
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyItem>>> {
+        let result = self
+            .hierarchy_provider
+            .prepare_call_hierarchy(params)
+            .await
+            .unwrap_or(None);
+        Ok(result)
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let result = self
+            .hierarchy_provider
+            .incoming_calls(params)
+            .await
+            .unwrap_or(None);
+        Ok(result)
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let result = self
+            .hierarchy_provider
+            .outgoing_calls(params)
+            .await
+            .unwrap_or(None);
+        Ok(result)
+    }
+
+    async fn prepare_type_hierarchy(
+        &self,
+        params: TypeHierarchyPrepareParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TypeHierarchyItem>>> {
+        let result = self
+            .hierarchy_provider
+            .prepare_type_hierarchy(params)
+            .await
+            .unwrap_or(None);
+        Ok(result)
+    }
+
+    async fn supertypes(
+        &self,
+        params: TypeHierarchySupertypesParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TypeHierarchyItem>>> {
+        let result = self
+            .hierarchy_provider
+            .supertypes(params)
+            .await
+            .unwrap_or(None);
+        Ok(result)
+    }
+
+    async fn subtypes(
+        &self,
+        params: TypeHierarchySubtypesParams,
+    ) -> tower_lsp::jsonrpc::Result<Option<Vec<TypeHierarchyItem>>> {
+        let result = self
+            .hierarchy_provider
+            .subtypes(params)
+            .await
+            .unwrap_or(None);
+        Ok(result)
+    }
+
     async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
         tracing::info!("Workspace folders changed");
 
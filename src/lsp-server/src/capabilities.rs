@@ -66,6 +66,16 @@ pub fn server_capabilities() -> ServerCapabilities {
             }
         ))),
 
+        // Call hierarchy support
+        call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+
+        // Type hierarchy support, which this version of lsp-types has no capability field for,
+        // and the custom `nagari/evaluate` request
+        experimental: Some(serde_json::json!({
+            "typeHierarchyProvider": true,
+            "evaluateProvider": true,
+        })),
+
         // Code actions support
         code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
             code_action_kinds: Some(vec![
@@ -1,14 +1,18 @@
+use crate::{document::DocumentManager, refactor};
 use anyhow::Result;
+use std::sync::Arc;
 use tower_lsp::lsp_types::*;
 
 pub struct CodeActionsProvider {
+    document_manager: Arc<DocumentManager>,
     // Cache for available code actions
     available_actions: Vec<CodeActionKind>,
 }
 
 impl CodeActionsProvider {
-    pub fn new() -> Self {
+    pub fn new(document_manager: Arc<DocumentManager>) -> Self {
         Self {
+            document_manager,
             available_actions: vec![
                 CodeActionKind::QUICKFIX,
                 CodeActionKind::REFACTOR,
@@ -63,39 +67,32 @@ impl CodeActionsProvider {
         }
 
         // Add refactoring actions
-        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
-            title: "Extract function".to_string(),
-            kind: Some(CodeActionKind::REFACTOR_EXTRACT),
-            diagnostics: None,
-            edit: None,
-            command: Some(Command {
-                title: "Extract function".to_string(),
-                command: "nagari.extractFunction".to_string(),
-                arguments: Some(vec![
-                    serde_json::to_value(uri).unwrap(),
-                    serde_json::to_value(range).unwrap(),
-                ]),
-            }),
-            is_preferred: Some(false),
-            disabled: None,
-            data: None,
-        }));
-
-        // Add organize imports action
-        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
-            title: "Organize imports".to_string(),
-            kind: Some(CodeActionKind::SOURCE_ORGANIZE_IMPORTS),
-            diagnostics: None,
-            edit: None,
-            command: Some(Command {
-                title: "Organize imports".to_string(),
-                command: "nagari.organizeImports".to_string(),
-                arguments: Some(vec![serde_json::to_value(uri).unwrap()]),
-            }),
-            is_preferred: Some(false),
-            disabled: None,
-            data: None,
-        }));
+        if let Some(text) = self.document_manager.get_document_text(uri).await {
+            if let Some(result) = refactor::extract_function(&text, range) {
+                actions.push(refactoring_action(
+                    "Extract function",
+                    CodeActionKind::REFACTOR_EXTRACT,
+                    uri,
+                    result,
+                ));
+            }
+            if let Some(result) = refactor::inline_variable(&text, range.start) {
+                actions.push(refactoring_action(
+                    "Inline variable",
+                    CodeActionKind::REFACTOR_INLINE,
+                    uri,
+                    result,
+                ));
+            }
+            if let Some(edits) = refactor::organize_imports(&text) {
+                actions.push(refactoring_action(
+                    "Organize imports",
+                    CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+                    uri,
+                    Ok(edits),
+                ));
+            }
+        }
 
         // Add format document action
         actions.push(CodeActionOrCommand::CodeAction(CodeAction {
@@ -120,72 +117,37 @@ impl CodeActionsProvider {
         }
     }
 
-    pub async fn code_action_resolve(&self, mut action: CodeAction) -> Result<CodeAction> {
-        // Resolve additional data for code actions that need it
-        if let Some(command) = &action.command {
-            match command.command.as_str() {
-                "nagari.extractFunction" => {
-                    // Add actual edit for extract function
-                    if let Some(args) = &command.arguments {
-                        if args.len() >= 2 {
-                            let uri: Url = serde_json::from_value(args[0].clone())?;
-                            let range: Range = serde_json::from_value(args[1].clone())?;
-
-                            action.edit = Some(WorkspaceEdit {
-                                changes: Some([(uri, vec![
-                                    TextEdit {
-                                        range,
-                                        new_text: "extractedFunction()".to_string(),
-                                    },
-                                    TextEdit {
-                                        range: Range {
-                                            start: Position { line: 0, character: 0 },
-                                            end: Position { line: 0, character: 0 },
-                                        },
-                                        new_text: "function extractedFunction() {\n    // Extracted code\n}\n\n".to_string(),
-                                    }
-                                ])].into_iter().collect()),
-                                ..Default::default()
-                            });
-                        }
-                    }
-                }
-                "nagari.organizeImports" => {
-                    // Add actual edit for organize imports
-                    if let Some(args) = &command.arguments {
-                        if !args.is_empty() {
-                            let uri: Url = serde_json::from_value(args[0].clone())?;
-
-                            action.edit = Some(WorkspaceEdit {
-                                changes: Some(
-                                    [(
-                                        uri,
-                                        vec![TextEdit {
-                                            range: Range {
-                                                start: Position {
-                                                    line: 0,
-                                                    character: 0,
-                                                },
-                                                end: Position {
-                                                    line: 10,
-                                                    character: 0,
-                                                },
-                                            },
-                                            new_text: "// Organized imports\n".to_string(),
-                                        }],
-                                    )]
-                                    .into_iter()
-                                    .collect(),
-                                ),
-                                ..Default::default()
-                            });
-                        }
-                    }
-                }
-                _ => {}
-            }
-        }
-
+    pub async fn code_action_resolve(&self, action: CodeAction) -> Result<CodeAction> {
+        // Every action is built with its edit up front
         Ok(action)
     }
 }
+
+fn refactoring_action(
+    title: &str,
+    kind: CodeActionKind,
+    uri: &Url,
+    result: Result<Vec<TextEdit>, String>,
+) -> CodeActionOrCommand {
+    let (edit, disabled) = match result {
+        Ok(edits) => (
+            Some(WorkspaceEdit {
+                changes: Some([(uri.clone(), edits)].into_iter().collect()),
+                ..Default::default()
+            }),
+            None,
+        ),
+        Err(reason) => (None, Some(CodeActionDisabled { reason })),
+    };
+
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_string(),
+        kind: Some(kind),
+        diagnostics: None,
+        edit,
+        command: None,
+        is_preferred: Some(false),
+        disabled,
+        data: None,
+    })
+}
@@ -3,6 +3,7 @@
 #![allow(unused_variables)]
 
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use nagari_compiler::externs::ExternKind;
 use std::sync::Arc;
 use tower_lsp::lsp_types::*;
 use tower_lsp::Client;
@@ -63,6 +64,9 @@ impl CompletionProvider {
         // Add package imports
         completions.extend(self.get_package_completions(&current_word).await);
 
+        // Add members of modules and globals declared in `.nagd` files
+        completions.extend(self.get_declared_module_completions(&text, word_start, &current_word));
+
         // Sort by relevance
         completions.sort_by(|a, b| {
             let score_a = self
@@ -107,6 +111,7 @@ impl CompletionProvider {
             "else",
             "while",
             "for",
+            "with",
             "return",
             "break",
             "continue",
@@ -336,6 +341,72 @@ impl CompletionProvider {
         completions
     }
 
+    /// Members of a module declared in a `.nagd` file, inside `import { ... } from "module"`
+    /// and after `module.` when the module is imported whole; declared globals elsewhere
+    fn get_declared_module_completions(
+        &self,
+        text: &str,
+        word_start: usize,
+        prefix: &str,
+    ) -> Vec<CompletionItem> {
+        let declarations = self.workspace_manager.extern_declarations();
+        if declarations.is_empty() {
+            return Vec::new();
+        }
+
+        let chars: Vec<char> = text.chars().collect();
+        let line_start = chars[..word_start]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map_or(0, |i| i + 1);
+        let line_end = chars[word_start..]
+            .iter()
+            .position(|&c| c == '\n')
+            .map_or(chars.len(), |i| word_start + i);
+        let line: String = chars[line_start..line_end].iter().collect();
+        let before: String = chars[line_start..word_start].iter().collect();
+
+        let members = if before.trim_start().starts_with("import") && before.contains('{') {
+            line.rsplit_once("from")
+                .map(|(_, source)| source.trim().trim_matches(|c| c == '"' || c == '\''))
+                .and_then(|source| declarations.module(source))
+                .map(|module| module.members.as_slice())
+        } else if let Some(object) = before.strip_suffix('.') {
+            let start = object
+                .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map_or(0, |i| i + 1);
+            let alias = &object[start..];
+            let imported_whole = text
+                .lines()
+                .any(|l| l.trim().strip_prefix("import ").map(str::trim) == Some(alias));
+            imported_whole
+                .then(|| declarations.module(alias))
+                .flatten()
+                .map(|module| module.members.as_slice())
+        } else {
+            Some(declarations.globals())
+        };
+
+        let Some(members) = members else {
+            return Vec::new();
+        };
+        members
+            .iter()
+            .filter(|member| prefix.is_empty() || member.name.starts_with(prefix))
+            .map(|member| CompletionItem {
+                label: member.name.clone(),
+                kind: Some(match member.kind {
+                    ExternKind::Function(_) => CompletionItemKind::FUNCTION,
+                    ExternKind::Value(_) => CompletionItemKind::CONSTANT,
+                }),
+                detail: Some(member.signature.clone()),
+                documentation: member.doc.clone().map(Documentation::String),
+                insert_text: Some(member.name.clone()),
+                ..Default::default()
+            })
+            .collect()
+    }
+
     fn extract_document_symbols(&self, text: &str) -> Vec<DocumentSymbol> {
         let mut symbols = Vec::new();
 
@@ -413,6 +484,7 @@ impl CompletionProvider {
                     name,
                     superclass,
                     methods,
+                    ..
                 } => {
                     let detail = if let Some(super_name) = superclass {
                         format!("class {} extends {}", name, super_name)
@@ -453,7 +525,8 @@ impl CompletionProvider {
                     }
                 }
                 nagari_parser::Statement::While { body, .. }
-                | nagari_parser::Statement::For { body, .. } => {
+                | nagari_parser::Statement::For { body, .. }
+                | nagari_parser::Statement::With { body, .. } => {
                     // Extract symbols from loop bodies
                     self.extract_symbols_from_statements(body, symbols);
                 }
@@ -1,29 +1,59 @@
 use anyhow::Result;
 use dashmap::DashMap;
-use nagari_compiler::Compiler;
-use nagari_parser::{Lexer, Parser};
-use std::sync::Arc;
+use nagari_compiler::{explain, Compiler, WarningLevel};
+use nagari_parser::{Directives, Lexer, Parser};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use tower_lsp::lsp_types::*;
 
 pub struct DiagnosticsProvider {
     // Cache for diagnostics per document
     diagnostics_cache: Arc<DashMap<Url, Vec<Diagnostic>>>,
-    compiler: Compiler,
+    compiler: RwLock<Compiler>,
+}
+
+/// Warning category, shared with lint rules and compiler warning levels, for each
+/// warning-style diagnostic code
+const WARNING_CATEGORIES: &[(&str, &str)] = &[
+    ("LINE_TOO_LONG", "line-length"),
+    ("TRAILING_WHITESPACE", "trailing-whitespace"),
+    ("MIXED_INDENTATION", "indentation"),
+    ("UNUSED_VARIABLE", "unused-variables"),
+    ("UNDEFINED_VARIABLE", "undefined-variables"),
+];
+
+fn warning_category(diagnostic: &Diagnostic) -> Option<&'static str> {
+    let Some(NumberOrString::String(code)) = &diagnostic.code else {
+        return None;
+    };
+    WARNING_CATEGORIES
+        .iter()
+        .find(|(known, _)| known == code)
+        .map(|(_, category)| *category)
 }
 
 impl DiagnosticsProvider {
     pub fn new() -> Self {
         Self {
             diagnostics_cache: Arc::new(DashMap::new()),
-            compiler: Compiler::new(),
+            compiler: RwLock::new(Compiler::new()),
         }
     }
 
+    /// Use the given warning levels for compilation and diagnostic severities
+    pub fn configure_warnings(&self, levels: HashMap<String, WarningLevel>, deny_warnings: bool) {
+        let mut compiler = self.compiler.write().unwrap();
+        compiler.config.warning_levels = levels;
+        compiler.config.deny_warnings = deny_warnings;
+    }
+
     pub async fn get_diagnostics(&self, uri: &Url, text: &str) -> Result<Vec<Diagnostic>> {
         let mut diagnostics = Vec::new();
 
         // 1. Lexical analysis - check for tokenization errors
-        if let Err(parse_errors) = self.analyze_syntax(text) {
+        let syntax = self.analyze_syntax(text);
+        let syntax_ok = syntax.is_ok();
+        if let Err(parse_errors) = syntax {
             for error in parse_errors {
                 diagnostics.push(Diagnostic {
                     range: Range {
@@ -38,7 +68,7 @@ impl DiagnosticsProvider {
                         },
                     },
                     severity: Some(DiagnosticSeverity::ERROR),
-                    code: Some(NumberOrString::String(format!("SYNTAX_{}", error.code))),
+                    code: Some(NumberOrString::String(error.code)),
                     source: Some("nagari".to_string()),
                     message: error.message,
                     related_information: None,
@@ -49,8 +79,14 @@ impl DiagnosticsProvider {
             }
         }
 
-        // 2. Semantic analysis - check for semantic errors
-        match self.analyze_semantics(text) {
+        // 2. Semantic analysis - check for semantic errors. Source with syntax errors does not
+        // compile, and they are reported above already.
+        let semantics = if syntax_ok {
+            self.analyze_semantics(text)
+        } else {
+            Ok(Vec::new())
+        };
+        match semantics {
             Ok(semantic_issues) => {
                 for issue in semantic_issues {
                     diagnostics.push(issue);
@@ -87,6 +123,34 @@ impl DiagnosticsProvider {
         let lint_diagnostics = self.analyze_style(text);
         diagnostics.extend(lint_diagnostics);
 
+        // Apply warning levels and `# nagari:ignore` comments, as `nag lint` does
+        let directives = Directives::from_source(text);
+        let compiler = self.compiler.read().unwrap();
+        diagnostics.retain_mut(|diagnostic| {
+            let Some(category) = warning_category(diagnostic) else {
+                return true;
+            };
+            if directives.is_suppressed(category, diagnostic.range.start.line as usize + 1) {
+                return false;
+            }
+            match compiler.config.warning_level(category) {
+                WarningLevel::Allow => false,
+                WarningLevel::Warn => true,
+                WarningLevel::Deny => {
+                    diagnostic.severity = Some(DiagnosticSeverity::ERROR);
+                    true
+                }
+            }
+        });
+        drop(compiler);
+
+        // Point coded diagnostics at their `nag explain` text
+        for diagnostic in &mut diagnostics {
+            if diagnostic.related_information.is_none() {
+                diagnostic.related_information = Self::explanation_for(uri, diagnostic);
+            }
+        }
+
         // Cache the diagnostics
         self.diagnostics_cache
             .insert(uri.clone(), diagnostics.clone());
@@ -103,53 +167,72 @@ impl DiagnosticsProvider {
         self.diagnostics_cache.get(uri).map(|diags| diags.clone())
     }
 
+    fn explanation_for(
+        uri: &Url,
+        diagnostic: &Diagnostic,
+    ) -> Option<Vec<DiagnosticRelatedInformation>> {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return None;
+        };
+        let explanation = explain::lookup(code)?;
+        let summary = explanation.text.split("\n\n").next().unwrap_or_default();
+
+        Some(vec![DiagnosticRelatedInformation {
+            location: Location {
+                uri: uri.clone(),
+                range: diagnostic.range,
+            },
+            message: format!(
+                "{} ({}): {} Run `nag explain {}` for examples and fixes.",
+                explanation.title,
+                explanation.code,
+                summary.replace('\n', " "),
+                explanation.code
+            ),
+        }])
+    }
+
     fn analyze_syntax(&self, text: &str) -> Result<(), Vec<SyntaxError>> {
-        let mut lexer = Lexer::new(text);
-        let mut errors = Vec::new();
+        let error = |code: &str, message: String, position: Option<(usize, usize)>| {
+            let (line, column) = position.unwrap_or((1, 1));
+            SyntaxError {
+                line,
+                column,
+                length: Some(1),
+                code: code.to_string(),
+                message,
+            }
+        };
 
-        // Tokenize the entire input and collect lexing errors
-        let tokens = match lexer.tokenize() {
+        // Tokenize the entire input; a lexing error ends the analysis
+        let tokens = match Lexer::new(text).tokenize() {
             Ok(tokens) => tokens,
             Err(parse_error) => {
-                errors.push(SyntaxError {
-                    line: 1, // Lexer errors need better position tracking
-                    column: 1,
-                    length: Some(1),
-                    code: "LEXER_ERROR".to_string(),
-                    message: format!("Lexical error: {}", parse_error),
-                });
-                return Err(errors);
+                let message = format!("Lexical error: {}", parse_error);
+                return Err(vec![error("E0001", message, parse_error.position())]);
             }
         };
 
-        // Parse the tokens and collect parsing errors
-        let mut parser = Parser::new(tokens);
-        match parser.parse() {
-            Ok(_) => {
-                if errors.is_empty() {
-                    Ok(())
-                } else {
-                    Err(errors)
-                }
-            }
-            Err(parse_error) => {
-                errors.push(SyntaxError {
-                    line: 1, // Parser errors need better position tracking
-                    column: 1,
-                    length: Some(1),
-                    code: "PARSER_ERROR".to_string(),
-                    message: format!("Parse error: {}", parse_error),
-                });
-                Err(errors)
-            }
+        // Parse past syntax errors so that all of them are reported at once
+        let (_, parse_errors) = Parser::new(tokens).parse_recovering();
+        if parse_errors.is_empty() {
+            return Ok(());
         }
+        Err(parse_errors
+            .iter()
+            .map(|parse_error| {
+                let message = format!("Parse error: {}", parse_error);
+                error("E0002", message, parse_error.position())
+            })
+            .collect())
     }
 
     fn analyze_semantics(&self, text: &str) -> Result<Vec<Diagnostic>> {
         let mut diagnostics = Vec::new();
 
         // Use the compiler to check for semantic issues
-        match self.compiler.compile_string(text, None) {
+        let compiled = self.compiler.read().unwrap().compile_string(text, None);
+        match compiled {
             Ok(_) => {
                 // Compilation successful, check for warnings
                 diagnostics.extend(self.check_unused_variables(text));
@@ -170,7 +253,7 @@ impl DiagnosticsProvider {
                         },
                     },
                     severity: Some(DiagnosticSeverity::ERROR),
-                    code: Some(NumberOrString::String("COMPILER_ERROR".to_string())),
+                    code: Some(NumberOrString::String(compiler_error.code().to_string())),
                     source: Some("nagari".to_string()),
                     message: format!("Compilation error: {}", compiler_error),
                     related_information: None,
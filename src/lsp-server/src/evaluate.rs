@@ -0,0 +1,279 @@
+//! Evaluation of expressions in the scope at a position, behind the `nagari/evaluate` request
+//! used by debug consoles and watch windows.
+//!
+//! The names visible at the position are found from the text: the single-line statements
+//! above it in the same or an enclosing block, and the parameters of the functions and loop
+//! variables of the blocks enclosing it. The expression is checked against them the way
+//! builds check calls into declared modules. When asked to, it is then run in the VM after
+//! the visible bindings. Parameters have no value outside a debug session, so expressions
+//! using them only type-check.
+
+use crate::{document::DocumentManager, workspace::WorkspaceManager};
+use anyhow::{anyhow, Result};
+use nagari_compiler::{bytecode, externs, Compiler, NagariError};
+use nagari_parser::{Expression, FunctionParameter, ParameterKind, Statement};
+use nagari_vm::BytecodeCache;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tower_lsp::lsp_types::*;
+
+pub const EVALUATE_REQUEST: &str = "nagari/evaluate";
+
+/// Global the VM stores the value of the evaluated expression in
+const RESULT: &str = "__evaluate__";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluateParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+    pub expression: String,
+    /// Run the expression in the VM, not only type-check it
+    #[serde(default)]
+    pub run: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Evaluation {
+    /// Type of the expression as far as it is known, `any` otherwise
+    pub type_name: String,
+    /// The value, if the expression was run
+    pub value: Option<String>,
+    pub errors: Vec<String>,
+}
+
+/// Statements visible at a position and parameters bound around it
+#[derive(Debug, Default)]
+struct Context {
+    /// Source of each statement, in order
+    sources: Vec<String>,
+    statements: Vec<Statement>,
+    parameters: Vec<FunctionParameter>,
+}
+
+pub struct EvaluationProvider {
+    document_manager: Arc<DocumentManager>,
+    workspace_manager: Arc<WorkspaceManager>,
+    /// Bytecode of the bindings run before expressions, which rarely change between requests
+    bytecode_cache: BytecodeCache,
+}
+
+impl EvaluationProvider {
+    pub fn with_managers(
+        document_manager: Arc<DocumentManager>,
+        workspace_manager: Arc<WorkspaceManager>,
+    ) -> Self {
+        Self {
+            document_manager,
+            workspace_manager,
+            bytecode_cache: BytecodeCache::new(),
+        }
+    }
+
+    pub async fn evaluate(&self, params: EvaluateParams) -> Result<Evaluation> {
+        self.evaluate_in_context(
+            &params.text_document.uri,
+            params.position,
+            &params.expression,
+            params.run,
+        )
+        .await
+    }
+
+    /// Type-check `expression` in the scope at `position` in the document, and run it if
+    /// `run` is set
+    pub async fn evaluate_in_context(
+        &self,
+        uri: &Url,
+        position: Position,
+        expression: &str,
+        run: bool,
+    ) -> Result<Evaluation> {
+        let text = self
+            .document_manager
+            .get_document_text(uri)
+            .await
+            .ok_or_else(|| anyhow!("Document {} is not open", uri))?;
+        let context = context_at(&text, position);
+
+        let parsed = match parse_expression(expression) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                return Ok(Evaluation {
+                    type_name: "any".to_string(),
+                    value: None,
+                    errors: vec![e],
+                })
+            }
+        };
+
+        let declarations = self.workspace_manager.extern_declarations();
+        let checked =
+            declarations.check_expression(&context.statements, &context.parameters, &parsed);
+        let mut evaluation = match checked {
+            Ok(value_type) => Evaluation {
+                type_name: externs::type_name(&value_type),
+                ..Evaluation::default()
+            },
+            Err(NagariError::TypeError(errors)) => Evaluation {
+                type_name: "any".to_string(),
+                value: None,
+                errors: errors.split("\n  ").map(str::to_string).collect(),
+            },
+            Err(e) => Evaluation {
+                type_name: "any".to_string(),
+                value: None,
+                errors: vec![e.to_string()],
+            },
+        };
+
+        if run && evaluation.errors.is_empty() {
+            match run_in_vm(&context, expression, &self.bytecode_cache).await {
+                Ok(value) => evaluation.value = Some(value),
+                Err(e) => evaluation.errors.push(e),
+            }
+        }
+        Ok(evaluation)
+    }
+}
+
+fn parse_expression(expression: &str) -> Result<Expression, String> {
+    let program = nagari_parser::parse(expression).map_err(|e| e.to_string())?;
+    match <[Statement; 1]>::try_from(program.statements) {
+        Ok([Statement::Expression(expression)])
+            if !matches!(expression, Expression::Assignment { .. }) =>
+        {
+            Ok(expression)
+        }
+        _ => Err(format!("`{}` is not an expression", expression.trim())),
+    }
+}
+
+/// Run the bindings visible in `context`, then `expression`, returning its value
+async fn run_in_vm(
+    context: &Context,
+    expression: &str,
+    cache: &BytecodeCache,
+) -> Result<String, String> {
+    let compiler = Compiler::new();
+    let mut vm = nagari_vm::VM::new(false);
+    vm.set_bytecode_cache(Some(cache.clone()));
+    for (source, statement) in context.sources.iter().zip(&context.statements) {
+        let is_binding = matches!(
+            statement,
+            Statement::Let { .. }
+                | Statement::Const { .. }
+                | Statement::Expression(Expression::Assignment { .. })
+        );
+        // A binding that fails leaves its name undefined, which only matters if it is used
+        if is_binding {
+            let _ = run_source(&compiler, &mut vm, source).await;
+        }
+    }
+
+    run_source(
+        &compiler,
+        &mut vm,
+        &format!("{RESULT} = ({})", expression.trim()),
+    )
+    .await?;
+    vm.get_global(RESULT)
+        .map(|value| value.to_string())
+        .ok_or_else(|| "The expression has no value".to_string())
+}
+
+async fn run_source(
+    compiler: &Compiler,
+    vm: &mut nagari_vm::VM,
+    source: &str,
+) -> Result<(), String> {
+    vm.load_source(source, nagari_compiler::VERSION, |source| {
+        let result = compiler
+            .compile_string(source, None)
+            .map_err(|e| e.to_string())?;
+        bytecode::generate(&result.ast).map_err(|e| e.to_string())
+    })?;
+    vm.run().await
+}
+
+/// The statements visible at `position` in `text`, and the parameters bound around it
+fn context_at(text: &str, position: Position) -> Context {
+    let lines: Vec<&str> = text.lines().collect();
+    let line = (position.line as usize).min(lines.len());
+    // Only lines indented no deeper than this are in the blocks enclosing the position
+    let mut limit = match lines.get(line) {
+        Some(text) if !text.trim().is_empty() => indentation(text),
+        _ => position.character as usize,
+    };
+
+    let mut visible = Vec::new();
+    let mut parameters = Vec::new();
+    for text in lines[..line].iter().rev() {
+        let trimmed = text.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = indentation(text);
+        if indent > limit {
+            // Inside a block that ended before the position
+            continue;
+        }
+        if trimmed.ends_with(':') {
+            if indent < limit {
+                // Parameters of inner blocks go last so they shadow outer ones
+                parameters.splice(0..0, header_parameters(trimmed));
+                limit = indent;
+            }
+            continue;
+        }
+        limit = indent;
+        visible.push(trimmed);
+    }
+
+    let mut context = Context {
+        parameters,
+        ..Context::default()
+    };
+    for source in visible.into_iter().rev() {
+        // Lines that are only part of a statement don't parse and are left out
+        if let Ok(program) = nagari_parser::parse(source) {
+            for statement in program.statements {
+                context.sources.push(source.to_string());
+                context.statements.push(statement);
+            }
+        }
+    }
+    context
+}
+
+/// Names bound by a block header: a function's parameters or a loop variable
+fn header_parameters(header: &str) -> Vec<FunctionParameter> {
+    if header.starts_with("def ") || header.starts_with("async def ") {
+        let function = nagari_parser::parse(&format!("{header}\n    pass\n"));
+        return match function.map(|program| program.statements) {
+            Ok(statements) => match statements.into_iter().next() {
+                Some(Statement::Function { parameters, .. }) => parameters,
+                _ => Vec::new(),
+            },
+            Err(_) => Vec::new(),
+        };
+    }
+
+    if let Some((variable, _)) = header
+        .strip_prefix("for ")
+        .and_then(|rest| rest.split_once(" in "))
+    {
+        return vec![FunctionParameter {
+            name: variable.trim().to_string(),
+            type_annotation: None,
+            default_value: None,
+            kind: ParameterKind::Positional,
+        }];
+    }
+    Vec::new()
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
@@ -390,9 +390,20 @@ impl NagariFormatter {
                 body,
                 is_async,
                 return_type,
+                decorators,
             } => {
                 let indent = self.get_current_indent();
-                let mut result = indent.clone();
+                let mut result = String::new();
+
+                for decorator in decorators {
+                    result.push_str(&format!("{}@{}", indent, decorator.name));
+                    if let Some(arguments) = &decorator.arguments {
+                        let arguments = self.format_arguments(arguments, &decorator.keywords);
+                        result.push_str(&format!("({})", arguments));
+                    }
+                    result.push('\n');
+                }
+                result.push_str(&indent);
 
                 if *is_async {
                     result.push_str("async ");
@@ -414,6 +425,7 @@ impl NagariFormatter {
                             ","
                         });
                     }
+                    result.push_str(param.kind.prefix());
                     result.push_str(&param.name);
                     if let Some(type_ann) = &param.type_annotation {
                         result.push_str(&format!(": {}", type_ann));
@@ -519,9 +531,11 @@ impl NagariFormatter {
                 variable,
                 iterable,
                 body,
+                is_async,
             } => {
                 let mut result = format!(
-                    "for {} in {} {{",
+                    "{}for {} in {} {{",
+                    if *is_async { "async " } else { "" },
                     variable,
                     self.format_expression_inline(iterable)
                 );
@@ -531,10 +545,58 @@ impl NagariFormatter {
                 result.push_str("\n}");
                 result
             }
+            nagari_parser::Statement::With {
+                items,
+                body,
+                is_async,
+            } => {
+                let items: Vec<String> = items
+                    .iter()
+                    .map(|item| {
+                        let context = self.format_expression_inline(&item.context);
+                        match &item.alias {
+                            Some(alias) => format!("{context} as {alias}"),
+                            None => context,
+                        }
+                    })
+                    .collect();
+                let keyword = if *is_async { "async with" } else { "with" };
+                let mut result = format!("{keyword} {} {{", items.join(", "));
+                for stmt in body {
+                    result.push_str(&format!("\n    {}", self.format_statement_inline(stmt)));
+                }
+                result.push_str("\n}");
+                result
+            }
             _ => "/* complex statement */".to_string(),
         }
     }
 
+    /// Positional arguments followed by `name=value` and `**mapping` ones, without parentheses
+    fn format_arguments(
+        &self,
+        arguments: &[nagari_parser::Expression],
+        keywords: &[nagari_parser::KeywordArgument],
+    ) -> String {
+        let keywords = keywords.iter().map(|keyword| {
+            let value = self.format_expression_inline(&keyword.value);
+            match &keyword.name {
+                Some(name) => format!("{}={}", name, value),
+                None => format!("**{}", value),
+            }
+        });
+        arguments
+            .iter()
+            .map(|arg| self.format_expression_inline(arg))
+            .chain(keywords)
+            .collect::<Vec<_>>()
+            .join(if self.options.space_after_comma {
+                ", "
+            } else {
+                ","
+            })
+    }
+
     fn format_expression_inline(&self, expression: &nagari_parser::Expression) -> String {
         match expression {
             nagari_parser::Expression::Literal(literal) => self.format_literal(literal),
@@ -559,20 +621,16 @@ impl NagariFormatter {
                 let op_str = self.format_unary_operator(operator);
                 format!("{}{}", op_str, operand_str)
             }
+            nagari_parser::Expression::Await(argument) => {
+                format!("await {}", self.format_expression_inline(argument))
+            }
             nagari_parser::Expression::Call {
                 function,
                 arguments,
+                keywords,
             } => {
                 let func_str = self.format_expression_inline(function);
-                let args_str = arguments
-                    .iter()
-                    .map(|arg| self.format_expression_inline(arg))
-                    .collect::<Vec<_>>()
-                    .join(if self.options.space_after_comma {
-                        ", "
-                    } else {
-                        ","
-                    });
+                let args_str = self.format_arguments(arguments, keywords);
                 format!("{}({})", func_str, args_str)
             }
             nagari_parser::Expression::Member {
@@ -599,6 +657,18 @@ impl NagariFormatter {
                     });
                 format!("[{}]", elements_str)
             }
+            nagari_parser::Expression::Set(elements) => {
+                let elements_str = elements
+                    .iter()
+                    .map(|elem| self.format_expression_inline(elem))
+                    .collect::<Vec<_>>()
+                    .join(if self.options.space_after_comma {
+                        ", "
+                    } else {
+                        ","
+                    });
+                format!("{{{}}}", elements_str)
+            }
             nagari_parser::Expression::Object(properties) => {
                 let props_str = properties
                     .iter()
@@ -303,7 +303,8 @@ impl GotoProvider {
                 None
             }
             nagari_parser::Statement::While { body, .. }
-            | nagari_parser::Statement::For { body, .. } => {
+            | nagari_parser::Statement::For { body, .. }
+            | nagari_parser::Statement::With { body, .. } => {
                 for stmt in body {
                     if let Some(location) =
                         self.check_statement_for_definition(stmt, symbol_name, uri, line_hint + 1)
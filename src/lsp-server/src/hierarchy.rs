@@ -0,0 +1,800 @@
+//! Call and type hierarchies.
+//!
+//! Each file is reduced to the functions, methods and classes it defines, the calls made
+//! from inside them and the superclasses each class names. The workspace index keeps one
+//! [`FileHierarchy`] per file; open documents are re-analyzed so edits show up immediately.
+//! Calls are matched to definitions by name, the same way references are.
+
+use crate::{document::DocumentManager, refactor::SourceMap, workspace::WorkspaceManager};
+use anyhow::Result;
+use nagari_parser::{Lexer, Token, TokenWithPosition};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tower_lsp::lsp_types::*;
+
+/// A function, method or class definition
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Class a method is defined in
+    pub container: Option<String>,
+    pub range: Range,
+    pub selection_range: Range,
+    /// Superclass names, for classes
+    pub bases: Vec<String>,
+}
+
+/// A call edge from a definition (or module-level code) to a callee name
+#[derive(Debug, Clone)]
+pub struct CallSite {
+    /// Index into [`FileHierarchy::definitions`]; `None` for module-level code
+    pub caller: Option<usize>,
+    pub callee: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileHierarchy {
+    pub definitions: Vec<Definition>,
+    pub calls: Vec<CallSite>,
+    /// Range of the whole file, used for module-level callers
+    pub module_range: Range,
+}
+
+/// How an open definition body ends
+#[derive(Debug, Clone, Copy)]
+enum Close {
+    /// Indented block, closed once the indentation level drops below this one
+    Dedent(usize),
+    /// Brace block, closed once the brace depth drops below this one
+    Brace(usize),
+}
+
+struct Scope {
+    definition: usize,
+    start: usize,
+    close: Close,
+}
+
+impl FileHierarchy {
+    /// Collect definitions, call edges and superclasses from `text`. Sources that do not lex
+    /// produce an empty hierarchy.
+    pub fn analyze(text: &str) -> Self {
+        let source = SourceMap::new(text);
+        let module_range = source.range(0, text.len());
+        let Ok(tokens) = Lexer::new(text).tokenize() else {
+            return Self {
+                module_range,
+                ..Self::default()
+            };
+        };
+
+        let mut hierarchy = Self {
+            module_range,
+            ..Self::default()
+        };
+        let mut scopes: Vec<Scope> = Vec::new();
+        // Definition whose header has been read but whose body has not started yet
+        let mut pending: Option<(usize, usize)> = None;
+        let (mut indent, mut braces, mut parens) = (0usize, 0usize, 0usize);
+
+        let close = |hierarchy: &mut Self, scope: Scope, end: usize| {
+            let end = text[..end.max(scope.start)].trim_end().len();
+            hierarchy.definitions[scope.definition].range = source.range(scope.start, end);
+        };
+
+        for (index, token) in tokens.iter().enumerate() {
+            match &token.token {
+                Token::Def | Token::Function | Token::Class => {
+                    let Some(name_token) = tokens.get(index + 1) else {
+                        continue;
+                    };
+                    let Token::Identifier(name) = &name_token.token else {
+                        continue;
+                    };
+
+                    let start = match index.checked_sub(1).map(|i| &tokens[i].token) {
+                        Some(Token::Async) => tokens[index - 1].offset,
+                        _ => token.offset,
+                    };
+                    let enclosing_class = scopes
+                        .last()
+                        .map(|scope| &hierarchy.definitions[scope.definition])
+                        .filter(|definition| definition.kind == SymbolKind::CLASS)
+                        .map(|definition| definition.name.clone());
+                    let (kind, container, bases) = if token.token == Token::Class {
+                        (SymbolKind::CLASS, None, superclasses(&tokens[index + 2..]))
+                    } else if enclosing_class.is_some() {
+                        (SymbolKind::METHOD, enclosing_class, Vec::new())
+                    } else {
+                        (SymbolKind::FUNCTION, None, Vec::new())
+                    };
+
+                    let selection_range =
+                        source.range(name_token.offset, name_token.offset + name.len());
+                    hierarchy.definitions.push(Definition {
+                        name: name.to_string(),
+                        kind,
+                        container,
+                        range: selection_range,
+                        selection_range,
+                        bases,
+                    });
+                    pending = Some((hierarchy.definitions.len() - 1, start));
+                }
+                Token::Identifier(name) => {
+                    let is_call = matches!(
+                        tokens.get(index + 1).map(|t| &t.token),
+                        Some(Token::LeftParen)
+                    );
+                    let is_definition = matches!(
+                        index.checked_sub(1).map(|i| &tokens[i].token),
+                        Some(Token::Def | Token::Function | Token::Class)
+                    );
+                    if is_call && !is_definition {
+                        hierarchy.calls.push(CallSite {
+                            caller: scopes.last().map(|scope| scope.definition),
+                            callee: name.to_string(),
+                            range: source.range(token.offset, token.offset + name.len()),
+                        });
+                    }
+                }
+                Token::LeftParen | Token::LeftBracket => parens += 1,
+                Token::RightParen | Token::RightBracket => parens = parens.saturating_sub(1),
+                Token::Indent => {
+                    indent += 1;
+                    if parens == 0 {
+                        if let Some((definition, start)) = pending.take() {
+                            scopes.push(Scope {
+                                definition,
+                                start,
+                                close: Close::Dedent(indent),
+                            });
+                        }
+                    }
+                }
+                Token::Dedent => {
+                    indent = indent.saturating_sub(1);
+                    while let Some(Scope {
+                        close: Close::Dedent(level),
+                        ..
+                    }) = scopes.last()
+                    {
+                        if *level <= indent {
+                            break;
+                        }
+                        let scope = scopes.pop().unwrap();
+                        close(&mut hierarchy, scope, token.offset);
+                    }
+                }
+                Token::LeftBrace => {
+                    braces += 1;
+                    if parens == 0 {
+                        if let Some((definition, start)) = pending.take() {
+                            scopes.push(Scope {
+                                definition,
+                                start,
+                                close: Close::Brace(braces),
+                            });
+                        }
+                    }
+                }
+                Token::RightBrace => {
+                    braces = braces.saturating_sub(1);
+                    while let Some(Scope {
+                        close: Close::Brace(level),
+                        ..
+                    }) = scopes.last()
+                    {
+                        if *level <= braces {
+                            break;
+                        }
+                        let scope = scopes.pop().unwrap();
+                        close(&mut hierarchy, scope, token.offset + 1);
+                    }
+                }
+                Token::Newline if parens == 0 => {
+                    // A header not ending in `:` has its body on the same line
+                    let after_colon = matches!(
+                        index.checked_sub(1).map(|i| &tokens[i].token),
+                        Some(Token::Colon)
+                    );
+                    if !after_colon {
+                        if let Some((definition, start)) = pending.take() {
+                            let scope = Scope {
+                                definition,
+                                start,
+                                close: Close::Dedent(indent),
+                            };
+                            close(&mut hierarchy, scope, token.offset);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((definition, start)) = pending {
+            scopes.push(Scope {
+                definition,
+                start,
+                close: Close::Dedent(indent),
+            });
+        }
+        while let Some(scope) = scopes.pop() {
+            close(&mut hierarchy, scope, text.len());
+        }
+
+        hierarchy
+    }
+
+    /// Definition whose name is at `position`
+    fn definition_at(&self, position: Position) -> Option<usize> {
+        self.definitions
+            .iter()
+            .position(|definition| contains(definition.selection_range, position))
+    }
+
+    /// Call site whose callee name is at `position`
+    fn call_at(&self, position: Position) -> Option<&CallSite> {
+        self.calls
+            .iter()
+            .find(|call| contains(call.range, position))
+    }
+
+    /// Definition an item returned by this server refers to
+    fn find_item(&self, name: &str, selection_range: Range) -> Option<usize> {
+        self.definitions.iter().position(|definition| {
+            definition.name == name && definition.selection_range == selection_range
+        })
+    }
+}
+
+/// Superclass names following a class name: `(Base, pkg.Other)` or `extends Base`
+fn superclasses(tokens: &[TokenWithPosition]) -> Vec<String> {
+    let mut bases = Vec::new();
+    match tokens.first().map(|t| &t.token) {
+        Some(Token::LeftParen) => {
+            let mut current = None;
+            for token in &tokens[1..] {
+                match &token.token {
+                    // Dotted names resolve by their last segment
+                    Token::Identifier(name) => current = Some(name.to_string()),
+                    Token::Dot => {}
+                    Token::Comma => bases.extend(current.take()),
+                    Token::RightParen => {
+                        bases.extend(current.take());
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        Some(Token::Identifier("extends")) => {
+            if let Some(Token::Identifier(name)) = tokens.get(1).map(|t| &t.token) {
+                bases.push(name.to_string());
+            }
+        }
+        _ => {}
+    }
+    bases
+}
+
+fn contains(range: Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
+
+fn call_item(uri: &Url, definition: &Definition) -> CallHierarchyItem {
+    CallHierarchyItem {
+        name: definition.name.clone(),
+        kind: definition.kind,
+        tags: None,
+        detail: definition.container.clone(),
+        uri: uri.clone(),
+        range: definition.range,
+        selection_range: definition.selection_range,
+        data: None,
+    }
+}
+
+/// Item standing in for module-level code that makes calls
+fn module_item(uri: &Url, hierarchy: &FileHierarchy) -> CallHierarchyItem {
+    let name = uri
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default()
+        .to_string();
+    CallHierarchyItem {
+        name,
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: hierarchy.module_range,
+        selection_range: Range::new(hierarchy.module_range.start, hierarchy.module_range.start),
+        data: None,
+    }
+}
+
+fn type_item(uri: &Url, definition: &Definition) -> TypeHierarchyItem {
+    TypeHierarchyItem {
+        name: definition.name.clone(),
+        kind: definition.kind,
+        tags: None,
+        detail: None,
+        uri: uri.clone(),
+        range: definition.range,
+        selection_range: definition.selection_range,
+        data: None,
+    }
+}
+
+pub struct HierarchyProvider {
+    document_manager: Arc<DocumentManager>,
+    workspace_manager: Arc<WorkspaceManager>,
+}
+
+impl HierarchyProvider {
+    pub fn with_managers(
+        document_manager: Arc<DocumentManager>,
+        workspace_manager: Arc<WorkspaceManager>,
+    ) -> Self {
+        Self {
+            document_manager,
+            workspace_manager,
+        }
+    }
+
+    /// Indexed hierarchies, with open documents re-analyzed from their current text
+    async fn hierarchies(&self) -> HashMap<Url, Arc<FileHierarchy>> {
+        let mut hierarchies: HashMap<Url, Arc<FileHierarchy>> = self
+            .workspace_manager
+            .file_hierarchies()
+            .into_iter()
+            .collect();
+        for uri in self.document_manager.list_documents().await {
+            if let Some(text) = self.document_manager.get_document_text(&uri).await {
+                hierarchies.insert(uri, Arc::new(FileHierarchy::analyze(&text)));
+            }
+        }
+        hierarchies
+    }
+
+    /// Definitions named `name`, preferring ones in `uri`
+    fn resolve<'h>(
+        hierarchies: &'h HashMap<Url, Arc<FileHierarchy>>,
+        uri: &Url,
+        name: &str,
+        kind: Option<SymbolKind>,
+    ) -> Option<(&'h Url, &'h Definition)> {
+        let matches = |definition: &&Definition| {
+            definition.name == name && kind.is_none_or(|kind| definition.kind == kind)
+        };
+        let local = hierarchies.get_key_value(uri).and_then(|(uri, hierarchy)| {
+            hierarchy
+                .definitions
+                .iter()
+                .find(matches)
+                .map(|definition| (uri, definition))
+        });
+        local.or_else(|| {
+            let mut candidates: Vec<_> = hierarchies
+                .iter()
+                .filter_map(|(uri, hierarchy)| {
+                    hierarchy
+                        .definitions
+                        .iter()
+                        .find(matches)
+                        .map(|definition| (uri, definition))
+                })
+                .collect();
+            // Deterministic choice when several files define the name
+            candidates.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+            candidates.into_iter().next()
+        })
+    }
+
+    pub async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let hierarchies = self.hierarchies().await;
+        let Some(hierarchy) = hierarchies.get(uri) else {
+            return Ok(None);
+        };
+
+        if let Some(index) = hierarchy.definition_at(position) {
+            return Ok(Some(vec![call_item(uri, &hierarchy.definitions[index])]));
+        }
+        if let Some(call) = hierarchy.call_at(position) {
+            if let Some((uri, definition)) = Self::resolve(&hierarchies, uri, &call.callee, None) {
+                return Ok(Some(vec![call_item(uri, definition)]));
+            }
+        }
+        Ok(None)
+    }
+
+    pub async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let name = &params.item.name;
+        let hierarchies = self.hierarchies().await;
+
+        let mut uris: Vec<&Url> = hierarchies.keys().collect();
+        uris.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        let mut incoming = Vec::new();
+        for uri in uris {
+            let hierarchy = &hierarchies[uri];
+            let mut by_caller: Vec<(Option<usize>, Vec<Range>)> = Vec::new();
+            for call in hierarchy.calls.iter().filter(|call| &call.callee == name) {
+                match by_caller
+                    .iter_mut()
+                    .find(|(caller, _)| *caller == call.caller)
+                {
+                    Some((_, ranges)) => ranges.push(call.range),
+                    None => by_caller.push((call.caller, vec![call.range])),
+                }
+            }
+            for (caller, from_ranges) in by_caller {
+                let from = match caller {
+                    Some(index) => call_item(uri, &hierarchy.definitions[index]),
+                    None => module_item(uri, hierarchy),
+                };
+                incoming.push(CallHierarchyIncomingCall { from, from_ranges });
+            }
+        }
+
+        Ok(Some(incoming))
+    }
+
+    pub async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let item = &params.item;
+        let hierarchies = self.hierarchies().await;
+        let Some(hierarchy) = hierarchies.get(&item.uri) else {
+            return Ok(None);
+        };
+        let caller = hierarchy.find_item(&item.name, item.selection_range);
+        if caller.is_none() && item.kind != SymbolKind::FILE {
+            return Ok(None);
+        }
+
+        let mut outgoing: Vec<CallHierarchyOutgoingCall> = Vec::new();
+        for call in hierarchy.calls.iter().filter(|call| call.caller == caller) {
+            let Some((uri, definition)) =
+                Self::resolve(&hierarchies, &item.uri, &call.callee, None)
+            else {
+                continue;
+            };
+            let to = call_item(uri, definition);
+            match outgoing
+                .iter_mut()
+                .find(|existing| existing.to.uri == to.uri && existing.to.range == to.range)
+            {
+                Some(existing) => existing.from_ranges.push(call.range),
+                None => outgoing.push(CallHierarchyOutgoingCall {
+                    to,
+                    from_ranges: vec![call.range],
+                }),
+            }
+        }
+
+        Ok(Some(outgoing))
+    }
+
+    pub async fn prepare_type_hierarchy(
+        &self,
+        params: TypeHierarchyPrepareParams,
+    ) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let hierarchies = self.hierarchies().await;
+        let Some(hierarchy) = hierarchies.get(uri) else {
+            return Ok(None);
+        };
+
+        if let Some(index) = hierarchy.definition_at(position) {
+            let definition = &hierarchy.definitions[index];
+            if definition.kind == SymbolKind::CLASS {
+                return Ok(Some(vec![type_item(uri, definition)]));
+            }
+            return Ok(None);
+        }
+
+        // A class name used elsewhere, e.g. in a constructor call
+        let Some(name) = self
+            .document_manager
+            .get_text_at_position(uri, position)
+            .await
+        else {
+            return Ok(None);
+        };
+        Ok(
+            Self::resolve(&hierarchies, uri, &name, Some(SymbolKind::CLASS))
+                .map(|(uri, definition)| vec![type_item(uri, definition)]),
+        )
+    }
+
+    pub async fn supertypes(
+        &self,
+        params: TypeHierarchySupertypesParams,
+    ) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        let item = &params.item;
+        let hierarchies = self.hierarchies().await;
+        let Some(hierarchy) = hierarchies.get(&item.uri) else {
+            return Ok(None);
+        };
+        let Some(index) = hierarchy.find_item(&item.name, item.selection_range) else {
+            return Ok(None);
+        };
+
+        let supertypes = hierarchy.definitions[index]
+            .bases
+            .iter()
+            .filter_map(|base| {
+                Self::resolve(&hierarchies, &item.uri, base, Some(SymbolKind::CLASS))
+            })
+            .map(|(uri, definition)| type_item(uri, definition))
+            .collect();
+        Ok(Some(supertypes))
+    }
+
+    pub async fn subtypes(
+        &self,
+        params: TypeHierarchySubtypesParams,
+    ) -> Result<Option<Vec<TypeHierarchyItem>>> {
+        let name = &params.item.name;
+        let hierarchies = self.hierarchies().await;
+
+        let mut subtypes: Vec<TypeHierarchyItem> = hierarchies
+            .iter()
+            .flat_map(|(uri, hierarchy)| {
+                hierarchy
+                    .definitions
+                    .iter()
+                    .filter(|definition| definition.bases.contains(name))
+                    .map(move |definition| type_item(uri, definition))
+            })
+            .collect();
+        subtypes.sort_by(|a, b| {
+            a.uri
+                .as_str()
+                .cmp(b.uri.as_str())
+                .then(a.range.start.cmp(&b.range.start))
+        });
+        Ok(Some(subtypes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &str = "def helper(x):\n    return x + 1\n\ndef main():\n    helper(1)\n    helper(2)\n    report()\n\nhelper(3)\n";
+    const ANIMALS: &str = "class Animal:\n    def speak(self):\n        pass\n\nclass Dog(Animal):\n    def speak(self):\n        helper(0)\n\nclass Puppy(Dog):\n    pass\n\nclass Cat(pets.Animal):\n    pass\n\ndef report():\n    pass\n";
+
+    fn uri(name: &str) -> Url {
+        Url::parse(&format!("file:///project/{name}")).unwrap()
+    }
+
+    fn at(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    /// A provider with `app.nag` and `animals.nag` open
+    async fn provider() -> HierarchyProvider {
+        let documents = Arc::new(DocumentManager::new());
+        documents
+            .open_document(uri("app.nag"), APP.to_string(), 1)
+            .await;
+        documents
+            .open_document(uri("animals.nag"), ANIMALS.to_string(), 1)
+            .await;
+        HierarchyProvider::with_managers(documents, Arc::new(WorkspaceManager::new()))
+    }
+
+    fn position_params(name: &str, position: Position) -> TextDocumentPositionParams {
+        TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri(name) },
+            position,
+        }
+    }
+
+    async fn call_item_at(
+        provider: &HierarchyProvider,
+        name: &str,
+        position: Position,
+    ) -> CallHierarchyItem {
+        let params = CallHierarchyPrepareParams {
+            text_document_position_params: position_params(name, position),
+            work_done_progress_params: Default::default(),
+        };
+        let mut items = provider
+            .prepare_call_hierarchy(params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        items.remove(0)
+    }
+
+    async fn type_item_at(
+        provider: &HierarchyProvider,
+        name: &str,
+        position: Position,
+    ) -> TypeHierarchyItem {
+        let params = TypeHierarchyPrepareParams {
+            text_document_position_params: position_params(name, position),
+            work_done_progress_params: Default::default(),
+        };
+        let mut items = provider
+            .prepare_type_hierarchy(params)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        items.remove(0)
+    }
+
+    /// Start lines of `ranges`
+    fn lines(ranges: &[Range]) -> Vec<u32> {
+        ranges.iter().map(|range| range.start.line).collect()
+    }
+
+    #[test]
+    fn test_analyze_finds_definitions_calls_and_bases() {
+        let hierarchy = FileHierarchy::analyze(ANIMALS);
+        let definitions: Vec<_> = hierarchy
+            .definitions
+            .iter()
+            .map(|d| {
+                (
+                    d.name.as_str(),
+                    d.kind,
+                    d.container.as_deref(),
+                    d.bases.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            definitions,
+            vec![
+                ("Animal", SymbolKind::CLASS, None, vec![]),
+                ("speak", SymbolKind::METHOD, Some("Animal"), vec![]),
+                ("Dog", SymbolKind::CLASS, None, vec!["Animal".to_string()]),
+                ("speak", SymbolKind::METHOD, Some("Dog"), vec![]),
+                ("Puppy", SymbolKind::CLASS, None, vec!["Dog".to_string()]),
+                ("Cat", SymbolKind::CLASS, None, vec!["Animal".to_string()]),
+                ("report", SymbolKind::FUNCTION, None, vec![]),
+            ]
+        );
+        let calls: Vec<_> = hierarchy
+            .calls
+            .iter()
+            .map(|call| (call.caller, call.callee.as_str(), call.range.start))
+            .collect();
+        assert_eq!(calls, vec![(Some(3), "helper", at(6, 8))]);
+    }
+
+    #[tokio::test]
+    async fn test_incoming_calls_come_from_functions_methods_and_modules() {
+        let provider = provider().await;
+        let helper = call_item_at(&provider, "app.nag", at(0, 5)).await;
+        assert_eq!(
+            (helper.name.as_str(), helper.kind),
+            ("helper", SymbolKind::FUNCTION)
+        );
+        // Preparing at a call finds the definition it calls
+        let called = call_item_at(&provider, "app.nag", at(4, 6)).await;
+        assert_eq!(called.selection_range, helper.selection_range);
+
+        let params = CallHierarchyIncomingCallsParams {
+            item: helper,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let incoming = provider.incoming_calls(params).await.unwrap().unwrap();
+        let callers: Vec<_> = incoming
+            .iter()
+            .map(|call| {
+                (
+                    call.from.name.as_str(),
+                    call.from.kind,
+                    call.from.uri.clone(),
+                    lines(&call.from_ranges),
+                )
+            })
+            .collect();
+        assert_eq!(
+            callers,
+            vec![
+                ("speak", SymbolKind::METHOD, uri("animals.nag"), vec![6]),
+                ("main", SymbolKind::FUNCTION, uri("app.nag"), vec![4, 5]),
+                ("app.nag", SymbolKind::FILE, uri("app.nag"), vec![8]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_outgoing_calls_resolve_across_files() {
+        let provider = provider().await;
+        let main = call_item_at(&provider, "app.nag", at(3, 5)).await;
+        let params = CallHierarchyOutgoingCallsParams {
+            item: main,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let outgoing = provider.outgoing_calls(params).await.unwrap().unwrap();
+        let callees: Vec<_> = outgoing
+            .iter()
+            .map(|call| {
+                (
+                    call.to.name.as_str(),
+                    call.to.uri.clone(),
+                    lines(&call.from_ranges),
+                )
+            })
+            .collect();
+        assert_eq!(
+            callees,
+            vec![
+                ("helper", uri("app.nag"), vec![4, 5]),
+                ("report", uri("animals.nag"), vec![6]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_supertypes_and_subtypes() {
+        let provider = provider().await;
+        let dog = type_item_at(&provider, "animals.nag", at(4, 7)).await;
+        assert_eq!((dog.name.as_str(), dog.kind), ("Dog", SymbolKind::CLASS));
+
+        let params = TypeHierarchySupertypesParams {
+            item: dog.clone(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let supertypes = provider.supertypes(params).await.unwrap().unwrap();
+        let names: Vec<_> = supertypes.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["Animal"]);
+
+        let animal = supertypes[0].clone();
+        let params = TypeHierarchySubtypesParams {
+            item: animal,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let subtypes = provider.subtypes(params).await.unwrap().unwrap();
+        let names: Vec<_> = subtypes.iter().map(|item| item.name.as_str()).collect();
+        // Dotted bases count by their last segment
+        assert_eq!(names, vec!["Dog", "Cat"]);
+
+        let params = TypeHierarchySubtypesParams {
+            item: dog,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let subtypes = provider.subtypes(params).await.unwrap().unwrap();
+        let names: Vec<_> = subtypes.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["Puppy"]);
+
+        // Functions have no type hierarchy
+        let params = TypeHierarchyPrepareParams {
+            text_document_position_params: position_params("animals.nag", at(15, 5)),
+            work_done_progress_params: Default::default(),
+        };
+        assert!(provider
+            .prepare_type_hierarchy(params)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
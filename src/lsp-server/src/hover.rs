@@ -244,9 +244,9 @@ impl HoverProvider {
                     .iter()
                     .map(|p| {
                         if let Some(type_ann) = &p.type_annotation {
-                            format!("{}: {}", p.name, type_ann)
+                            format!("{}{}: {}", p.kind.prefix(), p.name, type_ann)
                         } else {
-                            p.name.clone()
+                            format!("{}{}", p.kind.prefix(), p.name)
                         }
                     })
                     .collect::<Vec<_>>()
@@ -269,7 +269,7 @@ impl HoverProvider {
                 Some(SymbolInfo {
                     name: name.clone(),
                     kind: SymbolKind::FUNCTION,
-                    type_info: return_type.clone(),
+                    type_info: return_type.as_ref().map(ToString::to_string),
                     description: format!("Function {}", name),
                     signature: Some(signature),
                     documentation: None,
@@ -316,6 +316,7 @@ impl HoverProvider {
                 name,
                 superclass,
                 methods,
+                ..
             } if name == symbol_name => {
                 let extends_str = if let Some(super_name) = superclass {
                     format!(" extends {}", super_name)
@@ -394,7 +395,8 @@ impl HoverProvider {
                 None
             }
             nagari_parser::Statement::While { body, .. }
-            | nagari_parser::Statement::For { body, .. } => {
+            | nagari_parser::Statement::For { body, .. }
+            | nagari_parser::Statement::With { body, .. } => {
                 for stmt in body {
                     if let Some(symbol_info) = self.extract_symbol_from_statement(stmt, symbol_name)
                     {
@@ -416,6 +418,7 @@ impl HoverProvider {
                 nagari_parser::Literal::Null => "null".to_string(),
             },
             nagari_parser::Expression::Array(_) => "array".to_string(),
+            nagari_parser::Expression::Set(_) => "set".to_string(),
             nagari_parser::Expression::Object(_) => "object".to_string(),
             nagari_parser::Expression::Function { .. } => "function".to_string(),
             nagari_parser::Expression::Call { .. } => "unknown".to_string(),
@@ -283,7 +283,8 @@ impl InlayHintsProvider {
                     }
                 }
                 nagari_parser::Statement::While { body, .. }
-                | nagari_parser::Statement::For { body, .. } => {
+                | nagari_parser::Statement::For { body, .. }
+                | nagari_parser::Statement::With { body, .. } => {
                     for stmt in body {
                         self.process_statement_for_hints(stmt, lines, range, hints)
                             .await?;
@@ -308,6 +309,7 @@ impl InlayHintsProvider {
                 nagari_parser::Expression::Call {
                     function,
                     arguments,
+                    ..
                 } => {
                     if self.config.show_parameter_names {
                         self.add_call_parameter_hints(function, arguments, lines, range, hints)
@@ -523,6 +525,7 @@ impl InlayHintsProvider {
                         format!("{}[]", element_type)
                     }
                 }
+                nagari_parser::Expression::Set(_) => "Set".to_string(),
                 nagari_parser::Expression::Object(_) => "object".to_string(),
                 nagari_parser::Expression::Function { .. } => "function".to_string(),
                 nagari_parser::Expression::Call { function, .. } => {
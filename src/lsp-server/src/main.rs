@@ -6,7 +6,7 @@ use clap::Parser;
 use futures_util::{SinkExt, StreamExt};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_tungstenite::{accept_async, tungstenite::Message};
-use tower_lsp::{LspService, Server};
+use tower_lsp::{ClientSocket, LspService, Server};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod backend;
@@ -15,11 +15,14 @@ mod code_actions;
 mod completion;
 mod diagnostics;
 mod document;
+mod evaluate;
 mod formatting;
 mod goto;
+mod hierarchy;
 mod hover;
 mod inlay_hints;
 mod references;
+mod refactor;
 mod rename;
 mod semantic_tokens;
 mod symbols;
@@ -27,6 +30,13 @@ mod workspace;
 
 use backend::NagariLanguageServer;
 
+/// The language server along with its custom requests
+fn build_service() -> (LspService<NagariLanguageServer>, ClientSocket) {
+    LspService::build(NagariLanguageServer::new)
+        .custom_method(evaluate::EVALUATE_REQUEST, NagariLanguageServer::evaluate)
+        .finish()
+}
+
 // Helper function to extract LSP messages from accumulated data
 fn extract_lsp_message(data: &str) -> Option<(String, String)> {
     // Look for Content-Length header
@@ -65,7 +75,7 @@ async fn handle_websocket_connection(
     let (mut server_reader, server_writer) = tokio::io::duplex(8192);
 
     // Create the language server with the pipe I/O
-    let (service, socket) = LspService::new(NagariLanguageServer::new);
+    let (service, socket) = build_service();
 
     // Task to forward messages from WebSocket to LSP server
     let ws_to_server_task = tokio::spawn(async move {
@@ -342,7 +352,7 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Starting Nagari Language Server");
 
     // Create the language server
-    let (service, socket) = LspService::new(NagariLanguageServer::new);
+    let (service, socket) = build_service();
 
     // Start the server based on the communication method
     if let Some(port) = args.tcp {
@@ -0,0 +1,1106 @@
+//! AST-based refactorings offered as code actions.
+//!
+//! The parser AST carries no spans, so each refactoring parses the relevant text for its
+//! data flow and uses lexer token offsets to place edits. Every function returns `None` when
+//! the refactoring does not apply at all, and `Some(Err(reason))` when it applies but would
+//! change behaviour; the reason is shown on the disabled code action.
+
+use nagari_parser::{
+    ComprehensionGenerator, Expression, Lexer, Statement, Token, TokenWithPosition,
+};
+use std::collections::HashSet;
+use tower_lsp::lsp_types::*;
+
+pub type RefactorResult = Option<Result<Vec<TextEdit>, String>>;
+
+const EXTRACTED_FUNCTION_NAME: &str = "extracted";
+const DEFAULT_INDENT: &str = "    ";
+
+/// Line/offset bookkeeping for converting between LSP positions and byte offsets
+pub(crate) struct SourceMap<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { text, line_starts }
+    }
+
+    fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    fn line_start(&self, line: usize) -> usize {
+        self.line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.text.len())
+    }
+
+    /// Text of `line` without its line ending
+    fn line(&self, line: usize) -> &'a str {
+        let start = self.line_start(line);
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        self.text[start..end.max(start)].trim_end_matches('\r')
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset) - 1
+    }
+
+    pub(crate) fn position(&self, offset: usize) -> Position {
+        let line = self.line_of(offset);
+        let start = self.line_starts[line];
+        Position {
+            line: line as u32,
+            character: self.text[start..offset].encode_utf16().count() as u32,
+        }
+    }
+
+    pub(crate) fn offset(&self, position: Position) -> usize {
+        let start = self.line_start(position.line as usize);
+        let line = self.line(position.line as usize);
+        let mut units = 0;
+        for (index, ch) in line.char_indices() {
+            if units >= position.character as usize {
+                return start + index;
+            }
+            units += ch.len_utf16();
+        }
+        start + line.len()
+    }
+
+    pub(crate) fn range(&self, start: usize, end: usize) -> Range {
+        Range {
+            start: self.position(start),
+            end: self.position(end),
+        }
+    }
+
+    /// Range covering whole lines `first..=last`, including the final line ending
+    fn line_range(&self, first: usize, last: usize) -> Range {
+        let end = if last + 1 < self.line_count() {
+            Position {
+                line: last as u32 + 1,
+                character: 0,
+            }
+        } else {
+            self.position(self.text.len())
+        };
+        Range {
+            start: Position {
+                line: first as u32,
+                character: 0,
+            },
+            end,
+        }
+    }
+}
+
+fn indent_of(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("//")
+}
+
+fn tokenize(text: &str) -> Option<Vec<TokenWithPosition<'_>>> {
+    Lexer::new(text).tokenize().ok()
+}
+
+fn identifier<'t>(token: &'t TokenWithPosition) -> Option<&'t str> {
+    match &token.token {
+        Token::Identifier(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn is_statement_start(token: Option<&TokenWithPosition>) -> bool {
+    match token {
+        None => true,
+        Some(token) => matches!(
+            token.token,
+            Token::Newline | Token::Indent | Token::Dedent | Token::Let | Token::Const
+        ),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindingKind {
+    /// `x = ...`, `let x = ...`, `x: T = ...`
+    Declaration,
+    /// `x += ...` and friends
+    Update,
+    /// `for x in ...`
+    Loop,
+    /// `def f(x)`
+    Parameter,
+}
+
+#[derive(Debug, Clone)]
+struct Binding<'a> {
+    name: &'a str,
+    kind: BindingKind,
+    /// Index of the identifier token
+    index: usize,
+    offset: usize,
+}
+
+/// Every place a plain identifier is bound, in token order
+fn bindings<'a>(tokens: &[TokenWithPosition<'a>]) -> Vec<Binding<'a>> {
+    let mut bindings = Vec::new();
+    let mut param_depth: Option<usize> = None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|i| &tokens[i]);
+        let next = tokens.get(index + 1);
+
+        match (&token.token, param_depth) {
+            (Token::LeftParen, Some(depth)) => param_depth = Some(depth + 1),
+            (Token::RightParen, Some(1)) => param_depth = None,
+            (Token::RightParen, Some(depth)) => param_depth = Some(depth - 1),
+            (Token::LeftParen, None)
+                if index >= 2
+                    && matches!(tokens[index - 2].token, Token::Def | Token::Function) =>
+            {
+                param_depth = Some(1);
+            }
+            _ => {}
+        }
+
+        let Token::Identifier(name) = &token.token else {
+            continue;
+        };
+        let previous_token = previous.map(|t| &t.token);
+        let kind = if param_depth == Some(1)
+            && matches!(previous_token, Some(Token::LeftParen | Token::Comma))
+        {
+            Some(BindingKind::Parameter)
+        } else if matches!(previous_token, Some(Token::For)) {
+            Some(BindingKind::Loop)
+        } else if matches!(previous_token, Some(Token::Dot)) {
+            None
+        } else {
+            match next.map(|t| &t.token) {
+                Some(Token::Assign) => Some(BindingKind::Declaration),
+                Some(
+                    Token::PlusAssign
+                    | Token::MinusAssign
+                    | Token::MultiplyAssign
+                    | Token::DivideAssign,
+                ) => Some(BindingKind::Update),
+                Some(Token::Colon) if is_statement_start(previous) => tokens[index + 1..]
+                    .iter()
+                    .take_while(|t| !matches!(t.token, Token::Newline | Token::Eof))
+                    .any(|t| t.token == Token::Assign)
+                    .then_some(BindingKind::Declaration),
+                _ => None,
+            }
+        };
+
+        if let Some(kind) = kind {
+            bindings.push(Binding {
+                name,
+                kind,
+                index,
+                offset: token.offset,
+            });
+        }
+    }
+
+    bindings
+}
+
+/// Identifier tokens that read a variable (not property names after `.`)
+fn variable_uses<'a, 't>(
+    tokens: &'t [TokenWithPosition<'a>],
+) -> impl Iterator<Item = (usize, &'t TokenWithPosition<'a>)> {
+    tokens.iter().enumerate().filter(move |(index, token)| {
+        matches!(token.token, Token::Identifier(_))
+            && !(*index > 0 && tokens[index - 1].token == Token::Dot)
+    })
+}
+
+/// Names read and written by a list of statements, in first-use order
+#[derive(Debug, Default)]
+struct DataFlow {
+    /// Read before any write inside the analysed statements
+    reads: Vec<String>,
+    writes: Vec<String>,
+    /// Statements that first bind a name with `let`/`const`
+    declared: HashSet<String>,
+    has_return: bool,
+    loops: usize,
+}
+
+impl DataFlow {
+    fn read(&mut self, name: &str) {
+        if !self.writes.iter().any(|w| w == name) && !self.reads.iter().any(|r| r == name) {
+            self.reads.push(name.to_string());
+        }
+    }
+
+    fn write(&mut self, name: &str) {
+        if !self.writes.iter().any(|w| w == name) {
+            self.writes.push(name.to_string());
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { name, value } | Statement::Const { name, value } => {
+                self.expression(value);
+                self.write(name);
+            }
+            Statement::Expression(expression) => self.expression(expression),
+            Statement::Return(value) => {
+                self.has_return = true;
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                self.expression(condition);
+                self.statements(then_body);
+                if let Some(else_body) = else_body {
+                    self.statements(else_body);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.loops += 1;
+                self.expression(condition);
+                self.statements(body);
+            }
+            Statement::For {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                self.loops += 1;
+                self.expression(iterable);
+                self.write(variable);
+                self.statements(body);
+            }
+            Statement::With { items, body, .. } => {
+                for item in items {
+                    self.expression(&item.context);
+                    if let Some(alias) = &item.alias {
+                        self.write(alias);
+                    }
+                }
+                self.statements(body);
+            }
+            Statement::Match { subject, cases } => {
+                self.expression(subject);
+                for case in cases {
+                    for name in case.pattern.captures() {
+                        self.write(name);
+                    }
+                    if let Some(guard) = &case.guard {
+                        self.expression(guard);
+                    }
+                    self.statements(&case.body);
+                }
+            }
+            Statement::Function {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                self.write(name);
+                self.nested(parameters.iter().map(|p| p.name.as_str()), body);
+            }
+            Statement::Class { name, methods, .. } => {
+                self.write(name);
+                for method in methods {
+                    if let Statement::Function {
+                        parameters, body, ..
+                    } = method
+                    {
+                        self.nested(parameters.iter().map(|p| p.name.as_str()), body);
+                    }
+                }
+            }
+            Statement::ExportDeclaration { declaration } => self.statement(declaration),
+            Statement::Import { items, .. } => {
+                for item in items {
+                    self.write(item.alias.as_ref().unwrap_or(&item.name));
+                }
+            }
+            Statement::ExportNamed { .. } | Statement::ExportAll { .. } => {}
+        }
+    }
+
+    /// Free variables of a nested function are reads of the enclosing code
+    fn nested<'p>(&mut self, parameters: impl Iterator<Item = &'p str>, body: &[Statement]) {
+        let mut inner = DataFlow::default();
+        for parameter in parameters {
+            inner.write(parameter);
+        }
+        inner.statements(body);
+        for name in inner.reads {
+            self.read(&name);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Literal(_) => {}
+            // Any identifier-like word in raw JavaScript may refer to a Nagari binding
+            Expression::InlineJs(code) => {
+                for word in code
+                    .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+                    .filter(|word| word.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+                {
+                    self.read(word);
+                }
+            }
+            Expression::Identifier(name) => self.read(name),
+            Expression::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::Unary { operand, .. } | Expression::Await(operand) => {
+                self.expression(operand)
+            }
+            Expression::Call {
+                function,
+                arguments,
+                keywords,
+            } => {
+                self.expression(function);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                for keyword in keywords {
+                    self.expression(&keyword.value);
+                }
+            }
+            Expression::Member { object, .. } => self.expression(object),
+            Expression::Array(elements) | Expression::Set(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expression::Object(properties) => {
+                for property in properties {
+                    self.expression(&property.value);
+                }
+            }
+            Expression::Function {
+                parameters, body, ..
+            } => self.nested(parameters.iter().map(|p| p.name.as_str()), body),
+            Expression::Arrow {
+                parameters, body, ..
+            } => match body {
+                nagari_parser::ArrowFunctionBody::Expression(body) => {
+                    let body = [Statement::Return(Some((**body).clone()))];
+                    self.nested(parameters.iter().map(|p| p.name.as_str()), &body);
+                }
+                nagari_parser::ArrowFunctionBody::Block(body) => {
+                    self.nested(parameters.iter().map(|p| p.name.as_str()), body)
+                }
+            },
+            Expression::Assignment {
+                left,
+                operator,
+                right,
+            } => {
+                if let Expression::Identifier(name) = left.as_ref() {
+                    if *operator != nagari_parser::AssignmentOperator::Assign {
+                        self.read(name);
+                    }
+                    self.expression(right);
+                    self.write(name);
+                } else {
+                    self.expression(left);
+                    self.expression(right);
+                }
+            }
+            Expression::Conditional {
+                test,
+                consequent,
+                alternate,
+            } => {
+                self.expression(test);
+                self.expression(consequent);
+                self.expression(alternate);
+            }
+            Expression::TemplateLiteral { expressions, .. } => {
+                for expression in expressions {
+                    self.expression(expression);
+                }
+            }
+            Expression::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expression::Slice {
+                object,
+                start,
+                end,
+                step,
+            } => {
+                self.expression(object);
+                for bound in [start, end, step].into_iter().flatten() {
+                    self.expression(bound);
+                }
+            }
+            Expression::ListComprehension {
+                element,
+                generators,
+            }
+            | Expression::SetComprehension {
+                element,
+                generators,
+            }
+            | Expression::Generator {
+                element,
+                generators,
+            } => self.comprehension(generators, [element.as_ref()]),
+            Expression::DictComprehension {
+                key,
+                value,
+                generators,
+            } => self.comprehension(generators, [key.as_ref(), value.as_ref()]),
+        }
+    }
+
+    /// Like parameters, the targets of a comprehension are local to it
+    fn comprehension<'e>(
+        &mut self,
+        generators: &[ComprehensionGenerator],
+        results: impl IntoIterator<Item = &'e Expression>,
+    ) {
+        let mut inner = DataFlow::default();
+        for generator in generators {
+            inner.expression(&generator.iter);
+            inner.write(&generator.target);
+            for condition in &generator.conditions {
+                inner.expression(condition);
+            }
+        }
+        for result in results {
+            inner.expression(result);
+        }
+        for name in inner.reads {
+            self.read(&name);
+        }
+    }
+}
+
+/// First line of the nearest enclosing `def`, if the selection is inside one
+fn enclosing_function(source: &SourceMap, first: usize, indent: usize) -> Option<usize> {
+    (0..first).rev().find(|&line| {
+        let text = source.line(line);
+        let trimmed = text.trim_start();
+        !is_blank_or_comment(text)
+            && indent_of(text).len() < indent
+            && (trimmed.starts_with("def ")
+                || trimmed.starts_with("async def ")
+                || trimmed.starts_with("function ")
+                || trimmed.starts_with("async function "))
+    })
+}
+
+/// First line of the top-level statement containing `line`, above any leading comments
+fn top_level_start(source: &SourceMap, line: usize) -> usize {
+    let mut start = (0..=line)
+        .rev()
+        .find(|&l| {
+            let text = source.line(l);
+            !is_blank_or_comment(text) && indent_of(text).is_empty()
+        })
+        .unwrap_or(0);
+    while start > 0 {
+        let above = source.line(start - 1).trim();
+        if above.starts_with('#') || above.starts_with("//") || above.starts_with('@') {
+            start -= 1;
+        } else {
+            break;
+        }
+    }
+    start
+}
+
+/// End offset of the block that starts at `line` with the given indentation
+fn block_end(source: &SourceMap, line: usize, indent: usize) -> usize {
+    (line + 1..source.line_count())
+        .find(|&l| {
+            let text = source.line(l);
+            !is_blank_or_comment(text) && indent_of(text).len() < indent
+        })
+        .map_or(source.text.len(), |l| source.line_start(l))
+}
+
+fn unique_name(tokens: &[TokenWithPosition], base: &str) -> String {
+    let taken: HashSet<&str> = tokens.iter().filter_map(identifier).collect();
+    if !taken.contains(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}_{}", base, n))
+        .find(|name| !taken.contains(name.as_str()))
+        .unwrap()
+}
+
+/// Extract the statements on the selected lines into a new top-level function.
+///
+/// Variables bound before the selection and read inside it become parameters; a single
+/// variable assigned inside the selection and read after it becomes the return value.
+pub fn extract_function(text: &str, range: Range) -> RefactorResult {
+    let source = SourceMap::new(text);
+    let first = range.start.line as usize;
+    let mut last = range.end.line as usize;
+    if last > first && range.end.character == 0 {
+        last -= 1;
+    }
+    if range.start == range.end || last >= source.line_count() {
+        return None;
+    }
+
+    let lines: Vec<&str> = (first..=last).map(|l| source.line(l)).collect();
+    let first_code = lines.iter().position(|l| !is_blank_or_comment(l))?;
+    let base_indent = indent_of(lines[first_code]);
+    if lines
+        .iter()
+        .any(|l| !l.trim().is_empty() && !l.starts_with(base_indent))
+    {
+        return Some(Err("Selection is not a complete block of statements".into()));
+    }
+    if let Some(next) = (last + 1..source.line_count())
+        .map(|l| source.line(l))
+        .find(|l| !is_blank_or_comment(l))
+    {
+        if indent_of(next).len() > base_indent.len() {
+            return Some(Err("Selection ends inside a block".into()));
+        }
+    }
+
+    let body: Vec<&str> = lines
+        .iter()
+        .map(|l| l.strip_prefix(base_indent).unwrap_or(l.trim_start()))
+        .collect();
+    let body_text = body.join("\n") + "\n";
+    let Ok(program) = nagari_parser::parse(&body_text) else {
+        return Some(Err("Selection is not a complete block of statements".into()));
+    };
+    let Some(body_tokens) = tokenize(&body_text) else {
+        return Some(Err("Selection does not lex".into()));
+    };
+
+    let mut flow = DataFlow::default();
+    flow.statements(&program.statements);
+    if flow.has_return {
+        return Some(Err("Selection contains a return statement".into()));
+    }
+    if flow.loops == 0
+        && body_tokens
+            .iter()
+            .any(|t| matches!(t.token, Token::Break | Token::Continue))
+    {
+        return Some(Err("Selection contains break or continue".into()));
+    }
+    if body_tokens.iter().any(|t| t.token == Token::This) {
+        return Some(Err("Selection uses `this`".into()));
+    }
+    for line in &body {
+        let trimmed = line.trim_start();
+        for keyword in ["let ", "const "] {
+            if let Some(rest) = trimmed.strip_prefix(keyword) {
+                let name: String = rest
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                    .collect();
+                flow.declared.insert(name);
+            }
+        }
+    }
+
+    let Some(all_tokens) = tokenize(text) else {
+        return Some(Err("Document does not lex".into()));
+    };
+    let selection_start = source.line_start(first);
+    let selection_end = source.line_start(last + 1);
+
+    // Names in scope before the selection: the enclosing function's parameters and locals,
+    // or everything above the selection at module level.
+    let function_line = enclosing_function(&source, first, base_indent.len());
+    let scope_start = function_line.map_or(0, |l| source.line_start(l));
+    let bound_before: HashSet<&str> = bindings(&all_tokens)
+        .into_iter()
+        .filter(|b| b.offset >= scope_start && b.offset < selection_start)
+        .map(|b| b.name)
+        .collect();
+
+    let scope_end = match function_line {
+        Some(line) => block_end(&source, line, indent_of(source.line(line)).len() + 1),
+        None => text.len(),
+    };
+    let read_after: HashSet<&str> = variable_uses(&all_tokens)
+        .filter(|(_, t)| t.offset >= selection_end && t.offset < scope_end)
+        .filter_map(|(_, t)| identifier(t))
+        .collect();
+
+    let outputs: Vec<&String> = flow
+        .writes
+        .iter()
+        .filter(|name| read_after.contains(name.as_str()))
+        .collect();
+    if outputs.len() > 1 {
+        return Some(Err(format!(
+            "Selection assigns {} variables that are used afterwards",
+            outputs.len()
+        )));
+    }
+    let output = outputs.first().map(|name| name.as_str());
+
+    let mut parameters: Vec<&str> = flow
+        .reads
+        .iter()
+        .map(String::as_str)
+        .filter(|name| bound_before.contains(name))
+        .collect();
+    if let Some(output) = output {
+        // A conditional assignment must still see the incoming value
+        if bound_before.contains(output) && !parameters.contains(&output) {
+            parameters.push(output);
+        }
+    }
+
+    let name = unique_name(&all_tokens, EXTRACTED_FUNCTION_NAME);
+    let is_async = body_tokens.iter().any(|t| t.token == Token::Await);
+    let indent_unit = (0..first)
+        .rev()
+        .map(|l| source.line(l))
+        .find(|l| !is_blank_or_comment(l) && indent_of(l).len() < base_indent.len())
+        .map(|parent| &base_indent[indent_of(parent).len()..])
+        .filter(|unit| !unit.is_empty())
+        .unwrap_or(DEFAULT_INDENT);
+
+    let mut function = format!(
+        "{}def {}({}):\n",
+        if is_async { "async " } else { "" },
+        name,
+        parameters.join(", ")
+    );
+    for line in &body {
+        if !line.trim().is_empty() {
+            function.push_str(indent_unit);
+            function.push_str(line);
+        }
+        function.push('\n');
+    }
+    if let Some(output) = output {
+        function.push_str(&format!("{}return {}\n", indent_unit, output));
+    }
+    function.push('\n');
+
+    let call = format!(
+        "{}{}({})",
+        if is_async { "await " } else { "" },
+        name,
+        parameters.join(", ")
+    );
+    let call_line = match output {
+        Some(output) if flow.declared.contains(output) && !bound_before.contains(output) => {
+            let keyword = if body
+                .iter()
+                .any(|l| l.trim_start().starts_with(&format!("const {}", output)))
+            {
+                "const"
+            } else {
+                "let"
+            };
+            format!("{}{} {} = {}\n", base_indent, keyword, output, call)
+        }
+        Some(output) => format!("{}{} = {}\n", base_indent, output, call),
+        None => format!("{}{}\n", base_indent, call),
+    };
+
+    let insert_line = top_level_start(&source, function_line.unwrap_or(first)) as u32;
+    let insert_at = Position {
+        line: insert_line,
+        character: 0,
+    };
+    let mut replace = source.line_range(first, last);
+    if last + 1 >= source.line_count() && !text.ends_with('\n') {
+        replace.end = source.position(text.len());
+    }
+
+    Some(Ok(vec![
+        TextEdit {
+            range: Range {
+                start: insert_at,
+                end: insert_at,
+            },
+            new_text: function,
+        },
+        TextEdit {
+            range: replace,
+            new_text: call_line,
+        },
+    ]))
+}
+
+fn has_side_effects(expression: &Expression) -> bool {
+    match expression {
+        // Raw JavaScript may do anything
+        Expression::Call { .. }
+        | Expression::Assignment { .. }
+        | Expression::Await(_)
+        | Expression::InlineJs(_) => true,
+        Expression::Literal(_)
+        | Expression::Identifier(_)
+        | Expression::Function { .. }
+        | Expression::Arrow { .. } => false,
+        Expression::Binary { left, right, .. } => has_side_effects(left) || has_side_effects(right),
+        Expression::Unary { operand, .. } => has_side_effects(operand),
+        Expression::Member { object, .. } => has_side_effects(object),
+        Expression::Index { object, index } => has_side_effects(object) || has_side_effects(index),
+        Expression::Slice {
+            object,
+            start,
+            end,
+            step,
+        } => {
+            has_side_effects(object)
+                || [start, end, step]
+                    .into_iter()
+                    .flatten()
+                    .any(|bound| has_side_effects(bound))
+        }
+        Expression::Array(elements) | Expression::Set(elements) => {
+            elements.iter().any(has_side_effects)
+        }
+        Expression::Object(properties) => properties.iter().any(|p| has_side_effects(&p.value)),
+        Expression::Conditional {
+            test,
+            consequent,
+            alternate,
+        } => has_side_effects(test) || has_side_effects(consequent) || has_side_effects(alternate),
+        Expression::TemplateLiteral { expressions, .. } => expressions.iter().any(has_side_effects),
+        // Iterating may run arbitrary code
+        Expression::ListComprehension { .. }
+        | Expression::DictComprehension { .. }
+        | Expression::SetComprehension { .. }
+        | Expression::Generator { .. } => true,
+    }
+}
+
+fn needs_parentheses(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::Binary { .. }
+            | Expression::Unary { .. }
+            | Expression::Await(_)
+            | Expression::Conditional { .. }
+            | Expression::Assignment { .. }
+            | Expression::Arrow { .. }
+            | Expression::Function { .. }
+    )
+}
+
+/// Whether the token at `index` is a whole operand, so an inlined expression needs no parentheses
+fn stands_alone_at(tokens: &[TokenWithPosition], index: usize) -> bool {
+    let before = index.checked_sub(1).map(|i| &tokens[i].token);
+    let after = tokens.get(index + 1).map(|t| &t.token);
+    matches!(
+        before,
+        Some(
+            Token::LeftParen
+                | Token::LeftBracket
+                | Token::Comma
+                | Token::Assign
+                | Token::Return
+                | Token::Colon
+        )
+    ) && matches!(
+        after,
+        Some(
+            Token::RightParen
+                | Token::RightBracket
+                | Token::Comma
+                | Token::Newline
+                | Token::Eof
+                | Token::Dedent
+        )
+    )
+}
+
+/// Replace every use of the variable under the cursor with its initializer and remove the
+/// declaration.
+pub fn inline_variable(text: &str, position: Position) -> RefactorResult {
+    let source = SourceMap::new(text);
+    let cursor = source.offset(position);
+    let mut lexer = Lexer::new(text);
+    let tokens = lexer.tokenize().ok()?;
+
+    let name = tokens
+        .iter()
+        .filter_map(|t| identifier(t).map(|name| (t.offset, name)))
+        .find(|(offset, name)| *offset <= cursor && cursor <= offset + name.len())
+        .map(|(_, name)| name)?;
+
+    let all_bindings = bindings(&tokens);
+    let declaration = all_bindings
+        .iter()
+        .rfind(|b| b.name == name && b.offset <= cursor)?;
+    match declaration.kind {
+        BindingKind::Declaration => {}
+        BindingKind::Parameter => return Some(Err(format!("`{}` is a parameter", name))),
+        BindingKind::Loop => return Some(Err(format!("`{}` is a loop variable", name))),
+        BindingKind::Update => {
+            return Some(Err(format!("`{}` is reassigned", name)));
+        }
+    }
+
+    let declaration_line = source.line_of(declaration.offset);
+    let line_text = source.line(declaration_line);
+    let region_end = block_end(&source, declaration_line, indent_of(line_text).len());
+    if cursor >= region_end {
+        return None;
+    }
+    let first_token = tokens.iter().find(|t| {
+        source.line_of(t.offset) == declaration_line
+            && !matches!(t.token, Token::Indent | Token::Dedent)
+    })?;
+    if !matches!(
+        first_token.token,
+        Token::Let | Token::Const | Token::Identifier(_)
+    ) {
+        return Some(Err("Only simple declarations can be inlined".into()));
+    }
+
+    // Initializer: everything after `=` up to the end of the line or a trailing comment
+    let assign = tokens[declaration.index..]
+        .iter()
+        .find(|t| t.token == Token::Assign)?;
+    let line_end = source.line_start(declaration_line) + line_text.len();
+    let initializer_end = lexer
+        .comments()
+        .iter()
+        .map(|c| c.offset)
+        .find(|offset| *offset > assign.offset && *offset < line_end)
+        .unwrap_or(line_end);
+    let initializer = text[assign.offset + 1..initializer_end].trim();
+    let expression = match nagari_parser::parse(&format!("{}\n", initializer)) {
+        Ok(program) => match program.statements.as_slice() {
+            [Statement::Expression(expression)] => expression.clone(),
+            _ => return Some(Err("Initializer is not a single expression".into())),
+        },
+        Err(_) => return Some(Err("Initializer spans more than one line".into())),
+    };
+
+    if all_bindings
+        .iter()
+        .any(|b| b.name == name && b.offset > declaration.offset && b.offset < region_end)
+    {
+        return Some(Err(format!("`{}` is reassigned", name)));
+    }
+
+    let uses: Vec<usize> = variable_uses(&tokens)
+        .filter(|(index, t)| {
+            *index != declaration.index
+                && t.offset > declaration.offset
+                && t.offset < region_end
+                && identifier(t) == Some(name)
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if uses.is_empty() {
+        return Some(Err(format!("`{}` is never used", name)));
+    }
+    if uses.len() > 1 && has_side_effects(&expression) {
+        return Some(Err(
+            "Initializer has side effects and is used more than once".into(),
+        ));
+    }
+
+    let mut inputs = DataFlow::default();
+    inputs.expression(&expression);
+    let last_use = tokens[*uses.last().unwrap()].offset;
+    if let Some(changed) = all_bindings.iter().find(|b| {
+        b.offset > declaration.offset
+            && b.offset < last_use
+            && inputs.reads.iter().any(|r| r == b.name)
+    }) {
+        return Some(Err(format!(
+            "`{}` changes before `{}` is used",
+            changed.name, name
+        )));
+    }
+
+    let mut edits = vec![TextEdit {
+        range: source.line_range(declaration_line, declaration_line),
+        new_text: String::new(),
+    }];
+    for index in uses {
+        let token = &tokens[index];
+        let new_text = if needs_parentheses(&expression) && !stands_alone_at(&tokens, index) {
+            format!("({})", initializer)
+        } else {
+            initializer.to_string()
+        };
+        edits.push(TextEdit {
+            range: source.range(token.offset, token.offset + name.len()),
+            new_text,
+        });
+    }
+
+    Some(Ok(edits))
+}
+
+/// Merge, prune and sort the leading import block, using the formatter's import sorter.
+pub fn organize_imports(text: &str) -> Option<Vec<TextEdit>> {
+    let edit = nagari_parser::organize_imports_edit(text)?;
+    let source = SourceMap::new(text);
+    Some(vec![TextEdit {
+        range: source.range(edit.start, edit.end),
+        new_text: edit.text,
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `text` after `edits`, which must not overlap
+    fn apply(text: &str, mut edits: Vec<TextEdit>) -> String {
+        let source = SourceMap::new(text);
+        edits.sort_by_key(|edit| (edit.range.start.line, edit.range.start.character));
+        let mut result = text.to_string();
+        for edit in edits.iter().rev() {
+            let start = source.offset(edit.range.start);
+            let end = source.offset(edit.range.end);
+            result.replace_range(start..end, &edit.new_text);
+        }
+        result
+    }
+
+    fn lines(first: u32, last: u32) -> Range {
+        Range {
+            start: Position {
+                line: first,
+                character: 0,
+            },
+            end: Position {
+                line: last + 1,
+                character: 0,
+            },
+        }
+    }
+
+    fn at(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn test_extract_function_passes_captured_variables_and_returns_the_result() {
+        let text = "def main():\n    a = 1\n    b = 2\n    total = a + b\n    doubled = total * 2\n    print(doubled)\n";
+        let edits = extract_function(text, lines(3, 4)).unwrap().unwrap();
+        assert_eq!(
+            apply(text, edits),
+            "def extracted(a, b):\n    total = a + b\n    doubled = total * 2\n    return doubled\n\ndef main():\n    a = 1\n    b = 2\n    doubled = extracted(a, b)\n    print(doubled)\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_function_keeps_declarations_and_awaits() {
+        let text = "async def main(url):\n    let page = await fetch(url)\n    let size = len(page)\n    print(size)\n";
+        let edits = extract_function(text, lines(1, 2)).unwrap().unwrap();
+        assert_eq!(
+            apply(text, edits),
+            "async def extracted(url):\n    let page = await fetch(url)\n    let size = len(page)\n    return size\n\nasync def main(url):\n    let size = await extracted(url)\n    print(size)\n"
+        );
+
+        // At module level the function goes right above the selection, and doesn't shadow an
+        // existing one
+        let text = "def extracted():\n    pass\n\nx = 1\nprint(x)\n";
+        let edits = extract_function(text, lines(4, 4)).unwrap().unwrap();
+        assert_eq!(
+            apply(text, edits),
+            "def extracted():\n    pass\n\nx = 1\ndef extracted_2(x):\n    print(x)\n\nextracted_2(x)\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_function_refuses_selections_that_would_change_behaviour() {
+        let text = "def f(a):\n    b = a + 1\n    c = a * 2\n    if b:\n        return b\n    print(b, c)\n";
+        assert_eq!(
+            extract_function(text, lines(1, 2)).unwrap().unwrap_err(),
+            "Selection assigns 2 variables that are used afterwards"
+        );
+        assert_eq!(
+            extract_function(text, lines(3, 4)).unwrap().unwrap_err(),
+            "Selection contains a return statement"
+        );
+        assert_eq!(
+            extract_function(text, lines(3, 3)).unwrap().unwrap_err(),
+            "Selection ends inside a block"
+        );
+        assert_eq!(
+            extract_function(text, lines(4, 5)).unwrap().unwrap_err(),
+            "Selection is not a complete block of statements"
+        );
+    }
+
+    #[test]
+    fn test_inline_variable_replaces_every_use() {
+        let text = "def f(a, b):\n    sum = a + b\n    print(sum * 2)\n    return sum\n";
+        let edits = inline_variable(text, at(1, 5)).unwrap().unwrap();
+        assert_eq!(
+            apply(text, edits),
+            "def f(a, b):\n    print((a + b) * 2)\n    return a + b\n"
+        );
+    }
+
+    #[test]
+    fn test_inline_variable_refuses_reassigned_variables() {
+        let text = "x = 1\nprint(x)\nx = 2\nprint(x)\n";
+        assert_eq!(
+            inline_variable(text, at(1, 6)).unwrap().unwrap_err(),
+            "`x` is reassigned"
+        );
+        let text = "count = 0\ncount += 1\nprint(count)\n";
+        assert_eq!(
+            inline_variable(text, at(2, 7)).unwrap().unwrap_err(),
+            "`count` is reassigned"
+        );
+        let text = "a = 1\nb = a + 1\na = 5\nprint(b)\n";
+        assert_eq!(
+            inline_variable(text, at(1, 0)).unwrap().unwrap_err(),
+            "`a` changes before `b` is used"
+        );
+        let text = "def f(n):\n    print(n)\n";
+        assert_eq!(
+            inline_variable(text, at(1, 10)).unwrap().unwrap_err(),
+            "`n` is a parameter"
+        );
+        let text = "item = next_item()\nprint(item, item)\n";
+        assert_eq!(
+            inline_variable(text, at(0, 0)).unwrap().unwrap_err(),
+            "Initializer has side effects and is used more than once"
+        );
+    }
+}
@@ -138,15 +138,34 @@ impl ReferenceProvider {
         // Get all workspace files from document manager
         let document_uris = self.document_manager.list_documents().await;
 
-        for uri in document_uris {
+        for uri in &document_uris {
             if let Some(refs) = self
-                .find_references_in_file(&uri, symbol_name, include_declaration)
+                .find_references_in_file(uri, symbol_name, include_declaration)
                 .await
             {
                 locations.extend(refs);
             }
         }
 
+        // Files that aren't open are read from disk, but only if the workspace index
+        // says they mention the symbol at all
+        for uri in self.workspace_manager.files_mentioning(symbol_name) {
+            if document_uris.contains(&uri) {
+                continue;
+            }
+            let Ok(path) = uri.to_file_path() else {
+                continue;
+            };
+            if let Ok(text) = std::fs::read_to_string(path) {
+                locations.extend(self.find_references_in_text(
+                    &uri,
+                    &text,
+                    symbol_name,
+                    include_declaration,
+                ));
+            }
+        }
+
         locations
     }
 
@@ -156,49 +175,55 @@ impl ReferenceProvider {
         symbol_name: &str,
         include_declaration: bool,
     ) -> Option<Vec<Location>> {
-        if let Some(document) = self.document_manager.get_document(uri).await {
-            let mut locations = Vec::new();
-            let text = document.rope.to_string();
-            let lines: Vec<&str> = text.lines().collect();
-
-            // Create patterns for different types of references
-            let patterns = self.create_reference_patterns(symbol_name);
-
-            for (line_number, line) in lines.iter().enumerate() {
-                for pattern in &patterns {
-                    for mat in pattern.regex.find_iter(line) {
-                        let is_definition = pattern.is_definition;
-
-                        // Skip definitions if not requested
-                        if is_definition && !include_declaration {
-                            continue;
-                        }
-
-                        // Verify this is actually the symbol we're looking for
-                        if self.is_valid_symbol_match(line, mat.start(), symbol_name) {
-                            let location = Location {
-                                uri: uri.clone(),
-                                range: Range {
-                                    start: Position {
-                                        line: line_number as u32,
-                                        character: mat.start() as u32,
-                                    },
-                                    end: Position {
-                                        line: line_number as u32,
-                                        character: mat.end() as u32,
-                                    },
+        let document = self.document_manager.get_document(uri).await?;
+        let text = document.rope.to_string();
+        Some(self.find_references_in_text(uri, &text, symbol_name, include_declaration))
+    }
+
+    fn find_references_in_text(
+        &self,
+        uri: &Url,
+        text: &str,
+        symbol_name: &str,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        let mut locations = Vec::new();
+
+        // Create patterns for different types of references
+        let patterns = self.create_reference_patterns(symbol_name);
+
+        for (line_number, line) in text.lines().enumerate() {
+            for pattern in &patterns {
+                for mat in pattern.regex.find_iter(line) {
+                    let is_definition = pattern.is_definition;
+
+                    // Skip definitions if not requested
+                    if is_definition && !include_declaration {
+                        continue;
+                    }
+
+                    // Verify this is actually the symbol we're looking for
+                    if self.is_valid_symbol_match(line, mat.start(), symbol_name) {
+                        let location = Location {
+                            uri: uri.clone(),
+                            range: Range {
+                                start: Position {
+                                    line: line_number as u32,
+                                    character: mat.start() as u32,
+                                },
+                                end: Position {
+                                    line: line_number as u32,
+                                    character: mat.end() as u32,
                                 },
-                            };
-                            locations.push(location);
-                        }
+                            },
+                        };
+                        locations.push(location);
                     }
                 }
             }
-
-            Some(locations)
-        } else {
-            None
         }
+
+        locations
     }
 
     fn create_reference_patterns(&self, symbol_name: &str) -> Vec<ReferencePattern> {
@@ -1,6 +1,9 @@
+use crate::hierarchy::FileHierarchy;
 use anyhow::Result;
 use dashmap::DashMap;
 use ignore::WalkBuilder;
+use nagari_compiler::externs::{self, ExternDeclarations};
+use nagari_parser::{Interner, ParseOutcome, Statement, Symbol};
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -98,6 +101,10 @@ pub struct WorkspaceManager {
     workspace_folders: Arc<DashMap<Url, WorkspaceFolder>>,
     indexed_files: Arc<DashMap<Url, IndexedFile>>,
     symbol_index: Arc<DashMap<String, Vec<WorkspaceSymbol>>>,
+    /// Identifier interner shared by every indexed file
+    interner: Arc<Interner>,
+    /// `.nagd` declarations of untyped JavaScript modules, by file
+    extern_declarations: Arc<DashMap<PathBuf, ExternDeclarations>>,
 }
 
 #[derive(Debug, Clone)]
@@ -108,6 +115,10 @@ pub struct IndexedFile {
     pub symbols: Vec<WorkspaceSymbol>,
     pub imports: Vec<String>,
     pub exports: Vec<String>,
+    /// Every identifier that appears in the file
+    pub identifiers: Vec<Symbol>,
+    /// Definitions, call edges and superclasses for call/type hierarchies
+    pub hierarchy: Arc<FileHierarchy>,
 }
 
 impl WorkspaceManager {
@@ -116,6 +127,8 @@ impl WorkspaceManager {
             workspace_folders: Arc::new(DashMap::new()),
             indexed_files: Arc::new(DashMap::new()),
             symbol_index: Arc::new(DashMap::new()),
+            interner: Arc::new(Interner::new()),
+            extern_declarations: Arc::new(DashMap::new()),
         }
     }
 
@@ -166,14 +179,26 @@ impl WorkspaceManager {
             .git_ignore(true)
             .build();
 
+        let mut paths = Vec::new();
         for entry in walker {
             let entry = entry?;
-            let file_path = entry.path();
+            match entry.path().extension() {
+                Some(ext) if ext == "nag" => paths.push(entry.into_path()),
+                Some(ext) if ext == externs::EXTENSION => self.index_declarations(entry.path()),
+                _ => {}
+            }
+        }
 
-            if file_path.extension().map_or(false, |ext| ext == "nag") {
-                if let Err(e) = self.index_file(file_path).await {
-                    tracing::warn!("Failed to index file {}: {}", file_path.display(), e);
-                }
+        // Read and parse every file in parallel, off the async runtime
+        let interner = self.interner.clone();
+        let outcomes =
+            tokio::task::spawn_blocking(move || nagari_parser::parse_many_with(&paths, &interner))
+                .await?;
+
+        for outcome in outcomes {
+            let path = outcome.path.clone();
+            if let Err(e) = self.index_outcome(outcome) {
+                tracing::warn!("Failed to index file {}: {}", path.display(), e);
             }
         }
 
@@ -181,15 +206,59 @@ impl WorkspaceManager {
     }
 
     async fn index_file(&self, path: &Path) -> Result<()> {
-        let uri = Url::from_file_path(path).map_err(|_| anyhow::anyhow!("Invalid file path"))?;
+        if path
+            .extension()
+            .is_some_and(|ext| ext == externs::EXTENSION)
+        {
+            self.index_declarations(path);
+            return Ok(());
+        }
+
+        let outcomes = nagari_parser::parse_many_with(&[path], &self.interner);
+        match outcomes.into_iter().next() {
+            Some(outcome) => self.index_outcome(outcome),
+            None => Ok(()),
+        }
+    }
+
+    fn index_declarations(&self, path: &Path) {
+        match ExternDeclarations::load(path) {
+            Ok(declarations) => {
+                self.extern_declarations
+                    .insert(path.to_path_buf(), declarations);
+            }
+            Err(e) => tracing::warn!("Failed to load declarations {}: {}", path.display(), e),
+        }
+    }
+
+    /// Every module declared by the `.nagd` files of the workspace
+    pub fn extern_declarations(&self) -> ExternDeclarations {
+        let mut declarations = ExternDeclarations::new();
+        for entry in self.extern_declarations.iter() {
+            declarations.extend(entry.value().clone());
+        }
+        declarations
+    }
+
+    fn index_outcome(&self, outcome: ParseOutcome) -> Result<()> {
+        if let Err(nagari_parser::ParseError::Io { message, .. }) = &outcome.program {
+            return Err(anyhow::anyhow!("{}", message));
+        }
 
+        let path = outcome.path.as_path();
+        let uri = Url::from_file_path(path).map_err(|_| anyhow::anyhow!("Invalid file path"))?;
         let metadata = std::fs::metadata(path)?;
-        let content = std::fs::read_to_string(path)?;
+        let content = &outcome.source;
 
         // Extract symbols from the file
-        let symbols = self.extract_symbols(&content, &uri);
-        let imports = self.extract_imports(&content);
-        let exports = self.extract_exports(&content);
+        let symbols = self.extract_symbols(content, &uri);
+
+        // Prefer the parsed module structure; fall back to text matching for files
+        // that don't parse yet
+        let (imports, exports) = match &outcome.program {
+            Ok(program) => module_imports_exports(&program.statements),
+            Err(_) => (self.extract_imports(content), self.extract_exports(content)),
+        };
 
         let indexed_file = IndexedFile {
             uri: uri.clone(),
@@ -198,6 +267,8 @@ impl WorkspaceManager {
             symbols: symbols.clone(),
             imports,
             exports,
+            identifiers: outcome.identifiers,
+            hierarchy: Arc::new(FileHierarchy::analyze(content)),
         };
 
         self.indexed_files.insert(uri, indexed_file);
@@ -356,6 +427,27 @@ impl WorkspaceManager {
         locations
     }
 
+    /// Indexed files whose source contains `name` as an identifier
+    pub fn files_mentioning(&self, name: &str) -> Vec<Url> {
+        let Some(symbol) = self.interner.get(name) else {
+            return Vec::new();
+        };
+
+        self.indexed_files
+            .iter()
+            .filter(|entry| entry.value().identifiers.contains(&symbol))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Call and type hierarchy data for every indexed file
+    pub fn file_hierarchies(&self) -> Vec<(Url, Arc<FileHierarchy>)> {
+        self.indexed_files
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().hierarchy.clone()))
+            .collect()
+    }
+
     pub async fn get_document_symbols(&self, uri: &Url) -> Vec<WorkspaceSymbol> {
         if let Some(file) = self.indexed_files.get(uri) {
             file.symbols.clone()
@@ -444,3 +536,29 @@ impl WorkspaceManager {
             .collect()
     }
 }
+
+fn module_imports_exports(statements: &[Statement]) -> (Vec<String>, Vec<String>) {
+    let mut imports = Vec::new();
+    let mut exports = Vec::new();
+
+    for statement in statements {
+        match statement {
+            Statement::Import { source, .. } => imports.push(source.clone()),
+            Statement::ExportNamed { exports: named, .. } => exports.extend(
+                named
+                    .iter()
+                    .map(|export| export.alias.clone().unwrap_or_else(|| export.name.clone())),
+            ),
+            Statement::ExportDeclaration { declaration } => match declaration.as_ref() {
+                Statement::Function { name, .. }
+                | Statement::Class { name, .. }
+                | Statement::Let { name, .. }
+                | Statement::Const { name, .. } => exports.push(name.clone()),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    (imports, exports)
+}
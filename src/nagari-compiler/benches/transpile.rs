@@ -0,0 +1,132 @@
+//! Transpiler benchmarks on large generated programs.
+//!
+//! Besides criterion's timings, each input size prints the number of heap allocations and the
+//! peak heap usage of one transpilation, measured with a counting global allocator. The
+//! `transpile` benchmarks start from the converted AST; `compile` is the whole build of the
+//! source, for the share of build throughput the transpiler accounts for.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nagari_compiler::{runtime_package, transpiler, Compiler, Program};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(current, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// A program of `functions` functions and `classes` classes, with the loops, branches, calls,
+/// methods and lists typical programs have
+fn generated_program(functions: usize, classes: usize) -> String {
+    let mut source = String::new();
+    for i in 0..functions {
+        let _ = write!(
+            source,
+            "def compute_{i}(items: list, limit: int) -> int:\n\
+             \x20   total = 0\n\
+             \x20   for item in items:\n\
+             \x20       if item > limit:\n\
+             \x20           total = total + item * {i}\n\
+             \x20       else:\n\
+             \x20           if item == limit:\n\
+             \x20               print(\"limit \" + str(item) + \" reached in {i}\")\n\
+             \x20           else:\n\
+             \x20               total = total - 1\n\
+             \x20   while total > 1000:\n\
+             \x20       total = total - 500\n\
+             \x20   values = [total, limit, {i}]\n\
+             \x20   return len(values) + values[0]\n\n"
+        );
+    }
+    for i in 0..classes {
+        let _ = write!(
+            source,
+            "class Shape{i} {{\n\
+             \x20   def __init__(self, width: float, height: float):\n\
+             \x20       self.width = width\n\
+             \x20       self.height = height\n\n\
+             \x20   def area(self) -> float:\n\
+             \x20       return self.width * self.height\n\n\
+             \x20   def describe(self) -> str:\n\
+             \x20       return \"Shape{i} of area \" + str(self.area())\n\
+             }}\n\n"
+        );
+    }
+    for i in 0..functions {
+        let _ = writeln!(source, "print(compute_{i}([1, 2, 3, {i}], {i}))");
+    }
+    source
+}
+
+fn report_memory(label: &str, size: usize, run: impl FnOnce()) {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+
+    run();
+
+    println!(
+        "{label} ({size} functions): {} allocations, peak heap {} KiB",
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        (PEAK.load(Ordering::Relaxed) - baseline) / 1024
+    );
+}
+
+fn bench_transpile(c: &mut Criterion) {
+    let compiler = Compiler::new();
+    let runtime = runtime_package::DEFAULT_SPECIFIER;
+    let transpile = |program: &Program| {
+        transpiler::transpile(program, "es6", false, false, None, runtime, false).unwrap()
+    };
+    let mut group = c.benchmark_group("transpile");
+    group.sample_size(20);
+
+    for functions in [100, 1000] {
+        let source = generated_program(functions, functions / 10);
+        let program = compiler.parse_program(&source).unwrap();
+        let output = transpile(&program);
+        group.throughput(Throughput::Bytes(output.len() as u64));
+
+        report_memory("transpile", functions, || {
+            black_box(transpile(&program));
+        });
+        report_memory("compile", functions, || {
+            black_box(compiler.compile_string(&source, None).unwrap());
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("transpile", functions),
+            &program,
+            |b, program| b.iter(|| transpile(black_box(program))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("compile", functions),
+            &source,
+            |b, source| b.iter(|| compiler.compile_string(black_box(source), None).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_transpile);
+criterion_main!(benches);
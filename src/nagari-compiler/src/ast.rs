@@ -10,6 +10,7 @@ pub enum Statement {
     FunctionDef(FunctionDef),
     Assignment(Assignment),
     AttributeAssignment(AttributeAssignment),
+    IndexAssignment(IndexAssignment),
     TupleAssignment(TupleAssignment),
     If(IfStatement),
     While(WhileLoop),
@@ -60,6 +61,18 @@ pub struct Parameter {
     pub name: String,
     pub param_type: Option<Type>,
     pub default_value: Option<Expression>,
+    pub kind: ParameterKind,
+}
+
+/// How a parameter takes the arguments of a call
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterKind {
+    /// One argument, by position or by name
+    Positional,
+    /// `*args`: the positional arguments left over, as a list
+    Rest,
+    /// `**kwargs`: the keyword arguments no other parameter takes, as a dict
+    Keywords,
 }
 
 #[derive(Debug, Clone)]
@@ -76,6 +89,13 @@ pub struct AttributeAssignment {
     pub value: Expression,
 }
 
+#[derive(Debug, Clone)]
+pub struct IndexAssignment {
+    pub object: Expression,
+    pub index: Expression,
+    pub value: Expression,
+}
+
 #[derive(Debug, Clone)]
 pub struct TupleAssignment {
     pub targets: Vec<String>,
@@ -107,6 +127,8 @@ pub struct ForLoop {
     pub variable: String,
     pub iterable: Expression,
     pub body: Vec<Statement>,
+    /// `async for`: `for await` over an async iterable
+    pub is_async: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -173,6 +195,7 @@ pub enum Expression {
     Spread(Box<Expression>),
     TemplateLiteral(TemplateLiteral),
     FString(FStringExpression), // f"string with {expr}" format
+    InlineJs(String),           // js"""...""" raw JavaScript
 }
 
 #[derive(Debug, Clone)]
@@ -219,12 +242,21 @@ pub enum BinaryOperator {
     // Add missing logical operators
     And,
     Or,
+    // Power, floor division and bitwise operators
+    Power,
+    FloorDivide,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    LeftShift,
+    RightShift,
 }
 
 #[derive(Debug, Clone)]
 pub struct CallExpression {
     pub function: Box<Expression>,
     pub arguments: Vec<Expression>,
+    /// `name=value` arguments in order; a `**mapping` argument is a `Spread` with no name
     pub keyword_args: Vec<(String, Expression)>,
 }
 
@@ -244,6 +276,8 @@ pub enum Literal {
 pub struct WithStatement {
     pub items: Vec<WithItem>,
     pub body: Vec<Statement>,
+    /// `async with`: the context managers' `__aenter__` and `__aexit__` are awaited
+    pub is_async: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -297,6 +331,8 @@ pub struct YieldFromStatement {
 pub struct Decorator {
     pub name: String,
     pub arguments: Option<Vec<Expression>>,
+    /// `name=value` arguments, as in `CallExpression::keyword_args`
+    pub keyword_args: Vec<(String, Expression)>,
 }
 
 // Lambda Expressions
@@ -418,6 +454,7 @@ pub struct ClassDef {
     pub name: String,
     pub superclass: Option<String>,
     pub body: Vec<Statement>,
+    pub decorators: Vec<Decorator>,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,1142 @@
+//! Bundling of a program and the modules it imports into one file, without an external
+//! bundler.
+//!
+//! [`Compiler::bundle`] follows the relative imports of the entry module to the files they
+//! resolve to, and orders the modules so that each comes after the modules it imports, taking
+//! imports in the order they are written: the same sources always give the same bundle. The
+//! modules are then hoisted into one scope. Imports of bundled modules are dropped, a top-level
+//! name that several modules define is renamed with a `$1`, `$2`, ... suffix in all but the
+//! first of them, and the program they make up is transpiled once, so that the runtime import
+//! and helpers are in the bundle once.
+//!
+//! An [`BundleFormat::Esm`] bundle keeps the imports of other modules. An
+//! [`BundleFormat::Iife`] bundle is a function called right away that takes each module it
+//! imports as a parameter, from the global named after the module in camelCase
+//! (`nagari-runtime` from `nagariRuntime`), as rollup guesses it.
+
+use crate::ast::*;
+use crate::error::NagariError;
+use crate::module_graph::{self, ModuleGraph};
+use crate::portable::slash_path;
+use crate::timings::CompilationTimings;
+use crate::warnings::{self, Warning};
+use crate::{minify, transpiler, typechecker, Compiler};
+use nagari_parser::{import_sites, ImportOrigin};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What a bundle is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum BundleFormat {
+    /// A script running the program when loaded, for `<script>` tags
+    #[default]
+    Iife,
+    /// An ES module
+    Esm,
+}
+
+impl BundleFormat {
+    pub const ALL: [BundleFormat; 2] = [BundleFormat::Iife, BundleFormat::Esm];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BundleFormat::Iife => "iife",
+            BundleFormat::Esm => "esm",
+        }
+    }
+}
+
+impl FromStr for BundleFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.to_ascii_lowercase();
+        BundleFormat::ALL
+            .into_iter()
+            .find(|format| format.as_str() == name)
+            .ok_or_else(|| format!("Unknown bundle format '{name}' (expected iife or esm)"))
+    }
+}
+
+impl fmt::Display for BundleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A bundle and the modules it was made from
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub js_code: String,
+    /// Bundled modules, in the order of their code in the bundle; the entry module is last
+    pub modules: Vec<PathBuf>,
+    /// Warnings of the compilation of each module
+    pub warnings: Vec<(PathBuf, Warning)>,
+}
+
+/// A module of the bundle, before it is compiled
+struct Module {
+    path: PathBuf,
+    source: String,
+    /// Relative imports, in order, with the index of the module each resolves to
+    imports: Vec<(String, usize)>,
+}
+
+impl Compiler {
+    /// Bundle `entry` and the modules it imports, directly or not, into one file. Fails on
+    /// relative imports that don't resolve to a `.nag` file and on import cycles.
+    pub fn bundle<P: AsRef<Path>>(
+        &self,
+        entry: P,
+        format: BundleFormat,
+    ) -> Result<Bundle, NagariError> {
+        self.config.validate().map_err(NagariError::ConfigError)?;
+        if !matches!(self.config.target.as_str(), "es6" | "esm") {
+            return Err(NagariError::ConfigError(format!(
+                "bundles are built for the es6 and esm targets, not '{}'",
+                self.config.target
+            )));
+        }
+
+        let modules = discover(entry.as_ref())?;
+        ModuleGraph::build(
+            modules
+                .iter()
+                .map(|module| (module.path.as_path(), module.source.as_str())),
+        )
+        .check()?;
+        let order = bundle_order(&modules);
+
+        let mut programs = Vec::new();
+        let mut bundle_warnings = Vec::new();
+        for module in &modules {
+            if self.config.verbose {
+                println!("📦 Bundling {}", module.path.display());
+            }
+            let (program, module_warnings) = self
+                .lower_module(module)
+                .map_err(|e| in_module(&module.path, e))?;
+            programs.push(program);
+            bundle_warnings.extend(
+                module_warnings
+                    .into_iter()
+                    .map(|warning| (module.path.clone(), warning)),
+            );
+        }
+
+        let mut program = hoist(&modules, programs, &order)?;
+        if self.config.minify {
+            minify::program(&mut program);
+        }
+        let js_code = transpiler::transpile(
+            &program,
+            &self.config.target,
+            self.config.jsx,
+            self.config.ssr,
+            self.config.environment,
+            &self.config.runtime.specifier,
+            self.config.jsdoc,
+        )?;
+        let js_code = match format {
+            BundleFormat::Iife => wrap_iife(&js_code),
+            BundleFormat::Esm => js_code,
+        };
+        let js_code = if self.config.minify {
+            minify::compact(&js_code)
+        } else {
+            js_code
+        };
+        self.config
+            .limits
+            .check_output("JavaScript output", js_code.len())?;
+
+        Ok(Bundle {
+            js_code: self.config.line_endings.apply(js_code),
+            modules: order
+                .into_iter()
+                .map(|index| modules[index].path.clone())
+                .collect(),
+            warnings: bundle_warnings,
+        })
+    }
+
+    /// The internal AST of a module and its warnings, checked as a compilation of it would
+    fn lower_module(&self, module: &Module) -> Result<(Program, Vec<Warning>), NagariError> {
+        let mut timings = CompilationTimings::new();
+        let (external_ast, mut diagnostics) = self.parse_source(&module.source, &mut timings)?;
+        diagnostics.analyze(&external_ast);
+        let program = self.lower(external_ast, Some(&module.path), &mut timings)?;
+        diagnostics.extend(typechecker::check(&program, self.config.strict_types)?);
+        let warnings = warnings::enforce(
+            &self.config.warning_levels,
+            self.config.deny_warnings,
+            &module.source,
+            diagnostics.into_warnings(),
+        )?;
+        Ok((program, warnings))
+    }
+}
+
+/// `entry` and every module its relative imports lead to, the entry first
+fn discover(entry: &Path) -> Result<Vec<Module>, NagariError> {
+    let mut modules = vec![Module {
+        path: module_graph::normalize(entry),
+        source: String::new(),
+        imports: Vec::new(),
+    }];
+    let mut index = HashMap::from([(modules[0].path.clone(), 0)]);
+
+    let mut next = 0;
+    while next < modules.len() {
+        let path = modules[next].path.clone();
+        let source = fs::read_to_string(&path).map_err(|e| {
+            NagariError::IoError(format!("Failed to read {}: {e}", slash_path(&path)))
+        })?;
+
+        let mut imports = Vec::new();
+        for site in import_sites(&source) {
+            if site.origin != ImportOrigin::Local {
+                continue;
+            }
+            let candidates = module_graph::resolve(&path, &site.module);
+            let resolved = candidates
+                .iter()
+                .find(|candidate| index.contains_key(*candidate) || candidate.is_file())
+                .ok_or_else(|| {
+                    NagariError::IoError(format!(
+                        "{}:{}:{}: cannot find module '{}' to bundle",
+                        slash_path(&path),
+                        site.line,
+                        site.column,
+                        site.module
+                    ))
+                })?;
+            let target = match index.get(resolved) {
+                Some(&target) => target,
+                None => {
+                    index.insert(resolved.clone(), modules.len());
+                    modules.push(Module {
+                        path: resolved.clone(),
+                        source: String::new(),
+                        imports: Vec::new(),
+                    });
+                    modules.len() - 1
+                }
+            };
+            imports.push((site.module, target));
+        }
+
+        modules[next].source = source;
+        modules[next].imports = imports;
+        next += 1;
+    }
+
+    Ok(modules)
+}
+
+/// Indices of `modules` in bundle order: each module after the modules it imports, in the
+/// order of its imports, and the entry module last. A `# nagari:lazy` import that closes a
+/// cycle doesn't order anything.
+fn bundle_order(modules: &[Module]) -> Vec<usize> {
+    fn visit(modules: &[Module], module: usize, visited: &mut [bool], order: &mut Vec<usize>) {
+        if visited[module] {
+            return;
+        }
+        visited[module] = true;
+        for (_, target) in &modules[module].imports {
+            visit(modules, *target, visited, order);
+        }
+        order.push(module);
+    }
+
+    let mut visited = vec![false; modules.len()];
+    let mut order = Vec::with_capacity(modules.len());
+    visit(modules, 0, &mut visited, &mut order);
+    order
+}
+
+/// `error` of the module at `path`, with the path in front of its message
+fn in_module(path: &Path, error: NagariError) -> NagariError {
+    let located = |message: String| format!("{}: {message}", slash_path(path));
+    match error {
+        NagariError::LexError(message) => NagariError::LexError(located(message)),
+        NagariError::ParseError(message) => NagariError::ParseError(located(message)),
+        NagariError::TypeError(message) => NagariError::TypeError(located(message)),
+        NagariError::BytecodeError(message) => NagariError::BytecodeError(located(message)),
+        NagariError::IoError(message) => NagariError::IoError(located(message)),
+        NagariError::SemanticError(message) => NagariError::SemanticError(located(message)),
+        NagariError::ImportCycle(message) => NagariError::ImportCycle(message),
+        NagariError::ConfigError(message) => NagariError::ConfigError(located(message)),
+        NagariError::LimitExceeded(message) => NagariError::LimitExceeded(located(message)),
+    }
+}
+
+/// The program that the modules' programs make up in one scope, in bundle `order`
+fn hoist(
+    modules: &[Module],
+    programs: Vec<Program>,
+    order: &[usize],
+) -> Result<Program, NagariError> {
+    let bundled = |module: usize, specifier: &str| {
+        modules[module]
+            .imports
+            .iter()
+            .find(|(import, _)| import == specifier)
+            .map(|(_, target)| *target)
+    };
+    let failure = |module: usize, message: String| {
+        in_module(&modules[module].path, NagariError::SemanticError(message))
+    };
+
+    // Names imported from other modules keep them, so that their imports stay as written.
+    // Names imported from bundled modules are those modules' names.
+    let mut imported_from: HashMap<&str, &str> = HashMap::new();
+    let mut external: Vec<HashSet<&str>> = vec![HashSet::new(); modules.len()];
+    let mut local: Vec<HashMap<&str, usize>> = vec![HashMap::new(); modules.len()];
+    for &module in order {
+        for import in imports(&programs[module]) {
+            let target = bundled(module, &import.module);
+            for name in import_bindings(import) {
+                if let Some(target) = target {
+                    local[module].insert(name, target);
+                    continue;
+                }
+                match imported_from.insert(name, &import.module) {
+                    Some(other) if other != import.module => {
+                        return Err(failure(
+                            module,
+                            format!(
+                                "`{name}` is imported from both '{other}' and '{}', which one \
+                                 scope can't hold",
+                                import.module
+                            ),
+                        ));
+                    }
+                    _ => {
+                        external[module].insert(name);
+                    }
+                }
+            }
+        }
+    }
+
+    // Every other top-level name is renamed in the modules defining it after the first one
+    let mut taken: HashSet<String> = imported_from.keys().map(|name| name.to_string()).collect();
+    let mut defined: Vec<HashMap<String, String>> = vec![HashMap::new(); modules.len()];
+    for &module in order {
+        for name in top_level_names(&programs[module].statements) {
+            if defined[module].contains_key(&name) || external[module].contains(name.as_str()) {
+                continue;
+            }
+            let mut unique = name.clone();
+            let mut suffix = 0;
+            while taken.contains(&unique) {
+                suffix += 1;
+                unique = format!("{name}${suffix}");
+            }
+            taken.insert(unique.clone());
+            defined[module].insert(name, unique);
+        }
+    }
+
+    // Name `name` of `module` has in the bundle, following names a module imports from
+    // another one; None if no module defines it
+    let bundle_name = |module: usize, name: &str| {
+        let mut module = module;
+        for _ in 0..modules.len() {
+            if let Some(unique) = defined[module].get(name) {
+                return Some(unique.clone());
+            }
+            if external[module].contains(name) {
+                return Some(name.to_string());
+            }
+            module = *local[module].get(name)?;
+        }
+        None
+    };
+
+    let mut renames = Vec::with_capacity(modules.len());
+    for &module in order {
+        let mut module_renames: HashMap<String, String> = defined[module]
+            .iter()
+            .filter(|(name, unique)| name != unique)
+            .map(|(name, unique)| (name.clone(), unique.clone()))
+            .collect();
+        for (&name, &target) in &local[module] {
+            if defined[module].contains_key(name) {
+                continue;
+            }
+            let unique = bundle_name(target, name).ok_or_else(|| {
+                failure(
+                    module,
+                    format!(
+                        "{} has no top-level `{name}` to import",
+                        slash_path(&modules[target].path)
+                    ),
+                )
+            })?;
+            if unique != name {
+                module_renames.insert(name.to_string(), unique);
+            }
+        }
+        renames.push(module_renames);
+    }
+
+    let mut programs: Vec<Option<Program>> = programs.into_iter().map(Some).collect();
+    let mut statements = Vec::new();
+    let mut emitted: HashSet<(String, String)> = HashSet::new();
+    for (&module, renames) in order.iter().zip(&renames) {
+        let Some(program) = programs[module].take() else {
+            continue;
+        };
+        let start = statements.len();
+        for statement in program.statements {
+            match statement {
+                Statement::Import(import) if bundled(module, &import.module).is_some() => {}
+                // Other modules are imported once for each name
+                Statement::Import(ImportStatement {
+                    module: specifier,
+                    items: Some(items),
+                }) => {
+                    let items: Vec<String> = items
+                        .into_iter()
+                        .filter(|item| emitted.insert((specifier.clone(), item.clone())))
+                        .collect();
+                    if !items.is_empty() {
+                        statements.push(Statement::Import(ImportStatement {
+                            module: specifier,
+                            items: Some(items),
+                        }));
+                    }
+                }
+                statement => statements.push(statement),
+            }
+        }
+        if !renames.is_empty() {
+            rename_block(&mut statements[start..], renames);
+        }
+    }
+
+    Ok(Program { statements })
+}
+
+/// Top-level import statements of `program`
+fn imports(program: &Program) -> impl Iterator<Item = &ImportStatement> {
+    program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Import(import) => Some(import),
+            _ => None,
+        })
+}
+
+/// Names an import binds; `*` imports the module only for its side effects
+fn import_bindings(import: &ImportStatement) -> impl Iterator<Item = &str> {
+    import
+        .items
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .filter(|item| *item != "*")
+}
+
+/// Names that `statements` bind in the scope they are in, in order. Blocks count, since the
+/// transpiler declares a name on its first assignment in any of them.
+pub(crate) fn top_level_names(statements: &[Statement]) -> Vec<String> {
+    fn collect(statements: &[Statement], names: &mut Vec<String>) {
+        for statement in statements {
+            match statement {
+                Statement::FunctionDef(FunctionDef { name, .. })
+                | Statement::ClassDef(ClassDef { name, .. })
+                | Statement::Assignment(Assignment { name, .. }) => names.push(name.clone()),
+                Statement::TupleAssignment(TupleAssignment { targets, .. })
+                | Statement::ArrayDestructuringAssignment(ArrayDestructuringAssignment {
+                    targets,
+                    ..
+                }) => names.extend(targets.iter().cloned()),
+                Statement::If(if_stmt) => {
+                    collect(&if_stmt.then_branch, names);
+                    for elif in &if_stmt.elif_branches {
+                        collect(&elif.body, names);
+                    }
+                    if let Some(else_branch) = &if_stmt.else_branch {
+                        collect(else_branch, names);
+                    }
+                }
+                Statement::While(while_loop) => collect(&while_loop.body, names),
+                Statement::For(for_loop) => {
+                    names.push(for_loop.variable.clone());
+                    collect(&for_loop.body, names);
+                }
+                Statement::Match(match_stmt) => {
+                    for case in &match_stmt.cases {
+                        collect(&case.body, names);
+                    }
+                }
+                Statement::With(with_stmt) => {
+                    names.extend(
+                        with_stmt
+                            .items
+                            .iter()
+                            .flat_map(|item| item.optional_vars.clone()),
+                    );
+                    collect(&with_stmt.body, names);
+                }
+                Statement::Try(try_stmt) => {
+                    collect(&try_stmt.body, names);
+                    for handler in &try_stmt.except_handlers {
+                        names.extend(handler.name.clone());
+                        collect(&handler.body, names);
+                    }
+                    for block in [&try_stmt.else_clause, &try_stmt.finally_clause]
+                        .into_iter()
+                        .flatten()
+                    {
+                        collect(block, names);
+                    }
+                }
+                Statement::ExportDeclaration(export) => {
+                    collect(std::slice::from_ref(&*export.declaration), names)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut names = Vec::new();
+    collect(statements, &mut names);
+    names
+}
+
+fn rename(name: &mut String, renames: &HashMap<String, String>) {
+    if let Some(unique) = renames.get(name.as_str()) {
+        *name = unique.clone();
+    }
+}
+
+/// Rename the first part of a dotted name such as a decorator's
+fn rename_head(name: &mut String, renames: &HashMap<String, String>) {
+    let (head, rest) = match name.split_once('.') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (name.as_str(), None),
+    };
+    if let Some(unique) = renames.get(head) {
+        *name = match rest {
+            Some(rest) => format!("{unique}.{rest}"),
+            None => unique.clone(),
+        };
+    }
+}
+
+/// `renames` without the names that `parameters` shadow. Parameters keep their names, which
+/// keyword arguments refer to them by.
+fn unshadowed<'a, I>(
+    renames: &HashMap<String, String>,
+    parameters: I,
+) -> Option<HashMap<String, String>>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let shadowed: Vec<&str> = parameters
+        .into_iter()
+        .filter(|name| renames.contains_key(*name))
+        .collect();
+    (!shadowed.is_empty()).then(|| {
+        renames
+            .iter()
+            .filter(|(name, _)| !shadowed.contains(&name.as_str()))
+            .map(|(name, unique)| (name.clone(), unique.clone()))
+            .collect()
+    })
+}
+
+pub(crate) fn rename_block(statements: &mut [Statement], renames: &HashMap<String, String>) {
+    for statement in statements {
+        rename_statement(statement, renames);
+    }
+}
+
+fn rename_decorators(decorators: &mut [Decorator], renames: &HashMap<String, String>) {
+    for decorator in decorators {
+        rename_head(&mut decorator.name, renames);
+        for argument in decorator.arguments.iter_mut().flatten() {
+            rename_expression(argument, renames);
+        }
+        for (_, value) in &mut decorator.keyword_args {
+            rename_expression(value, renames);
+        }
+    }
+}
+
+fn rename_function(
+    parameters: &mut [Parameter],
+    body: &mut [Statement],
+    renames: &HashMap<String, String>,
+) {
+    for parameter in parameters.iter_mut() {
+        if let Some(default) = &mut parameter.default_value {
+            rename_expression(default, renames);
+        }
+    }
+    let inner = unshadowed(renames, parameters.iter().map(|p| p.name.as_str()));
+    rename_block(body, inner.as_ref().unwrap_or(renames));
+}
+
+fn rename_statement(statement: &mut Statement, renames: &HashMap<String, String>) {
+    match statement {
+        Statement::FunctionDef(function) => {
+            rename(&mut function.name, renames);
+            rename_decorators(&mut function.decorators, renames);
+            rename_function(&mut function.parameters, &mut function.body, renames);
+        }
+        Statement::ClassDef(class) => {
+            rename(&mut class.name, renames);
+            if let Some(superclass) = &mut class.superclass {
+                rename_head(superclass, renames);
+            }
+            rename_decorators(&mut class.decorators, renames);
+            // Methods and class attributes are members, which keep their names
+            for member in &mut class.body {
+                match member {
+                    Statement::FunctionDef(method) => {
+                        rename_decorators(&mut method.decorators, renames);
+                        rename_function(&mut method.parameters, &mut method.body, renames);
+                    }
+                    Statement::Assignment(attribute) => {
+                        rename_expression(&mut attribute.value, renames)
+                    }
+                    member => rename_statement(member, renames),
+                }
+            }
+        }
+        Statement::Assignment(assignment) => {
+            rename(&mut assignment.name, renames);
+            rename_expression(&mut assignment.value, renames);
+        }
+        Statement::AttributeAssignment(assignment) => {
+            rename_expression(&mut assignment.object, renames);
+            rename_expression(&mut assignment.value, renames);
+        }
+        Statement::IndexAssignment(assignment) => {
+            rename_expression(&mut assignment.object, renames);
+            rename_expression(&mut assignment.index, renames);
+            rename_expression(&mut assignment.value, renames);
+        }
+        Statement::TupleAssignment(TupleAssignment { targets, value })
+        | Statement::ArrayDestructuringAssignment(ArrayDestructuringAssignment {
+            targets,
+            value,
+        }) => {
+            for target in targets {
+                rename(target, renames);
+            }
+            rename_expression(value, renames);
+        }
+        Statement::DestructuringAssignment(assignment) => {
+            rename_expression(&mut assignment.target, renames);
+            rename_expression(&mut assignment.value, renames);
+        }
+        Statement::If(if_stmt) => {
+            rename_expression(&mut if_stmt.condition, renames);
+            rename_block(&mut if_stmt.then_branch, renames);
+            for elif in &mut if_stmt.elif_branches {
+                rename_expression(&mut elif.condition, renames);
+                rename_block(&mut elif.body, renames);
+            }
+            if let Some(else_branch) = &mut if_stmt.else_branch {
+                rename_block(else_branch, renames);
+            }
+        }
+        Statement::While(while_loop) => {
+            rename_expression(&mut while_loop.condition, renames);
+            rename_block(&mut while_loop.body, renames);
+        }
+        Statement::For(for_loop) => {
+            rename(&mut for_loop.variable, renames);
+            rename_expression(&mut for_loop.iterable, renames);
+            rename_block(&mut for_loop.body, renames);
+        }
+        Statement::Match(match_stmt) => {
+            rename_expression(&mut match_stmt.expression, renames);
+            for case in &mut match_stmt.cases {
+                rename_pattern(&mut case.pattern, renames);
+                rename_block(&mut case.body, renames);
+            }
+        }
+        Statement::Return(Some(value)) | Statement::Expression(value) | Statement::Del(value) => {
+            rename_expression(value, renames)
+        }
+        Statement::With(with_stmt) => {
+            for item in &mut with_stmt.items {
+                rename_expression(&mut item.context_expr, renames);
+                if let Some(name) = &mut item.optional_vars {
+                    rename(name, renames);
+                }
+            }
+            rename_block(&mut with_stmt.body, renames);
+        }
+        Statement::Try(try_stmt) => {
+            rename_block(&mut try_stmt.body, renames);
+            for handler in &mut try_stmt.except_handlers {
+                if let Some(name) = &mut handler.name {
+                    rename(name, renames);
+                }
+                rename_block(&mut handler.body, renames);
+            }
+            for block in [&mut try_stmt.else_clause, &mut try_stmt.finally_clause]
+                .into_iter()
+                .flatten()
+            {
+                rename_block(block, renames);
+            }
+        }
+        Statement::Raise(raise) => {
+            for value in [&mut raise.exception, &mut raise.cause]
+                .into_iter()
+                .flatten()
+            {
+                rename_expression(value, renames);
+            }
+        }
+        Statement::Yield(yield_stmt) => {
+            if let Some(value) = &mut yield_stmt.value {
+                rename_expression(value, renames);
+            }
+        }
+        Statement::YieldFrom(yield_from) => rename_expression(&mut yield_from.value, renames),
+        Statement::ExportDefault(export) => rename_expression(&mut export.value, renames),
+        Statement::ExportNamed(export) => {
+            for name in &mut export.exports {
+                rename(name, renames);
+            }
+        }
+        Statement::ExportDeclaration(export) => rename_statement(&mut export.declaration, renames),
+        _ => {}
+    }
+}
+
+fn rename_pattern(pattern: &mut Pattern, renames: &HashMap<String, String>) {
+    match pattern {
+        Pattern::Identifier(name) => rename(name, renames),
+        Pattern::Tuple(patterns) | Pattern::List(patterns) => {
+            for pattern in patterns {
+                rename_pattern(pattern, renames);
+            }
+        }
+        Pattern::Dict(pairs) => {
+            for (key, value) in pairs {
+                rename_pattern(key, renames);
+                rename_pattern(value, renames);
+            }
+        }
+        Pattern::Guard(pattern, condition) => {
+            rename_pattern(pattern, renames);
+            rename_expression(condition, renames);
+        }
+        Pattern::Constructor(class, patterns) => {
+            rename_head(class, renames);
+            for pattern in patterns {
+                rename_pattern(pattern, renames);
+            }
+        }
+        Pattern::Range(start, end) => {
+            rename_expression(start, renames);
+            rename_expression(end, renames);
+        }
+        Pattern::Literal(_) | Pattern::Wildcard => {}
+    }
+}
+
+fn rename_generators(generators: &mut [ComprehensionGenerator], renames: &HashMap<String, String>) {
+    for generator in generators {
+        rename(&mut generator.target, renames);
+        rename_expression(&mut generator.iter, renames);
+        for condition in &mut generator.conditions {
+            rename_expression(condition, renames);
+        }
+    }
+}
+
+fn rename_expression(expr: &mut Expression, renames: &HashMap<String, String>) {
+    let all = |exprs: &mut [Expression]| {
+        for expr in exprs {
+            rename_expression(expr, renames);
+        }
+    };
+
+    match expr {
+        Expression::Identifier(name) => rename(name, renames),
+        Expression::Binary(binary) => {
+            rename_expression(&mut binary.left, renames);
+            rename_expression(&mut binary.right, renames);
+        }
+        Expression::Call(call) => {
+            rename_expression(&mut call.function, renames);
+            all(&mut call.arguments);
+            for (_, value) in &mut call.keyword_args {
+                rename_expression(value, renames);
+            }
+        }
+        Expression::Await(inner) | Expression::Async(inner) | Expression::Spread(inner) => {
+            rename_expression(inner, renames)
+        }
+        Expression::List(items) | Expression::Tuple(items) | Expression::Set(items) => all(items),
+        Expression::Dict(pairs) | Expression::Dictionary(pairs) => {
+            for (key, value) in pairs {
+                rename_expression(key, renames);
+                rename_expression(value, renames);
+            }
+        }
+        Expression::Lambda(lambda) => {
+            let inner = unshadowed(renames, lambda.parameters.iter().map(String::as_str));
+            rename_expression(&mut lambda.body, inner.as_ref().unwrap_or(renames));
+        }
+        Expression::ListComprehension(comp) => {
+            rename_expression(&mut comp.element, renames);
+            rename_generators(&mut comp.generators, renames);
+        }
+        Expression::SetComprehension(comp) => {
+            rename_expression(&mut comp.element, renames);
+            rename_generators(&mut comp.generators, renames);
+        }
+        Expression::DictComprehension(comp) => {
+            rename_expression(&mut comp.key, renames);
+            rename_expression(&mut comp.value, renames);
+            rename_generators(&mut comp.generators, renames);
+        }
+        Expression::Generator(gen) => {
+            rename_expression(&mut gen.element, renames);
+            rename_generators(&mut gen.generators, renames);
+        }
+        Expression::Ternary(ternary) => {
+            rename_expression(&mut ternary.condition, renames);
+            rename_expression(&mut ternary.true_expr, renames);
+            rename_expression(&mut ternary.false_expr, renames);
+        }
+        Expression::Attribute(attr) => rename_expression(&mut attr.object, renames),
+        Expression::Index(index) => {
+            rename_expression(&mut index.object, renames);
+            rename_expression(&mut index.index, renames);
+        }
+        Expression::Subscript(sub) => {
+            rename_expression(&mut sub.object, renames);
+            rename_expression(&mut sub.index, renames);
+        }
+        Expression::Slice(slice) => {
+            rename_expression(&mut slice.object, renames);
+            for bound in [&mut slice.start, &mut slice.end, &mut slice.step]
+                .into_iter()
+                .flatten()
+            {
+                rename_expression(bound, renames);
+            }
+        }
+        Expression::Unary(unary) => rename_expression(&mut unary.operand, renames),
+        Expression::NamedExpr(named) => {
+            rename(&mut named.target, renames);
+            rename_expression(&mut named.value, renames);
+        }
+        Expression::FunctionExpr(function) => {
+            rename_function(&mut function.parameters, &mut function.body, renames)
+        }
+        Expression::TemplateLiteral(template) => all(&mut template.expressions),
+        Expression::FString(fstring) => {
+            for part in &mut fstring.parts {
+                match part {
+                    FStringPart::Expression(expression)
+                    | FStringPart::FormattedExpression { expression, .. } => {
+                        rename_expression(expression, renames)
+                    }
+                    FStringPart::Text(_) => {}
+                }
+            }
+        }
+        Expression::JSXElement(element) => rename_jsx(element, renames),
+        Expression::Literal(_) | Expression::InlineJs(_) => {}
+    }
+}
+
+fn rename_jsx(element: &mut JSXElement, renames: &HashMap<String, String>) {
+    // Lowercase tags are HTML elements, not components
+    if element.tag.starts_with(|c: char| c.is_ascii_uppercase()) {
+        rename_head(&mut element.tag, renames);
+    }
+    for attribute in &mut element.attributes {
+        if let Some(value) = &mut attribute.value {
+            rename_expression(value, renames);
+        }
+    }
+    for child in &mut element.children {
+        match child {
+            JSXChild::Element(child) => rename_jsx(child, renames),
+            JSXChild::Expression(expression) => rename_expression(expression, renames),
+            JSXChild::Text(_) => {}
+        }
+    }
+}
+
+/// `js_code`, an ES module, as a function called right away. Its imports, on lines of their
+/// own as the transpiler emits them, become bindings of the function's parameters.
+fn wrap_iife(js_code: &str) -> String {
+    let mut globals: Vec<String> = Vec::new();
+    let mut body = String::with_capacity(js_code.len());
+    for line in js_code.lines() {
+        match es_import(line) {
+            Some((clause, module)) => {
+                let global = global_name(module);
+                if let Some(binding) = clause.and_then(|clause| import_binding(clause, &global)) {
+                    body.push_str(&binding);
+                    body.push('\n');
+                }
+                if !globals.contains(&global) {
+                    globals.push(global);
+                }
+            }
+            None => {
+                body.push_str(line);
+                body.push('\n');
+            }
+        }
+    }
+
+    let globals = globals.join(", ");
+    format!("(function ({globals}) {{\n{body}}})({globals});\n")
+}
+
+/// The clause (`{ a, b }`, `name` or `* as name`; none for a side-effect import) and the
+/// module of an `import` statement
+fn es_import(line: &str) -> Option<(Option<&str>, &str)> {
+    let rest = line.strip_prefix("import ")?.strip_suffix(';')?;
+    let (clause, specifier) = match rest.rsplit_once(" from ") {
+        Some((clause, specifier)) => (Some(clause.trim()), specifier.trim()),
+        None => (None, rest.trim()),
+    };
+    let module = ['\'', '"'].into_iter().find_map(|quote| {
+        specifier
+            .strip_prefix(quote)
+            .and_then(|specifier| specifier.strip_suffix(quote))
+    })?;
+    Some((clause, module))
+}
+
+/// Declaration of what import `clause` binds, from the module in `global`
+fn import_binding(clause: &str, global: &str) -> Option<String> {
+    if let Some(items) = clause
+        .strip_prefix('{')
+        .and_then(|clause| clause.strip_suffix('}'))
+    {
+        let items: Vec<String> = items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty() && *item != "*")
+            .map(|item| match item.split_once(" as ") {
+                Some((name, alias)) => format!("{}: {}", name.trim(), alias.trim()),
+                None => item.to_string(),
+            })
+            .collect();
+        return (!items.is_empty())
+            .then(|| format!("const {{ {} }} = {global};", items.join(", ")));
+    }
+    let name = clause.strip_prefix("* as ").unwrap_or(clause).trim();
+    Some(format!("const {name} = {global};"))
+}
+
+/// Global a module is taken from in an IIFE bundle: its name in camelCase
+/// (`nagari-runtime` → `nagariRuntime`, `@scope/ui-kit` → `scopeUiKit`)
+fn global_name(module: &str) -> String {
+    let mut global = String::new();
+    for word in module
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .filter(|word| !word.is_empty())
+    {
+        let mut chars = word.chars();
+        if global.is_empty() {
+            global.push_str(word);
+        } else if let Some(first) = chars.next() {
+            global.push(first.to_ascii_uppercase());
+            global.push_str(chars.as_str());
+        }
+    }
+    if global.is_empty() || global.starts_with(|c: char| c.is_ascii_digit()) {
+        global.insert(0, '_');
+    }
+    global
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own under the temporary directory, with `files` written to it
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("nagari-bundler-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn module(imports: &[usize]) -> Module {
+        Module {
+            path: PathBuf::new(),
+            source: String::new(),
+            imports: imports
+                .iter()
+                .map(|&target| (format!("./{target}"), target))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_formats_parse_and_display() {
+        assert_eq!("iife".parse(), Ok(BundleFormat::Iife));
+        assert_eq!("ESM".parse(), Ok(BundleFormat::Esm));
+        assert_eq!(
+            "cjs".parse::<BundleFormat>(),
+            Err("Unknown bundle format 'cjs' (expected iife or esm)".to_string())
+        );
+        for format in BundleFormat::ALL {
+            assert_eq!(format.to_string().parse(), Ok(format));
+        }
+        assert_eq!(BundleFormat::default(), BundleFormat::Iife);
+    }
+
+    #[test]
+    fn test_modules_come_after_their_imports() {
+        // 0 imports 1 and 2, 1 imports 3, 2 imports 3 and 1
+        let modules = [module(&[1, 2]), module(&[3]), module(&[3, 1]), module(&[])];
+        assert_eq!(bundle_order(&modules), [3, 1, 2, 0]);
+
+        // A cycle through a lazy import doesn't visit a module twice
+        let modules = [module(&[1]), module(&[0])];
+        assert_eq!(bundle_order(&modules), [1, 0]);
+    }
+
+    #[test]
+    fn test_globals_are_camel_case_module_names() {
+        assert_eq!(global_name("nagari-runtime"), "nagariRuntime");
+        assert_eq!(global_name("@scope/ui-kit"), "scopeUiKit");
+        assert_eq!(global_name("lodash.debounce"), "lodashDebounce");
+        assert_eq!(global_name("$jquery_ui"), "$jquery_ui");
+        assert_eq!(global_name("3d-engine"), "_3dEngine");
+        assert_eq!(global_name("@/"), "_");
+    }
+
+    #[test]
+    fn test_imports_become_bindings_of_globals() {
+        assert_eq!(
+            es_import("import { a, b as c } from 'lib';"),
+            Some((Some("{ a, b as c }"), "lib"))
+        );
+        assert_eq!(es_import("import \"polyfill\";"), Some((None, "polyfill")));
+        assert_eq!(es_import("import x from lib;"), None);
+        assert_eq!(es_import("const x = 1;"), None);
+
+        assert_eq!(
+            import_binding("{ a, b as c }", "lib").as_deref(),
+            Some("const { a, b: c } = lib;")
+        );
+        assert_eq!(import_binding("{ }", "lib"), None);
+        assert_eq!(
+            import_binding("* as ns", "lib").as_deref(),
+            Some("const ns = lib;")
+        );
+        assert_eq!(
+            import_binding("React", "react").as_deref(),
+            Some("const React = react;")
+        );
+
+        let iife = wrap_iife(
+            "import { jsx } from 'nagari-runtime';\nimport 'polyfill';\nimport React from 'react';\nimport { useState } from 'react';\nconsole.log(1);\n",
+        );
+        assert_eq!(
+            iife,
+            "(function (nagariRuntime, polyfill, react) {\nconst { jsx } = nagariRuntime;\nconst React = react;\nconst { useState } = react;\nconsole.log(1);\n})(nagariRuntime, polyfill, react);\n"
+        );
+    }
+
+    #[test]
+    fn test_errors_name_their_module() {
+        let path = Path::new("src/app.nag");
+        let error = in_module(path, NagariError::ParseError("bad".to_string()));
+        assert!(matches!(error, NagariError::ParseError(message) if message == "src/app.nag: bad"));
+
+        // Cycles already list their modules
+        let cycle = in_module(path, NagariError::ImportCycle("a -> b -> a".to_string()));
+        assert!(matches!(cycle, NagariError::ImportCycle(message) if message == "a -> b -> a"));
+    }
+
+    #[test]
+    fn test_bundles_need_an_es_module_target_and_no_cycles() {
+        let dir = project(
+            "cycle",
+            &[
+                ("main.nag", "import { b } from \"./b\"\nprint(b())\n"),
+                (
+                    "b.nag",
+                    "import { main } from \"./main\"\ndef b():\n    return 1\n",
+                ),
+            ],
+        );
+        let main = dir.join("main.nag");
+
+        let config = crate::CompilerConfigBuilder::new().target("node").build();
+        let error = Compiler::with_config(config)
+            .bundle(&main, BundleFormat::Esm)
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            NagariError::ConfigError(message)
+                if message == "bundles are built for the es6 and esm targets, not 'node'"
+        ));
+
+        let error = Compiler::new()
+            .bundle(&main, BundleFormat::Esm)
+            .unwrap_err();
+        assert!(matches!(error, NagariError::ImportCycle(_)), "{error}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_modules_imported_twice_are_bundled_once() {
+        let dir = project(
+            "shared",
+            &[
+                (
+                    "main.nag",
+                    "import { a } from \"./a\"\nimport { b } from \"./lib/b\"\nprint(a(), b())\n",
+                ),
+                (
+                    "a.nag",
+                    "import { shared } from \"./lib/shared\"\ndef a():\n    return shared\n",
+                ),
+                (
+                    "lib/b.nag",
+                    "import { shared } from \"./shared\"\ndef b():\n    return shared\n",
+                ),
+                ("lib/shared.nag", "shared = 42\n"),
+            ],
+        );
+        let bundle = Compiler::new()
+            .bundle(dir.join("main.nag"), BundleFormat::Esm)
+            .unwrap();
+        let names: Vec<_> = bundle
+            .modules
+            .iter()
+            .map(|module| slash_path(module.strip_prefix(&dir).unwrap()))
+            .collect();
+        assert_eq!(names, ["lib/shared.nag", "a.nag", "lib/b.nag", "main.nag"]);
+        assert_eq!(bundle.js_code.matches("shared = 42").count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
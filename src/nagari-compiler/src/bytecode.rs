@@ -86,6 +86,60 @@ pub enum Opcode {
     Nop = 0x41,
     BuildTuple = 0x42,
     BuildSet = 0x43,
+
+    // Power, floor division and bitwise operations
+    BinaryPower = 0x44,
+    BinaryFloorDivide = 0x45,
+    BinaryBitAnd = 0x46,
+    BinaryBitOr = 0x47,
+    BinaryBitXor = 0x48,
+    BinaryLeftShift = 0x49,
+    BinaryRightShift = 0x4A,
+
+    // Classes
+    BuildClass = 0x4B,
+
+    // With statements
+    EnterContext = 0x4C,
+    ExitContext = 0x4D,
+
+    // Calls with keyword arguments
+    CallFuncKw = 0x4E,
+}
+
+impl Opcode {
+    /// What needs this opcode, if the VM cannot execute it yet
+    fn unsupported_construct(self) -> Option<&'static str> {
+        match self {
+            Opcode::ImportName | Opcode::ImportFrom | Opcode::ImportStar => Some("imports"),
+            Opcode::MakeFunction | Opcode::LoadClosure | Opcode::LoadDeref | Opcode::StoreDeref => {
+                Some("function definitions")
+            }
+            Opcode::SetupExcept | Opcode::PopExcept | Opcode::RaiseVarargs | Opcode::RaiseError => {
+                Some("exceptions")
+            }
+            Opcode::MatchSequence
+            | Opcode::MatchMapping
+            | Opcode::MatchClass
+            | Opcode::MatchKeys
+            | Opcode::CompareOp
+            | Opcode::CompareLength => Some("match statements"),
+            Opcode::GetIter
+            | Opcode::ForIter
+            | Opcode::SetupLoop
+            | Opcode::PopBlock
+            | Opcode::BreakLoop
+            | Opcode::ContinueLoop => Some("for loops"),
+            Opcode::UnpackSequence | Opcode::UnpackEx => Some("unpacking"),
+            Opcode::DeleteAttr => Some("del statements"),
+            Opcode::LoadGlobal | Opcode::StoreGlobal => Some("global declarations"),
+            Opcode::SetItem => Some("item assignments"),
+            Opcode::SetupAsync => Some("async expressions"),
+            Opcode::BuildTuple => Some("tuples"),
+            Opcode::BinaryAnd | Opcode::BinaryOr => Some("logical operators"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -106,6 +160,14 @@ pub enum ConstantValue {
     String(String),
     Bool(bool),
     None,
+    /// Function whose body starts at instruction `entry`
+    Function {
+        name: String,
+        entry: u32,
+        /// Names of the parameters, `*args` and `**kwargs` keeping their stars so the VM
+        /// gathers the arguments left over into them
+        parameters: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -121,6 +183,8 @@ pub struct LoopInfo {
     pub start_addr: usize,
     pub break_addrs: Vec<usize>,
     pub continue_addrs: Vec<usize>,
+    /// Context managers entered when the loop started
+    pub contexts: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -180,17 +244,19 @@ pub struct CodeGenerator {
 
     // Advanced features
     varnames: Vec<String>, // Local variable names
-    freevars: Vec<String>, // Free variables (for closures)
-    cellvars: Vec<String>, // Cell variables (for closures)
     imports: Vec<ImportInfo>,
 
     // Control flow tracking
     loop_stack: Vec<LoopInfo>,
     exception_stack: Vec<ExceptionInfo>,
+    /// Context managers entered by the `with` statements being compiled
+    contexts: usize,
 
     // Scoping
     scope_stack: Vec<ScopeInfo>,
     current_scope: ScopeType,
+    /// Class and receiver of the method being compiled, which `super()` stands for
+    method: Option<(String, String)>,
 }
 
 impl CodeGenerator {
@@ -204,28 +270,44 @@ impl CodeGenerator {
 
             // Advanced features
             varnames: Vec::new(),
-            freevars: Vec::new(),
-            cellvars: Vec::new(),
             imports: Vec::new(),
 
             // Control flow tracking
             loop_stack: Vec::new(),
             exception_stack: Vec::new(),
+            contexts: 0,
 
             // Scoping
             scope_stack: Vec::new(),
             current_scope: ScopeType::Module,
+            method: None,
         }
     }
 
     pub fn generate(&mut self, program: &Program) -> Result<Vec<u8>, NagariError> {
-        for statement in &program.statements {
-            self.compile_statement(statement)?;
+        for (index, statement) in program.statements.iter().enumerate() {
+            match statement {
+                // The value of a trailing expression stays on the stack for hosts evaluating
+                // scripts
+                Statement::Expression(expr) if index + 1 == program.statements.len() => {
+                    self.compile_expression(expr)?
+                }
+                statement => self.compile_statement(statement)?,
+            }
         }
 
         // Always end with a return
         self.emit(Opcode::Return, None);
 
+        // Bytecode the VM would stop at is reported now, against the construct that needs it
+        if let Some(construct) = self
+            .instructions
+            .iter()
+            .find_map(|instruction| instruction.opcode.unsupported_construct())
+        {
+            return Err(unsupported(construct));
+        }
+
         self.serialize()
     }
 
@@ -238,6 +320,7 @@ impl CodeGenerator {
             Statement::Assignment(assign) => self.compile_assignment(assign),
             Statement::If(if_stmt) => self.compile_if(if_stmt),
             Statement::While(while_loop) => self.compile_while(while_loop),
+            Statement::For(for_loop) if for_loop.is_async => Err(unsupported("async for loops")),
             Statement::For(for_loop) => {
                 self.compile_for_loop(for_loop)?;
                 Ok(())
@@ -257,6 +340,7 @@ impl CodeGenerator {
                 if self.loop_stack.is_empty() {
                     return Err(NagariError::SemanticError("break outside loop".to_string()));
                 }
+                self.exit_loop_contexts();
                 let break_jump = self.emit_jump(Opcode::Jump);
                 if let Some(loop_info) = self.loop_stack.last_mut() {
                     loop_info.break_addrs.push(break_jump);
@@ -269,33 +353,42 @@ impl CodeGenerator {
                         "continue outside loop".to_string(),
                     ));
                 }
+                self.exit_loop_contexts();
                 let continue_jump = self.emit_jump(Opcode::Jump);
                 if let Some(loop_info) = self.loop_stack.last_mut() {
                     loop_info.continue_addrs.push(continue_jump);
                 }
                 Ok(())
             }
-            // Placeholder implementations for the remaining variants
-            Statement::AttributeAssignment(_) => Ok(()),
-            Statement::TupleAssignment(_) => Ok(()),
-            Statement::Del(_) => Ok(()),
-            Statement::With(_) => Ok(()),
-            Statement::Try(_) => Ok(()),
-            Statement::Raise(_) => Ok(()),
+            // Type aliases only matter to the type checker
             Statement::TypeAlias(_) => Ok(()),
-            Statement::Yield(_) => Ok(()),
-            Statement::YieldFrom(_) => Ok(()),
-            Statement::ClassDef(_) => Ok(()),
-            Statement::DestructuringAssignment(_) => Ok(()),
-            Statement::ArrayDestructuringAssignment(_) => Ok(()),
-            Statement::ImportDefault(_) => Ok(()),
-            Statement::ImportNamed(_) => Ok(()),
-            Statement::ImportNamespace(_) => Ok(()),
-            Statement::ImportSideEffect(_) => Ok(()),
-            Statement::ExportDefault(_) => Ok(()),
-            Statement::ExportNamed(_) => Ok(()),
-            Statement::ExportAll(_) => Ok(()),
-            Statement::ExportDeclaration(_) => Ok(()),
+            Statement::AttributeAssignment(assign) => {
+                self.compile_expression(&assign.value)?;
+                self.compile_expression(&assign.object)?;
+                let name_index = self.add_name(&assign.attribute);
+                self.emit(Opcode::StoreAttr, Some(name_index));
+                Ok(())
+            }
+            Statement::IndexAssignment(_) => Err(unsupported("item assignments")),
+            Statement::TupleAssignment(_)
+            | Statement::DestructuringAssignment(_)
+            | Statement::ArrayDestructuringAssignment(_) => Err(unsupported("unpacking")),
+            Statement::Del(_) => Err(unsupported("del statements")),
+            Statement::With(with_stmt) if with_stmt.is_async => {
+                Err(unsupported("async with statements"))
+            }
+            Statement::With(with_stmt) => self.compile_with(with_stmt),
+            Statement::Try(_) | Statement::Raise(_) => Err(unsupported("exceptions")),
+            Statement::Yield(_) | Statement::YieldFrom(_) => Err(unsupported("generators")),
+            Statement::ClassDef(class_def) => self.compile_class_def(class_def),
+            Statement::ImportDefault(_)
+            | Statement::ImportNamed(_)
+            | Statement::ImportNamespace(_)
+            | Statement::ImportSideEffect(_) => Err(unsupported("imports")),
+            Statement::ExportDefault(_)
+            | Statement::ExportNamed(_)
+            | Statement::ExportAll(_)
+            | Statement::ExportDeclaration(_) => Err(unsupported("exports")),
             Statement::Pass => {
                 self.emit_opcode(Opcode::Nop);
                 Ok(())
@@ -304,141 +397,156 @@ impl CodeGenerator {
     }
 
     fn compile_function_def(&mut self, func_def: &FunctionDef) -> Result<(), NagariError> {
-        // Create a new scope for the function
-        let mut function_locals = std::collections::HashSet::new();
+        // `super()` in a function nested in a method has no receiver to go with
+        let method = self.method.take();
+        let result = self.compile_function(func_def);
+        self.method = method;
+        result?;
+
+        let name_index = self.add_name(&func_def.name);
+        self.emit_opcode_with_arg(Opcode::StoreName, name_index);
+        Ok(())
+    }
 
-        // Add parameters to the function's local variables
-        for param in &func_def.parameters {
-            function_locals.insert(param.name.clone());
+    /// Lay out the body of `func_def` and load the function
+    fn compile_function(&mut self, func_def: &FunctionDef) -> Result<(), NagariError> {
+        if func_def.is_async {
+            return Err(unsupported("async functions"));
+        }
+        if func_def.is_generator {
+            return Err(unsupported("generators"));
+        }
+        if !func_def.decorators.is_empty() {
+            return Err(unsupported("decorators"));
+        }
+        if func_def
+            .parameters
+            .iter()
+            .any(|param| param.default_value.is_some())
+        {
+            return Err(unsupported("default parameter values"));
         }
 
-        // Save current state
-        let saved_varnames = self.varnames.clone();
-        let saved_freevars = self.freevars.clone();
-        let saved_cellvars = self.cellvars.clone();
+        // The body is laid out where the function is defined, and jumped over until it's called
+        let skip_body = self.emit_jump(Opcode::Jump);
+        let entry = self.instructions.len();
 
         // Enter function scope
         let scope_info = ScopeInfo {
             scope_type: ScopeType::Function,
-            locals: function_locals.clone(),
+            locals: func_def
+                .parameters
+                .iter()
+                .map(|param| param.name.clone())
+                .collect(),
             parent_locals: self.get_current_locals(),
         };
         self.scope_stack.push(scope_info);
-
-        // Reset function-specific state
-        self.varnames.clear();
-        self.freevars.clear();
-        self.cellvars.clear();
-
-        // Add parameters to varnames (local variables)
+        let saved_varnames = std::mem::take(&mut self.varnames);
         for param in &func_def.parameters {
             self.add_varname(param.name.clone());
         }
+        // Loops around the definition are not the body's to break out of, nor are `with`
+        // statements its to exit
+        let saved_loops = std::mem::take(&mut self.loop_stack);
+        let saved_contexts = std::mem::take(&mut self.contexts);
 
-        // Create function code object
-        let _func_start = self.instructions.len();
-
-        // Compile default parameter values if any
-        let mut defaults_count = 0;
-        for param in func_def.parameters.iter().rev() {
-            if let Some(default) = &param.default_value {
-                self.compile_expression(default)?;
-                defaults_count += 1;
-            } else {
+        let mut result = Ok(());
+        for statement in &func_def.body {
+            result = self.compile_statement(statement);
+            if result.is_err() {
                 break;
             }
         }
 
-        // Mark function as async if needed
-        // Note: FunctionDef doesn't have is_async field in current AST
-        // if func_def.is_async {
-        //     self.emit_opcode(Opcode::SetupAsync);
-        // }
-
-        // Compile function body
-        for statement in &func_def.body {
-            self.compile_statement(statement)?;
-        }
+        self.loop_stack = saved_loops;
+        self.contexts = saved_contexts;
+        self.varnames = saved_varnames;
+        self.scope_stack.pop();
+        result?;
 
         // Ensure function returns something (None if no explicit return)
-        self.emit_opcode(Opcode::LoadConst);
         let none_const = self.add_constant(ConstantValue::None);
-        self.emit_arg(none_const);
+        self.emit_opcode_with_arg(Opcode::LoadConst, none_const);
         self.emit_opcode(Opcode::Return);
+        self.patch_jump(skip_body);
+
+        let function = self.add_constant(ConstantValue::Function {
+            name: func_def.name.clone(),
+            entry: entry as u32,
+            parameters: func_def
+                .parameters
+                .iter()
+                .map(|param| match param.kind {
+                    ParameterKind::Positional => param.name.clone(),
+                    ParameterKind::Rest => format!("*{}", param.name),
+                    ParameterKind::Keywords => format!("**{}", param.name),
+                })
+                .collect(),
+        });
+        self.emit_opcode_with_arg(Opcode::LoadConst, function);
 
-        // Create function object
-        let code_idx = self.create_code_object(
-            &func_def.name,
-            func_def.parameters.len(),
-            defaults_count,
-            self.varnames.clone(),
-            self.freevars.clone(),
-            self.cellvars.clone(),
-        );
-
-        // Restore previous state
-        self.scope_stack.pop();
-        self.varnames = saved_varnames;
-        self.freevars = saved_freevars;
-        self.cellvars = saved_cellvars;
-
-        // Load the function code and create function object
-        self.emit_opcode_with_arg(Opcode::LoadConst, code_idx as u32);
-        let name_const = self.add_constant(ConstantValue::String(func_def.name.clone()));
-        self.emit_opcode_with_arg(Opcode::LoadConst, name_const);
-
-        // Handle closures - load free variables
-        let freevars_clone = self.freevars.clone();
-        for freevar in &freevars_clone {
-            let deref_idx = self.add_name(freevar);
-            self.emit_opcode_with_arg(Opcode::LoadDeref, deref_idx);
-        }
+        Ok(())
+    }
 
-        // Create function with appropriate flags
-        let mut flags = 0;
-        // Note: FunctionDef doesn't have is_async field in current AST
-        // if func_def.is_async {
-        //     flags |= 0x80; // CO_COROUTINE
-        // }
-        if defaults_count > 0 {
-            flags |= 0x01; // Has defaults
+    /// The name and base of the class, then the name and value of each method and class
+    /// attribute, built into the class
+    fn compile_class_def(&mut self, class_def: &ClassDef) -> Result<(), NagariError> {
+        if !class_def.decorators.is_empty() {
+            return Err(unsupported("decorators"));
         }
-        if !self.freevars.is_empty() {
-            flags |= 0x10; // Has free variables
+        let name = self.add_constant(ConstantValue::String(class_def.name.clone()));
+        self.emit_opcode_with_arg(Opcode::LoadConst, name);
+        match &class_def.superclass {
+            Some(base) => {
+                let base = self.add_name(base);
+                self.emit_opcode_with_arg(Opcode::LoadName, base);
+            }
+            None => {
+                let none_const = self.add_constant(ConstantValue::None);
+                self.emit_opcode_with_arg(Opcode::LoadConst, none_const);
+            }
         }
 
-        self.emit_opcode_with_arg(Opcode::MakeFunction, flags);
-
-        // Store the function in the appropriate scope
-        let func_name_idx = self.add_varname(func_def.name.clone());
-        self.emit_opcode_with_arg(Opcode::StoreName, func_name_idx);
+        let mut count = 0;
+        for statement in &class_def.body {
+            match statement {
+                Statement::FunctionDef(method) => {
+                    let name = self.add_constant(ConstantValue::String(method.name.clone()));
+                    self.emit_opcode_with_arg(Opcode::LoadConst, name);
+                    let receiver = method.parameters.first().map(|param| param.name.clone());
+                    let outer = std::mem::replace(
+                        &mut self.method,
+                        receiver.map(|receiver| (class_def.name.clone(), receiver)),
+                    );
+                    let result = self.compile_function(method);
+                    self.method = outer;
+                    result?;
+                }
+                Statement::Assignment(assign) => {
+                    let name = self.add_constant(ConstantValue::String(assign.name.clone()));
+                    self.emit_opcode_with_arg(Opcode::LoadConst, name);
+                    self.compile_expression(&assign.value)?;
+                }
+                // Docstrings and `pass`
+                Statement::Expression(Expression::Literal(Literal::String(_)))
+                | Statement::Pass => continue,
+                _ => {
+                    return Err(NagariError::SemanticError(format!(
+                        "class '{}' may only contain methods and assignments",
+                        class_def.name
+                    )))
+                }
+            }
+            count += 1;
+        }
 
+        self.emit_opcode_with_arg(Opcode::BuildClass, count);
+        let name_index = self.add_name(&class_def.name);
+        self.emit_opcode_with_arg(Opcode::StoreName, name_index);
         Ok(())
     }
 
-    fn create_code_object(
-        &mut self,
-        name: &str,
-        arg_count: usize,
-        defaults_count: usize,
-        varnames: Vec<String>,
-        freevars: Vec<String>,
-        cellvars: Vec<String>,
-    ) -> usize {
-        // This would create a code object in a real implementation
-        // For now, we'll store the necessary information as a constant
-        let code_info = format!(
-            "CODE:{}:{}:{}:{}:{}:{}",
-            name,
-            arg_count,
-            defaults_count,
-            varnames.join(","),
-            freevars.join(","),
-            cellvars.join(",")
-        );
-        self.add_constant(ConstantValue::String(code_info)) as usize
-    }
-
     fn patch_jump_to(&mut self, jump_addr: usize, target_addr: usize) {
         // Calculate the relative offset
         let _offset = target_addr as i32 - jump_addr as i32;
@@ -506,19 +614,74 @@ impl CodeGenerator {
         Ok(())
     }
 
+    /// `with a as x, b:` enters `a`, binding what entering it returns to `x`, then `b`, and
+    /// exits them in reverse order after the body. The VM exits them when the body returns.
+    fn compile_with(&mut self, with_stmt: &WithStatement) -> Result<(), NagariError> {
+        for item in &with_stmt.items {
+            self.compile_expression(&item.context_expr)?;
+            self.emit_opcode(Opcode::EnterContext);
+            match &item.optional_vars {
+                Some(name) => {
+                    let name_index = self.add_name(name);
+                    self.emit_opcode_with_arg(Opcode::StoreName, name_index);
+                }
+                None => {
+                    self.emit_opcode(Opcode::Pop);
+                }
+            }
+            self.contexts += 1;
+        }
+
+        for statement in &with_stmt.body {
+            self.compile_statement(statement)?;
+        }
+
+        for _ in &with_stmt.items {
+            self.contexts -= 1;
+            self.emit_opcode(Opcode::ExitContext);
+        }
+        Ok(())
+    }
+
+    /// Exit the `with` statements a `break` or `continue` jumps out of
+    fn exit_loop_contexts(&mut self) {
+        let entered = self
+            .loop_stack
+            .last()
+            .map_or(0, |loop_info| loop_info.contexts);
+        for _ in entered..self.contexts {
+            self.emit_opcode(Opcode::ExitContext);
+        }
+    }
+
     fn compile_while(&mut self, while_loop: &WhileLoop) -> Result<(), NagariError> {
         let loop_start = self.instructions.len();
 
         self.compile_expression(&while_loop.condition)?;
         let exit_jump = self.emit_jump(Opcode::JumpIfFalse);
 
+        self.loop_stack.push(LoopInfo {
+            start_addr: loop_start,
+            break_addrs: Vec::new(),
+            continue_addrs: Vec::new(),
+            contexts: self.contexts,
+        });
         for statement in &while_loop.body {
             self.compile_statement(statement)?;
         }
+        let loop_info = self.loop_stack.pop().unwrap();
 
         self.emit_loop(loop_start);
         self.patch_jump(exit_jump);
 
+        // `continue` re-tests the condition, `break` leaves the loop
+        for continue_addr in loop_info.continue_addrs {
+            self.patch_jump_to(continue_addr, loop_start);
+        }
+        for break_addr in loop_info.break_addrs {
+            self.patch_jump(break_addr);
+        }
+
         Ok(())
     }
 
@@ -542,6 +705,7 @@ impl CodeGenerator {
             start_addr: loop_start,
             break_addrs: Vec::new(),
             continue_addrs: Vec::new(),
+            contexts: self.contexts,
         };
         self.loop_stack.push(loop_info);
 
@@ -908,44 +1072,51 @@ impl CodeGenerator {
                 Ok(())
             }
             // Placeholder implementations for missing Expression variants
-            Expression::JSXElement(_) => {
-                // TODO: Implement JSX element compilation
-                Ok(())
+            Expression::JSXElement(_) => Err(NagariError::BytecodeError(
+                "JSX is only supported by JavaScript targets".to_string(),
+            )),
+            Expression::Lambda(_) | Expression::FunctionExpr(_) => {
+                Err(unsupported("function definitions"))
             }
-            Expression::Lambda(_) => {
-                // TODO: Implement lambda compilation
+            Expression::ListComprehension(_)
+            | Expression::DictComprehension(_)
+            | Expression::SetComprehension(_)
+            | Expression::Generator(_) => Err(unsupported("comprehensions")),
+            Expression::Ternary(ternary) => {
+                self.compile_expression(&ternary.condition)?;
+                let else_jump = self.emit_jump(Opcode::JumpIfFalse);
+                self.compile_expression(&ternary.true_expr)?;
+                let end_jump = self.emit_jump(Opcode::Jump);
+                self.patch_jump(else_jump);
+                self.compile_expression(&ternary.false_expr)?;
+                self.patch_jump(end_jump);
                 Ok(())
             }
-            Expression::ListComprehension(_) => {
-                // TODO: Implement list comprehension compilation
+            Expression::Attribute(attribute) => {
+                self.compile_expression(&attribute.object)?;
+                let name_index = self.add_name(&attribute.attribute);
+                self.emit(Opcode::LoadAttr, Some(name_index));
                 Ok(())
             }
-            Expression::DictComprehension(_) => {
-                // TODO: Implement dict comprehension compilation
+            Expression::Index(index_access) => {
+                self.compile_expression(&index_access.object)?;
+                self.compile_expression(&index_access.index)?;
+                self.emit(Opcode::GetItem, None);
                 Ok(())
             }
-            Expression::SetComprehension(_) => {
-                // TODO: Implement set comprehension compilation
-                Ok(())
-            }
-            Expression::Generator(_) => {
-                // TODO: Implement generator compilation
-                Ok(())
-            }
-            Expression::Ternary(_) => {
-                // TODO: Implement ternary compilation
-                Ok(())
-            }
-            Expression::Attribute(_) => {
-                // TODO: Implement attribute access compilation
-                Ok(())
-            }
-            Expression::Index(_) => {
-                // TODO: Implement index access compilation
-                Ok(())
-            }
-            Expression::Slice(_) => {
-                // TODO: Implement slice compilation
+            Expression::Slice(slice) => {
+                // Object, start, stop and step (none when omitted), then BuildSlice
+                self.compile_expression(&slice.object)?;
+                for bound in [&slice.start, &slice.end, &slice.step] {
+                    match bound {
+                        Some(bound) => self.compile_expression(bound)?,
+                        None => {
+                            let none_const = self.add_constant(ConstantValue::None);
+                            self.emit(Opcode::LoadConst, Some(none_const));
+                        }
+                    }
+                }
+                self.emit(Opcode::BuildSlice, Some(3));
                 Ok(())
             }
             Expression::Tuple(elements) => {
@@ -962,14 +1133,18 @@ impl CodeGenerator {
                 self.emit(Opcode::BuildSet, Some(elements.len() as u32));
                 Ok(())
             }
-            Expression::Unary(_) => {
-                // TODO: Implement unary expression compilation
-                Ok(())
-            }
-            Expression::NamedExpr(_) => {
-                // TODO: Implement named expression (walrus operator) compilation
+            Expression::Unary(unary) => {
+                self.compile_expression(&unary.operand)?;
+                let opcode = match unary.operator {
+                    UnaryOperator::Plus => Opcode::UnaryPositive,
+                    UnaryOperator::Minus => Opcode::UnaryNegative,
+                    UnaryOperator::Not => Opcode::UnaryNot,
+                    UnaryOperator::BitwiseNot => Opcode::UnaryInvert,
+                };
+                self.emit(opcode, None);
                 Ok(())
             }
+            Expression::NamedExpr(_) => Err(unsupported("assignment expressions")),
             Expression::Dictionary(pairs) => {
                 for (key, value) in pairs {
                     self.compile_expression(key)?;
@@ -978,31 +1153,20 @@ impl CodeGenerator {
                 self.emit(Opcode::BuildDict, Some(pairs.len() as u32));
                 Ok(())
             }
-            Expression::Subscript(_) => {
-                // TODO: Implement subscript compilation
-                Ok(())
-            }
-            Expression::FunctionExpr(_) => {
-                // TODO: Implement function expression compilation
-                Ok(())
-            }
+            Expression::Subscript(_) => Err(unsupported("subscripts")),
             Expression::Async(expr) => {
                 self.compile_expression(expr)?;
                 self.emit(Opcode::SetupAsync, None);
                 Ok(())
             }
-            Expression::Spread(_) => {
-                // TODO: Implement spread operator compilation
-                Ok(())
-            }
-            Expression::TemplateLiteral(_) => {
-                // TODO: Implement template literal compilation
-                Ok(())
-            }
-            Expression::FString(_) => {
-                // TODO: Implement f-string compilation
-                Ok(())
+            Expression::Spread(_) => Err(unsupported("spreads")),
+            Expression::TemplateLiteral(_) | Expression::FString(_) => {
+                Err(unsupported("f-strings"))
             }
+            Expression::InlineJs(_) => Err(NagariError::BytecodeError(
+                "inline JavaScript (js\"\"\"...\"\"\") is only supported by JavaScript targets"
+                    .to_string(),
+            )),
         }
     }
 
@@ -1021,6 +1185,10 @@ impl CodeGenerator {
     }
 
     fn compile_binary(&mut self, binary: &BinaryExpression) -> Result<(), NagariError> {
+        if matches!(binary.operator, BinaryOperator::And | BinaryOperator::Or) {
+            return self.compile_logical(binary);
+        }
+
         self.compile_expression(&binary.left)?;
         self.compile_expression(&binary.right)?;
 
@@ -1038,12 +1206,38 @@ impl CodeGenerator {
             BinaryOperator::GreaterEqual => Opcode::BinaryGreaterEqual,
             BinaryOperator::And => Opcode::BinaryAnd,
             BinaryOperator::Or => Opcode::BinaryOr,
+            BinaryOperator::Power => Opcode::BinaryPower,
+            BinaryOperator::FloorDivide => Opcode::BinaryFloorDivide,
+            BinaryOperator::BitwiseAnd => Opcode::BinaryBitAnd,
+            BinaryOperator::BitwiseOr => Opcode::BinaryBitOr,
+            BinaryOperator::BitwiseXor => Opcode::BinaryBitXor,
+            BinaryOperator::LeftShift => Opcode::BinaryLeftShift,
+            BinaryOperator::RightShift => Opcode::BinaryRightShift,
         };
 
         self.emit(opcode, None);
         Ok(())
     }
 
+    /// `and` and `or` short-circuit, leaving the operand that decided the result
+    fn compile_logical(&mut self, binary: &BinaryExpression) -> Result<(), NagariError> {
+        self.compile_expression(&binary.left)?;
+        self.emit(Opcode::DupTop, None);
+        let left_false = self.emit_jump(Opcode::JumpIfFalse);
+        if matches!(binary.operator, BinaryOperator::And) {
+            self.emit(Opcode::Pop, None);
+            self.compile_expression(&binary.right)?;
+            self.patch_jump(left_false);
+        } else {
+            let end = self.emit_jump(Opcode::Jump);
+            self.patch_jump(left_false);
+            self.emit(Opcode::Pop, None);
+            self.compile_expression(&binary.right)?;
+            self.patch_jump(end);
+        }
+        Ok(())
+    }
+
     fn compile_call(&mut self, call: &CallExpression) -> Result<(), NagariError> {
         // Special case for print function
         if let Expression::Identifier(name) = &*call.function {
@@ -1056,11 +1250,54 @@ impl CodeGenerator {
             }
         }
 
+        // `super()` in a method is `super(Class, self)`
+        if matches!(call.function.as_ref(), Expression::Identifier(name) if name == "super")
+            && call.arguments.is_empty()
+        {
+            let Some((class, receiver)) = self.method.clone() else {
+                return Err(NagariError::SemanticError(
+                    "super() without arguments can only be used in methods".to_string(),
+                ));
+            };
+            for name in ["super", class.as_str(), receiver.as_str()] {
+                let name_index = self.add_name(name);
+                self.emit(Opcode::LoadName, Some(name_index));
+            }
+            self.emit(Opcode::CallFunc, Some(2));
+            return Ok(());
+        }
+
         self.compile_expression(&call.function)?;
         for arg in &call.arguments {
             self.compile_expression(arg)?;
         }
-        self.emit(Opcode::CallFunc, Some(call.arguments.len() as u32));
+        if call.keyword_args.is_empty() {
+            self.emit(Opcode::CallFunc, Some(call.arguments.len() as u32));
+            return Ok(());
+        }
+
+        // The values of the keyword arguments follow the positional ones, and a list of their
+        // names goes on top, with `**` for a dict of keyword arguments. The VM matches them to
+        // the parameters of what is called.
+        for (_, value) in &call.keyword_args {
+            match value {
+                Expression::Spread(mapping) => self.compile_expression(mapping)?,
+                value => self.compile_expression(value)?,
+            }
+        }
+        for (name, value) in &call.keyword_args {
+            let name = match value {
+                Expression::Spread(_) => "**",
+                _ => name.as_str(),
+            };
+            let name = self.add_constant(ConstantValue::String(name.to_string()));
+            self.emit_opcode_with_arg(Opcode::LoadConst, name);
+        }
+        self.emit_opcode_with_arg(Opcode::BuildList, call.keyword_args.len() as u32);
+        self.emit(
+            Opcode::CallFuncKw,
+            Some((call.arguments.len() + call.keyword_args.len()) as u32),
+        );
         Ok(())
     }
 
@@ -1078,15 +1315,6 @@ impl CodeGenerator {
         self.emit(opcode, Some(arg))
     }
 
-    fn emit_arg(&mut self, arg: u32) -> usize {
-        let instruction = Instruction {
-            opcode: Opcode::Nop, // Use Nop as placeholder for arg-only instructions
-            operand: Some(arg),
-        };
-        self.instructions.push(instruction);
-        self.instructions.len() - 1
-    }
-
     fn add_varname(&mut self, name: String) -> u32 {
         // Check if the variable name already exists
         for (index, var) in self.varnames.iter().enumerate() {
@@ -1144,7 +1372,7 @@ impl CodeGenerator {
         bytecode.extend_from_slice(b"NAG\x00");
 
         // Version
-        bytecode.extend_from_slice(&[0, 1]); // Version 0.1
+        bytecode.extend_from_slice(&1u16.to_le_bytes());
 
         // Constants section
         bytecode.extend_from_slice(&(self.constants.len() as u32).to_le_bytes());
@@ -1199,11 +1427,33 @@ impl CodeGenerator {
             ConstantValue::None => {
                 bytecode.push(4); // Type tag for none
             }
+            ConstantValue::Function {
+                name,
+                entry,
+                parameters,
+            } => {
+                bytecode.push(5); // Type tag for function
+                bytecode.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                bytecode.extend_from_slice(name.as_bytes());
+                bytecode.extend_from_slice(&entry.to_le_bytes());
+                bytecode.extend_from_slice(&(parameters.len() as u32).to_le_bytes());
+                for parameter in parameters {
+                    bytecode.extend_from_slice(&(parameter.len() as u32).to_le_bytes());
+                    bytecode.extend_from_slice(parameter.as_bytes());
+                }
+            }
         }
         Ok(())
     }
 }
 
+/// Error for a construct `nagrun` cannot execute yet
+fn unsupported(construct: &str) -> NagariError {
+    NagariError::BytecodeError(format!(
+        "{construct} are not supported by the bytecode target yet"
+    ))
+}
+
 pub fn generate(program: &Program) -> Result<Vec<u8>, NagariError> {
     let mut generator = CodeGenerator::new();
     generator.generate(program)
@@ -1212,213 +1462,164 @@ pub fn generate(program: &Program) -> Result<Vec<u8>, NagariError> {
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    fn create_test_generator() -> CodeGenerator {
-        CodeGenerator::new()
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The generator after generating bytecode for `source`, and the result
+    fn generate_source(source: &str) -> (CodeGenerator, Result<Vec<u8>, NagariError>) {
+        let tokens = Lexer::new(source)
+            .tokenize()
+            .expect("test source should lex");
+        let program = Parser::new(tokens)
+            .parse()
+            .expect("test source should parse");
+        let mut generator = CodeGenerator::new();
+        let result = generator.generate(&program);
+        (generator, result)
     }
 
-    fn create_simple_program(statements: Vec<Statement>) -> Program {
-        Program { statements }
+    fn opcodes(generator: &CodeGenerator) -> Vec<u8> {
+        generator
+            .instructions
+            .iter()
+            .map(|instruction| instruction.opcode as u8)
+            .collect()
     }
 
-    #[test]
-    fn test_import_simple() {
-        let mut generator = create_test_generator();
-        let import_stmt = Statement::Import {
-            source: "math".to_string(),
-            items: vec![],
-        };
-
-        assert!(generator.compile_statement(&import_stmt).is_ok());
-
-        // Verify that import info was recorded
-        assert_eq!(generator.imports.len(), 1);
-        assert_eq!(generator.imports[0].module_name, "math");
-        assert!(!generator.imports[0].is_star_import);
+    /// Whether `second` directly follows `first` somewhere
+    fn opcodes_contains_pair(generator: &CodeGenerator, first: Opcode, second: Opcode) -> bool {
+        opcodes(generator)
+            .windows(2)
+            .any(|pair| pair == [first as u8, second as u8])
+    }
 
-        // Verify bytecode was generated
-        assert!(!generator.instructions.is_empty());
+    fn error_message(result: Result<Vec<u8>, NagariError>) -> String {
+        match result {
+            Err(NagariError::BytecodeError(message) | NagariError::SemanticError(message)) => {
+                message
+            }
+            other => panic!("expected a bytecode error, got {other:?}"),
+        }
     }
 
     #[test]
-    fn test_import_from() {
-        let mut generator = create_test_generator();
-        let import_stmt = Statement::Import {
-            source: "math".to_string(),
-            items: vec![
-                ImportItem {
-                    name: "sqrt".to_string(),
-                    alias: None,
-                },
-                ImportItem {
-                    name: "pi".to_string(),
-                    alias: Some("PI".to_string()),
-                },
-            ],
-        };
-
-        assert!(generator.compile_statement(&import_stmt).is_ok());
+    fn test_functions_become_constants() {
+        let (generator, result) = generate_source("def add(a, b):\n    return a + b\n");
+        assert!(result.is_ok(), "{result:?}");
 
-        // Verify import info
-        assert_eq!(generator.imports.len(), 1);
-        assert_eq!(generator.imports[0].names, vec!["sqrt", "pi"]);
+        let function = generator
+            .constants
+            .iter()
+            .find_map(|constant| match &constant.value {
+                ConstantValue::Function {
+                    name, parameters, ..
+                } => Some((name.clone(), parameters.clone())),
+                _ => None,
+            });
         assert_eq!(
-            generator.imports[0].aliases,
-            vec![None, Some("PI".to_string())]
+            function,
+            Some(("add".to_string(), vec!["a".to_string(), "b".to_string()]))
         );
+        assert!(opcodes(&generator).contains(&(Opcode::BinaryAdd as u8)));
     }
 
     #[test]
-    fn test_function_compilation() {
-        let mut generator = create_test_generator();
-        let func_stmt = Statement::Function {
-            name: "add".to_string(),
-            parameters: vec![
-                FunctionParameter {
-                    name: "a".to_string(),
-                    type_annotation: None,
-                    default_value: None,
-                },
-                FunctionParameter {
-                    name: "b".to_string(),
-                    type_annotation: None,
-                    default_value: Some(Expression::Literal(Literal::Number(0.0))),
-                },
-            ],
-            body: vec![Statement::Return(Some(Expression::Binary {
-                left: Box::new(Expression::Identifier("a".to_string())),
-                operator: BinaryOperator::Add,
-                right: Box::new(Expression::Identifier("b".to_string())),
-            }))],
-            is_async: false,
-            return_type: None,
-        };
+    fn test_trailing_expression_stays_on_the_stack() {
+        let (generator, result) = generate_source("x = 1\nx + 2\n");
+        assert!(result.is_ok(), "{result:?}");
 
-        assert!(generator.compile_statement(&func_stmt).is_ok());
+        let opcodes = opcodes(&generator);
+        assert_eq!(
+            opcodes[opcodes.len() - 2..],
+            [Opcode::BinaryAdd as u8, Opcode::Return as u8]
+        );
 
-        // Verify function was created
-        assert!(generator.varnames.contains(&"add".to_string()));
-        assert!(!generator.instructions.is_empty());
+        // Other expression statements are popped
+        let (generator, _) = generate_source("x = 1\nx + 2\nx = 3\n");
+        assert!(opcodes_contains_pair(
+            &generator,
+            Opcode::BinaryAdd,
+            Opcode::Pop
+        ));
     }
 
     #[test]
-    fn test_for_loop_compilation() {
-        let mut generator = create_test_generator();
-        let for_stmt = Statement::For {
-            variable: "i".to_string(),
-            iterable: Expression::List(vec![
-                Expression::Literal(Literal::Number(1.0)),
-                Expression::Literal(Literal::Number(2.0)),
-                Expression::Literal(Literal::Number(3.0)),
-            ]),
-            body: vec![Statement::Expression(Expression::Call {
-                function: Box::new(Expression::Identifier("print".to_string())),
-                arguments: vec![Expression::Identifier("i".to_string())],
-            })],
-        };
-
-        assert!(generator.compile_statement(&for_stmt).is_ok());
+    fn test_unsupported_constructs_are_reported() {
+        let cases = [
+            ("import math\n", "imports"),
+            ("for i in [1, 2]:\n    print(i)\n", "for loops"),
+            ("a, b = 1, 2\n", "unpacking"),
+            ("del x\n", "del statements"),
+        ];
+        for (source, construct) in cases {
+            let (_, result) = generate_source(source);
+            assert_eq!(
+                error_message(result),
+                format!("{construct} are not supported by the bytecode target yet"),
+                "{source:?}"
+            );
+        }
+    }
 
-        // Verify loop opcodes were generated
-        let has_get_iter = generator
-            .instructions
+    #[test]
+    fn test_keyword_arguments_follow_the_positional_ones() {
+        // The old parser doesn't read `**mapping` arguments
+        let program = crate::test_program("f(1, b=2, **options)\n");
+        let mut generator = CodeGenerator::new();
+        let result = generator.generate(&program);
+        assert!(result.is_ok(), "{result:?}");
+
+        let names: Vec<_> = generator
+            .constants
             .iter()
-            .any(|inst| matches!(inst.opcode, Opcode::GetIter));
-        let has_for_iter = generator
+            .filter_map(|constant| match &constant.value {
+                ConstantValue::String(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["b", "**"]);
+        let call = generator
             .instructions
             .iter()
-            .any(|inst| matches!(inst.opcode, Opcode::ForIter));
-
-        assert!(has_get_iter);
-        assert!(has_for_iter);
-    }
-
-    #[test]
-    fn test_break_outside_loop_error() {
-        let mut generator = create_test_generator();
-        let break_stmt = Statement::Break;
-
-        assert!(generator.compile_statement(&break_stmt).is_err());
-    }
-
-    #[test]
-    fn test_continue_outside_loop_error() {
-        let mut generator = create_test_generator();
-        let continue_stmt = Statement::Continue;
-
-        assert!(generator.compile_statement(&continue_stmt).is_err());
+            .find(|instruction| matches!(instruction.opcode, Opcode::CallFuncKw))
+            .expect("the call should pass keyword arguments");
+        assert_eq!(call.operand, Some(3));
+        assert!(opcodes_contains_pair(
+            &generator,
+            Opcode::BuildList,
+            Opcode::CallFuncKw
+        ));
     }
 
     #[test]
-    fn test_pattern_matching_literal() {
-        let mut generator = create_test_generator();
-        let match_stmt = MatchStatement {
-            expression: Expression::Literal(Literal::Number(42.0)),
-            cases: vec![
-                MatchCase {
-                    pattern: Pattern::Literal(ConstantValue::Int(42)),
-                    guard: None,
-                    body: vec![Statement::Return(Some(Expression::Literal(
-                        Literal::String("matched".to_string()),
-                    )))],
-                },
-                MatchCase {
-                    pattern: Pattern::Wildcard,
-                    guard: None,
-                    body: vec![Statement::Return(Some(Expression::Literal(
-                        Literal::String("default".to_string()),
-                    )))],
-                },
-            ],
-        };
-
-        assert!(generator.compile_match(&match_stmt).is_ok());
-    }
-
-    #[test]
-    fn test_constant_serialization() {
-        let mut generator = create_test_generator();
-
-        // Add various constants
-        let int_idx = generator.add_constant(ConstantValue::Int(42));
-        let float_idx = generator.add_constant(ConstantValue::Float(3.14));
-        let string_idx = generator.add_constant(ConstantValue::String("hello".to_string()));
-        let bool_idx = generator.add_constant(ConstantValue::Bool(true));
-        let none_idx = generator.add_constant(ConstantValue::None);
-
-        // Verify constants were added
-        assert_eq!(generator.constants.len(), 5);
-        assert_ne!(int_idx, float_idx);
-        assert_ne!(float_idx, string_idx);
-
-        // Test serialization
-        let mut bytecode = Vec::new();
-        for constant in &generator.constants {
-            assert!(generator
-                .serialize_constant(constant, &mut bytecode)
-                .is_ok());
+    fn test_break_and_continue_outside_loop_are_errors() {
+        for (statement, message) in [
+            (Statement::Break, "break outside loop"),
+            (Statement::Continue, "continue outside loop"),
+        ] {
+            let program = Program {
+                statements: vec![statement],
+            };
+            let result = CodeGenerator::new().generate(&program);
+            assert_eq!(error_message(result), message);
         }
 
-        // Verify bytecode was generated
-        assert!(!bytecode.is_empty());
+        let (_, result) = generate_source("while true:\n    break\n");
+        assert!(result.is_ok(), "{result:?}");
     }
 
     #[test]
-    fn test_variable_name_management() {
-        let mut generator = create_test_generator();
-
-        let var1_idx = generator.add_varname("variable1".to_string());
-        let var2_idx = generator.add_varname("variable2".to_string());
-        let var1_idx_duplicate = generator.add_varname("variable1".to_string());
-
-        // First two should be different
-        assert_ne!(var1_idx, var2_idx);
-
-        // Adding same variable again should return same index
-        assert_eq!(var1_idx, var1_idx_duplicate);
-
-        // Verify variables are stored
-        assert!(generator.varnames.contains(&"variable1".to_string()));
-        assert!(generator.varnames.contains(&"variable2".to_string()));
+    fn test_constants_and_names_are_shared() {
+        let mut generator = CodeGenerator::new();
+        let first = generator.add_constant(ConstantValue::Int(42));
+        let string = generator.add_constant(ConstantValue::String("42".to_string()));
+        assert_ne!(first, string);
+        assert_eq!(generator.add_constant(ConstantValue::Int(42)), first);
+        assert_eq!(generator.constants.len(), 2);
+
+        let name = generator.add_name("x");
+        assert_eq!(generator.add_name("x"), name);
+        let variable = generator.add_varname("y".to_string());
+        assert_eq!(generator.add_varname("y".to_string()), variable);
     }
 }
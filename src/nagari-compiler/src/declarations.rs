@@ -0,0 +1,705 @@
+//! TypeScript declarations (`.d.ts`) for compiled modules.
+//!
+//! Functions, classes, constants and type aliases at the top level of a module are declared
+//! with the types from their annotations, so TypeScript projects get checked calls and
+//! completion when they use a Nagari library. Docstrings become JSDoc comments.
+//!
+//! Unannotated parameters are typed from their default value, unannotated constants from their
+//! value and unannotated functions return `void` when they never return a value. Everything
+//! else without a type TypeScript can name, such as a class used as an annotation, is `any`.
+//!
+//! A module with `export` statements declares what they export. Any other module declares every
+//! top-level name that doesn't start with an underscore.
+//!
+//! [`jsdoc`] puts the same types in JSDoc comments above the functions of the JavaScript output
+//! itself, for consumers without the `.d.ts` file.
+
+use crate::ast::*;
+use crate::transpiler::js_parameters;
+use crate::types::Type;
+use std::collections::{HashMap, HashSet};
+
+/// Declarations for `program`, as the contents of a `.d.ts` file
+pub fn generate(program: &Program) -> String {
+    let exports = Exports::of(program);
+    let mut out = String::from("// Generated TypeScript declarations\n");
+    let mut declared = HashSet::new();
+    let mut any_declared = false;
+
+    for statement in &program.statements {
+        let statement = match statement {
+            Statement::ExportDeclaration(export) => export.declaration.as_ref(),
+            statement => statement,
+        };
+        let Some(name) = declared_name(statement) else {
+            continue;
+        };
+        if !exports.declares(name) || !declared.insert(name) {
+            continue;
+        }
+        let export = if exports.named(name) { "export " } else { "" };
+
+        out.push('\n');
+        match statement {
+            Statement::FunctionDef(function) => {
+                push_doc(&mut out, &function.body, "");
+                out.push_str(&format!(
+                    "{export}declare function {}{};\n",
+                    function.name,
+                    signature(function, false)
+                ));
+            }
+            Statement::ClassDef(class) => push_class(&mut out, class, export),
+            Statement::Assignment(assignment) => {
+                let kind = if exports.reassigned.contains(assignment.name.as_str()) {
+                    "let"
+                } else {
+                    "const"
+                };
+                let value_type = match &assignment.var_type {
+                    Some(var_type) => ts_type(var_type),
+                    None => value_type(&assignment.value),
+                };
+                out.push_str(&format!(
+                    "{export}declare {kind} {}: {value_type};\n",
+                    assignment.name
+                ));
+            }
+            Statement::TypeAlias(alias) => {
+                out.push_str(&format!(
+                    "{export}type {} = {};\n",
+                    alias.name,
+                    ts_type(&alias.type_expr)
+                ));
+            }
+            _ => unreachable!("declared_name only names declarations"),
+        }
+        any_declared = true;
+    }
+
+    if let Some(default) = &exports.default {
+        out.push_str(&format!("\nexport default {default};\n"));
+    } else if !any_declared {
+        out.push_str("export {};\n");
+    }
+    out
+}
+
+/// The TypeScript spelling of `t`
+pub fn ts_type(t: &Type) -> String {
+    let join = |types: &[Type], separator: &str| {
+        types
+            .iter()
+            .map(ts_type)
+            .collect::<Vec<_>>()
+            .join(separator)
+    };
+    match t {
+        Type::Int | Type::Float => "number".to_string(),
+        Type::Str | Type::String => "string".to_string(),
+        Type::Bool => "boolean".to_string(),
+        Type::None => "null".to_string(),
+        Type::Unknown => "unknown".to_string(),
+        Type::Never => "never".to_string(),
+        Type::List(element) | Type::Array(element) => format!("{}[]", operand(element)),
+        Type::Set(element) => format!("Set<{}>", ts_type(element)),
+        Type::Tuple(elements) => format!("[{}]", join(elements, ", ")),
+        Type::Dict(key, value) | Type::Record(key, value) => {
+            format!("Record<{}, {}>", key_type(key), ts_type(value))
+        }
+        Type::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(name, _)| name.as_str());
+            let fields: Vec<String> = fields
+                .into_iter()
+                .map(|(name, field)| format!("{name}: {}", ts_type(field)))
+                .collect();
+            format!("{{ {} }}", fields.join("; "))
+        }
+        Type::Function(parameters, return_type) => {
+            let parameters: Vec<String> = parameters
+                .iter()
+                .enumerate()
+                .map(|(index, parameter)| format!("arg{index}: {}", ts_type(parameter)))
+                .collect();
+            format!(
+                "({}) => {}",
+                parameters.join(", "),
+                return_type_of(return_type)
+            )
+        }
+        Type::Union(union) => join(&union.types, " | "),
+        Type::Intersection(intersection) => join(&intersection.types, " & "),
+        Type::Partial(inner) => format!("Partial<{}>", ts_type(inner)),
+        Type::Required(inner) => format!("Required<{}>", ts_type(inner)),
+        Type::Readonly(inner) => format!("Readonly<{}>", ts_type(inner)),
+        Type::NonNullable(inner) => format!("NonNullable<{}>", ts_type(inner)),
+        _ => "any".to_string(),
+    }
+}
+
+/// `t` where it is followed by `[]`, parenthesized if it wouldn't bind tightly enough
+fn operand(t: &Type) -> String {
+    match t {
+        Type::Union(_) | Type::Intersection(_) | Type::Function(..) => format!("({})", ts_type(t)),
+        _ => ts_type(t),
+    }
+}
+
+/// `t` as a `Record` key, which can only be a string, number or symbol
+fn key_type(t: &Type) -> &'static str {
+    match t {
+        Type::Int | Type::Float => "number",
+        _ => "string",
+    }
+}
+
+/// `t` as a return type, where Nagari's `None` means nothing is returned
+fn return_type_of(t: &Type) -> String {
+    match t {
+        Type::None => "void".to_string(),
+        t => ts_type(t),
+    }
+}
+
+/// Which top-level names the module exports
+struct Exports<'a> {
+    /// Names in `export` statements, or `None` if the module has none
+    explicit: Option<HashSet<&'a str>>,
+    /// The identifier exported as default
+    default: Option<&'a str>,
+    /// Top-level names assigned more than once
+    reassigned: HashSet<&'a str>,
+}
+
+impl<'a> Exports<'a> {
+    fn of(program: &'a Program) -> Self {
+        let mut explicit: Option<HashSet<&str>> = None;
+        let mut default = None;
+        let mut assignments: HashMap<&str, usize> = HashMap::new();
+
+        for statement in &program.statements {
+            match statement {
+                Statement::ExportDeclaration(export) => {
+                    if let Some(name) = declared_name(&export.declaration) {
+                        explicit.get_or_insert_with(HashSet::new).insert(name);
+                    }
+                }
+                Statement::ExportNamed(export) if export.module.is_none() => {
+                    explicit
+                        .get_or_insert_with(HashSet::new)
+                        .extend(export.exports.iter().map(String::as_str));
+                }
+                Statement::ExportDefault(export) => {
+                    explicit.get_or_insert_with(HashSet::new);
+                    if let Expression::Identifier(name) = &export.value {
+                        default = Some(name.as_str());
+                    }
+                }
+                Statement::Assignment(assignment) => {
+                    *assignments.entry(&assignment.name).or_default() += 1;
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            explicit,
+            default,
+            reassigned: assignments
+                .into_iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+
+    /// Whether `name` is exported by name
+    fn named(&self, name: &str) -> bool {
+        match &self.explicit {
+            Some(names) => names.contains(name),
+            None => !name.starts_with('_'),
+        }
+    }
+
+    /// Whether `name` needs a declaration: it is exported by name or as default
+    fn declares(&self, name: &str) -> bool {
+        self.named(name) || self.default == Some(name)
+    }
+}
+
+/// The name `statement` declares, if it is a declaration
+fn declared_name(statement: &Statement) -> Option<&str> {
+    match statement {
+        Statement::FunctionDef(function) => Some(&function.name),
+        Statement::ClassDef(class) => Some(&class.name),
+        Statement::Assignment(assignment) => Some(&assignment.name),
+        Statement::TypeAlias(alias) => Some(&alias.name),
+        _ => None,
+    }
+}
+
+fn push_class(out: &mut String, class: &ClassDef, export: &str) {
+    let superclass = class
+        .superclass
+        .as_ref()
+        .map(|superclass| format!(" extends {superclass}"))
+        .unwrap_or_default();
+    out.push_str(&format!(
+        "{export}declare class {}{superclass} {{\n",
+        class.name
+    ));
+
+    let constructor = class.body.iter().find_map(|statement| match statement {
+        Statement::FunctionDef(function) if is_constructor(function) => Some(function),
+        _ => None,
+    });
+
+    // Class-level assignments are static properties; the attributes the constructor sets are
+    // instance ones
+    let mut properties: Vec<(&str, &str, String)> = Vec::new();
+    for statement in &class.body {
+        if let Statement::Assignment(assignment) = statement {
+            let property_type = match &assignment.var_type {
+                Some(var_type) => ts_type(var_type),
+                None => value_type(&assignment.value),
+            };
+            properties.push(("static ", &assignment.name, property_type));
+        }
+    }
+    if let Some(constructor) = constructor {
+        for statement in &constructor.body {
+            let Statement::AttributeAssignment(assignment) = statement else {
+                continue;
+            };
+            if !matches!(&assignment.object, Expression::Identifier(receiver) if receiver == "self" || receiver == "this")
+            {
+                continue;
+            }
+            // `self.name = name` takes the parameter's type
+            let parameter_type = match &assignment.value {
+                Expression::Identifier(value) => constructor
+                    .parameters
+                    .iter()
+                    .find(|parameter| parameter.name == *value)
+                    .map(parameter_type),
+                _ => None,
+            };
+            properties.push((
+                "",
+                &assignment.attribute,
+                parameter_type.unwrap_or_else(|| value_type(&assignment.value)),
+            ));
+        }
+    }
+    let mut seen = HashSet::new();
+    for (modifier, name, property_type) in properties {
+        if !name.starts_with('_') && seen.insert(name) {
+            out.push_str(&format!("    {modifier}{name}: {property_type};\n"));
+        }
+    }
+
+    if let Some(constructor) = constructor {
+        push_doc(out, &constructor.body, "    ");
+        let parameters = parameters(constructor, true);
+        out.push_str(&format!("    constructor({parameters});\n"));
+    }
+    for statement in &class.body {
+        let Statement::FunctionDef(method) = statement else {
+            continue;
+        };
+        let decorator = method
+            .decorators
+            .first()
+            .map(|decorator| decorator.name.as_str());
+        // A property's setter shares its name with the getter
+        let setter = decorator.is_some_and(|decorator| decorator.ends_with(".setter"));
+        if is_constructor(method)
+            || method.name.starts_with('_')
+            || !(seen.insert(&method.name) || setter)
+        {
+            continue;
+        }
+        push_doc(out, &method.body, "    ");
+        let declaration = match decorator {
+            Some("property") => format!("get {}(): {}", method.name, return_type(method)),
+            _ if setter => format!("set {}({})", method.name, parameters(method, true)),
+            Some("staticmethod") => format!("static {}{}", method.name, signature(method, false)),
+            Some("classmethod") => format!("static {}{}", method.name, signature(method, true)),
+            _ => format!("{}{}", method.name, signature(method, true)),
+        };
+        out.push_str(&format!("    {declaration};\n"));
+    }
+    out.push_str("}\n");
+}
+
+fn is_constructor(function: &FunctionDef) -> bool {
+    function.name == "__init__" || function.name == "constructor"
+}
+
+/// `(parameters): return type`, leaving out a method's `self` parameter
+fn signature(function: &FunctionDef, method: bool) -> String {
+    format!(
+        "({}): {}",
+        parameters(function, method),
+        return_type(function)
+    )
+}
+
+/// What calling `function` gives, a promise if it is async
+fn return_type(function: &FunctionDef) -> String {
+    let return_type = match &function.return_type {
+        Some(return_type) => return_type_of(return_type),
+        None if returns_value(&function.body) => "any".to_string(),
+        None => "void".to_string(),
+    };
+    if function.is_generator {
+        "Generator<any>".to_string()
+    } else if function.is_async {
+        format!("Promise<{return_type}>")
+    } else {
+        return_type
+    }
+}
+
+/// How many parameters of `function` stand for the instance or class it is called on
+fn receivers(function: &FunctionDef, method: bool) -> usize {
+    usize::from(method && !function.parameters.is_empty())
+}
+
+/// The parameters in the order of the JavaScript function, whose `*args` are a rest parameter
+/// and `**kwargs` an options object
+fn parameters(function: &FunctionDef, method: bool) -> String {
+    js_parameters(&function.parameters[receivers(function, method)..])
+        .into_iter()
+        .map(|parameter| match parameter.kind {
+            ParameterKind::Positional => {
+                let optional = if parameter.default_value.is_some() {
+                    "?"
+                } else {
+                    ""
+                };
+                format!(
+                    "{}{optional}: {}",
+                    parameter.name,
+                    parameter_type(parameter)
+                )
+            }
+            ParameterKind::Rest => {
+                format!(
+                    "...{}: Array<{}>",
+                    parameter.name,
+                    parameter_type(parameter)
+                )
+            }
+            ParameterKind::Keywords => format!(
+                "{}?: Record<string, {}>",
+                parameter.name,
+                parameter_type(parameter)
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parameter_type(parameter: &Parameter) -> String {
+    match (&parameter.param_type, &parameter.default_value) {
+        (Some(param_type), _) => ts_type(param_type),
+        (None, Some(default_value)) => value_type(default_value),
+        (None, None) => "any".to_string(),
+    }
+}
+
+/// The type of a constant's value, as far as it can be told without checking the program
+fn value_type(value: &Expression) -> String {
+    match value {
+        Expression::Literal(Literal::Int(_) | Literal::Float(_)) => "number".to_string(),
+        Expression::Literal(Literal::String(_))
+        | Expression::FString(_)
+        | Expression::TemplateLiteral(_) => "string".to_string(),
+        Expression::Literal(Literal::Bool(_)) => "boolean".to_string(),
+        Expression::Literal(Literal::None) => "null".to_string(),
+        Expression::Unary(unary) if matches!(unary.operator, UnaryOperator::Not) => {
+            "boolean".to_string()
+        }
+        Expression::Unary(unary) => value_type(&unary.operand),
+        Expression::List(elements) => {
+            let mut types: Vec<String> = elements.iter().map(value_type).collect();
+            types.dedup();
+            match types.as_slice() {
+                [element] if element != "any" => format!("{element}[]"),
+                _ => "any[]".to_string(),
+            }
+        }
+        Expression::Dict(_) | Expression::Dictionary(_) => "Record<string, any>".to_string(),
+        Expression::Set(_) => "Set<any>".to_string(),
+        _ => "any".to_string(),
+    }
+}
+
+/// Whether any `return` in `body`, outside of nested functions, returns a value
+fn returns_value(body: &[Statement]) -> bool {
+    body.iter().any(|statement| match statement {
+        Statement::Return(value) => value.is_some(),
+        Statement::If(if_statement) => {
+            returns_value(&if_statement.then_branch)
+                || if_statement
+                    .elif_branches
+                    .iter()
+                    .any(|branch| returns_value(&branch.body))
+                || if_statement
+                    .else_branch
+                    .as_deref()
+                    .is_some_and(returns_value)
+        }
+        Statement::While(while_loop) => returns_value(&while_loop.body),
+        Statement::For(for_loop) => returns_value(&for_loop.body),
+        Statement::With(with) => returns_value(&with.body),
+        Statement::Match(match_statement) => match_statement
+            .cases
+            .iter()
+            .any(|case| returns_value(&case.body)),
+        Statement::Try(try_statement) => {
+            returns_value(&try_statement.body)
+                || try_statement
+                    .except_handlers
+                    .iter()
+                    .any(|handler| returns_value(&handler.body))
+                || try_statement
+                    .else_clause
+                    .as_deref()
+                    .is_some_and(returns_value)
+                || try_statement
+                    .finally_clause
+                    .as_deref()
+                    .is_some_and(returns_value)
+        }
+        _ => false,
+    })
+}
+
+/// A JSDoc comment with the docstring of `function` and, if it has annotations, the types of
+/// its parameters and return value, for plain JavaScript output read by editors or checked by
+/// TypeScript's `checkJs`. Empty for a function with neither.
+pub fn jsdoc(function: &FunctionDef, method: bool, indent: &str) -> String {
+    let mut lines = docstring_lines(&function.body);
+    let annotated = function.return_type.is_some()
+        || function
+            .parameters
+            .iter()
+            .any(|parameter| parameter.param_type.is_some());
+    if annotated {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        for parameter in js_parameters(&function.parameters[receivers(function, method)..]) {
+            let parameter_type = parameter_type(parameter);
+            let (parameter_type, name) = match (parameter.kind, &parameter.default_value) {
+                (ParameterKind::Rest, _) => {
+                    (format!("...{parameter_type}"), parameter.name.clone())
+                }
+                (ParameterKind::Keywords, _) => (
+                    format!("Object<string, {parameter_type}>"),
+                    format!("[{}]", parameter.name),
+                ),
+                (ParameterKind::Positional, Some(_)) => {
+                    (parameter_type, format!("[{}]", parameter.name))
+                }
+                (ParameterKind::Positional, None) => (parameter_type, parameter.name.clone()),
+            };
+            lines.push(format!("@param {{{parameter_type}}} {name}"));
+        }
+        lines.push(format!("@returns {{{}}}", return_type(function)));
+    }
+
+    let mut out = String::new();
+    push_comment(&mut out, &lines, indent);
+    out
+}
+
+/// The docstring of `body` as a JSDoc comment
+fn push_doc(out: &mut String, body: &[Statement], indent: &str) {
+    push_comment(out, &docstring_lines(body), indent);
+}
+
+/// The lines of the docstring of `body`, trimmed and safe to put in a comment
+fn docstring_lines(body: &[Statement]) -> Vec<String> {
+    let Some(Statement::Expression(Expression::Literal(Literal::String(doc)))) = body.first()
+    else {
+        return Vec::new();
+    };
+    let doc = doc.trim();
+    if doc.is_empty() {
+        return Vec::new();
+    }
+    doc.lines()
+        .map(|line| line.trim().replace("*/", "*\\/"))
+        .collect()
+}
+
+/// `lines` as a JSDoc comment, or nothing if there are none
+fn push_comment(out: &mut String, lines: &[String], indent: &str) {
+    if lines.is_empty() {
+        return;
+    }
+    out.push_str(indent);
+    out.push_str("/**\n");
+    for line in lines {
+        if line.is_empty() {
+            out.push_str(&format!("{indent} *\n"));
+        } else {
+            out.push_str(&format!("{indent} * {line}\n"));
+        }
+    }
+    out.push_str(indent);
+    out.push_str(" */\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_program;
+
+    fn declarations(source: &str) -> String {
+        generate(&test_program(source))
+    }
+
+    fn function(source: &str) -> FunctionDef {
+        match test_program(source).statements.into_iter().next() {
+            Some(Statement::FunctionDef(function)) => function,
+            statement => panic!("expected a function, got {statement:?}"),
+        }
+    }
+
+    #[test]
+    fn test_export_statements_choose_what_is_declared() {
+        let mut program = test_program(
+            "def visible(a: int) -> int:\n    return a\n\ndef hidden():\n    pass\n\nsize = 1\n",
+        );
+        let visible = program.statements.remove(0);
+        program.statements.insert(
+            0,
+            Statement::ExportDeclaration(ExportDeclarationStatement {
+                declaration: Box::new(visible),
+            }),
+        );
+        program
+            .statements
+            .push(Statement::ExportDefault(ExportDefaultStatement {
+                value: Expression::Identifier("size".to_string()),
+            }));
+        let out = generate(&program);
+        assert!(
+            out.contains("export declare function visible(a: number): number;"),
+            "{out}"
+        );
+        assert!(!out.contains("hidden"), "{out}");
+        // The default export is declared without being exported by name
+        assert!(out.contains("\ndeclare const size: number;\n"), "{out}");
+        assert!(out.ends_with("\nexport default size;\n"), "{out}");
+
+        assert_eq!(
+            declarations("_private = 1\nprint(_private)\n"),
+            "// Generated TypeScript declarations\nexport {};\n"
+        );
+    }
+
+    #[test]
+    fn test_class_members() {
+        let out = declarations(
+            "class Counter extends Base {\n    limit = 10\n    def __init__(self, start: int, _step = 1):\n        self.count = start\n        self.label = \"c\"\n        self._hidden = 0\n    @property\n    def double(self) -> int:\n        return self.count * 2\n    @double.setter\n    def double(self, value: int):\n        self.count = value\n    @staticmethod\n    def zero() -> Counter:\n        return Counter(0)\n    @classmethod\n    def make(cls, start: int):\n        return cls(start)\n}\n",
+        );
+        let class = out
+            .split_once("export declare class Counter extends Base {\n")
+            .map(|(_, class)| class)
+            .unwrap_or_else(|| panic!("{out}"));
+        assert_eq!(
+            class,
+            "    static limit: number;\n    count: number;\n    label: string;\n    constructor(start: number, _step?: number);\n    get double(): number;\n    set double(value: number);\n    static zero(): any;\n    static make(start: number): any;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_return_types() {
+        let returns = |source: &str| return_type(&function(source));
+        assert_eq!(returns("def f():\n    pass\n"), "void");
+        assert_eq!(
+            returns("def f(x):\n    if x:\n        return 1\n    return\n"),
+            "any"
+        );
+        assert_eq!(
+            returns("def f(x):\n    while x:\n        for y in x:\n            return y\n"),
+            "any"
+        );
+        // A nested function's return is its own
+        assert_eq!(
+            returns("def f():\n    def g():\n        return 1\n    g()\n"),
+            "void"
+        );
+        assert_eq!(
+            returns("async def f() -> int:\n    return 1\n"),
+            "Promise<number>"
+        );
+        assert_eq!(returns("async def f():\n    pass\n"), "Promise<void>");
+
+        let mut generator = function("def f():\n    pass\n");
+        generator.is_generator = true;
+        assert_eq!(return_type(&generator), "Generator<any>");
+    }
+
+    #[test]
+    fn test_parameters_and_values() {
+        assert_eq!(
+            signature(
+                &function("def f(self, a, b = [1, 2], *rest: str, **options: int):\n    pass\n"),
+                true
+            ),
+            "(a: any, b?: number[], options?: Record<string, number>, ...rest: Array<string>): void"
+        );
+        let value = |source: &str| {
+            let Statement::Assignment(assignment) = &test_program(source).statements[0] else {
+                panic!("expected an assignment in {source}");
+            };
+            value_type(&assignment.value)
+        };
+        assert_eq!(value("x = -1.5\n"), "number");
+        assert_eq!(value("x = [1, \"a\"]\n"), "any[]");
+        assert_eq!(value("x = null\n"), "null");
+        assert_eq!(value("x = f()\n"), "any");
+    }
+
+    #[test]
+    fn test_ts_type() {
+        let union = Type::Union(crate::types::UnionType {
+            types: vec![Type::Int, Type::None],
+        });
+        assert_eq!(
+            ts_type(&Type::List(Box::new(union.clone()))),
+            "(number | null)[]"
+        );
+        assert_eq!(
+            ts_type(&Type::Function(
+                vec![Type::Str, union],
+                Box::new(Type::None)
+            )),
+            "(arg0: string, arg1: number | null) => void"
+        );
+        assert_eq!(
+            ts_type(&Type::Dict(Box::new(Type::Bool), Box::new(Type::Float))),
+            "Record<string, number>"
+        );
+        assert_eq!(
+            ts_type(&Type::Object(
+                [("b".to_string(), Type::Str), ("a".to_string(), Type::Int)]
+                    .into_iter()
+                    .collect()
+            )),
+            "{ a: number; b: string }"
+        );
+        assert_eq!(
+            ts_type(&Type::Tuple(vec![Type::Int, Type::Str])),
+            "[number, string]"
+        );
+        assert_eq!(ts_type(&Type::Any), "any");
+    }
+}
@@ -0,0 +1,413 @@
+//! Compile-time constants.
+//!
+//! Identifiers named by a define are replaced by its literal value before transpiling. `if`,
+//! `elif` and `while` conditions and ternaries that become constant are then folded, so code
+//! behind `if __DEV__:` is left out of release builds entirely.
+//!
+//! Two constants are always defined, and user defines take precedence over them:
+//! - `__TARGET__`: the compilation target (`"es6"`, `"es5"`, `"node"`, ...)
+//! - `__DEV__`: `False` when minifying for production, `True` otherwise
+
+use crate::ast::*;
+use std::collections::HashMap;
+
+pub const TARGET: &str = "__TARGET__";
+pub const DEV: &str = "__DEV__";
+
+/// Builtin constants for a build of `target`
+pub fn builtins(target: &str, dev: bool) -> HashMap<String, Literal> {
+    HashMap::from([
+        (TARGET.to_string(), Literal::String(target.to_string())),
+        (DEV.to_string(), Literal::Bool(dev)),
+    ])
+}
+
+/// Read a define given on the command line or in `nagari.toml`: `True`/`False` (or
+/// `true`/`false`), `None`, numbers and quoted strings; anything else is a plain string
+pub fn parse_value(value: &str) -> Literal {
+    let value = value.trim();
+    match value {
+        "True" | "true" => return Literal::Bool(true),
+        "False" | "false" => return Literal::Bool(false),
+        "None" | "null" => return Literal::None,
+        _ => {}
+    }
+    if let Ok(int) = value.parse::<i64>() {
+        return Literal::Int(int);
+    }
+    if let Ok(float) = value.parse::<f64>() {
+        return Literal::Float(float);
+    }
+    let unquoted = ['"', '\''].iter().find_map(|quote| {
+        value
+            .strip_prefix(*quote)
+            .and_then(|rest| rest.strip_suffix(*quote))
+    });
+    Literal::String(unquoted.unwrap_or(value).to_string())
+}
+
+/// Substitute `defines` in `program` and drop the branches they make unreachable
+pub fn apply(program: &mut Program, defines: &HashMap<String, Literal>) {
+    if !defines.is_empty() {
+        fold_block(&mut program.statements, defines);
+    }
+}
+
+fn fold_block(statements: &mut Vec<Statement>, defines: &HashMap<String, Literal>) {
+    let mut folded = Vec::with_capacity(statements.len());
+    for mut statement in statements.drain(..) {
+        fold_statement(&mut statement, defines);
+        match statement {
+            Statement::If(if_stmt) => folded.extend(fold_if(if_stmt)),
+            Statement::While(while_loop) if truthiness(&while_loop.condition) == Some(false) => {}
+            other => folded.push(other),
+        }
+    }
+    *statements = folded;
+}
+
+/// Statements left of an `if` whose conditions were substituted
+fn fold_if(if_stmt: IfStatement) -> Vec<Statement> {
+    let mut branches = vec![(if_stmt.condition, if_stmt.then_branch)];
+    branches.extend(
+        if_stmt
+            .elif_branches
+            .into_iter()
+            .map(|elif| (elif.condition, elif.body)),
+    );
+
+    let mut kept: Vec<(Expression, Vec<Statement>)> = Vec::new();
+    let mut else_branch = if_stmt.else_branch;
+    for (condition, body) in branches {
+        match truthiness(&condition) {
+            Some(false) => {}
+            Some(true) => {
+                // Later branches can never run; this one becomes the fallback
+                else_branch = Some(body);
+                break;
+            }
+            None => kept.push((condition, body)),
+        }
+    }
+
+    let mut kept = kept.into_iter();
+    let Some((condition, then_branch)) = kept.next() else {
+        return else_branch.unwrap_or_default();
+    };
+    vec![Statement::If(IfStatement {
+        condition,
+        then_branch,
+        elif_branches: kept
+            .map(|(condition, body)| ElifBranch { condition, body })
+            .collect(),
+        else_branch,
+    })]
+}
+
+/// Value of a condition made only of literals, as far as it can be known
+pub(crate) fn truthiness(expr: &Expression) -> Option<bool> {
+    match expr {
+        Expression::Literal(literal) => Some(match literal {
+            Literal::Bool(value) => *value,
+            Literal::Int(value) => *value != 0,
+            Literal::Float(value) => *value != 0.0,
+            Literal::String(value) => !value.is_empty(),
+            Literal::None => false,
+        }),
+        Expression::Unary(UnaryExpression {
+            operator: UnaryOperator::Not,
+            operand,
+        }) => truthiness(operand).map(|value| !value),
+        Expression::Binary(binary) => match binary.operator {
+            BinaryOperator::And => match truthiness(&binary.left)? {
+                false => Some(false),
+                true => truthiness(&binary.right),
+            },
+            BinaryOperator::Or => match truthiness(&binary.left)? {
+                true => Some(true),
+                false => truthiness(&binary.right),
+            },
+            BinaryOperator::Equal => literals_equal(&binary.left, &binary.right),
+            BinaryOperator::NotEqual => {
+                literals_equal(&binary.left, &binary.right).map(|equal| !equal)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub(crate) fn literals_equal(left: &Expression, right: &Expression) -> Option<bool> {
+    let (Expression::Literal(left), Expression::Literal(right)) = (left, right) else {
+        return None;
+    };
+    Some(match (left, right) {
+        (Literal::Int(a), Literal::Int(b)) => a == b,
+        (Literal::Int(a), Literal::Float(b)) | (Literal::Float(b), Literal::Int(a)) => {
+            *a as f64 == *b
+        }
+        (Literal::Float(a), Literal::Float(b)) => a == b,
+        (Literal::String(a), Literal::String(b)) => a == b,
+        (Literal::Bool(a), Literal::Bool(b)) => a == b,
+        (Literal::None, Literal::None) => true,
+        _ => false,
+    })
+}
+
+/// `defines` without the names that `parameters` shadow
+fn unshadowed<'a, I>(
+    defines: &HashMap<String, Literal>,
+    parameters: I,
+) -> Option<HashMap<String, Literal>>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let shadowed: Vec<&str> = parameters
+        .into_iter()
+        .filter(|name| defines.contains_key(*name))
+        .collect();
+    (!shadowed.is_empty()).then(|| {
+        defines
+            .iter()
+            .filter(|(name, _)| !shadowed.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    })
+}
+
+fn fold_function(
+    parameters: &mut [Parameter],
+    body: &mut Vec<Statement>,
+    defines: &HashMap<String, Literal>,
+) {
+    for parameter in parameters.iter_mut() {
+        if let Some(default) = &mut parameter.default_value {
+            fold_expression(default, defines);
+        }
+    }
+    let inner = unshadowed(defines, parameters.iter().map(|p| p.name.as_str()));
+    fold_block(body, inner.as_ref().unwrap_or(defines));
+}
+
+fn fold_statement(statement: &mut Statement, defines: &HashMap<String, Literal>) {
+    match statement {
+        Statement::FunctionDef(function) => {
+            for decorator in &mut function.decorators {
+                for argument in decorator.arguments.iter_mut().flatten() {
+                    fold_expression(argument, defines);
+                }
+            }
+            fold_function(&mut function.parameters, &mut function.body, defines);
+        }
+        Statement::Assignment(assignment) => fold_expression(&mut assignment.value, defines),
+        Statement::AttributeAssignment(assignment) => {
+            fold_expression(&mut assignment.object, defines);
+            fold_expression(&mut assignment.value, defines);
+        }
+        Statement::IndexAssignment(assignment) => {
+            fold_expression(&mut assignment.object, defines);
+            fold_expression(&mut assignment.index, defines);
+            fold_expression(&mut assignment.value, defines);
+        }
+        Statement::TupleAssignment(assignment) => fold_expression(&mut assignment.value, defines),
+        Statement::If(if_stmt) => {
+            fold_expression(&mut if_stmt.condition, defines);
+            fold_block(&mut if_stmt.then_branch, defines);
+            for elif in &mut if_stmt.elif_branches {
+                fold_expression(&mut elif.condition, defines);
+                fold_block(&mut elif.body, defines);
+            }
+            if let Some(else_branch) = &mut if_stmt.else_branch {
+                fold_block(else_branch, defines);
+            }
+        }
+        Statement::While(while_loop) => {
+            fold_expression(&mut while_loop.condition, defines);
+            fold_block(&mut while_loop.body, defines);
+        }
+        Statement::For(for_loop) => {
+            fold_expression(&mut for_loop.iterable, defines);
+            fold_block(&mut for_loop.body, defines);
+        }
+        Statement::Match(match_stmt) => {
+            fold_expression(&mut match_stmt.expression, defines);
+            for case in &mut match_stmt.cases {
+                fold_block(&mut case.body, defines);
+            }
+        }
+        Statement::Return(Some(value)) | Statement::Expression(value) | Statement::Del(value) => {
+            fold_expression(value, defines)
+        }
+        Statement::With(with_stmt) => {
+            for item in &mut with_stmt.items {
+                fold_expression(&mut item.context_expr, defines);
+            }
+            fold_block(&mut with_stmt.body, defines);
+        }
+        Statement::Try(try_stmt) => {
+            fold_block(&mut try_stmt.body, defines);
+            for handler in &mut try_stmt.except_handlers {
+                fold_block(&mut handler.body, defines);
+            }
+            for block in [&mut try_stmt.else_clause, &mut try_stmt.finally_clause]
+                .into_iter()
+                .flatten()
+            {
+                fold_block(block, defines);
+            }
+        }
+        Statement::Raise(raise) => {
+            for value in [&mut raise.exception, &mut raise.cause]
+                .into_iter()
+                .flatten()
+            {
+                fold_expression(value, defines);
+            }
+        }
+        Statement::Yield(yield_stmt) => {
+            if let Some(value) = &mut yield_stmt.value {
+                fold_expression(value, defines);
+            }
+        }
+        Statement::YieldFrom(yield_from) => fold_expression(&mut yield_from.value, defines),
+        Statement::ClassDef(class) => fold_block(&mut class.body, defines),
+        Statement::DestructuringAssignment(assignment) => {
+            fold_expression(&mut assignment.value, defines)
+        }
+        Statement::ArrayDestructuringAssignment(assignment) => {
+            fold_expression(&mut assignment.value, defines)
+        }
+        Statement::ExportDefault(export) => fold_expression(&mut export.value, defines),
+        Statement::ExportDeclaration(export) => fold_statement(&mut export.declaration, defines),
+        _ => {}
+    }
+}
+
+fn fold_generators(generators: &mut [ComprehensionGenerator], defines: &HashMap<String, Literal>) {
+    for generator in generators {
+        fold_expression(&mut generator.iter, defines);
+        for condition in &mut generator.conditions {
+            fold_expression(condition, defines);
+        }
+    }
+}
+
+fn fold_expression(expr: &mut Expression, defines: &HashMap<String, Literal>) {
+    let all = |exprs: &mut [Expression]| {
+        for expr in exprs {
+            fold_expression(expr, defines);
+        }
+    };
+
+    match expr {
+        Expression::Identifier(name) => {
+            if let Some(value) = defines.get(name.as_str()) {
+                *expr = Expression::Literal(value.clone());
+            }
+        }
+        Expression::Binary(binary) => {
+            fold_expression(&mut binary.left, defines);
+            fold_expression(&mut binary.right, defines);
+        }
+        Expression::Call(call) => {
+            fold_expression(&mut call.function, defines);
+            all(&mut call.arguments);
+            for (_, value) in &mut call.keyword_args {
+                fold_expression(value, defines);
+            }
+        }
+        Expression::Await(inner) | Expression::Async(inner) | Expression::Spread(inner) => {
+            fold_expression(inner, defines)
+        }
+        Expression::List(items) | Expression::Tuple(items) | Expression::Set(items) => all(items),
+        Expression::Dict(pairs) | Expression::Dictionary(pairs) => {
+            for (key, value) in pairs {
+                fold_expression(key, defines);
+                fold_expression(value, defines);
+            }
+        }
+        Expression::Lambda(lambda) => {
+            let inner = unshadowed(defines, lambda.parameters.iter().map(String::as_str));
+            fold_expression(&mut lambda.body, inner.as_ref().unwrap_or(defines));
+        }
+        Expression::ListComprehension(comp) => {
+            fold_expression(&mut comp.element, defines);
+            fold_generators(&mut comp.generators, defines);
+        }
+        Expression::SetComprehension(comp) => {
+            fold_expression(&mut comp.element, defines);
+            fold_generators(&mut comp.generators, defines);
+        }
+        Expression::DictComprehension(comp) => {
+            fold_expression(&mut comp.key, defines);
+            fold_expression(&mut comp.value, defines);
+            fold_generators(&mut comp.generators, defines);
+        }
+        Expression::Generator(gen) => {
+            fold_expression(&mut gen.element, defines);
+            fold_generators(&mut gen.generators, defines);
+        }
+        Expression::Ternary(ternary) => {
+            fold_expression(&mut ternary.condition, defines);
+            fold_expression(&mut ternary.true_expr, defines);
+            fold_expression(&mut ternary.false_expr, defines);
+            match truthiness(&ternary.condition) {
+                Some(true) => *expr = (*ternary.true_expr).clone(),
+                Some(false) => *expr = (*ternary.false_expr).clone(),
+                None => {}
+            }
+        }
+        Expression::Attribute(attr) => fold_expression(&mut attr.object, defines),
+        Expression::Index(index) => {
+            fold_expression(&mut index.object, defines);
+            fold_expression(&mut index.index, defines);
+        }
+        Expression::Subscript(sub) => {
+            fold_expression(&mut sub.object, defines);
+            fold_expression(&mut sub.index, defines);
+        }
+        Expression::Slice(slice) => {
+            fold_expression(&mut slice.object, defines);
+            for bound in [&mut slice.start, &mut slice.end, &mut slice.step]
+                .into_iter()
+                .flatten()
+            {
+                fold_expression(bound, defines);
+            }
+        }
+        Expression::Unary(unary) => fold_expression(&mut unary.operand, defines),
+        Expression::NamedExpr(named) => fold_expression(&mut named.value, defines),
+        Expression::FunctionExpr(function) => {
+            fold_function(&mut function.parameters, &mut function.body, defines)
+        }
+        Expression::TemplateLiteral(template) => all(&mut template.expressions),
+        Expression::FString(fstring) => {
+            for part in &mut fstring.parts {
+                match part {
+                    FStringPart::Expression(expression)
+                    | FStringPart::FormattedExpression { expression, .. } => {
+                        fold_expression(expression, defines)
+                    }
+                    FStringPart::Text(_) => {}
+                }
+            }
+        }
+        Expression::JSXElement(element) => fold_jsx(element, defines),
+        Expression::Literal(_) | Expression::InlineJs(_) => {}
+    }
+}
+
+fn fold_jsx(element: &mut JSXElement, defines: &HashMap<String, Literal>) {
+    for attribute in &mut element.attributes {
+        if let Some(value) = &mut attribute.value {
+            fold_expression(value, defines);
+        }
+    }
+    for child in &mut element.children {
+        match child {
+            JSXChild::Element(child) => fold_jsx(child, defines),
+            JSXChild::Expression(expression) => fold_expression(expression, defines),
+            JSXChild::Text(_) => {}
+        }
+    }
+}
@@ -0,0 +1,629 @@
+//! Warnings about code that compiles but is probably not what was meant.
+//!
+//! A [`Diagnostics`] collector is started from what the parser recorded and passed along the
+//! pipeline: the parser reports deprecated syntax, [`Diagnostics::analyze`] walks the parsed
+//! program for unused variables, unreachable code, shadowed names and parameters that are
+//! implicitly `any`, and the type checker adds its mismatches. Warnings about a statement carry
+//! the line and column the parser recorded for it.
+//!
+//! Only locals of functions are reported as unused, as module-level names may be used by
+//! importers, and a name is only reported as shadowing one of an enclosing function or a
+//! function or class of the module. Names starting with `_` are never reported.
+
+use crate::warnings::Warning;
+use nagari_parser::{
+    ArrowFunctionBody, AssignmentOperator, ComprehensionGenerator, Deprecation, Expression,
+    FunctionParameter, Pattern, Program, Span, Statement,
+};
+use std::collections::HashSet;
+
+/// Category of variables assigned in a function but never read
+pub const UNUSED_VARIABLES: &str = "unused-variables";
+/// Category of statements after a `return`
+pub const UNREACHABLE_CODE: &str = "unreachable-code";
+/// Category of names hiding one of an enclosing scope
+pub const SHADOWING: &str = "shadowing";
+/// Category of unannotated parameters of otherwise annotated functions
+pub const IMPLICIT_ANY: &str = "implicit-any";
+/// Category of syntax that has a replacement
+pub const DEPRECATED_SYNTAX: &str = "deprecated-syntax";
+/// Category of top-level statements the transpiler failed on with an internal error, which
+/// levels and `# nagari:ignore` comments don't apply to
+pub const INTERNAL_ERROR: &str = "internal-error";
+
+/// Warnings collected while compiling one source
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    warnings: Vec<Warning>,
+    /// Where the parsed statements start, in the order [`Parser::statement_spans`] gives
+    ///
+    /// [`Parser::statement_spans`]: nagari_parser::Parser::statement_spans
+    spans: Vec<Span>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from a parser's statement spans and the deprecated syntax it found
+    pub fn parsed(spans: &[Span], deprecations: &[Deprecation]) -> Self {
+        let mut diagnostics = Self {
+            warnings: Vec::new(),
+            spans: spans.to_vec(),
+        };
+        for deprecation in deprecations {
+            diagnostics.warn(
+                DEPRECATED_SYNTAX,
+                deprecation.message.clone(),
+                Some(deprecation.span),
+            );
+        }
+        diagnostics
+    }
+
+    pub fn warn(&mut self, category: &str, message: String, span: Option<Span>) {
+        self.warnings.push(Warning {
+            category: category.to_string(),
+            message,
+            line: span.map(|span| span.line),
+            column: span.map(|span| span.column),
+        });
+    }
+
+    pub fn extend(&mut self, warnings: impl IntoIterator<Item = Warning>) {
+        self.warnings.extend(warnings);
+    }
+
+    /// Report the unused variables, unreachable code, shadowed names and implicitly `any`
+    /// parameters of `program`, the program the spans were recorded for
+    pub fn analyze(&mut self, program: &Program) {
+        let mut module = Scope::default();
+        for statement in &program.statements {
+            if let Statement::Function { name, .. } | Statement::Class { name, .. } =
+                declared(statement)
+            {
+                module.bind(name, Kind::Definition, None);
+            }
+        }
+
+        let mut analyzer = Analyzer {
+            spans: self.spans.iter(),
+            scopes: vec![module],
+            warnings: Vec::new(),
+        };
+        analyzer.block(&program.statements);
+        self.warnings.extend(analyzer.warnings);
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The warnings in source order, followed by those without a line
+    pub fn into_warnings(mut self) -> Vec<Warning> {
+        self.warnings
+            .sort_by_key(|warning| (warning.line.is_none(), warning.line, warning.column));
+        self.warnings
+    }
+}
+
+/// The statement an `export` declares, or `statement` itself
+fn declared(statement: &Statement) -> &Statement {
+    match statement {
+        Statement::ExportDeclaration { declaration } => declared(declaration),
+        other => other,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Bound by `let`, `const` or assignment; reported when never read
+    Variable,
+    Parameter,
+    /// A function or class
+    Definition,
+    /// Bound by a loop, `with`, `case` or import
+    Other,
+}
+
+struct Binding {
+    name: String,
+    kind: Kind,
+    span: Option<Span>,
+}
+
+/// Names bound in the module or a function, and the names read in it
+#[derive(Default)]
+struct Scope {
+    /// How the function is named in messages; `None` for the module
+    function: Option<String>,
+    bindings: Vec<Binding>,
+    reads: HashSet<String>,
+}
+
+impl Scope {
+    fn get(&self, name: &str) -> Option<&Binding> {
+        self.bindings.iter().find(|binding| binding.name == name)
+    }
+
+    fn bind(&mut self, name: &str, kind: Kind, span: Option<Span>) {
+        self.bindings.push(Binding {
+            name: name.to_string(),
+            kind,
+            span,
+        });
+    }
+
+    fn describe(&self) -> String {
+        match &self.function {
+            Some(function) => function.clone(),
+            None => "the module".to_string(),
+        }
+    }
+}
+
+struct Analyzer<'a> {
+    spans: std::slice::Iter<'a, Span>,
+    /// The module and the functions being walked, innermost last
+    scopes: Vec<Scope>,
+    warnings: Vec<Warning>,
+}
+
+impl Analyzer<'_> {
+    fn warn(&mut self, category: &str, message: String, span: Option<Span>) {
+        self.warnings.push(Warning {
+            category: category.to_string(),
+            message,
+            line: span.map(|span| span.line),
+            column: span.map(|span| span.column),
+        });
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("the module scope is never left")
+    }
+
+    /// Walk `statements`, returning whether they always return
+    fn block(&mut self, statements: &[Statement]) -> bool {
+        let mut returns = false;
+        let mut reported = false;
+        for statement in statements {
+            let span = self.spans.next().copied();
+            if returns && !reported {
+                self.warn(
+                    UNREACHABLE_CODE,
+                    "unreachable code after return".to_string(),
+                    span,
+                );
+                reported = true;
+            }
+            returns |= self.statement(statement, span);
+        }
+        returns
+    }
+
+    /// Walk `statement`, which starts at `span`, returning whether it always returns
+    fn statement(&mut self, statement: &Statement, span: Option<Span>) -> bool {
+        match statement {
+            Statement::Let { name, value } | Statement::Const { name, value } => {
+                self.expression(value, span);
+                self.bind(name, Kind::Variable, span);
+            }
+            Statement::Expression(expr) => self.expression(expr, span),
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value, span);
+                }
+                return true;
+            }
+            Statement::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                self.expression(condition, span);
+                let then_returns = self.block(then_body);
+                let else_returns = else_body.as_ref().is_some_and(|body| self.block(body));
+                return then_returns && else_returns;
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition, span);
+                self.block(body);
+            }
+            Statement::For {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable, span);
+                self.bind(variable, Kind::Other, span);
+                self.block(body);
+            }
+            Statement::With { items, body, .. } => {
+                for item in items {
+                    self.expression(&item.context, span);
+                    if let Some(alias) = &item.alias {
+                        self.bind(alias, Kind::Other, span);
+                    }
+                }
+                self.block(body);
+            }
+            Statement::Match { subject, cases } => {
+                self.expression(subject, span);
+                for case in cases {
+                    self.pattern(&case.pattern, span);
+                    if let Some(guard) = &case.guard {
+                        self.expression(guard, span);
+                    }
+                    self.block(&case.body);
+                }
+            }
+            Statement::Function {
+                name,
+                parameters,
+                body,
+                return_type,
+                decorators,
+                ..
+            } => {
+                self.decorators(decorators, span);
+                self.bind(name, Kind::Definition, span);
+                if return_type.is_some() || parameters.iter().any(|p| p.type_annotation.is_some()) {
+                    self.implicit_any(name, parameters, span);
+                }
+                self.function(format!("{name}()"), parameters, span, |analyzer| {
+                    analyzer.block(body);
+                });
+            }
+            Statement::Class {
+                name,
+                superclass,
+                methods,
+                decorators,
+            } => {
+                self.decorators(decorators, span);
+                if let Some(superclass) = superclass {
+                    self.read(superclass);
+                }
+                self.bind(name, Kind::Definition, span);
+                self.methods(name, methods);
+            }
+            Statement::ExportDeclaration { declaration } => {
+                return self.statement(declaration, span);
+            }
+            Statement::ExportNamed { exports, source } => {
+                if source.is_none() {
+                    for export in exports {
+                        self.read(&export.name);
+                    }
+                }
+            }
+            Statement::ExportAll { .. } => {}
+            Statement::Import { items, .. } => {
+                for item in items {
+                    let local = item.alias.as_ref().unwrap_or(&item.name);
+                    self.bind(local, Kind::Other, span);
+                }
+            }
+        }
+        false
+    }
+
+    /// Walk the methods of `class`, which are attributes rather than names in scope
+    fn methods(&mut self, class: &str, methods: &[Statement]) {
+        for method in methods {
+            let span = self.spans.next().copied();
+            let Statement::Function {
+                name,
+                parameters,
+                body,
+                return_type,
+                decorators,
+                ..
+            } = method
+            else {
+                self.statement(method, span);
+                continue;
+            };
+
+            self.decorators(decorators, span);
+            let described = format!("{class}.{name}");
+            // The instance or class parameter is never annotated
+            let annotated = match parameters.first() {
+                Some(first) if matches!(first.name.as_str(), "self" | "cls") => &parameters[1..],
+                _ => parameters.as_slice(),
+            };
+            if return_type.is_some() || annotated.iter().any(|p| p.type_annotation.is_some()) {
+                self.implicit_any(&described, annotated, span);
+            }
+            self.function(format!("{described}()"), parameters, span, |analyzer| {
+                analyzer.block(body);
+            });
+        }
+    }
+
+    fn implicit_any(
+        &mut self,
+        function: &str,
+        parameters: &[FunctionParameter],
+        span: Option<Span>,
+    ) {
+        for parameter in parameters {
+            if parameter.type_annotation.is_none() && !parameter.name.starts_with('_') {
+                self.warn(
+                    IMPLICIT_ANY,
+                    format!(
+                        "parameter '{}' of {function}() has no type annotation and is implicitly any",
+                        parameter.name
+                    ),
+                    span,
+                );
+            }
+        }
+    }
+
+    /// Walk a function's parameter defaults, then its body with `walk` in a scope of its own,
+    /// reporting the variables it never reads
+    fn function(
+        &mut self,
+        described: String,
+        parameters: &[FunctionParameter],
+        span: Option<Span>,
+        walk: impl FnOnce(&mut Self),
+    ) {
+        for parameter in parameters {
+            if let Some(default) = &parameter.default_value {
+                self.expression(default, span);
+            }
+        }
+
+        self.scopes.push(Scope {
+            function: Some(described),
+            ..Scope::default()
+        });
+        for parameter in parameters {
+            self.bind(&parameter.name, Kind::Parameter, span);
+        }
+        walk(self);
+        let scope = self.scopes.pop().expect("pushed above");
+
+        for binding in &scope.bindings {
+            if binding.kind == Kind::Variable
+                && !binding.name.starts_with('_')
+                && !scope.reads.contains(&binding.name)
+            {
+                self.warn(
+                    UNUSED_VARIABLES,
+                    format!(
+                        "variable '{}' in {} is assigned but never used",
+                        binding.name,
+                        scope.describe()
+                    ),
+                    binding.span,
+                );
+            }
+        }
+        // Names read but not bound in the function are those of the scopes around it
+        let outer = self.scope();
+        for name in scope.reads {
+            if scope.bindings.iter().all(|binding| binding.name != name) {
+                outer.reads.insert(name);
+            }
+        }
+    }
+
+    /// Bind `name` in the innermost scope, reporting it when it hides a name of an enclosing
+    /// function, or a function or class of the module
+    fn bind(&mut self, name: &str, kind: Kind, span: Option<Span>) {
+        let (current, enclosing) = self
+            .scopes
+            .split_last_mut()
+            .expect("the module scope is never left");
+        if current.get(name).is_some() {
+            return;
+        }
+        current.bind(name, kind, span);
+        if current.function.is_none() || name.starts_with('_') {
+            return;
+        }
+
+        let shadowed = enclosing.iter().rev().find_map(|scope| {
+            let binding = scope.get(name)?;
+            Some(
+                (scope.function.is_some() || binding.kind == Kind::Definition)
+                    .then(|| scope.describe()),
+            )
+        });
+        if let Some(Some(outer)) = shadowed {
+            let message = format!(
+                "'{name}' in {} shadows '{name}' in {outer}",
+                current.describe()
+            );
+            self.warn(SHADOWING, message, span);
+        }
+    }
+
+    fn read(&mut self, name: &str) {
+        // `@app.route` reads `app`
+        let root = name.split('.').next().unwrap_or(name);
+        self.scope().reads.insert(root.to_string());
+    }
+
+    fn decorators(&mut self, decorators: &[nagari_parser::Decorator], span: Option<Span>) {
+        for decorator in decorators {
+            self.read(&decorator.name);
+            for argument in decorator.arguments.iter().flatten() {
+                self.expression(argument, span);
+            }
+            for keyword in &decorator.keywords {
+                self.expression(&keyword.value, span);
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern, span: Option<Span>) {
+        if let Pattern::Class { name, .. } = pattern {
+            self.read(name);
+        }
+        for name in pattern.captures() {
+            self.bind(name, Kind::Other, span);
+        }
+    }
+
+    /// Bind the names assigned by `target`, an assignment target
+    fn assign(&mut self, target: &Expression, span: Option<Span>) {
+        match target {
+            Expression::Identifier(name) => self.bind(name, Kind::Variable, span),
+            Expression::Array(targets) => {
+                for target in targets {
+                    self.assign(target, span);
+                }
+            }
+            other => self.expression(other, span),
+        }
+    }
+
+    /// Walk `expr`, part of the statement starting at `span`
+    fn expression(&mut self, expr: &Expression, span: Option<Span>) {
+        match expr {
+            Expression::Literal(_) | Expression::InlineJs(_) => {}
+            Expression::Identifier(name) => self.read(name),
+            Expression::Binary { left, right, .. } => {
+                self.expression(left, span);
+                self.expression(right, span);
+            }
+            Expression::Unary { operand, .. } | Expression::Await(operand) => {
+                self.expression(operand, span)
+            }
+            Expression::Call {
+                function,
+                arguments,
+                keywords,
+            } => {
+                self.expression(function, span);
+                for argument in arguments {
+                    self.expression(argument, span);
+                }
+                for keyword in keywords {
+                    self.expression(&keyword.value, span);
+                }
+            }
+            Expression::Member { object, .. } => self.expression(object, span),
+            Expression::Array(items) | Expression::Set(items) => {
+                for item in items {
+                    self.expression(item, span);
+                }
+            }
+            Expression::Object(properties) => {
+                for property in properties {
+                    self.expression(&property.value, span);
+                }
+            }
+            Expression::Function {
+                parameters, body, ..
+            } => {
+                self.function(
+                    "a function expression".to_string(),
+                    parameters,
+                    span,
+                    |analyzer| {
+                        analyzer.block(body);
+                    },
+                );
+            }
+            Expression::Arrow {
+                parameters, body, ..
+            } => {
+                self.function(
+                    "an arrow function".to_string(),
+                    parameters,
+                    span,
+                    |analyzer| match body {
+                        ArrowFunctionBody::Expression(body) => analyzer.expression(body, span),
+                        ArrowFunctionBody::Block(body) => {
+                            analyzer.block(body);
+                        }
+                    },
+                );
+            }
+            Expression::Assignment {
+                left,
+                operator,
+                right,
+            } => {
+                self.expression(right, span);
+                match operator {
+                    AssignmentOperator::Assign => self.assign(left, span),
+                    // `x += 1` needs `x` to have a value already
+                    _ => self.expression(left, span),
+                }
+            }
+            Expression::Conditional {
+                test,
+                consequent,
+                alternate,
+            } => {
+                self.expression(test, span);
+                self.expression(consequent, span);
+                self.expression(alternate, span);
+            }
+            Expression::TemplateLiteral { expressions, .. } => {
+                for expression in expressions {
+                    self.expression(expression, span);
+                }
+            }
+            Expression::Index { object, index } => {
+                self.expression(object, span);
+                self.expression(index, span);
+            }
+            Expression::Slice {
+                object,
+                start,
+                end,
+                step,
+            } => {
+                self.expression(object, span);
+                for bound in [start, end, step].into_iter().flatten() {
+                    self.expression(bound, span);
+                }
+            }
+            Expression::ListComprehension {
+                element,
+                generators,
+            }
+            | Expression::SetComprehension {
+                element,
+                generators,
+            }
+            | Expression::Generator {
+                element,
+                generators,
+            } => {
+                self.expression(element, span);
+                self.generators(generators, span);
+            }
+            Expression::DictComprehension {
+                key,
+                value,
+                generators,
+            } => {
+                self.expression(key, span);
+                self.expression(value, span);
+                self.generators(generators, span);
+            }
+        }
+    }
+
+    fn generators(&mut self, generators: &[ComprehensionGenerator], span: Option<Span>) {
+        for generator in generators {
+            self.expression(&generator.iter, span);
+            for condition in &generator.conditions {
+                self.expression(condition, span);
+            }
+        }
+    }
+}
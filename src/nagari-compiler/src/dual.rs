@@ -0,0 +1,224 @@
+//! Dual packages: an ES module build and a CommonJS build of the same library side by side,
+//! with a `package.json` whose `exports` map sends `import` and `require` to the right one.
+//!
+//! ```text
+//! dist/
+//!   package.json        name, version, dependencies and the exports map
+//!   esm/package.json    {"type": "module"}
+//!   esm/<module>.js     esm/<module>.d.ts
+//!   cjs/package.json    {"type": "commonjs"}
+//!   cjs/<module>.js     cjs/<module>.d.ts
+//! ```
+//!
+//! Each build has its own declarations, so TypeScript resolves `require` to CommonJS types and
+//! `import` to ES module types.
+
+use crate::runtime_package::RuntimePackage;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One of the two builds of a dual package
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Esm,
+    Cjs,
+}
+
+impl Format {
+    /// Compiler target of the build
+    pub fn target(self) -> &'static str {
+        match self {
+            Format::Esm => "esm",
+            Format::Cjs => "cjs",
+        }
+    }
+
+    /// Directory of the build inside the package
+    pub fn dir(self) -> &'static str {
+        match self {
+            Format::Esm => "esm",
+            Format::Cjs => "cjs",
+        }
+    }
+
+    /// `type` of the `package.json` marking the build's directory, which tells Node.js how
+    /// to load its `.js` files
+    fn package_type(self) -> &'static str {
+        match self {
+            Format::Esm => "module",
+            Format::Cjs => "commonjs",
+        }
+    }
+}
+
+/// What the package's `package.json` says besides its entry points
+#[derive(Debug, Clone, Default)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    /// Module path of the package's main module, e.g. `index`
+    pub main: Option<String>,
+    /// Subpaths such as `./strings` and the module paths they export; by default `.` exports
+    /// `main` and every module is exported under its own path
+    pub exports: Option<BTreeMap<String, String>>,
+    /// npm dependencies besides the runtime, with their version ranges
+    pub dependencies: BTreeMap<String, String>,
+    /// Runtime the modules were compiled against, a dependency unless it is vendored
+    pub runtime: RuntimePackage,
+}
+
+/// The `.nag` files under `source_dir` with their module paths: relative, `/`-separated and
+/// without the extension, e.g. `utils/strings`. Sorted by module path.
+pub fn collect_modules(source_dir: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+    fn walk(dir: &Path, prefix: &str, modules: &mut Vec<(PathBuf, String)>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if path.is_dir() {
+                walk(&path, &format!("{prefix}{name}/"), modules)?;
+            } else if let Some(stem) = name.strip_suffix(".nag") {
+                modules.push((path.clone(), format!("{prefix}{stem}")));
+            }
+        }
+        Ok(())
+    }
+
+    let mut modules = Vec::new();
+    walk(source_dir, "", &mut modules)?;
+    modules.sort_by(|(_, a), (_, b)| a.cmp(b));
+    Ok(modules)
+}
+
+/// The module path of the source file `path`, e.g. `utils/strings` for
+/// `src/utils/strings.nag` in `src`
+pub fn module_path(path: &Path, source_dir: &Path) -> String {
+    let path = path.strip_prefix(".").unwrap_or(path);
+    let source_dir = source_dir.strip_prefix(".").unwrap_or(source_dir);
+    let relative = path.strip_prefix(source_dir).unwrap_or(path);
+    let components: Vec<_> = relative
+        .with_extension("")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    components.join("/")
+}
+
+/// Where `format`'s build of `module` goes in a package at `package_dir`
+pub fn module_file(package_dir: &Path, format: Format, module: &str) -> PathBuf {
+    package_dir.join(format.dir()).join(format!("{module}.js"))
+}
+
+/// Write the `package.json` files marking the format of each build's directory
+pub fn write_format_markers(package_dir: &Path) -> io::Result<()> {
+    for format in [Format::Esm, Format::Cjs] {
+        let dir = package_dir.join(format.dir());
+        fs::create_dir_all(&dir)?;
+        let marker = serde_json::json!({ "type": format.package_type() });
+        fs::write(dir.join("package.json"), format!("{marker:#}\n"))?;
+    }
+    Ok(())
+}
+
+/// Files a consumer loads for one condition, declarations first as TypeScript requires
+#[derive(Debug, Serialize)]
+struct Target {
+    types: String,
+    default: String,
+}
+
+impl Target {
+    fn new(format: Format, module: &str) -> Self {
+        let dir = format.dir();
+        Self {
+            types: format!("./{dir}/{module}.d.ts"),
+            default: format!("./{dir}/{module}.js"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Conditions {
+    import: Target,
+    require: Target,
+}
+
+#[derive(Debug, Serialize)]
+struct PackageJson<'a> {
+    name: &'a str,
+    version: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<&'a str>,
+    /// Entry points for tools that predate `exports`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    main: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    module: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    types: Option<String>,
+    exports: BTreeMap<String, Conditions>,
+    dependencies: BTreeMap<String, String>,
+}
+
+/// The `package.json` of a dual package made of `modules`.
+///
+/// Fails if `package.exports` names a module that isn't one of `modules`.
+pub fn package_json(package: &PackageInfo, modules: &[String]) -> Result<String, String> {
+    let exports = match &package.exports {
+        Some(exports) => exports.clone(),
+        None => {
+            let mut exports: BTreeMap<String, String> = modules
+                .iter()
+                .map(|module| (format!("./{module}"), module.clone()))
+                .collect();
+            if let Some(main) = &package.main {
+                exports.insert(".".to_string(), main.clone());
+            }
+            exports
+        }
+    };
+    if let Some((subpath, module)) = exports.iter().find(|(_, module)| !modules.contains(module)) {
+        return Err(format!(
+            "export '{subpath}' refers to module '{module}', which is not in the package"
+        ));
+    }
+
+    let main = exports.get(".");
+    let mut dependencies = package.dependencies.clone();
+    if let Some((name, version)) = package.runtime.dependency() {
+        dependencies
+            .entry(name.to_string())
+            .or_insert_with(|| version.to_string());
+    }
+    let json = PackageJson {
+        name: &package.name,
+        version: &package.version,
+        description: package.description.as_deref(),
+        license: package.license.as_deref(),
+        main: main.map(|module| Target::new(Format::Cjs, module).default),
+        module: main.map(|module| Target::new(Format::Esm, module).default),
+        types: main.map(|module| Target::new(Format::Cjs, module).types),
+        exports: exports
+            .iter()
+            .map(|(subpath, module)| {
+                let conditions = Conditions {
+                    import: Target::new(Format::Esm, module),
+                    require: Target::new(Format::Cjs, module),
+                };
+                (subpath.clone(), conditions)
+            })
+            .collect(),
+        dependencies,
+    };
+    serde_json::to_string_pretty(&json)
+        .map(|json| json + "\n")
+        .map_err(|e| format!("Failed to serialize package.json: {e}"))
+}
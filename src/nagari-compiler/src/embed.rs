@@ -0,0 +1,395 @@
+//! Compile-time file embedding.
+//!
+//! `embed("path")` is replaced by the contents of the file as a string literal with LF line
+//! endings before transpiling, and `embed("path", "bytes")` by a list of its bytes. Since this
+//! happens on the AST, the JavaScript and bytecode targets see the same constant.
+//!
+//! Paths are relative to the directory of the source file and must stay inside the embed root,
+//! which is that directory unless configured otherwise: absolute paths and `..` steps out of
+//! the root are rejected. A program that defines its own top-level `embed` keeps it.
+
+use crate::ast::*;
+use crate::error::NagariError;
+use crate::portable;
+use std::path::{Path, PathBuf};
+
+pub const INTRINSIC: &str = "embed";
+
+/// Replace the `embed()` calls of `program` unless it defines its own `embed`, returning how
+/// many it replaced. `source` is the path of the program's file, and `root` the directory
+/// embedded files must be in, by default the one `source` is in.
+pub fn apply(
+    program: &mut Program,
+    source: Option<&Path>,
+    root: Option<&Path>,
+) -> Result<usize, NagariError> {
+    if program.statements.iter().any(defines_intrinsic) {
+        return Ok(0);
+    }
+    inline(&mut program.statements, source, root)
+}
+
+/// Replace the `embed()` calls of `statements`, whatever the rest of the program defines
+pub fn inline(
+    statements: &mut [Statement],
+    source: Option<&Path>,
+    root: Option<&Path>,
+) -> Result<usize, NagariError> {
+    let base_dir = source
+        .and_then(Path::parent)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut embedder = Embedder {
+        base_dir,
+        root: root.unwrap_or(base_dir),
+        replaced: 0,
+        error: None,
+    };
+    embedder.block(statements);
+    match embedder.error {
+        Some(error) => Err(error),
+        None => Ok(embedder.replaced),
+    }
+}
+
+fn defines_intrinsic(statement: &Statement) -> bool {
+    match statement {
+        Statement::FunctionDef(function) => function.name == INTRINSIC,
+        Statement::Assignment(assignment) => assignment.name == INTRINSIC,
+        Statement::ClassDef(class) => class.name == INTRINSIC,
+        Statement::ExportDeclaration(export) => defines_intrinsic(&export.declaration),
+        _ => false,
+    }
+}
+
+struct Embedder<'a> {
+    base_dir: &'a Path,
+    root: &'a Path,
+    replaced: usize,
+    /// First failed embed
+    error: Option<NagariError>,
+}
+
+impl Embedder<'_> {
+    fn block(&mut self, statements: &mut [Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::FunctionDef(function) => {
+                for decorator in &mut function.decorators {
+                    for argument in decorator.arguments.iter_mut().flatten() {
+                        self.expression(argument);
+                    }
+                }
+                self.function(&mut function.parameters, &mut function.body);
+            }
+            Statement::Assignment(assignment) => self.expression(&mut assignment.value),
+            Statement::AttributeAssignment(assignment) => {
+                self.expression(&mut assignment.object);
+                self.expression(&mut assignment.value);
+            }
+            Statement::IndexAssignment(assignment) => {
+                self.expression(&mut assignment.object);
+                self.expression(&mut assignment.index);
+                self.expression(&mut assignment.value);
+            }
+            Statement::TupleAssignment(assignment) => self.expression(&mut assignment.value),
+            Statement::If(if_stmt) => {
+                self.expression(&mut if_stmt.condition);
+                self.block(&mut if_stmt.then_branch);
+                for elif in &mut if_stmt.elif_branches {
+                    self.expression(&mut elif.condition);
+                    self.block(&mut elif.body);
+                }
+                if let Some(else_branch) = &mut if_stmt.else_branch {
+                    self.block(else_branch);
+                }
+            }
+            Statement::While(while_loop) => {
+                self.expression(&mut while_loop.condition);
+                self.block(&mut while_loop.body);
+            }
+            Statement::For(for_loop) => {
+                self.expression(&mut for_loop.iterable);
+                self.block(&mut for_loop.body);
+            }
+            Statement::Match(match_stmt) => {
+                self.expression(&mut match_stmt.expression);
+                for case in &mut match_stmt.cases {
+                    self.block(&mut case.body);
+                }
+            }
+            Statement::Return(Some(value))
+            | Statement::Expression(value)
+            | Statement::Del(value) => self.expression(value),
+            Statement::With(with_stmt) => {
+                for item in &mut with_stmt.items {
+                    self.expression(&mut item.context_expr);
+                }
+                self.block(&mut with_stmt.body);
+            }
+            Statement::Try(try_stmt) => {
+                self.block(&mut try_stmt.body);
+                for handler in &mut try_stmt.except_handlers {
+                    self.block(&mut handler.body);
+                }
+                for block in [&mut try_stmt.else_clause, &mut try_stmt.finally_clause]
+                    .into_iter()
+                    .flatten()
+                {
+                    self.block(block);
+                }
+            }
+            Statement::Raise(raise) => {
+                for value in [&mut raise.exception, &mut raise.cause]
+                    .into_iter()
+                    .flatten()
+                {
+                    self.expression(value);
+                }
+            }
+            Statement::Yield(yield_stmt) => {
+                if let Some(value) = &mut yield_stmt.value {
+                    self.expression(value);
+                }
+            }
+            Statement::YieldFrom(yield_from) => self.expression(&mut yield_from.value),
+            Statement::ClassDef(class) => self.block(&mut class.body),
+            Statement::DestructuringAssignment(assignment) => {
+                self.expression(&mut assignment.value)
+            }
+            Statement::ArrayDestructuringAssignment(assignment) => {
+                self.expression(&mut assignment.value)
+            }
+            Statement::ExportDefault(export) => self.expression(&mut export.value),
+            Statement::ExportDeclaration(export) => self.statement(&mut export.declaration),
+            _ => {}
+        }
+    }
+
+    fn function(&mut self, parameters: &mut [Parameter], body: &mut [Statement]) {
+        for parameter in parameters {
+            if let Some(default) = &mut parameter.default_value {
+                self.expression(default);
+            }
+        }
+        self.block(body);
+    }
+
+    fn generators(&mut self, generators: &mut [ComprehensionGenerator]) {
+        for generator in generators {
+            self.expression(&mut generator.iter);
+            for condition in &mut generator.conditions {
+                self.expression(condition);
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::Call(call) if is_intrinsic(&call.function) => match self.embed(call) {
+                Ok(value) => {
+                    *expr = value;
+                    self.replaced += 1;
+                }
+                Err(error) => {
+                    self.error.get_or_insert(error);
+                }
+            },
+            Expression::Call(call) => {
+                self.expression(&mut call.function);
+                for argument in &mut call.arguments {
+                    self.expression(argument);
+                }
+                for (_, value) in &mut call.keyword_args {
+                    self.expression(value);
+                }
+            }
+            Expression::Binary(binary) => {
+                self.expression(&mut binary.left);
+                self.expression(&mut binary.right);
+            }
+            Expression::Await(inner) | Expression::Async(inner) | Expression::Spread(inner) => {
+                self.expression(inner)
+            }
+            Expression::List(items) | Expression::Tuple(items) | Expression::Set(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            Expression::Dict(pairs) | Expression::Dictionary(pairs) => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::Lambda(lambda) => self.expression(&mut lambda.body),
+            Expression::ListComprehension(comp) => {
+                self.expression(&mut comp.element);
+                self.generators(&mut comp.generators);
+            }
+            Expression::SetComprehension(comp) => {
+                self.expression(&mut comp.element);
+                self.generators(&mut comp.generators);
+            }
+            Expression::DictComprehension(comp) => {
+                self.expression(&mut comp.key);
+                self.expression(&mut comp.value);
+                self.generators(&mut comp.generators);
+            }
+            Expression::Generator(gen) => {
+                self.expression(&mut gen.element);
+                self.generators(&mut gen.generators);
+            }
+            Expression::Ternary(ternary) => {
+                self.expression(&mut ternary.condition);
+                self.expression(&mut ternary.true_expr);
+                self.expression(&mut ternary.false_expr);
+            }
+            Expression::Attribute(attr) => self.expression(&mut attr.object),
+            Expression::Index(index) => {
+                self.expression(&mut index.object);
+                self.expression(&mut index.index);
+            }
+            Expression::Subscript(sub) => {
+                self.expression(&mut sub.object);
+                self.expression(&mut sub.index);
+            }
+            Expression::Slice(slice) => {
+                self.expression(&mut slice.object);
+                for bound in [&mut slice.start, &mut slice.end, &mut slice.step]
+                    .into_iter()
+                    .flatten()
+                {
+                    self.expression(bound);
+                }
+            }
+            Expression::Unary(unary) => self.expression(&mut unary.operand),
+            Expression::NamedExpr(named) => self.expression(&mut named.value),
+            Expression::FunctionExpr(function) => {
+                self.function(&mut function.parameters, &mut function.body)
+            }
+            Expression::TemplateLiteral(template) => {
+                for expression in &mut template.expressions {
+                    self.expression(expression);
+                }
+            }
+            Expression::FString(fstring) => {
+                for part in &mut fstring.parts {
+                    match part {
+                        FStringPart::Expression(expression)
+                        | FStringPart::FormattedExpression { expression, .. } => {
+                            self.expression(expression)
+                        }
+                        FStringPart::Text(_) => {}
+                    }
+                }
+            }
+            Expression::JSXElement(element) => self.jsx(element),
+            Expression::Identifier(_) | Expression::Literal(_) | Expression::InlineJs(_) => {}
+        }
+    }
+
+    fn jsx(&mut self, element: &mut JSXElement) {
+        for attribute in &mut element.attributes {
+            if let Some(value) = &mut attribute.value {
+                self.expression(value);
+            }
+        }
+        for child in &mut element.children {
+            match child {
+                JSXChild::Element(child) => self.jsx(child),
+                JSXChild::Expression(expression) => self.expression(expression),
+                JSXChild::Text(_) => {}
+            }
+        }
+    }
+
+    /// The constant an `embed(path[, "text" | "bytes"])` call stands for
+    fn embed(&self, call: &CallExpression) -> Result<Expression, NagariError> {
+        let usage = || {
+            NagariError::SemanticError(
+                "embed() takes a string literal path and optionally \"text\" or \"bytes\""
+                    .to_string(),
+            )
+        };
+        if !call.keyword_args.is_empty() {
+            return Err(usage());
+        }
+        let (path, as_bytes) = match call.arguments.as_slice() {
+            [path] => (string_literal(path).ok_or_else(usage)?, false),
+            [path, kind] => match string_literal(kind) {
+                Some("text") => (string_literal(path).ok_or_else(usage)?, false),
+                Some("bytes") => (string_literal(path).ok_or_else(usage)?, true),
+                _ => return Err(usage()),
+            },
+            _ => return Err(usage()),
+        };
+
+        let file = self.resolve(path)?;
+        let contents = std::fs::read(&file).map_err(|e| {
+            NagariError::IoError(format!(
+                "embed(\"{path}\"): cannot read {}: {e}",
+                file.display()
+            ))
+        })?;
+        if as_bytes {
+            return Ok(Expression::List(
+                contents
+                    .into_iter()
+                    .map(|byte| Expression::Literal(Literal::Int(byte.into())))
+                    .collect(),
+            ));
+        }
+        // Text is the same whatever line endings the checkout gave the file
+        String::from_utf8(contents)
+            .map(|text| Expression::Literal(Literal::String(portable::to_lf(&text).into_owned())))
+            .map_err(|_| {
+                NagariError::SemanticError(format!(
+                    "embed(\"{path}\"): the file is not UTF-8 text; use embed(\"{path}\", \"bytes\")"
+                ))
+            })
+    }
+
+    /// `path` relative to the source file, if it is inside the root
+    fn resolve(&self, path: &str) -> Result<PathBuf, NagariError> {
+        if Path::new(path).is_absolute() {
+            return Err(NagariError::SemanticError(format!(
+                "embed(\"{path}\"): the path must be relative to the source file"
+            )));
+        }
+        let file = self.base_dir.join(path);
+        let not_found = |e: std::io::Error| {
+            NagariError::IoError(format!(
+                "embed(\"{path}\"): cannot read {}: {e}",
+                file.display()
+            ))
+        };
+        // Links and `..` are resolved before the check, so neither leads out of the root
+        let resolved = file.canonicalize().map_err(not_found)?;
+        let root = self.root.canonicalize().map_err(not_found)?;
+        if !resolved.starts_with(&root) {
+            return Err(NagariError::SemanticError(format!(
+                "embed(\"{path}\"): {} is outside {}",
+                resolved.display(),
+                root.display()
+            )));
+        }
+        Ok(resolved)
+    }
+}
+
+fn is_intrinsic(function: &Expression) -> bool {
+    matches!(function, Expression::Identifier(name) if name == INTRINSIC)
+}
+
+fn string_literal(expr: &Expression) -> Option<&str> {
+    match expr {
+        Expression::Literal(Literal::String(text)) => Some(text),
+        _ => None,
+    }
+}
@@ -0,0 +1,88 @@
+//! Environment profiles: what the JavaScript engine running the output provides natively.
+//!
+//! Without a profile the output carries every polyfill and stub its target might need. With one,
+//! only shims for features the environment lacks are emitted, and helpers use what it has
+//! without checking for it first. Stubs for other environments' APIs (`document` on Node.js,
+//! `process` and `require` in browsers) are left out, so code shared between environments has
+//! to check for those itself.
+//!
+//! | Profile          | Engines                  | Emitted                            |
+//! | ---------------- | ------------------------ | ---------------------------------- |
+//! | `modern-browser` | evergreen browsers       | nothing                            |
+//! | `node18`         | Node.js 18+              | nothing                            |
+//! | `node20`         | Node.js 20+              | nothing                            |
+//! | `legacy`         | ES5 engines such as IE11 | ES2015+ library shims, ES5 helpers |
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Environment {
+    ModernBrowser,
+    Node18,
+    Node20,
+    Legacy,
+}
+
+/// Something generated code uses that not every environment provides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// The `globalThis` binding
+    GlobalThis,
+    /// Arrow functions, default and rest parameters in the runtime helpers
+    Es2015Syntax,
+    /// `String.prototype.padStart`, `Array.prototype.includes`, `Object.entries` and the other
+    /// library methods up to ES2017 that the output calls
+    Es2017Builtins,
+}
+
+impl Environment {
+    pub const ALL: [Environment; 4] = [
+        Environment::ModernBrowser,
+        Environment::Node18,
+        Environment::Node20,
+        Environment::Legacy,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Environment::ModernBrowser => "modern-browser",
+            Environment::Node18 => "node18",
+            Environment::Node20 => "node20",
+            Environment::Legacy => "legacy",
+        }
+    }
+
+    /// Whether the environment provides `feature` without a polyfill
+    pub fn supports(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::GlobalThis | Feature::Es2015Syntax | Feature::Es2017Builtins => {
+                *self != Environment::Legacy
+            }
+        }
+    }
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.to_ascii_lowercase();
+        Environment::ALL
+            .into_iter()
+            .find(|environment| environment.as_str() == name)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown environment '{name}' (expected modern-browser, node18, node20 or legacy)"
+                )
+            })
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
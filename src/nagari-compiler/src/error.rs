@@ -1,3 +1,6 @@
+use colored::Colorize;
+use nagari_parser::{ParseError, Span};
+use serde::Serialize;
 use std::fmt;
 
 #[derive(Debug)]
@@ -8,6 +11,27 @@ pub enum NagariError {
     BytecodeError(String),
     IoError(String),
     SemanticError(String),
+    ImportCycle(String),
+    ConfigError(String),
+    /// Source, nesting or output larger than the compiler's limits allow
+    LimitExceeded(String),
+}
+
+impl NagariError {
+    /// Stable error code, documented by `nag explain <code>`
+    pub fn code(&self) -> &'static str {
+        match self {
+            NagariError::LexError(_) => "E0001",
+            NagariError::ParseError(_) => "E0002",
+            NagariError::TypeError(_) => "E0003",
+            NagariError::BytecodeError(_) => "E0004",
+            NagariError::IoError(_) => "E0005",
+            NagariError::SemanticError(_) => "E0006",
+            NagariError::ImportCycle(_) => "E0007",
+            NagariError::ConfigError(_) => "E0008",
+            NagariError::LimitExceeded(_) => "E0009",
+        }
+    }
 }
 
 impl fmt::Display for NagariError {
@@ -19,8 +43,284 @@ impl fmt::Display for NagariError {
             NagariError::BytecodeError(msg) => write!(f, "Bytecode generation error: {msg}"),
             NagariError::IoError(msg) => write!(f, "IO error: {msg}"),
             NagariError::SemanticError(msg) => write!(f, "Semantic error: {msg}"),
+            NagariError::ImportCycle(msg) => write!(f, "Import cycle: {msg}"),
+            NagariError::ConfigError(msg) => write!(f, "Configuration error: {msg}"),
+            NagariError::LimitExceeded(msg) => write!(f, "Limit exceeded: {msg}"),
         }
     }
 }
 
 impl std::error::Error for NagariError {}
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Information attached to another diagnostic
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+/// A place in the source a [`Diagnostic`] points at, with what it says about it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// An error or warning in a form tools can take apart: a code, where it happened, and labels
+/// and help that say more. [`Diagnostic::render`] shows it the way a terminal user sees it,
+/// and it serializes as JSON for editors.
+///
+/// Lines and columns of spans count from 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// Error code, such as `E0002`, or warning category, such as `unused-variables`
+    pub code: String,
+    pub severity: Severity,
+    pub message: String,
+    /// Where the problem is, when it is known
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            severity,
+            message: message.into(),
+            span: None,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// The diagnostic as a terminal shows it: a header, the lines of `source` it points at
+    /// with carets under the code, and the help. Without `source` only the header and the
+    /// location are shown.
+    ///
+    /// ```text
+    /// error[E0002]: expected ')', found newline
+    ///  --> src/main.nag:1:15
+    ///   |
+    /// 1 | print(add(1, 2
+    ///   |               ^ expected ')'
+    ///   |
+    ///   = help: close the call's argument list
+    /// ```
+    pub fn render(&self, file: &str, source: Option<&str>) -> String {
+        let severity = match self.severity {
+            Severity::Error => self.severity.to_string().red().bold(),
+            Severity::Warning => self.severity.to_string().yellow().bold(),
+            Severity::Note => self.severity.to_string().cyan().bold(),
+        };
+        let mut out = format!("{}[{}]: {}\n", severity, self.code, self.message.bold());
+
+        let lines: Vec<&str> = source
+            .map(|source| source.lines().collect())
+            .unwrap_or_default();
+        let shown = |span: &Span| span.line >= 1 && span.line <= lines.len();
+        let mut marks: Vec<(Span, bool, &str)> = Vec::new();
+        if let Some(span) = self.span {
+            let label = self
+                .labels
+                .iter()
+                .find(|label| label.span == span)
+                .map_or("", |label| label.message.as_str());
+            marks.push((span, true, label));
+        }
+        for label in &self.labels {
+            if Some(label.span) != self.span {
+                marks.push((label.span, false, &label.message));
+            }
+        }
+        marks.retain(|(span, ..)| shown(span));
+        marks.sort_by_key(|(span, ..)| (span.line, span.column));
+
+        let width = marks
+            .iter()
+            .map(|(span, ..)| span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+        let bar = "|".blue().bold();
+        match self.span {
+            Some(span) => out.push_str(&format!(
+                "{}{} {}:{}:{}\n",
+                gutter,
+                "-->".blue().bold(),
+                file,
+                span.line,
+                span.column
+            )),
+            None => out.push_str(&format!("{}{} {}\n", gutter, "-->".blue().bold(), file)),
+        }
+
+        if !marks.is_empty() {
+            out.push_str(&format!("{gutter} {bar}\n"));
+            let mut previous = None;
+            for (span, primary, label) in &marks {
+                let line = lines[span.line - 1];
+                if previous != Some(span.line) {
+                    out.push_str(&format!(
+                        "{:>width$} {} {}\n",
+                        span.line.to_string().blue().bold(),
+                        bar,
+                        line
+                    ));
+                    previous = Some(span.line);
+                }
+                let start = span.column.saturating_sub(1);
+                let underline = if *primary { "^" } else { "-" }.repeat(marked_width(line, start));
+                let underline = if *primary {
+                    underline.red().bold()
+                } else {
+                    underline.blue().bold()
+                };
+                let padding: String = line
+                    .chars()
+                    .take(start)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let label = if label.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {label}")
+                };
+                out.push_str(&format!("{gutter} {bar} {padding}{underline}{label}\n"));
+            }
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{gutter} {bar}\n"));
+            out.push_str(&format!(
+                "{gutter} {} {}: {}\n",
+                "=".blue().bold(),
+                "help".bold(),
+                help
+            ));
+        }
+        out
+    }
+}
+
+/// How many characters from `start` a caret underline covers: the word or string starting
+/// there, or the one character
+fn marked_width(line: &str, start: usize) -> usize {
+    let mut rest = line.chars().skip(start);
+    let width = match rest.next() {
+        None => 1,
+        Some(quote @ ('"' | '\'')) => match rest.position(|c| c == quote) {
+            Some(end) => end + 2,
+            None => line.chars().count() - start,
+        },
+        Some(c) if c.is_alphanumeric() || c == '_' => {
+            1 + rest
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .count()
+        }
+        Some(_) => 1,
+    };
+    width.max(1)
+}
+
+impl From<&NagariError> for Diagnostic {
+    /// The error as a diagnostic without a span; [`Diagnostic::from`] a parse error keeps its
+    /// span
+    fn from(error: &NagariError) -> Self {
+        Diagnostic::error(error.code(), error.to_string())
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let at = |line: usize, column: usize| Span { line, column };
+        let code = match error {
+            ParseError::Io { .. } => "E0005",
+            ParseError::SourceTooLarge { .. } | ParseError::TooDeep { .. } => "E0009",
+            _ => "E0002",
+        };
+        match error {
+            ParseError::UnexpectedToken {
+                token,
+                line,
+                column,
+            } => Diagnostic::error(code, format!("unexpected {token}"))
+                .with_span(at(*line, *column))
+                .with_label(at(*line, *column), "not expected here"),
+            ParseError::Expected {
+                expected,
+                found,
+                line,
+                column,
+            } => Diagnostic::error(code, format!("expected {expected}, found {found}"))
+                .with_span(at(*line, *column))
+                .with_label(at(*line, *column), format!("expected {expected}")),
+            ParseError::SyntaxError {
+                message,
+                line,
+                column,
+            } => Diagnostic::error(code, message.clone()).with_span(at(*line, *column)),
+            ParseError::InvalidCharacter {
+                character,
+                line,
+                column,
+            } => Diagnostic::error(code, format!("invalid character '{character}'"))
+                .with_span(at(*line, *column))
+                .with_label(at(*line, *column), "not part of Nagari's syntax"),
+            ParseError::UnterminatedString { line } => {
+                Diagnostic::error(code, "unterminated string literal")
+                    .with_span(at(*line, 1))
+                    .with_help("close the string with the quote it starts with")
+            }
+            ParseError::TooDeep { max, line, column } => {
+                Diagnostic::error(code, format!("nesting deeper than the limit of {max}"))
+                    .with_span(at(*line, *column))
+                    .with_help("move the innermost part into a function or variable of its own")
+            }
+            ParseError::UnexpectedEof
+            | ParseError::UnexpectedEOF
+            | ParseError::UnexpectedEndOfInput => Diagnostic::error(code, error.to_string())
+                .with_help("the last statement or block is not finished"),
+            _ => Diagnostic::error(code, error.to_string()),
+        }
+    }
+}
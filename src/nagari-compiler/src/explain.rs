@@ -0,0 +1,80 @@
+//! Long-form explanations for compiler error codes, shown by `nag explain`
+
+/// A documented error code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorExplanation {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub text: &'static str,
+}
+
+const EXPLANATIONS: &[ErrorExplanation] = &[
+    ErrorExplanation {
+        code: "E0001",
+        title: "invalid token",
+        text: include_str!("../explanations/E0001.md"),
+    },
+    ErrorExplanation {
+        code: "E0002",
+        title: "syntax error",
+        text: include_str!("../explanations/E0002.md"),
+    },
+    ErrorExplanation {
+        code: "E0003",
+        title: "type mismatch",
+        text: include_str!("../explanations/E0003.md"),
+    },
+    ErrorExplanation {
+        code: "E0004",
+        title: "bytecode generation failed",
+        text: include_str!("../explanations/E0004.md"),
+    },
+    ErrorExplanation {
+        code: "E0005",
+        title: "file could not be read or written",
+        text: include_str!("../explanations/E0005.md"),
+    },
+    ErrorExplanation {
+        code: "E0006",
+        title: "semantic error",
+        text: include_str!("../explanations/E0006.md"),
+    },
+    ErrorExplanation {
+        code: "E0007",
+        title: "import cycle",
+        text: include_str!("../explanations/E0007.md"),
+    },
+    ErrorExplanation {
+        code: "E0008",
+        title: "invalid configuration",
+        text: include_str!("../explanations/E0008.md"),
+    },
+    ErrorExplanation {
+        code: "E0009",
+        title: "limit exceeded",
+        text: include_str!("../explanations/E0009.md"),
+    },
+];
+
+/// All documented error codes, in order
+pub fn all() -> &'static [ErrorExplanation] {
+    EXPLANATIONS
+}
+
+/// Look up an error code. Accepts `E0002`, `e0002` or just `2`.
+pub fn lookup(code: &str) -> Option<&'static ErrorExplanation> {
+    let code = normalize_code(code)?;
+    EXPLANATIONS
+        .iter()
+        .find(|explanation| explanation.code == code)
+}
+
+fn normalize_code(code: &str) -> Option<String> {
+    let code = code.trim();
+    let digits = code
+        .strip_prefix('E')
+        .or_else(|| code.strip_prefix('e'))
+        .unwrap_or(code);
+    let number: u32 = digits.parse().ok()?;
+    Some(format!("E{number:04}"))
+}
@@ -0,0 +1,1093 @@
+//! Declarations for untyped JavaScript modules.
+//!
+//! A `.nagd` file declares what an npm package (or any other JavaScript module) exports, so
+//! calls into it are checked and the language server can complete its members:
+//!
+//! ```text
+//! # Declarations for the `left-pad` package
+//! module "left-pad":
+//!     # Pad `text` on the left to `length` characters
+//!     def leftPad(text: str, length: int, fill: str = " ") -> str
+//!     async def fetchPadded(url: str) -> str
+//!     VERSION: str
+//! ```
+//!
+//! Comments right above a member document it. Parameters with a default may be left out and a
+//! `*rest` parameter takes any number of arguments. Types are Nagari type names (`int`,
+//! `list[str]`, `str | None`, ...); anything else is `any` and accepts every value.
+//!
+//! Members of a `globals:` block are available without an import, which is how functions
+//! registered by a host application are declared.
+
+use crate::error::NagariError;
+use crate::types::{CallableSignature, FunctionParameter, Type, UnionType};
+use nagari_parser::{
+    ArrowFunctionBody, BinaryOperator, ComprehensionGenerator, Expression, Literal, Program,
+    Statement,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Extension of declaration files
+pub const EXTENSION: &str = "nagd";
+
+/// Declared modules, by the name they are imported with, and declared globals
+#[derive(Debug, Clone, Default)]
+pub struct ExternDeclarations {
+    modules: HashMap<String, ExternModule>,
+    /// Members of `globals:` blocks, as a module without a name
+    globals: ExternModule,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExternModule {
+    pub name: String,
+    pub members: Vec<ExternMember>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExternMember {
+    pub name: String,
+    pub kind: ExternKind,
+    /// The declaration as written, e.g. `def leftPad(text: str, length: int) -> str`
+    pub signature: String,
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExternKind {
+    Function(CallableSignature),
+    Value(Type),
+}
+
+impl ExternModule {
+    pub fn member(&self, name: &str) -> Option<&ExternMember> {
+        self.members.iter().find(|member| member.name == name)
+    }
+}
+
+impl ExternDeclarations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the declarations in `source`; `origin` names it in error messages
+    pub fn parse(source: &str, origin: &str) -> Result<Self, NagariError> {
+        let mut declarations = Self::new();
+        let mut current: Option<ExternModule> = None;
+        let mut doc: Vec<String> = Vec::new();
+
+        for (index, raw) in source.lines().enumerate() {
+            let error = |message: String| {
+                NagariError::ParseError(format!("{origin}:{}: {message}", index + 1))
+            };
+            let line = raw.trim();
+            if line.is_empty() {
+                doc.clear();
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix('#') {
+                doc.push(comment.trim().to_string());
+                continue;
+            }
+
+            if !raw.starts_with(char::is_whitespace) && line == "globals:" {
+                declarations.insert(current.replace(ExternModule::default()));
+                doc.clear();
+                continue;
+            }
+
+            if !raw.starts_with(char::is_whitespace) {
+                let name = line
+                    .strip_prefix("module ")
+                    .and_then(|rest| rest.trim().strip_suffix(':'))
+                    .map(|name| name.trim().trim_matches(|c| c == '"' || c == '\''))
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| error(format!("expected `module \"name\":`, found `{line}`")))?;
+                declarations.insert(current.replace(ExternModule {
+                    name: name.to_string(),
+                    members: Vec::new(),
+                }));
+                doc.clear();
+                continue;
+            }
+
+            let module = current.as_mut().ok_or_else(|| {
+                error("member declared outside of a `module` or `globals` block".to_string())
+            })?;
+            let mut member = parse_member(line).map_err(error)?;
+            member.doc = (!doc.is_empty()).then(|| doc.join("\n"));
+            doc.clear();
+            module.members.push(member);
+        }
+
+        declarations.insert(current);
+        Ok(declarations)
+    }
+
+    /// Load a `.nagd` file, or every `.nagd` file below a directory
+    pub fn load(path: &Path) -> Result<Self, NagariError> {
+        let mut declarations = Self::new();
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .map_err(|e| NagariError::IoError(format!("{}: {e}", path.display())))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect::<Vec<_>>();
+            entries.sort();
+            for entry in entries {
+                if entry.is_dir() || entry.extension().is_some_and(|ext| ext == EXTENSION) {
+                    declarations.extend(Self::load(&entry)?);
+                }
+            }
+        } else {
+            let source = fs::read_to_string(path)
+                .map_err(|e| NagariError::IoError(format!("{}: {e}", path.display())))?;
+            declarations.extend(Self::parse(&source, &path.display().to_string())?);
+        }
+        Ok(declarations)
+    }
+
+    /// Add the modules and globals of `other`; members of a module declared in both are merged
+    pub fn extend(&mut self, other: ExternDeclarations) {
+        for module in other.modules.into_values() {
+            self.insert(Some(module));
+        }
+        self.insert(Some(other.globals));
+    }
+
+    fn insert(&mut self, module: Option<ExternModule>) {
+        let Some(module) = module else { return };
+        if module.name.is_empty() {
+            self.globals.members.extend(module.members);
+            return;
+        }
+        match self.modules.get_mut(&module.name) {
+            Some(existing) => existing.members.extend(module.members),
+            None => {
+                self.modules.insert(module.name.clone(), module);
+            }
+        }
+    }
+
+    pub fn module(&self, name: &str) -> Option<&ExternModule> {
+        self.modules.get(name)
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &ExternModule> {
+        self.modules.values()
+    }
+
+    /// Members available without an import
+    pub fn globals(&self) -> &[ExternMember] {
+        &self.globals.members
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty() && self.globals.members.is_empty()
+    }
+
+    /// Check the imports from and calls into declared modules and globals made by `program`
+    pub fn check(&self, program: &Program) -> Result<(), NagariError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let mut checker = Checker {
+            declarations: self,
+            errors: Vec::new(),
+        };
+        let mut scope = self.global_scope();
+        checker.block(&program.statements, &mut scope);
+        checker.finish()
+    }
+
+    /// Check `expression` in the scope left by the `context` statements, with `parameters`
+    /// of an enclosing function bound on top, and return its type as far as it is known.
+    /// Only errors in the expression itself are reported.
+    pub fn check_expression(
+        &self,
+        context: &[Statement],
+        parameters: &[nagari_parser::FunctionParameter],
+        expression: &Expression,
+    ) -> Result<Type, NagariError> {
+        let mut checker = Checker {
+            declarations: self,
+            errors: Vec::new(),
+        };
+        let mut scope = self.global_scope();
+        checker.block(context, &mut scope);
+        checker.errors.clear();
+
+        let mut scope = with_parameters(&scope, parameters);
+        let value_type = checker.expression(expression, &mut scope);
+        checker.finish().map(|()| value_type)
+    }
+
+    fn global_scope(&self) -> Scope<'_> {
+        self.globals
+            .members
+            .iter()
+            .map(|member| (member.name.clone(), Known::Member(&self.globals, member)))
+            .collect()
+    }
+}
+
+/// What a name in scope is known to be
+#[derive(Clone)]
+enum Known<'a> {
+    Module(&'a ExternModule),
+    Member(&'a ExternModule, &'a ExternMember),
+    Typed(Type),
+}
+
+type Scope<'a> = HashMap<String, Known<'a>>;
+
+struct Checker<'a> {
+    declarations: &'a ExternDeclarations,
+    errors: Vec<String>,
+}
+
+impl<'a> Checker<'a> {
+    fn finish(self) -> Result<(), NagariError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(NagariError::TypeError(self.errors.join("\n  ")))
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement], scope: &mut Scope<'a>) {
+        for statement in statements {
+            self.statement(statement, scope);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, scope: &mut Scope<'a>) {
+        match statement {
+            Statement::Let { name, value } | Statement::Const { name, value } => {
+                let value_type = self.expression(value, scope);
+                bind(scope, name, value_type);
+            }
+            Statement::Expression(expr) => {
+                self.expression(expr, scope);
+            }
+            Statement::Return(value) => {
+                if let Some(value) = value {
+                    self.expression(value, scope);
+                }
+            }
+            Statement::If {
+                condition,
+                then_body,
+                else_body,
+            } => {
+                self.expression(condition, scope);
+                self.block(then_body, &mut scope.clone());
+                if let Some(else_body) = else_body {
+                    self.block(else_body, &mut scope.clone());
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition, scope);
+                self.block(body, &mut scope.clone());
+            }
+            Statement::For {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable, scope);
+                let mut inner = scope.clone();
+                inner.remove(variable);
+                self.block(body, &mut inner);
+            }
+            Statement::With { items, body, .. } => {
+                let mut inner = scope.clone();
+                for item in items {
+                    self.expression(&item.context, scope);
+                    if let Some(alias) = &item.alias {
+                        inner.remove(alias);
+                    }
+                }
+                self.block(body, &mut inner);
+            }
+            Statement::Match { subject, cases } => {
+                self.expression(subject, scope);
+                for case in cases {
+                    let mut inner = scope.clone();
+                    for name in case.pattern.captures() {
+                        inner.remove(name);
+                    }
+                    if let Some(guard) = &case.guard {
+                        self.expression(guard, &mut inner);
+                    }
+                    self.block(&case.body, &mut inner);
+                }
+            }
+            Statement::Function {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                scope.remove(name);
+                let mut inner = with_parameters(scope, parameters);
+                self.block(body, &mut inner);
+            }
+            Statement::Class { name, methods, .. } => {
+                scope.remove(name);
+                self.block(methods, &mut scope.clone());
+            }
+            Statement::ExportDeclaration { declaration } => self.statement(declaration, scope),
+            Statement::ExportNamed { .. } | Statement::ExportAll { .. } => {}
+            Statement::Import { source, items } => {
+                let Some(module) = self.declarations.module(source) else {
+                    for item in items {
+                        scope.remove(item.alias.as_ref().unwrap_or(&item.name));
+                    }
+                    return;
+                };
+                for item in items {
+                    let local = item.alias.as_ref().unwrap_or(&item.name);
+                    if item.name == "*" {
+                        scope.insert(local.clone(), Known::Module(module));
+                    } else if let Some(member) = module.member(&item.name) {
+                        scope.insert(local.clone(), Known::Member(module, member));
+                    } else {
+                        scope.remove(local);
+                        self.errors.push(format!(
+                            "\"{}\" has no declared export '{}'",
+                            module.name, item.name
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Walk `expr`, returning its type as far as it is known
+    fn expression(&mut self, expr: &Expression, scope: &mut Scope<'a>) -> Type {
+        match expr {
+            Expression::Literal(literal) => match literal {
+                Literal::Number(n) if n.fract() == 0.0 => Type::Int,
+                Literal::Number(_) => Type::Float,
+                Literal::String(_) => Type::Str,
+                Literal::Boolean(_) => Type::Bool,
+                Literal::Null => Type::None,
+            },
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(Known::Typed(known)) => known.clone(),
+                Some(Known::Member(_, member)) => match &member.kind {
+                    ExternKind::Value(value_type) => value_type.clone(),
+                    ExternKind::Function(_) => Type::Any,
+                },
+                _ => Type::Any,
+            },
+            Expression::Call {
+                function,
+                arguments,
+                keywords,
+            } => {
+                let arguments: Vec<Type> = arguments
+                    .iter()
+                    .map(|argument| self.expression(argument, scope))
+                    .collect();
+                for keyword in keywords {
+                    self.expression(&keyword.value, scope);
+                }
+                match self.callee(function, scope) {
+                    // Keyword arguments reach the function as an options object, which its
+                    // declaration doesn't describe
+                    Some((module, member)) if keywords.is_empty() => {
+                        self.call(module, member, &arguments)
+                    }
+                    _ => {
+                        self.expression(function, scope);
+                        Type::Any
+                    }
+                }
+            }
+            Expression::Member {
+                object,
+                property,
+                computed,
+            } => {
+                if let (Expression::Identifier(name), false) = (object.as_ref(), computed) {
+                    if let Some(Known::Module(module)) = scope.get(name) {
+                        return match module.member(property) {
+                            Some(ExternMember {
+                                kind: ExternKind::Value(value_type),
+                                ..
+                            }) => value_type.clone(),
+                            Some(_) => Type::Any,
+                            None => {
+                                self.missing_member(module, property);
+                                Type::Any
+                            }
+                        };
+                    }
+                }
+                self.expression(object, scope);
+                Type::Any
+            }
+            Expression::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.expression(left, scope);
+                let right = self.expression(right, scope);
+                binary_type(operator, &left, &right)
+            }
+            Expression::Unary { operand, .. } | Expression::Await(operand) => {
+                self.expression(operand, scope);
+                Type::Any
+            }
+            Expression::Array(elements) => {
+                for element in elements {
+                    self.expression(element, scope);
+                }
+                Type::List(Box::new(Type::Any))
+            }
+            Expression::Set(elements) => {
+                for element in elements {
+                    self.expression(element, scope);
+                }
+                Type::Set(Box::new(Type::Any))
+            }
+            Expression::Object(properties) => {
+                for property in properties {
+                    self.expression(&property.value, scope);
+                }
+                Type::Dict(Box::new(Type::Str), Box::new(Type::Any))
+            }
+            Expression::Function {
+                parameters, body, ..
+            } => {
+                let mut inner = with_parameters(scope, parameters);
+                self.block(body, &mut inner);
+                Type::Any
+            }
+            Expression::Arrow {
+                parameters, body, ..
+            } => {
+                let mut inner = with_parameters(scope, parameters);
+                match body {
+                    ArrowFunctionBody::Expression(body) => {
+                        self.expression(body, &mut inner);
+                    }
+                    ArrowFunctionBody::Block(body) => self.block(body, &mut inner),
+                }
+                Type::Any
+            }
+            Expression::Assignment { left, right, .. } => {
+                let value_type = self.expression(right, scope);
+                match left.as_ref() {
+                    Expression::Identifier(name) => bind(scope, name, value_type.clone()),
+                    other => {
+                        self.expression(other, scope);
+                    }
+                }
+                value_type
+            }
+            Expression::Conditional {
+                test,
+                consequent,
+                alternate,
+            } => {
+                self.expression(test, scope);
+                self.expression(consequent, scope);
+                self.expression(alternate, scope);
+                Type::Any
+            }
+            Expression::TemplateLiteral { expressions, .. } => {
+                for expression in expressions {
+                    self.expression(expression, scope);
+                }
+                Type::Str
+            }
+            Expression::InlineJs(_) => Type::Any,
+            Expression::Index { object, index } => {
+                self.expression(object, scope);
+                self.expression(index, scope);
+                Type::Any
+            }
+            Expression::Slice {
+                object,
+                start,
+                end,
+                step,
+            } => {
+                self.expression(object, scope);
+                for bound in [start, end, step].into_iter().flatten() {
+                    self.expression(bound, scope);
+                }
+                Type::Any
+            }
+            Expression::ListComprehension {
+                element,
+                generators,
+            } => {
+                let mut inner = self.generators(generators, scope);
+                self.expression(element, &mut inner);
+                Type::List(Box::new(Type::Any))
+            }
+            Expression::SetComprehension {
+                element,
+                generators,
+            } => {
+                let mut inner = self.generators(generators, scope);
+                self.expression(element, &mut inner);
+                Type::Set(Box::new(Type::Any))
+            }
+            Expression::DictComprehension {
+                key,
+                value,
+                generators,
+            } => {
+                let mut inner = self.generators(generators, scope);
+                self.expression(key, &mut inner);
+                self.expression(value, &mut inner);
+                Type::Dict(Box::new(Type::Any), Box::new(Type::Any))
+            }
+            Expression::Generator {
+                element,
+                generators,
+            } => {
+                let mut inner = self.generators(generators, scope);
+                self.expression(element, &mut inner);
+                Type::Any
+            }
+        }
+    }
+
+    /// The scope inside a comprehension, where each target hides the outer binding of its name
+    fn generators(
+        &mut self,
+        generators: &[ComprehensionGenerator],
+        scope: &Scope<'a>,
+    ) -> Scope<'a> {
+        let mut inner = scope.clone();
+        for generator in generators {
+            self.expression(&generator.iter, &mut inner);
+            inner.remove(&generator.target);
+            for condition in &generator.conditions {
+                self.expression(condition, &mut inner);
+            }
+        }
+        inner
+    }
+
+    /// The declared member `function` refers to, if any
+    fn callee(
+        &mut self,
+        function: &Expression,
+        scope: &Scope<'a>,
+    ) -> Option<(&'a ExternModule, &'a ExternMember)> {
+        match function {
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(Known::Member(module, member)) => Some((*module, *member)),
+                _ => None,
+            },
+            Expression::Member {
+                object,
+                property,
+                computed: false,
+            } => match object.as_ref() {
+                Expression::Identifier(name) => match scope.get(name) {
+                    Some(Known::Module(module)) => {
+                        let module: &'a ExternModule = module;
+                        let member = module.member(property);
+                        if member.is_none() {
+                            self.missing_member(module, property);
+                        }
+                        member.map(|member| (module, member))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn missing_member(&mut self, module: &ExternModule, name: &str) {
+        self.errors.push(format!(
+            "\"{}\" has no declared export '{name}'",
+            module.name
+        ));
+    }
+
+    /// Check a call of `member` with arguments of the given types, returning its result type
+    fn call(&mut self, module: &ExternModule, member: &ExternMember, arguments: &[Type]) -> Type {
+        let signature = match &member.kind {
+            ExternKind::Function(signature) => signature,
+            ExternKind::Value(value_type) => {
+                if !matches!(value_type, Type::Any | Type::Function(..)) {
+                    self.errors.push(format!(
+                        "'{}'{} is declared as {}, not a function",
+                        member.name,
+                        origin(module),
+                        type_name(value_type)
+                    ));
+                }
+                return Type::Any;
+            }
+        };
+
+        let parameters = &signature.parameters;
+        let required = parameters.iter().filter(|p| !p.optional && !p.rest).count();
+        let maximum = (!parameters.iter().any(|p| p.rest)).then_some(parameters.len());
+        if arguments.len() < required || maximum.is_some_and(|max| arguments.len() > max) {
+            let expected = match maximum {
+                Some(max) if max == required => format!("{required}"),
+                Some(max) => format!("{required} to {max}"),
+                None => format!("at least {required}"),
+            };
+            self.errors.push(format!(
+                "{}(){} takes {expected} argument(s) but {} were given",
+                member.name,
+                origin(module),
+                arguments.len()
+            ));
+        }
+
+        for (index, argument) in arguments.iter().enumerate() {
+            let parameter = match parameters.get(index) {
+                Some(parameter) => parameter,
+                None => match parameters.last() {
+                    Some(last) if last.rest => last,
+                    _ => break,
+                },
+            };
+            if !accepts(&parameter.param_type, argument) {
+                self.errors.push(format!(
+                    "argument '{}' of {}(){} expects {}, got {}",
+                    parameter.name,
+                    member.name,
+                    origin(module),
+                    type_name(&parameter.param_type),
+                    type_name(argument)
+                ));
+            }
+        }
+
+        if signature.is_async {
+            Type::Any
+        } else {
+            signature.return_type.clone()
+        }
+    }
+}
+
+/// ` from "module"` in messages about members of a module; nothing for globals
+fn origin(module: &ExternModule) -> String {
+    if module.name.is_empty() {
+        String::new()
+    } else {
+        format!(" from \"{}\"", module.name)
+    }
+}
+
+/// Result type of `left operator right`, where it follows from the operand types
+fn binary_type(operator: &BinaryOperator, left: &Type, right: &Type) -> Type {
+    use BinaryOperator::*;
+    let numeric = |t: &Type| matches!(t, Type::Int | Type::Float);
+    match (operator, left, right) {
+        (Equal | NotEqual | Less | Greater | LessEqual | GreaterEqual, _, _) => Type::Bool,
+        (Add, Type::Str, Type::Str) => Type::Str,
+        (Add | Subtract | Multiply | Modulo, Type::Int, Type::Int) => Type::Int,
+        (BitwiseAnd | BitwiseOr | BitwiseXor | LeftShift | RightShift, Type::Int, Type::Int) => {
+            Type::Int
+        }
+        (Add | Subtract | Multiply | Modulo | Divide, left, right)
+            if numeric(left) && numeric(right) =>
+        {
+            Type::Float
+        }
+        _ => Type::Any,
+    }
+}
+
+/// Record the type of `name`, forgetting it when nothing is known
+fn bind(scope: &mut Scope, name: &str, value_type: Type) {
+    match value_type {
+        Type::Any => scope.remove(name),
+        known => scope.insert(name.to_string(), Known::Typed(known)),
+    };
+}
+
+/// `scope` as seen by a function body taking `parameters`
+fn with_parameters<'a>(
+    scope: &Scope<'a>,
+    parameters: &[nagari_parser::FunctionParameter],
+) -> Scope<'a> {
+    let mut inner = scope.clone();
+    for parameter in parameters {
+        let declared = parameter
+            .type_annotation
+            .as_ref()
+            .map_or(Type::Any, Type::from_annotation);
+        bind(&mut inner, &parameter.name, declared);
+    }
+    inner
+}
+
+/// Whether a value of type `actual` may be passed where `expected` is declared
+pub(crate) fn accepts(expected: &Type, actual: &Type) -> bool {
+    match (expected, actual) {
+        (Type::Any, _) | (_, Type::Any) => true,
+        (Type::Float, Type::Int) => true,
+        (expected, Type::Union(union)) => union.types.iter().all(|t| accepts(expected, t)),
+        (Type::Union(union), actual) => union.types.iter().any(|t| accepts(t, actual)),
+        (Type::List(expected), Type::List(actual)) | (Type::Set(expected), Type::Set(actual)) => {
+            accepts(expected, actual)
+        }
+        (Type::Dict(expected_key, expected_value), Type::Dict(key, value)) => {
+            accepts(expected_key, key) && accepts(expected_value, value)
+        }
+        (Type::Function(..), Type::Function(..)) => true,
+        (expected, actual) => expected == actual,
+    }
+}
+
+/// Name of `t` as written in declarations
+pub fn type_name(t: &Type) -> String {
+    match t {
+        Type::Int => "int".to_string(),
+        Type::Float => "float".to_string(),
+        Type::Str => "str".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::None => "None".to_string(),
+        Type::List(element) => format!("list[{}]", type_name(element)),
+        Type::Set(element) => format!("set[{}]", type_name(element)),
+        Type::Dict(key, value) => format!("dict[{}, {}]", type_name(key), type_name(value)),
+        Type::Function(..) => "callable".to_string(),
+        Type::Union(union) => union
+            .types
+            .iter()
+            .map(type_name)
+            .collect::<Vec<_>>()
+            .join(" | "),
+        _ => "any".to_string(),
+    }
+}
+
+fn parse_member(line: &str) -> Result<ExternMember, String> {
+    let (is_async, rest) = match line.strip_prefix("async ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, line),
+    };
+
+    let Some(rest) = rest.strip_prefix("def ") else {
+        let (name, annotation) = line
+            .split_once(':')
+            .filter(|_| !is_async)
+            .ok_or_else(|| format!("expected `def name(...)` or `name: type`, found `{line}`"))?;
+        return Ok(ExternMember {
+            name: identifier(name)?,
+            kind: ExternKind::Value(parse_type(annotation)),
+            signature: line.to_string(),
+            doc: None,
+        });
+    };
+
+    let (open, close) = match (rest.find('('), rest.rfind(')')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return Err(format!("expected a parameter list in `{line}`")),
+    };
+    let parameters = split_top_level(&rest[open + 1..close], ',')
+        .into_iter()
+        .filter(|parameter| !parameter.is_empty())
+        .map(parse_parameter)
+        .collect::<Result<Vec<_>, _>>()?;
+    let return_type = match rest[close + 1..].trim() {
+        "" => Type::Any,
+        tail => match tail.strip_prefix("->") {
+            Some(return_type) => parse_type(return_type),
+            None => {
+                return Err(format!(
+                    "expected `-> type` after the parameters, found `{tail}`"
+                ))
+            }
+        },
+    };
+
+    Ok(ExternMember {
+        name: identifier(&rest[..open])?,
+        kind: ExternKind::Function(CallableSignature {
+            type_parameters: Vec::new(),
+            parameters,
+            return_type,
+            is_async,
+            is_generator: false,
+        }),
+        signature: line.to_string(),
+        doc: None,
+    })
+}
+
+fn parse_parameter(text: &str) -> Result<FunctionParameter, String> {
+    let (text, rest) = match text.strip_prefix('*') {
+        Some(text) => (text, true),
+        None => (text, false),
+    };
+    let (head, default_value) = match text.split_once('=') {
+        Some((head, default)) => (head, Some(default.trim().to_string())),
+        None => (text, None),
+    };
+    let (name, param_type) = match head.split_once(':') {
+        Some((name, annotation)) => (name, parse_type(annotation)),
+        None => (head, Type::Any),
+    };
+
+    Ok(FunctionParameter {
+        name: identifier(name)?,
+        param_type,
+        optional: default_value.is_some(),
+        default_value,
+        rest,
+    })
+}
+
+fn identifier(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    let valid = text.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    if valid {
+        Ok(text.to_string())
+    } else {
+        Err(format!("`{text}` is not a valid name"))
+    }
+}
+
+/// Type of a declared annotation such as `int`, `list[str]` or `str | None`
+pub fn parse_type(text: &str) -> Type {
+    let text = text.trim();
+    let alternatives = split_top_level(text, '|');
+    if alternatives.len() > 1 {
+        return Type::Union(UnionType {
+            types: alternatives.into_iter().map(parse_type).collect(),
+        });
+    }
+
+    let (base, arguments) = match text.find('[') {
+        Some(open) if text.ends_with(']') => (
+            text[..open].trim(),
+            split_top_level(&text[open + 1..text.len() - 1], ','),
+        ),
+        _ => (text, Vec::new()),
+    };
+    Type::named(base, arguments.into_iter().map(parse_type).collect())
+}
+
+/// Split `text` on `separator` outside of brackets, parentheses and quotes
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '(' | '{') => depth += 1,
+            (None, ']' | ')' | '}') => depth = depth.saturating_sub(1),
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(text[start..index].trim());
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(text[start..].trim());
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ExternDeclarations {
+        ExternDeclarations::parse(source, "test.nagd").expect("declarations should parse")
+    }
+
+    fn parse_error(source: &str) -> String {
+        ExternDeclarations::parse(source, "test.nagd")
+            .unwrap_err()
+            .to_string()
+    }
+
+    /// The errors of checking `source` against `declarations`, one per line
+    fn errors(declarations: &ExternDeclarations, source: &str) -> Vec<String> {
+        let program = nagari_parser::parse(source).expect("test source should parse");
+        match declarations.check(&program) {
+            Ok(()) => Vec::new(),
+            Err(NagariError::TypeError(message)) => {
+                message.split("\n  ").map(str::to_string).collect()
+            }
+            Err(error) => panic!("unexpected error {error}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_members_and_docs() {
+        let declarations = parse(
+            "# About the module\nmodule 'ui':\n    # Shows a dialog\n    # and waits\n    async def confirm(text: str, *buttons: str) -> bool\n\n    # Cleared by the blank line\n\n    theme: dict[str, str]\n    def reset()\n",
+        );
+        let ui = declarations.module("ui").unwrap();
+        assert_eq!(ui.members.len(), 3);
+
+        let confirm = ui.member("confirm").unwrap();
+        assert_eq!(confirm.doc.as_deref(), Some("Shows a dialog\nand waits"));
+        assert_eq!(
+            confirm.signature,
+            "async def confirm(text: str, *buttons: str) -> bool"
+        );
+        let ExternKind::Function(signature) = &confirm.kind else {
+            panic!("confirm should be a function");
+        };
+        assert!(signature.is_async);
+        assert_eq!(signature.return_type, Type::Bool);
+        assert!(signature.parameters[1].rest);
+
+        let theme = ui.member("theme").unwrap();
+        assert!(theme.doc.is_none());
+        assert!(matches!(&theme.kind, ExternKind::Value(Type::Dict(..))));
+        let ExternKind::Function(reset) = &ui.member("reset").unwrap().kind else {
+            panic!("reset should be a function");
+        };
+        assert_eq!(reset.return_type, Type::Any);
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        assert_eq!(
+            parse_error("    def f()\n"),
+            "Parser error: test.nagd:1: member declared outside of a `module` or `globals` block"
+        );
+        assert!(parse_error("module:\n").contains("test.nagd:1: expected `module \"name\":`"));
+        assert!(parse_error("module \"m\":\n    def f() str\n")
+            .contains("test.nagd:2: expected `-> type` after the parameters, found `str`"));
+        assert!(parse_error("module \"m\":\n    async VERSION: str\n")
+            .contains("expected `def name(...)` or `name: type`"));
+        assert!(parse_error("module \"m\":\n    def f(a-b: int)\n")
+            .contains("`a-b` is not a valid name"));
+    }
+
+    #[test]
+    fn test_modules_declared_twice_are_merged() {
+        let mut declarations = parse("module \"m\":\n    def a()\nglobals:\n    def g()\n");
+        declarations.extend(parse(
+            "module \"m\":\n    def b()\nmodule \"n\":\n    x: int\n",
+        ));
+        let names: Vec<&str> = declarations
+            .module("m")
+            .unwrap()
+            .members
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(declarations.modules().count(), 2);
+        assert_eq!(declarations.globals().len(), 1);
+        assert!(!declarations.is_empty());
+        assert!(ExternDeclarations::new().is_empty());
+    }
+
+    #[test]
+    fn test_load_reads_nagd_files_below_a_directory() {
+        let dir = std::env::temp_dir().join(format!("nagari-externs-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a.nagd"), "module \"a\":\n    def f()\n").unwrap();
+        fs::write(dir.join("nested/b.nagd"), "module \"b\":\n    x: int\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a declaration").unwrap();
+        fs::write(dir.join("bad.txt"), "    def f()\n").unwrap();
+
+        let declarations = ExternDeclarations::load(&dir);
+        let broken = ExternDeclarations::load(&dir.join("bad.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let declarations = declarations.unwrap();
+        assert!(declarations.module("a").is_some());
+        assert!(declarations.module("b").is_some());
+        assert_eq!(declarations.modules().count(), 2);
+        assert!(broken.unwrap_err().to_string().contains("bad.txt:1:"));
+    }
+
+    #[test]
+    fn test_checks_follow_scopes_and_values() {
+        let declarations = parse(
+            "module \"m\":\n    def twice(n: int) -> int\n    LIMIT: int\n    handler: callable\n",
+        );
+        assert_eq!(
+            errors(
+                &declarations,
+                "import m\nm.LIMIT(1)\nm.handler(1)\nx = m.twice(\"a\")\n"
+            ),
+            [
+                "'LIMIT' from \"m\" is declared as int, not a function",
+                "argument 'n' of twice() from \"m\" expects int, got str",
+            ]
+        );
+        // A loop variable, parameter or import of an undeclared module hides the name
+        assert!(errors(
+            &declarations,
+            "import { twice } from \"m\"\nfor twice in [1]:\n    twice(\"a\")\ndef f(twice):\n    twice(\"a\")\n"
+        )
+        .is_empty());
+        assert!(errors(
+            &declarations,
+            "import { twice } from \"other\"\ntwice(\"a\")\n"
+        )
+        .is_empty());
+        // Keyword arguments become an options object the declaration doesn't describe
+        assert!(errors(
+            &declarations,
+            "import { twice } from \"m\"\ntwice(n=\"a\")\n"
+        )
+        .is_empty());
+        // Known types flow through assignments
+        assert_eq!(
+            errors(
+                &declarations,
+                "import { twice } from \"m\"\nn = twice(1)\nx = twice(n + 0.5)\n"
+            ),
+            ["argument 'n' of twice() from \"m\" expects int, got float"]
+        );
+    }
+
+    #[test]
+    fn test_types() {
+        let optional = parse_type("list[str] | None");
+        assert_eq!(type_name(&optional), "list[str] | None");
+        assert!(accepts(&optional, &Type::None));
+        assert!(accepts(&optional, &Type::List(Box::new(Type::Str))));
+        assert!(!accepts(&optional, &Type::List(Box::new(Type::Int))));
+        assert!(accepts(&Type::Float, &Type::Int));
+        assert!(!accepts(&Type::Int, &Type::Float));
+        // Every alternative of a union argument has to be accepted
+        assert!(!accepts(&Type::Str, &parse_type("str | int")));
+        assert_eq!(
+            type_name(&parse_type("dict[str, list[int]]")),
+            "dict[str, list[int]]"
+        );
+        assert_eq!(
+            split_top_level("a: dict[str, int], b = \"x, y\", c", ','),
+            ["a: dict[str, int]", "b = \"x, y\"", "c"]
+        );
+        assert_eq!(
+            binary_type(&BinaryOperator::Divide, &Type::Int, &Type::Int),
+            Type::Float
+        );
+        assert_eq!(
+            binary_type(&BinaryOperator::Add, &Type::Str, &Type::Int),
+            Type::Any
+        );
+    }
+}
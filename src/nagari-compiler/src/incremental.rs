@@ -0,0 +1,262 @@
+//! Re-transpilation of a changed file, one top-level statement at a time.
+//!
+//! Watch mode compiles the same file on every save, and most saves touch a single function.
+//! [`IncrementalCompiler`] keeps the output of the previous compilation per top-level
+//! statement and transpiles again only the statements whose syntax tree changed; the output
+//! of the others is reused as it was. A statement is also transpiled again when the variables
+//! declared before it changed, since they decide between `let x = ...` and `x = ...` in its
+//! output. The result is the same code a full compilation produces.
+//!
+//! Each compilation reports the top-level declarations it re-emitted or dropped, so a dev
+//! server can hot-reload just those functions and classes.
+
+use crate::error::NagariError;
+use crate::transpiler::{self, Section, SectionState};
+use crate::{ast, convert_parse_error, convert_statement, defines, embed, minify, Compiler};
+use nagari_parser::Statement;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct IncrementalResult {
+    /// Generated JavaScript code
+    pub js_code: String,
+    /// Names of the top-level declarations that are new or changed
+    pub changed: Vec<String>,
+    /// Names of the top-level declarations that are gone
+    pub removed: Vec<String>,
+    /// Top-level statements transpiled again
+    pub emitted: usize,
+    /// Top-level statements whose previous output was reused
+    pub reused: usize,
+}
+
+/// A top-level statement and its output
+struct CachedStatement {
+    statement: Statement,
+    fingerprint: u64,
+    /// State the statements before it left
+    before: SectionState,
+    section: Section,
+    /// Whether it embeds files, which may have changed since
+    embeds: bool,
+}
+
+pub struct IncrementalCompiler {
+    compiler: Compiler,
+    /// File the source is read from, which `embed()` paths are relative to
+    source_path: Option<PathBuf>,
+    /// Whether the program defines its own `embed`
+    embed_shadowed: bool,
+    header: Option<String>,
+    cache: Vec<CachedStatement>,
+}
+
+impl IncrementalCompiler {
+    pub fn new(compiler: Compiler) -> Self {
+        Self {
+            compiler,
+            source_path: None,
+            embed_shadowed: false,
+            header: None,
+            cache: Vec::new(),
+        }
+    }
+
+    pub fn with_source_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.source_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Forget the previous compilation, so the next one transpiles every statement
+    pub fn reset(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn compile(&mut self, source: &str) -> Result<IncrementalResult, NagariError> {
+        self.compiler
+            .config
+            .validate()
+            .map_err(NagariError::ConfigError)?;
+        let limits = self.compiler.config.limits.parser();
+        limits.check_source(source).map_err(convert_parse_error)?;
+        let tokens = nagari_parser::Lexer::new(source)
+            .tokenize()
+            .map_err(convert_parse_error)?;
+        let program = nagari_parser::Parser::new(tokens)
+            .with_limits(&limits)
+            .parse_program()
+            .map_err(convert_parse_error)?;
+        self.compiler.config.externs.check(&program)?;
+
+        // Statements compiled with the other meaning of `embed()` can't be reused
+        let embed_shadowed = program
+            .statements
+            .iter()
+            .any(|statement| declared_name(statement).as_deref() == Some(embed::INTRINSIC));
+        if embed_shadowed != self.embed_shadowed {
+            self.embed_shadowed = embed_shadowed;
+            self.reset();
+        }
+
+        let config = &self.compiler.config;
+        let constants = config.constants();
+
+        // Previous statements by fingerprint, each reusable once
+        let mut previous: HashMap<u64, Vec<CachedStatement>> = HashMap::new();
+        for cached in std::mem::take(&mut self.cache).into_iter().rev() {
+            previous.entry(cached.fingerprint).or_default().push(cached);
+        }
+        // Temporaries of re-emitted statements are numbered past every one in use
+        let mut next_temp = previous
+            .values()
+            .flatten()
+            .map(|cached| cached.section.state.temp_counter)
+            .max()
+            .unwrap_or(0);
+
+        let mut state = SectionState::default();
+        let mut changed = Vec::new();
+        let mut reused = 0;
+        for statement in program.statements {
+            let fingerprint = fingerprint(&statement);
+            let candidates = previous.entry(fingerprint).or_default();
+            let position = candidates.iter().rposition(|cached| {
+                !cached.embeds
+                    && cached.statement == statement
+                    && cached.before.declared == state.declared
+                    && cached.before.classes == state.classes
+                    && cached.before.sets == state.sets
+                    && cached.before.signatures == state.signatures
+            });
+
+            let cached = match position {
+                Some(position) => {
+                    reused += 1;
+                    candidates.remove(position)
+                }
+                None => {
+                    changed.extend(declared_name(&statement));
+                    let mut internal = ast::Program {
+                        statements: vec![convert_statement(statement.clone())?],
+                    };
+                    defines::apply(&mut internal, &constants);
+                    let embeds = !embed_shadowed
+                        && embed::inline(
+                            &mut internal.statements,
+                            self.source_path.as_deref(),
+                            config.embed_root.as_deref(),
+                        )? > 0;
+                    if config.minify {
+                        minify::program(&mut internal);
+                    }
+
+                    let before = SectionState {
+                        declared: state.declared.clone(),
+                        temp_counter: next_temp,
+                        classes: state.classes.clone(),
+                        sets: state.sets.clone(),
+                        signatures: state.signatures.clone(),
+                    };
+                    let section = transpiler::transpile_section(
+                        &internal.statements,
+                        &config.target,
+                        config.jsx,
+                        config.ssr,
+                        config.jsdoc,
+                        &before,
+                    )?;
+                    next_temp = section.state.temp_counter;
+                    CachedStatement {
+                        statement,
+                        fingerprint,
+                        before,
+                        section,
+                        embeds,
+                    }
+                }
+            };
+            state.declared = cached.section.state.declared.clone();
+            state.classes = cached.section.state.classes.clone();
+            state.sets = cached.section.state.sets.clone();
+            state.signatures = cached.section.state.signatures.clone();
+            self.cache.push(cached);
+        }
+
+        let current: HashSet<String> = self
+            .cache
+            .iter()
+            .filter_map(|cached| declared_name(&cached.statement))
+            .collect();
+        let mut removed: Vec<String> = previous
+            .into_values()
+            .flatten()
+            .filter_map(|cached| declared_name(&cached.statement))
+            .filter(|name| !current.contains(name))
+            .collect();
+        removed.sort();
+        removed.dedup();
+
+        let header = self.header.get_or_insert_with(|| {
+            transpiler::transpile_header(
+                &config.target,
+                config.jsx,
+                config.ssr,
+                config.environment,
+                &config.runtime.specifier,
+            )
+        });
+        let mut js_code = header.clone();
+        let mut helpers = HashSet::new();
+        for cached in &self.cache {
+            js_code.push_str(&cached.section.js_code);
+            helpers.extend(cached.section.helpers.iter().cloned());
+        }
+        js_code.push_str(&transpiler::transpile_footer(
+            &config.target,
+            config.jsx,
+            config.environment,
+            helpers,
+        ));
+        if config.minify {
+            js_code = minify::compact(&js_code);
+        }
+        config
+            .limits
+            .check_output("JavaScript output", js_code.len())?;
+
+        Ok(IncrementalResult {
+            js_code: config.line_endings.apply(js_code),
+            changed,
+            removed,
+            emitted: self.cache.len() - reused,
+            reused,
+        })
+    }
+}
+
+fn fingerprint(statement: &Statement) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{statement:?}").hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Name a top-level statement declares, if any
+fn declared_name(statement: &Statement) -> Option<String> {
+    match statement {
+        Statement::Function { name, .. }
+        | Statement::Class { name, .. }
+        | Statement::Let { name, .. }
+        | Statement::Const { name, .. } => Some(name.clone()),
+        Statement::Expression(nagari_parser::Expression::Assignment { left, .. }) => {
+            match left.as_ref() {
+                nagari_parser::Expression::Identifier(name) => Some(name.clone()),
+                _ => None,
+            }
+        }
+        Statement::ExportDeclaration { declaration } => declared_name(declaration),
+        _ => None,
+    }
+}
@@ -124,11 +124,13 @@ pub struct Lexer {
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
+        // Start at the line break after a shebang, like the parser's lexer
+        let position = nagari_parser::shebang(input).map_or(0, |line| line.chars().count());
         Self {
             input: input.chars().collect(),
-            position: 0,
+            position,
             line: 1,
-            column: 1,
+            column: position + 1,
             indent_stack: vec![0],
             bracket_depth: 0,
             jsx_depth: 0,
@@ -4,32 +4,84 @@
 //! including lexical analysis, parsing, type checking, and transpilation to JavaScript.
 
 pub mod ast;
+pub mod bundler;
 pub mod bytecode;
+pub mod declarations;
+pub mod defines;
+pub mod diagnostics;
+pub mod dual;
+pub mod embed;
+pub mod environment;
 pub mod error;
+pub mod explain;
+pub mod externs;
+pub mod incremental;
 pub mod lexer;
+pub mod limits;
+pub mod minify;
+pub mod module_graph;
 pub mod parser;
+pub mod portable;
+pub mod prelude;
+pub mod project;
+pub mod runtime_package;
+pub mod sourcemap;
+pub mod timings;
 pub mod transpiler;
+pub mod typechecker;
 pub mod types;
+pub mod warnings;
 
-#[cfg(test)]
-mod bytecode_tests;
-
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub use ast::Program;
-pub use error::NagariError;
+pub use bundler::{Bundle, BundleFormat};
+pub use diagnostics::Diagnostics;
+pub use environment::Environment;
+pub use error::{Diagnostic, Label, NagariError, Severity};
+pub use externs::ExternDeclarations;
+pub use incremental::{IncrementalCompiler, IncrementalResult};
 pub use lexer::Lexer;
-pub use parser::Parser as NagParser;
+pub use limits::Limits;
+pub use module_graph::{ImportCycle, ModuleGraph};
+pub use parser::Parser;
+pub use portable::LineEndings;
+pub use project::ModuleOutput;
+pub use runtime_package::RuntimePackage;
+pub use sourcemap::SourceMap;
+pub use timings::{CompilationTimings, Phase};
+pub use warnings::{Warning, WarningLevel};
+
+/// Version of the compiler; bytecode caches key their entries with it
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The parser under its old name
+#[deprecated(since = "0.3.0", note = "renamed to `Parser`")]
+pub type NagParser = Parser;
 
 // Import the enhanced parser for better code handling
 use nagari_parser;
 
+/// `source` parsed with the enhanced parser and converted to the internal AST, as the passes
+/// tested on it see it
+#[cfg(test)]
+fn test_program(source: &str) -> ast::Program {
+    let tokens = nagari_parser::Lexer::new(source)
+        .tokenize()
+        .expect("test source should lex");
+    let external_ast = nagari_parser::Parser::new(tokens)
+        .parse_program()
+        .expect("test source should parse");
+    convert_external_ast_to_internal(external_ast).expect("test source should convert")
+}
+
 // AST conversion function to translate between external and internal AST types
 fn convert_external_ast_to_internal(
     external_ast: nagari_parser::Program,
 ) -> Result<ast::Program, NagariError> {
-    let mut statements = Vec::new();
+    let mut statements = Vec::with_capacity(external_ast.statements.len());
 
     for external_stmt in external_ast.statements {
         let internal_stmt = convert_statement(external_stmt)?;
@@ -39,6 +91,58 @@ fn convert_external_ast_to_internal(
     Ok(ast::Program { statements })
 }
 
+fn convert_parse_error(e: nagari_parser::ParseError) -> NagariError {
+    match e {
+        nagari_parser::ParseError::UnexpectedToken {
+            token,
+            line,
+            column,
+        } => NagariError::ParseError(format!(
+            "Unexpected token '{}' at line {}, column {}",
+            token, line, column
+        )),
+        nagari_parser::ParseError::Expected {
+            expected,
+            found,
+            line,
+            column,
+        } => NagariError::ParseError(format!(
+            "Expected '{}' but found '{}' at line {}, column {}",
+            expected, found, line, column
+        )),
+        nagari_parser::ParseError::SyntaxError {
+            message,
+            line,
+            column,
+        } => NagariError::ParseError(format!(
+            "Syntax error at line {}, column {}: {}",
+            line, column, message
+        )),
+        nagari_parser::ParseError::Io { path, message } => {
+            NagariError::IoError(format!("Failed to read input file {}: {}", path, message))
+        }
+        nagari_parser::ParseError::SourceTooLarge { .. }
+        | nagari_parser::ParseError::TooDeep { .. } => NagariError::LimitExceeded(e.to_string()),
+        _ => NagariError::ParseError(format!("Parser error: {}", e)),
+    }
+}
+
+/// Why a file given to [`Compiler::compile_many`] did not compile. Syntax errors are kept as
+/// the parser reported them, for [`Compiler::diagnose_files`] to show where they are.
+enum Failure {
+    Parse(nagari_parser::ParseError),
+    Compile(NagariError),
+}
+
+impl Failure {
+    fn into_error(self) -> NagariError {
+        match self {
+            Failure::Parse(e) => convert_parse_error(e),
+            Failure::Compile(e) => e,
+        }
+    }
+}
+
 fn convert_statement(
     external_stmt: nagari_parser::Statement,
 ) -> Result<ast::Statement, NagariError> {
@@ -46,75 +150,14 @@ fn convert_statement(
     use nagari_parser::Statement as ExtStmt;
 
     match external_stmt {
-        ExtStmt::Expression(expr) => {
-            // Check if this expression is actually an assignment that should be a statement
-            match &expr {
-                nagari_parser::Expression::Assignment {
-                    left,
-                    operator,
-                    right,
-                } => {
-                    // Convert assignment expressions to assignment statements
-                    match left.as_ref() {
-                        nagari_parser::Expression::Identifier(name) => {
-                            // Use the operator information to determine assignment type
-                            let assignment_value = match operator {
-                                nagari_parser::AssignmentOperator::Assign => {
-                                    convert_expression(right.as_ref().clone())?
-                                }
-                                nagari_parser::AssignmentOperator::AddAssign => {
-                                    ast::Expression::Binary(ast::BinaryExpression {
-                                        left: Box::new(ast::Expression::Identifier(name.clone())),
-                                        operator: ast::BinaryOperator::Add,
-                                        right: Box::new(convert_expression(
-                                            right.as_ref().clone(),
-                                        )?),
-                                    })
-                                }
-                                nagari_parser::AssignmentOperator::SubtractAssign => {
-                                    ast::Expression::Binary(ast::BinaryExpression {
-                                        left: Box::new(ast::Expression::Identifier(name.clone())),
-                                        operator: ast::BinaryOperator::Subtract,
-                                        right: Box::new(convert_expression(
-                                            right.as_ref().clone(),
-                                        )?),
-                                    })
-                                }
-                                nagari_parser::AssignmentOperator::MultiplyAssign => {
-                                    ast::Expression::Binary(ast::BinaryExpression {
-                                        left: Box::new(ast::Expression::Identifier(name.clone())),
-                                        operator: ast::BinaryOperator::Multiply,
-                                        right: Box::new(convert_expression(
-                                            right.as_ref().clone(),
-                                        )?),
-                                    })
-                                }
-                                nagari_parser::AssignmentOperator::DivideAssign => {
-                                    ast::Expression::Binary(ast::BinaryExpression {
-                                        left: Box::new(ast::Expression::Identifier(name.clone())),
-                                        operator: ast::BinaryOperator::Divide,
-                                        right: Box::new(convert_expression(
-                                            right.as_ref().clone(),
-                                        )?),
-                                    })
-                                }
-                            };
-
-                            Ok(IntStmt::Assignment(ast::Assignment {
-                                name: name.clone(),
-                                var_type: None,
-                                value: assignment_value,
-                            }))
-                        }
-                        _ => {
-                            // For complex assignments, fall back to expression
-                            Ok(IntStmt::Expression(convert_expression(expr)?))
-                        }
-                    }
-                }
-                _ => Ok(IntStmt::Expression(convert_expression(expr)?)),
-            }
-        }
+        // Assignments become assignment statements. The operands are moved out of the external
+        // AST rather than cloned.
+        ExtStmt::Expression(nagari_parser::Expression::Assignment {
+            left,
+            operator,
+            right,
+        }) => convert_assignment(*left, &operator, *right),
+        ExtStmt::Expression(expr) => Ok(IntStmt::Expression(convert_expression(expr)?)),
         ExtStmt::Let { name, value } => Ok(IntStmt::Assignment(ast::Assignment {
             name,
             var_type: None,
@@ -131,19 +174,23 @@ fn convert_statement(
             body,
             is_async,
             return_type,
+            decorators,
         } => Ok(IntStmt::FunctionDef(ast::FunctionDef {
             name,
             parameters: parameters
                 .into_iter()
                 .map(|p| convert_function_parameter(p))
                 .collect::<Result<Vec<_>, _>>()?,
-            return_type: return_type.map(|t| convert_type_string_to_type(t)),
+            return_type: return_type.as_ref().map(types::Type::from_annotation),
             body: body
                 .into_iter()
                 .map(|s| convert_statement(s))
                 .collect::<Result<Vec<_>, _>>()?,
             is_async,
-            decorators: Vec::new(),
+            decorators: decorators
+                .into_iter()
+                .map(convert_decorator)
+                .collect::<Result<Vec<_>, _>>()?,
             is_generator: false,
         })),
         ExtStmt::Return(expr) => Ok(IntStmt::Return(
@@ -180,6 +227,7 @@ fn convert_statement(
             variable,
             iterable,
             body,
+            is_async,
         } => Ok(IntStmt::For(ast::ForLoop {
             variable,
             iterable: convert_expression(iterable)?,
@@ -187,11 +235,40 @@ fn convert_statement(
                 .into_iter()
                 .map(|s| convert_statement(s))
                 .collect::<Result<Vec<_>, _>>()?,
+            is_async,
+        })),
+        ExtStmt::With {
+            items,
+            body,
+            is_async,
+        } => Ok(IntStmt::With(ast::WithStatement {
+            items: items
+                .into_iter()
+                .map(|item| {
+                    Ok(ast::WithItem {
+                        context_expr: convert_expression(item.context)?,
+                        optional_vars: item.alias,
+                    })
+                })
+                .collect::<Result<Vec<_>, NagariError>>()?,
+            body: body
+                .into_iter()
+                .map(convert_statement)
+                .collect::<Result<Vec<_>, _>>()?,
+            is_async,
+        })),
+        ExtStmt::Match { subject, cases } => Ok(IntStmt::Match(ast::MatchStatement {
+            expression: convert_expression(subject)?,
+            cases: cases
+                .into_iter()
+                .map(convert_match_case)
+                .collect::<Result<Vec<_>, _>>()?,
         })),
         ExtStmt::Class {
             name,
             superclass,
             methods,
+            decorators,
         } => Ok(IntStmt::ClassDef(ast::ClassDef {
             name,
             superclass,
@@ -199,6 +276,10 @@ fn convert_statement(
                 .into_iter()
                 .map(|s| convert_statement(s))
                 .collect::<Result<Vec<_>, _>>()?,
+            decorators: decorators
+                .into_iter()
+                .map(convert_decorator)
+                .collect::<Result<Vec<_>, _>>()?,
         })),
         ExtStmt::Import { source, items } => Ok(IntStmt::Import(ast::ImportStatement {
             module: source,
@@ -227,6 +308,81 @@ fn convert_statement(
     }
 }
 
+/// The statement assigning `right` to `left`: a name, an attribute, an index or a list of names
+/// to unpack into. Compound operators desugar to `target = target <op> value`.
+fn convert_assignment(
+    left: nagari_parser::Expression,
+    operator: &nagari_parser::AssignmentOperator,
+    right: nagari_parser::Expression,
+) -> Result<ast::Statement, NagariError> {
+    use ast::Expression as IntExpr;
+
+    let target = convert_expression(left)?;
+    let mut value = convert_expression(right)?;
+    if let Some(operator) = compound_assignment_operator(operator) {
+        if matches!(target, IntExpr::List(_)) {
+            return Err(NagariError::ParseError(
+                "Compound assignment cannot unpack into several targets".to_string(),
+            ));
+        }
+        value = IntExpr::Binary(ast::BinaryExpression {
+            left: Box::new(target.clone()),
+            operator,
+            right: Box::new(value),
+        });
+    }
+
+    match target {
+        IntExpr::Identifier(name) => Ok(ast::Statement::Assignment(ast::Assignment {
+            name,
+            var_type: None,
+            value,
+        })),
+        IntExpr::Attribute(attribute) => Ok(ast::Statement::AttributeAssignment(
+            ast::AttributeAssignment {
+                object: *attribute.object,
+                attribute: attribute.attribute,
+                value,
+            },
+        )),
+        IntExpr::Index(index) => Ok(ast::Statement::IndexAssignment(ast::IndexAssignment {
+            object: *index.object,
+            index: *index.index,
+            value,
+        })),
+        IntExpr::List(elements) => {
+            let targets = elements
+                .into_iter()
+                .map(|element| match element {
+                    IntExpr::Identifier(name) => Ok(name),
+                    _ => Err(NagariError::ParseError(
+                        "Invalid tuple unpacking target".to_string(),
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ast::Statement::TupleAssignment(ast::TupleAssignment {
+                targets,
+                value,
+            }))
+        }
+        _ => Err(NagariError::ParseError(
+            "Invalid assignment target".to_string(),
+        )),
+    }
+}
+
+fn compound_assignment_operator(
+    operator: &nagari_parser::AssignmentOperator,
+) -> Option<ast::BinaryOperator> {
+    match operator {
+        nagari_parser::AssignmentOperator::Assign => None,
+        nagari_parser::AssignmentOperator::AddAssign => Some(ast::BinaryOperator::Add),
+        nagari_parser::AssignmentOperator::SubtractAssign => Some(ast::BinaryOperator::Subtract),
+        nagari_parser::AssignmentOperator::MultiplyAssign => Some(ast::BinaryOperator::Multiply),
+        nagari_parser::AssignmentOperator::DivideAssign => Some(ast::BinaryOperator::Divide),
+    }
+}
+
 fn convert_expression(
     external_expr: nagari_parser::Expression,
 ) -> Result<ast::Expression, NagariError> {
@@ -252,13 +408,17 @@ fn convert_expression(
         ExtExpr::Call {
             function,
             arguments,
+            keywords,
         } => Ok(IntExpr::Call(ast::CallExpression {
             function: Box::new(convert_expression(*function)?),
             arguments: arguments
                 .into_iter()
                 .map(|a| convert_expression(a))
                 .collect::<Result<Vec<_>, _>>()?,
-            keyword_args: Vec::new(),
+            keyword_args: keywords
+                .into_iter()
+                .map(convert_keyword_argument)
+                .collect::<Result<Vec<_>, _>>()?,
         })),
         ExtExpr::Member {
             object,
@@ -283,6 +443,12 @@ fn convert_expression(
                 .map(|e| convert_expression(e))
                 .collect::<Result<Vec<_>, _>>()?,
         )),
+        ExtExpr::Set(elements) => Ok(IntExpr::Set(
+            elements
+                .into_iter()
+                .map(convert_expression)
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
         ExtExpr::Object(properties) => Ok(IntExpr::Dict(
             properties
                 .into_iter()
@@ -343,15 +509,19 @@ fn convert_expression(
                         });
                     }
 
-                    // For async arrows, use function expression
-                    if is_async {
+                    // Async arrows, and arrows taking *args or **kwargs, become function
+                    // expressions
+                    let variadic = parameters
+                        .iter()
+                        .any(|p| p.kind != nagari_parser::ParameterKind::Positional);
+                    if is_async || variadic {
                         Ok(IntExpr::FunctionExpr(ast::FunctionExpr {
                             parameters: parameters
                                 .into_iter()
                                 .map(|p| convert_function_parameter(p))
                                 .collect::<Result<Vec<_>, _>>()?,
                             body: vec![ast::Statement::Return(Some(lambda_body))],
-                            is_async: true,
+                            is_async,
                             is_generator: false,
                         }))
                     } else {
@@ -404,11 +574,9 @@ fn convert_expression(
                     keyword_args: Vec::new(),
                 }))
             }
-            _ => Ok(IntExpr::Binary(ast::BinaryExpression {
-                left: Box::new(convert_expression(*left)?),
-                operator: ast::BinaryOperator::Equal,
-                right: Box::new(convert_expression(*right)?),
-            })),
+            _ => Err(NagariError::ParseError(
+                "Only names can be assigned inside an expression".to_string(),
+            )),
         },
         ExtExpr::Conditional {
             test,
@@ -428,30 +596,145 @@ fn convert_expression(
                     .collect::<Result<Vec<_>, _>>()?,
             }))
         }
+        ExtExpr::InlineJs(code) => Ok(IntExpr::InlineJs(code)),
+        ExtExpr::Await(argument) => Ok(IntExpr::Await(Box::new(convert_expression(*argument)?))),
         ExtExpr::Index { object, index } => Ok(IntExpr::Index(ast::IndexAccess {
             object: Box::new(convert_expression(*object)?),
             index: Box::new(convert_expression(*index)?),
         })),
+        ExtExpr::Slice {
+            object,
+            start,
+            end,
+            step,
+        } => {
+            let convert_bound = |bound: Option<Box<nagari_parser::Expression>>| {
+                bound
+                    .map(|b| convert_expression(*b).map(Box::new))
+                    .transpose()
+            };
+            Ok(IntExpr::Slice(ast::SliceExpression {
+                object: Box::new(convert_expression(*object)?),
+                start: convert_bound(start)?,
+                end: convert_bound(end)?,
+                step: convert_bound(step)?,
+            }))
+        }
+        ExtExpr::ListComprehension {
+            element,
+            generators,
+        } => Ok(IntExpr::ListComprehension(ast::ListComprehension {
+            element: Box::new(convert_expression(*element)?),
+            generators: convert_generators(generators)?,
+        })),
+        ExtExpr::DictComprehension {
+            key,
+            value,
+            generators,
+        } => Ok(IntExpr::DictComprehension(ast::DictComprehension {
+            key: Box::new(convert_expression(*key)?),
+            value: Box::new(convert_expression(*value)?),
+            generators: convert_generators(generators)?,
+        })),
+        ExtExpr::SetComprehension {
+            element,
+            generators,
+        } => Ok(IntExpr::SetComprehension(ast::SetComprehension {
+            element: Box::new(convert_expression(*element)?),
+            generators: convert_generators(generators)?,
+        })),
+        ExtExpr::Generator {
+            element,
+            generators,
+        } => Ok(IntExpr::Generator(ast::GeneratorExpression {
+            element: Box::new(convert_expression(*element)?),
+            generators: convert_generators(generators)?,
+        })),
     }
 }
 
+fn convert_generators(
+    generators: Vec<nagari_parser::ComprehensionGenerator>,
+) -> Result<Vec<ast::ComprehensionGenerator>, NagariError> {
+    generators
+        .into_iter()
+        .map(|generator| {
+            Ok(ast::ComprehensionGenerator {
+                target: generator.target,
+                iter: convert_expression(generator.iter)?,
+                conditions: generator
+                    .conditions
+                    .into_iter()
+                    .map(convert_expression)
+                    .collect::<Result<Vec<_>, _>>()?,
+            })
+        })
+        .collect()
+}
+
 fn convert_literal_to_expression(
     external_lit: nagari_parser::Literal,
 ) -> Result<ast::Expression, NagariError> {
-    use ast::Expression as IntExpr;
+    Ok(ast::Expression::Literal(convert_literal(external_lit)))
+}
+
+fn convert_literal(external_lit: nagari_parser::Literal) -> ast::Literal {
     use nagari_parser::Literal as ExtLit;
 
     match external_lit {
-        ExtLit::String(s) => Ok(IntExpr::Literal(ast::Literal::String(s))),
+        ExtLit::String(s) => ast::Literal::String(s),
         ExtLit::Number(n) => {
             if n.fract() == 0.0 {
-                Ok(IntExpr::Literal(ast::Literal::Int(n as i64)))
+                ast::Literal::Int(n as i64)
             } else {
-                Ok(IntExpr::Literal(ast::Literal::Float(n)))
+                ast::Literal::Float(n)
             }
         }
-        ExtLit::Boolean(b) => Ok(IntExpr::Literal(ast::Literal::Bool(b))),
-        ExtLit::Null => Ok(IntExpr::Literal(ast::Literal::None)),
+        ExtLit::Boolean(b) => ast::Literal::Bool(b),
+        ExtLit::Null => ast::Literal::None,
+    }
+}
+
+/// A `case` pattern, wrapped in a guard when the case has one
+fn convert_match_case(case: nagari_parser::MatchCase) -> Result<ast::MatchCase, NagariError> {
+    let pattern = convert_pattern(case.pattern);
+    Ok(ast::MatchCase {
+        pattern: match case.guard {
+            Some(guard) => ast::Pattern::Guard(Box::new(pattern), convert_expression(guard)?),
+            None => pattern,
+        },
+        body: case
+            .body
+            .into_iter()
+            .map(convert_statement)
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
+fn convert_pattern(external_pattern: nagari_parser::Pattern) -> ast::Pattern {
+    use ast::Pattern as IntPat;
+    use nagari_parser::Pattern as ExtPat;
+
+    match external_pattern {
+        ExtPat::Literal(lit) => IntPat::Literal(convert_literal(lit)),
+        ExtPat::Capture(name) => IntPat::Identifier(name),
+        ExtPat::Wildcard => IntPat::Wildcard,
+        ExtPat::Tuple(items) => IntPat::Tuple(items.into_iter().map(convert_pattern).collect()),
+        ExtPat::List(items) => IntPat::List(items.into_iter().map(convert_pattern).collect()),
+        ExtPat::Dict(entries) => IntPat::Dict(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    (
+                        IntPat::Literal(convert_literal(key)),
+                        convert_pattern(value),
+                    )
+                })
+                .collect(),
+        ),
+        ExtPat::Class { name, arguments } => {
+            IntPat::Constructor(name, arguments.into_iter().map(convert_pattern).collect())
+        }
     }
 }
 
@@ -475,12 +758,12 @@ fn convert_binary_operator(
         ExtOp::GreaterEqual => Ok(IntOp::GreaterEqual),
         ExtOp::And => Ok(IntOp::And),
         ExtOp::Or => Ok(IntOp::Or),
-        ExtOp::Power => Ok(IntOp::Multiply),
-        ExtOp::BitwiseAnd => Ok(IntOp::And),
-        ExtOp::BitwiseOr => Ok(IntOp::Or),
-        ExtOp::BitwiseXor => Ok(IntOp::NotEqual),
-        ExtOp::LeftShift => Ok(IntOp::Multiply),
-        ExtOp::RightShift => Ok(IntOp::Divide),
+        ExtOp::Power => Ok(IntOp::Power),
+        ExtOp::BitwiseAnd => Ok(IntOp::BitwiseAnd),
+        ExtOp::BitwiseOr => Ok(IntOp::BitwiseOr),
+        ExtOp::BitwiseXor => Ok(IntOp::BitwiseXor),
+        ExtOp::LeftShift => Ok(IntOp::LeftShift),
+        ExtOp::RightShift => Ok(IntOp::RightShift),
     }
 }
 
@@ -505,26 +788,53 @@ fn convert_function_parameter(
         name: external_param.name,
         param_type: external_param
             .type_annotation
-            .map(|t| convert_type_string_to_type(t)),
+            .as_ref()
+            .map(types::Type::from_annotation),
         default_value: external_param
             .default_value
             .map(|v| convert_expression(v))
             .transpose()?,
+        kind: match external_param.kind {
+            nagari_parser::ParameterKind::Positional => ast::ParameterKind::Positional,
+            nagari_parser::ParameterKind::Rest => ast::ParameterKind::Rest,
+            nagari_parser::ParameterKind::Keywords => ast::ParameterKind::Keywords,
+        },
     })
 }
 
-fn convert_import_item(external_item: nagari_parser::ImportItem) -> Result<String, NagariError> {
-    Ok(external_item.name)
+/// `name=value` as a named value, and `**mapping` as a spread without a name
+fn convert_keyword_argument(
+    keyword: nagari_parser::KeywordArgument,
+) -> Result<(String, ast::Expression), NagariError> {
+    let value = convert_expression(keyword.value)?;
+    Ok(match keyword.name {
+        Some(name) => (name, value),
+        None => (String::new(), ast::Expression::Spread(Box::new(value))),
+    })
 }
 
-fn convert_type_string_to_type(type_str: String) -> types::Type {
-    match type_str.as_str() {
-        "string" => types::Type::Str,
-        "number" => types::Type::Float,
-        "boolean" => types::Type::Bool,
-        "any" => types::Type::Any,
-        _ => types::Type::Any,
-    }
+fn convert_decorator(decorator: nagari_parser::Decorator) -> Result<ast::Decorator, NagariError> {
+    Ok(ast::Decorator {
+        name: decorator.name,
+        arguments: decorator
+            .arguments
+            .map(|arguments| {
+                arguments
+                    .into_iter()
+                    .map(convert_expression)
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?,
+        keyword_args: decorator
+            .keywords
+            .into_iter()
+            .map(convert_keyword_argument)
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
+fn convert_import_item(external_item: nagari_parser::ImportItem) -> Result<String, NagariError> {
+    Ok(external_item.name)
 }
 
 /// Main compiler interface for the Nagari programming language
@@ -540,16 +850,73 @@ pub struct CompilerConfig {
     pub target: String,
     /// Enable JSX support for React compatibility
     pub jsx: bool,
+    /// Build JSX into plain element trees that the runtime's `render_to_string` turns into
+    /// HTML, for server-side rendering without React; needs `jsx` and a Node target
+    pub ssr: bool,
     /// Generate source maps for debugging
     pub sourcemap: bool,
     /// Enable development mode with debug info
     pub devtools: bool,
-    /// Minify output (production mode)
+    /// Minify output (production mode): fold literal expressions, give local variables short
+    /// names and drop comments and whitespace; see [`minify`]
     pub minify: bool,
     /// Generate TypeScript declarations
     pub declarations: bool,
+    /// Put JSDoc comments with the types from annotations above generated functions, for
+    /// JavaScript consumers without the declarations
+    pub jsdoc: bool,
     /// Enable verbose output
     pub verbose: bool,
+    /// Level per warning category; the `warnings` group covers categories without an entry
+    pub warning_levels: HashMap<String, WarningLevel>,
+    /// Treat every warning without an explicit level as an error
+    pub deny_warnings: bool,
+    /// Fail on type errors found by the type checker instead of reporting them as
+    /// `type-mismatch` warnings
+    pub strict_types: bool,
+    /// Compile-time constants substituted before transpiling; these override `__DEV__` and
+    /// `__TARGET__`
+    pub defines: HashMap<String, ast::Literal>,
+    /// `.nagd` declarations that imports of untyped JavaScript modules are checked against
+    pub externs: ExternDeclarations,
+    /// Directory files read by `embed()` must be in; by default the importing file's own
+    pub embed_root: Option<PathBuf>,
+    /// Engines the output runs on, which decide the polyfills it carries; by default every
+    /// polyfill the target might need
+    pub environment: Option<Environment>,
+    /// Package the output imports its runtime helpers from, checked before compiling
+    pub runtime: RuntimePackage,
+    /// Largest source, nesting and output compiled
+    pub limits: Limits,
+    /// Directory [`Compiler::compile_project`] keeps the output of unchanged modules in
+    pub cache_dir: PathBuf,
+    /// Line endings of the JavaScript, source maps and declarations, LF unless overridden
+    pub line_endings: LineEndings,
+}
+
+impl CompilerConfig {
+    /// Effective level for warnings in `category`
+    pub fn warning_level(&self, category: &str) -> WarningLevel {
+        warnings::level_for(&self.warning_levels, self.deny_warnings, category)
+    }
+
+    /// Check the options fit together and the runtime package can be used
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ssr && !(self.jsx && matches!(self.target.as_str(), "node" | "cjs")) {
+            return Err(format!(
+                "server-side rendering needs JSX enabled and the node target, not '{}'",
+                self.target
+            ));
+        }
+        self.runtime.validate()
+    }
+
+    /// Builtin constants for this build, overridden by `defines`
+    pub fn constants(&self) -> HashMap<String, ast::Literal> {
+        let mut constants = defines::builtins(&self.target, !self.minify);
+        constants.extend(self.defines.clone());
+        constants
+    }
 }
 
 impl Default for CompilerConfig {
@@ -557,11 +924,24 @@ impl Default for CompilerConfig {
         Self {
             target: "es6".to_string(),
             jsx: false,
+            ssr: false,
             sourcemap: false,
             devtools: false,
             minify: false,
             declarations: false,
+            jsdoc: false,
             verbose: false,
+            warning_levels: HashMap::new(),
+            deny_warnings: false,
+            strict_types: false,
+            defines: HashMap::new(),
+            externs: ExternDeclarations::new(),
+            embed_root: None,
+            environment: None,
+            runtime: RuntimePackage::default(),
+            limits: Limits::default(),
+            cache_dir: PathBuf::from(project::CACHE_DIR),
+            line_endings: LineEndings::default(),
         }
     }
 }
@@ -577,8 +957,10 @@ pub struct CompilationResult {
     pub declarations: Option<String>,
     /// AST of the compiled program
     pub ast: Program,
-    /// List of warnings generated during compilation
-    pub warnings: Vec<String>,
+    /// Warnings generated during compilation, after levels and suppressions are applied
+    pub warnings: Vec<Warning>,
+    /// Time spent in each compilation phase
+    pub timings: CompilationTimings,
 }
 
 impl Compiler {
@@ -599,54 +981,158 @@ impl Compiler {
         &self,
         source: &str,
         filename: Option<&str>,
+    ) -> Result<CompilationResult, NagariError> {
+        self.compile_source(source, filename.map(Path::new))
+    }
+
+    /// Compile `source`, read from `path`; `embed()` paths are relative to it
+    fn compile_source(
+        &self,
+        source: &str,
+        path: Option<&Path>,
     ) -> Result<CompilationResult, NagariError> {
         if self.config.verbose {
             println!("🔄 Compiling Nagari source with enhanced parser...");
         }
 
-        // Use the enhanced external parser with dual syntax support
-        let external_ast = nagari_parser::parse(source).map_err(|e| match e {
-            nagari_parser::ParseError::UnexpectedToken {
-                token,
-                line,
-                column,
-            } => NagariError::ParseError(format!(
-                "Unexpected token '{}' at line {}, column {}",
-                token, line, column
-            )),
-            nagari_parser::ParseError::Expected {
-                expected,
-                found,
-                line,
-                column,
-            } => NagariError::ParseError(format!(
-                "Expected '{}' but found '{}' at line {}, column {}",
-                expected, found, line, column
-            )),
-            nagari_parser::ParseError::SyntaxError {
-                message,
-                line,
-                column,
-            } => NagariError::ParseError(format!(
-                "Syntax error at line {}, column {}: {}",
-                line, column, message
-            )),
-            _ => NagariError::ParseError(format!("Parser error: {}", e)),
+        let mut timings = CompilationTimings::new();
+        let (external_ast, diagnostics) = self.parse_source(source, &mut timings)?;
+        self.compile_parsed(external_ast, diagnostics, source, path, timings)
+    }
+
+    /// Compile a Nagari source string to VM bytecode, the contents of a `.nac` file that
+    /// `nagrun` executes
+    pub fn compile_to_bytecode(&self, source: &str) -> Result<Vec<u8>, NagariError> {
+        let mut timings = CompilationTimings::new();
+        let (external_ast, _) = self.parse_source(source, &mut timings)?;
+        let ast = self.lower(external_ast, None, &mut timings)?;
+        timings.time(Phase::TypeCheck, || {
+            typechecker::check(&ast, self.config.strict_types)
         })?;
+        let bytecode = bytecode::generate(&ast)?;
+        self.config
+            .limits
+            .check_output("Bytecode", bytecode.len())?;
+        Ok(bytecode)
+    }
+
+    /// Parse `source` with the enhanced parser, which supports both syntaxes, starting the
+    /// collection of its warnings
+    fn parse_source(
+        &self,
+        source: &str,
+        timings: &mut CompilationTimings,
+    ) -> Result<(nagari_parser::Program, Diagnostics), NagariError> {
+        let limits = self.config.limits.parser();
+        limits.check_source(source).map_err(convert_parse_error)?;
+        let tokens = timings
+            .time(Phase::Lex, || nagari_parser::Lexer::new(source).tokenize())
+            .map_err(convert_parse_error)?;
+        let mut parser = nagari_parser::Parser::new(tokens).with_limits(&limits);
+        let external_ast = timings
+            .time(Phase::Parse, || parser.parse_program())
+            .map_err(convert_parse_error)?;
+        let diagnostics = Diagnostics::parsed(parser.statement_spans(), parser.deprecations());
 
         if self.config.verbose {
             println!("✅ Enhanced parsing completed successfully");
         }
+        Ok((external_ast, diagnostics))
+    }
 
-        // Convert the external AST to the internal AST format for transpiler compatibility
-        let ast = convert_external_ast_to_internal(external_ast)?;
+    /// Check a parsed program and convert it to the internal AST that both the transpiler
+    /// and the bytecode generator take
+    fn lower(
+        &self,
+        external_ast: nagari_parser::Program,
+        path: Option<&Path>,
+        timings: &mut CompilationTimings,
+    ) -> Result<Program, NagariError> {
+        // Imports of and calls into declared JavaScript modules must match their declarations
+        timings.time(Phase::TypeCheck, || self.config.externs.check(&external_ast))?;
+
+        // Convert the external AST to the internal AST format for transpiler compatibility,
+        // then substitute compile-time constants and drop the branches they rule out, and
+        // inline embedded files
+        let ast = timings.time(Phase::Convert, || {
+            let mut ast = convert_external_ast_to_internal(external_ast)?;
+            defines::apply(&mut ast, &self.config.constants());
+            embed::apply(&mut ast, path, self.config.embed_root.as_deref())?;
+            Ok::<_, NagariError>(ast)
+        })?;
 
         if self.config.verbose {
             println!("✅ AST conversion completed");
         }
+        Ok(ast)
+    }
+
+    /// Convert and transpile an already-parsed program
+    fn compile_parsed(
+        &self,
+        external_ast: nagari_parser::Program,
+        mut diagnostics: Diagnostics,
+        source: &str,
+        path: Option<&Path>,
+        mut timings: CompilationTimings,
+    ) -> Result<CompilationResult, NagariError> {
+        self.config.validate().map_err(NagariError::ConfigError)?;
+        timings.time(Phase::TypeCheck, || diagnostics.analyze(&external_ast));
+        let mut ast = self.lower(external_ast, path, &mut timings)?;
+        let type_warnings = timings.time(Phase::TypeCheck, || {
+            typechecker::check(&ast, self.config.strict_types)
+        })?;
+        diagnostics.extend(type_warnings);
+        if self.config.minify {
+            timings.time(Phase::Convert, || minify::program(&mut ast));
+        }
 
-        // Transpilation
-        let js_code = transpiler::transpile(&ast, &self.config.target, self.config.jsx)?;
+        // Transpilation; an internal error on a statement only loses that statement
+        let (js_code, internal_errors) = timings.time(Phase::Transpile, || {
+            transpiler::transpile_recovering(
+                &ast,
+                &self.config.target,
+                self.config.jsx,
+                self.config.ssr,
+                self.config.environment,
+                &self.config.runtime.specifier,
+                self.config.jsdoc,
+            )
+        })?;
+        for error in internal_errors {
+            let statement = match &error.declaration {
+                Some(name) => format!("`{name}`"),
+                None => "a top-level statement".to_string(),
+            };
+            diagnostics.warn(
+                diagnostics::INTERNAL_ERROR,
+                format!(
+                    "internal compiler error in {statement}: {}; its output throws instead",
+                    error.message
+                ),
+                None,
+            );
+        }
+        let filename = path
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("input.nag");
+        // The map of the compaction is composed with the source map, which maps the output as
+        // it was before
+        let (js_code, compaction) = match (self.config.minify, self.config.sourcemap) {
+            (true, true) => timings.time(Phase::Transpile, || {
+                let (js_code, map) = minify::compact_mapped(&js_code, filename);
+                (js_code, Some(map))
+            }),
+            (true, false) => (
+                timings.time(Phase::Transpile, || minify::compact(&js_code)),
+                None,
+            ),
+            (false, _) => (js_code, None),
+        };
+        self.config
+            .limits
+            .check_output("JavaScript output", js_code.len())?;
 
         if self.config.verbose {
             println!("✅ Transpilation completed");
@@ -654,7 +1140,7 @@ impl Compiler {
 
         // Generate source map if enabled
         let source_map = if self.config.sourcemap {
-            Some(self.generate_source_map(filename.unwrap_or("input.nag"), source)?)
+            Some(self.generate_source_map(filename, source, compaction.as_ref())?)
         } else {
             None
         };
@@ -666,12 +1152,23 @@ impl Compiler {
             None
         };
 
+        // Allowed and suppressed warnings are dropped; denied ones fail the compilation
+        let warnings = warnings::enforce(
+            &self.config.warning_levels,
+            self.config.deny_warnings,
+            source,
+            diagnostics.into_warnings(),
+        )?;
+
+        // Emitted files are the same whatever platform builds them, unless configured otherwise
+        let line_endings = self.config.line_endings;
         Ok(CompilationResult {
-            js_code,
-            source_map,
-            declarations,
+            js_code: line_endings.apply(js_code),
+            source_map: source_map.map(|map| line_endings.apply(map)),
+            declarations: declarations.map(|declarations| line_endings.apply(declarations)),
             ast,
-            warnings: Vec::new(),
+            warnings,
+            timings,
         })
     }
 
@@ -689,12 +1186,7 @@ impl Compiler {
         let source = fs::read_to_string(input_path)
             .map_err(|e| NagariError::IoError(format!("Failed to read input file: {e}")))?;
 
-        let filename = input_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("input.nag");
-
-        self.compile_string(&source, Some(filename))
+        self.compile_source(&source, Some(input_path))
     }
 
     /// Transpile a Nagari file directly to JavaScript and write to output file
@@ -717,38 +1209,7 @@ impl Compiler {
         let source = fs::read_to_string(input_path)
             .map_err(|e| NagariError::IoError(format!("Failed to read input file: {e}")))?;
 
-        // Use the enhanced external parser
-        let external_ast = nagari_parser::parse(&source).map_err(|e| match e {
-            nagari_parser::ParseError::UnexpectedToken {
-                token,
-                line,
-                column,
-            } => NagariError::ParseError(format!(
-                "Unexpected token '{}' at line {}, column {}",
-                token, line, column
-            )),
-            nagari_parser::ParseError::Expected {
-                expected,
-                found,
-                line,
-                column,
-            } => NagariError::ParseError(format!(
-                "Expected '{}' but found '{}' at line {}, column {}",
-                expected, found, line, column
-            )),
-            nagari_parser::ParseError::SyntaxError {
-                message,
-                line,
-                column,
-            } => NagariError::ParseError(format!(
-                "Syntax error at line {}, column {}: {}",
-                line, column, message
-            )),
-            _ => NagariError::ParseError(format!("Parser error: {}", e)),
-        })?;
-
-        // Convert to internal AST
-        let ast = convert_external_ast_to_internal(external_ast)?;
+        let ast = self.parse_program(&source)?;
 
         if self.config.verbose {
             println!("✅ Syntax check passed with enhanced parser");
@@ -757,14 +1218,211 @@ impl Compiler {
         Ok(ast)
     }
 
+    /// Parse Nagari source and convert it to the internal AST, without transpiling
+    pub fn parse_program(&self, source: &str) -> Result<Program, NagariError> {
+        let external_ast = nagari_parser::parse(source).map_err(convert_parse_error)?;
+        convert_external_ast_to_internal(external_ast)
+    }
+
     /// Compile and write result to output file
     pub fn compile_to_file<P: AsRef<Path>, Q: AsRef<Path>>(
         &self,
         input_path: P,
         output_path: Q,
     ) -> Result<(), NagariError> {
-        let output_path = output_path.as_ref();
+        self.compile_to_file_timed(input_path, output_path)
+            .map(|_| ())
+    }
+
+    /// Compile and write result to output file, returning per-phase timings
+    pub fn compile_to_file_timed<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        input_path: P,
+        output_path: Q,
+    ) -> Result<CompilationTimings, NagariError> {
         let result = self.compile_file(input_path)?;
+        self.write_output(
+            result.js_code,
+            result.source_map,
+            result.declarations,
+            result.timings,
+            output_path.as_ref(),
+        )
+    }
+
+    /// Write a module of [`Compiler::compile_project`] (and its source map / declarations) to
+    /// `output_path`, returning its timings with the time spent writing
+    pub fn write_module(
+        &self,
+        module: ModuleOutput,
+        output_path: &Path,
+    ) -> Result<CompilationTimings, NagariError> {
+        self.write_output(
+            module.js_code,
+            module.source_map,
+            module.declarations,
+            module.timings,
+            output_path,
+        )
+    }
+
+    /// Compile many files, parsing them in parallel first.
+    ///
+    /// `jobs` pairs each input file with its output path. Results are returned in the same order.
+    /// Files that are part of an import cycle fail with [`NagariError::ImportCycle`].
+    pub fn compile_files<P: AsRef<Path> + Sync, Q: AsRef<Path>>(
+        &self,
+        jobs: &[(P, Q)],
+    ) -> Vec<Result<CompilationTimings, NagariError>> {
+        let inputs: Vec<&Path> = jobs.iter().map(|(input, _)| input.as_ref()).collect();
+        self.compile_many(&inputs)
+            .into_iter()
+            .zip(jobs)
+            .map(|(result, (_, output_path))| {
+                let result = result.map_err(Failure::into_error)?;
+                self.write_output(
+                    result.js_code,
+                    result.source_map,
+                    result.declarations,
+                    result.timings,
+                    output_path.as_ref(),
+                )
+            })
+            .collect()
+    }
+
+    /// Compile many files into a dual package at `package_dir`: an ES module build and a
+    /// CommonJS build, each with declarations. See [`dual`] for the layout.
+    ///
+    /// `jobs` pairs each input file with its module path, as returned by
+    /// [`dual::collect_modules`]. A file's timings cover both of its builds.
+    pub fn compile_dual<P: AsRef<Path> + Sync>(
+        &self,
+        jobs: &[(P, String)],
+        package_dir: &Path,
+    ) -> Vec<Result<CompilationTimings, NagariError>> {
+        if let Err(e) = dual::write_format_markers(package_dir) {
+            let error = format!("Failed to write package markers: {e}");
+            return jobs
+                .iter()
+                .map(|_| Err(NagariError::IoError(error.clone())))
+                .collect();
+        }
+
+        let build = |format: dual::Format| {
+            let mut config = self.config.clone();
+            config.target = format.target().to_string();
+            config.declarations = true;
+            let outputs: Vec<(&Path, PathBuf)> = jobs
+                .iter()
+                .map(|(input, module)| {
+                    let output = dual::module_file(package_dir, format, module);
+                    (input.as_ref(), output)
+                })
+                .collect();
+            Compiler::with_config(config).compile_files(&outputs)
+        };
+        let esm = build(dual::Format::Esm);
+        let cjs = build(dual::Format::Cjs);
+        esm.into_iter()
+            .zip(cjs)
+            .map(|(esm, cjs)| {
+                let mut timings = esm?;
+                for (phase, duration) in cjs?.phases() {
+                    timings.record(*phase, *duration);
+                }
+                Ok(timings)
+            })
+            .collect()
+    }
+
+    /// Run every compilation phase on many files without writing any output, returning the
+    /// warnings of each file in the order of `inputs`
+    pub fn check_files<P: AsRef<Path> + Sync>(
+        &self,
+        inputs: &[P],
+    ) -> Vec<Result<Vec<Warning>, NagariError>> {
+        let inputs: Vec<&Path> = inputs.iter().map(|input| input.as_ref()).collect();
+        self.compile_many(&inputs)
+            .into_iter()
+            .map(|result| {
+                result
+                    .map(|result| result.warnings)
+                    .map_err(Failure::into_error)
+            })
+            .collect()
+    }
+
+    /// Like [`Compiler::check_files`], with the errors and warnings of each file as
+    /// [`Diagnostic`]s. Syntax errors keep the span the parser reported them at.
+    pub fn diagnose_files<P: AsRef<Path> + Sync>(&self, inputs: &[P]) -> Vec<Vec<Diagnostic>> {
+        let inputs: Vec<&Path> = inputs.iter().map(|input| input.as_ref()).collect();
+        self.compile_many(&inputs)
+            .into_iter()
+            .map(|result| match result {
+                Ok(result) => result.warnings.iter().map(Diagnostic::from).collect(),
+                Err(Failure::Parse(e)) => vec![Diagnostic::from(&e)],
+                Err(Failure::Compile(e)) => vec![Diagnostic::from(&e)],
+            })
+            .collect()
+    }
+
+    /// Parse `inputs` in parallel and compile each one that is not part of an import cycle
+    fn compile_many(&self, inputs: &[&Path]) -> Vec<Result<CompilationResult, Failure>> {
+        let outcomes = nagari_parser::parse_many(inputs);
+
+        let graph = ModuleGraph::build(
+            outcomes
+                .iter()
+                .map(|outcome| (outcome.path.as_path(), outcome.source.as_str())),
+        );
+        let mut cycle_of = HashMap::new();
+        for cycle in graph.cycles() {
+            for module in cycle.modules() {
+                cycle_of.insert(module.to_path_buf(), cycle.clone());
+            }
+        }
+
+        outcomes
+            .into_iter()
+            .zip(graph.modules())
+            .map(|(outcome, module)| {
+                if self.config.verbose {
+                    println!("📁 Compiling file: {}", outcome.path.display());
+                }
+                if let Some(cycle) = cycle_of.get(module) {
+                    return Err(Failure::Compile(cycle.to_error()));
+                }
+
+                let mut timings = CompilationTimings::new();
+                timings.record(Phase::Lex, outcome.lex_time);
+                timings.record(Phase::Parse, outcome.parse_time);
+
+                let external_ast = outcome.program.map_err(Failure::Parse)?;
+                let diagnostics =
+                    Diagnostics::parsed(&outcome.statement_spans, &outcome.deprecations);
+                self.compile_parsed(
+                    external_ast,
+                    diagnostics,
+                    &outcome.source,
+                    Some(&outcome.path),
+                    timings,
+                )
+                .map_err(Failure::Compile)
+            })
+            .collect()
+    }
+
+    /// Write compiled code (and its source map / declarations) to `output_path`
+    fn write_output(
+        &self,
+        js_code: String,
+        source_map: Option<String>,
+        declarations: Option<String>,
+        mut timings: CompilationTimings,
+        output_path: &Path,
+    ) -> Result<CompilationTimings, NagariError> {
+        let write_start = std::time::Instant::now();
 
         // Create output directory if needed
         if let Some(parent) = output_path.parent() {
@@ -774,14 +1432,15 @@ impl Compiler {
         }
 
         // Add source map comment if enabled
-        let final_code = if self.config.sourcemap && result.source_map.is_some() {
+        let final_code = if self.config.sourcemap && source_map.is_some() {
             format!(
-                "{}\n//# sourceMappingURL={}.map",
-                result.js_code,
+                "{}{}//# sourceMappingURL={}.map",
+                js_code,
+                self.config.line_endings.newline(),
                 output_path.file_name().unwrap().to_str().unwrap()
             )
         } else {
-            result.js_code
+            js_code
         };
 
         // Write JavaScript output
@@ -789,48 +1448,58 @@ impl Compiler {
             .map_err(|e| NagariError::IoError(format!("Failed to write output file: {e}")))?;
 
         // Write source map if enabled
-        if let Some(source_map) = result.source_map {
+        if let Some(source_map) = source_map {
             let map_path = output_path.with_extension("js.map");
             fs::write(&map_path, source_map)
                 .map_err(|e| NagariError::IoError(format!("Failed to write source map: {e}")))?;
         }
 
         // Write TypeScript declarations if enabled
-        if let Some(declarations) = result.declarations {
+        if let Some(declarations) = declarations {
             let dts_path = output_path.with_extension("d.ts");
             fs::write(&dts_path, declarations)
                 .map_err(|e| NagariError::IoError(format!("Failed to write declarations: {e}")))?;
         }
 
+        timings.record(Phase::Write, write_start.elapsed());
+
         if self.config.verbose {
             println!("✅ Compiled successfully to: {}", output_path.display());
         }
 
-        Ok(())
+        Ok(timings)
     }
 
-    /// Generate a source map for the given source code
+    /// Generate a source map for the given source code, composed with the map of the
+    /// `compaction` of the output when it was minified
     fn generate_source_map(
         &self,
         filename: &str,
         source_content: &str,
+        compaction: Option<&SourceMap>,
     ) -> Result<String, NagariError> {
-        let sourcemap = serde_json::json!({
-            "version": 3,
-            "file": filename.replace(".nag", ".js"),
-            "sources": [filename],
-            "sourcesContent": [source_content],
-            "mappings": "AAAA" // Basic mapping - can be enhanced later
-        });
+        // Basic mapping - can be enhanced later
+        let file = filename.replace(".nag", ".js");
+        let sourcemap = SourceMap::line_identity(
+            &file,
+            filename,
+            Some(&portable::to_lf(source_content)),
+            1,
+        );
+        let sourcemap = match compaction {
+            Some(compaction) => SourceMap {
+                file: Some(file),
+                ..compaction.compose(|_| Some(&sourcemap))
+            },
+            None => sourcemap,
+        };
 
-        Ok(sourcemap.to_string())
+        Ok(sourcemap.to_json())
     }
 
     /// Generate TypeScript declarations for the given AST
-    fn generate_declarations(&self, _ast: &Program) -> Result<String, NagariError> {
-        // Basic TypeScript declaration generation
-        // This can be enhanced to extract actual type information from the AST
-        Ok("// Generated TypeScript declarations\nexport {};\n".to_string())
+    fn generate_declarations(&self, ast: &Program) -> Result<String, NagariError> {
+        Ok(declarations::generate(ast))
     }
 
     /// Update compiler configuration
@@ -839,9 +1508,14 @@ impl Compiler {
     }
 
     /// Get current compiler configuration
-    pub fn get_config(&self) -> &CompilerConfig {
+    pub fn config(&self) -> &CompilerConfig {
         &self.config
     }
+
+    #[deprecated(since = "0.3.0", note = "renamed to `config`")]
+    pub fn get_config(&self) -> &CompilerConfig {
+        self.config()
+    }
 }
 
 impl Default for Compiler {
@@ -872,6 +1546,11 @@ impl CompilerConfigBuilder {
         self
     }
 
+    pub fn ssr(mut self, ssr: bool) -> Self {
+        self.config.ssr = ssr;
+        self
+    }
+
     pub fn sourcemap(mut self, sourcemap: bool) -> Self {
         self.config.sourcemap = sourcemap;
         self
@@ -892,43 +1571,115 @@ impl CompilerConfigBuilder {
         self
     }
 
+    pub fn jsdoc(mut self, jsdoc: bool) -> Self {
+        self.config.jsdoc = jsdoc;
+        self
+    }
+
     pub fn verbose(mut self, verbose: bool) -> Self {
         self.config.verbose = verbose;
         self
     }
 
-    pub fn build(self) -> CompilerConfig {
+    pub fn warning_level(mut self, category: &str, level: WarningLevel) -> Self {
         self.config
+            .warning_levels
+            .insert(category.to_string(), level);
+        self
     }
-}
 
-impl Default for CompilerConfigBuilder {
-    fn default() -> Self {
-        Self::new()
+    pub fn warning_levels(mut self, levels: HashMap<String, WarningLevel>) -> Self {
+        self.config.warning_levels.extend(levels);
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    pub fn deny_warnings(mut self, deny_warnings: bool) -> Self {
+        self.config.deny_warnings = deny_warnings;
+        self
+    }
 
-    #[test]
-    fn test_compiler_creation() {
-        let compiler = Compiler::new();
-        assert_eq!(compiler.config.target, "es6");
-        assert!(!compiler.config.jsx);
+    pub fn strict_types(mut self, strict_types: bool) -> Self {
+        self.config.strict_types = strict_types;
+        self
     }
 
-    #[test]
-    fn test_compiler_config_builder() {
-        let config = CompilerConfigBuilder::new()
-            .target("esm")
-            .jsx(true)
-            .sourcemap(true)
-            .verbose(true)
-            .build();
+    pub fn define(mut self, name: &str, value: ast::Literal) -> Self {
+        self.config.defines.insert(name.to_string(), value);
+        self
+    }
 
-        assert_eq!(config.target, "esm");
+    pub fn defines(mut self, defines: HashMap<String, ast::Literal>) -> Self {
+        self.config.defines.extend(defines);
+        self
+    }
+
+    pub fn externs(mut self, externs: ExternDeclarations) -> Self {
+        self.config.externs.extend(externs);
+        self
+    }
+
+    pub fn embed_root<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.config.embed_root = Some(root.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn environment(mut self, environment: Option<Environment>) -> Self {
+        self.config.environment = environment;
+        self
+    }
+
+    pub fn runtime(mut self, runtime: RuntimePackage) -> Self {
+        self.config.runtime = runtime;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    pub fn cache_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.config.cache_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn line_endings(mut self, line_endings: LineEndings) -> Self {
+        self.config.line_endings = line_endings;
+        self
+    }
+
+    pub fn build(self) -> CompilerConfig {
+        self.config
+    }
+}
+
+impl Default for CompilerConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiler_creation() {
+        let compiler = Compiler::new();
+        assert_eq!(compiler.config.target, "es6");
+        assert!(!compiler.config.jsx);
+    }
+
+    #[test]
+    fn test_compiler_config_builder() {
+        let config = CompilerConfigBuilder::new()
+            .target("esm")
+            .jsx(true)
+            .sourcemap(true)
+            .verbose(true)
+            .build();
+
+        assert_eq!(config.target, "esm");
         assert!(config.jsx);
         assert!(config.sourcemap);
         assert!(config.verbose);
@@ -949,4 +1700,1908 @@ print(greet("World"))
         let _result = compiler.compile_string(source, Some("test.nag"));
         // Test should pass once the lexer/parser are fully implemented
     }
+
+    #[test]
+    fn test_compile_string_records_timings() {
+        let compiler = Compiler::new();
+        let result = compiler
+            .compile_string("x = 1\n", Some("timings.nag"))
+            .expect("simple assignment should compile");
+
+        for phase in [Phase::Lex, Phase::Parse, Phase::Convert, Phase::Transpile] {
+            assert!(
+                result.timings.get(phase).is_some(),
+                "missing {} timing",
+                phase
+            );
+        }
+        assert!(result.timings.get(Phase::Write).is_none());
+    }
+
+    #[test]
+    fn test_error_codes_have_explanations() {
+        let errors = [
+            NagariError::LexError(String::new()),
+            NagariError::ParseError(String::new()),
+            NagariError::TypeError(String::new()),
+            NagariError::BytecodeError(String::new()),
+            NagariError::IoError(String::new()),
+            NagariError::SemanticError(String::new()),
+            NagariError::ImportCycle(String::new()),
+            NagariError::ConfigError(String::new()),
+        ];
+
+        for error in errors {
+            let explanation = explain::lookup(error.code())
+                .unwrap_or_else(|| panic!("{} has no explanation", error.code()));
+            assert!(!explanation.text.trim().is_empty());
+        }
+
+        assert_eq!(explain::lookup("e2").map(|e| e.code), Some("E0002"));
+        assert!(explain::lookup("E9999").is_none());
+    }
+
+    #[test]
+    fn test_import_cycles_are_reported_with_their_chain() {
+        let a = "import { b } from \"./b\"\nimport { util } from \"./util\"\n";
+        let b = "import { c } from \"./nested/../c\"\n";
+        let c = "import { a } from \"./a.js\"\n";
+        let util = "import { b } from \"./b\"\n";
+        let modules = [
+            (Path::new("src/a.nag"), a),
+            (Path::new("src/b.nag"), b),
+            (Path::new("src/c.nag"), c),
+            (Path::new("src/util.nag"), util),
+        ];
+
+        let cycles = ModuleGraph::build(modules).cycles();
+        assert_eq!(cycles.len(), 1);
+        let cycle = &cycles[0];
+        let chain: Vec<&Path> = cycle.modules().collect();
+        assert_eq!(
+            chain,
+            [
+                Path::new("src/a.nag"),
+                Path::new("src/b.nag"),
+                Path::new("src/c.nag")
+            ]
+        );
+        let message = cycle.to_error().to_string();
+        assert!(message.contains("src/a.nag → src/b.nag → src/c.nag → src/a.nag"));
+        assert!(message.contains("src/c.nag:1:1: import { a } from \"./a.js\""));
+        assert_eq!(cycle.to_error().code(), "E0007");
+
+        let lazy_c = "# nagari:lazy -- only used in handlers\nimport { a } from \"./a\"\n";
+        let modules = [
+            (Path::new("src/a.nag"), a),
+            (Path::new("src/b.nag"), b),
+            (Path::new("src/c.nag"), lazy_c),
+        ];
+        assert!(ModuleGraph::build(modules).check().is_ok());
+    }
+
+    #[test]
+    fn test_check_files_reports_each_file_without_output() {
+        let dir = std::env::temp_dir().join(format!("nagari-check-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [
+            (dir.join("ok.nag"), "x = 1\nprint(x)\n"),
+            (dir.join("broken.nag"), "def f(:\n    pass\n"),
+        ];
+        for (path, source) in &files {
+            fs::write(path, source).unwrap();
+        }
+        let paths: Vec<&Path> = files.iter().map(|(path, _)| path.as_path()).collect();
+
+        let results = Compiler::new().check_files(&paths);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap(), &Vec::<Warning>::new());
+        assert_eq!(results[1].as_ref().unwrap_err().code(), "E0002");
+        assert!(!dir.join("ok.js").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diagnose_files_keeps_spans() {
+        let dir = std::env::temp_dir().join(format!("nagari-diagnose-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let broken = dir.join("broken.nag");
+        let source = "def add(a, b):\n    return a + b\n\nprint(add(1, 2)\n";
+        fs::write(&broken, source).unwrap();
+        let legacy = dir.join("legacy.nag");
+        fs::write(&legacy, "var total = 1\nprint(total)\n").unwrap();
+
+        let results = Compiler::new().diagnose_files(&[&broken, &legacy]);
+        let error = &results[0][0];
+        assert_eq!(error.severity, Severity::Error);
+        assert_eq!(error.code, "E0002");
+        assert_eq!(
+            error.span,
+            Some(nagari_parser::Span {
+                line: 4,
+                column: 16
+            })
+        );
+        let warning = &results[1][0];
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(warning.code, "deprecated-syntax");
+        assert_eq!(
+            warning.span.map(|span| (span.line, span.column)),
+            Some((1, 1))
+        );
+
+        colored::control::set_override(false);
+        let rendered = Diagnostic::error("E0002", "expected ')', found newline")
+            .with_span(nagari_parser::Span {
+                line: 4,
+                column: 11,
+            })
+            .with_label(
+                nagari_parser::Span {
+                    line: 4,
+                    column: 11,
+                },
+                "expected ')'",
+            )
+            .with_help("close the call's argument list")
+            .render("broken.nag", Some(source));
+        assert_eq!(
+            rendered,
+            "error[E0002]: expected ')', found newline\n \
+             --> broken.nag:4:11\n  \
+             |\n\
+             4 | print(add(1, 2)\n  \
+             |           ^ expected ')'\n  \
+             |\n  \
+             = help: close the call's argument list\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_embed_inlines_files_relative_to_the_source() {
+        let dir = std::env::temp_dir().join(format!("nagari-embed-{}", std::process::id()));
+        let project = dir.join("project");
+        fs::create_dir_all(project.join("data")).unwrap();
+        fs::write(project.join("data/greeting.txt"), "hello \"world\"\n").unwrap();
+        fs::write(project.join("data/blob.bin"), [0u8, 159, 255]).unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+        let main = project.join("main.nag");
+        fs::write(
+            &main,
+            "greeting = embed(\"data/greeting.txt\")\nblob = embed(\"data/blob.bin\", \"bytes\")\n",
+        )
+        .unwrap();
+        let result = Compiler::new().compile_file(&main).unwrap();
+        assert!(result.js_code.contains(r#"hello \"world\"\n"#));
+        assert!(result.js_code.contains("[0, 159, 255]"));
+        // The bytecode target is generated from the same tree
+        match &result.ast.statements[0] {
+            ast::Statement::Assignment(assignment) => assert!(matches!(
+                &assignment.value,
+                ast::Expression::Literal(ast::Literal::String(text)) if text == "hello \"world\"\n"
+            )),
+            other => panic!("unexpected statement {other:?}"),
+        }
+
+        fs::write(&main, "blob = embed(\"data/blob.bin\")\n").unwrap();
+        let error = Compiler::new().compile_file(&main).unwrap_err();
+        assert!(error.to_string().contains("\"bytes\""));
+
+        fs::write(&main, "secret = embed(\"../secret.txt\")\n").unwrap();
+        let error = Compiler::new().compile_file(&main).unwrap_err();
+        assert!(error.to_string().contains("outside"));
+        let widened = Compiler::with_config(CompilerConfigBuilder::new().embed_root(&dir).build());
+        let js_code = widened.compile_file(&main).unwrap().js_code;
+        assert!(js_code.contains("secret"));
+
+        fs::write(
+            &main,
+            "def embed(path):\n    return path\nx = embed(\"../secret.txt\")\n",
+        )
+        .unwrap();
+        assert!(Compiler::new().compile_file(&main).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compile_time_defines_strip_dead_branches() {
+        let source = r#"
+if __DEV__:
+    print("debug build")
+else:
+    if API_VERSION == 2:
+        print("v2")
+    else:
+        print("v1")
+
+def log(__DEV__):
+    if __DEV__:
+        print("shadowed")
+
+mode = __TARGET__ == "es5" ? "legacy" : "modern"
+"#;
+        let release = CompilerConfigBuilder::new()
+            .minify(true)
+            .define("API_VERSION", defines::parse_value("2"))
+            .build();
+        let js = Compiler::with_config(release)
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        assert!(!js.contains("debug build"));
+        assert!(js.contains("console.log(\"v2\")"));
+        assert!(!js.contains("\"v1\""));
+        assert!(js.contains("shadowed"));
+        assert!(js.contains("\"modern\"") && !js.contains("legacy"));
+        assert!(!js.contains("__TARGET__"));
+
+        let dev = CompilerConfigBuilder::new()
+            .define("API_VERSION", defines::parse_value("1"))
+            .build();
+        let js = Compiler::with_config(dev)
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        assert!(js.contains("debug build"));
+        assert!(!js.contains("\"v2\"") && !js.contains("\"v1\""));
+    }
+
+    #[test]
+    fn test_calls_into_declared_modules_are_checked() {
+        let declarations = ExternDeclarations::parse(
+            r#"
+module "left-pad":
+    # Pad `text` on the left
+    def leftPad(text: str, length: int, fill: str = " ") -> str
+    VERSION: str
+
+module "lodash":
+    def chunk(items: list, size: int) -> list
+    def max(*values: float) -> float
+"#,
+            "types/npm.nagd",
+        )
+        .unwrap();
+        let member = declarations.module("left-pad").unwrap().member("leftPad").unwrap();
+        assert_eq!(member.doc.as_deref(), Some("Pad `text` on the left"));
+
+        let compiler = Compiler::with_config(
+            CompilerConfigBuilder::new()
+                .externs(declarations)
+                .build(),
+        );
+        let valid = r#"
+import { leftPad as pad, VERSION } from "left-pad"
+import lodash
+
+def label(width: int):
+    return pad(VERSION, width, "0")
+
+print(pad("7", 3), lodash.chunk([1, 2, 3], 2), lodash.max(1, 2.5, 3))
+"#;
+        assert!(compiler.compile_string(valid, None).is_ok());
+
+        let invalid = r#"
+import { leftPad, rightPad } from "left-pad"
+import lodash
+
+def label(width: str):
+    return leftPad("x", width)
+
+print(leftPad("7"), leftPad(leftPad("7", 2), "3"), lodash.zip([1], [2]))
+"#;
+        let message = compiler.compile_string(invalid, None).unwrap_err().to_string();
+        assert!(message.contains("\"left-pad\" has no declared export 'rightPad'"));
+        assert!(message.contains("argument 'length' of leftPad() from \"left-pad\" expects int, got str"));
+        assert!(message.contains("leftPad() from \"left-pad\" takes 2 to 3 argument(s) but 1 were given"));
+        assert!(message.contains("\"lodash\" has no declared export 'zip'"));
+        assert_eq!(message.matches("expects int, got str").count(), 2);
+
+        let broken = ExternDeclarations::parse("module \"x\":\n    def f(a: int\n", "x.nagd");
+        assert!(broken.unwrap_err().to_string().contains("x.nagd:2: expected a parameter list"));
+    }
+
+    #[test]
+    fn test_calls_to_declared_globals_are_checked() {
+        let declarations = ExternDeclarations::parse(
+            "globals:\n    def fetch_user(id: int) -> dict\n    async def notify(*users: str)\n",
+            "types/host.nagd",
+        )
+        .unwrap();
+        assert_eq!(declarations.globals().len(), 2);
+
+        let compiler = Compiler::with_config(
+            CompilerConfigBuilder::new()
+                .externs(declarations)
+                .build(),
+        );
+        assert!(compiler
+            .compile_string("user = fetch_user(1)\nnotify(\"a\", \"b\")\n", None)
+            .is_ok());
+
+        let message = compiler
+            .compile_string("fetch_user(\"1\")\nnotify(2)\n", None)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("argument 'id' of fetch_user() expects int, got str"));
+        assert!(message.contains("argument 'users' of notify() expects str, got int"));
+
+        // A local definition shadows the declared global
+        let shadowed = "def fetch_user(name: str):\n    return name\n\nfetch_user(\"x\")\n";
+        assert!(compiler.compile_string(shadowed, None).is_ok());
+    }
+
+    #[test]
+    fn test_expressions_are_checked_in_context() {
+        let declarations = ExternDeclarations::parse(
+            "globals:\n    def fetch_user(id: int) -> dict\n",
+            "types/host.nagd",
+        )
+        .unwrap();
+        let context = nagari_parser::parse("x = 1\ny = x * 2\nname = \"a\"\n").unwrap();
+        let function = nagari_parser::parse("def f(n: float):\n    pass\n").unwrap();
+        let nagari_parser::Statement::Function { parameters, .. } = &function.statements[0]
+        else {
+            panic!("expected a function");
+        };
+
+        let check = |source: &str| {
+            let program = nagari_parser::parse(source).unwrap();
+            let nagari_parser::Statement::Expression(expression) = &program.statements[0] else {
+                panic!("expected an expression");
+            };
+            declarations
+                .check_expression(&context.statements, parameters, expression)
+                .map(|t| externs::type_name(&t))
+        };
+
+        assert_eq!(check("y + 1").unwrap(), "int");
+        assert_eq!(check("name + \"b\"").unwrap(), "str");
+        assert_eq!(check("n / 2").unwrap(), "float");
+        assert_eq!(check("fetch_user(x)").unwrap(), "dict[any, any]");
+        assert!(check("fetch_user(name)")
+            .unwrap_err()
+            .to_string()
+            .contains("argument 'id' of fetch_user() expects int, got str"));
+    }
+
+    #[test]
+    fn test_incremental_compilation_reuses_unchanged_statements() {
+        let before = "\
+def greet(name):
+    return \"Hello, \" + name
+
+def farewell(name):
+    return \"Bye, \" + name
+
+count = 1
+print(greet(\"a\"), len([count]))
+";
+        let after = before.replace("Hello", "Hi").replace("count = 1", "count = 2");
+
+        let mut incremental = IncrementalCompiler::new(Compiler::new());
+        let first = incremental.compile(before).unwrap();
+        assert_eq!(first.emitted, 4);
+        assert_eq!(first.changed, vec!["greet", "farewell", "count"]);
+
+        let second = incremental.compile(&after).unwrap();
+        let full = Compiler::new().compile_string(&after, None).unwrap();
+        assert_eq!(second.js_code, full.js_code);
+        assert_eq!(second.changed, vec!["greet", "count"]);
+        assert_eq!((second.emitted, second.reused), (2, 2));
+        assert!(second.removed.is_empty());
+
+        // Declaring `count` earlier turns the later assignment into a reassignment
+        let moved = format!(
+            "count = 0\n{}",
+            after.replace("def farewell(name):\n    return \"Bye, \" + name\n", "")
+        );
+        let third = incremental.compile(&moved).unwrap();
+        let full = Compiler::new().compile_string(&moved, None).unwrap();
+        assert_eq!(third.js_code, full.js_code);
+        assert_eq!(third.removed, vec!["farewell"]);
+    }
+
+    #[test]
+    fn test_output_is_the_same_with_windows_line_endings() {
+        let dir = std::env::temp_dir().join(format!("nagari-crlf-{}", std::process::id()));
+        let source = "message = \"first\nsecond\"\ndata = embed(\"data.txt\")\n\
+                      js\"\"\"console.log(1);\nconsole.log(2);\"\"\"\n\
+                      def greet(name: str) -> str:\n    return message + name\n";
+        // A Linux checkout and a Windows one of the same files
+        let checkout = |name: &str, newline: &str| {
+            let root = dir.join(name);
+            fs::create_dir_all(&root).unwrap();
+            fs::write(root.join("app.nag"), source.replace('\n', newline)).unwrap();
+            fs::write(root.join("data.txt"), "a\nb\n".replace('\n', newline)).unwrap();
+            root.join("app.nag")
+        };
+        let unix = checkout("unix", "\n");
+        let windows = checkout("windows", "\r\n");
+        let compile = |line_endings: LineEndings, input: &Path| {
+            let config = CompilerConfigBuilder::new()
+                .sourcemap(true)
+                .declarations(true)
+                .line_endings(line_endings)
+                .build();
+            Compiler::with_config(config).compile_file(input).unwrap()
+        };
+
+        let expected = compile(LineEndings::Lf, &unix);
+        let outputs = |result: &CompilationResult| {
+            [
+                result.js_code.clone(),
+                result.source_map.clone().unwrap(),
+                result.declarations.clone().unwrap(),
+            ]
+        };
+        for output in outputs(&expected) {
+            assert!(!output.contains('\r'), "{output}");
+        }
+        assert!(expected.js_code.contains(r#""first\nsecond""#));
+        assert!(expected.js_code.contains(r#""a\nb\n""#));
+        assert_eq!(
+            outputs(&compile(LineEndings::Lf, &windows)),
+            outputs(&expected)
+        );
+
+        // Overridden, every line of every file ends with CRLF
+        let crlf = compile(LineEndings::Crlf, &windows);
+        for (output, lf) in outputs(&crlf).iter().zip(outputs(&expected)) {
+            assert_eq!(output.replace("\r\n", "\n"), lf);
+            assert_eq!(output.matches('\n').count(), output.matches("\r\n").count());
+        }
+
+        assert_eq!(
+            portable::slash_path(Path::new(r"src\utils\strings.nag")),
+            "src/utils/strings.nag"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compile_project_recompiles_changed_modules_and_their_importers() {
+        let dir = std::env::temp_dir().join(format!("nagari-project-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let util = dir.join("util.nag");
+        let main = dir.join("main.nag");
+        let other = dir.join("other.nag");
+        fs::write(&util, "def double(x):\n    return x * 2\n").unwrap();
+        fs::write(
+            &main,
+            "import { double } from \"./util\"\nprint(double(2))\n",
+        )
+        .unwrap();
+        fs::write(&other, "print(1)\n").unwrap();
+        let inputs = [&util, &main, &other];
+        let compiler = Compiler::with_config(
+            CompilerConfigBuilder::new()
+                .cache_dir(dir.join(".nag-cache"))
+                .build(),
+        );
+        let cached = |results: Vec<Result<ModuleOutput, NagariError>>| -> Vec<bool> {
+            results.into_iter().map(|r| r.unwrap().cached).collect()
+        };
+
+        let first = compiler.compile_project(&inputs);
+        let main_js = first[1].as_ref().unwrap().js_code.clone();
+        assert_eq!(cached(first), [false, false, false]);
+
+        let second = compiler.compile_project(&inputs);
+        assert_eq!(second[1].as_ref().unwrap().js_code, main_js);
+        assert_eq!(cached(second), [true, true, true]);
+
+        // `main` imports `util`, so it is compiled again with it
+        fs::write(&util, "def double(x):\n    return x + x\n").unwrap();
+        assert_eq!(
+            cached(compiler.compile_project(&inputs)),
+            [false, false, true]
+        );
+
+        fs::write(&other, "def f(:\n").unwrap();
+        let results = compiler.compile_project(&inputs);
+        assert_eq!(results[2].as_ref().unwrap_err().code(), "E0002");
+        assert!(results[0].as_ref().unwrap().cached);
+
+        // Options that change the output invalidate every module
+        let mut minified = compiler.clone();
+        minified.config.minify = true;
+        assert_eq!(
+            cached(minified.compile_project(&inputs[..2])),
+            [false, false]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_hoists_modules_into_one_scope() {
+        let dir = std::env::temp_dir().join(format!("nagari-bundle-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("util.nag"), "def helper(x):\n    return x + 1\n").unwrap();
+        fs::write(
+            dir.join("lib/math.nag"),
+            "import { render } from \"preact\"\n\ndef helper(x):\n    return x * 2\n\ndef double(x):\n    return helper(x)\n",
+        )
+        .unwrap();
+        let main = dir.join("main.nag");
+        fs::write(
+            &main,
+            "import { helper } from \"./util\"\nimport { double } from \"./lib/math\"\nimport { render } from \"preact\"\n\ncount = 10\n\ndef main(count=1):\n    print(helper(count), double(count))\n\nmain(count=count)\n",
+        )
+        .unwrap();
+        let compiler = Compiler::new();
+
+        let bundle = compiler.bundle(&main, BundleFormat::Esm).unwrap();
+        let names: Vec<_> = bundle
+            .modules
+            .iter()
+            .map(|module| module.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["util.nag", "math.nag", "main.nag"]);
+        let js = &bundle.js_code;
+        assert_eq!(js.matches("from 'nagari-runtime';").count(), 1);
+        assert_eq!(js.matches("import { render } from \"preact\";").count(), 1);
+        assert!(!js.contains("./util"));
+        // The second `helper` is renamed, in its module only; parameters keep their names
+        assert!(js.contains("function helper(x) {\n    return (x + 1);\n}"));
+        assert!(js.contains("function helper$1(x) {\n    return (x * 2);\n}"));
+        assert!(js.contains("return helper$1(x);"));
+        assert!(js.contains("console.log(helper(count), double(count));"));
+
+        let iife = compiler.bundle(&main, BundleFormat::Iife).unwrap().js_code;
+        assert!(iife.starts_with("(function (nagariRuntime, preact) {\n\"use strict\";\n"));
+        assert!(iife.contains("\nconst { render } = preact;\n"));
+        assert!(!iife.contains("\nimport "));
+        assert!(iife.ends_with("})(nagariRuntime, preact);\n"));
+
+        fs::write(&main, "import { missing } from \"./nowhere\"\n").unwrap();
+        let error = compiler.bundle(&main, BundleFormat::Esm).unwrap_err();
+        assert!(error.to_string().contains("cannot find module './nowhere'"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_minify_folds_literals_and_shortens_locals() {
+        let source = "def area(width, height=2):\n    seconds = 60 * 60\n    label = \"a\" + \"b\"\n    for step in range(3):\n        seconds = seconds + step\n    def scaled(factor):\n        result = factor * seconds\n        return result\n    return scaled(width * height)\n\ndef largest(values):\n    max = 0\n    max = max(values)\n    return max\n\nprint(area(3, height=4), 1 - 4, 10 / 4)\n";
+        let config = CompilerConfigBuilder::new().minify(true).build();
+        let js = Compiler::with_config(config)
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+
+        assert!(!js.contains("// "));
+        assert!(!js.contains("    "));
+        // Parameters and functions keep their names; nested functions name their variables
+        // first, so the names they see of the function around them don't clash
+        assert!(js.contains("function area(width,height=2){"));
+        assert!(js.contains("$b=3600;$c=\"ab\";for(const $d of range(3)){$b=($b+$d);}"));
+        assert!(js.contains("function scaled(factor){let $a;$a=(factor*$b);return $a;}"));
+        assert!(js.contains("return scaled((width*height));}"));
+        // A called name may be a builtin
+        assert!(js.contains("max=Math.max(values);return max;"));
+        // Negative numbers are left as operations
+        assert!(js.contains("console.log(area(3,4),(1-4),2.5);"));
+
+        assert_eq!(
+            minify::compact(
+                "const a = \"x  // y\";  /* note */\nconst b = `t ${ a + `${ 1 }` }  u`;\n\
+                 if (a) {\n    return /  [/]  /g.test(b) + +a;\n}\nx = a - -b\n++x\n"
+            ),
+            "const a=\"x  // y\";const b=`t ${a+`${1}`}  u`;if(a){return/  [/]  /g.test(b)+ +a;}\nx=a- -b\n++x\n"
+        );
+    }
+
+    #[test]
+    fn test_inline_js_passes_through_with_typed_boundary() {
+        let source = r#"
+def clamp(x: int, lo: int, hi: int) -> int:
+    js"""
+    if (x < lo) {
+        return lo;
+    }
+    return Math.min(x, hi);
+    """
+
+started = js"""Date.now()"""
+print(clamp(started, 0, 10))
+"#;
+        let result = Compiler::new().compile_string(source, None).unwrap();
+        assert!(result.js_code.contains(
+            "function clamp(x, lo, hi) {\n    if (x < lo) {\n        return lo;\n    }\n    return Math.min(x, hi);\n}"
+        ));
+        assert!(result.js_code.contains("(Date.now())"));
+
+        match &result.ast.statements[0] {
+            ast::Statement::FunctionDef(function) => {
+                assert_eq!(function.return_type, Some(types::Type::Int));
+                assert!(function
+                    .parameters
+                    .iter()
+                    .all(|p| p.param_type == Some(types::Type::Int)));
+            }
+            other => panic!("expected a function, got {other:?}"),
+        }
+
+        let err = bytecode::generate(&result.ast).unwrap_err();
+        assert!(err.to_string().contains("only supported by JavaScript targets"));
+    }
+
+    #[test]
+    fn test_warning_levels() {
+        let config = CompilerConfigBuilder::new()
+            .warning_level("line-length", WarningLevel::Allow)
+            .deny_warnings(true)
+            .build();
+        assert_eq!(config.warning_level("line-length"), WarningLevel::Allow);
+        assert_eq!(config.warning_level("unused-variables"), WarningLevel::Deny);
+        assert_eq!(
+            CompilerConfig::default().warning_level("unused-variables"),
+            WarningLevel::Warn
+        );
+        assert_eq!("DENY".parse::<WarningLevel>(), Ok(WarningLevel::Deny));
+        assert!("forbid".parse::<WarningLevel>().is_err());
+
+        let warning = |category: &str, line| Warning {
+            category: category.to_string(),
+            message: "example".to_string(),
+            line: Some(line),
+            column: None,
+        };
+        let source = "let a = 1\nlet b = 2  # nagari:ignore unused-variables\n";
+
+        // Suppressed and allowed warnings never reach the deny check
+        let kept = warnings::enforce(
+            &config.warning_levels,
+            config.deny_warnings,
+            source,
+            vec![warning("unused-variables", 2), warning("line-length", 1)],
+        )
+        .unwrap();
+        assert!(kept.is_empty());
+
+        let denied = warnings::enforce(
+            &config.warning_levels,
+            config.deny_warnings,
+            source,
+            vec![warning("unused-variables", 1)],
+        );
+        assert!(matches!(denied, Err(NagariError::SemanticError(_))));
+
+        let mut levels = HashMap::new();
+        levels.insert(warnings::WARNINGS_GROUP.to_string(), WarningLevel::Allow);
+        levels.insert("shadowing".to_string(), WarningLevel::Warn);
+        let kept = warnings::enforce(
+            &levels,
+            false,
+            source,
+            vec![warning("shadowing", 1), warning("unused-variables", 1)],
+        )
+        .unwrap();
+        assert_eq!(kept, vec![warning("shadowing", 1)]);
+    }
+
+    #[test]
+    fn test_string_len_and_slice_use_code_points() {
+        let compiler = Compiler::new();
+        let result = compiler
+            .compile_string(
+                "word = \"café\"\nn = len(word)\nhead = word[1:3]\n",
+                Some("unicode.nag"),
+            )
+            .expect("len and slicing should compile");
+
+        let js = &result.js_code;
+        assert!(js.contains("nagariLen(word)"));
+        assert!(js.contains("nagariSlice(word, 1, 3, undefined)"));
+        assert!(js.contains("function nagariLen(obj)"));
+        assert!(js.contains("function nagariSlice(seq, start, stop, step)"));
+    }
+
+    #[test]
+    fn test_timers_import_uses_the_runtime_module() {
+        let source = "import { sleep, set_interval, cancel } from \"timers\"\n\
+                      handle = set_interval(tick, 100)\n\
+                      cancel(handle)\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("timers.nag"))
+            .unwrap()
+            .js_code;
+
+        // Node has a `timers` module of its own, which must not be imported instead
+        assert!(js.contains(
+            "const { sleep, set_interval, cancel } = InteropRegistry.getModule(\"timers\") || {};"
+        ));
+        assert!(!js.contains("from 'timers'"));
+    }
+
+    #[test]
+    fn test_events_import_uses_the_emitted_helper() {
+        let source = "import { on, emit, EventEmitter } from \"events\"\n\
+                      on(\"ready\", print)\n\
+                      emit(\"ready\", 1)\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("events.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains("const { on, emit, EventEmitter } = nagariEvents();"));
+        assert!(js.contains("function nagariEvents()"));
+        assert!(!js.contains("from 'events'"));
+    }
+
+    #[test]
+    fn test_schema_import_uses_the_emitted_helper() {
+        let source = "import { validate, optional } from \"schema\"\n\
+                      port = optional(\"int\", 80)\n\
+                      config = validate(schema, data)\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("schema.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains("const { validate, optional } = nagariSchema();"));
+        assert!(js.contains("function nagariSchema()"));
+        assert!(!js.contains("function nagariEvents()"));
+    }
+
+    #[test]
+    fn test_csv_and_struct_imports_use_the_runtime_modules() {
+        let source = "import { parse, next_row } from \"csv\"\n\
+                      import { pack, unpack } from \"struct\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("data.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(
+            js.contains("const { parse, next_row } = InteropRegistry.getModule(\"csv\") || {};")
+        );
+        assert!(
+            js.contains("const { pack, unpack } = InteropRegistry.getModule(\"struct\") || {};")
+        );
+        assert!(!js.contains("from 'csv'") && !js.contains("from 'struct'"));
+    }
+
+    #[test]
+    fn test_path_import_uses_the_runtime_module() {
+        let source = "import { join, relative, glob } from \"path\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("paths.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains(
+            "const { join, relative, glob } = InteropRegistry.getModule(\"path\") || {};"
+        ));
+        assert!(!js.contains("from 'path'"));
+    }
+
+    #[test]
+    fn test_crypto_import_uses_the_runtime_module() {
+        let source = "import { hash_sha256, hmac, uuid4 } from \"crypto\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("signing.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains(
+            "const { hash_sha256, hmac, uuid4 } = InteropRegistry.getModule(\"crypto\") || {};"
+        ));
+        assert!(!js.contains("from 'crypto'"));
+    }
+
+    #[test]
+    fn test_intl_import_uses_the_runtime_module() {
+        let source = "import { format_currency, format_date } from \"intl\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("invoice.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains(
+            "const { format_currency, format_date } = InteropRegistry.getModule(\"intl\") || {};"
+        ));
+        assert!(!js.contains("from 'intl'"));
+    }
+
+    #[test]
+    fn test_html_import_uses_the_runtime_module() {
+        let source = "import { html, safe } from \"html\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("page.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains("const { html, safe } = InteropRegistry.getModule(\"html\") || {};"));
+        assert!(!js.contains("from 'html'"));
+    }
+
+    #[test]
+    fn test_websocket_import_uses_the_runtime_module() {
+        let source = "import { connect, receive, serve } from \"websocket\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("chat.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains(
+            "const { connect, receive, serve } = InteropRegistry.getModule(\"websocket\") || {};"
+        ));
+        assert!(!js.contains("from 'websocket'"));
+    }
+
+    #[test]
+    fn test_http_server_import_uses_the_runtime_module() {
+        let source = "import { create, route, listen } from \"http.server\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("app.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains(
+            "const { create, route, listen } = InteropRegistry.getModule(\"http.server\") || {};"
+        ));
+        assert!(!js.contains("require(\"http.server\")"));
+    }
+
+    #[test]
+    fn test_sqlite_import_uses_the_runtime_module() {
+        let source = "import { open, execute, query } from \"sqlite\"\n";
+        let js = Compiler::new()
+            .compile_string(source, Some("shop.nag"))
+            .unwrap()
+            .js_code;
+
+        assert!(js.contains(
+            "const { open, execute, query } = InteropRegistry.getModule(\"sqlite\") || {};"
+        ));
+        assert!(!js.contains("require(\"sqlite\")"));
+    }
+
+    #[test]
+    fn test_es5_target_lowers_modern_syntax() {
+        let compiler = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        let result = compiler
+            .compile_string(
+                "def add(a, b = 2):\n    total = a + b\n    return total\n\nclass Animal {\n    def speak(self):\n        return \"...\"\n}\n\nfor item in [1, 2]:\n    print(add(item))\n",
+                Some("legacy.nag"),
+            )
+            .expect("es5 output should compile");
+
+        let js = &result.js_code;
+        assert!(js.contains("if (b === undefined)"));
+        assert!(js.contains("var Animal = (function () {"));
+        assert!(js.contains("Animal.prototype.speak = function () {"));
+        assert!(js.contains("nagariIter([1, 2])"));
+        for modern in ["=>", "let ", "const ", "class ", "`", "...)"] {
+            assert!(!js.contains(modern), "es5 output contains `{}`", modern);
+        }
+
+        // Async functions become state machines; generators have no es5 lowering
+        let fetch = |is_async: bool, body: Vec<ast::Statement>| ast::Program {
+            statements: vec![ast::Statement::FunctionDef(ast::FunctionDef {
+                name: "fetch_all".to_string(),
+                parameters: Vec::new(),
+                return_type: None,
+                body,
+                is_async,
+                decorators: Vec::new(),
+                is_generator: false,
+            })],
+        };
+        let awaited = ast::Statement::Assignment(ast::Assignment {
+            name: "data".to_string(),
+            var_type: None,
+            value: ast::Expression::Await(Box::new(ast::Expression::Identifier(
+                "request".to_string(),
+            ))),
+        });
+        let runtime = runtime_package::DEFAULT_SPECIFIER;
+        let js = transpiler::transpile(
+            &fetch(true, vec![awaited]),
+            "es5",
+            false,
+            false,
+            None,
+            runtime,
+            false,
+        )
+        .unwrap();
+        assert!(js.contains("return nagariAsync(function (__ctx) {"));
+        assert!(js.contains("data = __ctx.sent;"));
+        assert!(!js.contains("async function fetch_all") && !js.contains("await request"));
+
+        let yielded = ast::Statement::Yield(ast::YieldStatement { value: None });
+        assert!(transpiler::transpile(
+            &fetch(false, vec![yielded]),
+            "es5",
+            false,
+            false,
+            None,
+            runtime,
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_jsx_server_side_rendering() {
+        let element = |tag: &str, attributes, children| {
+            ast::Expression::JSXElement(ast::JSXElement {
+                tag: tag.to_string(),
+                attributes,
+                children,
+                self_closing: false,
+            })
+        };
+        let greeting = element(
+            "Greeting",
+            vec![ast::JSXAttribute {
+                name: "name".to_string(),
+                value: Some(ast::Expression::Literal(ast::Literal::String(
+                    "Ada".to_string(),
+                ))),
+            }],
+            Vec::new(),
+        );
+        let ast::Expression::JSXElement(greeting) = greeting else {
+            unreachable!()
+        };
+        let program = ast::Program {
+            statements: vec![ast::Statement::Assignment(ast::Assignment {
+                name: "page".to_string(),
+                var_type: None,
+                value: element("div", Vec::new(), vec![ast::JSXChild::Element(greeting)]),
+            })],
+        };
+        let runtime = runtime_package::DEFAULT_SPECIFIER;
+
+        let js = transpiler::transpile(&program, "node", true, true, None, runtime, false).unwrap();
+        assert!(js.contains("const { jsToNagari, nagariToJS, InteropRegistry"));
+        assert!(js.contains("jsx, Fragment, render_to_string }"), "{js}");
+        assert!(
+            js.contains(r#"jsx("div", null, jsx(Greeting, {name: "Ada"}))"#),
+            "{js}"
+        );
+        assert!(!js.contains("ReactInterop"));
+
+        // Without SSR, components stay names for React to resolve
+        let js =
+            transpiler::transpile(&program, "node", true, false, None, runtime, false).unwrap();
+        assert!(js.contains(r#"jsx("Greeting", {name: "Ada"})"#), "{js}");
+
+        let config = |target: &str, jsx: bool| {
+            CompilerConfigBuilder::new()
+                .target(target)
+                .jsx(jsx)
+                .ssr(true)
+                .build()
+        };
+        assert!(config("node", true).validate().is_ok());
+        assert!(config("node", false).validate().is_err());
+        assert!(config("es6", true).validate().is_err());
+    }
+
+    #[test]
+    fn test_internal_errors_only_lose_their_statement() {
+        let function = |name: &str| {
+            ast::Statement::FunctionDef(ast::FunctionDef {
+                name: name.to_string(),
+                parameters: Vec::new(),
+                return_type: None,
+                body: vec![ast::Statement::Return(Some(ast::Expression::Literal(
+                    ast::Literal::Int(1),
+                )))],
+                is_async: false,
+                decorators: Vec::new(),
+                is_generator: false,
+            })
+        };
+        // The parser never puts an export declaration at the top level, and the transpiler
+        // panics on one for es6
+        let program = ast::Program {
+            statements: vec![
+                function("before"),
+                ast::Statement::ExportDeclaration(ast::ExportDeclarationStatement {
+                    declaration: Box::new(function("broken")),
+                }),
+                function("after"),
+            ],
+        };
+        let runtime = runtime_package::DEFAULT_SPECIFIER;
+
+        let (js, errors) =
+            transpiler::transpile_recovering(&program, "es6", false, false, None, runtime, false)
+                .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].declaration.as_deref(), Some("broken"));
+        assert!(js.contains("function before()") && js.contains("function after()"));
+        assert!(
+            js.contains(
+                r#"throw new Error("Internal compiler error: `broken` could not be compiled");"#
+            ),
+            "{js}"
+        );
+        assert!(!js.contains("function broken()"));
+    }
+
+    #[test]
+    fn test_class_members() {
+        let source = r#"
+class Account extends Base {
+    rate = 2
+
+    def __init__(self, balance):
+        super().__init__("account")
+        self.__balance = balance
+
+    @property
+    def balance(self):
+        return self.__balance
+
+    @balance.setter
+    def balance(self, value):
+        self.__balance = self.__checked(value)
+
+    def __checked(self, value):
+        return value
+
+    @staticmethod
+    def empty():
+        return Account(0)
+
+    @classmethod
+    def opened(cls, balance):
+        return cls(balance)
+}
+
+def trace(f):
+    return f
+
+@trace
+def report():
+    return Account.empty().balance
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            "class Account extends Base {\n    #balance;\n    static rate = 2;\n",
+            "    constructor(balance) {\n        super(\"account\");\n        const self = this;\n        self.#balance = balance;\n",
+            "    get balance() {\n        const self = this;\n        return self.#balance;\n",
+            "    set balance(value) {\n        const self = this;\n        self.#balance = self.#checked(value);\n",
+            "    #checked(value) {\n",
+            "    static empty() {\n        return new Account(0);\n",
+            "    static opened(balance) {\n        const cls = this;\n        return new cls(balance);\n",
+            "report = trace(report);",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+
+        let es5 = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        let js = es5.compile_string(source, None).unwrap().js_code;
+        for expected in [
+            "__super.call(self, \"account\");\n        self._Account__balance = balance;",
+            "Object.defineProperty(Account.prototype, \"balance\", { get: function () {",
+            "Object.defineProperty(Account.prototype, \"balance\", { set: function (value) {",
+            "Account.prototype._Account__checked = function (value) {",
+            "Account.empty = function () {\n        return Account(0);",
+            "Account.opened = function (balance) {\n        var cls = this;",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+
+        let declarations =
+            Compiler::with_config(CompilerConfigBuilder::new().declarations(true).build())
+                .compile_string(source, None)
+                .unwrap()
+                .declarations
+                .unwrap();
+        for expected in [
+            "    static rate: number;\n",
+            "    get balance(): any;\n    set balance(value: any);\n",
+            "    static empty(): any;\n    static opened(balance: any): any;\n",
+        ] {
+            assert!(
+                declarations.contains(expected),
+                "missing `{expected}` in:\n{declarations}"
+            );
+        }
+        assert!(!declarations.contains("checked"));
+
+        let error = Compiler::new()
+            .compile_string(
+                "class A {\n    @cached\n    def f(self):\n        pass\n}\n",
+                None,
+            )
+            .unwrap_err();
+        assert!(error.to_string().contains("@property"), "{error}");
+    }
+
+    #[test]
+    fn test_super_calls() {
+        let source = r#"
+class A {
+    def __init__(self, x):
+        self.x = x
+
+    def describe(self):
+        return "A"
+}
+
+class B extends A {
+    def __init__(self, x):
+        super().__init__(x + 1)
+}
+
+class C extends B {
+    def describe(self):
+        return "C" + super().describe()
+}
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        assert!(js.contains("super((x + 1));"), "{js}");
+        assert!(js.contains("return (\"C\" + super.describe());"), "{js}");
+
+        let es5 = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        let js = es5.compile_string(source, None).unwrap().js_code;
+        assert!(js.contains("__super.call(self, (x + 1));"), "{js}");
+        assert!(
+            js.contains("return (\"C\" + __super.prototype.describe.call(self));"),
+            "{js}"
+        );
+
+        // The VM looks the methods up through the classes after the caller's
+        assert!(Compiler::new().compile_to_bytecode(source).is_ok());
+        let error = Compiler::new()
+            .compile_to_bytecode("def f(x):\n    return super().f(x)\n")
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("only be used in methods"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_set_operators() {
+        let source = r#"
+a = {1, 2, 3}
+b = set([2, 3])
+f = frozenset(a)
+
+def shared(x: set[int], y):
+    return x & y
+
+both = a | b
+print(both - f, a ^ b, b < a, a == {3, 2, 1}, 6 | 1)
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        assert!(js.contains("let b = new Set([2, 3]);"), "{js}");
+        assert!(js.contains("let f = nagariFrozenSet(a);"), "{js}");
+        assert!(js.contains("return nagariSetOp(\"&\", x, y);"), "{js}");
+        assert!(js.contains("let both = nagariSetOp(\"|\", a, b);"), "{js}");
+        assert!(js.contains("nagariSetOp(\"-\", both, f)"), "{js}");
+        assert!(js.contains("nagariSetOp(\"<\", b, a)"), "{js}");
+        assert!(
+            js.contains("nagariSetOp(\"==\", a, new Set([3, 2, 1]))"),
+            "{js}"
+        );
+        // Operands not known to be sets keep JavaScript's operators
+        assert!(js.contains("(6 | 1)"), "{js}");
+        assert!(js.contains("function nagariSetOp(op, a, b)"), "{js}");
+
+        let es5 = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        let js = es5.compile_string(source, None).unwrap().js_code;
+        assert!(js.contains("return nagariSetOp(\"&\", x, y);"), "{js}");
+
+        assert!(Compiler::new().compile_to_bytecode(source).is_ok());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_names_forward_to_their_replacements() {
+        let compiler = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        assert_eq!(compiler.get_config().target, compiler.config().target);
+
+        let tokens = Lexer::new("x = 1").tokenize().unwrap();
+        assert!(NagParser::new(tokens).parse().is_ok());
+    }
+
+    #[test]
+    fn test_environment_prunes_polyfills() {
+        let compile = |target: &str, environment: Option<Environment>| {
+            let config = CompilerConfigBuilder::new()
+                .target(target)
+                .environment(environment)
+                .build();
+            let result = Compiler::with_config(config).compile_string("x = 1", None);
+            result.unwrap().js_code
+        };
+
+        let unpruned = compile("node", None);
+        assert!(unpruned.contains("globalThis.document"));
+        let node = compile("node", Some(Environment::Node20));
+        assert!(!node.contains("globalThis.document"));
+        assert!(!node.contains("typeof globalThis"));
+        assert!(node.len() < unpruned.len());
+
+        let browser = compile("es6", Some(Environment::ModernBrowser));
+        assert!(!browser.contains("globalThis.process"));
+        let legacy = compile("es5", Some(Environment::Legacy));
+        assert!(legacy.contains("String.prototype.padStart"));
+        assert!(!legacy.contains("=>"));
+
+        assert_eq!("node18".parse(), Ok(Environment::Node18));
+        assert!("ie6".parse::<Environment>().is_err());
+    }
+
+    #[test]
+    fn test_runtime_specifier_is_configurable_and_validated() {
+        let compile = |target: &str, runtime: RuntimePackage| {
+            let config = CompilerConfigBuilder::new()
+                .target(target)
+                .runtime(runtime)
+                .build();
+            Compiler::with_config(config).compile_string("x = 1", None)
+        };
+
+        let mirrored = RuntimePackage::new("@myorg/nagari-runtime", "^0.5.2");
+        let js = compile("esm", mirrored.clone()).unwrap().js_code;
+        assert!(js.contains("from '@myorg/nagari-runtime';"));
+        assert!(!js.contains("'nagari-runtime'"));
+        assert_eq!(
+            mirrored.dependency(),
+            Some(("@myorg/nagari-runtime", "^0.5.2"))
+        );
+
+        let vendored = RuntimePackage::new("./vendor/nagari-runtime/index.js", "");
+        let js = compile("cjs", vendored.clone()).unwrap().js_code;
+        assert!(js.contains("require('./vendor/nagari-runtime/index.js')"));
+        assert_eq!(vendored.dependency(), None);
+
+        for runtime in [
+            RuntimePackage::new("My Runtime", "^0.5.0"),
+            RuntimePackage::new("nagari-runtime');alert(1)//", "^0.5.0"),
+            RuntimePackage::new("@myorg", "^0.5.0"),
+            RuntimePackage::new("nagari-runtime", "^0.5.0;"),
+        ] {
+            let error = compile("esm", runtime).unwrap_err();
+            assert_eq!(error.code(), "E0008");
+        }
+    }
+
+    #[test]
+    fn test_declarations_describe_functions_classes_and_constants() {
+        let source = r#"
+VERSION = "1.2.0"
+retries = 3
+retries = 5
+_cache = {}
+
+def greet(name: str, excited: bool = false) -> str:
+    "Greet `name`."
+    return name
+
+def load(url: str, headers: dict[str, str]) -> list[int] | None:
+    return []
+
+def log(message, level = 1):
+    print(message)
+
+def _helper():
+    return 1
+
+class Greeter {
+    def __init__(self, prefix: str):
+        pass
+    def hello(self, name: str) -> list[str]:
+        return [name]
+    def _secret(self):
+        pass
+}
+"#;
+        let compiler =
+            Compiler::with_config(CompilerConfigBuilder::new().declarations(true).build());
+        let declarations = compiler
+            .compile_string(source, Some("greeter.nag"))
+            .unwrap()
+            .declarations
+            .unwrap();
+
+        for expected in [
+            "export declare const VERSION: string;",
+            "export declare let retries: number;",
+            "/**\n * Greet `name`.\n */\nexport declare function greet(name: string, excited?: boolean): string;",
+            "export declare function load(url: string, headers: Record<string, string>): number[] | null;",
+            "export declare function log(message: any, level?: number): void;",
+            "export declare class Greeter {\n    constructor(prefix: string);\n    hello(name: string): string[];\n}",
+        ] {
+            assert!(
+                declarations.contains(expected),
+                "missing `{expected}` in:\n{declarations}"
+            );
+        }
+        assert!(!declarations.contains("_cache"));
+        assert!(!declarations.contains("_helper"));
+        assert!(!declarations.contains("_secret"));
+
+        assert_eq!(
+            declarations::ts_type(&externs::parse_type("dict[int, list[str | None]]")),
+            "Record<number, (string | null)[]>"
+        );
+    }
+
+    #[test]
+    fn test_jsdoc_comments() {
+        let source = r#"
+def add(a: int, b: int = 1) -> int:
+    "Add two numbers."
+    return a + b
+
+def noop():
+    pass
+"#;
+        let compiler = Compiler::with_config(CompilerConfigBuilder::new().jsdoc(true).build());
+        let js = compiler.compile_string(source, None).unwrap().js_code;
+        assert!(
+            js.contains("/**\n * Add two numbers.\n *\n * @param {number} a\n * @param {number} [b]\n * @returns {number}\n */\nfunction add("),
+            "{js}"
+        );
+        assert_eq!(js.matches("/**").count(), 1, "{js}");
+
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        assert!(!js.contains("/**"), "{js}");
+    }
+
+    #[test]
+    fn test_source_maps_compose_back_to_nag_sources() {
+        use sourcemap::{Original, Segment};
+
+        // app.js as transpiled from app.nag, with `greet` on its second line
+        let compiled = SourceMap::parse(
+            r#"{"version":3,"file":"app.js","sources":["app.nag"],"sourcesContent":["x = 1\n    def greet(): pass"],"names":["greet"],"mappings":"AAAA;IACIA"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            compiled.lookup(1, 6).unwrap().name.as_deref(),
+            Some("greet")
+        );
+
+        // A minified bundle of app.js and plain vendor.js, all on one line
+        let segment = |generated_column, source, line, column| Segment {
+            generated_column,
+            original: Some(Original {
+                source,
+                line,
+                column,
+                name: None,
+            }),
+        };
+        let bundle = SourceMap {
+            file: Some("bundle.min.js".to_string()),
+            sources: vec!["app.js".to_string(), "vendor.js".to_string()],
+            lines: vec![vec![
+                segment(0, 0, 0, 0),
+                segment(10, 0, 1, 6),
+                segment(20, 1, 0, 3),
+                segment(30, 0, 5, 0),
+            ]],
+            ..Default::default()
+        };
+        assert_eq!(SourceMap::parse(&bundle.to_json()).unwrap(), bundle);
+
+        let composed = bundle.compose(|source| (source == "app.js").then_some(&compiled));
+        assert_eq!(composed.sources, ["app.nag", "vendor.js"]);
+        assert!(composed.sources_content[0]
+            .as_deref()
+            .is_some_and(|content| content.starts_with("x = 1")));
+
+        let greet = composed.lookup(0, 12).unwrap();
+        assert_eq!(
+            (greet.source.as_str(), greet.line, greet.column),
+            ("app.nag", 1, 4)
+        );
+        assert_eq!(greet.name.as_deref(), Some("greet"));
+        let vendor = composed.lookup(0, 25).unwrap();
+        assert_eq!((vendor.source.as_str(), vendor.line), ("vendor.js", 0));
+        // app.js has no line 5, so that segment maps nowhere
+        assert!(composed.lookup(0, 30).is_none());
+        assert_eq!(SourceMap::parse(&composed.to_json()).unwrap(), composed);
+
+        assert!(SourceMap::parse(r#"{"version":3,"sources":[],"mappings":"AAAA"}"#).is_err());
+        assert!(SourceMap::parse(r#"{"version":2,"sources":["a"],"mappings":""}"#).is_err());
+    }
+
+    #[test]
+    fn test_minified_source_maps_map_the_minified_output() {
+        let config = CompilerConfigBuilder::new()
+            .minify(true)
+            .sourcemap(true)
+            .build();
+        let result = Compiler::with_config(config)
+            .compile_string("def greet(name):\n    return name\n\ngreet(\"x\")\n", None)
+            .unwrap();
+        let map = SourceMap::parse(&result.source_map.unwrap()).unwrap();
+        assert_eq!(map.file.as_deref(), Some("input.js"));
+        assert_eq!(map.sources, ["input.nag"]);
+        assert!(map.sources_content[0]
+            .as_deref()
+            .is_some_and(|content| content.starts_with("def greet")));
+
+        // Every mapping falls on the minified output, not on the lines it was compacted from
+        let lines: Vec<&str> = result.js_code.lines().collect();
+        assert!(map.lines.len() <= lines.len(), "{}", result.js_code);
+        for (line, segments) in map.lines.iter().enumerate() {
+            for segment in segments {
+                assert!((segment.generated_column as usize) < lines[line].encode_utf16().count());
+            }
+        }
+        assert_eq!(map.lookup(0, 0).map(|position| position.line), Some(0));
+        // The imports minifying joined onto the `"use strict";` line come from later lines of
+        // the transpiled output, which the transpile map doesn't cover
+        assert!(result.js_code.starts_with("\"use strict\";import{"));
+        assert_eq!(map.lookup(0, 13), None);
+    }
+
+    #[test]
+    fn test_dual_package_json_maps_conditions_to_builds() {
+        let modules = vec!["index".to_string(), "utils/strings".to_string()];
+        let package = dual::PackageInfo {
+            name: "greeter".to_string(),
+            version: "1.0.0".to_string(),
+            main: Some("index".to_string()),
+            ..Default::default()
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&dual::package_json(&package, &modules).unwrap()).unwrap();
+        assert_eq!(json["main"], "./cjs/index.js");
+        assert_eq!(json["module"], "./esm/index.js");
+        assert_eq!(json["exports"]["."]["require"]["types"], "./cjs/index.d.ts");
+        assert_eq!(
+            json["exports"]["./utils/strings"]["import"]["default"],
+            "./esm/utils/strings.js"
+        );
+        assert_eq!(
+            json["dependencies"]["nagari-runtime"],
+            runtime_package::DEFAULT_VERSION
+        );
+
+        let exports = [("./extra".to_string(), "extra".to_string())];
+        let package = dual::PackageInfo {
+            exports: Some(exports.into_iter().collect()),
+            ..package
+        };
+        assert!(dual::package_json(&package, &modules).is_err());
+        assert_eq!(
+            dual::module_path(Path::new("./src/utils/strings.nag"), Path::new("src")),
+            "utils/strings"
+        );
+    }
+
+    #[test]
+    fn test_power_and_bitwise_operators() {
+        let source = "a = -2 ** 2\nb = (-2) ** 2\nc = 2 ** 3 ** 2\nd = 1 | 6 ^ 3 & ~4 << 1 + 1\n";
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            "-(2 ** 2)",
+            "((-2) ** 2)",
+            "(2 ** (3 ** 2))",
+            "(1 | (6 ^ (3 & (~4 << (1 + 1)))))",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+
+        let compiler = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        let js = compiler.compile_string(source, None).unwrap().js_code;
+        assert!(js.contains("Math.pow(2, Math.pow(3, 2))"), "{js}");
+
+        let program = Compiler::new().parse_program(source).unwrap();
+        let code = bytecode::generate(&program).unwrap();
+        for opcode in [
+            bytecode::Opcode::BinaryPower,
+            bytecode::Opcode::BinaryBitOr,
+            bytecode::Opcode::BinaryBitXor,
+            bytecode::Opcode::BinaryBitAnd,
+            bytecode::Opcode::BinaryLeftShift,
+        ] {
+            assert!(
+                code.contains(&(opcode as u8)),
+                "no {opcode:?} in the bytecode"
+            );
+        }
+    }
+
+    #[test]
+    fn test_attribute_index_and_tuple_assignments() {
+        let source = "obj = Foo()\nitems = [1, 2]\nobj.count += 1\nitems[0] = 5\nitems[1] *= 2\na, b = 1, 2\na, b = b, a\nc, a = items\n";
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            "obj.count = (obj.count + 1);",
+            "items[0] = 5;",
+            "items[1] = (items[1] * 2);",
+            "let [a, b] = [1, 2];",
+            "[a, b] = [b, a];",
+            "let c;\n[c, a] = items;",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+        assert!(!js.contains("obj.count ==="), "{js}");
+
+        let compiler = Compiler::new();
+        assert!(compiler.compile_string("a, b += 1", None).is_err());
+        assert!(compiler.compile_string("a, f() = 1, 2", None).is_err());
+    }
+
+    #[test]
+    fn test_decorators() {
+        let source = r#"
+@trace
+@retry(3, delay = 0.5)
+def fetch(url):
+    return url
+
+@register("shapes")
+class Circle {
+    def area(self):
+        return 0
+}
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        assert!(
+            js.contains(r#"fetch = trace(retry(3, {"delay": 0.5})(fetch));"#),
+            "{js}"
+        );
+        assert!(
+            js.contains(r#"Circle = register("shapes")(Circle);"#),
+            "{js}"
+        );
+
+        assert!(Compiler::new().compile_to_bytecode(source).is_err());
+    }
+
+    #[test]
+    fn test_keyword_arguments() {
+        let source = r#"
+def greet(name, greeting = "Hello", punctuation = "!"):
+    return greeting + name + punctuation
+
+class Point {
+    def __init__(self, x, y = 0):
+        self.x = x
+}
+
+greet("Ada", punctuation = "?")
+p = Point(y = 2, x = 1)
+print("x", sep = "-", **options)
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            r#"greet("Ada", undefined, "?")"#,
+            "new Point(1, 2)",
+            r#"Object.assign({"sep": "-"}, options)"#,
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+
+        let compiler = Compiler::new();
+        for source in [
+            "def f(a):\n    return a\nf(b = 1)\n",
+            "def f(a):\n    return a\nf(1, a = 2)\n",
+            "def f(a, b):\n    return a\nf(b = 1)\n",
+        ] {
+            assert!(compiler.compile_string(source, None).is_err(), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_match_statements() {
+        let source = r#"
+def describe(value):
+    match value:
+        case 0: return "zero"
+        case (1, [y, "x"]):
+            return y
+        case {kind: "circle", r: r} if r > 10:
+            return r
+        case _:
+            return "other"
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            "if (__match_value__ === 0) {",
+            "Array.isArray(__match_value__) && __match_value__.length === 2 && (__match_value__[0] === 1) && (Array.isArray(__match_value__[1]) && __match_value__[1].length === 2 && (__match_value__[1][1] === \"x\"))",
+            "const y = __match_value__[1][0];",
+            "\"kind\" in __match_value__ && \"r\" in __match_value__ && (__match_value__[\"kind\"] === \"circle\")",
+            "const r = __match_value__[\"r\"];\n            if ((r > 10)) {\n                return r;",
+            "break __match_1;",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+        assert!(!js.contains("continue"), "{js}");
+
+        // `match` is only a keyword at the start of a statement ending in `:`
+        assert!(Compiler::new()
+            .compile_string("match = 1\nprint(match)\n", None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_comprehensions() {
+        let source = r#"
+xs = [1, -2, 3]
+doubled = [x * 2 for x in xs if x > 0]
+pairs = [x + y for x in xs for y in xs if x != y]
+squares = {x: x * x for x in xs}
+signs = {x > 0 for x in xs}
+total = sum(x for x in xs)
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            "for (const x of xs) {\n        if ((x > 0)) {\n            result.push((x * 2));",
+            "for (const y of xs) {",
+            "result[x] = (x * x);",
+            "const result = new Set();",
+            "sum((function*() {",
+            "yield x;",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+
+        // Names bound by a comprehension are known inside it
+        assert!(Compiler::new()
+            .compile_string("print([y for x in [[1]] for y in x if y])\n", None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_async_for_and_with() {
+        let source = r#"
+async def main(pages, lock):
+    async for page in pages:
+        print(page)
+    async with lock as held, lock:
+        print(held)
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            "for await (const page of pages) {",
+            "let held = typeof __async_ctx1_0.__aenter__ === 'function' ? await __async_ctx1_0.__aenter__() : __async_ctx1_0;",
+            "await __async_ctx1_1.__aexit__(null, null, null);",
+            "await __async_ctx1_0[Symbol.asyncDispose]();",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+        // The lock bound last is released first
+        assert!(js.find("__async_ctx1_1.__aexit__") < js.find("__async_ctx1_0.__aexit__"));
+
+        let es5 = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        let error = es5.compile_string(source, None).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("async for loops are not supported by the es5 target"));
+    }
+
+    #[test]
+    fn test_variadic_parameters() {
+        let source = r#"
+def total(first, *rest):
+    return first
+
+def both(a, *args, **kwargs):
+    return kwargs
+
+total(1, 2, 3)
+both(1, 2, flag=true)
+both(1, 2)
+"#;
+        let js = Compiler::new()
+            .compile_string(source, None)
+            .unwrap()
+            .js_code;
+        for expected in [
+            "function total(first, ...rest) {",
+            "function both(a, kwargs = {}, ...args) {",
+            "total(1, 2, 3)",
+            "both(1, {\"flag\": true}, 2)",
+            "both(1, undefined, 2)",
+        ] {
+            assert!(js.contains(expected), "missing `{expected}` in:\n{js}");
+        }
+
+        let es5 = Compiler::with_config(CompilerConfigBuilder::new().target("es5").build());
+        let js = es5.compile_string(source, None).unwrap().js_code;
+        assert!(js.contains("var args = Array.prototype.slice.call(arguments, 2);"));
+
+        let bytecode = Compiler::new()
+            .compile_to_bytecode("def f(*args):\n    return args\nprint(f(1, 2))\n")
+            .unwrap();
+        assert!(bytecode.windows(5).any(|bytes| bytes == b"*args"));
+    }
+
+    #[test]
+    fn test_compile_to_bytecode() {
+        let compiler = Compiler::with_config(
+            CompilerConfigBuilder::new()
+                .define("LIMIT", ast::Literal::Int(3))
+                .build(),
+        );
+        let code = compiler
+            .compile_to_bytecode("total = LIMIT * 2\nprint(total)\n")
+            .unwrap();
+        assert!(code.starts_with(b"NAG\x00"));
+        // Constants are substituted before the bytecode is generated
+        assert!(!code.windows(5).any(|name| name == b"LIMIT"));
+
+        assert!(compiler.compile_to_bytecode("total = (").is_err());
+        // Constructs the VM cannot run yet are rejected rather than written out
+        let err = compiler
+            .compile_to_bytecode("def double(x = 1):\n    return x * 2\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("default"), "{err}");
+        assert!(compiler
+            .compile_to_bytecode("def double(x): return x * 2\nprint(double(21))\n")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_limits() {
+        let compile = |limits: Limits, source: &str| {
+            let config = CompilerConfigBuilder::new().limits(limits).build();
+            Compiler::with_config(config).compile_string(source, None)
+        };
+
+        let deep = Limits {
+            max_depth: 10,
+            ..Limits::default()
+        };
+        let error =
+            compile(deep, &format!("x = {}1{}", "[".repeat(20), "]".repeat(20))).unwrap_err();
+        assert_eq!(error.code(), "E0009");
+        assert!(error.to_string().contains("limit of 10"));
+
+        let small = Limits {
+            max_source_size: 8,
+            ..Limits::default()
+        };
+        assert_eq!(compile(small, "x = 1 + 2").unwrap_err().code(), "E0009");
+
+        let tiny_output = Limits {
+            max_output_size: 16,
+            ..Limits::default()
+        };
+        let error = compile(tiny_output, "x = 1").unwrap_err();
+        assert!(matches!(error, NagariError::LimitExceeded(_)));
+        let compiler =
+            Compiler::with_config(CompilerConfigBuilder::new().limits(tiny_output).build());
+        assert!(compiler.compile_to_bytecode("x = 1").is_err());
+
+        assert!(compile(Limits::default(), "x = [[[1]]]").is_ok());
+    }
+
+    #[test]
+    fn test_type_checker() {
+        let compile = |strict: bool, source: &str| {
+            let config = CompilerConfigBuilder::new().strict_types(strict).build();
+            Compiler::with_config(config).compile_string(source, None)
+        };
+        let source = r#"
+def area(width: float, height: float) -> float:
+    return width * height
+
+def label(count: int) -> str:
+    return count
+
+size = "12"
+area(3, 4.5)
+area(size, height=2)
+"#;
+
+        let result = compile(false, source).unwrap();
+        let messages: Vec<&str> = result.warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "label() returns int but is declared to return str",
+                "argument 'width' of area() expects float, got str",
+            ]
+        );
+        assert!(result.warnings.iter().all(|w| w.category == "type-mismatch"));
+
+        let error = compile(true, source).unwrap_err();
+        assert_eq!(error.code(), "E0003");
+        assert!(error.to_string().contains("expects float, got str"));
+
+        // Locals only keep a type every branch agrees on, and unannotated code is never checked
+        let branches = r#"
+def double(n: int) -> int:
+    return n * 2
+
+value = 1
+if flag:
+    value = "one"
+double(value)
+total = 0
+for item in [1, 2, 3]:
+    total = total + item
+double(total)
+double(undeclared)
+"#;
+        assert!(compile(true, branches).is_ok());
+        let reassigned = "def repeat(count: int):\n    count = \"two\"\n";
+        assert!(compile(true, reassigned).is_err());
+    }
+
+    #[test]
+    fn test_compiler_warnings() {
+        let source = "var legacy = 1\n\ndef outer(items: list, scale):\n    total = 0\n    def add(n):\n        total = n\n        return total\n    return scale\n    print(add(1))\n";
+        let result = Compiler::new().compile_string(source, None).unwrap();
+        let found: Vec<_> = result
+            .warnings
+            .iter()
+            .map(|w| (w.category.as_str(), w.line, w.column))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("deprecated-syntax", Some(1), Some(1)),
+                ("implicit-any", Some(3), Some(1)),
+                ("unused-variables", Some(4), Some(5)),
+                ("shadowing", Some(6), Some(9)),
+                ("unreachable-code", Some(9), Some(5)),
+            ]
+        );
+        assert_eq!(
+            result.warnings[3].message,
+            "'total' in add() shadows 'total' in outer()"
+        );
+
+        let denied = CompilerConfigBuilder::new()
+            .warning_level("unused-variables", WarningLevel::Deny)
+            .build();
+        assert!(Compiler::with_config(denied)
+            .compile_string(source, None)
+            .is_err());
+
+        let clean = "def add(a: int, b: int) -> int:\n    _scratch = a\n    return a + b\n";
+        let result = Compiler::new().compile_string(clean, None).unwrap();
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    }
 }
@@ -0,0 +1,52 @@
+//! Limits on what the compiler takes and produces, so that a service compiling untrusted code,
+//! like a playground or a registry checking uploads, can't be made to exhaust its memory or
+//! stack. Exceeding one fails the compilation with [`NagariError::LimitExceeded`].
+//!
+//! The source size and nesting depth are checked while parsing, see [`nagari_parser::limits`];
+//! the output size once the JavaScript or bytecode is generated.
+
+use crate::error::NagariError;
+
+/// Largest input and output the compiler handles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Bytes of source per file
+    pub max_source_size: usize,
+    /// Nesting of statements and expressions
+    pub max_depth: usize,
+    /// Bytes of JavaScript or bytecode per file
+    pub max_output_size: usize,
+}
+
+impl Limits {
+    pub const DEFAULT_MAX_OUTPUT_SIZE: usize = 64 * 1024 * 1024;
+
+    /// The limits the parser checks
+    pub fn parser(&self) -> nagari_parser::Limits {
+        nagari_parser::Limits {
+            max_source_size: self.max_source_size,
+            max_depth: self.max_depth,
+        }
+    }
+
+    /// Fail if the generated `what` is larger than allowed
+    pub fn check_output(&self, what: &str, size: usize) -> Result<(), NagariError> {
+        if size > self.max_output_size {
+            return Err(NagariError::LimitExceeded(format!(
+                "{what} is {size} bytes, more than the limit of {}",
+                self.max_output_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_source_size: nagari_parser::Limits::DEFAULT_MAX_SOURCE_SIZE,
+            max_depth: nagari_parser::Limits::DEFAULT_MAX_DEPTH,
+            max_output_size: Self::DEFAULT_MAX_OUTPUT_SIZE,
+        }
+    }
+}
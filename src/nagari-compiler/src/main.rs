@@ -8,9 +8,16 @@ use std::path::Path;
 use std::process::Command;
 
 mod ast;
+mod bytecode;
+mod declarations;
+mod defines;
+mod embed;
+mod environment;
 mod error;
 mod lexer;
 mod parser;
+mod portable;
+mod runtime_package;
 mod transpiler;
 mod types;
 
@@ -23,6 +30,19 @@ use error::NagariError;
 use nagari_parser;
 use nagari_parser::{parse as external_parse, ParseError, Parser as ExternalParser};
 
+/// `source` parsed with the enhanced parser and converted to the internal AST, as the passes
+/// tested on it see it
+#[cfg(test)]
+fn test_program(source: &str) -> ast::Program {
+    let tokens = nagari_parser::Lexer::new(source)
+        .tokenize()
+        .expect("test source should lex");
+    let external_ast = ExternalParser::new(tokens)
+        .parse_program()
+        .expect("test source should parse");
+    convert_external_ast_to_internal(external_ast).expect("test source should convert")
+}
+
 // AST conversion function to translate between external and internal AST types
 fn convert_external_ast_to_internal(
     external_ast: nagari_parser::Program,
@@ -44,32 +64,12 @@ fn convert_statement(
     use nagari_parser::Statement as ExtStmt;
 
     match external_stmt {
-        ExtStmt::Expression(expr) => {
-            // Check if this expression is actually an assignment that should be a statement
-            match &expr {
-                nagari_parser::Expression::Assignment {
-                    left,
-                    operator,
-                    right,
-                } => {
-                    // Convert assignment expressions to assignment statements
-                    match left.as_ref() {
-                        nagari_parser::Expression::Identifier(name) => {
-                            Ok(IntStmt::Assignment(ast::Assignment {
-                                name: name.clone(),
-                                var_type: None,
-                                value: convert_expression(right.as_ref().clone())?,
-                            }))
-                        }
-                        _ => {
-                            // For complex assignments, fall back to expression
-                            Ok(IntStmt::Expression(convert_expression(expr)?))
-                        }
-                    }
-                }
-                _ => Ok(IntStmt::Expression(convert_expression(expr)?)),
-            }
-        }
+        ExtStmt::Expression(nagari_parser::Expression::Assignment {
+            left,
+            operator,
+            right,
+        }) => convert_assignment(*left, &operator, *right),
+        ExtStmt::Expression(expr) => Ok(IntStmt::Expression(convert_expression(expr)?)),
         ExtStmt::Let { name, value } => Ok(IntStmt::Assignment(ast::Assignment {
             name,
             var_type: None,
@@ -86,19 +86,23 @@ fn convert_statement(
             body,
             is_async,
             return_type,
+            decorators,
         } => Ok(IntStmt::FunctionDef(ast::FunctionDef {
             name,
             parameters: parameters
                 .into_iter()
                 .map(|p| convert_function_parameter(p))
                 .collect::<Result<Vec<_>, _>>()?,
-            return_type: return_type.map(|t| convert_type_string_to_type(t)),
+            return_type: return_type.as_ref().map(types::Type::from_annotation),
             body: body
                 .into_iter()
                 .map(|s| convert_statement(s))
                 .collect::<Result<Vec<_>, _>>()?,
             is_async,
-            decorators: Vec::new(),
+            decorators: decorators
+                .into_iter()
+                .map(convert_decorator)
+                .collect::<Result<Vec<_>, _>>()?,
             is_generator: false,
         })),
         ExtStmt::Return(expr) => Ok(IntStmt::Return(
@@ -135,6 +139,7 @@ fn convert_statement(
             variable,
             iterable,
             body,
+            is_async,
         } => Ok(IntStmt::For(ast::ForLoop {
             variable,
             iterable: convert_expression(iterable)?,
@@ -142,11 +147,40 @@ fn convert_statement(
                 .into_iter()
                 .map(|s| convert_statement(s))
                 .collect::<Result<Vec<_>, _>>()?,
+            is_async,
+        })),
+        ExtStmt::With {
+            items,
+            body,
+            is_async,
+        } => Ok(IntStmt::With(ast::WithStatement {
+            items: items
+                .into_iter()
+                .map(|item| {
+                    Ok(ast::WithItem {
+                        context_expr: convert_expression(item.context)?,
+                        optional_vars: item.alias,
+                    })
+                })
+                .collect::<Result<Vec<_>, NagariError>>()?,
+            body: body
+                .into_iter()
+                .map(convert_statement)
+                .collect::<Result<Vec<_>, _>>()?,
+            is_async,
+        })),
+        ExtStmt::Match { subject, cases } => Ok(IntStmt::Match(ast::MatchStatement {
+            expression: convert_expression(subject)?,
+            cases: cases
+                .into_iter()
+                .map(convert_match_case)
+                .collect::<Result<Vec<_>, _>>()?,
         })),
         ExtStmt::Class {
             name,
             superclass,
             methods,
+            decorators,
         } => Ok(IntStmt::ClassDef(ast::ClassDef {
             name,
             superclass,
@@ -154,6 +188,10 @@ fn convert_statement(
                 .into_iter()
                 .map(|s| convert_statement(s))
                 .collect::<Result<Vec<_>, _>>()?,
+            decorators: decorators
+                .into_iter()
+                .map(convert_decorator)
+                .collect::<Result<Vec<_>, _>>()?,
         })),
         ExtStmt::Import { source, items } => Ok(IntStmt::Import(ast::ImportStatement {
             module: source,
@@ -182,6 +220,81 @@ fn convert_statement(
     }
 }
 
+/// The statement assigning `right` to `left`: a name, an attribute, an index or a list of names
+/// to unpack into. Compound operators desugar to `target = target <op> value`.
+fn convert_assignment(
+    left: nagari_parser::Expression,
+    operator: &nagari_parser::AssignmentOperator,
+    right: nagari_parser::Expression,
+) -> Result<ast::Statement, NagariError> {
+    use ast::Expression as IntExpr;
+
+    let target = convert_expression(left)?;
+    let mut value = convert_expression(right)?;
+    if let Some(operator) = compound_assignment_operator(operator) {
+        if matches!(target, IntExpr::List(_)) {
+            return Err(NagariError::ParseError(
+                "Compound assignment cannot unpack into several targets".to_string(),
+            ));
+        }
+        value = IntExpr::Binary(ast::BinaryExpression {
+            left: Box::new(target.clone()),
+            operator,
+            right: Box::new(value),
+        });
+    }
+
+    match target {
+        IntExpr::Identifier(name) => Ok(ast::Statement::Assignment(ast::Assignment {
+            name,
+            var_type: None,
+            value,
+        })),
+        IntExpr::Attribute(attribute) => Ok(ast::Statement::AttributeAssignment(
+            ast::AttributeAssignment {
+                object: *attribute.object,
+                attribute: attribute.attribute,
+                value,
+            },
+        )),
+        IntExpr::Index(index) => Ok(ast::Statement::IndexAssignment(ast::IndexAssignment {
+            object: *index.object,
+            index: *index.index,
+            value,
+        })),
+        IntExpr::List(elements) => {
+            let targets = elements
+                .into_iter()
+                .map(|element| match element {
+                    IntExpr::Identifier(name) => Ok(name),
+                    _ => Err(NagariError::ParseError(
+                        "Invalid tuple unpacking target".to_string(),
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ast::Statement::TupleAssignment(ast::TupleAssignment {
+                targets,
+                value,
+            }))
+        }
+        _ => Err(NagariError::ParseError(
+            "Invalid assignment target".to_string(),
+        )),
+    }
+}
+
+fn compound_assignment_operator(
+    operator: &nagari_parser::AssignmentOperator,
+) -> Option<ast::BinaryOperator> {
+    match operator {
+        nagari_parser::AssignmentOperator::Assign => None,
+        nagari_parser::AssignmentOperator::AddAssign => Some(ast::BinaryOperator::Add),
+        nagari_parser::AssignmentOperator::SubtractAssign => Some(ast::BinaryOperator::Subtract),
+        nagari_parser::AssignmentOperator::MultiplyAssign => Some(ast::BinaryOperator::Multiply),
+        nagari_parser::AssignmentOperator::DivideAssign => Some(ast::BinaryOperator::Divide),
+    }
+}
+
 fn convert_expression(
     external_expr: nagari_parser::Expression,
 ) -> Result<ast::Expression, NagariError> {
@@ -207,16 +320,18 @@ fn convert_expression(
         ExtExpr::Call {
             function,
             arguments,
-        } => {
-            Ok(IntExpr::Call(ast::CallExpression {
-                function: Box::new(convert_expression(*function)?),
-                arguments: arguments
-                    .into_iter()
-                    .map(|a| convert_expression(a))
-                    .collect::<Result<Vec<_>, _>>()?,
-                keyword_args: Vec::new(), // External parser doesn't support keyword args yet
-            }))
-        }
+            keywords,
+        } => Ok(IntExpr::Call(ast::CallExpression {
+            function: Box::new(convert_expression(*function)?),
+            arguments: arguments
+                .into_iter()
+                .map(|a| convert_expression(a))
+                .collect::<Result<Vec<_>, _>>()?,
+            keyword_args: keywords
+                .into_iter()
+                .map(convert_keyword_argument)
+                .collect::<Result<Vec<_>, _>>()?,
+        })),
         ExtExpr::Member {
             object,
             property,
@@ -240,6 +355,12 @@ fn convert_expression(
                 .map(|e| convert_expression(e))
                 .collect::<Result<Vec<_>, _>>()?,
         )),
+        ExtExpr::Set(elements) => Ok(IntExpr::Set(
+            elements
+                .into_iter()
+                .map(convert_expression)
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
         ExtExpr::Object(properties) => Ok(IntExpr::Dict(
             properties
                 .into_iter()
@@ -287,15 +408,19 @@ fn convert_expression(
                         });
                     }
 
-                    // For async arrows, use function expression
-                    if is_async {
+                    // Async arrows, and arrows taking *args or **kwargs, become function
+                    // expressions
+                    let variadic = parameters
+                        .iter()
+                        .any(|p| p.kind != nagari_parser::ParameterKind::Positional);
+                    if is_async || variadic {
                         Ok(IntExpr::FunctionExpr(ast::FunctionExpr {
                             parameters: parameters
                                 .into_iter()
                                 .map(|p| convert_function_parameter(p))
                                 .collect::<Result<Vec<_>, _>>()?,
                             body: vec![ast::Statement::Return(Some(lambda_body))],
-                            is_async: true,
+                            is_async,
                             is_generator: false,
                         }))
                     } else {
@@ -344,14 +469,9 @@ fn convert_expression(
                         keyword_args: Vec::new(),
                     }))
                 }
-                _ => {
-                    // For complex left-hand sides, convert as binary expression for now
-                    Ok(IntExpr::Binary(ast::BinaryExpression {
-                        left: Box::new(convert_expression(*left)?),
-                        operator: ast::BinaryOperator::Equal, // Use Equal as fallback
-                        right: Box::new(convert_expression(*right)?),
-                    }))
-                }
+                _ => Err(NagariError::ParseError(
+                    "Only names can be assigned inside an expression".to_string(),
+                )),
             }
         }
         ExtExpr::Conditional {
@@ -372,30 +492,145 @@ fn convert_expression(
                     .collect::<Result<Vec<_>, _>>()?,
             }))
         }
+        ExtExpr::InlineJs(code) => Ok(IntExpr::InlineJs(code)),
+        ExtExpr::Await(argument) => Ok(IntExpr::Await(Box::new(convert_expression(*argument)?))),
         ExtExpr::Index { object, index } => Ok(IntExpr::Index(ast::IndexAccess {
             object: Box::new(convert_expression(*object)?),
             index: Box::new(convert_expression(*index)?),
         })),
+        ExtExpr::Slice {
+            object,
+            start,
+            end,
+            step,
+        } => {
+            let convert_bound = |bound: Option<Box<nagari_parser::Expression>>| {
+                bound
+                    .map(|b| convert_expression(*b).map(Box::new))
+                    .transpose()
+            };
+            Ok(IntExpr::Slice(ast::SliceExpression {
+                object: Box::new(convert_expression(*object)?),
+                start: convert_bound(start)?,
+                end: convert_bound(end)?,
+                step: convert_bound(step)?,
+            }))
+        }
+        ExtExpr::ListComprehension {
+            element,
+            generators,
+        } => Ok(IntExpr::ListComprehension(ast::ListComprehension {
+            element: Box::new(convert_expression(*element)?),
+            generators: convert_generators(generators)?,
+        })),
+        ExtExpr::DictComprehension {
+            key,
+            value,
+            generators,
+        } => Ok(IntExpr::DictComprehension(ast::DictComprehension {
+            key: Box::new(convert_expression(*key)?),
+            value: Box::new(convert_expression(*value)?),
+            generators: convert_generators(generators)?,
+        })),
+        ExtExpr::SetComprehension {
+            element,
+            generators,
+        } => Ok(IntExpr::SetComprehension(ast::SetComprehension {
+            element: Box::new(convert_expression(*element)?),
+            generators: convert_generators(generators)?,
+        })),
+        ExtExpr::Generator {
+            element,
+            generators,
+        } => Ok(IntExpr::Generator(ast::GeneratorExpression {
+            element: Box::new(convert_expression(*element)?),
+            generators: convert_generators(generators)?,
+        })),
     }
 }
 
+fn convert_generators(
+    generators: Vec<nagari_parser::ComprehensionGenerator>,
+) -> Result<Vec<ast::ComprehensionGenerator>, NagariError> {
+    generators
+        .into_iter()
+        .map(|generator| {
+            Ok(ast::ComprehensionGenerator {
+                target: generator.target,
+                iter: convert_expression(generator.iter)?,
+                conditions: generator
+                    .conditions
+                    .into_iter()
+                    .map(convert_expression)
+                    .collect::<Result<Vec<_>, _>>()?,
+            })
+        })
+        .collect()
+}
+
 fn convert_literal_to_expression(
     external_lit: nagari_parser::Literal,
 ) -> Result<ast::Expression, NagariError> {
-    use ast::Expression as IntExpr;
+    Ok(ast::Expression::Literal(convert_literal(external_lit)))
+}
+
+fn convert_literal(external_lit: nagari_parser::Literal) -> ast::Literal {
     use nagari_parser::Literal as ExtLit;
 
     match external_lit {
-        ExtLit::String(s) => Ok(IntExpr::Literal(ast::Literal::String(s))),
+        ExtLit::String(s) => ast::Literal::String(s),
         ExtLit::Number(n) => {
             if n.fract() == 0.0 {
-                Ok(IntExpr::Literal(ast::Literal::Int(n as i64)))
+                ast::Literal::Int(n as i64)
             } else {
-                Ok(IntExpr::Literal(ast::Literal::Float(n)))
+                ast::Literal::Float(n)
             }
         }
-        ExtLit::Boolean(b) => Ok(IntExpr::Literal(ast::Literal::Bool(b))),
-        ExtLit::Null => Ok(IntExpr::Literal(ast::Literal::None)),
+        ExtLit::Boolean(b) => ast::Literal::Bool(b),
+        ExtLit::Null => ast::Literal::None,
+    }
+}
+
+/// A `case` pattern, wrapped in a guard when the case has one
+fn convert_match_case(case: nagari_parser::MatchCase) -> Result<ast::MatchCase, NagariError> {
+    let pattern = convert_pattern(case.pattern);
+    Ok(ast::MatchCase {
+        pattern: match case.guard {
+            Some(guard) => ast::Pattern::Guard(Box::new(pattern), convert_expression(guard)?),
+            None => pattern,
+        },
+        body: case
+            .body
+            .into_iter()
+            .map(convert_statement)
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
+fn convert_pattern(external_pattern: nagari_parser::Pattern) -> ast::Pattern {
+    use ast::Pattern as IntPat;
+    use nagari_parser::Pattern as ExtPat;
+
+    match external_pattern {
+        ExtPat::Literal(lit) => IntPat::Literal(convert_literal(lit)),
+        ExtPat::Capture(name) => IntPat::Identifier(name),
+        ExtPat::Wildcard => IntPat::Wildcard,
+        ExtPat::Tuple(items) => IntPat::Tuple(items.into_iter().map(convert_pattern).collect()),
+        ExtPat::List(items) => IntPat::List(items.into_iter().map(convert_pattern).collect()),
+        ExtPat::Dict(entries) => IntPat::Dict(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    (
+                        IntPat::Literal(convert_literal(key)),
+                        convert_pattern(value),
+                    )
+                })
+                .collect(),
+        ),
+        ExtPat::Class { name, arguments } => {
+            IntPat::Constructor(name, arguments.into_iter().map(convert_pattern).collect())
+        }
     }
 }
 
@@ -419,13 +654,12 @@ fn convert_binary_operator(
         ExtOp::GreaterEqual => Ok(IntOp::GreaterEqual),
         ExtOp::And => Ok(IntOp::And),
         ExtOp::Or => Ok(IntOp::Or),
-        // Handle additional operators that don't have direct mappings
-        ExtOp::Power => Ok(IntOp::Multiply), // Simplified mapping
-        ExtOp::BitwiseAnd => Ok(IntOp::And), // Simplified mapping
-        ExtOp::BitwiseOr => Ok(IntOp::Or),   // Simplified mapping
-        ExtOp::BitwiseXor => Ok(IntOp::NotEqual), // Simplified mapping
-        ExtOp::LeftShift => Ok(IntOp::Multiply), // Simplified mapping
-        ExtOp::RightShift => Ok(IntOp::Divide), // Simplified mapping
+        ExtOp::Power => Ok(IntOp::Power),
+        ExtOp::BitwiseAnd => Ok(IntOp::BitwiseAnd),
+        ExtOp::BitwiseOr => Ok(IntOp::BitwiseOr),
+        ExtOp::BitwiseXor => Ok(IntOp::BitwiseXor),
+        ExtOp::LeftShift => Ok(IntOp::LeftShift),
+        ExtOp::RightShift => Ok(IntOp::RightShift),
     }
 }
 
@@ -450,11 +684,48 @@ fn convert_function_parameter(
         name: external_param.name,
         param_type: external_param
             .type_annotation
-            .map(|t| convert_type_string_to_type(t)),
+            .as_ref()
+            .map(types::Type::from_annotation),
         default_value: external_param
             .default_value
             .map(|v| convert_expression(v))
             .transpose()?,
+        kind: match external_param.kind {
+            nagari_parser::ParameterKind::Positional => ast::ParameterKind::Positional,
+            nagari_parser::ParameterKind::Rest => ast::ParameterKind::Rest,
+            nagari_parser::ParameterKind::Keywords => ast::ParameterKind::Keywords,
+        },
+    })
+}
+
+/// `name=value` as a named value, and `**mapping` as a spread without a name
+fn convert_keyword_argument(
+    keyword: nagari_parser::KeywordArgument,
+) -> Result<(String, ast::Expression), NagariError> {
+    let value = convert_expression(keyword.value)?;
+    Ok(match keyword.name {
+        Some(name) => (name, value),
+        None => (String::new(), ast::Expression::Spread(Box::new(value))),
+    })
+}
+
+fn convert_decorator(decorator: nagari_parser::Decorator) -> Result<ast::Decorator, NagariError> {
+    Ok(ast::Decorator {
+        name: decorator.name,
+        arguments: decorator
+            .arguments
+            .map(|arguments| {
+                arguments
+                    .into_iter()
+                    .map(convert_expression)
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?,
+        keyword_args: decorator
+            .keywords
+            .into_iter()
+            .map(convert_keyword_argument)
+            .collect::<Result<Vec<_>, _>>()?,
     })
 }
 
@@ -463,39 +734,39 @@ fn convert_import_item(external_item: nagari_parser::ImportItem) -> Result<Strin
     Ok(external_item.name)
 }
 
-fn convert_type_string_to_type(type_str: String) -> Type {
-    match type_str.as_str() {
-        "string" => Type::Str,
-        "number" => Type::Float,
-        "boolean" => Type::Bool,
-        "any" => Type::Any,
-        _ => Type::Any, // Default fallback
-    }
-}
-
 #[derive(Parser)]
 #[command(name = "nagc")]
-#[command(about = "Nagari compiler - transpiles .nag files to JavaScript")]
+#[command(about = "Nagari compiler - transpiles .nag files to JavaScript or VM bytecode")]
 #[command(version = "0.1.0")]
 struct Cli {
     /// Input file (.nag)
     input: String,
 
-    /// Output file (.js) - optional
+    /// Output file (.js, or .nac for bytecode) - optional
     #[arg(short, long)]
     output: Option<String>,
 
-    /// Target JavaScript format
-    #[arg(long, default_value = "es6", value_parser = ["es6", "node", "esm", "cjs"])]
+    /// Target JavaScript format, or `bytecode` for a .nac file that nagrun executes
+    #[arg(long, default_value = "es6", value_parser = ["es6", "es5", "node", "esm", "cjs", "bytecode"])]
     target: String,
 
     /// Enable JSX support for React compatibility
     #[arg(long)]
     jsx: bool,
 
-    /// Bundle output with dependencies
+    /// Build JSX for server-side rendering with the runtime's render_to_string instead of
+    /// React; needs --jsx and the node target
     #[arg(long)]
-    bundle: bool,
+    ssr: bool,
+
+    /// Bundle output with dependencies: `rollup` (the default) runs `npx rollup` on it,
+    /// `native` bundles the imported modules without Node
+    #[arg(long, value_name = "BUNDLER", num_args = 0..=1, default_missing_value = "rollup", value_parser = ["rollup", "native"])]
+    bundle: Option<String>,
+
+    /// Format of bundles: `iife` for a script, `esm` for an ES module
+    #[arg(long, default_value = "iife")]
+    bundle_format: nagari_compiler::BundleFormat,
 
     /// Generate source maps for debugging
     #[arg(long)]
@@ -528,6 +799,19 @@ struct Cli {
     /// Generate TypeScript declarations
     #[arg(long)]
     declarations: bool,
+
+    /// Put JSDoc comments with the types from annotations above generated functions
+    #[arg(long)]
+    jsdoc: bool,
+
+    /// Define a compile-time constant (NAME=VALUE); `__DEV__` and `__TARGET__` are predefined
+    #[arg(long = "define", value_name = "NAME=VALUE")]
+    defines: Vec<String>,
+
+    /// Environment the output runs on (modern-browser, node18, node20, legacy); only the
+    /// polyfills it needs are emitted
+    #[arg(long)]
+    environment: Option<environment::Environment>,
 }
 
 fn main() {
@@ -540,8 +824,11 @@ fn main() {
         if cli.jsx {
             println!("⚛️  JSX: enabled");
         }
-        if cli.bundle {
-            println!("📦 Bundle: enabled");
+        if cli.ssr {
+            println!("🖥️  SSR: enabled");
+        }
+        if let Some(bundler) = &cli.bundle {
+            println!("📦 Bundle: {} ({})", bundler, cli.bundle_format);
         }
         if cli.devtools {
             println!("🔧 DevTools: enabled");
@@ -564,7 +851,8 @@ fn main() {
                 std::process::exit(0);
             }
             Err(e) => {
-                eprintln!("❌ Syntax error: {}", e);
+                eprintln!("❌ Syntax error [{}]: {}", e.code(), e);
+                eprintln!("   Run `nag explain {}` for more information", e.code());
                 std::process::exit(1);
             }
         }
@@ -577,20 +865,21 @@ fn main() {
             }
 
             // Post-processing steps
-            if cli.bundle {
+            if cli.bundle.is_some() {
                 if let Err(e) = bundle_output(&output_path, &cli) {
                     eprintln!("⚠️  Bundle failed: {}", e);
                 }
             }
 
             if cli.minify {
-                if let Err(e) = minify_output(&output_path) {
+                if let Err(e) = minify_output(&output_path, &cli) {
                     eprintln!("⚠️  Minification failed: {}", e);
                 }
             }
         }
         Err(e) => {
-            eprintln!("❌ Compilation failed: {}", e);
+            eprintln!("❌ Compilation failed [{}]: {}", e.code(), e);
+            eprintln!("   Run `nag explain {}` for more information", e.code());
             std::process::exit(1);
         }
     }
@@ -606,7 +895,111 @@ fn compile_file(cli: &Cli) -> Result<String, NagariError> {
     }
 
     // Use the enhanced external parser with dual syntax support
-    let external_ast = nagari_parser::parse(&input_content).map_err(|e| match e {
+    let limits = nagari_parser::Limits::default();
+    limits
+        .check_source(&input_content)
+        .map_err(convert_parse_error)?;
+    let tokens = nagari_parser::Lexer::new(&input_content)
+        .tokenize()
+        .map_err(convert_parse_error)?;
+    let mut parser = nagari_parser::Parser::new(tokens).with_limits(&limits);
+    let external_ast = parser.parse_program().map_err(convert_parse_error)?;
+
+    if cli.verbose {
+        println!("✅ Enhanced parsing completed successfully");
+    }
+
+    // Report warnings, except those suppressed by `# nagari:ignore` comments
+    let mut diagnostics =
+        nagari_compiler::Diagnostics::parsed(parser.statement_spans(), parser.deprecations());
+    diagnostics.analyze(&external_ast);
+    let warnings = nagari_compiler::warnings::enforce(
+        &Default::default(),
+        false,
+        &input_content,
+        diagnostics.into_warnings(),
+    )
+    .map_err(|e| NagariError::SemanticError(e.to_string()))?;
+    for warning in &warnings {
+        eprintln!("⚠️  {}: {}", cli.input, warning);
+    }
+
+    // Convert the external AST to the internal AST format for transpiler compatibility
+    let mut ast = convert_external_ast_to_internal(external_ast)?;
+
+    // Configure transpiler based on target
+    let mut target = cli.target.clone();
+    if cli.bundle.is_some() && target == "es6" {
+        target = "esm".to_string(); // Use ES modules for bundling
+    }
+
+    // Substitute compile-time constants and drop the branches they rule out
+    let mut constants = defines::builtins(&cli.target, !cli.minify);
+    for define in &cli.defines {
+        let (name, value) = define.split_once('=').ok_or_else(|| {
+            NagariError::SemanticError(format!("Invalid define '{}' (expected NAME=VALUE)", define))
+        })?;
+        constants.insert(name.trim().to_string(), defines::parse_value(value));
+    }
+    defines::apply(&mut ast, &constants);
+    embed::apply(&mut ast, Some(Path::new(&cli.input)), None)?;
+
+    if cli.target == "bytecode" {
+        return write_bytecode(cli, &ast);
+    }
+    if cli.ssr && !(cli.jsx && matches!(target.as_str(), "node" | "cjs")) {
+        return Err(NagariError::ConfigError(format!(
+            "server-side rendering needs JSX enabled and the node target, not '{}'",
+            target
+        )));
+    }
+
+    let js_code = transpiler::transpile(
+        &ast,
+        &target,
+        cli.jsx,
+        cli.ssr,
+        cli.environment,
+        runtime_package::DEFAULT_SPECIFIER,
+        cli.jsdoc,
+    )?;
+
+    let output_path = prepare_output_path(cli)?;
+
+    // Add source map comment if enabled
+    let final_code = if cli.sourcemap {
+        format!(
+            "{}\n//# sourceMappingURL={}.map",
+            js_code,
+            Path::new(&output_path)
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+        )
+    } else {
+        js_code
+    };
+
+    // Write output
+    fs::write(&output_path, final_code)
+        .map_err(|e| NagariError::IoError(format!("Failed to write output file: {}", e)))?;
+
+    // Generate source map if enabled
+    if cli.sourcemap {
+        generate_sourcemap(&cli.input, &output_path, &input_content)?;
+    }
+
+    // Generate TypeScript declarations if enabled
+    if cli.declarations {
+        generate_declarations(&output_path, &ast)?;
+    }
+
+    Ok(output_path)
+}
+
+fn convert_parse_error(e: nagari_parser::ParseError) -> NagariError {
+    match e {
         nagari_parser::ParseError::UnexpectedToken {
             token,
             line,
@@ -632,34 +1025,51 @@ fn compile_file(cli: &Cli) -> Result<String, NagariError> {
             "Syntax error at line {}, column {}: {}",
             line, column, message
         )),
+        nagari_parser::ParseError::SourceTooLarge { .. }
+        | nagari_parser::ParseError::TooDeep { .. } => NagariError::LimitExceeded(e.to_string()),
         _ => NagariError::ParseError(format!("Parser error: {}", e)),
-    })?;
-
-    if cli.verbose {
-        println!("✅ Enhanced parsing completed successfully");
     }
+}
 
-    // Convert the external AST to the internal AST format for transpiler compatibility
-    let ast = convert_external_ast_to_internal(external_ast)?;
-
-    // Configure transpiler based on target
-    let mut target = cli.target.clone();
-    if cli.bundle && target == "es6" {
-        target = "esm".to_string(); // Use ES modules for bundling
+/// Generate the bytecode of `ast` and write it to a .nac file
+fn write_bytecode(cli: &Cli, ast: &ast::Program) -> Result<String, NagariError> {
+    if cli.jsx
+        || cli.ssr
+        || cli.bundle.is_some()
+        || cli.sourcemap
+        || cli.minify
+        || cli.declarations
+        || cli.jsdoc
+    {
+        return Err(NagariError::ConfigError(
+            "--jsx, --ssr, --bundle, --sourcemap, --minify, --declarations and --jsdoc only apply to JavaScript targets"
+                .to_string(),
+        ));
     }
 
-    let js_code = transpiler::transpile(&ast, &target, cli.jsx)?;
+    let bytecode = bytecode::generate(ast)?;
+    let output_path = prepare_output_path(cli)?;
+    fs::write(&output_path, bytecode)
+        .map_err(|e| NagariError::IoError(format!("Failed to write output file: {}", e)))?;
+    Ok(output_path)
+}
 
-    // Determine output path
+/// Determine the output path and create its directory
+fn prepare_output_path(cli: &Cli) -> Result<String, NagariError> {
+    let extension = if cli.target == "bytecode" {
+        "nac"
+    } else {
+        "js"
+    };
     let output_path = if let Some(output) = &cli.output {
         output.clone()
     } else if let Some(outdir) = &cli.outdir {
         let input_path = Path::new(&cli.input);
         let filename = input_path.file_stem().unwrap().to_str().unwrap();
-        format!("{}/{}.js", outdir, filename)
+        format!("{}/{}.{}", outdir, filename, extension)
     } else {
         let input_path = Path::new(&cli.input);
-        let output_path = input_path.with_extension("js");
+        let output_path = input_path.with_extension(extension);
         output_path.to_string_lossy().to_string()
     };
 
@@ -669,36 +1079,6 @@ fn compile_file(cli: &Cli) -> Result<String, NagariError> {
             NagariError::IoError(format!("Failed to create output directory: {}", e))
         })?;
     }
-
-    // Add source map comment if enabled
-    let final_code = if cli.sourcemap {
-        format!(
-            "{}\n//# sourceMappingURL={}.map",
-            js_code,
-            Path::new(&output_path)
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-        )
-    } else {
-        js_code
-    };
-
-    // Write output
-    fs::write(&output_path, final_code)
-        .map_err(|e| NagariError::IoError(format!("Failed to write output file: {}", e)))?;
-
-    // Generate source map if enabled
-    if cli.sourcemap {
-        generate_sourcemap(&cli.input, &output_path, &input_content)?;
-    }
-
-    // Generate TypeScript declarations if enabled
-    if cli.declarations {
-        generate_declarations(&output_path, &ast)?;
-    }
-
     Ok(output_path)
 }
 
@@ -726,6 +1106,11 @@ fn watch_mode(cli: &Cli) {
     println!("👀 Watching {} for changes...", cli.input);
 
     let mut last_modified = get_file_modified_time(&cli.input).unwrap_or(0);
+    let mut incremental = incremental_compiler(cli);
+    if let Some(compiler) = incremental.as_mut() {
+        // Fill the cache, so the first change only re-emits what it touched
+        let _ = compile_incremental(compiler, cli);
+    }
 
     loop {
         thread::sleep(Duration::from_millis(500));
@@ -735,6 +1120,19 @@ fn watch_mode(cli: &Cli) {
                 last_modified = current_modified;
                 println!("🔄 File changed, recompiling...");
 
+                if let Some(compiler) = incremental.as_mut() {
+                    match compile_incremental(compiler, cli) {
+                        Ok((output_path, result)) => {
+                            println!("✅ Recompiled successfully: {}", output_path);
+                            print_incremental_summary(&result);
+                        }
+                        Err(e) => {
+                            eprintln!("❌ Compilation error: {}", e);
+                        }
+                    }
+                    continue;
+                }
+
                 match compile_file(cli) {
                     Ok(output_path) => {
                         println!("✅ Recompiled successfully: {}", output_path);
@@ -748,6 +1146,70 @@ fn watch_mode(cli: &Cli) {
     }
 }
 
+/// Compiler that re-transpiles only the changed top-level statements, for builds that don't
+/// need the whole program at once. Source maps, declarations, bundles and bytecode do.
+fn incremental_compiler(cli: &Cli) -> Option<nagari_compiler::IncrementalCompiler> {
+    if cli.sourcemap || cli.declarations || cli.bundle.is_some() || cli.target == "bytecode" {
+        return None;
+    }
+
+    Some(
+        nagari_compiler::IncrementalCompiler::new(nagari_compiler::Compiler::with_config(
+            library_config(cli)?.build(),
+        ))
+        .with_source_path(&cli.input),
+    )
+}
+
+/// Configuration of the library's compiler for the options given; None if a define is
+/// invalid, which a full compilation reports
+fn library_config(cli: &Cli) -> Option<nagari_compiler::CompilerConfigBuilder> {
+    let mut config = nagari_compiler::CompilerConfigBuilder::new()
+        .target(&cli.target)
+        .jsx(cli.jsx)
+        .ssr(cli.ssr)
+        .jsdoc(cli.jsdoc)
+        .minify(cli.minify)
+        .environment(
+            cli.environment
+                .and_then(|environment| environment.as_str().parse().ok()),
+        );
+    for define in &cli.defines {
+        let (name, value) = define.split_once('=')?;
+        config = config.define(name.trim(), nagari_compiler::defines::parse_value(value));
+    }
+    Some(config)
+}
+
+fn compile_incremental(
+    compiler: &mut nagari_compiler::IncrementalCompiler,
+    cli: &Cli,
+) -> Result<(String, nagari_compiler::IncrementalResult), String> {
+    let input_content =
+        fs::read_to_string(&cli.input).map_err(|e| format!("Failed to read input file: {}", e))?;
+    let result = compiler
+        .compile(&input_content)
+        .map_err(|e| e.to_string())?;
+
+    let output_path = prepare_output_path(cli).map_err(|e| e.to_string())?;
+    fs::write(&output_path, &result.js_code)
+        .map_err(|e| format!("Failed to write output file: {}", e))?;
+    Ok((output_path, result))
+}
+
+fn print_incremental_summary(result: &nagari_compiler::IncrementalResult) {
+    if !result.changed.is_empty() {
+        println!("   Updated: {}", result.changed.join(", "));
+    }
+    if !result.removed.is_empty() {
+        println!("   Removed: {}", result.removed.join(", "));
+    }
+    println!(
+        "   {} statement(s) re-emitted, {} reused",
+        result.emitted, result.reused
+    );
+}
+
 fn get_file_modified_time(path: &str) -> Result<u64, std::io::Error> {
     let metadata = fs::metadata(path)?;
     Ok(metadata
@@ -758,15 +1220,24 @@ fn get_file_modified_time(path: &str) -> Result<u64, std::io::Error> {
 }
 
 fn bundle_output(output_path: &str, cli: &Cli) -> Result<(), String> {
+    let bundled_path = output_path.replace(".js", ".bundle.js");
+    if cli.bundle.as_deref() == Some("native") {
+        return native_bundle(&bundled_path, cli);
+    }
+
     if cli.verbose {
         println!("📦 Bundling with rollup...");
     }
 
     // Use rollup for bundling
     let mut cmd = Command::new("npx");
-    cmd.args(["rollup", output_path, "-f", "iife", "-o"]);
-
-    let bundled_path = output_path.replace(".js", ".bundle.js");
+    cmd.args([
+        "rollup",
+        output_path,
+        "-f",
+        cli.bundle_format.as_str(),
+        "-o",
+    ]);
     cmd.arg(&bundled_path);
 
     let output = cmd
@@ -787,24 +1258,49 @@ fn bundle_output(output_path: &str, cli: &Cli) -> Result<(), String> {
     Ok(())
 }
 
-fn minify_output(output_path: &str) -> Result<(), String> {
-    // Use terser for minification
-    let mut cmd = Command::new("npx");
-    cmd.args(["terser", output_path, "-o"]);
+/// Bundle the input and the modules it imports with the compiler's own bundler
+fn native_bundle(bundled_path: &str, cli: &Cli) -> Result<(), String> {
+    if cli.verbose {
+        println!("📦 Bundling natively...");
+    }
 
-    let minified_path = output_path.replace(".js", ".min.js");
-    cmd.arg(&minified_path);
-    cmd.args(["-c", "-m"]);
+    let config = library_config(cli).ok_or("Invalid define (expected NAME=VALUE)")?;
+    let bundle = nagari_compiler::Compiler::with_config(config.build())
+        .bundle(&cli.input, cli.bundle_format)
+        .map_err(|e| e.to_string())?;
+    // The input's warnings were reported by its compilation
+    for (module, warning) in &bundle.warnings {
+        if Some(module) != bundle.modules.last() {
+            eprintln!("⚠️  {}: {}", module.display(), warning);
+        }
+    }
+    fs::write(bundled_path, bundle.js_code)
+        .map_err(|e| format!("Failed to write bundle: {}", e))?;
 
-    let output = cmd
-        .output()
-        .map_err(|e| format!("Failed to run terser: {}", e))?;
+    if cli.verbose {
+        println!(
+            "📦 Bundle created: {} ({} modules)",
+            bundled_path,
+            bundle.modules.len()
+        );
+    }
 
-    if !output.status.success() {
-        return Err(format!(
-            "Terser failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
+    Ok(())
+}
+
+/// Write the input minified by the compiler's own minifier next to the output, as `.min.js`
+fn minify_output(output_path: &str, cli: &Cli) -> Result<(), String> {
+    let config = library_config(cli).ok_or("Invalid define (expected NAME=VALUE)")?;
+    let result = nagari_compiler::Compiler::with_config(config.build())
+        .compile_file(&cli.input)
+        .map_err(|e| e.to_string())?;
+
+    let minified_path = output_path.replace(".js", ".min.js");
+    fs::write(&minified_path, result.js_code)
+        .map_err(|e| format!("Failed to write minified output: {}", e))?;
+
+    if cli.verbose {
+        println!("🗜️  Minified output: {}", minified_path);
     }
 
     Ok(())
@@ -840,9 +1336,8 @@ fn generate_sourcemap(
     Ok(())
 }
 
-fn generate_declarations(output_path: &str, _ast: &ast::Program) -> Result<(), NagariError> {
-    // Basic TypeScript declaration generation
-    let declarations = "// Generated TypeScript declarations\nexport {};\n";
+fn generate_declarations(output_path: &str, ast: &ast::Program) -> Result<(), NagariError> {
+    let declarations = declarations::generate(ast);
 
     let dts_path = output_path.replace(".js", ".d.ts");
     fs::write(&dts_path, declarations)
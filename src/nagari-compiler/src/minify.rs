@@ -0,0 +1,899 @@
+//! Minification of the JavaScript output, without an external minifier.
+//!
+//! With [`CompilerConfig::minify`](crate::CompilerConfig) set, a program is minified twice over.
+//! Before it is transpiled, [`program`] folds literal expressions to their value (`60 * 60` to
+//! `3600`, `"a" + "b"` to `"ab"`) and gives the local variables of its functions short names.
+//! After, [`compact`] drops the comments and whitespace of the output that JavaScript doesn't
+//! need; [`compact_mapped`] also maps the result back to the output it compacted, for the
+//! source map of the compilation to be composed with.
+//!
+//! Local variables are named `$a`, `$b`, ...: a Nagari identifier can't have a `$` in it, so
+//! these never clash with a name of the program. A function's names come after those of the
+//! functions nested in it, which may use its variables. Some locals keep their names:
+//! - parameters, which keyword arguments refer to them by
+//! - functions and classes defined in a function, which the transpiler knows calls to by name
+//! - names that are called, which may be builtins the transpiler maps
+//! - names an object destructuring takes properties by
+//!
+//! A function with inline JavaScript in it, which could use any of its names, keeps all of
+//! them.
+//!
+//! Folding leaves alone what JavaScript could compute differently or print back in a way that
+//! parses differently: integers past 2^53, NaN and infinities, and negative numbers, which
+//! would lose the parentheses `(-1) ** n` needs.
+
+use crate::ast::*;
+use crate::bundler::{rename_block, top_level_names};
+use crate::defines::{literals_equal, truthiness};
+use crate::sourcemap::{Original, Segment, SourceMap};
+use std::collections::{HashMap, HashSet};
+
+/// Largest integer JavaScript numbers hold exactly
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Keywords after which a `/` starts a regular expression rather than dividing
+const REGEX_KEYWORDS: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+/// Fold the literal expressions of `program` and give the local variables of its functions
+/// short names
+pub fn program(program: &mut Program) {
+    minify_block(&mut program.statements, &mut Scope::default());
+}
+
+/// What minifying some code found in it, which decides the names of the function it is in
+#[derive(Debug, Default)]
+struct Scope {
+    /// Whether there is inline JavaScript in it
+    inline_js: bool,
+    /// Names that have to be kept
+    pinned: HashSet<String>,
+    /// Number of short names the functions in it gave their variables
+    taken: usize,
+}
+
+impl Scope {
+    fn merge(&mut self, inner: Scope) {
+        self.inline_js |= inner.inline_js;
+        self.pinned.extend(inner.pinned);
+        self.taken = self.taken.max(inner.taken);
+    }
+}
+
+/// The `index`th short name: `$a` to `$Z`, then `$aa`, `$ba`, ...
+fn short_name(mut index: usize) -> String {
+    const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    let mut name = String::from("$");
+    loop {
+        name.push(LETTERS[index % LETTERS.len()] as char);
+        index /= LETTERS.len();
+        if index == 0 {
+            return name;
+        }
+        index -= 1;
+    }
+}
+
+fn minify_function(parameters: &mut [Parameter], body: &mut [Statement], outer: &mut Scope) {
+    for parameter in parameters.iter_mut() {
+        if let Some(default) = &mut parameter.default_value {
+            minify_expression(default, outer);
+        }
+    }
+
+    let mut scope = Scope::default();
+    minify_block(body, &mut scope);
+    if !scope.inline_js {
+        let mut seen = HashSet::new();
+        let locals: Vec<String> = top_level_names(body)
+            .into_iter()
+            .filter(|name| {
+                !scope.pinned.contains(name)
+                    && !parameters.iter().any(|parameter| &parameter.name == name)
+                    && seen.insert(name.clone())
+            })
+            .collect();
+        let renames: HashMap<String, String> = locals
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, short_name(scope.taken + i)))
+            .collect();
+        scope.taken += renames.len();
+        rename_block(body, &renames);
+    }
+    outer.merge(scope);
+}
+
+fn minify_block(statements: &mut [Statement], scope: &mut Scope) {
+    for statement in statements {
+        minify_statement(statement, scope);
+    }
+}
+
+fn minify_decorators(decorators: &mut [Decorator], scope: &mut Scope) {
+    for decorator in decorators {
+        for argument in decorator.arguments.iter_mut().flatten() {
+            minify_expression(argument, scope);
+        }
+        for (_, value) in &mut decorator.keyword_args {
+            minify_expression(value, scope);
+        }
+    }
+}
+
+fn minify_statement(statement: &mut Statement, scope: &mut Scope) {
+    match statement {
+        Statement::FunctionDef(function) => {
+            scope.pinned.insert(function.name.clone());
+            minify_decorators(&mut function.decorators, scope);
+            minify_function(&mut function.parameters, &mut function.body, scope);
+        }
+        Statement::ClassDef(class) => {
+            scope.pinned.insert(class.name.clone());
+            minify_decorators(&mut class.decorators, scope);
+            for member in &mut class.body {
+                match member {
+                    Statement::FunctionDef(method) => {
+                        minify_decorators(&mut method.decorators, scope);
+                        minify_function(&mut method.parameters, &mut method.body, scope);
+                    }
+                    member => minify_statement(member, scope),
+                }
+            }
+        }
+        Statement::Assignment(assignment) => minify_expression(&mut assignment.value, scope),
+        Statement::AttributeAssignment(assignment) => {
+            minify_expression(&mut assignment.object, scope);
+            minify_expression(&mut assignment.value, scope);
+        }
+        Statement::IndexAssignment(assignment) => {
+            minify_expression(&mut assignment.object, scope);
+            minify_expression(&mut assignment.index, scope);
+            minify_expression(&mut assignment.value, scope);
+        }
+        Statement::TupleAssignment(TupleAssignment { value, .. })
+        | Statement::ArrayDestructuringAssignment(ArrayDestructuringAssignment { value, .. }) => {
+            minify_expression(value, scope)
+        }
+        Statement::DestructuringAssignment(assignment) => {
+            // Object destructuring takes properties by the names it binds
+            if let Expression::Dict(properties) = &assignment.target {
+                scope
+                    .pinned
+                    .extend(properties.iter().filter_map(|(key, _)| match key {
+                        Expression::Identifier(name) => Some(name.clone()),
+                        _ => None,
+                    }));
+            }
+            minify_expression(&mut assignment.target, scope);
+            minify_expression(&mut assignment.value, scope);
+        }
+        Statement::If(if_stmt) => {
+            minify_expression(&mut if_stmt.condition, scope);
+            minify_block(&mut if_stmt.then_branch, scope);
+            for elif in &mut if_stmt.elif_branches {
+                minify_expression(&mut elif.condition, scope);
+                minify_block(&mut elif.body, scope);
+            }
+            if let Some(else_branch) = &mut if_stmt.else_branch {
+                minify_block(else_branch, scope);
+            }
+        }
+        Statement::While(while_loop) => {
+            minify_expression(&mut while_loop.condition, scope);
+            minify_block(&mut while_loop.body, scope);
+        }
+        Statement::For(for_loop) => {
+            minify_expression(&mut for_loop.iterable, scope);
+            minify_block(&mut for_loop.body, scope);
+        }
+        Statement::Match(match_stmt) => {
+            minify_expression(&mut match_stmt.expression, scope);
+            for case in &mut match_stmt.cases {
+                minify_block(&mut case.body, scope);
+            }
+        }
+        Statement::Return(Some(value)) | Statement::Expression(value) | Statement::Del(value) => {
+            minify_expression(value, scope)
+        }
+        Statement::With(with_stmt) => {
+            for item in &mut with_stmt.items {
+                minify_expression(&mut item.context_expr, scope);
+            }
+            minify_block(&mut with_stmt.body, scope);
+        }
+        Statement::Try(try_stmt) => {
+            minify_block(&mut try_stmt.body, scope);
+            for handler in &mut try_stmt.except_handlers {
+                minify_block(&mut handler.body, scope);
+            }
+            for block in [&mut try_stmt.else_clause, &mut try_stmt.finally_clause]
+                .into_iter()
+                .flatten()
+            {
+                minify_block(block, scope);
+            }
+        }
+        Statement::Raise(raise) => {
+            for value in [&mut raise.exception, &mut raise.cause]
+                .into_iter()
+                .flatten()
+            {
+                minify_expression(value, scope);
+            }
+        }
+        Statement::Yield(yield_stmt) => {
+            if let Some(value) = &mut yield_stmt.value {
+                minify_expression(value, scope);
+            }
+        }
+        Statement::YieldFrom(yield_from) => minify_expression(&mut yield_from.value, scope),
+        Statement::ExportDefault(export) => minify_expression(&mut export.value, scope),
+        Statement::ExportDeclaration(export) => minify_statement(&mut export.declaration, scope),
+        _ => {}
+    }
+}
+
+fn minify_generators(generators: &mut [ComprehensionGenerator], scope: &mut Scope) {
+    for generator in generators {
+        minify_expression(&mut generator.iter, scope);
+        for condition in &mut generator.conditions {
+            minify_expression(condition, scope);
+        }
+    }
+}
+
+fn minify_expression(expr: &mut Expression, scope: &mut Scope) {
+    match expr {
+        Expression::Binary(binary) => {
+            minify_expression(&mut binary.left, scope);
+            minify_expression(&mut binary.right, scope);
+            if let Some(value) = fold_binary(binary) {
+                *expr = value;
+            }
+        }
+        Expression::Unary(unary) => {
+            minify_expression(&mut unary.operand, scope);
+            if matches!(unary.operator, UnaryOperator::Not)
+                && matches!(*unary.operand, Expression::Literal(_))
+            {
+                if let Some(value) = truthiness(&unary.operand) {
+                    *expr = Expression::Literal(Literal::Bool(!value));
+                }
+            }
+        }
+        Expression::Call(call) => {
+            if let Expression::Identifier(name) = call.function.as_ref() {
+                scope.pinned.insert(name.clone());
+            }
+            minify_expression(&mut call.function, scope);
+            for argument in &mut call.arguments {
+                minify_expression(argument, scope);
+            }
+            for (_, value) in &mut call.keyword_args {
+                minify_expression(value, scope);
+            }
+        }
+        Expression::Await(inner) | Expression::Async(inner) | Expression::Spread(inner) => {
+            minify_expression(inner, scope)
+        }
+        Expression::List(items) | Expression::Tuple(items) | Expression::Set(items) => {
+            for item in items {
+                minify_expression(item, scope);
+            }
+        }
+        Expression::Dict(pairs) | Expression::Dictionary(pairs) => {
+            for (key, value) in pairs {
+                minify_expression(key, scope);
+                minify_expression(value, scope);
+            }
+        }
+        Expression::Lambda(lambda) => minify_expression(&mut lambda.body, scope),
+        Expression::ListComprehension(comp) => {
+            minify_expression(&mut comp.element, scope);
+            minify_generators(&mut comp.generators, scope);
+        }
+        Expression::SetComprehension(comp) => {
+            minify_expression(&mut comp.element, scope);
+            minify_generators(&mut comp.generators, scope);
+        }
+        Expression::DictComprehension(comp) => {
+            minify_expression(&mut comp.key, scope);
+            minify_expression(&mut comp.value, scope);
+            minify_generators(&mut comp.generators, scope);
+        }
+        Expression::Generator(gen) => {
+            minify_expression(&mut gen.element, scope);
+            minify_generators(&mut gen.generators, scope);
+        }
+        Expression::Ternary(ternary) => {
+            minify_expression(&mut ternary.condition, scope);
+            minify_expression(&mut ternary.true_expr, scope);
+            minify_expression(&mut ternary.false_expr, scope);
+        }
+        // A number can't be the object of an attribute without parentheses (`5.toString()`),
+        // so only the operands of an operation there are folded
+        Expression::Attribute(attr) => match attr.object.as_mut() {
+            Expression::Binary(binary) => {
+                minify_expression(&mut binary.left, scope);
+                minify_expression(&mut binary.right, scope);
+            }
+            object => minify_expression(object, scope),
+        },
+        Expression::Index(index) => {
+            minify_expression(&mut index.object, scope);
+            minify_expression(&mut index.index, scope);
+        }
+        Expression::Subscript(sub) => {
+            minify_expression(&mut sub.object, scope);
+            minify_expression(&mut sub.index, scope);
+        }
+        Expression::Slice(slice) => {
+            minify_expression(&mut slice.object, scope);
+            for bound in [&mut slice.start, &mut slice.end, &mut slice.step]
+                .into_iter()
+                .flatten()
+            {
+                minify_expression(bound, scope);
+            }
+        }
+        Expression::NamedExpr(named) => minify_expression(&mut named.value, scope),
+        Expression::FunctionExpr(function) => {
+            minify_function(&mut function.parameters, &mut function.body, scope)
+        }
+        Expression::TemplateLiteral(template) => {
+            for expression in &mut template.expressions {
+                minify_expression(expression, scope);
+            }
+        }
+        Expression::FString(fstring) => {
+            for part in &mut fstring.parts {
+                match part {
+                    FStringPart::Expression(expression)
+                    | FStringPart::FormattedExpression { expression, .. } => {
+                        minify_expression(expression, scope)
+                    }
+                    FStringPart::Text(_) => {}
+                }
+            }
+        }
+        Expression::JSXElement(element) => minify_jsx(element, scope),
+        Expression::InlineJs(_) => scope.inline_js = true,
+        Expression::Identifier(_) | Expression::Literal(_) => {}
+    }
+}
+
+fn minify_jsx(element: &mut JSXElement, scope: &mut Scope) {
+    for attribute in &mut element.attributes {
+        if let Some(value) = &mut attribute.value {
+            minify_expression(value, scope);
+        }
+    }
+    for child in &mut element.children {
+        match child {
+            JSXChild::Element(child) => minify_jsx(child, scope),
+            JSXChild::Expression(expression) => minify_expression(expression, scope),
+            JSXChild::Text(_) => {}
+        }
+    }
+}
+
+/// Value of an operation on literals, if it can be folded
+fn fold_binary(binary: &BinaryExpression) -> Option<Expression> {
+    // `and` and `or` give one of their operands, the other may not be a literal
+    if let BinaryOperator::And | BinaryOperator::Or = binary.operator {
+        if !matches!(*binary.left, Expression::Literal(_)) {
+            return None;
+        }
+        let takes_left = truthiness(&binary.left)? == matches!(binary.operator, BinaryOperator::Or);
+        return Some(if takes_left {
+            (*binary.left).clone()
+        } else {
+            (*binary.right).clone()
+        });
+    }
+
+    let (Expression::Literal(left), Expression::Literal(right)) = (&*binary.left, &*binary.right)
+    else {
+        return None;
+    };
+    let value = match (&binary.operator, left, right) {
+        (BinaryOperator::Equal, ..) => Literal::Bool(literals_equal(&binary.left, &binary.right)?),
+        (BinaryOperator::NotEqual, ..) => {
+            Literal::Bool(!literals_equal(&binary.left, &binary.right)?)
+        }
+        (BinaryOperator::Add, Literal::String(left), Literal::String(right)) => {
+            Literal::String(format!("{left}{right}"))
+        }
+        (operator, Literal::Int(left), Literal::Int(right)) => {
+            fold_integers(operator, *left, *right)?
+        }
+        (operator, left, right) => fold_numbers(operator, number(left)?, number(right)?)?,
+    };
+    Some(Expression::Literal(value))
+}
+
+fn number(literal: &Literal) -> Option<f64> {
+    match literal {
+        Literal::Int(value) => Some(*value as f64),
+        Literal::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn fold_integers(operator: &BinaryOperator, left: i64, right: i64) -> Option<Literal> {
+    let safe = |value: i64| (0..=MAX_SAFE_INTEGER).contains(&value);
+    if !safe(left) || !safe(right) {
+        return None;
+    }
+    let value = match operator {
+        BinaryOperator::Add => left + right,
+        BinaryOperator::Subtract => left - right,
+        BinaryOperator::Multiply => left.checked_mul(right)?,
+        BinaryOperator::Modulo if right != 0 => left % right,
+        BinaryOperator::FloorDivide if right != 0 => left / right,
+        // Bitwise operators work on 32-bit integers
+        BinaryOperator::BitwiseAnd | BinaryOperator::BitwiseOr | BinaryOperator::BitwiseXor
+            if left <= i32::MAX as i64 && right <= i32::MAX as i64 =>
+        {
+            match operator {
+                BinaryOperator::BitwiseAnd => left & right,
+                BinaryOperator::BitwiseOr => left | right,
+                _ => left ^ right,
+            }
+        }
+        _ => return fold_numbers(operator, left as f64, right as f64),
+    };
+    safe(value).then_some(Literal::Int(value))
+}
+
+fn fold_numbers(operator: &BinaryOperator, left: f64, right: f64) -> Option<Literal> {
+    let value = match operator {
+        BinaryOperator::Less => return Some(Literal::Bool(left < right)),
+        BinaryOperator::Greater => return Some(Literal::Bool(left > right)),
+        BinaryOperator::LessEqual => return Some(Literal::Bool(left <= right)),
+        BinaryOperator::GreaterEqual => return Some(Literal::Bool(left >= right)),
+        BinaryOperator::Add => left + right,
+        BinaryOperator::Subtract => left - right,
+        BinaryOperator::Multiply => left * right,
+        BinaryOperator::Divide => left / right,
+        BinaryOperator::Modulo => left % right,
+        BinaryOperator::FloorDivide => (left / right).floor(),
+        _ => return None,
+    };
+    (value.is_finite() && value.is_sign_positive()).then_some(Literal::Float(value))
+}
+
+/// Whitespace between two tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Gap {
+    None,
+    Space,
+    Newline,
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// `js_code` without comments and the whitespace it doesn't need. A line break is kept where
+/// automatic semicolon insertion could depend on it: between two tokens that neither end nor
+/// continue an expression, such as `}` and a name.
+pub fn compact(js_code: &str) -> String {
+    compact_into(js_code, None)
+}
+
+/// `js_code` compacted as [`compact`] does, with a map from each token of the result back to
+/// where it was in `js_code`, which the map calls `source`
+pub fn compact_mapped(js_code: &str, source: &str) -> (String, SourceMap) {
+    let mut lines = vec![Vec::new()];
+    let code = compact_into(js_code, Some(&mut lines));
+    let map = SourceMap {
+        sources: vec![source.to_string()],
+        sources_content: vec![None],
+        lines,
+        ..Default::default()
+    };
+    (code, map)
+}
+
+/// A line and a column in UTF-16 code units, as source maps count them, moved along text
+#[derive(Debug, Default)]
+struct Position {
+    line: u32,
+    column: u32,
+}
+
+impl Position {
+    fn advance(&mut self, text: impl Iterator<Item = char>) {
+        for c in text {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 0;
+            } else {
+                self.column += c.len_utf16() as u32;
+            }
+        }
+    }
+}
+
+/// [`compact`], adding a segment per token to `mappings` when given
+fn compact_into(js_code: &str, mut mappings: Option<&mut Vec<Vec<Segment>>>) -> String {
+    let chars: Vec<char> = js_code.chars().collect();
+    // How far into the input and the output the positions of the mappings have got
+    let (mut input, mut input_at) = (Position::default(), 0);
+    let (mut output, mut output_at) = (Position::default(), 0);
+    let mut out = String::with_capacity(js_code.len());
+    let mut i = 0;
+
+    // A `#!` line has to stay the first line
+    if js_code.starts_with("#!") {
+        while i < chars.len() && chars[i] != '\n' {
+            out.push(chars[i]);
+            i += 1;
+        }
+        out.push('\n');
+        i += 1;
+    }
+
+    let mut gap = Gap::None;
+    let mut last_word = String::new();
+    // Brace depth of the code at each `${` of the template literals it is in
+    let mut templates: Vec<usize> = Vec::new();
+    let mut depth = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '\n' => {
+                gap = Gap::Newline;
+                i += 1;
+                continue;
+            }
+            c if c.is_whitespace() => {
+                gap = gap.max(Gap::Space);
+                i += 1;
+                continue;
+            }
+            '/' if next == Some('/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == '*' && chars[j + 1] == '/')
+                    .map_or(chars.len(), |j| j + 2);
+                let line_break = chars[i..end].contains(&'\n');
+                gap = gap.max(if line_break { Gap::Newline } else { Gap::Space });
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+
+        let previous = out.chars().last();
+        let start = i;
+        let mut word = false;
+        match c {
+            '"' | '\'' => {
+                i += 1;
+                while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+                i = (i + 1).min(chars.len());
+            }
+            '`' => i = template_end(&chars, i + 1, &mut templates, depth),
+            '}' if templates.last() == Some(&depth) => {
+                templates.pop();
+                i = template_end(&chars, i + 1, &mut templates, depth);
+            }
+            '{' => {
+                depth += 1;
+                i += 1;
+            }
+            '}' => {
+                depth = depth.saturating_sub(1);
+                i += 1;
+            }
+            '/' if regex_allowed(previous, &last_word) => i = regex_end(&chars, i),
+            c if c.is_ascii_digit() => {
+                i += 1;
+                while i < chars.len()
+                    && (is_word(chars[i])
+                        || chars[i] == '.'
+                        || (matches!(chars[i], '+' | '-')
+                            && matches!(chars[i - 1], 'e' | 'E')
+                            && !chars[start..i].contains(&'x')))
+                {
+                    i += 1;
+                }
+            }
+            c if is_word(c) => {
+                word = true;
+                while i < chars.len() && is_word(chars[i]) {
+                    i += 1;
+                }
+            }
+            _ => i += 1,
+        }
+
+        // Nothing needs to separate the first token from the `#!` line
+        if let Some(previous) = previous.filter(|&previous| previous != '\n') {
+            separate(&mut out, gap, previous, c);
+        }
+        gap = Gap::None;
+        last_word.clear();
+        if word {
+            last_word.extend(&chars[start..i]);
+        }
+        if let Some(mappings) = mappings.as_deref_mut() {
+            input.advance(chars[input_at..start].iter().copied());
+            input_at = start;
+            output.advance(out[output_at..].chars());
+            output_at = out.len();
+            mappings.resize_with(output.line as usize + 1, Vec::new);
+            mappings[output.line as usize].push(Segment {
+                generated_column: output.column,
+                original: Some(Original {
+                    source: 0,
+                    line: input.line,
+                    column: input.column,
+                    name: None,
+                }),
+            });
+        }
+        out.extend(&chars[start..i]);
+    }
+    out.push('\n');
+    out
+}
+
+/// End of a template literal's text from `i` on: after its closing backquote, or after a
+/// `${` whose code comes next, at brace `depth`
+fn template_end(chars: &[char], mut i: usize, templates: &mut Vec<usize>, depth: usize) -> usize {
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '`' => return i + 1,
+            '$' if chars.get(i + 1) == Some(&'{') => {
+                templates.push(depth);
+                return i + 2;
+            }
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// Whether a `/` after `previous` and the word `last_word` starts a regular expression
+fn regex_allowed(previous: Option<char>, last_word: &str) -> bool {
+    match previous {
+        None => true,
+        Some(c) if is_word(c) => REGEX_KEYWORDS.contains(&last_word),
+        Some(c) => !matches!(c, ')' | ']' | '"' | '\'' | '`'),
+    }
+}
+
+/// End of the regular expression literal starting at `start`, or just past its `/` if it
+/// isn't one after all
+fn regex_end(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    let mut class = false;
+    while i < chars.len() {
+        match chars[i] {
+            '\n' => break,
+            '\\' => i += 1,
+            '[' => class = true,
+            ']' => class = false,
+            '/' if !class => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    start + 1
+}
+
+/// Write what has to stay of a `gap` between `previous` and a token starting with `next`
+fn separate(out: &mut String, gap: Gap, previous: char, next: char) {
+    // Automatic semicolon insertion can't put a semicolon after something that doesn't end an
+    // expression, or before something that can only continue one
+    let line_break = gap == Gap::Newline
+        && !";{,([:?=<>*%&|^!~.".contains(previous)
+        && !")]},;:?.=&|^%*<>".contains(next);
+    if line_break {
+        out.push('\n');
+    } else if gap != Gap::None
+        && ((is_word(previous) && is_word(next))
+            || (previous.is_ascii_digit() && next == '.')
+            || (previous == '+' && next == '+')
+            || (previous == '-' && next == '-')
+            || (previous == '/' && matches!(next, '/' | '*')))
+    {
+        out.push(' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_program;
+
+    /// What `left operator right` folds to, if it does
+    fn fold(left: Literal, operator: BinaryOperator, right: Literal) -> Option<String> {
+        let binary = BinaryExpression {
+            left: Box::new(Expression::Literal(left)),
+            operator,
+            right: Box::new(Expression::Literal(right)),
+        };
+        fold_binary(&binary).map(|folded| format!("{folded:?}"))
+    }
+
+    fn literal(literal: Literal) -> Option<String> {
+        Some(format!("{:?}", Expression::Literal(literal)))
+    }
+
+    /// Names assigned to in the body of the function `source` starts with, after minifying
+    fn minified_locals(source: &str) -> Vec<String> {
+        let mut program = test_program(source);
+        super::program(&mut program);
+        let Some(Statement::FunctionDef(function)) = program.statements.first() else {
+            panic!("expected a function first");
+        };
+        function
+            .body
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Assignment(assignment) => Some(assignment.name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_short_names() {
+        assert_eq!(short_name(0), "$a");
+        assert_eq!(short_name(25), "$z");
+        assert_eq!(short_name(26), "$A");
+        assert_eq!(short_name(51), "$Z");
+        assert_eq!(short_name(52), "$aa");
+        assert_eq!(short_name(53), "$ba");
+        assert_eq!(short_name(52 + 52), "$ab");
+    }
+
+    #[test]
+    fn test_literals_fold_where_javascript_agrees() {
+        use BinaryOperator::*;
+        assert_eq!(
+            fold(Literal::Int(6), Multiply, Literal::Int(7)),
+            literal(Literal::Int(42))
+        );
+        assert_eq!(
+            fold(Literal::Int(7), FloorDivide, Literal::Int(2)),
+            literal(Literal::Int(3))
+        );
+        assert_eq!(
+            fold(Literal::Int(10), Divide, Literal::Int(4)),
+            literal(Literal::Float(2.5))
+        );
+        assert_eq!(
+            fold(Literal::Int(6), BitwiseXor, Literal::Int(3)),
+            literal(Literal::Int(5))
+        );
+        assert_eq!(
+            fold(Literal::Float(1.5), Less, Literal::Int(2)),
+            literal(Literal::Bool(true))
+        );
+        assert_eq!(
+            fold(
+                Literal::String("a".to_string()),
+                Add,
+                Literal::String("b".to_string())
+            ),
+            literal(Literal::String("ab".to_string()))
+        );
+        assert_eq!(
+            fold(Literal::Int(1), Equal, Literal::Float(1.0)),
+            literal(Literal::Bool(true))
+        );
+        assert_eq!(
+            fold(Literal::Int(0), Or, Literal::String("x".to_string())),
+            literal(Literal::String("x".to_string()))
+        );
+        assert_eq!(
+            fold(Literal::Int(0), And, Literal::Int(5)),
+            literal(Literal::Int(0))
+        );
+
+        // Negative results, division by zero, unsafe integers and 64-bit bitwise operands
+        assert_eq!(fold(Literal::Int(1), Subtract, Literal::Int(4)), None);
+        assert_eq!(fold(Literal::Int(1), Divide, Literal::Int(0)), None);
+        assert_eq!(fold(Literal::Int(1), Modulo, Literal::Int(0)), None);
+        assert_eq!(
+            fold(Literal::Int(MAX_SAFE_INTEGER), Add, Literal::Int(1)),
+            None
+        );
+        assert_eq!(
+            fold(Literal::Int(1 << 40), BitwiseAnd, Literal::Int(1)),
+            None
+        );
+        assert_eq!(
+            fold(Literal::String("a".to_string()), Add, Literal::Int(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_locals_get_short_names() {
+        assert_eq!(
+            minified_locals("def f(a):\n    total = a\n    count = 1\n    total = count\n"),
+            ["$a", "$b", "$a"]
+        );
+        // Called names may be builtins, and inline JavaScript could use any name
+        assert_eq!(
+            minified_locals("def f(a):\n    len = a\n    len = len(a)\n"),
+            ["len", "len"]
+        );
+        assert_eq!(
+            minified_locals("def f(a):\n    total = a\n    js\"\"\"console.log(total)\"\"\"\n"),
+            ["total"]
+        );
+    }
+
+    #[test]
+    fn test_compact_keeps_what_javascript_needs() {
+        assert_eq!(
+            compact("#!/usr/bin/env node\n// comment\nconst a = 1;\n"),
+            "#!/usr/bin/env node\nconst a=1;\n"
+        );
+        // Line breaks automatic semicolon insertion depends on stay
+        assert_eq!(compact("let a = b\n(c)\n"), "let a=b\n(c)\n");
+        assert_eq!(compact("return\nx\n"), "return\nx\n");
+        // Words keep a space between them, operators that would merge too
+        assert_eq!(
+            compact("typeof  x === 'a' + + y - -z;"),
+            "typeof x==='a'+ +y- -z;\n"
+        );
+        // Division and regular expressions
+        assert_eq!(compact("a = b / 2 / c;"), "a=b/2/c;\n");
+        assert_eq!(compact("return /a b/.test(s);"), "return/a b/.test(s);\n");
+    }
+
+    #[test]
+    fn test_compact_mapped_maps_tokens_back() {
+        let (code, map) =
+            compact_mapped("const a = 1;\n// comment\n  let é = 'é' + a;\n", "app.js");
+        assert_eq!(
+            code,
+            compact("const a = 1;\n// comment\n  let é = 'é' + a;\n")
+        );
+        assert_eq!(code, "const a=1;let é='é'+a;\n");
+        assert_eq!(map.sources, ["app.js"]);
+        let position = |column| {
+            let position = map.lookup(0, column).unwrap();
+            (position.line, position.column)
+        };
+        assert_eq!(position(0), (0, 0));
+        assert_eq!(position(6), (0, 6));
+        assert_eq!(position(8), (0, 10));
+        assert_eq!(position(10), (2, 2));
+        // `'é'` starts after `let é=`, columns counting UTF-16 code units
+        assert_eq!(position(16), (2, 10));
+        assert_eq!(position(20), (2, 16));
+    }
+}
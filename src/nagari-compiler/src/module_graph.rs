@@ -0,0 +1,286 @@
+//! Module graph of a project and import cycle detection.
+//!
+//! Edges come from top-level imports of relative paths (`./utils`, `../lib/math`) that resolve
+//! to another module being compiled. Imports marked `# nagari:lazy` are left out: they are the
+//! escape hatch for cycles whose bindings are only used after both modules have loaded.
+
+use crate::error::NagariError;
+use nagari_parser::{import_sites, ImportOrigin, ImportSite};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+const EXTENSIONS: &[&str] = &["nag", "js"];
+
+/// An import from one module of the graph to another
+#[derive(Debug, Clone)]
+pub struct ImportEdge {
+    /// Index of the imported module
+    pub target: usize,
+    pub site: ImportSite,
+    /// Text of the import statement
+    pub statement: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModuleGraph {
+    modules: Vec<PathBuf>,
+    edges: Vec<Vec<ImportEdge>>,
+}
+
+/// A chain of imports leading back to the module it started from
+#[derive(Debug, Clone)]
+pub struct ImportCycle {
+    /// Each module of the cycle with the import that leads to the next one
+    pub steps: Vec<(PathBuf, ImportEdge)>,
+}
+
+impl ImportCycle {
+    pub fn modules(&self) -> impl Iterator<Item = &Path> {
+        self.steps.iter().map(|(module, _)| module.as_path())
+    }
+
+    pub fn to_error(&self) -> NagariError {
+        NagariError::ImportCycle(self.to_string())
+    }
+}
+
+impl fmt::Display for ImportCycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut chain: Vec<String> = self
+            .modules()
+            .map(|module| module.display().to_string())
+            .collect();
+        if let Some(first) = chain.first().cloned() {
+            chain.push(first);
+        }
+        write!(f, "{}", chain.join(" → "))?;
+        for (module, edge) in &self.steps {
+            write!(
+                f,
+                "\n  {}:{}:{}: {}",
+                module.display(),
+                edge.site.line,
+                edge.site.column,
+                edge.statement
+            )?;
+        }
+        write!(
+            f,
+            "\n  Mark one of these imports `# nagari:lazy` if the cycle is intentional"
+        )
+    }
+}
+
+impl ModuleGraph {
+    /// Build the graph of `modules`, given as path and source text
+    pub fn build<'a, I>(modules: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a Path, &'a str)>,
+    {
+        let modules: Vec<(PathBuf, &str)> = modules
+            .into_iter()
+            .map(|(path, source)| (normalize(path), source))
+            .collect();
+        let index: HashMap<&Path, usize> = modules
+            .iter()
+            .enumerate()
+            .map(|(i, (path, _))| (path.as_path(), i))
+            .collect();
+
+        let edges = modules
+            .iter()
+            .map(|(path, source)| {
+                import_sites(source)
+                    .into_iter()
+                    .filter(|site| site.origin == ImportOrigin::Local && !site.lazy)
+                    .filter_map(|site| {
+                        let target = resolve(path, &site.module)
+                            .into_iter()
+                            .find_map(|candidate| index.get(candidate.as_path()).copied())?;
+                        Some(ImportEdge {
+                            target,
+                            statement: source[site.start..site.end].to_string(),
+                            site,
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            modules: modules.into_iter().map(|(path, _)| path).collect(),
+            edges,
+        }
+    }
+
+    pub fn modules(&self) -> &[PathBuf] {
+        &self.modules
+    }
+
+    /// Imports of the module at `index` in [`ModuleGraph::modules`] from other modules of the graph
+    pub fn imports(&self, index: usize) -> &[ImportEdge] {
+        &self.edges[index]
+    }
+
+    /// One cycle per group of mutually importing modules, shortest first from the group's
+    /// earliest module
+    pub fn cycles(&self) -> Vec<ImportCycle> {
+        self.strongly_connected()
+            .into_iter()
+            .filter_map(|group| {
+                let start = *group.iter().min()?;
+                self.shortest_cycle(start, &group)
+            })
+            .collect()
+    }
+
+    /// Fail with the first import cycle, if any
+    pub fn check(&self) -> Result<(), NagariError> {
+        match self.cycles().first() {
+            Some(cycle) => Err(cycle.to_error()),
+            None => Ok(()),
+        }
+    }
+
+    /// Strongly connected components with more than one module or a self import (Tarjan)
+    fn strongly_connected(&self) -> Vec<Vec<usize>> {
+        struct State {
+            counter: usize,
+            index: Vec<Option<usize>>,
+            low: Vec<usize>,
+            stack: Vec<usize>,
+            on_stack: Vec<bool>,
+            groups: Vec<Vec<usize>>,
+        }
+
+        fn visit(graph: &ModuleGraph, node: usize, state: &mut State) {
+            state.index[node] = Some(state.counter);
+            state.low[node] = state.counter;
+            state.counter += 1;
+            state.stack.push(node);
+            state.on_stack[node] = true;
+
+            for edge in &graph.edges[node] {
+                match state.index[edge.target] {
+                    None => {
+                        visit(graph, edge.target, state);
+                        state.low[node] = state.low[node].min(state.low[edge.target]);
+                    }
+                    Some(target_index) if state.on_stack[edge.target] => {
+                        state.low[node] = state.low[node].min(target_index);
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            if Some(state.low[node]) == state.index[node] {
+                let mut group = Vec::new();
+                while let Some(member) = state.stack.pop() {
+                    state.on_stack[member] = false;
+                    group.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                let self_import = graph.edges[node].iter().any(|edge| edge.target == node);
+                if group.len() > 1 || self_import {
+                    state.groups.push(group);
+                }
+            }
+        }
+
+        let count = self.modules.len();
+        let mut state = State {
+            counter: 0,
+            index: vec![None; count],
+            low: vec![0; count],
+            stack: Vec::new(),
+            on_stack: vec![false; count],
+            groups: Vec::new(),
+        };
+        for node in 0..count {
+            if state.index[node].is_none() {
+                visit(self, node, &mut state);
+            }
+        }
+
+        state
+            .groups
+            .sort_by_key(|group| group.iter().min().copied());
+        state.groups
+    }
+
+    /// Breadth-first search for the shortest import chain from `start` back to itself
+    fn shortest_cycle(&self, start: usize, group: &[usize]) -> Option<ImportCycle> {
+        let mut reached_by: HashMap<usize, (usize, usize)> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(node) = queue.pop_front() {
+            for (edge_index, edge) in self.edges[node].iter().enumerate() {
+                if !group.contains(&edge.target) || reached_by.contains_key(&edge.target) {
+                    continue;
+                }
+                reached_by.insert(edge.target, (node, edge_index));
+                if edge.target == start {
+                    return Some(self.cycle_ending_at(start, &reached_by));
+                }
+                queue.push_back(edge.target);
+            }
+        }
+
+        None
+    }
+
+    fn cycle_ending_at(
+        &self,
+        start: usize,
+        reached_by: &HashMap<usize, (usize, usize)>,
+    ) -> ImportCycle {
+        let mut steps = Vec::new();
+        let mut node = start;
+        loop {
+            let (from, edge_index) = reached_by[&node];
+            steps.push((
+                self.modules[from].clone(),
+                self.edges[from][edge_index].clone(),
+            ));
+            node = from;
+            if node == start {
+                break;
+            }
+        }
+        steps.reverse();
+        ImportCycle { steps }
+    }
+}
+
+/// Files a relative import of `module` from `importer` may refer to
+pub(crate) fn resolve(importer: &Path, module: &str) -> Vec<PathBuf> {
+    let base = importer.parent().unwrap_or(Path::new(""));
+    let path = normalize(&base.join(module));
+
+    let stem = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) if EXTENSIONS.contains(&extension) => path.with_extension(""),
+        _ => path,
+    };
+    vec![stem.with_extension("nag"), stem.join("index.nag")]
+}
+
+/// Resolve `.` and `..` components without touching the file system
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
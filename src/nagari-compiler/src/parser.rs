@@ -159,6 +159,7 @@ impl Parser {
                     name: param_name,
                     param_type,
                     default_value,
+                    kind: ParameterKind::Positional,
                 });
 
                 if !self.match_token(&Token::Comma) {
@@ -279,6 +280,7 @@ impl Parser {
             variable,
             iterable,
             body,
+            is_async: false,
         }))
     }
 
@@ -362,6 +364,13 @@ impl Parser {
                     value,
                 },
             )),
+            Expression::Index(index) => {
+                Ok(Statement::IndexAssignment(crate::ast::IndexAssignment {
+                    object: *index.object,
+                    index: *index.index,
+                    value,
+                }))
+            }
             Expression::Tuple(elements) => {
                 // Tuple unpacking assignment: x, y = expr
                 let mut targets = Vec::new();
@@ -937,7 +946,11 @@ impl Parser {
                 None
             };
 
-            decorators.push(Decorator { name, arguments });
+            decorators.push(Decorator {
+                name,
+                arguments,
+                keyword_args: Vec::new(),
+            });
             self.consume_newline()?;
         }
 
@@ -999,7 +1012,11 @@ impl Parser {
 
         let body = self.block()?;
 
-        Ok(Statement::With(WithStatement { items, body }))
+        Ok(Statement::With(WithStatement {
+            items,
+            body,
+            is_async: false,
+        }))
     }
 
     // Exception handling
@@ -1264,6 +1281,7 @@ impl Parser {
             name,
             superclass: bases.first().cloned(),
             body,
+            decorators: Vec::new(),
         }))
     }
 
@@ -1630,6 +1648,7 @@ impl Parser {
                         name: param_name,
                         param_type,
                         default_value,
+                        kind: ParameterKind::Positional,
                     });
 
                     if !self.match_token(&Token::Comma) {
@@ -1690,6 +1709,7 @@ impl Parser {
                     name: param_name,
                     param_type: None,
                     default_value: None,
+                    kind: ParameterKind::Positional,
                 });
 
                 if !self.match_token(&Token::Comma) {
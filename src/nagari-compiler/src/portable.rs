@@ -0,0 +1,92 @@
+//! Output that is the same whichever platform builds it.
+//!
+//! A checkout on Windows often has CRLF line endings where one on Linux has LF, and paths
+//! there are `\`-separated. Neither should change what a build emits: sources and embedded
+//! text files are read with LF line endings, emitted files end their lines with the
+//! configured [`LineEndings`] (LF unless overridden), and paths written into emitted files
+//! are `/`-separated.
+
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Line endings of emitted files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineEndings {
+    /// `\n`, whatever the platform
+    #[default]
+    Lf,
+    /// `\r\n`, whatever the platform
+    Crlf,
+    /// `\r\n` on Windows and `\n` elsewhere; builds on different platforms then differ
+    Native,
+}
+
+impl LineEndings {
+    pub const ALL: [LineEndings; 3] = [LineEndings::Lf, LineEndings::Crlf, LineEndings::Native];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEndings::Lf => "lf",
+            LineEndings::Crlf => "crlf",
+            LineEndings::Native => "native",
+        }
+    }
+
+    /// The line terminator these line endings stand for on this platform
+    pub fn newline(&self) -> &'static str {
+        match self {
+            LineEndings::Lf => "\n",
+            LineEndings::Crlf => "\r\n",
+            LineEndings::Native if cfg!(windows) => "\r\n",
+            LineEndings::Native => "\n",
+        }
+    }
+
+    /// `text` with every line ending, LF or CRLF, replaced by these
+    pub fn apply(&self, text: String) -> String {
+        let text = match to_lf(&text) {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(lf) => lf,
+        };
+        match self.newline() {
+            "\n" => text,
+            newline => text.replace('\n', newline),
+        }
+    }
+}
+
+impl FromStr for LineEndings {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.to_ascii_lowercase();
+        LineEndings::ALL
+            .into_iter()
+            .find(|endings| endings.as_str() == name)
+            .ok_or_else(|| format!("Unknown line endings '{name}' (expected lf, crlf or native)"))
+    }
+}
+
+impl fmt::Display for LineEndings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `text` with its CRLF line endings replaced by LF
+pub fn to_lf(text: &str) -> Cow<'_, str> {
+    if text.contains("\r\n") {
+        Cow::Owned(text.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// `path` as written into emitted files: `/`-separated on every platform
+pub fn slash_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
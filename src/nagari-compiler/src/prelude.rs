@@ -0,0 +1,18 @@
+//! The supported surface of the compiler library. Items reachable from here follow semantic
+//! versioning: they are only removed or changed incompatibly in a major release, after a
+//! release in which they are `#[deprecated]`. Everything else may change between minor
+//! releases.
+//!
+//! ```
+//! use nagari_compiler::prelude::*;
+//!
+//! let compiler = Compiler::with_config(CompilerConfigBuilder::new().target("es6").build());
+//! let result = compiler.compile_string("x = 1", None).unwrap();
+//! assert!(result.js_code.contains("x"));
+//! ```
+
+pub use crate::{
+    CompilationResult, CompilationTimings, Compiler, CompilerConfig, CompilerConfigBuilder,
+    Environment, ExternDeclarations, IncrementalCompiler, IncrementalResult, Lexer, NagariError,
+    Parser, Program, RuntimePackage, SourceMap, Warning, WarningLevel,
+};
@@ -0,0 +1,304 @@
+//! Incremental compilation of a project, with a cache kept on disk between builds.
+//!
+//! [`Compiler::compile_project`] gives every module a key hashed from its source, the keys of
+//! the modules it imports (through the [`ModuleGraph`]), the compiler version and the options
+//! that affect output. A module's key so changes when it, or any module it transitively
+//! imports, changes. Each compiled module is stored in the cache directory under its path with
+//! its key; the next build reuses the stored output of every module whose key is unchanged and
+//! compiles only the others.
+//!
+//! Modules calling `embed()` are always compiled again, since the files they embed are not
+//! part of the key. Modules that fail to compile are not cached.
+
+use crate::error::NagariError;
+use crate::module_graph::ModuleGraph;
+use crate::timings::CompilationTimings;
+use crate::warnings::Warning;
+use crate::{embed, Compiler, CompilerConfig, Failure};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// Default cache directory, relative to the directory the compiler runs in
+pub const CACHE_DIR: &str = ".nag-cache";
+
+/// Output of one module of a project
+#[derive(Debug, Clone)]
+pub struct ModuleOutput {
+    pub path: PathBuf,
+    /// Generated JavaScript code
+    pub js_code: String,
+    /// Source map content (if enabled)
+    pub source_map: Option<String>,
+    /// TypeScript declarations (if enabled)
+    pub declarations: Option<String>,
+    /// Warnings of the compilation that produced the output
+    pub warnings: Vec<Warning>,
+    /// Time spent in each compilation phase; none for modules from the cache
+    pub timings: CompilationTimings,
+    /// Whether the output was taken from the cache instead of compiled
+    pub cached: bool,
+}
+
+/// A module's output as stored in the cache directory
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    path: PathBuf,
+    key: u64,
+    js_code: String,
+    source_map: Option<String>,
+    declarations: Option<String>,
+    warnings: Vec<Warning>,
+}
+
+impl Compiler {
+    /// Compile the modules of a project, reusing the cached output of every module that has
+    /// not changed, nor any module it imports, since it was cached. Results are in the order of
+    /// `inputs`; modules that are part of an import cycle fail with
+    /// [`NagariError::ImportCycle`]. Nothing but the cache is written.
+    pub fn compile_project<P: AsRef<Path>>(
+        &self,
+        inputs: &[P],
+    ) -> Vec<Result<ModuleOutput, NagariError>> {
+        if let Err(e) = self.config.validate() {
+            return inputs
+                .iter()
+                .map(|_| Err(NagariError::ConfigError(e.clone())))
+                .collect();
+        }
+
+        let sources: Vec<Result<String, NagariError>> = inputs
+            .iter()
+            .map(|input| {
+                fs::read_to_string(input.as_ref())
+                    .map_err(|e| NagariError::IoError(format!("Failed to read input file: {e}")))
+            })
+            .collect();
+        let graph = ModuleGraph::build(inputs.iter().zip(&sources).map(|(input, source)| {
+            let source = source.as_deref().unwrap_or_default();
+            (input.as_ref(), source)
+        }));
+        let mut cycle_of = HashMap::new();
+        for cycle in graph.cycles() {
+            for module in cycle.modules() {
+                cycle_of.insert(module.to_path_buf(), cycle.clone());
+            }
+        }
+        let keys = module_keys(&graph, &sources, config_key(&self.config));
+        let cache_dir = &self.config.cache_dir;
+
+        let mut results: Vec<Option<Result<ModuleOutput, NagariError>>> = Vec::new();
+        let mut stale = Vec::new();
+        for (index, (source, module)) in sources.into_iter().zip(graph.modules()).enumerate() {
+            let source = match source {
+                Ok(source) => source,
+                Err(e) => {
+                    results.push(Some(Err(e)));
+                    continue;
+                }
+            };
+            if let Some(cycle) = cycle_of.get(module) {
+                results.push(Some(Err(cycle.to_error())));
+                continue;
+            }
+            let embeds = source.contains(&format!("{}(", embed::INTRINSIC));
+            let cached =
+                load_entry(cache_dir, module).filter(|entry| entry.key == keys[index] && !embeds);
+            match cached {
+                Some(entry) => {
+                    if self.config.verbose {
+                        println!("📦 Reusing cached output: {}", module.display());
+                    }
+                    results.push(Some(Ok(ModuleOutput {
+                        path: inputs[index].as_ref().to_path_buf(),
+                        js_code: entry.js_code,
+                        source_map: entry.source_map,
+                        declarations: entry.declarations,
+                        warnings: entry.warnings,
+                        timings: CompilationTimings::new(),
+                        cached: true,
+                    })));
+                }
+                None => {
+                    stale.push(index);
+                    results.push(None);
+                }
+            }
+        }
+
+        let stale_inputs: Vec<&Path> = stale.iter().map(|&index| inputs[index].as_ref()).collect();
+        for (index, result) in stale.iter().zip(self.compile_many(&stale_inputs)) {
+            let path = inputs[*index].as_ref().to_path_buf();
+            let result = result
+                .map_err(Failure::into_error)
+                .map(|result| ModuleOutput {
+                    path,
+                    js_code: result.js_code,
+                    source_map: result.source_map,
+                    declarations: result.declarations,
+                    warnings: result.warnings,
+                    timings: result.timings,
+                    cached: false,
+                });
+            if let Ok(output) = &result {
+                let module = &graph.modules()[*index];
+                // A cache that can't be written only makes the next build slower
+                if let Err(e) = store_entry(cache_dir, module, keys[*index], output) {
+                    if self.config.verbose {
+                        println!("⚠️ Failed to cache {}: {e}", module.display());
+                    }
+                }
+            }
+            results[*index] = Some(result);
+        }
+
+        results.into_iter().flatten().collect()
+    }
+}
+
+/// Key of the options that change what the compiler outputs
+fn config_key(config: &CompilerConfig) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    crate::VERSION.hash(&mut hasher);
+    (
+        &config.target,
+        config.jsx,
+        config.ssr,
+        config.sourcemap,
+        config.devtools,
+        config.minify,
+        config.declarations,
+        config.jsdoc,
+        config.deny_warnings,
+        config.strict_types,
+        config.line_endings,
+    )
+        .hash(&mut hasher);
+    format!(
+        "{:?} {:?} {:?} {:?}",
+        config.embed_root, config.environment, config.runtime, config.limits
+    )
+    .hash(&mut hasher);
+
+    // Maps are hashed in a stable order
+    let mut levels: Vec<String> = config
+        .warning_levels
+        .iter()
+        .map(|(category, level)| format!("{category}={level:?}"))
+        .collect();
+    levels.sort();
+    levels.hash(&mut hasher);
+    let mut defines: Vec<String> = config
+        .defines
+        .iter()
+        .map(|(name, value)| format!("{name}={value:?}"))
+        .collect();
+    defines.sort();
+    defines.hash(&mut hasher);
+    let mut externs: Vec<(&str, &str)> = config
+        .externs
+        .modules()
+        .flat_map(|module| {
+            let name = module.name.as_str();
+            module
+                .members
+                .iter()
+                .map(move |member| (name, member.signature.as_str()))
+        })
+        .chain(
+            config
+                .externs
+                .globals()
+                .iter()
+                .map(|member| ("", member.signature.as_str())),
+        )
+        .collect();
+    externs.sort_unstable();
+    externs.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+/// Key of every module of `graph`: its source and the keys of the modules it imports, on top
+/// of `config_key`
+fn module_keys(
+    graph: &ModuleGraph,
+    sources: &[Result<String, NagariError>],
+    config_key: u64,
+) -> Vec<u64> {
+    fn key(
+        index: usize,
+        graph: &ModuleGraph,
+        sources: &[Result<String, NagariError>],
+        config_key: u64,
+        keys: &mut [Option<u64>],
+        visiting: &mut HashSet<usize>,
+    ) -> u64 {
+        if let Some(key) = keys[index] {
+            return key;
+        }
+        // Modules of an import cycle fail without being cached, so their keys don't matter
+        if !visiting.insert(index) {
+            return 0;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        config_key.hash(&mut hasher);
+        sources[index]
+            .as_deref()
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        for edge in graph.imports(index) {
+            key(edge.target, graph, sources, config_key, keys, visiting).hash(&mut hasher);
+        }
+
+        visiting.remove(&index);
+        let key = hasher.finish();
+        keys[index] = Some(key);
+        key
+    }
+
+    let mut keys = vec![None; graph.modules().len()];
+    let mut visiting = HashSet::new();
+    (0..keys.len())
+        .map(|index| key(index, graph, sources, config_key, &mut keys, &mut visiting))
+        .collect()
+}
+
+/// File of `module`'s entry in the cache directory
+fn entry_file(cache_dir: &Path, module: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    module.hash(&mut hasher);
+    cache_dir.join(format!("{:016x}.json", hasher.finish()))
+}
+
+fn load_entry(cache_dir: &Path, module: &Path) -> Option<CacheEntry> {
+    let text = fs::read_to_string(entry_file(cache_dir, module)).ok()?;
+    serde_json::from_str::<CacheEntry>(&text)
+        .ok()
+        .filter(|entry| entry.path == module)
+}
+
+fn store_entry(
+    cache_dir: &Path,
+    module: &Path,
+    key: u64,
+    output: &ModuleOutput,
+) -> std::io::Result<()> {
+    fs::create_dir_all(cache_dir)?;
+    let entry = CacheEntry {
+        path: module.to_path_buf(),
+        key,
+        js_code: output.js_code.clone(),
+        source_map: output.source_map.clone(),
+        declarations: output.declarations.clone(),
+        warnings: output.warnings.clone(),
+    };
+    fs::write(
+        entry_file(cache_dir, module),
+        serde_json::to_string(&entry)?,
+    )
+}
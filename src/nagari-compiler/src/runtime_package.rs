@@ -0,0 +1,126 @@
+//! The runtime package compiled modules import their helpers from.
+//!
+//! By default that is `nagari-runtime` from npm. Organizations that mirror packages internally
+//! can point the output at a renamed package such as `@myorg/nagari-runtime`, and vendored
+//! runtimes can be imported by path. Paths are emitted as written, so they are resolved against
+//! each output file.
+
+use serde::{Deserialize, Serialize};
+
+/// Specifier of the runtime published on npm
+pub const DEFAULT_SPECIFIER: &str = "nagari-runtime";
+
+/// Version range of the published runtime that this compiler's output works with
+pub const DEFAULT_VERSION: &str = "^0.5.0";
+
+/// Longest package name npm accepts
+const MAX_NAME_LENGTH: usize = 214;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuntimePackage {
+    /// What the output imports: a package name, optionally with a subpath, or a path starting
+    /// with `./`, `../` or `/`
+    pub specifier: String,
+    /// Version range packages depend on; unused for paths
+    pub version: String,
+}
+
+impl Default for RuntimePackage {
+    fn default() -> Self {
+        Self {
+            specifier: DEFAULT_SPECIFIER.to_string(),
+            version: DEFAULT_VERSION.to_string(),
+        }
+    }
+}
+
+impl RuntimePackage {
+    pub fn new(specifier: &str, version: &str) -> Self {
+        Self {
+            specifier: specifier.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    /// Whether the specifier is a path rather than a package
+    pub fn is_path(&self) -> bool {
+        ["./", "../", "/"]
+            .iter()
+            .any(|prefix| self.specifier.starts_with(prefix))
+    }
+
+    /// The npm dependency on the runtime as its package name and version range, or `None` for
+    /// a vendored runtime imported by path
+    pub fn dependency(&self) -> Option<(&str, &str)> {
+        if self.is_path() {
+            return None;
+        }
+        let segments = if self.specifier.starts_with('@') {
+            2
+        } else {
+            1
+        };
+        let end = self
+            .specifier
+            .match_indices('/')
+            .nth(segments - 1)
+            .map_or(self.specifier.len(), |(index, _)| index);
+        Some((&self.specifier[..end], &self.version))
+    }
+
+    /// Check that the specifier can be imported and the version is a range npm understands
+    pub fn validate(&self) -> Result<(), String> {
+        let specifier = &self.specifier;
+        if specifier.is_empty() {
+            return Err("runtime specifier is empty".to_string());
+        }
+        if specifier
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '\'' | '"' | '\\' | '`'))
+        {
+            return Err(format!(
+                "runtime specifier '{specifier}' contains characters an import cannot have"
+            ));
+        }
+        let Some((name, version)) = self.dependency() else {
+            return Ok(());
+        };
+
+        let segment = |segment: &str| {
+            !segment.is_empty()
+                && !segment.starts_with(['.', '_'])
+                && segment.chars().all(|c| {
+                    c.is_ascii_lowercase()
+                        || c.is_ascii_digit()
+                        || matches!(c, '-' | '.' | '_' | '~')
+                })
+        };
+        let valid_name = match name.strip_prefix('@') {
+            Some(scoped) => scoped
+                .split_once('/')
+                .is_some_and(|(scope, name)| segment(scope) && segment(name)),
+            None => segment(name),
+        };
+        if !valid_name || name.len() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "runtime specifier '{specifier}' is neither a package name nor a path starting with './', '../' or '/'"
+            ));
+        }
+
+        let valid_version = !version.trim().is_empty()
+            && version.chars().all(|c| {
+                c.is_ascii_alphanumeric()
+                    || matches!(
+                        c,
+                        '.' | '^' | '~' | '<' | '>' | '=' | '*' | '|' | '+' | '-' | ' '
+                    )
+            });
+        if !valid_version {
+            return Err(format!(
+                "runtime version '{version}' is not a version range"
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+//! Reading, writing and composing [source maps v3](https://sourcemaps.info/spec.html).
+//!
+//! A pass that rewrites JavaScript after transpiling, like minifying, has its own map from its
+//! output back to its input. [`SourceMap::compose`] chains such a map with the maps of its
+//! inputs, so the final output maps straight back to the `.nag` sources, and
+//! [`SourceMap::lookup`] answers the question a debugger asks: which source position a
+//! generated position comes from.
+//!
+//! Lines and columns are 0-based throughout, as in the format itself.
+
+use serde::{Deserialize, Serialize};
+
+/// A position in one of the map's sources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalPosition {
+    pub source: String,
+    pub line: u32,
+    pub column: u32,
+    pub name: Option<String>,
+}
+
+/// One mapping on a generated line: where it starts and what it maps to, if anything
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub generated_column: u32,
+    pub original: Option<Original>,
+}
+
+/// What a segment maps to, by index into the map's `sources` and `names`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Original {
+    pub source: u32,
+    pub line: u32,
+    pub column: u32,
+    pub name: Option<u32>,
+}
+
+/// A source map with its mappings decoded, one list of segments per generated line
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    pub file: Option<String>,
+    pub source_root: Option<String>,
+    pub sources: Vec<String>,
+    pub sources_content: Vec<Option<String>>,
+    pub names: Vec<String>,
+    pub lines: Vec<Vec<Segment>>,
+}
+
+/// The JSON form of a source map
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_root: Option<String>,
+    sources: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sources_content: Vec<Option<String>>,
+    #[serde(default)]
+    names: Vec<String>,
+    mappings: String,
+}
+
+impl SourceMap {
+    /// A map of `source` onto an output of `lines` lines that keeps every line where it was
+    pub fn line_identity(file: &str, source: &str, content: Option<&str>, lines: u32) -> Self {
+        Self {
+            file: Some(file.to_string()),
+            sources: vec![source.to_string()],
+            sources_content: vec![content.map(str::to_string)],
+            lines: (0..lines)
+                .map(|line| {
+                    vec![Segment {
+                        generated_column: 0,
+                        original: Some(Original {
+                            source: 0,
+                            line,
+                            column: 0,
+                            name: None,
+                        }),
+                    }]
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Read a source map from its JSON form
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let raw: RawSourceMap =
+            serde_json::from_str(json).map_err(|e| format!("Invalid source map: {e}"))?;
+        if raw.version != 3 {
+            return Err(format!(
+                "Unsupported source map version {} (expected 3)",
+                raw.version
+            ));
+        }
+        let lines = decode_mappings(&raw.mappings, raw.sources.len(), raw.names.len())?;
+        Ok(Self {
+            file: raw.file,
+            source_root: raw.source_root,
+            sources: raw.sources,
+            sources_content: raw.sources_content,
+            names: raw.names,
+            lines,
+        })
+    }
+
+    /// The JSON form of the map
+    pub fn to_json(&self) -> String {
+        let raw = RawSourceMap {
+            version: 3,
+            file: self.file.clone(),
+            source_root: self.source_root.clone(),
+            sources: self.sources.clone(),
+            sources_content: self.sources_content.clone(),
+            names: self.names.clone(),
+            mappings: encode_mappings(&self.lines),
+        };
+        serde_json::to_string(&raw).expect("source maps serialize to JSON")
+    }
+
+    /// Where the generated position `line`:`column` comes from: the last segment of the line
+    /// starting at or before `column`
+    pub fn lookup(&self, line: u32, column: u32) -> Option<OriginalPosition> {
+        let segments = self.lines.get(line as usize)?;
+        let index = segments.partition_point(|segment| segment.generated_column <= column);
+        let original = segments[..index].last()?.original?;
+        Some(OriginalPosition {
+            source: self.sources.get(original.source as usize)?.clone(),
+            line: original.line,
+            column: original.column,
+            name: original
+                .name
+                .and_then(|name| self.names.get(name as usize).cloned()),
+        })
+    }
+
+    /// This map, of an output built from intermediate files, chained with `inner`'s maps of
+    /// those files back to their own sources.
+    ///
+    /// `inner` is asked for the map of each of this map's sources; sources without one are kept
+    /// as they are, so a bundle can mix compiled modules with plain JavaScript. Segments whose
+    /// intermediate position the inner map doesn't cover lose their mapping.
+    pub fn compose<'a>(&self, inner: impl Fn(&str) -> Option<&'a SourceMap>) -> SourceMap {
+        let mut composed = SourceMap {
+            file: self.file.clone(),
+            ..Default::default()
+        };
+        let inner_maps: Vec<Option<&SourceMap>> =
+            self.sources.iter().map(|source| inner(source)).collect();
+
+        composed.lines = self
+            .lines
+            .iter()
+            .map(|segments| {
+                segments
+                    .iter()
+                    .map(|segment| {
+                        let original = segment.original.and_then(|original| {
+                            let position = match inner_maps[original.source as usize] {
+                                Some(map) => map.lookup(original.line, original.column)?,
+                                None => OriginalPosition {
+                                    source: self.sources[original.source as usize].clone(),
+                                    line: original.line,
+                                    column: original.column,
+                                    name: None,
+                                },
+                            };
+                            // A name the outer pass gave the segment (a minifier's renamed
+                            // binding) points at the original name better than none
+                            let name = position.name.clone().or_else(|| {
+                                original
+                                    .name
+                                    .and_then(|name| self.names.get(name as usize).cloned())
+                            });
+                            let content = match inner_maps[original.source as usize] {
+                                Some(map) => map.content_of(&position.source),
+                                None => self.content_of(&position.source),
+                            };
+                            Some(composed.original(position, name, content))
+                        });
+                        Segment {
+                            generated_column: segment.generated_column,
+                            original,
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        composed
+    }
+
+    /// Content of `source` embedded in the map
+    fn content_of(&self, source: &str) -> Option<String> {
+        let index = self.sources.iter().position(|s| s == source)?;
+        self.sources_content.get(index).cloned().flatten()
+    }
+
+    /// `position` with its source and name added to this map's tables
+    fn original(
+        &mut self,
+        position: OriginalPosition,
+        name: Option<String>,
+        content: Option<String>,
+    ) -> Original {
+        let source = match self.sources.iter().position(|s| *s == position.source) {
+            Some(index) => index,
+            None => {
+                self.sources.push(position.source);
+                self.sources_content.push(content);
+                self.sources.len() - 1
+            }
+        };
+        let name = name.map(|name| match self.names.iter().position(|n| *n == name) {
+            Some(index) => index as u32,
+            None => {
+                self.names.push(name);
+                self.names.len() as u32 - 1
+            }
+        });
+        Original {
+            source: source as u32,
+            line: position.line,
+            column: position.column,
+            name,
+        }
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_vlq(value: i64, out: &mut String) {
+    let mut vlq = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = vlq & 0b11111;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+fn decode_vlq(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<i64, String> {
+    let mut value: i64 = 0;
+    let mut shift = 0;
+    loop {
+        let c = chars
+            .next()
+            .ok_or("Invalid source map: mappings end inside a value")?;
+        let digit = BASE64
+            .iter()
+            .position(|&b| b as char == c)
+            .ok_or_else(|| format!("Invalid source map: '{c}' in mappings"))?
+            as i64;
+        if shift > 60 {
+            return Err("Invalid source map: value in mappings is too large".to_string());
+        }
+        value |= (digit & 0b11111) << shift;
+        shift += 5;
+        if digit & 0b100000 == 0 {
+            break;
+        }
+    }
+    Ok(if value & 1 == 1 {
+        -(value >> 1)
+    } else {
+        value >> 1
+    })
+}
+
+fn decode_mappings(
+    mappings: &str,
+    sources: usize,
+    names: usize,
+) -> Result<Vec<Vec<Segment>>, String> {
+    let mut lines = Vec::new();
+    // Every field but the generated column is relative to the previous segment of the map
+    let (mut source, mut line, mut column, mut name) = (0i64, 0i64, 0i64, 0i64);
+    for encoded_line in mappings.split(';') {
+        let mut segments = Vec::new();
+        let mut generated_column = 0i64;
+        for encoded in encoded_line.split(',').filter(|s| !s.is_empty()) {
+            let mut chars = encoded.chars().peekable();
+            let mut fields = Vec::with_capacity(5);
+            while chars.peek().is_some() {
+                fields.push(decode_vlq(&mut chars)?);
+            }
+            generated_column += fields[0];
+            let original = match fields.len() {
+                1 => None,
+                4 | 5 => {
+                    source += fields[1];
+                    line += fields[2];
+                    column += fields[3];
+                    let segment_name = (fields.len() == 5).then(|| {
+                        name += fields[4];
+                        name
+                    });
+                    let in_range = |value: i64, len: usize| value >= 0 && (value as usize) < len;
+                    if !in_range(source, sources)
+                        || segment_name.is_some_and(|n| !in_range(n, names))
+                    {
+                        return Err(
+                            "Invalid source map: mapping refers to a missing source or name"
+                                .to_string(),
+                        );
+                    }
+                    if line < 0 || column < 0 {
+                        return Err("Invalid source map: negative position in mappings".to_string());
+                    }
+                    Some(Original {
+                        source: source as u32,
+                        line: line as u32,
+                        column: column as u32,
+                        name: segment_name.map(|n| n as u32),
+                    })
+                }
+                n => return Err(format!("Invalid source map: segment with {n} fields")),
+            };
+            if generated_column < 0 {
+                return Err("Invalid source map: negative column in mappings".to_string());
+            }
+            segments.push(Segment {
+                generated_column: generated_column as u32,
+                original,
+            });
+        }
+        segments.sort_by_key(|segment| segment.generated_column);
+        lines.push(segments);
+    }
+    Ok(lines)
+}
+
+fn encode_mappings(lines: &[Vec<Segment>]) -> String {
+    let mut out = String::new();
+    let (mut source, mut line, mut column, mut name) = (0i64, 0i64, 0i64, 0i64);
+    for (index, segments) in lines.iter().enumerate() {
+        if index > 0 {
+            out.push(';');
+        }
+        let mut generated_column = 0i64;
+        for (i, segment) in segments.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            encode_vlq(segment.generated_column as i64 - generated_column, &mut out);
+            generated_column = segment.generated_column as i64;
+            if let Some(original) = segment.original {
+                encode_vlq(original.source as i64 - source, &mut out);
+                encode_vlq(original.line as i64 - line, &mut out);
+                encode_vlq(original.column as i64 - column, &mut out);
+                source = original.source as i64;
+                line = original.line as i64;
+                column = original.column as i64;
+                if let Some(segment_name) = original.name {
+                    encode_vlq(segment_name as i64 - name, &mut out);
+                    name = segment_name as i64;
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(generated_column: u32, line: u32, column: u32, name: Option<u32>) -> Segment {
+        Segment {
+            generated_column,
+            original: Some(Original {
+                source: 0,
+                line,
+                column,
+                name,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_vlq_round_trips() {
+        for value in [0, 1, -1, 15, 16, -16, 1_000, -123_456, 1 << 40] {
+            let mut encoded = String::new();
+            encode_vlq(value, &mut encoded);
+            let mut chars = encoded.chars().peekable();
+            assert_eq!(decode_vlq(&mut chars), Ok(value));
+            assert!(chars.next().is_none());
+        }
+        let mut encoded = String::new();
+        encode_vlq(-1, &mut encoded);
+        encode_vlq(16, &mut encoded);
+        assert_eq!(encoded, "DgB");
+    }
+
+    #[test]
+    fn test_maps_round_trip_through_json() {
+        let map = SourceMap {
+            file: Some("app.js".to_string()),
+            sources: vec!["app.nag".to_string()],
+            sources_content: vec![Some("x = 1\n".to_string())],
+            names: vec!["x".to_string()],
+            lines: vec![
+                vec![segment(0, 0, 0, Some(0)), segment(4, 0, 4, None)],
+                vec![],
+                vec![
+                    Segment {
+                        generated_column: 0,
+                        original: None,
+                    },
+                    segment(2, 3, 1, None),
+                ],
+            ],
+            ..Default::default()
+        };
+        let json = map.to_json();
+        assert!(
+            json.contains("\"mappings\":\"AAAAA,IAAI;;A,EAGH\""),
+            "{json}"
+        );
+        assert_eq!(SourceMap::parse(&json).unwrap(), map);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_maps() {
+        let error = |json: &str| SourceMap::parse(json).unwrap_err();
+        assert!(error("{").starts_with("Invalid source map"));
+        assert_eq!(
+            error(r#"{"version":2,"sources":[],"mappings":""}"#),
+            "Unsupported source map version 2 (expected 3)"
+        );
+        assert_eq!(
+            error(r#"{"version":3,"sources":[],"mappings":"AAAA"}"#),
+            "Invalid source map: mapping refers to a missing source or name"
+        );
+        assert_eq!(
+            error(r#"{"version":3,"sources":["a"],"mappings":"AADA"}"#),
+            "Invalid source map: negative position in mappings"
+        );
+        assert_eq!(
+            error(r#"{"version":3,"sources":["a"],"mappings":"AA"}"#),
+            "Invalid source map: segment with 2 fields"
+        );
+        assert_eq!(
+            error(r#"{"version":3,"sources":["a"],"mappings":"A!"}"#),
+            "Invalid source map: '!' in mappings"
+        );
+        assert_eq!(
+            error(r#"{"version":3,"sources":["a"],"mappings":"g"}"#),
+            "Invalid source map: mappings end inside a value"
+        );
+    }
+
+    #[test]
+    fn test_lookup_takes_the_segment_at_or_before_the_column() {
+        let map = SourceMap {
+            sources: vec!["app.nag".to_string()],
+            names: vec!["total".to_string()],
+            lines: vec![vec![
+                segment(2, 0, 0, None),
+                segment(6, 1, 4, Some(0)),
+                Segment {
+                    generated_column: 10,
+                    original: None,
+                },
+            ]],
+            ..Default::default()
+        };
+        assert_eq!(map.lookup(0, 1), None);
+        assert_eq!(
+            map.lookup(0, 5),
+            Some(OriginalPosition {
+                source: "app.nag".to_string(),
+                line: 0,
+                column: 0,
+                name: None,
+            })
+        );
+        assert_eq!(map.lookup(0, 6).unwrap().name.as_deref(), Some("total"));
+        assert_eq!(map.lookup(0, 12), None);
+        assert_eq!(map.lookup(1, 0), None);
+    }
+
+    #[test]
+    fn test_line_identity_keeps_lines() {
+        let map = SourceMap::line_identity("app.js", "app.nag", Some("a\nb\n"), 2);
+        assert_eq!(map.file.as_deref(), Some("app.js"));
+        assert_eq!(map.sources_content, [Some("a\nb\n".to_string())]);
+        assert_eq!(map.lookup(1, 7).map(|position| position.line), Some(1));
+        assert_eq!(map.lookup(2, 0), None);
+    }
+
+    #[test]
+    fn test_compose_drops_positions_the_inner_map_does_not_cover() {
+        let inner = SourceMap::line_identity("app.js", "app.nag", None, 1);
+        let outer = SourceMap {
+            file: Some("app.min.js".to_string()),
+            sources: vec!["app.js".to_string()],
+            names: vec!["count".to_string()],
+            lines: vec![vec![segment(0, 0, 3, Some(0)), segment(5, 1, 0, None)]],
+            ..Default::default()
+        };
+        let composed = outer.compose(|source| (source == "app.js").then_some(&inner));
+        assert_eq!(composed.file.as_deref(), Some("app.min.js"));
+        assert_eq!(composed.sources, ["app.nag"]);
+        // The outer pass's name is kept for the original position
+        assert_eq!(
+            composed.lookup(0, 0),
+            Some(OriginalPosition {
+                source: "app.nag".to_string(),
+                line: 0,
+                column: 0,
+                name: Some("count".to_string()),
+            })
+        );
+        assert_eq!(composed.lookup(0, 5), None);
+    }
+}
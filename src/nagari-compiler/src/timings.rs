@@ -0,0 +1,86 @@
+//! Per-phase compilation timings used by `nag build --timings`
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A phase of the compilation pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Lex,
+    Parse,
+    Convert,
+    TypeCheck,
+    Transpile,
+    Write,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::Lex,
+        Phase::Parse,
+        Phase::Convert,
+        Phase::TypeCheck,
+        Phase::Transpile,
+        Phase::Write,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Lex => "lex",
+            Phase::Parse => "parse",
+            Phase::Convert => "convert",
+            Phase::TypeCheck => "typecheck",
+            Phase::Transpile => "transpile",
+            Phase::Write => "write",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Durations recorded for each phase that ran while compiling one module
+#[derive(Debug, Clone, Default)]
+pub struct CompilationTimings {
+    phases: Vec<(Phase, Duration)>,
+}
+
+impl CompilationTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f`, adding its wall-clock duration to `phase`
+    pub fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+        result
+    }
+
+    pub fn record(&mut self, phase: Phase, duration: Duration) {
+        match self.phases.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    /// Duration of `phase`, or `None` if it did not run
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|(p, _)| *p == phase)
+            .map(|(_, duration)| *duration)
+    }
+
+    pub fn phases(&self) -> &[(Phase, Duration)] {
+        &self.phases
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+}
@@ -90,10 +90,10 @@ impl BuiltinMapper {
         self.add_mapping(
             "len",
             BuiltinMapping {
-                js_equivalent: ".length".to_string(),
+                js_equivalent: "nagariLen".to_string(),
                 requires_import: None,
-                requires_helper: false,
-                is_method: true,
+                requires_helper: true,
+                is_method: false,
             },
         );
 
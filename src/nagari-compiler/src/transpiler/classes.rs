@@ -0,0 +1,308 @@
+// Classes on the es6 and later targets.
+//
+// `__init__` becomes the constructor and the first parameter of a method is bound to `this`.
+// Methods decorated with `@property` and `@<name>.setter` become accessors, `@staticmethod` and
+// `@classmethod` ones become static methods, and class-level assignments become static fields.
+// Python's class-private `__name` attributes become `#name` private members. Calling a class
+// defined earlier in the module constructs it with `new`, as JavaScript classes cannot be
+// called. The es5 target lowers the same class bodies to constructor functions in es5.rs.
+
+use super::JSTranspiler;
+use crate::ast::*;
+use crate::declarations;
+use crate::error::NagariError;
+use std::collections::BTreeSet;
+
+/// What a method of a class body is, from its name and decorators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum MethodKind {
+    Constructor,
+    Method,
+    Getter,
+    Setter,
+    Static,
+    ClassMethod,
+}
+
+impl MethodKind {
+    pub(super) fn of(method: &FunctionDef) -> Result<Self, NagariError> {
+        let kind = match method.decorators.as_slice() {
+            [] if method.name == "__init__" || method.name == "constructor" => {
+                MethodKind::Constructor
+            }
+            [] => MethodKind::Method,
+            [Decorator {
+                name,
+                arguments: None,
+                ..
+            }] => match name.as_str() {
+                "property" => MethodKind::Getter,
+                "staticmethod" => MethodKind::Static,
+                "classmethod" => MethodKind::ClassMethod,
+                name if name.strip_suffix(".setter") == Some(method.name.as_str()) => {
+                    MethodKind::Setter
+                }
+                _ => return Err(unsupported_decorators(method)),
+            },
+            _ => return Err(unsupported_decorators(method)),
+        };
+        if kind == MethodKind::Getter && method.parameters.len() != 1 {
+            return Err(NagariError::SemanticError(format!(
+                "property '{}' must take only the instance",
+                method.name
+            )));
+        }
+        if kind == MethodKind::Setter && method.parameters.len() != 2 {
+            return Err(NagariError::SemanticError(format!(
+                "setter of property '{}' must take the instance and the new value",
+                method.name
+            )));
+        }
+        Ok(kind)
+    }
+
+    /// Whether the first parameter is the instance or, for class methods, the class
+    pub(super) fn has_receiver(self) -> bool {
+        self != MethodKind::Static
+    }
+}
+
+fn unsupported_decorators(method: &FunctionDef) -> NagariError {
+    NagariError::SemanticError(format!(
+        "method '{name}' can only be decorated with one of @property, @{name}.setter, \
+         @staticmethod and @classmethod",
+        name = method.name
+    ))
+}
+
+/// Whether `name` is class-private, like Python's `__name` but not `__name__`
+pub(super) fn is_private(name: &str) -> bool {
+    name.len() > 2 && name.starts_with("__") && !name.ends_with("__")
+}
+
+/// Whether `statement` is `super().__init__(...)`
+pub(super) fn is_super_init(statement: &Statement) -> bool {
+    let Statement::Expression(Expression::Call(call)) = statement else {
+        return false;
+    };
+    matches!(
+        call.function.as_ref(),
+        Expression::Attribute(attr) if attr.attribute == "__init__" && is_super(&attr.object)
+    )
+}
+
+fn is_super(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::Call(call) if matches!(call.function.as_ref(), Expression::Identifier(name) if name == "super")
+    )
+}
+
+impl JSTranspiler {
+    /// `name` as written inside the class being transpiled, whose private names take
+    /// `private_prefix`
+    pub(super) fn member_name(&self, name: &str) -> String {
+        match &self.private_prefix {
+            Some(prefix) if is_private(name) => format!("{}{}", prefix, &name[2..]),
+            _ => name.to_string(),
+        }
+    }
+
+    pub(super) fn transpile_class_def(&mut self, class_def: &ClassDef) -> Result<(), NagariError> {
+        self.classes.insert(class_def.name.clone());
+        self.record_class_signature(class_def);
+        if self.is_es5() {
+            let previous_prefix = self
+                .private_prefix
+                .replace(format!("_{}__", class_def.name));
+            let result = self.transpile_es5_class(class_def);
+            self.private_prefix = previous_prefix;
+            result?;
+            return self.apply_decorators(&class_def.name, &class_def.decorators);
+        }
+
+        self.add_indent();
+        self.output.push_str("class ");
+        self.output.push_str(&class_def.name);
+
+        if let Some(superclass) = &class_def.superclass {
+            self.output.push_str(" extends ");
+            self.output.push_str(superclass);
+        }
+
+        self.output.push_str(" {\n");
+        self.indent_level += 1;
+        let previous_prefix = self.private_prefix.replace("#".to_string());
+        let fields_at = self.output.len();
+
+        // Private members declared by the class body itself
+        let mut declared = BTreeSet::new();
+        for statement in &class_def.body {
+            match statement {
+                Statement::FunctionDef(method) if is_private(&method.name) => {
+                    declared.insert(method.name[2..].to_string());
+                }
+                Statement::Assignment(assign) if is_private(&assign.name) => {
+                    declared.insert(assign.name[2..].to_string());
+                }
+                _ => {}
+            }
+        }
+
+        for statement in &class_def.body {
+            match statement {
+                Statement::FunctionDef(method) => self.transpile_method(class_def, method)?,
+                Statement::Assignment(assign) => {
+                    self.add_indent();
+                    self.output.push_str("static ");
+                    self.output.push_str(&self.member_name(&assign.name));
+                    self.output.push_str(" = ");
+                    self.transpile_expression(&assign.value)?;
+                    self.output.push(';');
+                }
+                // Docstrings and `pass`
+                Statement::Expression(Expression::Literal(Literal::String(_)))
+                | Statement::Pass => continue,
+                _ => {
+                    return Err(NagariError::SemanticError(format!(
+                        "class '{}' may only contain methods and assignments",
+                        class_def.name
+                    )))
+                }
+            }
+            self.output.push('\n');
+        }
+
+        // Instance fields only appear as `self.__name`, and JavaScript needs them declared
+        let body = self.output.split_off(fields_at);
+        let used: BTreeSet<&str> = body
+            .match_indices(".#")
+            .map(|(at, _)| {
+                let rest = &body[at + 2..];
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+                    .unwrap_or(rest.len());
+                &rest[..end]
+            })
+            .filter(|name| !name.is_empty() && !declared.contains(*name))
+            .collect();
+        for name in used {
+            self.add_indent();
+            writeln!(self.output, "#{};", name);
+        }
+        self.output.push_str(&body);
+
+        self.private_prefix = previous_prefix;
+        self.indent_level -= 1;
+        self.add_indent();
+        self.output.push('}');
+
+        self.apply_decorators(&class_def.name, &class_def.decorators)
+    }
+
+    fn transpile_method(
+        &mut self,
+        class_def: &ClassDef,
+        method: &FunctionDef,
+    ) -> Result<(), NagariError> {
+        let kind = MethodKind::of(method)?;
+        if self.jsdoc {
+            let indent = "    ".repeat(self.indent_level);
+            self.output
+                .push_str(&declarations::jsdoc(method, kind.has_receiver(), &indent));
+        }
+        self.add_indent();
+        match kind {
+            MethodKind::Static | MethodKind::ClassMethod => self.output.push_str("static "),
+            MethodKind::Getter => self.output.push_str("get "),
+            MethodKind::Setter => self.output.push_str("set "),
+            MethodKind::Constructor | MethodKind::Method => {}
+        }
+        if method.is_async {
+            self.output.push_str("async ");
+        }
+        if method.is_generator {
+            self.output.push('*');
+        }
+        match kind {
+            MethodKind::Constructor => self.output.push_str("constructor"),
+            _ => self.output.push_str(&self.member_name(&method.name)),
+        }
+
+        let (receiver, parameters) = match method.parameters.split_first() {
+            Some((first, rest)) if kind.has_receiver() => (Some(first.name.as_str()), rest),
+            _ => (None, method.parameters.as_slice()),
+        };
+        let Some(receiver) = receiver else {
+            return self.transpile_function_rest(parameters, &method.body, None);
+        };
+
+        // A derived constructor cannot use `this` before calling the superclass constructor,
+        // which JavaScript requires even when Python code leaves it out
+        let mut body = std::borrow::Cow::Borrowed(&method.body);
+        let mut bound_after = 0;
+        if kind == MethodKind::Constructor && class_def.superclass.is_some() {
+            match method.body.iter().position(is_super_init) {
+                Some(position) => bound_after = position + 1,
+                None => {
+                    body.to_mut().insert(0, super_init());
+                    bound_after = 1;
+                }
+            }
+        }
+
+        // `cls(...)` in a class method constructs the class
+        let new_class =
+            kind == MethodKind::ClassMethod && self.classes.insert(receiver.to_string());
+        let result = self.transpile_function_rest(parameters, &body, Some((receiver, bound_after)));
+        if new_class {
+            self.classes.remove(receiver);
+        }
+        result
+    }
+
+    /// `super().__init__(...)` as `super(...)` and `super().method(...)` as
+    /// `super.method(...)`. Returns `false` when `call` is not one of those.
+    pub(super) fn transpile_super_call(
+        &mut self,
+        call: &CallExpression,
+    ) -> Result<bool, NagariError> {
+        let Expression::Attribute(attr) = call.function.as_ref() else {
+            return Ok(false);
+        };
+        if !is_super(&attr.object) {
+            return Ok(false);
+        }
+
+        self.output.push_str("super");
+        if attr.attribute != "__init__" {
+            self.output.push('.');
+            self.output.push_str(&attr.attribute);
+        }
+        self.output.push('(');
+        for (i, arg) in call.arguments.iter().enumerate() {
+            if i > 0 {
+                self.output.push_str(", ");
+            }
+            self.transpile_expression(arg)?;
+        }
+        self.output.push(')');
+        Ok(true)
+    }
+}
+
+/// `super().__init__()`
+fn super_init() -> Statement {
+    Statement::Expression(Expression::Call(CallExpression {
+        function: Box::new(Expression::Attribute(AttributeAccess {
+            object: Box::new(Expression::Call(CallExpression {
+                function: Box::new(Expression::Identifier("super".to_string())),
+                arguments: Vec::new(),
+                keyword_args: Vec::new(),
+            })),
+            attribute: "__init__".to_string(),
+        })),
+        arguments: Vec::new(),
+        keyword_args: Vec::new(),
+    }))
+}
@@ -0,0 +1,1275 @@
+// Lowering for the `es5` target.
+//
+// ES5 engines have no `let`/`const`, arrow functions, classes, template literals, `for...of`,
+// spread or async functions. On the es5 target declarations become `var`, `for...of` becomes an
+// index loop over `nagariIter()`, classes become constructor functions with prototype methods
+// and `Object.defineProperty()` accessors, with class-private `__name` members renamed
+// `_Class__name` as Python does, and async functions become regenerator-style state machines driven by `nagariAsync()`.
+// Features without an ES5 lowering are rejected at compile time instead of emitting code the
+// target cannot parse.
+
+use super::classes::MethodKind;
+use super::{js_parameters, JSTranspiler};
+use crate::ast::*;
+use crate::error::NagariError;
+
+pub const ES5_TARGET: &str = "es5";
+
+/// State machine being generated for the async function currently transpiled on es5
+#[derive(Debug, Default)]
+pub struct AsyncFrame {
+    next_state: usize,
+    /// Indentation of the `case` labels
+    case_indent: usize,
+    /// `(continue, break)` states of the enclosing loops, `None` for loops emitted as-is
+    loops: Vec<Option<(usize, usize)>>,
+    /// Variables that must keep their value between resumptions
+    hoisted: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LoopJump {
+    Break,
+    Continue,
+}
+
+fn unsupported(feature: &str) -> NagariError {
+    NagariError::SemanticError(format!("{} are not supported by the es5 target", feature))
+}
+
+fn misplaced_await() -> NagariError {
+    NagariError::SemanticError(
+        "the es5 target only supports `await` inside an async function, as a statement, \
+         an assignment value or a return value"
+            .to_string(),
+    )
+}
+
+impl JSTranspiler {
+    pub(super) fn is_es5(&self) -> bool {
+        self.target == ES5_TARGET
+    }
+
+    /// `var` on es5, `keyword` otherwise
+    pub(super) fn binding_keyword(&self, keyword: &'static str) -> &'static str {
+        if self.is_es5() {
+            "var"
+        } else {
+            keyword
+        }
+    }
+
+    /// Fail with a target error when `feature` is used on es5
+    pub(super) fn require_es6(&self, feature: &str) -> Result<(), NagariError> {
+        if self.is_es5() {
+            Err(unsupported(feature))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `(name, "[i]")` accessor pairs for positional unpacking
+    pub(super) fn indexed_targets(targets: &[String]) -> Vec<(String, String)> {
+        targets
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), format!("[{}]", i)))
+            .collect()
+    }
+
+    pub(super) fn next_temp_id(&mut self) -> usize {
+        self.temp_counter += 1;
+        self.temp_counter
+    }
+
+    /// Open a loop binding `variable` to each item of `iterable`. On es5 this is an index loop
+    /// over `nagariIter(iterable)`; `fresh_binding` redeclares `variable` even when it is known.
+    pub(super) fn push_for_of(
+        &mut self,
+        variable: &str,
+        iterable: &Expression,
+        fresh_binding: bool,
+    ) -> Result<(), NagariError> {
+        if !self.is_es5() {
+            self.output.push_str("for (const ");
+            self.output.push_str(variable);
+            self.output.push_str(" of ");
+            self.transpile_expression(iterable)?;
+            self.output.push_str(") {\n");
+            return Ok(());
+        }
+
+        let id = self.next_temp_id();
+        let (index, items) = (format!("__i{}", id), format!("__items{}", id));
+        self.used_helpers.insert("nagariIter".to_string());
+
+        write!(
+            self.output,
+            "for (var {} = 0, {} = nagariIter(",
+            index, items
+        );
+        self.transpile_expression(iterable)?;
+        writeln!(
+            self.output,
+            "); {} < {}.length; {}++) {{",
+            index, items, index
+        );
+
+        self.indent_level += 1;
+        self.add_indent();
+        if fresh_binding || !self.declared_variables.contains(variable) {
+            self.output.push_str("var ");
+        }
+        writeln!(self.output, "{} = {}[{}];", variable, items, index);
+        self.indent_level -= 1;
+        Ok(())
+    }
+
+    pub(super) fn transpile_es5_lambda(
+        &mut self,
+        lambda: &LambdaExpression,
+    ) -> Result<(), NagariError> {
+        self.output.push_str("function (");
+        self.output.push_str(&lambda.parameters.join(", "));
+        self.output.push_str(") { return ");
+        self.transpile_expression(&lambda.body)?;
+        self.output.push_str("; }");
+        Ok(())
+    }
+
+    /// f-strings and template literals as string concatenation
+    pub(super) fn transpile_es5_fstring(
+        &mut self,
+        fstring: &FStringExpression,
+    ) -> Result<(), NagariError> {
+        self.output.push_str("(\"\"");
+        for part in &fstring.parts {
+            self.output.push_str(" + ");
+            match part {
+                FStringPart::Text(text) => {
+                    self.transpile_literal(&Literal::String(text.clone()))?;
+                }
+                FStringPart::Expression(expr) => {
+                    self.output.push('(');
+                    self.transpile_expression(expr)?;
+                    self.output.push(')');
+                }
+                FStringPart::FormattedExpression {
+                    expression,
+                    format_spec,
+                } => {
+                    self.output.push('(');
+                    self.transpile_formatted_expression(expression, format_spec)?;
+                    self.output.push(')');
+                }
+            }
+        }
+        self.output.push(')');
+        Ok(())
+    }
+
+    pub(super) fn transpile_es5_template_literal(
+        &mut self,
+        template: &TemplateLiteral,
+    ) -> Result<(), NagariError> {
+        self.output.push_str("(\"\"");
+        for (i, part) in template.parts.iter().enumerate() {
+            if !part.is_empty() {
+                self.output.push_str(" + ");
+                self.transpile_literal(&Literal::String(part.clone()))?;
+            }
+            if let Some(expr) = template.expressions.get(i) {
+                self.output.push_str(" + (");
+                self.transpile_expression(expr)?;
+                self.output.push(')');
+            }
+        }
+        self.output.push(')');
+        Ok(())
+    }
+
+    /// Elements of a list literal containing spreads: `[].concat([a], nagariIter(rest))`
+    fn transpile_es5_spread_elements(
+        &mut self,
+        elements: &[Expression],
+    ) -> Result<(), NagariError> {
+        self.output.push_str("[].concat(");
+        let mut run_open = false;
+        for (i, element) in elements.iter().enumerate() {
+            match element {
+                Expression::Spread(inner) => {
+                    if run_open {
+                        self.output.push(']');
+                        run_open = false;
+                    }
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.used_helpers.insert("nagariIter".to_string());
+                    self.output.push_str("nagariIter(");
+                    self.transpile_expression(inner)?;
+                    self.output.push(')');
+                }
+                _ => {
+                    if run_open {
+                        self.output.push_str(", ");
+                    } else {
+                        if i > 0 {
+                            self.output.push_str(", ");
+                        }
+                        self.output.push('[');
+                        run_open = true;
+                    }
+                    self.transpile_expression(element)?;
+                }
+            }
+        }
+        if run_open {
+            self.output.push(']');
+        }
+        self.output.push(')');
+        Ok(())
+    }
+
+    pub(super) fn transpile_es5_list(
+        &mut self,
+        elements: &[Expression],
+    ) -> Result<(), NagariError> {
+        if elements.iter().any(|e| matches!(e, Expression::Spread(_))) {
+            return self.transpile_es5_spread_elements(elements);
+        }
+
+        self.output.push('[');
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                self.output.push_str(", ");
+            }
+            self.transpile_expression(element)?;
+        }
+        self.output.push(']');
+        Ok(())
+    }
+
+    /// Calls that need rewriting on es5: `super()` method calls and spread arguments.
+    /// Returns `false` when `call` can be transpiled as usual.
+    pub(super) fn transpile_es5_call(
+        &mut self,
+        call: &CallExpression,
+    ) -> Result<bool, NagariError> {
+        if let Expression::Attribute(attr) = call.function.as_ref() {
+            if let Expression::Call(inner) = attr.object.as_ref() {
+                if matches!(inner.function.as_ref(), Expression::Identifier(name) if name == "super")
+                {
+                    // super().__init__(...) / super().method(...) inside a lowered class
+                    if attr.attribute == "__init__" {
+                        self.output.push_str("__super.call(self");
+                    } else {
+                        self.output.push_str("__super.prototype.");
+                        self.output.push_str(&attr.attribute);
+                        self.output.push_str(".call(self");
+                    }
+                    for arg in &call.arguments {
+                        self.output.push_str(", ");
+                        self.transpile_expression(arg)?;
+                    }
+                    self.output.push(')');
+                    return Ok(true);
+                }
+            }
+        }
+
+        if !call
+            .arguments
+            .iter()
+            .any(|arg| matches!(arg, Expression::Spread(_)))
+        {
+            return Ok(false);
+        }
+
+        // f(...args) -> f.apply(null, args); obj.f(...args) -> obj.f.apply(obj, args)
+        let this_arg = match call.function.as_ref() {
+            Expression::Identifier(name) if self.builtin_mapper.get_mapping(name).is_none() => {
+                "null".to_string()
+            }
+            Expression::Attribute(attr) => match attr.object.as_ref() {
+                Expression::Identifier(object) => object.clone(),
+                _ => return Err(unsupported("spread arguments to computed receivers")),
+            },
+            _ => {
+                return Err(unsupported(
+                    "spread arguments to builtins and computed callees",
+                ))
+            }
+        };
+        self.transpile_expression(&call.function)?;
+        self.output.push_str(".apply(");
+        self.output.push_str(&this_arg);
+        self.output.push_str(", ");
+        self.transpile_es5_spread_elements(&call.arguments)?;
+        self.output.push(')');
+        Ok(true)
+    }
+
+    /// `a, b = value` through a temporary
+    pub(super) fn transpile_es5_unpacking(
+        &mut self,
+        targets: &[(String, String)],
+        value: &Expression,
+    ) -> Result<(), NagariError> {
+        let temp = format!("__unpack{}", self.next_temp_id());
+        self.output.push_str("var ");
+        self.output.push_str(&temp);
+        self.output.push_str(" = ");
+        self.transpile_expression(value)?;
+        self.output.push(';');
+
+        for (name, accessor) in targets {
+            self.output.push('\n');
+            self.add_indent();
+            if self.declared_variables.insert(name.clone()) {
+                self.output.push_str("var ");
+            }
+            write!(self.output, "{} = {}{};", name, temp, accessor);
+        }
+        Ok(())
+    }
+
+    /// Function declaration or expression with es5 parameter defaults, `var` hoisting and,
+    /// for async functions, a state machine body. Methods (`bind_self`) take their first
+    /// parameter from `this`; named ones are class constructors and also accept Python-style
+    /// calls without `new`.
+    pub(super) fn transpile_es5_function(
+        &mut self,
+        name: Option<&str>,
+        parameters: &[Parameter],
+        body: &[Statement],
+        is_async: bool,
+        bind_self: bool,
+    ) -> Result<(), NagariError> {
+        let (receiver, parameters) = match parameters.split_first() {
+            Some((first, rest)) if bind_self => (first.name.as_str(), rest),
+            _ => ("self", parameters),
+        };
+
+        self.output.push_str("function ");
+        if let Some(name) = name {
+            self.output.push_str(name);
+        }
+        self.output.push('(');
+        let js_order = js_parameters(parameters);
+        self.output.push_str(
+            &js_order
+                .iter()
+                .filter(|p| p.kind != ParameterKind::Rest)
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        self.output.push_str(") {\n");
+
+        let previous_declared = std::mem::take(&mut self.declared_variables);
+        let previous_sets = self.enter_set_scope(parameters);
+        let previous_frame = self.async_frame.take();
+        self.indent_level += 1;
+
+        let no_keywords = Expression::Dict(Vec::new());
+        for (index, param) in js_order.into_iter().enumerate() {
+            self.declared_variables.insert(param.name.clone());
+            if param.kind == ParameterKind::Rest {
+                // The rest parameter is the last
+                self.add_indent();
+                writeln!(
+                    self.output,
+                    "var {} = Array.prototype.slice.call(arguments, {});",
+                    param.name, index
+                );
+                continue;
+            }
+            let default = match param.kind {
+                ParameterKind::Keywords => Some(&no_keywords),
+                _ => param.default_value.as_ref(),
+            };
+            if let Some(default) = default {
+                self.add_indent();
+                writeln!(self.output, "if ({} === undefined) {{", param.name);
+                self.indent_level += 1;
+                self.add_indent();
+                self.output.push_str(&param.name);
+                self.output.push_str(" = ");
+                self.transpile_expression(default)?;
+                self.output.push_str(";\n");
+                self.indent_level -= 1;
+                self.add_indent();
+                self.output.push_str("}\n");
+            }
+        }
+
+        if let (true, Some(name)) = (bind_self, name) {
+            self.add_indent();
+            writeln!(self.output, "if (!(this instanceof {})) {{", name);
+            self.indent_level += 1;
+            self.add_indent();
+            writeln!(self.output,
+                "return new (Function.prototype.bind.apply({}, [null].concat(Array.prototype.slice.call(arguments))))();",
+                name
+            );
+            self.indent_level -= 1;
+            self.add_indent();
+            self.output.push_str("}\n");
+        }
+
+        if bind_self {
+            self.add_indent();
+            writeln!(self.output, "var {} = this;", receiver);
+            self.declared_variables.insert(receiver.to_string());
+        }
+
+        let mut locals = Vec::new();
+        collect_locals(body, &mut locals);
+        locals.retain(|local| !self.declared_variables.contains(local));
+        locals.sort();
+        locals.dedup();
+        if !locals.is_empty() {
+            self.add_indent();
+            writeln!(self.output, "var {};", locals.join(", "));
+            self.declared_variables.extend(locals);
+        }
+
+        if is_async {
+            self.transpile_es5_async_body(body)?;
+        } else {
+            for statement in body {
+                self.transpile_statement(statement)?;
+                self.output.push('\n');
+            }
+        }
+
+        self.indent_level -= 1;
+        self.add_indent();
+        self.output.push('}');
+
+        self.declared_variables = previous_declared;
+        self.sets = previous_sets;
+        self.async_frame = previous_frame;
+        Ok(())
+    }
+
+    /// Constructor function plus prototype methods, wrapped in an IIFE receiving the superclass
+    pub(super) fn transpile_es5_class(&mut self, class_def: &ClassDef) -> Result<(), NagariError> {
+        let name = &class_def.name;
+        let methods: Vec<&FunctionDef> = class_def
+            .body
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::FunctionDef(func) => Some(func),
+                _ => None,
+            })
+            .collect();
+        let constructor = methods
+            .iter()
+            .find(|func| matches!(MethodKind::of(func), Ok(MethodKind::Constructor)));
+
+        self.add_indent();
+        write!(self.output, "var {} = (function (", name);
+        if class_def.superclass.is_some() {
+            self.output.push_str("__super");
+        }
+        self.output.push_str(") {\n");
+        self.indent_level += 1;
+
+        self.add_indent();
+        match constructor {
+            Some(init) => {
+                self.transpile_es5_function(Some(name), &init.parameters, &init.body, false, true)?;
+            }
+            None => {
+                // Without __init__ the superclass constructor receives the arguments
+                let body: Vec<Statement> = class_def
+                    .superclass
+                    .iter()
+                    .map(|_| {
+                        Statement::Expression(Expression::Call(CallExpression {
+                            function: Box::new(Expression::Identifier("__super.apply".to_string())),
+                            arguments: vec![
+                                Expression::Identifier("self".to_string()),
+                                Expression::Identifier("arguments".to_string()),
+                            ],
+                            keyword_args: Vec::new(),
+                        }))
+                    })
+                    .collect();
+                self.transpile_es5_function(Some(name), &[], &body, false, true)?;
+            }
+        }
+        self.output.push('\n');
+
+        if class_def.superclass.is_some() {
+            self.add_indent();
+            writeln!(
+                self.output,
+                "{}.prototype = Object.create(__super.prototype);",
+                name
+            );
+            self.add_indent();
+            writeln!(self.output, "{}.prototype.constructor = {};", name, name);
+        }
+
+        for stmt in &class_def.body {
+            match stmt {
+                Statement::FunctionDef(func) => {
+                    let kind = MethodKind::of(func)?;
+                    if kind == MethodKind::Constructor {
+                        continue;
+                    }
+                    if func.is_generator {
+                        return Err(unsupported("generator methods"));
+                    }
+                    let member = self.member_name(&func.name);
+                    self.add_indent();
+                    match kind {
+                        MethodKind::Getter | MethodKind::Setter => {
+                            let accessor = if kind == MethodKind::Getter {
+                                "get"
+                            } else {
+                                "set"
+                            };
+                            write!(
+                                self.output,
+                                "Object.defineProperty({}.prototype, \"{}\", {{ {}: ",
+                                name, member, accessor
+                            );
+                        }
+                        MethodKind::Static | MethodKind::ClassMethod => {
+                            write!(self.output, "{}.{} = ", name, member);
+                        }
+                        MethodKind::Constructor | MethodKind::Method => {
+                            write!(self.output, "{}.prototype.{} = ", name, member);
+                        }
+                    }
+                    self.transpile_es5_function(
+                        None,
+                        &func.parameters,
+                        &func.body,
+                        func.is_async,
+                        kind.has_receiver(),
+                    )?;
+                    if matches!(kind, MethodKind::Getter | MethodKind::Setter) {
+                        self.output.push_str(", configurable: true })");
+                    }
+                    self.output.push_str(";\n");
+                }
+                Statement::Assignment(assign) => {
+                    self.add_indent();
+                    let member = self.member_name(&assign.name);
+                    write!(self.output, "{}.{} = ", name, member);
+                    self.transpile_expression(&assign.value)?;
+                    self.output.push_str(";\n");
+                }
+                Statement::Expression(Expression::Literal(Literal::String(_)))
+                | Statement::Pass => {}
+                _ => {
+                    return Err(unsupported(
+                        "class body statements other than methods and assignments",
+                    ))
+                }
+            }
+        }
+
+        self.add_indent();
+        writeln!(self.output, "return {};", name);
+        self.indent_level -= 1;
+        self.add_indent();
+        self.output.push_str("})(");
+        if let Some(superclass) = &class_def.superclass {
+            self.output.push_str(superclass);
+        }
+        self.output.push_str(");");
+        Ok(())
+    }
+
+    /// Body of an async function as a `nagariAsync` state machine: every `await` returns the
+    /// awaited value from the machine and resumes at the next `case` with it in `__ctx.sent`
+    fn transpile_es5_async_body(&mut self, body: &[Statement]) -> Result<(), NagariError> {
+        self.used_helpers.insert("nagariAsync".to_string());
+        let base_indent = self.indent_level;
+        let preamble = std::mem::take(&mut self.output);
+
+        self.indent_level = base_indent + 4;
+        self.async_frame = Some(AsyncFrame {
+            next_state: 1,
+            case_indent: base_indent + 3,
+            ..Default::default()
+        });
+        for statement in body {
+            self.lower_async_statement(statement)?;
+        }
+        self.add_indent();
+        self.output.push_str("__ctx.done = true;\n");
+        self.add_indent();
+        self.output.push_str("return;\n");
+
+        let frame = self.async_frame.take().unwrap_or_default();
+        let machine = std::mem::replace(&mut self.output, preamble);
+        self.indent_level = base_indent;
+
+        if !frame.hoisted.is_empty() {
+            self.add_indent();
+            writeln!(self.output, "var {};", frame.hoisted.join(", "));
+        }
+        self.add_indent();
+        self.output
+            .push_str("return nagariAsync(function (__ctx) {\n");
+        self.indent_level += 1;
+        self.add_indent();
+        self.output.push_str("while (true) {\n");
+        self.indent_level += 1;
+        self.add_indent();
+        self.output.push_str("switch (__ctx.state) {\n");
+        self.indent_level += 1;
+        self.add_indent();
+        self.output.push_str("case 0:\n");
+        self.output.push_str(machine.as_str());
+        for _ in 0..3 {
+            self.indent_level -= 1;
+            self.add_indent();
+            self.output.push_str(if self.indent_level == base_indent {
+                "});\n"
+            } else {
+                "}\n"
+            });
+        }
+        Ok(())
+    }
+
+    fn lower_async_statement(&mut self, stmt: &Statement) -> Result<(), NagariError> {
+        if !statement_contains_await(stmt) {
+            self.transpile_statement(stmt)?;
+            self.output.push('\n');
+            return Ok(());
+        }
+
+        match stmt {
+            Statement::For(for_loop) if for_loop.is_async => Err(unsupported("async for loops")),
+            Statement::With(with_stmt) if with_stmt.is_async => {
+                Err(unsupported("async with statements"))
+            }
+            Statement::Expression(Expression::Await(value)) => self.push_await(value),
+            Statement::Assignment(assign) => {
+                let Expression::Await(value) = &assign.value else {
+                    return Err(misplaced_await());
+                };
+                self.push_await(value)?;
+                self.hoist(&assign.name);
+                self.add_indent();
+                writeln!(self.output, "{} = __ctx.sent;", assign.name);
+                Ok(())
+            }
+            Statement::AttributeAssignment(assign)
+                if !expression_contains_await(&assign.object) =>
+            {
+                let Expression::Await(value) = &assign.value else {
+                    return Err(misplaced_await());
+                };
+                self.push_await(value)?;
+                self.add_indent();
+                self.transpile_expression(&assign.object)?;
+                writeln!(self.output, ".{} = __ctx.sent;", assign.attribute);
+                Ok(())
+            }
+            Statement::IndexAssignment(assign)
+                if !expression_contains_await(&assign.object)
+                    && !expression_contains_await(&assign.index) =>
+            {
+                let Expression::Await(value) = &assign.value else {
+                    return Err(misplaced_await());
+                };
+                self.push_await(value)?;
+                self.add_indent();
+                self.transpile_expression(&assign.object)?;
+                self.output.push('[');
+                self.transpile_expression(&assign.index)?;
+                self.output.push_str("] = __ctx.sent;\n");
+                Ok(())
+            }
+            Statement::Return(Some(Expression::Await(value))) => {
+                // Resolving with the awaited promise adopts its result
+                self.transpile_es5_async_return(Some(value))?;
+                self.output.push('\n');
+                Ok(())
+            }
+            Statement::If(if_stmt)
+                if !expression_contains_await(&if_stmt.condition)
+                    && if_stmt.elif_branches.is_empty() =>
+            {
+                let else_state = self.next_state();
+                self.push_jump_unless(&if_stmt.condition, else_state)?;
+                for stmt in &if_stmt.then_branch {
+                    self.lower_async_statement(stmt)?;
+                }
+                match &if_stmt.else_branch {
+                    Some(else_body) => {
+                        let end_state = self.next_state();
+                        self.add_indent();
+                        self.push_state_jump(end_state);
+                        self.output.push('\n');
+                        self.push_case(else_state);
+                        for stmt in else_body {
+                            self.lower_async_statement(stmt)?;
+                        }
+                        self.push_case(end_state);
+                    }
+                    None => self.push_case(else_state),
+                }
+                Ok(())
+            }
+            Statement::While(while_loop) if !expression_contains_await(&while_loop.condition) => {
+                let (top, end) = (self.next_state(), self.next_state());
+                self.push_case(top);
+                self.push_jump_unless(&while_loop.condition, end)?;
+                self.lower_async_loop_body(&while_loop.body, top, end)?;
+                self.add_indent();
+                self.push_state_jump(top);
+                self.output.push('\n');
+                self.push_case(end);
+                Ok(())
+            }
+            Statement::For(for_loop) if !expression_contains_await(&for_loop.iterable) => {
+                let id = self.next_temp_id();
+                let (index, items) = (format!("__i{}", id), format!("__items{}", id));
+                self.hoist(&index);
+                self.hoist(&items);
+                self.hoist(&for_loop.variable);
+                self.used_helpers.insert("nagariIter".to_string());
+
+                self.add_indent();
+                self.output.push_str(&items);
+                self.output.push_str(" = nagariIter(");
+                self.transpile_expression(&for_loop.iterable)?;
+                self.output.push_str(");\n");
+                self.add_indent();
+                writeln!(self.output, "{} = 0;", index);
+
+                let (top, next, end) = (self.next_state(), self.next_state(), self.next_state());
+                self.push_case(top);
+                self.add_indent();
+                writeln!(self.output, "if ({} >= {}.length) {{", index, items);
+                self.indent_level += 1;
+                self.add_indent();
+                self.push_state_jump(end);
+                self.output.push('\n');
+                self.indent_level -= 1;
+                self.add_indent();
+                self.output.push_str("}\n");
+                self.add_indent();
+                writeln!(self.output, "{} = {}[{}];", for_loop.variable, items, index);
+
+                self.lower_async_loop_body(&for_loop.body, next, end)?;
+                self.push_case(next);
+                self.add_indent();
+                writeln!(self.output, "{}++;", index);
+                self.add_indent();
+                self.push_state_jump(top);
+                self.output.push('\n');
+                self.push_case(end);
+                Ok(())
+            }
+            _ => Err(misplaced_await()),
+        }
+    }
+
+    fn lower_async_loop_body(
+        &mut self,
+        body: &[Statement],
+        continue_state: usize,
+        break_state: usize,
+    ) -> Result<(), NagariError> {
+        if let Some(frame) = &mut self.async_frame {
+            frame.loops.push(Some((continue_state, break_state)));
+        }
+        for stmt in body {
+            self.lower_async_statement(stmt)?;
+        }
+        if let Some(frame) = &mut self.async_frame {
+            frame.loops.pop();
+        }
+        Ok(())
+    }
+
+    fn next_state(&mut self) -> usize {
+        let frame = self.async_frame.get_or_insert_with(AsyncFrame::default);
+        frame.next_state += 1;
+        frame.next_state - 1
+    }
+
+    fn hoist(&mut self, name: &str) {
+        if self.declared_variables.insert(name.to_string()) {
+            if let Some(frame) = &mut self.async_frame {
+                frame.hoisted.push(name.to_string());
+            }
+        }
+    }
+
+    fn push_case(&mut self, state: usize) {
+        let indent = self
+            .async_frame
+            .as_ref()
+            .map_or(0, |frame| frame.case_indent);
+        self.output.indent(indent);
+        writeln!(self.output, "case {}:", state);
+    }
+
+    /// Suspend on `value` and resume at a new state
+    fn push_await(&mut self, value: &Expression) -> Result<(), NagariError> {
+        let state = self.next_state();
+        self.add_indent();
+        writeln!(self.output, "__ctx.state = {};", state);
+        self.add_indent();
+        self.output.push_str("return ");
+        self.transpile_expression(value)?;
+        self.output.push_str(";\n");
+        self.push_case(state);
+        Ok(())
+    }
+
+    fn push_jump_unless(
+        &mut self,
+        condition: &Expression,
+        state: usize,
+    ) -> Result<(), NagariError> {
+        self.add_indent();
+        self.output.push_str("if (!(");
+        self.transpile_expression(condition)?;
+        self.output.push_str(")) {\n");
+        self.indent_level += 1;
+        self.add_indent();
+        self.push_state_jump(state);
+        self.output.push('\n');
+        self.indent_level -= 1;
+        self.add_indent();
+        self.output.push_str("}\n");
+        Ok(())
+    }
+
+    /// Continue the state machine at `state`
+    pub(super) fn push_state_jump(&mut self, state: usize) {
+        writeln!(self.output, "__ctx.state = {};", state);
+        self.add_indent();
+        self.output.push_str("continue;");
+    }
+
+    /// Target state of a `break`/`continue` in a loop lowered into the state machine
+    pub(super) fn lowered_loop_target(&self, jump: LoopJump) -> Option<usize> {
+        let (continue_state, break_state) = (*self.async_frame.as_ref()?.loops.last()?)?;
+        Some(match jump {
+            LoopJump::Break => break_state,
+            LoopJump::Continue => continue_state,
+        })
+    }
+
+    /// Loops emitted as-is inside a state machine keep their own `break`/`continue`
+    pub(super) fn enter_native_loop(&mut self) {
+        if let Some(frame) = &mut self.async_frame {
+            frame.loops.push(None);
+        }
+    }
+
+    pub(super) fn exit_native_loop(&mut self) {
+        if let Some(frame) = &mut self.async_frame {
+            frame.loops.pop();
+        }
+    }
+
+    /// `return` from a state machine finishes it
+    pub(super) fn transpile_es5_async_return(
+        &mut self,
+        expr: Option<&Expression>,
+    ) -> Result<(), NagariError> {
+        self.add_indent();
+        self.output.push_str("__ctx.done = true;\n");
+        self.add_indent();
+        self.output.push_str("return");
+        if let Some(expr) = expr {
+            self.output.push(' ');
+            self.transpile_expression(expr)?;
+        }
+        self.output.push(';');
+        Ok(())
+    }
+
+    pub(super) fn generate_es5_iter_helper(&self) -> String {
+        r#"
+// Helper for es5 loops and spreads: the items of an array, string (by code point) or collection
+function nagariIter(obj) {
+    if (Array.isArray(obj)) return obj;
+    if (typeof obj === 'string') return obj.match(/[\uD800-\uDBFF][\uDC00-\uDFFF]|[\s\S]/g) || [];
+    if (obj !== null && typeof obj === 'object' && typeof obj.forEach === 'function') {
+        var items = [];
+        obj.forEach(function (value, key) {
+            items.push(typeof Map !== 'undefined' && obj instanceof Map ? [key, value] : value);
+        });
+        return items;
+    }
+    throw new TypeError("'" + typeof obj + "' object is not iterable");
+}
+
+"#
+        .to_string()
+    }
+
+    pub(super) fn generate_es5_async_helper(&self) -> String {
+        r#"
+// Helper driving es5 async functions: `body` runs until the next await and returns the awaited
+// value; it is resumed with the settled value in `ctx.sent` until it sets `ctx.done`
+function nagariAsync(body) {
+    if (typeof Promise === 'undefined') {
+        throw new Error('async functions on the es5 target require a Promise polyfill');
+    }
+    return new Promise(function (resolve, reject) {
+        var ctx = { state: 0, sent: undefined, done: false };
+        function step(value) {
+            var result;
+            ctx.sent = value;
+            try {
+                result = body(ctx);
+            } catch (error) {
+                reject(error);
+                return;
+            }
+            if (ctx.done) {
+                resolve(result);
+            } else {
+                Promise.resolve(result).then(step, reject);
+            }
+        }
+        step(undefined);
+    });
+}
+
+"#
+        .to_string()
+    }
+}
+
+/// Names assigned in `statements` outside nested functions, declared up front on es5 so that
+/// async functions keep them between resumptions
+fn collect_locals(statements: &[Statement], locals: &mut Vec<String>) {
+    for statement in statements {
+        match statement {
+            Statement::Assignment(assign) => locals.push(assign.name.clone()),
+            Statement::TupleAssignment(assign) => locals.extend(assign.targets.iter().cloned()),
+            Statement::ArrayDestructuringAssignment(assign) => {
+                locals.extend(assign.targets.iter().cloned())
+            }
+            Statement::If(if_stmt) => {
+                collect_locals(&if_stmt.then_branch, locals);
+                for elif in &if_stmt.elif_branches {
+                    collect_locals(&elif.body, locals);
+                }
+                if let Some(else_body) = &if_stmt.else_branch {
+                    collect_locals(else_body, locals);
+                }
+            }
+            Statement::While(while_loop) => collect_locals(&while_loop.body, locals),
+            Statement::For(for_loop) => {
+                locals.push(for_loop.variable.clone());
+                collect_locals(&for_loop.body, locals);
+            }
+            Statement::Try(try_stmt) => {
+                collect_locals(&try_stmt.body, locals);
+                for handler in &try_stmt.except_handlers {
+                    collect_locals(&handler.body, locals);
+                }
+                if let Some(else_body) = &try_stmt.else_clause {
+                    collect_locals(else_body, locals);
+                }
+                if let Some(finally_body) = &try_stmt.finally_clause {
+                    collect_locals(finally_body, locals);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn statements_contain_await(statements: &[Statement]) -> bool {
+    statements.iter().any(statement_contains_await)
+}
+
+/// Whether `stmt` awaits, not counting nested functions
+fn statement_contains_await(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Expression(expr) | Statement::Del(expr) => expression_contains_await(expr),
+        Statement::Return(expr) => expr.as_ref().is_some_and(expression_contains_await),
+        Statement::Assignment(assign) => expression_contains_await(&assign.value),
+        Statement::AttributeAssignment(assign) => {
+            expression_contains_await(&assign.object) || expression_contains_await(&assign.value)
+        }
+        Statement::IndexAssignment(assign) => {
+            expression_contains_await(&assign.object)
+                || expression_contains_await(&assign.index)
+                || expression_contains_await(&assign.value)
+        }
+        Statement::TupleAssignment(assign) => expression_contains_await(&assign.value),
+        Statement::DestructuringAssignment(assign) => expression_contains_await(&assign.value),
+        Statement::ArrayDestructuringAssignment(assign) => expression_contains_await(&assign.value),
+        Statement::If(if_stmt) => {
+            expression_contains_await(&if_stmt.condition)
+                || statements_contain_await(&if_stmt.then_branch)
+                || if_stmt.elif_branches.iter().any(|elif| {
+                    expression_contains_await(&elif.condition)
+                        || statements_contain_await(&elif.body)
+                })
+                || if_stmt
+                    .else_branch
+                    .as_deref()
+                    .is_some_and(statements_contain_await)
+        }
+        Statement::While(while_loop) => {
+            expression_contains_await(&while_loop.condition)
+                || statements_contain_await(&while_loop.body)
+        }
+        Statement::For(for_loop) => {
+            for_loop.is_async
+                || expression_contains_await(&for_loop.iterable)
+                || statements_contain_await(&for_loop.body)
+        }
+        Statement::Match(match_stmt) => {
+            expression_contains_await(&match_stmt.expression)
+                || match_stmt
+                    .cases
+                    .iter()
+                    .any(|case| statements_contain_await(&case.body))
+        }
+        Statement::With(with_stmt) => {
+            with_stmt.is_async
+                || with_stmt
+                    .items
+                    .iter()
+                    .any(|item| expression_contains_await(&item.context_expr))
+                || statements_contain_await(&with_stmt.body)
+        }
+        Statement::Try(try_stmt) => {
+            statements_contain_await(&try_stmt.body)
+                || try_stmt
+                    .except_handlers
+                    .iter()
+                    .any(|handler| statements_contain_await(&handler.body))
+                || try_stmt
+                    .else_clause
+                    .as_deref()
+                    .is_some_and(statements_contain_await)
+                || try_stmt
+                    .finally_clause
+                    .as_deref()
+                    .is_some_and(statements_contain_await)
+        }
+        Statement::Raise(raise) => {
+            raise
+                .exception
+                .as_ref()
+                .is_some_and(expression_contains_await)
+                || raise.cause.as_ref().is_some_and(expression_contains_await)
+        }
+        Statement::Yield(yield_stmt) => yield_stmt
+            .value
+            .as_ref()
+            .is_some_and(expression_contains_await),
+        Statement::YieldFrom(yield_from) => expression_contains_await(&yield_from.value),
+        Statement::ExportDefault(export) => expression_contains_await(&export.value),
+        _ => false,
+    }
+}
+
+/// Whether `expr` awaits, not counting nested functions
+fn expression_contains_await(expr: &Expression) -> bool {
+    let any = |exprs: &[Expression]| exprs.iter().any(expression_contains_await);
+    let pairs = |pairs: &[(Expression, Expression)]| {
+        pairs
+            .iter()
+            .any(|(k, v)| expression_contains_await(k) || expression_contains_await(v))
+    };
+    let generators = |generators: &[ComprehensionGenerator]| {
+        generators.iter().any(|generator| {
+            expression_contains_await(&generator.iter) || any(&generator.conditions)
+        })
+    };
+
+    match expr {
+        Expression::Await(_) => true,
+        Expression::Binary(binary) => {
+            expression_contains_await(&binary.left) || expression_contains_await(&binary.right)
+        }
+        Expression::Call(call) => {
+            expression_contains_await(&call.function)
+                || any(&call.arguments)
+                || call
+                    .keyword_args
+                    .iter()
+                    .any(|(_, value)| expression_contains_await(value))
+        }
+        Expression::List(items) | Expression::Tuple(items) | Expression::Set(items) => any(items),
+        Expression::Dict(items) | Expression::Dictionary(items) => pairs(items),
+        Expression::ListComprehension(comp) => {
+            expression_contains_await(&comp.element) || generators(&comp.generators)
+        }
+        Expression::SetComprehension(comp) => {
+            expression_contains_await(&comp.element) || generators(&comp.generators)
+        }
+        Expression::DictComprehension(comp) => {
+            expression_contains_await(&comp.key)
+                || expression_contains_await(&comp.value)
+                || generators(&comp.generators)
+        }
+        Expression::Generator(gen) => {
+            expression_contains_await(&gen.element) || generators(&gen.generators)
+        }
+        Expression::Ternary(ternary) => {
+            expression_contains_await(&ternary.condition)
+                || expression_contains_await(&ternary.true_expr)
+                || expression_contains_await(&ternary.false_expr)
+        }
+        Expression::Attribute(attr) => expression_contains_await(&attr.object),
+        Expression::Index(index) => {
+            expression_contains_await(&index.object) || expression_contains_await(&index.index)
+        }
+        Expression::Subscript(sub) => {
+            expression_contains_await(&sub.object) || expression_contains_await(&sub.index)
+        }
+        Expression::Slice(slice) => {
+            expression_contains_await(&slice.object)
+                || [&slice.start, &slice.end, &slice.step]
+                    .into_iter()
+                    .flatten()
+                    .any(|bound| expression_contains_await(bound))
+        }
+        Expression::Unary(unary) => expression_contains_await(&unary.operand),
+        Expression::NamedExpr(named) => expression_contains_await(&named.value),
+        Expression::Spread(inner) => expression_contains_await(inner),
+        Expression::TemplateLiteral(template) => any(&template.expressions),
+        Expression::FString(fstring) => fstring.parts.iter().any(|part| match part {
+            FStringPart::Text(_) => false,
+            FStringPart::Expression(expression)
+            | FStringPart::FormattedExpression { expression, .. } => {
+                expression_contains_await(expression)
+            }
+        }),
+        // Literals, names, JSX and nested functions
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime_package::DEFAULT_SPECIFIER;
+    use crate::test_program;
+
+    fn transpile_es5(program: &Program) -> Result<String, NagariError> {
+        super::super::transpile(
+            program,
+            ES5_TARGET,
+            false,
+            false,
+            None,
+            DEFAULT_SPECIFIER,
+            false,
+        )
+    }
+
+    fn es5(source: &str) -> String {
+        transpile_es5(&test_program(source)).expect("source should transpile to es5")
+    }
+
+    fn es5_error(source: &str) -> String {
+        es5_error_of(&test_program(source))
+    }
+
+    fn es5_error_of(program: &Program) -> String {
+        match transpile_es5(program) {
+            Err(NagariError::SemanticError(message)) => message,
+            other => panic!("expected an es5 error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_declarations_loops_and_defaults() {
+        let js = es5("a, b = pair\nfor x in [a, b]:\n    print(x)\n\ndef f(a, b = 2):\n    return a\n\ndouble = x -> x * 2\n");
+        for expected in [
+            "var __unpack1 = pair;\nvar a = __unpack1[0];\nvar b = __unpack1[1];\n",
+            "for (var __i2 = 0, __items2 = nagariIter([a, b]); __i2 < __items2.length; __i2++) {\n    var x = __items2[__i2];\n    console.log(x);\n}\n",
+            "function f(a, b) {\n    if (b === undefined) {\n        b = 2;\n    }\n    return a;\n}\n",
+            "var double = function (x) { return (x * 2); };\n",
+        ] {
+            assert!(js.contains(expected), "missing {expected:?} in\n{js}");
+        }
+        assert!(js.contains("function nagariIter("));
+    }
+
+    #[test]
+    fn test_classes_become_constructor_functions() {
+        let js = es5(
+            "class Account {\n    def __init__(self, amount):\n        self.__secret = amount\n    @property\n    def secret(self):\n        return self.__secret\n    @staticmethod\n    def empty():\n        return Account(0)\n}\n",
+        );
+        for expected in [
+            "var Account = (function () {\n    function Account(amount) {\n",
+            "        var self = this;\n        self._Account__secret = amount;\n    }\n",
+            "    Object.defineProperty(Account.prototype, \"secret\", { get: function () {\n        var self = this;\n        return self._Account__secret;\n    }, configurable: true });\n",
+            "    Account.empty = function () {\n        return Account(0);\n    };\n    return Account;\n})();\n",
+        ] {
+            assert!(js.contains(expected), "missing {expected:?} in\n{js}");
+        }
+    }
+
+    #[test]
+    fn test_async_functions_become_state_machines() {
+        let js = es5("async def load(url):\n    data = await fetch(url)\n    for item in data:\n        total = item\n    return total\n");
+        for expected in [
+            "function load(url) {\n    var data, item, total;\n    return nagariAsync(function (__ctx) {\n",
+            "                case 0:\n                    __ctx.state = 1;\n                    return fetch(url);\n                case 1:\n                    data = __ctx.sent;\n",
+            "                        item = __items1[__i1];\n",
+            "                    __ctx.done = true;\n                    return total;\n",
+            "function nagariAsync(body) {",
+        ] {
+            assert!(js.contains(expected), "missing {expected:?} in\n{js}");
+        }
+
+        assert!(
+            es5_error("async def load(url):\n    print(await fetch(url))\n")
+                .starts_with("the es5 target only supports `await` inside an async function")
+        );
+    }
+
+    #[test]
+    fn test_features_without_a_lowering_are_rejected() {
+        let generator = Program {
+            statements: vec![Statement::FunctionDef(FunctionDef {
+                name: "items".to_string(),
+                parameters: Vec::new(),
+                return_type: None,
+                body: vec![Statement::Yield(YieldStatement {
+                    value: Some(Expression::Literal(Literal::Int(1))),
+                })],
+                is_async: false,
+                decorators: Vec::new(),
+                is_generator: true,
+            })],
+        };
+        assert_eq!(
+            es5_error_of(&generator),
+            "generator functions are not supported by the es5 target"
+        );
+    }
+
+    #[test]
+    fn test_locals_and_awaits_outside_nested_functions() {
+        let program = test_program(
+            "if ready:\n    first = 1\nelse:\n    second = await load()\nwhile busy:\n    third = 3\n\ndef nested():\n    fourth = await other()\n",
+        );
+        let mut locals = Vec::new();
+        collect_locals(&program.statements, &mut locals);
+        assert_eq!(locals, ["first", "second", "third"]);
+
+        assert!(statements_contain_await(&program.statements[..1]));
+        assert!(!statements_contain_await(&program.statements[1..]));
+    }
+}
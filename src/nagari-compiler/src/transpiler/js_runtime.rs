@@ -1,17 +1,20 @@
 // JavaScript runtime helpers and builtin mappings
 
+use crate::environment::{Environment, Feature};
 use std::collections::HashMap;
 
 pub struct JSRuntime {
     target: String,
+    environment: Option<Environment>,
     builtin_mappings: HashMap<String, String>,
 }
 
 #[allow(dead_code)]
 impl JSRuntime {
-    pub fn new(target: &str) -> Self {
+    pub fn new(target: &str, environment: Option<Environment>) -> Self {
         let mut runtime = Self {
             target: target.to_string(),
+            environment,
             builtin_mappings: HashMap::new(),
         };
         runtime.init_builtin_mappings();
@@ -22,7 +25,7 @@ impl JSRuntime {
         // Nagari builtin -> JavaScript equivalent
         self.builtin_mappings.insert("print".to_string(), "console.log".to_string());
         self.builtin_mappings.insert("input".to_string(), "prompt".to_string());
-        self.builtin_mappings.insert("len".to_string(), "length".to_string());
+        self.builtin_mappings.insert("len".to_string(), "nagariLen".to_string());
         self.builtin_mappings.insert("str".to_string(), "String".to_string());
         self.builtin_mappings.insert("int".to_string(), "parseInt".to_string());
         self.builtin_mappings.insert("float".to_string(), "parseFloat".to_string());
@@ -48,7 +51,21 @@ impl JSRuntime {
         self.builtin_mappings.get(name)
     }
 
+    /// Whether the output can use `feature` without checking for it, which takes an
+    /// environment that provides it
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.environment
+            .is_some_and(|environment| environment.supports(feature))
+    }
+
     pub fn generate_runtime_helpers(&self) -> String {
+        let es2015 = self
+            .environment
+            .is_none_or(|environment| environment.supports(Feature::Es2015Syntax));
+        if self.target == "es5" || !es2015 {
+            return self.generate_es5_runtime_helpers();
+        }
+
         let mut helpers = String::new();
 
         // Helper functions that don't exist in JS
@@ -109,7 +126,7 @@ function zip(...iterables) {
         r#"
 // Python-style sum function
 function sum(iterable, start = 0) {
-    return iterable.reduce((acc, val) => acc + val, start);
+    return Array.from(iterable).reduce((acc, val) => acc + val, start);
 }
 
 "#.to_string()
@@ -170,6 +187,81 @@ function setComp(iterable, transform, condition = () => true) {
 "#.to_string()
     }
 
+    /// The helpers above without default parameters, rest parameters or arrow functions
+    fn generate_es5_runtime_helpers(&self) -> String {
+        r#"
+// Python-style range function
+function range(start, stop, step) {
+    var result = [];
+    var i;
+    if (arguments.length === 1) {
+        stop = start;
+        start = 0;
+    }
+    if (step === undefined) step = 1;
+    if (step > 0) {
+        for (i = start; i < stop; i += step) {
+            result.push(i);
+        }
+    } else {
+        for (i = start; i > stop; i += step) {
+            result.push(i);
+        }
+    }
+    return result;
+}
+
+// Python-style zip function
+function zip() {
+    var iterables = Array.prototype.slice.call(arguments);
+    var length = iterables.length ? Infinity : 0;
+    var result = [];
+    var i, j, row;
+    for (j = 0; j < iterables.length; j++) {
+        length = Math.min(length, iterables[j].length);
+    }
+    for (i = 0; i < length; i++) {
+        row = [];
+        for (j = 0; j < iterables.length; j++) {
+            row.push(iterables[j][i]);
+        }
+        result.push(row);
+    }
+    return result;
+}
+
+// Python-style sum function
+function sum(iterable, start) {
+    return iterable.reduce(function (acc, val) { return acc + val; }, start === undefined ? 0 : start);
+}
+
+// Python-style enumerate function
+function enumerate(iterable, start) {
+    if (start === undefined) start = 0;
+    return iterable.map(function (item, index) { return [index + start, item]; });
+}
+
+// List comprehension helpers
+function listComp(iterable, transform, condition) {
+    return iterable.filter(condition || function () { return true; }).map(transform);
+}
+
+function dictComp(iterable, keyTransform, valueTransform, condition) {
+    var result = {};
+    iterable.filter(condition || function () { return true; }).forEach(function (item) {
+        result[keyTransform(item)] = valueTransform(item);
+    });
+    return result;
+}
+
+function setComp(iterable, transform, condition) {
+    return new Set(iterable.filter(condition || function () { return true; }).map(transform));
+}
+
+"#
+        .to_string()
+    }
+
     #[allow(dead_code)]
     pub fn wrap_async_function(&self, function_name: &str, is_async: bool) -> String {
         if is_async {
@@ -186,9 +278,20 @@ function setComp(iterable, transform, condition = () => true) {
         }
     }
 
+    /// Shims for what the environment lacks; without an environment, everything the target
+    /// might lack along with stubs for the APIs of other environments
     pub fn generate_polyfills(&self) -> String {
+        if let Some(environment) = self.environment {
+            // The ES5 shims define `globalThis` too
+            return if environment.supports(Feature::Es2017Builtins) {
+                String::new()
+            } else {
+                self.generate_es5_polyfills()
+            };
+        }
         match self.target.as_str() {
             "node" => self.generate_node_polyfills(),
+            "es5" => self.generate_es5_polyfills(),
             _ => self.generate_browser_polyfills(),
         }
     }
@@ -217,6 +320,91 @@ if (typeof window === 'undefined') {
 "#.to_string()
     }
 
+    /// ES5 shims for the ES2015+ library methods the generated code calls
+    fn generate_es5_polyfills(&self) -> String {
+        r#"
+// ES5 compatibility shims
+var __nagari_global = typeof globalThis !== 'undefined' ? globalThis
+    : typeof window !== 'undefined' ? window
+    : typeof global !== 'undefined' ? global
+    : Function('return this')();
+if (typeof __nagari_global.globalThis === 'undefined') {
+    __nagari_global.globalThis = __nagari_global;
+}
+
+if (!String.prototype.repeat) {
+    String.prototype.repeat = function (count) {
+        var result = '';
+        for (var i = 0; i < count; i++) result += this;
+        return result;
+    };
+}
+
+if (!String.prototype.padStart) {
+    String.prototype.padStart = function (length, fill) {
+        var s = String(this);
+        fill = fill === undefined ? ' ' : String(fill);
+        if (s.length >= length || !fill) return s;
+        var padding = fill.repeat(Math.ceil((length - s.length) / fill.length));
+        return padding.slice(0, length - s.length) + s;
+    };
+}
+
+if (!String.prototype.padEnd) {
+    String.prototype.padEnd = function (length, fill) {
+        var s = String(this);
+        fill = fill === undefined ? ' ' : String(fill);
+        if (s.length >= length || !fill) return s;
+        var padding = fill.repeat(Math.ceil((length - s.length) / fill.length));
+        return s + padding.slice(0, length - s.length);
+    };
+}
+
+if (!String.prototype.startsWith) {
+    String.prototype.startsWith = function (search, position) {
+        position = position || 0;
+        return this.substr(position, search.length) === search;
+    };
+}
+
+if (!String.prototype.endsWith) {
+    String.prototype.endsWith = function (search, length) {
+        if (length === undefined || length > this.length) length = this.length;
+        return this.substring(length - search.length, length) === search;
+    };
+}
+
+if (!String.prototype.includes) {
+    String.prototype.includes = function (search, start) {
+        return this.indexOf(search, start || 0) !== -1;
+    };
+}
+
+if (!Array.prototype.includes) {
+    Array.prototype.includes = function (item) {
+        for (var i = 0; i < this.length; i++) {
+            if (this[i] === item || (item !== item && this[i] !== this[i])) return true;
+        }
+        return false;
+    };
+}
+
+if (!Object.values) {
+    Object.values = function (obj) {
+        return Object.keys(obj).map(function (key) { return obj[key]; });
+    };
+}
+
+if (!Object.entries) {
+    Object.entries = function (obj) {
+        return Object.keys(obj).map(function (key) { return [key, obj[key]]; });
+    };
+}
+
+"#
+        .to_string()
+    }
+
     fn generate_browser_polyfills(&self) -> String {
         r#"
 // Browser polyfills
@@ -0,0 +1,227 @@
+// Keyword arguments on the JavaScript targets.
+//
+// JavaScript has no keyword arguments. A call to a function or class defined earlier in the
+// module gets them placed by parameter name, with `undefined` for the parameters in between so
+// their defaults apply, and is checked the way Python checks it: every keyword has to name a
+// parameter not given positionally, and every parameter without a default has to be given.
+// Anything else, such as a JavaScript library, gets them as an options object after the
+// positional arguments, with `**mapping` arguments merged into it by `Object.assign()`.
+//
+// A `*args` parameter is a JavaScript rest parameter and a `**kwargs` one such an options
+// object, defaulting to `{}`. A function taking both takes the options object first, as the rest
+// parameter has to come last, so calls to one defined in the module pass `undefined` in its place
+// when there are extra positional arguments but no keywords for it.
+
+use super::{JSTranspiler, Signature};
+use crate::ast::*;
+use crate::error::NagariError;
+
+impl JSTranspiler {
+    /// Remember the parameters of a function defined in the module, by which calls to it place
+    /// their keyword arguments
+    pub(super) fn record_signature(&mut self, name: &str, parameters: &[Parameter]) {
+        let has = |kind| parameters.iter().any(|parameter| parameter.kind == kind);
+        let signature = Signature {
+            parameters: parameters
+                .iter()
+                .filter(|parameter| parameter.kind == ParameterKind::Positional)
+                .map(|parameter| (parameter.name.clone(), parameter.default_value.is_some()))
+                .collect(),
+            rest: has(ParameterKind::Rest),
+            keywords: has(ParameterKind::Keywords),
+        };
+        self.signatures.insert(name.to_string(), signature);
+    }
+
+    /// Remember the parameters of a class's `__init__`, without the instance
+    pub(super) fn record_class_signature(&mut self, class_def: &ClassDef) {
+        let init = class_def.body.iter().find_map(|statement| match statement {
+            Statement::FunctionDef(method) if method.name == "__init__" => Some(method),
+            _ => None,
+        });
+        match init {
+            Some(init) => {
+                self.record_signature(&class_def.name, init.parameters.get(1..).unwrap_or(&[]))
+            }
+            None => {
+                self.signatures.remove(&class_def.name);
+            }
+        }
+    }
+
+    /// `call` with its keyword arguments made positional or gathered in an options object;
+    /// `None` if it has none and its positional arguments are already in place
+    pub(super) fn lower_keyword_arguments(
+        &self,
+        call: &CallExpression,
+    ) -> Result<Option<CallExpression>, NagariError> {
+        let signature = match call.function.as_ref() {
+            Expression::Identifier(name) => self.signatures.get(name),
+            _ => None,
+        };
+        // Extra positional arguments have to skip the options object of `**kwargs`
+        let skips_keywords = signature.is_some_and(|signature| {
+            signature.rest
+                && signature.keywords
+                && (call.arguments.len() > signature.parameters.len()
+                    || call
+                        .arguments
+                        .iter()
+                        .any(|arg| matches!(arg, Expression::Spread(_))))
+        });
+        if call.keyword_args.is_empty() && !skips_keywords {
+            return Ok(None);
+        }
+        let arguments = match call.function.as_ref() {
+            Expression::Identifier(name) if signature.is_some() => {
+                self.place_keyword_arguments(name, call)?
+            }
+            _ => {
+                let mut arguments = call.arguments.clone();
+                arguments.push(self.options_object(&call.keyword_args)?);
+                arguments
+            }
+        };
+        Ok(Some(CallExpression {
+            function: call.function.clone(),
+            arguments,
+            keyword_args: Vec::new(),
+        }))
+    }
+
+    /// The arguments of a call to the function `name`, with its keyword arguments placed
+    /// after the positional ones by parameter name
+    fn place_keyword_arguments(
+        &self,
+        name: &str,
+        call: &CallExpression,
+    ) -> Result<Vec<Expression>, NagariError> {
+        let signature = &self.signatures[name];
+        let parameters = &signature.parameters;
+        let error =
+            |message: String| Err(NagariError::SemanticError(format!("{name}() {message}")));
+        if call
+            .arguments
+            .iter()
+            .any(|arg| matches!(arg, Expression::Spread(_)))
+        {
+            return error(if call.keyword_args.is_empty() {
+                "can't take *arguments, as it takes both *args and **kwargs".to_string()
+            } else {
+                "can't take keyword arguments after *arguments".to_string()
+            });
+        }
+        if call.arguments.len() > parameters.len() && !signature.rest {
+            return error(format!(
+                "takes {} positional argument(s) but {} were given",
+                parameters.len(),
+                call.arguments.len()
+            ));
+        }
+
+        let (positional, extra) = call
+            .arguments
+            .split_at(call.arguments.len().min(parameters.len()));
+        let mut arguments: Vec<Option<Expression>> = positional.iter().cloned().map(Some).collect();
+        let mut unexpected = Vec::new();
+        for (keyword, value) in &call.keyword_args {
+            if let Expression::Spread(_) = value {
+                return error(
+                    "can't take **arguments, as its keyword arguments are placed when compiling"
+                        .to_string(),
+                );
+            }
+            let Some(index) = parameters.iter().position(|(name, _)| name == keyword) else {
+                if signature.keywords {
+                    unexpected.push((keyword.clone(), value.clone()));
+                    continue;
+                }
+                return error(format!("got an unexpected keyword argument '{keyword}'"));
+            };
+            if arguments.len() <= index {
+                arguments.resize(index + 1, None);
+            }
+            if arguments[index].is_some() {
+                return error(format!("got multiple values for argument '{keyword}'"));
+            }
+            arguments[index] = Some(value.clone());
+        }
+
+        for (index, (parameter, has_default)) in parameters.iter().enumerate() {
+            if !has_default && arguments.get(index).is_none_or(Option::is_none) {
+                return error(format!("missing required argument '{parameter}'"));
+            }
+        }
+
+        let undefined = || Expression::Identifier("undefined".to_string());
+        let mut arguments: Vec<Expression> = arguments
+            .into_iter()
+            .map(|argument| argument.unwrap_or_else(undefined))
+            .collect();
+        if !unexpected.is_empty() || (signature.keywords && !extra.is_empty()) {
+            arguments.resize_with(parameters.len(), undefined);
+            arguments.push(if unexpected.is_empty() {
+                undefined()
+            } else {
+                self.options_object(&unexpected)?
+            });
+        }
+        arguments.extend(extra.iter().cloned());
+        Ok(arguments)
+    }
+
+    /// `{name: value, ...}`, or `Object.assign({name: value}, mapping, ...)` with `**mapping`
+    /// arguments
+    fn options_object(&self, keywords: &[(String, Expression)]) -> Result<Expression, NagariError> {
+        let mut parts = vec![Vec::new()];
+        let mut mappings = Vec::new();
+        for (name, value) in keywords {
+            match value {
+                Expression::Spread(mapping) => {
+                    mappings.push(mapping.as_ref().clone());
+                    parts.push(Vec::new());
+                }
+                value => parts.last_mut().expect("at least one part").push((
+                    Expression::Literal(Literal::String(name.clone())),
+                    value.clone(),
+                )),
+            }
+        }
+        if mappings.is_empty() {
+            return Ok(Expression::Dict(parts.remove(0)));
+        }
+        self.require_es6("**arguments")?;
+
+        // The keywords and mappings in the order they were written, later ones winning
+        let mut arguments = Vec::new();
+        for (part, mapping) in parts
+            .into_iter()
+            .zip(mappings.into_iter().map(Some).chain([None]))
+        {
+            if !part.is_empty() || arguments.is_empty() {
+                arguments.push(Expression::Dict(part));
+            }
+            arguments.extend(mapping);
+        }
+        Ok(Expression::Call(CallExpression {
+            function: Box::new(Expression::Attribute(AttributeAccess {
+                object: Box::new(Expression::Identifier("Object".to_string())),
+                attribute: "assign".to_string(),
+            })),
+            arguments,
+            keyword_args: Vec::new(),
+        }))
+    }
+}
+
+/// `parameters` in the order of the JavaScript function: a `**kwargs` options object comes
+/// before `*args`, as a rest parameter has to be the last
+pub(crate) fn js_parameters(parameters: &[Parameter]) -> Vec<&Parameter> {
+    let mut parameters: Vec<&Parameter> = parameters.iter().collect();
+    if let [.., rest, keywords] = parameters.as_mut_slice() {
+        if rest.kind == ParameterKind::Rest && keywords.kind == ParameterKind::Keywords {
+            std::mem::swap(rest, keywords);
+        }
+    }
+    parameters
+}
@@ -1,58 +1,356 @@
 // Main transpiler module that coordinates all components
 
 use crate::ast::*;
+use crate::declarations;
+use crate::environment::{Environment, Feature};
 use crate::error::NagariError;
+use crate::runtime_package;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
 
 mod builtin_map;
+mod classes;
+mod es5;
 mod js_runtime;
+mod keywords;
 mod modules;
+mod sets;
+mod writer;
 
 use builtin_map::BuiltinMapper;
+use es5::{AsyncFrame, LoopJump};
 use js_runtime::JSRuntime;
+pub(crate) use keywords::js_parameters;
 use modules::ModuleResolver;
-
-pub fn transpile(program: &Program, target: &str, jsx: bool) -> Result<String, NagariError> {
-    let mut transpiler = JSTranspiler::new(target, jsx);
+use writer::Writer;
+
+pub fn transpile(
+    program: &Program,
+    target: &str,
+    jsx: bool,
+    ssr: bool,
+    environment: Option<Environment>,
+    runtime: &str,
+    jsdoc: bool,
+) -> Result<String, NagariError> {
+    let mut transpiler = JSTranspiler::new(target, jsx, environment);
+    transpiler.ssr = ssr;
+    transpiler.runtime = runtime.to_string();
+    transpiler.jsdoc = jsdoc;
     transpiler.transpile_program(program)
 }
 
+/// A top-level statement the transpiler failed on with an internal error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternalError {
+    /// Name of the function or class the statement defines, if any
+    pub declaration: Option<String>,
+    pub message: String,
+}
+
+/// Like [`transpile`], but a panic while transpiling a top-level statement only loses that
+/// statement: its output becomes code throwing an error when it runs (a function that throws,
+/// for a function or class) and the others are transpiled as usual. Returns the output with the
+/// internal errors it recovered from.
+pub fn transpile_recovering(
+    program: &Program,
+    target: &str,
+    jsx: bool,
+    ssr: bool,
+    environment: Option<Environment>,
+    runtime: &str,
+    jsdoc: bool,
+) -> Result<(String, Vec<InternalError>), NagariError> {
+    let mut transpiler = JSTranspiler::new(target, jsx, environment);
+    transpiler.ssr = ssr;
+    transpiler.runtime = runtime.to_string();
+    transpiler.jsdoc = jsdoc;
+    transpiler.recover = true;
+    let js_code = transpiler.transpile_program(program)?;
+    Ok((js_code, transpiler.internal_errors))
+}
+
+/// Transpiler state carried from one top-level statement to the next
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SectionState {
+    /// Variables declared so far, which decide between `let x = ...` and `x = ...`
+    pub declared: BTreeSet<String>,
+    /// Last number used for a generated temporary
+    pub temp_counter: usize,
+    /// Classes defined so far, which calls construct with `new`
+    pub classes: BTreeSet<String>,
+    /// Variables known to hold sets, whose operators work on their elements
+    pub sets: BTreeSet<String>,
+    /// Parameters of the functions and classes defined so far, which calls place keyword
+    /// arguments by
+    pub signatures: BTreeMap<String, Signature>,
+}
+
+/// Parameters of a function or class defined in the module
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Signature {
+    /// Each ordinary parameter with whether it has a default
+    pub parameters: Vec<(String, bool)>,
+    /// Whether a `*args` parameter takes the positional arguments left over
+    pub rest: bool,
+    /// Whether a `**kwargs` parameter takes the keyword arguments no other parameter does
+    pub keywords: bool,
+}
+
+/// Output of some top-level statements, to be stitched between [`transpile_header`] and
+/// [`transpile_footer`]
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub js_code: String,
+    pub helpers: HashSet<String>,
+    pub state: SectionState,
+}
+
+/// Everything the output of a program starts with, whatever its statements
+pub fn transpile_header(
+    target: &str,
+    jsx: bool,
+    ssr: bool,
+    environment: Option<Environment>,
+    runtime: &str,
+) -> String {
+    let mut transpiler = JSTranspiler::new(target, jsx, environment);
+    transpiler.ssr = ssr;
+    transpiler.runtime = runtime.to_string();
+    transpiler.push_header();
+    transpiler.output.into_string()
+}
+
+/// Transpile top-level `statements` as if the statements before them left `state`
+pub fn transpile_section(
+    statements: &[Statement],
+    target: &str,
+    jsx: bool,
+    ssr: bool,
+    jsdoc: bool,
+    state: &SectionState,
+) -> Result<Section, NagariError> {
+    let mut transpiler = JSTranspiler::new(target, jsx, None);
+    transpiler.ssr = ssr;
+    transpiler.jsdoc = jsdoc;
+    transpiler.declared_variables = state.declared.iter().cloned().collect();
+    transpiler.temp_counter = state.temp_counter;
+    transpiler.classes = state.classes.iter().cloned().collect();
+    transpiler.sets = state.sets.iter().cloned().collect();
+    transpiler.signatures = state.signatures.clone().into_iter().collect();
+    for statement in statements {
+        transpiler.transpile_statement(statement)?;
+        transpiler.output.push('\n');
+    }
+    Ok(Section {
+        js_code: transpiler.output.into_string(),
+        helpers: transpiler.used_helpers,
+        state: SectionState {
+            declared: transpiler.declared_variables.into_iter().collect(),
+            temp_counter: transpiler.temp_counter,
+            classes: transpiler.classes.into_iter().collect(),
+            sets: transpiler.sets.into_iter().collect(),
+            signatures: transpiler.signatures.into_iter().collect(),
+        },
+    })
+}
+
+/// The runtime helpers ending the output of a program that uses `helpers`
+pub fn transpile_footer(
+    target: &str,
+    jsx: bool,
+    environment: Option<Environment>,
+    helpers: HashSet<String>,
+) -> String {
+    let mut transpiler = JSTranspiler::new(target, jsx, environment);
+    transpiler.used_helpers = helpers;
+    transpiler.push_helpers();
+    transpiler.output.into_string()
+}
+
+/// Name of the function or class `statement` defines
+fn defined_name(statement: &Statement) -> Option<String> {
+    match statement {
+        Statement::FunctionDef(FunctionDef { name, .. })
+        | Statement::ClassDef(ClassDef { name, .. }) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// What replaces a top-level statement the transpiler failed on: a function throwing `thrown`
+/// in place of a function or class, so that the module still loads, or else a statement
+/// throwing it
+fn internal_error_stub(statement: &Statement, thrown: &str) -> Statement {
+    let throw = Statement::Expression(Expression::InlineJs(format!(
+        "throw new Error({});",
+        serde_json::to_string(thrown).unwrap_or_default()
+    )));
+    match statement {
+        Statement::ExportDeclaration(export) if defined_name(&export.declaration).is_some() => {
+            Statement::ExportDeclaration(ExportDeclarationStatement {
+                declaration: Box::new(internal_error_stub(&export.declaration, thrown)),
+            })
+        }
+        _ => match defined_name(statement) {
+            Some(name) => Statement::FunctionDef(FunctionDef {
+                name,
+                parameters: Vec::new(),
+                return_type: None,
+                body: vec![throw],
+                is_async: false,
+                decorators: Vec::new(),
+                is_generator: false,
+            }),
+            None => throw,
+        },
+    }
+}
+
 struct JSTranspiler {
     target: String,
     jsx_enabled: bool,
+    /// Build JSX into the runtime's plain element trees for `render_to_string`, not React's
+    ssr: bool,
     indent_level: usize,
-    output: String,
+    output: Writer,
     module_resolver: ModuleResolver,
     js_runtime: JSRuntime,
     builtin_mapper: BuiltinMapper,
     used_helpers: std::collections::HashSet<String>,
     declared_variables: std::collections::HashSet<String>,
     required_imports: std::collections::HashSet<String>,
+    /// Counter for generated temporaries (`__i1`, `__unpack2`, ...)
+    temp_counter: usize,
+    /// State machine of the async function being lowered on es5
+    async_frame: Option<AsyncFrame>,
+    /// Specifier the runtime helpers are imported from
+    runtime: String,
+    /// Put JSDoc comments typing functions above them
+    jsdoc: bool,
+    /// Classes defined so far, which calls construct with `new`
+    classes: std::collections::HashSet<String>,
+    /// Variables known to hold sets, whose operators work on their elements
+    sets: std::collections::HashSet<String>,
+    /// Parameters of the functions and classes defined so far, which calls place keyword
+    /// arguments by
+    signatures: HashMap<String, Signature>,
+    /// What the class-private `__name` members of the class being transpiled are renamed with
+    private_prefix: Option<String>,
+    /// Whether a panic on a top-level statement is recovered from, see [`transpile_recovering`]
+    recover: bool,
+    /// Top-level statements replaced after a panic
+    internal_errors: Vec<InternalError>,
 }
 
 impl JSTranspiler {
-    fn new(target: &str, jsx: bool) -> Self {
+    fn new(target: &str, jsx: bool, environment: Option<Environment>) -> Self {
         Self {
             target: target.to_string(),
             jsx_enabled: jsx,
+            ssr: false,
             indent_level: 0,
-            output: String::new(),
+            output: Writer::default(),
             module_resolver: ModuleResolver::new(target),
-            js_runtime: JSRuntime::new(target),
+            js_runtime: JSRuntime::new(target, environment),
             builtin_mapper: BuiltinMapper::new(),
             used_helpers: std::collections::HashSet::new(),
             declared_variables: std::collections::HashSet::new(),
             required_imports: std::collections::HashSet::new(),
+            temp_counter: 0,
+            async_frame: None,
+            runtime: runtime_package::DEFAULT_SPECIFIER.to_string(),
+            jsdoc: false,
+            classes: std::collections::HashSet::new(),
+            sets: std::collections::HashSet::new(),
+            signatures: HashMap::new(),
+            private_prefix: None,
+            recover: false,
+            internal_errors: Vec::new(),
         }
     }
 
     fn transpile_program(&mut self, program: &Program) -> Result<String, NagariError> {
+        self.output = Writer::with_capacity(
+            writer::BASE_CAPACITY + writer::STATEMENT_CAPACITY * program.statements.len(),
+        );
+        self.push_header();
+
+        // Transpile all statements
+        for statement in &program.statements {
+            if self.recover {
+                self.transpile_top_level(statement)?;
+            } else {
+                self.transpile_statement(statement)?;
+            }
+            self.output.push('\n');
+        }
+
+        self.push_helpers();
+
+        Ok(std::mem::take(&mut self.output).into_string())
+    }
+
+    /// Transpile a top-level statement, replacing its output with code that throws if the
+    /// transpiler panics on it
+    fn transpile_top_level(&mut self, statement: &Statement) -> Result<(), NagariError> {
+        let start = self.output.len();
+        let payload = match std::panic::catch_unwind(AssertUnwindSafe(|| {
+            self.transpile_statement(statement)
+        })) {
+            Ok(result) => return result,
+            Err(payload) => payload,
+        };
+
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let declaration = match statement {
+            Statement::ExportDeclaration(export) => defined_name(&export.declaration),
+            _ => defined_name(statement),
+        };
+        let thrown = match &declaration {
+            Some(name) => format!("Internal compiler error: `{name}` could not be compiled"),
+            None => "Internal compiler error: a statement could not be compiled".to_string(),
+        };
+
+        // Leave the top level as it was before the statement. The stub of a declaration goes
+        // through the same code as the declaration, so it may fail too; a bare throw can't.
+        let stub = internal_error_stub(statement, &thrown);
+        let stubbed = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            self.reset_top_level(start);
+            self.transpile_statement(&stub)
+        }));
+        if !matches!(stubbed, Ok(Ok(()))) {
+            self.reset_top_level(start);
+            self.transpile_statement(&internal_error_stub(&Statement::Pass, &thrown))?;
+        }
+        self.internal_errors.push(InternalError {
+            declaration,
+            message,
+        });
+        Ok(())
+    }
+
+    /// Drop the output from `start` on and the state of the statements being transpiled
+    fn reset_top_level(&mut self, start: usize) {
+        self.output.truncate(start);
+        self.indent_level = 0;
+        self.async_frame = None;
+        self.private_prefix = None;
+    }
+
+    fn push_header(&mut self) {
         // Add strict mode and runtime imports
-        if self.target == "es6" || self.target == "esm" {
+        if self.target == "es6" || self.target == "esm" || self.is_es5() {
             self.output.push_str("\"use strict\";\n\n");
         }
 
         // Add runtime imports
-        let runtime_imports = self.module_resolver.get_runtime_imports(self.jsx_enabled);
+        let runtime_imports =
+            self.module_resolver
+                .get_runtime_imports(&self.runtime, self.jsx_enabled, self.ssr);
         self.output.push_str(&runtime_imports);
         self.output.push_str("\n\n");
 
@@ -62,18 +360,18 @@ impl JSTranspiler {
 
         // Initialize interop if needed
         self.output.push_str("// Initialize Nagari runtime\n");
-        self.output
-            .push_str("if (typeof globalThis !== 'undefined' && !globalThis.__nagari__) {\n");
+        if self.js_runtime.supports(Feature::GlobalThis) {
+            self.output.push_str("if (!globalThis.__nagari__) {\n");
+        } else {
+            self.output
+                .push_str("if (typeof globalThis !== 'undefined' && !globalThis.__nagari__) {\n");
+        }
         self.output.push_str("    InteropRegistry.initialize();\n");
         self.output.push_str("}\n\n");
+    }
 
-        // Transpile all statements
-        for statement in &program.statements {
-            self.transpile_statement(statement)?;
-            self.output.push('\n');
-        }
-
-        // Add helper functions at the end
+    /// Add helper functions at the end
+    fn push_helpers(&mut self) {
         let mut helpers = self.js_runtime.generate_runtime_helpers();
 
         // Add conditional helpers based on what was used
@@ -81,6 +379,22 @@ impl JSTranspiler {
             helpers.push_str(&self.generate_center_string_helper());
         }
 
+        if self.used_helpers.contains("len") {
+            helpers.push_str(&self.generate_len_helper());
+        }
+
+        if self.used_helpers.contains("nagariSlice") {
+            helpers.push_str(&self.generate_slice_helper());
+        }
+
+        if self.used_helpers.contains("nagariIter") {
+            helpers.push_str(&self.generate_es5_iter_helper());
+        }
+
+        if self.used_helpers.contains("nagariAsync") {
+            helpers.push_str(&self.generate_es5_async_helper());
+        }
+
         if self.used_helpers.contains("arrayStep") {
             helpers.push_str(&self.generate_array_step_helper());
         }
@@ -97,9 +411,26 @@ impl JSTranspiler {
             helpers.push_str(&self.generate_decorator_helper());
         }
 
+        if self.used_helpers.contains("nagariEvents") {
+            helpers.push_str(&self.generate_events_helper());
+        }
+
+        if self.used_helpers.contains("nagariSchema") {
+            helpers.push_str(&self.generate_schema_helper());
+        }
+
+        if self.used_helpers.contains("nagariSets") {
+            helpers.push_str(&self.generate_sets_helper());
+        }
+
         self.output.push_str(&helpers);
+    }
 
-        Ok(self.output.clone())
+    /// Builtin modules implemented by a helper need it in the output
+    fn use_module_helper(&mut self, module: &str) {
+        if let Some(helper) = modules::module_helper(module) {
+            self.used_helpers.insert(helper.to_string());
+        }
     }
 
     fn transpile_statement(&mut self, stmt: &Statement) -> Result<(), NagariError> {
@@ -109,6 +440,9 @@ impl JSTranspiler {
             Statement::AttributeAssignment(attr_assign) => {
                 self.transpile_attribute_assignment(attr_assign)
             }
+            Statement::IndexAssignment(index_assign) => {
+                self.transpile_index_assignment(index_assign)
+            }
             Statement::TupleAssignment(tuple_assign) => {
                 self.transpile_tuple_assignment(tuple_assign)
             }
@@ -117,6 +451,10 @@ impl JSTranspiler {
             Statement::For(for_loop) => self.transpile_for(for_loop),
             Statement::Match(match_stmt) => self.transpile_match(match_stmt),
             Statement::Return(expr) => self.transpile_return(expr),
+            Statement::Expression(Expression::InlineJs(code)) => {
+                self.transpile_inline_js_block(code);
+                Ok(())
+            }
             Statement::Expression(expr) => {
                 self.add_indent();
                 self.transpile_expression(expr)?;
@@ -124,30 +462,35 @@ impl JSTranspiler {
                 Ok(())
             }
             Statement::Import(import) => {
+                self.use_module_helper(&import.module);
                 self.add_indent();
                 let import_code = self.module_resolver.resolve_import(import);
                 self.output.push_str(&import_code);
                 Ok(())
             }
             Statement::ImportDefault(import) => {
+                self.use_module_helper(&import.module);
                 self.add_indent();
                 let import_code = self.module_resolver.resolve_import_default(import);
                 self.output.push_str(&import_code);
                 Ok(())
             }
             Statement::ImportNamed(import) => {
+                self.use_module_helper(&import.module);
                 self.add_indent();
                 let import_code = self.module_resolver.resolve_import_named(import);
                 self.output.push_str(&import_code);
                 Ok(())
             }
             Statement::ImportNamespace(import) => {
+                self.use_module_helper(&import.module);
                 self.add_indent();
                 let import_code = self.module_resolver.resolve_import_namespace(import);
                 self.output.push_str(&import_code);
                 Ok(())
             }
             Statement::ImportSideEffect(import) => {
+                self.use_module_helper(&import.module);
                 self.add_indent();
                 let import_code = self.module_resolver.resolve_import_side_effect(import);
                 self.output.push_str(&import_code);
@@ -155,12 +498,18 @@ impl JSTranspiler {
             }
             Statement::Break => {
                 self.add_indent();
-                self.output.push_str("break;");
+                match self.lowered_loop_target(LoopJump::Break) {
+                    Some(state) => self.push_state_jump(state),
+                    None => self.output.push_str("break;"),
+                }
                 Ok(())
             }
             Statement::Continue => {
                 self.add_indent();
-                self.output.push_str("continue;");
+                match self.lowered_loop_target(LoopJump::Continue) {
+                    Some(state) => self.push_state_jump(state),
+                    None => self.output.push_str("continue;"),
+                }
                 Ok(())
             }
             Statement::Pass => {
@@ -200,25 +549,61 @@ impl JSTranspiler {
     }
 
     fn transpile_function(&mut self, func: &FunctionDef) -> Result<(), NagariError> {
+        self.record_signature(&func.name, &func.parameters);
+        if self.jsdoc {
+            let indent = "    ".repeat(self.indent_level);
+            self.output
+                .push_str(&declarations::jsdoc(func, false, &indent));
+        }
         self.add_indent();
 
+        if self.is_es5() {
+            if func.is_generator {
+                return self.require_es6("generator functions");
+            }
+            self.transpile_es5_function(
+                Some(&func.name),
+                &func.parameters,
+                &func.body,
+                func.is_async,
+                false,
+            )?;
+            return self.apply_decorators(&func.name, &func.decorators);
+        }
+
         if func.is_async {
             self.output.push_str("async ");
         }
 
         self.output.push_str("function ");
         self.output.push_str(&func.name);
+        self.transpile_function_rest(&func.parameters, &func.body, None)?;
+        self.apply_decorators(&func.name, &func.decorators)
+    }
+
+    /// `(parameters) { body }` of a function or method. `receiver` names the variable bound to
+    /// `this` and how many statements of the body run before it is bound.
+    fn transpile_function_rest(
+        &mut self,
+        parameters: &[Parameter],
+        body: &[Statement],
+        receiver: Option<(&str, usize)>,
+    ) -> Result<(), NagariError> {
         self.output.push('(');
 
         // Clear declared variables for this function scope
         let previous_declared = self.declared_variables.clone();
         self.declared_variables.clear();
+        let previous_sets = self.enter_set_scope(parameters);
 
         // Parameters
-        for (i, param) in func.parameters.iter().enumerate() {
+        for (i, param) in js_parameters(parameters).into_iter().enumerate() {
             if i > 0 {
                 self.output.push_str(", ");
             }
+            if param.kind == ParameterKind::Rest {
+                self.output.push_str("...");
+            }
             self.output.push_str(&param.name);
 
             // Mark parameter as declared
@@ -228,15 +613,20 @@ impl JSTranspiler {
             if let Some(default) = &param.default_value {
                 self.output.push_str(" = ");
                 self.transpile_expression(default)?;
+            } else if param.kind == ParameterKind::Keywords {
+                self.output.push_str(" = {}");
             }
         }
+        if let Some((name, _)) = receiver {
+            self.declared_variables.insert(name.to_string());
+        }
 
         self.output.push_str(") {\n");
         self.indent_level += 1;
 
         // First pass: collect all variable declarations in the function body
         let mut function_vars = std::collections::HashSet::<String>::new();
-        self.collect_variable_declarations(&func.body, &mut function_vars);
+        self.collect_variable_declarations(body, &mut function_vars);
 
         // Declare all function-scoped variables at the top (except parameters)
         for var in &function_vars {
@@ -250,10 +640,20 @@ impl JSTranspiler {
         }
 
         // Function body
-        for statement in &func.body {
+        let (receiver, bound_after) = match receiver {
+            Some((name, before)) => (Some(name), before.min(body.len())),
+            None => (None, 0),
+        };
+        for (i, statement) in body.iter().enumerate() {
+            if i == bound_after {
+                self.push_receiver(receiver);
+            }
             self.transpile_statement(statement)?;
             self.output.push('\n');
         }
+        if bound_after == body.len() {
+            self.push_receiver(receiver);
+        }
 
         self.indent_level -= 1;
         self.add_indent();
@@ -261,7 +661,55 @@ impl JSTranspiler {
 
         // Restore previous scope's declared variables
         self.declared_variables = previous_declared;
+        self.sets = previous_sets;
+
+        Ok(())
+    }
+
+    fn push_receiver(&mut self, receiver: Option<&str>) {
+        if let Some(name) = receiver {
+            self.add_indent();
+            writeln!(self.output, "const {} = this;", name);
+        }
+    }
 
+    /// `name = outer(inner(name));` after a function or class with decorators
+    fn apply_decorators(
+        &mut self,
+        name: &str,
+        decorators: &[Decorator],
+    ) -> Result<(), NagariError> {
+        if decorators.is_empty() {
+            return Ok(());
+        }
+        self.output.push('\n');
+        self.add_indent();
+        self.output.push_str(name);
+        self.output.push_str(" = ");
+        for decorator in decorators {
+            self.output.push_str(&decorator.name);
+            if let Some(arguments) = &decorator.arguments {
+                // Keyword arguments go to the decorator factory as they would in a call to it
+                let call = CallExpression {
+                    function: Box::new(Expression::Identifier(decorator.name.clone())),
+                    arguments: arguments.clone(),
+                    keyword_args: decorator.keyword_args.clone(),
+                };
+                let call = self.lower_keyword_arguments(&call)?.unwrap_or(call);
+                self.output.push('(');
+                for (i, argument) in call.arguments.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.transpile_expression(argument)?;
+                }
+                self.output.push(')');
+            }
+            self.output.push('(');
+        }
+        self.output.push_str(name);
+        self.output.push_str(&")".repeat(decorators.len()));
+        self.output.push(';');
         Ok(())
     }
 
@@ -303,7 +751,8 @@ impl JSTranspiler {
 
         if is_declaration {
             // First time seeing this variable - declare it with let (not const, in case it's reassigned)
-            self.output.push_str("let ");
+            self.output.push_str(self.binding_keyword("let"));
+            self.output.push(' ');
             self.declared_variables.insert(assign.name.clone());
         }
         // Otherwise, it's a reassignment - no declaration keyword needed
@@ -312,6 +761,7 @@ impl JSTranspiler {
         self.output.push_str(" = ");
         self.transpile_expression(&assign.value)?;
         self.output.push(';');
+        self.bind_set_name(&assign.name, assign.var_type.as_ref(), &assign.value);
 
         Ok(())
     }
@@ -325,7 +775,8 @@ impl JSTranspiler {
         // Transpile the object
         self.transpile_expression(&attr_assign.object)?;
         self.output.push('.');
-        self.output.push_str(&attr_assign.attribute);
+        self.output
+            .push_str(&self.member_name(&attr_assign.attribute));
         self.output.push_str(" = ");
 
         // Transpile the value
@@ -335,14 +786,51 @@ impl JSTranspiler {
         Ok(())
     }
 
+    fn transpile_index_assignment(
+        &mut self,
+        index_assign: &crate::ast::IndexAssignment,
+    ) -> Result<(), NagariError> {
+        self.add_indent();
+        self.transpile_expression(&index_assign.object)?;
+        self.output.push('[');
+        self.transpile_expression(&index_assign.index)?;
+        self.output.push_str("] = ");
+        self.transpile_expression(&index_assign.value)?;
+        self.output.push(';');
+
+        Ok(())
+    }
+
     fn transpile_tuple_assignment(
         &mut self,
         tuple_assign: &crate::ast::TupleAssignment,
     ) -> Result<(), NagariError> {
         self.add_indent();
 
-        // JavaScript destructuring assignment: let [a, b, c] = expression
-        self.output.push_str("let [");
+        if self.is_es5() {
+            let targets = Self::indexed_targets(&tuple_assign.targets);
+            return self.transpile_es5_unpacking(&targets, &tuple_assign.value);
+        }
+
+        // JavaScript destructuring assignment: let [a, b, c] = expression. Targets assigned
+        // before are reassigned, so new ones are declared on a line of their own.
+        let new_targets: Vec<String> = tuple_assign
+            .targets
+            .iter()
+            .filter(|target| !self.declared_variables.contains(*target))
+            .cloned()
+            .collect();
+        if new_targets.len() == tuple_assign.targets.len() {
+            self.output.push_str("let ");
+        } else if !new_targets.is_empty() {
+            self.output.push_str("let ");
+            self.output.push_str(&new_targets.join(", "));
+            self.output.push_str(";\n");
+            self.add_indent();
+        }
+        self.declared_variables.extend(new_targets);
+
+        self.output.push('[');
         for (i, target) in tuple_assign.targets.iter().enumerate() {
             if i > 0 {
                 self.output.push_str(", ");
@@ -369,9 +857,18 @@ impl JSTranspiler {
             Expression::Binary(binary) => self.transpile_binary(binary),
             Expression::Call(call) => self.transpile_call(call),
             Expression::Await(expr) => {
+                if self.is_es5() {
+                    // Awaits the es5 target supports are lowered before reaching here
+                    return Err(NagariError::SemanticError(
+                        "the es5 target only supports `await` inside an async function, as a \
+                         statement, an assignment value or a return value"
+                            .to_string(),
+                    ));
+                }
                 self.output.push_str("await ");
                 self.transpile_expression(expr)
             }
+            Expression::List(elements) if self.is_es5() => self.transpile_es5_list(elements),
             Expression::List(elements) => {
                 self.output.push('[');
                 for (i, element) in elements.iter().enumerate() {
@@ -410,6 +907,7 @@ impl JSTranspiler {
                 Ok(())
             }
             Expression::JSXElement(jsx) => self.transpile_jsx_element(jsx),
+            Expression::Lambda(lambda) if self.is_es5() => self.transpile_es5_lambda(lambda),
             Expression::Lambda(lambda) => {
                 self.output.push('(');
                 for (i, param) in lambda.parameters.iter().enumerate() {
@@ -426,16 +924,13 @@ impl JSTranspiler {
                 self.output.push_str("(function() {\n");
                 self.indent_level += 1;
                 self.add_indent();
-                self.output.push_str("const result = [];\n");
+                self.output.push_str(self.binding_keyword("const"));
+                self.output.push_str(" result = [];\n");
 
                 // Generate nested for loops for each generator
                 for generator in &comp.generators {
                     self.add_indent();
-                    self.output.push_str("for (const ");
-                    self.output.push_str(&generator.target);
-                    self.output.push_str(" of ");
-                    self.transpile_expression(&generator.iter)?;
-                    self.output.push_str(") {\n");
+                    self.push_for_of(&generator.target, &generator.iter, true)?;
                     self.indent_level += 1;
 
                     // Add conditions as if statements
@@ -478,16 +973,13 @@ impl JSTranspiler {
                 self.output.push_str("(function() {\n");
                 self.indent_level += 1;
                 self.add_indent();
-                self.output.push_str("const result = {};\n");
+                self.output.push_str(self.binding_keyword("const"));
+                self.output.push_str(" result = {};\n");
 
                 // Generate nested for loops for each generator
                 for generator in &comp.generators {
                     self.add_indent();
-                    self.output.push_str("for (const ");
-                    self.output.push_str(&generator.target);
-                    self.output.push_str(" of ");
-                    self.transpile_expression(&generator.iter)?;
-                    self.output.push_str(") {\n");
+                    self.push_for_of(&generator.target, &generator.iter, true)?;
                     self.indent_level += 1;
 
                     // Add conditions as if statements
@@ -532,16 +1024,13 @@ impl JSTranspiler {
                 self.output.push_str("(function() {\n");
                 self.indent_level += 1;
                 self.add_indent();
-                self.output.push_str("const result = new Set();\n");
+                self.output.push_str(self.binding_keyword("const"));
+                self.output.push_str(" result = new Set();\n");
 
                 // Generate nested for loops for each generator
                 for generator in &comp.generators {
                     self.add_indent();
-                    self.output.push_str("for (const ");
-                    self.output.push_str(&generator.target);
-                    self.output.push_str(" of ");
-                    self.transpile_expression(&generator.iter)?;
-                    self.output.push_str(") {\n");
+                    self.push_for_of(&generator.target, &generator.iter, true)?;
                     self.indent_level += 1;
 
                     // Add conditions as if statements
@@ -580,6 +1069,8 @@ impl JSTranspiler {
                 Ok(())
             }
             Expression::Generator(gen) => {
+                self.require_es6("generator expressions")?;
+
                 // Generate JavaScript generator function
                 self.output.push_str("(function*() {\n");
                 self.indent_level += 1;
@@ -630,7 +1121,7 @@ impl JSTranspiler {
             Expression::Attribute(attr) => {
                 self.transpile_expression(&attr.object)?;
                 self.output.push('.');
-                self.output.push_str(&attr.attribute);
+                self.output.push_str(&self.member_name(&attr.attribute));
                 Ok(())
             }
             Expression::Subscript(sub) => {
@@ -640,16 +1131,34 @@ impl JSTranspiler {
                 self.output.push(']');
                 Ok(())
             }
+            Expression::FunctionExpr(func) if self.is_es5() => {
+                if func.is_generator {
+                    return self.require_es6("generator functions");
+                }
+                self.transpile_es5_function(
+                    None,
+                    &func.parameters,
+                    &func.body,
+                    func.is_async,
+                    false,
+                )
+            }
             Expression::FunctionExpr(func) => {
                 if func.is_async {
                     self.output.push_str("async ");
                 }
                 self.output.push_str("function(");
-                for (i, param) in func.parameters.iter().enumerate() {
+                for (i, param) in js_parameters(&func.parameters).into_iter().enumerate() {
                     if i > 0 {
                         self.output.push_str(", ");
                     }
-                    self.output.push_str(&param.name);
+                    match param.kind {
+                        ParameterKind::Positional => self.output.push_str(&param.name),
+                        ParameterKind::Rest => write!(self.output, "...{}", param.name),
+                        ParameterKind::Keywords => {
+                            write!(self.output, "{} = {{}}", param.name)
+                        }
+                    }
                 }
                 self.output.push_str(") {\n");
                 self.indent_level += 1;
@@ -662,6 +1171,7 @@ impl JSTranspiler {
                 self.output.push('}');
                 Ok(())
             }
+            Expression::FString(fstring) if self.is_es5() => self.transpile_es5_fstring(fstring),
             Expression::FString(fstring) => {
                 // Transpile f-string to template literal
                 self.output.push('`');
@@ -737,33 +1247,21 @@ impl JSTranspiler {
                 Ok(())
             }
             Expression::Slice(slice) => {
-                // Array slicing: arr.slice(start, end)
+                // Slicing: nagariSlice(obj, start, end, step), by code point for strings
+                self.used_helpers.insert("nagariSlice".to_string());
+                self.output.push_str("nagariSlice(");
                 self.transpile_expression(&slice.object)?;
-                self.output.push_str(".slice(");
-
-                if let Some(start) = &slice.start {
-                    self.transpile_expression(start)?;
-                } else {
-                    self.output.push('0');
-                }
 
-                if slice.end.is_some() || slice.step.is_some() {
+                for bound in [&slice.start, &slice.end, &slice.step] {
                     self.output.push_str(", ");
-                    if let Some(end) = &slice.end {
-                        self.transpile_expression(end)?;
+                    if let Some(bound) = bound {
+                        self.transpile_expression(bound)?;
+                    } else {
+                        self.output.push_str("undefined");
                     }
                 }
 
                 self.output.push(')');
-
-                // Handle step separately with a helper function if needed
-                if let Some(step) = &slice.step {
-                    self.used_helpers.insert("arrayStep".to_string());
-                    self.output.push_str(".filter((_, i) => i % ");
-                    self.transpile_expression(step)?;
-                    self.output.push_str(" === 0)");
-                }
-
                 Ok(())
             }
             Expression::Set(elements) => {
@@ -790,6 +1288,13 @@ impl JSTranspiler {
                 self.declared_variables.insert(named_expr.target.clone());
                 Ok(())
             }
+            Expression::Async(expr) if self.is_es5() => {
+                self.output
+                    .push_str("Promise.resolve().then(function () { return ");
+                self.transpile_expression(expr)?;
+                self.output.push_str("; })");
+                Ok(())
+            }
             Expression::Async(expr) => {
                 // Async expression wrapper
                 self.output.push_str("(async () => ");
@@ -798,11 +1303,22 @@ impl JSTranspiler {
                 Ok(())
             }
             Expression::Spread(expr) => {
+                self.require_es6("spreads outside list literals and call arguments")?;
+
                 // Spread operator: ...expr
                 self.output.push_str("...");
                 self.transpile_expression(expr)?;
                 Ok(())
             }
+            Expression::InlineJs(code) => {
+                self.output.push('(');
+                self.output.push_str(code.trim());
+                self.output.push(')');
+                Ok(())
+            }
+            Expression::TemplateLiteral(template) if self.is_es5() => {
+                self.transpile_es5_template_literal(template)
+            }
             Expression::TemplateLiteral(template) => {
                 // Template literal with interpolations
                 self.output.push('`');
@@ -830,10 +1346,17 @@ impl JSTranspiler {
     }
     fn transpile_jsx_element(&mut self, jsx: &JSXElement) -> Result<(), NagariError> {
         if self.jsx_enabled {
-            // Use jsx() function from runtime
-            self.output.push_str("jsx(\"");
-            self.output.push_str(&jsx.tag);
-            self.output.push_str("\", ");
+            // Use jsx() function from runtime. Rendered on the server, components are called
+            // by render_to_string, so capitalized tags refer to them rather than naming them.
+            self.output.push_str("jsx(");
+            if self.ssr && jsx.tag.starts_with(|c: char| c.is_ascii_uppercase()) {
+                self.output.push_str(&jsx.tag);
+            } else {
+                self.output.push('"');
+                self.output.push_str(&jsx.tag);
+                self.output.push('"');
+            }
+            self.output.push_str(", ");
 
             // Props object
             if jsx.attributes.is_empty() {
@@ -955,6 +1478,31 @@ impl JSTranspiler {
         Ok(())
     }
     fn transpile_call(&mut self, call: &CallExpression) -> Result<(), NagariError> {
+        match self.lower_keyword_arguments(call)? {
+            Some(call) => self.transpile_positional_call(&call),
+            None => self.transpile_positional_call(call),
+        }
+    }
+
+    /// A call whose arguments are all in place
+    fn transpile_positional_call(&mut self, call: &CallExpression) -> Result<(), NagariError> {
+        if self.is_es5() && self.transpile_es5_call(call)? {
+            return Ok(());
+        }
+        if !self.is_es5() {
+            if self.transpile_super_call(call)? {
+                return Ok(());
+            }
+            if matches!(call.function.as_ref(), Expression::Identifier(name) if self.classes.contains(name))
+            {
+                self.output.push_str("new ");
+            }
+        }
+
+        if self.transpile_set_call(call)? {
+            return Ok(());
+        }
+
         if let Expression::Identifier(func_name) = call.function.as_ref() {
             // Special handling for functions that need non-standard transpilation
             if func_name == "hasattr" && call.arguments.len() == 2 {
@@ -969,7 +1517,11 @@ impl JSTranspiler {
                 // isinstance(obj, type) -> implement proper type checking
                 self.output.push_str("(function(obj, types) {\n");
                 self.output.push_str("  if (Array.isArray(types)) {\n");
-                self.output.push_str("    return types.some(t => {\n");
+                self.output.push_str(if self.is_es5() {
+                    "    return types.some(function (t) {\n"
+                } else {
+                    "    return types.some(t => {\n"
+                });
                 self.output
                     .push_str("      if (t === Array) return Array.isArray(obj);\n");
                 self.output.push_str("      if (t === Object || t.name === 'dict') return typeof obj === 'object' && obj !== null && !Array.isArray(obj);\n");
@@ -981,7 +1533,9 @@ impl JSTranspiler {
                 self.output.push_str("      return obj instanceof t;\n");
                 self.output.push_str("    });\n");
                 self.output.push_str("  } else {\n");
-                self.output.push_str("    const t = types;\n");
+                self.output.push_str("    ");
+                self.output.push_str(self.binding_keyword("const"));
+                self.output.push_str(" t = types;\n");
                 self.output
                     .push_str("    if (t === Array) return Array.isArray(obj);\n");
                 self.output.push_str("    if (t === Object || t.name === 'dict') return typeof obj === 'object' && obj !== null && !Array.isArray(obj);\n");
@@ -1014,8 +1568,24 @@ impl JSTranspiler {
                     self.required_imports.insert(import_module.clone());
                 }
 
-                if mapping.is_method {
-                    // Handle method calls like len(arr) -> arr.length
+                if mapping.is_method && self.is_es5() && mapping.js_equivalent.ends_with("...") {
+                    // extend(arr, items) without spread syntax
+                    let method = mapping.js_equivalent.trim_start_matches('.');
+                    let method = method.trim_end_matches("(...");
+                    write!(self.output, "Array.prototype.{}.apply(", method);
+                    for (i, arg) in call.arguments.iter().enumerate() {
+                        if i > 0 {
+                            self.output.push_str(", nagariIter(");
+                            self.used_helpers.insert("nagariIter".to_string());
+                            self.transpile_expression(arg)?;
+                            self.output.push(')');
+                        } else {
+                            self.transpile_expression(arg)?;
+                        }
+                    }
+                    self.output.push(')');
+                } else if mapping.is_method {
+                    // Handle method calls like append(arr, x) -> arr.push(x)
                     if !call.arguments.is_empty() {
                         self.transpile_expression(&call.arguments[0])?;
                         self.output.push_str(&mapping.js_equivalent);
@@ -1070,6 +1640,40 @@ impl JSTranspiler {
         Ok(())
     }
     fn transpile_binary(&mut self, binary: &BinaryExpression) -> Result<(), NagariError> {
+        if self.transpile_set_operation(binary)? {
+            return Ok(());
+        }
+        match binary.operator {
+            // Floor division rounds towards negative infinity, like Python's `//`
+            BinaryOperator::FloorDivide => {
+                self.output.push_str("Math.floor(");
+                self.transpile_expression(&binary.left)?;
+                self.output.push_str(" / ");
+                self.transpile_expression(&binary.right)?;
+                self.output.push(')');
+                return Ok(());
+            }
+            // `**` is ES2016
+            BinaryOperator::Power if self.is_es5() => {
+                self.output.push_str("Math.pow(");
+                self.transpile_expression(&binary.left)?;
+                self.output.push_str(", ");
+                self.transpile_expression(&binary.right)?;
+                self.output.push(')');
+                return Ok(());
+            }
+            // A unary operand on the left of `**` is a syntax error without parentheses
+            BinaryOperator::Power if matches!(*binary.left, Expression::Unary(_)) => {
+                self.output.push_str("((");
+                self.transpile_expression(&binary.left)?;
+                self.output.push_str(") ** ");
+                self.transpile_expression(&binary.right)?;
+                self.output.push(')');
+                return Ok(());
+            }
+            _ => {}
+        }
+
         self.output.push('(');
         self.transpile_expression(&binary.left)?;
 
@@ -1087,6 +1691,13 @@ impl JSTranspiler {
             BinaryOperator::GreaterEqual => " >= ",
             BinaryOperator::And => " && ",
             BinaryOperator::Or => " || ",
+            BinaryOperator::Power => " ** ",
+            BinaryOperator::BitwiseAnd => " & ",
+            BinaryOperator::BitwiseOr => " | ",
+            BinaryOperator::BitwiseXor => " ^ ",
+            BinaryOperator::LeftShift => " << ",
+            BinaryOperator::RightShift => " >> ",
+            BinaryOperator::FloorDivide => unreachable!("floor division is emitted above"),
         };
 
         self.output.push_str(op);
@@ -1096,8 +1707,34 @@ impl JSTranspiler {
         Ok(())
     }
     fn add_indent(&mut self) {
-        for _ in 0..self.indent_level {
-            self.output.push_str("    ");
+        self.output.indent(self.indent_level);
+    }
+
+    /// Emit a `js"""..."""` statement as written, re-indented to the current level
+    fn transpile_inline_js_block(&mut self, code: &str) {
+        let lines: Vec<&str> = code
+            .lines()
+            .skip_while(|line| line.trim().is_empty())
+            .collect();
+        let end = lines
+            .iter()
+            .rposition(|line| !line.trim().is_empty())
+            .map_or(0, |last| last + 1);
+        let margin = lines[..end]
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+
+        for (i, line) in lines[..end].iter().enumerate() {
+            if i > 0 {
+                self.output.push('\n');
+            }
+            if !line.trim().is_empty() {
+                self.add_indent();
+                self.output.push_str(line[margin..].trim_end());
+            }
         }
     }
     fn transpile_if(&mut self, if_stmt: &IfStatement) -> Result<(), NagariError> {
@@ -1134,10 +1771,12 @@ impl JSTranspiler {
         self.transpile_expression(&while_stmt.condition)?;
         self.output.push_str(") {\n");
         self.indent_level += 1;
+        self.enter_native_loop();
         for stmt in &while_stmt.body {
             self.transpile_statement(stmt)?;
             self.output.push('\n');
         }
+        self.exit_native_loop();
         self.indent_level -= 1;
         self.add_indent();
         self.output.push('}');
@@ -1146,21 +1785,28 @@ impl JSTranspiler {
 
     fn transpile_for(&mut self, for_stmt: &ForLoop) -> Result<(), NagariError> {
         self.add_indent();
-        self.output.push_str("for (const ");
-        self.output.push_str(&for_stmt.variable);
-        self.output.push_str(" of ");
-        self.transpile_expression(&for_stmt.iterable)?;
-        self.output.push_str(") {\n");
+        if for_stmt.is_async {
+            self.require_es6("async for loops")?;
+            self.output.push_str("for await (const ");
+            self.output.push_str(&for_stmt.variable);
+            self.output.push_str(" of ");
+            self.transpile_expression(&for_stmt.iterable)?;
+            self.output.push_str(") {\n");
+        } else {
+            self.push_for_of(&for_stmt.variable, &for_stmt.iterable, false)?;
+        }
         self.indent_level += 1;
 
         // Save current scope and mark loop variable as declared
         let previous_declared = self.declared_variables.clone();
         self.declared_variables.insert(for_stmt.variable.clone());
 
+        self.enter_native_loop();
         for stmt in &for_stmt.body {
             self.transpile_statement(stmt)?;
             self.output.push('\n');
         }
+        self.exit_native_loop();
 
         // Restore previous scope but keep any variables declared in this loop
         // This allows variables declared in the loop to be available outside
@@ -1181,130 +1827,45 @@ impl JSTranspiler {
     fn transpile_match(&mut self, match_stmt: &MatchStatement) -> Result<(), NagariError> {
         self.add_indent();
 
-        // Store the match expression in a variable for complex pattern matching
-        self.output.push_str("(function(__match_value__) {\n");
+        // A labeled block rather than a function, so `return`, `break` and `continue` in case
+        // bodies reach the enclosing function and loops
+        let label = format!("__match_{}", self.next_temp_id());
+        self.output.push_str(&label);
+        self.output.push_str(": {\n");
         self.indent_level += 1;
 
-        // Generate if-else chain instead of switch for complex pattern matching
-        let mut first_case = true;
-        for case in &match_stmt.cases {
-            self.add_indent();
+        // Store the match expression in a variable for complex pattern matching
+        self.add_indent();
+        self.output.push_str(self.binding_keyword("const"));
+        self.output.push_str(" __match_value__ = ");
+        self.transpile_expression(&match_stmt.expression)?;
+        self.output.push_str(";\n");
 
-            if first_case {
-                first_case = false;
-            } else {
-                self.output.push_str("else ");
-            }
+        // Each case is tried in turn, and the first one to match leaves the block
+        for case in &match_stmt.cases {
+            let (pattern, guard) = match &case.pattern {
+                Pattern::Guard(pattern, condition) => (pattern.as_ref(), Some(condition)),
+                pattern => (pattern, None),
+            };
 
-            match &case.pattern {
-                Pattern::Wildcard => {
-                    // Default case - always matches
-                    self.output.push_str("{\n");
-                }
-                Pattern::Literal(lit) => {
-                    self.output.push_str("if (__match_value__ === ");
-                    self.transpile_literal(lit)?;
-                    self.output.push_str(") {\n");
-                }
-                Pattern::Identifier(name) => {
-                    // Bind the value to the identifier
-                    self.output.push_str("{\n");
-                    self.indent_level += 1;
-                    self.add_indent();
-                    self.output.push_str("const ");
-                    self.output.push_str(name);
-                    self.output.push_str(" = __match_value__;\n");
-                    // Mark variable as declared
-                    self.declared_variables.insert(name.clone());
-                }
-                _ => {
-                    // For complex patterns, use the pattern check
-                    self.output.push_str("if (");
-                    self.transpile_pattern_check(&case.pattern)?;
-                    self.output.push_str(") {\n");
-                }
+            self.add_indent();
+            if !Self::is_irrefutable(pattern) {
+                self.output.push_str("if (");
+                self.transpile_pattern_check(pattern, "__match_value__")?;
+                self.output.push_str(") ");
             }
-
+            self.output.push_str("{\n");
             self.indent_level += 1;
 
-            // Add pattern destructuring for complex patterns
-            match &case.pattern {
-                Pattern::Tuple(patterns) => {
-                    for (i, pattern) in patterns.iter().enumerate() {
-                        if let Pattern::Identifier(name) = pattern {
-                            self.add_indent();
-                            self.output.push_str("const ");
-                            self.output.push_str(name);
-                            self.output.push_str(" = __match_value__[");
-                            self.output.push_str(&i.to_string());
-                            self.output.push_str("];\n");
-                            self.declared_variables.insert(name.clone());
-                        }
-                    }
-                }
-                Pattern::List(patterns) => {
-                    self.add_indent();
-                    self.output.push_str("const [");
-                    for (i, pattern) in patterns.iter().enumerate() {
-                        if i > 0 {
-                            self.output.push_str(", ");
-                        }
-                        if let Pattern::Identifier(name) = pattern {
-                            self.output.push_str(name);
-                            self.declared_variables.insert(name.clone());
-                        } else {
-                            self.output.push_str(&format!("__elem_{}", i));
-                        }
-                    }
-                    self.output.push_str("] = __match_value__;\n");
-                }
-                Pattern::Dict(pairs) => {
-                    for (key_pattern, value_pattern) in pairs {
-                        if let (Pattern::Literal(Literal::String(key)), Pattern::Identifier(name)) =
-                            (key_pattern, value_pattern)
-                        {
-                            self.add_indent();
-                            self.output.push_str("const ");
-                            self.output.push_str(name);
-                            self.output.push_str(" = __match_value__['");
-                            self.output.push_str(key);
-                            self.output.push_str("'];\n");
-                            self.declared_variables.insert(name.clone());
-                        }
-                    }
-                }
-                Pattern::Constructor(_class_name, field_patterns) => {
-                    for (i, field_pattern) in field_patterns.iter().enumerate() {
-                        if let Pattern::Identifier(name) = field_pattern {
-                            self.add_indent();
-                            self.output.push_str("const ");
-                            self.output.push_str(name);
-                            self.output.push_str(" = __match_value__.");
-                            self.output.push_str(&format!("field{}", i));
-                            self.output.push_str(" || __match_value__[");
-                            self.output.push_str(&i.to_string());
-                            self.output.push_str("];\n");
-                            self.declared_variables.insert(name.clone());
-                        }
-                    }
-                }
-                Pattern::Guard(pattern, condition) => {
-                    // Handle guard conditions
-                    if let Pattern::Identifier(name) = pattern.as_ref() {
-                        self.add_indent();
-                        self.output.push_str("const ");
-                        self.output.push_str(name);
-                        self.output.push_str(" = __match_value__;\n");
-                        self.declared_variables.insert(name.clone());
-                    }
+            self.transpile_pattern_bindings(pattern, "__match_value__")?;
 
-                    // Additional guard check
-                    self.add_indent();
-                    self.output.push_str("if (!(");
-                    self.transpile_expression(condition)?;
-                    self.output.push_str(")) continue;\n");
-                }
-                _ => {}
+            // A failed guard falls through to the next case
+            if let Some(condition) = guard {
+                self.add_indent();
+                self.output.push_str("if (");
+                self.transpile_expression(condition)?;
+                self.output.push_str(") {\n");
+                self.indent_level += 1;
             }
 
             for stmt in &case.body {
@@ -1312,9 +1873,14 @@ impl JSTranspiler {
                 self.output.push('\n');
             }
 
-            // Add return to break out of the match
             self.add_indent();
-            self.output.push_str("return;\n");
+            writeln!(self.output, "break {};", label);
+
+            if guard.is_some() {
+                self.indent_level -= 1;
+                self.add_indent();
+                self.output.push_str("}\n");
+            }
 
             self.indent_level -= 1;
             self.add_indent();
@@ -1323,14 +1889,93 @@ impl JSTranspiler {
 
         self.indent_level -= 1;
         self.add_indent();
-        self.output.push_str("})(");
-        self.transpile_expression(&match_stmt.expression)?;
-        self.output.push(')');
+        self.output.push('}');
 
         Ok(())
     }
 
+    /// Whether a pattern matches any value
+    fn is_irrefutable(pattern: &Pattern) -> bool {
+        matches!(pattern, Pattern::Identifier(_) | Pattern::Wildcard)
+    }
+
+    /// The JavaScript source of a literal
+    fn literal_source(&mut self, lit: &Literal) -> Result<String, NagariError> {
+        let output = std::mem::take(&mut self.output);
+        let result = self.transpile_literal(lit);
+        let source = std::mem::replace(&mut self.output, output);
+        result.map(|()| source.into_string())
+    }
+
+    /// The subjects of the items and fields a pattern matches patterns against, each with its
+    /// pattern, given the pattern's own subject
+    fn pattern_parts<'p>(
+        &mut self,
+        pattern: &'p Pattern,
+        subject: &str,
+    ) -> Result<Vec<(String, &'p Pattern)>, NagariError> {
+        Ok(match pattern {
+            Pattern::Tuple(patterns) | Pattern::List(patterns) => patterns
+                .iter()
+                .enumerate()
+                .map(|(i, pattern)| (format!("{}[{}]", subject, i), pattern))
+                .collect(),
+            Pattern::Dict(pairs) => {
+                let mut parts = Vec::new();
+                for (key_pattern, value_pattern) in pairs {
+                    if let Pattern::Literal(key) = key_pattern {
+                        let key = self.literal_source(key)?;
+                        parts.push((format!("{}[{}]", subject, key), value_pattern));
+                    }
+                }
+                parts
+            }
+            Pattern::Constructor(_class_name, field_patterns) => field_patterns
+                .iter()
+                .enumerate()
+                .map(|(i, pattern)| {
+                    (
+                        format!(
+                            "('field{}' in {} ? {}.field{} : {}[{}])",
+                            i, subject, subject, i, subject, i
+                        ),
+                        pattern,
+                    )
+                })
+                .collect(),
+            Pattern::Guard(pattern, _condition) => vec![(subject.to_string(), pattern.as_ref())],
+            _ => Vec::new(),
+        })
+    }
+
+    /// Declare the names a pattern binds, from the parts of `subject` they match
+    fn transpile_pattern_bindings(
+        &mut self,
+        pattern: &Pattern,
+        subject: &str,
+    ) -> Result<(), NagariError> {
+        if let Pattern::Identifier(name) = pattern {
+            self.add_indent();
+            self.output.push_str(self.binding_keyword("const"));
+            self.output.push(' ');
+            self.output.push_str(name);
+            self.output.push_str(" = ");
+            self.output.push_str(subject);
+            self.output.push_str(";\n");
+            self.declared_variables.insert(name.clone());
+            return Ok(());
+        }
+        for (part, pattern) in self.pattern_parts(pattern, subject)? {
+            self.transpile_pattern_bindings(pattern, &part)?;
+        }
+        Ok(())
+    }
+
     fn transpile_return(&mut self, expr: &Option<Expression>) -> Result<(), NagariError> {
+        if self.async_frame.is_some() {
+            return self.transpile_es5_async_return(expr.as_ref());
+        }
+
         self.add_indent();
         self.output.push_str("return");
         if let Some(e) = expr {
@@ -1341,53 +1986,77 @@ impl JSTranspiler {
         Ok(())
     }
 
-    fn transpile_pattern_check(&mut self, pattern: &Pattern) -> Result<(), NagariError> {
+    fn transpile_pattern_check(
+        &mut self,
+        pattern: &Pattern,
+        subject: &str,
+    ) -> Result<(), NagariError> {
         match pattern {
             Pattern::Literal(lit) => {
-                self.output.push_str("__match_value__ === ");
-                self.transpile_literal(lit)
+                self.output.push_str(subject);
+                self.output.push_str(" === ");
+                self.transpile_literal(lit)?;
             }
             Pattern::Identifier(_name) => {
                 self.output.push_str("true"); // Identifiers always match
-                Ok(())
             }
             Pattern::Wildcard => {
                 self.output.push_str("true"); // Wildcard always matches
-                Ok(())
             }
-            Pattern::Tuple(patterns) => {
-                self.output
-                    .push_str("Array.isArray(__match_value__) && __match_value__.length === ");
-                self.output.push_str(&patterns.len().to_string());
-                Ok(())
-            }
-            Pattern::List(_) => {
-                self.output.push_str("Array.isArray(__match_value__)");
-                Ok(())
+            Pattern::Tuple(patterns) | Pattern::List(patterns) => {
+                write!(
+                    self.output,
+                    "Array.isArray({}) && {}.length === {}",
+                    subject,
+                    subject,
+                    patterns.len()
+                );
             }
-            Pattern::Dict(_) => {
-                self.output
-                    .push_str("typeof __match_value__ === 'object' && __match_value__ !== null");
-                Ok(())
+            Pattern::Dict(pairs) => {
+                write!(
+                    self.output,
+                    "typeof {} === 'object' && {} !== null",
+                    subject, subject
+                );
+                for (key_pattern, _value_pattern) in pairs {
+                    if let Pattern::Literal(key) = key_pattern {
+                        self.output.push_str(" && ");
+                        self.transpile_literal(key)?;
+                        self.output.push_str(" in ");
+                        self.output.push_str(subject);
+                    }
+                }
             }
             Pattern::Guard(pattern, _condition) => {
                 // Check the base pattern, condition is checked separately
-                self.transpile_pattern_check(pattern)
+                return self.transpile_pattern_check(pattern, subject);
             }
             Pattern::Constructor(class_name, _) => {
-                self.output.push_str("__match_value__ instanceof ");
+                self.output.push_str(subject);
+                self.output.push_str(" instanceof ");
                 self.output.push_str(class_name);
-                Ok(())
             }
             Pattern::Range(start, end) => {
-                self.output
-                    .push_str("typeof __match_value__ === 'number' && __match_value__ >= ");
+                write!(
+                    self.output,
+                    "typeof {} === 'number' && {} >= ",
+                    subject, subject
+                );
                 self.transpile_expression(start)?;
-                self.output.push_str(" && __match_value__ <= ");
+                write!(self.output, " && {} <= ", subject);
                 self.transpile_expression(end)?;
-                Ok(())
             }
         }
+
+        // Then the patterns of the items and fields, once the subject is known to have them
+        for (part, pattern) in self.pattern_parts(pattern, subject)? {
+            if !Self::is_irrefutable(pattern) {
+                self.output.push_str(" && (");
+                self.transpile_pattern_check(pattern, &part)?;
+                self.output.push(')');
+            }
+        }
+        Ok(())
     }
 
     fn transpile_formatted_expression(
@@ -1604,15 +2273,61 @@ impl JSTranspiler {
     fn generate_center_string_helper(&self) -> String {
         r#"
 // Helper function for center-aligned string formatting
-function centerString(str, width, fill = ' ') {
-    const s = str.toString();
+function centerString(str, width, fill) {
+    var s = str.toString();
+    if (fill === undefined) fill = ' ';
     if (s.length >= width) return s;
-    const padding = width - s.length;
-    const leftPad = Math.floor(padding / 2);
-    const rightPad = padding - leftPad;
+    var padding = width - s.length;
+    var leftPad = Math.floor(padding / 2);
+    var rightPad = padding - leftPad;
     return fill.repeat(leftPad) + s + fill.repeat(rightPad);
 }
 
+"#
+        .to_string()
+    }
+
+    fn generate_len_helper(&self) -> String {
+        r#"
+// Helper function for len(): strings are measured in code points, not UTF-16 units
+function nagariLen(obj) {
+    if (typeof obj === 'string') return obj.replace(/[\uD800-\uDBFF][\uDC00-\uDFFF]/g, '_').length;
+    if (Array.isArray(obj)) return obj.length;
+    if (obj !== null && typeof obj === 'object' && typeof obj.size === 'number') return obj.size;
+    if (obj !== null && typeof obj === 'object') return Object.keys(obj).length;
+    throw new Error("object of type '" + typeof obj + "' has no len()");
+}
+
+"#
+        .to_string()
+    }
+
+    fn generate_slice_helper(&self) -> String {
+        r#"
+// Helper function for Python-style slicing; strings are sliced by code point
+function nagariSlice(seq, start, stop, step) {
+    var isString = typeof seq === 'string';
+    var items = isString ? seq.match(/[\uD800-\uDBFF][\uDC00-\uDFFF]|[\s\S]/g) || [] : seq;
+    var length = items.length;
+    var result = [];
+    var i, to;
+    if (step === undefined || step === null) step = 1;
+    if (step === 0) throw new Error('slice step cannot be zero');
+    function bound(index, fallback, low, high) {
+        if (index === undefined || index === null) return fallback;
+        if (index < 0) index += length;
+        return Math.min(Math.max(index, low), high);
+    }
+    if (step > 0) {
+        to = bound(stop, length, 0, length);
+        for (i = bound(start, 0, 0, length); i < to; i += step) result.push(items[i]);
+    } else {
+        to = bound(stop, -1, -1, length - 1);
+        for (i = bound(start, length - 1, -1, length - 1); i > to; i += step) result.push(items[i]);
+    }
+    return isString ? result.join('') : result;
+}
+
 "#
         .to_string()
     }
@@ -1622,8 +2337,8 @@ function centerString(str, width, fill = ' ') {
 // Helper function for array stepping (slice with step)
 function arrayStep(arr, step) {
     if (step === 1) return arr;
-    const result = [];
-    for (let i = 0; i < arr.length; i += step) {
+    var result = [];
+    for (var i = 0; i < arr.length; i += step) {
         result.push(arr[i]);
     }
     return result;
@@ -1734,6 +2449,221 @@ function checkType(obj, type) {
         .to_string()
     }
 
+    /// `helper` looking up the shared module on `globalThis` directly where the environment
+    /// is known to have it, instead of falling back to the `fallback` function
+    fn global_root(&self, helper: &str, fallback: &str) -> String {
+        if self.js_runtime.supports(Feature::GlobalThis) {
+            let lookup = format!("typeof globalThis !== 'undefined' ? globalThis : {fallback}");
+            helper.replace(&lookup, "globalThis")
+        } else {
+            helper.to_string()
+        }
+    }
+
+    fn generate_events_helper(&self) -> String {
+        // ES5 syntax, as it is emitted for every target. The module lives on `globalThis`, so
+        // every compiled module and the JavaScript host share the default emitter.
+        let helper = r#"
+// Helper for the events module: EventEmitter, and on/once/off/emit of a shared emitter
+function nagariEvents() {
+    var root = typeof globalThis !== 'undefined' ? globalThis : nagariEvents;
+    if (root.__nagari_events__) return root.__nagari_events__;
+
+    function EventEmitter() {
+        if (!(this instanceof EventEmitter)) return new EventEmitter();
+        this.listeners = {};
+    }
+    EventEmitter.prototype.add = function (event, listener, once) {
+        if (typeof listener !== 'function') throw new TypeError('listener must be a function');
+        (this.listeners[event] = this.listeners[event] || []).push({ listener: listener, once: once });
+        return this;
+    };
+    EventEmitter.prototype.on = function (event, listener) {
+        return this.add(event, listener, false);
+    };
+    EventEmitter.prototype.once = function (event, listener) {
+        return this.add(event, listener, true);
+    };
+    // Remove `listener`, or every listener of `event` without one
+    EventEmitter.prototype.off = function (event, listener) {
+        var entries = this.listeners[event] || [];
+        var kept = listener === undefined ? [] : entries.filter(function (entry) {
+            return entry.listener !== listener;
+        });
+        this.listeners[event] = kept;
+        return kept.length !== entries.length;
+    };
+    // Call the listeners of `event` in the order they were added
+    EventEmitter.prototype.emit = function (event) {
+        var args = Array.prototype.slice.call(arguments, 1);
+        var entries = this.listeners[event] || [];
+        this.listeners[event] = entries.filter(function (entry) { return !entry.once; });
+        entries.forEach(function (entry) { entry.listener.apply(null, args); });
+        return entries.length > 0;
+    };
+
+    var shared = new EventEmitter();
+    root.__nagari_events__ = {
+        EventEmitter: EventEmitter,
+        on: function (event, listener) { shared.on(event, listener); },
+        once: function (event, listener) { shared.once(event, listener); },
+        off: function (event, listener) { return shared.off(event, listener); },
+        emit: function () { return shared.emit.apply(shared, arguments); }
+    };
+    return root.__nagari_events__;
+}
+
+"#;
+        self.global_root(helper, "nagariEvents")
+    }
+
+    fn generate_schema_helper(&self) -> String {
+        // Mirrors the VM's `schema` module, so both targets accept and coerce the same data
+        let helper = r#"
+// Helper for the schema module: validate(), errors() and optional()
+function nagariSchema() {
+    var root = typeof globalThis !== 'undefined' ? globalThis : nagariSchema;
+    if (root.__nagari_schema__) return root.__nagari_schema__;
+
+    var MARKER = '__schema__';
+    var INT = /^[+-]?\d+$/;
+    var FLOAT = /^[+-]?(\d+\.?\d*|\.\d+)([eE][+-]?\d+)?$/;
+    var TRUE = ['true', 'yes', 'on', '1'];
+    var FALSE = ['false', 'no', 'off', '0'];
+
+    function isDict(value) {
+        return value !== null && typeof value === 'object' && !Array.isArray(value);
+    }
+    function typeName(value) {
+        if (value === null || value === undefined) return 'none';
+        if (typeof value === 'boolean') return 'bool';
+        if (typeof value === 'number') return Number.isInteger(value) ? 'int' : 'float';
+        if (typeof value === 'string') return 'str';
+        if (Array.isArray(value)) return 'list';
+        if (typeof value === 'function') return 'function';
+        return 'dict';
+    }
+    function copy(value) {
+        if (Array.isArray(value)) return value.map(copy);
+        if (!isDict(value)) return value;
+        var result = {};
+        Object.keys(value).forEach(function (key) { result[key] = copy(value[key]); });
+        return result;
+    }
+    // The spec and default of an optional spec, or null
+    function asOptional(spec) {
+        if (typeof spec === 'string' && spec.charAt(spec.length - 1) === '?') {
+            return { spec: spec.slice(0, -1), default: null };
+        }
+        if (isDict(spec) && MARKER in spec) return spec;
+        return null;
+    }
+    // `data` as the type `name`, or undefined if it isn't one and can't be converted
+    function coerce(name, data) {
+        var type = typeName(data);
+        switch (name) {
+            case 'any':
+                return data === undefined ? null : data;
+            case 'str':
+            case 'list':
+            case 'dict':
+                return type === name ? data : undefined;
+            case 'int':
+                if (typeof data === 'number') return Number.isInteger(data) ? data : undefined;
+                if (type === 'str' && INT.test(data.trim())) return parseInt(data.trim(), 10);
+                return undefined;
+            case 'float':
+                if (typeof data === 'number') return data;
+                if (type === 'str' && FLOAT.test(data.trim())) return parseFloat(data.trim());
+                return undefined;
+            case 'bool':
+                if (type === 'bool') return data;
+                if (data === 0 || data === 1) return data === 1;
+                if (type !== 'str') return undefined;
+                if (TRUE.indexOf(data.trim().toLowerCase()) >= 0) return true;
+                if (FALSE.indexOf(data.trim().toLowerCase()) >= 0) return false;
+                return undefined;
+            default:
+                throw new TypeError("invalid schema: unknown type '" + name + "'");
+        }
+    }
+    function check(spec, data, path, errors) {
+        function error(message) {
+            errors.push(path ? path + ': ' + message : message);
+            return null;
+        }
+        var opt = asOptional(spec);
+        if (opt) {
+            return data === null || data === undefined ? null : check(opt.spec, data, path, errors);
+        }
+        if (typeof spec === 'string') {
+            var value = coerce(spec, data);
+            return value === undefined ? error('expected ' + spec + ', got ' + typeName(data)) : value;
+        }
+        if (Array.isArray(spec)) {
+            if (spec.length !== 1) {
+                throw new TypeError('invalid schema: a list spec takes exactly one item spec');
+            }
+            if (!Array.isArray(data)) return error('expected list, got ' + typeName(data));
+            return data.map(function (item, index) {
+                return check(spec[0], item, path + '[' + index + ']', errors);
+            });
+        }
+        if (isDict(spec)) {
+            if (!isDict(data)) return error('expected dict, got ' + typeName(data));
+            var result = {};
+            // In order of the names, so errors come out the same on every run
+            Object.keys(spec).sort().forEach(function (name) {
+                var fieldPath = path ? path + '.' + name : name;
+                var fieldOpt = asOptional(spec[name]);
+                if (data[name] !== undefined) {
+                    result[name] = check(spec[name], data[name], fieldPath, errors);
+                } else if (fieldOpt) {
+                    result[name] = copy(fieldOpt.default);
+                } else {
+                    errors.push(fieldPath + ': required field is missing');
+                }
+            });
+            return result;
+        }
+        throw new TypeError(
+            'invalid schema: expected a type name, dict, list or optional(), got ' + typeName(spec)
+        );
+    }
+    function schemaOf(fn, schema) {
+        if (!isDict(schema)) {
+            throw new TypeError(fn + "() schema must be a dict, not '" + typeName(schema) + "'");
+        }
+        return schema;
+    }
+
+    root.__nagari_schema__ = {
+        optional: function (spec, fallback) {
+            var marker = { spec: spec, default: fallback === undefined ? null : fallback };
+            marker[MARKER] = 'optional';
+            return marker;
+        },
+        errors: function (schema, data) {
+            var errors = [];
+            check(schemaOf('errors', schema), data, '', errors);
+            return errors;
+        },
+        validate: function (schema, data) {
+            var errors = [];
+            var value = check(schemaOf('validate', schema), data, '', errors);
+            if (errors.length === 0) return value;
+            var failure = new Error('validation failed: ' + errors.join('; '));
+            failure.errors = errors;
+            throw failure;
+        }
+    };
+    return root.__nagari_schema__;
+}
+
+"#;
+        self.global_root(helper, "nagariSchema")
+    }
+
     fn generate_decorator_helper(&self) -> String {
         r#"
 // Helper functions for decorators
@@ -1778,6 +2708,10 @@ function classmethod(target, propertyKey, descriptor) {
     }
 
     fn transpile_with(&mut self, with_stmt: &WithStatement) -> Result<(), NagariError> {
+        if with_stmt.is_async {
+            return self.transpile_async_with(with_stmt);
+        }
+        self.require_es6("with statements")?;
         self.add_indent();
 
         // JavaScript doesn't have direct with statement equivalent, so we'll use IIFE pattern
@@ -1868,6 +2802,88 @@ function classmethod(target, propertyKey, descriptor) {
         Ok(())
     }
 
+    /// `async with` as nested `try`/`finally` blocks, one per context manager, awaiting its
+    /// `__aenter__()` on the way in and its `__aexit__()`, or `[Symbol.asyncDispose]()` for
+    /// JavaScript disposables, on the way out
+    fn transpile_async_with(&mut self, with_stmt: &WithStatement) -> Result<(), NagariError> {
+        self.require_es6("async with statements")?;
+        let id = self.next_temp_id();
+        let previous_declared = self.declared_variables.clone();
+
+        self.add_indent();
+        self.output.push_str("{\n");
+        self.indent_level += 1;
+        let mut managers = Vec::new();
+        for item in &with_stmt.items {
+            let manager = format!("__async_ctx{}_{}", id, managers.len());
+            self.add_indent();
+            write!(self.output, "const {} = ", manager);
+            self.transpile_expression(&item.context_expr)?;
+            self.output.push_str(";\n");
+
+            self.add_indent();
+            if let Some(name) = &item.optional_vars {
+                write!(self.output, "let {} = ", name);
+                self.declared_variables.insert(name.clone());
+            }
+            writeln!(
+                self.output,
+                "typeof {m}.__aenter__ === 'function' ? await {m}.__aenter__() : {m};",
+                m = manager
+            );
+            self.add_indent();
+            self.output.push_str("try {\n");
+            self.indent_level += 1;
+            managers.push(manager);
+        }
+
+        for stmt in &with_stmt.body {
+            self.transpile_statement(stmt)?;
+            self.output.push('\n');
+        }
+
+        for manager in managers.iter().rev() {
+            self.indent_level -= 1;
+            self.add_indent();
+            self.output.push_str("} finally {\n");
+            self.indent_level += 1;
+            self.add_indent();
+            writeln!(
+                self.output,
+                "if (typeof {m}.__aexit__ === 'function') {{",
+                m = manager
+            );
+            self.indent_level += 1;
+            self.add_indent();
+            writeln!(
+                self.output,
+                "await {}.__aexit__(null, null, null);",
+                manager
+            );
+            self.indent_level -= 1;
+            self.add_indent();
+            writeln!(self.output,
+                "}} else if (typeof Symbol.asyncDispose === 'symbol' && typeof {m}[Symbol.asyncDispose] === 'function') {{",
+                m = manager
+            );
+            self.indent_level += 1;
+            self.add_indent();
+            writeln!(self.output, "await {}[Symbol.asyncDispose]();", manager);
+            self.indent_level -= 1;
+            self.add_indent();
+            self.output.push_str("}\n");
+            self.indent_level -= 1;
+            self.add_indent();
+            self.output.push_str("}\n");
+        }
+        self.declared_variables = previous_declared;
+
+        self.indent_level -= 1;
+        self.add_indent();
+        self.output.push('}');
+        Ok(())
+    }
+
     fn transpile_try(&mut self, try_stmt: &TryStatement) -> Result<(), NagariError> {
         self.add_indent();
         self.output.push_str("try {\n");
@@ -1984,11 +3000,12 @@ function classmethod(target, propertyKey, descriptor) {
         self.output.push_str(&type_alias.name);
         self.output.push_str(" = ");
         // For now, just create a comment since JS doesn't have native type aliases
-        self.output.push_str(&format!("{:?}", type_alias.type_expr));
+        write!(self.output, "{:?}", type_alias.type_expr);
         self.output.push('\n');
 
         self.add_indent();
-        self.output.push_str("const ");
+        self.output.push_str(self.binding_keyword("const"));
+        self.output.push(' ');
         self.output.push_str(&type_alias.name);
         self.output
             .push_str(" = function(value) { return value; }; // Type alias");
@@ -1997,6 +3014,7 @@ function classmethod(target, propertyKey, descriptor) {
     }
 
     fn transpile_yield(&mut self, yield_stmt: &YieldStatement) -> Result<(), NagariError> {
+        self.require_es6("generators")?;
         self.add_indent();
         self.output.push_str("yield");
 
@@ -2010,6 +3028,7 @@ function classmethod(target, propertyKey, descriptor) {
     }
 
     fn transpile_yield_from(&mut self, yield_from: &YieldFromStatement) -> Result<(), NagariError> {
+        self.require_es6("generators")?;
         self.add_indent();
         self.output.push_str("yield* ");
         self.transpile_expression(&yield_from.value)?;
@@ -2017,37 +3036,35 @@ function classmethod(target, propertyKey, descriptor) {
         Ok(())
     }
 
-    fn transpile_class_def(&mut self, class_def: &ClassDef) -> Result<(), NagariError> {
-        self.add_indent();
-        self.output.push_str("class ");
-        self.output.push_str(&class_def.name);
-
-        if let Some(superclass) = &class_def.superclass {
-            self.output.push_str(" extends ");
-            self.output.push_str(superclass);
-        }
-
-        self.output.push_str(" {\n");
-        self.indent_level += 1;
-
-        for stmt in &class_def.body {
-            self.transpile_statement(stmt)?;
-            self.output.push('\n');
-        }
-
-        self.indent_level -= 1;
-        self.add_indent();
-        self.output.push('}');
-
-        Ok(())
-    }
-
     fn transpile_destructuring_assignment(
         &mut self,
         destructuring: &DestructuringAssignment,
     ) -> Result<(), NagariError> {
         self.add_indent();
 
+        if self.is_es5() {
+            let targets = match &destructuring.target {
+                Expression::Dict(properties) => properties
+                    .iter()
+                    .filter_map(|(key, _)| match key {
+                        Expression::Identifier(name) => Some((name.clone(), format!(".{}", name))),
+                        _ => None,
+                    })
+                    .collect(),
+                Expression::List(elements) => elements
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, element)| match element {
+                        Expression::Identifier(name) => Some((name.clone(), format!("[{}]", i))),
+                        _ => None,
+                    })
+                    .collect(),
+                Expression::Identifier(name) => vec![(name.clone(), String::new())],
+                _ => return self.require_es6("this destructuring target"),
+            };
+            return self.transpile_es5_unpacking(&targets, &destructuring.value);
+        }
+
         // Convert Nagari destructuring to JavaScript destructuring
         match &destructuring.target {
             Expression::Dict(properties) => {
@@ -2094,6 +3111,12 @@ function classmethod(target, propertyKey, descriptor) {
         array_destructuring: &ArrayDestructuringAssignment,
     ) -> Result<(), NagariError> {
         self.add_indent();
+
+        if self.is_es5() {
+            let targets = Self::indexed_targets(&array_destructuring.targets);
+            return self.transpile_es5_unpacking(&targets, &array_destructuring.value);
+        }
+
         self.output.push_str("const [");
 
         for (i, target) in array_destructuring.targets.iter().enumerate() {
@@ -2191,12 +3214,16 @@ function classmethod(target, propertyKey, descriptor) {
             self.output.push('\'');
         } else {
             // CommonJS style - require and re-export all properties
-            self.output.push_str("const __temp_exports = require('");
+            self.output.push_str(self.binding_keyword("const"));
+            self.output.push_str(" __temp_exports = require('");
             self.output.push_str(&export_all.module);
             self.output.push_str("');\n");
             self.add_indent();
-            self.output
-                .push_str("Object.keys(__temp_exports).forEach(key => {\n");
+            self.output.push_str(if self.is_es5() {
+                "Object.keys(__temp_exports).forEach(function (key) {\n"
+            } else {
+                "Object.keys(__temp_exports).forEach(key => {\n"
+            });
             self.indent_level += 1;
             self.add_indent();
             self.output
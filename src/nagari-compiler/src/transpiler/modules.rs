@@ -7,6 +7,16 @@ use crate::ast::{
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+/// Name of the helper generated with the program's output that implements a builtin module,
+/// for the modules that are not part of the runtime
+pub fn module_helper(module: &str) -> Option<&'static str> {
+    match module {
+        "events" => Some("nagariEvents"),
+        "schema" => Some("nagariSchema"),
+        _ => None,
+    }
+}
+
 pub struct ModuleResolver {
     builtin_modules: HashMap<String, BuiltinModule>,
     target: String,
@@ -79,30 +89,223 @@ impl ModuleResolver {
             js_equivalent: Some("http".to_string()),
         });
 
+        self.add_builtin_module(BuiltinModule {
+            name: "os".to_string(),
+            path: PathBuf::from("os"),
+            exports: vec![
+                "platform".to_string(),
+                "arch".to_string(),
+                "cpus".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: Some("os".to_string()),
+        });
+
+        // Unicode text utilities (provided by the Nagari runtime)
+        self.add_builtin_module(BuiltinModule {
+            name: "unicodedata".to_string(),
+            path: PathBuf::from("unicodedata"),
+            exports: vec!["normalize".to_string(), "graphemes".to_string()],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // Timers (provided by the Nagari runtime, not Node's `timers`)
+        self.add_builtin_module(BuiltinModule {
+            name: "timers".to_string(),
+            path: PathBuf::from("timers"),
+            exports: vec![
+                "sleep".to_string(),
+                "set_timeout".to_string(),
+                "set_interval".to_string(),
+                "cancel".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // Event emitter (generated as a helper, not Node's `events`)
+        self.add_builtin_module(BuiltinModule {
+            name: "events".to_string(),
+            path: PathBuf::from("events"),
+            exports: vec![
+                "EventEmitter".to_string(),
+                "on".to_string(),
+                "once".to_string(),
+                "off".to_string(),
+                "emit".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // Schema validation (generated as a helper)
+        self.add_builtin_module(BuiltinModule {
+            name: "schema".to_string(),
+            path: PathBuf::from("schema"),
+            exports: vec![
+                "validate".to_string(),
+                "errors".to_string(),
+                "optional".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // CSV reading and writing
+        self.add_builtin_module(BuiltinModule {
+            name: "csv".to_string(),
+            path: PathBuf::from("csv"),
+            exports: vec![
+                "parse".to_string(),
+                "format".to_string(),
+                "read".to_string(),
+                "write".to_string(),
+                "open".to_string(),
+                "next_row".to_string(),
+                "close".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // Binary record packing
+        self.add_builtin_module(BuiltinModule {
+            name: "struct".to_string(),
+            path: PathBuf::from("struct"),
+            exports: vec![
+                "pack".to_string(),
+                "unpack".to_string(),
+                "unpack_from".to_string(),
+                "calcsize".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // Path manipulation, the same on every backend
         self.add_builtin_module(BuiltinModule {
             name: "path".to_string(),
             path: PathBuf::from("path"),
             exports: vec![
                 "join".to_string(),
-                "resolve".to_string(),
                 "dirname".to_string(),
+                "basename".to_string(),
+                "extension".to_string(),
+                "normalize".to_string(),
+                "relative".to_string(),
+                "is_absolute".to_string(),
+                "glob".to_string(),
+                "sep".to_string(),
             ],
             js_path: None,
             interop_required: true,
-            js_equivalent: Some("path".to_string()),
+            js_equivalent: None,
         });
 
+        // Hashing, HMAC, encodings and UUIDs
         self.add_builtin_module(BuiltinModule {
-            name: "os".to_string(),
-            path: PathBuf::from("os"),
+            name: "crypto".to_string(),
+            path: PathBuf::from("crypto"),
             exports: vec![
-                "platform".to_string(),
-                "arch".to_string(),
-                "cpus".to_string(),
+                "hash_sha1".to_string(),
+                "hash_sha256".to_string(),
+                "hmac".to_string(),
+                "base64_encode".to_string(),
+                "base64_decode".to_string(),
+                "hex_encode".to_string(),
+                "hex_decode".to_string(),
+                "uuid4".to_string(),
             ],
             js_path: None,
             interop_required: true,
-            js_equivalent: Some("os".to_string()),
+            js_equivalent: None,
+        });
+
+        // Locale-aware number, currency and date formatting
+        self.add_builtin_module(BuiltinModule {
+            name: "intl".to_string(),
+            path: PathBuf::from("intl"),
+            exports: vec![
+                "format_number".to_string(),
+                "format_currency".to_string(),
+                "format_percent".to_string(),
+                "format_date".to_string(),
+                "format_time".to_string(),
+                "format_datetime".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // Markup templates that escape what goes into them
+        self.add_builtin_module(BuiltinModule {
+            name: "html".to_string(),
+            path: PathBuf::from("html"),
+            exports: vec!["html".to_string(), "escape".to_string(), "safe".to_string()],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // WebSocket clients and servers
+        self.add_builtin_module(BuiltinModule {
+            name: "websocket".to_string(),
+            path: PathBuf::from("websocket"),
+            exports: vec![
+                "connect".to_string(),
+                "send".to_string(),
+                "receive".to_string(),
+                "on_message".to_string(),
+                "close".to_string(),
+                "serve".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // HTTP servers
+        self.add_builtin_module(BuiltinModule {
+            name: "http.server".to_string(),
+            path: PathBuf::from("http.server"),
+            exports: vec![
+                "create".to_string(),
+                "route".to_string(),
+                "static_files".to_string(),
+                "listen".to_string(),
+                "stop".to_string(),
+                "response".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
+        });
+
+        // SQLite databases
+        self.add_builtin_module(BuiltinModule {
+            name: "sqlite".to_string(),
+            path: PathBuf::from("sqlite"),
+            exports: vec![
+                "open".to_string(),
+                "execute".to_string(),
+                "query".to_string(),
+                "begin".to_string(),
+                "commit".to_string(),
+                "rollback".to_string(),
+                "close".to_string(),
+            ],
+            js_path: None,
+            interop_required: true,
+            js_equivalent: None,
         });
 
         // Express framework
@@ -176,6 +379,27 @@ impl ModuleResolver {
         self.builtin_modules.get(name)
     }
 
+    fn is_es5(&self) -> bool {
+        self.target == "es5"
+    }
+
+    fn binding_keyword(&self) -> &'static str {
+        if self.is_es5() {
+            "var"
+        } else {
+            "const"
+        }
+    }
+
+    /// One `var` per imported name, as ES5 has no destructuring
+    fn es5_member_bindings(&self, names: &[String], source: &str) -> String {
+        names
+            .iter()
+            .map(|name| format!("var {} = {}.{};", name, source, name))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
     pub fn resolve_import(&self, import: &ImportStatement) -> String {
         if let Some(builtin) = self.get_builtin_module(&import.module) {
             if builtin.interop_required {
@@ -193,9 +417,13 @@ impl ModuleResolver {
         if self.builtin_modules.contains_key(&import.module) {
             // For default imports from builtin modules, generate InteropRegistry call
             format!(
-                "const {} = InteropRegistry.getModule('{}');",
-                import.name, import.module
+                "{} {} = {};",
+                self.binding_keyword(),
+                import.name,
+                self.builtin_source(&import.module)
             )
+        } else if self.is_es5() {
+            format!("var {} = require('{}');", import.name, import.module)
         } else {
             // Regular ES6 default import
             format!("import {} from '{}';", import.name, import.module)
@@ -211,12 +439,17 @@ impl ModuleResolver {
                 .iter()
                 .map(|name| {
                     format!(
-                        "const {} = InteropRegistry.getModule('{}').{};",
-                        name, import.module, name
+                        "{} {} = {}.{};",
+                        self.binding_keyword(),
+                        name,
+                        self.builtin_source(&import.module),
+                        name
                     )
                 })
                 .collect();
             imports.join("\n")
+        } else if self.is_es5() {
+            self.es5_member_bindings(&import.imports, &format!("require('{}')", import.module))
         } else {
             // Regular ES6 named import
             let imports_list = import.imports.join(", ");
@@ -229,20 +462,34 @@ impl ModuleResolver {
         if self.builtin_modules.contains_key(&import.module) {
             // For namespace imports from builtin modules, generate InteropRegistry call
             format!(
-                "const {} = InteropRegistry.getModule('{}');",
-                import.alias, import.module
+                "{} {} = {};",
+                self.binding_keyword(),
+                import.alias,
+                self.builtin_source(&import.module)
             )
+        } else if self.is_es5() {
+            format!("var {} = require('{}');", import.alias, import.module)
         } else {
             // Regular ES6 namespace import
             format!("import * as {} from '{}';", import.alias, import.module)
         }
     }
 
+    /// Expression evaluating to a builtin module's namespace
+    fn builtin_source(&self, module: &str) -> String {
+        match module_helper(module) {
+            Some(helper) => format!("{helper}()"),
+            None => format!("InteropRegistry.getModule('{module}')"),
+        }
+    }
+
     pub fn resolve_import_side_effect(&self, import: &ImportSideEffectStatement) -> String {
         // Check if it's a builtin module
         if self.builtin_modules.contains_key(&import.module) {
             // For side effect imports from builtin modules, just import the module
             format!("InteropRegistry.getModule('{}');", import.module)
+        } else if self.is_es5() {
+            format!("require('{}');", import.module)
         } else {
             // Regular ES6 side effect import
             format!("import '{}';", import.module)
@@ -250,9 +497,41 @@ impl ModuleResolver {
     }
 
     fn generate_interop_import(&self, import: &ImportStatement, builtin: &BuiltinModule) -> String {
+        let helper = module_helper(&import.module);
+        if self.is_es5() {
+            return match &import.items {
+                Some(items) if import.module == "react" => {
+                    self.es5_member_bindings(items, "ReactInterop")
+                }
+                Some(items) if helper.is_some() => {
+                    self.es5_member_bindings(items, &self.builtin_source(&import.module))
+                }
+                Some(items) => self.es5_member_bindings(
+                    items,
+                    &format!("(InteropRegistry.getModule(\"{}\") || {{}})", builtin.name),
+                ),
+                None if import.module == "react" => "var React = ReactInterop;".to_string(),
+                None if helper.is_some() => format!(
+                    "var {} = {};",
+                    import.module,
+                    self.builtin_source(&import.module)
+                ),
+                None => format!(
+                    "var {} = InteropRegistry.getModule(\"{}\");",
+                    import.module, builtin.name
+                ),
+            };
+        }
+
         if let Some(items) = &import.items {
             if import.module == "react" {
                 format!("const {{ {} }} = ReactInterop;", items.join(", "))
+            } else if helper.is_some() {
+                format!(
+                    "const {{ {} }} = {};",
+                    items.join(", "),
+                    self.builtin_source(&import.module)
+                )
             } else {
                 format!(
                     "const {{ {} }} = InteropRegistry.getModule(\"{}\") || {{}};",
@@ -262,6 +541,12 @@ impl ModuleResolver {
             }
         } else if import.module == "react" {
             "const React = ReactInterop;".to_string()
+        } else if helper.is_some() {
+            format!(
+                "const {} = {};",
+                import.module,
+                self.builtin_source(&import.module)
+            )
         } else {
             format!(
                 "const {} = InteropRegistry.getModule(\"{}\");",
@@ -294,6 +579,12 @@ impl ModuleResolver {
                     format!("const {} = require(\"{}\");", import.module, js_module)
                 }
             }
+            "es5" => match &import.items {
+                Some(items) => {
+                    self.es5_member_bindings(items, &format!("require(\"{}\")", js_module))
+                }
+                None => format!("var {} = require(\"{}\");", import.module, js_module),
+            },
             _ => self.generate_external_import(import),
         }
     }
@@ -322,6 +613,12 @@ impl ModuleResolver {
                     format!("const {} = require(\"{}\");", import.module, import.module)
                 }
             }
+            "es5" => match &import.items {
+                Some(items) => {
+                    self.es5_member_bindings(items, &format!("require(\"{}\")", import.module))
+                }
+                None => format!("var {} = require(\"{}\");", import.module, import.module),
+            },
             _ => {
                 if let Some(items) = &import.items {
                     format!(
@@ -336,7 +633,8 @@ impl ModuleResolver {
         }
     }
 
-    pub fn get_runtime_imports(&self, jsx_enabled: bool) -> String {
+    /// Import of the runtime helpers from the `runtime` specifier
+    pub fn get_runtime_imports(&self, runtime: &str, jsx_enabled: bool, ssr: bool) -> String {
         let mut imports = vec![
             "jsToNagari",
             "nagariToJS",
@@ -351,22 +649,33 @@ impl ModuleResolver {
             "str_center",
         ];
 
-        if jsx_enabled {
+        if jsx_enabled && ssr {
+            imports.extend_from_slice(&["jsx", "Fragment", "render_to_string"]);
+        } else if jsx_enabled {
             imports.extend_from_slice(&["jsx", "Fragment", "jsxToReact", "ReactInterop"]);
         }
 
         match self.target.as_str() {
             "esm" | "es6" => {
-                format!("import {{ {} }} from 'nagari-runtime';", imports.join(", "))
+                format!("import {{ {} }} from '{}';", imports.join(", "), runtime)
             }
             "node" | "cjs" => {
                 format!(
-                    "const {{ {} }} = require('nagari-runtime');",
-                    imports.join(", ")
+                    "const {{ {} }} = require('{}');",
+                    imports.join(", "),
+                    runtime
+                )
+            }
+            "es5" => {
+                let names: Vec<String> = imports.iter().map(|name| name.to_string()).collect();
+                format!(
+                    "var __nagari_runtime = require('{}');\n{}",
+                    runtime,
+                    self.es5_member_bindings(&names, "__nagari_runtime")
                 )
             }
             _ => {
-                format!("import {{ {} }} from 'nagari-runtime';", imports.join(", "))
+                format!("import {{ {} }} from '{}';", imports.join(", "), runtime)
             }
         }
     }
@@ -0,0 +1,211 @@
+// Set operators on the JavaScript targets.
+//
+// JavaScript's `|`, `&`, `^` and `-` turn a `Set` into a number, its comparisons compare sets by
+// identity or not at all, so an operator with an operand known to be a set calls `nagariSetOp()`
+// instead: union, intersection, symmetric difference and difference, subset and superset tests,
+// and equality by elements. An operand is known to be a set when it is a set literal or
+// comprehension, a `set()` or `frozenset()` call, another set operation, or a name bound to one
+// of those or annotated as a set. `frozenset()` builds a `Set` whose mutating methods throw, and
+// operations on it build frozen sets in turn.
+
+use super::JSTranspiler;
+use crate::ast::*;
+use crate::error::NagariError;
+use crate::types::Type;
+use std::collections::HashSet;
+
+/// The `op` argument of `nagariSetOp()` for an operator that sets overload
+fn set_operator(operator: &BinaryOperator) -> Option<&'static str> {
+    Some(match operator {
+        BinaryOperator::BitwiseOr => "|",
+        BinaryOperator::BitwiseAnd => "&",
+        BinaryOperator::BitwiseXor => "^",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterEqual => ">=",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        _ => return None,
+    })
+}
+
+/// Whether an operation with `operator` on sets gives a set, rather than a boolean
+fn builds_set(operator: &BinaryOperator) -> bool {
+    matches!(
+        operator,
+        BinaryOperator::BitwiseOr
+            | BinaryOperator::BitwiseAnd
+            | BinaryOperator::BitwiseXor
+            | BinaryOperator::Subtract
+    )
+}
+
+fn is_set_type(annotation: Option<&Type>) -> bool {
+    matches!(annotation, Some(Type::Set(_)))
+}
+
+impl JSTranspiler {
+    /// Whether `expr` is known to evaluate to a set
+    pub(super) fn is_set_expression(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Set(_) | Expression::SetComprehension(_) => true,
+            Expression::Identifier(name) => self.sets.contains(name),
+            Expression::Call(call) => matches!(
+                call.function.as_ref(),
+                Expression::Identifier(name) if name == "set" || name == "frozenset"
+            ),
+            Expression::Binary(binary) => {
+                builds_set(&binary.operator)
+                    && (self.is_set_expression(&binary.left)
+                        || self.is_set_expression(&binary.right))
+            }
+            _ => false,
+        }
+    }
+
+    /// Remember whether `name` holds a set after it is assigned `value`
+    pub(super) fn bind_set_name(
+        &mut self,
+        name: &str,
+        annotation: Option<&Type>,
+        value: &Expression,
+    ) {
+        if is_set_type(annotation) || self.is_set_expression(value) {
+            self.sets.insert(name.to_string());
+        } else {
+            self.sets.remove(name);
+        }
+    }
+
+    /// Start the scope of a function with `parameters`, returning the set names to restore after
+    pub(super) fn enter_set_scope(&mut self, parameters: &[Parameter]) -> HashSet<String> {
+        let outer = self.sets.clone();
+        for parameter in parameters {
+            // The annotation of `*args` or `**kwargs` is that of each of its values
+            if parameter.kind == ParameterKind::Positional
+                && is_set_type(parameter.param_type.as_ref())
+            {
+                self.sets.insert(parameter.name.clone());
+            } else {
+                self.sets.remove(&parameter.name);
+            }
+        }
+        outer
+    }
+
+    /// `nagariSetOp("|", a, b)` for an operator with a set operand; false for other operations
+    pub(super) fn transpile_set_operation(
+        &mut self,
+        binary: &BinaryExpression,
+    ) -> Result<bool, NagariError> {
+        let Some(operator) = set_operator(&binary.operator) else {
+            return Ok(false);
+        };
+        if !self.is_set_expression(&binary.left) && !self.is_set_expression(&binary.right) {
+            return Ok(false);
+        }
+
+        self.used_helpers.insert("nagariSets".to_string());
+        write!(self.output, "nagariSetOp(\"{operator}\", ");
+        self.transpile_expression(&binary.left)?;
+        self.output.push_str(", ");
+        self.transpile_expression(&binary.right)?;
+        self.output.push(')');
+        Ok(true)
+    }
+
+    /// `set(iterable)` as `new Set(iterable)` and `frozenset(iterable)` through the helper;
+    /// false for other calls
+    pub(super) fn transpile_set_call(
+        &mut self,
+        call: &CallExpression,
+    ) -> Result<bool, NagariError> {
+        let constructor = match call.function.as_ref() {
+            Expression::Identifier(name) if name == "set" => "new Set(",
+            Expression::Identifier(name) if name == "frozenset" => {
+                self.used_helpers.insert("nagariSets".to_string());
+                "nagariFrozenSet("
+            }
+            _ => return Ok(false),
+        };
+        if call.arguments.len() > 1 || !call.keyword_args.is_empty() {
+            return Ok(false);
+        }
+
+        self.output.push_str(constructor);
+        if let Some(iterable) = call.arguments.first() {
+            self.transpile_expression(iterable)?;
+        }
+        self.output.push(')');
+        Ok(true)
+    }
+
+    pub(super) fn generate_sets_helper(&self) -> String {
+        // ES5 syntax, as it is emitted for every target
+        r#"
+// Helper for set operators: union, intersection, differences and comparisons by element. Any
+// other operands get the plain JavaScript operator.
+function nagariSetOp(op, a, b) {
+    if (!(a instanceof Set) || !(b instanceof Set)) {
+        switch (op) {
+            case '|': return a | b;
+            case '&': return a & b;
+            case '^': return a ^ b;
+            case '-': return a - b;
+            case '<': return a < b;
+            case '<=': return a <= b;
+            case '>': return a > b;
+            case '>=': return a >= b;
+            case '==': return a === b;
+            case '!=': return a !== b;
+        }
+    }
+    function items(set) {
+        var result = [];
+        set.forEach(function (item) { result.push(item); });
+        return result;
+    }
+    function only(x, y) {
+        return items(x).filter(function (item) { return !y.has(item); });
+    }
+    function subset(x, y) {
+        return x.size <= y.size && only(x, y).length === 0;
+    }
+    // The result is frozen when the left operand is, as in Python
+    function make(values) {
+        return a.nagariFrozen ? nagariFrozenSet(values) : new Set(values);
+    }
+    switch (op) {
+        case '|': return make(items(a).concat(items(b)));
+        case '&': return make(items(a).filter(function (item) { return b.has(item); }));
+        case '-': return make(only(a, b));
+        case '^': return make(only(a, b).concat(only(b, a)));
+        case '<': return a.size < b.size && subset(a, b);
+        case '<=': return subset(a, b);
+        case '>': return a.size > b.size && subset(b, a);
+        case '>=': return subset(b, a);
+        case '==': return a.size === b.size && subset(a, b);
+        case '!=': return !(a.size === b.size && subset(a, b));
+    }
+    throw new Error('unsupported set operator ' + op);
+}
+
+// Helper for frozenset(): a Set whose add, delete and clear throw
+function nagariFrozenSet(iterable) {
+    var set = new Set(iterable);
+    function frozen() {
+        throw new TypeError("'frozenset' object does not support item assignment or deletion");
+    }
+    Object.defineProperty(set, 'nagariFrozen', { value: true });
+    ['add', 'delete', 'clear'].forEach(function (method) {
+        Object.defineProperty(set, method, { value: frozen });
+    });
+    return set;
+}
+
+"#
+        .to_string()
+    }
+}
@@ -0,0 +1,75 @@
+//! The buffer the transpiler writes its output to.
+//!
+//! Output is built from many small pieces, so the buffer is allocated once with room for a
+//! typical program of the size being transpiled, indentation is written without building a
+//! string for it, and `write!` formats straight into the buffer instead of through a temporary
+//! `format!` string.
+
+use std::fmt;
+
+/// Bytes of output per top-level statement the buffer starts with room for, besides
+/// [`BASE_CAPACITY`]; about what a short function transpiles to
+pub(super) const STATEMENT_CAPACITY: usize = 512;
+/// Bytes the buffer starts with room for whatever the program, enough for the header and the
+/// runtime helpers
+pub(super) const BASE_CAPACITY: usize = 8 * 1024;
+
+const INDENT: &str = "                                ";
+const INDENT_WIDTH: usize = 4;
+
+#[derive(Debug, Default)]
+pub(super) struct Writer {
+    buffer: String,
+}
+
+impl Writer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: String::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, ch: char) {
+        self.buffer.push(ch);
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.buffer.push_str(text);
+    }
+
+    /// Write `level` levels of indentation
+    pub fn indent(&mut self, level: usize) {
+        let mut width = level * INDENT_WIDTH;
+        while width > 0 {
+            let chunk = width.min(INDENT.len());
+            self.buffer.push_str(&INDENT[..chunk]);
+            width -= chunk;
+        }
+    }
+
+    /// What `write!` on the writer calls; formatting into a `String` can't fail
+    pub fn write_fmt(&mut self, args: fmt::Arguments) {
+        let _ = fmt::Write::write_fmt(&mut self.buffer, args);
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.buffer.truncate(len);
+    }
+
+    /// Take the output from `at` on out of the writer
+    pub fn split_off(&mut self, at: usize) -> String {
+        self.buffer.split_off(at)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.buffer
+    }
+
+    pub fn into_string(self) -> String {
+        self.buffer
+    }
+}
@@ -0,0 +1,1041 @@
+//! Static type checking of converted programs.
+//!
+//! Runs on the internal AST, after conversion and before transpiling. Parameters have the type
+//! of their annotation, locals the type of the value last assigned to them, and a call of a
+//! function defined in the module the type of its return annotation. Arguments of calls to
+//! functions and classes defined in the module are checked against the annotations of their
+//! parameters, defaults against the annotation of their parameter, and returned values against
+//! the return annotation.
+//!
+//! Whatever isn't known is `any` and fits everywhere, so unannotated code is never reported.
+//! After an `if`, a loop or a `try`, a local only keeps its type if every way through gave it
+//! that type. Findings are `type-mismatch` warnings, or errors in strict mode.
+
+use crate::ast::*;
+use crate::error::NagariError;
+use crate::externs::{accepts, type_name};
+use crate::types::Type;
+use crate::warnings::Warning;
+use std::collections::HashMap;
+
+/// Category of the warnings reported outside strict mode
+pub const CATEGORY: &str = "type-mismatch";
+
+/// Check `program`, returning its type errors as warnings, or failing on the first ones found
+/// when `strict`
+pub fn check(program: &Program, strict: bool) -> Result<Vec<Warning>, NagariError> {
+    let mut checker = Checker {
+        errors: Vec::new(),
+        returns: Vec::new(),
+    };
+    checker.block(&program.statements, &mut Scope::new());
+
+    if strict && !checker.errors.is_empty() {
+        return Err(NagariError::TypeError(checker.errors.join("\n  ")));
+    }
+    Ok(checker
+        .errors
+        .into_iter()
+        .map(|message| Warning {
+            category: CATEGORY.to_string(),
+            message,
+            line: None,
+            column: None,
+        })
+        .collect())
+}
+
+/// What a name in scope is known to be
+#[derive(Clone)]
+enum Known<'a> {
+    Function(&'a FunctionDef),
+    /// A class, with the `__init__` its calls are checked against
+    Class(Option<&'a FunctionDef>),
+    /// A value; `declared` ones keep their type when assigned to
+    Typed {
+        value_type: Type,
+        declared: bool,
+    },
+}
+
+impl Known<'_> {
+    fn value(value_type: Type) -> Self {
+        Known::Typed {
+            value_type,
+            declared: false,
+        }
+    }
+
+    fn same(&self, other: &Known) -> bool {
+        match (self, other) {
+            (Known::Function(a), Known::Function(b)) => std::ptr::eq(*a, *b),
+            (Known::Class(a), Known::Class(b)) => match (a, b) {
+                (Some(a), Some(b)) => std::ptr::eq(*a, *b),
+                (a, b) => a.is_none() && b.is_none(),
+            },
+            (
+                Known::Typed {
+                    value_type: a,
+                    declared: a_declared,
+                },
+                Known::Typed {
+                    value_type: b,
+                    declared: b_declared,
+                },
+            ) => a == b && a_declared == b_declared,
+            _ => false,
+        }
+    }
+}
+
+/// Names defined so far; names missing from it are builtins or globals
+type Scope<'a> = HashMap<String, Known<'a>>;
+
+struct Checker {
+    errors: Vec<String>,
+    /// Name and declared return type of each enclosing function
+    returns: Vec<(String, Option<Type>)>,
+}
+
+impl Checker {
+    fn block<'a>(&mut self, statements: &'a [Statement], scope: &mut Scope<'a>) {
+        // Functions and classes can be called before their definition runs
+        for statement in statements {
+            hoist(statement, scope);
+        }
+        for statement in statements {
+            self.statement(statement, scope);
+        }
+    }
+
+    /// Walk `statements` in a copy of `scope`, as one of the ways through a statement
+    fn branch<'a>(&mut self, statements: &'a [Statement], scope: &Scope<'a>) -> Scope<'a> {
+        let mut inner = scope.clone();
+        self.block(statements, &mut inner);
+        inner
+    }
+
+    fn statement<'a>(&mut self, statement: &'a Statement, scope: &mut Scope<'a>) {
+        match statement {
+            Statement::FunctionDef(function) => {
+                for decorator in &function.decorators {
+                    for argument in decorator.arguments.iter().flatten() {
+                        self.expression(argument, scope);
+                    }
+                    for (_, value) in &decorator.keyword_args {
+                        self.expression(value, scope);
+                    }
+                }
+                let return_type = match function.is_generator {
+                    true => None,
+                    false => function.return_type.as_ref(),
+                };
+                self.function(
+                    &function.name,
+                    &function.parameters,
+                    &function.body,
+                    return_type,
+                    scope,
+                );
+            }
+            Statement::Assignment(assignment) => {
+                let value_type = self.expression(&assignment.value, scope);
+                match &assignment.var_type {
+                    Some(declared) => {
+                        self.assignment(&assignment.name, declared, &value_type);
+                        scope.insert(
+                            assignment.name.clone(),
+                            Known::Typed {
+                                value_type: declared.clone(),
+                                declared: true,
+                            },
+                        );
+                    }
+                    None => self.assign(&assignment.name, value_type, scope),
+                }
+            }
+            Statement::AttributeAssignment(assignment) => {
+                self.expression(&assignment.object, scope);
+                self.expression(&assignment.value, scope);
+            }
+            Statement::IndexAssignment(assignment) => {
+                self.expression(&assignment.object, scope);
+                self.expression(&assignment.index, scope);
+                self.expression(&assignment.value, scope);
+            }
+            Statement::TupleAssignment(assignment) => {
+                self.expression(&assignment.value, scope);
+                for target in &assignment.targets {
+                    self.assign(target, Type::Any, scope);
+                }
+            }
+            Statement::If(if_stmt) => {
+                self.expression(&if_stmt.condition, scope);
+                let mut branches = vec![self.branch(&if_stmt.then_branch, scope)];
+                for elif in &if_stmt.elif_branches {
+                    self.expression(&elif.condition, scope);
+                    branches.push(self.branch(&elif.body, scope));
+                }
+                match &if_stmt.else_branch {
+                    Some(else_branch) => branches.push(self.branch(else_branch, scope)),
+                    None => branches.push(scope.clone()),
+                }
+                merge(scope, &branches);
+            }
+            Statement::While(while_loop) => {
+                widen(scope, &while_loop.body);
+                self.expression(&while_loop.condition, scope);
+                self.branch(&while_loop.body, scope);
+            }
+            Statement::For(for_loop) => {
+                let iterable = self.expression(&for_loop.iterable, scope);
+                widen(scope, &for_loop.body);
+                let mut inner = scope.clone();
+                inner.insert(
+                    for_loop.variable.clone(),
+                    Known::value(element_type(&iterable)),
+                );
+                self.block(&for_loop.body, &mut inner);
+                scope.insert(for_loop.variable.clone(), Known::value(Type::Any));
+            }
+            Statement::Match(match_stmt) => {
+                self.expression(&match_stmt.expression, scope);
+                let mut branches = vec![scope.clone()];
+                for case in &match_stmt.cases {
+                    let mut inner = scope.clone();
+                    self.pattern(&case.pattern, &mut inner);
+                    self.block(&case.body, &mut inner);
+                    branches.push(inner);
+                }
+                merge(scope, &branches);
+            }
+            Statement::Return(value) => {
+                let value_type = match value {
+                    Some(value) => self.expression(value, scope),
+                    None => Type::None,
+                };
+                if let Some((name, Some(declared))) = self.returns.last() {
+                    if !accepts(declared, &value_type) {
+                        self.errors.push(format!(
+                            "{name}() returns {} but is declared to return {}",
+                            type_name(&value_type),
+                            type_name(declared)
+                        ));
+                    }
+                }
+            }
+            Statement::Expression(expr) => {
+                self.expression(expr, scope);
+            }
+            Statement::Del(target) => {
+                self.expression(target, scope);
+                if let Expression::Identifier(name) = target {
+                    scope.remove(name);
+                }
+            }
+            Statement::With(with_stmt) => {
+                for item in &with_stmt.items {
+                    self.expression(&item.context_expr, scope);
+                    if let Some(name) = &item.optional_vars {
+                        self.assign(name, Type::Any, scope);
+                    }
+                }
+                self.block(&with_stmt.body, scope);
+            }
+            Statement::Try(try_stmt) => {
+                widen(scope, &try_stmt.body);
+                let mut branches = vec![self.branch(&try_stmt.body, scope)];
+                for handler in &try_stmt.except_handlers {
+                    let mut inner = scope.clone();
+                    if let Some(name) = &handler.name {
+                        inner.insert(name.clone(), Known::value(Type::Any));
+                    }
+                    self.block(&handler.body, &mut inner);
+                    branches.push(inner);
+                }
+                if let Some(else_clause) = &try_stmt.else_clause {
+                    let after_body = branches[0].clone();
+                    branches[0] = self.branch(else_clause, &after_body);
+                }
+                merge(scope, &branches);
+                if let Some(finally_clause) = &try_stmt.finally_clause {
+                    self.block(finally_clause, scope);
+                }
+            }
+            Statement::Raise(raise) => {
+                for value in [&raise.exception, &raise.cause].into_iter().flatten() {
+                    self.expression(value, scope);
+                }
+            }
+            Statement::Yield(yield_stmt) => {
+                if let Some(value) = &yield_stmt.value {
+                    self.expression(value, scope);
+                }
+            }
+            Statement::YieldFrom(yield_from) => {
+                self.expression(&yield_from.value, scope);
+            }
+            Statement::ClassDef(class) => {
+                // Methods aren't names in scope, so the body's functions aren't hoisted
+                let mut inner = scope.clone();
+                for statement in &class.body {
+                    self.statement(statement, &mut inner);
+                }
+            }
+            Statement::DestructuringAssignment(assignment) => {
+                self.expression(&assignment.value, scope);
+                let mut names = Vec::new();
+                target_names(&assignment.target, &mut names);
+                for name in names {
+                    self.assign(name, Type::Any, scope);
+                }
+            }
+            Statement::ArrayDestructuringAssignment(assignment) => {
+                self.expression(&assignment.value, scope);
+                for target in &assignment.targets {
+                    self.assign(target, Type::Any, scope);
+                }
+            }
+            Statement::ExportDefault(export) => {
+                self.expression(&export.value, scope);
+            }
+            Statement::ExportDeclaration(export) => self.statement(&export.declaration, scope),
+            Statement::Import(import) => match &import.items {
+                Some(items) => {
+                    for item in items {
+                        scope.insert(item.clone(), Known::value(Type::Any));
+                    }
+                }
+                None => {
+                    scope.insert(import.module.clone(), Known::value(Type::Any));
+                }
+            },
+            Statement::ImportDefault(import) => {
+                scope.insert(import.name.clone(), Known::value(Type::Any));
+            }
+            Statement::ImportNamed(import) => {
+                for name in &import.imports {
+                    scope.insert(name.clone(), Known::value(Type::Any));
+                }
+            }
+            Statement::ImportNamespace(import) => {
+                scope.insert(import.alias.clone(), Known::value(Type::Any));
+            }
+            Statement::Break
+            | Statement::Continue
+            | Statement::Pass
+            | Statement::TypeAlias(_)
+            | Statement::ImportSideEffect(_)
+            | Statement::ExportNamed(_)
+            | Statement::ExportAll(_) => {}
+        }
+    }
+
+    /// Check the defaults of `parameters` and walk `body` with them bound
+    fn function<'a>(
+        &mut self,
+        name: &str,
+        parameters: &'a [Parameter],
+        body: &'a [Statement],
+        return_type: Option<&Type>,
+        scope: &Scope<'a>,
+    ) {
+        let mut inner = scope.clone();
+        for parameter in parameters {
+            let default = parameter
+                .default_value
+                .as_ref()
+                .map(|default| self.expression(default, &mut scope.clone()));
+            if let (Some(declared), Some(default)) = (&parameter.param_type, &default) {
+                if !accepts(declared, default) {
+                    self.errors.push(format!(
+                        "parameter '{}' of {name}() defaults to {} but is declared as {}",
+                        parameter.name,
+                        type_name(default),
+                        type_name(declared)
+                    ));
+                }
+            }
+
+            let declared = parameter.param_type.clone().unwrap_or(Type::Any);
+            let value_type = match parameter.kind {
+                ParameterKind::Positional => declared,
+                ParameterKind::Rest => Type::List(Box::new(declared)),
+                ParameterKind::Keywords => Type::Dict(Box::new(Type::Str), Box::new(declared)),
+            };
+            inner.insert(
+                parameter.name.clone(),
+                Known::Typed {
+                    value_type,
+                    declared: parameter.param_type.is_some(),
+                },
+            );
+        }
+
+        self.returns.push((name.to_string(), return_type.cloned()));
+        self.block(body, &mut inner);
+        self.returns.pop();
+    }
+
+    /// Give `name` the type of the value assigned to it, or check the value against the type
+    /// `name` was declared with
+    fn assign(&mut self, name: &str, value_type: Type, scope: &mut Scope) {
+        if let Some(Known::Typed {
+            value_type: declared,
+            declared: true,
+        }) = scope.get(name)
+        {
+            let declared = declared.clone();
+            self.assignment(name, &declared, &value_type);
+            return;
+        }
+        scope.insert(name.to_string(), Known::value(value_type));
+    }
+
+    fn assignment(&mut self, name: &str, declared: &Type, value_type: &Type) {
+        if !accepts(declared, value_type) {
+            self.errors.push(format!(
+                "cannot assign {} to '{name}', which is declared as {}",
+                type_name(value_type),
+                type_name(declared)
+            ));
+        }
+    }
+
+    /// Bind the names `pattern` captures, walking its guards
+    fn pattern<'a>(&mut self, pattern: &'a Pattern, scope: &mut Scope<'a>) {
+        match pattern {
+            Pattern::Identifier(name) => {
+                scope.insert(name.clone(), Known::value(Type::Any));
+            }
+            Pattern::Tuple(patterns) | Pattern::List(patterns) => {
+                for pattern in patterns {
+                    self.pattern(pattern, scope);
+                }
+            }
+            Pattern::Dict(pairs) => {
+                for (_, value) in pairs {
+                    self.pattern(value, scope);
+                }
+            }
+            Pattern::Constructor(_, patterns) => {
+                for pattern in patterns {
+                    self.pattern(pattern, scope);
+                }
+            }
+            Pattern::Guard(pattern, guard) => {
+                self.pattern(pattern, scope);
+                self.expression(guard, scope);
+            }
+            Pattern::Range(start, end) => {
+                self.expression(start, scope);
+                self.expression(end, scope);
+            }
+            Pattern::Literal(_) | Pattern::Wildcard => {}
+        }
+    }
+
+    /// Walk `expr`, returning its type as far as it is known
+    fn expression<'a>(&mut self, expr: &'a Expression, scope: &mut Scope<'a>) -> Type {
+        match expr {
+            Expression::Literal(literal) => literal_type(literal),
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(Known::Typed { value_type, .. }) => value_type.clone(),
+                Some(Known::Function(_)) => Type::Function(Vec::new(), Box::new(Type::Any)),
+                _ => Type::Any,
+            },
+            Expression::Binary(binary) => {
+                let left = self.expression(&binary.left, scope);
+                let right = self.expression(&binary.right, scope);
+                binary_type(&binary.operator, &left, &right)
+            }
+            Expression::Call(call) => self.call(call, scope),
+            Expression::Await(inner) | Expression::Async(inner) | Expression::Spread(inner) => {
+                self.expression(inner, scope);
+                Type::Any
+            }
+            Expression::List(items) => {
+                let types = self.all(items, scope);
+                Type::List(Box::new(unify(&types)))
+            }
+            Expression::Set(items) => {
+                let types = self.all(items, scope);
+                Type::Set(Box::new(unify(&types)))
+            }
+            Expression::Tuple(items) => Type::Tuple(self.all(items, scope)),
+            Expression::Dict(pairs) | Expression::Dictionary(pairs) => {
+                let mut keys = Vec::new();
+                let mut values = Vec::new();
+                for (key, value) in pairs {
+                    keys.push(self.expression(key, scope));
+                    values.push(self.expression(value, scope));
+                }
+                Type::Dict(Box::new(unify(&keys)), Box::new(unify(&values)))
+            }
+            Expression::Lambda(lambda) => {
+                let mut inner = scope.clone();
+                for parameter in &lambda.parameters {
+                    inner.insert(parameter.clone(), Known::value(Type::Any));
+                }
+                self.returns.push(("lambda".to_string(), None));
+                let result = self.expression(&lambda.body, &mut inner);
+                self.returns.pop();
+                Type::Function(vec![Type::Any; lambda.parameters.len()], Box::new(result))
+            }
+            Expression::ListComprehension(comp) => {
+                let mut inner = self.generators(&comp.generators, scope);
+                Type::List(Box::new(self.expression(&comp.element, &mut inner)))
+            }
+            Expression::SetComprehension(comp) => {
+                let mut inner = self.generators(&comp.generators, scope);
+                Type::Set(Box::new(self.expression(&comp.element, &mut inner)))
+            }
+            Expression::DictComprehension(comp) => {
+                let mut inner = self.generators(&comp.generators, scope);
+                let key = self.expression(&comp.key, &mut inner);
+                let value = self.expression(&comp.value, &mut inner);
+                Type::Dict(Box::new(key), Box::new(value))
+            }
+            Expression::Generator(gen) => {
+                let mut inner = self.generators(&gen.generators, scope);
+                self.expression(&gen.element, &mut inner);
+                Type::Any
+            }
+            Expression::Ternary(ternary) => {
+                self.expression(&ternary.condition, scope);
+                let when_true = self.expression(&ternary.true_expr, scope);
+                let when_false = self.expression(&ternary.false_expr, scope);
+                unify(&[when_true, when_false])
+            }
+            Expression::Attribute(attr) => {
+                self.expression(&attr.object, scope);
+                Type::Any
+            }
+            Expression::Index(IndexAccess { object, index })
+            | Expression::Subscript(SubscriptExpression { object, index }) => {
+                let object = self.expression(object, scope);
+                let index = self.expression(index, scope);
+                match (object, index) {
+                    (Type::List(element), Type::Int) => *element,
+                    (Type::Dict(_, value), _) => *value,
+                    (Type::Str, Type::Int) => Type::Str,
+                    _ => Type::Any,
+                }
+            }
+            Expression::Slice(slice) => {
+                let object = self.expression(&slice.object, scope);
+                for bound in [&slice.start, &slice.end, &slice.step]
+                    .into_iter()
+                    .flatten()
+                {
+                    self.expression(bound, scope);
+                }
+                match object {
+                    Type::List(_) | Type::Str => object,
+                    _ => Type::Any,
+                }
+            }
+            Expression::Unary(unary) => {
+                let operand = self.expression(&unary.operand, scope);
+                match (&unary.operator, operand) {
+                    (UnaryOperator::Not, _) => Type::Bool,
+                    (UnaryOperator::Plus | UnaryOperator::Minus, Type::Int) => Type::Int,
+                    (UnaryOperator::Plus | UnaryOperator::Minus, Type::Float) => Type::Float,
+                    (UnaryOperator::BitwiseNot, Type::Int) => Type::Int,
+                    _ => Type::Any,
+                }
+            }
+            Expression::NamedExpr(named) => {
+                let value_type = self.expression(&named.value, scope);
+                self.assign(&named.target, value_type.clone(), scope);
+                value_type
+            }
+            Expression::FunctionExpr(function) => {
+                self.function(
+                    "function",
+                    &function.parameters,
+                    &function.body,
+                    None,
+                    scope,
+                );
+                Type::Function(Vec::new(), Box::new(Type::Any))
+            }
+            Expression::TemplateLiteral(template) => {
+                self.all(&template.expressions, scope);
+                Type::Str
+            }
+            Expression::FString(fstring) => {
+                for part in &fstring.parts {
+                    match part {
+                        FStringPart::Expression(expression)
+                        | FStringPart::FormattedExpression { expression, .. } => {
+                            self.expression(expression, scope);
+                        }
+                        FStringPart::Text(_) => {}
+                    }
+                }
+                Type::Str
+            }
+            Expression::JSXElement(element) => {
+                self.jsx(element, scope);
+                Type::Any
+            }
+            Expression::InlineJs(_) => Type::Any,
+        }
+    }
+
+    fn all<'a>(&mut self, exprs: &'a [Expression], scope: &mut Scope<'a>) -> Vec<Type> {
+        exprs
+            .iter()
+            .map(|expr| self.expression(expr, scope))
+            .collect()
+    }
+
+    fn jsx<'a>(&mut self, element: &'a JSXElement, scope: &mut Scope<'a>) {
+        for attribute in &element.attributes {
+            if let Some(value) = &attribute.value {
+                self.expression(value, scope);
+            }
+        }
+        for child in &element.children {
+            match child {
+                JSXChild::Element(child) => self.jsx(child, scope),
+                JSXChild::Expression(expression) => {
+                    self.expression(expression, scope);
+                }
+                JSXChild::Text(_) => {}
+            }
+        }
+    }
+
+    /// The scope inside a comprehension, with each target bound to the elements of its iterable
+    fn generators<'a>(
+        &mut self,
+        generators: &'a [ComprehensionGenerator],
+        scope: &Scope<'a>,
+    ) -> Scope<'a> {
+        let mut inner = scope.clone();
+        for generator in generators {
+            let iterable = self.expression(&generator.iter, &mut inner);
+            inner.insert(
+                generator.target.clone(),
+                Known::value(element_type(&iterable)),
+            );
+            for condition in &generator.conditions {
+                self.expression(condition, &mut inner);
+            }
+        }
+        inner
+    }
+
+    /// Check a call against the function or class it calls, returning its result type
+    fn call<'a>(&mut self, call: &'a CallExpression, scope: &mut Scope<'a>) -> Type {
+        let arguments = self.all(&call.arguments, scope);
+        let keywords: Vec<(&str, Type)> = call
+            .keyword_args
+            .iter()
+            .map(|(name, value)| (name.as_str(), self.expression(value, scope)))
+            .collect();
+
+        let Expression::Identifier(name) = call.function.as_ref() else {
+            self.expression(&call.function, scope);
+            return Type::Any;
+        };
+        match scope.get(name) {
+            Some(Known::Function(function)) => {
+                let function: &FunctionDef = function;
+                self.arguments(name, &function.parameters, call, &arguments, &keywords);
+                match (function.is_async, function.is_generator) {
+                    (false, false) => function.return_type.clone().unwrap_or(Type::Any),
+                    _ => Type::Any,
+                }
+            }
+            Some(Known::Class(init)) => {
+                if let Some(init) = init {
+                    let parameters = init.parameters.get(1..).unwrap_or(&[]);
+                    self.arguments(name, parameters, call, &arguments, &keywords);
+                }
+                Type::Any
+            }
+            Some(Known::Typed { .. }) => Type::Any,
+            None => builtin_result(name),
+        }
+    }
+
+    /// Check the arguments of a call to `name` against the annotations of its `parameters`
+    fn arguments(
+        &mut self,
+        name: &str,
+        parameters: &[Parameter],
+        call: &CallExpression,
+        arguments: &[Type],
+        keywords: &[(&str, Type)],
+    ) {
+        let positional: Vec<&Parameter> = parameters
+            .iter()
+            .filter(|parameter| parameter.kind == ParameterKind::Positional)
+            .collect();
+        let of_kind = |kind| parameters.iter().find(|parameter| parameter.kind == kind);
+        let rest = of_kind(ParameterKind::Rest);
+        let options = of_kind(ParameterKind::Keywords);
+
+        // After a `*list` argument, positions are no longer known
+        let known = call
+            .arguments
+            .iter()
+            .take_while(|argument| !matches!(argument, Expression::Spread(_)))
+            .count();
+        for (index, argument) in arguments.iter().take(known).enumerate() {
+            match positional.get(index).copied().or(rest) {
+                Some(parameter) => self.argument(name, parameter, argument),
+                None => break,
+            }
+        }
+        for (keyword, argument) in keywords {
+            let parameter = positional
+                .iter()
+                .copied()
+                .find(|parameter| parameter.name == *keyword)
+                .or(options);
+            if let Some(parameter) = parameter {
+                self.argument(name, parameter, argument);
+            }
+        }
+    }
+
+    fn argument(&mut self, function: &str, parameter: &Parameter, argument: &Type) {
+        let Some(declared) = &parameter.param_type else {
+            return;
+        };
+        if !accepts(declared, argument) {
+            self.errors.push(format!(
+                "argument '{}' of {function}() expects {}, got {}",
+                parameter.name,
+                type_name(declared),
+                type_name(argument)
+            ));
+        }
+    }
+}
+
+/// Bind the functions and classes `statement` defines; decorated ones may be anything
+fn hoist<'a>(statement: &'a Statement, scope: &mut Scope<'a>) {
+    match statement {
+        Statement::FunctionDef(function) if function.decorators.is_empty() => {
+            scope.insert(function.name.clone(), Known::Function(function));
+        }
+        Statement::ClassDef(class) if class.decorators.is_empty() => {
+            let init = class.body.iter().find_map(|statement| match statement {
+                Statement::FunctionDef(method) if method.name == "__init__" => Some(method),
+                _ => None,
+            });
+            scope.insert(class.name.clone(), Known::Class(init));
+        }
+        Statement::FunctionDef(FunctionDef { name, .. })
+        | Statement::ClassDef(ClassDef { name, .. }) => {
+            scope.insert(name.clone(), Known::value(Type::Any));
+        }
+        Statement::ExportDeclaration(export) => hoist(&export.declaration, scope),
+        _ => {}
+    }
+}
+
+/// Keep the type of a name after a statement only where every way through it, in `branches`,
+/// left that type
+fn merge<'a>(scope: &mut Scope<'a>, branches: &[Scope<'a>]) {
+    for (name, known) in scope.iter_mut() {
+        if let Known::Typed { declared: true, .. } = known {
+            continue;
+        }
+        let unchanged = branches
+            .iter()
+            .all(|branch| branch.get(name).is_some_and(|other| other.same(known)));
+        if !unchanged {
+            *known = Known::value(Type::Any);
+        }
+    }
+}
+
+/// Forget the types of the names `body` assigns, as a loop may run it again with other values
+fn widen(scope: &mut Scope, body: &[Statement]) {
+    let mut names = Vec::new();
+    assigned_names(body, &mut names);
+    for name in names {
+        if let Some(known) = scope.get_mut(name) {
+            if !matches!(known, Known::Typed { declared: true, .. }) {
+                *known = Known::value(Type::Any);
+            }
+        }
+    }
+}
+
+/// Names `statements` assign, outside of nested functions and classes
+fn assigned_names<'a>(statements: &'a [Statement], names: &mut Vec<&'a str>) {
+    for statement in statements {
+        match statement {
+            Statement::Assignment(assignment) => names.push(&assignment.name),
+            Statement::TupleAssignment(TupleAssignment { targets, .. })
+            | Statement::ArrayDestructuringAssignment(ArrayDestructuringAssignment {
+                targets,
+                ..
+            }) => names.extend(targets.iter().map(String::as_str)),
+            Statement::DestructuringAssignment(assignment) => {
+                target_names(&assignment.target, names)
+            }
+            Statement::FunctionDef(FunctionDef { name, .. })
+            | Statement::ClassDef(ClassDef { name, .. }) => names.push(name),
+            Statement::If(if_stmt) => {
+                assigned_names(&if_stmt.then_branch, names);
+                for elif in &if_stmt.elif_branches {
+                    assigned_names(&elif.body, names);
+                }
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    assigned_names(else_branch, names);
+                }
+            }
+            Statement::While(WhileLoop { body, .. })
+            | Statement::With(WithStatement { body, .. }) => assigned_names(body, names),
+            Statement::For(for_loop) => {
+                names.push(&for_loop.variable);
+                assigned_names(&for_loop.body, names);
+            }
+            Statement::Match(match_stmt) => {
+                for case in &match_stmt.cases {
+                    assigned_names(&case.body, names);
+                }
+            }
+            Statement::Try(try_stmt) => {
+                assigned_names(&try_stmt.body, names);
+                for handler in &try_stmt.except_handlers {
+                    assigned_names(&handler.body, names);
+                }
+                for block in [&try_stmt.else_clause, &try_stmt.finally_clause]
+                    .into_iter()
+                    .flatten()
+                {
+                    assigned_names(block, names);
+                }
+            }
+            Statement::ExportDeclaration(export) => {
+                assigned_names(std::slice::from_ref(export.declaration.as_ref()), names)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Names bound by a destructuring target such as `{a, b: [c, ...rest]}`
+fn target_names<'a>(target: &'a Expression, names: &mut Vec<&'a str>) {
+    match target {
+        Expression::Identifier(name) => names.push(name),
+        Expression::List(items) | Expression::Tuple(items) => {
+            for item in items {
+                target_names(item, names);
+            }
+        }
+        Expression::Dict(pairs) | Expression::Dictionary(pairs) => {
+            for (_, value) in pairs {
+                target_names(value, names);
+            }
+        }
+        Expression::Spread(inner) => target_names(inner, names),
+        _ => {}
+    }
+}
+
+fn literal_type(literal: &Literal) -> Type {
+    match literal {
+        Literal::Int(_) => Type::Int,
+        Literal::Float(_) => Type::Float,
+        Literal::String(_) => Type::Str,
+        Literal::Bool(_) => Type::Bool,
+        Literal::None => Type::None,
+    }
+}
+
+/// The common type of `types`: their type if they all have the same, `float` for a mix of
+/// numbers and `any` otherwise
+fn unify(types: &[Type]) -> Type {
+    match types.split_first() {
+        Some((first, rest)) if rest.iter().all(|t| t == first) => first.clone(),
+        Some(_) if types.iter().all(|t| matches!(t, Type::Int | Type::Float)) => Type::Float,
+        _ => Type::Any,
+    }
+}
+
+/// Type of the elements a `for` loop over a value of type `iterable` takes
+fn element_type(iterable: &Type) -> Type {
+    match iterable {
+        Type::List(element) | Type::Set(element) | Type::Dict(element, _) => {
+            element.as_ref().clone()
+        }
+        Type::Str => Type::Str,
+        _ => Type::Any,
+    }
+}
+
+/// Result type of `left operator right`, where it follows from the operand types
+fn binary_type(operator: &BinaryOperator, left: &Type, right: &Type) -> Type {
+    use BinaryOperator::*;
+    let numeric = |t: &Type| matches!(t, Type::Int | Type::Float);
+    match (operator, left, right) {
+        (Equal | NotEqual | Less | Greater | LessEqual | GreaterEqual, _, _) => Type::Bool,
+        (Add, Type::Str, Type::Str) => Type::Str,
+        (Multiply, Type::Str, Type::Int) | (Multiply, Type::Int, Type::Str) => Type::Str,
+        (Add, Type::List(a), Type::List(b)) if a == b => left.clone(),
+        (Add | Subtract | Multiply | Modulo | FloorDivide, Type::Int, Type::Int) => Type::Int,
+        (BitwiseAnd | BitwiseOr | BitwiseXor | LeftShift | RightShift, Type::Int, Type::Int) => {
+            Type::Int
+        }
+        (Add | Subtract | Multiply | Modulo | Divide | FloorDivide, left, right)
+            if numeric(left) && numeric(right) =>
+        {
+            Type::Float
+        }
+        _ => Type::Any,
+    }
+}
+
+/// Result type of calling the builtin `name`, when no definition in the module hides it
+fn builtin_result(name: &str) -> Type {
+    match name {
+        "str" | "repr" | "input" => Type::Str,
+        "int" | "len" | "ord" => Type::Int,
+        "float" => Type::Float,
+        "bool" | "isinstance" => Type::Bool,
+        _ => Type::Any,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_program;
+
+    /// Messages of the type errors in `source`
+    fn errors(source: &str) -> Vec<String> {
+        check(&test_program(source), false)
+            .expect("only strict checks fail")
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_defaults_and_returns_are_checked_against_annotations() {
+        let source = r#"
+def greet(name: str = 3) -> str:
+    return name
+
+def count() -> int:
+    return "many"
+
+def nothing() -> int:
+    return
+"#;
+        assert_eq!(
+            errors(source),
+            [
+                "parameter 'name' of greet() defaults to int but is declared as str",
+                "count() returns str but is declared to return int",
+                "nothing() returns None but is declared to return int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_class_calls_are_checked_against_init() {
+        let source = r#"
+class Point {
+    def __init__(self, x: int, y: int):
+        self.x = x
+        self.y = y
+}
+
+Point(1, 2)
+Point(1, "2")
+Point(y="3", x=1)
+"#;
+        assert_eq!(
+            errors(source),
+            [
+                "argument 'y' of Point() expects int, got str",
+                "argument 'y' of Point() expects int, got str",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_locals_take_the_type_of_their_values() {
+        let source = r#"
+def half(n: float) -> float:
+    return n / 2
+
+words = "a b"
+half(len(words))
+half(words)
+half(str(1) + "2")
+label = "x" * 3
+half(label)
+half([1, 2][0])
+"#;
+        assert_eq!(
+            errors(source),
+            [
+                "argument 'n' of half() expects float, got str",
+                "argument 'n' of half() expects float, got str",
+                "argument 'n' of half() expects float, got str",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_declared_parameters_keep_their_type() {
+        let source = "def repeat(count: int):\n    count = \"two\"\n    count = 3\n";
+        assert_eq!(
+            errors(source),
+            ["cannot assign str to 'count', which is declared as int"]
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_fails_with_every_error() {
+        let program = test_program("def f(x: int) -> int:\n    return x\n\nf(\"a\")\nf(1.5)\n");
+        match check(&program, true) {
+            Err(NagariError::TypeError(message)) => assert_eq!(
+                message,
+                "argument 'x' of f() expects int, got str\n  argument 'x' of f() expects int, got float"
+            ),
+            other => panic!("expected a type error, got {other:?}"),
+        }
+        let warnings = check(&program, false).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings.iter().all(|warning| warning.category == CATEGORY));
+    }
+
+    #[test]
+    fn test_type_rules() {
+        assert_eq!(unify(&[Type::Int, Type::Int]), Type::Int);
+        assert_eq!(unify(&[Type::Int, Type::Float]), Type::Float);
+        assert_eq!(unify(&[Type::Int, Type::Str]), Type::Any);
+        assert_eq!(unify(&[]), Type::Any);
+
+        let list_of_ints = Type::List(Box::new(Type::Int));
+        assert_eq!(element_type(&list_of_ints), Type::Int);
+        assert_eq!(element_type(&Type::Str), Type::Str);
+        assert_eq!(element_type(&Type::Int), Type::Any);
+
+        use BinaryOperator::*;
+        assert_eq!(binary_type(&Add, &Type::Int, &Type::Int), Type::Int);
+        assert_eq!(binary_type(&Divide, &Type::Int, &Type::Int), Type::Float);
+        assert_eq!(binary_type(&Add, &Type::Int, &Type::Float), Type::Float);
+        assert_eq!(binary_type(&Add, &Type::Str, &Type::Str), Type::Str);
+        assert_eq!(binary_type(&Multiply, &Type::Int, &Type::Str), Type::Str);
+        assert_eq!(binary_type(&Less, &Type::Str, &Type::Int), Type::Bool);
+        assert_eq!(binary_type(&Add, &Type::Str, &Type::Int), Type::Any);
+        assert_eq!(
+            binary_type(&Add, &list_of_ints, &list_of_ints),
+            list_of_ints
+        );
+    }
+}
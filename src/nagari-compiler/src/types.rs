@@ -1,4 +1,5 @@
 use crate::ast::{BinaryOperator, Expression, Literal, UnaryOperator};
+use nagari_parser::TypeAnnotation;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
@@ -169,6 +170,7 @@ impl Type {
             "list" | "List" => Some(Type::List(Box::new(Type::Any))), // Generic list
             "dict" | "Dict" => Some(Type::Dict(Box::new(Type::Any), Box::new(Type::Any))), // Generic dict
             "array" | "Array" => Some(Type::List(Box::new(Type::Any))), // Alias for list
+            "set" | "Set" | "frozenset" => Some(Type::Set(Box::new(Type::Any))), // Generic set
             "object" | "Object" => Some(Type::Dict(Box::new(Type::Str), Box::new(Type::Any))), // Generic object
             "callable" | "Callable" => Some(Type::Function(vec![], Box::new(Type::Any))), // Generic callable
             "js_error" => Some(Type::Any), // JavaScript error type - treat as Any for compatibility
@@ -184,6 +186,47 @@ impl Type {
         }
     }
 
+    /// The type an annotation stands for; classes and other names without a meaning of their
+    /// own are `Any`
+    pub fn from_annotation(annotation: &TypeAnnotation) -> Self {
+        let all = |types: &[TypeAnnotation]| types.iter().map(Type::from_annotation).collect();
+        match annotation {
+            TypeAnnotation::Name(name) => Type::named(name, Vec::new()),
+            TypeAnnotation::Generic { name, arguments } => Type::named(name, all(arguments)),
+            TypeAnnotation::Union(types) => Type::Union(UnionType { types: all(types) }),
+            TypeAnnotation::Callable {
+                parameters,
+                return_type,
+            } => Type::Function(
+                parameters.as_deref().map_or_else(Vec::new, all),
+                Box::new(Type::from_annotation(return_type)),
+            ),
+        }
+    }
+
+    /// `name[arguments]`, spelled as in annotations and `.nagd` declarations: `list[str]`,
+    /// `Optional[int]`, ... and the JavaScript names `string`, `number` and `boolean`
+    pub fn named(name: &str, arguments: Vec<Type>) -> Self {
+        let mut arguments = arguments.into_iter();
+        let mut argument = || Box::new(arguments.next().unwrap_or(Type::Any));
+        match name {
+            "list" | "List" | "array" | "Array" => Type::List(argument()),
+            "dict" | "Dict" => {
+                let key = argument();
+                Type::Dict(key, argument())
+            }
+            "set" | "Set" | "frozenset" => Type::Set(argument()),
+            "Optional" => Type::Union(UnionType {
+                types: vec![*argument(), Type::None],
+            }),
+            "None" | "null" | "void" => Type::None,
+            "string" => Type::Str,
+            "number" => Type::Float,
+            "boolean" => Type::Bool,
+            other => Type::from_string(other).unwrap_or(Type::Any),
+        }
+    }
+
     pub fn is_compatible(&self, other: &Type) -> bool {
         match (self, other) {
             (Type::Any, _) | (_, Type::Any) => true,
@@ -553,6 +596,9 @@ impl TypeInferenceEngine {
         match expr {
             Expression::Literal(literal) => Ok(self.infer_literal_type(literal)),
 
+            // Raw JavaScript is unchecked; wrap it in a typed function to give it a signature
+            Expression::InlineJs(_) => Ok(Type::Any),
+
             Expression::Identifier(name) => self
                 .type_variables
                 .get(name)
@@ -712,6 +758,24 @@ impl TypeInferenceEngine {
                 }
             }
 
+            BinaryOperator::Power | BinaryOperator::FloorDivide => match (left, right) {
+                (Type::Int, Type::Int) => Ok(Type::Int),
+                (Type::Float, Type::Float) => Ok(Type::Float),
+                (Type::Int, Type::Float) | (Type::Float, Type::Int) => Ok(Type::Float),
+                _ => Err(format!("Cannot perform arithmetic on {left} and {right}")),
+            },
+
+            BinaryOperator::BitwiseAnd
+            | BinaryOperator::BitwiseOr
+            | BinaryOperator::BitwiseXor
+            | BinaryOperator::LeftShift
+            | BinaryOperator::RightShift => match (left, right) {
+                (Type::Int, Type::Int) => Ok(Type::Int),
+                _ => Err(format!(
+                    "Bitwise operations can only be applied to integers, not {left} and {right}"
+                )),
+            },
+
             BinaryOperator::Equal | BinaryOperator::NotEqual => Ok(Type::Bool),
 
             BinaryOperator::Less
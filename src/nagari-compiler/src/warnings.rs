@@ -0,0 +1,164 @@
+//! Warning categories and the allow/warn/deny levels applied to them.
+//!
+//! Categories share their names with lint rules (`unused-variables`, `line-length`, ...), so a
+//! single `# nagari:ignore <category>` comment or level setting covers the compiler, `nag lint`
+//! and the language server alike. The `warnings` group sets the level of every category that
+//! has no level of its own.
+
+use crate::error::{Diagnostic, NagariError};
+use nagari_parser::{Directives, Span};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Pseudo-category naming every warning
+pub const WARNINGS_GROUP: &str = "warnings";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningLevel {
+    /// Drop the warning
+    Allow,
+    /// Report the warning
+    Warn,
+    /// Report the warning as an error and fail
+    Deny,
+}
+
+impl WarningLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WarningLevel::Allow => "allow",
+            WarningLevel::Warn => "warn",
+            WarningLevel::Deny => "deny",
+        }
+    }
+}
+
+impl FromStr for WarningLevel {
+    type Err = String;
+
+    fn from_str(level: &str) -> Result<Self, Self::Err> {
+        match level.to_ascii_lowercase().as_str() {
+            "allow" => Ok(WarningLevel::Allow),
+            "warn" => Ok(WarningLevel::Warn),
+            "deny" => Ok(WarningLevel::Deny),
+            other => Err(format!(
+                "Unknown warning level '{other}' (expected allow, warn or deny)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for WarningLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A warning reported during compilation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Warning {
+    pub category: String,
+    pub message: String,
+    /// 1-based line the warning refers to
+    pub line: Option<usize>,
+    /// 1-based column on `line` where the code the warning refers to starts
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(
+                f,
+                "line {line}, column {column}: {} [{}]",
+                self.message, self.category
+            ),
+            (Some(line), None) => write!(f, "line {line}: {} [{}]", self.message, self.category),
+            _ => write!(f, "{} [{}]", self.message, self.category),
+        }
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        let diagnostic = if warning.category == crate::diagnostics::INTERNAL_ERROR {
+            Diagnostic::error(&warning.category, &warning.message).with_help(
+                "this is a bug in the Nagari compiler; please report it with the code that \
+                 triggers it at https://github.com/ayanalamMOON/Nagari/issues",
+            )
+        } else {
+            Diagnostic::warning(&warning.category, &warning.message)
+        };
+        match warning.line {
+            Some(line) => diagnostic.with_span(Span {
+                line,
+                column: warning.column.unwrap_or(1),
+            }),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Level for `category`: its own entry, else the `warnings` group entry, else `deny` when
+/// `deny_warnings` is set and `warn` otherwise
+pub fn level_for(
+    levels: &HashMap<String, WarningLevel>,
+    deny_warnings: bool,
+    category: &str,
+) -> WarningLevel {
+    levels
+        .get(category)
+        .or_else(|| levels.get(WARNINGS_GROUP))
+        .copied()
+        .unwrap_or(if deny_warnings {
+            WarningLevel::Deny
+        } else {
+            WarningLevel::Warn
+        })
+}
+
+/// Drop allowed and suppressed warnings from `warnings`, failing if any denied one remains
+pub fn enforce(
+    levels: &HashMap<String, WarningLevel>,
+    deny_warnings: bool,
+    source: &str,
+    warnings: Vec<Warning>,
+) -> Result<Vec<Warning>, NagariError> {
+    if warnings.is_empty() {
+        return Ok(warnings);
+    }
+
+    let directives = Directives::from_source(source);
+    let mut reported = Vec::new();
+    let mut denied = Vec::new();
+    for warning in warnings {
+        if warning.category == crate::diagnostics::INTERNAL_ERROR {
+            reported.push(warning);
+            continue;
+        }
+        let suppressed = match warning.line {
+            Some(line) => directives.is_suppressed(&warning.category, line),
+            None => directives.is_suppressed(&warning.category, 0),
+        };
+        if suppressed {
+            continue;
+        }
+        match level_for(levels, deny_warnings, &warning.category) {
+            WarningLevel::Allow => {}
+            WarningLevel::Warn => reported.push(warning),
+            WarningLevel::Deny => denied.push(warning.to_string()),
+        }
+    }
+
+    if denied.is_empty() {
+        Ok(reported)
+    } else {
+        Err(NagariError::SemanticError(format!(
+            "denied warnings:\n  {}",
+            denied.join("\n  ")
+        )))
+    }
+}
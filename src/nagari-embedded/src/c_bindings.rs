@@ -450,7 +450,10 @@ fn create_null_value() -> CNagariValue {
 #[cfg(feature = "c-bindings")]
 fn embedded_value_to_c(value: EmbeddedValue) -> CNagariValue {
     match value {
-        EmbeddedValue::None => create_null_value(),
+        // Resources and handles only mean something to Rust hosts
+        EmbeddedValue::None | EmbeddedValue::Resource(_) | EmbeddedValue::Handle(..) => {
+            create_null_value()
+        }
         EmbeddedValue::Bool(b) => CNagariValue {
             value_type: CNagariValueType::Bool,
             data: CNagariValueData { bool_val: if b { 1 } else { 0 } },
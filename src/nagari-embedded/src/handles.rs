@@ -0,0 +1,94 @@
+//! Opaque handles to objects the host keeps for scripts.
+//!
+//! A host function stores an object in the runtime's [`Handles`] and hands scripts the
+//! `EmbeddedValue::Handle` it gets back. Scripts can keep and pass the handle around but can't
+//! look inside it; when it comes back to a host function, the host looks the object up again by
+//! handle and type. Resetting the runtime drops every object, and handles that outlive it no
+//! longer refer to anything, as ids are never reused.
+
+use crate::EmbeddedValue;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+type Entry = (String, Box<dyn Any + Send>);
+
+/// The objects behind a runtime's handles. Clones share the same objects, so host functions
+/// can keep one.
+#[derive(Clone, Default)]
+pub struct Handles(Arc<Mutex<HashMap<u64, Entry>>>);
+
+impl Handles {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, Entry>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Keep `object`, returning a handle with the type tag `tag` for scripts to refer to it by
+    pub fn insert<T: Any + Send>(&self, tag: &str, object: T) -> EmbeddedValue {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        self.lock().insert(id, (tag.to_string(), Box::new(object)));
+        EmbeddedValue::Handle(id, tag.to_string())
+    }
+
+    /// Call `f` with the object behind `handle`, which has to be a `T`. The handles are locked
+    /// meanwhile, so `f` must not use them.
+    pub fn with<T: Any, R>(
+        &self,
+        handle: &EmbeddedValue,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Result<R, String> {
+        let (id, tag) = handle_parts(handle)?;
+        let mut objects = self.lock();
+        let object = objects
+            .get_mut(&id)
+            .filter(|(kept, _)| kept == tag)
+            .ok_or_else(|| format!("invalid {tag} handle"))?;
+        let object = object.1.downcast_mut::<T>().ok_or_else(|| {
+            format!(
+                "{tag} handle does not refer to {}",
+                std::any::type_name::<T>()
+            )
+        })?;
+        Ok(f(object))
+    }
+
+    /// Take the object behind `handle` back, after which the handle no longer refers to
+    /// anything
+    pub fn remove<T: Any>(&self, handle: &EmbeddedValue) -> Result<T, String> {
+        self.with(handle, |_: &mut T| ())?;
+        let (id, _) = handle_parts(handle)?;
+        let (_, object) = self.lock().remove(&id).expect("checked above");
+        Ok(*object.downcast::<T>().expect("checked above"))
+    }
+
+    /// Whether `handle` still refers to an object
+    pub fn contains(&self, handle: &EmbeddedValue) -> bool {
+        handle_parts(handle)
+            .is_ok_and(|(id, tag)| self.lock().get(&id).is_some_and(|(kept, _)| kept == tag))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Drop every object, invalidating all handles
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+}
+
+fn handle_parts(handle: &EmbeddedValue) -> Result<(u64, &str), String> {
+    match handle {
+        EmbeddedValue::Handle(id, tag) => Ok((*id, tag)),
+        _ => Err("expected a handle".to_string()),
+    }
+}
@@ -1,12 +1,28 @@
 use async_trait::async_trait;
-use nagari_vm::{Value as NagariValue, VM as NagariVM};
+use nagari_vm::modules::{parse_statement, parse_statements, Import, ModuleScope, ModuleStatement};
+use nagari_vm::yielding::DEFAULT_YIELD_INTERVAL;
+use nagari_vm::{
+    ExecutionLimits, HostCallback, Module, ModuleRegistry, PermissionPrompt, Value as NagariValue,
+    YieldHook, VM as NagariVM,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 #[cfg(feature = "async")]
 use tokio::sync::RwLock as AsyncRwLock;
 
+pub use handles::Handles;
+pub use nagari_vm::heap::{HeapDiff, HeapNode, HeapSnapshot};
+pub use nagari_vm::schedule::{JobRecord, JobStore, Trigger};
+pub use nagari_vm::{Access, HostSignature, Permission, PermissionDecision, Resource};
+pub use native::{Capability, MethodTable, NativeObject};
+
+pub mod handles;
+pub mod native;
+pub mod prelude;
+
 // Platform-specific bindings
 #[cfg(feature = "python")]
 pub mod python;
@@ -27,19 +43,53 @@ pub use nodejs::*;
 #[cfg(feature = "c-bindings")]
 pub use c_bindings::*;
 
+/// Names visible to an expression besides the VM's globals: a module's namespace while it loads
+type Scope = HashMap<String, NagariValue>;
+
+/// Module hooks called by `EmbeddedRuntime::reload_module`
+const UNLOAD_HOOK: &str = "__on_unload__";
+const RELOAD_HOOK: &str = "__on_reload__";
+
+/// A script's result and the gated operations it performed, from `run_script_with_report`
+#[derive(Debug, Clone)]
+pub struct ScriptReport {
+    /// What `run_script` would have returned
+    pub result: Result<EmbeddedValue, String>,
+    /// The IO and network accesses the script made or was denied, in order
+    pub accesses: Vec<Access>,
+}
+
 // Core embedded runtime
 pub struct EmbeddedRuntime {
     vm: Arc<Mutex<NagariVM>>,
-    modules: HashMap<String, String>,
+    modules: ModuleRegistry,
+    handles: Handles,
     config: RuntimeConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RuntimeConfig {
     pub memory_limit: Option<usize>,
+    /// Milliseconds a script run or function call may take before it fails with a
+    /// `TimeoutError`, timers and host calls it waits for included
     pub execution_timeout: Option<u64>,
+    /// Instructions a script run or function call may execute before it fails with a
+    /// `TimeoutError`
+    #[serde(default)]
+    pub instruction_limit: Option<u64>,
     pub allow_io: bool,
     pub allow_network: bool,
+    /// Asked whether to allow IO and network access that `allow_io` and `allow_network`
+    /// don't, as scripts first need each path or host
+    #[serde(skip)]
+    pub permission_prompt: Option<PermissionPrompt>,
+    /// Instructions between the points where a long run lets the host do other work: the async
+    /// runtime yields to tokio there, and both call `yield_hook`. `None` never yields.
+    #[serde(default = "default_yield_interval")]
+    pub yield_interval: Option<u64>,
+    /// Called at each yield point, such as to pump a UI event loop while a script computes
+    #[serde(skip)]
+    pub yield_hook: Option<YieldHook>,
     pub sandbox_mode: bool,
     pub debug_mode: bool,
 }
@@ -49,52 +99,126 @@ impl Default for RuntimeConfig {
         Self {
             memory_limit: Some(64 * 1024 * 1024), // 64MB default
             execution_timeout: Some(5000),        // 5 seconds
+            instruction_limit: None,
             allow_io: false,
             allow_network: false,
+            permission_prompt: None,
+            yield_interval: default_yield_interval(),
+            yield_hook: None,
             sandbox_mode: true,
             debug_mode: false,
         }
     }
 }
 
+fn default_yield_interval() -> Option<u64> {
+    Some(DEFAULT_YIELD_INTERVAL)
+}
+
+impl RuntimeConfig {
+    fn host_timeout(&self) -> Option<Duration> {
+        self.execution_timeout.map(Duration::from_millis)
+    }
+
+    fn execution_limits(&self) -> ExecutionLimits {
+        ExecutionLimits {
+            timeout: self.host_timeout(),
+            max_instructions: self.instruction_limit,
+        }
+    }
+
+    fn check_host_function(&self, name: &str) -> Result<(), String> {
+        if self.sandbox_mode && name.contains("unsafe") {
+            return Err("Unsafe functions not allowed in sandbox mode".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether scripts may get to use `capability`, outright or by asking the permission prompt
+    fn may_use(&self, capability: Capability) -> bool {
+        self.allows(capability)
+            || (self.permission_prompt.is_some() && capability != Capability::Unsafe)
+    }
+
+    fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Io => self.allow_io,
+            Capability::Network => self.allow_network,
+            Capability::Unsafe => !self.sandbox_mode,
+        }
+    }
+}
+
 impl EmbeddedRuntime {
     pub fn new(config: RuntimeConfig) -> Result<Self, String> {
-        let vm = NagariVM::new(config.debug_mode);
+        let mut vm = NagariVM::new(config.debug_mode);
+        vm.set_host_timeout(config.host_timeout());
+        vm.set_execution_limits(config.execution_limits());
+        vm.set_allow_network(config.allow_network);
+        vm.set_allow_io(config.allow_io);
+        vm.set_permission_prompt(config.permission_prompt.clone());
+        vm.set_yield_interval(config.yield_interval);
+        vm.set_yield_hook(config.yield_hook.clone());
         Ok(Self {
             vm: Arc::new(Mutex::new(vm)),
-            modules: HashMap::new(),
+            modules: ModuleRegistry::new(),
+            handles: Handles::default(),
             config,
         })
     }
-    pub fn run_script(&mut self, script: &str) -> Result<EmbeddedValue, String> {
-        // Apply runtime config constraints
-        if let Some(_timeout) = self.config.execution_timeout {
-            // In a real implementation, this would set up execution timeout
-            if self.config.debug_mode {
-                eprintln!("Executing script with timeout constraint");
-            }
-        }
 
+    /// Compile `script` to bytecode and run it in the VM, returning the value of its trailing
+    /// expression statement, or none if it ends with another kind of statement
+    pub fn run_script(&mut self, script: &str) -> Result<EmbeddedValue, String> {
         // Check permissions based on config
-        if !self.config.allow_io && script.contains("fs") {
+        if !self.config.may_use(Capability::Io) && script.contains("fs") {
             return Err("IO operations not allowed".to_string());
         }
 
-        if !self.config.allow_network && script.contains("http") {
+        if !self.config.may_use(Capability::Network) && script.contains("http") {
             return Err("Network operations not allowed".to_string());
         }
 
-        // Simple script evaluation - similar to WASM implementation
         if self.config.debug_mode {
             eprintln!("Executing script: {}", &script[..script.len().min(50)]);
         }
 
-        // Use the same compilation and execution logic as WASM
-        let result = self.compile_and_run_embedded_source(script)?;
+        let result = self.run_statements(script)?;
 
         Ok(EmbeddedValue::from_nagari(result))
     }
 
+    /// Run `script` like `run_script`, also reporting the IO and network accesses it made or
+    /// was denied, for hosts that keep an audit trail of what scripts do
+    pub fn run_script_with_report(&mut self, script: &str) -> Result<ScriptReport, String> {
+        self.vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?
+            .start_access_report();
+        let result = self.run_script(script);
+        let accesses = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?
+            .finish_access_report();
+        Ok(ScriptReport { result, accesses })
+    }
+
+    /// Call `listener` with every IO and network access scripts make or are denied, as it
+    /// happens. Unlike a report, this covers the timers and handlers of scripts that keep
+    /// running after `run_script` returns. Listeners run while the VM is locked, so they must
+    /// not call back into the runtime.
+    pub fn on_access<F>(&mut self, listener: F) -> Result<(), String>
+    where
+        F: Fn(&Access) + Send + Sync + 'static,
+    {
+        self.vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?
+            .set_access_listener(Some(Arc::new(listener)));
+        Ok(())
+    }
+
     pub fn call_function(
         &mut self,
         name: &str,
@@ -115,15 +239,16 @@ impl EmbeddedRuntime {
     }
 
     pub fn load_module(&mut self, name: &str, code: &str) -> Result<(), String> {
-        if !self.config.allow_io && name.contains("fs") {
+        if !self.config.may_use(Capability::Io) && name.contains("fs") {
             return Err("IO operations not allowed in this runtime".to_string());
         }
 
-        if !self.config.allow_network && name.contains("http") {
+        if !self.config.may_use(Capability::Network) && name.contains("http") {
             return Err("Network operations not allowed in this runtime".to_string());
         }
 
-        self.modules.insert(name.to_string(), code.to_string());
+        let module = self.evaluate_module(name, code)?;
+        self.modules.insert(module);
 
         if self.config.debug_mode {
             eprintln!("Loaded module: {} ({} bytes)", name, code.len());
@@ -132,21 +257,264 @@ impl EmbeddedRuntime {
         Ok(())
     }
 
-    pub fn register_host_function<F>(&mut self, name: &str, _func: F) -> Result<(), String>
+    /// Replace a loaded module without restarting the VM. The new code is evaluated first and
+    /// nothing changes if it fails or no longer exports a name that is imported somewhere.
+    ///
+    /// A host function bound to `__on_unload__` in the old module is called with the old
+    /// namespace, and one bound to `__on_reload__` in the new module with whatever that
+    /// returned; names in a dict returned by `__on_reload__` are set in the new namespace.
+    /// Names that scripts and other modules imported from the module are then rebound.
+    pub fn reload_module(&mut self, name: &str, code: &str) -> Result<(), String> {
+        let previous = self
+            .modules
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("No module named '{}'", name))?;
+        let mut module = self.evaluate_module(name, code)?;
+        self.modules.check_replacement(&module)?;
+
+        let state = match previous.namespace.get(UNLOAD_HOOK) {
+            Some(hook) => {
+                let namespace = NagariValue::Dict(previous.namespace.clone());
+                self.call_hook(name, UNLOAD_HOOK, hook, vec![namespace])?
+            }
+            None => NagariValue::None,
+        };
+        if let Some(hook) = module.namespace.get(RELOAD_HOOK).cloned() {
+            if let NagariValue::Dict(migrated) =
+                self.call_hook(name, RELOAD_HOOK, &hook, vec![state])?
+            {
+                module.namespace.extend(migrated);
+            }
+        }
+
+        let globals = self.modules.replace(module)?;
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        for (global, value) in globals {
+            vm.define_global(&global, value);
+        }
+
+        if self.config.debug_mode {
+            eprintln!("Reloaded module: {} ({} bytes)", name, code.len());
+        }
+
+        Ok(())
+    }
+
+    /// Call a module's lifecycle hook, which has to be a host function
+    fn call_hook(
+        &mut self,
+        module: &str,
+        hook: &str,
+        function: &NagariValue,
+        args: Vec<NagariValue>,
+    ) -> Result<NagariValue, String> {
+        let NagariValue::Builtin(function) = function else {
+            return Err(format!(
+                "{} in module '{}' must be a host function, not {}",
+                hook,
+                module,
+                function.type_name()
+            ));
+        };
+        let result = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?
+            .call_host_function(&function.name, args)?;
+        self.resolve(result)
+    }
+
+    pub fn get_loaded_modules(&self) -> Vec<String> {
+        self.modules.names()
+    }
+
+    pub fn get_module(&self, name: &str) -> Option<&Module> {
+        self.modules.get(name)
+    }
+
+    pub fn register_host_function<F>(&mut self, name: &str, func: F) -> Result<(), String>
     where
         F: Fn(Vec<EmbeddedValue>) -> EmbeddedValue + Send + Sync + 'static,
     {
-        if self.config.sandbox_mode && name.contains("unsafe") {
-            return Err("Unsafe functions not allowed in sandbox mode".to_string());
-        }
+        self.register_callback(name, sync_host_callback(func), None)
+    }
+
+    /// Register a host function whose calls are checked against `signature` before it runs
+    pub fn register_typed_host_function<F>(
+        &mut self,
+        name: &str,
+        signature: HostSignature,
+        func: F,
+    ) -> Result<(), String>
+    where
+        F: Fn(Vec<EmbeddedValue>) -> EmbeddedValue + Send + Sync + 'static,
+    {
+        self.register_callback(name, sync_host_callback(func), Some(signature))
+    }
+
+    /// Register an async host function. Scripts get an awaitable value from calling it, and
+    /// `await` blocks on the host future for at most `execution_timeout`.
+    pub fn register_async_host_function<H>(&mut self, name: &str, func: H) -> Result<(), String>
+    where
+        H: HostFunction + Send + Sync + 'static,
+    {
+        self.register_callback(name, async_host_callback(func), None)
+    }
+
+    pub fn register_typed_async_host_function<H>(
+        &mut self,
+        name: &str,
+        signature: HostSignature,
+        func: H,
+    ) -> Result<(), String>
+    where
+        H: HostFunction + Send + Sync + 'static,
+    {
+        self.register_callback(name, async_host_callback(func), Some(signature))
+    }
+
+    fn register_callback(
+        &mut self,
+        name: &str,
+        callback: HostCallback,
+        signature: Option<HostSignature>,
+    ) -> Result<(), String> {
+        self.config.check_host_function(name)?;
+
+        self.vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?
+            .register_host_function(name, callback, signature);
 
-        // In a real implementation, this would register the function with the VM
         if self.config.debug_mode {
             eprintln!("Registered host function: {}", name);
         }
 
         Ok(())
     }
+
+    /// Make `object` the global `name`, whose methods and properties scripts use directly.
+    /// Methods needing a capability this runtime doesn't allow fail when called. Like other
+    /// globals, the object goes away with `reset`.
+    pub fn register_native_object<T: NativeObject>(
+        &mut self,
+        name: &str,
+        object: T,
+    ) -> Result<(), String> {
+        let object = native_value(object, &self.config);
+        self.vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?
+            .define_global(name, object);
+
+        if self.config.debug_mode {
+            eprintln!("Registered native object: {}", name);
+        }
+
+        Ok(())
+    }
+
+    /// The objects behind the handles this runtime's scripts get. Host functions keep a clone
+    /// to create handles and look them up.
+    pub fn handles(&self) -> Handles {
+        self.handles.clone()
+    }
+
+    /// A `.nagd` declaration of the registered host functions. Saved under the project's
+    /// `typings` directory, it lets the type checker and language server know about them.
+    pub fn host_declarations(&self) -> Result<String, String> {
+        let vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        Ok(vm.host_declarations())
+    }
+
+    /// Call `listener` with the arguments of every `events.emit(event, ...)` made by scripts,
+    /// returning a subscription id for `off_event`. Listeners run while the VM is locked, so
+    /// they must not call back into the runtime.
+    pub fn on_event<F>(&mut self, event: &str, listener: F) -> Result<u64, String>
+    where
+        F: Fn(Vec<EmbeddedValue>) + Send + Sync + 'static,
+    {
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        Ok(vm.subscribe_event(event, event_listener(listener), false))
+    }
+
+    /// Remove the subscription `id`, returning whether it existed
+    pub fn off_event(&mut self, id: u64) -> Result<bool, String> {
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        Ok(vm.unsubscribe_event(id))
+    }
+
+    /// Emit `event` into the scripts, calling their listeners and the host's with `args`.
+    /// Returns whether anything was listening.
+    pub fn emit_event(&mut self, event: &str, args: Vec<EmbeddedValue>) -> Result<bool, String> {
+        let args = args.into_iter().map(|arg| arg.to_nagari()).collect();
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        nagari_vm::host::block_on(vm.emit_event(event, args))?
+    }
+
+    /// Keep the jobs scripts register with the `schedule` module in `store`, so that they
+    /// survive restarts. Jobs already in it are registered again by `restore_jobs`.
+    pub fn set_job_store<S: JobStore + 'static>(&mut self, store: S) -> Result<(), String> {
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        vm.set_job_store(Some(Arc::new(store)))
+    }
+
+    /// Register the saved jobs whose function the scripts define again, returning their names
+    pub fn restore_jobs(&mut self) -> Result<Vec<String>, String> {
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        vm.restore_jobs()
+    }
+
+    /// The jobs scripts registered
+    pub fn jobs(&self) -> Result<Vec<JobRecord>, String> {
+        let vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        Ok(vm.jobs())
+    }
+
+    /// When the earliest job is due, for hosts that sleep until then
+    pub fn next_job_due(&self) -> Result<Option<SystemTime>, String> {
+        let vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        Ok(vm.next_job_due())
+    }
+
+    /// Run the jobs that are due, returning how many ran. Hosts call this from their own loop
+    /// or timer, as the runtime has no thread of its own.
+    pub fn run_due_jobs(&mut self) -> Result<usize, String> {
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        nagari_vm::host::block_on(vm.run_due_jobs(SystemTime::now()))?
+    }
+
     pub fn set_global(&mut self, name: &str, value: EmbeddedValue) -> Result<(), String> {
         let mut vm = self
             .vm
@@ -178,6 +546,16 @@ impl EmbeddedRuntime {
         }
     }
 
+    /// Snapshot of the values the scripts hold on to. Compare two with `HeapSnapshot::diff` to
+    /// see what a long-lived session keeps accumulating.
+    pub fn heap_snapshot(&self) -> Result<HeapSnapshot, String> {
+        let vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        Ok(vm.heap_snapshot())
+    }
+
     pub fn reset(&mut self) -> Result<(), String> {
         let mut vm = self
             .vm
@@ -188,6 +566,7 @@ impl EmbeddedRuntime {
         vm.clear_globals();
 
         self.modules.clear();
+        self.handles.clear();
 
         if self.config.debug_mode {
             eprintln!("Runtime reset");
@@ -196,79 +575,73 @@ impl EmbeddedRuntime {
         Ok(())
     }
 
-    // Helper methods for embedded execution
-    fn compile_and_run_embedded_source(&mut self, source: &str) -> Result<NagariValue, String> {
-        // Simple expression evaluator for basic operations
-        // This mirrors the WASM implementation but for embedded use
-        let trimmed = source.trim();
-
-        // Handle simple numeric literals
-        if let Ok(num) = trimmed.parse::<i64>() {
-            return Ok(NagariValue::Int(num));
-        }
-
-        if let Ok(num) = trimmed.parse::<f64>() {
-            return Ok(NagariValue::Float(num));
-        }
-
-        // Handle string literals
-        if trimmed.starts_with('"') && trimmed.ends_with('"') {
-            let string_content = &trimmed[1..trimmed.len()-1];
-            return Ok(NagariValue::String(string_content.to_string()));
-        }
-
-        // Handle boolean literals
-        if trimmed == "true" {
-            return Ok(NagariValue::Bool(true));
-        }
-        if trimmed == "false" {
-            return Ok(NagariValue::Bool(false));
-        }
-        if trimmed == "null" || trimmed == "None" {
-            return Ok(NagariValue::None);
-        }
-
-        // Handle simple variable lookups through VM
-        if let Ok(vm) = self.vm.lock() {
-            if let Some(value) = vm.get_global(trimmed) {
-                return Ok(value.clone());
-            }
+    /// Wait for a pending host call on a runtime of its own; other values resolve to themselves
+    fn resolve(&self, value: NagariValue) -> Result<NagariValue, String> {
+        let call = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?
+            .take_host_call(&value)?;
+        match call {
+            Some(call) => nagari_vm::host::block_on(call.wait())?,
+            None => Ok(value),
         }
+    }
 
-        // Handle simple arithmetic expressions (a + b)
-        if let Some(pos) = trimmed.find(" + ") {
-            let left_str = &trimmed[..pos].trim();
-            let right_str = &trimmed[pos + 3..].trim();
+    // Helper methods for embedded execution
 
-            if let (Ok(left), Ok(right)) = (self.compile_and_run_embedded_source(left_str), self.compile_and_run_embedded_source(right_str)) {
-                return left.add(&right);
+    /// Run a script: its imports bind names in the VM's globals, and the rest is compiled and
+    /// run as one program, whose trailing expression gives the result
+    fn run_statements(&mut self, script: &str) -> Result<NagariValue, String> {
+        let (imports, program) = split_imports(script)?;
+        for import in imports {
+            let bindings = self.modules.import_into_script(&import)?;
+            let mut vm = self
+                .vm
+                .lock()
+                .map_err(|e| format!("Failed to lock VM: {}", e))?;
+            for (name, value) in bindings {
+                vm.define_global(&name, value);
             }
         }
+        self.execute(&program, None)
+    }
 
-        // Handle simple function calls like print("hello")
-        if trimmed.starts_with("print(") && trimmed.ends_with(")") {
-            let args_str = &trimmed[6..trimmed.len()-1];
-            let arg_value = self.compile_and_run_embedded_source(args_str)?;
-
-            // Simple print implementation for embedded
-            match &arg_value {
-                NagariValue::String(s) => println!("{}", s),
-                NagariValue::Int(i) => println!("{}", i),
-                NagariValue::Float(f) => println!("{}", f),
-                NagariValue::Bool(b) => println!("{}", b),
-                NagariValue::None => println!("None"),
-                _ => println!("{:?}", arg_value),
+    /// Evaluate a module's statements in a namespace of its own
+    fn evaluate_module(&mut self, name: &str, code: &str) -> Result<Module, String> {
+        let mut scope = ModuleScope::new();
+        let statements = parse_statements(code).map_err(|e| format!("module '{name}', {e}"))?;
+        for statement in statements {
+            match statement {
+                ModuleStatement::Import(import) => scope.import(self.modules.resolve(&import)?),
+                ModuleStatement::Binding {
+                    name,
+                    expr,
+                    exported,
+                } => {
+                    let value = self.execute(expr, Some(&scope.namespace))?;
+                    scope.bind(name, value, exported);
+                }
+                ModuleStatement::Expression(expr) => {
+                    self.execute(expr, Some(&scope.namespace))?;
+                }
             }
-
-            return Ok(NagariValue::None);
         }
+        Ok(scope.finish(name, code))
+    }
 
-        // For unhandled expressions, return None with debug info
-        if self.config.debug_mode {
-            eprintln!("Unhandled expression in embedded mode: {}", trimmed);
+    /// Compile `source` to bytecode and run it, with the names of `scope` visible besides the
+    /// VM's globals. The value of a trailing expression statement is the result.
+    fn execute(&mut self, source: &str, scope: Option<&Scope>) -> Result<NagariValue, String> {
+        let mut vm = self
+            .vm
+            .lock()
+            .map_err(|e| format!("Failed to lock VM: {}", e))?;
+        vm.load_source(source, nagari_compiler::VERSION, compile)?;
+        match scope {
+            Some(scope) => nagari_vm::host::block_on(vm.evaluate_in(scope.clone()))?,
+            None => nagari_vm::host::block_on(vm.evaluate())?,
         }
-
-        Ok(NagariValue::None)
     }
 
     fn call_embedded_function(&mut self, function_name: &str, args: Vec<NagariValue>) -> Result<NagariValue, String> {
@@ -372,32 +745,15 @@ impl EmbeddedRuntime {
                 ])))
             }
             _ => {
-                // Check if it's a user-defined function in VM
-                if let Ok(vm) = self.vm.lock() {
-                    if let Some(value) = vm.get_global(function_name) {
-                        match value {
-                            NagariValue::Function(_) => {
-                                // For now, just return None since we can't execute user functions yet
-                                if self.config.debug_mode {
-                                    eprintln!("Called user function '{}' (not fully implemented)", function_name);
-                                }
-                                Ok(NagariValue::None)
-                            }
-                            NagariValue::Builtin(_) => {
-                                // For now, just return None since we can't execute builtins directly
-                                if self.config.debug_mode {
-                                    eprintln!("Called builtin function '{}' (not fully implemented)", function_name);
-                                }
-                                Ok(NagariValue::None)
-                            }
-                            _ => Err(format!("'{}' object is not callable", value.type_name())),
-                        }
-                    } else {
-                        Err(format!("name '{}' is not defined", function_name))
-                    }
-                } else {
-                    Err("VM lock failed".to_string())
-                }
+                // Host and script functions alike; a host call may leave a value to await
+                let result = {
+                    let mut vm = self
+                        .vm
+                        .lock()
+                        .map_err(|e| format!("Failed to lock VM: {}", e))?;
+                    nagari_vm::host::block_on(vm.call_function(function_name, args))??
+                };
+                self.resolve(result)
             }
         }
     }
@@ -413,6 +769,13 @@ pub enum EmbeddedValue {
     String(String),
     Array(Vec<EmbeddedValue>),
     Object(HashMap<String, EmbeddedValue>),
+    /// Native resource of the host, which scripts can only pass around and close with `with`.
+    /// It is released once the last value referring to it is dropped, if not before.
+    #[serde(skip)]
+    Resource(Resource),
+    /// Opaque handle to an object in the runtime's `Handles`, by id and type tag
+    #[serde(skip)]
+    Handle(u64, String),
 }
 
 impl EmbeddedValue {
@@ -431,6 +794,8 @@ impl EmbeddedValue {
                     .map(|(k, v)| (k, Self::from_nagari(v)))
                     .collect(),
             ),
+            NagariValue::Resource(resource) => EmbeddedValue::Resource(resource),
+            NagariValue::Handle(id, tag) => EmbeddedValue::Handle(id, tag),
             _ => EmbeddedValue::None,
         }
     }
@@ -448,6 +813,8 @@ impl EmbeddedValue {
             EmbeddedValue::Object(obj) => {
                 NagariValue::Dict(obj.into_iter().map(|(k, v)| (k, v.to_nagari())).collect())
             }
+            EmbeddedValue::Resource(resource) => NagariValue::Resource(resource),
+            EmbeddedValue::Handle(id, tag) => NagariValue::Handle(id, tag),
         }
     }
 
@@ -493,62 +860,90 @@ impl EmbeddedValue {
             _ => None,
         }
     }
+
+    pub fn as_resource(&self) -> Option<&Resource> {
+        match self {
+            EmbeddedValue::Resource(resource) => Some(resource),
+            _ => None,
+        }
+    }
 }
 
 // Async runtime for async/await support
 #[cfg(feature = "async")]
 pub struct AsyncEmbeddedRuntime {
     vm: Arc<AsyncRwLock<NagariVM>>,
-    modules: Arc<AsyncRwLock<HashMap<String, String>>>,
+    modules: Arc<AsyncRwLock<ModuleRegistry>>,
     config: RuntimeConfig,
 }
 
 #[cfg(feature = "async")]
 impl AsyncEmbeddedRuntime {
     pub async fn new(config: RuntimeConfig) -> Result<Self, String> {
-        let vm = NagariVM::new(false); // debug = false
+        let mut vm = NagariVM::new(false); // debug = false
+        vm.set_host_timeout(config.host_timeout());
+        vm.set_execution_limits(config.execution_limits());
+        vm.set_allow_network(config.allow_network);
+        vm.set_allow_io(config.allow_io);
+        vm.set_permission_prompt(config.permission_prompt.clone());
+        vm.set_yield_interval(config.yield_interval);
+        vm.set_yield_hook(config.yield_hook.clone());
 
         Ok(Self {
             vm: Arc::new(AsyncRwLock::new(vm)),
-            modules: Arc::new(AsyncRwLock::new(HashMap::new())),
+            modules: Arc::new(AsyncRwLock::new(ModuleRegistry::new())),
             config,
         })
     }
-    pub async fn run_script(&self, script: &str) -> Result<EmbeddedValue, String> {
-        // Apply runtime config constraints
-        if let Some(_timeout) = self.config.execution_timeout {
-            if self.config.debug_mode {
-                eprintln!("Executing async script with timeout constraint");
-            }
-        }
 
+    /// Compile `script` to bytecode and run it in the VM, like `EmbeddedRuntime::run_script`
+    pub async fn run_script(&self, script: &str) -> Result<EmbeddedValue, String> {
         // Check permissions
-        if !self.config.allow_io && script.contains("fs") {
+        if !self.config.may_use(Capability::Io) && script.contains("fs") {
             return Err("IO operations not allowed".to_string());
         }
 
-        if !self.config.allow_network && script.contains("http") {
+        if !self.config.may_use(Capability::Network) && script.contains("http") {
             return Err("Network operations not allowed".to_string());
         }
 
-        // Simple script evaluation for async context
-        let result = self.compile_and_run_async_source(script).await?;
+        let result = self.run_statements(script).await?;
 
         Ok(EmbeddedValue::from_nagari(result))
     }
 
-    pub async fn load_module_async(&self, name: &str, code: &str) -> Result<(), String> {
-        let mut modules = self.modules.write().await;
+    /// Run `script` like `run_script`, also reporting the IO and network accesses it made or
+    /// was denied
+    pub async fn run_script_with_report(&self, script: &str) -> ScriptReport {
+        self.vm.write().await.start_access_report();
+        let result = self.run_script(script).await;
+        let accesses = self.vm.write().await.finish_access_report();
+        ScriptReport { result, accesses }
+    }
+
+    /// Call `listener` with every IO and network access scripts make or are denied, as it
+    /// happens
+    pub async fn on_access<F>(&self, listener: F)
+    where
+        F: Fn(&Access) + Send + Sync + 'static,
+    {
+        self.vm
+            .write()
+            .await
+            .set_access_listener(Some(Arc::new(listener)));
+    }
 
-        if !self.config.allow_io && name.contains("fs") {
+    pub async fn load_module(&self, name: &str, code: &str) -> Result<(), String> {
+        if !self.config.may_use(Capability::Io) && name.contains("fs") {
             return Err("IO operations not allowed in this runtime".to_string());
         }
 
-        if !self.config.allow_network && name.contains("http") {
+        if !self.config.may_use(Capability::Network) && name.contains("http") {
             return Err("Network operations not allowed in this runtime".to_string());
         }
 
-        modules.insert(name.to_string(), code.to_string());
+        let module = self.evaluate_module(name, code).await?;
+        self.modules.write().await.insert(module);
 
         if self.config.debug_mode {
             eprintln!("Loaded async module: {} ({} bytes)", name, code.len());
@@ -557,11 +952,147 @@ impl AsyncEmbeddedRuntime {
         Ok(())
     }
 
+    /// Register an async host function. Scripts get an awaitable value from calling it, and
+    /// `await` waits on the host future for at most `execution_timeout`.
+    pub async fn register_host_function<H>(&self, name: &str, func: H) -> Result<(), String>
+    where
+        H: HostFunction + Send + Sync + 'static,
+    {
+        self.register_callback(name, func, None).await
+    }
+
+    /// Register a host function whose calls are checked against `signature` before it runs
+    pub async fn register_typed_host_function<H>(
+        &self,
+        name: &str,
+        signature: HostSignature,
+        func: H,
+    ) -> Result<(), String>
+    where
+        H: HostFunction + Send + Sync + 'static,
+    {
+        self.register_callback(name, func, Some(signature)).await
+    }
+
+    async fn register_callback<H>(
+        &self,
+        name: &str,
+        func: H,
+        signature: Option<HostSignature>,
+    ) -> Result<(), String>
+    where
+        H: HostFunction + Send + Sync + 'static,
+    {
+        self.config.check_host_function(name)?;
+
+        self.vm
+            .write()
+            .await
+            .register_host_function(name, async_host_callback(func), signature);
+
+        if self.config.debug_mode {
+            eprintln!("Registered async host function: {}", name);
+        }
+
+        Ok(())
+    }
+
+    /// Make `object` the global `name`, like `EmbeddedRuntime::register_native_object`
+    pub async fn register_native_object<T: NativeObject>(&self, name: &str, object: T) {
+        let object = native_value(object, &self.config);
+        self.vm.write().await.define_global(name, object);
+
+        if self.config.debug_mode {
+            eprintln!("Registered native object: {}", name);
+        }
+    }
+
+    /// A `.nagd` declaration of the registered host functions, for the type checker and
+    /// language server
+    pub async fn host_declarations(&self) -> String {
+        self.vm.read().await.host_declarations()
+    }
+
+    /// Call `listener` with the arguments of every `events.emit(event, ...)` made by scripts,
+    /// returning a subscription id for `off_event`. Listeners run while the VM is locked, so
+    /// they must not call back into the runtime.
+    pub async fn on_event<F>(&self, event: &str, listener: F) -> u64
+    where
+        F: Fn(Vec<EmbeddedValue>) + Send + Sync + 'static,
+    {
+        self.vm
+            .write()
+            .await
+            .subscribe_event(event, event_listener(listener), false)
+    }
+
+    /// Remove the subscription `id`, returning whether it existed
+    pub async fn off_event(&self, id: u64) -> bool {
+        self.vm.write().await.unsubscribe_event(id)
+    }
+
+    /// Emit `event` into the scripts, calling their listeners and the host's with `args`.
+    /// Returns whether anything was listening.
+    pub async fn emit_event(&self, event: &str, args: Vec<EmbeddedValue>) -> Result<bool, String> {
+        let args = args.into_iter().map(|arg| arg.to_nagari()).collect();
+        self.vm.write().await.emit_event(event, args).await
+    }
+
+    /// Keep the jobs scripts register in `store`, like `EmbeddedRuntime::set_job_store`
+    pub async fn set_job_store<S: JobStore + 'static>(&self, store: S) -> Result<(), String> {
+        self.vm.write().await.set_job_store(Some(Arc::new(store)))
+    }
+
+    /// Register the saved jobs whose function the scripts define again, returning their names
+    pub async fn restore_jobs(&self) -> Result<Vec<String>, String> {
+        self.vm.write().await.restore_jobs()
+    }
+
+    /// The jobs scripts registered
+    pub async fn jobs(&self) -> Vec<JobRecord> {
+        self.vm.read().await.jobs()
+    }
+
+    /// When the earliest job is due
+    pub async fn next_job_due(&self) -> Option<SystemTime> {
+        self.vm.read().await.next_job_due()
+    }
+
+    /// Run the jobs that are due, returning how many ran
+    pub async fn run_due_jobs(&self) -> Result<usize, String> {
+        self.vm.write().await.run_due_jobs(SystemTime::now()).await
+    }
+
+    /// Run jobs as they come due until one fails. The VM is only locked while jobs run, so
+    /// the runtime stays usable from other tasks, and jobs registered meanwhile are picked up
+    /// within a second.
+    pub async fn run_scheduler(&self) -> Result<(), String> {
+        loop {
+            let now = SystemTime::now();
+            let wake = match self.next_job_due().await {
+                Some(due) => due.min(now + Duration::from_secs(1)),
+                None => now + Duration::from_secs(1),
+            };
+            if let Ok(wait) = wake.duration_since(now) {
+                tokio::time::sleep(wait).await;
+            }
+            self.run_due_jobs().await?;
+        }
+    }
+
+    /// Snapshot of the values the scripts hold on to
+    pub async fn heap_snapshot(&self) -> HeapSnapshot {
+        self.vm.read().await.heap_snapshot()
+    }
+
     pub async fn get_loaded_modules(&self) -> Vec<String> {
-        let modules = self.modules.read().await;
-        modules.keys().cloned().collect()
+        self.modules.read().await.names()
     }
-    pub async fn call_function_async(
+
+    pub async fn get_module(&self, name: &str) -> Option<Module> {
+        self.modules.read().await.get(name).cloned()
+    }
+    pub async fn call_function(
         &self,
         name: &str,
         args: Vec<EmbeddedValue>,
@@ -579,75 +1110,79 @@ impl AsyncEmbeddedRuntime {
         Ok(EmbeddedValue::from_nagari(result))
     }
 
-    // Async helper methods
-    async fn compile_and_run_async_source(&self, source: &str) -> Result<NagariValue, String> {
-        // Simple expression evaluator for async operations
-        let trimmed = source.trim();
-
-        // Handle simple numeric literals
-        if let Ok(num) = trimmed.parse::<i64>() {
-            return Ok(NagariValue::Int(num));
-        }
+    #[deprecated(since = "0.3.0", note = "renamed to `load_module`")]
+    pub async fn load_module_async(&self, name: &str, code: &str) -> Result<(), String> {
+        self.load_module(name, code).await
+    }
 
-        if let Ok(num) = trimmed.parse::<f64>() {
-            return Ok(NagariValue::Float(num));
-        }
+    #[deprecated(since = "0.3.0", note = "renamed to `call_function`")]
+    pub async fn call_function_async(
+        &self,
+        name: &str,
+        args: Vec<EmbeddedValue>,
+    ) -> Result<EmbeddedValue, String> {
+        self.call_function(name, args).await
+    }
 
-        // Handle string literals
-        if trimmed.starts_with('"') && trimmed.ends_with('"') {
-            let string_content = &trimmed[1..trimmed.len()-1];
-            return Ok(NagariValue::String(string_content.to_string()));
+    /// Await a pending host call without holding the VM lock; other values resolve to themselves
+    async fn resolve(&self, value: NagariValue) -> Result<NagariValue, String> {
+        let call = self.vm.write().await.take_host_call(&value)?;
+        match call {
+            Some(call) => call.wait().await,
+            None => Ok(value),
         }
+    }
 
-        // Handle boolean literals
-        if trimmed == "true" {
-            return Ok(NagariValue::Bool(true));
-        }
-        if trimmed == "false" {
-            return Ok(NagariValue::Bool(false));
-        }
-        if trimmed == "null" || trimmed == "None" {
-            return Ok(NagariValue::None);
-        }
+    // Async helper methods
 
-        // Handle simple variable lookups through VM
-        {
-            let vm = self.vm.read().await;
-            if let Some(value) = vm.get_global(trimmed) {
-                return Ok(value.clone());
+    /// Run a script: its imports bind names in the VM's globals, and the rest is compiled and
+    /// run as one program, whose trailing expression gives the result
+    async fn run_statements(&self, script: &str) -> Result<NagariValue, String> {
+        let (imports, program) = split_imports(script)?;
+        for import in imports {
+            let bindings = self.modules.write().await.import_into_script(&import)?;
+            let mut vm = self.vm.write().await;
+            for (name, value) in bindings {
+                vm.define_global(&name, value);
             }
         }
+        self.execute(&program, None).await
+    }
 
-        // Handle async function calls
-        if trimmed.starts_with("await ") {
-            let inner_expr = &trimmed[6..].trim();
-            return Box::pin(self.compile_and_run_async_source(inner_expr)).await;
-        }
-
-        // Handle simple function calls like print("hello")
-        if trimmed.starts_with("print(") && trimmed.ends_with(")") {
-            let args_str = &trimmed[6..trimmed.len()-1];
-            let arg_value = Box::pin(self.compile_and_run_async_source(args_str)).await?;
-
-            // Simple print implementation for async
-            match &arg_value {
-                NagariValue::String(s) => println!("{}", s),
-                NagariValue::Int(i) => println!("{}", i),
-                NagariValue::Float(f) => println!("{}", f),
-                NagariValue::Bool(b) => println!("{}", b),
-                NagariValue::None => println!("None"),
-                _ => println!("{:?}", arg_value),
+    /// Evaluate a module's statements in a namespace of its own
+    async fn evaluate_module(&self, name: &str, code: &str) -> Result<Module, String> {
+        let mut scope = ModuleScope::new();
+        let statements = parse_statements(code).map_err(|e| format!("module '{name}', {e}"))?;
+        for statement in statements {
+            match statement {
+                ModuleStatement::Import(import) => {
+                    scope.import(self.modules.read().await.resolve(&import)?)
+                }
+                ModuleStatement::Binding {
+                    name,
+                    expr,
+                    exported,
+                } => {
+                    let value = self.execute(expr, Some(&scope.namespace)).await?;
+                    scope.bind(name, value, exported);
+                }
+                ModuleStatement::Expression(expr) => {
+                    self.execute(expr, Some(&scope.namespace)).await?;
+                }
             }
-
-            return Ok(NagariValue::None);
         }
+        Ok(scope.finish(name, code))
+    }
 
-        // For unhandled expressions, return None with debug info
-        if self.config.debug_mode {
-            eprintln!("Unhandled async expression: {}", trimmed);
+    /// Compile `source` to bytecode and run it, with the names of `scope` visible besides the
+    /// VM's globals. The value of a trailing expression statement is the result.
+    async fn execute(&self, source: &str, scope: Option<&Scope>) -> Result<NagariValue, String> {
+        let mut vm = self.vm.write().await;
+        vm.load_source(source, nagari_compiler::VERSION, compile)?;
+        match scope {
+            Some(scope) => vm.evaluate_in(scope.clone()).await,
+            None => vm.evaluate().await,
         }
-
-        Ok(NagariValue::None)
     }
 
     async fn call_async_function(&self, function_name: &str, args: Vec<NagariValue>) -> Result<NagariValue, String> {
@@ -706,29 +1241,14 @@ impl AsyncEmbeddedRuntime {
                 Ok(NagariValue::String(format!("Response from {}", url)))
             }
             _ => {
-                // Check if it's a user-defined function in VM
-                {
-                    let vm = self.vm.read().await;
-                    if let Some(value) = vm.get_global(function_name) {
-                        match value {
-                            NagariValue::Function(_) => {
-                                if self.config.debug_mode {
-                                    eprintln!("Called async user function '{}' (not fully implemented)", function_name);
-                                }
-                                Ok(NagariValue::None)
-                            }
-                            NagariValue::Builtin(_) => {
-                                if self.config.debug_mode {
-                                    eprintln!("Called async builtin function '{}' (not fully implemented)", function_name);
-                                }
-                                Ok(NagariValue::None)
-                            }
-                            _ => Err(format!("'{}' object is not callable", value.type_name())),
-                        }
-                    } else {
-                        Err(format!("name '{}' is not defined", function_name))
-                    }
-                }
+                // Host and script functions alike; a host call may leave a value to await
+                let result = self
+                    .vm
+                    .write()
+                    .await
+                    .call_function(function_name, args)
+                    .await?;
+                self.resolve(result).await
             }
         }
     }
@@ -751,6 +1271,74 @@ where
     }
 }
 
+fn sync_host_callback<F>(func: F) -> HostCallback
+where
+    F: Fn(Vec<EmbeddedValue>) -> EmbeddedValue + Send + Sync + 'static,
+{
+    HostCallback::Sync(Arc::new(move |args: Vec<NagariValue>| {
+        let args = args.into_iter().map(EmbeddedValue::from_nagari).collect();
+        Ok(func(args).to_nagari())
+    }))
+}
+
+/// Wrap a host closure as an event listener, converting the event's arguments
+fn event_listener<F>(listener: F) -> nagari_vm::EventListener
+where
+    F: Fn(Vec<EmbeddedValue>) + Send + Sync + 'static,
+{
+    Arc::new(move |args: &[NagariValue]| {
+        let args = args.iter().cloned().map(EmbeddedValue::from_nagari);
+        listener(args.collect());
+        Ok(())
+    })
+}
+
+/// Wrap a host function so the VM can start it and await its result
+fn async_host_callback<H>(func: H) -> HostCallback
+where
+    H: HostFunction + Send + Sync + 'static,
+{
+    let func = Arc::new(func);
+    HostCallback::Async(Arc::new(move |args: Vec<NagariValue>| {
+        let func = Arc::clone(&func);
+        let args = args.into_iter().map(EmbeddedValue::from_nagari).collect();
+        Box::pin(async move { func.call(args).await.map(EmbeddedValue::to_nagari) })
+    }))
+}
+
+/// Wrap a host object for the VM, checking its methods against what `config` allows
+fn native_value<T: NativeObject>(object: T, config: &RuntimeConfig) -> NagariValue {
+    NagariValue::Native(nagari_vm::NativeObject::new(native::NativeAdapter::new(
+        object, config,
+    )))
+}
+
+/// Compile Nagari source to VM bytecode
+fn compile(source: &str) -> Result<Vec<u8>, String> {
+    nagari_compiler::Compiler::new()
+        .compile_to_bytecode(source)
+        .map_err(|e| e.to_string())
+}
+
+/// Take the import statements out of `script`, leaving blank lines in their place so the rest
+/// keeps its line numbers
+fn split_imports(script: &str) -> Result<(Vec<Import>, String), String> {
+    let mut imports = Vec::new();
+    let mut program = String::with_capacity(script.len());
+    for (index, line) in script.lines().enumerate() {
+        if line.starts_with("import ") || line.starts_with("from ") {
+            let statement = parse_statement(line).map_err(|e| format!("line {}: {e}", index + 1))?;
+            if let ModuleStatement::Import(import) = statement {
+                imports.push(import);
+            }
+        } else {
+            program.push_str(line);
+        }
+        program.push('\n');
+    }
+    Ok((imports, program))
+}
+
 // Event system for runtime notifications
 #[derive(Debug, Clone)]
 pub enum RuntimeEvent {
@@ -868,6 +1456,11 @@ impl RuntimeBuilder {
         self
     }
 
+    pub fn instruction_limit(mut self, instructions: u64) -> Self {
+        self.config.instruction_limit = Some(instructions);
+        self
+    }
+
     pub fn allow_io(mut self, allow: bool) -> Self {
         self.config.allow_io = allow;
         self
@@ -878,6 +1471,33 @@ impl RuntimeBuilder {
         self
     }
 
+    /// Decide on IO and network access not allowed outright while scripts run, e.g. by asking
+    /// the user. `prompt` is asked once per path or host unless it decides for good.
+    pub fn permission_prompt<F>(mut self, prompt: F) -> Self
+    where
+        F: Fn(&Permission) -> PermissionDecision + Send + Sync + 'static,
+    {
+        self.config.permission_prompt = Some(PermissionPrompt::new(prompt));
+        self
+    }
+
+    /// Let the host do other work every `instructions` instructions of a run, or never with
+    /// `None`
+    pub fn yield_interval(mut self, instructions: Option<u64>) -> Self {
+        self.config.yield_interval = instructions;
+        self
+    }
+
+    /// Call `hook` at each yield point of a run. It runs while the VM is locked, so it must
+    /// not call back into the runtime.
+    pub fn on_yield<F>(mut self, hook: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.config.yield_hook = Some(YieldHook::new(hook));
+        self
+    }
+
     pub fn sandbox_mode(mut self, enabled: bool) -> Self {
         self.config.sandbox_mode = enabled;
         self
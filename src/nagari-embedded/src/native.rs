@@ -0,0 +1,146 @@
+//! Host objects that scripts call methods on directly, as in `player.move(x, y)`.
+//!
+//! A type implementing [`NativeObject`] lists its methods in a [`MethodTable`], each optionally
+//! needing a [`Capability`] of the runtime. Calling a method the runtime's config doesn't allow
+//! fails with a `PermissionError` instead of running it.
+
+use crate::{EmbeddedValue, RuntimeConfig};
+use nagari_vm::{HostObject, Value as NagariValue};
+use std::collections::HashMap;
+use std::fmt;
+
+/// What a method of a host object needs the runtime to allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Allowed by `allow_io`
+    Io,
+    /// Allowed by `allow_network`
+    Network,
+    /// Only allowed outside sandbox mode
+    Unsafe,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Io => write!(f, "IO"),
+            Capability::Network => write!(f, "network"),
+            Capability::Unsafe => write!(f, "unsafe"),
+        }
+    }
+}
+
+type Method<T> =
+    Box<dyn Fn(&mut T, Vec<EmbeddedValue>) -> Result<EmbeddedValue, String> + Send + Sync>;
+
+/// Methods scripts can call on a host object, by name
+pub struct MethodTable<T> {
+    methods: HashMap<String, (Option<Capability>, Method<T>)>,
+}
+
+impl<T> Default for MethodTable<T> {
+    fn default() -> Self {
+        Self {
+            methods: HashMap::new(),
+        }
+    }
+}
+
+impl<T> MethodTable<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn method<F>(mut self, name: &str, method: F) -> Self
+    where
+        F: Fn(&mut T, Vec<EmbeddedValue>) -> Result<EmbeddedValue, String> + Send + Sync + 'static,
+    {
+        self.methods
+            .insert(name.to_string(), (None, Box::new(method)));
+        self
+    }
+
+    /// A method that only runs if the runtime allows `capability`
+    pub fn method_requiring<F>(mut self, name: &str, capability: Capability, method: F) -> Self
+    where
+        F: Fn(&mut T, Vec<EmbeddedValue>) -> Result<EmbeddedValue, String> + Send + Sync + 'static,
+    {
+        self.methods
+            .insert(name.to_string(), (Some(capability), Box::new(method)));
+        self
+    }
+}
+
+/// A host type whose values scripts use like their own objects
+pub trait NativeObject: Send + Sized + 'static {
+    /// Name of the type, as scripts see it
+    fn type_name(&self) -> &str;
+
+    fn methods() -> MethodTable<Self>;
+
+    /// Property `name`, which scripts read as `object.name`
+    fn get_property(&self, _name: &str) -> Option<EmbeddedValue> {
+        None
+    }
+
+    /// Set property `name`, as scripts do with `object.name = value`
+    fn set_property(&mut self, name: &str, _value: EmbeddedValue) -> Result<(), String> {
+        Err(format!(
+            "cannot set attribute '{name}' of '{}' object",
+            self.type_name()
+        ))
+    }
+}
+
+/// A host object as the VM sees it, with the capabilities its runtime allows
+pub(crate) struct NativeAdapter<T> {
+    object: T,
+    methods: MethodTable<T>,
+    config: RuntimeConfig,
+}
+
+impl<T: NativeObject> NativeAdapter<T> {
+    pub fn new(object: T, config: &RuntimeConfig) -> Self {
+        Self {
+            object,
+            methods: T::methods(),
+            config: config.clone(),
+        }
+    }
+}
+
+impl<T: NativeObject> HostObject for NativeAdapter<T> {
+    fn type_name(&self) -> &str {
+        self.object.type_name()
+    }
+
+    fn has_method(&self, name: &str) -> bool {
+        self.methods.methods.contains_key(name)
+    }
+
+    fn call_method(&mut self, name: &str, args: Vec<NagariValue>) -> Result<NagariValue, String> {
+        let type_name = self.object.type_name();
+        let Some((capability, method)) = self.methods.methods.get(name) else {
+            return Err(format!("'{type_name}' object has no attribute '{name}'"));
+        };
+        if let Some(capability) = capability {
+            if !self.config.allows(*capability) {
+                return Err(format!(
+                    "PermissionError: {type_name}.{name}() requires {capability} access, \
+                     which this runtime does not allow"
+                ));
+            }
+        }
+        let args = args.into_iter().map(EmbeddedValue::from_nagari).collect();
+        method(&mut self.object, args).map(EmbeddedValue::to_nagari)
+    }
+
+    fn get_property(&self, name: &str) -> Option<NagariValue> {
+        self.object.get_property(name).map(EmbeddedValue::to_nagari)
+    }
+
+    fn set_property(&mut self, name: &str, value: NagariValue) -> Result<(), String> {
+        self.object
+            .set_property(name, EmbeddedValue::from_nagari(value))
+    }
+}
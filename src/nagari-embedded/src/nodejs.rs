@@ -205,7 +205,10 @@ fn js_value_to_embedded(cx: &mut FunctionContext, value: Handle<JsValue>) -> Neo
 #[cfg(feature = "nodejs")]
 fn embedded_value_to_js(cx: &mut FunctionContext, value: EmbeddedValue) -> JsResult<JsValue> {
     match value {
-        EmbeddedValue::None => Ok(cx.null().upcast()),
+        // Resources and handles only mean something to Rust hosts
+        EmbeddedValue::None | EmbeddedValue::Resource(_) | EmbeddedValue::Handle(..) => {
+            Ok(cx.null().upcast())
+        }
         EmbeddedValue::Bool(b) => Ok(cx.boolean(b).upcast()),
         EmbeddedValue::Int(i) => Ok(cx.number(i as f64).upcast()),
         EmbeddedValue::Float(f) => Ok(cx.number(f).upcast()),
@@ -0,0 +1,19 @@
+//! The supported surface of the embedding API. Items reachable from here follow semantic
+//! versioning: they are only removed or changed incompatibly in a major release, after a
+//! release in which they are `#[deprecated]`. Everything else may change between minor
+//! releases.
+//!
+//! ```
+//! use nagari_embedded::prelude::*;
+//!
+//! let mut runtime = RuntimeBuilder::new().sandbox_mode(true).build().unwrap();
+//! runtime.set_global("answer", EmbeddedValue::Int(42)).unwrap();
+//! assert_eq!(runtime.run_script("answer").unwrap().as_int(), Some(42));
+//! ```
+
+#[cfg(feature = "async")]
+pub use crate::AsyncEmbeddedRuntime;
+pub use crate::{
+    Capability, EmbeddedRuntime, EmbeddedValue, Handles, HeapDiff, HeapSnapshot, HostFunction,
+    HostSignature, MethodTable, NativeObject, RuntimeBuilder, RuntimeConfig,
+};
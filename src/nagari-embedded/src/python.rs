@@ -150,7 +150,10 @@ fn py_to_embedded_value(obj: &PyAny) -> PyResult<EmbeddedValue> {
 #[cfg(feature = "python")]
 fn embedded_value_to_py(py: Python, value: EmbeddedValue) -> PyResult<PyObject> {
     match value {
-        EmbeddedValue::None => Ok(py.None()),
+        // Resources and handles only mean something to Rust hosts
+        EmbeddedValue::None | EmbeddedValue::Resource(_) | EmbeddedValue::Handle(..) => {
+            Ok(py.None())
+        }
         EmbeddedValue::Bool(b) => Ok(b.into_py(py)),
         EmbeddedValue::Int(i) => Ok(i.into_py(py)),
         EmbeddedValue::Float(f) => Ok(f.into_py(py)),
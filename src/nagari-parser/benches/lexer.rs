@@ -0,0 +1,45 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nagari_parser::Lexer;
+
+const SAMPLES: &[&str] = &[
+    include_str!("../../../examples/algorithms.nag"),
+    include_str!("../../../examples/file_operations.nag"),
+    include_str!("../../../examples/interop_demo.nag"),
+    include_str!("../../../examples/math_demo.nag"),
+    include_str!("../../../examples/react_todo_app.nag"),
+    include_str!("../../../examples/string_functions_demo_simple.nag"),
+    include_str!("../../../examples/vue_task_app.nag"),
+];
+
+/// Concatenate the sample programs until the source is at least `min_bytes` long
+fn corpus(min_bytes: usize) -> String {
+    let mut source = String::with_capacity(min_bytes + 8192);
+    while source.len() < min_bytes {
+        for sample in SAMPLES {
+            source.push_str(sample);
+            source.push('\n');
+        }
+    }
+    source
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer/tokenize");
+    group.sample_size(10);
+
+    for size in [16 * 1024, 128 * 1024] {
+        let source = corpus(size);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, source| {
+            b.iter(|| {
+                let tokens = Lexer::new(black_box(source)).tokenize().unwrap();
+                black_box(tokens.len())
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize);
+criterion_main!(benches);
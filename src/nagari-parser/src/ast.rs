@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ArrowFunctionBody {
@@ -11,6 +12,20 @@ pub struct Program {
     pub statements: Vec<Statement>,
 }
 
+/// 1-based line and column where a statement or token starts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Syntax that still parses but has a replacement, and the message to report it with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deprecation {
+    pub span: Span,
+    pub message: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Statement {
     Let {
@@ -36,18 +51,36 @@ pub enum Statement {
         variable: String,
         iterable: Expression,
         body: Vec<Statement>,
+        /// `async for`, over an async iterable
+        is_async: bool,
+    },
+    /// `with open(path) as file, lock:`
+    With {
+        items: Vec<WithItem>,
+        body: Vec<Statement>,
+        /// `async with`, awaiting the context managers' enter and exit
+        is_async: bool,
+    },
+    /// `match subject:` and its `case` clauses, tried in order
+    Match {
+        subject: Expression,
+        cases: Vec<MatchCase>,
     },
     Function {
         name: String,
         parameters: Vec<FunctionParameter>,
         body: Vec<Statement>,
         is_async: bool,
-        return_type: Option<String>,
+        return_type: Option<TypeAnnotation>,
+        /// `@decorator` lines above a `def`, outermost first
+        decorators: Vec<Decorator>,
     },
     Class {
         name: String,
         superclass: Option<String>,
         methods: Vec<Statement>,
+        /// `@decorator` lines above the class, outermost first
+        decorators: Vec<Decorator>,
     },
     ExportNamed {
         exports: Vec<NamedExport>,
@@ -79,9 +112,11 @@ pub enum Expression {
         operator: UnaryOperator,
         operand: Box<Expression>,
     },
+    Await(Box<Expression>),
     Call {
         function: Box<Expression>,
         arguments: Vec<Expression>,
+        keywords: Vec<KeywordArgument>,
     },
     Member {
         object: Box<Expression>,
@@ -90,17 +125,19 @@ pub enum Expression {
     },
     Array(Vec<Expression>),
     Object(Vec<ObjectProperty>),
+    /// `{a, b}`
+    Set(Vec<Expression>),
     Function {
         parameters: Vec<FunctionParameter>,
         body: Vec<Statement>,
         is_async: bool,
-        return_type: Option<String>,
+        return_type: Option<TypeAnnotation>,
     },
     Arrow {
         parameters: Vec<FunctionParameter>,
         body: ArrowFunctionBody,
         is_async: bool,
-        return_type: Option<String>,
+        return_type: Option<TypeAnnotation>,
     },
     Assignment {
         left: Box<Expression>,
@@ -116,10 +153,39 @@ pub enum Expression {
         parts: Vec<String>,
         expressions: Vec<Expression>,
     },
+    /// `js"""..."""`: JavaScript passed through to the output as written
+    InlineJs(String),
     Index {
         object: Box<Expression>,
         index: Box<Expression>,
     },
+    Slice {
+        object: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        step: Option<Box<Expression>>,
+    },
+    /// `[x * 2 for x in xs if x > 0]`
+    ListComprehension {
+        element: Box<Expression>,
+        generators: Vec<ComprehensionGenerator>,
+    },
+    /// `{k: v for k in ks}`
+    DictComprehension {
+        key: Box<Expression>,
+        value: Box<Expression>,
+        generators: Vec<ComprehensionGenerator>,
+    },
+    /// `{x for x in xs}`
+    SetComprehension {
+        element: Box<Expression>,
+        generators: Vec<ComprehensionGenerator>,
+    },
+    /// `(x for x in xs)`, or `f(x for x in xs)` as the only argument of a call
+    Generator {
+        element: Box<Expression>,
+        generators: Vec<ComprehensionGenerator>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -188,11 +254,143 @@ pub struct ImportItem {
     pub alias: Option<String>,
 }
 
+/// `name=value` argument of a call, or `**value` when it has no name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordArgument {
+    pub name: Option<String>,
+    pub value: Expression,
+}
+
+/// Context manager of a `with` statement and the name its `__enter__()` result is bound to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WithItem {
+    pub context: Expression,
+    pub alias: Option<String>,
+}
+
+/// `@name` or `@name(arguments)`; `name` may be dotted, as in `@value.setter`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decorator {
+    pub name: String,
+    pub arguments: Option<Vec<Expression>>,
+    /// `name=value` and `**mapping` arguments, as in a call
+    pub keywords: Vec<KeywordArgument>,
+}
+
+/// `for target in iter` in a comprehension and the `if` conditions after it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComprehensionGenerator {
+    pub target: String,
+    pub iter: Expression,
+    pub conditions: Vec<Expression>,
+}
+
+/// `case pattern if guard:` and the statements it runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchCase {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Vec<Statement>,
+}
+
+/// What a `case` matches its subject against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// `1`, `-2.5`, `"text"`, `true` or `null`, matching an equal value
+    Literal(Literal),
+    /// A name, matching anything and binding it to the name
+    Capture(String),
+    /// `_`, matching anything
+    Wildcard,
+    /// `(a, b)`: a tuple of as many items, each matching its pattern
+    Tuple(Vec<Pattern>),
+    /// `[a, b]`: a list of as many items, each matching its pattern
+    List(Vec<Pattern>),
+    /// `{key: pattern}`: a dict with the keys, their values matching the patterns
+    Dict(Vec<(Literal, Pattern)>),
+    /// `Point(x, y)`: an instance of the class, its fields matching the patterns in order
+    Class {
+        name: String,
+        arguments: Vec<Pattern>,
+    },
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionParameter {
     pub name: String,
-    pub type_annotation: Option<String>,
+    pub type_annotation: Option<TypeAnnotation>,
     pub default_value: Option<Expression>,
+    pub kind: ParameterKind,
+}
+
+/// How a parameter takes the arguments of a call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ParameterKind {
+    /// One argument, by position or by name
+    #[default]
+    Positional,
+    /// `*args` or `...args`: the positional arguments left over, as a list
+    Rest,
+    /// `**kwargs`: the keyword arguments no other parameter takes, as a dict
+    Keywords,
+}
+
+impl ParameterKind {
+    /// What is written before the parameter's name
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ParameterKind::Positional => "",
+            ParameterKind::Rest => "*",
+            ParameterKind::Keywords => "**",
+        }
+    }
+}
+
+/// A type written in an annotation, as in `x: list[int]` or `-> str | None`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TypeAnnotation {
+    /// `int`, `None`, `Point`, or a dotted name like `models.User`
+    Name(String),
+    /// `list[int]` or `dict[str, float]`: a name applied to type arguments
+    Generic {
+        name: String,
+        arguments: Vec<TypeAnnotation>,
+    },
+    /// `int | None`
+    Union(Vec<TypeAnnotation>),
+    /// `Callable[[int], str]`, or `Callable[..., str]` without `parameters` for any arguments
+    Callable {
+        parameters: Option<Vec<TypeAnnotation>>,
+        return_type: Box<TypeAnnotation>,
+    },
+}
+
+impl fmt::Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn join(types: &[TypeAnnotation], separator: &str) -> String {
+            types
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(separator)
+        }
+
+        match self {
+            TypeAnnotation::Name(name) => write!(f, "{name}"),
+            TypeAnnotation::Generic { name, arguments } => {
+                write!(f, "{name}[{}]", join(arguments, ", "))
+            }
+            TypeAnnotation::Union(types) => write!(f, "{}", join(types, " | ")),
+            TypeAnnotation::Callable {
+                parameters: Some(parameters),
+                return_type,
+            } => write!(f, "Callable[[{}], {return_type}]", join(parameters, ", ")),
+            TypeAnnotation::Callable {
+                parameters: None,
+                return_type,
+            } => write!(f, "Callable[..., {return_type}]"),
+        }
+    }
 }
 
 // Implement is_lvalue method for Expression
@@ -208,3 +406,22 @@ impl Expression {
         )
     }
 }
+
+impl Pattern {
+    /// The names the pattern binds, in order
+    pub fn captures(&self) -> Vec<&str> {
+        match self {
+            Pattern::Capture(name) => vec![name.as_str()],
+            Pattern::Tuple(items)
+            | Pattern::List(items)
+            | Pattern::Class {
+                arguments: items, ..
+            } => items.iter().flat_map(Pattern::captures).collect(),
+            Pattern::Dict(entries) => entries
+                .iter()
+                .flat_map(|(_, value)| value.captures())
+                .collect(),
+            Pattern::Literal(_) | Pattern::Wildcard => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Parsing many files at once, for project builds and workspace indexing.
+
+use crate::ast::{Deprecation, Program, Span};
+use crate::error::ParseError;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::Token;
+use dashmap::DashMap;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Handle to a string stored in an [`Interner`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Thread-safe string interner shared by parallel parses.
+///
+/// The same string always maps to the same [`Symbol`], whichever thread interned it first.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: DashMap<Arc<str>, Symbol>,
+    strings: RwLock<Vec<Arc<str>>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&self, value: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(value) {
+            return *symbol;
+        }
+
+        let value: Arc<str> = Arc::from(value);
+        *self.symbols.entry(value.clone()).or_insert_with(|| {
+            let mut strings = self.strings.write().unwrap();
+            strings.push(value);
+            Symbol((strings.len() - 1) as u32)
+        })
+    }
+
+    /// Look up a string without interning it
+    pub fn get(&self, value: &str) -> Option<Symbol> {
+        self.symbols.get(value).map(|symbol| *symbol)
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> Arc<str> {
+        self.strings.read().unwrap()[symbol.index()].clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Result of reading and parsing one file with [`parse_many`]
+#[derive(Debug)]
+pub struct ParseOutcome {
+    pub path: PathBuf,
+    /// File contents; empty if the file could not be read
+    pub source: String,
+    pub program: Result<Program, ParseError>,
+    /// Distinct identifiers in the file, in order of first appearance
+    pub identifiers: Vec<Symbol>,
+    /// See [`Parser::statement_spans`]
+    pub statement_spans: Vec<Span>,
+    pub deprecations: Vec<Deprecation>,
+    pub lex_time: Duration,
+    pub parse_time: Duration,
+}
+
+/// Read and parse `paths` in parallel. Outcomes are returned in the same order as `paths`.
+pub fn parse_many<P: AsRef<Path> + Sync>(paths: &[P]) -> Vec<ParseOutcome> {
+    parse_many_with(paths, &Interner::new())
+}
+
+/// Like [`parse_many`], interning identifiers into an existing `interner`
+pub fn parse_many_with<P: AsRef<Path> + Sync>(
+    paths: &[P],
+    interner: &Interner,
+) -> Vec<ParseOutcome> {
+    paths
+        .par_iter()
+        .map(|path| parse_file(path.as_ref(), interner))
+        .collect()
+}
+
+fn parse_file(path: &Path, interner: &Interner) -> ParseOutcome {
+    let mut outcome = ParseOutcome {
+        path: path.to_path_buf(),
+        source: String::new(),
+        program: Err(ParseError::UnexpectedEof),
+        identifiers: Vec::new(),
+        statement_spans: Vec::new(),
+        deprecations: Vec::new(),
+        lex_time: Duration::ZERO,
+        parse_time: Duration::ZERO,
+    };
+
+    outcome.source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            outcome.program = Err(ParseError::Io {
+                path: path.display().to_string(),
+                message: e.to_string(),
+            });
+            return outcome;
+        }
+    };
+
+    let start = Instant::now();
+    let tokens = Lexer::new(&outcome.source).tokenize();
+    outcome.lex_time = start.elapsed();
+
+    let tokens = match tokens {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            outcome.program = Err(e);
+            return outcome;
+        }
+    };
+
+    let mut seen = HashSet::new();
+    for token in &tokens {
+        if let Token::Identifier(name) = token.token {
+            let symbol = interner.intern(name);
+            if seen.insert(symbol) {
+                outcome.identifiers.push(symbol);
+            }
+        }
+    }
+
+    let start = Instant::now();
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse_program();
+    outcome.parse_time = start.elapsed();
+    outcome.program = program;
+    outcome.statement_spans = parser.statement_spans().to_vec();
+    outcome.deprecations = parser.deprecations().to_vec();
+    outcome
+}
@@ -0,0 +1,208 @@
+//! Suppression directives carried in comments.
+//!
+//! - `# nagari:ignore [rule, ...]` suppresses lint rules on the line it ends, or on the next
+//!   line when the comment stands on its own line. With no rules, every rule is suppressed.
+//! - `# nagari:ignore-file [rule, ...]` suppresses lint rules for the whole file. With no rules
+//!   the formatter also leaves the file untouched.
+//! - `# fmt: off` / `# fmt: on` mark a region the formatter copies verbatim.
+//! - `# nagari:lazy` marks the import on its line (or the next line) as deliberately lazy, so
+//!   the project compiler leaves it out of import cycle detection.
+//!
+//! Anything after ` -- ` is a free-form reason and is ignored.
+
+use crate::lexer::Lexer;
+use crate::token::{Comment, BLOCK_COMMENT, LINE_COMMENTS};
+use std::collections::{HashMap, HashSet};
+
+const IGNORE: &str = "nagari:ignore";
+const IGNORE_FILE: &str = "nagari:ignore-file";
+const LAZY: &str = "nagari:lazy";
+
+/// Rules named by a directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleSet {
+    All,
+    Only(Vec<String>),
+}
+
+impl RuleSet {
+    fn parse(args: &str) -> Self {
+        let args = args.split(" -- ").next().unwrap_or_default();
+        let rules: Vec<String> = args
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|rule| !rule.is_empty())
+            .map(str::to_string)
+            .collect();
+        if rules.is_empty() {
+            RuleSet::All
+        } else {
+            RuleSet::Only(rules)
+        }
+    }
+
+    pub fn contains(&self, rule: &str) -> bool {
+        match self {
+            RuleSet::All => true,
+            RuleSet::Only(rules) => rules.iter().any(|r| r == rule),
+        }
+    }
+
+    fn merge(&mut self, other: RuleSet) {
+        match (&mut *self, other) {
+            (RuleSet::All, _) => {}
+            (_, RuleSet::All) => *self = RuleSet::All,
+            (RuleSet::Only(rules), RuleSet::Only(more)) => rules.extend(more),
+        }
+    }
+}
+
+/// All suppression directives found in one source file. Lines are 1-based.
+#[derive(Debug, Clone, Default)]
+pub struct Directives {
+    file: Option<RuleSet>,
+    lines: HashMap<usize, RuleSet>,
+    fmt_off: Vec<(usize, usize)>,
+    lazy: HashSet<usize>,
+}
+
+impl Directives {
+    /// Collect directives from the comments the lexer keeps as trivia.
+    ///
+    /// Files that do not lex fall back to a line-based scan, so directives still apply to
+    /// generated or half-written code.
+    pub fn from_source(source: &str) -> Self {
+        let mut lexer = Lexer::new(source);
+        match lexer.tokenize() {
+            Ok(_) => Self::from_comments(source, lexer.comments()),
+            Err(_) => Self::from_comments(source, &scan_line_comments(source)),
+        }
+    }
+
+    pub fn from_comments(source: &str, comments: &[Comment]) -> Self {
+        let mut directives = Directives::default();
+        let mut fmt_off_start = None;
+
+        for comment in comments {
+            let body = comment_body(comment.text);
+
+            if let Some(args) = body.strip_prefix(IGNORE_FILE) {
+                let rules = RuleSet::parse(args);
+                match &mut directives.file {
+                    Some(existing) => existing.merge(rules),
+                    None => directives.file = Some(rules),
+                }
+            } else if let Some(args) = body.strip_prefix(IGNORE) {
+                if !args.is_empty() && !args.starts_with(char::is_whitespace) {
+                    continue;
+                }
+                let line = if stands_alone(source, comment) {
+                    comment.line + 1
+                } else {
+                    comment.line
+                };
+                let rules = RuleSet::parse(args);
+                match directives.lines.get_mut(&line) {
+                    Some(existing) => existing.merge(rules),
+                    None => {
+                        directives.lines.insert(line, rules);
+                    }
+                }
+            } else if let Some(reason) = body.strip_prefix(LAZY) {
+                if reason.is_empty() || reason.starts_with(char::is_whitespace) {
+                    directives.lazy.insert(if stands_alone(source, comment) {
+                        comment.line + 1
+                    } else {
+                        comment.line
+                    });
+                }
+            } else if let Some(state) = body.strip_prefix("fmt:") {
+                match state.trim() {
+                    "off" if fmt_off_start.is_none() => fmt_off_start = Some(comment.line),
+                    "on" => {
+                        if let Some(start) = fmt_off_start.take() {
+                            directives.fmt_off.push((start, comment.line));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(start) = fmt_off_start {
+            directives.fmt_off.push((start, usize::MAX));
+        }
+
+        directives
+    }
+
+    /// Whether diagnostics from `rule` on `line` should be dropped
+    pub fn is_suppressed(&self, rule: &str, line: usize) -> bool {
+        self.file.as_ref().is_some_and(|rules| rules.contains(rule))
+            || self
+                .lines
+                .get(&line)
+                .is_some_and(|rules| rules.contains(rule))
+    }
+
+    /// Whether the import on `line` is marked `# nagari:lazy`
+    pub fn is_lazy(&self, line: usize) -> bool {
+        self.lazy.contains(&line)
+    }
+
+    /// Whether the whole file is excluded from linting and formatting
+    pub fn is_file_ignored(&self) -> bool {
+        self.file == Some(RuleSet::All)
+    }
+
+    /// Whether `line` lies inside a `# fmt: off` region
+    pub fn is_formatting_disabled(&self, line: usize) -> bool {
+        self.is_file_ignored()
+            || self
+                .fmt_off
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&line))
+    }
+}
+
+/// Comment text without its delimiters and surrounding whitespace
+fn comment_body(text: &str) -> &str {
+    let text = match text.strip_prefix(BLOCK_COMMENT.0) {
+        Some(rest) => rest.strip_suffix(BLOCK_COMMENT.1).unwrap_or(rest),
+        None => LINE_COMMENTS
+            .iter()
+            .find_map(|prefix| text.strip_prefix(prefix))
+            .unwrap_or(text),
+    };
+    text.trim()
+}
+
+/// Whether only whitespace precedes `comment` on its line
+fn stands_alone(source: &str, comment: &Comment) -> bool {
+    let before = &source[..comment.offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    before[line_start..].trim().is_empty()
+}
+
+/// Best-effort comment scan for sources the lexer rejects
+fn scan_line_comments(source: &str) -> Vec<Comment<'_>> {
+    let mut comments = Vec::new();
+    let mut offset = 0;
+
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let start = LINE_COMMENTS
+            .iter()
+            .filter_map(|prefix| line.find(prefix))
+            .min();
+        if let Some(start) = start {
+            comments.push(Comment {
+                text: line[start..].trim_end(),
+                line: index + 1,
+                column: line[..start].chars().count() + 1,
+                offset: offset + start,
+            });
+        }
+        offset += line.len();
+    }
+
+    comments
+}
@@ -54,4 +54,34 @@ pub enum ParseError {
 
     #[error("Unexpected end of file")]
     UnexpectedEOF,
+
+    #[error("Failed to read {path}: {message}")]
+    Io { path: String, message: String },
+
+    #[error("Source is {size} bytes, more than the limit of {max}")]
+    SourceTooLarge { size: usize, max: usize },
+
+    #[error("Nesting deeper than the limit of {max} at line {line}, column {column}")]
+    TooDeep {
+        max: usize,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl ParseError {
+    /// Line and column the error is at, when it has them
+    pub fn position(&self) -> Option<(usize, usize)> {
+        let (line, column) = match self {
+            ParseError::UnexpectedToken { line, column, .. }
+            | ParseError::InvalidCharacter { line, column, .. }
+            | ParseError::Expected { line, column, .. }
+            | ParseError::SyntaxError { line, column, .. }
+            | ParseError::TooDeep { line, column, .. } => (*line, *column),
+            ParseError::UnterminatedString { line } => (*line, 1),
+            _ => return None,
+        };
+        // Some errors are made before the position is known, with a line of 0
+        (line > 0).then_some((line, column))
+    }
 }
@@ -0,0 +1,292 @@
+//! Editor grammar generation from the lexer's token tables.
+//!
+//! `nag tooling gen-grammar` writes these files so that editor highlighting is derived from
+//! [`KEYWORDS`], [`OPERATORS`] and [`PUNCTUATION`] instead of being maintained by hand.
+
+use crate::token::{
+    Keyword, KeywordKind, Token, BLOCK_COMMENT, KEYWORDS, LINE_COMMENTS, OPERATORS, PUNCTUATION,
+    STRING_ESCAPES, STRING_QUOTES,
+};
+use serde_json::{json, Value};
+
+const HEADER: &str = "Generated by `nag tooling gen-grammar` from the Nagari lexer tables.";
+
+fn keywords_of(kind: KeywordKind) -> Vec<&'static str> {
+    KEYWORDS
+        .iter()
+        .filter(|keyword| keyword.kind == kind)
+        .map(|keyword| keyword.text)
+        .collect()
+}
+
+fn keyword_text(token: &Token) -> &'static str {
+    KEYWORDS
+        .iter()
+        .find(|keyword: &&Keyword| &keyword.token == token)
+        .map(|keyword| keyword.text)
+        .expect("keyword token missing from KEYWORDS")
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() * 2);
+    for ch in text.chars() {
+        if "\\.^$|?*+()[]{}/-".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn alternation(words: &[&str]) -> String {
+    words
+        .iter()
+        .map(|word| regex_escape(word))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn escape_class() -> String {
+    STRING_ESCAPES
+        .iter()
+        .map(|ch| regex_escape(&ch.to_string()))
+        .collect()
+}
+
+fn quote_name(quote: char) -> &'static str {
+    match quote {
+        '"' => "double",
+        '\'' => "single",
+        _ => "other",
+    }
+}
+
+/// TextMate grammar (`nagari.tmLanguage.json`), usable by VS Code, Sublime Text and others
+pub fn textmate_grammar() -> Value {
+    let keyword_scopes = [
+        (KeywordKind::Control, "keyword.control.nagari"),
+        (KeywordKind::Declaration, "storage.type.nagari"),
+        (KeywordKind::Constant, "constant.language.nagari"),
+        (KeywordKind::Variable, "variable.language.nagari"),
+    ];
+    let keyword_patterns: Vec<Value> = keyword_scopes
+        .iter()
+        .map(|(kind, scope)| {
+            json!({
+                "name": scope,
+                "match": format!("\\b(?:{})\\b", alternation(&keywords_of(*kind))),
+            })
+        })
+        .collect();
+
+    let mut comment_patterns: Vec<Value> = LINE_COMMENTS
+        .iter()
+        .map(|prefix| {
+            json!({
+                "name": "comment.line.nagari",
+                "match": format!("{}.*$", regex_escape(prefix)),
+            })
+        })
+        .collect();
+    comment_patterns.push(json!({
+        "name": "comment.block.nagari",
+        "begin": regex_escape(BLOCK_COMMENT.0),
+        "end": regex_escape(BLOCK_COMMENT.1),
+    }));
+
+    let string_patterns: Vec<Value> = STRING_QUOTES
+        .iter()
+        .map(|quote| {
+            let quote_text = regex_escape(&quote.to_string());
+            json!({
+                "name": format!("string.quoted.{}.nagari", quote_name(*quote)),
+                "begin": quote_text,
+                "end": quote_text,
+                "patterns": [{
+                    "name": "constant.character.escape.nagari",
+                    "match": format!("\\\\[{}]", escape_class()),
+                }],
+            })
+        })
+        .collect();
+
+    let operators: Vec<&str> = OPERATORS.iter().map(|(text, _)| *text).collect();
+
+    json!({
+        "$schema": "https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json",
+        "comment": HEADER,
+        "name": "Nagari",
+        "scopeName": "source.nagari",
+        "fileTypes": ["nag"],
+        "patterns": [
+            { "include": "#comments" },
+            { "include": "#strings" },
+            { "include": "#declarations" },
+            { "include": "#keywords" },
+            { "include": "#numbers" },
+            { "include": "#operators" },
+        ],
+        "repository": {
+            "comments": { "patterns": comment_patterns },
+            "strings": { "patterns": string_patterns },
+            "declarations": {
+                "patterns": [
+                    {
+                        "match": format!(
+                            "\\b({}|{})\\s+([A-Za-z_][A-Za-z0-9_]*)",
+                            keyword_text(&Token::Def),
+                            keyword_text(&Token::Function)
+                        ),
+                        "captures": {
+                            "1": { "name": "storage.type.function.nagari" },
+                            "2": { "name": "entity.name.function.nagari" },
+                        },
+                    },
+                    {
+                        "match": format!(
+                            "\\b({})\\s+([A-Za-z_][A-Za-z0-9_]*)",
+                            keyword_text(&Token::Class)
+                        ),
+                        "captures": {
+                            "1": { "name": "storage.type.class.nagari" },
+                            "2": { "name": "entity.name.type.class.nagari" },
+                        },
+                    },
+                ],
+            },
+            "keywords": { "patterns": keyword_patterns },
+            "numbers": {
+                "name": "constant.numeric.nagari",
+                "match": "\\b[0-9][0-9.]*\\b",
+            },
+            "operators": {
+                "name": "keyword.operator.nagari",
+                "match": alternation(&operators),
+            },
+        },
+    })
+}
+
+fn js_string(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn js_choice(words: &[&str]) -> String {
+    let items: Vec<String> = words.iter().map(|word| js_string(word)).collect();
+    format!("choice({})", items.join(", "))
+}
+
+/// tree-sitter `grammar.js` skeleton.
+///
+/// Token rules are complete; syntax rules are a flat token stream to be extended by hand.
+/// Indentation-sensitive blocks need an external scanner, which is not generated.
+pub fn tree_sitter_grammar() -> String {
+    let operators: Vec<&str> = OPERATORS.iter().map(|(text, _)| *text).collect();
+    let punctuation: Vec<&str> = PUNCTUATION.iter().map(|(text, _)| *text).collect();
+
+    let strings: Vec<String> = STRING_QUOTES
+        .iter()
+        .map(|quote| {
+            let quote_text = js_string(&quote.to_string());
+            format!(
+                "seq({q}, repeat(choice(/[^{raw}\\\\\\n]/, $.escape_sequence)), {q})",
+                q = quote_text,
+                raw = regex_escape(&quote.to_string()),
+            )
+        })
+        .collect();
+
+    let mut comments: Vec<String> = LINE_COMMENTS
+        .iter()
+        .map(|prefix| format!("seq({}, /.*/)", js_string(prefix)))
+        .collect();
+    comments.push(format!(
+        "seq({}, /[^*]*\\*+([^/*][^*]*\\*+)*/, '/')",
+        js_string(BLOCK_COMMENT.0)
+    ));
+
+    format!(
+        r#"// {header}
+// Token rules mirror the lexer. Extend the syntax rules below; indentation-sensitive
+// blocks (INDENT/DEDENT) need an external scanner.
+
+module.exports = grammar({{
+  name: 'nagari',
+
+  extras: $ => [/\s/, $.comment],
+
+  word: $ => $.identifier,
+
+  rules: {{
+    source_file: $ => repeat($._token),
+
+    _token: $ => choice(
+      $.keyword_control,
+      $.keyword_declaration,
+      $.constant,
+      $.language_variable,
+      $.operator,
+      $.punctuation,
+      $.string,
+      $.number,
+      $.identifier,
+    ),
+
+    keyword_control: $ => {control},
+
+    keyword_declaration: $ => {declaration},
+
+    constant: $ => {constant},
+
+    language_variable: $ => {variable},
+
+    operator: $ => {operators},
+
+    punctuation: $ => {punctuation},
+
+    string: $ => choice(
+      {strings},
+    ),
+
+    escape_sequence: $ => /\\[{escapes}]/,
+
+    number: $ => /[0-9][0-9.]*/,
+
+    identifier: $ => /[\p{{L}}_][\p{{L}}\p{{N}}_]*/,
+
+    comment: $ => token(choice(
+      {comments},
+    )),
+  }},
+}});
+"#,
+        header = HEADER,
+        control = js_choice(&keywords_of(KeywordKind::Control)),
+        declaration = js_choice(&keywords_of(KeywordKind::Declaration)),
+        constant = js_choice(&keywords_of(KeywordKind::Constant)),
+        variable = js_choice(&keywords_of(KeywordKind::Variable)),
+        operators = js_choice(&operators),
+        punctuation = js_choice(&punctuation),
+        strings = strings.join(",\n      "),
+        escapes = escape_class(),
+        comments = comments.join(",\n      "),
+    )
+}
+
+/// tree-sitter highlight queries (`queries/highlights.scm`) for [`tree_sitter_grammar`]
+pub fn tree_sitter_highlights() -> String {
+    format!(
+        "; {HEADER}\n\n\
+         (keyword_control) @keyword\n\
+         (keyword_declaration) @keyword\n\
+         (constant) @constant.builtin\n\
+         (language_variable) @variable.builtin\n\
+         (operator) @operator\n\
+         (punctuation) @punctuation\n\
+         (string) @string\n\
+         (escape_sequence) @string.escape\n\
+         (number) @number\n\
+         (comment) @comment\n\
+         (identifier) @variable\n"
+    )
+}
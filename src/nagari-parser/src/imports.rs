@@ -0,0 +1,562 @@
+//! Import organization shared by `nag format --organize-imports` and the language server.
+//!
+//! The leading block of top-level imports is rewritten so that duplicate imports are merged,
+//! unused bindings are dropped and statements are grouped by origin (standard library,
+//! packages, local paths) and sorted. A comment or any other statement ends the block.
+//!
+//! [`import_sites`] lists every top-level import with its position, for building the module
+//! graph.
+
+use crate::directives::Directives;
+use crate::lexer::Lexer;
+use crate::token::{Token, TokenWithPosition};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Modules shipped in Nagari's `stdlib/`
+pub const STDLIB_MODULES: &[&str] = &[
+    "core", "crypto", "db", "fs", "http", "json", "math", "os", "time",
+];
+
+/// Where an imported module comes from; also the order of the groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ImportOrigin {
+    Stdlib,
+    Package,
+    Local,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum ModuleSource {
+    /// `math`, `matplotlib.pyplot`
+    Bare(String),
+    /// `"react"`, `"./utils"`
+    Quoted(String),
+    /// `js("canvas")`
+    Js(String),
+}
+
+impl ModuleSource {
+    fn name(&self) -> &str {
+        match self {
+            ModuleSource::Bare(name) | ModuleSource::Quoted(name) | ModuleSource::Js(name) => name,
+        }
+    }
+
+    fn origin(&self) -> ImportOrigin {
+        let name = self.name();
+        if let ModuleSource::Js(_) = self {
+            return ImportOrigin::Package;
+        }
+        if name.starts_with('.') || name.starts_with('/') {
+            return ImportOrigin::Local;
+        }
+        let root = name.split(['/', '.']).next().unwrap_or(name);
+        if STDLIB_MODULES.contains(&root) {
+            ImportOrigin::Stdlib
+        } else {
+            ImportOrigin::Package
+        }
+    }
+}
+
+impl fmt::Display for ModuleSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModuleSource::Bare(name) => write!(f, "{}", name),
+            ModuleSource::Quoted(name) => write!(f, "\"{}\"", name),
+            ModuleSource::Js(name) => write!(f, "js(\"{}\")", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct ImportName {
+    name: String,
+    alias: Option<String>,
+}
+
+impl ImportName {
+    fn local(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+impl fmt::Display for ImportName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.alias {
+            Some(alias) => write!(f, "{} as {}", self.name, alias),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// One import statement, in the spelling it was written with
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum ImportStatement {
+    /// `import a.b as c`
+    Module {
+        source: ModuleSource,
+        alias: Option<String>,
+    },
+    /// `import "polyfill"`
+    SideEffect { source: ModuleSource },
+    /// `import React from "react"`
+    Default { source: ModuleSource, name: String },
+    /// `import { a, b as c } from "m"`
+    Named {
+        source: ModuleSource,
+        names: Vec<ImportName>,
+    },
+    /// `from m import a, b` or `from "m" import { a, b }`
+    From {
+        source: ModuleSource,
+        names: Vec<ImportName>,
+        braced: bool,
+    },
+}
+
+impl ImportStatement {
+    fn source(&self) -> &ModuleSource {
+        match self {
+            ImportStatement::Module { source, .. }
+            | ImportStatement::SideEffect { source }
+            | ImportStatement::Default { source, .. }
+            | ImportStatement::Named { source, .. }
+            | ImportStatement::From { source, .. } => source,
+        }
+    }
+
+    /// Merge `other` into `self` if both import from the same module in the same style
+    fn merge(&mut self, other: &ImportStatement) -> bool {
+        match (self, other) {
+            (
+                ImportStatement::Named { source, names },
+                ImportStatement::Named {
+                    source: other_source,
+                    names: other_names,
+                },
+            ) if source == other_source => {
+                names.extend(other_names.iter().cloned());
+                true
+            }
+            (
+                ImportStatement::From {
+                    source,
+                    names,
+                    braced,
+                },
+                ImportStatement::From {
+                    source: other_source,
+                    names: other_names,
+                    braced: other_braced,
+                },
+            ) if source == other_source && braced == other_braced => {
+                names.extend(other_names.iter().cloned());
+                true
+            }
+            (this, other) => this == other,
+        }
+    }
+
+    /// Drop unused bindings; returns `false` when nothing imported is used
+    fn retain_used(&mut self, used: &HashSet<&str>) -> bool {
+        match self {
+            ImportStatement::Module { source, alias } => {
+                let local = alias.as_deref().unwrap_or_else(|| {
+                    let name = source.name();
+                    name.split('.').next().unwrap_or(name)
+                });
+                used.contains(local)
+            }
+            ImportStatement::SideEffect { .. } => true,
+            ImportStatement::Default { name, .. } => used.contains(name.as_str()),
+            ImportStatement::Named { names, .. } | ImportStatement::From { names, .. } => {
+                names.retain(|n| n.name == "*" || used.contains(n.local()));
+                !names.is_empty()
+            }
+        }
+    }
+
+    fn normalize(&mut self) {
+        if let ImportStatement::Named { names, .. } | ImportStatement::From { names, .. } = self {
+            names.sort_by(|a, b| {
+                (a.name.to_lowercase(), &a.alias).cmp(&(b.name.to_lowercase(), &b.alias))
+            });
+            names.dedup();
+        }
+    }
+
+    fn sort_key(&self) -> (ImportOrigin, String, &ImportStatement) {
+        (
+            self.source().origin(),
+            self.source().name().to_lowercase(),
+            self,
+        )
+    }
+}
+
+fn join_names(names: &[ImportName]) -> String {
+    names
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for ImportStatement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportStatement::Module { source, alias } => match alias {
+                Some(alias) => write!(f, "import {} as {}", source, alias),
+                None => write!(f, "import {}", source),
+            },
+            ImportStatement::SideEffect { source } => write!(f, "import {}", source),
+            ImportStatement::Default { source, name } => {
+                write!(f, "import {} from {}", name, source)
+            }
+            ImportStatement::Named { source, names } => {
+                write!(f, "import {{ {} }} from {}", join_names(names), source)
+            }
+            ImportStatement::From {
+                source,
+                names,
+                braced: true,
+            } => write!(f, "from {} import {{ {} }}", source, join_names(names)),
+            ImportStatement::From {
+                source,
+                names,
+                braced: false,
+            } => write!(f, "from {} import {}", source, join_names(names)),
+        }
+    }
+}
+
+/// Replacement for the byte range `start..end` of the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Reads import statements from a token stream
+struct ImportReader<'t, 'a> {
+    tokens: &'t [TokenWithPosition<'a>],
+    position: usize,
+}
+
+impl<'t, 'a> ImportReader<'t, 'a> {
+    fn peek(&self) -> &'t Token<'a> {
+        self.tokens
+            .get(self.position)
+            .map_or(&Token::Eof, |t| &t.token)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        match self.peek() {
+            Token::Identifier(name) => {
+                self.position += 1;
+                Some(name.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Skip line breaks inside `{ ... }`
+    fn skip_layout(&mut self) {
+        while matches!(self.peek(), Token::Newline | Token::Indent | Token::Dedent) {
+            self.position += 1;
+        }
+    }
+
+    fn source(&mut self) -> Option<ModuleSource> {
+        match self.peek().clone() {
+            Token::String(name) => {
+                self.position += 1;
+                Some(ModuleSource::Quoted(name.into_owned()))
+            }
+            Token::Identifier("js")
+                if self.tokens.get(self.position + 1).map(|t| &t.token)
+                    == Some(&Token::LeftParen) =>
+            {
+                self.position += 2;
+                let Token::String(name) = self.peek().clone() else {
+                    return None;
+                };
+                self.position += 1;
+                self.eat(&Token::RightParen)
+                    .then(|| ModuleSource::Js(name.into_owned()))
+            }
+            Token::Identifier(_) => {
+                let mut path = self.identifier()?;
+                while self.eat(&Token::Dot) {
+                    path.push('.');
+                    path.push_str(&self.identifier()?);
+                }
+                Some(ModuleSource::Bare(path))
+            }
+            _ => None,
+        }
+    }
+
+    fn names(&mut self, braced: bool) -> Option<Vec<ImportName>> {
+        let mut names = Vec::new();
+        loop {
+            if braced {
+                self.skip_layout();
+            }
+            let name = if self.eat(&Token::Star) {
+                "*".to_string()
+            } else {
+                self.identifier()?
+            };
+            let alias = if self.eat(&Token::As) {
+                Some(self.identifier()?)
+            } else {
+                None
+            };
+            names.push(ImportName { name, alias });
+            if braced {
+                self.skip_layout();
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+            if braced {
+                self.skip_layout();
+                if self.peek() == &Token::RightBrace {
+                    break;
+                }
+            }
+        }
+        if braced {
+            self.skip_layout();
+            if !self.eat(&Token::RightBrace) {
+                return None;
+            }
+        }
+        Some(names)
+    }
+
+    fn statement(&mut self) -> Option<ImportStatement> {
+        let statement = if self.eat(&Token::From) {
+            let source = self.source()?;
+            if !self.eat(&Token::Import) {
+                return None;
+            }
+            let braced = self.eat(&Token::LeftBrace);
+            let names = self.names(braced)?;
+            ImportStatement::From {
+                source,
+                names,
+                braced,
+            }
+        } else if self.eat(&Token::Import) {
+            if self.eat(&Token::LeftBrace) {
+                let names = self.names(true)?;
+                if !self.eat(&Token::From) {
+                    return None;
+                }
+                ImportStatement::Named {
+                    names,
+                    source: self.source()?,
+                }
+            } else if let Token::String(_) = self.peek() {
+                ImportStatement::SideEffect {
+                    source: self.source()?,
+                }
+            } else if let (Token::Identifier(name), Some(Token::From)) = (
+                self.peek(),
+                self.tokens.get(self.position + 1).map(|t| &t.token),
+            ) {
+                let name = name.to_string();
+                self.position += 2;
+                ImportStatement::Default {
+                    name,
+                    source: self.source()?,
+                }
+            } else {
+                let source = self.source()?;
+                let alias = if self.eat(&Token::As) {
+                    Some(self.identifier()?)
+                } else {
+                    None
+                };
+                ImportStatement::Module { source, alias }
+            }
+        } else {
+            return None;
+        };
+
+        self.eat(&Token::Semicolon);
+        matches!(self.peek(), Token::Newline | Token::Eof).then_some(statement)
+    }
+}
+
+/// Compute the edit that organizes the leading import block of `source`.
+///
+/// Returns `None` when the file does not lex, has no leading imports, contains an import form
+/// this does not understand, or is already organized.
+pub fn organize_imports_edit(source: &str) -> Option<ImportEdit> {
+    let tokens = Lexer::new(source).tokenize().ok()?;
+    let mut reader = ImportReader {
+        tokens: &tokens,
+        position: 0,
+    };
+
+    let mut statements = Vec::new();
+    let mut block: Option<(usize, usize)> = None;
+    loop {
+        while reader.eat(&Token::Newline) {}
+        let Some(start) = tokens.get(reader.position) else {
+            break;
+        };
+        if !matches!(start.token, Token::Import | Token::From) {
+            break;
+        }
+        // Comments between imports end the block so they are never moved or dropped
+        if let Some((_, end)) = block {
+            if !source[end..start.offset].trim().is_empty() {
+                break;
+            }
+        }
+        let statement = reader.statement()?;
+        let end = tokens
+            .get(reader.position)
+            .filter(|t| t.token != Token::Eof)
+            .map_or(source.trim_end().len(), |t| t.offset);
+        block = Some((block.map_or(start.offset, |(start, _)| start), end));
+        statements.push(statement);
+    }
+    let (start, mut end) = block?;
+
+    let used: HashSet<&str> = tokens[reader.position..]
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index == 0 || tokens[reader.position + index - 1].token != Token::Dot)
+        .filter_map(|(_, t)| match &t.token {
+            Token::Identifier(name) => Some(*name),
+            _ => None,
+        })
+        .collect();
+
+    let mut merged: Vec<ImportStatement> = Vec::new();
+    for statement in statements {
+        if !merged.iter_mut().any(|existing| existing.merge(&statement)) {
+            merged.push(statement);
+        }
+    }
+    merged.retain_mut(|statement| statement.retain_used(&used));
+    merged.iter_mut().for_each(ImportStatement::normalize);
+    merged.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+    let mut text = String::new();
+    let mut previous_origin = None;
+    for statement in &merged {
+        let origin = statement.source().origin();
+        if previous_origin.is_some_and(|previous| previous != origin) {
+            text.push('\n');
+        }
+        if previous_origin.is_some() {
+            text.push('\n');
+        }
+        text.push_str(&statement.to_string());
+        previous_origin = Some(origin);
+    }
+
+    if text.is_empty() {
+        // Everything was unused: also drop the blank lines that followed the block
+        end = source.len() - source[end..].trim_start().len();
+    }
+
+    (source[start..end] != text).then_some(ImportEdit { start, end, text })
+}
+
+/// `source` with its leading import block organized
+pub fn organize_imports(source: &str) -> String {
+    match organize_imports_edit(source) {
+        Some(edit) => {
+            let mut organized = String::with_capacity(source.len());
+            organized.push_str(&source[..edit.start]);
+            organized.push_str(&edit.text);
+            organized.push_str(&source[edit.end..]);
+            organized
+        }
+        None => source.to_string(),
+    }
+}
+
+/// A top-level import statement and where it is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportSite {
+    /// Module as written, without quotes: `./utils`, `math.vector`
+    pub module: String,
+    pub origin: ImportOrigin,
+    /// 1-based position of the `import` or `from` keyword
+    pub line: usize,
+    pub column: usize,
+    /// Byte range of the statement, without the line break
+    pub start: usize,
+    pub end: usize,
+    /// Marked `# nagari:lazy`: the imported bindings are only used once both modules loaded
+    pub lazy: bool,
+}
+
+/// Every top-level import statement of `source`, in order. Empty when the file does not lex.
+pub fn import_sites(source: &str) -> Vec<ImportSite> {
+    let mut lexer = Lexer::new(source);
+    let Ok(tokens) = lexer.tokenize() else {
+        return Vec::new();
+    };
+    let directives = Directives::from_comments(source, lexer.comments());
+    let mut reader = ImportReader {
+        tokens: &tokens,
+        position: 0,
+    };
+
+    let mut sites = Vec::new();
+    let mut depth = 0usize;
+    let mut at_statement_start = true;
+    while let Some(start) = tokens.get(reader.position) {
+        let index = reader.position;
+        match start.token {
+            Token::Indent => depth += 1,
+            Token::Dedent => depth = depth.saturating_sub(1),
+            Token::Import | Token::From if depth == 0 && at_statement_start => {
+                if let Some(statement) = reader.statement() {
+                    let end = tokens
+                        .get(reader.position)
+                        .filter(|t| t.token != Token::Eof)
+                        .map_or(source.trim_end().len(), |t| t.offset);
+                    sites.push(ImportSite {
+                        module: statement.source().name().to_string(),
+                        origin: statement.source().origin(),
+                        line: start.line,
+                        column: start.column,
+                        start: start.offset,
+                        end: start.offset + source[start.offset..end].trim_end().len(),
+                        lazy: directives.is_lazy(start.line),
+                    });
+                    continue;
+                }
+                // Not an import form listed above; skip the keyword
+                reader.position = index;
+            }
+            _ => {}
+        }
+        at_statement_start = matches!(start.token, Token::Newline | Token::Indent | Token::Dedent);
+        reader.position += 1;
+    }
+
+    sites
+}
@@ -1,39 +1,67 @@
 use crate::error::ParseError;
-use crate::token::{Token, TokenWithPosition};
+use crate::token::{keyword_token, Comment, Token, TokenWithPosition};
+use std::borrow::Cow;
 use std::collections::VecDeque;
 
-pub struct Lexer {
-    input: String,
+/// Delimiter of inline JavaScript blocks, after the `js` prefix
+const INLINE_JS_QUOTE: &str = "\"\"\"";
+
+/// Tokenizer over a borrowed source buffer.
+///
+/// Identifiers and escape-free string literals are returned as slices of the
+/// input, so tokenizing does not allocate per token. `position` and token
+/// offsets are byte offsets into the source.
+pub struct Lexer<'a> {
+    input: &'a str,
     position: usize,
     line: usize,
     column: usize,
     indent_stack: Vec<usize>,
-    pending_tokens: VecDeque<Token>,
+    pending_tokens: VecDeque<Token<'a>>,
     at_line_start: bool,
+    comments: Vec<Comment<'a>>,
+    /// Offset, line and column where the token being scanned starts
+    token_start: (usize, usize, usize),
+}
+
+/// The `#!` line a script starts with, without its line break
+pub fn shebang(source: &str) -> Option<&str> {
+    source
+        .starts_with("#!")
+        .then(|| source.lines().next().unwrap_or_default())
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        // A shebang is for the shell, not a comment of the program; lexing starts at its line
+        // break so line numbers stay the same
+        let position = shebang(input).map_or(0, str::len);
         Self {
-            input: input.to_string(),
-            position: 0,
+            input,
+            position,
             line: 1,
-            column: 1,
+            column: position + 1,
             indent_stack: vec![0],
             pending_tokens: VecDeque::new(),
             at_line_start: true,
+            comments: Vec::new(),
+            token_start: (0, 1, 1),
         }
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<TokenWithPosition>, ParseError> {
-        let mut tokens = Vec::new();
+    /// Comments seen so far, in source order
+    pub fn comments(&self) -> &[Comment<'a>] {
+        &self.comments
+    }
 
-        while !self.is_at_end() || !self.pending_tokens.is_empty() {
-            let start_line = self.line;
-            let start_column = self.column;
-            let start_offset = self.position;
+    pub fn tokenize(&mut self) -> Result<Vec<TokenWithPosition<'a>>, ParseError> {
+        // Roughly one token per four bytes of typical source
+        let mut tokens = Vec::with_capacity(self.input.len() / 4 + 1);
 
+        while !self.is_at_end() || !self.pending_tokens.is_empty() {
+            self.token_start = (self.position, self.line, self.column);
             let token = self.next_token()?;
+            let (start_offset, start_line, start_column) = self.token_start;
 
             tokens.push(TokenWithPosition {
                 token,
@@ -64,7 +92,7 @@ impl Lexer {
         Ok(tokens)
     }
 
-    fn next_token(&mut self) -> Result<Token, ParseError> {
+    fn next_token(&mut self) -> Result<Token<'a>, ParseError> {
         // If we have pending tokens (like DEDENT), return them first
         if let Some(token) = self.pending_tokens.pop_front() {
             return Ok(token);
@@ -77,29 +105,26 @@ impl Lexer {
             // Skip completely empty lines (including comment-only lines)
             loop {
                 // Count leading whitespace WITHOUT advancing position
+                let bytes = self.input.as_bytes();
                 let mut spaces = 0;
                 let mut temp_pos = self.position;
 
-                while temp_pos < self.input.len() {
-                    let ch = self.input.chars().nth(temp_pos).unwrap();
-                    if ch == ' ' {
-                        spaces += 1;
-                        temp_pos += 1;
-                    } else if ch == '\t' {
-                        spaces += 8;
-                        temp_pos += 1;
-                    } else {
-                        break;
+                while temp_pos < bytes.len() {
+                    match bytes[temp_pos] {
+                        b' ' => spaces += 1,
+                        b'\t' => spaces += 8,
+                        _ => break,
                     }
+                    temp_pos += 1;
                 }
 
                 // Check what follows the whitespace
-                if temp_pos >= self.input.len() {
+                if temp_pos >= bytes.len() {
                     return Ok(Token::Eof);
                 }
 
-                let next_char = self.input.chars().nth(temp_pos).unwrap();
-                if next_char == '\n' || next_char == '\r' {
+                let next_char = bytes[temp_pos];
+                if next_char == b'\n' || next_char == b'\r' {
                     // This is an empty line (only whitespace + newline/carriage return) - skip it entirely
                     // But don't use skip_to_next_line() because it sets at_line_start = true
                     // Instead, manually advance past the line ending
@@ -121,13 +146,12 @@ impl Lexer {
                     }
                     // Continue to next line without processing indentation
                     continue;
-                } else if next_char == '#' {
+                } else if next_char == b'#' {
                     // This is a comment line - skip it entirely
                     // But don't use skip_to_next_line() because it sets at_line_start = true
                     self.position = temp_pos; // move to the # character
-                    while !self.is_at_end() && self.peek() != '\n' && self.peek() != '\r' {
-                        self.advance();
-                    }
+                    self.column += spaces;
+                    self.line_comment(temp_pos);
                     if !self.is_at_end() {
                         // Handle both \r\n (Windows) and \n (Unix) line endings
                         if self.peek() == '\r' {
@@ -193,6 +217,7 @@ impl Lexer {
             return Ok(Token::Eof);
         }
 
+        self.token_start = (self.position, self.line, self.column);
         let ch = self.advance();
 
         match ch {
@@ -206,8 +231,17 @@ impl Lexer {
             ',' => Ok(Token::Comma),
             ';' => Ok(Token::Semicolon),
             ':' => Ok(Token::Colon),
-            '.' => Ok(Token::Dot),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    Ok(Token::Ellipsis)
+                } else {
+                    Ok(Token::Dot)
+                }
+            }
             '?' => Ok(Token::QuestionMark),
+            '@' => Ok(Token::At),
             '+' => {
                 if self.peek() == '=' {
                     self.advance();
@@ -244,11 +278,12 @@ impl Lexer {
                     Ok(Token::DivideAssign)
                 } else if self.peek() == '/' {
                     // Line comment
+                    self.line_comment(self.position - 1);
                     self.skip_to_next_line()?;
                     self.next_token()
                 } else if self.peek() == '*' {
                     // Block comment
-                    self.skip_block_comment()?;
+                    self.skip_block_comment(self.position - 1)?;
                     self.next_token()
                 } else {
                     Ok(Token::Slash)
@@ -329,11 +364,11 @@ impl Lexer {
             }
             '#' => {
                 // Handle comments - skip to end of line
+                self.line_comment(self.position - 1);
                 self.skip_to_next_line()?;
                 self.next_token() // Get next token after comment
             }
-            '"' => self.string_literal(),
-            '\'' => self.string_literal(),
+            '"' | '\'' => self.string_literal(ch),
             _ if ch.is_ascii_digit() => self.number_literal(ch),
             _ if ch.is_alphabetic() || ch == '_' => self.identifier_or_keyword(ch),
             _ => Err(ParseError::InvalidCharacter {
@@ -344,13 +379,16 @@ impl Lexer {
         }
     }
 
-    fn string_literal(&mut self) -> Result<Token, ParseError> {
-        let quote = self.input.chars().nth(self.position - 1).unwrap();
-        let mut value = String::new();
+    fn string_literal(&mut self, quote: char) -> Result<Token<'a>, ParseError> {
+        let start = self.position;
+        // Only allocate once an escape sequence forces the value to differ from the source
+        let mut owned: Option<String> = None;
 
         while !self.is_at_end() && self.peek() != quote {
+            let ch_start = self.position;
             let ch = self.advance();
             if ch == '\\' {
+                let value = owned.get_or_insert_with(|| self.input[start..ch_start].to_string());
                 if !self.is_at_end() {
                     let escaped = self.advance();
                     match escaped {
@@ -366,7 +404,10 @@ impl Lexer {
                         }
                     }
                 }
-            } else {
+            } else if ch == '\r' && self.peek() == '\n' {
+                // A line break in the literal is `\n`, whatever the line endings of the file
+                owned.get_or_insert_with(|| self.input[start..ch_start].to_string());
+            } else if let Some(value) = owned.as_mut() {
                 value.push(ch);
             }
         }
@@ -375,65 +416,65 @@ impl Lexer {
             return Err(ParseError::UnterminatedString { line: self.line });
         }
 
+        let end = self.position;
         self.advance(); // Consume closing quote
-        Ok(Token::String(value))
+        Ok(Token::String(match owned {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&self.input[start..end]),
+        }))
+    }
+
+    /// Raw JavaScript up to the closing `"""`; nothing is escaped inside the block
+    fn inline_js(&mut self) -> Result<Token<'a>, ParseError> {
+        let line = self.line;
+        for _ in 0..INLINE_JS_QUOTE.len() {
+            self.advance();
+        }
+        let start = self.position;
+        let Some(length) = self.input[start..].find(INLINE_JS_QUOTE) else {
+            return Err(ParseError::UnterminatedString { line });
+        };
+
+        while self.position < start + length {
+            if self.advance() == '\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+        }
+        for _ in 0..INLINE_JS_QUOTE.len() {
+            self.advance();
+        }
+        Ok(Token::InlineJs(&self.input[start..start + length]))
     }
 
-    fn number_literal(&mut self, first_digit: char) -> Result<Token, ParseError> {
-        let mut value = String::new();
-        value.push(first_digit);
+    fn number_literal(&mut self, first_digit: char) -> Result<Token<'a>, ParseError> {
+        let start = self.position - first_digit.len_utf8();
 
         while !self.is_at_end() && (self.peek().is_ascii_digit() || self.peek() == '.') {
-            value.push(self.advance());
+            self.advance();
         }
 
-        value
+        let literal = &self.input[start..self.position];
+        literal
             .parse::<f64>()
             .map(Token::Number)
-            .map_err(|_| ParseError::InvalidNumber { literal: value })
+            .map_err(|_| ParseError::InvalidNumber {
+                literal: literal.to_string(),
+            })
     }
 
-    fn identifier_or_keyword(&mut self, first_char: char) -> Result<Token, ParseError> {
-        let mut value = String::new();
-        value.push(first_char);
+    fn identifier_or_keyword(&mut self, first_char: char) -> Result<Token<'a>, ParseError> {
+        let start = self.position - first_char.len_utf8();
 
         while !self.is_at_end() && (self.peek().is_alphanumeric() || self.peek() == '_') {
-            value.push(self.advance());
+            self.advance();
         }
 
-        let token = match value.as_str() {
-            "let" => Token::Let,
-            "const" => Token::Const,
-            "var" => Token::Var,
-            "function" => Token::Function,
-            "def" => Token::Def,
-            "return" => Token::Return,
-            "if" => Token::If,
-            "else" => Token::Else,
-            "while" => Token::While,
-            "for" => Token::For,
-            "break" => Token::Break,
-            "continue" => Token::Continue,
-            "true" => Token::True,
-            "false" => Token::False,
-            "null" => Token::Null,
-            "class" => Token::Class,
-            "import" => Token::Import,
-            "export" => Token::Export,
-            "from" => Token::From,
-            "as" => Token::As,
-            "async" => Token::Async,
-            "await" => Token::Await,
-            "try" => Token::Try,
-            "catch" => Token::Catch,
-            "finally" => Token::Finally,
-            "throw" => Token::Throw,
-            "new" => Token::New,
-            "this" => Token::This,
-            "super" => Token::Super,
-            "static" => Token::Static,
-            _ => Token::Identifier(value),
-        };
+        let value = &self.input[start..self.position];
+        if value == "js" && self.input[self.position..].starts_with(INLINE_JS_QUOTE) {
+            return self.inline_js();
+        }
+        let token = keyword_token(value).unwrap_or(Token::Identifier(value));
 
         Ok(token)
     }
@@ -470,13 +511,36 @@ impl Lexer {
         Ok(())
     }
 
-    fn skip_block_comment(&mut self) -> Result<(), ParseError> {
+    /// Consume a line comment starting at byte `start`, up to (not including) the line ending
+    fn line_comment(&mut self, start: usize) {
+        let line = self.line;
+        let column = self.column - (self.position - start);
+        while !self.is_at_end() && self.peek() != '\n' && self.peek() != '\r' {
+            self.advance();
+        }
+        self.comments.push(Comment {
+            text: &self.input[start..self.position],
+            line,
+            column,
+            offset: start,
+        });
+    }
+
+    fn skip_block_comment(&mut self, start: usize) -> Result<(), ParseError> {
+        let line = self.line;
+        let column = self.column - 1;
         self.advance(); // consume '*'
 
         while !self.is_at_end() {
             if self.peek() == '*' && self.peek_next() == '/' {
                 self.advance(); // consume '*'
                 self.advance(); // consume '/'
+                self.comments.push(Comment {
+                    text: &self.input[start..self.position],
+                    line,
+                    column,
+                    offset: start,
+                });
                 return Ok(());
             }
             if self.peek() == '\n' {
@@ -494,18 +558,20 @@ impl Lexer {
     }
 
     fn advance(&mut self) -> char {
-        let ch = self.input.chars().nth(self.position).unwrap_or('\0');
-        self.position += 1;
+        let ch = self.peek();
+        self.position += ch.len_utf8();
         self.column += 1;
         ch
     }
 
     fn peek(&self) -> char {
-        self.input.chars().nth(self.position).unwrap_or('\0')
+        self.input[self.position..].chars().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        self.input.chars().nth(self.position + 1).unwrap_or('\0')
+        let mut chars = self.input[self.position..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
     }
 
     fn is_at_end(&self) -> bool {
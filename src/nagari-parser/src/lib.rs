@@ -1,6 +1,11 @@
 pub mod ast;
+pub mod batch;
+pub mod directives;
 pub mod error;
+pub mod grammar;
+pub mod imports;
 pub mod lexer;
+pub mod limits;
 pub mod parser;
 pub mod token;
 
@@ -8,16 +13,48 @@ pub mod token;
 mod test_indentation;
 
 pub use ast::*;
+pub use batch::{parse_many, parse_many_with, Interner, ParseOutcome, Symbol};
+pub use directives::{Directives, RuleSet};
 pub use error::*;
+pub use imports::{
+    import_sites, organize_imports, organize_imports_edit, ImportEdit, ImportOrigin, ImportSite,
+};
 pub use lexer::*;
+pub use limits::Limits;
 pub use parser::*;
 pub use token::*;
 
 /// Parse Nagari source code into an AST
 pub fn parse(source: &str) -> Result<Program, ParseError> {
+    parse_with_limits(source, &Limits::default())
+}
+
+/// Parse Nagari source code into an AST, carrying on after syntax errors: the statements that
+/// fail to parse are left out of the program, and every error is returned. See
+/// [`Parser::parse_recovering`].
+pub fn parse_recovering(source: &str) -> (Program, Vec<ParseError>) {
+    let limits = Limits::default();
+    let tokens = match limits
+        .check_source(source)
+        .and_then(|_| Lexer::new(source).tokenize())
+    {
+        Ok(tokens) => tokens,
+        Err(error) => {
+            let program = Program {
+                statements: Vec::new(),
+            };
+            return (program, vec![error]);
+        }
+    };
+    Parser::new(tokens).with_limits(&limits).parse_recovering()
+}
+
+/// Parse Nagari source code into an AST, failing on input larger or deeper than `limits` allow
+pub fn parse_with_limits(source: &str, limits: &Limits) -> Result<Program, ParseError> {
+    limits.check_source(source)?;
     let mut lexer = Lexer::new(source);
     let tokens = lexer.tokenize()?;
-    let mut parser = Parser::new(tokens);
+    let mut parser = Parser::new(tokens).with_limits(limits);
     parser.parse_program()
 }
 
@@ -118,6 +155,7 @@ impl SemanticValidator {
                 variable,
                 iterable,
                 body,
+                ..
             } => {
                 self.validate_expression(iterable)?;
                 self.declared_variables.insert(variable.clone());
@@ -125,6 +163,31 @@ impl SemanticValidator {
                     self.validate_statement(stmt)?;
                 }
             }
+            Statement::With { items, body, .. } => {
+                for item in items {
+                    self.validate_expression(&item.context)?;
+                    if let Some(alias) = &item.alias {
+                        self.declared_variables.insert(alias.clone());
+                    }
+                }
+                for stmt in body {
+                    self.validate_statement(stmt)?;
+                }
+            }
+            Statement::Match { subject, cases } => {
+                self.validate_expression(subject)?;
+                for case in cases {
+                    for name in case.pattern.captures() {
+                        self.declared_variables.insert(name.to_string());
+                    }
+                    if let Some(guard) = &case.guard {
+                        self.validate_expression(guard)?;
+                    }
+                    for stmt in &case.body {
+                        self.validate_statement(stmt)?;
+                    }
+                }
+            }
             Statement::Return(expr) => {
                 if let Some(e) = expr {
                     self.validate_expression(e)?;
@@ -173,14 +236,21 @@ impl SemanticValidator {
             Expression::Unary { operand, .. } => {
                 self.validate_expression(operand)?;
             }
+            Expression::Await(argument) => {
+                self.validate_expression(argument)?;
+            }
             Expression::Call {
                 function,
                 arguments,
+                keywords,
             } => {
                 self.validate_expression(function)?;
                 for arg in arguments {
                     self.validate_expression(arg)?;
                 }
+                for keyword in keywords {
+                    self.validate_expression(&keyword.value)?;
+                }
             }
             Expression::Member { object, .. } => {
                 self.validate_expression(object)?;
@@ -189,7 +259,7 @@ impl SemanticValidator {
                 self.validate_expression(left)?;
                 self.validate_expression(right)?;
             }
-            Expression::Array(elements) => {
+            Expression::Array(elements) | Expression::Set(elements) => {
                 for element in elements {
                     self.validate_expression(element)?;
                 }
@@ -257,9 +327,63 @@ impl SemanticValidator {
                 self.validate_expression(object)?;
                 self.validate_expression(index)?;
             }
+            Expression::Slice {
+                object,
+                start,
+                end,
+                step,
+            } => {
+                self.validate_expression(object)?;
+                for bound in [start, end, step].into_iter().flatten() {
+                    self.validate_expression(bound)?;
+                }
+            }
+            Expression::ListComprehension {
+                element,
+                generators,
+            }
+            | Expression::SetComprehension {
+                element,
+                generators,
+            }
+            | Expression::Generator {
+                element,
+                generators,
+            } => {
+                self.validate_generators(generators)?;
+                self.validate_expression(element)?;
+            }
+            Expression::DictComprehension {
+                key,
+                value,
+                generators,
+            } => {
+                self.validate_generators(generators)?;
+                self.validate_expression(key)?;
+                self.validate_expression(value)?;
+            }
             Expression::Literal(_) => {
                 // Literals are always valid
             }
+            Expression::InlineJs(_) => {
+                // Inline JavaScript is passed through unchecked
+            }
+        }
+        Ok(())
+    }
+
+    /// Each generator's target is bound from its iterable before its conditions and the later
+    /// generators see it
+    fn validate_generators(
+        &mut self,
+        generators: &[ComprehensionGenerator],
+    ) -> Result<(), ParseError> {
+        for generator in generators {
+            self.validate_expression(&generator.iter)?;
+            self.declared_variables.insert(generator.target.clone());
+            for condition in &generator.conditions {
+                self.validate_expression(condition)?;
+            }
         }
         Ok(())
     }
@@ -372,4 +496,590 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn test_lexer_borrows_from_source() {
+        use std::borrow::Cow;
+
+        let source = "greeting = \"héllo wörld\"\nescaped = 'a\\tb'\n";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+
+        assert_eq!(tokens[0].token, Token::Identifier("greeting"));
+        assert!(matches!(
+            &tokens[2].token,
+            Token::String(Cow::Borrowed("héllo wörld"))
+        ));
+        assert!(matches!(&tokens[6].token, Token::String(Cow::Owned(s)) if s == "a\tb"));
+
+        // Offsets are byte offsets into the source
+        let escaped = &tokens[4];
+        assert_eq!(&source[escaped.offset..escaped.offset + 7], "escaped");
+        let assign = &tokens[1];
+        assert_eq!(&source[assign.offset..assign.offset + 1], "=");
+        assert_eq!(assign.column, 10);
+    }
+
+    #[test]
+    fn test_parse_many_shares_interner() {
+        let dir = std::env::temp_dir().join(format!("nagari-parse-many-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.nag");
+        let second = dir.join("second.nag");
+        std::fs::write(&first, "let total = 1;\n").unwrap();
+        std::fs::write(&second, "let count = total + 1;\n").unwrap();
+        let missing = dir.join("missing.nag");
+
+        let interner = Interner::new();
+        let outcomes = parse_many_with(&[&first, &second, &missing], &interner);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(outcomes.len(), 3);
+        assert_eq!(outcomes[0].path, first);
+        assert!(outcomes[0].program.is_ok());
+        assert!(outcomes[1].program.is_ok());
+        assert!(matches!(outcomes[2].program, Err(ParseError::Io { .. })));
+
+        let total = interner.get("total").unwrap();
+        assert_eq!(outcomes[0].identifiers, vec![total]);
+        assert!(outcomes[1].identifiers.contains(&total));
+        assert_eq!(&*interner.resolve(total), "total");
+    }
+
+    #[test]
+    fn test_token_tables_match_lexer() {
+        fn first_token(text: &str) -> Token<'_> {
+            Lexer::new(text).tokenize().unwrap()[0].token.clone()
+        }
+
+        for keyword in KEYWORDS {
+            assert_eq!(first_token(keyword.text), keyword.token, "{}", keyword.text);
+        }
+        for (text, token) in OPERATORS.iter().chain(PUNCTUATION) {
+            assert_eq!(&first_token(text), token, "{}", text);
+        }
+
+        let textmate = grammar::textmate_grammar().to_string();
+        let tree_sitter = grammar::tree_sitter_grammar();
+        for keyword in KEYWORDS {
+            assert!(textmate.contains(keyword.text), "{}", keyword.text);
+            assert!(tree_sitter.contains(&format!("'{}'", keyword.text)));
+        }
+    }
+
+    #[test]
+    fn test_suppression_directives() {
+        let source = "\
+# nagari:ignore-file line-length
+let a = 1  # nagari:ignore unused-variables, shadowing -- kept for docs
+# nagari:ignore
+let b = 2
+# fmt: off
+let   c = [1,2,3]
+# fmt: on
+let d = 4 // nagari:ignored-typo
+";
+        let mut lexer = Lexer::new(source);
+        lexer.tokenize().unwrap();
+        assert_eq!(lexer.comments().len(), 6);
+        assert_eq!(lexer.comments()[1].column, 12);
+
+        let directives = Directives::from_source(source);
+        assert!(!directives.is_file_ignored());
+        assert!(directives.is_suppressed("line-length", 9));
+        assert!(directives.is_suppressed("unused-variables", 2));
+        assert!(directives.is_suppressed("shadowing", 2));
+        assert!(!directives.is_suppressed("kept", 2));
+        assert!(directives.is_suppressed("anything", 4));
+        assert!(!directives.is_suppressed("unused-variables", 8));
+        assert!(!directives.is_formatting_disabled(4));
+        assert!(directives.is_formatting_disabled(6));
+        assert!(!directives.is_formatting_disabled(8));
+
+        // Unlexable sources still honour directives
+        let broken = Directives::from_source("let x = @\n# nagari:ignore-file\n");
+        assert!(broken.is_file_ignored());
+    }
+
+    #[test]
+    fn test_shebang_is_skipped() {
+        let source = "#!/usr/bin/env nag\n# Greets\nprint(\"hi\")\n";
+        assert_eq!(shebang(source), Some("#!/usr/bin/env nag"));
+        assert_eq!(shebang("# not a shebang\n"), None);
+
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(lexer.comments().len(), 1);
+        assert_eq!(lexer.comments()[0].line, 2);
+        let print = tokens
+            .iter()
+            .find(|token| token.token == Token::Identifier("print"))
+            .unwrap();
+        assert_eq!(print.line, 3);
+        assert!(parse(source).is_ok());
+    }
+
+    #[test]
+    fn test_organize_imports() {
+        let source = "import \"./utils\"\n\
+                      from os import path\n\
+                      import math\n\
+                      from react import useState, useEffect\n\
+                      from os import env\n\
+                      import json\n\
+                      \n\
+                      let x = math.sqrt(path.join(env, useState))\n";
+
+        let organized = organize_imports(source);
+        assert_eq!(
+            organized,
+            "import math\n\
+             from os import env, path\n\
+             \n\
+             from react import useState\n\
+             \n\
+             import \"./utils\"\n\
+             \n\
+             let x = math.sqrt(path.join(env, useState))\n"
+        );
+
+        // Already organized sources produce no edit
+        assert!(organize_imports_edit(&organized).is_none());
+    }
+
+    #[test]
+    fn test_slice_parsing() {
+        let program = parse("word[1:3]\nword[::-1]\nword[2:]\nword[0]\n").unwrap();
+        let expressions: Vec<&Expression> = program
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Expression(expression) => Some(expression),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(expressions.len(), 4);
+
+        let number = |n: f64| Some(Box::new(Expression::Literal(Literal::Number(n))));
+        let bounds = |expression: &Expression| match expression {
+            Expression::Slice {
+                start, end, step, ..
+            } => Some((start.clone(), end.clone(), step.clone())),
+            _ => None,
+        };
+        assert_eq!(
+            bounds(expressions[0]),
+            Some((number(1.0), number(3.0), None))
+        );
+        assert!(matches!(
+            bounds(expressions[1]),
+            Some((None, None, Some(step))) if matches!(*step, Expression::Unary { .. })
+        ));
+        assert_eq!(bounds(expressions[2]), Some((number(2.0), None, None)));
+        assert!(matches!(expressions[3], Expression::Index { .. }));
+    }
+
+    #[test]
+    fn test_set_literal_parsing() {
+        let program = parse("{1, 2,}\n{name: 1}\n{}\n{x}\n").unwrap();
+        let expressions: Vec<&Expression> = program
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Expression(expression) => Some(expression),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(expressions[0], Expression::Set(elements) if elements.len() == 2));
+        assert!(matches!(expressions[1], Expression::Object(properties) if properties.len() == 1));
+        // An empty pair of braces is still an empty object
+        assert!(matches!(expressions[2], Expression::Object(properties) if properties.is_empty()));
+        assert!(matches!(expressions[3], Expression::Set(elements) if elements.len() == 1));
+    }
+
+    #[test]
+    fn test_with_statement_parsing() {
+        let program = parse("with open(path) as file, lock:\n    file.read()\n").unwrap();
+        let Statement::With {
+            items,
+            body,
+            is_async,
+        } = &program.statements[0]
+        else {
+            panic!("expected a with statement");
+        };
+        assert!(!is_async);
+        assert_eq!(items.len(), 2);
+        assert!(matches!(items[0].context, Expression::Call { .. }));
+        assert_eq!(items[0].alias.as_deref(), Some("file"));
+        assert!(matches!(&items[1].context, Expression::Identifier(name) if name == "lock"));
+        assert_eq!(items[1].alias, None);
+        assert_eq!(body.len(), 1);
+    }
+
+    #[test]
+    fn test_async_statement_parsing() {
+        let source = "async def main():\n    async for page in pages():\n        show(page)\n    async with lock as held:\n        show(held)\n\nasync (x) -> x\n";
+        let program = parse(source).unwrap();
+        let Statement::Function { body, is_async, .. } = &program.statements[0] else {
+            panic!("expected a function");
+        };
+        assert!(is_async);
+        assert!(matches!(
+            &body[0],
+            Statement::For { variable, is_async: true, .. } if variable == "page"
+        ));
+        let Statement::With {
+            items, is_async, ..
+        } = &body[1]
+        else {
+            panic!("expected a with statement");
+        };
+        assert!(is_async);
+        assert_eq!(items[0].alias.as_deref(), Some("held"));
+        // Other statements starting with `async` are still expressions
+        assert!(matches!(
+            &program.statements[1],
+            Statement::Expression(Expression::Arrow { is_async: true, .. })
+        ));
+    }
+
+    #[test]
+    fn test_variadic_parameter_parsing() {
+        let program = parse("def f(a, *args, **kwargs):\n    pass\n\n(...rest) -> rest\n").unwrap();
+        let Statement::Function { parameters, .. } = &program.statements[0] else {
+            panic!("expected a function");
+        };
+        let kinds: Vec<_> = parameters
+            .iter()
+            .map(|p| (p.name.as_str(), p.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("a", ParameterKind::Positional),
+                ("args", ParameterKind::Rest),
+                ("kwargs", ParameterKind::Keywords),
+            ]
+        );
+        assert!(matches!(
+            &program.statements[1],
+            Statement::Expression(Expression::Arrow { parameters, .. })
+                if parameters[0].kind == ParameterKind::Rest
+        ));
+
+        for (source, message) in [
+            (
+                "def f(*args, b):\n    pass\n",
+                "Parameter 'b' can't follow '*args'",
+            ),
+            (
+                "def f(**kw, *args):\n    pass\n",
+                "Parameter '*args' can't follow '**kw'",
+            ),
+            (
+                "def f(*args=1):\n    pass\n",
+                "Parameter '*args' can't have a default value",
+            ),
+        ] {
+            let error = parse(source).unwrap_err().to_string();
+            assert!(error.contains(message), "{error}");
+        }
+    }
+
+    #[test]
+    fn test_match_statement_parsing() {
+        let source = "match point:\n    case (0, -1): show(0)\n    case Point(x, _) if x > 0:\n        show(x)\n    case {kind: \"circle\", r: [r]}:\n        show(r)\n    case (only,):\n        show(only)\n";
+        let program = parse(source).unwrap();
+        let Statement::Match { subject, cases } = &program.statements[0] else {
+            panic!("expected a match statement");
+        };
+        assert!(matches!(subject, Expression::Identifier(name) if name == "point"));
+        assert_eq!(cases.len(), 4);
+        assert_eq!(
+            cases[0].pattern,
+            Pattern::Tuple(vec![
+                Pattern::Literal(Literal::Number(0.0)),
+                Pattern::Literal(Literal::Number(-1.0)),
+            ])
+        );
+        assert_eq!(
+            cases[1].pattern,
+            Pattern::Class {
+                name: "Point".to_string(),
+                arguments: vec![Pattern::Capture("x".to_string()), Pattern::Wildcard],
+            }
+        );
+        assert!(matches!(cases[1].guard, Some(Expression::Binary { .. })));
+        assert_eq!(cases[1].body.len(), 1);
+        assert_eq!(cases[2].pattern.captures(), ["r"]);
+        assert_eq!(
+            cases[3].pattern,
+            Pattern::Tuple(vec![Pattern::Capture("only".to_string())])
+        );
+
+        // Elsewhere `match` is a name like any other
+        assert!(parse("match = find(text)\nmatch(1)\n").is_ok());
+    }
+
+    #[test]
+    fn test_comprehension_parsing() {
+        let source = "[x * 2 for x in xs if x > 0]\n[[x, y] for x in xs for y in ys if x if y]\n{k: v for k in ks}\n{str(k): 1 for k in ks}\n{x for x in xs}\nsum(x for x in xs)\n(x for x in xs)\n";
+        let program = parse(source).unwrap();
+        let expressions: Vec<&Expression> = program
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Expression(expression) => Some(expression),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(expressions.len(), 7);
+
+        let Expression::ListComprehension {
+            element,
+            generators,
+        } = expressions[0]
+        else {
+            panic!("expected a list comprehension");
+        };
+        assert!(matches!(element.as_ref(), Expression::Binary { .. }));
+        assert_eq!(generators.len(), 1);
+        assert_eq!(generators[0].target, "x");
+        assert!(matches!(&generators[0].iter, Expression::Identifier(name) if name == "xs"));
+        assert!(matches!(
+            generators[0].conditions[..],
+            [Expression::Binary { .. }]
+        ));
+
+        let Expression::ListComprehension { generators, .. } = expressions[1] else {
+            panic!("expected a list comprehension");
+        };
+        let shape: Vec<(&str, usize)> = generators
+            .iter()
+            .map(|generator| (generator.target.as_str(), generator.conditions.len()))
+            .collect();
+        assert_eq!(shape, [("x", 0), ("y", 2)]);
+
+        // The key of a comprehension is a variable, not a property name
+        assert!(matches!(
+            expressions[2],
+            Expression::DictComprehension { key, .. }
+                if matches!(key.as_ref(), Expression::Identifier(name) if name == "k")
+        ));
+        assert!(matches!(
+            expressions[3],
+            Expression::DictComprehension { key, .. }
+                if matches!(key.as_ref(), Expression::Call { .. })
+        ));
+        assert!(matches!(
+            expressions[4],
+            Expression::SetComprehension { .. }
+        ));
+        assert!(matches!(
+            expressions[5],
+            Expression::Call { arguments, .. }
+                if matches!(arguments[..], [Expression::Generator { .. }])
+        ));
+        assert!(matches!(expressions[6], Expression::Generator { .. }));
+    }
+
+    #[test]
+    fn test_keyword_argument_parsing() {
+        let program = parse("draw(shape, color = \"red\", **options)\n").unwrap();
+        let Statement::Expression(Expression::Call {
+            arguments,
+            keywords,
+            ..
+        }) = &program.statements[0]
+        else {
+            panic!("expected a call");
+        };
+        assert_eq!(arguments.len(), 1);
+        assert_eq!(keywords[0].name.as_deref(), Some("color"));
+        assert!(matches!(&keywords[1].value, Expression::Identifier(name) if name == "options"));
+        assert_eq!(keywords[1].name, None);
+
+        assert!(parse("draw(color = \"red\", shape)\n").is_err());
+    }
+
+    #[test]
+    fn test_await_parsing() {
+        let program = parse("value = await fetch(\"motd\") + 1\n").unwrap();
+        let Statement::Expression(Expression::Assignment { right, .. }) = &program.statements[0]
+        else {
+            panic!("expected an assignment");
+        };
+        // `await` binds tighter than binary operators
+        let Expression::Binary { left, .. } = right.as_ref() else {
+            panic!("expected a binary expression");
+        };
+        assert!(matches!(
+            left.as_ref(),
+            Expression::Await(argument) if matches!(**argument, Expression::Call { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decorator_parsing() {
+        let source = "class Box {\n    @value.setter\n    def value(self, v):\n        pass\n}\n\n@cache(32)\n@trace\nasync def load(key):\n    return key\n";
+        let program = parse(source).unwrap();
+        let Statement::Class { methods, .. } = &program.statements[0] else {
+            panic!("expected a class");
+        };
+        let Statement::Function { decorators, .. } = &methods[0] else {
+            panic!("expected a method");
+        };
+        assert_eq!(decorators[0].name, "value.setter");
+        assert!(decorators[0].arguments.is_none());
+
+        let Statement::Function {
+            decorators,
+            is_async,
+            ..
+        } = &program.statements[1]
+        else {
+            panic!("expected a function");
+        };
+        assert!(is_async);
+        let names: Vec<&str> = decorators.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["cache", "trace"]);
+        assert_eq!(decorators[0].arguments.as_ref().unwrap().len(), 1);
+
+        let program = parse("@register(\"shapes\")\nclass Circle {\n    pass\n}\n").unwrap();
+        let Statement::Class { decorators, .. } = &program.statements[0] else {
+            panic!("expected a class");
+        };
+        assert_eq!(decorators[0].name, "register");
+
+        assert!(parse("@trace\nx = 1\n").is_err());
+    }
+
+    #[test]
+    fn test_single_line_function_body() {
+        let program = parse("def twice(x): return x * 2\ntwice(21)\n").unwrap();
+        assert_eq!(program.statements.len(), 2);
+        let Statement::Function { name, body, .. } = &program.statements[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(name, "twice");
+        assert!(matches!(body.as_slice(), [Statement::Return(Some(_))]));
+    }
+
+    #[test]
+    fn test_type_annotation_parsing() {
+        let source = "def f(xs: list[int], lookup: dict[str, float], name: str | None, \
+                      on_done: Callable[[int], str], any_call: Callable[..., None]) -> list[str]:\n    pass\n";
+        let program = parse(source).unwrap();
+        let Statement::Function {
+            parameters,
+            return_type,
+            ..
+        } = &program.statements[0]
+        else {
+            panic!("expected a function");
+        };
+        let annotations: Vec<_> = parameters
+            .iter()
+            .map(|p| p.type_annotation.clone().unwrap())
+            .collect();
+        let name = |name: &str| TypeAnnotation::Name(name.to_string());
+        assert_eq!(
+            annotations[0],
+            TypeAnnotation::Generic {
+                name: "list".to_string(),
+                arguments: vec![name("int")],
+            }
+        );
+        assert_eq!(
+            annotations[2],
+            TypeAnnotation::Union(vec![name("str"), name("None")])
+        );
+        assert_eq!(
+            annotations[3],
+            TypeAnnotation::Callable {
+                parameters: Some(vec![name("int")]),
+                return_type: Box::new(name("str")),
+            }
+        );
+        let written: Vec<_> = annotations.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            written,
+            [
+                "list[int]",
+                "dict[str, float]",
+                "str | None",
+                "Callable[[int], str]",
+                "Callable[..., None]",
+            ]
+        );
+        assert_eq!(return_type.as_ref().unwrap().to_string(), "list[str]");
+
+        let error = parse("def f(x: Callable[int]):\n    pass\n").unwrap_err();
+        assert!(matches!(error, ParseError::UnexpectedToken { column: 19, .. }));
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            max_source_size: 64,
+            max_depth: 10,
+        };
+        let nested = format!("x = {}1{}\n", "(".repeat(20), ")".repeat(20));
+        assert!(matches!(
+            parse_with_limits(&nested, &limits),
+            Err(ParseError::TooDeep { max: 10, .. })
+        ));
+        let chain = format!("x = 1{}\n", " + 1".repeat(12));
+        assert!(matches!(
+            parse_with_limits(&chain, &limits),
+            Err(ParseError::TooDeep { .. })
+        ));
+        assert!(parse_with_limits("x = ((1)) + 2 * 3\n", &limits).is_ok());
+
+        let large = "x = 1\n".repeat(20);
+        assert!(matches!(
+            parse_with_limits(&large, &limits),
+            Err(ParseError::SourceTooLarge { size: 120, max: 64 })
+        ));
+        assert!(parse(&large).is_ok());
+    }
+
+    #[test]
+    fn test_statement_spans_and_deprecations() {
+        let source = "var total = 0\ndef add(n):\n    if n:\n        return n\n    return 0\n";
+        let mut parser = Parser::new(Lexer::new(source).tokenize().unwrap());
+        parser.parse_program().unwrap();
+
+        let lines: Vec<usize> = parser.statement_spans().iter().map(|s| s.line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5]);
+        assert_eq!(parser.statement_spans()[3].column, 9);
+        assert_eq!(parser.deprecations().len(), 1);
+        assert_eq!(parser.deprecations()[0].span, Span { line: 1, column: 1 });
+    }
+
+    #[test]
+    fn test_parse_recovering() {
+        let source = "x = 1\ny = (2 +\ndef broken(:\n    return 1\nz = 3\ndef ok():\n    a = )\n    return 2\n";
+        let (program, errors) = parse_recovering(source);
+
+        assert_eq!(errors.len(), 3);
+        let lines: Vec<usize> = errors
+            .iter()
+            .filter_map(|e| e.position())
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(lines, [2, 3, 7]);
+
+        // The broken def is left out with its body; the statements around it are kept
+        assert_eq!(program.statements.len(), 3);
+        assert!(matches!(
+            &program.statements[2],
+            Statement::Function { body, .. } if body.len() == 1
+        ));
+        assert!(parse(source).is_err());
+
+        let (program, errors) = parse_recovering("x = 1\n");
+        assert!(errors.is_empty());
+        assert_eq!(program.statements.len(), 1);
+    }
 }
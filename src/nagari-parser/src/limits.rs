@@ -0,0 +1,43 @@
+//! Limits on what the parser takes, so that parsing untrusted source can't exhaust memory or
+//! the stack.
+//!
+//! Depth counts statements nested in blocks, expressions nested in brackets or on the right of
+//! an assignment, and operators applied to the result of others, as in `a + b + c`: anything
+//! that makes the tree and the passes walking it deeper. [`Parser::new`](crate::Parser::new)
+//! uses the defaults, which no hand-written program comes near.
+
+use crate::error::ParseError;
+
+/// Largest input the parser takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Bytes of source
+    pub max_source_size: usize,
+    /// Nesting of statements and expressions
+    pub max_depth: usize,
+}
+
+impl Limits {
+    pub const DEFAULT_MAX_SOURCE_SIZE: usize = 8 * 1024 * 1024;
+    pub const DEFAULT_MAX_DEPTH: usize = 200;
+
+    /// Fail if `source` is larger than allowed
+    pub fn check_source(&self, source: &str) -> Result<(), ParseError> {
+        if source.len() > self.max_source_size {
+            return Err(ParseError::SourceTooLarge {
+                size: source.len(),
+                max: self.max_source_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_source_size: Self::DEFAULT_MAX_SOURCE_SIZE,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+}
@@ -2,16 +2,42 @@
 
 use crate::ast::*;
 use crate::error::ParseError;
+use crate::limits::Limits;
 use crate::token::{Token, TokenWithPosition};
 
-pub struct Parser {
-    tokens: Vec<TokenWithPosition>,
+pub struct Parser<'a> {
+    tokens: Vec<TokenWithPosition<'a>>,
     current: usize,
+    /// Statements and expressions being parsed inside each other
+    depth: usize,
+    max_depth: usize,
+    /// Where each statement starts, in the order their parsing began
+    spans: Vec<Span>,
+    deprecations: Vec<Deprecation>,
+    /// Whether a statement that fails to parse is left out instead of ending the parse
+    recover: bool,
+    /// Errors of the statements left out while recovering
+    errors: Vec<ParseError>,
 }
 
-impl Parser {
-    pub fn new(tokens: Vec<TokenWithPosition>) -> Self {
-        Self { tokens, current: 0 }
+impl<'a> Parser<'a> {
+    pub fn new(tokens: Vec<TokenWithPosition<'a>>) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            depth: 0,
+            max_depth: Limits::DEFAULT_MAX_DEPTH,
+            spans: Vec::new(),
+            deprecations: Vec::new(),
+            recover: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Fail on nesting deeper than `limits` allow; the source size is checked before lexing
+    pub fn with_limits(mut self, limits: &Limits) -> Self {
+        self.max_depth = limits.max_depth;
+        self
     }
 
     pub fn parse_program(&mut self) -> Result<Program, ParseError> {
@@ -27,7 +53,7 @@ impl Parser {
                 continue;
             }
 
-            statements.push(self.parse_statement()?);
+            self.parse_block_statement(&mut statements)?;
         }
 
         Ok(Program { statements })
@@ -37,11 +63,111 @@ impl Parser {
         self.parse_program()
     }
 
+    /// Parse the program, carrying on after syntax errors. A statement that fails to parse is
+    /// left out of the program, along with the indented block it opens, and parsing resumes
+    /// at the next statement of the same block. Returns the program and every error, in
+    /// source order.
+    pub fn parse_recovering(&mut self) -> (Program, Vec<ParseError>) {
+        self.recover = true;
+        let result = self.parse_program();
+        self.recover = false;
+
+        let mut errors = std::mem::take(&mut self.errors);
+        let program = result.unwrap_or_else(|error| {
+            errors.push(error);
+            Program {
+                statements: Vec::new(),
+            }
+        });
+        (program, errors)
+    }
+
+    /// Where the parsed statements start, each before the statements nested in it: the order
+    /// of a depth-first walk visiting statements, and the expressions holding statements, in
+    /// source order. An exported declaration shares the span of its `export` statement.
+    pub fn statement_spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Deprecated syntax in the parsed source, in source order
+    pub fn deprecations(&self) -> &[Deprecation] {
+        &self.deprecations
+    }
+
     fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        self.nested(Self::parse_statement_body)
+    }
+
+    /// Parse the next statement of a block into `statements`. While recovering, a statement
+    /// that fails is left out instead: its error is kept and parsing moves on to the next one.
+    fn parse_block_statement(&mut self, statements: &mut Vec<Statement>) -> Result<(), ParseError> {
+        let start = self.current;
+        let spans = self.spans.len();
+        let span = self.span();
+        match self.parse_statement() {
+            Ok(statement) => {
+                statements.push(statement);
+                Ok(())
+            }
+            Err(error) if self.recover => {
+                // The spans of a statement left out would not match the walk of the program
+                self.spans.truncate(spans);
+                self.errors.push(match error.position() {
+                    Some(_) => error,
+                    None => ParseError::SyntaxError {
+                        message: error.to_string(),
+                        line: span.line,
+                        column: span.column,
+                    },
+                });
+                self.synchronize(start);
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Move past a statement that started at `start` and failed to parse: to the end of the
+    /// line the error is on, and past the indented block that follows it, if any
+    fn synchronize(&mut self, start: usize) {
+        if self.current == start && !self.is_at_end() {
+            self.current += 1;
+        }
+        while let Some(token) = self.tokens.get(self.current) {
+            match token.token {
+                Token::Newline => {
+                    self.current += 1;
+                    break;
+                }
+                Token::Dedent | Token::Eof => return,
+                _ => self.current += 1,
+            }
+        }
+
+        if self.check(&Token::Indent) {
+            let mut depth = 0;
+            while let Some(token) = self.tokens.get(self.current) {
+                match token.token {
+                    Token::Indent => depth += 1,
+                    Token::Dedent => depth -= 1,
+                    Token::Eof => return,
+                    _ => {}
+                }
+                self.current += 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn parse_statement_body(&mut self) -> Result<Statement, ParseError> {
         // Skip any indentation tokens before parsing the statement
         while self.check(&Token::Indent) || self.check(&Token::Dedent) {
             let _ = self.advance();
         }
+        let span = self.span();
+        self.spans.push(span);
 
         match self.peek_token()?.map(|t| t.token.clone()) {
             Some(Token::ExportNamed) => {
@@ -59,15 +185,27 @@ impl Parser {
                 Ok(Statement::ExportDeclaration { declaration })
             }
             Some(Token::Let) => self.parse_let_statement(),
+            Some(Token::Var) => self.parse_var_statement(),
             Some(Token::Const) => self.parse_const_statement(),
             Some(Token::Import) => self.parse_import_statement(),
             Some(Token::Function) => self.parse_function_statement(),
             Some(Token::Def) => self.parse_def_statement(),
+            Some(Token::At) => self.parse_decorated_statement(),
             Some(Token::Return) => self.parse_return_statement(),
             Some(Token::If) => self.parse_if_statement(),
             Some(Token::While) => self.parse_while_statement(),
             Some(Token::For) => self.parse_for_statement(),
+            Some(Token::With) => self.parse_with_statement(),
+            Some(Token::Async) if self.next_token_is(&Token::Def) => self.parse_def_statement(),
+            Some(Token::Async) if self.next_token_is(&Token::Function) => {
+                self.parse_function_statement()
+            }
+            Some(Token::Async) if self.next_token_is(&Token::For) => self.parse_for_statement(),
+            Some(Token::Async) if self.next_token_is(&Token::With) => self.parse_with_statement(),
             Some(Token::Class) => self.parse_class_statement(),
+            Some(Token::Identifier("match")) if self.is_match_statement() => {
+                self.parse_match_statement()
+            }
             Some(Token::Identifier(_)) => {
                 // Check if this is a Python-style typed variable declaration: identifier: type = value
                 if self.is_typed_variable_declaration() {
@@ -75,18 +213,52 @@ impl Parser {
                 } else {
                     // Regular expression statement
                     let expr = self.parse_expression()?;
+                    let expr = self.parse_tuple_assignment(expr)?;
                     self.consume_statement_terminator()?;
                     Ok(Statement::Expression(expr))
                 }
             }
             _ => {
                 let expr = self.parse_expression()?;
+                let expr = self.parse_tuple_assignment(expr)?;
                 self.consume_statement_terminator()?;
                 Ok(Statement::Expression(expr))
             }
         }
     }
 
+    /// `a, b = 1, 2`: an assignment from the array of the values to the array of the targets,
+    /// if `first` is followed by a comma
+    fn parse_tuple_assignment(&mut self, first: Expression) -> Result<Expression, ParseError> {
+        if !self.check(&Token::Comma) {
+            return Ok(first);
+        }
+
+        let mut targets = vec![first];
+        while self.match_token(&Token::Comma) {
+            targets.push(self.parse_conditional()?);
+        }
+        if !targets.iter().all(Expression::is_lvalue) {
+            return Err(ParseError::InvalidAssignmentTarget);
+        }
+        self.consume(&Token::Assign, "Expected '='")?;
+
+        let mut values = vec![self.parse_expression()?];
+        while self.match_token(&Token::Comma) {
+            values.push(self.parse_expression()?);
+        }
+        let value = match values.len() {
+            1 => values.pop().unwrap(),
+            _ => Expression::Array(values),
+        };
+
+        Ok(Expression::Assignment {
+            left: Box::new(Expression::Array(targets)),
+            operator: AssignmentOperator::Assign,
+            right: Box::new(value),
+        })
+    }
+
     fn parse_let_statement(&mut self) -> Result<Statement, ParseError> {
         self.consume(&Token::Let, "Expected 'let'")?;
         let name = self.consume_identifier("Expected variable name")?;
@@ -97,6 +269,22 @@ impl Parser {
         Ok(Statement::Let { name, value })
     }
 
+    /// `var name = value`, the JavaScript spelling of `let`
+    fn parse_var_statement(&mut self) -> Result<Statement, ParseError> {
+        let span = self.span();
+        self.consume(&Token::Var, "Expected 'var'")?;
+        self.deprecations.push(Deprecation {
+            span,
+            message: "`var` declarations are deprecated; use `let`".to_string(),
+        });
+        let name = self.consume_identifier("Expected variable name")?;
+        self.consume(&Token::Assign, "Expected '='")?;
+        let value = self.parse_expression()?;
+        self.consume_statement_terminator()?;
+
+        Ok(Statement::Let { name, value })
+    }
+
     fn parse_const_statement(&mut self) -> Result<Statement, ParseError> {
         self.consume(&Token::Const, "Expected 'const'")?;
         let name = self.consume_identifier("Expected variable name")?;
@@ -122,13 +310,12 @@ impl Parser {
         let mut parameters = Vec::new();
         if !self.check(&Token::RightParen) {
             loop {
+                let kind = self.parse_parameter_kind();
                 let param_name = self.consume_identifier("Expected parameter name")?;
 
                 // Check for type annotation: param: Type
                 let type_annotation = if self.match_token(&Token::Colon) {
-                    self.skip_type_annotation()?;
-                    // For now, just store as string - we'll improve this later
-                    Some("any".to_string())
+                    Some(self.parse_type_annotation()?)
                 } else {
                     None
                 };
@@ -144,6 +331,7 @@ impl Parser {
                     name: param_name,
                     type_annotation,
                     default_value,
+                    kind,
                 });
 
                 if !self.match_token(&Token::Comma) {
@@ -152,12 +340,12 @@ impl Parser {
             }
         }
 
+        self.check_parameters(&parameters)?;
         self.consume(&Token::RightParen, "Expected ')'")?;
 
         // Check for return type annotation: -> Type
         let return_type = if self.match_token(&Token::Arrow) {
-            self.skip_type_annotation()?;
-            Some("any".to_string())
+            Some(self.parse_type_annotation()?)
         } else {
             None
         };
@@ -172,6 +360,7 @@ impl Parser {
             body,
             is_async,
             return_type,
+            decorators: Vec::new(),
         })
     }
 
@@ -190,12 +379,12 @@ impl Parser {
         let mut parameters = Vec::new();
         if !self.check(&Token::RightParen) {
             loop {
+                let kind = self.parse_parameter_kind();
                 let param_name = self.consume_identifier("Expected parameter name")?;
 
                 // Check for type annotation: param: Type
                 let type_annotation = if self.match_token(&Token::Colon) {
-                    self.skip_type_annotation()?;
-                    Some("any".to_string())
+                    Some(self.parse_type_annotation()?)
                 } else {
                     None
                 };
@@ -211,6 +400,7 @@ impl Parser {
                     name: param_name,
                     type_annotation,
                     default_value,
+                    kind,
                 });
 
                 if !self.match_token(&Token::Comma) {
@@ -219,18 +409,31 @@ impl Parser {
             }
         }
 
+        self.check_parameters(&parameters)?;
         self.consume(&Token::RightParen, "Expected ')'")?;
 
         // Check for return type annotation: -> Type
         let return_type = if self.match_token(&Token::Arrow) {
-            self.skip_type_annotation()?;
-            Some("any".to_string())
+            Some(self.parse_type_annotation()?)
         } else {
             None
         };
 
         self.consume(&Token::Colon, "Expected ':'")?;
 
+        // A single statement may follow the colon on the same line: def f(x): return x
+        if !self.check(&Token::Newline) {
+            let body = vec![self.parse_statement()?];
+            return Ok(Statement::Function {
+                name,
+                parameters,
+                body,
+                is_async,
+                return_type,
+                decorators: Vec::new(),
+            });
+        }
+
         // Expect a newline after the colon (Pythonic syntax)
         self.consume(&Token::Newline, "Expected newline after ':'")?;
 
@@ -246,7 +449,7 @@ impl Parser {
                 continue;
             }
 
-            body.push(self.parse_statement()?);
+            self.parse_block_statement(&mut body)?;
         }
 
         // Consume the DEDENT token
@@ -260,9 +463,54 @@ impl Parser {
             body,
             is_async,
             return_type,
+            decorators: Vec::new(),
         })
     }
 
+    /// `@decorator` lines followed by the `def` or `class` they apply to
+    fn parse_decorated_statement(&mut self) -> Result<Statement, ParseError> {
+        let mut decorators = Vec::new();
+        while self.match_token(&Token::At) {
+            let mut name = self.consume_identifier("Expected decorator name")?;
+            while self.match_token(&Token::Dot) {
+                name.push('.');
+                name.push_str(&self.consume_identifier("Expected attribute name")?);
+            }
+            let (arguments, keywords) = if self.match_token(&Token::LeftParen) {
+                let (arguments, keywords) = self.parse_arguments()?;
+                (Some(arguments), keywords)
+            } else {
+                (None, Vec::new())
+            };
+            decorators.push(Decorator {
+                name,
+                arguments,
+                keywords,
+            });
+            self.consume(&Token::Newline, "Expected newline after decorator")?;
+            while self.match_token(&Token::Newline) {}
+        }
+
+        let async_def = self.check(&Token::Async) && self.next_token_is(&Token::Def);
+        let mut statement = if self.check(&Token::Def) || async_def {
+            self.parse_def_statement()?
+        } else if self.check(&Token::Class) {
+            self.parse_class_statement()?
+        } else {
+            self.parse_function_statement()?
+        };
+        if let Statement::Function {
+            decorators: slot, ..
+        }
+        | Statement::Class {
+            decorators: slot, ..
+        } = &mut statement
+        {
+            *slot = decorators;
+        }
+        Ok(statement)
+    }
+
     fn parse_return_statement(&mut self) -> Result<Statement, ParseError> {
         self.consume(&Token::Return, "Expected 'return'")?;
 
@@ -307,7 +555,7 @@ impl Parser {
                     let _ = self.advance();
                     continue;
                 }
-                statements.push(self.parse_statement()?);
+                self.parse_block_statement(&mut statements)?;
             }
 
             if self.check(&Token::Dedent) {
@@ -335,7 +583,7 @@ impl Parser {
                         let _ = self.advance();
                         continue;
                     }
-                    else_statements.push(self.parse_statement()?);
+                    self.parse_block_statement(&mut else_statements)?;
                 }
 
                 if self.check(&Token::Dedent) {
@@ -389,7 +637,7 @@ impl Parser {
                     let _ = self.advance();
                     continue;
                 }
-                statements.push(self.parse_statement()?);
+                self.parse_block_statement(&mut statements)?;
             }
 
             if self.check(&Token::Dedent) {
@@ -406,7 +654,254 @@ impl Parser {
         Ok(Statement::While { condition, body })
     }
 
+    fn parse_with_statement(&mut self) -> Result<Statement, ParseError> {
+        let is_async = self.match_token(&Token::Async);
+        self.consume(&Token::With, "Expected 'with'")?;
+
+        let mut items = Vec::new();
+        loop {
+            let context = self.parse_expression()?;
+            let alias = if self.match_token(&Token::As) {
+                Some(self.consume_identifier("Expected name after 'as'")?)
+            } else {
+                None
+            };
+            items.push(WithItem { context, alias });
+            if !self.match_token(&Token::Comma) {
+                break;
+            }
+        }
+
+        // Check for syntax style: Python (:) or JavaScript ({})
+        let body = if self.check(&Token::Colon) {
+            self.consume(&Token::Colon, "Expected ':'")?;
+            self.consume(&Token::Newline, "Expected newline after ':'")?;
+            self.consume(&Token::Indent, "Expected indented block")?;
+
+            let mut statements = Vec::new();
+            while !self.check(&Token::Dedent) && !self.is_at_end() {
+                if self.check(&Token::Newline) {
+                    let _ = self.advance();
+                    continue;
+                }
+                self.parse_block_statement(&mut statements)?;
+            }
+
+            if self.check(&Token::Dedent) {
+                let _ = self.advance();
+            }
+
+            statements
+        } else {
+            self.consume(&Token::LeftBrace, "Expected '{'")?;
+            self.parse_block()?
+        };
+
+        Ok(Statement::With {
+            items,
+            body,
+            is_async,
+        })
+    }
+
+    /// Whether the `match` at the current token starts a match statement. `match` is only a
+    /// keyword there, on a line ending in `:`, so it can still name variables and functions.
+    fn is_match_statement(&self) -> bool {
+        let rest = self.tokens.get(self.current + 1..).unwrap_or_default();
+        if matches!(
+            rest.first(),
+            None | Some(TokenWithPosition {
+                token: Token::Colon,
+                ..
+            })
+        ) {
+            return false;
+        }
+        let mut depth = 0usize;
+        let mut last = None;
+        for token_with_pos in rest {
+            match token_with_pos.token {
+                Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+                Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                    depth = depth.saturating_sub(1)
+                }
+                Token::Newline | Token::Semicolon | Token::Eof if depth == 0 => break,
+                _ => {}
+            }
+            last = Some(&token_with_pos.token);
+        }
+        last == Some(&Token::Colon)
+    }
+
+    fn parse_match_statement(&mut self) -> Result<Statement, ParseError> {
+        self.advance()?; // `match`
+        let subject = self.parse_expression()?;
+        self.consume(&Token::Colon, "Expected ':'")?;
+        self.consume(&Token::Newline, "Expected newline after ':'")?;
+        self.consume(&Token::Indent, "Expected indented block")?;
+
+        let mut cases = Vec::new();
+        while !self.check(&Token::Dedent) && !self.is_at_end() {
+            if self.check(&Token::Newline) {
+                let _ = self.advance();
+                continue;
+            }
+            if !self.check(&Token::Identifier("case")) {
+                return Err(self.unexpected_token());
+            }
+            self.advance()?;
+            let pattern = self.parse_pattern()?;
+            let guard = if self.match_token(&Token::If) {
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            self.consume(&Token::Colon, "Expected ':'")?;
+            let body = self.parse_case_body()?;
+            cases.push(MatchCase {
+                pattern,
+                guard,
+                body,
+            });
+        }
+
+        if self.check(&Token::Dedent) {
+            let _ = self.advance();
+        }
+
+        Ok(Statement::Match { subject, cases })
+    }
+
+    /// The statements of a `case`, after its `:`: an indented block, or a single statement on
+    /// the same line
+    fn parse_case_body(&mut self) -> Result<Vec<Statement>, ParseError> {
+        if !self.check(&Token::Newline) {
+            return Ok(vec![self.parse_statement()?]);
+        }
+        self.consume(&Token::Newline, "Expected newline after ':'")?;
+        self.consume(&Token::Indent, "Expected indented block")?;
+
+        let mut body = Vec::new();
+        while !self.check(&Token::Dedent) && !self.is_at_end() {
+            if self.check(&Token::Newline) {
+                let _ = self.advance();
+                continue;
+            }
+            self.parse_block_statement(&mut body)?;
+        }
+
+        if self.check(&Token::Dedent) {
+            let _ = self.advance();
+        }
+
+        Ok(body)
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, ParseError> {
+        match self.peek()?.token.clone() {
+            Token::Identifier("_") => {
+                self.advance()?;
+                Ok(Pattern::Wildcard)
+            }
+            Token::Identifier(name) => {
+                let name = name.to_string();
+                self.advance()?;
+                if self.match_token(&Token::LeftParen) {
+                    let (arguments, _) = self.parse_pattern_items(&Token::RightParen)?;
+                    Ok(Pattern::Class { name, arguments })
+                } else {
+                    Ok(Pattern::Capture(name))
+                }
+            }
+            Token::LeftParen => {
+                self.advance()?;
+                let (mut items, trailing_comma) = self.parse_pattern_items(&Token::RightParen)?;
+                // `(pattern)` only groups, while `(pattern,)` is a tuple of one
+                if items.len() == 1 && !trailing_comma {
+                    Ok(items.remove(0))
+                } else {
+                    Ok(Pattern::Tuple(items))
+                }
+            }
+            Token::LeftBracket => {
+                self.advance()?;
+                let (items, _) = self.parse_pattern_items(&Token::RightBracket)?;
+                Ok(Pattern::List(items))
+            }
+            Token::LeftBrace => {
+                self.advance()?;
+                let mut entries = Vec::new();
+                while !self.check(&Token::RightBrace) {
+                    // Keys are written as in dict literals, where `{name: ...}` means "name"
+                    let key = match self.peek()?.token {
+                        Token::Identifier(name) => {
+                            self.advance()?;
+                            Literal::String(name.to_string())
+                        }
+                        _ => self.parse_literal_pattern()?,
+                    };
+                    self.consume(&Token::Colon, "Expected ':'")?;
+                    entries.push((key, self.parse_pattern()?));
+                    if !self.match_token(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.consume(&Token::RightBrace, "Expected '}'")?;
+                Ok(Pattern::Dict(entries))
+            }
+            _ => Ok(Pattern::Literal(self.parse_literal_pattern()?)),
+        }
+    }
+
+    /// Comma-separated patterns up to `close`, and whether a comma came after the last one
+    fn parse_pattern_items(&mut self, close: &Token) -> Result<(Vec<Pattern>, bool), ParseError> {
+        let mut items = Vec::new();
+        let mut trailing_comma = false;
+        while !self.check(close) {
+            items.push(self.parse_pattern()?);
+            trailing_comma = self.match_token(&Token::Comma);
+            if !trailing_comma {
+                break;
+            }
+        }
+        self.consume(close, "Expected closing bracket")?;
+        Ok((items, trailing_comma))
+    }
+
+    /// A literal a pattern compares with: a string, a number, which may be negative, a
+    /// boolean or `null`
+    fn parse_literal_pattern(&mut self) -> Result<Literal, ParseError> {
+        let negative = self.match_token(&Token::Minus);
+        let literal = match &self.peek()?.token {
+            Token::Number(n) if negative => Literal::Number(-n),
+            Token::Number(n) => Literal::Number(*n),
+            Token::String(s) | Token::StringLiteral(s) if !negative => {
+                Literal::String(s.to_string())
+            }
+            Token::True if !negative => Literal::Boolean(true),
+            Token::False if !negative => Literal::Boolean(false),
+            Token::Boolean(b) if !negative => Literal::Boolean(*b),
+            Token::Null if !negative => Literal::Null,
+            _ => return Err(self.unexpected_token()),
+        };
+        self.advance()?;
+        Ok(literal)
+    }
+
+    /// An error for the current token, which doesn't fit where it is
+    fn unexpected_token(&self) -> ParseError {
+        match self.peek_token().ok().flatten() {
+            Some(token_with_pos) => ParseError::UnexpectedToken {
+                token: format!("{:?}", token_with_pos.token),
+                line: token_with_pos.line,
+                column: token_with_pos.column,
+            },
+            None => ParseError::UnexpectedEof,
+        }
+    }
+
     fn parse_for_statement(&mut self) -> Result<Statement, ParseError> {
+        let is_async = self.match_token(&Token::Async);
         self.consume(&Token::For, "Expected 'for'")?;
 
         // Check for JavaScript-style syntax: for (variable in iterable)
@@ -421,7 +916,7 @@ impl Parser {
         // Expect 'in' keyword
         if let Ok(Some(token_with_pos)) = self.peek_token() {
             if let Token::Identifier(ident) = &token_with_pos.token {
-                if ident == "in" {
+                if *ident == "in" {
                     let _ = self.advance(); // consume 'in'
                 } else {
                     return Err(ParseError::UnexpectedToken {
@@ -467,7 +962,7 @@ impl Parser {
                     let _ = self.advance();
                     continue;
                 }
-                statements.push(self.parse_statement()?);
+                self.parse_block_statement(&mut statements)?;
             }
 
             if self.check(&Token::Dedent) {
@@ -485,6 +980,7 @@ impl Parser {
             variable,
             iterable,
             body,
+            is_async,
         })
     }
 
@@ -492,7 +988,7 @@ impl Parser {
         self.consume(&Token::Class, "Expected 'class'")?;
         let name = self.consume_identifier("Expected class name")?;
 
-        let superclass = if self.match_token(&Token::Identifier("extends".to_string())) {
+        let superclass = if self.match_token(&Token::Identifier("extends")) {
             Some(self.consume_identifier("Expected superclass name")?)
         } else {
             None
@@ -505,6 +1001,7 @@ impl Parser {
             name,
             superclass,
             methods,
+            decorators: Vec::new(),
         })
     }
 
@@ -519,7 +1016,7 @@ impl Parser {
                 let _ = self.advance();
                 continue;
             }
-            statements.push(self.parse_statement()?);
+            self.parse_block_statement(&mut statements)?;
         }
 
         self.consume(&Token::RightBrace, "Expected '}'")?;
@@ -531,6 +1028,10 @@ impl Parser {
     }
 
     fn parse_assignment(&mut self) -> Result<Expression, ParseError> {
+        self.nested(Self::parse_assignment_body)
+    }
+
+    fn parse_assignment_body(&mut self) -> Result<Expression, ParseError> {
         let expr = self.parse_conditional()?;
 
         // Check if this is an assignment expression
@@ -579,7 +1080,7 @@ impl Parser {
         if self.match_token(&Token::QuestionMark) {
             let consequent = self.parse_expression()?;
             self.consume(&Token::Colon, "Expected ':'")?;
-            let alternate = self.parse_conditional()?;
+            let alternate = self.nested(Self::parse_conditional)?;
             return Ok(Expression::Conditional {
                 test: Box::new(expr),
                 consequent: Box::new(consequent),
@@ -592,8 +1093,10 @@ impl Parser {
 
     fn parse_logical_or(&mut self) -> Result<Expression, ParseError> {
         let mut expr = self.parse_logical_and()?;
+        let mut links = 0;
 
         while self.match_token(&Token::Or) {
+            self.chain(&mut links)?;
             let right = self.parse_logical_and()?;
             expr = Expression::Binary {
                 left: Box::new(expr),
@@ -607,8 +1110,10 @@ impl Parser {
 
     fn parse_logical_and(&mut self) -> Result<Expression, ParseError> {
         let mut expr = self.parse_equality()?;
+        let mut links = 0;
 
         while self.match_token(&Token::And) {
+            self.chain(&mut links)?;
             let right = self.parse_equality()?;
             expr = Expression::Binary {
                 left: Box::new(expr),
@@ -621,6 +1126,7 @@ impl Parser {
     }
     fn parse_equality(&mut self) -> Result<Expression, ParseError> {
         let mut expr = self.parse_comparison()?;
+        let mut links = 0;
 
         while let Ok(Some(token_with_pos)) = self.peek_token() {
             match &token_with_pos.token {
@@ -630,6 +1136,7 @@ impl Parser {
                         Token::NotEqual => BinaryOperator::NotEqual,
                         _ => unreachable!(),
                     };
+                    self.chain(&mut links)?;
                     let right = self.parse_comparison()?;
                     expr = Expression::Binary {
                         left: Box::new(expr),
@@ -644,7 +1151,8 @@ impl Parser {
         Ok(expr)
     }
     fn parse_comparison(&mut self) -> Result<Expression, ParseError> {
-        let mut expr = self.parse_term()?;
+        let mut expr = self.parse_bitwise_or()?;
+        let mut links = 0;
 
         while let Ok(Some(token_with_pos)) = self.peek_token() {
             match &token_with_pos.token {
@@ -656,6 +1164,84 @@ impl Parser {
                         Token::LessEqual => BinaryOperator::LessEqual,
                         _ => unreachable!(),
                     };
+                    self.chain(&mut links)?;
+                    let right = self.parse_bitwise_or()?;
+                    expr = Expression::Binary {
+                        left: Box::new(expr),
+                        operator,
+                        right: Box::new(right),
+                    };
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+    fn parse_bitwise_or(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.parse_bitwise_xor()?;
+        let mut links = 0;
+
+        while self.match_token(&Token::BitwiseOr) {
+            self.chain(&mut links)?;
+            let right = self.parse_bitwise_xor()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: BinaryOperator::BitwiseOr,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_bitwise_xor(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.parse_bitwise_and()?;
+        let mut links = 0;
+
+        while self.match_token(&Token::BitwiseXor) {
+            self.chain(&mut links)?;
+            let right = self.parse_bitwise_and()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: BinaryOperator::BitwiseXor,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_bitwise_and(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.parse_shift()?;
+        let mut links = 0;
+
+        while self.match_token(&Token::BitwiseAnd) {
+            self.chain(&mut links)?;
+            let right = self.parse_shift()?;
+            expr = Expression::Binary {
+                left: Box::new(expr),
+                operator: BinaryOperator::BitwiseAnd,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_shift(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.parse_term()?;
+        let mut links = 0;
+
+        while let Ok(Some(token_with_pos)) = self.peek_token() {
+            match &token_with_pos.token {
+                Token::LeftShift | Token::RightShift => {
+                    let operator = match &self.advance()?.token {
+                        Token::LeftShift => BinaryOperator::LeftShift,
+                        Token::RightShift => BinaryOperator::RightShift,
+                        _ => unreachable!(),
+                    };
+                    self.chain(&mut links)?;
                     let right = self.parse_term()?;
                     expr = Expression::Binary {
                         left: Box::new(expr),
@@ -669,8 +1255,10 @@ impl Parser {
 
         Ok(expr)
     }
+
     fn parse_term(&mut self) -> Result<Expression, ParseError> {
         let mut expr = self.parse_factor()?;
+        let mut links = 0;
 
         while let Ok(Some(token_with_pos)) = self.peek_token() {
             match &token_with_pos.token {
@@ -680,6 +1268,7 @@ impl Parser {
                         Token::Plus => BinaryOperator::Add,
                         _ => unreachable!(),
                     };
+                    self.chain(&mut links)?;
                     let right = self.parse_factor()?;
                     expr = Expression::Binary {
                         left: Box::new(expr),
@@ -696,6 +1285,7 @@ impl Parser {
 
     fn parse_factor(&mut self) -> Result<Expression, ParseError> {
         let mut expr = self.parse_unary()?;
+        let mut links = 0;
 
         while let Ok(Some(token_with_pos)) = self.peek_token() {
             match &token_with_pos.token {
@@ -706,6 +1296,7 @@ impl Parser {
                         Token::Percent => BinaryOperator::Modulo,
                         _ => unreachable!(),
                     };
+                    self.chain(&mut links)?;
                     let right = self.parse_unary()?;
                     expr = Expression::Binary {
                         left: Box::new(expr),
@@ -723,27 +1314,49 @@ impl Parser {
     fn parse_unary(&mut self) -> Result<Expression, ParseError> {
         if let Ok(Some(token_with_pos)) = self.peek_token() {
             match &token_with_pos.token {
-                Token::Not | Token::Minus | Token::Plus => {
+                Token::Not | Token::Minus | Token::Plus | Token::BitwiseNot => {
                     let operator = match &self.advance()?.token {
                         Token::Not => UnaryOperator::Not,
                         Token::Minus => UnaryOperator::Minus,
                         Token::Plus => UnaryOperator::Plus,
+                        Token::BitwiseNot => UnaryOperator::BitwiseNot,
                         _ => unreachable!(),
                     };
-                    let right = self.parse_unary()?;
+                    let right = self.nested(Self::parse_unary)?;
                     return Ok(Expression::Unary {
                         operator,
                         operand: Box::new(right),
                     });
                 }
+                Token::Await => {
+                    self.advance()?;
+                    let argument = self.nested(Self::parse_unary)?;
+                    return Ok(Expression::Await(Box::new(argument)));
+                }
                 _ => {}
             }
         }
-        self.parse_call()
+        self.parse_power()
+    }
+
+    /// `**` binds tighter than a unary operator on its left (`-2 ** 2` is -4) but not on its
+    /// right (`2 ** -1`), and groups from the right
+    fn parse_power(&mut self) -> Result<Expression, ParseError> {
+        let base = self.parse_call()?;
+        if self.match_token(&Token::Power) {
+            let exponent = self.nested(Self::parse_unary)?;
+            return Ok(Expression::Binary {
+                left: Box::new(base),
+                operator: BinaryOperator::Power,
+                right: Box::new(exponent),
+            });
+        }
+        Ok(base)
     }
 
     fn parse_call(&mut self) -> Result<Expression, ParseError> {
         let mut expr = self.parse_primary()?;
+        let mut links = 0;
 
         loop {
             if self.match_token(&Token::LeftParen) {
@@ -756,26 +1369,104 @@ impl Parser {
                     computed: false,
                 };
             } else if self.match_token(&Token::LeftBracket) {
-                let index = self.parse_expression()?;
-                self.consume(&Token::RightBracket, "Expected ']'")?;
-                expr = Expression::Index {
-                    object: Box::new(expr),
-                    index: Box::new(index),
+                let start = if self.check(&Token::Colon) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expression()?))
                 };
+
+                if self.match_token(&Token::Colon) {
+                    // Slice: obj[start:end] or obj[start:end:step], any part optional
+                    let end = self.parse_slice_bound()?;
+                    let step = if self.match_token(&Token::Colon) {
+                        self.parse_slice_bound()?
+                    } else {
+                        None
+                    };
+                    self.consume(&Token::RightBracket, "Expected ']'")?;
+                    expr = Expression::Slice {
+                        object: Box::new(expr),
+                        start,
+                        end,
+                        step,
+                    };
+                } else {
+                    self.consume(&Token::RightBracket, "Expected ']'")?;
+                    expr = Expression::Index {
+                        object: Box::new(expr),
+                        // `start` is only absent when a colon follows
+                        index: start.expect("index expression"),
+                    };
+                }
             } else {
                 break;
             }
+            self.chain(&mut links)?;
         }
 
         Ok(expr)
     }
 
+    /// Optional slice bound, absent when the next token ends it
+    fn parse_slice_bound(&mut self) -> Result<Option<Box<Expression>>, ParseError> {
+        if self.check(&Token::Colon) || self.check(&Token::RightBracket) {
+            Ok(None)
+        } else {
+            Ok(Some(Box::new(self.parse_expression()?)))
+        }
+    }
+
+    /// Arguments of a call after its `(`: positional ones first, then `name=value` and
+    /// `**mapping` ones
     fn finish_call(&mut self, callee: Expression) -> Result<Expression, ParseError> {
+        let (arguments, keywords) = self.parse_arguments()?;
+        Ok(Expression::Call {
+            function: Box::new(callee),
+            arguments,
+            keywords,
+        })
+    }
+
+    /// The arguments of a call after its `(`, up to and including the `)`
+    fn parse_arguments(&mut self) -> Result<(Vec<Expression>, Vec<KeywordArgument>), ParseError> {
         let mut arguments = Vec::new();
+        let mut keywords = Vec::new();
 
         if !self.check(&Token::RightParen) {
             loop {
-                arguments.push(self.parse_expression()?);
+                if self.match_token(&Token::Power) {
+                    keywords.push(KeywordArgument {
+                        name: None,
+                        value: self.parse_expression()?,
+                    });
+                } else if let Some(name) = self.keyword_argument_name() {
+                    // The name and `=`
+                    self.current += 2;
+                    keywords.push(KeywordArgument {
+                        name: Some(name),
+                        value: self.parse_expression()?,
+                    });
+                } else if keywords.is_empty() {
+                    let argument = self.parse_expression()?;
+                    if arguments.is_empty() && self.check(&Token::For) {
+                        // `sum(x for x in xs)` needs no parentheses of its own
+                        arguments.push(Expression::Generator {
+                            element: Box::new(argument),
+                            generators: self.parse_comprehension_generators()?,
+                        });
+                    } else {
+                        arguments.push(argument);
+                    }
+                } else {
+                    let (line, column) = self
+                        .peek_token()?
+                        .map_or((0, 0), |token| (token.line, token.column));
+                    return Err(ParseError::SyntaxError {
+                        message: "Positional argument follows keyword argument".to_string(),
+                        line,
+                        column,
+                    });
+                }
                 if !self.match_token(&Token::Comma) {
                     break;
                 }
@@ -783,11 +1474,21 @@ impl Parser {
         }
 
         self.consume(&Token::RightParen, "Expected ')'")?;
+        Ok((arguments, keywords))
+    }
 
-        Ok(Expression::Call {
-            function: Box::new(callee),
-            arguments,
-        })
+    /// Name of the keyword argument starting at the current token, if it is one
+    fn keyword_argument_name(&self) -> Option<String> {
+        match (
+            self.tokens.get(self.current),
+            self.tokens.get(self.current + 1),
+        ) {
+            (Some(name), Some(assign)) if assign.token == Token::Assign => match &name.token {
+                Token::Identifier(name) => Some(name.to_string()),
+                _ => None,
+            },
+            _ => None,
+        }
     }
 
     fn parse_primary(&mut self) -> Result<Expression, ParseError> {
@@ -811,22 +1512,27 @@ impl Parser {
                     Ok(Expression::Literal(Literal::Number(value)))
                 }
                 Token::String(s) => {
-                    let value = s.clone();
+                    let value = s.to_string();
                     self.advance()?;
                     Ok(Expression::Literal(Literal::String(value)))
                 }
                 Token::StringLiteral(s) => {
-                    let value = s.clone();
+                    let value = s.to_string();
                     self.advance()?;
                     Ok(Expression::Literal(Literal::String(value)))
                 }
-                Token::TemplateStart(s) => self.parse_template_literal(s.clone()),
+                Token::TemplateStart(s) => self.parse_template_literal(s.to_string()),
+                Token::InlineJs(code) => {
+                    let code = code.to_string();
+                    self.advance()?;
+                    Ok(Expression::InlineJs(code))
+                }
                 Token::Async => {
                     // Check if this is an async arrow function
                     self.parse_async_arrow_function()
                 }
                 Token::Identifier(name) => {
-                    let name = name.clone();
+                    let name = name.to_string();
                     self.advance()?;
                     // Check if this is an arrow function
                     if self.check(&Token::Arrow) {
@@ -842,6 +1548,7 @@ impl Parser {
                                 name,
                                 type_annotation: None,
                                 default_value: None,
+                                kind: ParameterKind::Positional,
                             }],
                             body,
                             is_async: false,
@@ -857,6 +1564,11 @@ impl Parser {
                 }
                 Token::LeftBracket => self.parse_array_literal(),
                 Token::LeftBrace => self.parse_object_literal(),
+                Token::Super => {
+                    // `super().method(...)` inside a method
+                    self.advance()?;
+                    Ok(Expression::Identifier("super".to_string()))
+                }
                 _ => Err(ParseError::UnexpectedToken {
                     token: format!("{:?}", token_with_pos.token),
                     line: token_with_pos.line,
@@ -873,11 +1585,18 @@ impl Parser {
         let mut elements = Vec::new();
 
         if !self.check(&Token::RightBracket) {
-            loop {
+            let element = self.parse_expression()?;
+            if self.check(&Token::For) {
+                let generators = self.parse_comprehension_generators()?;
+                self.consume(&Token::RightBracket, "Expected ']'")?;
+                return Ok(Expression::ListComprehension {
+                    element: Box::new(element),
+                    generators,
+                });
+            }
+            elements.push(element);
+            while self.match_token(&Token::Comma) {
                 elements.push(self.parse_expression()?);
-                if !self.match_token(&Token::Comma) {
-                    break;
-                }
             }
         }
 
@@ -887,6 +1606,14 @@ impl Parser {
 
     fn parse_object_literal(&mut self) -> Result<Expression, ParseError> {
         self.consume(&Token::LeftBrace, "Expected '{'")?;
+        // `{a, b}` is a set, while `{}` is an empty object as in Python
+        let starts_property = matches!(
+            self.tokens.get(self.current + 1),
+            Some(next) if next.token == Token::Colon
+        );
+        if !self.check(&Token::RightBrace) && !starts_property {
+            return self.parse_set_elements();
+        }
         let mut properties = Vec::new();
 
         if !self.check(&Token::RightBrace) {
@@ -894,6 +1621,10 @@ impl Parser {
                 let key = self.consume_identifier("Expected property name")?;
                 self.consume(&Token::Colon, "Expected ':'")?;
                 let value = self.parse_expression()?;
+                if properties.is_empty() && self.check(&Token::For) {
+                    // The key of a comprehension is a variable rather than a name
+                    return self.finish_dict_comprehension(Expression::Identifier(key), value);
+                }
                 properties.push(ObjectProperty { key, value });
 
                 if !self.match_token(&Token::Comma) {
@@ -906,11 +1637,131 @@ impl Parser {
         Ok(Expression::Object(properties))
     }
 
+    /// The elements of a set literal after its `{`, or a comprehension
+    fn parse_set_elements(&mut self) -> Result<Expression, ParseError> {
+        let first = self.parse_expression()?;
+        if self.match_token(&Token::Colon) {
+            // `{key(k): v for k in ks}`, whose key isn't a name
+            let value = self.parse_expression()?;
+            if !self.check(&Token::For) {
+                return Err(self.unexpected_token());
+            }
+            return self.finish_dict_comprehension(first, value);
+        }
+        if self.check(&Token::For) {
+            let generators = self.parse_comprehension_generators()?;
+            self.consume(&Token::RightBrace, "Expected '}'")?;
+            return Ok(Expression::SetComprehension {
+                element: Box::new(first),
+                generators,
+            });
+        }
+
+        let mut elements = vec![first];
+        while self.match_token(&Token::Comma) && !self.check(&Token::RightBrace) {
+            elements.push(self.parse_expression()?);
+        }
+
+        self.consume(&Token::RightBrace, "Expected '}'")?;
+        Ok(Expression::Set(elements))
+    }
+
+    /// The generators and `}` of a dict comprehension after its `key: value`
+    fn finish_dict_comprehension(
+        &mut self,
+        key: Expression,
+        value: Expression,
+    ) -> Result<Expression, ParseError> {
+        let generators = self.parse_comprehension_generators()?;
+        self.consume(&Token::RightBrace, "Expected '}'")?;
+        Ok(Expression::DictComprehension {
+            key: Box::new(key),
+            value: Box::new(value),
+            generators,
+        })
+    }
+
+    /// The `for target in iter` clauses of a comprehension after its element, each followed by
+    /// any number of `if condition`s
+    fn parse_comprehension_generators(
+        &mut self,
+    ) -> Result<Vec<ComprehensionGenerator>, ParseError> {
+        let mut generators = Vec::new();
+        while self.match_token(&Token::For) {
+            let target = self.consume_identifier("Expected variable name")?;
+            if !self.match_token(&Token::Identifier("in")) {
+                return Err(self.unexpected_token());
+            }
+            // `if` and `for` end these rather than starting a conditional
+            let iter = self.parse_logical_or()?;
+            let mut conditions = Vec::new();
+            while self.match_token(&Token::If) {
+                conditions.push(self.parse_logical_or()?);
+            }
+            generators.push(ComprehensionGenerator {
+                target,
+                iter,
+                conditions,
+            });
+        }
+        Ok(generators)
+    }
+
     // Implement missing methods and correct field access
     fn is_at_end(&self) -> bool {
         self.current >= self.tokens.len()
     }
 
+    /// Whether the token after the current one is `expected`
+    /// Run `parse` a level deeper, failing past the maximum depth
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth >= self.max_depth {
+            return Err(self.too_deep());
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Count one more operator applied to the result of those before it, which nests the tree
+    /// as deeply as a bracket would
+    fn chain(&self, links: &mut usize) -> Result<(), ParseError> {
+        *links += 1;
+        if self.depth + *links > self.max_depth {
+            return Err(self.too_deep());
+        }
+        Ok(())
+    }
+
+    fn too_deep(&self) -> ParseError {
+        let Span { line, column } = self.span();
+        ParseError::TooDeep {
+            max: self.max_depth,
+            line,
+            column,
+        }
+    }
+
+    /// Where the current token starts
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.current)
+            .map_or(Span::default(), |token| Span {
+                line: token.line,
+                column: token.column,
+            })
+    }
+
+    fn next_token_is(&self, expected: &Token) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|next| &next.token == expected)
+    }
+
     fn check(&self, expected: &Token) -> bool {
         if let Some(token_with_pos) = self.peek_token().ok().flatten() {
             &token_with_pos.token == expected
@@ -919,11 +1770,11 @@ impl Parser {
         }
     }
 
-    fn consume_token(&mut self) -> Result<&TokenWithPosition, ParseError> {
+    fn consume_token(&mut self) -> Result<&TokenWithPosition<'a>, ParseError> {
         self.advance()
     }
 
-    fn peek(&self) -> Result<&TokenWithPosition, ParseError> {
+    fn peek(&self) -> Result<&TokenWithPosition<'a>, ParseError> {
         self.peek_token()?.ok_or(ParseError::UnexpectedEof)
     }
 
@@ -1047,7 +1898,7 @@ impl Parser {
         })
     }
 
-    fn advance(&mut self) -> Result<&TokenWithPosition, ParseError> {
+    fn advance(&mut self) -> Result<&TokenWithPosition<'a>, ParseError> {
         if self.is_at_end() {
             Err(ParseError::UnexpectedEof)
         } else {
@@ -1098,9 +1949,9 @@ impl Parser {
     fn consume_identifier(&mut self, _error_message: &str) -> Result<String, ParseError> {
         if let Some(token_with_pos) = self.peek_token().ok().flatten() {
             if let Token::Identifier(name) = &token_with_pos.token {
-                let name_clone = name.clone();
+                let name = name.to_string();
                 self.advance().ok();
-                return Ok(name_clone);
+                return Ok(name);
             }
         }
         Err(ParseError::UnexpectedToken {
@@ -1127,7 +1978,7 @@ impl Parser {
         })
     }
 
-    fn peek_token(&self) -> Result<Option<&TokenWithPosition>, ParseError> {
+    fn peek_token(&self) -> Result<Option<&TokenWithPosition<'a>>, ParseError> {
         if self.is_at_end() {
             Ok(None)
         } else {
@@ -1186,33 +2037,63 @@ impl Parser {
         Ok(Statement::Import { source, items })
     }
 
-    fn skip_type_annotation(&mut self) -> Result<(), ParseError> {
-        // Skip a type annotation by consuming tokens until we hit a delimiter
-        // This handles: str, int, list[dict], dict[str, int], etc.
+    /// `int`, `list[int]`, `dict[str, float]`, `int | None` or `Callable[[int], str]`
+    fn parse_type_annotation(&mut self) -> Result<TypeAnnotation, ParseError> {
+        let mut alternatives = vec![self.parse_type_term()?];
+        while self.match_token(&Token::BitwiseOr) {
+            alternatives.push(self.parse_type_term()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            TypeAnnotation::Union(alternatives)
+        })
+    }
 
-        self.consume_identifier("Expected type name")?;
+    /// A type without `|`
+    fn parse_type_term(&mut self) -> Result<TypeAnnotation, ParseError> {
+        if self.match_token(&Token::Null) {
+            return Ok(TypeAnnotation::Name("None".to_string()));
+        }
+        let mut name = self.consume_identifier("Expected type name")?;
+        while self.match_token(&Token::Dot) {
+            name.push('.');
+            name.push_str(&self.consume_identifier("Expected type name")?);
+        }
+        if !self.match_token(&Token::LeftBracket) {
+            return Ok(TypeAnnotation::Name(name));
+        }
 
-        // Handle generic types like list[dict] or dict[str, int]
-        if self.match_token(&Token::LeftBracket) {
-            let mut bracket_depth = 1;
-            while bracket_depth > 0 && !self.is_at_end() {
-                match self.peek_token()?.map(|t| &t.token) {
-                    Some(Token::LeftBracket) => {
-                        bracket_depth += 1;
-                        let _ = self.advance();
-                    }
-                    Some(Token::RightBracket) => {
-                        bracket_depth -= 1;
-                        let _ = self.advance();
-                    }
-                    _ => {
-                        let _ = self.advance();
-                    }
-                }
-            }
+        if name == "Callable" {
+            let parameters = if self.match_token(&Token::Ellipsis) {
+                None
+            } else {
+                self.consume(&Token::LeftBracket, "Expected '[' or '...'")?;
+                Some(self.parse_type_arguments()?)
+            };
+            self.consume(&Token::Comma, "Expected ','")?;
+            let return_type = self.nested(Self::parse_type_annotation)?;
+            self.consume(&Token::RightBracket, "Expected ']'")?;
+            return Ok(TypeAnnotation::Callable {
+                parameters,
+                return_type: Box::new(return_type),
+            });
         }
+        let arguments = self.parse_type_arguments()?;
+        Ok(TypeAnnotation::Generic { name, arguments })
+    }
 
-        Ok(())
+    /// Types separated by commas, up to and including the closing `]`
+    fn parse_type_arguments(&mut self) -> Result<Vec<TypeAnnotation>, ParseError> {
+        let mut arguments = Vec::new();
+        while !self.check(&Token::RightBracket) {
+            arguments.push(self.nested(Self::parse_type_annotation)?);
+            if !self.match_token(&Token::Comma) {
+                break;
+            }
+        }
+        self.consume(&Token::RightBracket, "Expected ']'")?;
+        Ok(arguments)
     }
 
     fn parse_template_literal(&mut self, start: String) -> Result<Expression, ParseError> {
@@ -1231,11 +2112,11 @@ impl Parser {
                 match token_with_pos.token {
                     Token::TemplateMiddle(s) => {
                         self.advance()?;
-                        parts.push(s);
+                        parts.push(s.into_owned());
                     }
                     Token::TemplateEnd(s) => {
                         self.advance()?;
-                        parts.push(s);
+                        parts.push(s.into_owned());
                         break;
                     }
                     _ => {
@@ -1255,12 +2136,12 @@ impl Parser {
     fn consume_string_literal(&mut self) -> Result<String, ParseError> {
         match self.peek_token()?.map(|t| &t.token) {
             Some(Token::String(s)) => {
-                let result = s.clone();
+                let result = s.to_string();
                 let _ = self.advance();
                 Ok(result)
             }
             Some(Token::StringLiteral(s)) => {
-                let result = s.clone();
+                let result = s.to_string();
                 let _ = self.advance();
                 Ok(result)
             }
@@ -1291,8 +2172,8 @@ impl Parser {
         // Consume colon
         self.consume(&Token::Colon, "Expected ':'")?;
 
-        // Skip type annotation (we'll parse it but not use it for now)
-        self.skip_type_annotation()?;
+        // Parse the type annotation (not used for now)
+        self.parse_type_annotation()?;
 
         // Expect assignment
         self.consume(&Token::Assign, "Expected '='")?;
@@ -1319,9 +2200,10 @@ impl Parser {
             let mut parameters = Vec::new();
             if !self.check(&Token::RightParen) {
                 loop {
+                    let kind = self.parse_parameter_kind();
                     let name = self.consume_identifier("Expected parameter name")?;
                     let type_annotation = if self.match_token(&Token::Colon) {
-                        Some(self.consume_identifier("Expected type annotation")?)
+                        Some(self.parse_type_annotation()?)
                     } else {
                         None
                     };
@@ -1336,6 +2218,7 @@ impl Parser {
                         name,
                         type_annotation,
                         default_value,
+                        kind,
                     });
 
                     if !self.match_token(&Token::Comma) {
@@ -1344,6 +2227,7 @@ impl Parser {
                 }
             }
 
+            self.check_parameters(&parameters)?;
             self.consume(&Token::RightParen, "Expected ')'")?;
             self.consume(&Token::Arrow, "Expected '=>'")?;
 
@@ -1381,6 +2265,7 @@ impl Parser {
                     name,
                     type_annotation: None,
                     default_value: None,
+                    kind: ParameterKind::Positional,
                 }],
                 body,
                 is_async: true,
@@ -1425,9 +2310,23 @@ impl Parser {
             }
         }
 
+        // `(*args) ->` and `(...args) ->` can only start the parameters of an arrow function
+        if self.check(&Token::Star) || self.check(&Token::Power) || self.check(&Token::Ellipsis) {
+            return self.parse_arrow_function_after(Vec::new());
+        }
+
         // Parse first element (could be parameter or expression)
         let first_expr = self.parse_expression()?;
 
+        if self.check(&Token::For) {
+            let generators = self.parse_comprehension_generators()?;
+            self.consume(&Token::RightParen, "Expected ')'")?;
+            return Ok(Expression::Generator {
+                element: Box::new(first_expr),
+                generators,
+            });
+        }
+
         // Check if we have a comma (indicating multiple parameters)
         if self.match_token(&Token::Comma) {
             // This is definitely arrow function parameters
@@ -1439,6 +2338,7 @@ impl Parser {
                     name,
                     type_annotation: None,
                     default_value: None,
+                    kind: ParameterKind::Positional,
                 });
             } else {
                 return Err(ParseError::SyntaxError {
@@ -1448,52 +2348,7 @@ impl Parser {
                 });
             }
 
-            // Parse remaining parameters
-            if !self.check(&Token::RightParen) {
-                loop {
-                    let name = self.consume_identifier("Expected parameter name")?;
-                    let type_annotation = if self.match_token(&Token::Colon) {
-                        Some(self.consume_identifier("Expected type annotation")?)
-                    } else {
-                        None
-                    };
-
-                    let default_value = if self.match_token(&Token::Assign) {
-                        Some(self.parse_assignment()?)
-                    } else {
-                        None
-                    };
-
-                    parameters.push(FunctionParameter {
-                        name,
-                        type_annotation,
-                        default_value,
-                    });
-
-                    if !self.match_token(&Token::Comma) {
-                        break;
-                    }
-                }
-            }
-
-            self.consume(&Token::RightParen, "Expected ')'")?;
-            self.consume(&Token::Arrow, "Expected '=>'")?;
-
-            // Parse body - could be an expression or a block
-            let body = if self.check(&Token::LeftBrace) {
-                // Block body: { statements... }
-                ArrowFunctionBody::Block(self.parse_arrow_function_block_body()?)
-            } else {
-                // Expression body: expression
-                ArrowFunctionBody::Expression(Box::new(self.parse_assignment()?))
-            };
-
-            Ok(Expression::Arrow {
-                parameters,
-                body,
-                is_async: false,
-                return_type: None,
-            })
+            self.parse_arrow_function_after(parameters)
         } else {
             // Check if we have ')' => which indicates single parameter arrow function
             self.consume(&Token::RightParen, "Expected ')'")?;
@@ -1517,6 +2372,7 @@ impl Parser {
                             name,
                             type_annotation: None,
                             default_value: None,
+                            kind: ParameterKind::Positional,
                         }],
                         body,
                         is_async: false,
@@ -1536,6 +2392,109 @@ impl Parser {
         }
     }
 
+    /// The parameters of an arrow function after `parameters`, then its body
+    fn parse_arrow_function_after(
+        &mut self,
+        mut parameters: Vec<FunctionParameter>,
+    ) -> Result<Expression, ParseError> {
+        if !self.check(&Token::RightParen) {
+            loop {
+                let kind = self.parse_parameter_kind();
+                let name = self.consume_identifier("Expected parameter name")?;
+                let type_annotation = if self.match_token(&Token::Colon) {
+                    Some(self.parse_type_annotation()?)
+                } else {
+                    None
+                };
+
+                let default_value = if self.match_token(&Token::Assign) {
+                    Some(self.parse_assignment()?)
+                } else {
+                    None
+                };
+
+                parameters.push(FunctionParameter {
+                    name,
+                    type_annotation,
+                    default_value,
+                    kind,
+                });
+
+                if !self.match_token(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.check_parameters(&parameters)?;
+        self.consume(&Token::RightParen, "Expected ')'")?;
+        self.consume(&Token::Arrow, "Expected '=>'")?;
+
+        // Parse body - could be an expression or a block
+        let body = if self.check(&Token::LeftBrace) {
+            // Block body: { statements... }
+            ArrowFunctionBody::Block(self.parse_arrow_function_block_body()?)
+        } else {
+            // Expression body: expression
+            ArrowFunctionBody::Expression(Box::new(self.parse_assignment()?))
+        };
+
+        Ok(Expression::Arrow {
+            parameters,
+            body,
+            is_async: false,
+            return_type: None,
+        })
+    }
+
+    /// `*` or `...` before the name of a rest parameter, and `**` before a keywords one
+    fn parse_parameter_kind(&mut self) -> ParameterKind {
+        if self.match_token(&Token::Star) || self.match_token(&Token::Ellipsis) {
+            ParameterKind::Rest
+        } else if self.match_token(&Token::Power) {
+            ParameterKind::Keywords
+        } else {
+            ParameterKind::Positional
+        }
+    }
+
+    /// Fail unless `parameters` are ordinary ones, then at most one rest parameter and at most
+    /// one keywords parameter, in that order and without defaults
+    fn check_parameters(&self, parameters: &[FunctionParameter]) -> Result<(), ParseError> {
+        let spelled = |parameter: &FunctionParameter| {
+            format!("{}{}", parameter.kind.prefix(), parameter.name)
+        };
+        let with_default = parameters.iter().find(|parameter| {
+            parameter.kind != ParameterKind::Positional && parameter.default_value.is_some()
+        });
+        let out_of_order = parameters
+            .windows(2)
+            .find(|pair| pair[0].kind != ParameterKind::Positional && pair[1].kind <= pair[0].kind);
+        let message = match (with_default, out_of_order) {
+            (Some(parameter), _) => {
+                format!(
+                    "Parameter '{}' can't have a default value",
+                    spelled(parameter)
+                )
+            }
+            (None, Some(pair)) => format!(
+                "Parameter '{}' can't follow '{}'",
+                spelled(&pair[1]),
+                spelled(&pair[0])
+            ),
+            (None, None) => return Ok(()),
+        };
+
+        let (line, column) = self
+            .peek_token()?
+            .map_or((0, 0), |token| (token.line, token.column));
+        Err(ParseError::SyntaxError {
+            message,
+            line,
+            column,
+        })
+    }
+
     /// Parse arrow function block body: { statements... }
     fn parse_arrow_function_block_body(&mut self) -> Result<Vec<Statement>, ParseError> {
         self.consume(&Token::LeftBrace, "Expected '{'")?;
@@ -1551,7 +2510,7 @@ impl Parser {
                 let _ = self.advance();
                 continue;
             }
-            statements.push(self.parse_statement()?);
+            self.parse_block_statement(&mut statements)?;
         }
 
         self.consume(&Token::RightBrace, "Expected '}'")?;
@@ -1,13 +1,17 @@
+use std::borrow::Cow;
+
+/// A lexical token. Text-carrying variants borrow from the source buffer
+/// wherever possible; see [`crate::Lexer`].
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token {
+pub enum Token<'a> {
     // Literals
     Number(f64),
-    String(String),
+    String(Cow<'a, str>),
     Boolean(bool),
     Null,
 
     // Identifiers
-    Identifier(String),
+    Identifier(&'a str),
 
     // Keywords
     Let,
@@ -39,6 +43,7 @@ pub enum Token {
     This,
     Super,
     Static,
+    With,
 
     // Operators
     Plus,
@@ -81,8 +86,10 @@ pub enum Token {
     Semicolon,
     Colon,
     Dot,
+    Ellipsis, // ...
     Arrow,
     QuestionMark,
+    At,
 
     // Special
     Newline,
@@ -94,18 +101,159 @@ pub enum Token {
     ExportNamed,       // export { ... } from '...';
     ExportAll,         // export * from '...';
     ExportDeclaration, // export default ...;
-    StringLiteral(String),
+    StringLiteral(Cow<'a, str>),
 
     // Template literal tokens
-    TemplateStart(String),  // f"text before {
-    TemplateMiddle(String), // } text between {
-    TemplateEnd(String),    // } text after"
+    TemplateStart(Cow<'a, str>),  // f"text before {
+    TemplateMiddle(Cow<'a, str>), // } text between {
+    TemplateEnd(Cow<'a, str>),    // } text after"
+
+    /// Raw JavaScript of a `js"""..."""` block, between the quotes
+    InlineJs(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenWithPosition<'a> {
+    pub token: Token<'a>,
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
 }
 
+/// A comment skipped by the lexer, kept as trivia for the formatter and linter
 #[derive(Debug, Clone, PartialEq)]
-pub struct TokenWithPosition {
-    pub token: Token,
+pub struct Comment<'a> {
+    /// Comment text including its delimiters
+    pub text: &'a str,
     pub line: usize,
     pub column: usize,
     pub offset: usize,
 }
+
+/// Highlighting category of a keyword, used when generating editor grammars
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeywordKind {
+    /// Control flow: `if`, `return`, `try`, ...
+    Control,
+    /// Declarations and modifiers: `def`, `class`, `import`, `async`, ...
+    Declaration,
+    /// Literal constants: `true`, `false`, `null`
+    Constant,
+    /// Language-provided bindings: `this`, `super`
+    Variable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyword {
+    pub text: &'static str,
+    pub token: Token<'static>,
+    pub kind: KeywordKind,
+}
+
+const fn keyword(text: &'static str, token: Token<'static>, kind: KeywordKind) -> Keyword {
+    Keyword { text, token, kind }
+}
+
+/// Every reserved word the lexer recognises. This table drives both the lexer and
+/// `nag tooling gen-grammar`, so editor highlighting matches what actually lexes as a keyword.
+pub const KEYWORDS: &[Keyword] = &[
+    keyword("let", Token::Let, KeywordKind::Declaration),
+    keyword("const", Token::Const, KeywordKind::Declaration),
+    keyword("var", Token::Var, KeywordKind::Declaration),
+    keyword("function", Token::Function, KeywordKind::Declaration),
+    keyword("def", Token::Def, KeywordKind::Declaration),
+    keyword("return", Token::Return, KeywordKind::Control),
+    keyword("if", Token::If, KeywordKind::Control),
+    keyword("else", Token::Else, KeywordKind::Control),
+    keyword("while", Token::While, KeywordKind::Control),
+    keyword("for", Token::For, KeywordKind::Control),
+    keyword("break", Token::Break, KeywordKind::Control),
+    keyword("continue", Token::Continue, KeywordKind::Control),
+    keyword("true", Token::True, KeywordKind::Constant),
+    keyword("false", Token::False, KeywordKind::Constant),
+    keyword("null", Token::Null, KeywordKind::Constant),
+    keyword("class", Token::Class, KeywordKind::Declaration),
+    keyword("import", Token::Import, KeywordKind::Declaration),
+    keyword("export", Token::Export, KeywordKind::Declaration),
+    keyword("from", Token::From, KeywordKind::Declaration),
+    keyword("as", Token::As, KeywordKind::Declaration),
+    keyword("async", Token::Async, KeywordKind::Declaration),
+    keyword("await", Token::Await, KeywordKind::Control),
+    keyword("try", Token::Try, KeywordKind::Control),
+    keyword("catch", Token::Catch, KeywordKind::Control),
+    keyword("finally", Token::Finally, KeywordKind::Control),
+    keyword("throw", Token::Throw, KeywordKind::Control),
+    keyword("new", Token::New, KeywordKind::Declaration),
+    keyword("this", Token::This, KeywordKind::Variable),
+    keyword("super", Token::Super, KeywordKind::Variable),
+    keyword("static", Token::Static, KeywordKind::Declaration),
+    keyword("with", Token::With, KeywordKind::Control),
+];
+
+/// Operator spellings produced by the lexer, longest first within each prefix
+pub const OPERATORS: &[(&str, Token<'static>)] = &[
+    ("**", Token::Power),
+    ("==", Token::Equal),
+    ("!=", Token::NotEqual),
+    ("<=", Token::LessEqual),
+    (">=", Token::GreaterEqual),
+    ("<<", Token::LeftShift),
+    (">>", Token::RightShift),
+    ("&&", Token::And),
+    ("||", Token::Or),
+    ("+=", Token::PlusAssign),
+    ("-=", Token::MinusAssign),
+    ("*=", Token::MultiplyAssign),
+    ("/=", Token::DivideAssign),
+    ("->", Token::Arrow),
+    ("+", Token::Plus),
+    ("-", Token::Minus),
+    ("*", Token::Star),
+    ("/", Token::Slash),
+    ("%", Token::Percent),
+    ("<", Token::Less),
+    (">", Token::Greater),
+    ("!", Token::Not),
+    ("&", Token::BitwiseAnd),
+    ("|", Token::BitwiseOr),
+    ("^", Token::BitwiseXor),
+    ("~", Token::BitwiseNot),
+    ("=", Token::Assign),
+];
+
+/// Punctuation produced by the lexer
+pub const PUNCTUATION: &[(&str, Token<'static>)] = &[
+    ("(", Token::LeftParen),
+    (")", Token::RightParen),
+    ("{", Token::LeftBrace),
+    ("}", Token::RightBrace),
+    ("[", Token::LeftBracket),
+    ("]", Token::RightBracket),
+    (",", Token::Comma),
+    (";", Token::Semicolon),
+    (":", Token::Colon),
+    ("...", Token::Ellipsis),
+    (".", Token::Dot),
+    ("?", Token::QuestionMark),
+    ("@", Token::At),
+];
+
+/// Line comment prefixes
+pub const LINE_COMMENTS: &[&str] = &["#", "//"];
+
+/// Block comment delimiters
+pub const BLOCK_COMMENT: (&str, &str) = ("/*", "*/");
+
+/// String quote characters
+pub const STRING_QUOTES: &[char] = &['"', '\''];
+
+/// Characters that may follow a backslash inside a string literal
+pub const STRING_ESCAPES: &[char] = &['n', 't', 'r', '\\', '\'', '"'];
+
+/// Look up the keyword token for `text`, if it is reserved
+pub fn keyword_token(text: &str) -> Option<Token<'static>> {
+    KEYWORDS
+        .iter()
+        .find(|keyword| keyword.text == text)
+        .map(|keyword| keyword.token.clone())
+}
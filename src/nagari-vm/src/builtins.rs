@@ -1,4 +1,8 @@
-use crate::value::{BuiltinFunction, Value};
+use crate::value::{BuiltinFunction, Super, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
 
 pub fn setup_builtins() -> Vec<(&'static str, Value)> {
     vec![
@@ -51,9 +55,82 @@ pub fn setup_builtins() -> Vec<(&'static str, Value)> {
                 arity: 1,
             }),
         ),
+        (
+            "set",
+            Value::Builtin(BuiltinFunction {
+                name: "set".to_string(),
+                arity: 1,
+            }),
+        ),
+        (
+            "frozenset",
+            Value::Builtin(BuiltinFunction {
+                name: "frozenset".to_string(),
+                arity: 1,
+            }),
+        ),
+        (
+            "super",
+            Value::Builtin(BuiltinFunction {
+                name: "super".to_string(),
+                arity: 2,
+            }),
+        ),
+        ("unicodedata", unicodedata_module()),
+        ("timers", crate::timers::module()),
+        ("events", crate::events::module()),
+        ("schedule", crate::schedule::module()),
+        ("weakref", crate::weakref::module()),
+        ("schema", crate::schema::module()),
+        ("csv", crate::csv::module()),
+        ("struct", crate::packing::module()),
+        ("path", crate::path::module()),
+        ("crypto", crate::crypto::module()),
+        ("intl", crate::intl::module()),
+        ("html", crate::html::module()),
+        #[cfg(feature = "websocket")]
+        ("websocket", crate::websocket::module()),
+        #[cfg(feature = "sqlite")]
+        ("sqlite", crate::sqlite::module()),
+        ("json", crate::json::module()),
+        #[cfg(feature = "http-server")]
+        (
+            "http",
+            Value::Dict(HashMap::from([(
+                "server".to_string(),
+                crate::http_server::module(),
+            )])),
+        ),
     ]
 }
 
+/// The `unicodedata` module: a dict of builtins named `unicodedata.<function>`
+fn unicodedata_module() -> Value {
+    let functions = [("normalize", 2), ("graphemes", 1)];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("unicodedata.{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Names of the parameters of the builtin `name`, by which keyword arguments are placed;
+/// `None` if it only takes positional arguments
+pub fn builtin_parameters(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        name if name.starts_with(crate::json::PREFIX) => crate::json::parameters(name),
+        _ => None,
+    }
+}
+
 pub async fn call_builtin(name: &str, args: &[Value]) -> Result<Value, String> {
     match name {
         "print" => builtin_print(args).await,
@@ -63,6 +140,18 @@ pub async fn call_builtin(name: &str, args: &[Value]) -> Result<Value, String> {
         "int" => builtin_int(args),
         "float" => builtin_float(args),
         "bool" => builtin_bool(args),
+        "set" => builtin_set("set", args).map(Value::Set),
+        "frozenset" => builtin_set("frozenset", args).map(Value::FrozenSet),
+        "super" => builtin_super(args),
+        "unicodedata.normalize" => builtin_normalize(args),
+        "unicodedata.graphemes" => builtin_graphemes(args),
+        name if name.starts_with(crate::schema::PREFIX) => crate::schema::call(name, args),
+        name if name.starts_with(crate::packing::PREFIX) => crate::packing::call(name, args),
+        name if name.starts_with(crate::path::PREFIX) => crate::path::call(name, args),
+        name if name.starts_with(crate::crypto::PREFIX) => crate::crypto::call(name, args),
+        name if name.starts_with(crate::intl::PREFIX) => crate::intl::call(name, args),
+        name if name.starts_with(crate::html::PREFIX) => crate::html::call(name, args),
+        name if name.starts_with(crate::json::PREFIX) => crate::json::call(name, args),
         _ => Err(format!("Unknown builtin function: {name}")),
     }
 }
@@ -86,9 +175,13 @@ fn builtin_len(args: &[Value]) -> Result<Value, String> {
     }
 
     match &args[0] {
-        Value::String(s) => Ok(Value::Int(s.len() as i64)),
+        // Strings are measured in Unicode scalar values, not bytes
+        Value::String(s) => Ok(Value::Int(s.chars().count() as i64)),
         Value::List(l) => Ok(Value::Int(l.len() as i64)),
         Value::Dict(d) => Ok(Value::Int(d.len() as i64)),
+        Value::Set(s) | Value::FrozenSet(s) => Ok(Value::Int(s.len() as i64)),
+        Value::Map(m) => Ok(Value::Int(m.len() as i64)),
+        Value::Bytes(b) => Ok(Value::Int(b.len() as i64)),
         _ => Err(format!(
             "object of type '{}' has no len()",
             args[0].type_name()
@@ -104,7 +197,66 @@ fn builtin_type(args: &[Value]) -> Result<Value, String> {
         ));
     }
 
-    Ok(Value::String(args[0].type_name().to_string()))
+    match &args[0] {
+        Value::Instance(instance) => Ok(Value::String(instance.class.name.clone())),
+        other => Ok(Value::String(other.type_name().to_string())),
+    }
+}
+
+/// Distinct elements of the iterable `set()` or `frozenset()` was called with, if any
+fn builtin_set(name: &str, args: &[Value]) -> Result<Vec<Value>, String> {
+    let items = match args {
+        [] => Vec::new(),
+        [Value::List(items) | Value::Set(items) | Value::FrozenSet(items)] => items.clone(),
+        [Value::String(s)] => s.chars().map(|c| Value::String(c.to_string())).collect(),
+        [Value::Map(entries)] => entries.iter().map(|(key, _)| key.clone()).collect(),
+        [Value::Dict(entries)] => {
+            // Sorted like the dict prints, since its own order changes from run to run
+            let mut keys: Vec<&String> = entries.keys().collect();
+            keys.sort();
+            keys.into_iter()
+                .map(|key| Value::String(key.clone()))
+                .collect()
+        }
+        [other] => return Err(format!("'{}' object is not iterable", other.type_name())),
+        _ => {
+            return Err(format!(
+                "{name}() takes at most 1 argument ({} given)",
+                args.len()
+            ))
+        }
+    };
+    Ok(crate::value::distinct(items))
+}
+
+/// `super(class, instance)`; the compiler passes both when a method calls `super()`
+fn builtin_super(args: &[Value]) -> Result<Value, String> {
+    match args {
+        [Value::Class(class), Value::Instance(instance)] => {
+            if !instance.class.is_subclass_of(class) {
+                return Err(format!(
+                    "super(): '{}' object is not an instance of '{}'",
+                    instance.class.name, class.name
+                ));
+            }
+            Ok(Value::Super(Super {
+                class: Arc::clone(class),
+                instance: instance.clone(),
+            }))
+        }
+        [Value::Class(_), other] => Err(format!(
+            "super() argument 2 must be an instance, not '{}'",
+            other.type_name()
+        )),
+        [other, _] => Err(format!(
+            "super() argument 1 must be a class, not '{}'",
+            other.type_name()
+        )),
+        _ => Err(format!(
+            "super() takes exactly 2 arguments ({} given)",
+            args.len()
+        )),
+    }
 }
 
 fn builtin_str(args: &[Value]) -> Result<Value, String> {
@@ -174,3 +326,51 @@ fn builtin_bool(args: &[Value]) -> Result<Value, String> {
 
     Ok(Value::Bool(args[0].is_truthy()))
 }
+
+fn builtin_normalize(args: &[Value]) -> Result<Value, String> {
+    if args.len() != 2 {
+        return Err(format!(
+            "normalize() takes exactly 2 arguments ({} given)",
+            args.len()
+        ));
+    }
+
+    match (&args[0], &args[1]) {
+        (Value::String(form), Value::String(s)) => {
+            let normalized = match form.as_str() {
+                "NFC" => s.nfc().collect(),
+                "NFD" => s.nfd().collect(),
+                "NFKC" => s.nfkc().collect(),
+                "NFKD" => s.nfkd().collect(),
+                _ => return Err(format!("invalid normalization form: '{form}'")),
+            };
+            Ok(Value::String(normalized))
+        }
+        _ => Err(format!(
+            "normalize() arguments must be str, not '{}' and '{}'",
+            args[0].type_name(),
+            args[1].type_name()
+        )),
+    }
+}
+
+fn builtin_graphemes(args: &[Value]) -> Result<Value, String> {
+    if args.len() != 1 {
+        return Err(format!(
+            "graphemes() takes exactly 1 argument ({} given)",
+            args.len()
+        ));
+    }
+
+    match &args[0] {
+        Value::String(s) => Ok(Value::List(
+            s.graphemes(true)
+                .map(|g| Value::String(g.to_string()))
+                .collect(),
+        )),
+        _ => Err(format!(
+            "graphemes() argument must be str, not '{}'",
+            args[0].type_name()
+        )),
+    }
+}
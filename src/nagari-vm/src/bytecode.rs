@@ -1,4 +1,5 @@
-use crate::value::Value;
+use crate::value::{Function, Value};
+use std::sync::Arc;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -33,6 +34,27 @@ pub enum Opcode {
     SetupLoop = 0x1C,
     PopBlock = 0x1D,
     Await = 0x1E,
+    BuildSlice = 0x2F,
+    LoadAttr = 0x30,
+    StoreAttr = 0x31,
+    UnaryNot = 0x37,
+    UnaryInvert = 0x38,
+    UnaryPositive = 0x39,
+    UnaryNegative = 0x3A,
+    DupTop = 0x3D,
+    Nop = 0x41,
+    BuildSet = 0x43,
+    BinaryPower = 0x44,
+    BinaryFloorDivide = 0x45,
+    BinaryBitAnd = 0x46,
+    BinaryBitOr = 0x47,
+    BinaryBitXor = 0x48,
+    BinaryLeftShift = 0x49,
+    BinaryRightShift = 0x4A,
+    BuildClass = 0x4B,
+    EnterContext = 0x4C,
+    ExitContext = 0x4D,
+    CallFuncKw = 0x4E,
 }
 
 impl Opcode {
@@ -68,18 +90,39 @@ impl Opcode {
             0x1C => Some(Opcode::SetupLoop),
             0x1D => Some(Opcode::PopBlock),
             0x1E => Some(Opcode::Await),
+            0x2F => Some(Opcode::BuildSlice),
+            0x30 => Some(Opcode::LoadAttr),
+            0x31 => Some(Opcode::StoreAttr),
+            0x37 => Some(Opcode::UnaryNot),
+            0x38 => Some(Opcode::UnaryInvert),
+            0x39 => Some(Opcode::UnaryPositive),
+            0x3A => Some(Opcode::UnaryNegative),
+            0x3D => Some(Opcode::DupTop),
+            0x41 => Some(Opcode::Nop),
+            0x43 => Some(Opcode::BuildSet),
+            0x44 => Some(Opcode::BinaryPower),
+            0x45 => Some(Opcode::BinaryFloorDivide),
+            0x46 => Some(Opcode::BinaryBitAnd),
+            0x47 => Some(Opcode::BinaryBitOr),
+            0x48 => Some(Opcode::BinaryBitXor),
+            0x49 => Some(Opcode::BinaryLeftShift),
+            0x4A => Some(Opcode::BinaryRightShift),
+            0x4B => Some(Opcode::BuildClass),
+            0x4C => Some(Opcode::EnterContext),
+            0x4D => Some(Opcode::ExitContext),
+            0x4E => Some(Opcode::CallFuncKw),
             _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Instruction {
     pub opcode: Opcode,
     pub operand: u32,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct BytecodeFile {
     pub constants: Vec<Value>,
     pub names: Vec<String>,
@@ -245,6 +288,41 @@ impl BytecodeFile {
                 // None
                 Ok((Value::None, cursor))
             }
+            5 => {
+                // Function: name, entry instruction and parameter names
+                let (name, bytes_read) = Self::load_string(&data[cursor..])?;
+                cursor += bytes_read;
+                if cursor + 8 > data.len() {
+                    return Err("Invalid function constant: insufficient data".to_string());
+                }
+                let entry = u32::from_le_bytes([
+                    data[cursor],
+                    data[cursor + 1],
+                    data[cursor + 2],
+                    data[cursor + 3],
+                ]) as usize;
+                let parameter_count = u32::from_le_bytes([
+                    data[cursor + 4],
+                    data[cursor + 5],
+                    data[cursor + 6],
+                    data[cursor + 7],
+                ]) as usize;
+                cursor += 8;
+                let mut parameters = Vec::with_capacity(parameter_count);
+                for _ in 0..parameter_count {
+                    let (parameter, bytes_read) = Self::load_string(&data[cursor..])?;
+                    parameters.push(parameter);
+                    cursor += bytes_read;
+                }
+                // The VM gives the function its code when the constant is loaded
+                let function = Function {
+                    name,
+                    parameters,
+                    entry,
+                    code: Arc::default(),
+                };
+                Ok((Value::Function(function), cursor))
+            }
             _ => Err(format!("Unknown constant type tag: {type_tag}")),
         }
     }
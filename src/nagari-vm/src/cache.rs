@@ -0,0 +1,377 @@
+//! Cache of compiled bytecode, for hosts that load the same sources again and again: servers
+//! that restart, REPL sessions, editors evaluating expressions.
+//!
+//! Entries are keyed by the SHA-256 of the compiler version and the source, so a new compiler
+//! never reuses bytecode from an old one. They are kept in memory and, if the host configures a
+//! directory, in `<key>.nac` files there, which outlive the process. Files that don't load as
+//! bytecode are recompiled and overwritten. Failing to write one only costs the next process a
+//! compilation.
+//!
+//! Memory holds at most [`BytecodeCache::capacity`] entries. Past that, the least recently used
+//! one is evicted from memory; its file stays, so the next lookup is a disk hit.
+//!
+//! A cache is a handle: clones share their entries and statistics, so one cache can serve
+//! several VMs.
+
+use crate::bytecode::BytecodeFile;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Entries kept in memory when the host doesn't choose
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Where lookups were answered from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    /// Lookups that had to compile the source
+    pub misses: u64,
+    /// Entries dropped from memory to stay within the capacity
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub fn lookups(&self) -> u64 {
+        self.memory_hits + self.disk_hits + self.misses
+    }
+
+    /// Share of lookups that didn't compile, from 0 to 1; 0 before the first lookup
+    pub fn hit_rate(&self) -> f64 {
+        match self.lookups() {
+            0 => 0.0,
+            lookups => (self.memory_hits + self.disk_hits) as f64 / lookups as f64,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct BytecodeCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    entries: HashMap<String, Entry>,
+    directory: Option<PathBuf>,
+    capacity: usize,
+    /// Counts lookups, to order entries by when they were last used
+    clock: u64,
+    stats: CacheStats,
+}
+
+struct Entry {
+    bytecode: Arc<[u8]>,
+    last_used: u64,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            directory: None,
+            capacity: DEFAULT_CAPACITY,
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+impl Inner {
+    /// The bytecode under `key`, marking it as just used
+    fn get(&mut self, key: &str) -> Option<Arc<[u8]>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        Some(entry.bytecode.clone())
+    }
+
+    fn insert(&mut self, key: String, bytecode: Arc<[u8]>) {
+        self.clock += 1;
+        let last_used = self.clock;
+        self.entries.insert(
+            key,
+            Entry {
+                bytecode,
+                last_used,
+            },
+        );
+        self.evict();
+    }
+
+    /// Drop the least recently used entries until no more than `capacity` are left
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+impl BytecodeCache {
+    /// A cache kept in memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache that also keeps its entries in `directory`, creating it if needed
+    pub fn with_directory(directory: impl Into<PathBuf>) -> Result<Self, String> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).map_err(|e| {
+            format!(
+                "Failed to create bytecode cache directory {}: {e}",
+                directory.display()
+            )
+        })?;
+        let cache = Self::new();
+        cache.lock().directory = Some(directory);
+        Ok(cache)
+    }
+
+    /// The key of `source` compiled by `compiler_version`
+    pub fn key(source: &str, compiler_version: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(compiler_version.as_bytes());
+        hasher.update([0]);
+        hasher.update(source.as_bytes());
+        hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// The bytecode of `source`, calling `compile` only if no compiler of `compiler_version`
+    /// compiled it before
+    pub fn get_or_compile(
+        &self,
+        source: &str,
+        compiler_version: &str,
+        compile: impl FnOnce(&str) -> Result<Vec<u8>, String>,
+    ) -> Result<Arc<[u8]>, String> {
+        let key = Self::key(source, compiler_version);
+        let directory = {
+            let mut inner = self.lock();
+            if let Some(bytecode) = inner.get(&key) {
+                inner.stats.memory_hits += 1;
+                return Ok(bytecode);
+            }
+            inner.directory.clone()
+        };
+
+        let path = directory.map(|directory| directory.join(format!("{key}.nac")));
+        if let Some(bytecode) = path.as_deref().and_then(read_entry) {
+            let mut inner = self.lock();
+            inner.stats.disk_hits += 1;
+            inner.insert(key, bytecode.clone());
+            return Ok(bytecode);
+        }
+
+        // Compiling can take a while, so other VMs sharing the cache aren't held up meanwhile
+        let bytecode: Arc<[u8]> = compile(source)?.into();
+        if let Some(path) = &path {
+            write_entry(path, &bytecode);
+        }
+        let mut inner = self.lock();
+        inner.stats.misses += 1;
+        inner.insert(key, bytecode.clone());
+        Ok(bytecode)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Most entries kept in memory
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    /// Keep at most `entries` entries in memory, evicting the least recently used ones if there
+    /// are more already. With 0, only the directory caches anything.
+    pub fn set_capacity(&self, entries: usize) {
+        let mut inner = self.lock();
+        inner.capacity = entries;
+        inner.evict();
+    }
+
+    /// Number of entries in memory
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget the entries in memory; files in the directory are kept
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // The entries stay consistent even if a holder panicked
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The entry at `path`, if it exists and loads
+fn read_entry(path: &Path) -> Option<Arc<[u8]>> {
+    let data = fs::read(path).ok()?;
+    BytecodeFile::load(&data).ok()?;
+    Some(data.into())
+}
+
+/// Write an entry through a temporary file, so a concurrent reader never sees half of it
+fn write_entry(path: &Path, bytecode: &[u8]) {
+    let temporary = path.with_extension(format!("nac.{}.tmp", std::process::id()));
+    if fs::write(&temporary, bytecode).is_err() || fs::rename(&temporary, path).is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// Bytecode whose only constant is `source`, counting the compilations
+    fn compiler(count: &Cell<usize>) -> impl Fn(&str) -> Result<Vec<u8>, String> + '_ {
+        move |source| {
+            count.set(count.get() + 1);
+            let mut bytecode = b"NAG\x00".to_vec();
+            bytecode.extend(1u16.to_le_bytes());
+            bytecode.extend(1u32.to_le_bytes());
+            bytecode.push(2);
+            bytecode.extend((source.len() as u32).to_le_bytes());
+            bytecode.extend(source.as_bytes());
+            // No names and no instructions
+            bytecode.extend([0; 8]);
+            Ok(bytecode)
+        }
+    }
+
+    fn stats(memory_hits: u64, disk_hits: u64, misses: u64, evictions: u64) -> CacheStats {
+        CacheStats {
+            memory_hits,
+            disk_hits,
+            misses,
+            evictions,
+        }
+    }
+
+    #[test]
+    fn test_memory_hits_skip_compiling() {
+        let compilations = Cell::new(0);
+        let compile = compiler(&compilations);
+        let cache = BytecodeCache::new();
+        let first = cache.get_or_compile("x = 1", "1.0", &compile).unwrap();
+        let again = cache.get_or_compile("x = 1", "1.0", &compile).unwrap();
+        assert_eq!(first, again);
+        assert_eq!(compilations.get(), 1);
+        assert_eq!(cache.stats(), stats(1, 0, 1, 0));
+        assert_eq!(cache.stats().hit_rate(), 0.5);
+
+        // Clones share the entries
+        cache
+            .clone()
+            .get_or_compile("x = 1", "1.0", &compile)
+            .unwrap();
+        assert_eq!(cache.stats(), stats(2, 0, 1, 0));
+    }
+
+    #[test]
+    fn test_disk_hits_outlive_the_cache() {
+        let directory = tempfile::tempdir().unwrap();
+        let compilations = Cell::new(0);
+        let compile = compiler(&compilations);
+        let cache = BytecodeCache::with_directory(directory.path()).unwrap();
+        let compiled = cache.get_or_compile("x = 1", "1.0", &compile).unwrap();
+        let file = directory
+            .path()
+            .join(format!("{}.nac", BytecodeCache::key("x = 1", "1.0")));
+        assert_eq!(fs::read(&file).unwrap(), &*compiled);
+
+        // A new process finds the file
+        let cache = BytecodeCache::with_directory(directory.path()).unwrap();
+        let loaded = cache.get_or_compile("x = 1", "1.0", &compile).unwrap();
+        assert_eq!(loaded, compiled);
+        assert_eq!(compilations.get(), 1);
+        assert_eq!(cache.stats(), stats(0, 1, 0, 0));
+
+        // A file that isn't bytecode is compiled again and replaced
+        fs::write(&file, b"garbage").unwrap();
+        let cache = BytecodeCache::with_directory(directory.path()).unwrap();
+        cache.get_or_compile("x = 1", "1.0", &compile).unwrap();
+        assert_eq!(compilations.get(), 2);
+        assert_eq!(fs::read(&file).unwrap(), &*compiled);
+    }
+
+    #[test]
+    fn test_changed_sources_and_compilers_are_compiled_again() {
+        let compilations = Cell::new(0);
+        let compile = compiler(&compilations);
+        let cache = BytecodeCache::new();
+        let old = cache.get_or_compile("x = 1", "1.0", &compile).unwrap();
+        let edited = cache.get_or_compile("x = 2", "1.0", &compile).unwrap();
+        assert_ne!(old, edited);
+        cache.get_or_compile("x = 1", "1.1", &compile).unwrap();
+        assert_eq!(compilations.get(), 3);
+        assert_eq!(cache.stats(), stats(0, 0, 3, 0));
+        assert_ne!(
+            BytecodeCache::key("x = 1", "1.0"),
+            BytecodeCache::key("x = 1", "1.1")
+        );
+    }
+
+    #[test]
+    fn test_least_recently_used_entries_are_evicted() {
+        let compilations = Cell::new(0);
+        let compile = compiler(&compilations);
+        let cache = BytecodeCache::new();
+        assert_eq!(cache.capacity(), DEFAULT_CAPACITY);
+        cache.set_capacity(2);
+        cache.get_or_compile("a", "1.0", &compile).unwrap();
+        cache.get_or_compile("b", "1.0", &compile).unwrap();
+        cache.get_or_compile("a", "1.0", &compile).unwrap();
+        // `b` was used longest ago
+        cache.get_or_compile("c", "1.0", &compile).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats(), stats(1, 0, 3, 1));
+
+        cache.get_or_compile("a", "1.0", &compile).unwrap();
+        cache.get_or_compile("c", "1.0", &compile).unwrap();
+        assert_eq!(compilations.get(), 3);
+        cache.get_or_compile("b", "1.0", &compile).unwrap();
+        assert_eq!(compilations.get(), 4);
+        assert_eq!(cache.stats(), stats(3, 0, 4, 2));
+
+        cache.set_capacity(0);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().evictions, 4);
+    }
+
+    #[test]
+    fn test_evicted_entries_are_read_back_from_disk() {
+        let directory = tempfile::tempdir().unwrap();
+        let compilations = Cell::new(0);
+        let compile = compiler(&compilations);
+        let cache = BytecodeCache::with_directory(directory.path()).unwrap();
+        cache.set_capacity(1);
+        cache.get_or_compile("a", "1.0", &compile).unwrap();
+        cache.get_or_compile("b", "1.0", &compile).unwrap();
+        cache.get_or_compile("a", "1.0", &compile).unwrap();
+        assert_eq!(compilations.get(), 2);
+        assert_eq!(cache.stats(), stats(0, 1, 2, 2));
+    }
+}
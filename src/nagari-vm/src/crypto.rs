@@ -0,0 +1,288 @@
+//! The `crypto` module: `hash_sha1(data)`, `hash_sha256(data)`, `hmac(key, data[, algorithm])`,
+//! `base64_encode(data)`, `base64_decode(text[, as_bytes])`, `hex_encode(data)`,
+//! `hex_decode(text[, as_bytes])` and `uuid4()`.
+//!
+//! Data is a str, hashed as its UTF-8 bytes, or bytes. Digests come back as lower-case hex.
+//! Decoding gives a str unless `as_bytes` is true, and fails if the decoded bytes aren't UTF-8.
+//! `hmac` signs with `"sha256"` unless `algorithm` is `"sha1"`.
+
+use crate::value::{BuiltinFunction, Value};
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "crypto.";
+
+/// The `crypto` module: a dict of builtins named `crypto.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("hash_sha1", 1),
+        ("hash_sha256", 1),
+        ("hmac", 2),
+        ("base64_encode", 1),
+        ("base64_decode", 1),
+        ("hex_encode", 1),
+        ("hex_decode", 1),
+        ("uuid4", 0),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Call the builtin `name` of the module
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let function = &name[PREFIX.len()..];
+    match (function, args) {
+        ("hash_sha1", [data]) => Ok(hex(&Sha1::digest(bytes(function, data)?))),
+        ("hash_sha256", [data]) => Ok(hex(&Sha256::digest(bytes(function, data)?))),
+        ("hmac", [key, data, algorithm @ ..]) => {
+            let key = bytes(function, key)?;
+            let data = bytes(function, data)?;
+            let signature = match algorithm {
+                [] => hmac::<Hmac<Sha256>>(key, data),
+                [Value::String(algorithm)] if algorithm == "sha256" => {
+                    hmac::<Hmac<Sha256>>(key, data)
+                }
+                [Value::String(algorithm)] if algorithm == "sha1" => hmac::<Hmac<Sha1>>(key, data),
+                [other] => {
+                    return Err(format!(
+                        "hmac() algorithm must be \"sha1\" or \"sha256\", got {other}"
+                    ))
+                }
+                _ => return Err(arguments(function, "2 or 3", args)),
+            };
+            Ok(hex(&signature))
+        }
+        ("base64_encode", [data]) => Ok(Value::String(
+            general_purpose::STANDARD.encode(bytes(function, data)?),
+        )),
+        ("base64_decode", [Value::String(text), rest @ ..]) => {
+            let decoded = general_purpose::STANDARD
+                .decode(text.trim())
+                .map_err(|e| format!("base64_decode(): {e}"))?;
+            decoded_value(function, decoded, rest)
+        }
+        ("hex_encode", [data]) => Ok(hex(bytes(function, data)?)),
+        ("hex_decode", [Value::String(text), rest @ ..]) => {
+            decoded_value(function, from_hex(text.trim())?, rest)
+        }
+        ("base64_decode" | "hex_decode", [other, ..]) => Err(format!(
+            "{function}() text must be a str, not '{}'",
+            other.type_name()
+        )),
+        ("uuid4", []) => Ok(Value::String(uuid::Uuid::new_v4().to_string())),
+        ("uuid4", _) => Err(arguments(function, "no", args)),
+        ("hmac", _) => Err(arguments(function, "2 or 3", args)),
+        ("base64_decode" | "hex_decode", _) => Err(arguments(function, "1 or 2", args)),
+        ("hash_sha1" | "hash_sha256" | "base64_encode" | "hex_encode", _) => {
+            Err(arguments(function, "exactly 1", args))
+        }
+        _ => Err(format!("Unknown builtin function: {name}")),
+    }
+}
+
+fn arguments(function: &str, expected: &str, args: &[Value]) -> String {
+    format!(
+        "{function}() takes {expected} arguments ({} given)",
+        args.len()
+    )
+}
+
+/// The bytes of a str or bytes argument
+fn bytes<'a>(function: &str, data: &'a Value) -> Result<&'a [u8], String> {
+    match data {
+        Value::String(s) => Ok(s.as_bytes()),
+        Value::Bytes(bytes) => Ok(bytes),
+        other => Err(format!(
+            "{function}() data must be str or bytes, not '{}'",
+            other.type_name()
+        )),
+    }
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <M as hmac::digest::KeyInit>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> Value {
+    Value::String(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) {
+        return Err("hex_decode(): text must have an even number of digits".to_string());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .filter(|pair| pair.bytes().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("hex_decode(): invalid hex digits at {i}"))
+        })
+        .collect()
+}
+
+/// Decoded bytes as bytes, or as a str unless `as_bytes` is true
+fn decoded_value(function: &str, decoded: Vec<u8>, options: &[Value]) -> Result<Value, String> {
+    match options {
+        [Value::Bool(true)] => Ok(Value::Bytes(decoded)),
+        [] | [Value::Bool(false)] => String::from_utf8(decoded).map(Value::String).map_err(|_| {
+            format!(
+                "{function}(): decoded data is not UTF-8 text; pass true for as_bytes to get bytes"
+            )
+        }),
+        [other] => Err(format!(
+            "{function}() as_bytes must be a bool, not '{}'",
+            other.type_name()
+        )),
+        _ => Err(format!(
+            "{function}() takes 1 or 2 arguments ({} given)",
+            options.len() + 1
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn test_hashes_match_the_published_vectors() {
+        for (function, data, digest) in [
+            (
+                "crypto.hash_sha1",
+                "abc",
+                "a9993e364706816aba3e25717850c26c9cd0d89d",
+            ),
+            (
+                "crypto.hash_sha256",
+                "abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                "crypto.hash_sha256",
+                "",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+        ] {
+            assert_eq!(call(function, &[string(data)]).unwrap(), string(digest));
+            assert_eq!(
+                call(function, &[Value::Bytes(data.as_bytes().to_vec())]).unwrap(),
+                string(digest)
+            );
+        }
+    }
+
+    #[test]
+    fn test_hmacs_match_the_rfc_vectors() {
+        // RFC 4231 test cases 1, 2 and 6, and RFC 2202 test case 2
+        let jefe = || [string("Jefe"), string("what do ya want for nothing?")];
+        assert_eq!(
+            call(
+                "crypto.hmac",
+                &[Value::Bytes(vec![0x0b; 20]), string("Hi There")]
+            )
+            .unwrap(),
+            string("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+        );
+        assert_eq!(
+            call("crypto.hmac", &jefe()).unwrap(),
+            string("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        assert_eq!(
+            call(
+                "crypto.hmac",
+                &[
+                    Value::Bytes(vec![0xaa; 131]),
+                    string("Test Using Larger Than Block-Size Key - Hash Key First"),
+                ]
+            )
+            .unwrap(),
+            string("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54")
+        );
+        assert_eq!(
+            call(
+                "crypto.hmac",
+                &[jefe().as_slice(), &[string("sha1")]].concat()
+            )
+            .unwrap(),
+            string("effcdf6ae5eb2fa2d27416d5f184df9c259a7c79")
+        );
+        assert_eq!(
+            call(
+                "crypto.hmac",
+                &[jefe().as_slice(), &[string("md5")]].concat()
+            )
+            .unwrap_err(),
+            "hmac() algorithm must be \"sha1\" or \"sha256\", got md5"
+        );
+    }
+
+    #[test]
+    fn test_encodings_round_trip() {
+        assert_eq!(
+            call("crypto.base64_encode", &[string("foobar")]).unwrap(),
+            string("Zm9vYmFy")
+        );
+        assert_eq!(
+            call("crypto.base64_decode", &[string(" Zm9vYg== ")]).unwrap(),
+            string("foob")
+        );
+        assert_eq!(
+            call("crypto.hex_encode", &[Value::Bytes(vec![0, 0xab, 0xff])]).unwrap(),
+            string("00abff")
+        );
+        assert_eq!(
+            call("crypto.hex_decode", &[string("00ABff"), Value::Bool(true)]).unwrap(),
+            Value::Bytes(vec![0, 0xab, 0xff])
+        );
+
+        assert_eq!(
+            call("crypto.hex_decode", &[string("ff")]).unwrap_err(),
+            "hex_decode(): decoded data is not UTF-8 text; pass true for as_bytes to get bytes"
+        );
+        assert_eq!(
+            call("crypto.hex_decode", &[string("0g")]).unwrap_err(),
+            "hex_decode(): invalid hex digits at 0"
+        );
+        assert_eq!(
+            call("crypto.hex_decode", &[string("abc")]).unwrap_err(),
+            "hex_decode(): text must have an even number of digits"
+        );
+    }
+
+    #[test]
+    fn test_uuids_are_random_version_4() {
+        let Value::String(first) = call("crypto.uuid4", &[]).unwrap() else {
+            panic!("uuid4() returns a str");
+        };
+        let Value::String(second) = call("crypto.uuid4", &[]).unwrap() else {
+            panic!("uuid4() returns a str");
+        };
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 36);
+        assert_eq!(&first[14..15], "4");
+    }
+}
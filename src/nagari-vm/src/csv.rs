@@ -0,0 +1,523 @@
+//! The `csv` module: `parse(text[, header, delimiter])`, `format(rows[, header, delimiter])`,
+//! `read(path[, header, delimiter])`, `write(path, rows[, header, delimiter])`, and
+//! `open(path[, header, delimiter])`, `next_row(reader)` and `close(reader)` to go through a
+//! large file a row at a time.
+//!
+//! Rows are lists of strings, or dicts keyed by the header when `header` is true, in which case
+//! the first row is the header and every other row must have as many fields. Fields holding the
+//! delimiter, a quote or a line break are quoted, with quotes doubled, and quoted fields may
+//! span lines. Blank lines are skipped. Written rows end with `\n`; their values are written
+//! as `str()` shows them, and `none` as an empty field.
+//!
+//! Reading, writing and opening a file needs IO access to its path, which hosts allow with
+//! [`VM::set_allow_io`](crate::VM::set_allow_io) or a
+//! [permission prompt](crate::permissions). Readers belong to the VM that opened them, and
+//! clearing its globals closes them.
+
+use crate::permissions::{Permission, Permissions};
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "csv.";
+
+/// The `csv` module: a dict of builtins named `csv.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("parse", 1),
+        ("format", 1),
+        ("read", 1),
+        ("write", 2),
+        ("open", 1),
+        ("next_row", 1),
+        ("close", 1),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+struct Reader {
+    lines: BufReader<File>,
+    records: Records,
+}
+
+/// The open readers of a VM
+#[derive(Default)]
+pub struct Csv {
+    readers: HashMap<i64, Reader>,
+    next_id: i64,
+    permissions: Permissions,
+}
+
+impl Csv {
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    /// Call the builtin `name` of the module
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, String> {
+        let function = &name[PREFIX.len()..];
+        match (function, args) {
+            ("parse", [Value::String(text), options @ ..]) => {
+                let mut records = Records::new(function, options)?;
+                let mut rows = Vec::new();
+                // A record ends with a line break, or the end of the text
+                for line in text.split_inclusive('\n') {
+                    if let Some(row) = records.push(line)? {
+                        rows.push(row);
+                    }
+                }
+                rows.extend(records.finish()?);
+                Ok(Value::List(rows))
+            }
+            ("read", [Value::String(path), options @ ..]) => {
+                self.permissions.check(name, Permission::Io(path.clone()))?;
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("read(): cannot read '{path}': {e}"))?;
+                let mut args = vec![Value::String(text)];
+                args.extend_from_slice(options);
+                self.call("csv.parse", &args)
+            }
+            ("format", [Value::List(rows), options @ ..]) => {
+                Ok(Value::String(format_rows(function, rows, options)?))
+            }
+            ("write", [Value::String(path), Value::List(rows), options @ ..]) => {
+                let text = format_rows(function, rows, options)?;
+                self.permissions.check(name, Permission::Io(path.clone()))?;
+                std::fs::write(path, text)
+                    .map_err(|e| format!("write(): cannot write '{path}': {e}"))?;
+                Ok(Value::None)
+            }
+            ("open", [Value::String(path), options @ ..]) => {
+                let records = Records::new(function, options)?;
+                self.permissions.check(name, Permission::Io(path.clone()))?;
+                let file =
+                    File::open(path).map_err(|e| format!("open(): cannot open '{path}': {e}"))?;
+                self.next_id += 1;
+                self.readers.insert(
+                    self.next_id,
+                    Reader {
+                        lines: BufReader::new(file),
+                        records,
+                    },
+                );
+                Ok(Value::Int(self.next_id))
+            }
+            ("next_row", [Value::Int(handle)]) => {
+                let reader = self
+                    .readers
+                    .get_mut(handle)
+                    .ok_or_else(|| format!("next_row(): reader {handle} is not open"))?;
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    let read = reader
+                        .lines
+                        .read_line(&mut line)
+                        .map_err(|e| format!("next_row(): {e}"))?;
+                    if read == 0 {
+                        return Ok(reader.records.finish()?.unwrap_or(Value::None));
+                    }
+                    if let Some(row) = reader.records.push(&line)? {
+                        return Ok(row);
+                    }
+                }
+            }
+            ("close", [Value::Int(handle)]) => {
+                Ok(Value::Bool(self.readers.remove(handle).is_some()))
+            }
+            ("parse" | "read" | "open" | "format" | "write" | "next_row" | "close", _) => {
+                Err(usage(function, args))
+            }
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    pub fn close_all(&mut self) {
+        self.readers.clear();
+    }
+}
+
+fn usage(function: &str, args: &[Value]) -> String {
+    let expected = match function {
+        "parse" => "parse(text: str[, header: bool, delimiter: str])",
+        "read" => "read(path: str[, header: bool, delimiter: str])",
+        "open" => "open(path: str[, header: bool, delimiter: str])",
+        "format" => "format(rows: list[, header: list, delimiter: str])",
+        "write" => "write(path: str, rows: list[, header: list, delimiter: str])",
+        _ => return format!("{function}() takes a reader from open()"),
+    };
+    let given: Vec<&str> = args.iter().map(Value::type_name).collect();
+    format!("expected {expected}, got ({})", given.join(", "))
+}
+
+fn delimiter(function: &str, value: Option<&Value>) -> Result<char, String> {
+    match value {
+        None => Ok(','),
+        Some(Value::String(s)) if s.chars().count() == 1 && !matches!(s.as_str(), "\"" | "\n" | "\r") => {
+            Ok(s.chars().next().unwrap())
+        }
+        Some(other) => Err(format!(
+            "{function}() delimiter must be one character other than a quote or line break, got {other}"
+        )),
+    }
+}
+
+/// Rows parsed from lines of text, with what is left of a record whose quoted field goes on
+/// to the next line
+struct Records {
+    delimiter: char,
+    /// `Some(None)` until the header row is read
+    header: Option<Option<Vec<String>>>,
+    pending: String,
+    /// Line the pending record starts on
+    line: usize,
+    lines_read: usize,
+}
+
+impl Records {
+    fn new(function: &str, options: &[Value]) -> Result<Self, String> {
+        let header = match options.first() {
+            None | Some(Value::Bool(false)) => None,
+            Some(Value::Bool(true)) => Some(None),
+            Some(other) => {
+                return Err(format!(
+                    "{function}() header must be a bool, not '{}'",
+                    other.type_name()
+                ))
+            }
+        };
+        if options.len() > 2 {
+            return Err(format!(
+                "{function}() takes at most 3 arguments ({} given)",
+                options.len() + 1
+            ));
+        }
+        Ok(Self {
+            delimiter: delimiter(function, options.get(1))?,
+            header,
+            pending: String::new(),
+            line: 0,
+            lines_read: 0,
+        })
+    }
+
+    /// Add a line, returning the row it completes if any
+    fn push(&mut self, line: &str) -> Result<Option<Value>, String> {
+        self.lines_read += 1;
+        if self.pending.is_empty() {
+            self.line = self.lines_read;
+        }
+        self.pending.push_str(line);
+        // Inside a quoted field while the quotes don't pair up
+        if self.pending.matches('"').count() % 2 == 1 {
+            return Ok(None);
+        }
+        let record = std::mem::take(&mut self.pending);
+        self.row(&record)
+    }
+
+    /// The row of the last record, at the end of the input
+    fn finish(&mut self) -> Result<Option<Value>, String> {
+        if self.pending.is_empty() {
+            return Ok(None);
+        }
+        if self.pending.matches('"').count() % 2 == 1 {
+            return Err(format!("line {}: quoted field is not closed", self.line));
+        }
+        let record = std::mem::take(&mut self.pending);
+        self.row(&record)
+    }
+
+    fn row(&mut self, record: &str) -> Result<Option<Value>, String> {
+        let record = record
+            .strip_suffix('\n')
+            .map(|record| record.strip_suffix('\r').unwrap_or(record))
+            .unwrap_or(record);
+        if record.is_empty() {
+            return Ok(None);
+        }
+        let fields = split_fields(record, self.delimiter)
+            .map_err(|message| format!("line {}: {message}", self.line))?;
+        match &mut self.header {
+            None => Ok(Some(Value::List(
+                fields.into_iter().map(Value::String).collect(),
+            ))),
+            Some(header @ None) => {
+                *header = Some(fields);
+                Ok(None)
+            }
+            Some(Some(header)) => {
+                if fields.len() != header.len() {
+                    return Err(format!(
+                        "line {}: row has {} fields, the header has {}",
+                        self.line,
+                        fields.len(),
+                        header.len()
+                    ));
+                }
+                Ok(Some(Value::Dict(
+                    header
+                        .iter()
+                        .cloned()
+                        .zip(fields.into_iter().map(Value::String))
+                        .collect(),
+                )))
+            }
+        }
+    }
+}
+
+/// The fields of one record, without its line break
+fn split_fields(record: &str, delimiter: char) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = record.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("quoted field is not closed".to_string()),
+                }
+            }
+            match chars.next() {
+                None => {
+                    fields.push(field);
+                    return Ok(fields);
+                }
+                Some(c) if c == delimiter => fields.push(field),
+                Some(c) => return Err(format!("unexpected '{c}' after a quoted field")),
+            }
+        } else {
+            loop {
+                match chars.next() {
+                    None => {
+                        fields.push(field);
+                        return Ok(fields);
+                    }
+                    Some(c) if c == delimiter => break,
+                    Some(c) => field.push(c),
+                }
+            }
+            fields.push(field);
+        }
+    }
+}
+
+fn format_rows(function: &str, rows: &[Value], options: &[Value]) -> Result<String, String> {
+    let header = match options.first() {
+        None | Some(Value::None) => None,
+        Some(Value::List(names)) => Some(
+            names
+                .iter()
+                .map(|name| match name {
+                    Value::String(name) => Ok(name.clone()),
+                    other => Err(format!(
+                        "{function}() header names must be str, not '{}'",
+                        other.type_name()
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Some(other) => {
+            return Err(format!(
+                "{function}() header must be a list of column names, not '{}'",
+                other.type_name()
+            ))
+        }
+    };
+    let delimiter = delimiter(function, options.get(1))?;
+
+    let mut text = String::new();
+    let mut write_row = |fields: Vec<String>| {
+        let fields: Vec<String> = fields
+            .into_iter()
+            .map(|field| quote(field, delimiter))
+            .collect();
+        text.push_str(&fields.join(&delimiter.to_string()));
+        text.push('\n');
+    };
+    if let Some(header) = &header {
+        write_row(header.clone());
+    }
+    for (index, row) in rows.iter().enumerate() {
+        let fields = match (row, &header) {
+            (Value::List(values), _) => values.iter().map(cell).collect(),
+            (Value::Dict(values), Some(header)) => header
+                .iter()
+                .map(|name| values.get(name).map(cell).unwrap_or_default())
+                .collect(),
+            (Value::Dict(_), None) => {
+                return Err(format!(
+                    "{function}() needs a header to write dict rows, for the order of the columns"
+                ))
+            }
+            (other, _) => {
+                return Err(format!(
+                    "{function}() row {index} must be a list or dict, not '{}'",
+                    other.type_name()
+                ))
+            }
+        };
+        write_row(fields);
+    }
+    Ok(text)
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::None => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn quote(field: String, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::{PermissionDecision, PermissionPrompt};
+
+    fn string(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    fn list(fields: &[&str]) -> Value {
+        Value::List(fields.iter().map(|field| string(field)).collect())
+    }
+
+    #[test]
+    fn test_quoted_fields_round_trip() {
+        let rows = Value::List(vec![
+            list(&["plain", "with,comma", "with \"quotes\""]),
+            list(&["two\nlines", "", " spaced "]),
+            list(&["\"", "crlf\r\nend", ","]),
+        ]);
+        let mut csv = Csv::default();
+
+        let text = csv.call("csv.format", std::slice::from_ref(&rows)).unwrap();
+        assert_eq!(
+            text,
+            string(
+                "plain,\"with,comma\",\"with \"\"quotes\"\"\"\n\
+                 \"two\nlines\",, spaced \n\
+                 \"\"\"\",\"crlf\r\nend\",\",\"\n"
+            )
+        );
+        assert_eq!(csv.call("csv.parse", &[text]).unwrap(), rows);
+    }
+
+    #[test]
+    fn test_headers_and_delimiters_round_trip() {
+        let header = list(&["name", "note"]);
+        let row = |name: &str, note: &str| {
+            Value::Dict(HashMap::from([
+                ("name".to_string(), string(name)),
+                ("note".to_string(), string(note)),
+            ]))
+        };
+        let rows = Value::List(vec![row("ada", "a;b"), row("alan", "")]);
+        let mut csv = Csv::default();
+
+        let text = csv
+            .call("csv.format", &[rows.clone(), header, string(";")])
+            .unwrap();
+        assert_eq!(text, string("name;note\nada;\"a;b\"\nalan;\n"));
+        assert_eq!(
+            csv.call("csv.parse", &[text, Value::Bool(true), string(";")])
+                .unwrap(),
+            rows
+        );
+    }
+
+    #[test]
+    fn test_malformed_text_is_an_error() {
+        let mut csv = Csv::default();
+        for (text, error) in [
+            ("a,\"b\n", "line 1: quoted field is not closed"),
+            ("\"a\"b,c\n", "line 1: unexpected 'b' after a quoted field"),
+        ] {
+            assert_eq!(csv.call("csv.parse", &[string(text)]).unwrap_err(), error);
+        }
+        assert_eq!(
+            csv.call("csv.parse", &[string("a,b\n1\n"), Value::Bool(true)])
+                .unwrap_err(),
+            "line 2: row has 1 fields, the header has 2"
+        );
+    }
+
+    #[test]
+    fn test_files_need_io_access() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("people.csv");
+        let path = path.to_string_lossy().into_owned();
+        let rows = Value::List(vec![Value::List(vec![string("ada"), Value::Int(36)])]);
+
+        let mut csv = Csv::default();
+        let permissions = Permissions::default();
+        csv.set_permissions(permissions.clone());
+        permissions.start_report();
+        for (function, args) in [
+            ("csv.write", vec![string(&path), rows.clone()]),
+            ("csv.read", vec![string(&path)]),
+            ("csv.open", vec![string(&path)]),
+        ] {
+            let error = csv.call(function, &args).unwrap_err();
+            assert!(
+                error.ends_with("(): IO operations are not allowed"),
+                "{error}"
+            );
+        }
+        assert!(!std::path::Path::new(&path).exists());
+        let accesses = permissions.finish_report();
+        let operations: Vec<_> = accesses
+            .iter()
+            .map(|access| access.operation.as_str())
+            .collect();
+        assert_eq!(operations, ["csv.write", "csv.read", "csv.open"]);
+        assert!(accesses
+            .iter()
+            .all(|access| !access.allowed && access.permission == Permission::Io(path.clone())));
+
+        permissions.set_prompt(Some(PermissionPrompt::new(|_| {
+            PermissionDecision::AllowAlways
+        })));
+        csv.call("csv.write", &[string(&path), rows.clone()])
+            .unwrap();
+        assert_eq!(
+            csv.call("csv.read", &[string(&path)]).unwrap(),
+            Value::List(vec![Value::List(vec![string("ada"), string("36")])])
+        );
+        let reader = csv.call("csv.open", &[string(&path)]).unwrap();
+        assert_eq!(
+            csv.call("csv.next_row", &[reader]).unwrap(),
+            Value::List(vec![string("ada"), string("36")])
+        );
+    }
+}
@@ -74,4 +74,13 @@ impl Environment {
     pub fn define_global(&mut self, name: &str, value: Value) {
         self.globals.insert(name.to_string(), value);
     }
+
+    pub fn globals(&self) -> &HashMap<String, Value> {
+        &self.globals
+    }
+
+    /// Local scopes, outermost first
+    pub fn locals(&self) -> &[HashMap<String, Value>] {
+        &self.locals
+    }
 }
@@ -0,0 +1,262 @@
+//! The `events` module: `on(event, listener)`, `once(event, listener)`, `off(event[, listener])`
+//! and `emit(event, *args)` on an emitter shared by the scripts a VM runs and its host.
+//!
+//! Scripts listen with builtins and host functions; the host subscribes with closures through
+//! [`VM::subscribe_event`](crate::VM::subscribe_event), and emits into the scripts with
+//! [`VM::emit_event`](crate::VM::emit_event). Listeners run in the order they were added, and a
+//! `once` listener is removed before it runs. Clearing the VM's globals removes the scripts'
+//! listeners; the host's stay, like its host functions.
+
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "events.";
+
+/// A host listener, called with the event's arguments
+pub type EventListener = Arc<dyn Fn(&[Value]) -> Result<(), String> + Send + Sync>;
+
+/// The `events` module: a dict of builtins named `events.<function>`
+pub fn module() -> Value {
+    let functions = [("on", 2), ("once", 2), ("off", 2), ("emit", 1)];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+#[derive(Clone)]
+pub enum Listener {
+    Script(Value),
+    Host(EventListener),
+}
+
+struct Subscription {
+    id: u64,
+    listener: Listener,
+    once: bool,
+}
+
+#[derive(Default)]
+pub struct Events {
+    subscriptions: HashMap<String, Vec<Subscription>>,
+    next_id: u64,
+}
+
+impl Events {
+    /// Add `listener` to `event`, returning its subscription id
+    pub fn add(&mut self, event: &str, listener: Listener, once: bool) -> u64 {
+        self.next_id += 1;
+        self.subscriptions
+            .entry(event.to_string())
+            .or_default()
+            .push(Subscription {
+                id: self.next_id,
+                listener,
+                once,
+            });
+        self.next_id
+    }
+
+    /// Remove the script listener `listener` of `event`, or all of them if `None`, returning
+    /// whether any was removed
+    pub fn remove(&mut self, event: &str, listener: Option<&Value>) -> bool {
+        let Some(subscriptions) = self.subscriptions.get_mut(event) else {
+            return false;
+        };
+        let count = subscriptions.len();
+        subscriptions.retain(|subscription| match (&subscription.listener, listener) {
+            (Listener::Script(_), None) => false,
+            (Listener::Script(value), Some(listener)) => value != listener,
+            (Listener::Host(_), _) => true,
+        });
+        subscriptions.len() != count
+    }
+
+    /// Remove the subscription `id`, returning whether it existed
+    pub fn unsubscribe(&mut self, id: u64) -> bool {
+        let mut removed = false;
+        for subscriptions in self.subscriptions.values_mut() {
+            let count = subscriptions.len();
+            subscriptions.retain(|subscription| subscription.id != id);
+            removed |= subscriptions.len() != count;
+        }
+        removed
+    }
+
+    /// The listeners an emission of `event` calls, dropping the `once` ones
+    pub fn take_listeners(&mut self, event: &str) -> Vec<Listener> {
+        let Some(subscriptions) = self.subscriptions.get_mut(event) else {
+            return Vec::new();
+        };
+        let listeners = subscriptions
+            .iter()
+            .map(|subscription| subscription.listener.clone())
+            .collect();
+        subscriptions.retain(|subscription| !subscription.once);
+        listeners
+    }
+
+    /// Script listeners with the events they listen to
+    pub fn script_listeners(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.subscriptions
+            .iter()
+            .flat_map(|(event, subscriptions)| {
+                subscriptions
+                    .iter()
+                    .filter_map(move |subscription| match &subscription.listener {
+                        Listener::Script(value) => Some((event.as_str(), value)),
+                        Listener::Host(_) => None,
+                    })
+            })
+    }
+
+    /// Remove every script listener
+    pub fn clear_script_listeners(&mut self) {
+        for subscriptions in self.subscriptions.values_mut() {
+            subscriptions.retain(|subscription| matches!(subscription.listener, Listener::Host(_)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::HostCallback;
+    use crate::vm::VM;
+    use std::sync::Mutex;
+
+    fn builtin(name: &str) -> Value {
+        Value::Builtin(BuiltinFunction {
+            name: name.to_string(),
+            arity: 0,
+        })
+    }
+
+    fn names(listeners: &[Listener]) -> Vec<String> {
+        listeners
+            .iter()
+            .map(|listener| match listener {
+                Listener::Script(value) => value.to_string(),
+                Listener::Host(_) => "host".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_listeners_run_in_order_and_once_listeners_once() {
+        let mut events = Events::default();
+        events.add("ready", Listener::Script(builtin("a")), false);
+        events.add("ready", Listener::Script(builtin("b")), true);
+        events.add("ready", Listener::Host(Arc::new(|_| Ok(()))), false);
+        events.add("done", Listener::Script(builtin("c")), false);
+
+        let first = names(&events.take_listeners("ready"));
+        let again = names(&events.take_listeners("ready"));
+        assert_eq!(first.len(), 3);
+        assert_eq!(first[2], "host");
+        assert_eq!(again, [first[0].clone(), first[2].clone()]);
+        assert!(events.take_listeners("missing").is_empty());
+    }
+
+    #[test]
+    fn test_scripts_only_remove_their_own_listeners() {
+        let mut events = Events::default();
+        events.add("ready", Listener::Script(builtin("a")), false);
+        events.add("ready", Listener::Script(builtin("b")), false);
+        let host = events.add("ready", Listener::Host(Arc::new(|_| Ok(()))), false);
+
+        assert!(events.remove("ready", Some(&builtin("a"))));
+        assert!(!events.remove("ready", Some(&builtin("a"))));
+        assert!(!events.remove("missing", None));
+        assert_eq!(events.script_listeners().count(), 1);
+        assert!(events.remove("ready", None));
+        assert_eq!(events.take_listeners("ready").len(), 1);
+
+        events.add("done", Listener::Script(builtin("c")), false);
+        events.clear_script_listeners();
+        assert_eq!(events.script_listeners().count(), 0);
+        assert!(events.unsubscribe(host));
+        assert!(!events.unsubscribe(host));
+        assert!(events.take_listeners("ready").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scripts_and_the_host_share_the_emitter() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut vm = VM::new(false);
+        for name in ["greet", "wave"] {
+            let log = log.clone();
+            let callback = HostCallback::Sync(Arc::new(move |args: Vec<Value>| {
+                let args: Vec<String> = args.iter().map(Value::to_string).collect();
+                log.lock()
+                    .unwrap()
+                    .push(format!("{name}({})", args.join(", ")));
+                Ok(Value::None)
+            }));
+            vm.register_host_function(name, callback, None);
+        }
+        let host_log = log.clone();
+        vm.subscribe_event(
+            "join",
+            Arc::new(move |args| {
+                host_log
+                    .lock()
+                    .unwrap()
+                    .push(format!("host({})", args.len()));
+                Ok(())
+            }),
+            false,
+        );
+        let events = |name: &str| builtin(&format!("{PREFIX}{name}"));
+        let join = || Value::String("join".to_string());
+
+        vm.call(events("on"), vec![join(), builtin("greet")])
+            .await
+            .unwrap();
+        vm.call(events("once"), vec![join(), builtin("wave")])
+            .await
+            .unwrap();
+        let emit = vec![join(), Value::String("ada".to_string()), Value::Int(1)];
+        assert_eq!(
+            vm.call(events("emit"), emit.clone()).await,
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(vm.emit_event("join", vec![Value::Int(2)]).await, Ok(true));
+        // In the order they were added, the host's first, and the `once` listener only once
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "host(2)",
+                "greet(ada, 1)",
+                "wave(ada, 1)",
+                "host(1)",
+                "greet(2)"
+            ]
+        );
+
+        // Clearing the globals drops the script's listeners but not the host's
+        log.lock().unwrap().clear();
+        vm.clear_globals();
+        assert_eq!(vm.call(events("emit"), emit).await, Ok(Value::Bool(true)));
+        assert_eq!(*log.lock().unwrap(), ["host(2)"]);
+        assert_eq!(
+            vm.call(events("off"), vec![join()]).await,
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            vm.call(events("on"), vec![join(), Value::Int(1)]).await,
+            Err("on() listener must be a function, not 'int'".to_string())
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Heap snapshots, for finding out what a long-lived VM holds on to.
+//!
+//! A snapshot is the graph of the values reachable from the VM's roots: globals, local scopes,
+//! the operand stack, the context managers of `with` statements, pending timers, finalizers
+//! and script event listeners. Builtins are left out. Values own what they contain, so every
+//! node has exactly one retainer, and [`HeapSnapshot::path`] spells out the chain of references
+//! that keeps a node alive.
+//!
+//! Sizes are estimates of the bytes a value occupies, its own allocations included, rather than
+//! measurements of the allocator. A value's slot in the list or dict holding it counts towards
+//! the value.
+//!
+//! Snapshots serialize to JSON, so one taken early in a session can be kept and compared with a
+//! later one by [`HeapSnapshot::diff`].
+
+use crate::value::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::mem::size_of;
+
+/// Number of roots listed by a snapshot's summary
+const SUMMARY_ROOTS: usize = 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeapSnapshot {
+    pub nodes: Vec<HeapNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeapNode {
+    pub id: usize,
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// Bytes of the value without the values it contains
+    pub self_size: usize,
+    /// Bytes freed with the value, the values it contains included
+    pub retained_size: usize,
+    /// The node holding this one; `None` for a root
+    pub retainer: Option<usize>,
+    /// How the retainer holds the node (`[2]`, `["name"]`), or which root it is
+    pub edge: String,
+    pub children: Vec<usize>,
+}
+
+/// Number and total size of the nodes of one type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TypeStats {
+    pub count: usize,
+    pub size: usize,
+}
+
+/// What changed between two snapshots
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeapDiff {
+    /// Types whose nodes changed in number or size, the largest growth first
+    pub types: Vec<TypeChange>,
+    /// Roots whose retained size changed, the largest growth first. A root missing from one of
+    /// the snapshots retains 0 bytes in it.
+    pub roots: Vec<RootChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeChange {
+    pub type_name: String,
+    pub before: TypeStats,
+    pub after: TypeStats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootChange {
+    pub root: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl HeapSnapshot {
+    /// A snapshot of `roots`, each a description of the root and the value it holds
+    pub fn new<'a>(roots: impl IntoIterator<Item = (String, &'a Value)>) -> Self {
+        let mut snapshot = Self::default();
+        for (root, value) in roots {
+            snapshot.add(value, None, root);
+        }
+        snapshot
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid heap snapshot: {e}"))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("heap snapshots serialize to JSON")
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &HeapNode> {
+        self.nodes.iter().filter(|node| node.retainer.is_none())
+    }
+
+    /// Bytes retained by all the roots
+    pub fn total_size(&self) -> usize {
+        self.roots().map(|root| root.retained_size).sum()
+    }
+
+    /// Number and size of the nodes of each type
+    pub fn type_stats(&self) -> BTreeMap<String, TypeStats> {
+        let mut stats: BTreeMap<String, TypeStats> = BTreeMap::new();
+        for node in &self.nodes {
+            let entry = stats.entry(node.type_name.clone()).or_default();
+            entry.count += 1;
+            entry.size += node.self_size;
+        }
+        stats
+    }
+
+    /// The references from a root down to node `id`, as in `global cache["users"][3]`
+    pub fn path(&self, id: usize) -> Option<String> {
+        let mut edges = Vec::new();
+        let mut node = self.nodes.get(id)?;
+        loop {
+            edges.push(node.edge.as_str());
+            match node.retainer {
+                Some(retainer) => node = self.nodes.get(retainer)?,
+                None => break,
+            }
+        }
+        edges.reverse();
+        Some(edges.concat())
+    }
+
+    /// What changed from this snapshot to `later`
+    pub fn diff(&self, later: &HeapSnapshot) -> HeapDiff {
+        let (before, after) = (self.type_stats(), later.type_stats());
+        let type_names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let mut types: Vec<TypeChange> = type_names
+            .into_iter()
+            .map(|type_name| TypeChange {
+                type_name: type_name.clone(),
+                before: before.get(type_name).copied().unwrap_or_default(),
+                after: after.get(type_name).copied().unwrap_or_default(),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        types
+            .sort_by_key(|change| std::cmp::Reverse(growth(change.before.size, change.after.size)));
+
+        let retained = |snapshot: &HeapSnapshot| -> HashMap<String, usize> {
+            snapshot
+                .roots()
+                .map(|root| (root.edge.clone(), root.retained_size))
+                .collect()
+        };
+        let (before, after) = (retained(self), retained(later));
+        let root_names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+        let mut roots: Vec<RootChange> = root_names
+            .into_iter()
+            .map(|root| RootChange {
+                root: root.clone(),
+                before: before.get(root).copied().unwrap_or(0),
+                after: after.get(root).copied().unwrap_or(0),
+            })
+            .filter(|change| change.before != change.after)
+            .collect();
+        roots.sort_by_key(|change| std::cmp::Reverse(growth(change.before, change.after)));
+
+        HeapDiff { types, roots }
+    }
+
+    /// Add the node of `value` and the nodes of the values it contains, returning its id
+    fn add(&mut self, value: &Value, retainer: Option<usize>, edge: String) -> usize {
+        let id = self.nodes.len();
+        let self_size = self_size(value);
+        self.nodes.push(HeapNode {
+            id,
+            type_name: value.type_name().to_string(),
+            self_size,
+            retained_size: self_size,
+            retainer,
+            edge,
+            children: Vec::new(),
+        });
+
+        let mut children = Vec::new();
+        let mut retained_size = self_size;
+        for (edge, child) in contents(value) {
+            let child = self.add(child, Some(id), edge);
+            retained_size += self.nodes[child].retained_size;
+            children.push(child);
+        }
+        self.nodes[id].children = children;
+        self.nodes[id].retained_size = retained_size;
+        id
+    }
+}
+
+/// Whether `value` is a builtin or a module of builtins, which every VM starts with
+pub fn is_builtin(value: &Value) -> bool {
+    match value {
+        Value::Builtin(_) => true,
+        Value::Dict(entries) => !entries.is_empty() && entries.values().all(is_builtin),
+        _ => false,
+    }
+}
+
+/// The values `value` contains, with how it holds them
+fn contents(value: &Value) -> Vec<(String, &Value)> {
+    match value {
+        Value::List(items) | Value::Set(items) | Value::FrozenSet(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (format!("[{index}]"), item))
+            .collect(),
+        Value::Dict(entries) => {
+            let mut entries: Vec<_> = entries.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            entries
+                .into_iter()
+                .map(|(key, value)| (format!("[{key:?}]"), value))
+                .collect()
+        }
+        Value::Map(entries) => entries
+            .iter()
+            .enumerate()
+            .flat_map(|(index, (key, value))| {
+                let label = match key {
+                    Value::String(key) => format!("{key:?}"),
+                    key => key.to_string(),
+                };
+                [
+                    (format!(".keys[{index}]"), key),
+                    (format!("[{label}]"), value),
+                ]
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Estimated bytes of `value`, not counting the values it contains but counting their slots
+/// in it only as far as they are unused
+fn self_size(value: &Value) -> usize {
+    let allocated = match value {
+        Value::String(string) => string.capacity(),
+        Value::List(items) | Value::Set(items) | Value::FrozenSet(items) => {
+            (items.capacity() - items.len()) * size_of::<Value>()
+        }
+        Value::Dict(entries) => {
+            entries.capacity() * size_of::<(String, Value)>() - entries.len() * size_of::<Value>()
+                + entries.keys().map(String::capacity).sum::<usize>()
+        }
+        Value::Map(entries) => (entries.capacity() - entries.len()) * size_of::<(Value, Value)>(),
+        Value::Bytes(bytes) => bytes.capacity(),
+        Value::Function(function) => {
+            // The code is shared with the bytecode that defined the function
+            function.name.capacity()
+                + function
+                    .parameters
+                    .iter()
+                    .map(String::capacity)
+                    .sum::<usize>()
+        }
+        Value::Builtin(builtin) => builtin.name.capacity(),
+        Value::Pending(call) => call.function.capacity(),
+        Value::Handle(_, tag) => tag.capacity(),
+        _ => 0,
+    };
+    size_of::<Value>() + allocated
+}
+
+fn growth(before: usize, after: usize) -> i64 {
+    after as i64 - before as i64
+}
+
+impl fmt::Display for HeapSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} values, {} bytes",
+            self.nodes.len(),
+            self.total_size()
+        )?;
+        for (type_name, stats) in self.type_stats() {
+            writeln!(
+                f,
+                "  {type_name:<10} {:>8} values {:>10} bytes",
+                stats.count, stats.size
+            )?;
+        }
+
+        let mut roots: Vec<&HeapNode> = self.roots().collect();
+        roots.sort_by_key(|root| std::cmp::Reverse(root.retained_size));
+        if !roots.is_empty() {
+            writeln!(f, "Largest roots:")?;
+        }
+        for root in roots.into_iter().take(SUMMARY_ROOTS) {
+            writeln!(f, "  {:>10} bytes  {}", root.retained_size, root.edge)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for HeapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.types.is_empty() && self.roots.is_empty() {
+            return writeln!(f, "No change");
+        }
+        for change in &self.types {
+            writeln!(
+                f,
+                "  {:<10} {:>+8} values {:>+10} bytes",
+                change.type_name,
+                growth(change.before.count, change.after.count),
+                growth(change.before.size, change.after.size)
+            )?;
+        }
+        if !self.roots.is_empty() {
+            writeln!(f, "Roots:")?;
+        }
+        for change in &self.roots {
+            writeln!(
+                f,
+                "  {:>+10} bytes  {} ({} -> {})",
+                growth(change.before, change.after),
+                change.root,
+                change.before,
+                change.after
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+//! Functions provided by the program embedding the VM.
+//!
+//! Synchronous host functions run as soon as they are called. Calling an asynchronous one
+//! only creates its future: the call evaluates to a pending value, and the future is driven
+//! when the script awaits that value. Awaiting fails once the host timeout has elapsed.
+//!
+//! A host function may be registered with a signature. Calls are then checked against it
+//! before the function runs, its result is checked afterwards, and the signature shows up in
+//! the `.nagd` declarations generated for the type checker and the language server.
+
+use crate::value::{PendingCall, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub type HostFuture = Pin<Box<dyn Future<Output = Result<Value, String>> + Send>>;
+
+#[derive(Clone)]
+pub enum HostCallback {
+    Sync(Arc<dyn Fn(Vec<Value>) -> Result<Value, String> + Send + Sync>),
+    Async(Arc<dyn Fn(Vec<Value>) -> HostFuture + Send + Sync>),
+}
+
+impl HostCallback {
+    fn is_async(&self) -> bool {
+        matches!(self, HostCallback::Async(_))
+    }
+}
+
+/// Parameter and result types of a host function, as Nagari type names (`int`, `list[str]`,
+/// `str | None`, ...). Unlisted types and `any` accept every value.
+#[derive(Debug, Clone, Default)]
+pub struct HostSignature {
+    parameters: Vec<(String, String)>,
+    rest: Option<(String, String)>,
+    return_type: Option<String>,
+}
+
+impl HostSignature {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn param(mut self, name: &str, type_name: &str) -> Self {
+        self.parameters
+            .push((name.to_string(), type_name.to_string()));
+        self
+    }
+
+    /// Accept any number of further arguments of `type_name`
+    pub fn rest(mut self, name: &str, type_name: &str) -> Self {
+        self.rest = Some((name.to_string(), type_name.to_string()));
+        self
+    }
+
+    pub fn returns(mut self, type_name: &str) -> Self {
+        self.return_type = Some(type_name.to_string());
+        self
+    }
+
+    pub fn check_arguments(&self, function: &str, args: &[Value]) -> Result<(), String> {
+        let required = self.parameters.len();
+        if args.len() < required || (self.rest.is_none() && args.len() > required) {
+            let expected = match self.rest {
+                Some(_) => format!("at least {required}"),
+                None => required.to_string(),
+            };
+            return Err(format!(
+                "{function}() takes {expected} argument(s) but {} were given",
+                args.len()
+            ));
+        }
+
+        for (index, arg) in args.iter().enumerate() {
+            let Some((name, type_name)) = self.parameters.get(index).or(self.rest.as_ref()) else {
+                break;
+            };
+            if !matches_type(type_name, arg) {
+                return Err(format!(
+                    "argument '{name}' of {function}() expects {type_name}, got {}",
+                    arg.type_name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_result(&self, function: &str, result: &Value) -> Result<(), String> {
+        match &self.return_type {
+            Some(type_name) if !matches_type(type_name, result) => Err(format!(
+                "{function}() should return {type_name}, got {}",
+                result.type_name()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The function as a `.nagd` member, e.g. `def clamp(x: int, lo: int) -> int`
+    pub fn declaration(&self, function: &str, is_async: bool) -> String {
+        let mut parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .map(|(name, type_name)| format!("{name}: {type_name}"))
+            .collect();
+        if let Some((name, type_name)) = &self.rest {
+            parameters.push(format!("*{name}: {type_name}"));
+        }
+        let prefix = if is_async { "async def" } else { "def" };
+        let mut declaration = format!("{prefix} {function}({})", parameters.join(", "));
+        if let Some(return_type) = &self.return_type {
+            declaration.push_str(&format!(" -> {return_type}"));
+        }
+        declaration
+    }
+}
+
+/// Whether `value` is of the type named `type_name`
+fn matches_type(type_name: &str, value: &Value) -> bool {
+    type_name.split('|').any(|alternative| {
+        let alternative = alternative.trim();
+        let base = alternative
+            .split_once('[')
+            .map_or(alternative, |(base, _)| base.trim());
+        match (base, value) {
+            ("any", _) => true,
+            ("float", Value::Int(_)) => true,
+            ("None", Value::None) => true,
+            ("callable", Value::Function(_) | Value::Builtin(_)) => true,
+            (base, value) => base == value.type_name(),
+        }
+    })
+}
+
+/// Future of an asynchronous host function call, taken out of the VM to be awaited
+pub struct HostCall {
+    pub function: String,
+    future: HostFuture,
+    timeout: Option<Duration>,
+    signature: Option<HostSignature>,
+}
+
+impl HostCall {
+    pub async fn wait(self) -> Result<Value, String> {
+        let result = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, self.future).await {
+                Ok(result) => result,
+                Err(_) => Err(format!(
+                    "host function '{}' timed out after {} ms",
+                    self.function,
+                    timeout.as_millis()
+                )),
+            },
+            None => self.future.await,
+        }?;
+        if let Some(signature) = &self.signature {
+            signature.check_result(&self.function, &result)?;
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Default)]
+pub struct HostFunctions {
+    functions: HashMap<String, (HostCallback, Option<HostSignature>)>,
+    /// Futures are only `Send`; the mutex keeps the VM `Sync` for hosts that share it
+    pending: HashMap<u64, Mutex<HostFuture>>,
+    next_id: u64,
+    timeout: Option<Duration>,
+}
+
+impl HostFunctions {
+    pub fn register(
+        &mut self,
+        name: &str,
+        callback: HostCallback,
+        signature: Option<HostSignature>,
+    ) {
+        self.functions
+            .insert(name.to_string(), (callback, signature));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    /// Limit how long awaiting a single host call may take
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Call `name`, returning a pending value if it is asynchronous
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let Some((callback, signature)) = self.functions.get(name) else {
+            return Err(format!("Unknown host function: {name}"));
+        };
+        if let Some(signature) = signature {
+            signature.check_arguments(name, &args)?;
+        }
+
+        match callback {
+            HostCallback::Sync(function) => {
+                let result = function(args)?;
+                if let Some(signature) = signature {
+                    signature.check_result(name, &result)?;
+                }
+                Ok(result)
+            }
+            HostCallback::Async(function) => {
+                let future = function(args);
+                let id = self.next_id;
+                self.next_id += 1;
+                self.pending.insert(id, Mutex::new(future));
+                Ok(Value::Pending(PendingCall {
+                    id,
+                    function: name.to_string(),
+                }))
+            }
+        }
+    }
+
+    /// The call behind a pending value; `None` for any other value or one already awaited
+    pub fn take(&mut self, value: &Value) -> Option<HostCall> {
+        let Value::Pending(call) = value else {
+            return None;
+        };
+        let future = self.pending.remove(&call.id)?;
+        let future = future.into_inner().unwrap_or_else(|e| e.into_inner());
+        Some(HostCall {
+            function: call.function.clone(),
+            future,
+            timeout: self.timeout,
+            signature: self
+                .functions
+                .get(&call.function)
+                .and_then(|(_, signature)| signature.clone()),
+        })
+    }
+
+    /// A `.nagd` `globals:` block declaring every host function. Functions registered without
+    /// a signature take and return anything.
+    pub fn declarations(&self) -> String {
+        let mut names: Vec<&String> = self.functions.keys().collect();
+        names.sort();
+
+        let mut declarations = String::from("globals:\n");
+        for name in names {
+            let (callback, signature) = &self.functions[name];
+            let declaration = match signature {
+                Some(signature) => signature.declaration(name, callback.is_async()),
+                None => HostSignature::new()
+                    .rest("args", "any")
+                    .declaration(name, callback.is_async()),
+            };
+            declarations.push_str(&format!("    {declaration}\n"));
+        }
+        declarations
+    }
+
+    /// Drop the futures of calls that were never awaited
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Drive `future` to completion on a new current-thread runtime, for hosts without one.
+/// Must not be called from within an async runtime.
+pub fn block_on<F: Future>(future: F) -> Result<F::Output, String> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Failed to start async runtime: {e}"))?;
+    Ok(runtime.block_on(future))
+}
@@ -0,0 +1,251 @@
+//! The `html` module: `html(template, *values)`, `escape(value)` and `safe(text)`.
+//!
+//! Markup is kept apart from text by the `SafeHtml` type. `html` fills the `{}` placeholders of
+//! a template with its values in order (`{0}`, `{1}`, ... pick one by index, and `{{` and `}}`
+//! stand for braces), escaping `&`, `<`, `>`, `"` and `'` in every value but `SafeHtml`s, which
+//! go in as they are. Lists put in each of their items, and `none` nothing. The result is a
+//! `SafeHtml` itself, so templates nest. `escape` makes a `SafeHtml` of a value's escaped
+//! text, and `safe` one of markup the script trusts. `str()` of a `SafeHtml` is its markup.
+
+use crate::native::{HostObject, NativeObject};
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "html.";
+
+/// The `html` module: a dict of builtins named `html.<function>`
+pub fn module() -> Value {
+    let functions = [("html", 1), ("escape", 1), ("safe", 1)];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Call the builtin `name` of the module
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let function = &name[PREFIX.len()..];
+    let markup = match (function, args) {
+        ("html", [Value::String(template), values @ ..]) => fill(template, values)?,
+        ("html", [other, ..]) => {
+            return Err(format!(
+                "html() template must be a str, not '{}'",
+                other.type_name()
+            ))
+        }
+        ("escape", [value]) => markup(value),
+        ("safe", [Value::String(text)]) => text.clone(),
+        ("safe", [value]) => match safe_html(value) {
+            Some(markup) => markup,
+            None => {
+                return Err(format!(
+                    "safe() text must be a str, not '{}'",
+                    value.type_name()
+                ))
+            }
+        },
+        ("html", []) => return Err("html() takes at least 1 argument (0 given)".to_string()),
+        ("escape" | "safe", _) => {
+            return Err(format!(
+                "{function}() takes exactly 1 argument ({} given)",
+                args.len()
+            ))
+        }
+        _ => return Err(format!("Unknown builtin function: {name}")),
+    };
+    Ok(Value::Native(NativeObject::new(SafeHtml(markup))))
+}
+
+/// Markup that is safe to put in a page as it is
+struct SafeHtml(String);
+
+impl HostObject for SafeHtml {
+    fn type_name(&self) -> &str {
+        "SafeHtml"
+    }
+
+    fn has_method(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn call_method(&mut self, name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        Err(format!("'SafeHtml' object has no attribute '{name}'"))
+    }
+
+    fn get_property(&self, name: &str) -> Option<Value> {
+        (name == "html").then(|| Value::String(self.0.clone()))
+    }
+
+    fn set_property(&mut self, name: &str, _value: Value) -> Result<(), String> {
+        Err(format!("cannot set attribute '{name}' of 'SafeHtml' object"))
+    }
+
+    fn display(&self) -> Option<String> {
+        Some(self.0.clone())
+    }
+}
+
+/// The markup of `value` if it is a `SafeHtml`
+fn safe_html(value: &Value) -> Option<String> {
+    match value {
+        Value::Native(object) if object.type_name() == "SafeHtml" => Some(object.to_string()),
+        _ => None,
+    }
+}
+
+/// `value` as markup: `SafeHtml`s as they are, lists item by item, `none` as nothing and
+/// anything else as its escaped text
+fn markup(value: &Value) -> String {
+    match value {
+        Value::None => String::new(),
+        Value::List(items) => items.iter().map(markup).collect(),
+        value => safe_html(value).unwrap_or_else(|| escape(&value.to_string())),
+    }
+}
+
+/// `text` with the characters that mean something in markup replaced by references
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `template` with its placeholders replaced by the markup of `values`
+fn fill(template: &str, values: &[Value]) -> Result<String, String> {
+    let mut filled = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                filled.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                filled.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err("html() template has an unmatched '{'".to_string()),
+                    }
+                }
+                let index = if field.is_empty() {
+                    next += 1;
+                    next - 1
+                } else {
+                    field.parse::<usize>().map_err(|_| {
+                        format!("html() placeholder '{{{field}}}' must be {{}} or an index")
+                    })?
+                };
+                let value = values.get(index).ok_or_else(|| {
+                    format!(
+                        "html() placeholder {index} has no value ({} given)",
+                        values.len()
+                    )
+                })?;
+                filled.push_str(&markup(value));
+            }
+            '}' => return Err("html() template has an unmatched '}'".to_string()),
+            c => filled.push(c),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    /// The markup of what `name` returns for `args`
+    fn render(name: &str, args: &[Value]) -> String {
+        let value = call(name, args).unwrap();
+        safe_html(&value).expect("the html builtins return SafeHtml")
+    }
+
+    #[test]
+    fn test_values_are_escaped() {
+        assert_eq!(
+            escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(render("html.escape", &[string("&amp;")]), "&amp;amp;");
+        assert_eq!(
+            render(
+                "html.html",
+                &[
+                    string("<p title=\"{}\">{}</p>"),
+                    string("\"><script>"),
+                    Value::Int(3),
+                ]
+            ),
+            "<p title=\"&quot;&gt;&lt;script&gt;\">3</p>"
+        );
+    }
+
+    #[test]
+    fn test_safe_html_nests_without_escaping_twice() {
+        let bold = render("html.html", &[string("<b>{}</b>"), string("a<b")]);
+        let bold = call("html.safe", &[string(&bold)]).unwrap();
+        let items = Value::List(vec![bold.clone(), string("&"), Value::None]);
+
+        assert_eq!(
+            render("html.html", &[string("<p>{}</p>"), items]),
+            "<p><b>a&lt;b</b>&amp;</p>"
+        );
+        assert_eq!(
+            render("html.safe", std::slice::from_ref(&bold)),
+            "<b>a&lt;b</b>"
+        );
+        assert_eq!(render("html.escape", &[bold]), "<b>a&lt;b</b>");
+    }
+
+    #[test]
+    fn test_placeholders_pick_values_by_position_or_index() {
+        assert_eq!(
+            render(
+                "html.html",
+                &[string("{1}{{{0}}}{}"), string("a"), string("<")]
+            ),
+            "&lt;{a}a"
+        );
+        for (template, error) in [
+            ("{", "html() template has an unmatched '{'"),
+            ("}", "html() template has an unmatched '}'"),
+            ("{x}", "html() placeholder '{x}' must be {} or an index"),
+            ("{} {}", "html() placeholder 1 has no value (1 given)"),
+        ] {
+            assert_eq!(
+                call("html.html", &[string(template), Value::Int(1)]).unwrap_err(),
+                error
+            );
+        }
+    }
+}
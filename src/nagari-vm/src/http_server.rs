@@ -0,0 +1,1165 @@
+//! The `http.server` module: `create()`, `route(app, method, path, handler)`,
+//! `static_files(app, prefix, directory)`, `listen(app, port[, host])`, `stop(app)` and
+//! `response(body[, status, headers])`.
+//!
+//! Apps are the integers `create` returns. `route` has `handler` called with each request for
+//! `method` (or any method, for `"*"`) whose path matches `path`, where a `:name` segment
+//! matches any one segment and passes it on as a param. Routes are tried in the order they were
+//! added. The request is a dict of its `method`, `path`, `query` and `params` dicts, `headers`
+//! (with lowercase names), `body` (a `str`, or `bytes` if it isn't UTF-8) and `json`, the
+//! parsed body of a request sent as `application/json` or `none`. A body that isn't valid JSON
+//! is answered with 400 before any handler sees it.
+//!
+//! What a handler returns is the response: a `str` as plain text, a `SafeHtml` as HTML,
+//! `bytes` as they are, `none` as 204 No Content, a `Response` from `response()` with the
+//! status and headers it was given, and anything else as JSON. A handler that fails is
+//! answered with 500, and its error ends the run, as an error in a timer callback does.
+//!
+//! `static_files` serves the files under `directory` for the GET and HEAD requests below
+//! `prefix` that no route takes, with `index.html` for directories; paths with `..` segments,
+//! encoded or not, and links out of `directory` are not served. Serving them needs IO access,
+//! asked about the canonical path of `directory` when `static_files` is called. Other requests
+//! no route takes get 404, or 405 if a route takes the path with another method. `listen`
+//! serves `app` on `port` of `host`, `127.0.0.1` unless given (`"0.0.0.0"` serves every
+//! interface), returning the port, which the system picks for port 0. Handlers run while the
+//! VM waits for timers, as websocket handlers do, and `stop` stops serving. Listening needs
+//! network access, which hosts allow with
+//! [`VM::set_allow_network`](crate::VM::set_allow_network) or a
+//! [permission prompt](crate::permissions), asked about `<host>:<port>`.
+
+use crate::json::{self, Style};
+use crate::native::{HostObject, NativeObject};
+use crate::permissions::{Permission, Permissions};
+use crate::value::{BuiltinFunction, Value};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{Component, Path, PathBuf};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "http.server.";
+
+/// The `http.server` module: a dict of builtins named `http.server.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("create", 0),
+        ("route", 4),
+        ("static_files", 3),
+        ("listen", 2),
+        ("stop", 1),
+        ("response", 1),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Status, headers and body of a response
+#[derive(Debug, Clone)]
+pub struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type".to_string(), content_type.to_string())],
+            body: body.into(),
+        }
+    }
+
+    fn text(status: u16, text: &str) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", text)
+    }
+
+    /// The response for what a handler returned
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::None => Ok(Self {
+                status: 204,
+                headers: Vec::new(),
+                body: Vec::new(),
+            }),
+            Value::String(text) => Ok(Self::text(200, text)),
+            Value::Bytes(data) => Ok(Self::new(200, "application/octet-stream", data.clone())),
+            Value::Native(object) if object.type_name() == "SafeHtml" => Ok(Self::new(
+                200,
+                "text/html; charset=utf-8",
+                object.to_string(),
+            )),
+            Value::Native(object) if object.type_name() == "Response" => {
+                let (
+                    Some(Value::Int(status)),
+                    Some(Value::Dict(headers)),
+                    Some(Value::Bytes(body)),
+                ) = (
+                    object.get_attr("status"),
+                    object.get_attr("headers"),
+                    object.get_attr("body"),
+                )
+                else {
+                    return Err(
+                        "'Response' object is missing its status, headers or body".to_string()
+                    );
+                };
+                let mut headers: Vec<_> = headers
+                    .into_iter()
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect();
+                headers.sort();
+                Ok(Self {
+                    status: status as u16,
+                    headers,
+                    body,
+                })
+            }
+            value => {
+                let style = Style {
+                    indent: None,
+                    canonical: false,
+                };
+                let text = json::stringify(value, &style)?;
+                Ok(Self::new(200, "application/json", text))
+            }
+        }
+    }
+}
+
+/// What `response()` returns
+impl HostObject for Reply {
+    fn type_name(&self) -> &str {
+        "Response"
+    }
+
+    fn has_method(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn call_method(&mut self, name: &str, _args: Vec<Value>) -> Result<Value, String> {
+        Err(format!("'Response' object has no attribute '{name}'"))
+    }
+
+    fn get_property(&self, name: &str) -> Option<Value> {
+        match name {
+            "status" => Some(Value::Int(i64::from(self.status))),
+            "headers" => Some(Value::Dict(
+                self.headers
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                    .collect(),
+            )),
+            "body" => Some(Value::Bytes(self.body.clone())),
+            _ => None,
+        }
+    }
+
+    fn set_property(&mut self, name: &str, _value: Value) -> Result<(), String> {
+        Err(format!(
+            "cannot set attribute '{name}' of 'Response' object"
+        ))
+    }
+}
+
+/// A request a handler has to answer
+pub struct Exchange {
+    pub handler: Value,
+    pub request: Value,
+    reply: oneshot::Sender<Reply>,
+}
+
+impl Exchange {
+    /// Answer with what the handler returned
+    pub fn respond(self, value: &Value) -> Result<(), String> {
+        let reply = Reply::from_value(value);
+        let _ = self
+            .reply
+            .send(reply.clone().unwrap_or_else(|_| server_error()));
+        reply.map(drop)
+    }
+
+    /// Answer for a handler that failed
+    pub fn fail(self) {
+        let _ = self.reply.send(server_error());
+    }
+}
+
+fn server_error() -> Reply {
+    Reply::text(500, "Internal Server Error")
+}
+
+/// A request as it comes in from a server's connections
+struct Incoming {
+    app: i64,
+    method: String,
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    reply: oneshot::Sender<Reply>,
+}
+
+struct Route {
+    /// Uppercase method, or `*` for any
+    method: String,
+    segments: Vec<String>,
+    handler: Value,
+}
+
+struct App {
+    routes: Vec<Route>,
+    /// Path prefixes and the directories they serve
+    statics: Vec<(Vec<String>, PathBuf)>,
+    server: Option<JoinHandle<()>>,
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        if let Some(server) = &self.server {
+            server.abort();
+        }
+    }
+}
+
+/// What an app does with a request
+enum Dispatch {
+    Handler(Value, HashMap<String, Value>),
+    File(PathBuf),
+    Reply(Reply),
+}
+
+/// The apps of a VM
+pub struct HttpServers {
+    apps: HashMap<i64, App>,
+    next_id: i64,
+    sender: UnboundedSender<Incoming>,
+    receiver: UnboundedReceiver<Incoming>,
+    permissions: Permissions,
+}
+
+impl Default for HttpServers {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            apps: HashMap::new(),
+            next_id: 0,
+            sender,
+            receiver,
+            permissions: Permissions::default(),
+        }
+    }
+}
+
+impl HttpServers {
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    /// Call the builtin `name` of the module
+    pub async fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, String> {
+        let function = &name[PREFIX.len()..];
+        match (function, args) {
+            ("create", []) => {
+                self.next_id += 1;
+                self.apps.insert(
+                    self.next_id,
+                    App {
+                        routes: Vec::new(),
+                        statics: Vec::new(),
+                        server: None,
+                    },
+                );
+                Ok(Value::Int(self.next_id))
+            }
+            ("route", [app, Value::String(method), Value::String(path), handler]) => {
+                if !matches!(
+                    handler,
+                    Value::Builtin(_) | Value::Function(_) | Value::Method(_)
+                ) {
+                    return Err(format!(
+                        "route() handler must be a function, not '{}'",
+                        handler.type_name()
+                    ));
+                }
+                let route = Route {
+                    method: method.to_uppercase(),
+                    segments: segments(path).map(str::to_string).collect(),
+                    handler: handler.clone(),
+                };
+                self.app(function, app)?.routes.push(route);
+                Ok(Value::None)
+            }
+            ("route", [_, method, path, _]) => {
+                let (name, value) = match method {
+                    Value::String(_) => ("path", path),
+                    _ => ("method", method),
+                };
+                Err(format!(
+                    "route() {name} must be a str, not '{}'",
+                    value.type_name()
+                ))
+            }
+            ("static_files", [app, Value::String(prefix), Value::String(directory)]) => {
+                self.app_id(function, app)?;
+                // Every file served is below the directory the script was allowed to read. One
+                // that can't be resolved is asked about as written, so that scripts without
+                // access don't learn whether it exists
+                let directory = match std::fs::canonicalize(directory) {
+                    Ok(canonical) => canonical,
+                    Err(e) => {
+                        self.permissions
+                            .check(name, Permission::Io(directory.clone()))?;
+                        return Err(format!("static_files(): cannot serve '{directory}': {e}"));
+                    }
+                };
+                self.permissions
+                    .check(name, Permission::Io(directory.display().to_string()))?;
+                let prefix = segments(prefix).map(str::to_string).collect();
+                self.app(function, app)?.statics.push((prefix, directory));
+                Ok(Value::None)
+            }
+            ("static_files", [_, prefix, directory]) => {
+                let (name, value) = match prefix {
+                    Value::String(_) => ("directory", directory),
+                    _ => ("prefix", prefix),
+                };
+                Err(format!(
+                    "static_files() {name} must be a str, not '{}'",
+                    value.type_name()
+                ))
+            }
+            ("listen", [app, Value::Int(port), host @ ..]) if host.len() <= 1 => {
+                let host = match host.first() {
+                    None | Some(Value::None) => "127.0.0.1",
+                    Some(Value::String(host)) => host.as_str(),
+                    Some(other) => {
+                        return Err(format!(
+                            "listen() host must be a str, not '{}'",
+                            other.type_name()
+                        ))
+                    }
+                };
+                self.permissions
+                    .check(name, Permission::Network(format!("{host}:{port}")))?;
+                let id = self.app_id(function, app)?;
+                if self.app(function, app)?.server.is_some() {
+                    return Err(format!("listen(): app {id} is already listening"));
+                }
+                let port = u16::try_from(*port)
+                    .map_err(|_| format!("listen() port must be from 0 to 65535, got {port}"))?;
+                let (server, port) = self
+                    .serve(id, host, port)
+                    .map_err(|e| format!("listen(): cannot listen on {host}:{port}: {e}"))?;
+                self.app(function, app)?.server = Some(server);
+                Ok(Value::Int(i64::from(port)))
+            }
+            ("listen", [_, other, rest @ ..]) if rest.len() <= 1 => Err(format!(
+                "listen() port must be an int, not '{}'",
+                other.type_name()
+            )),
+            ("listen", _) => Err(format!(
+                "listen() takes from 2 to 3 argument(s) ({} given)",
+                args.len()
+            )),
+            ("stop", [app]) => {
+                let app = self.app(function, app)?;
+                Ok(Value::Bool(match app.server.take() {
+                    Some(server) => {
+                        server.abort();
+                        true
+                    }
+                    None => false,
+                }))
+            }
+            ("response", [body, rest @ ..]) if rest.len() <= 2 => {
+                let mut reply = Reply::from_value(body)?;
+                if body == &Value::None {
+                    reply.status = 200;
+                }
+                match rest.first() {
+                    None | Some(Value::None) => {}
+                    Some(Value::Int(status)) if (100..=599).contains(status) => {
+                        reply.status = *status as u16;
+                    }
+                    Some(Value::Int(status)) => {
+                        return Err(format!(
+                            "response() status must be from 100 to 599, got {status}"
+                        ))
+                    }
+                    Some(other) => {
+                        return Err(format!(
+                            "response() status must be an int, not '{}'",
+                            other.type_name()
+                        ))
+                    }
+                }
+                match rest.get(1) {
+                    None | Some(Value::None) => {}
+                    Some(Value::Dict(headers)) => {
+                        let mut names: Vec<_> = headers.keys().collect();
+                        names.sort();
+                        for name in names {
+                            let value = headers[name].to_string();
+                            let name = name.to_lowercase();
+                            reply.headers.retain(|(existing, _)| *existing != name);
+                            reply.headers.push((name, value));
+                        }
+                    }
+                    Some(other) => {
+                        return Err(format!(
+                            "response() headers must be a dict, not '{}'",
+                            other.type_name()
+                        ))
+                    }
+                }
+                Ok(Value::Native(NativeObject::new(reply)))
+            }
+            ("response", _) => Err(format!(
+                "response() takes from 1 to 3 argument(s) ({} given)",
+                args.len()
+            )),
+            ("create" | "route" | "static_files" | "stop", _) => Err(format!(
+                "{function}() takes {} argument(s) ({} given)",
+                match function {
+                    "create" => 0,
+                    "stop" => 1,
+                    "static_files" => 3,
+                    _ => 4,
+                },
+                args.len()
+            )),
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    /// Whether a handler may still be called: an app is listening
+    pub fn listening(&self) -> bool {
+        self.apps.values().any(|app| app.server.is_some())
+    }
+
+    /// Wait for the next request and answer it if no handler has to, returning it otherwise
+    pub async fn next_exchange(&mut self) -> Option<Exchange> {
+        let incoming = self
+            .receiver
+            .recv()
+            .await
+            .expect("the module keeps a sender of its own");
+        // A request of an app that stopped meanwhile is dropped, which fails it
+        let app = self.apps.get(&incoming.app)?;
+        match app.dispatch(&incoming.method, &incoming.path) {
+            Dispatch::Handler(handler, params) => match request_value(&incoming, params) {
+                Ok(request) => Some(Exchange {
+                    handler,
+                    request,
+                    reply: incoming.reply,
+                }),
+                Err(reply) => {
+                    let _ = incoming.reply.send(reply);
+                    None
+                }
+            },
+            Dispatch::File(path) => {
+                tokio::spawn(send_file(path, incoming.reply));
+                None
+            }
+            Dispatch::Reply(reply) => {
+                let _ = incoming.reply.send(reply);
+                None
+            }
+        }
+    }
+
+    /// Handlers, for heap snapshots
+    pub fn callbacks(&self) -> impl Iterator<Item = (String, &Value)> {
+        self.apps.iter().flat_map(|(id, app)| {
+            app.routes.iter().map(move |route| {
+                let path = format!("/{}", route.segments.join("/"));
+                (
+                    format!("http.server app {id} {} {path}", route.method),
+                    &route.handler,
+                )
+            })
+        })
+    }
+
+    pub fn close_all(&mut self) {
+        self.apps.clear();
+        while self.receiver.try_recv().is_ok() {}
+    }
+
+    fn app_id(&self, function: &str, value: &Value) -> Result<i64, String> {
+        match value {
+            Value::Int(id) => Ok(*id),
+            other => Err(format!(
+                "{function}() app must be an int, not '{}'",
+                other.type_name()
+            )),
+        }
+    }
+
+    fn app(&mut self, function: &str, value: &Value) -> Result<&mut App, String> {
+        let id = self.app_id(function, value)?;
+        self.apps
+            .get_mut(&id)
+            .ok_or_else(|| format!("{function}(): app {id} does not exist"))
+    }
+
+    /// Start serving app `id` on `port` of `host`, passing its requests on to the module
+    fn serve(&self, id: i64, host: &str, port: u16) -> Result<(JoinHandle<()>, u16), String> {
+        let listener = std::net::TcpListener::bind((host, port)).map_err(|e| e.to_string())?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        let requests = self.sender.clone();
+        let service = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    forward(id, request, requests.clone())
+                }))
+            }
+        });
+        let server = Server::from_tcp(listener)
+            .map_err(|e| e.to_string())?
+            .serve(service);
+        let server = tokio::spawn(async move {
+            let _ = server.await;
+        });
+        Ok((server, port))
+    }
+}
+
+impl App {
+    fn dispatch(&self, method: &str, path: &str) -> Dispatch {
+        let path: Vec<&str> = segments(path).collect();
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            let Some(params) = route.matches(&path) else {
+                continue;
+            };
+            if route.method == "*"
+                || route.method == method
+                || (method == "HEAD" && route.method == "GET")
+            {
+                return Dispatch::Handler(route.handler.clone(), params);
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+        }
+        if matches!(method, "GET" | "HEAD") {
+            for (prefix, directory) in &self.statics {
+                if let Some(file) = static_file(prefix, directory, &path) {
+                    return Dispatch::File(file);
+                }
+            }
+        }
+        if allowed.is_empty() {
+            return Dispatch::Reply(Reply::text(404, "Not Found"));
+        }
+        let mut reply = Reply::text(405, "Method Not Allowed");
+        reply
+            .headers
+            .push(("allow".to_string(), allowed.join(", ")));
+        Dispatch::Reply(reply)
+    }
+}
+
+impl Route {
+    /// The params of `path` if the route's path matches it
+    fn matches(&self, path: &[&str]) -> Option<HashMap<String, Value>> {
+        if path.len() != self.segments.len() {
+            return None;
+        }
+        let mut params = HashMap::new();
+        for (segment, part) in self.segments.iter().zip(path) {
+            match segment.strip_prefix(':') {
+                Some(name) => {
+                    params.insert(name.to_string(), Value::String(decode(part, false)));
+                }
+                None if segment == part => {}
+                None => return None,
+            }
+        }
+        Some(params)
+    }
+}
+
+/// The non-empty segments of `path`
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// The file under `directory` that `path` asks for, if it is below `prefix` and exists
+fn static_file(prefix: &[String], directory: &Path, path: &[&str]) -> Option<PathBuf> {
+    if path.len() < prefix.len()
+        || path
+            .iter()
+            .zip(prefix)
+            .any(|(part, segment)| part != segment)
+    {
+        return None;
+    }
+    let mut file = directory.to_path_buf();
+    for segment in &path[prefix.len()..] {
+        // Checked once decoded, so that `%2e%2e` or `%2f` can't climb out of `directory`
+        let segment = decode(segment, false);
+        let mut components = Path::new(&segment).components();
+        if segment.contains(['/', '\\'])
+            || !matches!(
+                (components.next(), components.next()),
+                (Some(Component::Normal(_)), None)
+            )
+        {
+            return None;
+        }
+        file.push(segment);
+    }
+    if file.is_dir() {
+        file.push("index.html");
+    }
+    // Nor can a link in it
+    let file = file.canonicalize().ok()?;
+    let directory = directory.canonicalize().ok()?;
+    (file.starts_with(directory) && file.is_file()).then_some(file)
+}
+
+async fn send_file(path: PathBuf, reply: oneshot::Sender<Reply>) {
+    let response = match tokio::fs::read(&path).await {
+        Ok(data) => Reply::new(200, content_type(&path), data),
+        Err(_) => Reply::text(404, "Not Found"),
+    };
+    let _ = reply.send(response);
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `text` with its `%XX` escapes, and `+`s if `form` is set, decoded
+fn decode(text: &str, form: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' if form => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The dict handlers get for `incoming`, or the reply for a body that isn't valid JSON
+fn request_value(incoming: &Incoming, params: HashMap<String, Value>) -> Result<Value, Reply> {
+    let query: HashMap<String, Value> = incoming
+        .query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name, true), Value::String(decode(value, true)))
+        })
+        .collect();
+    let mut headers: HashMap<String, Value> = HashMap::new();
+    for (name, value) in &incoming.headers {
+        match headers.get_mut(name) {
+            Some(Value::String(existing)) => {
+                existing.push_str(", ");
+                existing.push_str(value);
+            }
+            _ => {
+                headers.insert(name.clone(), Value::String(value.clone()));
+            }
+        }
+    }
+    let body = match String::from_utf8(incoming.body.clone()) {
+        Ok(text) => Value::String(text),
+        Err(e) => Value::Bytes(e.into_bytes()),
+    };
+    let is_json = match headers.get("content-type") {
+        Some(Value::String(content_type)) => content_type
+            .split(';')
+            .next()
+            .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case("application/json")),
+        _ => false,
+    };
+    let json = match &body {
+        Value::String(text) if is_json && !text.trim().is_empty() => {
+            json::parse(text).map_err(|e| Reply::text(400, &format!("Invalid JSON body: {e}")))?
+        }
+        Value::Bytes(_) if is_json => {
+            return Err(Reply::text(400, "Invalid JSON body: not UTF-8"));
+        }
+        _ => Value::None,
+    };
+    let request = [
+        ("method", Value::String(incoming.method.clone())),
+        ("path", Value::String(incoming.path.clone())),
+        ("query", Value::Dict(query)),
+        ("params", Value::Dict(params)),
+        ("headers", Value::Dict(headers)),
+        ("body", body),
+        ("json", json),
+    ];
+    Ok(Value::Dict(
+        request
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    ))
+}
+
+/// Pass a request of app `app` on to the module and send back the reply it gets
+async fn forward(
+    app: i64,
+    request: Request<Body>,
+    requests: UnboundedSender<Incoming>,
+) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let reply = match hyper::body::to_bytes(body).await {
+        Ok(body) => {
+            let (sender, receiver) = oneshot::channel();
+            let incoming = Incoming {
+                app,
+                method: parts.method.as_str().to_string(),
+                path: parts.uri.path().to_string(),
+                query: parts.uri.query().unwrap_or_default().to_string(),
+                headers: parts
+                    .headers
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                        (name.as_str().to_string(), value)
+                    })
+                    .collect(),
+                body: body.to_vec(),
+                reply: sender,
+            };
+            match requests.send(incoming) {
+                Ok(()) => receiver
+                    .await
+                    .unwrap_or_else(|_| Reply::text(503, "Service Unavailable")),
+                Err(_) => Reply::text(503, "Service Unavailable"),
+            }
+        }
+        Err(_) => Reply::text(400, "Bad Request"),
+    };
+    let mut response = Response::builder().status(reply.status);
+    for (name, value) in &reply.headers {
+        response = response.header(name.as_str(), value.as_str());
+    }
+    Ok(response.body(Body::from(reply.body)).unwrap_or_else(|_| {
+        let mut response = Response::new(Body::from("Internal Server Error"));
+        *response.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::HostCallback;
+    use crate::vm::VM;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// The status line the app listening on `port` answers a GET of `path` with
+    async fn get(servers: &mut HttpServers, port: u16, path: &str) -> String {
+        let request =
+            format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        let mut client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        });
+        let response = loop {
+            tokio::select! {
+                response = &mut client => break response.unwrap(),
+                exchange = servers.next_exchange() => assert!(exchange.is_none()),
+            }
+        };
+        response.lines().next().unwrap_or_default().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_static_files_stay_in_their_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let public = root.path().join("public");
+        std::fs::create_dir_all(public.join("docs")).unwrap();
+        std::fs::write(public.join("app.js"), "run()").unwrap();
+        std::fs::write(public.join("docs").join("index.html"), "<p>docs</p>").unwrap();
+        std::fs::write(root.path().join("secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.path().join("secret.txt"), public.join("link.txt"))
+            .unwrap();
+
+        let mut servers = HttpServers::default();
+        let permissions = Permissions::default();
+        permissions.set_allow_network(true);
+        permissions.set_allow_io(true);
+        servers.set_permissions(permissions);
+        let app = servers.call("http.server.create", &[]).await.unwrap();
+        let directory = Value::String(public.to_string_lossy().into_owned());
+        servers
+            .call(
+                "http.server.static_files",
+                &[app.clone(), Value::String("/static".to_string()), directory],
+            )
+            .await
+            .unwrap();
+        let Ok(Value::Int(port)) = servers
+            .call("http.server.listen", &[app, Value::Int(0)])
+            .await
+        else {
+            panic!("listen() should return the port");
+        };
+        let port = port as u16;
+
+        for path in ["/static/app.js", "/static/docs", "/static/%61pp.js"] {
+            assert_eq!(
+                get(&mut servers, port, path).await,
+                "HTTP/1.1 200 OK",
+                "{path}"
+            );
+        }
+        for path in [
+            "/static/../secret.txt",
+            "/static/%2e%2e/secret.txt",
+            "/static/%2E%2E/%2e%2e/etc/passwd",
+            "/static/%2e%2e/%2e%2e/%2e%2e/%2e%2e/etc/passwd",
+            "/static/..%2fsecret.txt",
+            "/static/..%5csecret.txt",
+            "/static/docs/%2e%2e/%2e%2e/secret.txt",
+            "/static/%2fetc%2fpasswd",
+            "/static/.",
+            "/static/link.txt",
+            "/static/missing.txt",
+        ] {
+            assert_eq!(
+                get(&mut servers, port, path).await,
+                "HTTP/1.1 404 Not Found",
+                "{path}"
+            );
+        }
+    }
+
+    async fn call(vm: &mut VM, function: &str, args: Vec<Value>) -> Result<Value, String> {
+        let function = Value::Builtin(BuiltinFunction {
+            name: function.to_string(),
+            arity: 0,
+        });
+        vm.call(function, args).await
+    }
+
+    /// Register a host function handling requests with `handle`
+    fn handler(
+        vm: &mut VM,
+        name: &str,
+        handle: impl Fn(&HashMap<String, Value>) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Value {
+        let callback = HostCallback::Sync(Arc::new(move |args| match args.as_slice() {
+            [Value::Dict(request)] => handle(request),
+            _ => Err("handlers take the request".to_string()),
+        }));
+        vm.register_host_function(name, callback, None);
+        vm.get_global(name).unwrap().clone()
+    }
+
+    /// The field `name` of a request dict's field `field`
+    fn field(request: &HashMap<String, Value>, field: &str, name: &str) -> Value {
+        match &request[field] {
+            Value::Dict(fields) => fields.get(name).cloned().unwrap_or(Value::None),
+            other => panic!("{field} is a {}", other.type_name()),
+        }
+    }
+
+    /// A request for `target`, with a JSON `body` if it has one
+    fn request(method: &str, target: &str, body: Option<&str>) -> String {
+        let content_type = match body {
+            Some(_) => "Content-Type: application/json\r\n",
+            None => "",
+        };
+        let body = body.unwrap_or_default();
+        format!(
+            "{method} {target} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+             {content_type}Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Send each request to `port` in turn while the VM runs the handlers, returning the
+    /// responses
+    async fn exchange(vm: &mut VM, port: i64, requests: &[String]) -> Vec<String> {
+        let requests = requests.to_vec();
+        let client = tokio::spawn(async move {
+            let mut responses = Vec::new();
+            for request in requests {
+                let mut stream = TcpStream::connect(("127.0.0.1", port as u16))
+                    .await
+                    .unwrap();
+                stream.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+                responses.push(response);
+            }
+            responses
+        });
+        while !client.is_finished() {
+            call(vm, "timers.sleep", vec![Value::Int(10)])
+                .await
+                .unwrap();
+        }
+        client.await.unwrap()
+    }
+
+    /// The status line, lowercase headers and body of a response
+    fn parse(response: &str) -> (&str, HashMap<String, &str>, &str) {
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let mut lines = head.lines();
+        let status = lines.next().unwrap();
+        let headers = lines
+            .filter_map(|line| line.split_once(": "))
+            .map(|(name, value)| (name.to_lowercase(), value))
+            .collect();
+        (status, headers, body)
+    }
+
+    /// A VM serving an app with its `routes`, and the port it listens on
+    async fn serve(vm: &mut VM, routes: Vec<(&str, &str, Value)>) -> i64 {
+        vm.set_allow_network(true);
+        let app = call(vm, "http.server.create", vec![]).await.unwrap();
+        for (method, path, handler) in routes {
+            let args = vec![
+                app.clone(),
+                Value::String(method.to_string()),
+                Value::String(path.to_string()),
+                handler,
+            ];
+            call(vm, "http.server.route", args).await.unwrap();
+        }
+        match call(vm, "http.server.listen", vec![app, Value::Int(0)]).await {
+            Ok(Value::Int(port)) => port,
+            other => panic!("listen() returned {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes_pass_requests_to_their_handlers() {
+        let mut vm = VM::new(false);
+        let user = handler(&mut vm, "user", |request| {
+            Ok(Value::List(vec![
+                request["method"].clone(),
+                field(request, "params", "id"),
+                field(request, "query", "sort"),
+            ]))
+        });
+        let create = handler(&mut vm, "create", |request| match &request["json"] {
+            Value::Dict(json) => Ok(json["name"].clone()),
+            other => Err(format!("expected a JSON object, got {other}")),
+        });
+        let any = handler(&mut vm, "any", |_| Ok(Value::None));
+        let port = serve(
+            &mut vm,
+            vec![
+                ("GET", "/users/:id", user),
+                ("post", "/users", create),
+                ("*", "/any", any),
+            ],
+        )
+        .await;
+
+        let json = r#"{"name": "ada"}"#;
+        let responses = exchange(
+            &mut vm,
+            port,
+            &[
+                request("GET", "/users/ada%20l?sort=name", None),
+                request("POST", "/users", Some(json)),
+                request("POST", "/users", Some("{")),
+                request("DELETE", "/users/1", None),
+                request("PUT", "/users", None),
+                request("PATCH", "/any", None),
+                request("GET", "/users/1/posts", None),
+            ],
+        )
+        .await;
+        let responses: Vec<_> = responses.iter().map(|response| parse(response)).collect();
+
+        let (status, headers, body) = &responses[0];
+        assert_eq!(*status, "HTTP/1.1 200 OK");
+        assert_eq!(headers["content-type"], "application/json");
+        assert_eq!(*body, r#"["GET","ada l","name"]"#);
+
+        let (status, headers, body) = &responses[1];
+        assert_eq!(*status, "HTTP/1.1 200 OK");
+        assert_eq!(headers["content-type"], "text/plain; charset=utf-8");
+        assert_eq!(*body, "ada");
+
+        assert_eq!(responses[2].0, "HTTP/1.1 400 Bad Request");
+        assert!(responses[2].2.starts_with("Invalid JSON body: "));
+
+        let (status, headers, _) = &responses[3];
+        assert_eq!(*status, "HTTP/1.1 405 Method Not Allowed");
+        assert_eq!(headers["allow"], "GET");
+        let (status, headers, _) = &responses[4];
+        assert_eq!(*status, "HTTP/1.1 405 Method Not Allowed");
+        assert_eq!(headers["allow"], "POST");
+
+        assert_eq!(responses[5].0, "HTTP/1.1 204 No Content");
+        assert_eq!(responses[6].0, "HTTP/1.1 404 Not Found");
+    }
+
+    #[tokio::test]
+    async fn test_a_failing_handler_gets_500_and_ends_the_run() {
+        let mut vm = VM::new(false);
+        let fail = handler(&mut vm, "fail", |_| Err("no such user".to_string()));
+        let port = serve(&mut vm, vec![("GET", "/", fail)]).await;
+
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(("127.0.0.1", port as u16))
+                .await
+                .unwrap();
+            let request = request("GET", "/", None);
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        });
+        let error = call(&mut vm, "timers.sleep", vec![Value::Int(5000)])
+            .await
+            .unwrap_err();
+        assert!(error.contains("no such user"), "{error}");
+        let response = client.await.unwrap();
+        assert_eq!(parse(&response).0, "HTTP/1.1 500 Internal Server Error");
+    }
+
+    #[tokio::test]
+    async fn test_listening_needs_network_access() {
+        let mut vm = VM::new(false);
+        let app = call(&mut vm, "http.server.create", vec![]).await.unwrap();
+        vm.start_access_report();
+        assert_eq!(
+            call(
+                &mut vm,
+                "http.server.listen",
+                vec![app.clone(), Value::Int(8080)]
+            )
+            .await,
+            Err("listen(): network operations are not allowed".to_string())
+        );
+        let every_interface = Value::String("0.0.0.0".to_string());
+        assert_eq!(
+            call(
+                &mut vm,
+                "http.server.listen",
+                vec![app, Value::Int(8080), every_interface]
+            )
+            .await,
+            Err("listen(): network operations are not allowed".to_string())
+        );
+        let accesses = vm.finish_access_report();
+        let permissions: Vec<_> = accesses
+            .into_iter()
+            .map(|access| access.permission)
+            .collect();
+        assert_eq!(
+            permissions,
+            [
+                Permission::Network("127.0.0.1:8080".to_string()),
+                Permission::Network("0.0.0.0:8080".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serving_files_needs_io_access_to_the_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let mut vm = VM::new(false);
+        vm.set_allow_network(true);
+        let app = call(&mut vm, "http.server.create", vec![]).await.unwrap();
+        vm.start_access_report();
+        // Asked about where the files really are, not the path as the script wrote it
+        let directory = root.path().join("public").join("..");
+        let args = vec![
+            app.clone(),
+            Value::String("/".to_string()),
+            Value::String(directory.to_string_lossy().into_owned()),
+        ];
+        // A directory that doesn't exist fails the same way
+        assert_eq!(
+            call(&mut vm, "http.server.static_files", args.clone()).await,
+            Err("static_files(): IO operations are not allowed".to_string())
+        );
+        std::fs::create_dir(root.path().join("public")).unwrap();
+        assert_eq!(
+            call(&mut vm, "http.server.static_files", args.clone()).await,
+            Err("static_files(): IO operations are not allowed".to_string())
+        );
+        let accesses = vm.finish_access_report();
+        let canonical = root.path().canonicalize().unwrap();
+        let permissions: Vec<_> = accesses.iter().map(|access| &access.permission).collect();
+        assert_eq!(
+            permissions,
+            [
+                &Permission::Io(directory.to_string_lossy().into_owned()),
+                &Permission::Io(canonical.display().to_string())
+            ]
+        );
+        assert!(accesses.iter().all(|access| !access.allowed));
+
+        vm.set_allow_io(true);
+        assert_eq!(
+            call(&mut vm, "http.server.static_files", args).await,
+            Ok(Value::None)
+        );
+    }
+}
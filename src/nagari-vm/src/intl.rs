@@ -0,0 +1,445 @@
+//! The `intl` module: `format_number(value[, locale, decimals])`,
+//! `format_currency(amount, currency[, locale])`, `format_percent(value[, locale, decimals])`,
+//! `format_date(date[, locale, style])`, `format_time(date[, locale, style])` and
+//! `format_datetime(date[, locale, date_style, time_style])`.
+//!
+//! Locales are BCP 47 tags such as `"en-US"` or `"de-DE"`, `"en-US"` by default. Numbers and
+//! dates are formatted with ICU4X's CLDR data, the same rules JavaScript's `Intl` follows, so
+//! both backends agree on digits, separators, grouping and month names. Numbers show up to 3
+//! decimals unless `decimals` fixes how many, percents none and currencies as many as the
+//! currency has. Currency symbols are the narrow ones (`$`, `€`, `£`, ...), placed as the
+//! locale's language places them; currencies without a known symbol show their code.
+//!
+//! Dates are `date` values or milliseconds since the epoch, formatted in UTC. Date styles are
+//! `"short"`, `"medium"`, `"long"` and `"full"`, time styles `"short"` and `"medium"`.
+
+use crate::value::{civil_date, BuiltinFunction, Value};
+use fixed_decimal::{FixedDecimal, FloatPrecision};
+use icu_calendar::{DateTime, Gregorian};
+use icu_datetime::options::length;
+use icu_datetime::TypedDateTimeFormatter;
+use icu_decimal::FixedDecimalFormatter;
+use icu_locid::Locale;
+use std::collections::HashMap;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "intl.";
+
+/// Locale used when none is given
+const DEFAULT_LOCALE: &str = "en-US";
+
+/// Non-breaking space CLDR puts between amounts and symbols
+const NBSP: char = '\u{a0}';
+
+/// The `intl` module: a dict of builtins named `intl.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("format_number", 1),
+        ("format_currency", 2),
+        ("format_percent", 1),
+        ("format_date", 1),
+        ("format_time", 1),
+        ("format_datetime", 1),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Call the builtin `name` of the module
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let function = &name[PREFIX.len()..];
+    let text = match (function, args) {
+        ("format_number", [value, rest @ ..]) if rest.len() <= 2 => {
+            let locale = locale(function, rest.first())?;
+            let (min, max) = match decimals(function, rest.get(1))? {
+                Some(decimals) => (decimals, decimals),
+                None => (0, 3),
+            };
+            number(&locale, decimal(function, value)?, min, max)?
+        }
+        ("format_percent", [value, rest @ ..]) if rest.len() <= 2 => {
+            let locale = locale(function, rest.first())?;
+            let decimals = decimals(function, rest.get(1))?.unwrap_or(0);
+            let mut value = decimal(function, value)?;
+            value.multiply_pow10(2);
+            let number = number(&locale, value, decimals, decimals)?;
+            percent(&locale, number)
+        }
+        ("format_currency", [amount, Value::String(currency), rest @ ..]) if rest.len() <= 1 => {
+            let locale = locale(function, rest.first())?;
+            let currency = currency.to_uppercase();
+            if currency.len() != 3 || !currency.bytes().all(|c| c.is_ascii_alphabetic()) {
+                return Err(format!(
+                    "format_currency() currency must be an ISO 4217 code such as \"USD\", got {currency}"
+                ));
+            }
+            let digits = currency_digits(&currency);
+            let number = number(&locale, decimal(function, amount)?, digits, digits)?;
+            money(&locale, &currency, number)
+        }
+        ("format_currency", [_, other, ..]) => {
+            return Err(format!(
+                "format_currency() currency must be a str, not '{}'",
+                other.type_name()
+            ))
+        }
+        ("format_date" | "format_time" | "format_datetime", [date, rest @ ..])
+            if rest.len() <= if function == "format_datetime" { 3 } else { 2 } =>
+        {
+            let locale = locale(function, rest.first())?;
+            let styles = &rest[rest.len().min(1)..];
+            let options = match function {
+                "format_date" => {
+                    length::Bag::from_date_style(date_style(function, styles.first())?)
+                }
+                "format_time" => {
+                    length::Bag::from_time_style(time_style(function, styles.first())?)
+                }
+                _ => length::Bag::from_date_time_style(
+                    date_style(function, styles.first())?,
+                    time_style(function, styles.get(1))?,
+                ),
+            };
+            let formatter =
+                TypedDateTimeFormatter::<Gregorian>::try_new(&(&locale).into(), options.into())
+                    .map_err(|e| format!("{function}(): {e}"))?;
+            formatter.format_to_string(&datetime(function, date)?)
+        }
+        (
+            "format_number" | "format_percent" | "format_currency" | "format_date" | "format_time"
+            | "format_datetime",
+            _,
+        ) => {
+            return Err(format!(
+                "{function}() takes {} arguments ({} given)",
+                match function {
+                    "format_currency" => "2 or 3",
+                    "format_datetime" => "1 to 4",
+                    _ => "1 to 3",
+                },
+                args.len()
+            ))
+        }
+        _ => return Err(format!("Unknown builtin function: {name}")),
+    };
+    Ok(Value::String(text))
+}
+
+fn locale(function: &str, value: Option<&Value>) -> Result<Locale, String> {
+    match value {
+        None | Some(Value::None) => Ok(DEFAULT_LOCALE.parse().expect("valid locale")),
+        Some(Value::String(tag)) => tag
+            .parse()
+            .map_err(|_| format!("{function}() locale '{tag}' is not a valid locale tag")),
+        Some(other) => Err(format!(
+            "{function}() locale must be a str, not '{}'",
+            other.type_name()
+        )),
+    }
+}
+
+fn decimals(function: &str, value: Option<&Value>) -> Result<Option<i16>, String> {
+    match value {
+        None | Some(Value::None) => Ok(None),
+        Some(Value::Int(n)) if (0..=20).contains(n) => Ok(Some(*n as i16)),
+        Some(other) => Err(format!(
+            "{function}() decimals must be an int from 0 to 20, got {other}"
+        )),
+    }
+}
+
+fn decimal(function: &str, value: &Value) -> Result<FixedDecimal, String> {
+    match value {
+        Value::Int(n) => Ok(FixedDecimal::from(*n)),
+        Value::Float(x) => FixedDecimal::try_from_f64(*x, FloatPrecision::Floating)
+            .map_err(|_| format!("{function}() cannot format {x}")),
+        other => Err(format!(
+            "{function}() value must be a number, not '{}'",
+            other.type_name()
+        )),
+    }
+}
+
+/// `value` rounded half away from zero to between `min` and `max` decimals, in `locale`'s
+/// digits and separators
+fn number(locale: &Locale, mut value: FixedDecimal, min: i16, max: i16) -> Result<String, String> {
+    value.half_expand(-max);
+    value.trim_start();
+    value.trim_end();
+    value.pad_end(-min);
+    let formatter = FixedDecimalFormatter::try_new(&locale.into(), Default::default())
+        .map_err(|e| format!("cannot format numbers for '{locale}': {e}"))?;
+    Ok(formatter.format_to_string(&value))
+}
+
+/// Languages writing `50 %` rather than `50%`
+const SPACED_PERCENT: &[&str] = &[
+    "ca", "cs", "da", "de", "es", "fi", "fr", "hr", "lt", "nb", "no", "ro", "ru", "sk", "sl", "sv",
+];
+
+fn percent(locale: &Locale, number: String) -> String {
+    let language = locale.id.language.as_str();
+    match language {
+        "tr" => format!("%{number}"),
+        // Latin American Spanish has no space
+        "es" if locale
+            .id
+            .region
+            .is_some_and(|region| region.as_str() != "ES") =>
+        {
+            format!("{number}%")
+        }
+        _ if SPACED_PERCENT.contains(&language) => format!("{number}{NBSP}%"),
+        _ => format!("{number}%"),
+    }
+}
+
+/// Languages writing the currency symbol after the amount
+const SYMBOL_AFTER: &[&str] = &[
+    "bg", "ca", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu", "it", "lt", "lv", "nb",
+    "no", "pl", "ro", "ru", "sk", "sl", "sv", "uk", "vi",
+];
+
+fn money(locale: &Locale, currency: &str, number: String) -> String {
+    let symbol = currency_symbol(currency).unwrap_or(currency);
+    // Symbols ending in a letter, like codes, are spaced from an amount after them
+    let spacing = match symbol.ends_with(char::is_alphabetic) {
+        true => NBSP.to_string(),
+        false => String::new(),
+    };
+    // The sign goes before a symbol written before the amount
+    let digits = number
+        .find(|c: char| c.is_numeric())
+        .unwrap_or(number.len());
+    let (sign, amount) = number.split_at(digits);
+    let region = locale.id.region.map(|region| region.to_string());
+    match (locale.id.language.as_str(), region.as_deref()) {
+        ("es", Some(region)) if region != "ES" => format!("{sign}{symbol}{spacing}{amount}"),
+        ("pt", Some("PT")) => format!("{number}{NBSP}{symbol}"),
+        ("pt", _) => format!("{sign}{symbol}{NBSP}{amount}"),
+        ("nl", _) => format!("{symbol}{NBSP}{number}"),
+        (language, _) if SYMBOL_AFTER.contains(&language) => format!("{number}{NBSP}{symbol}"),
+        _ => format!("{sign}{symbol}{spacing}{amount}"),
+    }
+}
+
+/// The narrow symbol of `currency`, for the currencies that have one
+fn currency_symbol(currency: &str) -> Option<&'static str> {
+    Some(match currency {
+        "USD" | "CAD" | "AUD" | "NZD" | "MXN" | "SGD" | "HKD" | "ARS" | "CLP" | "COP" => "$",
+        "EUR" => "€",
+        "GBP" => "£",
+        "JPY" | "CNY" => "¥",
+        "INR" => "₹",
+        "KRW" => "₩",
+        "BRL" => "R$",
+        "RUB" => "₽",
+        "UAH" => "₴",
+        "TRY" => "₺",
+        "ILS" => "₪",
+        "NGN" => "₦",
+        "PHP" => "₱",
+        "VND" => "₫",
+        "THB" => "฿",
+        "PLN" => "zł",
+        "SEK" | "NOK" | "DKK" | "ISK" => "kr",
+        _ => return None,
+    })
+}
+
+/// Number of decimals of amounts in `currency`
+fn currency_digits(currency: &str) -> i16 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+fn date_style(function: &str, value: Option<&Value>) -> Result<length::Date, String> {
+    match value {
+        None | Some(Value::None) => Ok(length::Date::Medium),
+        Some(Value::String(style)) => match style.as_str() {
+            "short" => Ok(length::Date::Short),
+            "medium" => Ok(length::Date::Medium),
+            "long" => Ok(length::Date::Long),
+            "full" => Ok(length::Date::Full),
+            _ => Err(format!(
+                "{function}() date style must be \"short\", \"medium\", \"long\" or \"full\", got {style}"
+            )),
+        },
+        Some(other) => Err(format!(
+            "{function}() style must be a str, not '{}'",
+            other.type_name()
+        )),
+    }
+}
+
+fn time_style(function: &str, value: Option<&Value>) -> Result<length::Time, String> {
+    match value {
+        None | Some(Value::None) => Ok(length::Time::Short),
+        Some(Value::String(style)) => match style.as_str() {
+            "short" => Ok(length::Time::Short),
+            "medium" => Ok(length::Time::Medium),
+            _ => Err(format!(
+                "{function}() time style must be \"short\" or \"medium\", got {style}"
+            )),
+        },
+        Some(other) => Err(format!(
+            "{function}() style must be a str, not '{}'",
+            other.type_name()
+        )),
+    }
+}
+
+/// The UTC date and time of a date or milliseconds since the epoch
+fn datetime(function: &str, value: &Value) -> Result<DateTime<Gregorian>, String> {
+    let millis = match value {
+        Value::Date(millis) | Value::Float(millis) => *millis,
+        Value::Int(millis) => *millis as f64,
+        other => {
+            return Err(format!(
+                "{function}() date must be a date or milliseconds since the epoch, not '{}'",
+                other.type_name()
+            ))
+        }
+    };
+    if !millis.is_finite() {
+        return Err(format!("{function}(): invalid date"));
+    }
+    let millis = millis as i64;
+    let (year, month, day) = civil_date(millis.div_euclid(86_400_000));
+    let seconds = millis.rem_euclid(86_400_000) / 1000;
+    i32::try_from(year)
+        .ok()
+        .and_then(|year| {
+            DateTime::try_new_gregorian_datetime(
+                year,
+                month as u8,
+                day as u8,
+                (seconds / 3600) as u8,
+                (seconds / 60 % 60) as u8,
+                (seconds % 60) as u8,
+            )
+            .ok()
+        })
+        .ok_or_else(|| format!("{function}(): date out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn format(function: &str, args: &[Value]) -> String {
+        match call(&format!("{PREFIX}{function}"), args) {
+            Ok(Value::String(text)) => text,
+            other => panic!("{function}() returned {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_numbers_use_the_locale_separators() {
+        assert_eq!(
+            format("format_number", &[Value::Float(1234567.8915)]),
+            "1,234,567.892"
+        );
+        assert_eq!(
+            format(
+                "format_number",
+                &[Value::Float(1234.5), string("de-DE"), Value::Int(2)]
+            ),
+            "1.234,50"
+        );
+        assert_eq!(
+            format("format_number", &[Value::Int(-1234), string("fr-FR")]),
+            "-1\u{202f}234"
+        );
+        assert_eq!(format("format_percent", &[Value::Float(0.256)]), "26%");
+        assert_eq!(
+            format(
+                "format_percent",
+                &[Value::Float(0.256), string("de-DE"), Value::Int(1)]
+            ),
+            "25,6\u{a0}%"
+        );
+        assert_eq!(
+            format("format_percent", &[Value::Float(0.5), string("tr-TR")]),
+            "%50"
+        );
+    }
+
+    #[test]
+    fn test_currencies_place_their_symbols_and_decimals() {
+        for (amount, currency, locale, expected) in [
+            (Value::Float(-1234.5), "USD", "en-US", "-$1,234.50"),
+            (Value::Float(1234.5), "eur", "de-DE", "1.234,50\u{a0}€"),
+            (Value::Float(1234.5), "JPY", "en-US", "¥1,235"),
+            (Value::Int(5), "CHF", "en-US", "CHF\u{a0}5.00"),
+        ] {
+            assert_eq!(
+                format(
+                    "format_currency",
+                    &[amount, string(currency), string(locale)]
+                ),
+                expected,
+                "{currency} in {locale}"
+            );
+        }
+        assert_eq!(
+            call("intl.format_currency", &[Value::Int(1), string("US")]).unwrap_err(),
+            "format_currency() currency must be an ISO 4217 code such as \"USD\", got US"
+        );
+    }
+
+    #[test]
+    fn test_dates_are_formatted_in_utc() {
+        let moment = Value::Int(1_700_000_000_000);
+        assert_eq!(format("format_date", &[Value::Date(0.0)]), "Jan 1, 1970");
+        assert_eq!(
+            format("format_date", &[Value::Int(0), Value::None, string("full")]),
+            "Thursday, January 1, 1970"
+        );
+        assert_eq!(
+            format(
+                "format_date",
+                &[moment.clone(), string("de-DE"), string("long")]
+            ),
+            "14. November 2023"
+        );
+        assert_eq!(
+            format("format_time", std::slice::from_ref(&moment)),
+            "10:13\u{202f}PM"
+        );
+        assert_eq!(
+            format(
+                "format_datetime",
+                &[moment, string("en-GB"), string("short"), string("medium")]
+            ),
+            "14/11/2023, 22:13:20"
+        );
+        assert_eq!(
+            call(
+                "intl.format_time",
+                &[Value::Int(0), Value::None, string("long")]
+            )
+            .unwrap_err(),
+            "format_time() time style must be \"short\" or \"medium\", got long"
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! The `json` module: `loads(text)` and `dumps(value[, indent, canonical])`.
+//!
+//! Numbers without a fraction or exponent load as ints if they fit in one, and as floats
+//! otherwise. Dicts are written with their keys sorted, so the same value always gives the same
+//! text; maps are written as objects, in insertion order, and need str keys. Sets are written as
+//! arrays and dates as ISO 8601 strings. Floats are written in their shortest form that reads
+//! back to the same float, with a fraction or an exponent so they don't load as ints. NaN,
+//! infinities, bytes and functions can't be written.
+//!
+//! Canonical output, from `dumps(value, canonical=true)` or [`Value::to_canonical_json`], is
+//! meant for comparing and hashing values: it has no whitespace, map keys and set elements are
+//! sorted too, and `-0.0` is written as `0.0`, so values holding the same data give the same
+//! text whatever order their sets and maps were built in.
+
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "json.";
+
+/// The `json` module: a dict of builtins named `json.<function>`
+pub fn module() -> Value {
+    let functions = [("loads", 1), ("dumps", 1)];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Names of the parameters of the builtin `name` of the module, as `stdlib/json.nag` declares
+/// them
+pub fn parameters(name: &str) -> Option<&'static [&'static str]> {
+    match &name[PREFIX.len()..] {
+        "loads" => Some(&["json_str"]),
+        "dumps" => Some(&["obj", "indent", "canonical"]),
+        _ => None,
+    }
+}
+
+/// Call the builtin `name` of the module
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let function = &name[PREFIX.len()..];
+    match (function, args) {
+        ("loads", [Value::String(text)]) => parse(text).map_err(|e| format!("{function}(): {e}")),
+        ("loads", [other]) => Err(format!(
+            "{function}() text must be a str, not '{}'",
+            other.type_name()
+        )),
+        ("loads", _) => Err(arguments(function, "exactly 1", args)),
+        ("dumps", [value, options @ ..]) if options.len() <= 2 => {
+            let indent = match options.first() {
+                None | Some(Value::None) => None,
+                Some(Value::Int(indent)) if *indent >= 0 => Some(*indent as usize),
+                Some(other) => {
+                    return Err(format!(
+                        "{function}() indent must be a non-negative int or none, not {other}"
+                    ))
+                }
+            };
+            let canonical = match options.get(1) {
+                None => false,
+                Some(Value::Bool(canonical)) => *canonical,
+                Some(other) => {
+                    return Err(format!(
+                        "{function}() canonical must be a bool, not '{}'",
+                        other.type_name()
+                    ))
+                }
+            };
+            if canonical && indent.is_some() {
+                return Err(format!("{function}(): canonical output cannot be indented"));
+            }
+            let style = Style { indent, canonical };
+            stringify(value, &style)
+                .map(Value::String)
+                .map_err(|e| format!("{function}(): {e}"))
+        }
+        ("dumps", _) => Err(arguments(function, "1 to 3", args)),
+        _ => Err(format!("Unknown builtin function: {name}")),
+    }
+}
+
+fn arguments(function: &str, expected: &str, args: &[Value]) -> String {
+    format!(
+        "{function}() takes {expected} arguments ({} given)",
+        args.len()
+    )
+}
+
+/// The value of the JSON `text`
+pub fn parse(text: &str) -> Result<Value, String> {
+    let json: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("invalid JSON: {e}"))?;
+    Ok(from_serde(json))
+}
+
+fn from_serde(json: serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::None,
+        serde_json::Value::Bool(b) => Value::Bool(b),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(n) => Value::Int(n),
+            None => Value::Float(number.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Array(items) => Value::List(items.into_iter().map(from_serde).collect()),
+        serde_json::Value::Object(entries) => Value::Dict(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, from_serde(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// How [`stringify`] writes a value
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Style {
+    /// Spaces per level of nesting, with one member per line; `None` for no whitespace
+    pub indent: Option<usize>,
+    /// Sort map keys and set elements, and write `-0.0` as `0.0`
+    pub canonical: bool,
+}
+
+impl Style {
+    #[allow(dead_code)] // Used by embedding hosts
+    pub const CANONICAL: Style = Style {
+        indent: None,
+        canonical: true,
+    };
+}
+
+/// The JSON text of `value`
+pub fn stringify(value: &Value, style: &Style) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(&mut out, value, style, 0)?;
+    Ok(out)
+}
+
+fn write_value(out: &mut String, value: &Value, style: &Style, depth: usize) -> Result<(), String> {
+    match value {
+        Value::None => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Int(n) => out.push_str(&n.to_string()),
+        Value::Float(float) => write_float(out, *float, style)?,
+        Value::String(s) => write_string(out, s),
+        Value::Date(_) => write_string(out, &value.to_string()),
+        Value::List(items) => write_array(out, items.iter().collect(), style, depth)?,
+        Value::Set(items) | Value::FrozenSet(items) => {
+            let mut items: Vec<&Value> = items.iter().collect();
+            if style.canonical {
+                // The order of a set is incidental, so its elements are sorted by their text
+                let mut texts = items
+                    .into_iter()
+                    .map(|item| Ok((stringify(item, style)?, item)))
+                    .collect::<Result<Vec<_>, String>>()?;
+                texts.sort_by(|(a, _), (b, _)| a.cmp(b));
+                items = texts.into_iter().map(|(_, item)| item).collect();
+            }
+            write_array(out, items, style, depth)?;
+        }
+        Value::Dict(entries) => {
+            let mut entries: Vec<(&str, &Value)> = entries
+                .iter()
+                .map(|(key, value)| (key.as_str(), value))
+                .collect();
+            entries.sort_by_key(|(key, _)| *key);
+            write_object(out, entries, style, depth)?;
+        }
+        Value::Map(entries) => {
+            let mut members = Vec::with_capacity(entries.len());
+            for (key, value) in entries {
+                match key {
+                    Value::String(key) => members.push((key.as_str(), value)),
+                    other => {
+                        return Err(format!("map keys must be str, not '{}'", other.type_name()))
+                    }
+                }
+            }
+            if style.canonical {
+                members.sort_by_key(|(key, _)| *key);
+            }
+            write_object(out, members, style, depth)?;
+        }
+        Value::Bytes(_)
+        | Value::Function(_)
+        | Value::Builtin(_)
+        | Value::Pending(_)
+        | Value::Class(_)
+        | Value::Instance(_)
+        | Value::Method(_)
+        | Value::Super(_)
+        | Value::Resource(_)
+        | Value::WeakRef(_)
+        | Value::Native(_)
+        | Value::NativeMethod(_)
+        | Value::Handle(..) => {
+            return Err(format!(
+                "'{}' objects are not JSON serializable",
+                value.type_name()
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Write `float` in the shortest form that reads back to it, which Rust's `Debug` gives,
+/// keeping a fraction or exponent
+fn write_float(out: &mut String, float: f64, style: &Style) -> Result<(), String> {
+    if !float.is_finite() {
+        return Err(format!("{float} is not a valid JSON number"));
+    }
+    let float = if style.canonical && float == 0.0 {
+        0.0
+    } else {
+        float
+    };
+    out.push_str(&format!("{float:?}"));
+    Ok(())
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_array(
+    out: &mut String,
+    items: Vec<&Value>,
+    style: &Style,
+    depth: usize,
+) -> Result<(), String> {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        separate(out, i, style, depth + 1);
+        write_value(out, item, style, depth + 1)?;
+    }
+    close(out, items.is_empty(), style, depth);
+    out.push(']');
+    Ok(())
+}
+
+fn write_object(
+    out: &mut String,
+    members: Vec<(&str, &Value)>,
+    style: &Style,
+    depth: usize,
+) -> Result<(), String> {
+    out.push('{');
+    for (i, (key, value)) in members.iter().enumerate() {
+        separate(out, i, style, depth + 1);
+        write_string(out, key);
+        out.push_str(if style.indent.is_some() { ": " } else { ":" });
+        write_value(out, value, style, depth + 1)?;
+    }
+    close(out, members.is_empty(), style, depth);
+    out.push('}');
+    Ok(())
+}
+
+/// Start the member at `index` of a container nested `depth` deep
+fn separate(out: &mut String, index: usize, style: &Style, depth: usize) {
+    if index > 0 {
+        out.push(',');
+    }
+    if let Some(indent) = style.indent {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * depth));
+    }
+}
+
+/// End a container nested `depth` deep
+fn close(out: &mut String, empty: bool, style: &Style, depth: usize) {
+    if let (Some(indent), false) = (style.indent, empty) {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * depth));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn dumps(args: &[Value]) -> Result<Value, String> {
+        call("json.dumps", args)
+    }
+
+    #[test]
+    fn test_keys_are_sorted() {
+        let nested = Value::Dict(HashMap::from([
+            ("b".to_string(), Value::Int(2)),
+            ("a".to_string(), Value::Int(1)),
+        ]));
+        let dict = Value::Dict(HashMap::from([
+            ("zeta".to_string(), Value::None),
+            ("alpha".to_string(), nested),
+            ("Beta".to_string(), Value::Bool(true)),
+        ]));
+        assert_eq!(
+            stringify(&dict, &Style::default()).unwrap(),
+            r#"{"Beta":true,"alpha":{"a":1,"b":2},"zeta":null}"#
+        );
+
+        // Maps keep their insertion order, unless the output is canonical
+        let map = Value::Map(vec![
+            (string("b"), Value::Int(2)),
+            (string("a"), Value::Int(1)),
+        ]);
+        assert_eq!(
+            stringify(&map, &Style::default()).unwrap(),
+            r#"{"b":2,"a":1}"#
+        );
+        assert_eq!(map.to_canonical_json().unwrap(), r#"{"a":1,"b":2}"#);
+        let set = Value::Set(vec![
+            string("b"),
+            Value::Int(10),
+            Value::Int(9),
+            string("a"),
+        ]);
+        assert_eq!(
+            stringify(&set, &Style::default()).unwrap(),
+            r#"["b",10,9,"a"]"#
+        );
+        assert_eq!(set.to_canonical_json().unwrap(), r#"["a","b",10,9]"#);
+
+        // Sets and maps built in any order give the same canonical text
+        let reordered = Value::Map(vec![
+            (string("a"), Value::Int(1)),
+            (string("b"), Value::Int(2)),
+        ]);
+        assert_eq!(
+            map.to_canonical_json().unwrap(),
+            reordered.to_canonical_json().unwrap()
+        );
+        assert_eq!(
+            Value::Map(vec![(Value::Int(1), Value::None)])
+                .to_canonical_json()
+                .unwrap_err(),
+            "map keys must be str, not 'int'"
+        );
+    }
+
+    #[test]
+    fn test_numbers_use_their_shortest_form() {
+        let cases = [
+            (Value::Int(-42), "-42"),
+            (Value::Int(i64::MAX), "9223372036854775807"),
+            (Value::Float(1.0), "1.0"),
+            (Value::Float(0.1), "0.1"),
+            (Value::Float(0.1 + 0.2), "0.30000000000000004"),
+            (Value::Float(1e21), "1e21"),
+            (Value::Float(1.5e-7), "1.5e-7"),
+            (Value::Float(-0.0), "-0.0"),
+        ];
+        for (value, text) in cases {
+            assert_eq!(stringify(&value, &Style::default()).unwrap(), text);
+            // Every float reads back as the same float
+            assert_eq!(parse(text).unwrap(), value);
+        }
+        assert_eq!(Value::Float(-0.0).to_canonical_json().unwrap(), "0.0");
+        assert_eq!(
+            Value::Float(f64::NAN).to_canonical_json().unwrap_err(),
+            "NaN is not a valid JSON number"
+        );
+        assert_eq!(
+            dumps(&[Value::Float(f64::INFINITY)]).unwrap_err(),
+            "dumps(): inf is not a valid JSON number"
+        );
+        assert_eq!(
+            parse("12345678901234567890").unwrap(),
+            Value::Float(1.2345678901234567e19)
+        );
+    }
+
+    #[test]
+    fn test_strings_are_escaped() {
+        let text = "quote \" backslash \\ newline \n tab \t bell \u{7} form \u{c} back \u{8} é 😀";
+        let json = stringify(&string(text), &Style::default()).unwrap();
+        assert_eq!(
+            json,
+            r#""quote \" backslash \\ newline \n tab \t bell \u0007 form \f back \b é 😀""#
+        );
+        assert_eq!(parse(&json).unwrap(), string(text));
+
+        // Keys are escaped like values
+        let dict = Value::Dict(HashMap::from([("a\"b".to_string(), string("</script>"))]));
+        assert_eq!(dict.to_canonical_json().unwrap(), r#"{"a\"b":"</script>"}"#);
+    }
+
+    #[test]
+    fn test_dumps_options() {
+        let value = Value::List(vec![
+            Value::Int(1),
+            Value::Dict(HashMap::from([("a".to_string(), Value::List(vec![]))])),
+        ]);
+        assert_eq!(
+            dumps(std::slice::from_ref(&value)).unwrap(),
+            string(r#"[1,{"a":[]}]"#)
+        );
+        assert_eq!(
+            dumps(&[value.clone(), Value::Int(2)]).unwrap(),
+            string("[\n  1,\n  {\n    \"a\": []\n  }\n]")
+        );
+        // Keyword arguments don't compile to bytecode, so canonical output is asked for
+        // positionally
+        assert_eq!(
+            dumps(&[
+                Value::Set(vec![Value::Int(2), Value::Int(1)]),
+                Value::None,
+                Value::Bool(true)
+            ])
+            .unwrap(),
+            string("[1,2]")
+        );
+        assert_eq!(
+            dumps(&[value.clone(), Value::Int(2), Value::Bool(true)]).unwrap_err(),
+            "dumps(): canonical output cannot be indented"
+        );
+        assert_eq!(
+            dumps(&[value.clone(), Value::None, Value::Int(1)]).unwrap_err(),
+            "dumps() canonical must be a bool, not 'int'"
+        );
+        assert_eq!(
+            dumps(&[Value::Bytes(vec![1])]).unwrap_err(),
+            "dumps(): 'bytes' objects are not JSON serializable"
+        );
+        assert_eq!(
+            call("json.loads", &[string("{")]).unwrap_err(),
+            "loads(): invalid JSON: EOF while parsing an object at line 1 column 1"
+        );
+    }
+}
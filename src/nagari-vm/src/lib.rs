@@ -2,13 +2,52 @@
 // Re-export internal modules for external use
 pub mod builtins;
 pub mod bytecode;
+pub mod cache;
+pub mod crypto;
+pub mod csv;
 pub mod env;
+pub mod events;
+pub mod heap;
+pub mod host;
+pub mod html;
+#[cfg(feature = "http-server")]
+pub mod http_server;
+pub mod intl;
+pub mod json;
+pub mod limits;
+pub mod modules;
+pub mod native;
+pub mod packing;
+pub mod path;
+pub mod permissions;
+pub mod plugin;
+pub mod resources;
+pub mod schedule;
+pub mod schema;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod timers;
 pub mod value;
 pub mod vm;
+pub mod weakref;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+pub mod yielding;
 
 // Expose VM and value types for external use
 pub use vm::VM;
+pub use cache::{BytecodeCache, CacheStats};
+pub use heap::{HeapDiff, HeapSnapshot};
 pub use value::Value;
+pub use events::EventListener;
+pub use host::{HostCall, HostCallback, HostFuture, HostSignature};
+pub use limits::ExecutionLimits;
+pub use modules::{Module, ModuleRegistry};
+pub use native::{HostObject, NativeObject};
+pub use permissions::{Access, Permission, PermissionDecision, PermissionPrompt};
+pub use plugin::NativeModule;
+pub use resources::Resource;
+pub use yielding::YieldHook;
 
 // Expose builtins setup and call
 pub use builtins::{setup_builtins, call_builtin};
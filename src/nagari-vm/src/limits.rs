@@ -0,0 +1,94 @@
+//! Limits on how much work a run of the VM may do, so a host can run untrusted scripts.
+//!
+//! A run is what a host starts: running or evaluating loaded bytecode, calling a global
+//! function or emitting an event, together with the timer callbacks and calls it makes. Every
+//! instruction a run executes spends one unit of its budget, and every few hundred instructions
+//! the clock is compared with its deadline, so a script stuck in `while true:` fails with a
+//! `TimeoutError` instead of hanging its host. Waiting for timers stops at the deadline too.
+
+use std::time::{Duration, Instant};
+
+/// Instructions executed between checks of the clock, which costs more than counting
+const CLOCK_CHECK_INTERVAL: u64 = 256;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecutionLimits {
+    /// Wall-clock time a run may take
+    pub timeout: Option<Duration>,
+    /// Instructions a run may execute
+    pub max_instructions: Option<u64>,
+}
+
+impl ExecutionLimits {
+    fn is_unlimited(&self) -> bool {
+        self.timeout.is_none() && self.max_instructions.is_none()
+    }
+}
+
+/// What is left of the limits of the current run
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    limits: ExecutionLimits,
+    running: bool,
+    deadline: Option<Instant>,
+    executed: u64,
+}
+
+impl Budget {
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> ExecutionLimits {
+        self.limits
+    }
+
+    /// Start a run with the full budget
+    pub fn start(&mut self) {
+        self.running = !self.limits.is_unlimited();
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.executed = 0;
+    }
+
+    /// End the run, after which nothing is counted until the next one starts
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.deadline = None;
+    }
+
+    /// Spend one instruction, failing once the run is out of instructions or time
+    pub fn spend(&mut self) -> Result<(), String> {
+        if !self.running {
+            return Ok(());
+        }
+        self.executed += 1;
+        if let Some(max) = self.limits.max_instructions {
+            if self.executed > max {
+                return Err(format!(
+                    "TimeoutError: script exceeded its limit of {max} instructions"
+                ));
+            }
+        }
+        if self.executed.is_multiple_of(CLOCK_CHECK_INTERVAL) {
+            self.check_clock()?;
+        }
+        Ok(())
+    }
+
+    pub fn check_clock(&self) -> Result<(), String> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(self.timeout_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// When the run has to be over, if it has a timeout
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline.filter(|_| self.running)
+    }
+
+    pub fn timeout_error(&self) -> String {
+        let millis = self.limits.timeout.unwrap_or_default().as_millis();
+        format!("TimeoutError: script exceeded the execution timeout of {millis} ms")
+    }
+}
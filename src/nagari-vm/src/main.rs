@@ -6,10 +6,52 @@ mod vm;
 mod value;
 mod bytecode;
 mod builtins;
+#[allow(dead_code)] // Only used by embedding hosts
+mod cache;
+mod crypto;
+mod csv;
 mod env;
+#[allow(dead_code)] // Host subscriptions are only made by embedding hosts
+mod events;
+#[allow(dead_code)] // Only used by embedding hosts
+mod heap;
+#[allow(dead_code)] // Only registered by embedding hosts
+mod host;
+mod html;
+#[cfg(feature = "http-server")]
+mod http_server;
+mod intl;
+mod json;
+#[allow(dead_code)] // Limits are only set by embedding hosts
+mod limits;
+#[allow(dead_code)] // Only used by embedding hosts
+mod modules;
+#[allow(dead_code)] // Host objects are only registered by embedding hosts
+mod native;
+mod packing;
+mod path;
+#[allow(dead_code)] // Prompts are only set by embedding hosts
+mod permissions;
+mod plugin;
+#[allow(dead_code)] // Resources are only created by embedding hosts
+mod resources;
+#[allow(dead_code)] // Jobs are only driven by embedding hosts
+mod schedule;
+mod schema;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod timers;
+mod weakref;
+#[cfg(feature = "websocket")]
+mod websocket;
+#[allow(dead_code)] // Yield hooks are only set by embedding hosts
+mod yielding;
 
 use vm::VM;
 
+/// Simple error alias for VM operations, as in the library
+type Error = String;
+
 #[derive(Parser)]
 #[command(name = "nagrun")]
 #[command(about = "Nagari virtual machine - runs .nac bytecode files")]
@@ -24,13 +66,34 @@ struct Cli {
     /// Debug mode
     #[arg(short, long)]
     debug: bool,
+
+    /// Let the script open network connections and servers
+    #[arg(long)]
+    allow_network: bool,
+
+    /// Let the script open SQLite databases, read and write CSV files and glob paths
+    #[arg(long)]
+    allow_io: bool,
+
+    /// Load a native extension module from a shared library before running (repeatable)
+    #[arg(long = "native-module", value_name = "PATH")]
+    native_modules: Vec<String>,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
 
-    match run_bytecode_file(&cli.input, cli.verbose, cli.debug).await {
+    match run_bytecode_file(
+        &cli.input,
+        cli.verbose,
+        cli.debug,
+        cli.allow_network,
+        cli.allow_io,
+        &cli.native_modules,
+    )
+    .await
+    {
         Ok(_) => {
             if cli.verbose {
                 println!("✅ Execution completed successfully");
@@ -47,6 +110,9 @@ async fn run_bytecode_file(
     input_path: &str,
     verbose: bool,
     debug: bool,
+    allow_network: bool,
+    allow_io: bool,
+    native_modules: &[String],
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Check if file exists and has correct extension
     if !Path::new(input_path).exists() {
@@ -70,6 +136,16 @@ async fn run_bytecode_file(
 
     // Create and run VM
     let mut vm = VM::new(debug);
+    vm.set_allow_network(allow_network);
+    vm.set_allow_io(allow_io);
+    // Naming a module on the command line is what allows loading it
+    vm.set_allow_native_modules(!native_modules.is_empty());
+    for path in native_modules {
+        let name = vm.load_native_module(path)?;
+        if verbose {
+            println!("🔌 Loaded native module '{}' from {}", name, path);
+        }
+    }
     vm.load_bytecode(&bytecode)?;
 
     if verbose {
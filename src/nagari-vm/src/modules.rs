@@ -0,0 +1,369 @@
+//! Namespaces of modules loaded by an embedding host.
+//!
+//! Each module's top-level `name = expr` bindings live in a namespace of its own, out of reach
+//! of other modules and of scripts. Bindings written `export name = expr` are the module's
+//! exports. A module with no `export` bindings exports every name it defines that doesn't
+//! start with `_`.
+//! Scripts and other modules reach the exports with `import name`, `import name as alias` or
+//! `from name import a, b as c`.
+//!
+//! The registry remembers what each import bound, so replacing a module can rebind the names
+//! its importers took from it. Values importers computed from those names are not recomputed.
+
+use crate::value::Value;
+use std::collections::HashMap;
+
+/// One line of a module or script
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleStatement<'a> {
+    Import(Import),
+    Binding {
+        name: &'a str,
+        expr: &'a str,
+        exported: bool,
+    },
+    Expression(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Import {
+    /// `import module` or `import module as alias`: the module's namespace object
+    Module {
+        module: String,
+        alias: Option<String>,
+    },
+    /// `from module import name, other as alias`
+    Names {
+        module: String,
+        names: Vec<(String, Option<String>)>,
+    },
+}
+
+impl Import {
+    /// The names this import binds
+    pub fn bindings(&self) -> Vec<ImportBinding> {
+        match self {
+            Import::Module { module, alias } => vec![ImportBinding {
+                local: alias.clone().unwrap_or_else(|| module.clone()),
+                module: module.clone(),
+                export: None,
+            }],
+            Import::Names { module, names } => names
+                .iter()
+                .map(|(name, alias)| ImportBinding {
+                    local: alias.clone().unwrap_or_else(|| name.clone()),
+                    module: module.clone(),
+                    export: Some(name.clone()),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A name bound by an import
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportBinding {
+    pub local: String,
+    pub module: String,
+    /// The imported export, or `None` for the module's namespace object
+    pub export: Option<String>,
+}
+
+/// Split `code` into statements, one per non-empty line
+pub fn parse_statements(code: &str) -> Result<Vec<ModuleStatement<'_>>, String> {
+    let mut statements = Vec::new();
+    for (index, line) in code.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let statement = parse_statement(line).map_err(|e| format!("line {}: {e}", index + 1))?;
+        statements.push(statement);
+    }
+    Ok(statements)
+}
+
+/// Parse a single trimmed, non-empty line
+pub fn parse_statement(line: &str) -> Result<ModuleStatement<'_>, String> {
+    if let Some(rest) = line.strip_prefix("import ") {
+        let (module, alias) = match rest.split_once(" as ") {
+            Some((module, alias)) => (module, Some(identifier(alias)?)),
+            None => (rest, None),
+        };
+        return Ok(ModuleStatement::Import(Import::Module {
+            module: identifier(module)?,
+            alias,
+        }));
+    }
+
+    if let Some(rest) = line.strip_prefix("from ") {
+        let (module, names) = rest
+            .split_once(" import ")
+            .ok_or_else(|| format!("expected `from module import names`, found `{line}`"))?;
+        let names = names
+            .split(',')
+            .map(|name| match name.split_once(" as ") {
+                Some((name, alias)) => Ok((identifier(name)?, Some(identifier(alias)?))),
+                None => Ok((identifier(name)?, None)),
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        return Ok(ModuleStatement::Import(Import::Names {
+            module: identifier(module)?,
+            names,
+        }));
+    }
+
+    let (exported, rest) = match line.strip_prefix("export ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, line),
+    };
+    match assignment(rest) {
+        Some((name, expr)) => Ok(ModuleStatement::Binding {
+            name,
+            expr,
+            exported,
+        }),
+        None if exported => Err(format!("expected `export name = value`, found `{line}`")),
+        None => Ok(ModuleStatement::Expression(line)),
+    }
+}
+
+/// `name = expr`, but not a comparison such as `a == b` or `a <= b`
+fn assignment(line: &str) -> Option<(&str, &str)> {
+    let bytes = line.as_bytes();
+    let index = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && bytes.get(i + 1) != Some(&b'=')
+            && !(i > 0 && matches!(bytes[i - 1], b'=' | b'!' | b'<' | b'>'))
+    })?;
+    let name = line[..index].trim();
+    is_identifier(name).then(|| (name, line[index + 1..].trim()))
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn identifier(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    if is_identifier(text) {
+        Ok(text.to_string())
+    } else {
+        Err(format!("`{text}` is not a valid name"))
+    }
+}
+
+/// A loaded module
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub source: String,
+    pub namespace: HashMap<String, Value>,
+    pub exports: Vec<String>,
+    /// Names the module imported from other modules
+    pub imports: Vec<ImportBinding>,
+}
+
+impl Module {
+    /// The namespace object `import name` binds: a dict of the exported values
+    pub fn namespace_object(&self) -> Value {
+        Value::Dict(
+            self.exports
+                .iter()
+                .filter_map(|name| Some((name.clone(), self.namespace.get(name)?.clone())))
+                .collect(),
+        )
+    }
+
+    pub fn export(&self, name: &str) -> Result<Value, String> {
+        self.exports
+            .iter()
+            .any(|export| export == name)
+            .then(|| self.namespace.get(name).cloned())
+            .flatten()
+            .ok_or_else(|| format!("cannot import name '{name}' from module '{}'", self.name))
+    }
+}
+
+/// Namespace of a module while its statements are evaluated
+#[derive(Debug, Default)]
+pub struct ModuleScope {
+    pub namespace: HashMap<String, Value>,
+    defined: Vec<String>,
+    exported: Vec<String>,
+    imports: Vec<ImportBinding>,
+}
+
+impl ModuleScope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&mut self, name: &str, value: Value, exported: bool) {
+        self.namespace.insert(name.to_string(), value);
+        if !self.defined.iter().any(|defined| defined == name) {
+            self.defined.push(name.to_string());
+        }
+        if exported && !self.exported.iter().any(|export| export == name) {
+            self.exported.push(name.to_string());
+        }
+    }
+
+    /// Bring imported names into scope; they are not exported again
+    pub fn import(&mut self, bindings: Vec<(ImportBinding, Value)>) {
+        for (binding, value) in bindings {
+            self.namespace.insert(binding.local.clone(), value);
+            self.imports.push(binding);
+        }
+    }
+
+    pub fn finish(self, name: &str, source: &str) -> Module {
+        let exports = if self.exported.is_empty() {
+            self.defined
+                .into_iter()
+                .filter(|name| !name.starts_with('_'))
+                .collect()
+        } else {
+            self.exported
+        };
+        Module {
+            name: name.to_string(),
+            source: source.to_string(),
+            namespace: self.namespace,
+            exports,
+            imports: self.imports,
+        }
+    }
+}
+
+/// Loaded modules, by name
+#[derive(Debug, Clone, Default)]
+pub struct ModuleRegistry {
+    modules: HashMap<String, Module>,
+    /// Names scripts imported, which live among the host's globals
+    script_imports: Vec<ImportBinding>,
+}
+
+impl ModuleRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `module`, replacing a module of the same name
+    pub fn insert(&mut self, module: Module) {
+        self.modules.insert(module.name.clone(), module);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Module> {
+        self.modules.get(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Module> {
+        self.modules.remove(name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.modules.keys().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.modules.clear();
+        self.script_imports.clear();
+    }
+
+    /// The names `import` binds and their values
+    pub fn resolve(&self, import: &Import) -> Result<Vec<(ImportBinding, Value)>, String> {
+        import
+            .bindings()
+            .into_iter()
+            .map(|binding| {
+                let value = self.value_of(&binding)?;
+                Ok((binding, value))
+            })
+            .collect()
+    }
+
+    /// Resolve an import made by a script, remembering it for `replace`
+    pub fn import_into_script(&mut self, import: &Import) -> Result<Vec<(String, Value)>, String> {
+        let mut globals = Vec::new();
+        for (binding, value) in self.resolve(import)? {
+            self.script_imports
+                .retain(|existing| existing.local != binding.local);
+            globals.push((binding.local.clone(), value));
+            self.script_imports.push(binding);
+        }
+        Ok(globals)
+    }
+
+    fn value_of(&self, binding: &ImportBinding) -> Result<Value, String> {
+        let module = self
+            .modules
+            .get(&binding.module)
+            .ok_or_else(|| format!("No module named '{}'", binding.module))?;
+        match &binding.export {
+            Some(name) => module.export(name),
+            None => Ok(module.namespace_object()),
+        }
+    }
+
+    /// Fail if putting `module` in place of the loaded module of its name would leave an
+    /// import from it unresolved
+    pub fn check_replacement(&self, module: &Module) -> Result<(), String> {
+        let module_imports = self.modules.values().flat_map(|importer| {
+            importer
+                .imports
+                .iter()
+                .map(move |binding| (format!("module '{}'", importer.name), binding))
+        });
+        let script_imports = self
+            .script_imports
+            .iter()
+            .map(|binding| ("a script".to_string(), binding));
+
+        for (importer, binding) in module_imports.chain(script_imports) {
+            if binding.module != module.name {
+                continue;
+            }
+            if let Some(name) = &binding.export {
+                if !module.exports.contains(name) {
+                    return Err(format!(
+                        "module '{}' no longer exports '{name}', which {importer} imports",
+                        module.name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Put `module` in place of the loaded module of its name and rebind what other modules
+    /// imported from it. Returns the script globals to rebind.
+    pub fn replace(&mut self, module: Module) -> Result<Vec<(String, Value)>, String> {
+        self.check_replacement(&module)?;
+        let name = module.name.clone();
+        self.insert(module);
+
+        let importers: Vec<String> = self.modules.keys().cloned().collect();
+        for importer in importers {
+            let bindings: Vec<ImportBinding> = self.modules[&importer]
+                .imports
+                .iter()
+                .filter(|binding| binding.module == name)
+                .cloned()
+                .collect();
+            for binding in bindings {
+                let value = self.value_of(&binding)?;
+                if let Some(importer) = self.modules.get_mut(&importer) {
+                    importer.namespace.insert(binding.local, value);
+                }
+            }
+        }
+
+        self.script_imports
+            .iter()
+            .filter(|binding| binding.module == name)
+            .map(|binding| Ok((binding.local.clone(), self.value_of(binding)?)))
+            .collect()
+    }
+}
@@ -0,0 +1,108 @@
+//! Objects of the host that scripts use like their own: `player.move(x, y)` calls a method of
+//! the host's object, and `player.name` reads one of its properties.
+//!
+//! Copies of the value refer to the same object, which is locked while one of its methods runs
+//! or a property is read or set. Methods can't call back into the VM.
+
+use crate::value::Value;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// An object of the host, as the VM sees it
+pub trait HostObject: Send {
+    /// Name of the object's type, as scripts see it
+    fn type_name(&self) -> &str;
+
+    fn has_method(&self, name: &str) -> bool;
+
+    fn call_method(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String>;
+
+    /// Property `name`, if the object has one
+    fn get_property(&self, name: &str) -> Option<Value>;
+
+    fn set_property(&mut self, name: &str, value: Value) -> Result<(), String>;
+
+    /// What `str()` and `print` show for the object, if not `<type object>`
+    fn display(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Object of the host, shared by the values referring to it
+#[derive(Clone)]
+pub struct NativeObject(Arc<Mutex<Box<dyn HostObject>>>);
+
+impl NativeObject {
+    pub fn new(object: impl HostObject + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Box::new(object))))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Box<dyn HostObject>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn type_name(&self) -> String {
+        self.lock().type_name().to_string()
+    }
+
+    /// Attribute `name`: a method bound to the object, or a property
+    pub fn get_attr(&self, name: &str) -> Option<Value> {
+        let object = self.lock();
+        if object.has_method(name) {
+            return Some(Value::NativeMethod(NativeMethod {
+                receiver: self.clone(),
+                name: name.to_string(),
+            }));
+        }
+        object.get_property(name)
+    }
+
+    pub fn set_attr(&self, name: &str, value: Value) -> Result<(), String> {
+        self.lock().set_property(name, value)
+    }
+}
+
+impl PartialEq for NativeObject {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NativeObject")
+            .field(&self.type_name())
+            .finish()
+    }
+}
+
+impl fmt::Display for NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let object = self.lock();
+        match object.display() {
+            Some(text) => write!(f, "{text}"),
+            None => write!(f, "<{} object>", object.type_name()),
+        }
+    }
+}
+
+/// Method of a host object, bound to the object it was looked up on
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeMethod {
+    pub receiver: NativeObject,
+    pub name: String,
+}
+
+impl NativeMethod {
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, String> {
+        self.receiver.lock().call_method(&self.name, args)
+    }
+}
+
+impl fmt::Display for NativeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<method {}.{}>", self.receiver.type_name(), self.name)
+    }
+}
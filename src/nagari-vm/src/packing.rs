@@ -0,0 +1,441 @@
+//! The `struct` module: `pack(format, *values)`, `unpack(format, data)`,
+//! `unpack_from(format, data[, offset])` and `calcsize(format)`, converting between values and
+//! the bytes of binary records.
+//!
+//! A format is an optional byte order, `<` little-endian, `>` or `!` big-endian and `@` or `=`
+//! the machine's, followed by codes each optionally preceded by a count: `x` a pad byte, `c`
+//! one byte, `b`/`B` 8-bit, `h`/`H` 16-bit, `i`/`I` and `l`/`L` 32-bit and `q`/`Q` 64-bit
+//! integers (upper case unsigned), `?` a bool, `f` and `d` 32 and 64-bit floats, and `s` bytes,
+//! as many as its count. Sizes are the standard ones and fields are never aligned.
+
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "struct.";
+
+/// The `struct` module: a dict of builtins named `struct.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("pack", 1),
+        ("unpack", 2),
+        ("unpack_from", 2),
+        ("calcsize", 1),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Call the builtin `name` of the module
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let function = &name[PREFIX.len()..];
+    let Some(Value::String(format)) = args.first() else {
+        return Err(format!(
+            "{function}() format must be a str, not '{}'",
+            args.first().map_or("nothing", Value::type_name)
+        ));
+    };
+    let format = Format::parse(format)?;
+    match (function, &args[1..]) {
+        ("calcsize", []) => Ok(Value::Int(format.size() as i64)),
+        ("pack", values) => format.pack(values).map(Value::Bytes),
+        ("unpack", [Value::Bytes(data)]) => {
+            if data.len() != format.size() {
+                return Err(format!(
+                    "unpack() needs {} bytes, got {}",
+                    format.size(),
+                    data.len()
+                ));
+            }
+            Ok(Value::List(format.unpack(data)))
+        }
+        ("unpack_from", [Value::Bytes(data), rest @ ..]) => {
+            let offset = match rest {
+                [] => 0,
+                [Value::Int(offset)] if *offset >= 0 => *offset as usize,
+                _ => return Err("unpack_from() offset must be a non-negative int".to_string()),
+            };
+            let end = offset + format.size();
+            if end > data.len() {
+                return Err(format!(
+                    "unpack_from() needs {end} bytes from offset {offset}, got {}",
+                    data.len()
+                ));
+            }
+            Ok(Value::List(format.unpack(&data[offset..end])))
+        }
+        ("unpack" | "unpack_from", [other, ..]) => Err(format!(
+            "{function}() data must be bytes, not '{}'",
+            other.type_name()
+        )),
+        ("calcsize" | "unpack" | "unpack_from", _) => Err(format!(
+            "{function}() takes {} arguments ({} given)",
+            match function {
+                "calcsize" => "exactly 1",
+                "unpack" => "exactly 2",
+                _ => "2 or 3",
+            },
+            args.len()
+        )),
+        _ => Err(format!("Unknown builtin function: {name}")),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Pad,
+    Char,
+    Bool,
+    Int { size: usize, signed: bool },
+    Float { size: usize },
+    Bytes(usize),
+}
+
+impl Field {
+    fn size(self) -> usize {
+        match self {
+            Field::Pad | Field::Char | Field::Bool => 1,
+            Field::Int { size, .. } | Field::Float { size } | Field::Bytes(size) => size,
+        }
+    }
+}
+
+struct Format {
+    little_endian: bool,
+    fields: Vec<Field>,
+}
+
+impl Format {
+    fn parse(format: &str) -> Result<Self, String> {
+        let mut chars = format.chars().filter(|c| !c.is_whitespace()).peekable();
+        let little_endian = match chars.peek() {
+            Some('<') => true,
+            Some('>' | '!') => false,
+            _ => cfg!(target_endian = "little"),
+        };
+        if matches!(chars.peek(), Some('<' | '>' | '!' | '@' | '=')) {
+            chars.next();
+        }
+
+        let mut fields = Vec::new();
+        while let Some(c) = chars.next() {
+            let mut count = None;
+            let mut code = c;
+            while let Some(digit) = code.to_digit(10) {
+                count = Some(count.unwrap_or(0) * 10 + digit as usize);
+                code = chars
+                    .next()
+                    .ok_or_else(|| format!("bad struct format '{format}': count without a code"))?;
+            }
+            let field = match code {
+                'x' => Field::Pad,
+                'c' => Field::Char,
+                '?' => Field::Bool,
+                'b' | 'B' => Field::Int {
+                    size: 1,
+                    signed: code == 'b',
+                },
+                'h' | 'H' => Field::Int {
+                    size: 2,
+                    signed: code == 'h',
+                },
+                'i' | 'I' | 'l' | 'L' => Field::Int {
+                    size: 4,
+                    signed: code.is_lowercase(),
+                },
+                'q' | 'Q' => Field::Int {
+                    size: 8,
+                    signed: code == 'q',
+                },
+                'f' => Field::Float { size: 4 },
+                'd' => Field::Float { size: 8 },
+                's' => {
+                    // The count of `s` is the length of one field
+                    fields.push(Field::Bytes(count.unwrap_or(1)));
+                    continue;
+                }
+                other => {
+                    return Err(format!(
+                        "bad struct format '{format}': unknown code '{other}'"
+                    ))
+                }
+            };
+            fields.extend(std::iter::repeat_n(field, count.unwrap_or(1)));
+        }
+        Ok(Self {
+            little_endian,
+            fields,
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.fields.iter().map(|field| field.size()).sum()
+    }
+
+    /// Number of values packed and unpacked
+    fn values(&self) -> usize {
+        self.fields
+            .iter()
+            .filter(|field| !matches!(field, Field::Pad))
+            .count()
+    }
+
+    fn pack(&self, values: &[Value]) -> Result<Vec<u8>, String> {
+        if values.len() != self.values() {
+            return Err(format!(
+                "pack() expected {} values, got {}",
+                self.values(),
+                values.len()
+            ));
+        }
+        let mut values = values.iter().enumerate();
+        let mut data = Vec::with_capacity(self.size());
+        for field in &self.fields {
+            if let Field::Pad = field {
+                data.push(0);
+                continue;
+            }
+            let (index, value) = values.next().expect("counted above");
+            let mismatch = |expected: &str| {
+                format!(
+                    "pack() value {index} must be {expected}, not '{}'",
+                    value.type_name()
+                )
+            };
+            match (*field, value) {
+                (Field::Char, Value::Bytes(byte)) if byte.len() == 1 => data.push(byte[0]),
+                (Field::Char, _) => return Err(mismatch("bytes of length 1")),
+                (Field::Bool, value) => data.push(value.is_truthy() as u8),
+                (Field::Int { size, signed }, Value::Int(n)) => {
+                    let bits = size as u32 * 8;
+                    let in_range = if signed {
+                        bits == 64 || (*n >= -(1 << (bits - 1)) && *n < 1 << (bits - 1))
+                    } else {
+                        *n >= 0 && (bits == 64 || *n < 1 << bits)
+                    };
+                    if !in_range {
+                        return Err(format!(
+                            "pack() value {index} does not fit in {} {bits}-bit field",
+                            if signed { "a signed" } else { "an unsigned" }
+                        ));
+                    }
+                    let bytes = n.to_le_bytes();
+                    self.push_ordered(&mut data, &bytes[..size]);
+                }
+                (Field::Int { .. }, _) => return Err(mismatch("an int")),
+                (Field::Float { size }, Value::Int(_) | Value::Float(_)) => {
+                    let x = match value {
+                        Value::Int(n) => *n as f64,
+                        Value::Float(x) => *x,
+                        _ => unreachable!(),
+                    };
+                    if size == 4 {
+                        self.push_ordered(&mut data, &(x as f32).to_le_bytes());
+                    } else {
+                        self.push_ordered(&mut data, &x.to_le_bytes());
+                    }
+                }
+                (Field::Float { .. }, _) => return Err(mismatch("a number")),
+                (Field::Bytes(size), Value::Bytes(bytes)) => {
+                    data.extend(bytes.iter().copied().chain(std::iter::repeat(0)).take(size))
+                }
+                (Field::Bytes(size), Value::String(s)) => {
+                    data.extend(s.bytes().chain(std::iter::repeat(0)).take(size))
+                }
+                (Field::Bytes(_), _) => return Err(mismatch("bytes or a str")),
+                (Field::Pad, _) => unreachable!(),
+            }
+        }
+        Ok(data)
+    }
+
+    fn unpack(&self, data: &[u8]) -> Vec<Value> {
+        let mut values = Vec::with_capacity(self.values());
+        let mut offset = 0;
+        for field in &self.fields {
+            let bytes = &data[offset..offset + field.size()];
+            offset += field.size();
+            let value = match *field {
+                Field::Pad => continue,
+                Field::Char | Field::Bytes(_) => Value::Bytes(bytes.to_vec()),
+                Field::Bool => Value::Bool(bytes[0] != 0),
+                Field::Int { size, signed } => {
+                    let mut le = self.little_endian_bytes(bytes);
+                    // Sign-extend to the 8 bytes of an i64
+                    let fill = if signed && le[size - 1] & 0x80 != 0 {
+                        0xff
+                    } else {
+                        0
+                    };
+                    le.resize(8, fill);
+                    Value::Int(i64::from_le_bytes(le.try_into().unwrap()))
+                }
+                Field::Float { size: 4 } => Value::Float(f32::from_le_bytes(
+                    self.little_endian_bytes(bytes).try_into().unwrap(),
+                ) as f64),
+                Field::Float { .. } => Value::Float(f64::from_le_bytes(
+                    self.little_endian_bytes(bytes).try_into().unwrap(),
+                )),
+            };
+            values.push(value);
+        }
+        values
+    }
+
+    /// Append little-endian `bytes` in the format's byte order
+    fn push_ordered(&self, data: &mut Vec<u8>, bytes: &[u8]) {
+        if self.little_endian {
+            data.extend_from_slice(bytes);
+        } else {
+            data.extend(bytes.iter().rev());
+        }
+    }
+
+    fn little_endian_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        if self.little_endian {
+            bytes.to_vec()
+        } else {
+            bytes.iter().rev().copied().collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(data: &[u8]) -> Value {
+        Value::Bytes(data.to_vec())
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn test_values_pack_in_the_byte_order_and_unpack_back() {
+        let values = vec![
+            Value::Int(1),
+            Value::Int(-2),
+            Value::Bool(true),
+            Value::Float(1.5),
+        ];
+        let little = call(
+            "struct.pack",
+            &[vec![string("<Hhx?d")], values.clone()].concat(),
+        )
+        .unwrap();
+        let big = call(
+            "struct.pack",
+            &[vec![string(">Hhx?d")], values.clone()].concat(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            little,
+            bytes(&[1, 0, 0xfe, 0xff, 0, 1, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f])
+        );
+        assert_eq!(
+            big,
+            bytes(&[0, 1, 0xff, 0xfe, 0, 1, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            call("struct.unpack", &[string("<Hhx?d"), little]).unwrap(),
+            Value::List(values.clone())
+        );
+        assert_eq!(
+            call("struct.unpack", &[string("!Hhx?d"), big]).unwrap(),
+            Value::List(values)
+        );
+        assert_eq!(
+            call("struct.calcsize", &[string("<Hhx?d")]).unwrap(),
+            Value::Int(14)
+        );
+    }
+
+    #[test]
+    fn test_integers_keep_their_sign_and_range() {
+        let extremes = [
+            ("<b", -128),
+            ("<B", 255),
+            ("<h", -32768),
+            ("<H", 65535),
+            ("<i", i32::MIN as i64),
+            ("<I", u32::MAX as i64),
+            ("<q", i64::MIN),
+            ("<Q", i64::MAX),
+        ];
+        for (format, n) in extremes {
+            let data = call("struct.pack", &[string(format), Value::Int(n)]).unwrap();
+            assert_eq!(
+                call("struct.unpack", &[string(format), data]).unwrap(),
+                Value::List(vec![Value::Int(n)]),
+                "{format}"
+            );
+        }
+
+        assert_eq!(
+            call("struct.pack", &[string("<b"), Value::Int(128)]).unwrap_err(),
+            "pack() value 0 does not fit in a signed 8-bit field"
+        );
+        assert_eq!(
+            call("struct.pack", &[string("<H"), Value::Int(-1)]).unwrap_err(),
+            "pack() value 0 does not fit in an unsigned 16-bit field"
+        );
+    }
+
+    #[test]
+    fn test_byte_strings_are_padded_and_read_at_offsets() {
+        let data = call(
+            "struct.pack",
+            &[string("<4sc2s"), string("ab"), bytes(b"x"), bytes(b"long")],
+        )
+        .unwrap();
+        assert_eq!(data, bytes(b"ab\0\0xlo"));
+
+        let record = Value::Bytes([b"head".as_slice(), &[7, 0]].concat());
+        assert_eq!(
+            call(
+                "struct.unpack_from",
+                &[string("<H"), record.clone(), Value::Int(4)]
+            )
+            .unwrap(),
+            Value::List(vec![Value::Int(7)])
+        );
+        assert_eq!(
+            call("struct.unpack_from", &[string("<H"), record, Value::Int(5)]).unwrap_err(),
+            "unpack_from() needs 7 bytes from offset 5, got 6"
+        );
+    }
+
+    #[test]
+    fn test_mismatched_arguments_are_errors() {
+        assert_eq!(
+            call("struct.pack", &[string("<2h"), Value::Int(1)]).unwrap_err(),
+            "pack() expected 2 values, got 1"
+        );
+        assert_eq!(
+            call("struct.pack", &[string("<c"), string("a")]).unwrap_err(),
+            "pack() value 0 must be bytes of length 1, not 'str'"
+        );
+        assert_eq!(
+            call("struct.unpack", &[string("<i"), bytes(&[0; 3])]).unwrap_err(),
+            "unpack() needs 4 bytes, got 3"
+        );
+        assert_eq!(
+            call("struct.calcsize", &[string("<3z")]).unwrap_err(),
+            "bad struct format '<3z': unknown code 'z'"
+        );
+    }
+}
@@ -0,0 +1,600 @@
+//! The `path` module: `join(*parts)`, `dirname(path)`, `basename(path)`, `extension(path)`,
+//! `normalize(path)`, `relative(from, to)`, `is_absolute(path)`, `glob(pattern)` and `sep`.
+//!
+//! Paths are strings handled the same way on every backend, with the separators of the
+//! platform: `/` on Unix, and on Windows `\`, with `/` accepted as well and drive letters
+//! (`C:\`) for roots. Only `glob` looks at the file system.
+//!
+//! `glob` patterns match `*` against any characters but a separator, `?` against one, and
+//! `[abc]`, `[a-z]` and `[!abc]` against one of a set; a `**` component matches any number of
+//! directories. Wildcards don't match names starting with `.` unless the pattern does. Matches
+//! come back sorted. A `..` component can't follow a wildcard, so that every match is below the
+//! path globbing starts looking in. In the VM, globbing needs IO access to that path, the
+//! pattern up to its first wildcard, which hosts allow with
+//! [`VM::set_allow_io`](crate::VM::set_allow_io) or a [permission prompt](crate::permissions).
+
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "path.";
+
+/// The separator of the platform's paths
+pub const SEP: char = if cfg!(windows) { '\\' } else { '/' };
+
+/// The `path` module: a dict of builtins named `path.<function>`, and `sep`
+pub fn module() -> Value {
+    let functions = [
+        ("join", 0),
+        ("dirname", 1),
+        ("basename", 1),
+        ("extension", 1),
+        ("normalize", 1),
+        ("relative", 2),
+        ("is_absolute", 1),
+        ("glob", 1),
+    ];
+    let mut module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    module.insert("sep".to_string(), Value::String(SEP.to_string()));
+    Value::Dict(module)
+}
+
+/// Call the builtin `name` of the module
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let function = &name[PREFIX.len()..];
+    let paths = args
+        .iter()
+        .map(|arg| match arg {
+            Value::String(path) => Ok(path.as_str()),
+            other => Err(format!(
+                "{function}() arguments must be str, not '{}'",
+                other.type_name()
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let string = |s: String| Ok(Value::String(s));
+    match (function, paths.as_slice()) {
+        ("join", parts) => string(join(parts)),
+        ("dirname", [path]) => string(dirname(path)),
+        ("basename", [path]) => string(basename(path).to_string()),
+        ("extension", [path]) => string(extension(path).to_string()),
+        ("normalize", [path]) => string(normalize(path)),
+        ("relative", [from, to]) => relative(from, to).map(Value::String),
+        ("is_absolute", [path]) => Ok(Value::Bool(is_absolute(path))),
+        ("glob", [pattern]) => {
+            glob(pattern).map(|paths| Value::List(paths.into_iter().map(Value::String).collect()))
+        }
+        ("dirname" | "basename" | "extension" | "normalize" | "is_absolute" | "glob", _) => {
+            Err(format!(
+                "{function}() takes exactly 1 argument ({} given)",
+                args.len()
+            ))
+        }
+        ("relative", _) => Err(format!(
+            "relative() takes exactly 2 arguments ({} given)",
+            args.len()
+        )),
+        _ => Err(format!("Unknown builtin function: {name}")),
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || (cfg!(windows) && c == '\\')
+}
+
+/// Length of the root of `path`: `/` on Unix, and a drive letter, a separator or both on
+/// Windows
+fn root_len(path: &str) -> usize {
+    let mut len = 0;
+    if cfg!(windows) {
+        let bytes = path.as_bytes();
+        if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+            len = 2;
+        }
+    }
+    if path[len..].starts_with(is_separator) {
+        len += 1;
+    }
+    len
+}
+
+/// Whether `path` starts from a root directory rather than the current one
+pub fn is_absolute(path: &str) -> bool {
+    root_len(path) > 0 && path[..root_len(path)].ends_with(is_separator)
+}
+
+/// The root of `path` with the platform's separator, and its components
+fn split(path: &str) -> (String, Vec<&str>) {
+    let root_len = root_len(path);
+    let root = path[..root_len].replace(is_separator, &SEP.to_string());
+    let components = path[root_len..]
+        .split(is_separator)
+        .filter(|component| !component.is_empty())
+        .collect();
+    (root, components)
+}
+
+/// `path` without `.` components, separators in a row or at the end, and with `..` removing
+/// the component before it where there is one
+pub fn normalize(path: &str) -> String {
+    let (root, components) = split(path);
+    let absolute = root.ends_with(SEP);
+    let mut normalized: Vec<&str> = Vec::new();
+    for component in components {
+        match component {
+            "." => {}
+            ".." => match normalized.last() {
+                Some(&last) if last != ".." => {
+                    normalized.pop();
+                }
+                // Nothing is above the root
+                _ if absolute => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    let joined = normalized.join(&SEP.to_string());
+    match (root.is_empty(), joined.is_empty()) {
+        (true, true) => ".".to_string(),
+        _ => root + &joined,
+    }
+}
+
+/// `parts` joined with separators and normalized. A part with a root replaces what comes
+/// before it.
+pub fn join(parts: &[&str]) -> String {
+    let mut joined = String::new();
+    for part in parts.iter().filter(|part| !part.is_empty()) {
+        if root_len(part) > 0 {
+            joined.clear();
+        } else if !joined.is_empty() && !joined.ends_with(is_separator) {
+            joined.push(SEP);
+        }
+        joined.push_str(part);
+    }
+    normalize(&joined)
+}
+
+/// `path` without trailing separators, unless it is all root
+fn trim_end(path: &str) -> &str {
+    let root = root_len(path);
+    let trimmed = path.trim_end_matches(is_separator);
+    if trimmed.len() < root {
+        &path[..root]
+    } else {
+        trimmed
+    }
+}
+
+/// The directory `path` is in: everything before its last component
+pub fn dirname(path: &str) -> String {
+    let path = trim_end(path);
+    let root = root_len(path);
+    match path[root..].rfind(is_separator) {
+        Some(index) => {
+            let dir = path[..root + index].trim_end_matches(is_separator);
+            if dir.len() < root {
+                path[..root].to_string()
+            } else {
+                dir.to_string()
+            }
+        }
+        None if root > 0 => path[..root].to_string(),
+        None => ".".to_string(),
+    }
+}
+
+/// The last component of `path`
+pub fn basename(path: &str) -> &str {
+    let path = trim_end(path);
+    let root = root_len(path);
+    let rest = &path[root..];
+    match rest.rfind(is_separator) {
+        Some(index) => &rest[index + 1..],
+        None => rest,
+    }
+}
+
+/// The extension of `path`'s last component, from its last `.`, or an empty string. Names
+/// starting with their only `.`, such as `.bashrc`, have none.
+pub fn extension(path: &str) -> &str {
+    let name = basename(path);
+    match name.rfind('.') {
+        Some(index) if index > 0 => &name[index..],
+        _ => "",
+    }
+}
+
+/// The path leading from the directory `from` to `to`
+pub fn relative(from: &str, to: &str) -> Result<String, String> {
+    let from = normalize(from);
+    let to = normalize(to);
+    let (from_root, from_parts) = split(&from);
+    let (to_root, to_parts) = split(&to);
+    if !same(&from_root, &to_root) {
+        return Err(format!(
+            "relative(): '{from}' and '{to}' don't have the same root"
+        ));
+    }
+    let common = from_parts
+        .iter()
+        .zip(&to_parts)
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    if from_parts[common..].contains(&"..") {
+        return Err(format!(
+            "relative(): can't tell the way from '{from}' to '{to}' without the current directory"
+        ));
+    }
+    let mut parts = vec![".."; from_parts.len() - common];
+    parts.extend(&to_parts[common..]);
+    Ok(match parts.is_empty() {
+        true => ".".to_string(),
+        false => parts.join(&SEP.to_string()),
+    })
+}
+
+/// Whether two roots or components are the same; Windows paths ignore case
+fn same(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.to_lowercase() == b.to_lowercase()
+    } else {
+        a == b
+    }
+}
+
+/// The paths matching `pattern`, sorted
+pub fn glob(pattern: &str) -> Result<Vec<String>, String> {
+    let (root, components) = split(pattern);
+    if let Some(wildcard) = components.iter().position(|c| c.contains(['*', '?', '['])) {
+        if components[wildcard..].contains(&"..") {
+            return Err(format!(
+                "glob(): '..' can't follow a wildcard, as in '{pattern}'"
+            ));
+        }
+    }
+    // Paths matched so far, as written and as opened
+    let mut matches = vec![root.clone()];
+    for (index, component) in components.iter().enumerate() {
+        let last = index == components.len() - 1;
+        let mut next = Vec::new();
+        for base in &matches {
+            let dir = if base.is_empty() { "." } else { base.as_str() };
+            if *component == "**" {
+                descendants(base, dir, &mut next);
+            } else if component.contains(['*', '?', '[']) {
+                let Ok(entries) = std::fs::read_dir(dir) else {
+                    continue;
+                };
+                for entry in entries.flatten() {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+                    if (last || is_dir) && matches_component(component, &name) {
+                        next.push(child(base, &name));
+                    }
+                }
+            } else {
+                let path = child(base, component);
+                if std::path::Path::new(&path).exists() {
+                    next.push(path);
+                }
+            }
+        }
+        next.sort();
+        next.dedup();
+        matches = next;
+    }
+    if components.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(matches)
+}
+
+/// The path `glob(pattern)` starts looking in: the pattern up to its first wildcard
+pub fn glob_root(pattern: &str) -> String {
+    let (mut root, components) = split(pattern);
+    for component in components {
+        if component.contains(['*', '?', '[']) {
+            break;
+        }
+        root = child(&root, component);
+    }
+    if root.is_empty() {
+        ".".to_string()
+    } else {
+        root
+    }
+}
+
+fn child(base: &str, name: &str) -> String {
+    if base.is_empty() || base.ends_with(SEP) {
+        format!("{base}{name}")
+    } else {
+        format!("{base}{SEP}{name}")
+    }
+}
+
+/// `base` and the directories below it that aren't hidden
+fn descendants(base: &str, dir: &str, found: &mut Vec<String>) {
+    found.push(base.to_string());
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            continue;
+        }
+        let path = child(base, &name);
+        descendants(&path, &path, found);
+    }
+}
+
+/// Whether `name` matches the glob component `pattern`
+pub fn matches_component(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    wildcard(&pattern, &name)
+}
+
+/// Whether `name` matches `pattern`, going through both once: on a mismatch, the last `*` takes
+/// one more character and matching resumes after it, which never needs an earlier `*` to take
+/// a different number
+fn wildcard(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where matching resumes after the last `*`: its pattern position and the next name one
+    let mut star = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+            continue;
+        }
+        if let Some(len) = single(&pattern[p..], name[n]) {
+            p += len;
+            n += 1;
+            continue;
+        }
+        match star {
+            Some((after, taken)) => {
+                p = after;
+                n = taken + 1;
+                star = Some((after, n));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The length of the one-character wildcard, class or literal at the start of `pattern`, if it
+/// matches `c`
+fn single(pattern: &[char], c: char) -> Option<usize> {
+    let matched = match pattern.first()? {
+        '*' => return None,
+        '?' => return Some(1),
+        '[' => {
+            if let Some((matcher, len)) = class(&pattern[1..]) {
+                return matcher(c).then_some(len + 1);
+            }
+            // An unclosed `[` is a literal
+            c == '['
+        }
+        &literal => c == literal,
+    };
+    matched.then_some(1)
+}
+
+/// The character class at the start of `pattern`, after its `[`, and its length with the `]`
+fn class(pattern: &[char]) -> Option<(impl Fn(char) -> bool + '_, usize)> {
+    let negated = pattern.first() == Some(&'!');
+    let start = usize::from(negated);
+    // A `]` right after the `[` is part of the set
+    let end = start + 1 + pattern.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let set = &pattern[start..end];
+    let matcher = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < set.len() {
+            if i + 2 < set.len() && set[i + 1] == '-' {
+                found |= set[i] <= c && c <= set[i + 2];
+                i += 3;
+            } else {
+                found |= set[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((matcher, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::Permission;
+    use crate::vm::VM;
+    use serde_json::Value as Json;
+
+    /// Vectors the JavaScript runtime's tests check its `glob` against too
+    fn vectors() -> Json {
+        let text = include_str!("../../../tests/fixtures/glob.json");
+        serde_json::from_str(text).unwrap()
+    }
+
+    fn strings(value: &Json) -> Vec<&str> {
+        value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item.as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_components_match_the_shared_vectors() {
+        for vector in vectors()["components"].as_array().unwrap() {
+            let pattern = vector["pattern"].as_str().unwrap();
+            let name = vector["name"].as_str().unwrap();
+            let expected = vector["matches"].as_bool().unwrap();
+            assert_eq!(
+                matches_component(pattern, name),
+                expected,
+                "{pattern:?} against {name:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_glob_rejects_the_shared_vectors() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = child(&directory.path().to_string_lossy(), "");
+        for pattern in strings(&vectors()["rejected"]) {
+            let error = glob(&format!("{root}{pattern}")).unwrap_err();
+            assert!(error.contains("'..' can't follow a wildcard"), "{error}");
+        }
+        // Before any wildcard, `..` is part of where globbing starts looking
+        assert!(glob(&format!("{root}data/../*")).is_ok());
+    }
+
+    #[test]
+    fn test_glob_finds_the_shared_vectors() {
+        let vectors = vectors();
+        let directory = tempfile::tempdir().unwrap();
+        for file in strings(&vectors["tree"]) {
+            let path = directory.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let root = child(&directory.path().to_string_lossy(), "");
+        for vector in vectors["globs"].as_array().unwrap() {
+            let pattern = vector["pattern"].as_str().unwrap();
+            let found: Vec<String> = glob(&format!("{root}{pattern}"))
+                .unwrap()
+                .into_iter()
+                .map(|path| path[root.len()..].replace(SEP, "/"))
+                .collect();
+            assert_eq!(found, strings(&vector["matches"]), "{pattern}");
+        }
+    }
+
+    /// `path` with the platform's separators
+    fn sep(path: &str) -> String {
+        path.replace('/', &SEP.to_string())
+    }
+
+    #[test]
+    fn test_paths_are_normalized_and_joined() {
+        for (path, expected) in [
+            ("a/./b//c/", "a/b/c"),
+            ("a/b/../../..", ".."),
+            ("../a/../../b", "../../b"),
+            ("/../a/..", "/"),
+            ("./", "."),
+            ("", "."),
+        ] {
+            assert_eq!(normalize(path), sep(expected), "{path:?}");
+        }
+
+        assert_eq!(join(&["a", "b/", "c.nag"]), sep("a/b/c.nag"));
+        assert_eq!(join(&["a", "", "../b"]), "b");
+        assert_eq!(join(&["a", "/etc", "hosts"]), sep("/etc/hosts"));
+        assert_eq!(join(&[]), ".");
+    }
+
+    #[test]
+    fn test_paths_are_split_into_their_parts() {
+        for (path, dir, base, ext) in [
+            ("src/main.nag", "src", "main.nag", ".nag"),
+            ("src/lib/", "src", "lib", ""),
+            ("archive.tar.gz", ".", "archive.tar.gz", ".gz"),
+            ("/.bashrc", "/", ".bashrc", ""),
+            ("/", "/", "", ""),
+        ] {
+            let path = sep(path);
+            assert_eq!(dirname(&path), sep(dir), "{path:?}");
+            assert_eq!(basename(&path), base, "{path:?}");
+            assert_eq!(extension(&path), ext, "{path:?}");
+        }
+        assert!(is_absolute(&sep("/srv")));
+        assert!(!is_absolute("srv"));
+    }
+
+    #[test]
+    fn test_relative_paths_lead_between_directories() {
+        for (from, to, expected) in [
+            ("/srv/app", "/srv/app/static/a.css", "static/a.css"),
+            ("/srv/app/static", "/srv/data", "../../data"),
+            ("src/", "./src", "."),
+            ("a", "../b", "../../b"),
+        ] {
+            assert_eq!(relative(&sep(from), &sep(to)).unwrap(), sep(expected));
+        }
+
+        assert_eq!(
+            relative("..", "a").unwrap_err(),
+            "relative(): can't tell the way from '..' to 'a' without the current directory"
+        );
+        assert_eq!(
+            relative(&sep("/srv"), "srv").unwrap_err(),
+            format!(
+                "relative(): '{}' and 'srv' don't have the same root",
+                sep("/srv")
+            )
+        );
+    }
+
+    #[test]
+    fn test_glob_root_stops_at_the_first_wildcard() {
+        assert_eq!(glob_root("*.nag"), ".");
+        assert_eq!(glob_root("src/**/*.nag"), "src");
+        assert_eq!(glob_root("/srv/data/[ab]*.csv"), sep("/srv/data"));
+        assert_eq!(glob_root("src/lib/notes.txt"), sep("src/lib/notes.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_glob_needs_io_access() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("a.nag"), "").unwrap();
+        let root = directory.path().to_string_lossy().into_owned();
+        let pattern = Value::String(child(&root, "*.nag"));
+        let glob = Value::Builtin(BuiltinFunction {
+            name: "path.glob".to_string(),
+            arity: 1,
+        });
+
+        let mut vm = VM::new(false);
+        vm.start_access_report();
+        let error = vm
+            .call(glob.clone(), vec![pattern.clone()])
+            .await
+            .unwrap_err();
+        assert_eq!(error, "glob(): IO operations are not allowed");
+        let accesses = vm.finish_access_report();
+        assert_eq!(accesses.len(), 1);
+        assert_eq!(accesses[0].operation, "path.glob");
+        assert_eq!(accesses[0].permission, Permission::Io(root.clone()));
+
+        vm.set_allow_io(true);
+        let found = vm.call(glob, vec![pattern]).await.unwrap();
+        assert_eq!(
+            found,
+            Value::List(vec![Value::String(child(&root, "a.nag"))])
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Gated capabilities, and how hosts decide on them as scripts need them.
+//!
+//! Scripts may only open SQLite databases, read or write CSV files and glob paths when IO is
+//! allowed, and only connect to or serve websockets and HTTP when the network is. Hosts may
+//! only load native extension modules when those are allowed. Instead of allowing a capability
+//! outright, a host can set a [`PermissionPrompt`] with
+//! [`VM::set_permission_prompt`](crate::VM::set_permission_prompt): the first time a script
+//! needs a path or host the run waits while the prompt decides, as in Deno's interactive
+//! prompts. Decisions to always allow or deny are remembered for that path or host until the
+//! prompt is replaced.
+//!
+//! Every check is an [`Access`], allowed or not. Hosts that need an audit trail collect those of
+//! a run with [`VM::start_access_report`](crate::VM::start_access_report) and
+//! [`VM::finish_access_report`](crate::VM::finish_access_report), or have each passed to an
+//! [`AccessListener`] as it happens, which suits scripts that keep running.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Access a script needs. More kinds of access may be gated in later versions, so hosts
+/// matching on it need a wildcard arm.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Permission {
+    /// IO on a file path
+    Io(String),
+    /// Network access to a host, as `host:port` when there is a port
+    Network(String),
+    /// Loading a native extension module from the shared library at a path
+    Native(String),
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Permission::Io(path) => write!(f, "IO access to '{path}'"),
+            Permission::Network(host) => write!(f, "network access to '{host}'"),
+            Permission::Native(path) => write!(f, "loading native module '{path}'"),
+        }
+    }
+}
+
+/// What a prompt decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionDecision {
+    /// Allow this access only
+    Allow,
+    /// Allow this and every later access to the same path or host
+    AllowAlways,
+    /// Deny this access only
+    Deny,
+    /// Deny this and every later access to the same path or host
+    DenyAlways,
+}
+
+/// A gated operation a script performed, or was stopped from performing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Access {
+    /// The builtin that needed it, such as `sqlite.open`
+    pub operation: String,
+    pub permission: Permission,
+    pub allowed: bool,
+}
+
+/// Called with each access as it is checked
+pub type AccessListener = Arc<dyn Fn(&Access) + Send + Sync>;
+
+/// Asks the host whether to allow access the VM doesn't allow outright
+#[derive(Clone)]
+pub struct PermissionPrompt(Arc<dyn Fn(&Permission) -> PermissionDecision + Send + Sync>);
+
+impl PermissionPrompt {
+    pub fn new<F>(prompt: F) -> Self
+    where
+        F: Fn(&Permission) -> PermissionDecision + Send + Sync + 'static,
+    {
+        Self(Arc::new(prompt))
+    }
+}
+
+impl fmt::Debug for PermissionPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PermissionPrompt")
+    }
+}
+
+/// What a VM allows, shared with the modules that check it
+#[derive(Clone, Default)]
+pub struct Permissions {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    allow_io: bool,
+    allow_network: bool,
+    allow_native: bool,
+    prompt: Option<PermissionPrompt>,
+    /// Decisions the prompt said to remember
+    remembered: HashMap<Permission, bool>,
+    /// Accesses since the report was started, if one is
+    report: Option<Vec<Access>>,
+    listener: Option<AccessListener>,
+}
+
+impl Permissions {
+    pub fn set_allow_io(&self, allow: bool) {
+        self.lock().allow_io = allow;
+    }
+
+    pub fn set_allow_network(&self, allow: bool) {
+        self.lock().allow_network = allow;
+    }
+
+    pub fn set_allow_native(&self, allow: bool) {
+        self.lock().allow_native = allow;
+    }
+
+    /// Ask `prompt` about access that isn't allowed outright, forgetting what the previous
+    /// prompt decided
+    pub fn set_prompt(&self, prompt: Option<PermissionPrompt>) {
+        let mut state = self.lock();
+        state.prompt = prompt;
+        state.remembered.clear();
+    }
+
+    pub fn set_listener(&self, listener: Option<AccessListener>) {
+        self.lock().listener = listener;
+    }
+
+    /// Collect the accesses from now on, dropping those of a report that wasn't finished
+    pub fn start_report(&self) {
+        self.lock().report = Some(Vec::new());
+    }
+
+    /// The accesses since `start_report`, in order, no longer collecting them
+    pub fn finish_report(&self) -> Vec<Access> {
+        self.lock().report.take().unwrap_or_default()
+    }
+
+    /// Fail unless `permission` is allowed for the builtin `name`, asking the prompt if it
+    /// hasn't decided for good
+    pub fn check(&self, name: &str, permission: Permission) -> Result<(), String> {
+        let function = name.rsplit('.').next().unwrap_or(name);
+        let allowed = self.allowed(function, &permission);
+        let access = Access {
+            operation: name.to_string(),
+            permission,
+            allowed: allowed.is_ok(),
+        };
+        let listener = {
+            let mut state = self.lock();
+            if let Some(report) = &mut state.report {
+                report.push(access.clone());
+            }
+            state.listener.clone()
+        };
+        if let Some(listener) = listener {
+            listener(&access);
+        }
+        allowed
+    }
+
+    fn allowed(&self, function: &str, permission: &Permission) -> Result<(), String> {
+        let prompt = {
+            let state = self.lock();
+            let allowed = match permission {
+                Permission::Io(_) => state.allow_io,
+                Permission::Network(_) => state.allow_network,
+                Permission::Native(_) => state.allow_native,
+            };
+            if allowed {
+                return Ok(());
+            }
+            match (state.remembered.get(permission), &state.prompt) {
+                (Some(true), _) => return Ok(()),
+                (Some(false), _) => return Err(denied(function, permission)),
+                (None, Some(prompt)) => prompt.clone(),
+                (None, None) => {
+                    return Err(match permission {
+                        Permission::Io(_) => format!("{function}(): IO operations are not allowed"),
+                        Permission::Network(_) => {
+                            format!("{function}(): network operations are not allowed")
+                        }
+                        Permission::Native(_) => {
+                            format!("{function}(): native modules are not allowed")
+                        }
+                    })
+                }
+            }
+        };
+
+        // Not locked while the host decides, which may mean waiting for a user
+        let decision = (prompt.0)(permission);
+        let allow = matches!(
+            decision,
+            PermissionDecision::Allow | PermissionDecision::AllowAlways
+        );
+        if matches!(
+            decision,
+            PermissionDecision::AllowAlways | PermissionDecision::DenyAlways
+        ) {
+            self.lock().remembered.insert(permission.clone(), allow);
+        }
+        if allow {
+            Ok(())
+        } else {
+            Err(denied(function, permission))
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn denied(function: &str, permission: &Permission) -> String {
+    format!("{function}(): {permission} was denied")
+}
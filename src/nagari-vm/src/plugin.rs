@@ -0,0 +1,882 @@
+//! Native extension modules: shared libraries that add functions and constants to the VM
+//! through a stable C ABI, for work scripts are too slow for, such as image processing or math
+//! kernels.
+//!
+//! A module exports `nagari_module_init`, returning a description of the module that, with
+//! everything it points to, stays valid while the library is loaded:
+//!
+//! ```c
+//! #define NAGARI_ABI_VERSION 1
+//!
+//! enum { NAGARI_NONE, NAGARI_BOOL, NAGARI_INT, NAGARI_FLOAT, NAGARI_STR, NAGARI_BYTES, NAGARI_LIST };
+//!
+//! typedef struct NagariValue {
+//!     uint32_t tag;
+//!     union {
+//!         int64_t int_val;      /* NAGARI_INT, and NAGARI_BOOL as 0 or 1 */
+//!         double float_val;     /* NAGARI_FLOAT */
+//!         struct {
+//!             const void *ptr;  /* UTF-8, bytes, or NagariValue items of a list */
+//!             size_t len;       /* in bytes, or items for NAGARI_LIST */
+//!         } buffer;
+//!     } data;
+//! } NagariValue;
+//!
+//! /* Returns 0 on success; otherwise `result` is a NAGARI_STR error message */
+//! typedef int32_t (*NagariFunction)(const NagariValue *args, size_t argc, NagariValue *result);
+//!
+//! typedef struct { const char *name; NagariFunction call; int32_t arity; } NagariFunctionDef;
+//! typedef struct { const char *name; NagariValue value; } NagariConstantDef;
+//!
+//! typedef struct NagariModuleDef {
+//!     uint32_t abi_version;             /* NAGARI_ABI_VERSION */
+//!     const char *name;
+//!     const NagariFunctionDef *functions;
+//!     size_t function_count;
+//!     const NagariConstantDef *constants;
+//!     size_t constant_count;
+//!     void (*release)(NagariValue *result); /* frees a result once copied; may be NULL */
+//! } NagariModuleDef;
+//!
+//! const NagariModuleDef *nagari_module_init(void);
+//! ```
+//!
+//! Scripts reach a module as a global of its name, calling its functions and reading its
+//! constants as attributes: `image.blur(pixels, image.RADIUS)`. Arguments are only valid during
+//! the call. Functions with an arity of -1 take any number of arguments; others are only called
+//! with as many as their arity. Calls of one module don't overlap, and can't call back into the
+//! VM.
+//!
+//! Loading a library runs its code with the privileges of the host, so
+//! [`VM::load_native_module`](crate::VM::load_native_module) checks for
+//! [`Permission::Native`](crate::Permission::Native), which
+//! [`VM::set_allow_native_modules`](crate::VM::set_allow_native_modules) allows.
+
+use crate::native::HostObject;
+use crate::value::Value;
+use std::ffi::{c_char, c_void, CStr};
+use std::path::Path;
+
+/// Version of the ABI below, which modules must have been built against
+pub const ABI_VERSION: u32 = 1;
+
+pub const TAG_NONE: u32 = 0;
+pub const TAG_BOOL: u32 = 1;
+pub const TAG_INT: u32 = 2;
+pub const TAG_FLOAT: u32 = 3;
+pub const TAG_STR: u32 = 4;
+pub const TAG_BYTES: u32 = 5;
+pub const TAG_LIST: u32 = 6;
+
+/// `NagariValue`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginValue {
+    pub tag: u32,
+    pub data: PluginValueData,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union PluginValueData {
+    pub int_val: i64,
+    pub float_val: f64,
+    pub buffer: PluginBuffer,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginBuffer {
+    pub ptr: *const c_void,
+    pub len: usize,
+}
+
+/// `NagariFunction`
+pub type PluginFunction =
+    unsafe extern "C" fn(args: *const PluginValue, argc: usize, result: *mut PluginValue) -> i32;
+
+/// `NagariFunctionDef`
+#[repr(C)]
+pub struct PluginFunctionDef {
+    pub name: *const c_char,
+    pub call: Option<PluginFunction>,
+    pub arity: i32,
+}
+
+/// `NagariConstantDef`
+#[repr(C)]
+pub struct PluginConstantDef {
+    pub name: *const c_char,
+    pub value: PluginValue,
+}
+
+/// `NagariModuleDef`
+#[repr(C)]
+pub struct PluginModuleDef {
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub functions: *const PluginFunctionDef,
+    pub function_count: usize,
+    pub constants: *const PluginConstantDef,
+    pub constant_count: usize,
+    pub release: Option<unsafe extern "C" fn(result: *mut PluginValue)>,
+}
+
+impl PluginValue {
+    const NONE: Self = Self {
+        tag: TAG_NONE,
+        data: PluginValueData { int_val: 0 },
+    };
+
+    fn buffer(tag: u32, ptr: *const c_void, len: usize) -> Self {
+        Self {
+            tag,
+            data: PluginValueData {
+                buffer: PluginBuffer { ptr, len },
+            },
+        }
+    }
+}
+
+struct Function {
+    call: PluginFunction,
+    /// `None` for any number of arguments
+    arity: Option<usize>,
+}
+
+/// A loaded native module, as the object scripts reach it by
+pub struct NativeModule {
+    name: String,
+    functions: Vec<(String, Function)>,
+    constants: Vec<(String, Value)>,
+    release: Option<unsafe extern "C" fn(result: *mut PluginValue)>,
+    /// Kept loaded while the module's functions may be called, unless the module is part of
+    /// the program
+    _library: Option<Library>,
+}
+
+impl NativeModule {
+    /// Load the module of the shared library at `path`, which runs the library's initializers
+    /// and `nagari_module_init`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let library = Library::open(path)?;
+        let init = library.symbol(c"nagari_module_init")?;
+        // The symbol is the `nagari_module_init` of the ABI
+        let init = unsafe {
+            std::mem::transmute::<*mut c_void, unsafe extern "C" fn() -> *const PluginModuleDef>(
+                init,
+            )
+        };
+        let definition = unsafe { init().as_ref() }
+            .ok_or_else(|| format!("{}: nagari_module_init returned NULL", path.display()))?;
+        // The library stays loaded while the module is
+        let mut module = unsafe { Self::describe(definition) }
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        module._library = Some(library);
+        Ok(module)
+    }
+
+    /// The module `definition` describes
+    ///
+    /// # Safety
+    ///
+    /// `definition` and everything it points to must be valid, and its functions callable
+    /// while the module is.
+    unsafe fn describe(definition: &PluginModuleDef) -> Result<Self, String> {
+        if definition.abi_version != ABI_VERSION {
+            return Err(format!(
+                "built for native module ABI version {}, but the VM supports version {ABI_VERSION}",
+                definition.abi_version
+            ));
+        }
+        let name = unsafe { identifier(definition.name) }
+            .ok_or_else(|| "the module has no valid name".to_string())?;
+
+        let mut functions = Vec::new();
+        for function in unsafe { items(definition.functions, definition.function_count) } {
+            let function_name = unsafe { identifier(function.name) }
+                .ok_or_else(|| format!("{name}: a function has no valid name"))?;
+            let call = function
+                .call
+                .ok_or_else(|| format!("{name}.{function_name}: the function is NULL"))?;
+            let arity = usize::try_from(function.arity).ok();
+            functions.push((function_name, Function { call, arity }));
+        }
+
+        let mut constants = Vec::new();
+        for constant in unsafe { items(definition.constants, definition.constant_count) } {
+            let constant_name = unsafe { identifier(constant.name) }
+                .ok_or_else(|| format!("{name}: a constant has no valid name"))?;
+            let value = unsafe { lift(&constant.value) }
+                .map_err(|e| format!("{name}.{constant_name}: {e}"))?;
+            constants.push((constant_name, value));
+        }
+
+        Ok(Self {
+            name,
+            functions,
+            constants,
+            release: definition.release,
+            _library: None,
+        })
+    }
+
+    /// The name scripts reach the module by
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl HostObject for NativeModule {
+    fn type_name(&self) -> &str {
+        "module"
+    }
+
+    fn has_method(&self, name: &str) -> bool {
+        self.functions.iter().any(|(function, _)| function == name)
+    }
+
+    fn call_method(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let qualified = format!("{}.{name}", self.name);
+        let Some((_, function)) = self.functions.iter().find(|(function, _)| function == name)
+        else {
+            return Err(format!("{qualified}() is not a function of the module"));
+        };
+        if let Some(arity) = function.arity.filter(|arity| *arity != args.len()) {
+            return Err(format!(
+                "{qualified}() takes {arity} argument(s) but {} were given",
+                args.len()
+            ));
+        }
+
+        // Lists are lowered into buffers of their own, which must outlive the call
+        let mut lists = Vec::new();
+        let lowered = args
+            .iter()
+            .map(|arg| lower(arg, &mut lists))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|type_name| format!("{qualified}() can't take a {type_name} argument"))?;
+
+        let mut result = PluginValue::NONE;
+        let status = unsafe { (function.call)(lowered.as_ptr(), lowered.len(), &mut result) };
+        let value = unsafe { lift(&result) };
+        if let Some(release) = self.release {
+            unsafe { release(&mut result) };
+        }
+
+        match (status, value) {
+            (0, Ok(value)) => Ok(value),
+            (0, Err(e)) => Err(format!("{qualified}() returned {e}")),
+            (_, Ok(Value::String(message))) => Err(format!("{qualified}(): {message}")),
+            (status, _) => Err(format!("{qualified}() failed with status {status}")),
+        }
+    }
+
+    fn get_property(&self, name: &str) -> Option<Value> {
+        self.constants
+            .iter()
+            .find(|(constant, _)| constant == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn set_property(&mut self, name: &str, _value: Value) -> Result<(), String> {
+        Err(format!(
+            "cannot assign to '{name}' of native module '{}'",
+            self.name
+        ))
+    }
+
+    fn display(&self) -> Option<String> {
+        Some(format!("<native module '{}'>", self.name))
+    }
+}
+
+/// `value` as the ABI passes it, borrowing its strings and bytes. Values of other types than
+/// the ABI's fail with their type name.
+fn lower(value: &Value, lists: &mut Vec<Vec<PluginValue>>) -> Result<PluginValue, &'static str> {
+    Ok(match value {
+        Value::None => PluginValue::NONE,
+        Value::Bool(b) => PluginValue {
+            tag: TAG_BOOL,
+            data: PluginValueData { int_val: *b as i64 },
+        },
+        Value::Int(i) => PluginValue {
+            tag: TAG_INT,
+            data: PluginValueData { int_val: *i },
+        },
+        Value::Float(f) => PluginValue {
+            tag: TAG_FLOAT,
+            data: PluginValueData { float_val: *f },
+        },
+        Value::String(s) => PluginValue::buffer(TAG_STR, s.as_ptr().cast(), s.len()),
+        Value::Bytes(bytes) => PluginValue::buffer(TAG_BYTES, bytes.as_ptr().cast(), bytes.len()),
+        Value::List(items) => {
+            let items = items
+                .iter()
+                .map(|item| lower(item, lists))
+                .collect::<Result<Vec<_>, _>>()?;
+            // Moving the vector into `lists` leaves its buffer where it is
+            let value = PluginValue::buffer(TAG_LIST, items.as_ptr().cast(), items.len());
+            lists.push(items);
+            value
+        }
+        other => return Err(other.type_name()),
+    })
+}
+
+/// Copy of a value a module made
+///
+/// # Safety
+///
+/// The buffers of `value` must be valid for their lengths.
+unsafe fn lift(value: &PluginValue) -> Result<Value, String> {
+    Ok(match value.tag {
+        TAG_NONE => Value::None,
+        TAG_BOOL => Value::Bool(value.data.int_val != 0),
+        TAG_INT => Value::Int(value.data.int_val),
+        TAG_FLOAT => Value::Float(value.data.float_val),
+        TAG_STR => {
+            let buffer = value.data.buffer;
+            let bytes = items::<u8>(buffer.ptr.cast(), buffer.len).to_vec();
+            Value::String(
+                String::from_utf8(bytes)
+                    .map_err(|_| "a string that is not valid UTF-8".to_string())?,
+            )
+        }
+        TAG_BYTES => {
+            let buffer = value.data.buffer;
+            Value::Bytes(items::<u8>(buffer.ptr.cast(), buffer.len).to_vec())
+        }
+        TAG_LIST => {
+            let buffer = value.data.buffer;
+            Value::List(
+                items::<PluginValue>(buffer.ptr.cast(), buffer.len)
+                    .iter()
+                    .map(|item| lift(item))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        tag => return Err(format!("a value of unknown type {tag}")),
+    })
+}
+
+/// The `len` items at `ptr`, which may be NULL when there are none
+///
+/// # Safety
+///
+/// Unless NULL, `ptr` must point to `len` valid items.
+unsafe fn items<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// A name the module gave, if it is a valid identifier
+///
+/// # Safety
+///
+/// Unless NULL, `ptr` must point to a NUL-terminated string.
+unsafe fn identifier(ptr: *const c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let name = CStr::from_ptr(ptr).to_str().ok()?;
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|first| first.is_alphabetic() || first == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    valid.then(|| name.to_string())
+}
+
+/// A loaded shared library, unloaded when dropped
+struct Library {
+    // Never read where native modules aren't supported, as no library is opened there
+    #[cfg_attr(
+        not(any(all(unix, not(target_arch = "wasm32")), windows)),
+        allow(dead_code)
+    )]
+    handle: *mut c_void,
+}
+
+// The handle is only used to look up symbols and unload the library, both thread-safe
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+#[cfg(all(unix, not(target_arch = "wasm32")))]
+impl Library {
+    fn open(path: &Path) -> Result<Self, String> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| format!("{}: the path contains a NUL byte", path.display()))?;
+        let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            // Which names the path already
+            return Err(last_error());
+        }
+        Ok(Self { handle })
+    }
+
+    fn symbol(&self, name: &CStr) -> Result<*mut c_void, String> {
+        let symbol = unsafe { libc::dlsym(self.handle, name.as_ptr()) };
+        if symbol.is_null() {
+            return Err(last_error());
+        }
+        Ok(symbol)
+    }
+}
+
+#[cfg(all(unix, not(target_arch = "wasm32")))]
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.handle) };
+    }
+}
+
+#[cfg(all(unix, not(target_arch = "wasm32")))]
+fn last_error() -> String {
+    let error = unsafe { libc::dlerror() };
+    if error.is_null() {
+        "unknown error".to_string()
+    } else {
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn LoadLibraryW(name: *const u16) -> *mut c_void;
+    fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+    fn FreeLibrary(module: *mut c_void) -> i32;
+}
+
+#[cfg(windows)]
+impl Library {
+    fn open(path: &Path) -> Result<Self, String> {
+        use std::os::windows::ffi::OsStrExt;
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let handle = unsafe { LoadLibraryW(wide.as_ptr()) };
+        if handle.is_null() {
+            return Err(format!(
+                "{}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(Self { handle })
+    }
+
+    fn symbol(&self, name: &CStr) -> Result<*mut c_void, String> {
+        let symbol = unsafe { GetProcAddress(self.handle, name.as_ptr()) };
+        if symbol.is_null() {
+            return Err(format!(
+                "{}: {}",
+                name.to_string_lossy(),
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(symbol)
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Library {
+    fn drop(&mut self) {
+        unsafe { FreeLibrary(self.handle) };
+    }
+}
+
+#[cfg(not(any(all(unix, not(target_arch = "wasm32")), windows)))]
+impl Library {
+    fn open(_path: &Path) -> Result<Self, String> {
+        Err("native modules are not supported on this platform".to_string())
+    }
+
+    fn symbol(&self, _name: &CStr) -> Result<*mut c_void, String> {
+        unreachable!("no library can be opened")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permissions::{Permission, PermissionDecision, PermissionPrompt};
+    use crate::vm::VM;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn round_trip(value: &Value) -> Value {
+        let mut lists = Vec::new();
+        let lowered = lower(value, &mut lists).unwrap();
+        unsafe { lift(&lowered) }.unwrap()
+    }
+
+    #[test]
+    fn test_values_round_trip_through_the_abi() {
+        let values = [
+            Value::None,
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Int(i64::MIN),
+            Value::Float(-2.5),
+            Value::String(String::new()),
+            Value::String("größe ✓".to_string()),
+            Value::Bytes(vec![0, 255, 7]),
+            Value::List(vec![
+                Value::Int(1),
+                Value::List(vec![Value::String("nested".to_string()), Value::None]),
+                Value::List(Vec::new()),
+            ]),
+        ];
+        for value in &values {
+            assert_eq!(&round_trip(value), value);
+        }
+    }
+
+    #[test]
+    fn test_values_outside_the_abi_are_refused() {
+        let mut lists = Vec::new();
+        let dict = Value::Dict(HashMap::new());
+        assert_eq!(lower(&dict, &mut lists).err(), Some("dict"));
+        let list = Value::List(vec![Value::Int(1), Value::Set(Vec::new())]);
+        assert_eq!(lower(&list, &mut lists).err(), Some("set"));
+    }
+
+    #[test]
+    fn test_lifting_checks_values_from_modules() {
+        let invalid = [0xff_u8, 0xfe];
+        let string = PluginValue::buffer(TAG_STR, invalid.as_ptr().cast(), invalid.len());
+        assert_eq!(
+            unsafe { lift(&string) },
+            Err("a string that is not valid UTF-8".to_string())
+        );
+
+        let empty = PluginValue::buffer(TAG_LIST, std::ptr::null(), 0);
+        assert_eq!(unsafe { lift(&empty) }, Ok(Value::List(Vec::new())));
+
+        let unknown = PluginValue {
+            tag: 99,
+            data: PluginValueData { int_val: 0 },
+        };
+        assert_eq!(
+            unsafe { lift(&unknown) },
+            Err("a value of unknown type 99".to_string())
+        );
+    }
+
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+    const BAD_INPUT: &str = "bad input";
+
+    unsafe extern "C" fn add(
+        args: *const PluginValue,
+        argc: usize,
+        result: *mut PluginValue,
+    ) -> i32 {
+        let args = std::slice::from_raw_parts(args, argc);
+        *result = PluginValue {
+            tag: TAG_INT,
+            data: PluginValueData {
+                int_val: args[0].data.int_val + args[1].data.int_val,
+            },
+        };
+        0
+    }
+
+    /// The items of a list in reverse, in a buffer `release` frees
+    unsafe extern "C" fn reverse(
+        args: *const PluginValue,
+        _argc: usize,
+        result: *mut PluginValue,
+    ) -> i32 {
+        let buffer = (*args).data.buffer;
+        let mut items = items(buffer.ptr.cast::<PluginValue>(), buffer.len).to_vec();
+        items.reverse();
+        let items = Box::into_raw(items.into_boxed_slice());
+        *result = PluginValue::buffer(TAG_LIST, items.cast::<PluginValue>().cast(), buffer.len);
+        0
+    }
+
+    unsafe extern "C" fn fail(
+        _args: *const PluginValue,
+        _argc: usize,
+        result: *mut PluginValue,
+    ) -> i32 {
+        *result = PluginValue::buffer(TAG_STR, BAD_INPUT.as_ptr().cast(), BAD_INPUT.len());
+        1
+    }
+
+    unsafe extern "C" fn fail_silently(
+        _args: *const PluginValue,
+        _argc: usize,
+        _result: *mut PluginValue,
+    ) -> i32 {
+        7
+    }
+
+    unsafe extern "C" fn release(result: *mut PluginValue) {
+        let value = *result;
+        if value.tag == TAG_LIST {
+            let items = std::ptr::slice_from_raw_parts_mut(
+                value.data.buffer.ptr as *mut PluginValue,
+                value.data.buffer.len,
+            );
+            drop(Box::from_raw(items));
+        }
+        RELEASED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn definition(
+        name: &'static CStr,
+        functions: &[PluginFunctionDef],
+        constants: &[PluginConstantDef],
+    ) -> PluginModuleDef {
+        PluginModuleDef {
+            abi_version: ABI_VERSION,
+            name: name.as_ptr(),
+            functions: functions.as_ptr(),
+            function_count: functions.len(),
+            constants: constants.as_ptr(),
+            constant_count: constants.len(),
+            release: Some(release),
+        }
+    }
+
+    #[test]
+    fn test_functions_are_called_across_the_abi() {
+        let functions = [
+            PluginFunctionDef {
+                name: c"add".as_ptr(),
+                call: Some(add),
+                arity: 2,
+            },
+            PluginFunctionDef {
+                name: c"reverse".as_ptr(),
+                call: Some(reverse),
+                arity: 1,
+            },
+            PluginFunctionDef {
+                name: c"fail".as_ptr(),
+                call: Some(fail),
+                arity: -1,
+            },
+            PluginFunctionDef {
+                name: c"fail_silently".as_ptr(),
+                call: Some(fail_silently),
+                arity: 0,
+            },
+        ];
+        let constants = [PluginConstantDef {
+            name: c"SCALE".as_ptr(),
+            value: PluginValue {
+                tag: TAG_FLOAT,
+                data: PluginValueData { float_val: 0.5 },
+            },
+        }];
+        let definition = definition(c"kernels", &functions, &constants);
+        let mut module = unsafe { NativeModule::describe(&definition) }.unwrap();
+        assert_eq!(module.name(), "kernels");
+
+        assert_eq!(
+            module.call_method("add", vec![Value::Int(2), Value::Int(40)]),
+            Ok(Value::Int(42))
+        );
+        let released = RELEASED.load(Ordering::SeqCst);
+        let list = vec![Value::Int(1), Value::String("two".to_string()), Value::None];
+        assert_eq!(
+            module.call_method("reverse", vec![Value::List(list)]),
+            Ok(Value::List(vec![
+                Value::None,
+                Value::String("two".to_string()),
+                Value::Int(1)
+            ]))
+        );
+        assert!(RELEASED.load(Ordering::SeqCst) > released);
+
+        assert_eq!(
+            module.call_method("add", vec![Value::Int(1)]),
+            Err("kernels.add() takes 2 argument(s) but 1 were given".to_string())
+        );
+        assert_eq!(
+            module.call_method("fail", vec![Value::Int(1), Value::Bool(true)]),
+            Err("kernels.fail(): bad input".to_string())
+        );
+        assert_eq!(
+            module.call_method("fail_silently", Vec::new()),
+            Err("kernels.fail_silently() failed with status 7".to_string())
+        );
+        assert_eq!(
+            module.call_method("reverse", vec![Value::Dict(HashMap::new())]),
+            Err("kernels.reverse() can't take a dict argument".to_string())
+        );
+
+        assert!(module.has_method("add"));
+        assert!(!module.has_method("SCALE"));
+        assert_eq!(module.get_property("SCALE"), Some(Value::Float(0.5)));
+        assert!(module.set_property("SCALE", Value::Int(1)).is_err());
+    }
+
+    #[test]
+    fn test_definitions_are_checked() {
+        let describe = |definition: &PluginModuleDef| {
+            unsafe { NativeModule::describe(definition) }.err().unwrap()
+        };
+
+        let mut old = definition(c"kernels", &[], &[]);
+        old.abi_version = ABI_VERSION + 1;
+        assert!(describe(&old).contains(&format!("ABI version {}", ABI_VERSION + 1)));
+
+        assert_eq!(
+            describe(&definition(c"not a name", &[], &[])),
+            "the module has no valid name"
+        );
+
+        let functions = [PluginFunctionDef {
+            name: c"missing".as_ptr(),
+            call: None,
+            arity: 0,
+        }];
+        assert_eq!(
+            describe(&definition(c"kernels", &functions, &[])),
+            "kernels.missing: the function is NULL"
+        );
+    }
+
+    /// Build the test plugin with `features` and return the path of its library
+    fn test_plugin(features: &str) -> PathBuf {
+        let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/test-plugin/Cargo.toml");
+        // Next to the tests, in a target directory of its own so the build doesn't wait for
+        // the lock of the one running them
+        let target = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .and_then(Path::parent)
+            .unwrap()
+            .join("test-plugin")
+            .join(if features.is_empty() {
+                "default"
+            } else {
+                features
+            });
+        let status = Command::new(env!("CARGO"))
+            .args([
+                "build",
+                "--quiet",
+                "--manifest-path",
+                manifest,
+                "--target-dir",
+            ])
+            .arg(&target)
+            .args(["--features", features])
+            .status()
+            .unwrap();
+        assert!(status.success(), "building the test plugin failed");
+        target.join("debug").join(format!(
+            "{}nagari_test_plugin{}",
+            std::env::consts::DLL_PREFIX,
+            std::env::consts::DLL_SUFFIX
+        ))
+    }
+
+    fn call(module: &Value, function: &str, args: Vec<Value>) -> Result<Value, String> {
+        match module.get_attr(function)? {
+            Value::NativeMethod(method) => method.call(args),
+            other => panic!("{function} is a {}", other.type_name()),
+        }
+    }
+
+    #[test]
+    fn test_vm_loads_native_modules() {
+        let path = test_plugin("");
+        let mut vm = VM::new(false);
+        assert_eq!(
+            vm.load_native_module(&path),
+            Err("load_native_module(): native modules are not allowed".to_string())
+        );
+        assert!(vm.get_global("testmod").is_none());
+
+        vm.set_allow_native_modules(true);
+        assert_eq!(vm.load_native_module(&path), Ok("testmod".to_string()));
+        let module = vm.get_global("testmod").cloned().unwrap();
+        assert_eq!(module.to_string(), "<native module 'testmod'>");
+
+        let numbers = Value::List(vec![Value::Int(1), Value::Float(2.5)]);
+        assert_eq!(call(&module, "sum", vec![numbers]), Ok(Value::Float(3.5)));
+        assert_eq!(
+            call(&module, "shout", vec![Value::String("quiet".to_string())]),
+            Ok(Value::String("QUIET".to_string()))
+        );
+        assert_eq!(
+            call(
+                &module,
+                "count",
+                vec![Value::None, Value::Int(2), Value::Bool(true)]
+            ),
+            Ok(Value::Int(3))
+        );
+        assert_eq!(
+            call(&module, "sum", vec![Value::Int(5)]),
+            Err("testmod.sum(): expected a list of numbers".to_string())
+        );
+        assert_eq!(module.get_attr("ANSWER"), Ok(Value::Int(42)));
+        assert_eq!(module.get_attr("NOTHING"), Ok(Value::None));
+
+        // Loaded modules are globals the host provides, like host functions
+        vm.clear_globals();
+        assert_eq!(vm.get_global("testmod"), Some(&module));
+    }
+
+    #[test]
+    fn test_loading_checks_the_library() {
+        let mut vm = VM::new(false);
+        vm.set_allow_native_modules(true);
+
+        let error = vm.load_native_module(test_plugin("old-abi")).unwrap_err();
+        assert!(
+            error.contains("built for native module ABI version 0, but the VM supports version 1"),
+            "{error}"
+        );
+
+        let error = vm.load_native_module(test_plugin("no-init")).unwrap_err();
+        assert!(error.contains("nagari_module_init"), "{error}");
+
+        assert!(vm.load_native_module("no/such/library.so").is_err());
+        assert!(vm.get_global("testmod").is_none());
+    }
+
+    #[test]
+    fn test_prompt_decides_on_native_modules() {
+        let mut vm = VM::new(false);
+        vm.set_permission_prompt(Some(PermissionPrompt::new(|permission| match permission {
+            Permission::Native(path) if path == "trusted.so" => PermissionDecision::Allow,
+            _ => PermissionDecision::Deny,
+        })));
+        vm.start_access_report();
+        assert_eq!(
+            vm.load_native_module("untrusted.so"),
+            Err(
+                "load_native_module(): loading native module 'untrusted.so' was denied".to_string()
+            )
+        );
+        // Allowed, but there is no such library
+        assert!(vm.load_native_module("trusted.so").is_err());
+
+        let accesses = vm.finish_access_report();
+        assert_eq!(accesses.len(), 2);
+        assert_eq!(accesses[0].operation, "load_native_module");
+        assert!(!accesses[0].allowed);
+        assert_eq!(
+            accesses[1].permission,
+            Permission::Native("trusted.so".to_string())
+        );
+        assert!(accesses[1].allowed);
+    }
+}
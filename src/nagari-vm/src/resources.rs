@@ -0,0 +1,125 @@
+//! Handles to native resources of the host: files, sockets, textures.
+//!
+//! A host function hands a script a [`Resource`] holding what releases the thing it stands
+//! for. The release runs exactly once, at the first of: the host closing the resource, the end
+//! of the `with` statement that entered it, or the last value referring to it being dropped. A
+//! script that forgets to close a resource still releases it as soon as it stops referring to
+//! it, instead of whenever something gets around to collecting it.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// Releases a native resource
+pub type Release = Box<dyn FnOnce() + Send>;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+struct Handle {
+    id: u64,
+    kind: String,
+    /// Taken when the resource is closed
+    release: Mutex<Option<Release>>,
+}
+
+impl Handle {
+    fn close(&self) -> bool {
+        // Released outside the lock, as releasing may look at the resource again
+        let release = self.release.lock().unwrap().take();
+        match release {
+            Some(release) => {
+                release();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let release = self
+            .release
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        if let Some(release) = release {
+            release();
+        }
+    }
+}
+
+/// A native resource of the host, shared by the values referring to it
+#[derive(Clone)]
+pub struct Resource(Arc<Handle>);
+
+impl Resource {
+    /// A resource of `kind` (`"file"`, `"socket"`, ...), released by `release`
+    pub fn new(kind: &str, release: impl FnOnce() + Send + 'static) -> Self {
+        Self(Arc::new(Handle {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            kind: kind.to_string(),
+            release: Mutex::new(Some(Box::new(release))),
+        }))
+    }
+
+    /// Number telling the resource apart from every other one, for the host to find what the
+    /// resource stands for
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.0.kind
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.release.lock().unwrap().is_none()
+    }
+
+    /// Release the resource now, returning whether it was still open
+    pub fn close(&self) -> bool {
+        self.0.close()
+    }
+
+    pub fn downgrade(&self) -> WeakResource {
+        WeakResource(Arc::downgrade(&self.0))
+    }
+}
+
+impl PartialEq for Resource {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resource")
+            .field("id", &self.id())
+            .field("kind", &self.kind())
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.is_closed() { "closed " } else { "" };
+        write!(f, "<{state}{} {}>", self.kind(), self.id())
+    }
+}
+
+/// Reference to a resource that does not keep it open
+#[derive(Clone)]
+pub struct WeakResource(Weak<Handle>);
+
+impl WeakResource {
+    pub fn upgrade(&self) -> Option<Resource> {
+        self.0.upgrade().map(Resource)
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.0.ptr_eq(&other.0)
+    }
+}
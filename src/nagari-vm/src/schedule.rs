@@ -0,0 +1,503 @@
+//! The `schedule` module: `every(name, ms, job)`, `cron(name, expression, job)`,
+//! `cancel(name)` and `jobs()`.
+//!
+//! Jobs have names, and registering one under the name of another replaces it. `every` runs its
+//! job every `ms` milliseconds, and `cron` at the minutes a cron expression matches in UTC:
+//! `minute hour day month weekday`, each field `*`, a value, a range `a-b`, any of these with a
+//! step `/n`, or a list of them, with `jan`-`dec` and `sun`-`sat` for months and weekdays, or
+//! one of `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly`. As in cron, a day matches if
+//! either its day of the month or its weekday does when both fields are restricted. A job that
+//! missed runs runs once, then again at its next time. `jobs()` lists the jobs as dicts of
+//! their `name`, `every` or `cron`, and `next_run` and `last_run` dates.
+//!
+//! Unlike timers, jobs don't keep a run going: the host drives them with
+//! [`VM::run_due_jobs`](crate::VM::run_due_jobs) when
+//! [`VM::next_job_due`](crate::VM::next_job_due) says. A [`JobStore`] set with
+//! [`VM::set_job_store`](crate::VM::set_job_store) keeps them across restarts: a job registered
+//! again under a saved name and schedule carries on from its saved times, and
+//! [`VM::restore_jobs`](crate::VM::restore_jobs) registers the saved jobs calling a global
+//! function again without the scripts doing so. Clearing the VM's globals removes its jobs, but
+//! leaves them saved.
+
+use crate::timers;
+use crate::value::{civil_date, BuiltinFunction, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "schedule.";
+
+/// The `schedule` module: a dict of builtins named `schedule.<function>`
+pub fn module() -> Value {
+    let functions = [("every", 3), ("cron", 3), ("cancel", 1), ("jobs", 0)];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// When a job runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// Every so many milliseconds
+    Every(u64),
+    /// At the minutes a cron expression matches
+    Cron(String),
+}
+
+/// A registered job, as a [`JobStore`] saves it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub name: String,
+    pub trigger: Trigger,
+    /// Global function the job calls, by which `restore_jobs` finds it again. None for other
+    /// callables.
+    pub function: Option<String>,
+    /// Milliseconds since the epoch
+    pub next_run: i64,
+    pub last_run: Option<i64>,
+}
+
+/// Where a host keeps the jobs scripts register
+pub trait JobStore: Send + Sync {
+    /// The jobs saved last
+    fn load(&self) -> Result<Vec<JobRecord>, String>;
+
+    /// Replace the saved jobs with `jobs`, whenever one is registered, cancelled or run
+    fn save(&self, jobs: &[JobRecord]) -> Result<(), String>;
+}
+
+struct Job {
+    record: JobRecord,
+    timing: Timing,
+    callback: Value,
+}
+
+enum Timing {
+    Every(i64),
+    Cron(Cron),
+}
+
+impl Timing {
+    /// The first time after `time` the job runs at
+    fn after(&self, time: i64) -> Option<i64> {
+        match self {
+            Timing::Every(period) => Some(time + period),
+            Timing::Cron(cron) => cron.after(time),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    /// In the order they were registered
+    jobs: Vec<Job>,
+    /// Saved jobs that haven't been registered again
+    saved: Vec<JobRecord>,
+    store: Option<Arc<dyn JobStore>>,
+}
+
+impl Scheduler {
+    /// Call the builtin `name` of the module. `globals` tell whether a job calls a global
+    /// function.
+    pub fn call(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        globals: &HashMap<String, Value>,
+    ) -> Result<Value, String> {
+        let function = &name[PREFIX.len()..];
+        match (function, args) {
+            ("every" | "cron", [Value::String(job), when, callback]) => {
+                if !matches!(
+                    callback,
+                    Value::Builtin(_) | Value::Function(_) | Value::Method(_)
+                ) {
+                    return Err(format!(
+                        "{function}() job must be a function, not '{}'",
+                        callback.type_name()
+                    ));
+                }
+                let trigger = match when {
+                    _ if function == "every" => {
+                        let period = timers::delay(function, when)?;
+                        Trigger::Every(period.as_millis().max(1) as u64)
+                    }
+                    Value::String(expression) => Trigger::Cron(expression.clone()),
+                    other => {
+                        return Err(format!(
+                            "cron() expression must be a str, not '{}'",
+                            other.type_name()
+                        ))
+                    }
+                };
+                let global = match callback {
+                    Value::Function(f) if globals.get(&f.name) == Some(callback) => {
+                        Some(f.name.clone())
+                    }
+                    _ => None,
+                };
+                self.register(job, trigger, global, callback.clone(), now())
+                    .map_err(|e| format!("{function}(): {e}"))?;
+                Ok(Value::None)
+            }
+            ("every" | "cron", [other, _, _]) => Err(format!(
+                "{function}() name must be a str, not '{}'",
+                other.type_name()
+            )),
+            ("cancel", [Value::String(job)]) => self
+                .cancel(job)
+                .map(Value::Bool)
+                .map_err(|e| format!("cancel(): {e}")),
+            ("cancel", [other]) => Err(format!(
+                "cancel() name must be a str, not '{}'",
+                other.type_name()
+            )),
+            ("jobs", []) => Ok(Value::List(
+                self.jobs.iter().map(|job| info(&job.record)).collect(),
+            )),
+            ("every" | "cron", _) => Err(format!(
+                "{function}() takes 3 argument(s) ({} given)",
+                args.len()
+            )),
+            ("cancel", _) => Err(format!(
+                "cancel() takes 1 argument(s) ({} given)",
+                args.len()
+            )),
+            ("jobs", _) => Err(format!("jobs() takes no arguments ({} given)", args.len())),
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    /// Register the job `name`, replacing any other of that name. A saved job of that name and
+    /// schedule passes its times on.
+    pub fn register(
+        &mut self,
+        name: &str,
+        trigger: Trigger,
+        function: Option<String>,
+        callback: Value,
+        now: i64,
+    ) -> Result<(), String> {
+        let timing = match &trigger {
+            Trigger::Every(period) => Timing::Every((*period).max(1) as i64),
+            Trigger::Cron(expression) => Timing::Cron(
+                Cron::parse(expression)
+                    .map_err(|e| format!("invalid cron expression '{expression}': {e}"))?,
+            ),
+        };
+        let saved = self
+            .saved
+            .iter()
+            .position(|saved| saved.name == name)
+            .map(|index| self.saved.remove(index))
+            .filter(|saved| saved.trigger == trigger);
+        let (next_run, last_run) = match saved {
+            Some(saved) => (saved.next_run, saved.last_run),
+            None => (timing.after(now).unwrap_or(i64::MAX), None),
+        };
+
+        self.jobs.retain(|job| job.record.name != name);
+        self.jobs.push(Job {
+            record: JobRecord {
+                name: name.to_string(),
+                trigger,
+                function,
+                next_run,
+                last_run,
+            },
+            timing,
+            callback,
+        });
+        self.save()
+    }
+
+    /// Remove the job `name`, registered or saved, returning whether there was one
+    pub fn cancel(&mut self, name: &str) -> Result<bool, String> {
+        let count = self.jobs.len() + self.saved.len();
+        self.jobs.retain(|job| job.record.name != name);
+        self.saved.retain(|saved| saved.name != name);
+        if self.jobs.len() + self.saved.len() == count {
+            return Ok(false);
+        }
+        self.save().map(|()| true)
+    }
+
+    /// Remove every registered job, leaving it saved if there is a store
+    pub fn clear(&mut self) {
+        let jobs = self.jobs.drain(..).map(|job| job.record);
+        if self.store.is_some() {
+            self.saved.extend(jobs);
+        }
+    }
+
+    /// Keep the jobs in `store`, taking the jobs it saved that aren't registered as saved ones
+    pub fn set_store(&mut self, store: Option<Arc<dyn JobStore>>) -> Result<(), String> {
+        self.saved = match &store {
+            Some(store) => {
+                let registered = |name: &str| self.jobs.iter().any(|job| job.record.name == name);
+                let saved = store.load().map_err(|e| format!("cannot load jobs: {e}"))?;
+                saved
+                    .into_iter()
+                    .filter(|saved| !registered(&saved.name))
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        self.store = store;
+        self.save()
+    }
+
+    /// Save the registered and saved jobs, if there is a store
+    pub fn save(&self) -> Result<(), String> {
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let records: Vec<JobRecord> = self
+            .jobs
+            .iter()
+            .map(|job| job.record.clone())
+            .chain(self.saved.iter().cloned())
+            .collect();
+        store
+            .save(&records)
+            .map_err(|e| format!("cannot save jobs: {e}"))
+    }
+
+    pub fn jobs(&self) -> Vec<JobRecord> {
+        self.jobs.iter().map(|job| job.record.clone()).collect()
+    }
+
+    /// Saved jobs that haven't been registered again
+    pub fn saved(&self) -> &[JobRecord] {
+        &self.saved
+    }
+
+    /// Callbacks of the registered jobs, by name
+    pub fn callbacks(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.jobs
+            .iter()
+            .map(|job| (job.record.name.as_str(), &job.callback))
+    }
+
+    /// When the earliest job is due, if any is registered
+    pub fn next_due(&self) -> Option<i64> {
+        self.jobs.iter().map(|job| job.record.next_run).min()
+    }
+
+    /// Names of the jobs due by `time`, earliest first
+    pub fn due(&self, time: i64) -> Vec<String> {
+        let mut due: Vec<(i64, usize, &str)> = self
+            .jobs
+            .iter()
+            .enumerate()
+            .filter(|(_, job)| job.record.next_run <= time)
+            .map(|(index, job)| (job.record.next_run, index, job.record.name.as_str()))
+            .collect();
+        due.sort();
+        due.into_iter()
+            .map(|(_, _, name)| name.to_string())
+            .collect()
+    }
+
+    /// Start a run of the job `name` at `time` if it is still due, returning its callback. Its
+    /// next run is at its next time after the one it was due at, or after `time` if it missed
+    /// that too.
+    pub fn start(&mut self, name: &str, time: i64) -> Option<Value> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.record.name == name && job.record.next_run <= time)?;
+        let next_run = job
+            .timing
+            .after(job.record.next_run)
+            .filter(|next_run| *next_run > time)
+            .or_else(|| job.timing.after(time));
+        job.record.next_run = next_run.unwrap_or(i64::MAX);
+        job.record.last_run = Some(time);
+        Some(job.callback.clone())
+    }
+}
+
+/// `time` in milliseconds since the epoch
+pub fn millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+/// The time `millis` milliseconds after the epoch
+pub fn system_time(millis: i64) -> SystemTime {
+    let offset = Duration::from_millis(millis.unsigned_abs());
+    if millis < 0 {
+        UNIX_EPOCH - offset
+    } else {
+        UNIX_EPOCH + offset
+    }
+}
+
+fn now() -> i64 {
+    millis(SystemTime::now())
+}
+
+/// A job as `jobs()` lists it
+fn info(record: &JobRecord) -> Value {
+    let trigger = match &record.trigger {
+        Trigger::Every(period) => ("every".to_string(), Value::Int(*period as i64)),
+        Trigger::Cron(expression) => ("cron".to_string(), Value::String(expression.clone())),
+    };
+    let last_run = record
+        .last_run
+        .map_or(Value::None, |time| Value::Date(time as f64));
+    Value::Dict(HashMap::from([
+        ("name".to_string(), Value::String(record.name.clone())),
+        trigger,
+        ("next_run".to_string(), Value::Date(record.next_run as f64)),
+        ("last_run".to_string(), last_run),
+    ]))
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// The values of each field a cron expression matches, as bit sets
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether both the day of the month and the weekday are restricted, so that a day
+    /// matching either matches
+    either_day: bool,
+}
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!("expected 5 fields, got {}", fields.len()));
+        };
+        // Both 0 and 7 are Sunday
+        let mut weekdays = field(weekday, 0, 7, &WEEKDAYS)?;
+        if weekdays & 1 << 7 != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        let cron = Self {
+            minutes: field(minute, 0, 59, &[])?,
+            hours: field(hour, 0, 23, &[])?,
+            days: field(day, 1, 31, &[])?,
+            months: field(month, 1, 12, &MONTHS)?,
+            weekdays,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        };
+        // Like `30 0 31 4 *`, as April has 30 days
+        if cron.after(0).is_none() {
+            return Err("it never matches".to_string());
+        }
+        Ok(cron)
+    }
+
+    /// Whether the date `days` after 1970-01-01 matches
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_date(days);
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4).rem_euclid(7);
+        let day = self.days & 1 << day != 0;
+        let weekday = self.weekdays & 1 << weekday != 0;
+        self.months & 1 << month != 0
+            && if self.either_day {
+                day || weekday
+            } else {
+                day && weekday
+            }
+    }
+
+    /// The first minute it matches after `time`, both in milliseconds since the epoch
+    fn after(&self, time: i64) -> Option<i64> {
+        let start = time.div_euclid(60_000) + 1;
+        let (mut day, mut first_minute) = (start.div_euclid(1440), start.rem_euclid(1440));
+        // Any date comes around within 8 years, February 29 included
+        for _ in 0..8 * 366 {
+            if self.matches_day(day) {
+                let minute = (first_minute..1440).find(|minute| {
+                    self.hours & 1 << (minute / 60) != 0 && self.minutes & 1 << (minute % 60) != 0
+                });
+                if let Some(minute) = minute {
+                    return Some((day * 1440 + minute) * 60_000);
+                }
+            }
+            day += 1;
+            first_minute = 0;
+        }
+        None
+    }
+}
+
+/// The values a cron field matches, as a bit set. `names` stand for the values from `min` on.
+fn field(text: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| {
+        let value = match names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(text))
+        {
+            Some(index) => index as u32 + min,
+            None => text
+                .parse()
+                .map_err(|_| format!("invalid value '{text}'"))?,
+        };
+        if (min..=max).contains(&value) {
+            Ok(value)
+        } else {
+            Err(format!("{value} is not within {min}-{max}"))
+        }
+    };
+
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("invalid step '{step}'")),
+            },
+            None => (part, None),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // `a/n` runs from `a` to the end
+            None if step.is_some() => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if first > last {
+            return Err(format!("invalid range '{range}'"));
+        }
+        for value in (first..=last).step_by(step.unwrap_or(1)) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
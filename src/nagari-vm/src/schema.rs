@@ -0,0 +1,330 @@
+//! The `schema` module: `validate(schema, data)`, `errors(schema, data)` and
+//! `optional(spec[, default])`.
+//!
+//! A schema is a dict from field names to specs. A spec is a type name (`"str"`, `"int"`,
+//! `"float"`, `"bool"`, `"list"`, `"dict"` or `"any"`, with a trailing `?` to make the field
+//! optional), a nested schema, a one-item list `[spec]` for a list of such items, or
+//! `optional(spec, default)`. A missing optional field gets its default, `none` unless given,
+//! and optional fields also accept `none`.
+//!
+//! Validation coerces where it is lossless: strings of numbers to `int` and `float`,
+//! `"true"`/`"false"` (and `yes`/`no`, `on`/`off`, `1`/`0`) to `bool`, ints to `float` and
+//! whole floats to `int`. The result holds the schema's fields only. Errors name the field's
+//! path, such as `address.city` or `tags[1]`.
+
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "schema.";
+
+/// Key marking the dict `optional()` returns
+const MARKER: &str = "__schema__";
+
+/// The `schema` module: a dict of builtins named `schema.<function>`
+pub fn module() -> Value {
+    let functions = [("validate", 2), ("errors", 2), ("optional", 1)];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Call the builtin `name` of the module
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let function = &name[PREFIX.len()..];
+    match (function, args) {
+        ("optional", [spec]) => Ok(optional(spec.clone(), Value::None)),
+        ("optional", [spec, default]) => Ok(optional(spec.clone(), default.clone())),
+        ("validate" | "errors", [schema @ Value::Dict(_), data]) => {
+            let mut validator = Validator::default();
+            let value = validator.value(schema, data, "")?;
+            match function {
+                "errors" => Ok(Value::List(
+                    validator.errors.into_iter().map(Value::String).collect(),
+                )),
+                _ if validator.errors.is_empty() => Ok(value),
+                _ => Err(format!(
+                    "validation failed: {}",
+                    validator.errors.join("; ")
+                )),
+            }
+        }
+        ("validate" | "errors", [schema, _]) => Err(format!(
+            "{function}() schema must be a dict, not '{}'",
+            schema.type_name()
+        )),
+        ("optional", _) => Err(format!(
+            "optional() takes 1 or 2 arguments ({} given)",
+            args.len()
+        )),
+        _ => Err(format!(
+            "{function}() takes exactly 2 arguments ({} given)",
+            args.len()
+        )),
+    }
+}
+
+fn optional(spec: Value, default: Value) -> Value {
+    Value::Dict(HashMap::from([
+        (MARKER.to_string(), Value::String("optional".to_string())),
+        ("spec".to_string(), spec),
+        ("default".to_string(), default),
+    ]))
+}
+
+/// The spec and default of an optional spec, if `spec` is one
+fn as_optional(spec: &Value) -> Option<(Value, Value)> {
+    match spec {
+        Value::String(name) => name
+            .strip_suffix('?')
+            .map(|name| (Value::String(name.to_string()), Value::None)),
+        Value::Dict(dict) if dict.contains_key(MARKER) => Some((
+            dict.get("spec").cloned().unwrap_or(Value::None),
+            dict.get("default").cloned().unwrap_or(Value::None),
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Validator {
+    errors: Vec<String>,
+}
+
+impl Validator {
+    /// `data` checked and coerced against `spec`. Mismatches are recorded as errors; an
+    /// invalid spec fails the call.
+    fn value(&mut self, spec: &Value, data: &Value, path: &str) -> Result<Value, String> {
+        if let Some((spec, _)) = as_optional(spec) {
+            return match data {
+                Value::None => Ok(Value::None),
+                data => self.value(&spec, data, path),
+            };
+        }
+        match spec {
+            Value::String(name) => match coerce(name, data)? {
+                Some(value) => Ok(value),
+                None => {
+                    self.error(path, format!("expected {name}, got {}", data.type_name()));
+                    Ok(Value::None)
+                }
+            },
+            Value::Dict(fields) => {
+                let Value::Dict(data) = data else {
+                    self.error(path, format!("expected dict, got {}", data.type_name()));
+                    return Ok(Value::None);
+                };
+                // In order of the names, so errors come out the same on every run
+                let mut names: Vec<&String> = fields.keys().collect();
+                names.sort();
+                let mut result = HashMap::new();
+                for name in names {
+                    let field_path = match path {
+                        "" => name.clone(),
+                        _ => format!("{path}.{name}"),
+                    };
+                    let value = match (data.get(name), as_optional(&fields[name])) {
+                        (Some(value), _) => self.value(&fields[name], value, &field_path)?,
+                        (None, Some((_, default))) => default,
+                        (None, None) => {
+                            self.error(&field_path, "required field is missing".to_string());
+                            continue;
+                        }
+                    };
+                    result.insert(name.clone(), value);
+                }
+                Ok(Value::Dict(result))
+            }
+            Value::List(items) => {
+                let [item_spec] = items.as_slice() else {
+                    return Err("invalid schema: a list spec takes exactly one item spec".into());
+                };
+                let Value::List(data) = data else {
+                    self.error(path, format!("expected list, got {}", data.type_name()));
+                    return Ok(Value::None);
+                };
+                data.iter()
+                    .enumerate()
+                    .map(|(index, item)| self.value(item_spec, item, &format!("{path}[{index}]")))
+                    .collect::<Result<_, _>>()
+                    .map(Value::List)
+            }
+            other => Err(format!(
+                "invalid schema: expected a type name, dict, list or optional(), got {}",
+                other.type_name()
+            )),
+        }
+    }
+
+    fn error(&mut self, path: &str, message: String) {
+        self.errors.push(match path {
+            "" => message,
+            _ => format!("{path}: {message}"),
+        });
+    }
+}
+
+/// `data` as the type `name`, or `None` if it isn't one and can't be converted
+fn coerce(name: &str, data: &Value) -> Result<Option<Value>, String> {
+    let value = match (name, data) {
+        ("any", data) => Some(data.clone()),
+        ("str", Value::String(_))
+        | ("int", Value::Int(_))
+        | ("float", Value::Float(_))
+        | ("bool", Value::Bool(_))
+        | ("list", Value::List(_))
+        | ("dict", Value::Dict(_)) => Some(data.clone()),
+        ("int", Value::Float(f)) if f.fract() == 0.0 && f.is_finite() => {
+            Some(Value::Int(*f as i64))
+        }
+        ("int", Value::String(s)) => s.trim().parse().ok().map(Value::Int),
+        ("float", Value::Int(n)) => Some(Value::Float(*n as f64)),
+        ("float", Value::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|f| f.is_finite())
+            .map(Value::Float),
+        ("bool", Value::Int(n @ (0 | 1))) => Some(Value::Bool(*n == 1)),
+        ("bool", Value::String(s)) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Some(Value::Bool(true)),
+            "false" | "no" | "off" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ("str" | "int" | "float" | "bool" | "list" | "dict", _) => None,
+        _ => return Err(format!("invalid schema: unknown type '{name}'")),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict(entries: &[(&str, Value)]) -> Value {
+        Value::Dict(
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn errors(schema: &Value, data: &Value) -> Vec<String> {
+        match call("schema.errors", &[schema.clone(), data.clone()]).unwrap() {
+            Value::List(errors) => errors
+                .into_iter()
+                .map(|error| match error {
+                    Value::String(error) => error,
+                    other => panic!("expected a string, got {other:?}"),
+                })
+                .collect(),
+            other => panic!("expected a list, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_validation_coerces_and_keeps_the_schema_fields() {
+        let port = call("schema.optional", &[string("int"), Value::Int(80)]).unwrap();
+        let schema = dict(&[
+            ("name", string("str")),
+            ("port", port),
+            ("debug", string("bool")),
+            ("ratio", string("float")),
+            ("tags", Value::List(vec![string("int")])),
+        ]);
+        let data = dict(&[
+            ("name", string("api")),
+            ("debug", string("yes")),
+            ("ratio", Value::Int(2)),
+            ("tags", Value::List(vec![string("1"), Value::Float(2.0)])),
+            ("extra", Value::Int(1)),
+        ]);
+
+        let expected = dict(&[
+            ("name", string("api")),
+            ("port", Value::Int(80)),
+            ("debug", Value::Bool(true)),
+            ("ratio", Value::Float(2.0)),
+            ("tags", Value::List(vec![Value::Int(1), Value::Int(2)])),
+        ]);
+        assert_eq!(call("schema.validate", &[schema, data]).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_errors_name_the_field_paths_in_order() {
+        let schema = dict(&[
+            (
+                "address",
+                dict(&[("city", string("str")), ("zip", string("int"))]),
+            ),
+            ("tags", Value::List(vec![string("str")])),
+            ("nickname", string("str?")),
+        ]);
+        let data = dict(&[
+            ("address", dict(&[("zip", string("12a"))])),
+            ("tags", Value::List(vec![string("a"), Value::Int(1)])),
+            ("nickname", Value::None),
+        ]);
+
+        assert_eq!(
+            errors(&schema, &data),
+            [
+                "address.city: required field is missing",
+                "address.zip: expected int, got str",
+                "tags[1]: expected str, got int",
+            ]
+        );
+        assert_eq!(
+            call("schema.validate", &[schema, data]).unwrap_err(),
+            "validation failed: address.city: required field is missing; \
+             address.zip: expected int, got str; tags[1]: expected str, got int"
+        );
+    }
+
+    #[test]
+    fn test_only_lossless_coercions_are_made() {
+        let schema = dict(&[("n", string("int")), ("on", string("bool"))]);
+        let data = dict(&[("n", Value::Float(1.5)), ("on", Value::Int(2))]);
+
+        assert_eq!(
+            errors(&schema, &data),
+            ["n: expected int, got float", "on: expected bool, got int"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_schemas_fail_the_call() {
+        let unknown = dict(&[("n", string("integer"))]);
+        let data = dict(&[("n", Value::Int(1))]);
+        assert_eq!(
+            call("schema.validate", &[unknown, data.clone()]).unwrap_err(),
+            "invalid schema: unknown type 'integer'"
+        );
+
+        let list = dict(&[("n", Value::List(vec![]))]);
+        assert_eq!(
+            call("schema.errors", &[list, data.clone()]).unwrap_err(),
+            "invalid schema: a list spec takes exactly one item spec"
+        );
+
+        assert_eq!(
+            call("schema.validate", &[string("int"), data]).unwrap_err(),
+            "validate() schema must be a dict, not 'str'"
+        );
+    }
+}
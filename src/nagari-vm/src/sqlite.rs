@@ -0,0 +1,465 @@
+//! The `sqlite` module: `open(path)`, `execute(db, sql[, params])`, `query(db, sql[, params])`,
+//! `begin(db)`, `commit(db)`, `rollback(db)` and `close(db)`.
+//!
+//! Databases are the integers `open` returns, and `open(":memory:")` opens one that lives in
+//! memory. `params` fill the placeholders of a statement: a list the `?` ones in order, and a
+//! dict the `:name` ones by name, so values never have to be put into the SQL itself. Ints,
+//! floats, strs, bytes, bools (as 1 and 0) and `none` (as NULL) can be bound. `execute` runs a
+//! statement and returns a dict of how many rows it `changes` and the `last_insert_id` of the
+//! database; `query` returns the rows a statement selects, each a dict keyed by column name.
+//! `begin` starts a transaction, which `commit` ends keeping its changes and `rollback`
+//! discarding them.
+//!
+//! Opening a database needs IO access to its path, which hosts allow with
+//! [`VM::set_allow_io`](crate::VM::set_allow_io) or a
+//! [permission prompt](crate::permissions). Databases belong to the VM that opened them, and
+//! clearing its globals closes them.
+
+use crate::permissions::{Permission, Permissions};
+use crate::value::{BuiltinFunction, Value};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{Connection, Statement};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "sqlite.";
+
+/// The `sqlite` module: a dict of builtins named `sqlite.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("open", 1),
+        ("execute", 2),
+        ("query", 2),
+        ("begin", 1),
+        ("commit", 1),
+        ("rollback", 1),
+        ("close", 1),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// The open databases of a VM
+#[derive(Default)]
+pub struct Sqlite {
+    /// Each behind a lock only so that the VM can be shared between threads
+    databases: HashMap<i64, Mutex<Connection>>,
+    next_id: i64,
+    permissions: Permissions,
+}
+
+impl Sqlite {
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    /// Call the builtin `name` of the module
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, String> {
+        let function = &name[PREFIX.len()..];
+        match (function, args) {
+            ("open", [Value::String(path)]) => {
+                self.permissions.check(name, Permission::Io(path.clone()))?;
+                let connection = Connection::open(path)
+                    .map_err(|e| format!("open(): cannot open '{path}': {e}"))?;
+                self.next_id += 1;
+                self.databases.insert(self.next_id, Mutex::new(connection));
+                Ok(Value::Int(self.next_id))
+            }
+            ("open", [other]) => Err(format!(
+                "open() path must be a str, not '{}'",
+                other.type_name()
+            )),
+            ("execute", [db, Value::String(sql), params @ ..]) if params.len() <= 1 => {
+                let connection = self.database(function, db)?;
+                let mut statement = prepare(function, connection, sql)?;
+                bind(function, &mut statement, params.first())?;
+                let changes = statement
+                    .raw_execute()
+                    .map_err(|e| format!("execute(): {e}"))?;
+                Ok(Value::Dict(HashMap::from([
+                    ("changes".to_string(), Value::Int(changes as i64)),
+                    (
+                        "last_insert_id".to_string(),
+                        Value::Int(connection.last_insert_rowid()),
+                    ),
+                ])))
+            }
+            ("query", [db, Value::String(sql), params @ ..]) if params.len() <= 1 => {
+                let connection = self.database(function, db)?;
+                let mut statement = prepare(function, connection, sql)?;
+                bind(function, &mut statement, params.first())?;
+                let columns: Vec<String> = statement
+                    .column_names()
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                let mut rows = statement.raw_query();
+                let mut result = Vec::new();
+                while let Some(row) = rows.next().map_err(|e| format!("query(): {e}"))? {
+                    let mut fields = HashMap::new();
+                    for (i, column) in columns.iter().enumerate() {
+                        let value = row.get_ref(i).map_err(|e| format!("query(): {e}"))?;
+                        fields.insert(column.clone(), from_sql(value));
+                    }
+                    result.push(Value::Dict(fields));
+                }
+                Ok(Value::List(result))
+            }
+            ("execute" | "query", [_, other, ..]) if args.len() <= 3 => Err(format!(
+                "{function}() sql must be a str, not '{}'",
+                other.type_name()
+            )),
+            ("begin" | "commit" | "rollback", [db]) => {
+                let sql = match function {
+                    "begin" => "BEGIN",
+                    "commit" => "COMMIT",
+                    _ => "ROLLBACK",
+                };
+                self.database(function, db)?
+                    .execute_batch(sql)
+                    .map_err(|e| format!("{function}(): {e}"))?;
+                Ok(Value::None)
+            }
+            ("close", [db]) => {
+                let id = database_id(function, db)?;
+                Ok(Value::Bool(self.databases.remove(&id).is_some()))
+            }
+            ("execute" | "query", _) => Err(format!(
+                "{function}() takes 2 or 3 argument(s) ({} given)",
+                args.len()
+            )),
+            ("open" | "begin" | "commit" | "rollback" | "close", _) => Err(format!(
+                "{function}() takes 1 argument(s) ({} given)",
+                args.len()
+            )),
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    pub fn close_all(&mut self) {
+        self.databases.clear();
+    }
+
+    fn database(&mut self, function: &str, value: &Value) -> Result<&mut Connection, String> {
+        let id = database_id(function, value)?;
+        self.databases
+            .get_mut(&id)
+            .map(|database| database.get_mut().unwrap_or_else(PoisonError::into_inner))
+            .ok_or_else(|| format!("{function}(): database {id} is not open"))
+    }
+}
+
+fn database_id(function: &str, value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Int(id) => Ok(*id),
+        other => Err(format!(
+            "{function}() database must be an int, not '{}'",
+            other.type_name()
+        )),
+    }
+}
+
+fn prepare<'c>(
+    function: &str,
+    connection: &'c Connection,
+    sql: &str,
+) -> Result<Statement<'c>, String> {
+    connection
+        .prepare(sql)
+        .map_err(|e| format!("{function}(): {e}"))
+}
+
+/// Bind `params` to the placeholders of `statement`: a list to `?` ones in order, and a dict to
+/// named ones
+fn bind(function: &str, statement: &mut Statement, params: Option<&Value>) -> Result<(), String> {
+    let count = statement.parameter_count();
+    match params {
+        None | Some(Value::None) if count == 0 => Ok(()),
+        Some(Value::List(values)) => {
+            if values.len() != count {
+                return Err(format!(
+                    "{function}() statement has {count} parameter(s) ({} given)",
+                    values.len()
+                ));
+            }
+            for (i, value) in values.iter().enumerate() {
+                bind_value(function, statement, i + 1, value)?;
+            }
+            Ok(())
+        }
+        Some(Value::Dict(values)) => {
+            for index in 1..=count {
+                let Some(name) = statement.parameter_name(index).map(str::to_string) else {
+                    return Err(format!(
+                        "{function}() params must be a list for '?' placeholders"
+                    ));
+                };
+                // Names are written `:name`, `@name` or `$name`
+                let value = values
+                    .get(&name[1..])
+                    .ok_or_else(|| format!("{function}() has no value for parameter '{name}'"))?;
+                bind_value(function, statement, index, value)?;
+            }
+            Ok(())
+        }
+        None | Some(Value::None) => Err(format!(
+            "{function}() statement has {count} parameter(s) (0 given)"
+        )),
+        Some(other) => Err(format!(
+            "{function}() params must be a list or dict, not '{}'",
+            other.type_name()
+        )),
+    }
+}
+
+fn bind_value(
+    function: &str,
+    statement: &mut Statement,
+    index: usize,
+    value: &Value,
+) -> Result<(), String> {
+    let value = match value {
+        Value::Int(n) => SqlValue::Integer(*n),
+        Value::Float(f) => SqlValue::Real(*f),
+        Value::String(s) => SqlValue::Text(s.clone()),
+        Value::Bytes(data) => SqlValue::Blob(data.clone()),
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::None => SqlValue::Null,
+        other => {
+            return Err(format!(
+                "{function}() cannot bind a '{}' parameter",
+                other.type_name()
+            ))
+        }
+    };
+    statement
+        .raw_bind_parameter(index, value)
+        .map_err(|e| format!("{function}(): {e}"))
+}
+
+fn from_sql(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::None,
+        ValueRef::Integer(n) => Value::Int(n),
+        ValueRef::Real(f) => Value::Float(f),
+        ValueRef::Text(text) => Value::String(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(data) => Value::Bytes(data.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn dict(entries: &[(&str, Value)]) -> Value {
+        Value::Dict(
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    /// A database in memory with a `users` table
+    fn users() -> (Sqlite, Value) {
+        let mut sqlite = Sqlite::default();
+        sqlite.permissions.set_allow_io(true);
+        let db = sqlite.call("sqlite.open", &[string(":memory:")]).unwrap();
+        let create = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, score REAL, \
+                      avatar BLOB, admin INTEGER)";
+        sqlite
+            .call("sqlite.execute", &[db.clone(), string(create)])
+            .unwrap();
+        (sqlite, db)
+    }
+
+    fn names(sqlite: &mut Sqlite, db: &Value) -> Vec<Value> {
+        let rows = sqlite
+            .call(
+                "sqlite.query",
+                &[db.clone(), string("SELECT name FROM users ORDER BY id")],
+            )
+            .unwrap();
+        let Value::List(rows) = rows else {
+            panic!("query() returns a list");
+        };
+        rows.into_iter()
+            .map(|row| match row {
+                Value::Dict(mut row) => row.remove("name").unwrap(),
+                other => panic!("rows are dicts, not {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_params_are_bound_not_spliced_into_the_sql() {
+        let (mut sqlite, db) = users();
+        let insert = string("INSERT INTO users (name, score, avatar, admin) VALUES (?, ?, ?, ?)");
+        let name = "Robert'); DROP TABLE users; --";
+        let params = Value::List(vec![
+            string(name),
+            Value::Float(1.5),
+            Value::Bytes(vec![0, 255]),
+            Value::Bool(true),
+        ]);
+        assert_eq!(
+            sqlite
+                .call("sqlite.execute", &[db.clone(), insert, params])
+                .unwrap(),
+            dict(&[
+                ("changes", Value::Int(1)),
+                ("last_insert_id", Value::Int(1))
+            ])
+        );
+        let insert = string("INSERT INTO users (name, score) VALUES (:name, :score)");
+        let params = dict(&[("name", string("ada")), ("score", Value::None)]);
+        sqlite
+            .call("sqlite.execute", &[db.clone(), insert, params])
+            .unwrap();
+
+        let select = string("SELECT * FROM users WHERE name = ?");
+        assert_eq!(
+            sqlite
+                .call(
+                    "sqlite.query",
+                    &[db.clone(), select, Value::List(vec![string(name)])]
+                )
+                .unwrap(),
+            Value::List(vec![dict(&[
+                ("id", Value::Int(1)),
+                ("name", string(name)),
+                ("score", Value::Float(1.5)),
+                ("avatar", Value::Bytes(vec![0, 255])),
+                ("admin", Value::Int(1)),
+            ])])
+        );
+        assert_eq!(names(&mut sqlite, &db), [string(name), string("ada")]);
+    }
+
+    #[test]
+    fn test_params_must_match_the_placeholders() {
+        let (mut sqlite, db) = users();
+        let insert = string("INSERT INTO users (name, score) VALUES (?, ?)");
+        let named = string("SELECT * FROM users WHERE name = :name");
+        for (sql, params, error) in [
+            (
+                insert.clone(),
+                Some(Value::List(vec![string("ada")])),
+                "execute() statement has 2 parameter(s) (1 given)",
+            ),
+            (
+                insert.clone(),
+                None,
+                "execute() statement has 2 parameter(s) (0 given)",
+            ),
+            (
+                insert.clone(),
+                Some(dict(&[("name", string("ada"))])),
+                "execute() params must be a list for '?' placeholders",
+            ),
+            (
+                named,
+                Some(dict(&[("nam", string("ada"))])),
+                "execute() has no value for parameter ':name'",
+            ),
+            (
+                insert,
+                Some(Value::List(vec![string("ada"), Value::List(vec![])])),
+                "execute() cannot bind a 'list' parameter",
+            ),
+        ] {
+            let mut args = vec![db.clone(), sql];
+            args.extend(params);
+            assert_eq!(sqlite.call("sqlite.execute", &args).unwrap_err(), error);
+        }
+        assert!(names(&mut sqlite, &db).is_empty());
+    }
+
+    #[test]
+    fn test_rollback_discards_what_commit_keeps() {
+        let (mut sqlite, db) = users();
+        let insert = |sqlite: &mut Sqlite, name: &str| {
+            let sql = string("INSERT INTO users (name) VALUES (?)");
+            let params = Value::List(vec![string(name)]);
+            sqlite
+                .call("sqlite.execute", &[db.clone(), sql, params])
+                .unwrap();
+        };
+
+        sqlite
+            .call("sqlite.begin", std::slice::from_ref(&db))
+            .unwrap();
+        insert(&mut sqlite, "kept");
+        sqlite
+            .call("sqlite.commit", std::slice::from_ref(&db))
+            .unwrap();
+        sqlite
+            .call("sqlite.begin", std::slice::from_ref(&db))
+            .unwrap();
+        insert(&mut sqlite, "discarded");
+        assert_eq!(names(&mut sqlite, &db).len(), 2);
+        sqlite
+            .call("sqlite.rollback", std::slice::from_ref(&db))
+            .unwrap();
+
+        assert_eq!(names(&mut sqlite, &db), [string("kept")]);
+        assert_eq!(
+            sqlite
+                .call("sqlite.rollback", std::slice::from_ref(&db))
+                .unwrap_err(),
+            "rollback(): cannot rollback - no transaction is active"
+        );
+        assert_eq!(
+            sqlite
+                .call("sqlite.close", std::slice::from_ref(&db))
+                .unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            sqlite.call("sqlite.begin", &[db]).unwrap_err(),
+            "begin(): database 1 is not open"
+        );
+    }
+
+    #[test]
+    fn test_opening_needs_io_access() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("app.db");
+        let path = path.to_string_lossy().into_owned();
+        let mut sqlite = Sqlite::default();
+        let permissions = Permissions::default();
+        sqlite.set_permissions(permissions.clone());
+
+        permissions.start_report();
+        for path in [path.as_str(), ":memory:"] {
+            assert_eq!(
+                sqlite.call("sqlite.open", &[string(path)]).unwrap_err(),
+                "open(): IO operations are not allowed"
+            );
+        }
+        let accesses = permissions.finish_report();
+        assert_eq!(accesses.len(), 2);
+        assert_eq!(accesses[0].permission, Permission::Io(path.clone()));
+        assert!(!std::path::Path::new(&path).exists());
+
+        permissions.set_allow_io(true);
+        sqlite.call("sqlite.open", &[string(&path)]).unwrap();
+        assert!(std::path::Path::new(&path).exists());
+    }
+}
@@ -0,0 +1,275 @@
+//! The `timers` module: `sleep(ms)`, `set_timeout(fn, ms)`, `set_interval(fn, ms)` and
+//! `cancel(handle)`.
+//!
+//! Timers belong to the VM that set them and only fire while it runs: while the script sleeps,
+//! and after its last instruction until none is left, the way an event loop keeps a program
+//! alive. Callbacks run in the order they are due, an interval's again every period until it
+//! is cancelled. Handles are the integers `set_timeout` and `set_interval` return.
+//!
+//! Clearing the VM's globals cancels every timer, and so does a run that fails or is dropped
+//! before it finishes, so no callback outlives the script that scheduled it.
+
+use crate::value::{BuiltinFunction, Value};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "timers.";
+
+/// The `timers` module: a dict of builtins named `timers.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("sleep", 1),
+        ("set_timeout", 2),
+        ("set_interval", 2),
+        ("cancel", 1),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+struct Timer {
+    id: i64,
+    due: Instant,
+    /// Period of an interval
+    interval: Option<Duration>,
+    callback: Value,
+}
+
+#[derive(Default)]
+pub struct Timers {
+    pending: Vec<Timer>,
+    next_id: i64,
+}
+
+impl Timers {
+    /// Schedule `callback` in `delay`, and every `delay` after that if `repeat`, returning the
+    /// timer's handle
+    pub fn schedule(&mut self, callback: Value, delay: Duration, repeat: bool) -> i64 {
+        self.next_id += 1;
+        self.pending.push(Timer {
+            id: self.next_id,
+            due: Instant::now() + delay,
+            // As in browsers, an interval waits at least a millisecond between callbacks
+            interval: repeat.then_some(delay.max(Duration::from_millis(1))),
+            callback,
+        });
+        self.next_id
+    }
+
+    /// Cancel the timer `id`, returning whether it was pending
+    pub fn cancel(&mut self, id: i64) -> bool {
+        let count = self.pending.len();
+        self.pending.retain(|timer| timer.id != id);
+        self.pending.len() != count
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Number of timers that have not fired or been cancelled
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Callbacks of the timers that have not fired or been cancelled, by handle
+    pub fn callbacks(&self) -> impl Iterator<Item = (i64, &Value)> {
+        self.pending.iter().map(|timer| (timer.id, &timer.callback))
+    }
+
+    /// When the earliest timer is due, if any is pending
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|timer| timer.due).min()
+    }
+
+    /// The callback of the earliest timer due by `deadline`, if any, and when it is due. An
+    /// interval is scheduled for its next period, and a timeout is done.
+    pub fn next_due(&mut self, deadline: Option<Instant>) -> Option<(Instant, Value)> {
+        // Timers due at the same time fire in the order they were set
+        let index = (0..self.pending.len())
+            .min_by_key(|&index| (self.pending[index].due, self.pending[index].id))?;
+        let due = self.pending[index].due;
+        if deadline.is_some_and(|deadline| due > deadline) {
+            return None;
+        }
+
+        let timer = &mut self.pending[index];
+        match timer.interval {
+            Some(period) => {
+                // A late interval skips the periods it missed rather than firing in a burst
+                timer.due = (due + period).max(Instant::now());
+                Some((due, timer.callback.clone()))
+            }
+            None => Some((due, self.pending.remove(index).callback)),
+        }
+    }
+}
+
+/// A delay in milliseconds, as passed to `function`
+pub fn delay(function: &str, value: &Value) -> Result<Duration, String> {
+    let ms = match value {
+        Value::Int(ms) => *ms as f64,
+        Value::Float(ms) => *ms,
+        other => {
+            return Err(format!(
+                "{function}() delay must be a number of milliseconds, not '{}'",
+                other.type_name()
+            ))
+        }
+    };
+    if !ms.is_finite() || ms < 0.0 {
+        return Err(format!("{function}() delay must not be negative, got {ms}"));
+    }
+    Ok(Duration::from_secs_f64(ms / 1000.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::HostCallback;
+    use crate::vm::VM;
+    use std::sync::{Arc, Mutex};
+
+    fn builtin(name: &str) -> Value {
+        Value::Builtin(BuiltinFunction {
+            name: name.to_string(),
+            arity: 0,
+        })
+    }
+
+    /// A VM with host functions `first`, `second` and `tick` that log their names when called
+    fn logging_vm() -> (VM, Arc<Mutex<Vec<&'static str>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut vm = VM::new(false);
+        for name in ["first", "second", "tick"] {
+            let log = log.clone();
+            let callback = HostCallback::Sync(Arc::new(move |_| {
+                log.lock().unwrap().push(name);
+                Ok(Value::None)
+            }));
+            vm.register_host_function(name, callback, None);
+        }
+        (vm, log)
+    }
+
+    #[test]
+    fn test_timers_come_due_in_order() {
+        let mut timers = Timers::default();
+        let later = timers.schedule(builtin("later"), Duration::from_secs(60), false);
+        let first = timers.schedule(builtin("first"), Duration::ZERO, false);
+        let second = timers.schedule(builtin("second"), Duration::ZERO, false);
+        assert!(later < first && first < second);
+        assert_eq!(timers.pending(), 3);
+
+        let now = Some(Instant::now());
+        assert_eq!(timers.next_due(now).map(|(_, f)| f), Some(builtin("first")));
+        assert_eq!(
+            timers.next_due(now).map(|(_, f)| f),
+            Some(builtin("second"))
+        );
+        // The one a minute away isn't due yet, unless the caller waits for everything
+        assert_eq!(timers.next_due(now), None);
+        assert_eq!(timers.pending(), 1);
+        assert_eq!(
+            timers.next_due(None).map(|(_, f)| f),
+            Some(builtin("later"))
+        );
+        assert_eq!(timers.pending(), 0);
+        assert_eq!(timers.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_intervals_repeat_until_cancelled() {
+        let mut timers = Timers::default();
+        let id = timers.schedule(builtin("tick"), Duration::ZERO, true);
+        let (due, _) = timers.next_due(None).unwrap();
+        // An interval of 0 still waits a millisecond between callbacks
+        assert!(timers.next_deadline().unwrap() >= due + Duration::from_millis(1));
+        for _ in 0..3 {
+            assert_eq!(timers.next_due(None).map(|(_, f)| f), Some(builtin("tick")));
+        }
+        assert_eq!(timers.pending(), 1);
+
+        assert!(timers.cancel(id));
+        assert!(!timers.cancel(id));
+        assert_eq!(timers.next_due(None), None);
+    }
+
+    #[test]
+    fn test_delays_are_milliseconds() {
+        assert_eq!(
+            delay("sleep", &Value::Int(250)),
+            Ok(Duration::from_millis(250))
+        );
+        assert_eq!(
+            delay("sleep", &Value::Float(1.5)),
+            Ok(Duration::from_micros(1500))
+        );
+        assert_eq!(
+            delay("sleep", &Value::Int(-1)),
+            Err("sleep() delay must not be negative, got -1".to_string())
+        );
+        assert_eq!(
+            delay("sleep", &Value::Float(f64::NAN)),
+            Err("sleep() delay must not be negative, got NaN".to_string())
+        );
+        assert_eq!(
+            delay("sleep", &Value::String("1".to_string())),
+            Err("sleep() delay must be a number of milliseconds, not 'str'".to_string())
+        );
+    }
+
+    async fn call(vm: &mut VM, function: &str, args: Vec<Value>) -> Result<Value, String> {
+        vm.call(builtin(&format!("{PREFIX}{function}")), args).await
+    }
+
+    #[tokio::test]
+    async fn test_sleeping_fires_the_timers_due_meanwhile() {
+        let (mut vm, log) = logging_vm();
+        let timeout = |name, ms| vec![builtin(name), Value::Int(ms)];
+        call(&mut vm, "set_timeout", timeout("second", 60))
+            .await
+            .unwrap();
+        call(&mut vm, "set_timeout", timeout("first", 10))
+            .await
+            .unwrap();
+        let interval = call(&mut vm, "set_interval", timeout("tick", 50))
+            .await
+            .unwrap();
+        assert_eq!(vm.pending_timers(), 3);
+
+        call(&mut vm, "sleep", vec![Value::Int(120)]).await.unwrap();
+        // The interval ticked at 50 and 100 ms
+        assert_eq!(*log.lock().unwrap(), ["first", "tick", "second", "tick"]);
+        assert_eq!(vm.pending_timers(), 1);
+
+        let cancel = vec![interval];
+        assert_eq!(
+            call(&mut vm, "cancel", cancel.clone()).await,
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            call(&mut vm, "cancel", cancel).await,
+            Ok(Value::Bool(false))
+        );
+        call(&mut vm, "sleep", vec![Value::Int(60)]).await.unwrap();
+        assert_eq!(log.lock().unwrap().len(), 4);
+
+        assert_eq!(
+            call(&mut vm, "set_timeout", vec![Value::Int(1), Value::Int(5)]).await,
+            Err("set_timeout() callback must be a function, not 'int'".to_string())
+        );
+    }
+}
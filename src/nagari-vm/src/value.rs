@@ -1,3 +1,10 @@
+use crate::bytecode::BytecodeFile;
+use crate::native::{NativeMethod, NativeObject};
+use crate::resources::Resource;
+use crate::weakref::WeakRef;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+
 #[derive(Debug, Clone, PartialEq)]
 #[allow(dead_code)]
 pub enum Value {
@@ -10,14 +17,202 @@ pub enum Value {
     Function(Function),
     Builtin(BuiltinFunction),
     None,
+    /// Point in time, in milliseconds since the Unix epoch (UTC)
+    Date(f64),
+    /// Distinct values in insertion order
+    Set(Vec<Value>),
+    /// Set that cannot change once built, as `frozenset()` returns
+    FrozenSet(Vec<Value>),
+    /// Entries with keys of any type, in insertion order
+    Map(Vec<(Value, Value)>),
+    Bytes(Vec<u8>),
+    /// Asynchronous host function call that has not been awaited yet
+    Pending(PendingCall),
+    Class(Arc<Class>),
+    Instance(Instance),
+    /// Method of a class, bound to the instance it was looked up on
+    Method(Method),
+    /// What `super(class, instance)` returns: the instance, looking up attributes in the
+    /// classes after `class` in the method resolution order of its own
+    Super(Super),
+    /// Native resource of the host
+    Resource(Resource),
+    /// What `weakref.ref(obj)` returns
+    WeakRef(WeakRef),
+    /// Object of the host
+    Native(NativeObject),
+    /// Method of a host object, bound to the object it was looked up on
+    NativeMethod(NativeMethod),
+    /// Opaque reference to an object the host keeps, by id and type tag. Scripts can store,
+    /// pass around and compare handles, but nothing else.
+    Handle(u64, String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Function defined by a script. Its body is part of the bytecode that defined it, which it
+/// keeps running in after other bytecode is loaded.
+#[derive(Clone, PartialEq)]
 pub struct Function {
     pub name: String,
-    pub arity: usize,
-    pub code: Vec<u8>, // Bytecode for the function
-    pub is_async: bool,
+    /// Names of the parameters, with a `*` before the one taking the positional arguments left
+    /// over as a list and `**` before the one taking keyword arguments as a dict
+    pub parameters: Vec<String>,
+    /// Index of the first instruction of the body
+    pub entry: usize,
+    pub code: Arc<BytecodeFile>,
+}
+
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("parameters", &self.parameters)
+            .field("entry", &self.entry)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Class defined by a script. Classes compare equal only to themselves.
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub base: Option<Arc<Class>>,
+    /// Methods and class attributes, by name
+    pub attributes: HashMap<String, Value>,
+}
+
+impl PartialEq for Class {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Class {
+    /// The class followed by its bases, nearest first: the order its attributes are looked
+    /// up in
+    pub fn mro(&self) -> impl Iterator<Item = &Class> {
+        std::iter::successors(Some(self), |class| class.base.as_deref())
+    }
+
+    /// Attribute `name` of the class or, failing that, of the nearest base that has it
+    pub fn lookup(&self, name: &str) -> Option<&Value> {
+        self.mro().find_map(|class| class.attributes.get(name))
+    }
+
+    pub fn is_subclass_of(&self, other: &Class) -> bool {
+        self.mro().any(|class| class == other)
+    }
+}
+
+/// Instance of a script class. Copies of the value refer to the same instance, so changes to
+/// its fields show through all of them.
+#[derive(Clone)]
+pub struct Instance {
+    pub class: Arc<Class>,
+    fields: Arc<Mutex<HashMap<String, Value>>>,
+}
+
+impl Instance {
+    pub fn new(class: Arc<Class>) -> Self {
+        Self {
+            class,
+            fields: Arc::default(),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn set_field(&self, name: &str, value: Value) {
+        self.fields.lock().unwrap().insert(name.to_string(), value);
+    }
+
+    pub fn downgrade(&self) -> WeakInstance {
+        WeakInstance {
+            class: Arc::clone(&self.class),
+            fields: Arc::downgrade(&self.fields),
+        }
+    }
+
+    /// Attribute `name`: a field of the instance, or an attribute of its class with methods
+    /// bound to the instance
+    pub fn get_attr(&self, name: &str) -> Option<Value> {
+        self.field(name)
+            .or_else(|| Some(self.bind(self.class.lookup(name)?)))
+    }
+
+    /// `attribute` of a class as looked up on the instance: bound to it if it is a method
+    pub fn bind(&self, attribute: &Value) -> Value {
+        match attribute {
+            Value::Function(function) => Value::Method(Method {
+                receiver: self.clone(),
+                function: function.clone(),
+            }),
+            other => other.clone(),
+        }
+    }
+}
+
+impl PartialEq for Instance {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.fields, &other.fields)
+    }
+}
+
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Not the fields, which may refer back to the instance
+        f.debug_struct("Instance")
+            .field("class", &self.class.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reference to an instance that does not keep it alive
+#[derive(Clone)]
+pub struct WeakInstance {
+    class: Arc<Class>,
+    fields: Weak<Mutex<HashMap<String, Value>>>,
+}
+
+impl WeakInstance {
+    pub fn upgrade(&self) -> Option<Instance> {
+        Some(Instance {
+            class: Arc::clone(&self.class),
+            fields: self.fields.upgrade()?,
+        })
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.fields.ptr_eq(&other.fields)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Method {
+    pub receiver: Instance,
+    pub function: Function,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Super {
+    pub class: Arc<Class>,
+    pub instance: Instance,
+}
+
+impl Super {
+    /// Attribute `name` of the first class after `class` in the instance's method resolution
+    /// order that has it, bound to the instance
+    pub fn get_attr(&self, name: &str) -> Option<Value> {
+        let attribute = self
+            .instance
+            .class
+            .mro()
+            .skip_while(|class| *class != &*self.class)
+            .skip(1)
+            .find_map(|class| class.attributes.get(name))?;
+        Some(self.instance.bind(attribute))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -26,6 +221,13 @@ pub struct BuiltinFunction {
     pub arity: usize,
 }
 
+/// Handle to the future of a host function call, which the VM keeps until it is awaited
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingCall {
+    pub id: u64,
+    pub function: String,
+}
+
 impl Value {
     pub fn type_name(&self) -> &'static str {
         match self {
@@ -38,6 +240,21 @@ impl Value {
             Value::Function(_) => "function",
             Value::Builtin(_) => "builtin",
             Value::None => "none",
+            Value::Date(_) => "date",
+            Value::Set(_) => "set",
+            Value::FrozenSet(_) => "frozenset",
+            Value::Map(_) => "map",
+            Value::Bytes(_) => "bytes",
+            Value::Pending(_) => "awaitable",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "object",
+            Value::Method(_) => "method",
+            Value::Super(_) => "super",
+            Value::Resource(_) => "resource",
+            Value::WeakRef(_) => "weakref",
+            Value::Native(_) => "object",
+            Value::NativeMethod(_) => "method",
+            Value::Handle(..) => "handle",
         }
     }
 
@@ -49,11 +266,122 @@ impl Value {
             Value::String(s) => !s.is_empty(),
             Value::List(l) => !l.is_empty(),
             Value::Dict(d) => !d.is_empty(),
+            Value::Set(s) | Value::FrozenSet(s) => !s.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+            Value::Bytes(b) => !b.is_empty(),
             Value::None => false,
             _ => true,
         }
     }
 
+    /// `self.name`: the entry of a module namespace or other dict, an attribute of an
+    /// instance, class, host object or `super()`, or the `kind` or `closed` of a resource
+    pub fn get_attr(&self, name: &str) -> Result<Value, String> {
+        match self {
+            Value::Dict(d) => d.get(name).cloned(),
+            Value::Class(class) => class.lookup(name).cloned(),
+            Value::Instance(instance) => instance.get_attr(name),
+            Value::Super(proxy) => proxy.get_attr(name),
+            Value::Native(object) => object.get_attr(name),
+            Value::Resource(resource) => match name {
+                "kind" => Some(Value::String(resource.kind().to_string())),
+                "closed" => Some(Value::Bool(resource.is_closed())),
+                _ => None,
+            },
+            _ => None,
+        }
+        .ok_or_else(|| match self {
+            Value::Instance(instance) => {
+                format!("'{}' object has no attribute '{name}'", instance.class.name)
+            }
+            Value::Class(class) => format!("class '{}' has no attribute '{name}'", class.name),
+            Value::Native(object) => {
+                format!("'{}' object has no attribute '{name}'", object.type_name())
+            }
+            _ => format!("'{}' object has no attribute '{name}'", self.type_name()),
+        })
+    }
+
+    /// `self.name = value`, which only instances and host objects take
+    pub fn set_attr(&self, name: &str, value: Value) -> Result<(), String> {
+        match self {
+            Value::Instance(instance) => {
+                instance.set_field(name, value);
+                Ok(())
+            }
+            Value::Native(object) => object.set_attr(name, value),
+            _ => Err(format!(
+                "cannot set attribute '{name}' of '{}' object",
+                self.type_name()
+            )),
+        }
+    }
+
+    /// `self[index]`. Strings are indexed by Unicode scalar value, and negative indices
+    /// count from the end of strings and lists.
+    pub fn get_item(&self, index: &Value) -> Result<Value, String> {
+        match (self, index) {
+            (Value::String(s), Value::Int(i)) => {
+                let length = s.chars().count();
+                resolve_index(*i, length)
+                    .and_then(|i| s.chars().nth(i))
+                    .map(|c| Value::String(c.to_string()))
+                    .ok_or_else(|| "string index out of range".to_string())
+            }
+            (Value::List(l), Value::Int(i)) => resolve_index(*i, l.len())
+                .map(|i| l[i].clone())
+                .ok_or_else(|| "list index out of range".to_string()),
+            (Value::Dict(d), Value::String(key)) => d
+                .get(key)
+                .cloned()
+                .ok_or_else(|| format!("key not found: '{key}'")),
+            (Value::Map(m), key) => m
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .ok_or_else(|| format!("key not found: '{key}'")),
+            (Value::Bytes(b), Value::Int(i)) => resolve_index(*i, b.len())
+                .map(|i| Value::Int(b[i] as i64))
+                .ok_or_else(|| "bytes index out of range".to_string()),
+            _ => Err(format!(
+                "'{}' object cannot be indexed by '{}'",
+                self.type_name(),
+                index.type_name()
+            )),
+        }
+    }
+
+    /// `self[start:stop:step]` with Python semantics. Strings are sliced by Unicode scalar
+    /// value, matching `len()`.
+    pub fn slice(
+        &self,
+        start: Option<i64>,
+        stop: Option<i64>,
+        step: Option<i64>,
+    ) -> Result<Value, String> {
+        let step = step.unwrap_or(1);
+        if step == 0 {
+            return Err("slice step cannot be zero".to_string());
+        }
+
+        match self {
+            Value::String(s) => {
+                let chars: Vec<char> = s.chars().collect();
+                let indices = slice_indices(chars.len(), start, stop, step);
+                Ok(Value::String(indices.map(|i| chars[i]).collect()))
+            }
+            Value::List(l) => {
+                let indices = slice_indices(l.len(), start, stop, step);
+                Ok(Value::List(indices.map(|i| l[i].clone()).collect()))
+            }
+            Value::Bytes(b) => {
+                let indices = slice_indices(b.len(), start, stop, step);
+                Ok(Value::Bytes(indices.map(|i| b[i]).collect()))
+            }
+            _ => Err(format!("'{}' object is not sliceable", self.type_name())),
+        }
+    }
+
     pub fn add(&self, other: &Value) -> Result<Value, String> {
         match (self, other) {
             (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
@@ -66,6 +394,7 @@ impl Value {
                 result.extend(b.clone());
                 Ok(Value::List(result))
             }
+            (Value::Bytes(a), Value::Bytes(b)) => Ok(Value::Bytes([a.as_slice(), b].concat())),
             _ => Err(format!(
                 "Cannot add {} and {}",
                 self.type_name(),
@@ -80,6 +409,12 @@ impl Value {
             (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
             (Value::Int(a), Value::Float(b)) => Ok(Value::Float(*a as f64 - b)),
             (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a - *b as f64)),
+            _ if self.is_set() && other.is_set() => Ok(self.set_operation(other, |a, b| {
+                a.iter()
+                    .filter(|item| !contains(b, item))
+                    .cloned()
+                    .collect()
+            })),
             _ => Err(format!(
                 "Cannot subtract {} and {}",
                 self.type_name(),
@@ -178,16 +513,198 @@ impl Value {
         }
     }
 
+    pub fn power(&self, other: &Value) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) if *b >= 0 => u32::try_from(*b)
+                .ok()
+                .and_then(|b| a.checked_pow(b))
+                .map(Value::Int)
+                .ok_or_else(|| "Integer overflow in power".to_string()),
+            (Value::Int(a), Value::Int(b)) => Ok(Value::Float((*a as f64).powf(*b as f64))),
+            (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a.powf(*b))),
+            (Value::Int(a), Value::Float(b)) => Ok(Value::Float((*a as f64).powf(*b))),
+            (Value::Float(a), Value::Int(b)) => Ok(Value::Float(a.powf(*b as f64))),
+            _ => Err(format!(
+                "Cannot raise {} to {}",
+                self.type_name(),
+                other.type_name()
+            )),
+        }
+    }
+
+    /// Division rounded towards negative infinity, like Python's `//`
+    pub fn floor_divide(&self, other: &Value) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => {
+                if *b == 0 {
+                    Err("Division by zero".to_string())
+                } else {
+                    let quotient = a / b;
+                    if a % b != 0 && (*a < 0) != (*b < 0) {
+                        Ok(Value::Int(quotient - 1))
+                    } else {
+                        Ok(Value::Int(quotient))
+                    }
+                }
+            }
+            _ => match self.divide(other)? {
+                Value::Float(quotient) => Ok(Value::Float(quotient.floor())),
+                quotient => Ok(quotient),
+            },
+        }
+    }
+
+    /// `&`: bitwise and of integers, or the intersection of sets
+    pub fn bit_and(&self, other: &Value) -> Result<Value, String> {
+        if self.is_set() && other.is_set() {
+            return Ok(self.set_operation(other, |a, b| {
+                a.iter().filter(|item| contains(b, item)).cloned().collect()
+            }));
+        }
+        self.integer_operation(other, "bitwise and", |a, b| Some(a & b))
+    }
+
+    /// `|`: bitwise or of integers, or the union of sets
+    pub fn bit_or(&self, other: &Value) -> Result<Value, String> {
+        if self.is_set() && other.is_set() {
+            return Ok(self.set_operation(other, |a, b| {
+                let mut union = a.to_vec();
+                union.extend(b.iter().filter(|item| !contains(a, item)).cloned());
+                union
+            }));
+        }
+        self.integer_operation(other, "bitwise or", |a, b| Some(a | b))
+    }
+
+    /// `^`: bitwise xor of integers, or the elements in exactly one of two sets
+    pub fn bit_xor(&self, other: &Value) -> Result<Value, String> {
+        if self.is_set() && other.is_set() {
+            return Ok(self.set_operation(other, |a, b| {
+                let mut difference: Vec<Value> = a
+                    .iter()
+                    .filter(|item| !contains(b, item))
+                    .cloned()
+                    .collect();
+                difference.extend(b.iter().filter(|item| !contains(a, item)).cloned());
+                difference
+            }));
+        }
+        self.integer_operation(other, "bitwise xor", |a, b| Some(a ^ b))
+    }
+
+    pub fn left_shift(&self, other: &Value) -> Result<Value, String> {
+        self.integer_operation(other, "shift", |a, b| a.checked_shl(u32::try_from(b).ok()?))
+    }
+
+    pub fn right_shift(&self, other: &Value) -> Result<Value, String> {
+        self.integer_operation(other, "shift", |a, b| {
+            // Shifting by the width or more leaves only the sign
+            Some(a >> u32::try_from(b).ok()?.min(63))
+        })
+    }
+
+    pub fn negate(&self) -> Result<Value, String> {
+        match self {
+            Value::Int(n) => n
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| "Integer overflow in negation".to_string()),
+            Value::Float(f) => Ok(Value::Float(-f)),
+            _ => Err(format!("Cannot negate {}", self.type_name())),
+        }
+    }
+
+    /// Unary `+`, which only numbers take
+    pub fn positive(&self) -> Result<Value, String> {
+        match self {
+            Value::Int(_) | Value::Float(_) => Ok(self.clone()),
+            _ => Err(format!("Cannot apply unary + to {}", self.type_name())),
+        }
+    }
+
+    /// Bitwise `~`
+    pub fn invert(&self) -> Result<Value, String> {
+        match self {
+            Value::Int(n) => Ok(Value::Int(!n)),
+            _ => Err(format!("Cannot bitwise invert {}", self.type_name())),
+        }
+    }
+
+    fn integer_operation(
+        &self,
+        other: &Value,
+        operation: &str,
+        apply: impl Fn(i64, i64) -> Option<i64>,
+    ) -> Result<Value, String> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => apply(*a, *b)
+                .map(Value::Int)
+                .ok_or_else(|| format!("Invalid {operation} of {a} by {b}")),
+            _ => Err(format!(
+                "Cannot {operation} {} and {}",
+                self.type_name(),
+                other.type_name()
+            )),
+        }
+    }
+
     pub fn equals(&self, other: &Value) -> Value {
-        Value::Bool(self == other)
+        Value::Bool(self.is_equal(other))
     }
 
     pub fn not_equals(&self, other: &Value) -> Value {
-        Value::Bool(self != other)
+        Value::Bool(!self.is_equal(other))
+    }
+
+    /// `==`. Sets and frozen sets are equal when they have the same elements, in any order.
+    fn is_equal(&self, other: &Value) -> bool {
+        match (self.set_elements(), other.set_elements()) {
+            (Some(a), Some(b)) => a.len() == b.len() && is_subset(a, b),
+            _ => self == other,
+        }
+    }
+
+    fn is_set(&self) -> bool {
+        self.set_elements().is_some()
+    }
+
+    fn set_elements(&self) -> Option<&[Value]> {
+        match self {
+            Value::Set(items) | Value::FrozenSet(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Combine the elements of two sets into a set of the same kind as `self`
+    fn set_operation(
+        &self,
+        other: &Value,
+        combine: impl Fn(&[Value], &[Value]) -> Vec<Value>,
+    ) -> Value {
+        let items = combine(
+            self.set_elements().unwrap_or_default(),
+            other.set_elements().unwrap_or_default(),
+        );
+        match self {
+            Value::FrozenSet(_) => Value::FrozenSet(items),
+            _ => Value::Set(items),
+        }
+    }
+
+    /// Compare two sets by inclusion, for `<`, `<=`, `>` and `>=`
+    fn compare_sets(&self, other: &Value, proper: bool, subset: bool) -> Option<Value> {
+        let (a, b) = (self.set_elements()?, other.set_elements()?);
+        let (smaller, larger) = if subset { (a, b) } else { (b, a) };
+        let included = is_subset(smaller, larger) && !(proper && smaller.len() == larger.len());
+        Some(Value::Bool(included))
     }
 
     pub fn less(&self, other: &Value) -> Result<Value, String> {
+        if let Some(result) = self.compare_sets(other, true, true) {
+            return Ok(result);
+        }
         match (self, other) {
+            (Value::Date(a), Value::Date(b)) => Ok(Value::Bool(a < b)),
             (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
             (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a < b)),
             (Value::Int(a), Value::Float(b)) => Ok(Value::Bool((*a as f64) < *b)),
@@ -202,7 +719,11 @@ impl Value {
     }
 
     pub fn greater(&self, other: &Value) -> Result<Value, String> {
+        if let Some(result) = self.compare_sets(other, true, false) {
+            return Ok(result);
+        }
         match (self, other) {
+            (Value::Date(a), Value::Date(b)) => Ok(Value::Bool(a > b)),
             (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
             (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a > b)),
             (Value::Int(a), Value::Float(b)) => Ok(Value::Bool((*a as f64) > *b)),
@@ -217,7 +738,11 @@ impl Value {
     }
 
     pub fn less_equal(&self, other: &Value) -> Result<Value, String> {
+        if let Some(result) = self.compare_sets(other, false, true) {
+            return Ok(result);
+        }
         match (self, other) {
+            (Value::Date(a), Value::Date(b)) => Ok(Value::Bool(a <= b)),
             (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
             (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a <= b)),
             (Value::Int(a), Value::Float(b)) => Ok(Value::Bool((*a as f64) <= *b)),
@@ -232,7 +757,11 @@ impl Value {
     }
 
     pub fn greater_equal(&self, other: &Value) -> Result<Value, String> {
+        if let Some(result) = self.compare_sets(other, false, false) {
+            return Ok(result);
+        }
         match (self, other) {
+            (Value::Date(a), Value::Date(b)) => Ok(Value::Bool(a >= b)),
             (Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
             (Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a >= b)),
             (Value::Int(a), Value::Float(b)) => Ok(Value::Bool((*a as f64) >= *b)),
@@ -245,6 +774,94 @@ impl Value {
             )),
         }
     }
+
+    /// JSON text of the value, as `json.dumps()` writes it; see [`crate::json`]
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn to_json(&self, indent: Option<usize>) -> Result<String, String> {
+        let style = crate::json::Style {
+            indent,
+            canonical: false,
+        };
+        crate::json::stringify(self, &style)
+    }
+
+    /// Canonical JSON text of the value: no whitespace, keys and set elements sorted, and
+    /// floats written the same way on every platform. Meant for comparing values in tests and
+    /// hashing them.
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn to_canonical_json(&self) -> Result<String, String> {
+        crate::json::stringify(self, &crate::json::Style::CANONICAL)
+    }
+}
+
+/// Whether `items` has an element equal to `value`
+fn contains(items: &[Value], value: &Value) -> bool {
+    items.iter().any(|item| item.is_equal(value))
+}
+
+/// Whether every element of `a` is in `b`
+fn is_subset(a: &[Value], b: &[Value]) -> bool {
+    a.iter().all(|item| contains(b, item))
+}
+
+/// Distinct elements of `items`, in the order they first appear
+pub(crate) fn distinct(items: impl IntoIterator<Item = Value>) -> Vec<Value> {
+    let mut distinct = Vec::new();
+    for item in items {
+        if !contains(&distinct, &item) {
+            distinct.push(item);
+        }
+    }
+    distinct
+}
+
+/// Resolve a possibly negative index against `length`
+fn resolve_index(index: i64, length: usize) -> Option<usize> {
+    let index = if index < 0 {
+        index + length as i64
+    } else {
+        index
+    };
+    (0..length as i64)
+        .contains(&index)
+        .then_some(index as usize)
+}
+
+/// Positions selected by a slice over a sequence of `length` items; `step` is non-zero
+fn slice_indices(
+    length: usize,
+    start: Option<i64>,
+    stop: Option<i64>,
+    step: i64,
+) -> impl Iterator<Item = usize> {
+    let length = length as i64;
+    let bound = |index: Option<i64>, default: i64, low: i64, high: i64| match index {
+        None => default,
+        Some(i) if i < 0 => (i + length).clamp(low, high),
+        Some(i) => i.clamp(low, high),
+    };
+
+    let (mut current, stop) = if step > 0 {
+        (bound(start, 0, 0, length), bound(stop, length, 0, length))
+    } else {
+        (
+            bound(start, length - 1, -1, length - 1),
+            bound(stop, -1, -1, length - 1),
+        )
+    };
+
+    std::iter::from_fn(move || {
+        let in_range = if step > 0 {
+            current < stop
+        } else {
+            current > stop
+        };
+        in_range.then(|| {
+            let index = current as usize;
+            current += step;
+            index
+        })
+    })
 }
 
 impl std::fmt::Display for Value {
@@ -259,12 +876,83 @@ impl std::fmt::Display for Value {
                 write!(f, "[{}]", items.join(", "))
             }
             Value::Dict(d) => {
-                let items: Vec<String> = d.iter().map(|(k, v)| format!("{k}: {v}")).collect();
+                // Sorted by key, since the order of a dict's entries changes from run to run
+                let mut entries: Vec<_> = d.iter().collect();
+                entries.sort_by_key(|(k, _)| *k);
+                let items: Vec<String> = entries.iter().map(|(k, v)| format!("{k}: {v}")).collect();
                 write!(f, "{{{}}}", items.join(", "))
             }
             Value::Function(func) => write!(f, "<function {}>", func.name),
             Value::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name),
+            Value::Pending(call) => write!(f, "<pending {}()>", call.function),
+            Value::Class(class) => write!(f, "<class {}>", class.name),
+            Value::Instance(instance) => write!(f, "<{} object>", instance.class.name),
+            Value::Method(method) => write!(
+                f,
+                "<method {}.{}>",
+                method.receiver.class.name, method.function.name
+            ),
+            Value::Super(proxy) => write!(f, "<super of {}>", proxy.class.name),
+            Value::Resource(resource) => write!(f, "{resource}"),
+            Value::WeakRef(weak) => write!(f, "{weak}"),
+            Value::Native(object) => write!(f, "{object}"),
+            Value::NativeMethod(method) => write!(f, "{method}"),
+            Value::Handle(_, tag) => write!(f, "<{tag} handle>"),
             Value::None => write!(f, "none"),
+            Value::Date(millis) => write_iso_date(f, *millis),
+            // `{}` is an empty dict, so empty sets are written as the call that makes them
+            Value::Set(s) if s.is_empty() => write!(f, "set()"),
+            Value::FrozenSet(s) if s.is_empty() => write!(f, "frozenset()"),
+            Value::Set(s) => {
+                let items: Vec<String> = s.iter().map(|v| v.to_string()).collect();
+                write!(f, "{{{}}}", items.join(", "))
+            }
+            Value::FrozenSet(s) => {
+                let items: Vec<String> = s.iter().map(|v| v.to_string()).collect();
+                write!(f, "frozenset({{{}}})", items.join(", "))
+            }
+            Value::Map(m) => {
+                let items: Vec<String> = m.iter().map(|(k, v)| format!("{k}: {v}")).collect();
+                write!(f, "Map({{{}}})", items.join(", "))
+            }
+            Value::Bytes(b) => {
+                let items: Vec<String> = b.iter().map(|byte| byte.to_string()).collect();
+                write!(f, "bytes([{}])", items.join(", "))
+            }
         }
     }
 }
+
+/// Write `millis` since the epoch as an ISO 8601 UTC timestamp, like JavaScript's
+/// `Date.prototype.toISOString`
+fn write_iso_date(f: &mut std::fmt::Formatter<'_>, millis: f64) -> std::fmt::Result {
+    if !millis.is_finite() {
+        return write!(f, "Invalid Date");
+    }
+    let millis = millis as i64;
+    let (year, month, day) = civil_date(millis.div_euclid(86_400_000));
+    let ms_of_day = millis.rem_euclid(86_400_000);
+
+    write!(
+        f,
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000
+    )
+}
+
+/// Year, month and day of the date `days` after 1970-01-01 (Howard Hinnant's algorithm)
+pub(crate) fn civil_date(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
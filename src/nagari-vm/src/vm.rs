@@ -1,14 +1,185 @@
-use crate::builtins::{call_builtin, setup_builtins};
+use crate::builtins::{builtin_parameters, call_builtin, setup_builtins};
 use crate::bytecode::{BytecodeFile, Instruction, Opcode};
+use crate::cache::BytecodeCache;
+use crate::csv::{self, Csv};
 use crate::env::Environment;
-use crate::value::Value;
+use crate::events::{self, EventListener, Events, Listener};
+use crate::heap::{self, HeapSnapshot};
+use crate::host::{HostCall, HostCallback, HostFunctions, HostSignature};
+#[cfg(feature = "http-server")]
+use crate::http_server::{self, Exchange, HttpServers};
+use crate::limits::{Budget, ExecutionLimits};
+use crate::native::NativeObject;
+use crate::permissions::{Access, AccessListener, Permission, PermissionPrompt, Permissions};
+use crate::plugin::NativeModule;
+use crate::schedule::{self, JobRecord, JobStore, Scheduler};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::{self, Sqlite};
+use crate::timers::{self, Timers};
+use crate::value::{BuiltinFunction, Class, Function, Instance, Value};
+use crate::weakref::{self, Finalizers, WeakRef};
+#[cfg(feature = "websocket")]
+use crate::websocket::{self, Websockets};
+use crate::yielding::{YieldHook, YieldPoints};
+use crate::Error;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How deep calls of script functions may nest
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// What comes in from the network for the script to handle
+enum NetworkEvent {
+    /// A websocket handler or listener to call, and its arguments
+    #[cfg(feature = "websocket")]
+    Callback(Value, Vec<Value>),
+    /// A request for an `http.server` handler
+    #[cfg(feature = "http-server")]
+    Request(Exchange),
+}
+
+/// Prefixes of the builtins that can't be callbacks, as they wait for or schedule callbacks
+/// themselves
+const NOT_CALLBACKS: &[&str] = &[
+    timers::PREFIX,
+    schedule::PREFIX,
+    events::PREFIX,
+    weakref::PREFIX,
+    #[cfg(feature = "websocket")]
+    websocket::PREFIX,
+    #[cfg(feature = "http-server")]
+    http_server::PREFIX,
+];
+
+/// Call of a script function in progress
+struct Frame {
+    /// Instruction to go on with in the caller
+    return_to: usize,
+    /// Bytecode of the caller
+    bytecode: Option<Arc<BytecodeFile>>,
+    /// Height of the stack when the function was called
+    depth: usize,
+    /// What the call results in instead of what the function returns: the instance a class
+    /// call constructs
+    result: Option<Value>,
+}
+
+/// Context manager entered by a `with` statement that has yet to be exited
+struct Context {
+    /// Number of script function calls in progress when it was entered
+    depth: usize,
+    manager: Value,
+}
+
+/// How a call of a script function, method or class goes
+enum ScriptCall {
+    /// Running `function` with `args` and the keyword arguments `keywords`, the call resulting
+    /// in `result` if given
+    Run {
+        function: Function,
+        args: Vec<Value>,
+        keywords: Vec<(String, Value)>,
+        result: Option<Value>,
+    },
+    /// Done already, like constructing an instance of a class without `__init__`
+    Done(Value),
+}
+
+impl ScriptCall {
+    fn new(
+        callee: Value,
+        mut args: Vec<Value>,
+        keywords: Vec<(String, Value)>,
+    ) -> Result<Self, String> {
+        match callee {
+            Value::Function(function) => Ok(ScriptCall::Run {
+                function,
+                args,
+                keywords,
+                result: None,
+            }),
+            Value::Method(method) => {
+                args.insert(0, Value::Instance(method.receiver));
+                Ok(ScriptCall::Run {
+                    function: method.function,
+                    args,
+                    keywords,
+                    result: None,
+                })
+            }
+            Value::Class(class) => Self::construct(class, args, keywords),
+            other => Err(format!(
+                "Cannot call non-function value: {}",
+                other.type_name()
+            )),
+        }
+    }
+
+    /// A new instance of `class`, initialized by the `__init__` it has or inherits
+    fn construct(
+        class: Arc<Class>,
+        mut args: Vec<Value>,
+        keywords: Vec<(String, Value)>,
+    ) -> Result<Self, String> {
+        let instance = Value::Instance(Instance::new(Arc::clone(&class)));
+        match class.lookup("__init__") {
+            Some(Value::Function(init)) => {
+                args.insert(0, instance.clone());
+                Ok(ScriptCall::Run {
+                    function: init.clone(),
+                    args,
+                    keywords,
+                    result: Some(instance),
+                })
+            }
+            Some(other) => Err(format!(
+                "{}.__init__ must be a function, not '{}'",
+                class.name,
+                other.type_name()
+            )),
+            None if args.is_empty() && keywords.is_empty() => Ok(ScriptCall::Done(instance)),
+            None => Err(format!(
+                "{}() takes no arguments ({} given)",
+                class.name,
+                args.len() + keywords.len()
+            )),
+        }
+    }
+}
 
 pub struct VM {
     stack: Vec<Value>,
     environment: Environment,
-    bytecode: Option<BytecodeFile>,
+    bytecode: Option<Arc<BytecodeFile>>,
     instruction_pointer: usize,
+    /// Script functions being called, innermost last
+    frames: Vec<Frame>,
     debug: bool,
+    host_functions: HostFunctions,
+    timers: Timers,
+    scheduler: Scheduler,
+    events: Events,
+    csv: Csv,
+    #[cfg(feature = "sqlite")]
+    sqlite: Sqlite,
+    #[cfg(feature = "websocket")]
+    websockets: Websockets,
+    #[cfg(feature = "http-server")]
+    http_servers: HttpServers,
+    permissions: Permissions,
+    /// Native extension modules the host loaded, by name
+    native_modules: Vec<(String, Value)>,
+    bytecode_cache: Option<BytecodeCache>,
+    /// What `print` wrote while output is captured
+    output: Option<String>,
+    budget: Budget,
+    yield_points: YieldPoints,
+    finalizers: Finalizers,
+    contexts: Vec<Context>,
 }
 
 impl VM {
@@ -18,9 +189,37 @@ impl VM {
             environment: Environment::new(),
             bytecode: None,
             instruction_pointer: 0,
+            frames: Vec::new(),
             debug,
+            host_functions: HostFunctions::default(),
+            timers: Timers::default(),
+            scheduler: Scheduler::default(),
+            events: Events::default(),
+            csv: Csv::default(),
+            #[cfg(feature = "sqlite")]
+            sqlite: Sqlite::default(),
+            #[cfg(feature = "websocket")]
+            websockets: Websockets::default(),
+            #[cfg(feature = "http-server")]
+            http_servers: HttpServers::default(),
+            permissions: Permissions::default(),
+            native_modules: Vec::new(),
+            bytecode_cache: None,
+            output: None,
+            budget: Budget::default(),
+            yield_points: YieldPoints::default(),
+            finalizers: Finalizers::default(),
+            contexts: Vec::new(),
         };
 
+        vm.csv.set_permissions(vm.permissions.clone());
+        #[cfg(feature = "sqlite")]
+        vm.sqlite.set_permissions(vm.permissions.clone());
+        #[cfg(feature = "websocket")]
+        vm.websockets.set_permissions(vm.permissions.clone());
+        #[cfg(feature = "http-server")]
+        vm.http_servers.set_permissions(vm.permissions.clone());
+
         // Setup built-in functions
         for (name, value) in setup_builtins() {
             vm.environment.define_global(name, value);
@@ -30,12 +229,165 @@ impl VM {
     }
 
     pub fn load_bytecode(&mut self, data: &[u8]) -> Result<(), String> {
-        self.bytecode = Some(BytecodeFile::load(data)?);
+        self.bytecode = Some(Arc::new(BytecodeFile::load(data)?));
         self.instruction_pointer = 0;
         Ok(())
     }
+
+    /// Load `source`, compiled by `compile` unless the bytecode cache has it already.
+    /// `compiler_version` keeps bytecode of other compilers out of the cache.
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn load_source(
+        &mut self,
+        source: &str,
+        compiler_version: &str,
+        compile: impl FnOnce(&str) -> Result<Vec<u8>, String>,
+    ) -> Result<(), String> {
+        let bytecode = match &self.bytecode_cache {
+            Some(cache) => {
+                let bytecode = cache.get_or_compile(source, compiler_version, compile)?;
+                if self.debug {
+                    let stats = cache.stats();
+                    println!(
+                        "🗃️ Bytecode cache: {} memory hits, {} disk hits, {} misses ({:.0}% hit rate)",
+                        stats.memory_hits,
+                        stats.disk_hits,
+                        stats.misses,
+                        stats.hit_rate() * 100.0
+                    );
+                }
+                bytecode
+            }
+            None => compile(source)?.into(),
+        };
+        self.load_bytecode(&bytecode)
+    }
+
+    /// Cache the bytecode of sources loaded with `load_source` in `cache`, or stop caching
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn set_bytecode_cache(&mut self, cache: Option<BytecodeCache>) {
+        self.bytecode_cache = cache;
+    }
+
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn bytecode_cache(&self) -> Option<&BytecodeCache> {
+        self.bytecode_cache.as_ref()
+    }
+
+    /// Snapshot of the values the VM holds, for tracking down leaks in long-lived sessions
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn heap_snapshot(&self) -> HeapSnapshot {
+        let mut globals: Vec<_> = self
+            .environment
+            .globals()
+            .iter()
+            .filter(|(_, value)| !heap::is_builtin(value))
+            .collect();
+        globals.sort_by_key(|(name, _)| *name);
+        let mut roots: Vec<(String, &Value)> = globals
+            .into_iter()
+            .map(|(name, value)| (format!("global {name}"), value))
+            .collect();
+
+        for (depth, scope) in self.environment.locals().iter().enumerate() {
+            let mut locals: Vec<_> = scope.iter().collect();
+            locals.sort_by_key(|(name, _)| *name);
+            roots.extend(
+                locals
+                    .into_iter()
+                    .map(|(name, value)| (format!("local {name} (scope {depth})"), value)),
+            );
+        }
+        roots.extend(
+            self.stack
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (format!("stack[{index}]"), value)),
+        );
+        roots.extend(
+            self.contexts
+                .iter()
+                .enumerate()
+                .map(|(index, context)| (format!("context {index}"), &context.manager)),
+        );
+        roots.extend(
+            self.timers
+                .callbacks()
+                .map(|(id, callback)| (format!("timer {id}"), callback)),
+        );
+        roots.extend(
+            self.scheduler
+                .callbacks()
+                .map(|(name, callback)| (format!("job {name:?}"), callback)),
+        );
+        #[cfg(feature = "websocket")]
+        roots.extend(self.websockets.callbacks());
+        #[cfg(feature = "http-server")]
+        roots.extend(self.http_servers.callbacks());
+        roots.extend(
+            self.finalizers
+                .values()
+                .map(|(id, value)| (format!("finalizer {id}"), value)),
+        );
+        let mut listeners: Vec<_> = self.events.script_listeners().collect();
+        listeners.sort_by_key(|(event, _)| *event);
+        roots.extend(
+            listeners
+                .into_iter()
+                .enumerate()
+                .map(|(index, (event, listener))| {
+                    (format!("listener {index} of {event:?}"), listener)
+                }),
+        );
+
+        HeapSnapshot::new(roots)
+    }
     pub async fn run(&mut self) -> Result<(), String> {
-        let bytecode_len = if let Some(bytecode) = &self.bytecode {
+        self.budget.start();
+        self.yield_points.start();
+        let result = match self.run_instructions().await {
+            Ok(()) => self.finish_timers().await,
+            Err((Some(index), e)) => Err(format!("Runtime error at instruction {index}: {e}")),
+            Err((None, e)) => Err(e),
+        };
+        self.budget.stop();
+        result
+    }
+
+    /// Limit how long each run started by the host may take and how many instructions it
+    /// may execute; see [`crate::limits`]
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn set_execution_limits(&mut self, limits: ExecutionLimits) {
+        self.budget.set_limits(limits);
+    }
+
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn execution_limits(&self) -> ExecutionLimits {
+        self.budget.limits()
+    }
+
+    /// Stop at a yield point every `interval` instructions, or never with `None`; see
+    /// [`crate::yielding`]
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn set_yield_interval(&mut self, interval: Option<u64>) {
+        self.yield_points.set_interval(interval);
+    }
+
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn yield_interval(&self) -> Option<u64> {
+        self.yield_points.interval()
+    }
+
+    /// Call `hook` at each yield point, such as to keep the host's UI responsive
+    #[allow(dead_code)] // Used by embedding hosts
+    pub fn set_yield_hook(&mut self, hook: Option<YieldHook>) {
+        self.yield_points.set_hook(hook);
+    }
+
+    /// Execute the loaded bytecode, failing with the error and the index of the instruction
+    /// that raised it, if one did
+    async fn run_instructions(&mut self) -> Result<(), (Option<usize>, String)> {
+        if let Some(bytecode) = &self.bytecode {
             if self.debug {
                 println!("🐛 Debug mode enabled");
                 println!("📊 Constants: {}", bytecode.constants.len());
@@ -43,42 +395,188 @@ impl VM {
                 println!("📋 Instructions: {}", bytecode.instructions.len());
                 println!();
             }
-            bytecode.instructions.len()
         } else {
-            return Err("No bytecode loaded".to_string());
-        };
+            return Err((None, "No bytecode loaded".to_string()));
+        }
 
-        while self.instruction_pointer < bytecode_len {
-            let instruction = if let Some(bytecode) = &self.bytecode {
-                bytecode.instructions[self.instruction_pointer].clone()
-            } else {
-                return Err("Bytecode disappeared during execution".to_string());
+        // Timers, calls and contexts left by a run that was dropped before it finished
+        self.timers.cancel_all();
+        self.frames.clear();
+        self.contexts.clear();
+
+        self.execute().await
+    }
+
+    /// Execute instructions from the instruction pointer on, until the end of the bytecode or
+    /// a return from the code it started in, which may be the body of a script function. Calls
+    /// made along the way are returned from, or unwound on errors.
+    async fn execute(&mut self) -> Result<(), (Option<usize>, String)> {
+        let base = self.frames.len();
+        loop {
+            let index = self.instruction_pointer;
+            let instruction = match &self.bytecode {
+                Some(bytecode) => match bytecode.instructions.get(index) {
+                    Some(instruction) => instruction.clone(),
+                    None => break,
+                },
+                None => return Err((None, "Bytecode disappeared during execution".to_string())),
             };
 
             if self.debug {
                 self.debug_instruction(&instruction);
             }
 
-            match self.execute_instruction(&instruction).await {
-                Ok(should_continue) => {
-                    if !should_continue {
+            // Jumps move the pointer on to their target instead
+            self.instruction_pointer += 1;
+
+            if self.yield_points.tick() {
+                self.yield_points.yield_now().await;
+            }
+
+            let outcome = match self.budget.spend() {
+                Ok(()) => self.execute_instruction(&instruction).await,
+                Err(e) => Err(e),
+            };
+            let outcome = match outcome {
+                // Returning exits the contexts entered in the function, or in the code the
+                // loop started in
+                Ok(false) => self
+                    .exit_contexts(self.frames.len(), None)
+                    .await
+                    .map(|()| false),
+                Ok(true) if drops_value(instruction.opcode) => {
+                    self.run_finalizers().await.map(|()| true)
+                }
+                outcome => outcome,
+            };
+            match outcome {
+                Ok(true) => {}
+                // A return
+                Ok(false) => {
+                    let returning_from = self.frames.len();
+                    if returning_from > 0 {
+                        self.return_from_call();
+                    }
+                    if returning_from == base {
                         break;
                     }
                 }
                 Err(e) => {
-                    return Err(format!(
-                        "Runtime error at instruction {}: {}",
-                        self.instruction_pointer, e
-                    ));
+                    // Resources are released even if exiting fails, and the error stays the
+                    // one that ended the run
+                    let _ = self.exit_contexts(base, Some(&e)).await;
+                    // Including the call the loop started in, if it did
+                    while self.frames.len() > base.saturating_sub(1) {
+                        self.leave_call();
+                    }
+                    self.timers.cancel_all();
+                    return Err((Some(index), e));
                 }
             }
+        }
+        Ok(())
+    }
 
-            self.instruction_pointer += 1;
+    /// Start running the body of a script function, in a scope of its own
+    fn enter_call(
+        &mut self,
+        function: Function,
+        args: Vec<Value>,
+        keywords: Vec<(String, Value)>,
+        result: Option<Value>,
+    ) -> Result<(), String> {
+        let arguments = bind_arguments(&function, args, keywords)?;
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(format!(
+                "maximum call depth of {MAX_CALL_DEPTH} exceeded in {}()",
+                function.name
+            ));
         }
 
+        self.frames.push(Frame {
+            return_to: self.instruction_pointer,
+            bytecode: self.bytecode.replace(Arc::clone(&function.code)),
+            depth: self.stack.len(),
+            result,
+        });
+        self.instruction_pointer = function.entry;
+        self.environment.push_scope();
+        for (name, value) in arguments {
+            self.environment.define(name, value);
+        }
         Ok(())
     }
 
+    /// Go back to the caller of the innermost script function, with the value it returned
+    fn return_from_call(&mut self) {
+        let depth = self.frames.last().unwrap().depth;
+        let value = if self.stack.len() > depth {
+            self.stack.pop().unwrap()
+        } else {
+            Value::None
+        };
+        let result = self.leave_call();
+        self.stack.push(result.unwrap_or(value));
+    }
+
+    /// Go back to the caller of the innermost script function, dropping what it left. Returns
+    /// what the call results in if not what the function returned.
+    fn leave_call(&mut self) -> Option<Value> {
+        let frame = self.frames.pop().unwrap();
+        self.stack.truncate(frame.depth);
+        self.environment.pop_scope();
+        self.instruction_pointer = frame.return_to;
+        self.bytecode = frame.bytecode;
+        frame.result
+    }
+
+    /// Like an event loop, a run lasts until every timer has fired or been cancelled. The
+    /// finalizers of what the run dropped run at its end.
+    async fn finish_timers(&mut self) -> Result<(), String> {
+        self.run_timers(None).await.map_err(|e| {
+            self.timers.cancel_all();
+            format!("Runtime error in timer callback: {e}")
+        })?;
+        self.run_finalizers()
+            .await
+            .map_err(|e| format!("Runtime error in finalizer: {e}"))
+    }
+
+    /// Run the loaded bytecode like `run`, returning the value of its trailing expression
+    /// statement, or none if it ends with another kind of statement. Errors are the script's
+    /// own, without the instruction that raised them.
+    #[allow(dead_code)] // Used by embedding hosts
+    pub async fn evaluate(&mut self) -> Result<Value, String> {
+        let depth = self.stack.len();
+        self.budget.start();
+        self.yield_points.start();
+        let result = match self.run_instructions().await {
+            Ok(()) => self.finish_timers().await,
+            Err((_, e)) => Err(e),
+        };
+        self.budget.stop();
+        let value = if self.stack.len() > depth {
+            self.stack.pop()
+        } else {
+            None
+        };
+        self.stack.truncate(depth);
+        result.map(|()| value.unwrap_or(Value::None))
+    }
+
+    /// `evaluate` with the names of `scope` visible besides the globals, as they are to the
+    /// code of a module in its namespace
+    #[allow(dead_code)] // Used by embedding hosts
+    pub async fn evaluate_in(&mut self, scope: HashMap<String, Value>) -> Result<Value, String> {
+        self.environment.push_scope();
+        for (name, value) in scope {
+            self.environment.define(&name, value);
+        }
+        let result = self.evaluate().await;
+        self.environment.pop_scope();
+        result
+    }
+
     async fn execute_instruction(&mut self, instruction: &Instruction) -> Result<bool, String> {
         let bytecode = self.bytecode.as_ref().unwrap();
 
@@ -88,7 +586,13 @@ impl VM {
                 if const_index >= bytecode.constants.len() {
                     return Err(format!("Constant index out of bounds: {const_index}"));
                 }
-                let value = bytecode.constants[const_index].clone();
+                let value = match &bytecode.constants[const_index] {
+                    Value::Function(function) => Value::Function(Function {
+                        code: Arc::clone(bytecode),
+                        ..function.clone()
+                    }),
+                    constant => constant.clone(),
+                };
                 self.stack.push(value);
             }
 
@@ -114,7 +618,12 @@ impl VM {
                 let name = &bytecode.names[name_index];
 
                 if let Some(value) = self.stack.pop() {
-                    self.environment.set(name, value)?;
+                    // Names assigned in a function are its own
+                    if !self.frames.is_empty() {
+                        self.environment.define(name, value);
+                    } else {
+                        self.environment.set(name, value)?;
+                    }
                 } else {
                     return Err("Stack underflow in StoreName".to_string());
                 }
@@ -132,38 +641,60 @@ impl VM {
                     return Err("Stack underflow in Print".to_string());
                 }
 
-                let args: Vec<Value> = (0..arg_count)
-                    .map(|_| self.stack.pop().unwrap())
-                    .rev()
-                    .collect();
+                // The arguments are on top of the stack, the first one deepest
+                let args = self.stack.split_off(self.stack.len() - arg_count);
 
-                call_builtin("print", &args).await?;
+                self.print(&args).await?;
                 self.stack.push(Value::None);
             }
 
-            Opcode::CallFunc => {
+            Opcode::CallFunc | Opcode::CallFuncKw => {
+                // `CallFuncKw` has a list of the keyword arguments' names on top
+                let names = match instruction.opcode {
+                    Opcode::CallFuncKw => match self.stack.pop() {
+                        Some(Value::List(names)) => names,
+                        _ => return Err("CallFuncKw needs a list of keyword names".to_string()),
+                    },
+                    _ => Vec::new(),
+                };
                 let arg_count = instruction.operand as usize;
-                if self.stack.len() < arg_count + 1 {
-                    return Err("Stack underflow in CallFunc".to_string());
+                if self.stack.len() < arg_count + 1 || names.len() > arg_count {
+                    return Err(format!("Stack underflow in {:?}", instruction.opcode));
                 }
 
-                let args: Vec<Value> = (0..arg_count)
-                    .map(|_| self.stack.pop().unwrap())
-                    .rev()
-                    .collect();
-
-                let function = self.stack.pop().unwrap();
+                // The arguments are on top of the stack, the first one deepest, and the values
+                // of the keyword arguments after the positional ones
+                let mut args = self.stack.split_off(self.stack.len() - arg_count);
+                let values = args.split_off(args.len() - names.len());
+                let keywords = keyword_arguments(names, values)?;
 
-                match function {
-                    Value::Builtin(builtin) => {
-                        let result = call_builtin(&builtin.name, &args).await?;
+                // Script functions are run by this loop, hosts call them through `call`
+                match self.stack.pop().unwrap() {
+                    callee @ (Value::Function(_) | Value::Method(_) | Value::Class(_)) => {
+                        match ScriptCall::new(callee, args, keywords)? {
+                            ScriptCall::Run {
+                                function,
+                                args,
+                                keywords,
+                                result,
+                            } => self.enter_call(function, args, keywords, result)?,
+                            ScriptCall::Done(value) => self.stack.push(value),
+                        }
+                    }
+                    Value::Builtin(builtin) if !keywords.is_empty() => {
+                        let args = place_keywords(&builtin.name, args, keywords)?;
+                        let result = self.call(Value::Builtin(builtin), args).await?;
                         self.stack.push(result);
                     }
-                    _ => {
+                    function if !keywords.is_empty() => {
                         return Err(format!(
-                            "Cannot call non-function value: {}",
+                            "'{}' objects take no keyword arguments",
                             function.type_name()
-                        ));
+                        ))
+                    }
+                    function => {
+                        let result = self.call(function, args).await?;
+                        self.stack.push(result);
                     }
                 }
             }
@@ -174,14 +705,12 @@ impl VM {
 
             Opcode::Jump => {
                 self.instruction_pointer = instruction.operand as usize;
-                return Ok(true); // Continue without incrementing IP
             }
 
             Opcode::JumpIfFalse => {
                 if let Some(condition) = self.stack.pop() {
                     if !condition.is_truthy() {
                         self.instruction_pointer = instruction.operand as usize;
-                        return Ok(true); // Continue without incrementing IP
                     }
                 } else {
                     return Err("Stack underflow in JumpIfFalse".to_string());
@@ -194,6 +723,13 @@ impl VM {
             Opcode::BinaryMultiply => self.binary_operation(|a, b| a.multiply(b))?,
             Opcode::BinaryDivide => self.binary_operation(|a, b| a.divide(b))?,
             Opcode::BinaryModulo => self.binary_operation(|a, b| a.modulo(b))?,
+            Opcode::BinaryPower => self.binary_operation(|a, b| a.power(b))?,
+            Opcode::BinaryFloorDivide => self.binary_operation(|a, b| a.floor_divide(b))?,
+            Opcode::BinaryBitAnd => self.binary_operation(|a, b| a.bit_and(b))?,
+            Opcode::BinaryBitOr => self.binary_operation(|a, b| a.bit_or(b))?,
+            Opcode::BinaryBitXor => self.binary_operation(|a, b| a.bit_xor(b))?,
+            Opcode::BinaryLeftShift => self.binary_operation(|a, b| a.left_shift(b))?,
+            Opcode::BinaryRightShift => self.binary_operation(|a, b| a.right_shift(b))?,
             Opcode::BinaryEqual => self.binary_operation(|a, b| Ok(a.equals(b)))?,
             Opcode::BinaryNotEqual => self.binary_operation(|a, b| Ok(a.not_equals(b)))?,
             Opcode::BinaryLess => self.binary_operation(|a, b| a.less(b))?,
@@ -201,6 +737,19 @@ impl VM {
             Opcode::BinaryLessEqual => self.binary_operation(|a, b| a.less_equal(b))?,
             Opcode::BinaryGreaterEqual => self.binary_operation(|a, b| a.greater_equal(b))?,
 
+            // Unary operations
+            Opcode::UnaryNot => self.unary_operation(|a| Ok(Value::Bool(!a.is_truthy())))?,
+            Opcode::UnaryNegative => self.unary_operation(Value::negate)?,
+            Opcode::UnaryPositive => self.unary_operation(Value::positive)?,
+            Opcode::UnaryInvert => self.unary_operation(Value::invert)?,
+
+            Opcode::DupTop => match self.stack.last() {
+                Some(top) => self.stack.push(top.clone()),
+                None => return Err("Stack underflow in DupTop".to_string()),
+            },
+
+            Opcode::Nop => {}
+
             Opcode::BuildList => {
                 let count = instruction.operand as usize;
                 if self.stack.len() < count {
@@ -215,6 +764,16 @@ impl VM {
                 self.stack.push(Value::List(list));
             }
 
+            Opcode::BuildSet => {
+                let count = instruction.operand as usize;
+                if self.stack.len() < count {
+                    return Err("Stack underflow in BuildSet".to_string());
+                }
+
+                let items = self.stack.split_off(self.stack.len() - count);
+                self.stack.push(Value::Set(crate::value::distinct(items)));
+            }
+
             Opcode::BuildDict => {
                 let count = instruction.operand as usize;
                 if self.stack.len() < count * 2 {
@@ -236,6 +795,127 @@ impl VM {
                 self.stack.push(Value::Dict(dict));
             }
 
+            Opcode::GetItem => {
+                if self.stack.len() < 2 {
+                    return Err("Stack underflow in GetItem".to_string());
+                }
+
+                let index = self.stack.pop().unwrap();
+                let object = self.stack.pop().unwrap();
+                self.stack.push(object.get_item(&index)?);
+            }
+
+            Opcode::LoadAttr => {
+                let name_index = instruction.operand as usize;
+                if name_index >= bytecode.names.len() {
+                    return Err(format!("Name index out of bounds: {name_index}"));
+                }
+                let Some(object) = self.stack.pop() else {
+                    return Err("Stack underflow in LoadAttr".to_string());
+                };
+                let name = &bytecode.names[name_index];
+                self.stack.push(object.get_attr(name)?);
+            }
+
+            Opcode::StoreAttr => {
+                let name_index = instruction.operand as usize;
+                if name_index >= bytecode.names.len() {
+                    return Err(format!("Name index out of bounds: {name_index}"));
+                }
+                if self.stack.len() < 2 {
+                    return Err("Stack underflow in StoreAttr".to_string());
+                }
+                let object = self.stack.pop().unwrap();
+                let value = self.stack.pop().unwrap();
+                object.set_attr(&bytecode.names[name_index], value)?;
+            }
+
+            Opcode::BuildClass => {
+                // The name and base, then the name and value of each attribute
+                let count = instruction.operand as usize;
+                if self.stack.len() < count * 2 + 2 {
+                    return Err("Stack underflow in BuildClass".to_string());
+                }
+
+                let mut attributes = HashMap::with_capacity(count);
+                for _ in 0..count {
+                    let value = self.stack.pop().unwrap();
+                    let Value::String(name) = self.stack.pop().unwrap() else {
+                        return Err("Class attribute names must be strings".to_string());
+                    };
+                    attributes.insert(name, value);
+                }
+                let base = match self.stack.pop().unwrap() {
+                    Value::Class(base) => Some(base),
+                    Value::None => None,
+                    other => {
+                        return Err(format!(
+                            "base class must be a class, not '{}'",
+                            other.type_name()
+                        ))
+                    }
+                };
+                let Value::String(name) = self.stack.pop().unwrap() else {
+                    return Err("Class name must be a string".to_string());
+                };
+                self.stack.push(Value::Class(Arc::new(Class {
+                    name,
+                    base,
+                    attributes,
+                })));
+            }
+
+            Opcode::BuildSlice => {
+                if self.stack.len() < 4 {
+                    return Err("Stack underflow in BuildSlice".to_string());
+                }
+
+                let mut bounds = [None; 3];
+                for bound in bounds.iter_mut().rev() {
+                    *bound = match self.stack.pop().unwrap() {
+                        Value::Int(n) => Some(n),
+                        Value::None => None,
+                        other => {
+                            return Err(format!(
+                                "slice indices must be integers or none, not '{}'",
+                                other.type_name()
+                            ))
+                        }
+                    };
+                }
+                let object = self.stack.pop().unwrap();
+                let [start, stop, step] = bounds;
+                self.stack.push(object.slice(start, stop, step)?);
+            }
+
+            Opcode::Await => {
+                if self.stack.is_empty() {
+                    return Err("Stack underflow in Await".to_string());
+                }
+                let value = self.stack.pop().unwrap();
+                let result = self.resolve(value).await?;
+                self.stack.push(result);
+            }
+
+            Opcode::EnterContext => {
+                let Some(manager) = self.stack.pop() else {
+                    return Err("Stack underflow in EnterContext".to_string());
+                };
+                let entered = self.enter_context(&manager).await?;
+                self.contexts.push(Context {
+                    depth: self.frames.len(),
+                    manager,
+                });
+                self.stack.push(entered);
+            }
+
+            Opcode::ExitContext => {
+                let Some(context) = self.contexts.pop() else {
+                    return Err("No context manager to exit".to_string());
+                };
+                self.exit_context(context.manager, None).await?;
+            }
+
             _ => {
                 return Err(format!("Unimplemented opcode: {:?}", instruction.opcode));
             }
@@ -244,6 +924,534 @@ impl VM {
         Ok(true)
     }
 
+    /// What `with manager:` binds: an open resource itself, or what an object's `__enter__()`
+    /// returns
+    async fn enter_context(&mut self, manager: &Value) -> Result<Value, String> {
+        match manager {
+            Value::Resource(resource) if resource.is_closed() => {
+                Err(format!("cannot enter closed {} resource", resource.kind()))
+            }
+            Value::Resource(_) => Ok(manager.clone()),
+            Value::Instance(instance) => {
+                let (Some(enter), Some(_)) = (
+                    instance.get_attr("__enter__"),
+                    instance.get_attr("__exit__"),
+                ) else {
+                    return Err(format!(
+                        "'{}' object does not support the context manager protocol",
+                        instance.class.name
+                    ));
+                };
+                self.call(enter, Vec::new()).await
+            }
+            other => Err(format!(
+                "'{}' object does not support the context manager protocol",
+                other.type_name()
+            )),
+        }
+    }
+
+    /// Leave the `with` statement that entered `manager`, closing a resource or calling an
+    /// object's `__exit__(kind, message, none)` with the error that ended the statement, if
+    /// one did, or with three nones
+    async fn exit_context(&mut self, manager: Value, error: Option<&str>) -> Result<(), String> {
+        match manager {
+            Value::Resource(resource) => {
+                resource.close();
+                Ok(())
+            }
+            Value::Instance(instance) => {
+                let exit = instance.get_attr("__exit__").ok_or_else(|| {
+                    format!(
+                        "'{}' object has no attribute '__exit__'",
+                        instance.class.name
+                    )
+                })?;
+                let args = match error {
+                    Some(error) => {
+                        let kind = match error.split_once(": ") {
+                            Some((kind, _)) if kind.ends_with("Error") => kind,
+                            _ => "RuntimeError",
+                        };
+                        vec![
+                            Value::String(kind.to_string()),
+                            Value::String(error.to_string()),
+                            Value::None,
+                        ]
+                    }
+                    None => vec![Value::None, Value::None, Value::None],
+                };
+                self.call(exit, args).await.map(drop)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Exit the contexts entered while `depth` or more script function calls were in
+    /// progress, innermost first. Every one of them is exited, and the first failure returned.
+    async fn exit_contexts(&mut self, depth: usize, error: Option<&str>) -> Result<(), String> {
+        let mut result = Ok(());
+        while self
+            .contexts
+            .last()
+            .is_some_and(|context| context.depth >= depth)
+        {
+            let context = self.contexts.pop().unwrap();
+            let exited = self.exit_context(context.manager, error).await;
+            if result.is_ok() {
+                result = exited;
+            }
+        }
+        result
+    }
+
+    /// Call the finalizers of the objects that went away
+    async fn run_finalizers(&mut self) -> Result<(), String> {
+        if self.finalizers.is_empty() {
+            return Ok(());
+        }
+        for (callback, args) in self.finalizers.take_collected() {
+            self.call_callback(callback, args).await?;
+        }
+        Ok(())
+    }
+
+    fn call_weakref(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let function = &name[weakref::PREFIX.len()..];
+        match (function, args.as_slice()) {
+            ("ref", [target]) => Ok(Value::WeakRef(WeakRef::new(target)?)),
+            ("finalize", [target, callback, rest @ ..]) => {
+                if !matches!(
+                    callback,
+                    Value::Builtin(_) | Value::Function(_) | Value::Method(_)
+                ) {
+                    return Err(format!(
+                        "finalize() callback must be a function, not '{}'",
+                        callback.type_name()
+                    ));
+                }
+                let target = WeakRef::new(target)?;
+                let handle = self
+                    .finalizers
+                    .register(target, callback.clone(), rest.to_vec());
+                Ok(Value::Int(handle))
+            }
+            ("detach", [Value::Int(handle)]) => Ok(Value::Bool(self.finalizers.detach(*handle))),
+            ("detach", [other]) => Err(format!(
+                "detach() handle must be an int, not '{}'",
+                other.type_name()
+            )),
+            ("ref" | "detach", _) => Err(format!(
+                "{function}() takes 1 argument(s) ({} given)",
+                args.len()
+            )),
+            ("finalize", _) => Err(format!(
+                "finalize() takes at least 2 argument(s) ({} given)",
+                args.len()
+            )),
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    /// Call `function` the way a script would
+    pub async fn call(&mut self, function: Value, args: Vec<Value>) -> Result<Value, String> {
+        match function {
+            Value::Builtin(builtin) if builtin.name.starts_with(timers::PREFIX) => {
+                self.call_timers(&builtin.name, args).await
+            }
+            Value::Builtin(builtin) if builtin.name.starts_with(events::PREFIX) => {
+                self.call_events(&builtin.name, args).await
+            }
+            Value::Builtin(builtin) if builtin.name.starts_with(schedule::PREFIX) => {
+                let globals = self.environment.globals();
+                self.scheduler.call(&builtin.name, &args, globals)
+            }
+            Value::Builtin(builtin) if builtin.name.starts_with(weakref::PREFIX) => {
+                self.call_weakref(&builtin.name, args)
+            }
+            Value::Builtin(builtin) if builtin.name.starts_with(csv::PREFIX) => {
+                self.csv.call(&builtin.name, &args)
+            }
+            #[cfg(feature = "sqlite")]
+            Value::Builtin(builtin) if builtin.name.starts_with(sqlite::PREFIX) => {
+                self.sqlite.call(&builtin.name, &args)
+            }
+            #[cfg(feature = "websocket")]
+            Value::Builtin(builtin) if builtin.name.starts_with(websocket::PREFIX) => {
+                self.call_websocket(&builtin.name, args).await
+            }
+            #[cfg(feature = "http-server")]
+            Value::Builtin(builtin) if builtin.name.starts_with(http_server::PREFIX) => {
+                self.http_servers.call(&builtin.name, &args).await
+            }
+            Value::Builtin(builtin) if builtin.name == "path.glob" => {
+                self.glob(&builtin.name, &args)
+            }
+            Value::Builtin(builtin) if self.host_functions.contains(&builtin.name) => {
+                self.host_functions.call(&builtin.name, args)
+            }
+            Value::Builtin(builtin) if builtin.name == "print" => self.print(&args).await,
+            Value::Builtin(builtin) => call_builtin(&builtin.name, &args).await,
+            Value::Function(_) | Value::Method(_) | Value::Class(_) => {
+                self.run_function(function, args).await
+            }
+            Value::NativeMethod(method) => method.call(args),
+            Value::WeakRef(weak) if args.is_empty() => Ok(weak.upgrade().unwrap_or(Value::None)),
+            Value::WeakRef(_) => Err(format!(
+                "weakref() takes no arguments ({} given)",
+                args.len()
+            )),
+            _ => Err(format!(
+                "Cannot call non-function value: {}",
+                function.type_name()
+            )),
+        }
+    }
+
+    /// `path.glob`, once the script may read where the pattern starts looking
+    fn glob(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        if let [Value::String(pattern)] = args {
+            let root = crate::path::glob_root(pattern);
+            self.permissions.check(name, Permission::Io(root))?;
+        }
+        crate::path::call(name, args)
+    }
+
+    /// Print `args` like the `print` builtin, or keep the line while output is captured
+    async fn print(&mut self, args: &[Value]) -> Result<Value, String> {
+        match &mut self.output {
+            Some(output) => {
+                let line: Vec<String> = args.iter().map(Value::to_string).collect();
+                output.push_str(&line.join(" "));
+                output.push('\n');
+                Ok(Value::None)
+            }
+            None => call_builtin("print", args).await,
+        }
+    }
+
+    /// Call the global function `name` with `args` the way a script would, returning what it
+    /// returns. A script function runs in a call frame of its own until it returns.
+    #[allow(dead_code)] // Used by embedding hosts
+    pub async fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let function = self
+            .environment
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("name '{name}' is not defined"))?;
+        self.budget.start();
+        self.yield_points.start();
+        let mut result = self.call(function, args).await;
+        if result.is_ok() {
+            if let Err(e) = self.run_finalizers().await {
+                result = Err(format!("Runtime error in finalizer: {e}"));
+            }
+        }
+        self.budget.stop();
+        result
+    }
+
+    /// Run a script function, method or class to completion, returning what the call results
+    /// in. Boxed, as the function's body can call back into the host.
+    fn run_function(
+        &mut self,
+        callee: Value,
+        args: Vec<Value>,
+    ) -> Pin<Box<dyn Future<Output = Result<Value, String>> + Send + '_>> {
+        Box::pin(async move {
+            let (function, args, result) = match ScriptCall::new(callee, args, Vec::new())? {
+                ScriptCall::Run {
+                    function,
+                    args,
+                    result,
+                    ..
+                } => (function, args, result),
+                ScriptCall::Done(value) => return Ok(value),
+            };
+            self.enter_call(function, args, Vec::new(), result)?;
+            self.execute().await.map_err(|(_, e)| e)?;
+            Ok(self.stack.pop().unwrap_or(Value::None))
+        })
+    }
+
+    async fn call_events(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let function = &name[events::PREFIX.len()..];
+        let event = match args.first() {
+            Some(Value::String(event)) => event.clone(),
+            Some(other) => {
+                return Err(format!(
+                    "{function}() event must be a str, not '{}'",
+                    other.type_name()
+                ))
+            }
+            None => return Err(format!("{function}() missing required argument 'event'")),
+        };
+        match (function, &args[1..]) {
+            ("on" | "once", [listener]) => {
+                if !matches!(
+                    listener,
+                    Value::Builtin(_) | Value::Function(_) | Value::Method(_)
+                ) {
+                    return Err(format!(
+                        "{function}() listener must be a function, not '{}'",
+                        listener.type_name()
+                    ));
+                }
+                let listener = Listener::Script(listener.clone());
+                self.events.add(&event, listener, function == "once");
+                Ok(Value::None)
+            }
+            ("off", [listener]) => Ok(Value::Bool(self.events.remove(&event, Some(listener)))),
+            ("off", []) => Ok(Value::Bool(self.events.remove(&event, None))),
+            ("emit", rest) => {
+                let had_listeners = self.dispatch_event(&event, rest.to_vec()).await?;
+                Ok(Value::Bool(had_listeners))
+            }
+            ("on" | "once" | "off", rest) => Err(format!(
+                "{function}() takes {} argument(s) ({} given)",
+                if function == "off" { "1 or 2" } else { "2" },
+                rest.len() + 1
+            )),
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    async fn call_timers(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        let function = &name[timers::PREFIX.len()..];
+        match (function, args.as_slice()) {
+            ("sleep", [ms]) => {
+                let until = Instant::now() + timers::delay(function, ms)?;
+                self.run_timers(Some(until)).await?;
+                self.sleep_until(until).await?;
+                Ok(Value::None)
+            }
+            ("set_timeout" | "set_interval", [callback, ms]) => {
+                if !matches!(
+                    callback,
+                    Value::Builtin(_) | Value::Function(_) | Value::Method(_)
+                ) {
+                    return Err(format!(
+                        "{function}() callback must be a function, not '{}'",
+                        callback.type_name()
+                    ));
+                }
+                let delay = timers::delay(function, ms)?;
+                let repeat = function == "set_interval";
+                let handle = self.timers.schedule(callback.clone(), delay, repeat);
+                Ok(Value::Int(handle))
+            }
+            ("cancel", [Value::Int(handle)]) => Ok(Value::Bool(self.timers.cancel(*handle))),
+            ("cancel", [other]) => Err(format!(
+                "cancel() handle must be an int, not '{}'",
+                other.type_name()
+            )),
+            ("sleep" | "set_timeout" | "set_interval" | "cancel", _) => Err(format!(
+                "{function}() takes {} argument(s) ({} given)",
+                if function.starts_with("set_") { 2 } else { 1 },
+                args.len()
+            )),
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    /// Fire the timers due by `until`, or all of them until none is left, as they come due.
+    /// While a websocket or HTTP server runs or a socket is listened to, its handlers and
+    /// listeners are called as connections, requests and messages come in, and without `until`
+    /// the wait lasts until none is left either.
+    async fn run_timers(&mut self, until: Option<Instant>) -> Result<(), String> {
+        loop {
+            if !self.listening() {
+                let Some((due, callback)) = self.timers.next_due(until) else {
+                    return Ok(());
+                };
+                self.sleep_until(due).await?;
+                self.call_callback(callback, Vec::new()).await?;
+                continue;
+            }
+
+            let now = Instant::now();
+            let due_by = until.map_or(now, |until| until.min(now));
+            if let Some((_, callback)) = self.timers.next_due(Some(due_by)) {
+                self.call_callback(callback, Vec::new()).await?;
+                continue;
+            }
+            if until.is_some_and(|until| until <= now) {
+                return Ok(());
+            }
+            let wake = [self.timers.next_deadline(), until]
+                .into_iter()
+                .flatten()
+                .min();
+            if let Some(event) = self.next_network_event(wake).await? {
+                self.handle_network_event(event).await?;
+            }
+        }
+    }
+
+    /// Whether a websocket or HTTP server runs or a socket is listened to
+    fn listening(&self) -> bool {
+        #[cfg(feature = "websocket")]
+        if self.websockets.listening() {
+            return true;
+        }
+        #[cfg(feature = "http-server")]
+        if self.http_servers.listening() {
+            return true;
+        }
+        false
+    }
+
+    /// Wait for the next websocket event or HTTP request until `wake`, returning what the
+    /// script has to handle for it, if anything. Fails at the deadline of the run if that comes
+    /// first.
+    async fn next_network_event(
+        &mut self,
+        wake: Option<Instant>,
+    ) -> Result<Option<NetworkEvent>, String> {
+        // Builds without a module wait for its events forever
+        #[cfg(feature = "websocket")]
+        let websocket = self.websockets.next_event();
+        #[cfg(feature = "websocket")]
+        let websocket = async {
+            let callback = websocket.await;
+            callback.map(|(callback, args)| NetworkEvent::Callback(callback, args))
+        };
+        #[cfg(not(feature = "websocket"))]
+        let websocket = std::future::pending();
+        #[cfg(feature = "http-server")]
+        let http_server = self.http_servers.next_exchange();
+        #[cfg(feature = "http-server")]
+        let http_server = async { http_server.await.map(NetworkEvent::Request) };
+        #[cfg(not(feature = "http-server"))]
+        let http_server = std::future::pending();
+        let next = async {
+            tokio::select! {
+                event = websocket => event,
+                event = http_server => event,
+            }
+        };
+        let deadline = self.budget.deadline();
+        let Some(until) = [wake, deadline].into_iter().flatten().min() else {
+            return Ok(next.await);
+        };
+        match tokio::time::timeout_at(until.into(), next).await {
+            Ok(event) => Ok(event),
+            Err(_) if deadline.is_some_and(|deadline| deadline <= until) => {
+                Err(self.budget.timeout_error())
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Call the websocket handler or listener, or the HTTP handler, that `event` is for. A
+    /// request whose handler fails is answered with 500 before the error ends the run.
+    async fn handle_network_event(&mut self, event: NetworkEvent) -> Result<(), String> {
+        match event {
+            #[cfg(feature = "websocket")]
+            NetworkEvent::Callback(callback, args) => {
+                self.call_callback(callback, args).await.map(drop)
+            }
+            #[cfg(feature = "http-server")]
+            NetworkEvent::Request(exchange) => {
+                let request = vec![exchange.request.clone()];
+                match self.call_callback(exchange.handler.clone(), request).await {
+                    Ok(value) => exchange.respond(&value),
+                    Err(e) => {
+                        exchange.fail();
+                        Err(e)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait for the next message of the socket `receive(socket)` is called with, running timers,
+    /// listeners and handlers meanwhile
+    #[cfg(feature = "websocket")]
+    async fn receive_websocket(&mut self, args: &[Value]) -> Result<Value, String> {
+        loop {
+            if let Some(message) = self.websockets.take_message(args)? {
+                return Ok(message);
+            }
+            if let Some((_, callback)) = self.timers.next_due(Some(Instant::now())) {
+                self.call_callback(callback, Vec::new()).await?;
+                continue;
+            }
+            let wake = self.timers.next_deadline();
+            if let Some(event) = self.next_network_event(wake).await? {
+                self.handle_network_event(event).await?;
+            }
+        }
+    }
+
+    /// Call a builtin of the websocket module, failing at the deadline of the run if it is
+    /// still waiting then
+    #[cfg(feature = "websocket")]
+    async fn call_websocket(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        if name == "websocket.receive" {
+            return self.receive_websocket(&args).await;
+        }
+        let Some(deadline) = self.budget.deadline() else {
+            return self.websockets.call(name, &args).await;
+        };
+        let call = self.websockets.call(name, &args);
+        match tokio::time::timeout_at(deadline.into(), call).await {
+            Ok(result) => result,
+            Err(_) => Err(self.budget.timeout_error()),
+        }
+    }
+
+    /// Wait until `until`, failing at the deadline of the run if that comes first
+    async fn sleep_until(&mut self, until: Instant) -> Result<(), String> {
+        match self.budget.deadline() {
+            Some(deadline) if deadline < until => {
+                tokio::time::sleep_until(deadline.into()).await;
+                Err(self.budget.timeout_error())
+            }
+            _ => {
+                tokio::time::sleep_until(until.into()).await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Call a timer callback, event listener or request handler, waiting for it if it is
+    /// asynchronous, and return what it returns
+    async fn call_callback(&mut self, callback: Value, args: Vec<Value>) -> Result<Value, String> {
+        match callback {
+            Value::Builtin(builtin)
+                if NOT_CALLBACKS
+                    .iter()
+                    .any(|prefix| builtin.name.starts_with(prefix)) =>
+            {
+                Err(format!("{}() can't be used as a callback", builtin.name))
+            }
+            Value::Builtin(builtin) if builtin.name.starts_with(csv::PREFIX) => {
+                self.csv.call(&builtin.name, &args)
+            }
+            #[cfg(feature = "sqlite")]
+            Value::Builtin(builtin) if builtin.name.starts_with(sqlite::PREFIX) => {
+                self.sqlite.call(&builtin.name, &args)
+            }
+            Value::Builtin(builtin) if builtin.name == "path.glob" => {
+                self.glob(&builtin.name, &args)
+            }
+            Value::Builtin(builtin) if self.host_functions.contains(&builtin.name) => {
+                let result = self.host_functions.call(&builtin.name, args)?;
+                self.resolve(result).await
+            }
+            Value::Builtin(builtin) => call_builtin(&builtin.name, &args).await,
+            Value::Function(_) | Value::Method(_) | Value::Class(_) => {
+                self.run_function(callback, args).await
+            }
+            Value::NativeMethod(method) => method.call(args),
+            other => Err(format!(
+                "Cannot call non-function value: {}",
+                other.type_name()
+            )),
+        }
+    }
+
     fn binary_operation<F>(&mut self, op: F) -> Result<(), String>
     where
         F: FnOnce(&Value, &Value) -> Result<Value, String>,
@@ -261,6 +1469,18 @@ impl VM {
         Ok(())
     }
 
+    fn unary_operation<F>(&mut self, op: F) -> Result<(), String>
+    where
+        F: FnOnce(&Value) -> Result<Value, String>,
+    {
+        let operand = self
+            .stack
+            .pop()
+            .ok_or_else(|| "Stack underflow in unary operation".to_string())?;
+        self.stack.push(op(&operand)?);
+        Ok(())
+    }
+
     fn debug_instruction(&self, instruction: &Instruction) {
         let bytecode = self.bytecode.as_ref().unwrap();
 
@@ -305,12 +1525,634 @@ impl VM {
         self.environment.set(name, value)
     }
 
+    /// Names of the globals, leaving out builtin and host functions
+    #[allow(dead_code)] // Used by WASM, embedded, and REPL modules
+    pub fn global_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .environment
+            .globals()
+            .iter()
+            .filter(|(_, value)| !heap::is_builtin(value))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Keep what `print` writes from now on instead of writing it to stdout
+    #[allow(dead_code)] // Used by the WASM runtime
+    pub fn capture_output(&mut self) {
+        self.output = Some(String::new());
+    }
+
+    /// What `print` wrote since `capture_output`, ending the capture
+    #[allow(dead_code)] // Used by the WASM runtime
+    pub fn take_output(&mut self) -> Option<String> {
+        self.output.take()
+    }
+
     #[allow(dead_code)] // Used by WASM, embedded, and REPL modules
     pub fn clear_globals(&mut self) {
+        self.host_functions.clear_pending();
+        self.timers.cancel_all();
+        self.scheduler.clear();
+        self.events.clear_script_listeners();
+        self.finalizers.clear();
+        self.csv.close_all();
+        #[cfg(feature = "sqlite")]
+        self.sqlite.close_all();
+        #[cfg(feature = "websocket")]
+        self.websockets.close_all();
+        #[cfg(feature = "http-server")]
+        self.http_servers.close_all();
         self.environment = Environment::new();
         // Re-setup built-ins after clearing
         for (name, value) in setup_builtins() {
             self.environment.define_global(name, value);
         }
+        for name in self.host_functions.names() {
+            self.define_host_global(&name);
+        }
+        for (name, module) in &self.native_modules {
+            self.environment.define_global(name, module.clone());
+        }
+    }
+
+    /// Make `name` callable from scripts, checking calls against `signature` if one is given.
+    /// Host functions survive `clear_globals`.
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn register_host_function(
+        &mut self,
+        name: &str,
+        callback: HostCallback,
+        signature: Option<HostSignature>,
+    ) {
+        self.host_functions.register(name, callback, signature);
+        self.define_host_global(name);
+    }
+
+    /// Declarations of the registered host functions, in `.nagd` format
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn host_declarations(&self) -> String {
+        self.host_functions.declarations()
+    }
+
+    fn define_host_global(&mut self, name: &str) {
+        self.environment.define_global(
+            name,
+            Value::Builtin(BuiltinFunction {
+                name: name.to_string(),
+                arity: 0,
+            }),
+        );
+    }
+
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn has_host_function(&self, name: &str) -> bool {
+        self.host_functions.contains(name)
+    }
+
+    /// Call a registered host function the way a script would
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn call_host_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+        self.host_functions.call(name, args)
+    }
+
+    /// Limit how long awaiting a single host function call may take
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn set_host_timeout(&mut self, timeout: Option<Duration>) {
+        self.host_functions.set_timeout(timeout);
+    }
+
+    /// Let scripts open websocket connections and servers and HTTP servers, which they may not
+    /// by default
+    pub fn set_allow_network(&mut self, allow: bool) {
+        self.permissions.set_allow_network(allow);
+    }
+
+    /// Let scripts open SQLite databases, which they may not by default
+    pub fn set_allow_io(&mut self, allow: bool) {
+        self.permissions.set_allow_io(allow);
+    }
+
+    /// Let the host load native extension modules, which it may not by default
+    pub fn set_allow_native_modules(&mut self, allow: bool) {
+        self.permissions.set_allow_native(allow);
+    }
+
+    /// Load the native extension module of the shared library at `path` as a global of the
+    /// module's name, which is returned. The module replaces one loaded before under the same
+    /// name, and survives `clear_globals`. See [`plugin`](crate::plugin) for the ABI.
+    pub fn load_native_module(&mut self, path: impl AsRef<Path>) -> Result<String, String> {
+        let path = path.as_ref();
+        self.permissions.check(
+            "load_native_module",
+            Permission::Native(path.display().to_string()),
+        )?;
+        let module = NativeModule::load(path)?;
+        let name = module.name().to_string();
+        let module = Value::Native(NativeObject::new(module));
+        self.native_modules.retain(|(loaded, _)| *loaded != name);
+        self.native_modules.push((name.clone(), module.clone()));
+        self.environment.define_global(&name, module);
+        Ok(name)
+    }
+
+    /// Ask `prompt` whether to allow the IO and network access that isn't allowed outright,
+    /// as scripts first need each path or host
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn set_permission_prompt(&mut self, prompt: Option<PermissionPrompt>) {
+        self.permissions.set_prompt(prompt);
+    }
+
+    /// Call `listener` with every IO and network access scripts make or are denied, as it
+    /// happens
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn set_access_listener(&mut self, listener: Option<AccessListener>) {
+        self.permissions.set_listener(listener);
+    }
+
+    /// Collect the IO and network accesses scripts make or are denied from now on
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn start_access_report(&mut self) {
+        self.permissions.start_report();
+    }
+
+    /// The accesses since `start_access_report`, in the order they were checked
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn finish_access_report(&mut self) -> Vec<Access> {
+        self.permissions.finish_report()
+    }
+
+    /// Cancel every pending timer, e.g. when the host gives up on a script
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn cancel_timers(&mut self) {
+        self.timers.cancel_all();
+    }
+
+    /// Number of timers that have not fired or been cancelled yet
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn pending_timers(&self) -> usize {
+        self.timers.pending()
+    }
+
+    /// Keep the jobs scripts register in `store`, or stop keeping them. Saved jobs are
+    /// registered again by `restore_jobs`, or by scripts registering them under the same name
+    /// and schedule, which carry on from their saved times.
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn set_job_store(&mut self, store: Option<Arc<dyn JobStore>>) -> Result<(), String> {
+        self.scheduler.set_store(store)
+    }
+
+    /// Register the saved jobs whose global function is defined again, returning their names
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn restore_jobs(&mut self) -> Result<Vec<String>, String> {
+        let mut restored = Vec::new();
+        for record in self.scheduler.saved().to_vec() {
+            let callback = record
+                .function
+                .as_ref()
+                .and_then(|function| self.environment.globals().get(function))
+                .filter(|callback| matches!(callback, Value::Function(_)));
+            let Some(callback) = callback.cloned() else {
+                continue;
+            };
+            let now = schedule::millis(SystemTime::now());
+            self.scheduler.register(
+                &record.name,
+                record.trigger,
+                record.function,
+                callback,
+                now,
+            )?;
+            restored.push(record.name);
+        }
+        Ok(restored)
+    }
+
+    /// The jobs scripts registered, in the order they did
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn jobs(&self) -> Vec<JobRecord> {
+        self.scheduler.jobs()
+    }
+
+    /// When the earliest job is due, if there is any
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn next_job_due(&self) -> Option<SystemTime> {
+        self.scheduler.next_due().map(schedule::system_time)
+    }
+
+    /// Run the jobs due by `time`, earliest first, returning how many ran. A job that fails
+    /// doesn't keep the others from running, and the first error is returned once they have.
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub async fn run_due_jobs(&mut self, time: SystemTime) -> Result<usize, String> {
+        let time = schedule::millis(time);
+        let mut ran = 0;
+        let mut result = Ok(());
+        self.budget.start();
+        self.yield_points.start();
+        for name in self.scheduler.due(time) {
+            // An earlier job may have cancelled it
+            let Some(callback) = self.scheduler.start(&name, time) else {
+                continue;
+            };
+            ran += 1;
+            if let Err(e) = self.call_callback(callback, Vec::new()).await {
+                result = result.and(Err(format!("job '{name}': {e}")));
+            }
+        }
+        if ran > 0 {
+            result = result.and(self.scheduler.save());
+        }
+        if result.is_ok() {
+            if let Err(e) = self.run_finalizers().await {
+                result = Err(format!("Runtime error in finalizer: {e}"));
+            }
+        }
+        self.budget.stop();
+        result.map(|()| ran)
+    }
+
+    /// Call `listener` whenever a script emits `event`, or only the next time if `once`.
+    /// Returns an id for `unsubscribe_event`. Subscriptions survive `clear_globals`.
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn subscribe_event(&mut self, event: &str, listener: EventListener, once: bool) -> u64 {
+        self.events.add(event, Listener::Host(listener), once)
+    }
+
+    #[allow(dead_code)] // Used by the embedded runtime
+    pub fn unsubscribe_event(&mut self, id: u64) -> bool {
+        self.events.unsubscribe(id)
+    }
+
+    /// Call the listeners of `event`, the scripts' and the host's, with `args`. Returns
+    /// whether there were any.
+    #[allow(dead_code)] // Used by embedding hosts
+    pub async fn emit_event(&mut self, event: &str, args: Vec<Value>) -> Result<bool, String> {
+        self.budget.start();
+        self.yield_points.start();
+        let result = self.dispatch_event(event, args).await;
+        self.budget.stop();
+        result
+    }
+
+    async fn dispatch_event(&mut self, event: &str, args: Vec<Value>) -> Result<bool, String> {
+        let listeners = self.events.take_listeners(event);
+        for listener in &listeners {
+            match listener {
+                Listener::Script(callback) => {
+                    self.call_callback(callback.clone(), args.clone()).await?;
+                }
+                Listener::Host(listener) => listener(&args)?,
+            }
+        }
+        Ok(!listeners.is_empty())
+    }
+
+    /// Take the call behind a pending value, to await it without borrowing the VM
+    pub fn take_host_call(&mut self, value: &Value) -> Result<Option<HostCall>, String> {
+        match (value, self.host_functions.take(value)) {
+            (_, Some(call)) => Ok(Some(call)),
+            (Value::Pending(call), None) => {
+                Err(format!("{}() has already been awaited", call.function))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Await `value`: pending host calls resolve to their result, other values to themselves
+    pub async fn resolve(&mut self, value: Value) -> Result<Value, String> {
+        match self.take_host_call(&value)? {
+            Some(call) => call.wait().await,
+            None => Ok(value),
+        }
+    }
+}
+
+/// The keyword arguments of a call, from the list of their names and their values. A `**`
+/// name stands for a dict of keyword arguments, which are taken in the order of their names.
+fn keyword_arguments(
+    names: Vec<Value>,
+    values: Vec<Value>,
+) -> Result<Vec<(String, Value)>, String> {
+    let mut keywords = Vec::with_capacity(names.len());
+    for (name, value) in names.into_iter().zip(values) {
+        match (name, value) {
+            (Value::String(name), Value::Dict(mapping)) if name == "**" => {
+                let mut entries: Vec<_> = mapping.into_iter().collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                keywords.extend(entries);
+            }
+            (Value::String(name), value) if name == "**" => {
+                return Err(format!(
+                    "argument after ** must be a dict, not '{}'",
+                    value.type_name()
+                ))
+            }
+            (Value::String(name), value) => keywords.push((name, value)),
+            (name, _) => {
+                return Err(format!(
+                    "keyword names must be str, not '{}'",
+                    name.type_name()
+                ))
+            }
+        }
+    }
+    Ok(keywords)
+}
+
+/// The value of each parameter of `function` in a call with `args` and `keywords`, in the order
+/// of the parameters. A keyword argument goes to the parameter it names, or into the `**kwargs`
+/// dict if none has its name.
+fn bind_arguments(
+    function: &Function,
+    args: Vec<Value>,
+    keywords: Vec<(String, Value)>,
+) -> Result<Vec<(&str, Value)>, String> {
+    let positional: Vec<&str> = function
+        .parameters
+        .iter()
+        .filter(|name| !name.starts_with('*'))
+        .map(String::as_str)
+        .collect();
+    let rest = function
+        .parameters
+        .iter()
+        .any(|name| name.starts_with('*') && !name.starts_with("**"));
+    let takes_keywords = function
+        .parameters
+        .iter()
+        .any(|name| name.starts_with("**"));
+    if (args.len() < positional.len() && keywords.is_empty())
+        || (args.len() > positional.len() && !rest)
+    {
+        return Err(format!(
+            "{}() takes {}{} argument(s) ({} given)",
+            function.name,
+            if rest { "at least " } else { "" },
+            positional.len(),
+            args.len()
+        ));
+    }
+
+    let mut args = args.into_iter();
+    let mut values: Vec<Option<Value>> = positional.iter().map(|_| args.next()).collect();
+    let extra: Vec<Value> = args.collect();
+    let mut kwargs = HashMap::new();
+    for (keyword, value) in keywords {
+        let taken = match positional.iter().position(|name| *name == keyword) {
+            Some(index) => values[index].replace(value).is_some(),
+            None if takes_keywords => kwargs.insert(keyword.clone(), value).is_some(),
+            None => {
+                return Err(format!(
+                    "{}() got an unexpected keyword argument '{keyword}'",
+                    function.name
+                ))
+            }
+        };
+        if taken {
+            return Err(format!(
+                "{}() got multiple values for argument '{keyword}'",
+                function.name
+            ));
+        }
+    }
+    if let Some(index) = values.iter().position(Option::is_none) {
+        return Err(format!(
+            "{}() missing required argument '{}'",
+            function.name, positional[index]
+        ));
+    }
+
+    let mut values = values.into_iter().flatten();
+    let (mut extra, mut kwargs) = (Some(extra), Some(kwargs));
+    Ok(function
+        .parameters
+        .iter()
+        .map(|name| {
+            if let Some(name) = name.strip_prefix("**") {
+                (name, Value::Dict(kwargs.take().unwrap_or_default()))
+            } else if let Some(name) = name.strip_prefix('*') {
+                (name, Value::List(extra.take().unwrap_or_default()))
+            } else {
+                (name.as_str(), values.next().unwrap_or(Value::None))
+            }
+        })
+        .collect())
+}
+
+/// The arguments of a call to the builtin `name`, with `keywords` placed after `args` by the
+/// names of its parameters. Parameters skipped over are passed none.
+fn place_keywords(
+    name: &str,
+    args: Vec<Value>,
+    keywords: Vec<(String, Value)>,
+) -> Result<Vec<Value>, String> {
+    let function = name.rsplit('.').next().unwrap_or(name);
+    let Some(parameters) = builtin_parameters(name) else {
+        return Err(format!("{function}() takes no keyword arguments"));
+    };
+    let mut placed: Vec<Option<Value>> = args.into_iter().map(Some).collect();
+    for (keyword, value) in keywords {
+        let Some(index) = parameters
+            .iter()
+            .position(|parameter| *parameter == keyword)
+        else {
+            return Err(format!(
+                "{function}() got an unexpected keyword argument '{keyword}'"
+            ));
+        };
+        if placed.len() <= index {
+            placed.resize(index + 1, None);
+        }
+        if placed[index].replace(value).is_some() {
+            return Err(format!(
+                "{function}() got multiple values for argument '{keyword}'"
+            ));
+        }
+    }
+    Ok(placed
+        .into_iter()
+        .map(|value| value.unwrap_or(Value::None))
+        .collect())
+}
+
+/// Whether `opcode` drops a value, which may have been the last one referring to an object
+/// with a finalizer
+fn drops_value(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        Opcode::Pop | Opcode::StoreName | Opcode::StoreAttr | Opcode::ExitContext
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// A VM that has run `source`
+    async fn run(source: &str) -> VM {
+        let mut vm = VM::new(false);
+        vm.load_source(source, nagari_compiler::VERSION, |source| {
+            nagari_compiler::Compiler::new()
+                .compile_to_bytecode(source)
+                .map_err(|e| e.to_string())
+        })
+        .unwrap();
+        vm.run().await.unwrap();
+        vm
+    }
+
+    #[tokio::test]
+    async fn test_call_function_calls_script_functions() {
+        let mut vm =
+            run("calls = 0\ndef add(a, b):\n    calls = calls + 1\n    return a + b\n").await;
+        let result = vm
+            .call_function("add", vec![Value::Int(2), Value::Int(3)])
+            .await
+            .unwrap();
+        assert_eq!(result, Value::Int(5));
+        let result = vm
+            .call_function(
+                "add",
+                vec![Value::String("a".into()), Value::String("b".into())],
+            )
+            .await
+            .unwrap();
+        assert_eq!(result, Value::String("ab".into()));
+        // Calls run in frames of their own, leaving the globals to the script
+        assert_eq!(vm.get_global("calls"), Some(&Value::Int(0)));
+    }
+
+    #[tokio::test]
+    async fn test_call_function_calls_builtins_and_host_functions() {
+        let mut vm = VM::new(false);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        let callback = HostCallback::Sync(Arc::new(move |args: Vec<Value>| {
+            recorded.lock().unwrap().extend(args);
+            Ok(Value::Bool(true))
+        }));
+        vm.register_host_function("notify", callback, None);
+
+        let result = vm
+            .call_function("notify", vec![Value::Int(1)])
+            .await
+            .unwrap();
+        assert_eq!(result, Value::Bool(true));
+        assert_eq!(*seen.lock().unwrap(), vec![Value::Int(1)]);
+        let result = vm
+            .call_function("len", vec![Value::String("four".into())])
+            .await
+            .unwrap();
+        assert_eq!(result, Value::Int(4));
+    }
+
+    #[tokio::test]
+    async fn test_call_function_errors() {
+        let mut vm = run("def broken(n):\n    return n / 0\nnumber = 1\n").await;
+        assert_eq!(
+            vm.call_function("missing", vec![]).await.unwrap_err(),
+            "name 'missing' is not defined"
+        );
+        assert_eq!(
+            vm.call_function("broken", vec![]).await.unwrap_err(),
+            "broken() takes 1 argument(s) (0 given)"
+        );
+        assert_eq!(
+            vm.call_function("broken", vec![Value::Int(1)])
+                .await
+                .unwrap_err(),
+            "Division by zero"
+        );
+        assert_eq!(
+            vm.call_function("number", vec![]).await.unwrap_err(),
+            "Cannot call non-function value: int"
+        );
+    }
+
+    /// What running `source` fails with
+    async fn run_error(source: &str) -> String {
+        let mut vm = VM::new(false);
+        vm.load_source(source, nagari_compiler::VERSION, |source| {
+            nagari_compiler::Compiler::new()
+                .compile_to_bytecode(source)
+                .map_err(|e| e.to_string())
+        })
+        .unwrap();
+        vm.run().await.unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn test_keyword_arguments_bind_by_name() {
+        let vm = run("def greet(name, greeting, punctuation):\n    \
+                      return greeting + \", \" + name + punctuation\n\
+                      def log(level, *messages, **fields):\n    \
+                      return [level, messages, fields]\n\
+                      class Point {\n    \
+                      def __init__(self, x, y):\n        \
+                      self.x = x\n        \
+                      self.y = y\n\
+                      }\n\
+                      a = greet(\"Ada\", punctuation=\"?\", greeting=\"Hi\")\n\
+                      b = log(\"info\", \"ready\", user=\"ada\", **{id: 7})\n\
+                      c = Point(1, y=2).y\n")
+        .await;
+        assert_eq!(vm.get_global("a"), Some(&Value::String("Hi, Ada?".into())));
+        let fields = HashMap::from([
+            ("user".to_string(), Value::String("ada".into())),
+            ("id".to_string(), Value::Int(7)),
+        ]);
+        assert_eq!(
+            vm.get_global("b"),
+            Some(&Value::List(vec![
+                Value::String("info".into()),
+                Value::List(vec![Value::String("ready".into())]),
+                Value::Dict(fields),
+            ]))
+        );
+        assert_eq!(vm.get_global("c"), Some(&Value::Int(2)));
+    }
+
+    #[tokio::test]
+    async fn test_keyword_argument_errors() {
+        let functions = "def add(a, b):\n    return a + b\n";
+        for (call, message) in [
+            (
+                "add(1, c=2)",
+                "add() got an unexpected keyword argument 'c'",
+            ),
+            ("add(1, a=2)", "add() got multiple values for argument 'a'"),
+            ("add(b=2)", "add() missing required argument 'a'"),
+            (
+                "add(1, **[2])",
+                "argument after ** must be a dict, not 'list'",
+            ),
+            ("len(\"a\", obj=1)", "len() takes no keyword arguments"),
+            (
+                "json.dumps(1, sort=true)",
+                "dumps() got an unexpected keyword argument 'sort'",
+            ),
+        ] {
+            let error = run_error(&format!("{functions}{call}\n")).await;
+            assert!(error.contains(message), "{call}: {error}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_dumps_takes_canonical_by_keyword() {
+        let vm = run("data = {b: [1.5, \"x\"], a: null}\n\
+                      canonical = json.dumps(data, canonical=true)\n\
+                      indented = json.dumps(obj=[1], indent=2)\n")
+        .await;
+        assert_eq!(
+            vm.get_global("canonical"),
+            Some(&Value::String(r#"{"a":null,"b":[1.5,"x"]}"#.into()))
+        );
+        assert_eq!(
+            vm.get_global("indented"),
+            Some(&Value::String("[\n  1\n]".into()))
+        );
     }
 }
@@ -0,0 +1,161 @@
+//! The `weakref` module: `ref(obj)`, `finalize(obj, callback, *args)` and `detach(handle)`.
+//!
+//! Objects and host resources are shared by the values referring to them and go away with the
+//! last of those. `ref(obj)` returns a weak reference, which doesn't count: calling it returns
+//! the object while something else still refers to it, and none afterwards.
+//!
+//! `finalize` schedules `callback(*args)` for when the object goes away and returns a handle
+//! `detach` takes to cancel it. The VM looks for objects that went away whenever a script drops
+//! a value, by assigning over a name or an attribute, discarding the value of an expression,
+//! leaving a `with` statement or returning from a function, and at the end of each run. The
+//! callbacks then run in the order they were scheduled. A callback or argument referring to its
+//! object keeps it alive, so its callback never runs. Clearing the VM's globals drops the
+//! finalizers along with the objects.
+
+use crate::resources::WeakResource;
+use crate::value::{BuiltinFunction, Value, WeakInstance};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "weakref.";
+
+/// The `weakref` module: a dict of builtins named `weakref.<function>`
+pub fn module() -> Value {
+    let functions = [("ref", 1), ("finalize", 2), ("detach", 1)];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// Reference to an object or resource that does not keep it alive
+#[derive(Clone)]
+pub enum WeakRef {
+    Instance(WeakInstance),
+    Resource(WeakResource),
+}
+
+impl WeakRef {
+    /// A weak reference to `value`, which must be an object or a resource
+    pub fn new(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Instance(instance) => Ok(WeakRef::Instance(instance.downgrade())),
+            Value::Resource(resource) => Ok(WeakRef::Resource(resource.downgrade())),
+            other => Err(format!(
+                "cannot create weak reference to '{}' object",
+                other.type_name()
+            )),
+        }
+    }
+
+    /// The object, unless it went away
+    pub fn upgrade(&self) -> Option<Value> {
+        match self {
+            WeakRef::Instance(instance) => instance.upgrade().map(Value::Instance),
+            WeakRef::Resource(resource) => resource.upgrade().map(Value::Resource),
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        self.upgrade().is_none()
+    }
+}
+
+impl PartialEq for WeakRef {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (WeakRef::Instance(a), WeakRef::Instance(b)) => a.ptr_eq(b),
+            (WeakRef::Resource(a), WeakRef::Resource(b)) => a.ptr_eq(b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for WeakRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WeakRef({self})")
+    }
+}
+
+impl fmt::Display for WeakRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.upgrade() {
+            Some(Value::Instance(instance)) => write!(f, "<weakref to {}>", instance.class.name),
+            Some(target) => write!(f, "<weakref to {target}>"),
+            None => write!(f, "<weakref; dead>"),
+        }
+    }
+}
+
+struct Finalizer {
+    id: i64,
+    target: WeakRef,
+    callback: Value,
+    args: Vec<Value>,
+}
+
+#[derive(Default)]
+pub struct Finalizers {
+    pending: Vec<Finalizer>,
+    next_id: i64,
+}
+
+impl Finalizers {
+    /// Call `callback` with `args` once `target` goes away, returning the finalizer's handle
+    pub fn register(&mut self, target: WeakRef, callback: Value, args: Vec<Value>) -> i64 {
+        self.next_id += 1;
+        self.pending.push(Finalizer {
+            id: self.next_id,
+            target,
+            callback,
+            args,
+        });
+        self.next_id
+    }
+
+    /// Cancel the finalizer `id`, returning whether it had yet to run
+    pub fn detach(&mut self, id: i64) -> bool {
+        let count = self.pending.len();
+        self.pending.retain(|finalizer| finalizer.id != id);
+        self.pending.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Callbacks and arguments of the finalizers whose objects went away, which are done
+    pub fn take_collected(&mut self) -> Vec<(Value, Vec<Value>)> {
+        let (collected, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|finalizer| finalizer.target.is_dead());
+        self.pending = pending;
+        collected
+            .into_iter()
+            .map(|finalizer: Finalizer| (finalizer.callback, finalizer.args))
+            .collect()
+    }
+
+    /// Callbacks and arguments of the finalizers yet to run, by handle
+    pub fn values(&self) -> impl Iterator<Item = (i64, &Value)> {
+        self.pending.iter().flat_map(|finalizer| {
+            std::iter::once(&finalizer.callback)
+                .chain(&finalizer.args)
+                .map(|value| (finalizer.id, value))
+        })
+    }
+}
@@ -0,0 +1,600 @@
+//! The `websocket` module: `connect(url)`, `send(socket, message)`, `receive(socket)`,
+//! `on_message(socket, listener)`, `close(socket)` and `serve(port, handler)`.
+//!
+//! Sockets and servers are the integers `connect` and `serve` return. Messages are `str`s for
+//! text frames and `bytes` for binary ones. `receive` waits for the next message of a socket,
+//! running timers, listeners and handlers meanwhile as `sleep` does, or returns `none` once
+//! the other end has closed the socket. A socket with a listener passes its messages to the
+//! listener instead, and `on_message(socket, null)` stops listening. `serve` accepts
+//! connections on `port` on every interface and calls `handler` with each new socket.
+//! `close` closes a socket, sending a close frame, or stops a server.
+//!
+//! Listeners and handlers run while the VM waits for timers, which it keeps doing as long as a
+//! server runs or a socket with a listener is open, the way an event loop keeps a program
+//! alive. Connecting and serving need network access, which hosts allow with
+//! [`VM::set_allow_network`](crate::VM::set_allow_network) or a
+//! [permission prompt](crate::permissions), asked about the host of the URL or
+//! `0.0.0.0:<port>`. Sockets and servers belong to the VM that opened them, and clearing its
+//! globals closes them.
+
+use crate::permissions::{Permission, Permissions};
+use crate::value::{BuiltinFunction, Value};
+use futures_util::stream::SplitStream;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::http::Uri;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// Prefix of the builtins' names
+pub const PREFIX: &str = "websocket.";
+
+/// The `websocket` module: a dict of builtins named `websocket.<function>`
+pub fn module() -> Value {
+    let functions = [
+        ("connect", 1),
+        ("send", 2),
+        ("receive", 1),
+        ("on_message", 2),
+        ("close", 1),
+        ("serve", 2),
+    ];
+    let module: HashMap<String, Value> = functions
+        .into_iter()
+        .map(|(name, arity)| {
+            (
+                name.to_string(),
+                Value::Builtin(BuiltinFunction {
+                    name: format!("{PREFIX}{name}"),
+                    arity,
+                }),
+            )
+        })
+        .collect();
+    Value::Dict(module)
+}
+
+/// What comes in from the tasks reading sockets and accepting connections
+enum Event {
+    Message(i64, Value),
+    /// The other end closed the socket, or the connection broke
+    Closed(i64),
+    Accepted(i64, Box<WebSocketStream<tokio::net::TcpStream>>),
+}
+
+type Writer = Pin<Box<dyn Sink<Message, Error = WsError> + Send + Sync>>;
+
+struct Socket {
+    writer: Writer,
+    reader: JoinHandle<()>,
+    /// Messages no one has received yet
+    inbox: VecDeque<Value>,
+    listener: Option<Value>,
+    closed: bool,
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+struct Server {
+    handler: Value,
+    acceptor: JoinHandle<()>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.acceptor.abort();
+    }
+}
+
+/// The open sockets and running servers of a VM
+pub struct Websockets {
+    sockets: HashMap<i64, Socket>,
+    servers: HashMap<i64, Server>,
+    next_id: i64,
+    sender: UnboundedSender<Event>,
+    receiver: UnboundedReceiver<Event>,
+    /// Events to handle ahead of new ones: messages that came in before their socket's
+    /// listener was added
+    pending: VecDeque<Event>,
+    permissions: Permissions,
+}
+
+impl Default for Websockets {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sockets: HashMap::new(),
+            servers: HashMap::new(),
+            next_id: 0,
+            sender,
+            receiver,
+            pending: VecDeque::new(),
+            permissions: Permissions::default(),
+        }
+    }
+}
+
+impl Websockets {
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    /// Call the builtin `name` of the module
+    pub async fn call(&mut self, name: &str, args: &[Value]) -> Result<Value, String> {
+        let function = &name[PREFIX.len()..];
+        match (function, args) {
+            ("connect", [Value::String(url)]) => {
+                self.permissions
+                    .check(name, Permission::Network(host(url)))?;
+                let (stream, _) = tokio_tungstenite::connect_async(url.as_str())
+                    .await
+                    .map_err(|e| format!("connect(): cannot connect to '{url}': {e}"))?;
+                Ok(Value::Int(self.add_socket(stream)))
+            }
+            ("send", [socket, message]) => {
+                let message = match message {
+                    Value::String(text) => Message::Text(text.clone()),
+                    Value::Bytes(data) => Message::Binary(data.clone()),
+                    other => {
+                        return Err(format!(
+                            "send() message must be str or bytes, not '{}'",
+                            other.type_name()
+                        ))
+                    }
+                };
+                let id = self.socket_id(function, socket)?;
+                self.socket(function, id)?
+                    .writer
+                    .send(message)
+                    .await
+                    .map_err(|e| format!("send(): {e}"))?;
+                Ok(Value::None)
+            }
+            ("on_message", [socket, listener]) => {
+                let id = self.socket_id(function, socket)?;
+                let listener = match listener {
+                    Value::None => None,
+                    Value::Builtin(_) | Value::Function(_) | Value::Method(_) => {
+                        Some(listener.clone())
+                    }
+                    other => {
+                        return Err(format!(
+                            "on_message() listener must be a function, not '{}'",
+                            other.type_name()
+                        ))
+                    }
+                };
+                let socket = self.socket(function, id)?;
+                socket.listener = listener;
+                if socket.listener.is_some() {
+                    // What came in before is the listener's, ahead of what comes in next
+                    let inbox = std::mem::take(&mut socket.inbox);
+                    for message in inbox.into_iter().rev() {
+                        self.pending.push_front(Event::Message(id, message));
+                    }
+                }
+                Ok(Value::None)
+            }
+            ("close", [handle]) => {
+                let id = self.socket_id(function, handle)?;
+                if self.servers.remove(&id).is_some() {
+                    return Ok(Value::Bool(true));
+                }
+                match self.sockets.remove(&id) {
+                    Some(mut socket) => {
+                        // The other end may be gone already, which is as good as closed
+                        let _ = socket.writer.close().await;
+                        Ok(Value::Bool(true))
+                    }
+                    None => Ok(Value::Bool(false)),
+                }
+            }
+            ("serve", [Value::Int(port), handler]) => {
+                self.permissions
+                    .check(name, Permission::Network(format!("0.0.0.0:{port}")))?;
+                if !matches!(
+                    handler,
+                    Value::Builtin(_) | Value::Function(_) | Value::Method(_)
+                ) {
+                    return Err(format!(
+                        "serve() handler must be a function, not '{}'",
+                        handler.type_name()
+                    ));
+                }
+                let port = u16::try_from(*port)
+                    .map_err(|_| format!("serve() port must be from 0 to 65535, got {port}"))?;
+                let listener = TcpListener::bind(("0.0.0.0", port))
+                    .await
+                    .map_err(|e| format!("serve(): cannot listen on port {port}: {e}"))?;
+                self.next_id += 1;
+                let id = self.next_id;
+                let events = self.sender.clone();
+                let acceptor = tokio::spawn(async move {
+                    while let Ok((stream, _)) = listener.accept().await {
+                        let events = events.clone();
+                        // A client failing its handshake doesn't hold up the others
+                        tokio::spawn(async move {
+                            if let Ok(stream) = tokio_tungstenite::accept_async(stream).await {
+                                let _ = events.send(Event::Accepted(id, Box::new(stream)));
+                            }
+                        });
+                    }
+                });
+                self.servers.insert(
+                    id,
+                    Server {
+                        handler: handler.clone(),
+                        acceptor,
+                    },
+                );
+                Ok(Value::Int(id))
+            }
+            ("serve", [other, _]) => Err(format!(
+                "serve() port must be an int, not '{}'",
+                other.type_name()
+            )),
+            ("connect", [other]) => Err(format!(
+                "connect() url must be a str, not '{}'",
+                other.type_name()
+            )),
+            ("connect" | "send" | "on_message" | "close" | "serve", _) => Err(format!(
+                "{function}() takes {} argument(s) ({} given)",
+                if matches!(function, "send" | "on_message" | "serve") { 2 } else { 1 },
+                args.len()
+            )),
+            _ => Err(format!("Unknown builtin function: {name}")),
+        }
+    }
+
+    /// What `receive(socket)` returns if it need not wait: the next message of the socket, or
+    /// none once it is closed
+    pub fn take_message(&mut self, args: &[Value]) -> Result<Option<Value>, String> {
+        let [socket] = args else {
+            return Err(format!(
+                "receive() takes 1 argument(s) ({} given)",
+                args.len()
+            ));
+        };
+        let id = self.socket_id("receive", socket)?;
+        let socket = self.socket("receive", id)?;
+        match socket.inbox.pop_front() {
+            Some(message) => Ok(Some(message)),
+            None if socket.closed => Ok(Some(Value::None)),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether a listener or handler may still be called: a server is running, or a socket
+    /// with a listener is open
+    pub fn listening(&self) -> bool {
+        !self.servers.is_empty()
+            || self
+                .sockets
+                .values()
+                .any(|socket| socket.listener.is_some() && !socket.closed)
+            || !self.pending.is_empty()
+    }
+
+    /// Wait for the next event and handle it, returning the listener or handler to call for it
+    /// and its arguments, if it has one
+    pub async fn next_event(&mut self) -> Option<(Value, Vec<Value>)> {
+        let event = match self.pending.pop_front() {
+            Some(event) => event,
+            None => self.next_received().await,
+        };
+        match self.file(event)? {
+            Event::Message(id, message) => {
+                let listener = self.sockets.get(&id)?.listener.clone()?;
+                Some((listener, vec![message]))
+            }
+            Event::Accepted(server, stream) => {
+                let handler = self.servers.get(&server)?.handler.clone();
+                let id = self.add_socket(*stream);
+                Some((handler, vec![Value::Int(id)]))
+            }
+            Event::Closed(_) => None,
+        }
+    }
+
+    /// Listeners and handlers, for heap snapshots
+    pub fn callbacks(&self) -> impl Iterator<Item = (String, &Value)> {
+        let listeners = self.sockets.iter().filter_map(|(id, socket)| {
+            let listener = socket.listener.as_ref()?;
+            Some((format!("websocket listener {id}"), listener))
+        });
+        let handlers = self
+            .servers
+            .iter()
+            .map(|(id, server)| (format!("websocket server {id}"), &server.handler));
+        listeners.chain(handlers)
+    }
+
+    pub fn close_all(&mut self) {
+        self.sockets.clear();
+        self.servers.clear();
+        self.pending.clear();
+        while self.receiver.try_recv().is_ok() {}
+    }
+
+    fn socket_id(&self, function: &str, value: &Value) -> Result<i64, String> {
+        match value {
+            Value::Int(id) => Ok(*id),
+            other => Err(format!(
+                "{function}() socket must be an int, not '{}'",
+                other.type_name()
+            )),
+        }
+    }
+
+    fn socket(&mut self, function: &str, id: i64) -> Result<&mut Socket, String> {
+        self.sockets
+            .get_mut(&id)
+            .ok_or_else(|| format!("{function}(): socket {id} is not open"))
+    }
+
+    fn add_socket<S>(&mut self, stream: WebSocketStream<S>) -> i64
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.next_id += 1;
+        let id = self.next_id;
+        let (writer, reader) = stream.split();
+        let reader = tokio::spawn(read_messages(id, reader, self.sender.clone()));
+        self.sockets.insert(
+            id,
+            Socket {
+                writer: Box::pin(writer),
+                reader,
+                inbox: VecDeque::new(),
+                listener: None,
+                closed: false,
+            },
+        );
+        id
+    }
+
+    async fn next_received(&mut self) -> Event {
+        self.receiver
+            .recv()
+            .await
+            .expect("the module keeps a sender of its own")
+    }
+
+    /// Put away what `event` brings that no listener or handler waits for, returning it if it
+    /// is for one
+    fn file(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::Message(id, message) => {
+                let socket = self.sockets.get_mut(&id)?;
+                if socket.listener.is_some() {
+                    return Some(Event::Message(id, message));
+                }
+                socket.inbox.push_back(message);
+                None
+            }
+            Event::Closed(id) => {
+                if let Some(socket) = self.sockets.get_mut(&id) {
+                    socket.closed = true;
+                }
+                None
+            }
+            Event::Accepted(server, _) if !self.servers.contains_key(&server) => None,
+            event => Some(event),
+        }
+    }
+}
+
+/// Pass the messages of socket `id` on to `events` until the other end closes it
+async fn read_messages<S>(id: i64, mut reader: SplitStream<S>, events: UnboundedSender<Event>)
+where
+    S: Stream<Item = Result<Message, WsError>>,
+{
+    while let Some(Ok(message)) = reader.next().await {
+        let message = match message {
+            Message::Text(text) => Value::String(text),
+            Message::Binary(data) => Value::Bytes(data),
+            Message::Close(_) => break,
+            // Pings are answered by the stream itself
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+        };
+        if events.send(Event::Message(id, message)).is_err() {
+            return;
+        }
+    }
+    let _ = events.send(Event::Closed(id));
+}
+
+/// The `host:port` of `url` that permission prompts are asked about, or `url` itself if it
+/// has none
+fn host(url: &str) -> String {
+    url.parse::<Uri>()
+        .ok()
+        .and_then(|uri| uri.authority().map(|authority| authority.to_string()))
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::HostCallback;
+    use crate::vm::VM;
+    use std::sync::{Arc, Mutex};
+
+    async fn call(vm: &mut VM, function: &str, args: Vec<Value>) -> Result<Value, String> {
+        let function = Value::Builtin(BuiltinFunction {
+            name: function.to_string(),
+            arity: 0,
+        });
+        vm.call(function, args).await
+    }
+
+    /// A host function logging the arguments it is called with
+    fn logger(vm: &mut VM, name: &str) -> (Value, Arc<Mutex<Vec<Value>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let calls = log.clone();
+        let callback = HostCallback::Sync(Arc::new(move |args| {
+            calls.lock().unwrap().extend(args);
+            Ok(Value::None)
+        }));
+        vm.register_host_function(name, callback, None);
+        (vm.get_global(name).unwrap().clone(), log)
+    }
+
+    /// A port nothing listens on
+    fn free_port() -> i64 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port() as i64
+    }
+
+    #[tokio::test]
+    async fn test_messages_go_both_ways_until_a_socket_closes() {
+        let mut vm = VM::new(false);
+        vm.set_allow_network(true);
+        let (handler, accepted) = logger(&mut vm, "accepted");
+        let port = free_port();
+        let server = call(&mut vm, "websocket.serve", vec![Value::Int(port), handler])
+            .await
+            .unwrap();
+        let url = Value::String(format!("ws://127.0.0.1:{port}"));
+        let client = call(&mut vm, "websocket.connect", vec![url]).await.unwrap();
+        let hello = Value::String("hello".to_string());
+        call(
+            &mut vm,
+            "websocket.send",
+            vec![client.clone(), hello.clone()],
+        )
+        .await
+        .unwrap();
+
+        // The handler gets the server's end of the connection while the script sleeps
+        call(&mut vm, "timers.sleep", vec![Value::Int(100)])
+            .await
+            .unwrap();
+        let accepted = accepted.lock().unwrap().clone();
+        assert_eq!(
+            accepted.len(),
+            1,
+            "the handler is called once per connection"
+        );
+        let peer = accepted[0].clone();
+        assert_eq!(
+            call(&mut vm, "websocket.receive", vec![peer.clone()]).await,
+            Ok(hello)
+        );
+        let data = Value::Bytes(vec![0, 1, 255]);
+        call(&mut vm, "websocket.send", vec![peer.clone(), data.clone()])
+            .await
+            .unwrap();
+        assert_eq!(
+            call(&mut vm, "websocket.receive", vec![client.clone()]).await,
+            Ok(data)
+        );
+
+        assert_eq!(
+            call(&mut vm, "websocket.close", vec![client.clone()]).await,
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            call(&mut vm, "websocket.receive", vec![peer]).await,
+            Ok(Value::None)
+        );
+        assert_eq!(
+            call(&mut vm, "websocket.receive", vec![client]).await,
+            Err("receive(): socket 2 is not open".to_string())
+        );
+        assert_eq!(
+            call(&mut vm, "websocket.close", vec![server]).await,
+            Ok(Value::Bool(true))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_listeners_get_the_messages_received_before_them() {
+        let mut vm = VM::new(false);
+        vm.set_allow_network(true);
+        let (handler, accepted) = logger(&mut vm, "accepted");
+        let (listener, messages) = logger(&mut vm, "message");
+        let port = free_port();
+        call(&mut vm, "websocket.serve", vec![Value::Int(port), handler])
+            .await
+            .unwrap();
+        let url = Value::String(format!("ws://127.0.0.1:{port}"));
+        let client = call(&mut vm, "websocket.connect", vec![url]).await.unwrap();
+        for message in ["one", "two"] {
+            let message = Value::String(message.to_string());
+            call(&mut vm, "websocket.send", vec![client.clone(), message])
+                .await
+                .unwrap();
+        }
+        call(&mut vm, "timers.sleep", vec![Value::Int(100)])
+            .await
+            .unwrap();
+        let peer = accepted.lock().unwrap()[0].clone();
+
+        call(&mut vm, "websocket.on_message", vec![peer, listener])
+            .await
+            .unwrap();
+        let three = Value::String("three".to_string());
+        call(&mut vm, "websocket.send", vec![client, three])
+            .await
+            .unwrap();
+        call(&mut vm, "timers.sleep", vec![Value::Int(100)])
+            .await
+            .unwrap();
+        let expected: Vec<Value> = ["one", "two", "three"]
+            .map(|message| Value::String(message.to_string()))
+            .into();
+        assert_eq!(*messages.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_connecting_and_serving_need_network_access() {
+        let mut vm = VM::new(false);
+        let (handler, _) = logger(&mut vm, "accepted");
+        vm.start_access_report();
+        let url = Value::String("ws://example.com:8080/chat".to_string());
+        assert_eq!(
+            call(&mut vm, "websocket.connect", vec![url]).await,
+            Err("connect(): network operations are not allowed".to_string())
+        );
+        assert_eq!(
+            call(&mut vm, "websocket.serve", vec![Value::Int(9000), handler]).await,
+            Err("serve(): network operations are not allowed".to_string())
+        );
+        let accesses = vm.finish_access_report();
+        let permissions: Vec<_> = accesses
+            .iter()
+            .map(|access| {
+                (
+                    access.operation.as_str(),
+                    &access.permission,
+                    access.allowed,
+                )
+            })
+            .collect();
+        assert_eq!(
+            permissions,
+            [
+                (
+                    "websocket.connect",
+                    &Permission::Network("example.com:8080".to_string()),
+                    false
+                ),
+                (
+                    "websocket.serve",
+                    &Permission::Network("0.0.0.0:9000".to_string()),
+                    false
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Yield points, so a long computation doesn't starve the host of the thread it runs on.
+//!
+//! Every so many instructions a run stops at a yield point: it calls the host's [`YieldHook`],
+//! if there is one, and lets the async runtime it is driven by run other tasks before going on.
+//! A host that drives runs to completion on its own thread, as the blocking embedded runtime
+//! does, uses the hook to pump its UI or event loop; on a shared tokio runtime the other tasks
+//! get their turn without a hook.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Instructions between yield points when the host doesn't choose
+pub const DEFAULT_YIELD_INTERVAL: u64 = 10_000;
+
+/// Called at each yield point of a run. It runs while the VM is borrowed, so it must not call
+/// back into it.
+#[derive(Clone)]
+pub struct YieldHook(Arc<dyn Fn() + Send + Sync>);
+
+impl YieldHook {
+    pub fn new<F>(hook: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Self(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for YieldHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("YieldHook")
+    }
+}
+
+/// When the current run yields next
+#[derive(Debug)]
+pub(crate) struct YieldPoints {
+    /// `None` to never yield
+    interval: Option<u64>,
+    hook: Option<YieldHook>,
+    /// Instructions left until the next yield point
+    countdown: u64,
+}
+
+impl Default for YieldPoints {
+    fn default() -> Self {
+        Self {
+            interval: Some(DEFAULT_YIELD_INTERVAL),
+            hook: None,
+            countdown: DEFAULT_YIELD_INTERVAL,
+        }
+    }
+}
+
+impl YieldPoints {
+    pub fn set_interval(&mut self, interval: Option<u64>) {
+        self.interval = interval.filter(|interval| *interval > 0);
+        self.start();
+    }
+
+    pub fn interval(&self) -> Option<u64> {
+        self.interval
+    }
+
+    pub fn set_hook(&mut self, hook: Option<YieldHook>) {
+        self.hook = hook;
+    }
+
+    /// Start a run with the whole interval before its first yield point
+    pub fn start(&mut self) {
+        self.countdown = self.interval.unwrap_or(0);
+    }
+
+    /// Count one instruction, returning whether the run reached a yield point
+    pub fn tick(&mut self) -> bool {
+        let Some(interval) = self.interval else {
+            return false;
+        };
+        self.countdown = self.countdown.saturating_sub(1);
+        if self.countdown > 0 {
+            return false;
+        }
+        self.countdown = interval;
+        true
+    }
+
+    /// Call the hook, then let the async runtime run other tasks
+    pub async fn yield_now(&self) {
+        if let Some(hook) = &self.hook {
+            (hook.0)();
+        }
+        tokio::task::yield_now().await;
+    }
+}
@@ -0,0 +1,213 @@
+//! Native extension module built against the C ABI of `nagari_vm::plugin`, with its own
+//! definitions of the ABI types as a module written in another language would have. The
+//! plugin tests of the VM build and load it.
+
+use std::ffi::{c_char, c_void};
+
+const NAGARI_NONE: u32 = 0;
+const NAGARI_INT: u32 = 2;
+const NAGARI_FLOAT: u32 = 3;
+const NAGARI_STR: u32 = 4;
+const NAGARI_LIST: u32 = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct NagariValue {
+    tag: u32,
+    data: Data,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Data {
+    int_val: i64,
+    float_val: f64,
+    buffer: Buffer,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Buffer {
+    ptr: *const c_void,
+    len: usize,
+}
+
+type NagariFunction = unsafe extern "C" fn(*const NagariValue, usize, *mut NagariValue) -> i32;
+
+#[repr(C)]
+pub struct NagariFunctionDef {
+    name: *const c_char,
+    call: NagariFunction,
+    arity: i32,
+}
+
+#[repr(C)]
+pub struct NagariConstantDef {
+    name: *const c_char,
+    value: NagariValue,
+}
+
+#[repr(C)]
+pub struct NagariModuleDef {
+    abi_version: u32,
+    name: *const c_char,
+    functions: *const NagariFunctionDef,
+    function_count: usize,
+    constants: *const NagariConstantDef,
+    constant_count: usize,
+    release: unsafe extern "C" fn(*mut NagariValue),
+}
+
+// Only read, by the VM
+unsafe impl Sync for NagariModuleDef {}
+unsafe impl Sync for NagariFunctionDef {}
+unsafe impl Sync for NagariConstantDef {}
+
+const ERROR: &str = "expected a list of numbers";
+
+fn string(tag: u32, text: &'static str) -> NagariValue {
+    NagariValue {
+        tag,
+        data: Data {
+            buffer: Buffer {
+                ptr: text.as_ptr().cast(),
+                len: text.len(),
+            },
+        },
+    }
+}
+
+/// `sum(numbers)`: the sum of a list of ints and floats, as a float
+unsafe extern "C" fn sum(args: *const NagariValue, _argc: usize, result: *mut NagariValue) -> i32 {
+    let list = *args;
+    if list.tag != NAGARI_LIST {
+        *result = string(NAGARI_STR, ERROR);
+        return 1;
+    }
+    let items = std::slice::from_raw_parts(
+        list.data.buffer.ptr.cast::<NagariValue>(),
+        list.data.buffer.len,
+    );
+    let mut total = 0.0;
+    for item in items {
+        total += match item.tag {
+            NAGARI_INT => item.data.int_val as f64,
+            NAGARI_FLOAT => item.data.float_val,
+            _ => {
+                *result = string(NAGARI_STR, ERROR);
+                return 1;
+            }
+        };
+    }
+    *result = NagariValue {
+        tag: NAGARI_FLOAT,
+        data: Data { float_val: total },
+    };
+    0
+}
+
+/// `shout(text)`: the text in upper case, allocated by the module and freed by `release`
+unsafe extern "C" fn shout(
+    args: *const NagariValue,
+    _argc: usize,
+    result: *mut NagariValue,
+) -> i32 {
+    let text = *args;
+    let bytes = std::slice::from_raw_parts(text.data.buffer.ptr.cast::<u8>(), text.data.buffer.len);
+    let shouted = Box::<[u8]>::from(bytes.to_ascii_uppercase());
+    let len = shouted.len();
+    *result = NagariValue {
+        tag: NAGARI_STR,
+        data: Data {
+            buffer: Buffer {
+                ptr: Box::into_raw(shouted).cast::<u8>().cast(),
+                len,
+            },
+        },
+    };
+    0
+}
+
+/// `count(...)`: how many arguments it was given
+unsafe extern "C" fn count(
+    _args: *const NagariValue,
+    argc: usize,
+    result: *mut NagariValue,
+) -> i32 {
+    *result = NagariValue {
+        tag: NAGARI_INT,
+        data: Data {
+            int_val: argc as i64,
+        },
+    };
+    0
+}
+
+unsafe extern "C" fn release(value: *mut NagariValue) {
+    let value = *value;
+    if value.tag == NAGARI_STR && value.data.buffer.ptr != ERROR.as_ptr().cast() {
+        let bytes = std::ptr::slice_from_raw_parts_mut(
+            value.data.buffer.ptr as *mut u8,
+            value.data.buffer.len,
+        );
+        drop(Box::from_raw(bytes));
+    }
+}
+
+static FUNCTIONS: [NagariFunctionDef; 3] = [
+    NagariFunctionDef {
+        name: c"sum".as_ptr(),
+        call: sum,
+        arity: 1,
+    },
+    NagariFunctionDef {
+        name: c"shout".as_ptr(),
+        call: shout,
+        arity: 1,
+    },
+    NagariFunctionDef {
+        name: c"count".as_ptr(),
+        call: count,
+        arity: -1,
+    },
+];
+
+static CONSTANTS: [NagariConstantDef; 2] = [
+    NagariConstantDef {
+        name: c"ANSWER".as_ptr(),
+        value: NagariValue {
+            tag: NAGARI_INT,
+            data: Data { int_val: 42 },
+        },
+    },
+    NagariConstantDef {
+        name: c"NOTHING".as_ptr(),
+        value: NagariValue {
+            tag: NAGARI_NONE,
+            data: Data { int_val: 0 },
+        },
+    },
+];
+
+static MODULE: NagariModuleDef = NagariModuleDef {
+    abi_version: if cfg!(feature = "old-abi") { 0 } else { 1 },
+    name: c"testmod".as_ptr(),
+    functions: FUNCTIONS.as_ptr(),
+    function_count: FUNCTIONS.len(),
+    constants: CONSTANTS.as_ptr(),
+    constant_count: CONSTANTS.len(),
+    release,
+};
+
+#[cfg(not(feature = "no-init"))]
+#[no_mangle]
+pub extern "C" fn nagari_module_init() -> *const NagariModuleDef {
+    &MODULE
+}
+
+/// Keeps the module in the library when it has no `nagari_module_init`
+#[cfg(feature = "no-init")]
+#[no_mangle]
+pub extern "C" fn nagari_test_module() -> *const NagariModuleDef {
+    &MODULE
+}
@@ -1,9 +1,21 @@
 #![allow(unexpected_cfgs)]
 
 use js_sys::Array;
-use nagari_vm::{Value as NagariValue, VM as NagariVM};
+use marshal::{js_value_to_nagari, nagari_value_to_js};
+use nagari_vm::modules::{parse_statement, parse_statements, Import, ModuleScope, ModuleStatement};
+use nagari_vm::{HostCallback, Module, ModuleRegistry, Value as NagariValue, VM as NagariVM};
 use std::collections::HashMap;
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+mod marshal;
+pub mod prelude;
+mod repl;
+mod store;
+
+pub use marshal::MarshalOptions;
+pub use store::NagariStore;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator.
 #[cfg(feature = "wee_alloc")]
@@ -70,11 +82,33 @@ impl JSValue {
     }
 }
 
+/// A JavaScript function kept by the VM, such as one registered with `register_js_function`
+struct JsHostFunction(js_sys::Function);
+
+// SAFETY: host callbacks must be `Send + Sync`. On wasm32 without the `atomics` feature there
+// are no threads: the VM, its callbacks and every JavaScript function run on the one thread of
+// the page or worker that loaded the module, so a `JsHostFunction` is never sent to or shared
+// with another thread. A build with `atomics` can run the VM on several threads, so it doesn't
+// get these impls.
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+unsafe impl Send for JsHostFunction {}
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+unsafe impl Sync for JsHostFunction {}
+
+/// The message of an exception thrown by JavaScript code
+fn js_error_message(error: &JsValue) -> String {
+    match error.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => error.as_string().unwrap_or_else(|| format!("{error:?}")),
+    }
+}
+
 // Main WASM VM interface
 #[wasm_bindgen]
 pub struct NagariWasmVM {
     vm: NagariVM,
-    globals: HashMap<String, NagariValue>,
+    modules: ModuleRegistry,
+    marshal: MarshalOptions,
 }
 
 #[wasm_bindgen]
@@ -85,14 +119,21 @@ impl NagariWasmVM {
 
         Ok(NagariWasmVM {
             vm,
-            globals: HashMap::new(),
+            modules: ModuleRegistry::new(),
+            marshal: MarshalOptions::default(),
         })
     }
 
+    /// Choose how `Map`, `Set` and `Date` arguments and globals are converted
+    #[wasm_bindgen]
+    pub fn set_marshal_options(&mut self, options: MarshalOptions) {
+        self.marshal = options;
+    }
+
     #[wasm_bindgen]
     pub fn run(&mut self, code: &str) -> Result<JSValue, JsValue> {
-        // Compile source code to bytecode and execute it
-        match self.compile_and_run_source(code) {
+        // Compile and run the script; imports and assignments become globals
+        match self.run_statements(code) {
             Ok(result) => Ok(JSValue::new(nagari_value_to_js(&result))),
             Err(e) => Err(JsValue::from_str(&e)),
         }
@@ -101,7 +142,7 @@ impl NagariWasmVM {
     #[wasm_bindgen]
     pub fn eval(&mut self, code: &str) -> Result<JSValue, JsValue> {
         // Compile and execute source code directly
-        match self.compile_and_run_source(code) {
+        match self.execute(code, None) {
             Ok(result) => Ok(JSValue::new(nagari_value_to_js(&result))),
             Err(e) => Err(JsValue::from_str(&e)),
         }
@@ -113,13 +154,15 @@ impl NagariWasmVM {
         let mut nagari_args = Vec::new();
         for i in 0..args.length() {
             let js_val = args.get(i);
-            let nagari_val = js_value_to_nagari(&js_val)
+            let nagari_val = js_value_to_nagari(&js_val, &self.marshal)
                 .map_err(|e| JsValue::from_str(&format!("Argument conversion error: {:?}", e)))?;
             nagari_args.push(nagari_val);
         }
 
         // Call the function using the VM
-        match self.call_function(function_name, nagari_args) {
+        let result = nagari_vm::host::block_on(self.vm.call_function(function_name, nagari_args))
+            .and_then(|result| result);
+        match result {
             Ok(result) => Ok(JSValue::new(nagari_value_to_js(&result))),
             Err(e) => Err(JsValue::from_str(&e)),
         }
@@ -127,55 +170,64 @@ impl NagariWasmVM {
 
     #[wasm_bindgen]
     pub fn load_module(&mut self, module_name: &str, code: &str) -> Result<(), JsValue> {
-        // Store the module code for later compilation and loading
-        self.globals.insert(
-            format!("__module_{}", module_name),
-            NagariValue::String(code.to_string()),
-        );
-
-        // Attempt to compile and load the module immediately
-        match self.compile_and_load_module(module_name, code) {
-            Ok(()) => Ok(()),
-            Err(e) => Err(JsValue::from_str(&e)),
+        // Evaluate the module in its own namespace; scripts reach it with `import`
+        let module = self
+            .evaluate_module(module_name, code)
+            .map_err(|e| JsValue::from_str(&e))?;
+        self.modules.insert(module);
+        Ok(())
+    }
+
+    /// The exports of a loaded module, as an object
+    #[wasm_bindgen]
+    pub fn get_module_exports(&self, module_name: &str) -> Result<JSValue, JsValue> {
+        match self.modules.get(module_name) {
+            Some(module) => Ok(JSValue::new(nagari_value_to_js(&module.namespace_object()))),
+            None => Err(JsValue::from_str(&format!("No module named '{}'", module_name))),
         }
     }
 
     #[wasm_bindgen]
     pub fn set_global(&mut self, name: &str, value: JsValue) -> Result<(), JsValue> {
-        let nagari_value = js_value_to_nagari(&value)?;
-        self.globals.insert(name.to_string(), nagari_value);
+        let nagari_value = js_value_to_nagari(&value, &self.marshal)?;
+        self.vm.define_global(name, nagari_value);
         Ok(())
     }
 
     #[wasm_bindgen]
     pub fn get_global(&self, name: &str) -> Result<JSValue, JsValue> {
-        if let Some(value) = self.globals.get(name) {
+        if let Some(value) = self.vm.get_global(name) {
             Ok(JSValue::new(nagari_value_to_js(value)))
         } else {
             Ok(JSValue::new(JsValue::undefined()))
         }
     }
 
+    /// Make `func` callable from scripts as `name`. Its arguments and result are converted
+    /// like those of `call`, and an exception it throws fails the script.
     #[wasm_bindgen]
     pub fn register_js_function(
         &mut self,
         name: &str,
         func: &js_sys::Function,
     ) -> Result<(), JsValue> {
-        // Create a builtin function that wraps the JS function
-        let builtin_func = nagari_vm::value::BuiltinFunction {
-            name: name.to_string(),
-            arity: func.length() as usize,
-        };
-
-        let func_value = NagariValue::Builtin(builtin_func);
-        self.vm.define_global(name, func_value);
-
-        // Store the JavaScript function reference for later use
-        self.globals.insert(
-            format!("__js_func_{}", name),
-            NagariValue::String(format!("JS_FUNCTION:{}", name)),
-        );
+        let function = JsHostFunction(func.clone());
+        let options = self.marshal;
+        let function_name = name.to_string();
+        let callback = HostCallback::Sync(Arc::new(move |args: Vec<NagariValue>| {
+            let js_args: Array = args.iter().map(nagari_value_to_js).collect();
+            let result = function
+                .0
+                .apply(&JsValue::NULL, &js_args)
+                .map_err(|e| format!("{function_name}() raised: {}", js_error_message(&e)))?;
+            js_value_to_nagari(&result, &options).map_err(|e| {
+                format!(
+                    "{function_name}() returned an unsupported value: {}",
+                    js_error_message(&e)
+                )
+            })
+        }));
+        self.vm.register_host_function(name, callback, None);
 
         Ok(())
     }
@@ -195,7 +247,7 @@ impl NagariWasmVM {
         js_sys::Reflect::set(
             &stats,
             &JsValue::from_str("globals_count"),
-            &JsValue::from_f64(self.globals.len() as f64),
+            &JsValue::from_f64(self.vm.global_names().len() as f64),
         ).unwrap();
 
         js_sys::Reflect::set(
@@ -216,7 +268,7 @@ impl NagariWasmVM {
     #[wasm_bindgen]
     pub fn reset(&mut self) -> Result<(), JsValue> {
         // Clear all globals and reset VM state
-        self.globals.clear();
+        self.modules.clear();
         self.vm.clear_globals();
 
         // Reinitialize the VM with fresh state
@@ -236,7 +288,10 @@ impl NagariWasmVM {
         ))))
     }
 
+    // The glue wasm-bindgen generates calls these, hence the `allow` next to `deprecated`
     #[wasm_bindgen]
+    #[deprecated(since = "0.3.0", note = "use `set_global`")]
+    #[allow(deprecated)]
     pub fn set_global_variable(&mut self, name: &str, value: &str) -> Result<(), JsValue> {
         // Convert string value to NagariValue for now
         let nagari_value = NagariValue::String(value.to_string());
@@ -245,6 +300,8 @@ impl NagariWasmVM {
     }
 
     #[wasm_bindgen]
+    #[deprecated(since = "0.3.0", note = "use `get_global`")]
+    #[allow(deprecated)]
     pub fn get_global_variable(&self, name: &str) -> Result<JSValue, JsValue> {
         match self.vm.get_global(name) {
             Some(value) => Ok(JSValue::new(nagari_value_to_js(value))),
@@ -256,9 +313,11 @@ impl NagariWasmVM {
     }
 
     #[wasm_bindgen]
+    #[deprecated(since = "0.3.0", note = "use `reset`")]
+    #[allow(deprecated)]
     pub fn reset_vm(&mut self) -> Result<(), JsValue> {
         self.vm.clear_globals();
-        self.globals.clear();
+        self.modules.clear();
         Ok(())
     }
 
@@ -266,270 +325,103 @@ impl NagariWasmVM {
     pub fn get_vm_state(&self) -> Result<String, JsValue> {
         Ok(format!(
             "VM initialized with {} global variables",
-            self.globals.len()
+            self.vm.global_names().len()
         ))
     }
 
     // Helper methods for internal use
-    fn compile_and_run_source(&mut self, source: &str) -> Result<NagariValue, String> {
-        // Simple expression evaluator for basic operations
-        // This is a placeholder until full compiler integration
-        let trimmed = source.trim();
-
-        // Handle simple numeric literals
-        if let Ok(num) = trimmed.parse::<i64>() {
-            return Ok(NagariValue::Int(num));
-        }
-
-        if let Ok(num) = trimmed.parse::<f64>() {
-            return Ok(NagariValue::Float(num));
-        }
-
-        // Handle string literals
-        if trimmed.starts_with('"') && trimmed.ends_with('"') {
-            let string_content = &trimmed[1..trimmed.len()-1];
-            return Ok(NagariValue::String(string_content.to_string()));
-        }
-
-        // Handle boolean literals
-        if trimmed == "true" {
-            return Ok(NagariValue::Bool(true));
-        }
-        if trimmed == "false" {
-            return Ok(NagariValue::Bool(false));
-        }
-        if trimmed == "null" || trimmed == "None" {
-            return Ok(NagariValue::None);
-        }
-
-        // Handle simple variable lookups
-        if let Some(value) = self.globals.get(trimmed) {
-            return Ok(value.clone());
-        }
 
-        // Handle simple arithmetic expressions (a + b)
-        if let Some(pos) = trimmed.find(" + ") {
-            let left_str = &trimmed[..pos].trim();
-            let right_str = &trimmed[pos + 3..].trim();
-
-            if let (Ok(left), Ok(right)) = (self.compile_and_run_source(left_str), self.compile_and_run_source(right_str)) {
-                return left.add(&right);
-            }
+    /// Run a script: its imports bind names in the VM's globals, and the rest is compiled and
+    /// run as one program, whose trailing expression gives the result
+    fn run_statements(&mut self, script: &str) -> Result<NagariValue, String> {
+        let (imports, program) = split_imports(script)?;
+        for import in imports {
+            self.import(&import)?;
         }
+        self.execute(&program, None)
+    }
 
-        // Handle simple function calls like print("hello")
-        if trimmed.starts_with("print(") && trimmed.ends_with(")") {
-            let args_str = &trimmed[6..trimmed.len()-1];
-            let arg_value = self.compile_and_run_source(args_str)?;
-
-            // Simple print implementation
-            match &arg_value {
-                NagariValue::String(s) => println!("{}", s),
-                NagariValue::Int(i) => println!("{}", i),
-                NagariValue::Float(f) => println!("{}", f),
-                NagariValue::Bool(b) => println!("{}", b),
-                NagariValue::None => println!("None"),
-                _ => println!("{:?}", arg_value),
+    /// Run one statement of a script: an import, or code to compile and run
+    fn run_statement(&mut self, statement: &str) -> Result<NagariValue, String> {
+        if statement.starts_with("import ") || statement.starts_with("from ") {
+            if let ModuleStatement::Import(import) = parse_statement(statement)? {
+                self.import(&import)?;
             }
-
             return Ok(NagariValue::None);
         }
-
-        // For now, return None for unhandled expressions
-        Ok(NagariValue::None)
+        self.execute(statement, None)
     }
 
-    fn call_function(&mut self, function_name: &str, args: Vec<NagariValue>) -> Result<NagariValue, String> {
-        // Check if it's a built-in function
-        match function_name {
-            "print" => {
-                for (i, arg) in args.iter().enumerate() {
-                    if i > 0 {
-                        print!(" ");
-                    }
-                    match arg {
-                        NagariValue::String(s) => print!("{}", s),
-                        NagariValue::Int(i) => print!("{}", i),
-                        NagariValue::Float(f) => print!("{}", f),
-                        NagariValue::Bool(b) => print!("{}", b),
-                        NagariValue::None => print!("None"),
-                        NagariValue::List(l) => print!("{:?}", l),
-                        NagariValue::Dict(d) => print!("{:?}", d),
-                        _ => print!("{:?}", arg),
-                    }
-                }
-                println!();
-                Ok(NagariValue::None)
-            }
-            "len" => {
-                if args.len() != 1 {
-                    return Err("len() takes exactly one argument".to_string());
-                }
-                match &args[0] {
-                    NagariValue::String(s) => Ok(NagariValue::Int(s.len() as i64)),
-                    NagariValue::List(l) => Ok(NagariValue::Int(l.len() as i64)),
-                    NagariValue::Dict(d) => Ok(NagariValue::Int(d.len() as i64)),
-                    _ => Err(format!("object of type '{}' has no len()", args[0].type_name())),
-                }
-            }
-            "str" => {
-                if args.len() != 1 {
-                    return Err("str() takes exactly one argument".to_string());
-                }
-                let string_repr = match &args[0] {
-                    NagariValue::String(s) => s.clone(),
-                    NagariValue::Int(i) => i.to_string(),
-                    NagariValue::Float(f) => f.to_string(),
-                    NagariValue::Bool(b) => b.to_string(),
-                    NagariValue::None => "None".to_string(),
-                    _ => format!("{:?}", args[0]),
-                };
-                Ok(NagariValue::String(string_repr))
-            }
-            "int" => {
-                if args.len() != 1 {
-                    return Err("int() takes exactly one argument".to_string());
-                }
-                match &args[0] {
-                    NagariValue::Int(i) => Ok(NagariValue::Int(*i)),
-                    NagariValue::Float(f) => Ok(NagariValue::Int(*f as i64)),
-                    NagariValue::String(s) => {
-                        s.parse::<i64>()
-                            .map(NagariValue::Int)
-                            .map_err(|_| format!("invalid literal for int(): '{}'", s))
-                    }
-                    NagariValue::Bool(b) => Ok(NagariValue::Int(if *b { 1 } else { 0 })),
-                    _ => Err(format!("int() argument must be a string or a number, not '{}'", args[0].type_name())),
-                }
-            }
-            "float" => {
-                if args.len() != 1 {
-                    return Err("float() takes exactly one argument".to_string());
-                }
-                match &args[0] {
-                    NagariValue::Float(f) => Ok(NagariValue::Float(*f)),
-                    NagariValue::Int(i) => Ok(NagariValue::Float(*i as f64)),
-                    NagariValue::String(s) => {
-                        s.parse::<f64>()
-                            .map(NagariValue::Float)
-                            .map_err(|_| format!("could not convert string to float: '{}'", s))
-                    }
-                    _ => Err(format!("float() argument must be a string or a number, not '{}'", args[0].type_name())),
-                }
-            }
-            _ => {
-                // Check if it's a user-defined function
-                if let Some(value) = self.vm.get_global(function_name) {
-                    match value {
-                        NagariValue::Function(_) => {
-                            // For now, just return None since we can't execute user functions yet
-                            Ok(NagariValue::None)
-                        }
-                        NagariValue::Builtin(_) => {
-                            // For now, just return None since we can't execute builtins directly
-                            Ok(NagariValue::None)
-                        }
-                        _ => Err(format!("'{}' object is not callable", value.type_name())),
-                    }
-                } else {
-                    Err(format!("name '{}' is not defined", function_name))
-                }
-            }
+    /// Bind the names `import` brings in as globals
+    fn import(&mut self, import: &Import) -> Result<(), String> {
+        for (name, value) in self.modules.import_into_script(import)? {
+            self.vm.define_global(&name, value);
         }
-    }
-
-    fn compile_and_load_module(&mut self, module_name: &str, code: &str) -> Result<(), String> {
-        // Simple module loading - for now just execute the code and store any definitions
-        let result = self.compile_and_run_source(code)?;
-
-        // Store the module result
-        self.globals.insert(
-            format!("__module_result_{}", module_name),
-            result,
-        );
-
         Ok(())
     }
-}
-
-// Helper functions for converting between JS and Nagari values
-fn js_value_to_nagari(value: &JsValue) -> Result<NagariValue, JsValue> {
-    if value.is_null() || value.is_undefined() {
-        return Ok(NagariValue::None);
-    }
 
-    if let Some(b) = value.as_bool() {
-        return Ok(NagariValue::Bool(b));
-    }
-
-    if let Some(n) = value.as_f64() {
-        if n.fract() == 0.0 && n >= i64::MIN as f64 && n <= i64::MAX as f64 {
-            return Ok(NagariValue::Int(n as i64));
-        } else {
-            return Ok(NagariValue::Float(n));
-        }
-    }
-
-    if let Some(s) = value.as_string() {
-        return Ok(NagariValue::String(s));
-    }
-
-    if js_sys::Array::is_array(value) {
-        let array = js_sys::Array::from(value);
-        let mut nagari_array = Vec::new();
-
-        for i in 0..array.length() {
-            let element = array.get(i);
-            let nagari_element = js_value_to_nagari(&element)?;
-            nagari_array.push(nagari_element);
+    /// Evaluate a module's statements in a namespace of its own
+    fn evaluate_module(&mut self, name: &str, code: &str) -> Result<Module, String> {
+        let mut scope = ModuleScope::new();
+        let statements = parse_statements(code).map_err(|e| format!("module '{name}', {e}"))?;
+        for statement in statements {
+            match statement {
+                ModuleStatement::Import(import) => scope.import(self.modules.resolve(&import)?),
+                ModuleStatement::Binding {
+                    name,
+                    expr,
+                    exported,
+                } => {
+                    let value = self.execute(expr, Some(&scope.namespace))?;
+                    scope.bind(name, value, exported);
+                }
+                ModuleStatement::Expression(expr) => {
+                    self.execute(expr, Some(&scope.namespace))?;
+                }
+            }
         }
-
-        return Ok(NagariValue::List(nagari_array));
+        Ok(scope.finish(name, code))
     }
 
-    if value.is_object() {
-        let object = js_sys::Object::from(value.clone());
-        let mut nagari_object = HashMap::new();
-
-        let keys = js_sys::Object::keys(&object);
-        for i in 0..keys.length() {
-            let key = keys.get(i).as_string().unwrap_or_default();
-            let js_val = js_sys::Reflect::get(&object, &keys.get(i)).unwrap();
-            let nagari_val = js_value_to_nagari(&js_val)?;
-            nagari_object.insert(key, nagari_val);
+    /// Compile `source` to bytecode and run it, with the names of `scope` visible besides the
+    /// VM's globals. The value of a trailing expression statement is the result.
+    fn execute(
+        &mut self,
+        source: &str,
+        scope: Option<&HashMap<String, NagariValue>>,
+    ) -> Result<NagariValue, String> {
+        self.vm.load_source(source, nagari_compiler::VERSION, compile)?;
+        match scope {
+            Some(scope) => nagari_vm::host::block_on(self.vm.evaluate_in(scope.clone()))?,
+            None => nagari_vm::host::block_on(self.vm.evaluate())?,
         }
-
-        return Ok(NagariValue::Dict(nagari_object));
     }
+}
 
-    Err(JsValue::from_str("Unsupported JavaScript value type"))
+/// Compile Nagari source to VM bytecode
+fn compile(source: &str) -> Result<Vec<u8>, String> {
+    nagari_compiler::Compiler::new()
+        .compile_to_bytecode(source)
+        .map_err(|e| e.to_string())
 }
 
-fn nagari_value_to_js(value: &NagariValue) -> JsValue {
-    match value {
-        NagariValue::None => JsValue::null(),
-        NagariValue::Bool(b) => JsValue::from_bool(*b),
-        NagariValue::Int(i) => JsValue::from_f64(*i as f64),
-        NagariValue::Float(f) => JsValue::from_f64(*f),
-        NagariValue::String(s) => JsValue::from_str(s),
-        NagariValue::List(arr) => {
-            let js_array = js_sys::Array::new();
-            for (i, item) in arr.iter().enumerate() {
-                js_array.set(i as u32, nagari_value_to_js(item));
+/// Take the import statements out of `script`, leaving blank lines in their place so the rest
+/// keeps its line numbers
+fn split_imports(script: &str) -> Result<(Vec<Import>, String), String> {
+    let mut imports = Vec::new();
+    let mut program = String::with_capacity(script.len());
+    for (index, line) in script.lines().enumerate() {
+        if line.starts_with("import ") || line.starts_with("from ") {
+            let statement = parse_statement(line).map_err(|e| format!("line {}: {e}", index + 1))?;
+            if let ModuleStatement::Import(import) = statement {
+                imports.push(import);
             }
-            js_array.into()
-        }
-        NagariValue::Dict(obj) => {
-            let js_object = js_sys::Object::new();
-            for (key, val) in obj {
-                let key_js = JsValue::from_str(key);
-                let val_js = nagari_value_to_js(val);
-                js_sys::Reflect::set(&js_object, &key_js, &val_js).unwrap();
-            }
-            js_object.into()
+        } else {
+            program.push_str(line);
         }
-        _ => JsValue::undefined(), // For Function, Builtin, etc.
+        program.push('\n');
     }
+    Ok((imports, program))
 }
 
 // Utility functions for browser integration
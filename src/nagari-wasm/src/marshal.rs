@@ -0,0 +1,215 @@
+//! Conversions between JavaScript values and Nagari values.
+//!
+//! Besides primitives, arrays and plain objects, the standard built-in objects map to VM
+//! values of their own: `Date` to a date, `Map` to a map with keys of any type, `Set` to a
+//! set, and `Uint8Array`/`ArrayBuffer` to bytes. Other typed arrays become lists of numbers.
+//! Each conversion back to JavaScript produces the same kind of object again.
+
+use js_sys::{Array, Object, Reflect};
+use nagari_vm::Value as NagariValue;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// How standard JavaScript objects are converted when they enter the VM
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarshalOptions {
+    /// Convert `Map`s to dicts keyed by the string form of their keys
+    pub maps_as_dicts: bool,
+    /// Convert `Set`s to lists
+    pub sets_as_lists: bool,
+    /// Convert `Date`s to milliseconds since the epoch
+    pub dates_as_numbers: bool,
+}
+
+#[wasm_bindgen]
+impl MarshalOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> MarshalOptions {
+        MarshalOptions::default()
+    }
+}
+
+/// Copy the elements of a typed array of one of the given types into a list
+macro_rules! typed_array_to_list {
+    ($value:expr, $variant:ident as $ty:ty, [$($array:ty),+]) => {
+        $(
+            if let Some(array) = $value.dyn_ref::<$array>() {
+                return Ok(NagariValue::List(
+                    array
+                        .to_vec()
+                        .into_iter()
+                        .map(|n| NagariValue::$variant(n as $ty))
+                        .collect(),
+                ));
+            }
+        )+
+    };
+}
+
+pub fn js_value_to_nagari(
+    value: &JsValue,
+    options: &MarshalOptions,
+) -> Result<NagariValue, JsValue> {
+    if value.is_null() || value.is_undefined() {
+        return Ok(NagariValue::None);
+    }
+
+    if let Some(b) = value.as_bool() {
+        return Ok(NagariValue::Bool(b));
+    }
+
+    if let Some(n) = value.as_f64() {
+        if n.fract() == 0.0 && n >= i64::MIN as f64 && n <= i64::MAX as f64 {
+            return Ok(NagariValue::Int(n as i64));
+        } else {
+            return Ok(NagariValue::Float(n));
+        }
+    }
+
+    if let Some(s) = value.as_string() {
+        return Ok(NagariValue::String(s));
+    }
+
+    if Array::is_array(value) {
+        let array = Array::from(value);
+        let mut nagari_array = Vec::new();
+
+        for i in 0..array.length() {
+            let element = array.get(i);
+            let nagari_element = js_value_to_nagari(&element, options)?;
+            nagari_array.push(nagari_element);
+        }
+
+        return Ok(NagariValue::List(nagari_array));
+    }
+
+    if let Some(date) = value.dyn_ref::<js_sys::Date>() {
+        let millis = date.get_time();
+        return Ok(if options.dates_as_numbers {
+            NagariValue::Float(millis)
+        } else {
+            NagariValue::Date(millis)
+        });
+    }
+
+    if value.is_instance_of::<js_sys::Map>() {
+        // `Array.from(map)` lists the entries as `[key, value]` pairs
+        let mut entries = Vec::new();
+        for entry in Array::from(value).iter() {
+            let entry = Array::from(&entry);
+            let key = js_value_to_nagari(&entry.get(0), options)?;
+            let val = js_value_to_nagari(&entry.get(1), options)?;
+            entries.push((key, val));
+        }
+
+        return Ok(if options.maps_as_dicts {
+            NagariValue::Dict(
+                entries
+                    .into_iter()
+                    .map(|(key, val)| (key.to_string(), val))
+                    .collect(),
+            )
+        } else {
+            NagariValue::Map(entries)
+        });
+    }
+
+    if value.is_instance_of::<js_sys::Set>() {
+        let items = Array::from(value)
+            .iter()
+            .map(|item| js_value_to_nagari(&item, options))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        return Ok(if options.sets_as_lists {
+            NagariValue::List(items)
+        } else {
+            NagariValue::Set(items)
+        });
+    }
+
+    if value.is_instance_of::<js_sys::Uint8Array>()
+        || value.is_instance_of::<js_sys::Uint8ClampedArray>()
+        || value.is_instance_of::<js_sys::ArrayBuffer>()
+    {
+        return Ok(NagariValue::Bytes(js_sys::Uint8Array::new(value).to_vec()));
+    }
+
+    typed_array_to_list!(
+        value,
+        Int as i64,
+        [
+            js_sys::Int8Array,
+            js_sys::Int16Array,
+            js_sys::Uint16Array,
+            js_sys::Int32Array,
+            js_sys::Uint32Array
+        ]
+    );
+    typed_array_to_list!(
+        value,
+        Float as f64,
+        [js_sys::Float32Array, js_sys::Float64Array]
+    );
+
+    if value.is_object() {
+        let object = Object::from(value.clone());
+        let mut nagari_object = HashMap::new();
+
+        let keys = Object::keys(&object);
+        for i in 0..keys.length() {
+            let key = keys.get(i).as_string().unwrap_or_default();
+            let js_val = Reflect::get(&object, &keys.get(i))?;
+            let nagari_val = js_value_to_nagari(&js_val, options)?;
+            nagari_object.insert(key, nagari_val);
+        }
+
+        return Ok(NagariValue::Dict(nagari_object));
+    }
+
+    Err(JsValue::from_str("Unsupported JavaScript value type"))
+}
+
+pub fn nagari_value_to_js(value: &NagariValue) -> JsValue {
+    match value {
+        NagariValue::None => JsValue::null(),
+        NagariValue::Bool(b) => JsValue::from_bool(*b),
+        NagariValue::Int(i) => JsValue::from_f64(*i as f64),
+        NagariValue::Float(f) => JsValue::from_f64(*f),
+        NagariValue::String(s) => JsValue::from_str(s),
+        NagariValue::List(arr) => {
+            let js_array = Array::new();
+            for (i, item) in arr.iter().enumerate() {
+                js_array.set(i as u32, nagari_value_to_js(item));
+            }
+            js_array.into()
+        }
+        NagariValue::Dict(obj) => {
+            let js_object = Object::new();
+            for (key, val) in obj {
+                let key_js = JsValue::from_str(key);
+                let val_js = nagari_value_to_js(val);
+                Reflect::set(&js_object, &key_js, &val_js).unwrap();
+            }
+            js_object.into()
+        }
+        NagariValue::Date(millis) => js_sys::Date::new(&JsValue::from_f64(*millis)).into(),
+        NagariValue::Map(entries) => {
+            let js_map = js_sys::Map::new();
+            for (key, val) in entries {
+                js_map.set(&nagari_value_to_js(key), &nagari_value_to_js(val));
+            }
+            js_map.into()
+        }
+        NagariValue::Set(items) | NagariValue::FrozenSet(items) => {
+            let js_set = js_sys::Set::new(&JsValue::undefined());
+            for item in items {
+                js_set.add(&nagari_value_to_js(item));
+            }
+            js_set.into()
+        }
+        NagariValue::Bytes(bytes) => js_sys::Uint8Array::from(bytes.as_slice()).into(),
+        _ => JsValue::undefined(), // For Function, Builtin, etc.
+    }
+}
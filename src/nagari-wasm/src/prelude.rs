@@ -0,0 +1,6 @@
+//! The supported surface of the WebAssembly runtime, for Rust crates building on it. Items
+//! reachable from here follow semantic versioning: they are only removed or changed
+//! incompatibly in a major release, after a release in which they are `#[deprecated]`.
+//! Everything else may change between minor releases.
+
+pub use crate::{JSValue, MarshalOptions, NagariStore, NagariWasmVM};
@@ -0,0 +1,146 @@
+//! Read-eval loop for browser REPLs and playgrounds.
+//!
+//! Like the CLI REPL, each evaluation runs against the globals left by the previous ones,
+//! and input that is plainly unfinished (an open bracket or string, a trailing `:`) is not
+//! run at all, so the page can ask for another line. The result is a plain object:
+//!
+//! ```text
+//! {
+//!     value,            // value of the last expression, or null
+//!     output: string,   // what print() wrote
+//!     error: null | { message: string, range: { start: { line, character }, end: ... } },
+//!     incomplete: bool,
+//!     execution_time,   // milliseconds
+//! }
+//! ```
+//!
+//! Lines and characters in error ranges count from zero, as in the editor protocols.
+
+use crate::marshal::nagari_value_to_js;
+use crate::NagariWasmVM;
+use js_sys::{Object, Reflect};
+use nagari_vm::Value as NagariValue;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+impl NagariWasmVM {
+    /// Evaluate `code` in the scope built up by earlier calls
+    pub fn eval_incremental(&mut self, code: &str) -> JsValue {
+        let result = Object::new();
+        if is_incomplete(code) {
+            set(&result, "value", JsValue::null());
+            set(&result, "output", JsValue::from_str(""));
+            set(&result, "error", JsValue::null());
+            set(&result, "incomplete", JsValue::TRUE);
+            set(&result, "execution_time", JsValue::from_f64(0.0));
+            return result.into();
+        }
+
+        let started = js_sys::Date::now();
+        self.vm.capture_output();
+        let outcome = self.eval_lines(code);
+        let output = self.vm.take_output().unwrap_or_default();
+
+        let (value, error) = match outcome {
+            Ok(value) => (nagari_value_to_js(&value), JsValue::null()),
+            Err((line, message)) => (JsValue::null(), error_object(code, line, &message)),
+        };
+        set(&result, "value", value);
+        set(&result, "output", JsValue::from_str(&output));
+        set(&result, "error", error);
+        set(&result, "incomplete", JsValue::FALSE);
+        set(
+            &result,
+            "execution_time",
+            JsValue::from_f64(js_sys::Date::now() - started),
+        );
+        result.into()
+    }
+}
+
+impl NagariWasmVM {
+    /// Run `code` a statement at a time, stopping at the first error and returning the line
+    /// its statement starts on. Indented lines belong to the statement above, as a `def` body.
+    fn eval_lines(&mut self, code: &str) -> Result<NagariValue, (usize, String)> {
+        let lines: Vec<&str> = code.lines().collect();
+        let mut value = NagariValue::None;
+        let mut index = 0;
+        while index < lines.len() {
+            let start = index;
+            let line = lines[start].trim();
+            index += 1;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut statement = line.to_string();
+            while let Some(next) = lines
+                .get(index)
+                .filter(|next| next.starts_with([' ', '\t']) || next.trim().is_empty())
+            {
+                statement.push('\n');
+                statement.push_str(next);
+                index += 1;
+            }
+            value = self
+                .run_statement(&statement)
+                .map_err(|message| (start, message))?;
+        }
+        Ok(value)
+    }
+}
+
+/// Whether `input` needs more lines before it can be evaluated
+fn is_incomplete(input: &str) -> bool {
+    let input = input.trim();
+    if input.ends_with([':', '(', '[', '{']) {
+        return true;
+    }
+
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for ch in input.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if ch == '\\' => escaped = true,
+            Some(open) if ch == open => quote = None,
+            Some(_) => {}
+            None => match ch {
+                '"' | '\'' => quote = Some(ch),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    quote.is_some() || depth > 0
+}
+
+/// `{ message, range }` covering the text of line `line` of `code`
+fn error_object(code: &str, line: usize, message: &str) -> JsValue {
+    let text = code.lines().nth(line).unwrap_or_default();
+    // JavaScript counts characters in UTF-16 code units
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().encode_utf16().count();
+
+    let range = Object::new();
+    set(&range, "start", position(line, start));
+    set(&range, "end", position(line, end));
+
+    let error = Object::new();
+    set(&error, "message", JsValue::from_str(message));
+    set(&error, "range", range.into());
+    error.into()
+}
+
+fn position(line: usize, character: usize) -> JsValue {
+    let position = Object::new();
+    set(&position, "line", JsValue::from_f64(line as f64));
+    set(&position, "character", JsValue::from_f64(character as f64));
+    position.into()
+}
+
+fn set(object: &Object, key: &str, value: JsValue) {
+    Reflect::set(object, &JsValue::from_str(key), &value).unwrap();
+}
@@ -0,0 +1,278 @@
+//! Reactive state shared by scripts and the page, for UI frameworks to bind to.
+//!
+//! A [`NagariStore`] holds named values. Scripts read and write them through the object
+//! `attach_store` makes a global (`store.get("count")`, `store.set("count", 1)`,
+//! `store.delete("count")`), and the page does the same with the store's own methods.
+//! Subscribers register for a key and are told which keys changed:
+//!
+//! ```text
+//! const store = new NagariStore();
+//! vm.attach_store("store", store);
+//! const id = store.subscribe("user", (keys) => render(store.get("user.name")));
+//! vm.run('store.set("user.name", "Ada")');   // render runs once, after the script
+//! store.unsubscribe(id);
+//! ```
+//!
+//! Keys are dotted paths: a subscription to `user` hears about changes to `user.name`, one to
+//! `user.name` about `user` being replaced, and one to `""` about every key. Writes that leave
+//! a value as it was are not changes. Changes are batched: subscribers are called on the next
+//! microtask, once each, with the keys they care about in the order they first changed, or at
+//! once by `flush()`. A subscriber may read and write the store; its writes form the next batch.
+
+use crate::marshal::{js_value_to_nagari, nagari_value_to_js};
+use crate::{JsHostFunction, MarshalOptions, NagariWasmVM};
+use js_sys::Array;
+use nagari_vm::{HostObject, NativeObject, Value as NagariValue};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = queueMicrotask)]
+    fn queue_microtask(callback: &JsValue);
+}
+
+struct Subscriber {
+    id: u32,
+    key: String,
+    callback: JsHostFunction,
+}
+
+#[derive(Default)]
+struct StoreState {
+    values: HashMap<String, NagariValue>,
+    subscribers: Vec<Subscriber>,
+    next_id: u32,
+    /// Keys changed since the last flush, in the order they first changed
+    changed: Vec<String>,
+    flush_queued: bool,
+}
+
+/// Whether a change to `changed` concerns a subscriber to `key`
+fn related(key: &str, changed: &str) -> bool {
+    fn within(inner: &str, outer: &str) -> bool {
+        inner
+            .strip_prefix(outer)
+            .is_some_and(|rest| rest.starts_with('.'))
+    }
+    key.is_empty() || key == changed || within(changed, key) || within(key, changed)
+}
+
+/// Named state that scripts change and pages subscribe to
+#[wasm_bindgen]
+#[derive(Clone, Default)]
+pub struct NagariStore {
+    state: Arc<Mutex<StoreState>>,
+}
+
+impl NagariStore {
+    fn lock(&self) -> MutexGuard<'_, StoreState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn set_value(&self, key: &str, value: NagariValue) {
+        let mut state = self.lock();
+        if state.values.get(key) == Some(&value) {
+            return;
+        }
+        state.values.insert(key.to_string(), value);
+        self.changed(state, key);
+    }
+
+    fn delete_value(&self, key: &str) -> bool {
+        let mut state = self.lock();
+        if state.values.remove(key).is_none() {
+            return false;
+        }
+        self.changed(state, key);
+        true
+    }
+
+    /// Record that `key` changed, queueing a flush if none is
+    fn changed(&self, mut state: MutexGuard<'_, StoreState>, key: &str) {
+        if !state.changed.iter().any(|changed| changed == key) {
+            state.changed.push(key.to_string());
+        }
+        if !state.flush_queued {
+            state.flush_queued = true;
+            drop(state);
+            let store = self.clone();
+            queue_microtask(&Closure::once_into_js(move || store.flush()));
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl NagariStore {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> NagariStore {
+        NagariStore::default()
+    }
+
+    /// The value of `key`, or `undefined` if it has none
+    pub fn get(&self, key: &str) -> JsValue {
+        match self.lock().values.get(key) {
+            Some(value) => nagari_value_to_js(value),
+            None => JsValue::undefined(),
+        }
+    }
+
+    /// Set `key` to `value`, converted like the arguments of `NagariWasmVM::call`
+    pub fn set(&self, key: &str, value: JsValue) -> Result<(), JsValue> {
+        let value = js_value_to_nagari(&value, &MarshalOptions::default())?;
+        self.set_value(key, value);
+        Ok(())
+    }
+
+    /// Remove `key`, returning whether it had a value
+    pub fn delete(&self, key: &str) -> bool {
+        self.delete_value(key)
+    }
+
+    /// The keys that have values
+    pub fn keys(&self) -> Array {
+        let mut keys: Vec<_> = self.lock().values.keys().cloned().collect();
+        keys.sort();
+        keys.into_iter().map(JsValue::from).collect()
+    }
+
+    /// Call `callback` with an array of the changed keys after changes concerning `key`,
+    /// returning an id for `unsubscribe`
+    pub fn subscribe(&self, key: &str, callback: &js_sys::Function) -> u32 {
+        let mut state = self.lock();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.subscribers.push(Subscriber {
+            id,
+            key: key.to_string(),
+            callback: JsHostFunction(callback.clone()),
+        });
+        id
+    }
+
+    /// Remove a subscriber, returning whether there was one with that id
+    pub fn unsubscribe(&self, id: u32) -> bool {
+        let mut state = self.lock();
+        let before = state.subscribers.len();
+        state.subscribers.retain(|subscriber| subscriber.id != id);
+        state.subscribers.len() != before
+    }
+
+    /// Tell subscribers about the changes so far now, rather than on the next microtask. An
+    /// exception a subscriber throws is rethrown after the others have been called.
+    pub fn flush(&self) -> Result<(), JsValue> {
+        let (changed, subscribers) = {
+            let mut state = self.lock();
+            state.flush_queued = false;
+            let changed = std::mem::take(&mut state.changed);
+            let subscribers: Vec<_> = state
+                .subscribers
+                .iter()
+                .filter_map(|subscriber| {
+                    let keys: Array = changed
+                        .iter()
+                        .filter(|changed| related(&subscriber.key, changed))
+                        .map(|changed| JsValue::from_str(changed))
+                        .collect();
+                    (keys.length() > 0).then(|| (subscriber.callback.0.clone(), keys))
+                })
+                .collect();
+            (changed, subscribers)
+        };
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let mut error = None;
+        for (callback, keys) in subscribers {
+            if let Err(e) = callback.call1(&JsValue::NULL, &keys) {
+                error.get_or_insert(e);
+            }
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+/// The store as scripts see it
+struct StoreObject(NagariStore);
+
+impl StoreObject {
+    fn key(method: &str, args: &[NagariValue], count: usize) -> Result<String, String> {
+        if args.len() != count {
+            return Err(format!(
+                "store.{method}() takes exactly {count} argument{} ({} given)",
+                if count == 1 { "" } else { "s" },
+                args.len()
+            ));
+        }
+        match &args[0] {
+            NagariValue::String(key) => Ok(key.clone()),
+            other => Err(format!(
+                "store.{method}() key must be a string, not '{}'",
+                other.type_name()
+            )),
+        }
+    }
+}
+
+impl HostObject for StoreObject {
+    fn type_name(&self) -> &str {
+        "Store"
+    }
+
+    fn has_method(&self, name: &str) -> bool {
+        matches!(name, "get" | "set" | "delete" | "keys")
+    }
+
+    fn call_method(&mut self, name: &str, args: Vec<NagariValue>) -> Result<NagariValue, String> {
+        match name {
+            "get" => {
+                let key = Self::key(name, &args, 1)?;
+                Ok(self
+                    .0
+                    .lock()
+                    .values
+                    .get(&key)
+                    .cloned()
+                    .unwrap_or(NagariValue::None))
+            }
+            "set" => {
+                let key = Self::key(name, &args, 2)?;
+                self.0.set_value(&key, args[1].clone());
+                Ok(NagariValue::None)
+            }
+            "delete" => {
+                let key = Self::key(name, &args, 1)?;
+                Ok(NagariValue::Bool(self.0.delete_value(&key)))
+            }
+            "keys" => {
+                let mut keys: Vec<_> = self.0.lock().values.keys().cloned().collect();
+                keys.sort();
+                Ok(NagariValue::List(
+                    keys.into_iter().map(NagariValue::String).collect(),
+                ))
+            }
+            _ => Err(format!("'Store' object has no attribute '{name}'")),
+        }
+    }
+
+    fn get_property(&self, _name: &str) -> Option<NagariValue> {
+        None
+    }
+
+    fn set_property(&mut self, name: &str, _value: NagariValue) -> Result<(), String> {
+        Err(format!("cannot set attribute '{name}' of 'Store' object"))
+    }
+}
+
+#[wasm_bindgen]
+impl NagariWasmVM {
+    /// Make `store` the global `name` of scripts. `reset` removes it, like other globals.
+    pub fn attach_store(&mut self, name: &str, store: &NagariStore) {
+        let object = NativeObject::new(StoreObject(store.clone()));
+        self.vm.define_global(name, NagariValue::Native(object));
+    }
+}
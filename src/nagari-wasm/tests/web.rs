@@ -0,0 +1,35 @@
+//! Tests run in a JavaScript engine: `wasm-pack test --node src/nagari-wasm`
+
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, Function};
+use nagari_wasm::NagariWasmVM;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn scripts_call_registered_js_functions() {
+    let mut vm = NagariWasmVM::new().unwrap();
+    let add = Function::new_with_args("a, b", "return a + b");
+    vm.register_js_function("add", &add).unwrap();
+
+    assert_eq!(vm.eval("add(2, 3)").unwrap().as_number(), Some(5.0));
+
+    vm.run("def twice(x):\n    return add(x, x)\n").unwrap();
+    let args = Array::of1(&JsValue::from_f64(21.0));
+    assert_eq!(vm.call("twice", &args).unwrap().as_number(), Some(42.0));
+}
+
+#[wasm_bindgen_test]
+fn exceptions_thrown_by_js_functions_fail_the_script() {
+    let mut vm = NagariWasmVM::new().unwrap();
+    let fail = Function::new_no_args("throw new Error('no network')");
+    vm.register_js_function("fetch_data", &fail).unwrap();
+
+    let error = vm
+        .eval("fetch_data()")
+        .err()
+        .and_then(|e| e.as_string())
+        .unwrap();
+    assert!(error.contains("fetch_data() raised: no network"), "{error}");
+}
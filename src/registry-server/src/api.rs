@@ -3,10 +3,12 @@ use axum::{
     Router,
 };
 
+use crate::AppState;
+
 pub mod handlers;
 
 /// Create the API router with all endpoints
-pub fn create_router() -> Router {
+pub fn create_router() -> Router<AppState> {
     Router::new()
         .route("/health", get(health_check))
         .route("/admin/cache", delete(clear_cache))
@@ -36,7 +38,7 @@ async fn trigger_maintenance() -> &'static str {
 }
 
 /// API v1 routes
-fn api_v1_routes() -> Router {
+fn api_v1_routes() -> Router<AppState> {
     Router::new()
         .nest("/packages", handlers::packages::routes())
         .nest("/auth", handlers::auth::routes())
@@ -3,8 +3,10 @@ use axum::{
     Router,
 };
 
+use crate::AppState;
+
 /// Authentication routes
-pub fn routes() -> Router {
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/login", post(login))
         .route("/register", post(register))
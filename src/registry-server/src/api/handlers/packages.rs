@@ -1,10 +1,16 @@
 use axum::{
+    extract::{Multipart, State},
     routing::{get, post, put, delete},
-    Router,
+    Json, Router,
 };
+use serde_json::{json, Value};
+
+use crate::error::{AppError, Result};
+use crate::validation;
+use crate::AppState;
 
 /// Package management routes
-pub fn routes() -> Router {
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/", get(list_packages).post(publish_package))
         .route("/:name", get(get_package))
@@ -20,9 +26,42 @@ pub async fn list_packages() -> &'static str {
     "List packages"
 }
 
-/// Publish a new package
-pub async fn publish_package() -> &'static str {
-    "Publish package"
+/// Publish a new package, once its tarball passes validation
+pub async fn publish_package(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<Value>> {
+    let mut tarball = None;
+    while let Some(field) = multipart.next_field().await.map_err(|e| {
+        AppError::BadRequest(format!("Invalid multipart data: {}", e))
+    })? {
+        if field.name() == Some("tarball") {
+            tarball = Some(field.bytes().await.map_err(|e| {
+                AppError::BadRequest(format!("Failed to read tarball: {}", e))
+            })?);
+        }
+    }
+    let tarball = tarball.ok_or_else(|| {
+        AppError::BadRequest("Missing tarball".to_string())
+    })?;
+
+    // Compiling the sources takes a while, so it runs off the async workers
+    let config = state.config.registry.clone();
+    let upload = tarball.clone();
+    let manifest = tokio::task::spawn_blocking(move || validation::validate_package(&upload, &config))
+        .await
+        .map_err(|e| AppError::Internal(format!("Package validation failed: {}", e)))?
+        .map_err(AppError::PackageRejected)?;
+
+    state.storage
+        .store_package(&manifest.name, &manifest.version, &tarball)
+        .await
+        .map_err(|e| AppError::Storage(e.to_string()))?;
+
+    Ok(Json(json!({
+        "name": manifest.name,
+        "version": manifest.version,
+    })))
 }
 
 /// Get package information
@@ -3,8 +3,10 @@ use axum::{
     Router,
 };
 
+use crate::AppState;
+
 /// User management routes
-pub fn routes() -> Router {
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/", get(list_users))
         .route("/:username", get(get_user).put(update_user).delete(delete_user))
@@ -5,6 +5,8 @@ use axum::{
 };
 use serde_json::json;
 
+use crate::validation::Diagnostic;
+
 pub type Result<T> = std::result::Result<T, AppError>;
 
 #[derive(Debug, thiserror::Error)]
@@ -38,10 +40,19 @@ pub enum AppError {
 
     #[error("Internal server error: {0}")]
     Internal(String),
+
+    #[error("Package failed validation")]
+    PackageRejected(Vec<Diagnostic>),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        // Authors see every problem that kept their package out
+        let diagnostics = match &self {
+            AppError::PackageRejected(diagnostics) => Some(json!(diagnostics)),
+            _ => None,
+        };
+
         let (status, error_message) = match self {
             AppError::Database(err) => {
                 tracing::error!("Database error: {}", err);
@@ -62,12 +73,19 @@ impl IntoResponse for AppError {
                 tracing::error!("Internal error: {}", msg);
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
             }
+            AppError::PackageRejected(_) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, "Package failed validation")
+            }
         };
 
-        let body = Json(json!({
+        let mut body = json!({
             "error": error_message,
             "status": status.as_u16()
-        }));
+        });
+        if let Some(diagnostics) = diagnostics {
+            body["diagnostics"] = diagnostics;
+        }
+        let body = Json(body);
 
         (status, body).into_response()
     }
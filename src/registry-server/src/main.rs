@@ -2,7 +2,7 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 
-use axum::{routing::{get, post, put, delete}, Router};
+use axum::{extract::DefaultBodyLimit, routing::{get, post, put, delete}, Router};
 use clap::Parser;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
@@ -14,9 +14,11 @@ mod api;
 mod auth;
 mod config;
 mod db;
+mod error;
 mod services;
 mod storage;
 mod middleware;
+mod validation;
 
 use config::Config;
 use db::Database;
@@ -114,10 +116,17 @@ async fn main() -> anyhow::Result<()> {
 }
 
 pub fn create_app(state: AppState) -> Router {
+    // Uploads may be as large as packages are allowed to be
+    let max_package_size = state.config.registry.max_package_size as usize;
+
     Router::new()
         // Package endpoints
         .route("/packages", get(handlers::packages::list_packages))
-        .route("/packages", post(handlers::packages::publish_package))
+        .route(
+            "/packages",
+            post(handlers::packages::publish_package)
+                .layer(DefaultBodyLimit::max(max_package_size)),
+        )
         .route("/packages/:name", get(handlers::packages::get_package))
         .route("/packages/:name", delete(handlers::packages::delete_package))
         .route("/packages/:name/:version", get(handlers::packages::get_package_version))
@@ -0,0 +1,262 @@
+//! Checks a package has to pass before the registry accepts it.
+//!
+//! The tarball is unpacked into a fresh temporary directory, refusing links, entries that
+//! would land outside of it and more data than `max_package_size`. Its `nagari.json` must give
+//! a valid name, version and dependencies, and every `.nag` source must compile as
+//! `nag check` would have it, with `embed()` kept inside the package. All the problems found
+//! are reported together, so authors can fix them before publishing again.
+
+use crate::config::RegistryConfig;
+use flate2::read::GzDecoder;
+use nagari_compiler::{dual, CompilerConfigBuilder, RuntimePackage};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Longest package name, as on npm
+const MAX_NAME_LENGTH: usize = 214;
+
+/// A problem that keeps a package from being published
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    /// File of the package the problem is in, relative to its root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Compiler error code, explained by `nag explain`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(file: Option<&str>, message: String) -> Self {
+        Self {
+            file: file.map(str::to_string),
+            code: None,
+            message,
+        }
+    }
+}
+
+/// The parts of `nagari.json` the registry checks
+#[derive(Debug, Clone, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    pub main: Option<String>,
+    #[serde(default)]
+    pub dependencies: HashMap<String, serde_json::Value>,
+    pub nagari: Option<ManifestNagari>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestNagari {
+    pub source_dir: Option<String>,
+    pub runtime: Option<RuntimePackage>,
+}
+
+/// Unpack `tarball` and check it, returning its manifest if it can be published
+pub fn validate_package(
+    tarball: &[u8],
+    config: &RegistryConfig,
+) -> Result<Manifest, Vec<Diagnostic>> {
+    let sandbox = tempfile::tempdir().map_err(|e| {
+        vec![Diagnostic::new(
+            None,
+            format!("Cannot create a directory to unpack into: {e}"),
+        )]
+    })?;
+    unpack(tarball, sandbox.path(), config.max_package_size)
+        .map_err(|message| vec![Diagnostic::new(None, message)])?;
+
+    // Tarballs made by `nag package pack` keep everything under `package/`
+    let root = match sandbox.path().join("package") {
+        root if root.is_dir() => root,
+        _ => sandbox.path().to_path_buf(),
+    };
+    let manifest = read_manifest(&root).map_err(|diagnostic| vec![diagnostic])?;
+
+    let mut diagnostics = check_manifest(&manifest, &root);
+    diagnostics.extend(check_sources(&manifest, &root));
+    if diagnostics.is_empty() {
+        Ok(manifest)
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Unpack the gzipped `tarball` into `dir`
+fn unpack(tarball: &[u8], dir: &Path, max_size: u64) -> Result<(), String> {
+    let mut archive = tar::Archive::new(GzDecoder::new(tarball));
+    let entries = archive
+        .entries()
+        .map_err(|e| format!("Package is not a gzipped tarball: {e}"))?;
+    let mut unpacked = 0u64;
+    for entry in entries {
+        let mut entry = entry.map_err(|e| format!("Package tarball is corrupt: {e}"))?;
+        let path = entry
+            .path()
+            .map_err(|e| format!("Package tarball is corrupt: {e}"))?
+            .into_owned();
+        let kind = entry.header().entry_type();
+        if kind.is_symlink() || kind.is_hard_link() {
+            return Err(format!("'{}' is a link", path.display()));
+        }
+        if !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!("'{}' is outside of the package", path.display()));
+        }
+        if !kind.is_file() && !kind.is_dir() {
+            continue;
+        }
+
+        unpacked += entry.size();
+        if unpacked > max_size {
+            return Err(format!(
+                "Package unpacks to more than the limit of {max_size} bytes"
+            ));
+        }
+        entry
+            .unpack_in(dir)
+            .map_err(|e| format!("Cannot unpack '{}': {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn read_manifest(root: &Path) -> Result<Manifest, Diagnostic> {
+    let file = Some("nagari.json");
+    let content = std::fs::read_to_string(root.join("nagari.json"))
+        .map_err(|_| Diagnostic::new(file, "Package has no nagari.json".to_string()))?;
+    serde_json::from_str(&content)
+        .map_err(|e| Diagnostic::new(file, format!("Invalid manifest: {e}")))
+}
+
+fn check_manifest(manifest: &Manifest, root: &Path) -> Vec<Diagnostic> {
+    let mut problems = Vec::new();
+    if !is_package_name(&manifest.name) {
+        problems.push(format!(
+            "'{}' is not a valid package name: use lowercase letters, digits, '-', '.' and '_', optionally under an @scope/",
+            manifest.name
+        ));
+    }
+    if let Err(e) = semver::Version::parse(&manifest.version) {
+        problems.push(format!("Version '{}' is invalid: {e}", manifest.version));
+    }
+
+    let mut dependencies: Vec<_> = manifest.dependencies.iter().collect();
+    dependencies.sort_by_key(|(name, _)| name.as_str());
+    for (name, spec) in dependencies {
+        let range = match spec {
+            serde_json::Value::String(range) => Some(range.as_str()),
+            spec => spec.get("version").and_then(|version| version.as_str()),
+        };
+        match range {
+            Some(range) if is_version_range(range) => {}
+            Some(range) => problems.push(format!(
+                "Dependency '{name}' has an invalid version range '{range}'"
+            )),
+            None => problems.push(format!(
+                "Dependency '{name}' has no version, so it cannot be installed from the registry"
+            )),
+        }
+    }
+
+    if let Some(main) = &manifest.main {
+        if !is_inside(main) || !root.join(main).is_file() {
+            problems.push(format!("Main module '{main}' is not in the package"));
+        }
+    }
+    if let Some(runtime) = manifest
+        .nagari
+        .as_ref()
+        .and_then(|nagari| nagari.runtime.as_ref())
+    {
+        if let Err(e) = runtime.validate() {
+            problems.push(format!("Invalid runtime: {e}"));
+        }
+    }
+
+    problems
+        .into_iter()
+        .map(|message| Diagnostic::new(Some("nagari.json"), message))
+        .collect()
+}
+
+/// Compile every source of the package, reporting the errors `nag check` would
+fn check_sources(manifest: &Manifest, root: &Path) -> Vec<Diagnostic> {
+    let source_dir = manifest
+        .nagari
+        .as_ref()
+        .and_then(|nagari| nagari.source_dir.as_deref())
+        .unwrap_or("src");
+    let file = Some("nagari.json");
+    if !is_inside(source_dir) {
+        return vec![Diagnostic::new(
+            file,
+            format!("Source directory '{source_dir}' is not in the package"),
+        )];
+    }
+    let sources: Vec<PathBuf> = match dual::collect_modules(&root.join(source_dir)) {
+        Ok(modules) if !modules.is_empty() => {
+            modules.into_iter().map(|(source, _)| source).collect()
+        }
+        _ => {
+            return vec![Diagnostic::new(
+                file,
+                format!("Package has no .nag sources in '{source_dir}'"),
+            )]
+        }
+    };
+
+    let compiler = nagari_compiler::Compiler::with_config(
+        CompilerConfigBuilder::new().embed_root(root).build(),
+    );
+    sources
+        .iter()
+        .zip(compiler.check_files(&sources))
+        .filter_map(|(source, result)| {
+            let error = result.err()?;
+            let file = source.strip_prefix(root).unwrap_or(source);
+            Some(Diagnostic {
+                file: Some(file.to_string_lossy().replace('\\', "/")),
+                code: Some(error.code().to_string()),
+                message: error.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// `name` or `@scope/name`, each part made of lowercase letters, digits, `-`, `.`, `_` and `~`
+/// and not starting with `.` or `_`
+fn is_package_name(name: &str) -> bool {
+    let segment = |segment: &str| {
+        !segment.is_empty()
+            && !segment.starts_with(['.', '_'])
+            && segment.chars().all(|c| {
+                c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '.' | '_' | '~')
+            })
+    };
+    let valid = match name.strip_prefix('@') {
+        Some(scoped) => scoped
+            .split_once('/')
+            .is_some_and(|(scope, name)| segment(scope) && segment(name)),
+        None => segment(name),
+    };
+    valid && name.len() <= MAX_NAME_LENGTH
+}
+
+/// A semver range, or several joined with `||`
+fn is_version_range(range: &str) -> bool {
+    range
+        .split("||")
+        .all(|range| semver::VersionReq::parse(range.trim()).is_ok())
+}
+
+/// Whether the relative `path` stays inside the package
+fn is_inside(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}